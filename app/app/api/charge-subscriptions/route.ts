@@ -41,6 +41,7 @@ function buildChargeInstruction(
     subscriptionPDA: PublicKey,
     userTokenAccount: PublicKey,
     recipientTokenAccount: PublicKey,
+    onPlan: boolean,
     programId: PublicKey
 ): TransactionInstruction {
     const TOKEN_PROGRAM_ID = new PublicKey('TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA');
@@ -52,6 +53,17 @@ function buildChargeInstruction(
             { pubkey: userTokenAccount, isSigner: false, isWritable: true },
             { pubkey: recipientTokenAccount, isSigner: false, isWritable: true },
             { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+            // A plan subscription is charged with its PlanSubscription, last
+            ...(onPlan
+                ? [{
+                    pubkey: PublicKey.findProgramAddressSync(
+                        [Buffer.from('plan_subscription'), subscriptionPDA.toBuffer()],
+                        programId
+                    )[0],
+                    isSigner: false,
+                    isWritable: true,
+                }]
+                : []),
         ],
         programId,
         data: discriminator,
@@ -130,7 +142,7 @@ export async function POST(request: NextRequest) {
                 const data = account.account.data;
 
                 // Validate account
-                if (data.length < 210) {
+                if (data.length !== 236) {
                     results.skipped.push({
                         address: account.pubkey.toBase58(),
                         reason: 'Invalid size',
@@ -141,7 +153,7 @@ export async function POST(request: NextRequest) {
                 // Check discriminator
                 const discriminator = data.slice(0, 8);
                 const expectedDiscriminator = crypto.createHash('sha256')
-                    .update('account:Subscription')
+                    .update('account:SubscriptionV5')
                     .digest()
                     .slice(0, 8);
 
@@ -153,26 +165,12 @@ export async function POST(request: NextRequest) {
                     continue;
                 }
 
-                // Parse subscription
-                let offset = 8;
-                const authority = new PublicKey(data.slice(offset, offset + 32));
-                offset += 32 * 4; // Skip recipient, user_token, recipient_token
-                const tokenMint = new PublicKey(data.slice(offset, offset + 32));
-                offset += 32 + 8; // Skip mint and amount
-
-                const intervalSeconds = Number(data.readBigInt64LE(offset));
-                offset += 8;
-
-                const lastChargeTimestamp = Number(data.readBigInt64LE(offset));
-                offset += 8 + 8 + 1; // Skip created_at and has_expiry
-
-                // Handle optional expires_at
-                const hasExpiry = data.readUInt8(offset - 1) === 1;
-                if (hasExpiry) {
-                    offset += 8;
-                }
-
-                const isActive = data.readUInt8(offset) === 1;
+                // Parse subscription (fixed offsets, see the program README)
+                const isActive = data.readUInt8(8) === 1;
+                const nextChargeAt = Number(data.readBigInt64LE(41));
+                const authority = new PublicKey(data.slice(49, 81));
+                const tokenMint = new PublicKey(data.slice(145, 177));
+                const onPlan = data.readUInt8(218) === 1;
 
                 if (!isActive) {
                     results.skipped.push({
@@ -183,24 +181,24 @@ export async function POST(request: NextRequest) {
                 }
 
                 // Check if interval passed
-                const timeSinceLastCharge = now - lastChargeTimestamp;
-                if (timeSinceLastCharge < intervalSeconds) {
+                if (now < nextChargeAt) {
                     results.skipped.push({
                         address: account.pubkey.toBase58(),
-                        reason: `Not ready (${Math.ceil((intervalSeconds - timeSinceLastCharge) / 60)}m remaining)`,
+                        reason: `Not ready (${Math.ceil((nextChargeAt - now) / 60)}m remaining)`,
                     });
                     continue;
                 }
 
                 // Get token accounts
-                const userTokenAccount = new PublicKey(data.slice(8 + 64, 8 + 96));
-                const recipientTokenAccount = new PublicKey(data.slice(8 + 96, 8 + 128));
+                const userTokenAccount = new PublicKey(data.slice(81, 113));
+                const recipientTokenAccount = new PublicKey(data.slice(113, 145));
 
                 // Build and send transaction
                 const instruction = buildChargeInstruction(
                     account.pubkey,
                     userTokenAccount,
                     recipientTokenAccount,
+                    onPlan,
                     programId
                 );
 
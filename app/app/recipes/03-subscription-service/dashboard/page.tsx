@@ -10,7 +10,8 @@ import {
 import {
     SUBSCRIPTION_CONSTANTS,
     getPlanById,
-    formatInterval
+    formatInterval,
+    nextChargeTimestamp
 } from '@/lib/constants';
 import { useLazorkitWalletConnect } from '@/hooks/useLazorkitWalletConnect';
 import { getConnection } from '@/lib/solana-utils';
@@ -24,6 +25,7 @@ interface SubscriptionData {
     amountPerPeriod: number;
     intervalSeconds: number;
     lastChargeTimestamp: number;
+    nextChargeAt: number;
     createdAt: number;
     expiresAt: number | null;
     isActive: boolean;
@@ -66,52 +68,23 @@ export default function DashboardPage() {
         return () => clearInterval(interval);
     }, [lastTriggerTime, cooldownRemaining]);
 
+    // Fixed offsets of the current layout (see the program README); only
+    // expires_at, last, is optional
     const parseSubscriptionData = (data: Buffer): SubscriptionData => {
-        let offset = 8;
-
-        const authority = new PublicKey(data.slice(offset, offset + 32)).toBase58();
-        offset += 32;
-
-        const recipient = new PublicKey(data.slice(offset, offset + 32)).toBase58();
-        offset += 32;
-
-        const userTokenAccount = new PublicKey(data.slice(offset, offset + 32)).toBase58();
-        offset += 32;
-
-        const recipientTokenAccount = new PublicKey(data.slice(offset, offset + 32)).toBase58();
-        offset += 32;
-
-        const tokenMint = new PublicKey(data.slice(offset, offset + 32)).toBase58();
-        offset += 32;
-
-        const amountPerPeriod = Number(data.readBigUInt64LE(offset)) / 1_000_000;
-        offset += 8;
-
-        const intervalSeconds = Number(data.readBigInt64LE(offset));
-        offset += 8;
-
-        const lastChargeTimestamp = Number(data.readBigInt64LE(offset));
-        offset += 8;
-
-        const createdAt = Number(data.readBigInt64LE(offset));
-        offset += 8;
-
-        const hasExpiry = data.readUInt8(offset) === 1;
-        offset += 1;
-
-        let expiresAt: number | null = null;
-        if (hasExpiry) {
-            expiresAt = Number(data.readBigInt64LE(offset));
-            offset += 8;
-        }
-
-        const isActive = data.readUInt8(offset) === 1;
-        offset += 1;
-
-        const totalCharged = Number(data.readBigUInt64LE(offset)) / 1_000_000;
-        offset += 8;
-
-        const bump = data.readUInt8(offset);
+        const isActive = data.readUInt8(8) === 1;
+        const recipient = new PublicKey(data.slice(9, 41)).toBase58();
+        const nextChargeAt = Number(data.readBigInt64LE(41));
+        const authority = new PublicKey(data.slice(49, 81)).toBase58();
+        const userTokenAccount = new PublicKey(data.slice(81, 113)).toBase58();
+        const recipientTokenAccount = new PublicKey(data.slice(113, 145)).toBase58();
+        const tokenMint = new PublicKey(data.slice(145, 177)).toBase58();
+        const amountPerPeriod = Number(data.readBigUInt64LE(177)) / 1_000_000;
+        const intervalSeconds = Number(data.readBigInt64LE(185));
+        const lastChargeTimestamp = Number(data.readBigInt64LE(193));
+        const createdAt = Number(data.readBigInt64LE(201));
+        const totalCharged = Number(data.readBigUInt64LE(209)) / 1_000_000;
+        const bump = data.readUInt8(217);
+        const expiresAt = data.readUInt8(227) === 1 ? Number(data.readBigInt64LE(228)) : null;
 
         return {
             authority,
@@ -122,6 +95,7 @@ export default function DashboardPage() {
             amountPerPeriod,
             intervalSeconds,
             lastChargeTimestamp,
+            nextChargeAt,
             createdAt,
             expiresAt,
             isActive,
@@ -285,7 +259,8 @@ export default function DashboardPage() {
     const getNextChargeDate = () => {
         if (!subscriptionData) return '';
         
-        const nextCharge = new Date((subscriptionData.lastChargeTimestamp + subscriptionData.intervalSeconds) * 1000);
+        const { lastChargeTimestamp, intervalSeconds, createdAt, nextChargeAt } = subscriptionData;
+        const nextCharge = new Date((nextChargeTimestamp(lastChargeTimestamp, intervalSeconds, createdAt) ?? nextChargeAt) * 1000);
         const now = new Date();
         const isDue = nextCharge <= now;
         
@@ -167,11 +167,34 @@ export function formatPrice(priceUSDC: number): string {
  * Format interval (seconds) to human-readable string
  */
 export function formatInterval(intervalSeconds: number): string {
+    // Calendar intervals are stored as minus their number of months
+    if (intervalSeconds === -1) return 'month';
+    if (intervalSeconds === -3) return 'quarter';
+    if (intervalSeconds === -12) return 'year';
     const days = Math.floor(intervalSeconds / (24 * 60 * 60));
     if (days < 1) return `${intervalSeconds} seconds`;
     return `${days} days`;
 }
 
+/**
+ * When a period paid at `lastCharge` ends, as the program computes it:
+ * calendar intervals land on the day of the month the subscription was
+ * created (UTC), clamped to shorter months, at `lastCharge`'s time of day.
+ * Returns null for cron schedules, whose due time is only in next_charge_at.
+ */
+export function nextChargeTimestamp(lastCharge: number, intervalSeconds: number, createdAt: number): number | null {
+    if (intervalSeconds > 0) return lastCharge + intervalSeconds;
+    if (![-1, -3, -12].includes(intervalSeconds)) return null;
+
+    const from = new Date(lastCharge * 1000);
+    const anchorDay = new Date(createdAt * 1000).getUTCDate();
+    const month = from.getUTCMonth() - intervalSeconds;
+    // Day 0 of the following month is this month's last day
+    const daysInMonth = new Date(Date.UTC(from.getUTCFullYear(), month + 1, 0)).getUTCDate();
+    const timeOfDay = lastCharge - Math.floor(lastCharge / 86400) * 86400;
+    return Date.UTC(from.getUTCFullYear(), month, Math.min(anchorDay, daysInMonth)) / 1000 + timeOfDay;
+}
+
 /**
  * Calculate expiry timestamp from months
  */
@@ -40,6 +40,44 @@ export function getSubscriptionPDA(
     );
 }
 
+// Exists only while the merchant has banned the wallet from subscribing
+export function getDenylistPDA(
+    recipient: PublicKey,
+    wallet: PublicKey
+): [PublicKey, number] {
+    return PublicKey.findProgramAddressSync(
+        [
+            Buffer.from('denylist'),
+            recipient.toBuffer(),
+            wallet.toBuffer(),
+        ],
+        SUBSCRIPTION_PROGRAM_ID
+    );
+}
+
+// Exists only while the merchant is invite-only
+export function getSubscriberAllowlistPDA(recipient: PublicKey): [PublicKey, number] {
+    return PublicKey.findProgramAddressSync(
+        [Buffer.from('allowlist'), recipient.toBuffer()],
+        SUBSCRIPTION_PROGRAM_ID
+    );
+}
+
+// Exists only while the merchant has invited the wallet to subscribe
+export function getAllowlistEntryPDA(
+    recipient: PublicKey,
+    wallet: PublicKey
+): [PublicKey, number] {
+    return PublicKey.findProgramAddressSync(
+        [
+            Buffer.from('allowlist'),
+            recipient.toBuffer(),
+            wallet.toBuffer(),
+        ],
+        SUBSCRIPTION_PROGRAM_ID
+    );
+}
+
 export function getAssociatedTokenAddressSync(
     mint: PublicKey,
     owner: PublicKey,
@@ -105,6 +143,9 @@ export async function buildInitializeSubscriptionIx(
     );
 
     const [subscriptionPDA] = getSubscriptionPDA(userWallet, MERCHANT_WALLET);
+    const [denylistPDA] = getDenylistPDA(MERCHANT_WALLET, userWallet);
+    const [allowlistPDA] = getSubscriberAllowlistPDA(MERCHANT_WALLET);
+    const [allowlistEntryPDA] = getAllowlistEntryPDA(MERCHANT_WALLET, userWallet);
 
     console.log('User token account:', userTokenAccount.toBase58());
     console.log('Merchant token account:', merchantTokenAccount.toBase58());
@@ -180,6 +221,9 @@ export async function buildInitializeSubscriptionIx(
             { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
             { pubkey: userWallet, isSigner: true, isWritable: true },
             { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+            { pubkey: denylistPDA, isSigner: false, isWritable: false },
+            { pubkey: allowlistPDA, isSigner: false, isWritable: false },
+            { pubkey: allowlistEntryPDA, isSigner: false, isWritable: false },
         ],
         programId: SUBSCRIPTION_PROGRAM_ID,
         data: encodeInitializeSubscriptionData(discriminator, amountLamports, interval, expiry),
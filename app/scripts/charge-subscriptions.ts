@@ -38,6 +38,21 @@ const RPC_URL = env.NEXT_PUBLIC_SOLANA_RPC_URL || 'https://api.devnet.solana.com
 const PROGRAM_ID = env.NEXT_PUBLIC_SUBSCRIPTION_PROGRAM_ID;
 const TOKEN_PROGRAM_ID = new PublicKey('TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA');
 
+// Optional leader election: replicas started with the same group share one
+// on-chain keeper lease, and only its holder charges. The lease must outlast
+// the time between runs, or leadership changes hands every run.
+const KEEPER_LEASE_GROUP = env.KEEPER_LEASE_GROUP ? new PublicKey(env.KEEPER_LEASE_GROUP) : null;
+const KEEPER_LEASE_SECONDS = Number(env.KEEPER_LEASE_SECONDS || 120);
+// KeeperLeaseHeld (6019)
+const KEEPER_LEASE_HELD = 'custom program error: 0x1783';
+
+// Subscription accounts are always 236 bytes
+const SUBSCRIPTION_SIZE = 236;
+const SUBSCRIPTION_DISCRIMINATOR = crypto.createHash('sha256')
+    .update('account:SubscriptionV5')
+    .digest()
+    .slice(0, 8);
+
 if (!PROGRAM_ID) {
     console.error('❌ NEXT_PUBLIC_SUBSCRIPTION_PROGRAM_ID not found in .env.local');
     process.exit(1);
@@ -46,6 +61,9 @@ if (!PROGRAM_ID) {
 console.log('📋 Configuration:');
 console.log('   RPC:', RPC_URL);
 console.log('   Program ID:', PROGRAM_ID);
+if (KEEPER_LEASE_GROUP) {
+    console.log('   Keeper lease group:', KEEPER_LEASE_GROUP.toBase58());
+}
 console.log('');
 
 // Load merchant keypair
@@ -76,10 +94,18 @@ function getInstructionDiscriminator(name: string): Buffer {
     return hash.slice(0, 8);
 }
 
+function planSubscriptionAddress(subscriptionPDA: PublicKey, programId: PublicKey): PublicKey {
+    return PublicKey.findProgramAddressSync(
+        [Buffer.from('plan_subscription'), subscriptionPDA.toBuffer()],
+        programId
+    )[0];
+}
+
 function buildChargeInstruction(
     subscriptionPDA: PublicKey,
     userTokenAccount: PublicKey,
     recipientTokenAccount: PublicKey,
+    onPlan: boolean,
     programId: PublicKey
 ): TransactionInstruction {
     const discriminator = getInstructionDiscriminator('charge_subscription');
@@ -90,17 +116,196 @@ function buildChargeInstruction(
             { pubkey: userTokenAccount, isSigner: false, isWritable: true },
             { pubkey: recipientTokenAccount, isSigner: false, isWritable: true },
             { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+            // A plan subscription is charged with its PlanSubscription, last
+            ...(onPlan
+                ? [{ pubkey: planSubscriptionAddress(subscriptionPDA, programId), isSigner: false, isWritable: true }]
+                : []),
         ],
         programId,
         data: discriminator,
     });
 }
 
+interface SubscriptionState {
+    isActive: boolean;
+    recipient: PublicKey;
+    nextChargeAt: number;
+    authority: PublicKey;
+    userTokenAccount: PublicKey;
+    recipientTokenAccount: PublicKey;
+    tokenMint: PublicKey;
+    amountPerPeriod: bigint;
+    intervalSeconds: number;
+    lastChargeTimestamp: number;
+    createdAt: number;
+    totalCharged: bigint;
+    expiresAt: number | null;
+    onPlan: boolean;
+}
+
+// interval_seconds holds minus the number of months for calendar intervals,
+// and a cron schedule below -2^62 (top bits 10); next_charge_at has the due time
+const CALENDAR_INTERVALS: Record<number, string> = { [-1]: 'monthly', [-3]: 'quarterly', [-12]: 'yearly' };
+const CRON_SCHEDULE_BELOW = -(2 ** 62);
+
+function isScheduledInterval(intervalSeconds: number): boolean {
+    return intervalSeconds in CALENDAR_INTERVALS || intervalSeconds < CRON_SCHEDULE_BELOW;
+}
+
+function describeInterval(intervalSeconds: number): string {
+    if (intervalSeconds < CRON_SCHEDULE_BELOW) return 'cron schedule';
+    return CALENDAR_INTERVALS[intervalSeconds] ?? `${Math.floor(intervalSeconds / 86400)} days`;
+}
+
+// Every field sits at a fixed offset (see the program README);
+// only expires_at, last, is optional
+
+function decodeSubscription(data: Buffer): SubscriptionState {
+    return {
+        isActive: data.readUInt8(8) === 1,
+        recipient: new PublicKey(data.slice(9, 41)),
+        nextChargeAt: Number(data.readBigInt64LE(41)),
+        authority: new PublicKey(data.slice(49, 81)),
+        userTokenAccount: new PublicKey(data.slice(81, 113)),
+        recipientTokenAccount: new PublicKey(data.slice(113, 145)),
+        tokenMint: new PublicKey(data.slice(145, 177)),
+        amountPerPeriod: data.readBigUInt64LE(177),
+        intervalSeconds: Number(data.readBigInt64LE(185)),
+        lastChargeTimestamp: Number(data.readBigInt64LE(193)),
+        createdAt: Number(data.readBigInt64LE(201)),
+        totalCharged: data.readBigUInt64LE(209),
+        onPlan: data.readUInt8(218) === 1,
+        expiresAt: data.readUInt8(227) === 1 ? Number(data.readBigInt64LE(228)) : null,
+    };
+}
+
+function keeperLeaseAddress(group: PublicKey, programId: PublicKey): PublicKey {
+    return PublicKey.findProgramAddressSync(
+        [Buffer.from('keeper_lease'), group.toBuffer()],
+        programId
+    )[0];
+}
+
+interface KeeperLease {
+    term: bigint;
+    expiresAt: number;
+}
+
+// Take or renew the group's lease; null while another keeper holds it
+async function acquireKeeperLease(group: PublicKey, programId: PublicKey): Promise<KeeperLease | null> {
+    const leasePDA = keeperLeaseAddress(group, programId);
+    const data = Buffer.alloc(16);
+    getInstructionDiscriminator('acquire_keeper_lease').copy(data, 0);
+    data.writeBigInt64LE(BigInt(KEEPER_LEASE_SECONDS), 8);
+
+    const instruction = new TransactionInstruction({
+        keys: [
+            { pubkey: leasePDA, isSigner: false, isWritable: true },
+            { pubkey: MERCHANT_KEYPAIR.publicKey, isSigner: true, isWritable: false },
+        ],
+        programId,
+        data,
+    });
+
+    try {
+        await sendAndConfirmTransaction(
+            connection,
+            new Transaction().add(instruction),
+            [MERCHANT_KEYPAIR],
+            { commitment: 'confirmed' }
+        );
+    } catch (err: any) {
+        if (String(err.message).includes(KEEPER_LEASE_HELD)) {
+            return null;
+        }
+        throw err;
+    }
+
+    // KeeperLease: discriminator, group, holder, term (u64), expires_at (i64), ...
+    const account = await connection.getAccountInfo(leasePDA, 'confirmed');
+    if (!account) {
+        throw new Error('keeper lease account not found');
+    }
+    return {
+        term: account.data.readBigUInt64LE(72),
+        expiresAt: Number(account.data.readBigInt64LE(80)),
+    };
+}
+
+// Goes ahead of each charge, so charges still in flight when this keeper
+// loses the lease fail instead of racing the new holder's
+function buildCheckKeeperLeaseInstruction(
+    group: PublicKey,
+    term: bigint,
+    programId: PublicKey
+): TransactionInstruction {
+    const data = Buffer.alloc(16);
+    getInstructionDiscriminator('check_keeper_lease').copy(data, 0);
+    data.writeBigUInt64LE(term, 8);
+
+    return new TransactionInstruction({
+        keys: [
+            { pubkey: keeperLeaseAddress(group, programId), isSigner: false, isWritable: false },
+            { pubkey: MERCHANT_KEYPAIR.publicKey, isSigner: true, isWritable: false },
+        ],
+        programId,
+        data,
+    });
+}
+
+// Send one charge, behind the lease check when running with a lease
+async function sendCharge(
+    subscriptionPDA: PublicKey,
+    subscription: SubscriptionState,
+    programId: PublicKey,
+    lease: KeeperLease | null
+): Promise<string> {
+    // Build charge instruction
+    const instruction = buildChargeInstruction(
+        subscriptionPDA,
+        subscription.userTokenAccount,
+        subscription.recipientTokenAccount,
+        subscription.onPlan,
+        programId
+    );
+
+    // Create and send transaction (NO LAZORKIT - using traditional keypair!)
+    const transaction = new Transaction();
+    if (KEEPER_LEASE_GROUP && lease) {
+        transaction.add(buildCheckKeeperLeaseInstruction(KEEPER_LEASE_GROUP, lease.term, programId));
+    }
+    transaction.add(instruction);
+    transaction.feePayer = MERCHANT_KEYPAIR.publicKey;
+
+    const { blockhash } = await connection.getLatestBlockhash();
+    transaction.recentBlockhash = blockhash;
+
+    console.log(`   📤 Sending transaction...`);
+
+    // Sign with merchant keypair (NO FACE ID!)
+    return sendAndConfirmTransaction(
+        connection,
+        transaction,
+        [MERCHANT_KEYPAIR],
+        { commitment: 'confirmed' }
+    );
+}
+
 async function chargeAllSubscriptions() {
     console.log('🔍 Scanning for subscriptions to charge...\n');
 
     const programId = new PublicKey(PROGRAM_ID);
 
+    let lease: KeeperLease | null = null;
+    if (KEEPER_LEASE_GROUP) {
+        lease = await acquireKeeperLease(KEEPER_LEASE_GROUP, programId);
+        if (!lease) {
+            console.log('⏸️  Another keeper holds the lease - standing by');
+            return;
+        }
+        console.log(`👑 Holding the keeper lease (term ${lease.term}) until ${new Date(lease.expiresAt * 1000).toLocaleString()}\n`);
+    }
+
     try {
         // Get all subscription accounts
         const accounts = await connection.getProgramAccounts(programId);
@@ -121,71 +326,23 @@ async function chargeAllSubscriptions() {
                 // Parse subscription data
                 const data = account.account.data;
 
-                // Basic size check (should be at least 210 bytes)
-                if (data.length < 210) {
+                if (data.length !== SUBSCRIPTION_SIZE) {
                     console.log(`⏭️  Skipping ${account.pubkey.toBase58().slice(0, 8)}... - too small (${data.length} bytes)\n`);
                     skippedCount++;
                     continue;
                 }
 
                 // Check Anchor discriminator (first 8 bytes should match subscription discriminator)
-                const discriminator = data.slice(0, 8);
-                const expectedDiscriminator = crypto.createHash('sha256')
-                    .update('account:Subscription')
-                    .digest()
-                    .slice(0, 8);
-
-                if (!discriminator.equals(expectedDiscriminator)) {
+                if (!data.slice(0, 8).equals(SUBSCRIPTION_DISCRIMINATOR)) {
                     console.log(`⏭️  Skipping ${account.pubkey.toBase58().slice(0, 8)}... - wrong discriminator (not a subscription account)\n`);
                     skippedCount++;
                     continue;
                 }
 
-                let offset = 8; // Skip discriminator
-
-                const authority = new PublicKey(data.slice(offset, offset + 32));
-                offset += 32;
-
-                const recipient = new PublicKey(data.slice(offset, offset + 32));
-                offset += 32;
-
-                const userTokenAccount = new PublicKey(data.slice(offset, offset + 32));
-                offset += 32;
-
-                const recipientTokenAccount = new PublicKey(data.slice(offset, offset + 32));
-                offset += 32;
-
-                const tokenMint = new PublicKey(data.slice(offset, offset + 32));
-                offset += 32;
-
-                const amountPerPeriod = Number(data.readBigUInt64LE(offset)) / 1_000_000;
-                offset += 8;
-
-                const intervalSeconds = Number(data.readBigInt64LE(offset));
-                offset += 8;
-
-                const lastChargeTimestamp = Number(data.readBigInt64LE(offset));
-                offset += 8;
-
-                const createdAt = Number(data.readBigInt64LE(offset));
-                offset += 8;
-
-                const hasExpiry = data.readUInt8(offset) === 1;
-                offset += 1;
-
-                let expiresAt: number | null = null;
-                if (hasExpiry) {
-                    expiresAt = Number(data.readBigInt64LE(offset));
-                    offset += 8;
-                }
-
-                const isActive = data.readUInt8(offset) === 1;
-                offset += 1;
-
-                const totalCharged = Number(data.readBigUInt64LE(offset)) / 1_000_000;
-                offset += 8;
-
-                const bump = data.readUInt8(offset);
+                const subscription = decodeSubscription(data);
+                const { isActive, nextChargeAt, authority, intervalSeconds, lastChargeTimestamp } = subscription;
+                const amountPerPeriod = Number(subscription.amountPerPeriod) / 1_000_000;
+                const totalCharged = Number(subscription.totalCharged) / 1_000_000;
 
                 // Validate data makes sense
                 if (amountPerPeriod > 1_000_000 || totalCharged > 1_000_000) {
@@ -194,7 +351,7 @@ async function chargeAllSubscriptions() {
                     continue;
                 }
 
-                if (intervalSeconds < 0 || intervalSeconds > 365 * 24 * 60 * 60) {
+                if (!isScheduledInterval(intervalSeconds) && (intervalSeconds <= 0 || intervalSeconds > 365 * 24 * 60 * 60)) {
                     console.log(`⏭️  Skipping ${authority.toBase58().slice(0, 8)}... - invalid interval\n`);
                     skippedCount++;
                     continue;
@@ -212,15 +369,14 @@ async function chargeAllSubscriptions() {
                 }
 
                 // Check if needs charging
-                const timeSinceLastCharge = now - lastChargeTimestamp;
-                const canCharge = timeSinceLastCharge >= intervalSeconds;
+                const canCharge = now >= nextChargeAt;
 
-                const timeRemaining = Math.max(0, intervalSeconds - timeSinceLastCharge);
+                const timeRemaining = Math.max(0, nextChargeAt - now);
                 const hoursRemaining = Math.floor(timeRemaining / 3600);
                 const minutesRemaining = Math.floor((timeRemaining % 3600) / 60);
 
                 console.log(`   Last Charged: ${new Date(lastChargeTimestamp * 1000).toLocaleString()}`);
-                console.log(`   Interval: ${Math.floor(intervalSeconds / 86400)} days`);
+                console.log(`   Interval: ${describeInterval(intervalSeconds)}`);
 
                 if (!canCharge) {
                     console.log(`   ⏭️  Skipping - next charge in ${hoursRemaining}h ${minutesRemaining}m\n`);
@@ -231,30 +387,17 @@ async function chargeAllSubscriptions() {
                 console.log(`   ⚡ Ready to charge!\n`);
                 console.log(`   🔨 Building transaction...`);
 
-                // Build charge instruction
-                const instruction = buildChargeInstruction(
-                    account.pubkey,
-                    userTokenAccount,
-                    recipientTokenAccount,
-                    programId
-                );
-
-                // Create and send transaction (NO LAZORKIT - using traditional keypair!)
-                const transaction = new Transaction().add(instruction);
-                transaction.feePayer = MERCHANT_KEYPAIR.publicKey;
-
-                const { blockhash } = await connection.getLatestBlockhash();
-                transaction.recentBlockhash = blockhash;
-
-                console.log(`   📤 Sending transaction...`);
+                // Renew once half the lease is gone; stop if it was lost
+                if (KEEPER_LEASE_GROUP && lease
+                    && Math.floor(Date.now() / 1000) >= lease.expiresAt - KEEPER_LEASE_SECONDS / 2) {
+                    lease = await acquireKeeperLease(KEEPER_LEASE_GROUP, programId);
+                    if (!lease) {
+                        console.log('   ⏸️  Lost the keeper lease - leaving the rest to the new holder\n');
+                        break;
+                    }
+                }
 
-                // Sign with merchant keypair (NO FACE ID!)
-                const signature = await sendAndConfirmTransaction(
-                    connection,
-                    transaction,
-                    [MERCHANT_KEYPAIR],
-                    { commitment: 'confirmed' }
-                );
+                const signature = await sendCharge(account.pubkey, subscription, programId, lease);
 
                 console.log(`   ✅ Charged! Signature: ${signature}`);
                 console.log(`   🔗 View: https://explorer.solana.com/tx/${signature}?cluster=devnet\n`);
@@ -279,15 +422,219 @@ async function chargeAllSubscriptions() {
     }
 }
 
-// Run the script
-console.log('🚀 Starting automatic subscription charging...\n');
+// A billing event as the webhook service (program/subscription-program/webhook)
+// delivers it to the indexer
+interface BillingEvent {
+    kind: 'created' | 'charged' | 'cancelled' | 'updated';
+    signature: string;
+    slot: number;
+    timestamp: number;
+    subscription: string;
+    recipient: string | null;
+    amount: number | null;
+    mint: string | null;
+}
+
+interface Discrepancy {
+    subscription: string;
+    problem: 'missed-charge' | 'missed-events' | 'not-indexed' | 'indexer-ahead' | 'closed-but-open';
+    detail: string;
+    // Unindexed transactions of the subscription, for missed-events
+    signatures?: string[];
+    replayed?: string;
+}
+
+// The indexer's events, exported as a JSON array or one event per line
+function loadIndexedEvents(file: string): BillingEvent[] {
+    const content = fs.readFileSync(file, 'utf-8').trim();
+    if (content.startsWith('[')) {
+        return JSON.parse(content);
+    }
+    return content.split('\n').filter(line => line.trim()).map(line => JSON.parse(line));
+}
+
+// Transactions that touched the subscription and succeeded, newest first
+async function unindexedSignatures(subscriptionPDA: PublicKey, indexed: Set<string>): Promise<string[]> {
+    const signatures = await connection.getSignaturesForAddress(subscriptionPDA, { limit: 1000 });
+    return signatures
+        .filter(info => !info.err && !indexed.has(info.signature))
+        .map(info => info.signature);
+}
 
-chargeAllSubscriptions()
-    .then(() => {
-        console.log('\n✅ Done!\n');
-        process.exit(0);
-    })
-    .catch((err) => {
-        console.error('\n❌ Fatal error:', err);
+// Compare on-chain subscriptions with what the indexer recorded. Overdue
+// charges are replayed with --replay: the program charges only what is due,
+// so a charge that raced another keeper just fails. Missing events are only
+// reported, with the transactions to backfill them from.
+async function reconcile(args: string[]) {
+    const flag = (name: string) => {
+        const index = args.indexOf(name);
+        return index >= 0 ? args[index + 1] : undefined;
+    };
+    const eventsPath = flag('--events') || env.INDEXER_EVENTS_PATH;
+    const grace = Number(flag('--grace') || 3600);
+    const replay = args.includes('--replay');
+
+    if (!eventsPath) {
+        console.error('❌ Pass the indexer export with --events <file> or set INDEXER_EVENTS_PATH');
         process.exit(1);
-    });
\ No newline at end of file
+    }
+
+    console.log('🔍 Reconciling on-chain subscriptions with the indexer...\n');
+
+    const programId = new PublicKey(PROGRAM_ID);
+    const events = loadIndexedEvents(eventsPath);
+    const bySubscription = new Map<string, BillingEvent[]>();
+    for (const event of events) {
+        const list = bySubscription.get(event.subscription) ?? [];
+        list.push(event);
+        bySubscription.set(event.subscription, list);
+    }
+
+    const accounts = await connection.getProgramAccounts(programId, {
+        filters: [
+            { dataSize: SUBSCRIPTION_SIZE },
+            { memcmp: { offset: 0, bytes: SUBSCRIPTION_DISCRIMINATOR.toString('base64'), encoding: 'base64' } },
+        ],
+    });
+    console.log(`✅ ${accounts.length} subscription(s) on-chain, ${events.length} indexed event(s)\n`);
+
+    const now = Math.floor(Date.now() / 1000);
+    const discrepancies: Discrepancy[] = [];
+    const overdue: { pubkey: PublicKey; subscription: SubscriptionState; discrepancy: Discrepancy }[] = [];
+
+    for (const account of accounts) {
+        const address = account.pubkey.toBase58();
+        const subscription = decodeSubscription(account.account.data);
+        const indexed = bySubscription.get(address) ?? [];
+        bySubscription.delete(address);
+
+        const live = subscription.isActive
+            && (subscription.expiresAt === null || now < subscription.expiresAt);
+        if (live && now >= subscription.nextChargeAt + grace) {
+            const discrepancy: Discrepancy = {
+                subscription: address,
+                problem: 'missed-charge',
+                detail: `due since ${new Date(subscription.nextChargeAt * 1000).toISOString()}`,
+            };
+            discrepancies.push(discrepancy);
+            overdue.push({ pubkey: account.pubkey, subscription, discrepancy });
+        }
+
+        if (indexed.length === 0) {
+            discrepancies.push({
+                subscription: address,
+                problem: 'not-indexed',
+                detail: `created ${new Date(subscription.createdAt * 1000).toISOString()}, no events`,
+                signatures: await unindexedSignatures(account.pubkey, new Set()),
+            });
+            continue;
+        }
+
+        const indexedTotal = indexed
+            .filter(event => event.kind === 'created' || event.kind === 'charged')
+            .reduce((total, event) => total + BigInt(event.amount ?? 0), BigInt(0));
+        if (subscription.totalCharged > indexedTotal) {
+            discrepancies.push({
+                subscription: address,
+                problem: 'missed-events',
+                detail: `charged ${subscription.totalCharged} on-chain, ${indexedTotal} indexed`,
+                signatures: await unindexedSignatures(
+                    account.pubkey,
+                    new Set(indexed.map(event => event.signature))
+                ),
+            });
+        } else if (subscription.totalCharged < indexedTotal) {
+            discrepancies.push({
+                subscription: address,
+                problem: 'indexer-ahead',
+                detail: `charged ${subscription.totalCharged} on-chain, ${indexedTotal} indexed (duplicate events?)`,
+            });
+        }
+    }
+
+    // Indexed subscriptions whose account is gone were cleaned up; the
+    // indexer should have seen them cancelled first
+    for (const [address, indexed] of bySubscription) {
+        if (!indexed.some(event => event.kind === 'cancelled')) {
+            discrepancies.push({
+                subscription: address,
+                problem: 'closed-but-open',
+                detail: 'account closed on-chain, no cancellation indexed',
+            });
+        }
+    }
+
+    if (replay && overdue.length > 0) {
+        let lease: KeeperLease | null = null;
+        if (KEEPER_LEASE_GROUP) {
+            lease = await acquireKeeperLease(KEEPER_LEASE_GROUP, programId);
+        }
+        if (KEEPER_LEASE_GROUP && !lease) {
+            console.log('⏸️  Another keeper holds the lease - not replaying charges\n');
+        } else {
+            for (const { pubkey, subscription, discrepancy } of overdue) {
+                console.log(`⚡ Replaying charge for ${pubkey.toBase58().slice(0, 8)}...`);
+                try {
+                    discrepancy.replayed = await sendCharge(pubkey, subscription, programId, lease);
+                    console.log(`   ✅ Charged! Signature: ${discrepancy.replayed}\n`);
+                } catch (err: any) {
+                    console.error(`   ❌ Error:`, err.message);
+                    console.log('');
+                }
+            }
+        }
+    }
+
+    const unresolved = discrepancies.filter(discrepancy => !discrepancy.replayed);
+    if (args.includes('--json')) {
+        console.log(JSON.stringify(discrepancies, null, 2));
+    } else {
+        for (const discrepancy of discrepancies) {
+            const status = discrepancy.replayed ? '✅ replayed' : '❌';
+            console.log(`${status} ${discrepancy.problem} ${discrepancy.subscription}: ${discrepancy.detail}`);
+            for (const signature of discrepancy.signatures ?? []) {
+                console.log(`   unindexed: ${signature}`);
+            }
+        }
+    }
+
+    console.log('━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━');
+    console.log(`✨ Reconciliation:`);
+    console.log(`   📋 Discrepancies: ${discrepancies.length}`);
+    console.log(`   ✅ Replayed: ${discrepancies.length - unresolved.length}`);
+    console.log(`   ❌ Unresolved: ${unresolved.length}`);
+    console.log('━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━');
+
+    // The caller exits non-zero on unresolved discrepancies, so a scheduled
+    // reconcile can alert
+    return unresolved.length === 0;
+}
+
+// Run the script: `charge` (the default) or `reconcile`
+const [command = 'charge', ...args] = process.argv.slice(2);
+
+if (command === 'reconcile') {
+    reconcile(args)
+        .then((clean) => process.exit(clean ? 0 : 2))
+        .catch((err) => {
+            console.error('\n❌ Fatal error:', err);
+            process.exit(1);
+        });
+} else if (command === 'charge') {
+    console.log('🚀 Starting automatic subscription charging...\n');
+
+    chargeAllSubscriptions()
+        .then(() => {
+            console.log('\n✅ Done!\n');
+            process.exit(0);
+        })
+        .catch((err) => {
+            console.error('\n❌ Fatal error:', err);
+            process.exit(1);
+        });
+} else {
+    console.error(`❌ Unknown command: ${command}`);
+    console.log('Usage: charge-subscriptions.ts [charge]');
+    console.log('       charge-subscriptions.ts reconcile --events <indexer export> [--grace <seconds>] [--replay] [--json]');
+    process.exit(1);
+}
\ No newline at end of file
@@ -0,0 +1,121 @@
+import { Connection, PublicKey, TransactionInstruction } from '@solana/web3.js';
+import * as fs from 'fs';
+import * as path from 'path';
+import {
+    buildCancelSubscriptionIx,
+    buildCleanupCancelledSubscriptionIx,
+    buildInitializeSubscriptionIx,
+    getSubscriptionPDA,
+    MERCHANT_WALLET,
+    SUBSCRIPTION_PROGRAM_ID,
+    USDC_MINT,
+} from '../lib/program/subscription-service';
+
+// Writes the instructions this app builds as JSON test vectors for the Rust
+// client (program/subscription-program/client/tests/ts_vectors.rs), so both
+// SDKs are checked byte for byte against each other.
+//
+//   npx tsx scripts/encoding-vectors.ts
+
+const OUTPUT = path.join(
+    __dirname,
+    '../../program/subscription-program/client/tests/vectors/ts-client.json'
+);
+
+// Fixed wallets so the vectors are stable across runs
+const USERS = [
+    new PublicKey('7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosgAsU'),
+    new PublicKey('9WzDXwBbmkg8ZTbNMqUxvQRAyrZzDsGYdLVL9zYtAWWM'),
+];
+
+// Every token account exists, the subscription does not: the service then
+// emits only the program instruction
+const subscriptions = USERS.map(user => getSubscriptionPDA(user, MERCHANT_WALLET)[0]);
+const connection = {
+    getAccountInfo: async (address: PublicKey) =>
+        subscriptions.some(pda => pda.equals(address)) ? null : { data: Buffer.alloc(0) },
+} as unknown as Connection;
+
+function vector(name: string, args: Record<string, unknown>, ix: TransactionInstruction) {
+    return {
+        name,
+        args,
+        program_id: ix.programId.toBase58(),
+        accounts: ix.keys.map(key => ({
+            pubkey: key.pubkey.toBase58(),
+            is_signer: key.isSigner,
+            is_writable: key.isWritable,
+        })),
+        data: ix.data.toString('hex'),
+    };
+}
+
+async function main() {
+    // Quiet the service's progress logging
+    const log = console.log;
+    console.log = () => {};
+
+    const vectors = [];
+
+    const cases = [
+        { user: USERS[0], amountPerPeriod: 10, intervalSeconds: 2_592_000 },
+        { user: USERS[1], amountPerPeriod: 1, intervalSeconds: 86_400, expiresAt: 1_767_225_600 },
+        { user: USERS[0], amountPerPeriod: 250, intervalSeconds: 604_800, expiresAt: 1_735_689_600 },
+    ];
+    for (const { user, amountPerPeriod, intervalSeconds, expiresAt } of cases) {
+        const [ix] = await buildInitializeSubscriptionIx(
+            { userWallet: user, amountPerPeriod, intervalSeconds, expiresAt },
+            connection
+        );
+        vectors.push(
+            vector(
+                'initialize_subscription',
+                {
+                    authority: user.toBase58(),
+                    recipient: MERCHANT_WALLET.toBase58(),
+                    token_mint: USDC_MINT.toBase58(),
+                    amount_per_period: amountPerPeriod * 1_000_000,
+                    interval_seconds: intervalSeconds,
+                    expires_at: expiresAt ?? null,
+                },
+                ix
+            )
+        );
+    }
+
+    for (const user of USERS) {
+        const args = { authority: user.toBase58(), recipient: MERCHANT_WALLET.toBase58() };
+        vectors.push(
+            vector(
+                'cancel_subscription',
+                { ...args, token_mint: USDC_MINT.toBase58() },
+                await buildCancelSubscriptionIx(user)
+            )
+        );
+        vectors.push(
+            vector('cleanup_cancelled_subscription', args, await buildCleanupCancelledSubscriptionIx(user))
+        );
+    }
+
+    console.log = log;
+
+    fs.mkdirSync(path.dirname(OUTPUT), { recursive: true });
+    fs.writeFileSync(
+        OUTPUT,
+        JSON.stringify(
+            {
+                generator: 'app/scripts/encoding-vectors.ts',
+                program_id: SUBSCRIPTION_PROGRAM_ID.toBase58(),
+                vectors,
+            },
+            null,
+            2
+        ) + '\n'
+    );
+    console.log(`Wrote ${vectors.length} vectors to ${OUTPUT}`);
+}
+
+main().catch(err => {
+    console.error(err);
+    process.exit(1);
+});
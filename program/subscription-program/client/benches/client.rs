@@ -0,0 +1,153 @@
+//! Keeper hot paths: a keeper derives addresses, builds a charge and parses
+//! the resulting logs for every due subscription, every tick.
+//!
+//! ```bash
+//! cargo bench -p subscription-client
+//! ```
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::Event;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use subscription_client::{events, instructions, pda, transaction, Subscription, PROGRAM_ID};
+use subscription_program::SubscriptionCharged;
+
+const AMOUNT: u64 = 10_000_000;
+const INTERVAL: i64 = 30 * 24 * 60 * 60;
+const BATCH: usize = 64;
+
+fn subscription(authority: Pubkey, recipient: Pubkey, mint: Pubkey) -> Subscription {
+    Subscription {
+        authority,
+        recipient,
+        user_token_account: pda::associated_token_address(&authority, &mint),
+        recipient_token_account: pda::associated_token_address(&recipient, &mint),
+        token_mint: mint,
+        amount_per_period: AMOUNT,
+        interval_seconds: INTERVAL,
+        last_charge_timestamp: 1_735_689_600,
+        next_charge_at: 1_735_689_600 + INTERVAL,
+        created_at: 1_735_689_600,
+        expires_at: None,
+        is_active: true,
+        total_charged: AMOUNT,
+        bump: pda::subscription_address(&authority, &recipient).1,
+        on_plan: false,
+        max_total_spend: Subscription::NO_SPEND_LIMIT,
+    }
+}
+
+/// Logs of a successful `charge_subscription`, wrapped in a smart-wallet CPI
+fn charge_logs(authority: Pubkey, recipient: Pubkey) -> Vec<String> {
+    let event = SubscriptionCharged {
+        subscription: pda::subscription_address(&authority, &recipient).0,
+        authority,
+        recipient,
+        amount: AMOUNT,
+        total_charged: 2 * AMOUNT,
+        reference: None,
+        timestamp: 1_738_281_600,
+    };
+    let wallet = Pubkey::new_unique();
+
+    vec![
+        format!("Program {wallet} invoke [1]"),
+        "Program log: Instruction: Execute".to_string(),
+        format!("Program {PROGRAM_ID} invoke [2]"),
+        "Program log: Instruction: ChargeSubscription".to_string(),
+        format!("Program {} invoke [3]", spl_token::ID),
+        "Program log: Instruction: Transfer".to_string(),
+        format!(
+            "Program {} consumed 4645 of 180000 compute units",
+            spl_token::ID
+        ),
+        format!("Program {} success", spl_token::ID),
+        "Program log: Subscription charged!".to_string(),
+        format!("Program data: {}", BASE64_STANDARD.encode(event.data())),
+        format!("Program {PROGRAM_ID} consumed 19274 of 194000 compute units"),
+        format!("Program {PROGRAM_ID} success"),
+        format!("Program data: {}", BASE64_STANDARD.encode([0u8; 48])),
+        format!("Program {wallet} success"),
+    ]
+}
+
+fn pda(c: &mut Criterion) {
+    let authority = Pubkey::new_unique();
+    let recipient = Pubkey::new_unique();
+    let mint = Pubkey::new_unique();
+
+    let mut group = c.benchmark_group("pda");
+    group.bench_function("subscription_address", |b| {
+        b.iter(|| pda::subscription_address(black_box(&authority), black_box(&recipient)))
+    });
+    group.bench_function("associated_token_address", |b| {
+        b.iter(|| pda::associated_token_address(black_box(&authority), black_box(&mint)))
+    });
+    group.finish();
+}
+
+fn instruction_building(c: &mut Criterion) {
+    let authority = Pubkey::new_unique();
+    let recipient = Pubkey::new_unique();
+    let mint = Pubkey::new_unique();
+    let state = subscription(authority, recipient, mint);
+    let address = pda::subscription_address(&authority, &recipient).0;
+
+    let charges: Vec<_> = (0..BATCH)
+        .map(|_| {
+            let authority = Pubkey::new_unique();
+            let state = subscription(authority, recipient, mint);
+            let address = pda::subscription_address(&authority, &recipient).0;
+            instructions::charge_subscription(&address, &state)
+        })
+        .collect();
+    let keeper = Pubkey::new_unique();
+    let blockhash = solana_hash::Hash::new_unique();
+
+    let mut group = c.benchmark_group("instructions");
+    group.bench_function("initialize_subscription", |b| {
+        b.iter(|| {
+            instructions::initialize_subscription(
+                black_box(&authority),
+                black_box(&recipient),
+                black_box(&mint),
+                black_box(&authority),
+                AMOUNT,
+                INTERVAL,
+                None,
+            )
+        })
+    });
+    group.bench_function("charge_subscription", |b| {
+        b.iter(|| instructions::charge_subscription(black_box(&address), black_box(&state)))
+    });
+
+    group.throughput(Throughput::Elements(BATCH as u64));
+    group.bench_function("pack_charges", |b| {
+        b.iter(|| {
+            transaction::pack_instructions(&keeper, black_box(&charges), &[], blockhash).unwrap()
+        })
+    });
+    group.finish();
+}
+
+fn event_parsing(c: &mut Criterion) {
+    let logs = charge_logs(Pubkey::new_unique(), Pubkey::new_unique());
+    let encoded = logs
+        .iter()
+        .find_map(|log| log.strip_prefix("Program data: "))
+        .unwrap();
+    let data = BASE64_STANDARD.decode(encoded).unwrap();
+
+    let mut group = c.benchmark_group("events");
+    group.bench_function("decode_event", |b| {
+        b.iter(|| events::decode_event(black_box(&data)).unwrap())
+    });
+    group.bench_function("parse_logs", |b| {
+        b.iter(|| events::parse_logs(black_box(&logs)).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, pda, instruction_building, event_parsing);
+criterion_main!(benches);
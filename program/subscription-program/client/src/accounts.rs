@@ -0,0 +1,185 @@
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::{AccountDeserialize, Discriminator, Space};
+use spl_token::state::Account as TokenAccount;
+use subscription_program::{
+    LEGACY_SUBSCRIPTION_DISCRIMINATOR, SUBSCRIPTION_V2_DISCRIMINATOR,
+    SUBSCRIPTION_V3_DISCRIMINATOR, SUBSCRIPTION_V4_DISCRIMINATOR,
+};
+
+use crate::{ClientError, Result, Subscription};
+
+/// `getMultipleAccounts` accepts at most this many keys per request
+pub const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+/// Decode any Anchor account (discriminator checked)
+pub fn decode_account<T: AccountDeserialize>(address: &Pubkey, data: &[u8]) -> Result<T> {
+    T::try_deserialize(&mut &data[..]).map_err(|source| ClientError::Decode {
+        address: *address,
+        source,
+    })
+}
+
+pub fn decode_subscription(address: &Pubkey, data: &[u8]) -> Result<Subscription> {
+    decode_account(address, data)
+}
+
+/// Size of every subscription account in the current layout, for a
+/// `dataSize` filter
+pub const SUBSCRIPTION_ACCOUNT_SIZE: usize = 8 + Subscription::INIT_SPACE;
+
+/// `(offset, bytes)` memcmp filters selecting subscriptions, optionally only
+/// active ones and only those paying `recipient`. The offsets are fixed in
+/// the current layout, so the same pairs work for `getProgramAccounts` and
+/// Geyser account subscriptions.
+pub fn subscription_filters(
+    recipient: Option<&Pubkey>,
+    active_only: bool,
+) -> Vec<(usize, Vec<u8>)> {
+    let mut filters = vec![(0, Subscription::DISCRIMINATOR.to_vec())];
+    if active_only {
+        filters.push((Subscription::IS_ACTIVE_OFFSET, vec![1]));
+    }
+    if let Some(recipient) = recipient {
+        filters.push((
+            Subscription::RECIPIENT_OFFSET,
+            recipient.to_bytes().to_vec(),
+        ));
+    }
+    filters
+}
+
+/// `next_charge_at` read from raw account bytes without decoding the rest,
+/// `None` if `data` is not a subscription in the current layout
+pub fn next_charge_at(data: &[u8]) -> Option<i64> {
+    if !data.starts_with(Subscription::DISCRIMINATOR) {
+        return None;
+    }
+    let offset = Subscription::NEXT_CHARGE_AT_OFFSET;
+    let bytes = data.get(offset..offset + 8)?;
+    Some(i64::from_le_bytes(bytes.try_into().ok()?))
+}
+
+/// Whether `data` is a subscription still in an older layout, the original
+/// one, `SubscriptionV2`, `SubscriptionV3` or `SubscriptionV4`; see
+/// [`crate::instructions::migrate_subscription`]
+pub fn is_legacy_subscription(data: &[u8]) -> bool {
+    data.starts_with(LEGACY_SUBSCRIPTION_DISCRIMINATOR)
+        || data.starts_with(SUBSCRIPTION_V2_DISCRIMINATOR)
+        || data.starts_with(SUBSCRIPTION_V3_DISCRIMINATOR)
+        || data.starts_with(SUBSCRIPTION_V4_DISCRIMINATOR)
+}
+
+/// Whether `data` is a deactivated subscription that can be shrunk to a
+/// tombstone; see [`crate::instructions::compact_subscription`]
+pub fn is_compactable_subscription(data: &[u8]) -> bool {
+    data.starts_with(Subscription::DISCRIMINATOR)
+        && data.get(Subscription::IS_ACTIVE_OFFSET) == Some(&0)
+}
+
+/// Decode an SPL token account (user or merchant side of a subscription)
+pub fn decode_token_account(address: &Pubkey, data: &[u8]) -> Result<TokenAccount> {
+    TokenAccount::unpack(data).map_err(|err| ClientError::Decode {
+        address: *address,
+        source: err.into(),
+    })
+}
+
+#[cfg(feature = "rpc")]
+pub use self::rpc::*;
+
+#[cfg(feature = "rpc")]
+mod rpc {
+    use solana_client::nonblocking::rpc_client::RpcClient;
+
+    use super::*;
+
+    /// Fetch and decode an account, `None` if it does not exist
+    pub async fn fetch_account_opt<T: AccountDeserialize>(
+        rpc: &RpcClient,
+        address: &Pubkey,
+    ) -> Result<Option<T>> {
+        let account = rpc
+            .get_account_with_commitment(address, rpc.commitment())
+            .await?
+            .value;
+
+        account
+            .map(|account| decode_account(address, &account.data))
+            .transpose()
+    }
+
+    /// Fetch and decode an account, erroring if it does not exist
+    pub async fn fetch_account<T: AccountDeserialize>(
+        rpc: &RpcClient,
+        address: &Pubkey,
+    ) -> Result<T> {
+        fetch_account_opt(rpc, address)
+            .await?
+            .ok_or(ClientError::AccountNotFound(*address))
+    }
+
+    /// Batched fetch, chunked to the RPC limit. Results line up with `addresses`;
+    /// missing accounts come back as `None`.
+    pub async fn fetch_multiple_accounts<T: AccountDeserialize>(
+        rpc: &RpcClient,
+        addresses: &[Pubkey],
+    ) -> Result<Vec<Option<T>>> {
+        let mut decoded = Vec::with_capacity(addresses.len());
+
+        for chunk in addresses.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            let accounts = rpc.get_multiple_accounts(chunk).await?;
+            for (address, account) in chunk.iter().zip(accounts) {
+                decoded.push(
+                    account
+                        .map(|account| decode_account(address, &account.data))
+                        .transpose()?,
+                );
+            }
+        }
+
+        Ok(decoded)
+    }
+
+    pub async fn fetch_subscription(rpc: &RpcClient, address: &Pubkey) -> Result<Subscription> {
+        fetch_account(rpc, address).await
+    }
+
+    pub async fn fetch_subscriptions(
+        rpc: &RpcClient,
+        addresses: &[Pubkey],
+    ) -> Result<Vec<Option<Subscription>>> {
+        fetch_multiple_accounts(rpc, addresses).await
+    }
+
+    pub async fn fetch_token_account(rpc: &RpcClient, address: &Pubkey) -> Result<TokenAccount> {
+        let account = rpc
+            .get_account_with_commitment(address, rpc.commitment())
+            .await?
+            .value
+            .ok_or(ClientError::AccountNotFound(*address))?;
+
+        decode_token_account(address, &account.data)
+    }
+
+    /// Batched token account fetch, e.g. for checking balances of every due subscription
+    pub async fn fetch_token_accounts(
+        rpc: &RpcClient,
+        addresses: &[Pubkey],
+    ) -> Result<Vec<Option<TokenAccount>>> {
+        let mut decoded = Vec::with_capacity(addresses.len());
+
+        for chunk in addresses.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            let accounts = rpc.get_multiple_accounts(chunk).await?;
+            for (address, account) in chunk.iter().zip(accounts) {
+                decoded.push(
+                    account
+                        .map(|account| decode_token_account(address, &account.data))
+                        .transpose()?,
+                );
+            }
+        }
+
+        Ok(decoded)
+    }
+}
@@ -0,0 +1,186 @@
+//! Client for the allowance wallet recipe program.
+//!
+//! The child's passkey wallet signs [`spend`] itself, or hands a session key
+//! to the app: [`create_session`] registers its policy and the app signs
+//! spends with it until the session expires or is revoked.
+
+use allowance::{accounts, instruction};
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+
+pub use allowance::{
+    Allowance, CategoryCap, SessionKey, ID as ALLOWANCE_PROGRAM_ID, MAX_CATEGORIES,
+};
+
+use crate::pda::associated_token_address;
+
+pub const ALLOWANCE_SEED: &[u8] = b"allowance";
+pub const SESSION_SEED: &[u8] = b"session";
+
+/// Allowance PDA from `funder` to `child`
+pub fn allowance_address(funder: &Pubkey, child: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[ALLOWANCE_SEED, funder.as_ref(), child.as_ref()],
+        &ALLOWANCE_PROGRAM_ID,
+    )
+}
+
+/// Session PDA holding the policy of `key` under an allowance
+pub fn session_address(allowance: &Pubkey, key: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[SESSION_SEED, allowance.as_ref(), key.as_ref()],
+        &ALLOWANCE_PROGRAM_ID,
+    )
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: ALLOWANCE_PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// Funds from the funder's ATA for `mint`. The vault, the allowance PDA's
+/// ATA for `mint`, must exist; create it beforehand with `CreateIdempotent`.
+pub fn create_allowance(
+    funder: &Pubkey,
+    child: &Pubkey,
+    mint: &Pubkey,
+    payer: &Pubkey,
+    amount_per_period: u64,
+    interval_seconds: i64,
+    caps: Vec<u64>,
+) -> Instruction {
+    let allowance = allowance_address(funder, child).0;
+    build(
+        accounts::CreateAllowance {
+            allowance,
+            funder: *funder,
+            child: *child,
+            mint: *mint,
+            funder_token_account: associated_token_address(funder, mint),
+            vault: associated_token_address(&allowance, mint),
+            payer: *payer,
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+        },
+        instruction::CreateAllowance {
+            amount_per_period,
+            interval_seconds,
+            caps,
+        },
+    )
+}
+
+pub fn set_caps(allowance: &Allowance, caps: Vec<u64>) -> Instruction {
+    build(
+        accounts::SetCaps {
+            allowance: allowance_address(&allowance.funder, &allowance.child).0,
+            funder: allowance.funder,
+        },
+        instruction::SetCaps { caps },
+    )
+}
+
+pub fn top_up(allowance: &Allowance) -> Instruction {
+    build(
+        accounts::TopUp {
+            allowance: allowance_address(&allowance.funder, &allowance.child).0,
+            funder_token_account: allowance.funder_token_account,
+            vault: allowance.vault,
+            token_program: spl_token::ID,
+        },
+        instruction::TopUp {},
+    )
+}
+
+/// Signed by the child; `category_mask` has bit `n` set for each category `n` allowed
+pub fn create_session(
+    allowance: &Allowance,
+    payer: &Pubkey,
+    key: &Pubkey,
+    category_mask: u8,
+    max_per_spend: u64,
+    expires_at: i64,
+) -> Instruction {
+    let allowance_key = allowance_address(&allowance.funder, &allowance.child).0;
+    build(
+        accounts::CreateSession {
+            allowance: allowance_key,
+            session: session_address(&allowance_key, key).0,
+            child: allowance.child,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreateSession {
+            key: *key,
+            category_mask,
+            max_per_spend,
+            expires_at,
+        },
+    )
+}
+
+/// Signed by `authority`, the child or the funder
+pub fn revoke_session(allowance: &Allowance, key: &Pubkey, authority: &Pubkey) -> Instruction {
+    let allowance_key = allowance_address(&allowance.funder, &allowance.child).0;
+    build(
+        accounts::RevokeSession {
+            allowance: allowance_key,
+            session: session_address(&allowance_key, key).0,
+            authority: *authority,
+            child: allowance.child,
+        },
+        instruction::RevokeSession {},
+    )
+}
+
+/// Pays `destination`, a token account for the allowance mint. `spender` is
+/// the child or a session key; a session key's policy account is passed
+/// along.
+pub fn spend(
+    allowance: &Allowance,
+    spender: &Pubkey,
+    destination: &Pubkey,
+    category: u8,
+    amount: u64,
+) -> Instruction {
+    let allowance_key = allowance_address(&allowance.funder, &allowance.child).0;
+    let session = (*spender != allowance.child).then(|| session_address(&allowance_key, spender).0);
+    build(
+        accounts::Spend {
+            allowance: allowance_key,
+            session,
+            spender: *spender,
+            vault: allowance.vault,
+            destination: *destination,
+            token_program: spl_token::ID,
+        },
+        instruction::Spend { category, amount },
+    )
+}
+
+/// Returns the vault to the funder's account; revoke sessions beforehand
+pub fn close_allowance(allowance: &Allowance) -> Instruction {
+    build(
+        accounts::CloseAllowance {
+            allowance: allowance_address(&allowance.funder, &allowance.child).0,
+            funder: allowance.funder,
+            vault: allowance.vault,
+            funder_token_account: allowance.funder_token_account,
+            token_program: spl_token::ID,
+        },
+        instruction::CloseAllowance {},
+    )
+}
+
+/// One keeper pass: a `top_up` for every allowance due at `now`
+pub fn due_top_ups(allowances: &[Allowance], now: i64) -> Vec<Instruction> {
+    allowances
+        .iter()
+        .filter(|allowance| allowance.check_due(now).is_ok())
+        .map(top_up)
+        .collect()
+}
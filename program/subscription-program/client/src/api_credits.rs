@@ -0,0 +1,184 @@
+//! Client for the prepaid API credits recipe program.
+//!
+//! The provider's metering service signs [`report_usage`] with its meter
+//! authority. The keeper refills low balances with [`due_top_ups`], the same
+//! way it charges subscriptions.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use api_credits::{accounts, instruction};
+
+pub use api_credits::{AutoTopUp, CreditAccount, Service, ID as API_CREDITS_PROGRAM_ID};
+
+use crate::pda::associated_token_address;
+
+pub const SERVICE_SEED: &[u8] = b"service";
+pub const CREDITS_SEED: &[u8] = b"credits";
+
+/// Service PDA of a provider; a provider sells one kind of credit
+pub fn service_address(provider: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[SERVICE_SEED, provider.as_ref()], &API_CREDITS_PROGRAM_ID)
+}
+
+/// Credit balance PDA of `user` at a service
+pub fn credit_account_address(service: &Pubkey, user: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[CREDITS_SEED, service.as_ref(), user.as_ref()],
+        &API_CREDITS_PROGRAM_ID,
+    )
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: API_CREDITS_PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// Payments land in the provider's ATA for `mint`
+pub fn create_service(
+    provider: &Pubkey,
+    mint: &Pubkey,
+    payer: &Pubkey,
+    meter_authority: &Pubkey,
+    price_per_credit: u64,
+    low_balance_threshold: u64,
+) -> Instruction {
+    build(
+        accounts::CreateService {
+            service: service_address(provider).0,
+            provider: *provider,
+            mint: *mint,
+            provider_token_account: associated_token_address(provider, mint),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreateService {
+            meter_authority: *meter_authority,
+            price_per_credit,
+            low_balance_threshold,
+        },
+    )
+}
+
+pub fn update_service(
+    provider: &Pubkey,
+    meter_authority: &Pubkey,
+    price_per_credit: u64,
+    low_balance_threshold: u64,
+) -> Instruction {
+    build(
+        accounts::UpdateService {
+            service: service_address(provider).0,
+            provider: *provider,
+        },
+        instruction::UpdateService {
+            meter_authority: *meter_authority,
+            price_per_credit,
+            low_balance_threshold,
+        },
+    )
+}
+
+pub fn open_account(service: &Pubkey, user: &Pubkey, payer: &Pubkey) -> Instruction {
+    build(
+        accounts::OpenAccount {
+            service: *service,
+            credit_account: credit_account_address(service, user).0,
+            user: *user,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::OpenAccount {},
+    )
+}
+
+/// Paid from the user's ATA for the service mint
+pub fn buy_credits(service: &Service, user: &Pubkey, credits: u64) -> Instruction {
+    let service_key = service_address(&service.provider).0;
+    build(
+        accounts::BuyCredits {
+            service: service_key,
+            credit_account: credit_account_address(&service_key, user).0,
+            user: *user,
+            user_token_account: associated_token_address(user, &service.mint),
+            provider_token_account: service.provider_token_account,
+            token_program: spl_token::ID,
+        },
+        instruction::BuyCredits { credits },
+    )
+}
+
+/// Signed by the service's meter authority; `report_id` must exceed the last
+/// one applied to this user
+pub fn report_usage(service: &Service, user: &Pubkey, report_id: u64, credits: u64) -> Instruction {
+    let service_key = service_address(&service.provider).0;
+    build(
+        accounts::ReportUsage {
+            service: service_key,
+            credit_account: credit_account_address(&service_key, user).0,
+            meter_authority: service.meter_authority,
+        },
+        instruction::ReportUsage { report_id, credits },
+    )
+}
+
+/// Delegates the user's ATA for the service mint to their credit account
+pub fn set_auto_top_up(service: &Service, user: &Pubkey, credits: u64) -> Instruction {
+    let service_key = service_address(&service.provider).0;
+    build(
+        accounts::SetAutoTopUp {
+            service: service_key,
+            credit_account: credit_account_address(&service_key, user).0,
+            user: *user,
+            user_token_account: associated_token_address(user, &service.mint),
+            token_program: spl_token::ID,
+        },
+        instruction::SetAutoTopUp { credits },
+    )
+}
+
+/// `None` when auto top-up is off
+pub fn cancel_auto_top_up(account: &CreditAccount) -> Option<Instruction> {
+    let top_up = account.auto_top_up?;
+    Some(build(
+        accounts::CancelAutoTopUp {
+            credit_account: credit_account_address(&account.service, &account.user).0,
+            user: account.user,
+            user_token_account: top_up.user_token_account,
+            token_program: spl_token::ID,
+        },
+        instruction::CancelAutoTopUp {},
+    ))
+}
+
+/// `None` when auto top-up is off
+pub fn top_up(service: &Service, account: &CreditAccount) -> Option<Instruction> {
+    let top_up = account.auto_top_up?;
+    Some(build(
+        accounts::TopUp {
+            service: account.service,
+            credit_account: credit_account_address(&account.service, &account.user).0,
+            user_token_account: top_up.user_token_account,
+            provider_token_account: service.provider_token_account,
+            token_program: spl_token::ID,
+        },
+        instruction::TopUp {},
+    ))
+}
+
+/// One keeper pass: a `top_up` for every balance of `service` that is under
+/// its threshold and has auto top-up on
+pub fn due_top_ups(service: &Service, accounts: &[CreditAccount]) -> Vec<Instruction> {
+    accounts
+        .iter()
+        .filter(|account| {
+            account
+                .check_top_up_due(service.low_balance_threshold)
+                .is_ok()
+        })
+        .filter_map(|account| top_up(service, account))
+        .collect()
+}
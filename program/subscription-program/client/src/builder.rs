@@ -0,0 +1,266 @@
+//! Validated construction of `initialize_subscription`.
+//!
+//! The program accepts whatever it is given and the first charge happens in
+//! the same instruction, so a bad amount or a wrong token account costs the
+//! user a failed (or worse, a successful but useless) transaction. The builder
+//! catches those mistakes before anything is signed.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use subscription_program::Interval;
+use thiserror::Error;
+
+use crate::instructions;
+use crate::pda::associated_token_address;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum BuildError {
+    #[error("amount_per_period is required")]
+    MissingAmount,
+    #[error("amount_per_period must be greater than zero")]
+    ZeroAmount,
+    #[error("interval_seconds is required")]
+    MissingInterval,
+    #[error("interval_seconds {0} is not an interval, see `Interval`")]
+    InvalidInterval(i64),
+    #[error("expires_at {expires_at} is not after the current time {now}")]
+    ExpiryInPast { expires_at: i64, now: i64 },
+    #[error("subscriber and merchant are the same wallet")]
+    SelfSubscription,
+    #[error("token account for {owner} should be the ATA {expected}, got {provided}")]
+    AtaMismatch {
+        owner: Pubkey,
+        expected: Pubkey,
+        provided: Pubkey,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct InitializeSubscriptionBuilder {
+    authority: Pubkey,
+    recipient: Pubkey,
+    token_mint: Pubkey,
+    payer: Option<Pubkey>,
+    amount_per_period: Option<u64>,
+    interval_seconds: Option<i64>,
+    expires_at: Option<i64>,
+    user_token_account: Option<Pubkey>,
+    recipient_token_account: Option<Pubkey>,
+    now: Option<i64>,
+}
+
+impl InitializeSubscriptionBuilder {
+    pub fn new(authority: Pubkey, recipient: Pubkey, token_mint: Pubkey) -> Self {
+        Self {
+            authority,
+            recipient,
+            token_mint,
+            payer: None,
+            amount_per_period: None,
+            interval_seconds: None,
+            expires_at: None,
+            user_token_account: None,
+            recipient_token_account: None,
+            now: None,
+        }
+    }
+
+    /// Rent payer, e.g. the LazorKit paymaster. Defaults to `authority`.
+    pub fn payer(mut self, payer: Pubkey) -> Self {
+        self.payer = Some(payer);
+        self
+    }
+
+    pub fn amount_per_period(mut self, amount: u64) -> Self {
+        self.amount_per_period = Some(amount);
+        self
+    }
+
+    pub fn interval_seconds(mut self, interval: i64) -> Self {
+        self.interval_seconds = Some(interval);
+        self
+    }
+
+    /// Billing interval, calendar months included; sets `interval_seconds`
+    pub fn interval(self, interval: Interval) -> Self {
+        self.interval_seconds(interval.to_seconds_field())
+    }
+
+    pub fn expires_at(mut self, expires_at: i64) -> Self {
+        self.expires_at = Some(expires_at);
+        self
+    }
+
+    /// Token accounts the caller already resolved; checked against the ATA derivation
+    pub fn user_token_account(mut self, address: Pubkey) -> Self {
+        self.user_token_account = Some(address);
+        self
+    }
+
+    pub fn recipient_token_account(mut self, address: Pubkey) -> Self {
+        self.recipient_token_account = Some(address);
+        self
+    }
+
+    /// Current unix time; enables the expiry check
+    pub fn now(mut self, now: i64) -> Self {
+        self.now = Some(now);
+        self
+    }
+
+    pub fn build(self) -> Result<Instruction, BuildError> {
+        let amount_per_period = self.amount_per_period.ok_or(BuildError::MissingAmount)?;
+        if amount_per_period == 0 {
+            return Err(BuildError::ZeroAmount);
+        }
+
+        let interval_seconds = self.interval_seconds.ok_or(BuildError::MissingInterval)?;
+        if Interval::from_seconds_field(interval_seconds).is_none() {
+            return Err(BuildError::InvalidInterval(interval_seconds));
+        }
+
+        if let (Some(expires_at), Some(now)) = (self.expires_at, self.now) {
+            if expires_at <= now {
+                return Err(BuildError::ExpiryInPast { expires_at, now });
+            }
+        }
+
+        if self.authority == self.recipient {
+            return Err(BuildError::SelfSubscription);
+        }
+
+        check_ata(&self.authority, &self.token_mint, self.user_token_account)?;
+        check_ata(
+            &self.recipient,
+            &self.token_mint,
+            self.recipient_token_account,
+        )?;
+
+        Ok(instructions::initialize_subscription(
+            &self.authority,
+            &self.recipient,
+            &self.token_mint,
+            &self.payer.unwrap_or(self.authority),
+            amount_per_period,
+            interval_seconds,
+            self.expires_at,
+        ))
+    }
+}
+
+fn check_ata(owner: &Pubkey, mint: &Pubkey, provided: Option<Pubkey>) -> Result<(), BuildError> {
+    let expected = associated_token_address(owner, mint);
+    match provided {
+        Some(provided) if provided != expected => Err(BuildError::AtaMismatch {
+            owner: *owner,
+            expected,
+            provided,
+        }),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use subscription_program::Interval;
+
+    use super::*;
+    use crate::pda::subscription_address;
+
+    fn builder() -> InitializeSubscriptionBuilder {
+        InitializeSubscriptionBuilder::new(
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        )
+        .amount_per_period(1_000)
+        .interval(Interval::Monthly)
+    }
+
+    #[test]
+    fn builds_with_the_derived_accounts() {
+        let builder = builder();
+        let (authority, recipient, mint) =
+            (builder.authority, builder.recipient, builder.token_mint);
+        let ix = builder
+            .user_token_account(associated_token_address(&authority, &mint))
+            .build()
+            .unwrap();
+
+        let keys: Vec<Pubkey> = ix.accounts.iter().map(|meta| meta.pubkey).collect();
+        assert!(keys.contains(&subscription_address(&authority, &recipient).0));
+        assert!(keys.contains(&associated_token_address(&authority, &mint)));
+        assert!(keys.contains(&associated_token_address(&recipient, &mint)));
+    }
+
+    #[test]
+    fn amount_and_interval_are_required() {
+        let authority = Pubkey::new_unique();
+        let bare = InitializeSubscriptionBuilder::new(
+            authority,
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+
+        assert_eq!(bare.clone().build(), Err(BuildError::MissingAmount));
+        assert_eq!(
+            bare.clone().amount_per_period(0).build(),
+            Err(BuildError::ZeroAmount)
+        );
+        assert_eq!(
+            bare.amount_per_period(1).build(),
+            Err(BuildError::MissingInterval)
+        );
+    }
+
+    #[test]
+    fn rejects_intervals_the_program_cannot_bill() {
+        for interval in [0, -2, -7] {
+            assert_eq!(
+                builder().interval_seconds(interval).build(),
+                Err(BuildError::InvalidInterval(interval))
+            );
+        }
+    }
+
+    #[test]
+    fn expiry_must_be_in_the_future() {
+        assert_eq!(
+            builder().expires_at(100).now(100).build(),
+            Err(BuildError::ExpiryInPast {
+                expires_at: 100,
+                now: 100
+            })
+        );
+        assert!(builder().expires_at(101).now(100).build().is_ok());
+        // Without a clock the program is left to check it
+        assert!(builder().expires_at(100).build().is_ok());
+    }
+
+    #[test]
+    fn rejects_subscribing_to_yourself() {
+        let wallet = Pubkey::new_unique();
+        let ix = InitializeSubscriptionBuilder::new(wallet, wallet, Pubkey::new_unique())
+            .amount_per_period(1)
+            .interval(Interval::Monthly)
+            .build();
+
+        assert_eq!(ix, Err(BuildError::SelfSubscription));
+    }
+
+    #[test]
+    fn token_accounts_must_be_the_atas() {
+        let builder = builder();
+        let (recipient, mint) = (builder.recipient, builder.token_mint);
+        let provided = Pubkey::new_unique();
+
+        assert_eq!(
+            builder.recipient_token_account(provided).build(),
+            Err(BuildError::AtaMismatch {
+                owner: recipient,
+                expected: associated_token_address(&recipient, &mint),
+                provided,
+            })
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! Client for the charity donations recipe program.
+//!
+//! Each donation owns a split-checkout split holding its charities and
+//! weights, at [`donation_split_address`]. Instructions that touch the split
+//! append the charities' token accounts in order, and [`due_donations`]
+//! builds the keeper's `donate` calls from the stored split.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use charity_donations::{accounts, instruction};
+
+pub use charity_donations::{Donation, ID as CHARITY_DONATIONS_PROGRAM_ID, SPLIT_ID};
+
+use crate::split_checkout::{split_address, Payee, SplitConfig, SPLIT_CHECKOUT_PROGRAM_ID};
+
+pub const DONATION_SEED: &[u8] = b"donation";
+
+/// Donation PDA for a (donor, donation_id) pair
+pub fn donation_address(donor: &Pubkey, donation_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[DONATION_SEED, donor.as_ref(), &donation_id.to_le_bytes()],
+        &CHARITY_DONATIONS_PROGRAM_ID,
+    )
+}
+
+/// Split PDA owned by a donation
+pub fn donation_split_address(donation: &Pubkey) -> Pubkey {
+    split_address(donation, SPLIT_ID).0
+}
+
+fn build(
+    accounts: impl ToAccountMetas,
+    charities: &[Payee],
+    data: impl InstructionData,
+) -> Instruction {
+    let mut metas = accounts.to_account_metas(None);
+    metas.extend(
+        charities
+            .iter()
+            .map(|charity| AccountMeta::new(charity.token_account, false)),
+    );
+    Instruction {
+        program_id: CHARITY_DONATIONS_PROGRAM_ID,
+        accounts: metas,
+        data: data.data(),
+    }
+}
+
+/// `charities` weights are bps adding up to 10000; `payer` can be a relayer
+#[allow(clippy::too_many_arguments)]
+pub fn create_donation(
+    donor: &Pubkey,
+    mint: &Pubkey,
+    donor_token_account: &Pubkey,
+    payer: &Pubkey,
+    donation_id: u64,
+    charities: &[Payee],
+    amount_per_period: u64,
+    interval_seconds: i64,
+) -> Instruction {
+    let donation = donation_address(donor, donation_id).0;
+    build(
+        accounts::CreateDonation {
+            donation,
+            split: donation_split_address(&donation),
+            donor: *donor,
+            mint: *mint,
+            donor_token_account: *donor_token_account,
+            payer: *payer,
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+            split_checkout_program: SPLIT_CHECKOUT_PROGRAM_ID,
+        },
+        charities,
+        instruction::CreateDonation {
+            donation_id,
+            charities: charities.to_vec(),
+            amount_per_period,
+            interval_seconds,
+        },
+    )
+}
+
+pub fn update_charities(donation: &Donation, charities: &[Payee]) -> Instruction {
+    build(
+        accounts::UpdateCharities {
+            donation: donation_address(&donation.donor, donation.donation_id).0,
+            split: donation.split,
+            donor: donation.donor,
+            split_checkout_program: SPLIT_CHECKOUT_PROGRAM_ID,
+        },
+        charities,
+        instruction::UpdateCharities {
+            charities: charities.to_vec(),
+        },
+    )
+}
+
+pub fn update_amount(
+    donation: &Donation,
+    amount_per_period: u64,
+    interval_seconds: i64,
+) -> Instruction {
+    build(
+        accounts::UpdateDonation {
+            donation: donation_address(&donation.donor, donation.donation_id).0,
+            donor: donation.donor,
+        },
+        &[],
+        instruction::UpdateAmount {
+            amount_per_period,
+            interval_seconds,
+        },
+    )
+}
+
+/// `split` is the donation's current split, whose charities are appended
+pub fn donate(donation: &Donation, split: &SplitConfig) -> Instruction {
+    build(
+        accounts::Donate {
+            donation: donation_address(&donation.donor, donation.donation_id).0,
+            split: donation.split,
+            donor_token_account: donation.donor_token_account,
+            token_program: spl_token::ID,
+            split_checkout_program: SPLIT_CHECKOUT_PROGRAM_ID,
+        },
+        &split.payees,
+        instruction::Donate {},
+    )
+}
+
+pub fn cancel_donation(donation: &Donation) -> Instruction {
+    build(
+        accounts::CancelDonation {
+            donation: donation_address(&donation.donor, donation.donation_id).0,
+            donor: donation.donor,
+            donor_token_account: donation.donor_token_account,
+            token_program: spl_token::ID,
+        },
+        &[],
+        instruction::CancelDonation {},
+    )
+}
+
+/// One keeper pass over donations and their splits: a `donate` for every
+/// donation due at `now`
+pub fn due_donations(donations: &[(Donation, SplitConfig)], now: i64) -> Vec<Instruction> {
+    donations
+        .iter()
+        .filter(|(donation, _)| donation.check_due(now).is_ok())
+        .map(|(donation, split)| donate(donation, split))
+        .collect()
+}
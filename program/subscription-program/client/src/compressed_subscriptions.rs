@@ -0,0 +1,408 @@
+//! Client for the compressed subscriptions recipe program.
+//!
+//! Compressed subscriptions have no account to fetch. Their state lives in
+//! Light state trees and is served by a Photon indexer, which also produces
+//! the validity proofs every instruction needs. This module builds the
+//! Photon requests, reads their responses and turns the result into
+//! instructions: tree accounts are packed into remaining accounts and
+//! referred to by index, the way Light's system program expects.
+
+use std::str::FromStr;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use compressed_subscriptions::{accounts, instruction};
+use serde_json::{json, Value};
+
+pub use compressed_subscriptions::{
+    account_compression_authority_address, address_seed, cpi_authority_address, delegate_address,
+    derive_address, registered_program_address, CompressedAccountMeta, CompressedProof,
+    CompressedSubscription, PackedAddressTreeInfo, PackedStateTreeInfo,
+    ACCOUNT_COMPRESSION_PROGRAM_ID, ADDRESS_TREE, ID as COMPRESSED_SUBSCRIPTIONS_PROGRAM_ID,
+    LIGHT_SYSTEM_PROGRAM_ID, NOOP_PROGRAM_ID,
+};
+
+use crate::error::{ClientError, Result};
+use crate::pda::associated_token_address;
+
+/// A Light state tree and the nullifier queue paired with it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StateTree {
+    pub tree: Pubkey,
+    pub queue: Pubkey,
+}
+
+/// The address queue paired with [`ADDRESS_TREE`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressQueue(pub Pubkey);
+
+/// A proof from Photon's `getValidityProof`, with the root each input or
+/// new address was proven against, in request order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidityProof {
+    pub proof: CompressedProof,
+    pub root_indices: Vec<u16>,
+}
+
+/// A compressed subscription as Photon indexes it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexedSubscription {
+    pub address: [u8; 32],
+    /// The leaf: what `getValidityProof` proves
+    pub hash: [u8; 32],
+    pub tree: Pubkey,
+    pub leaf_index: u32,
+    pub state: CompressedSubscription,
+}
+
+/// Remaining accounts for a Light CPI, each listed once; instructions refer
+/// to them by position
+#[derive(Debug, Default)]
+pub struct PackedAccounts {
+    keys: Vec<Pubkey>,
+}
+
+impl PackedAccounts {
+    /// Index of `key`, adding it if it is new
+    pub fn insert(&mut self, key: Pubkey) -> u8 {
+        match self.keys.iter().position(|known| *known == key) {
+            Some(index) => index as u8,
+            None => {
+                self.keys.push(key);
+                (self.keys.len() - 1) as u8
+            }
+        }
+    }
+
+    pub fn to_account_metas(&self) -> Vec<AccountMeta> {
+        self.keys
+            .iter()
+            .map(|key| AccountMeta::new(*key, false))
+            .collect()
+    }
+}
+
+fn photon_error(message: impl Into<String>) -> ClientError {
+    ClientError::Photon(message.into())
+}
+
+fn request(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params })
+}
+
+fn result_value(response: &Value) -> Result<&Value> {
+    if let Some(error) = response.get("error") {
+        return Err(photon_error(error.to_string()));
+    }
+    response
+        .pointer("/result/value")
+        .ok_or_else(|| photon_error("response has no result"))
+}
+
+fn base58_32(value: &Value, field: &str) -> Result<[u8; 32]> {
+    let text = value
+        .get(field)
+        .and_then(Value::as_str)
+        .ok_or_else(|| photon_error(format!("missing {field}")))?;
+    Pubkey::from_str(text)
+        .map(|key| key.to_bytes())
+        .map_err(|_| photon_error(format!("{field} is not 32 bytes of base58")))
+}
+
+fn bytes<const N: usize>(value: &Value, field: &str) -> Result<[u8; N]> {
+    let array = value
+        .get(field)
+        .and_then(Value::as_array)
+        .ok_or_else(|| photon_error(format!("missing proof.{field}")))?;
+    let bytes: Vec<u8> = array
+        .iter()
+        .map(|byte| byte.as_u64().and_then(|byte| u8::try_from(byte).ok()))
+        .collect::<Option<_>>()
+        .ok_or_else(|| photon_error(format!("proof.{field} is not bytes")))?;
+    bytes
+        .try_into()
+        .map_err(|_| photon_error(format!("proof.{field} is not {N} bytes")))
+}
+
+/// `getCompressedAccountsByOwner` for every subscription the program holds
+pub fn compressed_accounts_request() -> Value {
+    request(
+        "getCompressedAccountsByOwner",
+        json!({ "owner": COMPRESSED_SUBSCRIPTIONS_PROGRAM_ID.to_string() }),
+    )
+}
+
+/// The subscriptions in a `getCompressedAccountsByOwner` response. Accounts
+/// of other types are skipped.
+pub fn parse_compressed_accounts(response: &Value) -> Result<Vec<IndexedSubscription>> {
+    let items = result_value(response)?
+        .get("items")
+        .and_then(Value::as_array)
+        .ok_or_else(|| photon_error("missing items"))?;
+
+    let mut subscriptions = Vec::new();
+    for item in items {
+        let Some(data) = item.get("data").filter(|data| !data.is_null()) else {
+            continue;
+        };
+        let discriminator = data
+            .get("discriminator")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| photon_error("missing data.discriminator"))?
+            .to_le_bytes();
+        let encoded = data
+            .get("data")
+            .and_then(Value::as_str)
+            .ok_or_else(|| photon_error("missing data.data"))?;
+        let raw = BASE64_STANDARD
+            .decode(encoded)
+            .map_err(|err| photon_error(err.to_string()))?;
+        let Ok(state) = CompressedSubscription::decode(&discriminator, &raw) else {
+            continue;
+        };
+
+        subscriptions.push(IndexedSubscription {
+            address: base58_32(item, "address")?,
+            hash: base58_32(item, "hash")?,
+            tree: Pubkey::from(base58_32(item, "tree")?),
+            leaf_index: item
+                .get("leafIndex")
+                .and_then(Value::as_u64)
+                .and_then(|index| u32::try_from(index).ok())
+                .ok_or_else(|| photon_error("missing leafIndex"))?,
+            state,
+        });
+    }
+    Ok(subscriptions)
+}
+
+/// `getValidityProof` that the `hashes` are current leaves and the
+/// `new_addresses` are not yet taken in [`ADDRESS_TREE`]
+pub fn validity_proof_request(hashes: &[[u8; 32]], new_addresses: &[[u8; 32]]) -> Value {
+    let hashes: Vec<String> = hashes
+        .iter()
+        .map(|hash| Pubkey::from(*hash).to_string())
+        .collect();
+    let new_addresses: Vec<Value> = new_addresses
+        .iter()
+        .map(|address| {
+            json!({
+                "address": Pubkey::from(*address).to_string(),
+                "tree": ADDRESS_TREE.to_string(),
+            })
+        })
+        .collect();
+    request(
+        "getValidityProof",
+        json!({ "hashes": hashes, "newAddressesWithTrees": new_addresses }),
+    )
+}
+
+pub fn parse_validity_proof(response: &Value) -> Result<ValidityProof> {
+    let value = result_value(response)?;
+    let proof = value
+        .get("compressedProof")
+        .ok_or_else(|| photon_error("missing compressedProof"))?;
+    let root_indices = value
+        .get("rootIndices")
+        .and_then(Value::as_array)
+        .ok_or_else(|| photon_error("missing rootIndices"))?
+        .iter()
+        .map(|index| index.as_u64().and_then(|index| u16::try_from(index).ok()))
+        .collect::<Option<_>>()
+        .ok_or_else(|| photon_error("rootIndices are not u16"))?;
+
+    Ok(ValidityProof {
+        proof: CompressedProof {
+            a: bytes(proof, "a")?,
+            b: bytes(proof, "b")?,
+            c: bytes(proof, "c")?,
+        },
+        root_indices,
+    })
+}
+
+/// The address a pair's subscription gets
+pub fn subscription_address(authority: &Pubkey, recipient: &Pubkey) -> [u8; 32] {
+    derive_address(&address_seed(authority, recipient), &ADDRESS_TREE)
+}
+
+fn light_accounts(fee_payer: &Pubkey) -> accounts::LightSystemAccounts {
+    accounts::LightSystemAccounts {
+        fee_payer: *fee_payer,
+        cpi_authority: cpi_authority_address().0,
+        registered_program_pda: registered_program_address(),
+        noop_program: NOOP_PROGRAM_ID,
+        account_compression_authority: account_compression_authority_address(),
+        account_compression_program: ACCOUNT_COMPRESSION_PROGRAM_ID,
+        self_program: COMPRESSED_SUBSCRIPTIONS_PROGRAM_ID,
+        light_system_program: LIGHT_SYSTEM_PROGRAM_ID,
+        system_program: system_program::ID,
+    }
+}
+
+fn build(
+    accounts: impl ToAccountMetas,
+    data: impl InstructionData,
+    packed: &PackedAccounts,
+) -> Instruction {
+    let mut metas = accounts.to_account_metas(None);
+    metas.extend(packed.to_account_metas());
+    Instruction {
+        program_id: COMPRESSED_SUBSCRIPTIONS_PROGRAM_ID,
+        accounts: metas,
+        data: data.data(),
+    }
+}
+
+/// Where an indexed subscription's leaf is, with its successor going to
+/// `output`
+fn account_meta(
+    subscription: &IndexedSubscription,
+    state_tree: &StateTree,
+    output: &StateTree,
+    root_index: u16,
+    packed: &mut PackedAccounts,
+) -> Result<CompressedAccountMeta> {
+    if subscription.tree != state_tree.tree {
+        return Err(photon_error(format!(
+            "subscription is in tree {}, not {}",
+            subscription.tree, state_tree.tree
+        )));
+    }
+    Ok(CompressedAccountMeta {
+        tree_info: PackedStateTreeInfo {
+            merkle_tree_pubkey_index: packed.insert(state_tree.tree),
+            queue_pubkey_index: packed.insert(state_tree.queue),
+            leaf_index: subscription.leaf_index,
+            root_index,
+        },
+        address: subscription.address,
+        output_state_tree_index: packed.insert(output.tree),
+    })
+}
+
+/// Subscribe `authority` to `recipient` from / into their ATAs for
+/// `token_mint`. `proof` proves the pair's address is new; `payer` can be a
+/// relayer.
+#[allow(clippy::too_many_arguments)]
+pub fn open_subscription(
+    authority: &Pubkey,
+    recipient: &Pubkey,
+    token_mint: &Pubkey,
+    payer: &Pubkey,
+    proof: &ValidityProof,
+    address_queue: &AddressQueue,
+    output: &StateTree,
+    amount_per_period: u64,
+    interval_seconds: i64,
+    expires_at: Option<i64>,
+) -> Instruction {
+    let mut packed = PackedAccounts::default();
+    let address_tree = PackedAddressTreeInfo {
+        address_merkle_tree_pubkey_index: packed.insert(ADDRESS_TREE),
+        address_queue_pubkey_index: packed.insert(address_queue.0),
+        root_index: proof.root_indices.first().copied().unwrap_or_default(),
+    };
+    let output_tree_index = packed.insert(output.tree);
+
+    build(
+        accounts::OpenSubscription {
+            authority: *authority,
+            recipient: *recipient,
+            user_token_account: associated_token_address(authority, token_mint),
+            recipient_token_account: associated_token_address(recipient, token_mint),
+            token_mint: *token_mint,
+            delegate: delegate_address(authority, recipient).0,
+            token_program: spl_token::ID,
+            light: light_accounts(payer),
+        },
+        instruction::OpenSubscription {
+            proof: proof.proof,
+            address_tree,
+            output_tree_index,
+            amount_per_period,
+            interval_seconds,
+            expires_at,
+        },
+        &packed,
+    )
+}
+
+/// Charge a due subscription; `proof` proves its current leaf. Anyone can
+/// send it, typically a keeper paying as `payer`.
+pub fn charge_subscription(
+    subscription: &IndexedSubscription,
+    proof: &ValidityProof,
+    state_tree: &StateTree,
+    output: &StateTree,
+    payer: &Pubkey,
+) -> Result<Instruction> {
+    let mut packed = PackedAccounts::default();
+    let root_index = proof.root_indices.first().copied().unwrap_or_default();
+    let account = account_meta(subscription, state_tree, output, root_index, &mut packed)?;
+    let state = &subscription.state;
+
+    Ok(build(
+        accounts::ChargeSubscription {
+            user_token_account: state.user_token_account,
+            recipient_token_account: state.recipient_token_account,
+            delegate: delegate_address(&state.authority, &state.recipient).0,
+            token_program: spl_token::ID,
+            light: light_accounts(payer),
+        },
+        instruction::ChargeSubscription {
+            proof: proof.proof,
+            account,
+            current: state.clone(),
+        },
+        &packed,
+    ))
+}
+
+/// Cancel as the subscriber; `proof` proves the current leaf
+pub fn cancel_subscription(
+    subscription: &IndexedSubscription,
+    proof: &ValidityProof,
+    state_tree: &StateTree,
+    payer: &Pubkey,
+) -> Result<Instruction> {
+    let mut packed = PackedAccounts::default();
+    let root_index = proof.root_indices.first().copied().unwrap_or_default();
+    let account = account_meta(
+        subscription,
+        state_tree,
+        state_tree,
+        root_index,
+        &mut packed,
+    )?;
+    let state = &subscription.state;
+
+    Ok(build(
+        accounts::CancelSubscription {
+            authority: state.authority,
+            user_token_account: state.user_token_account,
+            token_program: spl_token::ID,
+            light: light_accounts(payer),
+        },
+        instruction::CancelSubscription {
+            proof: proof.proof,
+            account,
+            current: state.clone(),
+        },
+        &packed,
+    ))
+}
+
+/// The indexed subscriptions a keeper should charge at `now`
+pub fn due_subscriptions(
+    subscriptions: &[IndexedSubscription],
+    now: i64,
+) -> Vec<&IndexedSubscription> {
+    subscriptions
+        .iter()
+        .filter(|subscription| subscription.state.check_chargeable(now).is_ok())
+        .collect()
+}
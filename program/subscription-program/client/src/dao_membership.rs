@@ -0,0 +1,225 @@
+//! Client for the DAO membership recipe program.
+//!
+//! Dues are an ordinary subscription from the member to the DAO's treasury,
+//! created with [`crate::instructions::initialize_subscription`]. Stored
+//! statuses are kept current by a crank: [`due_refreshes`] returns a
+//! `refresh_member` for every member whose dues changed standing.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use dao_membership::{accounts, instruction};
+
+pub use dao_membership::{Dao, Member, Proposal, Vote, ID as DAO_MEMBERSHIP_PROGRAM_ID};
+
+use crate::pda::subscription_address;
+use crate::Subscription;
+
+pub const DAO_SEED: &[u8] = b"dao";
+pub const MEMBER_SEED: &[u8] = b"member";
+pub const PROPOSAL_SEED: &[u8] = b"proposal";
+pub const VOTE_SEED: &[u8] = b"vote";
+
+/// DAO PDA of an admin; one per admin
+pub fn dao_address(admin: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[DAO_SEED, admin.as_ref()], &DAO_MEMBERSHIP_PROGRAM_ID)
+}
+
+/// Member PDA of an owner in a DAO
+pub fn member_address(dao: &Pubkey, owner: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[MEMBER_SEED, dao.as_ref(), owner.as_ref()],
+        &DAO_MEMBERSHIP_PROGRAM_ID,
+    )
+}
+
+/// Proposal PDA for a DAO's `proposal_id`
+pub fn proposal_address(dao: &Pubkey, proposal_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[PROPOSAL_SEED, dao.as_ref(), &proposal_id.to_le_bytes()],
+        &DAO_MEMBERSHIP_PROGRAM_ID,
+    )
+}
+
+/// Vote record of a voter on a proposal
+pub fn vote_address(proposal: &Pubkey, voter: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[VOTE_SEED, proposal.as_ref(), voter.as_ref()],
+        &DAO_MEMBERSHIP_PROGRAM_ID,
+    )
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: DAO_MEMBERSHIP_PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// Members pay at least `dues_amount` every `dues_interval` to `treasury`
+pub fn create_dao(
+    admin: &Pubkey,
+    payer: &Pubkey,
+    treasury: &Pubkey,
+    dues_amount: u64,
+    dues_interval: i64,
+    grace_period: i64,
+) -> Instruction {
+    build(
+        accounts::CreateDao {
+            dao: dao_address(admin).0,
+            admin: *admin,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreateDao {
+            treasury: *treasury,
+            dues_amount,
+            dues_interval,
+            grace_period,
+        },
+    )
+}
+
+pub fn update_dues(
+    admin: &Pubkey,
+    dues_amount: u64,
+    dues_interval: i64,
+    grace_period: i64,
+) -> Instruction {
+    build(
+        accounts::UpdateDao {
+            dao: dao_address(admin).0,
+            admin: *admin,
+        },
+        instruction::UpdateDues {
+            dues_amount,
+            dues_interval,
+            grace_period,
+        },
+    )
+}
+
+pub fn admit_member(admin: &Pubkey, owner: &Pubkey, payer: &Pubkey, weight: u64) -> Instruction {
+    let dao = dao_address(admin).0;
+    build(
+        accounts::AdmitMember {
+            dao,
+            member: member_address(&dao, owner).0,
+            admin: *admin,
+            owner: *owner,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::AdmitMember { weight },
+    )
+}
+
+pub fn set_weight(admin: &Pubkey, owner: &Pubkey, weight: u64) -> Instruction {
+    let dao = dao_address(admin).0;
+    build(
+        accounts::SetWeight {
+            dao,
+            member: member_address(&dao, owner).0,
+            admin: *admin,
+        },
+        instruction::SetWeight { weight },
+    )
+}
+
+pub fn refresh_member(dao: &Dao, owner: &Pubkey) -> Instruction {
+    let address = dao_address(&dao.admin).0;
+    build(
+        accounts::RefreshMember {
+            dao: address,
+            member: member_address(&address, owner).0,
+            subscription: subscription_address(owner, &dao.treasury).0,
+        },
+        instruction::RefreshMember {},
+    )
+}
+
+pub fn remove_member(admin: &Pubkey, owner: &Pubkey) -> Instruction {
+    let dao = dao_address(admin).0;
+    build(
+        accounts::RemoveMember {
+            dao,
+            member: member_address(&dao, owner).0,
+            admin: *admin,
+        },
+        instruction::RemoveMember {},
+    )
+}
+
+/// Opens proposal `dao.proposal_count`; `payer` can be a relayer
+pub fn create_proposal(
+    dao: &Dao,
+    owner: &Pubkey,
+    payer: &Pubkey,
+    description_hash: [u8; 32],
+    voting_end: i64,
+) -> Instruction {
+    let address = dao_address(&dao.admin).0;
+    build(
+        accounts::CreateProposal {
+            dao: address,
+            proposal: proposal_address(&address, dao.proposal_count).0,
+            member: member_address(&address, owner).0,
+            owner: *owner,
+            subscription: subscription_address(owner, &dao.treasury).0,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreateProposal {
+            description_hash,
+            voting_end,
+        },
+    )
+}
+
+/// `payer` can be a relayer
+pub fn cast_vote(
+    dao: &Dao,
+    proposal_id: u64,
+    owner: &Pubkey,
+    payer: &Pubkey,
+    approve: bool,
+) -> Instruction {
+    let address = dao_address(&dao.admin).0;
+    let proposal = proposal_address(&address, proposal_id).0;
+    build(
+        accounts::CastVote {
+            dao: address,
+            proposal,
+            vote: vote_address(&proposal, owner).0,
+            member: member_address(&address, owner).0,
+            owner: *owner,
+            subscription: subscription_address(owner, &dao.treasury).0,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CastVote { approve },
+    )
+}
+
+/// What `member` would vote with at `now`: their weight while their dues
+/// are paid, otherwise nothing
+pub fn voting_weight(dao: &Dao, member: &Member, subscription: &Subscription, now: i64) -> u64 {
+    if dao.check_dues(subscription, now).is_ok() {
+        member.weight
+    } else {
+        0
+    }
+}
+
+/// One crank pass over a DAO's members and their dues subscriptions: a
+/// `refresh_member` for every member whose stored status no longer matches
+/// their dues at `now`
+pub fn due_refreshes(dao: &Dao, members: &[(Member, Subscription)], now: i64) -> Vec<Instruction> {
+    members
+        .iter()
+        .filter(|(member, subscription)| member.active != dao.check_dues(subscription, now).is_ok())
+        .map(|(member, _)| refresh_member(dao, &member.owner))
+        .collect()
+}
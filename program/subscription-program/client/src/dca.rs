@@ -0,0 +1,142 @@
+//! Client for the dollar-cost-averaging recipe program.
+//!
+//! Each cycle needs a fresh Jupiter route, so the keeper helper only picks
+//! the due plans. For each one the keeper quotes `amount_per_cycle` from the
+//! plan's input vault, with the DCA PDA as the swapping user and the owner's
+//! output account as the destination, and passes the route to
+//! [`execute_cycle`].
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use dca::{accounts, instruction};
+
+pub use dca::{Dca, ID as DCA_PROGRAM_ID, JUPITER_PROGRAM_ID};
+
+use crate::pda::associated_token_address;
+
+pub const DCA_SEED: &[u8] = b"dca";
+
+/// DCA PDA of `owner` for one input/output pair
+pub fn dca_address(owner: &Pubkey, input_mint: &Pubkey, output_mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            DCA_SEED,
+            owner.as_ref(),
+            input_mint.as_ref(),
+            output_mint.as_ref(),
+        ],
+        &DCA_PROGRAM_ID,
+    )
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: DCA_PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// Spends from the owner's ATA for `input_mint` and buys into their ATA for
+/// `output_mint`. The input vault, the DCA PDA's ATA for `input_mint`, must
+/// exist; create it beforehand with `CreateIdempotent`.
+pub fn open_dca(
+    owner: &Pubkey,
+    input_mint: &Pubkey,
+    output_mint: &Pubkey,
+    payer: &Pubkey,
+    amount_per_cycle: u64,
+    interval_seconds: i64,
+    min_out_per_cycle: u64,
+) -> Instruction {
+    let dca = dca_address(owner, input_mint, output_mint).0;
+    build(
+        accounts::OpenDca {
+            dca,
+            owner: *owner,
+            input_mint: *input_mint,
+            output_mint: *output_mint,
+            user_input_account: associated_token_address(owner, input_mint),
+            user_output_account: associated_token_address(owner, output_mint),
+            input_vault: associated_token_address(&dca, input_mint),
+            payer: *payer,
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+        },
+        instruction::OpenDca {
+            amount_per_cycle,
+            interval_seconds,
+            min_out_per_cycle,
+        },
+    )
+}
+
+pub fn update_dca(
+    dca: &Dca,
+    amount_per_cycle: u64,
+    interval_seconds: i64,
+    min_out_per_cycle: u64,
+) -> Instruction {
+    build(
+        accounts::UpdateDca {
+            dca: dca_address(&dca.owner, &dca.input_mint, &dca.output_mint).0,
+            owner: dca.owner,
+        },
+        instruction::UpdateDca {
+            amount_per_cycle,
+            interval_seconds,
+            min_out_per_cycle,
+        },
+    )
+}
+
+/// Runs one cycle with a Jupiter route: `route_data` is the swap
+/// instruction's data and `route_accounts` its accounts. They are appended
+/// with the DCA PDA's signer flag cleared; the program signs for it.
+pub fn execute_cycle(
+    dca: &Dca,
+    route_data: Vec<u8>,
+    route_accounts: &[AccountMeta],
+) -> Instruction {
+    let dca_key = dca_address(&dca.owner, &dca.input_mint, &dca.output_mint).0;
+    let mut ix = build(
+        accounts::ExecuteCycle {
+            dca: dca_key,
+            user_input_account: dca.user_input_account,
+            user_output_account: dca.user_output_account,
+            input_vault: dca.input_vault,
+            token_program: spl_token::ID,
+            jupiter_program: JUPITER_PROGRAM_ID,
+        },
+        instruction::ExecuteCycle { route_data },
+    );
+    ix.accounts
+        .extend(route_accounts.iter().map(|meta| AccountMeta {
+            is_signer: meta.is_signer && meta.pubkey != dca_key,
+            ..meta.clone()
+        }));
+    ix
+}
+
+/// Returns leftovers to, and revokes the delegation on, the owner's input account
+pub fn close_dca(dca: &Dca) -> Instruction {
+    build(
+        accounts::CloseDca {
+            dca: dca_address(&dca.owner, &dca.input_mint, &dca.output_mint).0,
+            owner: dca.owner,
+            user_input_account: dca.user_input_account,
+            input_vault: dca.input_vault,
+            token_program: spl_token::ID,
+        },
+        instruction::CloseDca {},
+    )
+}
+
+/// One keeper pass: the plans with a cycle due at `now`, each still needing
+/// a route before it can go to [`execute_cycle`]
+pub fn due_cycles(dcas: &[Dca], now: i64) -> Vec<&Dca> {
+    dcas.iter()
+        .filter(|dca| dca.check_due(now).is_ok())
+        .collect()
+}
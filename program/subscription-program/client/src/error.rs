@@ -0,0 +1,375 @@
+use anchor_lang::error::ErrorCode as AnchorErrorCode;
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::system_program;
+use num_traits::FromPrimitive;
+use solana_instruction::error::InstructionError;
+use solana_system_interface::error::SystemError;
+use spl_token::error::TokenError;
+use subscription_program::ErrorCode;
+use thiserror::Error;
+
+use crate::PROGRAM_ID;
+
+pub type Result<T> = std::result::Result<T, ClientError>;
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+    #[error("account {0} not found")]
+    AccountNotFound(Pubkey),
+
+    #[error("account {address} could not be decoded: {source}")]
+    Decode {
+        address: Pubkey,
+        source: anchor_lang::error::Error,
+    },
+
+    #[error("event could not be decoded: {0}")]
+    EventDecode(String),
+
+    #[error("message could not be compiled: {0}")]
+    CompileMessage(String),
+
+    #[error("transaction is {size} bytes, over the packet limit")]
+    TransactionTooLarge { size: usize },
+
+    #[error("{0} is not a required signer of this message")]
+    UnexpectedSigner(Pubkey),
+
+    #[error("signature for {0} does not verify against the message")]
+    InvalidSignature(Pubkey),
+
+    #[error("missing signatures from {0:?}")]
+    MissingSignatures(Vec<Pubkey>),
+
+    #[error("signing failed: {0}")]
+    Signing(String),
+
+    #[error("offline transaction: {0}")]
+    Offline(String),
+
+    #[error("blockhash expired before the transaction landed ({attempts} attempts)")]
+    BlockhashExpired { attempts: usize },
+
+    #[error("nonce account {0} advanced before the transaction landed")]
+    NonceAdvanced(Pubkey),
+
+    #[error("transaction {signature} failed: {error}")]
+    TransactionFailed {
+        signature: solana_signature::Signature,
+        error: solana_transaction_error::TransactionError,
+    },
+
+    #[error("Photon response: {0}")]
+    Photon(String),
+
+    #[error("unexpected RPC response: {0}")]
+    UnexpectedResponse(String),
+
+    /// Boxed: the RPC client's error is several hundred bytes, and every
+    /// `Result` of this crate would carry it
+    #[cfg(feature = "rpc")]
+    #[error(transparent)]
+    Rpc(Box<solana_client::client_error::ClientError>),
+}
+
+#[cfg(feature = "rpc")]
+impl From<solana_client::client_error::ClientError> for ClientError {
+    fn from(error: solana_client::client_error::ClientError) -> Self {
+        ClientError::Rpc(Box::new(error))
+    }
+}
+
+/// Every on-chain `ErrorCode`, used to map a custom error number back to its variant
+const PROGRAM_ERRORS: &[ErrorCode] = &[
+    ErrorCode::SubscriptionInactive,
+    ErrorCode::SubscriptionExpired,
+    ErrorCode::IntervalNotMet,
+    ErrorCode::SubscriptionAlreadyCancelled,
+    ErrorCode::InvalidTokenAccount,
+    ErrorCode::SubscriptionStillActive,
+    ErrorCode::ArithmeticOverflow,
+    ErrorCode::InvalidWalletAuthority,
+    ErrorCode::NotCalledByWallet,
+    ErrorCode::DelegatedToPolicy,
+    ErrorCode::InvalidMerchantConfig,
+    ErrorCode::TooManyFundingSources,
+    ErrorCode::InvalidFundingSource,
+    ErrorCode::SubscriptionAlreadyMigrated,
+    ErrorCode::InvalidChargeThread,
+    ErrorCode::InvalidAttestation,
+    ErrorCode::InvalidChargeTask,
+    ErrorCode::InvalidKeeperLease,
+    ErrorCode::NotALeaseKeeper,
+    ErrorCode::KeeperLeaseHeld,
+    ErrorCode::KeeperLeaseNotHeld,
+    ErrorCode::InvalidInterval,
+    ErrorCode::InvalidMultisig,
+    ErrorCode::NotMerchantAuthority,
+    ErrorCode::MultisigApprovalRequired,
+    ErrorCode::InvalidPayoutAccount,
+    ErrorCode::PayoutChangeTimelocked,
+    ErrorCode::InvalidVaultTokenAccount,
+    ErrorCode::InvalidWithdrawal,
+    ErrorCode::InvalidHoldback,
+    ErrorCode::RevenueHeldBack,
+    ErrorCode::InvalidWithdrawalLimit,
+    ErrorCode::WithdrawalLimitLocked,
+    ErrorCode::WithdrawalLimitExceeded,
+    ErrorCode::InvalidWithdrawalDestination,
+    ErrorCode::TooManyWithdrawalDestinations,
+    ErrorCode::DestinationNotApproved,
+    ErrorCode::InvalidSlaCommitment,
+    ErrorCode::SlaCommitmentActive,
+    ErrorCode::InvalidDowntime,
+    ErrorCode::NoSlaCredit,
+    ErrorCode::InsufficientHeldRevenue,
+    ErrorCode::InvalidSlaDiscount,
+    ErrorCode::InvalidAmount,
+    ErrorCode::InvalidPriceUpdate,
+    ErrorCode::StalePrice,
+    ErrorCode::UsdPriceRequired,
+    ErrorCode::InvalidUsdPeg,
+    ErrorCode::InvalidFallbackMint,
+    ErrorCode::InvalidFallbackPayment,
+    ErrorCode::PrimaryCanPay,
+    ErrorCode::PeriodAlreadyCharged,
+    ErrorCode::InvalidDepositAccount,
+    ErrorCode::DepositLocked,
+    ErrorCode::InvalidPlan,
+    ErrorCode::CadenceUnchanged,
+    ErrorCode::PlanNotMetered,
+    ErrorCode::NoTierUpgrade,
+    ErrorCode::TierUpgradeVetoClosed,
+    ErrorCode::TierUpgradeVetoOpen,
+    ErrorCode::NoOverageRate,
+    ErrorCode::OverageExceedsUsage,
+    ErrorCode::InvalidSpendAlerts,
+    ErrorCode::InvalidSpendLimit,
+    ErrorCode::SubscriberDenied,
+    ErrorCode::SubscriberNotAllowlisted,
+    ErrorCode::PlanFull,
+    ErrorCode::PlanNotFull,
+    ErrorCode::WaitlistEmpty,
+    ErrorCode::WaitlistEntryPromotable,
+    ErrorCode::InvalidIntroPricing,
+    ErrorCode::InvalidPriceRamp,
+    ErrorCode::PlanSubscriptionRequired,
+    ErrorCode::PricedByPlan,
+    ErrorCode::PricedInUsd,
+    ErrorCode::SpendLimitExceeded,
+    ErrorCode::InvalidPlatformFee,
+    ErrorCode::EmptyPlatformConfigChange,
+    ErrorCode::PlatformConfigChangeTimelocked,
+];
+
+/// Framework errors the program's account validation can realistically raise
+const ANCHOR_ERRORS: &[AnchorErrorCode] = &[
+    AnchorErrorCode::InstructionFallbackNotFound,
+    AnchorErrorCode::InstructionDidNotDeserialize,
+    AnchorErrorCode::ConstraintMut,
+    AnchorErrorCode::ConstraintHasOne,
+    AnchorErrorCode::ConstraintSigner,
+    AnchorErrorCode::ConstraintRaw,
+    AnchorErrorCode::ConstraintSeeds,
+    AnchorErrorCode::ConstraintAddress,
+    AnchorErrorCode::AccountDiscriminatorMismatch,
+    AnchorErrorCode::AccountDidNotDeserialize,
+    AnchorErrorCode::AccountNotEnoughKeys,
+    AnchorErrorCode::AccountOwnedByWrongProgram,
+    AnchorErrorCode::AccountNotSigner,
+    AnchorErrorCode::AccountNotSystemOwned,
+    AnchorErrorCode::AccountNotInitialized,
+];
+
+/// A failed transaction's custom error, decoded into something a relayer or
+/// CLI can show a human ("Not enough time has passed since last charge"
+/// instead of `0x1772`).
+#[derive(Debug, Clone, Error)]
+pub enum SubscriptionError {
+    #[error("{0}")]
+    Program(ErrorCode),
+
+    #[error("{0}")]
+    Anchor(AnchorErrorCode),
+
+    #[error("token program: {0}")]
+    Token(TokenError),
+
+    #[error("system program: {0}")]
+    System(SystemError),
+
+    #[error("program {program_id} failed with custom error {code:#x}")]
+    Unknown { program_id: Pubkey, code: u32 },
+}
+
+impl SubscriptionError {
+    /// Decode `code` as raised by `program_id` (ours, a CPI'd token program, or
+    /// the system program). The same number means different things per program.
+    pub fn from_custom(program_id: &Pubkey, code: u32) -> Self {
+        let unknown = SubscriptionError::Unknown {
+            program_id: *program_id,
+            code,
+        };
+
+        if *program_id == PROGRAM_ID {
+            PROGRAM_ERRORS
+                .iter()
+                .find(|error| u32::from(**error) == code)
+                .map(|error| SubscriptionError::Program(*error))
+                .or_else(|| {
+                    ANCHOR_ERRORS
+                        .iter()
+                        .find(|error| u32::from(**error) == code)
+                        .map(|error| SubscriptionError::Anchor(*error))
+                })
+                .unwrap_or(unknown)
+        } else if *program_id == spl_token::ID {
+            TokenError::from_u32(code)
+                .map(SubscriptionError::Token)
+                .unwrap_or(unknown)
+        } else if *program_id == system_program::ID {
+            SystemError::from_u32(code)
+                .map(SubscriptionError::System)
+                .unwrap_or(unknown)
+        } else {
+            unknown
+        }
+    }
+
+    /// Decode the error of an instruction that invoked `program_id` at the top level.
+    /// CPI failures surface with the caller's index, so prefer [`Self::from_logs`]
+    /// when logs are available.
+    pub fn from_instruction_error(program_id: &Pubkey, error: &InstructionError) -> Option<Self> {
+        match error {
+            InstructionError::Custom(code) => Some(Self::from_custom(program_id, *code)),
+            _ => None,
+        }
+    }
+
+    /// Find the innermost `Program <id> failed: custom program error: 0x..` line
+    pub fn from_logs<S: AsRef<str>>(logs: &[S]) -> Option<Self> {
+        logs.iter().find_map(|log| {
+            let rest = log.as_ref().strip_prefix("Program ")?;
+            let (program_id, rest) = rest.split_once(" failed: custom program error: ")?;
+            let code = u32::from_str_radix(rest.trim().trim_start_matches("0x"), 16).ok()?;
+            Some(Self::from_custom(&program_id.parse().ok()?, code))
+        })
+    }
+
+    /// Whether a keeper should try the same charge again later
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            SubscriptionError::Program(ErrorCode::IntervalNotMet)
+                | SubscriptionError::Token(TokenError::InsufficientFunds)
+        )
+    }
+}
+
+#[cfg(feature = "rpc")]
+impl ClientError {
+    /// The decoded program error behind a failed send/simulate, if any
+    pub fn subscription_error(&self) -> Option<SubscriptionError> {
+        use solana_client::client_error::ClientErrorKind;
+        use solana_client::rpc_request::{RpcError, RpcResponseErrorData};
+
+        let ClientError::Rpc(error) = self else {
+            return None;
+        };
+        match error.kind() {
+            ClientErrorKind::RpcError(RpcError::RpcResponseError {
+                data: RpcResponseErrorData::SendTransactionPreflightFailure(result),
+                ..
+            }) => SubscriptionError::from_logs(result.logs.as_deref()?),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+
+    const IDL: &str = include_str!(
+        "../../programs/subscription-program/tests/snapshots/subscription_program.json"
+    );
+
+    #[test]
+    fn every_program_error_decodes() {
+        let idl: Value = serde_json::from_str(IDL).unwrap();
+        let errors = idl["errors"].as_array().unwrap();
+        assert_eq!(errors.len(), PROGRAM_ERRORS.len());
+
+        for error in errors {
+            let code = error["code"].as_u64().unwrap() as u32;
+            match SubscriptionError::from_custom(&PROGRAM_ID, code) {
+                SubscriptionError::Program(decoded) => {
+                    assert_eq!(decoded.name(), error["name"].as_str().unwrap());
+                }
+                other => panic!("{code} decoded as {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn codes_are_read_per_program() {
+        assert!(matches!(
+            SubscriptionError::from_custom(&PROGRAM_ID, 6002),
+            SubscriptionError::Program(ErrorCode::IntervalNotMet)
+        ));
+        assert!(matches!(
+            SubscriptionError::from_custom(&PROGRAM_ID, AnchorErrorCode::ConstraintSeeds.into()),
+            SubscriptionError::Anchor(AnchorErrorCode::ConstraintSeeds)
+        ));
+        assert!(matches!(
+            SubscriptionError::from_custom(&spl_token::ID, 1),
+            SubscriptionError::Token(TokenError::InsufficientFunds)
+        ));
+        assert!(matches!(
+            SubscriptionError::from_custom(&system_program::ID, 1),
+            SubscriptionError::System(SystemError::ResultWithNegativeLamports)
+        ));
+    }
+
+    #[test]
+    fn unknown_codes_keep_program_and_number() {
+        let other = Pubkey::new_unique();
+        for (program_id, code) in [
+            (PROGRAM_ID, 5_999),
+            (spl_token::ID, 6_000),
+            (system_program::ID, 100),
+            (other, 1),
+        ] {
+            match SubscriptionError::from_custom(&program_id, code) {
+                SubscriptionError::Unknown {
+                    program_id: decoded,
+                    code: number,
+                } => assert_eq!((decoded, number), (program_id, code)),
+                error => panic!("{program_id} {code} decoded as {error:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn innermost_failure_is_read_from_logs() {
+        let logs = [
+            format!("Program {PROGRAM_ID} invoke [1]"),
+            format!("Program {} invoke [2]", spl_token::ID),
+            "Program log: Error: insufficient funds".to_string(),
+            format!(
+                "Program {} failed: custom program error: 0x1",
+                spl_token::ID
+            ),
+            format!("Program {PROGRAM_ID} failed: custom program error: 0x1"),
+        ];
+
+        let error = SubscriptionError::from_logs(&logs).unwrap();
+        assert!(matches!(
+            error,
+            SubscriptionError::Token(TokenError::InsufficientFunds)
+        ));
+        assert!(error.is_retryable());
+    }
+}
@@ -0,0 +1,160 @@
+//! Client for the escrow recipe program.
+//!
+//! Shares the subscription client's transaction, sending and offline-signing
+//! code; only PDA derivation, instruction builders and the keeper check live
+//! here. Vaults are always the escrow PDA's ATA.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use escrow::{accounts, instruction};
+
+pub use escrow::{Escrow, EscrowState, Resolution, ID as ESCROW_PROGRAM_ID};
+
+use crate::pda::associated_token_address;
+
+pub const ESCROW_SEED: &[u8] = b"escrow";
+
+/// Escrow PDA for a (buyer, seller, escrow_id) triple
+pub fn escrow_address(buyer: &Pubkey, seller: &Pubkey, escrow_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            ESCROW_SEED,
+            buyer.as_ref(),
+            seller.as_ref(),
+            &escrow_id.to_le_bytes(),
+        ],
+        &ESCROW_PROGRAM_ID,
+    )
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: ESCROW_PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// Fund an escrow from the buyer's ATA. The vault ATA must exist, so prepend a
+/// `CreateIdempotent` for `associated_token_address(&escrow, mint)`.
+#[allow(clippy::too_many_arguments)]
+pub fn create_escrow(
+    buyer: &Pubkey,
+    seller: &Pubkey,
+    arbiter: &Pubkey,
+    mint: &Pubkey,
+    payer: &Pubkey,
+    escrow_id: u64,
+    amount: u64,
+    delivery_deadline: i64,
+    review_period: i64,
+) -> Instruction {
+    let escrow = escrow_address(buyer, seller, escrow_id).0;
+    build(
+        accounts::CreateEscrow {
+            escrow,
+            buyer: *buyer,
+            seller: *seller,
+            arbiter: *arbiter,
+            mint: *mint,
+            buyer_token_account: associated_token_address(buyer, mint),
+            seller_token_account: associated_token_address(seller, mint),
+            vault: associated_token_address(&escrow, mint),
+            token_program: spl_token::ID,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreateEscrow {
+            escrow_id,
+            amount,
+            delivery_deadline,
+            review_period,
+        },
+    )
+}
+
+pub fn mark_delivered(escrow_address: &Pubkey, escrow: &Escrow) -> Instruction {
+    build(
+        accounts::MarkDelivered {
+            escrow: *escrow_address,
+            seller: escrow.seller,
+        },
+        instruction::MarkDelivered {},
+    )
+}
+
+/// `signer` is the buyer or the seller
+pub fn open_dispute(escrow_address: &Pubkey, signer: &Pubkey) -> Instruction {
+    build(
+        accounts::OpenDispute {
+            escrow: *escrow_address,
+            signer: *signer,
+        },
+        instruction::OpenDispute {},
+    )
+}
+
+fn settle(
+    escrow_address: &Pubkey,
+    escrow: &Escrow,
+    signer: &Pubkey,
+    data: impl InstructionData,
+) -> Instruction {
+    build(
+        accounts::Settle {
+            escrow: *escrow_address,
+            signer: *signer,
+            buyer: escrow.buyer,
+            buyer_token_account: escrow.buyer_token_account,
+            seller_token_account: escrow.seller_token_account,
+            vault: escrow.vault,
+            token_program: spl_token::ID,
+        },
+        data,
+    )
+}
+
+/// Buyer pays the seller
+pub fn release(escrow_address: &Pubkey, escrow: &Escrow) -> Instruction {
+    settle(
+        escrow_address,
+        escrow,
+        &escrow.buyer,
+        instruction::Release {},
+    )
+}
+
+/// Seller returns the funds to the buyer
+pub fn refund(escrow_address: &Pubkey, escrow: &Escrow) -> Instruction {
+    settle(
+        escrow_address,
+        escrow,
+        &escrow.seller,
+        instruction::Refund {},
+    )
+}
+
+pub fn resolve_dispute(escrow_address: &Pubkey, escrow: &Escrow, seller_share: u64) -> Instruction {
+    settle(
+        escrow_address,
+        escrow,
+        &escrow.arbiter,
+        instruction::ResolveDispute { seller_share },
+    )
+}
+
+/// Permissionless; `keeper` only signs
+pub fn settle_expired(escrow_address: &Pubkey, escrow: &Escrow, keeper: &Pubkey) -> Instruction {
+    settle(
+        escrow_address,
+        escrow,
+        keeper,
+        instruction::SettleExpired {},
+    )
+}
+
+/// What a keeper's `settle_expired` would do at `now`, or `None` to skip
+pub fn due_settlement(escrow: &Escrow, now: i64) -> Option<Resolution> {
+    escrow.expired_resolution(now).ok()
+}
@@ -0,0 +1,285 @@
+//! Typed decoding of the program's Anchor events.
+//!
+//! `emit!` writes events as `Program data: <base64>` log lines; `emit_cpi!`
+//! writes them as self-CPI inner instructions prefixed with
+//! [`EVENT_IX_TAG_LE`]. Both carry `discriminator || borsh(event)`.
+
+use anchor_lang::event::EVENT_IX_TAG_LE;
+use anchor_lang::{AnchorDeserialize, Discriminator};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use subscription_program::{
+    AllowlistChanged, BillingCadenceChanged, ChargeAttested, ChargeFunctionRegistered,
+    ChargeShortfall, ChargeTaskQueued, ChargeThreadCreated, DelegationRevoked, DenylistChanged,
+    DepositPaid, DepositRefunded, DowntimeAttested, FallbackFundingUsed, FallbackMintChanged,
+    FundingSourcesUpdated, InviteOnlyChanged, KeeperLeaseAcquired, MerchantConfigUpdated,
+    MerchantMultisigChanged, MerchantVaultCreated, OverageCharged, PayoutChangeScheduled,
+    PlanCapacitySet, PlanCreated, PlanIntroPricingSet, PlanMeteringSet, PlanPriceRampSet,
+    PlanSeatReleased, PlatformConfigChangeCancelled, PlatformConfigChangeQueued,
+    PlatformConfigUpdated, PriceCacheRefreshed, RecipientTokenAccountChanged, RevenueHeld,
+    RevenueRecovered, RevenueWithdrawn, ScheduledPriceApplied, SetupFeePaid, SlaCommitmentChanged,
+    SlaCreditPaid, SlaDiscountApplied, SlaDiscountScheduled, SpendLimitReached, SpendLimitSet,
+    SpendThresholdCrossed, SubscriptionCancelled, SubscriptionCharged, SubscriptionCompacted,
+    SubscriptionCreated, SubscriptionMigrated, SubscriptionUpdated, TierUpgradeScheduled,
+    TierUpgraded, UsageRecorded, UsdPriceSet, VaultHoldbackUpdated, WaitlistJoined, WaitlistLeft,
+    WaitlistPromoted, WithdrawalDestinationChanged, WithdrawalLimitUpdated,
+};
+
+use crate::{ClientError, Result, PROGRAM_ID};
+
+const PROGRAM_DATA: &str = "Program data: ";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubscriptionEvent {
+    Created(SubscriptionCreated),
+    Charged(SubscriptionCharged),
+    Cancelled(SubscriptionCancelled),
+    Updated(SubscriptionUpdated),
+    DelegationRevoked(DelegationRevoked),
+    ChargeShortfall(ChargeShortfall),
+    MerchantConfigUpdated(MerchantConfigUpdated),
+    FundingSourcesUpdated(FundingSourcesUpdated),
+    FallbackFundingUsed(FallbackFundingUsed),
+    Migrated(SubscriptionMigrated),
+    ChargeThreadCreated(ChargeThreadCreated),
+    ChargeFunctionRegistered(ChargeFunctionRegistered),
+    ChargeAttested(ChargeAttested),
+    ChargeTaskQueued(ChargeTaskQueued),
+    KeeperLeaseAcquired(KeeperLeaseAcquired),
+    MerchantMultisigChanged(MerchantMultisigChanged),
+    PayoutChangeScheduled(PayoutChangeScheduled),
+    RecipientTokenAccountChanged(RecipientTokenAccountChanged),
+    Compacted(SubscriptionCompacted),
+    MerchantVaultCreated(MerchantVaultCreated),
+    RevenueWithdrawn(RevenueWithdrawn),
+    VaultHoldbackUpdated(VaultHoldbackUpdated),
+    RevenueHeld(RevenueHeld),
+    WithdrawalLimitUpdated(WithdrawalLimitUpdated),
+    RevenueRecovered(RevenueRecovered),
+    WithdrawalDestinationChanged(WithdrawalDestinationChanged),
+    SlaCommitmentChanged(SlaCommitmentChanged),
+    DowntimeAttested(DowntimeAttested),
+    SlaCreditPaid(SlaCreditPaid),
+    SlaDiscountScheduled(SlaDiscountScheduled),
+    SlaDiscountApplied(SlaDiscountApplied),
+    PriceCacheRefreshed(PriceCacheRefreshed),
+    UsdPriceSet(UsdPriceSet),
+    FallbackMintChanged(FallbackMintChanged),
+    DepositPaid(DepositPaid),
+    DepositRefunded(DepositRefunded),
+    SetupFeePaid(SetupFeePaid),
+    PlanCreated(PlanCreated),
+    BillingCadenceChanged(BillingCadenceChanged),
+    PlanMeteringSet(PlanMeteringSet),
+    UsageRecorded(UsageRecorded),
+    TierUpgradeScheduled(TierUpgradeScheduled),
+    TierUpgraded(TierUpgraded),
+    OverageCharged(OverageCharged),
+    SpendThresholdCrossed(SpendThresholdCrossed),
+    SpendLimitSet(SpendLimitSet),
+    SpendLimitReached(SpendLimitReached),
+    DenylistChanged(DenylistChanged),
+    InviteOnlyChanged(InviteOnlyChanged),
+    AllowlistChanged(AllowlistChanged),
+    PlanCapacitySet(PlanCapacitySet),
+    PlanSeatReleased(PlanSeatReleased),
+    WaitlistJoined(WaitlistJoined),
+    WaitlistLeft(WaitlistLeft),
+    WaitlistPromoted(WaitlistPromoted),
+    PlanIntroPricingSet(PlanIntroPricingSet),
+    PlanPriceRampSet(PlanPriceRampSet),
+    ScheduledPriceApplied(ScheduledPriceApplied),
+    PlatformConfigUpdated(PlatformConfigUpdated),
+    PlatformConfigChangeQueued(PlatformConfigChangeQueued),
+    PlatformConfigChangeCancelled(PlatformConfigChangeCancelled),
+}
+
+/// Decode `discriminator || borsh(event)`. Unknown discriminators yield `None`.
+pub fn decode_event(data: &[u8]) -> Result<Option<SubscriptionEvent>> {
+    if data.len() < 8 {
+        return Ok(None);
+    }
+    let (discriminator, mut payload) = data.split_at(8);
+
+    let event = if discriminator == SubscriptionCreated::DISCRIMINATOR {
+        SubscriptionEvent::Created(deserialize(&mut payload)?)
+    } else if discriminator == SubscriptionCharged::DISCRIMINATOR {
+        SubscriptionEvent::Charged(deserialize(&mut payload)?)
+    } else if discriminator == SubscriptionCancelled::DISCRIMINATOR {
+        SubscriptionEvent::Cancelled(deserialize(&mut payload)?)
+    } else if discriminator == SubscriptionUpdated::DISCRIMINATOR {
+        SubscriptionEvent::Updated(deserialize(&mut payload)?)
+    } else if discriminator == DelegationRevoked::DISCRIMINATOR {
+        SubscriptionEvent::DelegationRevoked(deserialize(&mut payload)?)
+    } else if discriminator == ChargeShortfall::DISCRIMINATOR {
+        SubscriptionEvent::ChargeShortfall(deserialize(&mut payload)?)
+    } else if discriminator == MerchantConfigUpdated::DISCRIMINATOR {
+        SubscriptionEvent::MerchantConfigUpdated(deserialize(&mut payload)?)
+    } else if discriminator == FundingSourcesUpdated::DISCRIMINATOR {
+        SubscriptionEvent::FundingSourcesUpdated(deserialize(&mut payload)?)
+    } else if discriminator == FallbackFundingUsed::DISCRIMINATOR {
+        SubscriptionEvent::FallbackFundingUsed(deserialize(&mut payload)?)
+    } else if discriminator == SubscriptionMigrated::DISCRIMINATOR {
+        SubscriptionEvent::Migrated(deserialize(&mut payload)?)
+    } else if discriminator == ChargeThreadCreated::DISCRIMINATOR {
+        SubscriptionEvent::ChargeThreadCreated(deserialize(&mut payload)?)
+    } else if discriminator == ChargeFunctionRegistered::DISCRIMINATOR {
+        SubscriptionEvent::ChargeFunctionRegistered(deserialize(&mut payload)?)
+    } else if discriminator == ChargeAttested::DISCRIMINATOR {
+        SubscriptionEvent::ChargeAttested(deserialize(&mut payload)?)
+    } else if discriminator == ChargeTaskQueued::DISCRIMINATOR {
+        SubscriptionEvent::ChargeTaskQueued(deserialize(&mut payload)?)
+    } else if discriminator == KeeperLeaseAcquired::DISCRIMINATOR {
+        SubscriptionEvent::KeeperLeaseAcquired(deserialize(&mut payload)?)
+    } else if discriminator == MerchantMultisigChanged::DISCRIMINATOR {
+        SubscriptionEvent::MerchantMultisigChanged(deserialize(&mut payload)?)
+    } else if discriminator == PayoutChangeScheduled::DISCRIMINATOR {
+        SubscriptionEvent::PayoutChangeScheduled(deserialize(&mut payload)?)
+    } else if discriminator == RecipientTokenAccountChanged::DISCRIMINATOR {
+        SubscriptionEvent::RecipientTokenAccountChanged(deserialize(&mut payload)?)
+    } else if discriminator == SubscriptionCompacted::DISCRIMINATOR {
+        SubscriptionEvent::Compacted(deserialize(&mut payload)?)
+    } else if discriminator == MerchantVaultCreated::DISCRIMINATOR {
+        SubscriptionEvent::MerchantVaultCreated(deserialize(&mut payload)?)
+    } else if discriminator == RevenueWithdrawn::DISCRIMINATOR {
+        SubscriptionEvent::RevenueWithdrawn(deserialize(&mut payload)?)
+    } else if discriminator == VaultHoldbackUpdated::DISCRIMINATOR {
+        SubscriptionEvent::VaultHoldbackUpdated(deserialize(&mut payload)?)
+    } else if discriminator == RevenueHeld::DISCRIMINATOR {
+        SubscriptionEvent::RevenueHeld(deserialize(&mut payload)?)
+    } else if discriminator == WithdrawalLimitUpdated::DISCRIMINATOR {
+        SubscriptionEvent::WithdrawalLimitUpdated(deserialize(&mut payload)?)
+    } else if discriminator == RevenueRecovered::DISCRIMINATOR {
+        SubscriptionEvent::RevenueRecovered(deserialize(&mut payload)?)
+    } else if discriminator == WithdrawalDestinationChanged::DISCRIMINATOR {
+        SubscriptionEvent::WithdrawalDestinationChanged(deserialize(&mut payload)?)
+    } else if discriminator == SlaCommitmentChanged::DISCRIMINATOR {
+        SubscriptionEvent::SlaCommitmentChanged(deserialize(&mut payload)?)
+    } else if discriminator == DowntimeAttested::DISCRIMINATOR {
+        SubscriptionEvent::DowntimeAttested(deserialize(&mut payload)?)
+    } else if discriminator == SlaCreditPaid::DISCRIMINATOR {
+        SubscriptionEvent::SlaCreditPaid(deserialize(&mut payload)?)
+    } else if discriminator == SlaDiscountScheduled::DISCRIMINATOR {
+        SubscriptionEvent::SlaDiscountScheduled(deserialize(&mut payload)?)
+    } else if discriminator == SlaDiscountApplied::DISCRIMINATOR {
+        SubscriptionEvent::SlaDiscountApplied(deserialize(&mut payload)?)
+    } else if discriminator == PriceCacheRefreshed::DISCRIMINATOR {
+        SubscriptionEvent::PriceCacheRefreshed(deserialize(&mut payload)?)
+    } else if discriminator == UsdPriceSet::DISCRIMINATOR {
+        SubscriptionEvent::UsdPriceSet(deserialize(&mut payload)?)
+    } else if discriminator == FallbackMintChanged::DISCRIMINATOR {
+        SubscriptionEvent::FallbackMintChanged(deserialize(&mut payload)?)
+    } else if discriminator == DepositPaid::DISCRIMINATOR {
+        SubscriptionEvent::DepositPaid(deserialize(&mut payload)?)
+    } else if discriminator == DepositRefunded::DISCRIMINATOR {
+        SubscriptionEvent::DepositRefunded(deserialize(&mut payload)?)
+    } else if discriminator == SetupFeePaid::DISCRIMINATOR {
+        SubscriptionEvent::SetupFeePaid(deserialize(&mut payload)?)
+    } else if discriminator == PlanCreated::DISCRIMINATOR {
+        SubscriptionEvent::PlanCreated(deserialize(&mut payload)?)
+    } else if discriminator == BillingCadenceChanged::DISCRIMINATOR {
+        SubscriptionEvent::BillingCadenceChanged(deserialize(&mut payload)?)
+    } else if discriminator == PlanMeteringSet::DISCRIMINATOR {
+        SubscriptionEvent::PlanMeteringSet(deserialize(&mut payload)?)
+    } else if discriminator == UsageRecorded::DISCRIMINATOR {
+        SubscriptionEvent::UsageRecorded(deserialize(&mut payload)?)
+    } else if discriminator == TierUpgradeScheduled::DISCRIMINATOR {
+        SubscriptionEvent::TierUpgradeScheduled(deserialize(&mut payload)?)
+    } else if discriminator == TierUpgraded::DISCRIMINATOR {
+        SubscriptionEvent::TierUpgraded(deserialize(&mut payload)?)
+    } else if discriminator == OverageCharged::DISCRIMINATOR {
+        SubscriptionEvent::OverageCharged(deserialize(&mut payload)?)
+    } else if discriminator == SpendThresholdCrossed::DISCRIMINATOR {
+        SubscriptionEvent::SpendThresholdCrossed(deserialize(&mut payload)?)
+    } else if discriminator == SpendLimitSet::DISCRIMINATOR {
+        SubscriptionEvent::SpendLimitSet(deserialize(&mut payload)?)
+    } else if discriminator == SpendLimitReached::DISCRIMINATOR {
+        SubscriptionEvent::SpendLimitReached(deserialize(&mut payload)?)
+    } else if discriminator == DenylistChanged::DISCRIMINATOR {
+        SubscriptionEvent::DenylistChanged(deserialize(&mut payload)?)
+    } else if discriminator == InviteOnlyChanged::DISCRIMINATOR {
+        SubscriptionEvent::InviteOnlyChanged(deserialize(&mut payload)?)
+    } else if discriminator == AllowlistChanged::DISCRIMINATOR {
+        SubscriptionEvent::AllowlistChanged(deserialize(&mut payload)?)
+    } else if discriminator == PlanCapacitySet::DISCRIMINATOR {
+        SubscriptionEvent::PlanCapacitySet(deserialize(&mut payload)?)
+    } else if discriminator == PlanSeatReleased::DISCRIMINATOR {
+        SubscriptionEvent::PlanSeatReleased(deserialize(&mut payload)?)
+    } else if discriminator == WaitlistJoined::DISCRIMINATOR {
+        SubscriptionEvent::WaitlistJoined(deserialize(&mut payload)?)
+    } else if discriminator == WaitlistLeft::DISCRIMINATOR {
+        SubscriptionEvent::WaitlistLeft(deserialize(&mut payload)?)
+    } else if discriminator == WaitlistPromoted::DISCRIMINATOR {
+        SubscriptionEvent::WaitlistPromoted(deserialize(&mut payload)?)
+    } else if discriminator == PlanIntroPricingSet::DISCRIMINATOR {
+        SubscriptionEvent::PlanIntroPricingSet(deserialize(&mut payload)?)
+    } else if discriminator == PlanPriceRampSet::DISCRIMINATOR {
+        SubscriptionEvent::PlanPriceRampSet(deserialize(&mut payload)?)
+    } else if discriminator == ScheduledPriceApplied::DISCRIMINATOR {
+        SubscriptionEvent::ScheduledPriceApplied(deserialize(&mut payload)?)
+    } else if discriminator == PlatformConfigUpdated::DISCRIMINATOR {
+        SubscriptionEvent::PlatformConfigUpdated(deserialize(&mut payload)?)
+    } else if discriminator == PlatformConfigChangeQueued::DISCRIMINATOR {
+        SubscriptionEvent::PlatformConfigChangeQueued(deserialize(&mut payload)?)
+    } else if discriminator == PlatformConfigChangeCancelled::DISCRIMINATOR {
+        SubscriptionEvent::PlatformConfigChangeCancelled(deserialize(&mut payload)?)
+    } else {
+        return Ok(None);
+    };
+
+    Ok(Some(event))
+}
+
+/// Decode the data of an `emit_cpi!` inner instruction
+pub fn decode_inner_instruction(data: &[u8]) -> Result<Option<SubscriptionEvent>> {
+    match data.strip_prefix(EVENT_IX_TAG_LE) {
+        Some(event) => decode_event(event),
+        None => Ok(None),
+    }
+}
+
+/// Extract every event our program emitted from a transaction's log messages.
+///
+/// Tracks the invoke stack so `Program data:` lines written by other programs
+/// (e.g. a wrapping LazorKit wallet CPI) are ignored.
+pub fn parse_logs<S: AsRef<str>>(logs: &[S]) -> Result<Vec<SubscriptionEvent>> {
+    let program_id = PROGRAM_ID.to_string();
+    let mut stack: Vec<&str> = Vec::new();
+    let mut events = Vec::new();
+
+    for log in logs {
+        let log = log.as_ref();
+
+        if let Some(rest) = log.strip_prefix("Program ") {
+            if let Some((id, tail)) = rest.split_once(' ') {
+                if tail.starts_with("invoke [") {
+                    stack.push(id);
+                    continue;
+                }
+                if tail == "success" || tail.starts_with("failed") {
+                    stack.pop();
+                    continue;
+                }
+            }
+        }
+
+        let Some(encoded) = log.strip_prefix(PROGRAM_DATA) else {
+            continue;
+        };
+        if stack.last() != Some(&program_id.as_str()) {
+            continue;
+        }
+
+        let data = BASE64_STANDARD
+            .decode(encoded)
+            .map_err(|err| ClientError::EventDecode(err.to_string()))?;
+        if let Some(event) = decode_event(&data)? {
+            events.push(event);
+        }
+    }
+
+    Ok(events)
+}
+
+fn deserialize<T: AnchorDeserialize>(payload: &mut &[u8]) -> Result<T> {
+    T::deserialize(payload).map_err(|err| ClientError::EventDecode(err.to_string()))
+}
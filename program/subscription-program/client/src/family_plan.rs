@@ -0,0 +1,160 @@
+//! Client for the family plan recipe program.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use family_plan::{accounts, instruction};
+
+pub use family_plan::{
+    FamilyPlan, Member, MemberStatus, ID as FAMILY_PLAN_PROGRAM_ID, MAX_MEMBERS,
+};
+
+use crate::pda::associated_token_address;
+
+pub const FAMILY_SEED: &[u8] = b"family";
+
+/// Plan PDA of an organizer for one merchant
+pub fn plan_address(organizer: &Pubkey, merchant: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[FAMILY_SEED, organizer.as_ref(), merchant.as_ref()],
+        &FAMILY_PLAN_PROGRAM_ID,
+    )
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: FAMILY_PLAN_PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// The organizer pays from, and the merchant is paid into, their ATAs for `mint`
+#[allow(clippy::too_many_arguments)]
+pub fn create_plan(
+    organizer: &Pubkey,
+    merchant: &Pubkey,
+    mint: &Pubkey,
+    payer: &Pubkey,
+    amount_per_period: u64,
+    interval_seconds: i64,
+    weight: u16,
+    max_share: u64,
+) -> Instruction {
+    build(
+        accounts::CreatePlan {
+            plan: plan_address(organizer, merchant).0,
+            organizer: *organizer,
+            merchant: *merchant,
+            mint: *mint,
+            organizer_token_account: associated_token_address(organizer, mint),
+            merchant_token_account: associated_token_address(merchant, mint),
+            payer: *payer,
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+        },
+        instruction::CreatePlan {
+            amount_per_period,
+            interval_seconds,
+            weight,
+            max_share,
+        },
+    )
+}
+
+fn manage_accounts(plan: &FamilyPlan) -> accounts::ManagePlan {
+    accounts::ManagePlan {
+        plan: plan_address(&plan.organizer, &plan.merchant).0,
+        organizer: plan.organizer,
+    }
+}
+
+pub fn invite_member(plan: &FamilyPlan, wallet: &Pubkey, weight: u16) -> Instruction {
+    build(
+        manage_accounts(plan),
+        instruction::InviteMember {
+            wallet: *wallet,
+            weight,
+        },
+    )
+}
+
+pub fn remove_member(plan: &FamilyPlan, wallet: &Pubkey) -> Instruction {
+    build(
+        manage_accounts(plan),
+        instruction::RemoveMember { wallet: *wallet },
+    )
+}
+
+/// The member pays from their ATA for the plan's mint
+pub fn accept_invite(plan: &FamilyPlan, member: &Pubkey, max_share: u64) -> Instruction {
+    build(
+        accounts::AcceptInvite {
+            plan: plan_address(&plan.organizer, &plan.merchant).0,
+            member: *member,
+            member_token_account: associated_token_address(member, &plan.mint),
+            token_program: spl_token::ID,
+        },
+        instruction::AcceptInvite { max_share },
+    )
+}
+
+/// Revokes the delegation on the account the member joined with
+pub fn leave_plan(plan: &FamilyPlan, member: &Pubkey) -> Instruction {
+    let member_token_account = plan
+        .members
+        .iter()
+        .find(|entry| entry.wallet == *member)
+        .map_or_else(
+            || associated_token_address(member, &plan.mint),
+            |entry| entry.token_account,
+        );
+    build(
+        accounts::LeavePlan {
+            plan: plan_address(&plan.organizer, &plan.merchant).0,
+            member: *member,
+            member_token_account,
+            token_program: spl_token::ID,
+        },
+        instruction::LeavePlan {},
+    )
+}
+
+/// Passes the active members' token accounts in plan order
+pub fn charge(plan: &FamilyPlan) -> Instruction {
+    let mut ix = build(
+        accounts::Charge {
+            plan: plan_address(&plan.organizer, &plan.merchant).0,
+            merchant_token_account: plan.merchant_token_account,
+            token_program: spl_token::ID,
+        },
+        instruction::Charge {},
+    );
+    ix.accounts.extend(
+        plan.active_members()
+            .into_iter()
+            .map(|index| AccountMeta::new(plan.members[index].token_account, false)),
+    );
+    ix
+}
+
+pub fn cancel_plan(plan: &FamilyPlan) -> Instruction {
+    build(
+        accounts::CancelPlan {
+            plan: plan_address(&plan.organizer, &plan.merchant).0,
+            organizer: plan.organizer,
+            organizer_token_account: plan.members[0].token_account,
+            token_program: spl_token::ID,
+        },
+        instruction::CancelPlan {},
+    )
+}
+
+/// One keeper pass: a `charge` for every plan due at `now`
+pub fn due_charges(plans: &[FamilyPlan], now: i64) -> Vec<Instruction> {
+    plans
+        .iter()
+        .filter(|plan| plan.check_due(now).is_ok())
+        .map(charge)
+        .collect()
+}
@@ -0,0 +1,147 @@
+//! Client for the hybrid auth recipe program.
+//!
+//! A guard PDA holds subscriptions on behalf of a passkey and a traditional
+//! keypair. High-value actions need the keypair to sign the transaction and
+//! the passkey to sign the [`action_message`], verified by a
+//! [`secp256r1_instruction`] placed ahead of the action.
+//!
+//! [`secp256r1_instruction`]: crate::passkey_devices::secp256r1_instruction
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::sysvar;
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use hybrid_auth::{accounts, instruction};
+
+pub use hybrid_auth::{Guard, GuardedAction, ACTION_DOMAIN, ID as HYBRID_AUTH_PROGRAM_ID};
+
+use crate::pda::{
+    allowlist_entry_address, associated_token_address, denylist_address,
+    subscriber_allowlist_address, subscription_address,
+};
+use crate::{Subscription, PROGRAM_ID as SUBSCRIPTION_PROGRAM_ID};
+
+pub const GUARD_SEED: &[u8] = b"hybrid";
+
+/// Guard PDA of a keypair
+pub fn guard_address(keypair: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[GUARD_SEED, keypair.as_ref()], &HYBRID_AUTH_PROGRAM_ID)
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: HYBRID_AUTH_PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// `payer` can be a relayer
+pub fn create_guard(keypair: &Pubkey, payer: &Pubkey, passkey: [u8; 33]) -> Instruction {
+    build(
+        accounts::CreateGuard {
+            guard: guard_address(keypair).0,
+            keypair: *keypair,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreateGuard { passkey },
+    )
+}
+
+/// Open a subscription paid from the guard's ATA; needs both factors. The
+/// keypair signs the transaction, so its meta is marked as a signer.
+pub fn open_subscription(
+    keypair: &Pubkey,
+    recipient: &Pubkey,
+    token_mint: &Pubkey,
+    payer: &Pubkey,
+    amount_per_period: u64,
+    interval_seconds: i64,
+    expires_at: Option<i64>,
+) -> Instruction {
+    let guard = guard_address(keypair).0;
+    let mut instruction = build(
+        accounts::OpenGuardedSubscription {
+            guard,
+            keypair: *keypair,
+            instructions: sysvar::instructions::ID,
+            subscription: subscription_address(&guard, recipient).0,
+            recipient: *recipient,
+            guard_token_account: associated_token_address(&guard, token_mint),
+            recipient_token_account: associated_token_address(recipient, token_mint),
+            token_mint: *token_mint,
+            token_program: spl_token::ID,
+            payer: *payer,
+            system_program: system_program::ID,
+            denylist_entry: denylist_address(recipient, &guard).0,
+            allowlist: subscriber_allowlist_address(recipient).0,
+            allowlist_entry: allowlist_entry_address(recipient, &guard).0,
+            subscription_program: SUBSCRIPTION_PROGRAM_ID,
+        },
+        instruction::OpenSubscription {
+            amount_per_period,
+            interval_seconds,
+            expires_at,
+        },
+    );
+    instruction.accounts[1].is_signer = true;
+    instruction
+}
+
+/// With `keypair_signs` false, the passkey alone must approve
+pub fn update_subscription(
+    keypair: &Pubkey,
+    keypair_signs: bool,
+    subscription: &Pubkey,
+    new_amount: Option<u64>,
+    new_interval: Option<i64>,
+    new_expires_at: Option<i64>,
+) -> Instruction {
+    let mut instruction = build(
+        accounts::UpdateGuardedSubscription {
+            guard: guard_address(keypair).0,
+            keypair: *keypair,
+            instructions: sysvar::instructions::ID,
+            subscription: *subscription,
+            subscription_program: SUBSCRIPTION_PROGRAM_ID,
+        },
+        instruction::UpdateSubscription {
+            new_amount,
+            new_interval,
+            new_expires_at,
+        },
+    );
+    instruction.accounts[1].is_signer = keypair_signs;
+    instruction
+}
+
+/// With `keypair_signs` false, the passkey alone must approve
+pub fn cancel_subscription(
+    keypair: &Pubkey,
+    keypair_signs: bool,
+    subscription: &Subscription,
+) -> Instruction {
+    let guard = guard_address(keypair).0;
+    let mut instruction = build(
+        accounts::CancelGuardedSubscription {
+            guard,
+            keypair: *keypair,
+            instructions: sysvar::instructions::ID,
+            subscription: subscription_address(&guard, &subscription.recipient).0,
+            guard_token_account: subscription.user_token_account,
+            token_program: spl_token::ID,
+            subscription_program: SUBSCRIPTION_PROGRAM_ID,
+        },
+        instruction::CancelSubscription {},
+    );
+    instruction.accounts[1].is_signer = keypair_signs;
+    instruction
+}
+
+/// What the passkey signs for `action` at the guard's current nonce
+pub fn action_message(guard: &Guard, action: &GuardedAction) -> Vec<u8> {
+    guard
+        .action_message(&guard_address(&guard.keypair).0, action)
+        .expect("serializing into a Vec does not fail")
+}
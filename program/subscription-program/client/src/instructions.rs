@@ -0,0 +1,2013 @@
+//! Instruction builders. Account order and data encoding come from the
+//! program's Anchor-generated `accounts` / `instruction` modules, so they can
+//! never drift from the on-chain definitions.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::sysvar;
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use subscription_program::{
+    accounts, charge_task_instruction, instruction, WaitlistEntry, CLOCKWORK_THREAD_PROGRAM_ID,
+    TUKTUK_PROGRAM_ID,
+};
+
+use crate::pda::{
+    accepted_mint_address, allowlist_entry_address, associated_token_address,
+    charge_function_address, charge_task_address, charge_thread_address, denylist_address,
+    deposit_address, downtime_address, fallback_payment_address, funding_sources_address,
+    keeper_lease_address, merchant_config_address, merchant_multisig_address,
+    merchant_vault_address, payout_change_address, plan_address, plan_subscription_address,
+    platform_config_address, platform_config_change_address, price_cache_address,
+    program_data_address, queue_authority_address, setup_fee_address, sla_address,
+    sla_credit_address, spend_alerts_address, subscriber_allowlist_address, subscription_address,
+    task_queue_authority_address, usd_peg_address, waitlist_address, waitlist_entry_address,
+    CHARGE_THREAD_ID,
+};
+use crate::spending_limits::{policy_address, SPENDING_LIMITS_PROGRAM_ID};
+use crate::{BillingCadence, RampStep, Subscription, PROGRAM_ID};
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// Subscribe `authority` to `recipient`, paying from / into their ATAs for `token_mint`
+pub fn initialize_subscription(
+    authority: &Pubkey,
+    recipient: &Pubkey,
+    token_mint: &Pubkey,
+    payer: &Pubkey,
+    amount_per_period: u64,
+    interval_seconds: i64,
+    expires_at: Option<i64>,
+) -> Instruction {
+    build(
+        accounts::InitializeSubscription {
+            subscription: subscription_address(authority, recipient).0,
+            authority: *authority,
+            recipient: *recipient,
+            user_token_account: associated_token_address(authority, token_mint),
+            recipient_token_account: associated_token_address(recipient, token_mint),
+            token_mint: *token_mint,
+            token_program: spl_token::ID,
+            payer: *payer,
+            system_program: system_program::ID,
+            denylist_entry: denylist_address(recipient, authority).0,
+            allowlist: subscriber_allowlist_address(recipient).0,
+            allowlist_entry: allowlist_entry_address(recipient, authority).0,
+        },
+        instruction::InitializeSubscription {
+            amount_per_period,
+            interval_seconds,
+            expires_at,
+        },
+    )
+}
+
+/// [`initialize_subscription`] for a LazorKit smart wallet `authority`. This
+/// is the inner instruction the wallet program invokes: wrap it in the wallet's
+/// execute call rather than sending it directly.
+pub fn initialize_wallet_subscription(
+    authority: &Pubkey,
+    recipient: &Pubkey,
+    token_mint: &Pubkey,
+    payer: &Pubkey,
+    amount_per_period: u64,
+    interval_seconds: i64,
+    expires_at: Option<i64>,
+) -> Instruction {
+    build(
+        accounts::InitializeWalletSubscription {
+            subscription: subscription_address(authority, recipient).0,
+            authority: *authority,
+            recipient: *recipient,
+            user_token_account: associated_token_address(authority, token_mint),
+            recipient_token_account: associated_token_address(recipient, token_mint),
+            token_mint: *token_mint,
+            token_program: spl_token::ID,
+            instructions: sysvar::instructions::ID,
+            payer: *payer,
+            system_program: system_program::ID,
+            denylist_entry: denylist_address(recipient, authority).0,
+            allowlist: subscriber_allowlist_address(recipient).0,
+            allowlist_entry: allowlist_entry_address(recipient, authority).0,
+        },
+        instruction::InitializeWalletSubscription {
+            amount_per_period,
+            interval_seconds,
+            expires_at,
+        },
+    )
+}
+
+/// [`initialize_subscription`] that also takes a one-time `setup_fee` with
+/// the first period
+#[allow(clippy::too_many_arguments)]
+pub fn initialize_subscription_with_setup_fee(
+    authority: &Pubkey,
+    recipient: &Pubkey,
+    token_mint: &Pubkey,
+    payer: &Pubkey,
+    amount_per_period: u64,
+    interval_seconds: i64,
+    expires_at: Option<i64>,
+    setup_fee: u64,
+) -> Instruction {
+    let subscription = subscription_address(authority, recipient).0;
+    build(
+        accounts::InitializeSubscriptionWithSetupFee {
+            init: accounts::InitializeSubscription {
+                subscription,
+                authority: *authority,
+                recipient: *recipient,
+                user_token_account: associated_token_address(authority, token_mint),
+                recipient_token_account: associated_token_address(recipient, token_mint),
+                token_mint: *token_mint,
+                token_program: spl_token::ID,
+                payer: *payer,
+                system_program: system_program::ID,
+                denylist_entry: denylist_address(recipient, authority).0,
+                allowlist: subscriber_allowlist_address(recipient).0,
+                allowlist_entry: allowlist_entry_address(recipient, authority).0,
+            },
+            setup_fee: setup_fee_address(&subscription).0,
+            system_program: system_program::ID,
+        },
+        instruction::InitializeSubscriptionWithSetupFee {
+            amount_per_period,
+            interval_seconds,
+            expires_at,
+            setup_fee,
+        },
+    )
+}
+
+/// [`initialize_subscription_with_setup_fee`] for a LazorKit smart wallet
+/// `authority`, wrapped like [`initialize_wallet_subscription`]
+#[allow(clippy::too_many_arguments)]
+pub fn initialize_wallet_subscription_with_setup_fee(
+    authority: &Pubkey,
+    recipient: &Pubkey,
+    token_mint: &Pubkey,
+    payer: &Pubkey,
+    amount_per_period: u64,
+    interval_seconds: i64,
+    expires_at: Option<i64>,
+    setup_fee: u64,
+) -> Instruction {
+    let subscription = subscription_address(authority, recipient).0;
+    build(
+        accounts::InitializeWalletSubscriptionWithSetupFee {
+            init: accounts::InitializeWalletSubscription {
+                subscription,
+                authority: *authority,
+                recipient: *recipient,
+                user_token_account: associated_token_address(authority, token_mint),
+                recipient_token_account: associated_token_address(recipient, token_mint),
+                token_mint: *token_mint,
+                token_program: spl_token::ID,
+                instructions: sysvar::instructions::ID,
+                payer: *payer,
+                system_program: system_program::ID,
+                denylist_entry: denylist_address(recipient, authority).0,
+                allowlist: subscriber_allowlist_address(recipient).0,
+                allowlist_entry: allowlist_entry_address(recipient, authority).0,
+            },
+            setup_fee: setup_fee_address(&subscription).0,
+            system_program: system_program::ID,
+        },
+        instruction::InitializeWalletSubscriptionWithSetupFee {
+            amount_per_period,
+            interval_seconds,
+            expires_at,
+            setup_fee,
+        },
+    )
+}
+
+/// Recurring charge; token accounts are taken from the subscription state
+pub fn charge_subscription(
+    subscription_address: &Pubkey,
+    subscription: &Subscription,
+) -> Instruction {
+    build(
+        accounts::ChargeSubscription {
+            subscription: *subscription_address,
+            user_token_account: subscription.user_token_account,
+            recipient_token_account: subscription.recipient_token_account,
+            token_program: spl_token::ID,
+        },
+        instruction::ChargeSubscription {},
+    )
+}
+
+/// [`charge_subscription`] passing the recipient's merchant config, so the
+/// merchant's partial-charge and catch-up settings apply
+pub fn charge_subscription_partial(
+    subscription_address: &Pubkey,
+    subscription: &Subscription,
+) -> Instruction {
+    let mut instruction = charge_subscription(subscription_address, subscription);
+    instruction.accounts.push(AccountMeta::new_readonly(
+        merchant_config_address(&subscription.recipient).0,
+        false,
+    ));
+    instruction
+}
+
+/// `charge_subscription` for a user whose token account is behind a
+/// spending-limits policy listing the subscription as a spender. A
+/// subscription on a plan gets its `PlanSubscription` added.
+pub fn charge_subscription_with_policy(
+    subscription_address: &Pubkey,
+    subscription: &Subscription,
+) -> Instruction {
+    let instruction = build(
+        accounts::ChargeSubscriptionWithPolicy {
+            subscription: *subscription_address,
+            policy: policy_address(&subscription.user_token_account).0,
+            user_token_account: subscription.user_token_account,
+            recipient_token_account: subscription.recipient_token_account,
+            token_program: spl_token::ID,
+            spending_limits_program: SPENDING_LIMITS_PROGRAM_ID,
+        },
+        instruction::ChargeSubscriptionWithPolicy {},
+    );
+    if subscription.on_plan {
+        with_plan_schedule(instruction, subscription_address)
+    } else {
+        instruction
+    }
+}
+
+pub fn cancel_subscription(
+    authority: &Pubkey,
+    recipient: &Pubkey,
+    user_token_account: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::CancelSubscription {
+            subscription: subscription_address(authority, recipient).0,
+            authority: *authority,
+            user_token_account: *user_token_account,
+            token_program: spl_token::ID,
+        },
+        instruction::CancelSubscription {},
+    )
+}
+
+/// Append the subscription's `PlanSubscription` and `plan`, writable, to a
+/// cancel, so it gives up its plan seat
+pub fn with_plan_seat(
+    mut instruction: Instruction,
+    subscription_address: &Pubkey,
+    plan: &Pubkey,
+) -> Instruction {
+    instruction.accounts.extend([
+        AccountMeta::new(plan_subscription_address(subscription_address).0, false),
+        AccountMeta::new(*plan, false),
+    ]);
+    instruction
+}
+
+pub fn cleanup_cancelled_subscription(authority: &Pubkey, recipient: &Pubkey) -> Instruction {
+    build(
+        accounts::CleanupCancelledSubscription {
+            subscription: subscription_address(authority, recipient).0,
+            authority: *authority,
+        },
+        instruction::CleanupCancelledSubscription {},
+    )
+}
+
+pub fn update_subscription(
+    authority: &Pubkey,
+    recipient: &Pubkey,
+    new_amount: Option<u64>,
+    new_interval: Option<i64>,
+    new_expires_at: Option<i64>,
+) -> Instruction {
+    build(
+        accounts::UpdateSubscription {
+            subscription: subscription_address(authority, recipient).0,
+            authority: *authority,
+        },
+        instruction::UpdateSubscription {
+            new_amount,
+            new_interval,
+            new_expires_at,
+        },
+    )
+}
+
+/// How often a charge thread checks whether a subscription with
+/// `interval_seconds` is due, as a Clockwork cron string: every minute for
+/// intervals under an hour, otherwise (calendar intervals included) hourly. A check before the due time
+/// fails in simulation and costs nothing.
+pub fn charge_schedule(interval_seconds: i64) -> String {
+    if (1..3_600).contains(&interval_seconds) {
+        "0 * * * * * *".to_string()
+    } else {
+        "0 0 * * * * *".to_string()
+    }
+}
+
+/// Hand billing to a Clockwork thread that sends `charge_subscription` on
+/// `schedule`. `amount` lamports from `payer` fund the thread's fees.
+pub fn create_charge_thread(
+    authority: &Pubkey,
+    recipient: &Pubkey,
+    payer: &Pubkey,
+    thread_id: &[u8],
+    schedule: String,
+    amount: u64,
+) -> Instruction {
+    let subscription = subscription_address(authority, recipient).0;
+    build(
+        accounts::CreateChargeThread {
+            subscription,
+            authority: *authority,
+            payer: *payer,
+            thread: charge_thread_address(&subscription, thread_id).0,
+            thread_program: CLOCKWORK_THREAD_PROGRAM_ID,
+            system_program: system_program::ID,
+        },
+        instruction::CreateChargeThread {
+            thread_id: thread_id.to_vec(),
+            schedule,
+            amount,
+        },
+    )
+}
+
+/// [`initialize_subscription`] followed by [`create_charge_thread`] on
+/// [`charge_schedule`], for one transaction: the subscription is billed by
+/// the automation network from the start
+#[allow(clippy::too_many_arguments)]
+pub fn initialize_subscription_with_charge_thread(
+    authority: &Pubkey,
+    recipient: &Pubkey,
+    token_mint: &Pubkey,
+    payer: &Pubkey,
+    amount_per_period: u64,
+    interval_seconds: i64,
+    expires_at: Option<i64>,
+    thread_lamports: u64,
+) -> [Instruction; 2] {
+    [
+        initialize_subscription(
+            authority,
+            recipient,
+            token_mint,
+            payer,
+            amount_per_period,
+            interval_seconds,
+            expires_at,
+        ),
+        create_charge_thread(
+            authority,
+            recipient,
+            payer,
+            CHARGE_THREAD_ID,
+            charge_schedule(interval_seconds),
+            thread_lamports,
+        ),
+    ]
+}
+
+/// Queue tuktuk task `task_id` on `task_queue` to charge the subscription
+/// when it is next due; each run queues the one after. Signed by the
+/// recipient. Metas pushed onto the result, such as a merchant config, are
+/// passed on to every charge.
+pub fn queue_charge_task(
+    subscription_address: &Pubkey,
+    subscription: &Subscription,
+    payer: &Pubkey,
+    task_queue: &Pubkey,
+    task_id: u16,
+) -> Instruction {
+    let queue_authority = queue_authority_address(&subscription.recipient).0;
+    build(
+        accounts::QueueChargeTask {
+            subscription: *subscription_address,
+            recipient: subscription.recipient,
+            payer: *payer,
+            queue_authority,
+            task_queue_authority: task_queue_authority_address(task_queue, &queue_authority).0,
+            task_queue: *task_queue,
+            task: charge_task_address(task_queue, task_id).0,
+            tuktuk_program: TUKTUK_PROGRAM_ID,
+            system_program: system_program::ID,
+        },
+        instruction::QueueChargeTask { task_id },
+    )
+}
+
+/// The charge a tuktuk task runs; also sendable directly, like
+/// [`charge_subscription`]
+pub fn charge_subscription_tuktuk(
+    subscription_address: &Pubkey,
+    subscription: &Subscription,
+) -> Instruction {
+    charge_task_instruction(subscription_address, subscription, &[])
+}
+
+/// Create `group`'s keeper lease, electable between `keepers`. `payer` can
+/// be a relayer.
+pub fn create_keeper_lease(group: &Pubkey, payer: &Pubkey, keepers: Vec<Pubkey>) -> Instruction {
+    build(
+        accounts::CreateKeeperLease {
+            lease: keeper_lease_address(group).0,
+            group: *group,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreateKeeperLease { keepers },
+    )
+}
+
+pub fn update_keeper_lease(group: &Pubkey, keepers: Vec<Pubkey>) -> Instruction {
+    build(
+        accounts::UpdateKeeperLease {
+            lease: keeper_lease_address(group).0,
+            group: *group,
+        },
+        instruction::UpdateKeeperLease { keepers },
+    )
+}
+
+pub fn close_keeper_lease(group: &Pubkey) -> Instruction {
+    build(
+        accounts::CloseKeeperLease {
+            lease: keeper_lease_address(group).0,
+            group: *group,
+        },
+        instruction::CloseKeeperLease {},
+    )
+}
+
+/// Take or renew `group`'s lease for `keeper`
+pub fn acquire_keeper_lease(group: &Pubkey, keeper: &Pubkey, duration_seconds: i64) -> Instruction {
+    build(
+        accounts::AcquireKeeperLease {
+            lease: keeper_lease_address(group).0,
+            keeper: *keeper,
+        },
+        instruction::AcquireKeeperLease { duration_seconds },
+    )
+}
+
+pub fn release_keeper_lease(group: &Pubkey, keeper: &Pubkey) -> Instruction {
+    build(
+        accounts::AcquireKeeperLease {
+            lease: keeper_lease_address(group).0,
+            keeper: *keeper,
+        },
+        instruction::ReleaseKeeperLease {},
+    )
+}
+
+/// Put `check_keeper_lease` ahead of `instructions`, so the transaction
+/// only lands while `keeper` still holds `group`'s lease in `term`
+pub fn with_keeper_lease(
+    instructions: &[Instruction],
+    group: &Pubkey,
+    keeper: &Pubkey,
+    term: u64,
+) -> Vec<Instruction> {
+    let check = build(
+        accounts::CheckKeeperLease {
+            lease: keeper_lease_address(group).0,
+            keeper: *keeper,
+        },
+        instruction::CheckKeeperLease { term },
+    );
+    std::iter::once(check)
+        .chain(instructions.iter().cloned())
+        .collect()
+}
+
+/// Rewrite a legacy subscription in the current layout. Anyone can send it;
+/// `payer` tops up rent for the larger account.
+pub fn migrate_subscription(subscription_address: &Pubkey, payer: &Pubkey) -> Instruction {
+    build(
+        accounts::MigrateSubscription {
+            subscription: *subscription_address,
+            plan_subscription: plan_subscription_address(subscription_address).0,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::MigrateSubscription {},
+    )
+}
+
+/// Shrink a deactivated subscription to a tombstone. Anyone can send it;
+/// the rent difference goes to `authority`.
+pub fn compact_subscription(subscription_address: &Pubkey, authority: &Pubkey) -> Instruction {
+    build(
+        accounts::CompactSubscription {
+            subscription: *subscription_address,
+            authority: *authority,
+        },
+        instruction::CompactSubscription {},
+    )
+}
+
+pub fn close_subscription_tombstone(authority: &Pubkey, recipient: &Pubkey) -> Instruction {
+    build(
+        accounts::CloseSubscriptionTombstone {
+            tombstone: subscription_address(authority, recipient).0,
+            authority: *authority,
+        },
+        instruction::CloseSubscriptionTombstone {},
+    )
+}
+
+/// Accounts of `charge_subscription` ahead of its remaining accounts
+const CHARGE_ACCOUNTS: usize = 4;
+
+/// Turn a charge built by [`charge_subscription`], optionally extended by
+/// [`charge_subscription_partial`], [`with_fallback_funding`] or
+/// [`with_sla_discounts`], into `charge_subscription_with_reference`, so its
+/// `SubscriptionCharged` events carry `reference`, such as an order or
+/// invoice ID.
+pub fn with_reference(mut instruction: Instruction, reference: [u8; 32]) -> Instruction {
+    instruction.data = instruction::ChargeSubscriptionWithReference { reference }.data();
+    instruction
+}
+
+/// Add the subscription's fallback funding to a charge built by
+/// [`charge_subscription`] or [`charge_subscription_partial`].
+/// `token_accounts` must be the registered list, in order.
+pub fn with_fallback_funding(
+    mut instruction: Instruction,
+    subscription_address: &Pubkey,
+    token_accounts: &[Pubkey],
+) -> Instruction {
+    // The funding PDA goes in the second remaining slot; hold the first
+    // with the program ID when there is no merchant config
+    if instruction.accounts.len() == CHARGE_ACCOUNTS {
+        instruction
+            .accounts
+            .push(AccountMeta::new_readonly(PROGRAM_ID, false));
+    }
+    instruction.accounts.push(AccountMeta::new_readonly(
+        funding_sources_address(subscription_address).0,
+        false,
+    ));
+    instruction.accounts.extend(
+        token_accounts
+            .iter()
+            .map(|account| AccountMeta::new(*account, false)),
+    );
+    instruction
+}
+
+/// Add the subscription's `PlanSubscription`, writable, to a charge built by
+/// [`charge_subscription`] or extended by [`charge_subscription_partial`] or
+/// [`with_fallback_funding`], before any [`with_sla_discounts`] receipts.
+/// Required for a subscription with `on_plan` set; the charge fails without
+/// it.
+pub fn with_plan_schedule(
+    mut instruction: Instruction,
+    subscription_address: &Pubkey,
+) -> Instruction {
+    instruction.accounts.push(AccountMeta::new(
+        plan_subscription_address(subscription_address).0,
+        false,
+    ));
+    instruction
+}
+
+/// Add the subscription's pending SLA discounts, one per outage in
+/// `attestations`, to the end of a charge built by [`charge_subscription`]
+/// or extended by [`charge_subscription_partial`] or
+/// [`with_fallback_funding`]. They come off the first period.
+pub fn with_sla_discounts(
+    mut instruction: Instruction,
+    subscription_address: &Pubkey,
+    attestations: &[Pubkey],
+) -> Instruction {
+    instruction
+        .accounts
+        .extend(attestations.iter().map(|attestation| {
+            AccountMeta::new(
+                sla_credit_address(attestation, subscription_address).0,
+                false,
+            )
+        }));
+    instruction
+}
+
+/// Append the subscription's `SpendAlerts`, read-only, to the end of a
+/// charge, after any [`with_sla_discounts`] receipts, so the charge emits
+/// `SpendThresholdCrossed` for the milestones it passes
+pub fn with_spend_alerts(
+    mut instruction: Instruction,
+    subscription_address: &Pubkey,
+) -> Instruction {
+    instruction.accounts.push(AccountMeta::new_readonly(
+        spend_alerts_address(subscription_address).0,
+        false,
+    ));
+    instruction
+}
+
+/// Turn a charge built by [`charge_subscription`], optionally extended by
+/// [`charge_subscription_partial`] or [`with_fallback_funding`], into
+/// `charge_subscription_attested`. The recipient's registered Switchboard
+/// Function sends it, signed by `enclave_signer`.
+pub fn with_attestation(
+    mut instruction: Instruction,
+    recipient: &Pubkey,
+    function: &Pubkey,
+    enclave_signer: &Pubkey,
+) -> Instruction {
+    instruction.accounts.splice(
+        CHARGE_ACCOUNTS..CHARGE_ACCOUNTS,
+        [
+            AccountMeta::new_readonly(charge_function_address(recipient).0, false),
+            AccountMeta::new_readonly(*function, false),
+            AccountMeta::new_readonly(*enclave_signer, true),
+        ],
+    );
+    instruction.data = instruction::ChargeSubscriptionAttested {}.data();
+    instruction
+}
+
+/// Turn a charge built by [`charge_subscription`], optionally extended by
+/// [`with_fallback_funding`] or [`with_sla_discounts`], into
+/// `charge_subscription_usd` for a USD-priced subscription, at the price in
+/// the cache of `feed_id`
+pub fn with_usd_price(
+    mut instruction: Instruction,
+    subscription_address: &Pubkey,
+    feed_id: &[u8; 32],
+) -> Instruction {
+    instruction.accounts.splice(
+        CHARGE_ACCOUNTS..CHARGE_ACCOUNTS,
+        [
+            AccountMeta::new_readonly(usd_peg_address(subscription_address).0, false),
+            AccountMeta::new_readonly(price_cache_address(feed_id).0, false),
+        ],
+    );
+    instruction.data = instruction::ChargeSubscriptionUsd {}.data();
+    instruction
+}
+
+/// Let `function` bill the recipient's subscriptions. `payer` can be a relayer.
+pub fn register_charge_function(
+    recipient: &Pubkey,
+    function: &Pubkey,
+    payer: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::RegisterChargeFunction {
+            charge_function: charge_function_address(recipient).0,
+            recipient: *recipient,
+            function: *function,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::RegisterChargeFunction {},
+    )
+}
+
+pub fn close_charge_function(recipient: &Pubkey) -> Instruction {
+    build(
+        accounts::CloseChargeFunction {
+            charge_function: charge_function_address(recipient).0,
+            recipient: *recipient,
+        },
+        instruction::CloseChargeFunction {},
+    )
+}
+
+/// `authority` is the recipient, or its multisig's vault once it has one;
+/// `payer` can be a relayer
+pub fn create_merchant_config(
+    recipient: &Pubkey,
+    authority: &Pubkey,
+    payer: &Pubkey,
+    allow_partial_charges: bool,
+    max_periods_per_charge: u8,
+) -> Instruction {
+    build(
+        accounts::CreateMerchantConfig {
+            merchant_config: merchant_config_address(recipient).0,
+            merchant_multisig: merchant_multisig_address(recipient).0,
+            recipient: *recipient,
+            authority: *authority,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreateMerchantConfig {
+            allow_partial_charges,
+            max_periods_per_charge,
+        },
+    )
+}
+
+/// `authority` is the recipient, or its multisig's vault once it has one
+pub fn update_merchant_config(
+    recipient: &Pubkey,
+    authority: &Pubkey,
+    allow_partial_charges: bool,
+    max_periods_per_charge: u8,
+) -> Instruction {
+    build(
+        accounts::UpdateMerchantConfig {
+            merchant_config: merchant_config_address(recipient).0,
+            merchant_multisig: merchant_multisig_address(recipient).0,
+            recipient: *recipient,
+            authority: *authority,
+        },
+        instruction::UpdateMerchantConfig {
+            allow_partial_charges,
+            max_periods_per_charge,
+        },
+    )
+}
+
+/// Put the recipient's settings under `vault_index` of a Squads `multisig`
+pub fn set_merchant_multisig(
+    recipient: &Pubkey,
+    multisig: &Pubkey,
+    payer: &Pubkey,
+    vault_index: u8,
+) -> Instruction {
+    build(
+        accounts::SetMerchantMultisig {
+            merchant_multisig: merchant_multisig_address(recipient).0,
+            multisig: *multisig,
+            recipient: *recipient,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::SetMerchantMultisig { vault_index },
+    )
+}
+
+/// Hand the settings back to the recipient's key. Signed by `vault`, so it
+/// goes into a Squads vault transaction.
+pub fn close_merchant_multisig(recipient: &Pubkey, vault: &Pubkey) -> Instruction {
+    build(
+        accounts::CloseMerchantMultisig {
+            merchant_multisig: merchant_multisig_address(recipient).0,
+            recipient: *recipient,
+            vault: *vault,
+        },
+        instruction::CloseMerchantMultisig {},
+    )
+}
+
+/// Schedule `token_account` as the recipient's payout account, effective
+/// after the program's timelock. `authority` is the recipient, or its
+/// multisig's vault.
+pub fn set_recipient_token_account(
+    recipient: &Pubkey,
+    authority: &Pubkey,
+    token_account: &Pubkey,
+    payer: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::SetRecipientTokenAccount {
+            payout_change: payout_change_address(recipient).0,
+            merchant_multisig: merchant_multisig_address(recipient).0,
+            recipient: *recipient,
+            authority: *authority,
+            token_account: *token_account,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::SetRecipientTokenAccount {},
+    )
+}
+
+/// Move a subscription to its recipient's scheduled payout account;
+/// permissionless once the timelock has run out
+pub fn apply_recipient_token_account(
+    subscription_address: &Pubkey,
+    subscription: &Subscription,
+    token_account: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::ApplyRecipientTokenAccount {
+            subscription: *subscription_address,
+            payout_change: payout_change_address(&subscription.recipient).0,
+            token_account: *token_account,
+        },
+        instruction::ApplyRecipientTokenAccount {},
+    )
+}
+
+/// Withdraw the recipient's pending payout change, or clear an applied one
+pub fn close_payout_change(recipient: &Pubkey, authority: &Pubkey) -> Instruction {
+    build(
+        accounts::ClosePayoutChange {
+            payout_change: payout_change_address(recipient).0,
+            merchant_multisig: merchant_multisig_address(recipient).0,
+            recipient: *recipient,
+            authority: *authority,
+        },
+        instruction::ClosePayoutChange {},
+    )
+}
+
+/// Open the recipient's revenue vault over `token_account`, which must
+/// already be owned by [`merchant_vault_address`]. `authority` is the
+/// recipient, or its multisig's vault.
+pub fn create_merchant_vault(
+    recipient: &Pubkey,
+    authority: &Pubkey,
+    token_account: &Pubkey,
+    payer: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::CreateMerchantVault {
+            vault: merchant_vault_address(recipient).0,
+            merchant_multisig: merchant_multisig_address(recipient).0,
+            recipient: *recipient,
+            authority: *authority,
+            token_account: *token_account,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreateMerchantVault {},
+    )
+}
+
+/// Sweep `amount` from the recipient's vault `token_account` to `destination`,
+/// one of the vault's approved destinations
+pub fn withdraw_revenue(
+    recipient: &Pubkey,
+    authority: &Pubkey,
+    token_account: &Pubkey,
+    destination: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    build(
+        accounts::WithdrawRevenue {
+            vault: merchant_vault_address(recipient).0,
+            merchant_multisig: merchant_multisig_address(recipient).0,
+            recipient: *recipient,
+            authority: *authority,
+            token_account: *token_account,
+            destination: *destination,
+            token_program: spl_token::ID,
+        },
+        instruction::WithdrawRevenue { amount },
+    )
+}
+
+/// Approve `token_account` as a withdrawal destination of the recipient's
+/// vault, usable once the program's timelock has run out
+pub fn add_withdrawal_destination(
+    recipient: &Pubkey,
+    authority: &Pubkey,
+    token_account: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::AddWithdrawalDestination {
+            vault: merchant_vault_address(recipient).0,
+            merchant_multisig: merchant_multisig_address(recipient).0,
+            recipient: *recipient,
+            authority: *authority,
+            token_account: *token_account,
+        },
+        instruction::AddWithdrawalDestination {},
+    )
+}
+
+/// Drop `token_account` from the vault's withdrawal destinations
+pub fn remove_withdrawal_destination(
+    recipient: &Pubkey,
+    authority: &Pubkey,
+    token_account: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::RemoveWithdrawalDestination {
+            vault: merchant_vault_address(recipient).0,
+            merchant_multisig: merchant_multisig_address(recipient).0,
+            recipient: *recipient,
+            authority: *authority,
+        },
+        instruction::RemoveWithdrawalDestination {
+            token_account: *token_account,
+        },
+    )
+}
+
+/// Cap the merchant's withdrawals from its vault to `limit` per
+/// `period_seconds`. `authority` is the merchant authority for a first or
+/// tighter limit, and `admin` (the current withdrawal admin) for anything else.
+pub fn set_withdrawal_limit(
+    recipient: &Pubkey,
+    authority: &Pubkey,
+    limit: u64,
+    period_seconds: i64,
+    admin: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::SetWithdrawalLimit {
+            vault: merchant_vault_address(recipient).0,
+            merchant_multisig: merchant_multisig_address(recipient).0,
+            recipient: *recipient,
+            authority: *authority,
+        },
+        instruction::SetWithdrawalLimit {
+            limit,
+            period_seconds,
+            admin: *admin,
+        },
+    )
+}
+
+/// Move `amount` out of the vault past its withdrawal limit, signed by the
+/// vault's withdrawal admin
+pub fn recover_revenue(
+    recipient: &Pubkey,
+    withdrawal_admin: &Pubkey,
+    token_account: &Pubkey,
+    destination: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    build(
+        accounts::RecoverRevenue {
+            vault: merchant_vault_address(recipient).0,
+            recipient: *recipient,
+            withdrawal_admin: *withdrawal_admin,
+            token_account: *token_account,
+            destination: *destination,
+            token_program: spl_token::ID,
+        },
+        instruction::RecoverRevenue { amount },
+    )
+}
+
+/// Hold `holdback_bps` of the vault's future revenue for `holdback_days`.
+/// `token_account` is the vault's token account.
+pub fn set_vault_holdback(
+    recipient: &Pubkey,
+    authority: &Pubkey,
+    token_account: &Pubkey,
+    holdback_bps: u16,
+    holdback_days: u16,
+) -> Instruction {
+    build(
+        accounts::SetVaultHoldback {
+            vault: merchant_vault_address(recipient).0,
+            merchant_multisig: merchant_multisig_address(recipient).0,
+            recipient: *recipient,
+            authority: *authority,
+            token_account: *token_account,
+        },
+        instruction::SetVaultHoldback {
+            holdback_bps,
+            holdback_days,
+        },
+    )
+}
+
+/// Start the holdback on revenue the vault received since its last sync;
+/// permissionless, so a keeper can send it after a round of charges
+pub fn sync_merchant_vault(recipient: &Pubkey, token_account: &Pubkey) -> Instruction {
+    build(
+        accounts::SyncMerchantVault {
+            vault: merchant_vault_address(recipient).0,
+            token_account: *token_account,
+        },
+        instruction::SyncMerchantVault {},
+    )
+}
+
+/// Commit the recipient to credit subscribers for downtime `attester`
+/// attests to, from its vault's held-back revenue
+pub fn create_sla_commitment(
+    recipient: &Pubkey,
+    authority: &Pubkey,
+    attester: &Pubkey,
+    credit_multiplier: u16,
+    payer: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::CreateSlaCommitment {
+            sla: sla_address(recipient).0,
+            vault: merchant_vault_address(recipient).0,
+            merchant_multisig: merchant_multisig_address(recipient).0,
+            recipient: *recipient,
+            authority: *authority,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreateSlaCommitment {
+            attester: *attester,
+            credit_multiplier,
+        },
+    )
+}
+
+/// Give notice that the recipient's SLA commitment ends
+pub fn end_sla_commitment(recipient: &Pubkey, authority: &Pubkey) -> Instruction {
+    build(
+        accounts::EndSlaCommitment {
+            sla: sla_address(recipient).0,
+            merchant_multisig: merchant_multisig_address(recipient).0,
+            recipient: *recipient,
+            authority: *authority,
+        },
+        instruction::EndSlaCommitment {},
+    )
+}
+
+/// Close an ended SLA commitment, refunding the rent to the recipient
+pub fn close_sla_commitment(recipient: &Pubkey, authority: &Pubkey) -> Instruction {
+    build(
+        accounts::CloseSlaCommitment {
+            sla: sla_address(recipient).0,
+            merchant_multisig: merchant_multisig_address(recipient).0,
+            recipient: *recipient,
+            authority: *authority,
+        },
+        instruction::CloseSlaCommitment {},
+    )
+}
+
+/// Attest an outage of the recipient's service, signed by its SLA attester
+pub fn attest_downtime(
+    recipient: &Pubkey,
+    attester: &Pubkey,
+    started_at: i64,
+    ended_at: i64,
+    payer: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::AttestDowntime {
+            sla: sla_address(recipient).0,
+            attestation: downtime_address(recipient, started_at).0,
+            recipient: *recipient,
+            attester: *attester,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::AttestDowntime {
+            started_at,
+            ended_at,
+        },
+    )
+}
+
+/// Pay a subscription its credit for the outage at `attestation`, from the
+/// vault `token_account`; permissionless
+pub fn credit_sla(
+    subscription_address: &Pubkey,
+    subscription: &Subscription,
+    attestation: &Pubkey,
+    token_account: &Pubkey,
+    payer: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::CreditSla {
+            sla: sla_address(&subscription.recipient).0,
+            attestation: *attestation,
+            subscription: *subscription_address,
+            vault: merchant_vault_address(&subscription.recipient).0,
+            token_account: *token_account,
+            user_token_account: subscription.user_token_account,
+            credit: sla_credit_address(attestation, subscription_address).0,
+            payer: *payer,
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+        },
+        instruction::CreditSla {},
+    )
+}
+
+/// Take a subscription's credit for the outage at `attestation` off its next
+/// charges instead of paying it out; permissionless
+pub fn schedule_sla_discount(
+    subscription_address: &Pubkey,
+    recipient: &Pubkey,
+    attestation: &Pubkey,
+    payer: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::ScheduleSlaDiscount {
+            sla: sla_address(recipient).0,
+            attestation: *attestation,
+            subscription: *subscription_address,
+            credit: sla_credit_address(attestation, subscription_address).0,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::ScheduleSlaDiscount {},
+    )
+}
+
+/// Create the price cache of Pyth feed `feed_id`; permissionless
+pub fn create_price_cache(feed_id: [u8; 32], payer: &Pubkey) -> Instruction {
+    build(
+        accounts::CreatePriceCache {
+            price_cache: price_cache_address(&feed_id).0,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreatePriceCache { feed_id },
+    )
+}
+
+/// Copy the price in Pyth's `price_update` account into the cache of
+/// `feed_id`; permissionless
+pub fn refresh_price_cache(feed_id: &[u8; 32], price_update: &Pubkey) -> Instruction {
+    build(
+        accounts::RefreshPriceCache {
+            price_cache: price_cache_address(feed_id).0,
+            price_update: *price_update,
+        },
+        instruction::RefreshPriceCache {},
+    )
+}
+
+/// Price a subscription at `usd_per_period` (6 decimals) in its token at
+/// the price of Pyth feed `feed_id`
+pub fn set_usd_price(
+    subscription_address: &Pubkey,
+    subscription: &Subscription,
+    feed_id: &[u8; 32],
+    usd_per_period: u64,
+    payer: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::SetUsdPrice {
+            subscription: *subscription_address,
+            usd_peg: usd_peg_address(subscription_address).0,
+            price_cache: price_cache_address(feed_id).0,
+            token_mint: subscription.token_mint,
+            authority: subscription.authority,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::SetUsdPrice { usd_per_period },
+    )
+}
+
+/// Go back to charging a fixed `amount_per_period` in tokens
+pub fn close_usd_peg(
+    subscription_address: &Pubkey,
+    authority: &Pubkey,
+    amount_per_period: u64,
+) -> Instruction {
+    build(
+        accounts::CloseUsdPeg {
+            subscription: *subscription_address,
+            usd_peg: usd_peg_address(subscription_address).0,
+            authority: *authority,
+        },
+        instruction::CloseUsdPeg { amount_per_period },
+    )
+}
+
+/// Accept payment in `token_mint` into the recipient's `token_account`, for
+/// subscribers falling back to it. `payer` can be a relayer.
+pub fn accept_fallback_mint(
+    recipient: &Pubkey,
+    authority: &Pubkey,
+    token_mint: &Pubkey,
+    token_account: &Pubkey,
+    payer: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::AcceptFallbackMint {
+            accepted_mint: accepted_mint_address(recipient, token_mint).0,
+            merchant_multisig: merchant_multisig_address(recipient).0,
+            recipient: *recipient,
+            authority: *authority,
+            token_mint: *token_mint,
+            token_account: *token_account,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::AcceptFallbackMint {},
+    )
+}
+
+pub fn close_accepted_mint(
+    recipient: &Pubkey,
+    authority: &Pubkey,
+    token_mint: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::CloseAcceptedMint {
+            accepted_mint: accepted_mint_address(recipient, token_mint).0,
+            merchant_multisig: merchant_multisig_address(recipient).0,
+            recipient: *recipient,
+            authority: *authority,
+        },
+        instruction::CloseAcceptedMint {},
+    )
+}
+
+/// Ban `wallet` from opening subscriptions to `recipient`. `payer` can be a
+/// relayer.
+pub fn deny_subscriber(
+    recipient: &Pubkey,
+    authority: &Pubkey,
+    wallet: &Pubkey,
+    payer: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::DenySubscriber {
+            denylist_entry: denylist_address(recipient, wallet).0,
+            merchant_multisig: merchant_multisig_address(recipient).0,
+            recipient: *recipient,
+            authority: *authority,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::DenySubscriber { wallet: *wallet },
+    )
+}
+
+pub fn allow_subscriber(recipient: &Pubkey, authority: &Pubkey, wallet: &Pubkey) -> Instruction {
+    build(
+        accounts::AllowSubscriber {
+            denylist_entry: denylist_address(recipient, wallet).0,
+            merchant_multisig: merchant_multisig_address(recipient).0,
+            recipient: *recipient,
+            authority: *authority,
+        },
+        instruction::AllowSubscriber {},
+    )
+}
+
+/// Make `recipient` invite-only, so only wallets added with
+/// [`add_to_allowlist`] can subscribe. `payer` can be a relayer.
+pub fn create_subscriber_allowlist(
+    recipient: &Pubkey,
+    authority: &Pubkey,
+    payer: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::CreateSubscriberAllowlist {
+            allowlist: subscriber_allowlist_address(recipient).0,
+            merchant_multisig: merchant_multisig_address(recipient).0,
+            recipient: *recipient,
+            authority: *authority,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreateSubscriberAllowlist {},
+    )
+}
+
+pub fn close_subscriber_allowlist(recipient: &Pubkey, authority: &Pubkey) -> Instruction {
+    build(
+        accounts::CloseSubscriberAllowlist {
+            allowlist: subscriber_allowlist_address(recipient).0,
+            merchant_multisig: merchant_multisig_address(recipient).0,
+            recipient: *recipient,
+            authority: *authority,
+        },
+        instruction::CloseSubscriberAllowlist {},
+    )
+}
+
+/// Invite `wallet` to subscribe to an invite-only `recipient`. `payer` can
+/// be a relayer.
+pub fn add_to_allowlist(
+    recipient: &Pubkey,
+    authority: &Pubkey,
+    wallet: &Pubkey,
+    payer: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::AddToAllowlist {
+            allowlist_entry: allowlist_entry_address(recipient, wallet).0,
+            merchant_multisig: merchant_multisig_address(recipient).0,
+            recipient: *recipient,
+            authority: *authority,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::AddToAllowlist { wallet: *wallet },
+    )
+}
+
+pub fn remove_from_allowlist(
+    recipient: &Pubkey,
+    authority: &Pubkey,
+    wallet: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::RemoveFromAllowlist {
+            allowlist_entry: allowlist_entry_address(recipient, wallet).0,
+            merchant_multisig: merchant_multisig_address(recipient).0,
+            recipient: *recipient,
+            authority: *authority,
+        },
+        instruction::RemoveFromAllowlist {},
+    )
+}
+
+/// Fall back to the authority's ATA for `fallback_mint`, a mint the
+/// recipient accepts, when the subscription's own account is empty. The
+/// ATA must also be approved to the subscription PDA. `payer` can be a
+/// relayer.
+pub fn set_fallback_payment(
+    subscription_address: &Pubkey,
+    subscription: &Subscription,
+    fallback_mint: &Pubkey,
+    payer: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::SetFallbackPayment {
+            subscription: *subscription_address,
+            fallback_payment: fallback_payment_address(subscription_address).0,
+            accepted_mint: accepted_mint_address(&subscription.recipient, fallback_mint).0,
+            token_mint: subscription.token_mint,
+            fallback_mint: *fallback_mint,
+            token_account: associated_token_address(&subscription.authority, fallback_mint),
+            authority: subscription.authority,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::SetFallbackPayment {},
+    )
+}
+
+pub fn close_fallback_payment(subscription_address: &Pubkey, authority: &Pubkey) -> Instruction {
+    build(
+        accounts::CloseFallbackPayment {
+            fallback_payment: fallback_payment_address(subscription_address).0,
+            subscription: *subscription_address,
+            authority: *authority,
+        },
+        instruction::CloseFallbackPayment {},
+    )
+}
+
+/// Charge what is due from the authority's ATA for `fallback_mint`, set by
+/// [`set_fallback_payment`], into `merchant_token_account`, the account the
+/// recipient accepts that mint in. It takes the remaining accounts of a
+/// plain charge but funding sources: the recipient's merchant config first
+/// (or [`PROGRAM_ID`] in its place), then [`with_plan_schedule`], required
+/// on a plan, [`with_sla_discounts`] and [`with_spend_alerts`].
+pub fn charge_subscription_fallback(
+    subscription_address: &Pubkey,
+    subscription: &Subscription,
+    fallback_mint: &Pubkey,
+    merchant_token_account: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::ChargeSubscriptionFallback {
+            charge: accounts::ChargeSubscription {
+                subscription: *subscription_address,
+                user_token_account: subscription.user_token_account,
+                recipient_token_account: subscription.recipient_token_account,
+                token_program: spl_token::ID,
+            },
+            fallback_payment: fallback_payment_address(subscription_address).0,
+            token_account: associated_token_address(&subscription.authority, fallback_mint),
+            accepted_mint: accepted_mint_address(&subscription.recipient, fallback_mint).0,
+            merchant_token_account: *merchant_token_account,
+        },
+        instruction::ChargeSubscriptionFallback {},
+    )
+}
+
+/// Pay a deposit of `amount` from the subscription's token account into
+/// `token_account`, which must already be owned by [`deposit_address`].
+/// `payer` can be a relayer.
+pub fn pay_deposit(
+    subscription_address: &Pubkey,
+    subscription: &Subscription,
+    token_account: &Pubkey,
+    amount: u64,
+    payer: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::PayDeposit {
+            subscription: *subscription_address,
+            deposit: deposit_address(subscription_address).0,
+            token_account: *token_account,
+            user_token_account: subscription.user_token_account,
+            authority: subscription.authority,
+            payer: *payer,
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+        },
+        instruction::PayDeposit { amount },
+    )
+}
+
+/// Refund the deposit of a cancelled subscription. `token_account` is its
+/// escrow and `refund_account` the token account it was paid from, both as
+/// recorded in the deposit.
+pub fn refund_deposit(
+    subscription_address: &Pubkey,
+    authority: &Pubkey,
+    token_account: &Pubkey,
+    refund_account: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::RefundDeposit {
+            deposit: deposit_address(subscription_address).0,
+            subscription: *subscription_address,
+            token_account: *token_account,
+            refund_account: *refund_account,
+            authority: *authority,
+            token_program: spl_token::ID,
+        },
+        instruction::RefundDeposit {},
+    )
+}
+
+/// Offer plan `plan_id` at `monthly_price` of `token_mint`, with
+/// `annual_discount_bps` off for annual billing
+pub fn create_plan(
+    recipient: &Pubkey,
+    authority: &Pubkey,
+    token_mint: &Pubkey,
+    payer: &Pubkey,
+    plan_id: u64,
+    monthly_price: u64,
+    annual_discount_bps: u16,
+) -> Instruction {
+    build(
+        accounts::CreatePlan {
+            plan: plan_address(recipient, plan_id).0,
+            merchant_multisig: merchant_multisig_address(recipient).0,
+            recipient: *recipient,
+            authority: *authority,
+            token_mint: *token_mint,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreatePlan {
+            plan_id,
+            monthly_price,
+            annual_discount_bps,
+        },
+    )
+}
+
+/// Bill the subscription at `plan` every `cadence`, from its next charge
+pub fn join_plan(
+    subscription_address: &Pubkey,
+    subscription: &Subscription,
+    plan: &Pubkey,
+    payer: &Pubkey,
+    cadence: BillingCadence,
+) -> Instruction {
+    build(
+        accounts::JoinPlan {
+            subscription: *subscription_address,
+            plan: *plan,
+            waitlist: waitlist_address(plan).0,
+            plan_subscription: plan_subscription_address(subscription_address).0,
+            authority: subscription.authority,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::JoinPlan { cadence },
+    )
+}
+
+/// Move a plan subscription to `cadence`; going annual charges the
+/// prorated difference from the subscription's token account
+pub fn switch_billing_cadence(
+    subscription_address: &Pubkey,
+    subscription: &Subscription,
+    plan: &Pubkey,
+    cadence: BillingCadence,
+) -> Instruction {
+    build(
+        accounts::SwitchBillingCadence {
+            subscription: *subscription_address,
+            plan_subscription: plan_subscription_address(subscription_address).0,
+            plan: *plan,
+            user_token_account: subscription.user_token_account,
+            recipient_token_account: subscription.recipient_token_account,
+            authority: subscription.authority,
+            token_program: spl_token::ID,
+        },
+        instruction::SwitchBillingCadence { cadence },
+    )
+}
+
+/// Meter `plan` with `included_units` a period, moving subscriptions over
+/// it for `upgrade_after_periods` in a row to `next_tier` and billing units
+/// beyond the allotment at `overage_rate`
+pub fn set_plan_metering(
+    recipient: &Pubkey,
+    authority: &Pubkey,
+    plan: &Pubkey,
+    included_units: u64,
+    upgrade_after_periods: u8,
+    next_tier: Option<Pubkey>,
+    overage_rate: u64,
+) -> Instruction {
+    build(
+        accounts::SetPlanMetering {
+            plan: *plan,
+            merchant_multisig: merchant_multisig_address(recipient).0,
+            recipient: *recipient,
+            authority: *authority,
+        },
+        instruction::SetPlanMetering {
+            included_units,
+            upgrade_after_periods,
+            next_tier,
+            overage_rate,
+        },
+    )
+}
+
+/// Take at most `max_subscribers` subscriptions on `plan` at once; zero
+/// lifts the cap
+pub fn set_plan_capacity(
+    recipient: &Pubkey,
+    authority: &Pubkey,
+    plan: &Pubkey,
+    max_subscribers: u32,
+) -> Instruction {
+    build(
+        accounts::SetPlanCapacity {
+            plan: *plan,
+            merchant_multisig: merchant_multisig_address(recipient).0,
+            recipient: *recipient,
+            authority: *authority,
+        },
+        instruction::SetPlanCapacity { max_subscribers },
+    )
+}
+
+/// Take `intro_discount_bps` off the first `intro_periods` of subscribers
+/// joining `plan` from now on; zero periods ends the offer
+pub fn set_plan_intro_pricing(
+    recipient: &Pubkey,
+    authority: &Pubkey,
+    plan: &Pubkey,
+    intro_discount_bps: u16,
+    intro_periods: u8,
+) -> Instruction {
+    build(
+        accounts::SetPlanIntroPricing {
+            plan: *plan,
+            merchant_multisig: merchant_multisig_address(recipient).0,
+            recipient: *recipient,
+            authority: *authority,
+        },
+        instruction::SetPlanIntroPricing {
+            intro_discount_bps,
+            intro_periods,
+        },
+    )
+}
+
+/// Price the plan's first periods by `price_ramp`, signed by the merchant
+/// authority
+pub fn set_plan_price_ramp(
+    recipient: &Pubkey,
+    authority: &Pubkey,
+    plan: &Pubkey,
+    price_ramp: Vec<RampStep>,
+) -> Instruction {
+    build(
+        accounts::SetPlanPriceRamp {
+            plan: *plan,
+            merchant_multisig: merchant_multisig_address(recipient).0,
+            recipient: *recipient,
+            authority: *authority,
+        },
+        instruction::SetPlanPriceRamp { price_ramp },
+    )
+}
+
+/// Record `units` of usage in the subscription's current period, signed by
+/// the merchant authority
+pub fn record_usage(
+    subscription_address: &Pubkey,
+    subscription: &Subscription,
+    plan: &Pubkey,
+    authority: &Pubkey,
+    units: u64,
+) -> Instruction {
+    build(
+        accounts::RecordUsage {
+            plan_subscription: plan_subscription_address(subscription_address).0,
+            subscription: *subscription_address,
+            plan: *plan,
+            merchant_multisig: merchant_multisig_address(&subscription.recipient).0,
+            recipient: subscription.recipient,
+            authority: *authority,
+        },
+        instruction::RecordUsage { units },
+    )
+}
+
+/// Turn down the subscription's scheduled tier upgrade
+pub fn veto_tier_upgrade(subscription_address: &Pubkey, authority: &Pubkey) -> Instruction {
+    build(
+        accounts::VetoTierUpgrade {
+            plan_subscription: plan_subscription_address(subscription_address).0,
+            subscription: *subscription_address,
+            authority: *authority,
+        },
+        instruction::VetoTierUpgrade {},
+    )
+}
+
+/// Move the subscription from `plan` to `next_tier` once the veto window
+/// has passed; anyone can send it
+pub fn apply_tier_upgrade(
+    subscription_address: &Pubkey,
+    plan: &Pubkey,
+    next_tier: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::ApplyTierUpgrade {
+            plan_subscription: plan_subscription_address(subscription_address).0,
+            subscription: *subscription_address,
+            plan: *plan,
+            next_tier: *next_tier,
+        },
+        instruction::ApplyTierUpgrade {},
+    )
+}
+
+/// Free the `plan` seat of a subscription that is no longer active; anyone
+/// can send it, and the rent goes to `recipient`
+pub fn release_plan_seat(
+    subscription_address: &Pubkey,
+    recipient: &Pubkey,
+    plan: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::ReleasePlanSeat {
+            plan_subscription: plan_subscription_address(subscription_address).0,
+            subscription: *subscription_address,
+            plan: *plan,
+            recipient: *recipient,
+        },
+        instruction::ReleasePlanSeat {},
+    )
+}
+
+/// Let users queue for `plan` while it is full. `payer` can be a relayer.
+pub fn create_waitlist(
+    recipient: &Pubkey,
+    authority: &Pubkey,
+    plan: &Pubkey,
+    payer: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::CreateWaitlist {
+            waitlist: waitlist_address(plan).0,
+            plan: *plan,
+            merchant_multisig: merchant_multisig_address(recipient).0,
+            recipient: *recipient,
+            authority: *authority,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreateWaitlist {},
+    )
+}
+
+/// Queue `authority` for `plan` at `position`, the waitlist's `tail`.
+/// `payer` also puts up the rent of the accounts promotion creates.
+#[allow(clippy::too_many_arguments)]
+pub fn join_waitlist(
+    recipient: &Pubkey,
+    plan: &Pubkey,
+    position: u64,
+    authority: &Pubkey,
+    user_token_account: &Pubkey,
+    recipient_token_account: &Pubkey,
+    payer: &Pubkey,
+    cadence: BillingCadence,
+) -> Instruction {
+    build(
+        accounts::JoinWaitlist {
+            waitlist: waitlist_address(plan).0,
+            entry: waitlist_entry_address(plan, position).0,
+            plan: *plan,
+            subscription: subscription_address(authority, recipient).0,
+            authority: *authority,
+            user_token_account: *user_token_account,
+            recipient_token_account: *recipient_token_account,
+            denylist_entry: denylist_address(recipient, authority).0,
+            allowlist: subscriber_allowlist_address(recipient).0,
+            allowlist_entry: allowlist_entry_address(recipient, authority).0,
+            token_program: spl_token::ID,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::JoinWaitlist { cadence },
+    )
+}
+
+/// Leave a plan's waitlist, refunding `entry` to whoever paid for it
+pub fn leave_waitlist(recipient: &Pubkey, entry: &WaitlistEntry) -> Instruction {
+    build(
+        accounts::LeaveWaitlist {
+            entry: waitlist_entry_address(&entry.plan, entry.position).0,
+            plan: entry.plan,
+            subscription: subscription_address(&entry.authority, recipient).0,
+            authority: entry.authority,
+            payer: entry.payer,
+            user_token_account: entry.user_token_account,
+            token_program: spl_token::ID,
+        },
+        instruction::LeaveWaitlist {},
+    )
+}
+
+/// Subscribe the waitlist's oldest entry, `entry`, to its plan. `payer`
+/// covers the new accounts' rent and gets the entry's lamports.
+pub fn promote_from_waitlist(
+    recipient: &Pubkey,
+    token_mint: &Pubkey,
+    entry: &WaitlistEntry,
+    payer: &Pubkey,
+) -> Instruction {
+    let subscription = subscription_address(&entry.authority, recipient).0;
+    build(
+        accounts::PromoteFromWaitlist {
+            waitlist: waitlist_address(&entry.plan).0,
+            entry: waitlist_entry_address(&entry.plan, entry.position).0,
+            plan: entry.plan,
+            subscription,
+            plan_subscription: plan_subscription_address(&subscription).0,
+            authority: entry.authority,
+            recipient: *recipient,
+            user_token_account: entry.user_token_account,
+            recipient_token_account: entry.recipient_token_account,
+            token_mint: *token_mint,
+            denylist_entry: denylist_address(recipient, &entry.authority).0,
+            allowlist: subscriber_allowlist_address(recipient).0,
+            allowlist_entry: allowlist_entry_address(recipient, &entry.authority).0,
+            token_program: spl_token::ID,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::PromoteFromWaitlist {},
+    )
+}
+
+/// Move `plan`'s waitlist past `position`, its `head`, when the entry there
+/// cannot be promoted. `entry` is `None` once the entry has been left.
+pub fn skip_waitlist_entry(
+    recipient: &Pubkey,
+    plan: &Pubkey,
+    position: u64,
+    entry: Option<&WaitlistEntry>,
+) -> Instruction {
+    let entry_address = waitlist_entry_address(plan, position).0;
+    // A left entry's accounts are never read, so any stand in for them
+    let (wallet, user_token_account, rent_receiver) = match entry {
+        Some(entry) => (entry.authority, entry.user_token_account, entry.payer),
+        None => (entry_address, entry_address, entry_address),
+    };
+    build(
+        accounts::SkipWaitlistEntry {
+            waitlist: waitlist_address(plan).0,
+            entry: entry_address,
+            plan: *plan,
+            subscription: subscription_address(&wallet, recipient).0,
+            denylist_entry: denylist_address(recipient, &wallet).0,
+            allowlist: subscriber_allowlist_address(recipient).0,
+            allowlist_entry: allowlist_entry_address(recipient, &wallet).0,
+            user_token_account,
+            rent_receiver,
+        },
+        instruction::SkipWaitlistEntry {},
+    )
+}
+
+/// Bill `units` of the period's usage beyond the plan's allotment at its
+/// overage rate, signed by the merchant authority
+pub fn charge_overage(
+    subscription_address: &Pubkey,
+    subscription: &Subscription,
+    plan: &Pubkey,
+    authority: &Pubkey,
+    units: u64,
+) -> Instruction {
+    build(
+        accounts::ChargeOverage {
+            plan_subscription: plan_subscription_address(subscription_address).0,
+            subscription: *subscription_address,
+            plan: *plan,
+            merchant_multisig: merchant_multisig_address(&subscription.recipient).0,
+            recipient: subscription.recipient,
+            authority: *authority,
+            user_token_account: subscription.user_token_account,
+            recipient_token_account: subscription.recipient_token_account,
+            token_program: spl_token::ID,
+        },
+        instruction::ChargeOverage { units },
+    )
+}
+
+/// Alert on `thresholds` of the subscription's `total_charged`, ascending.
+/// `payer` can be a relayer.
+pub fn create_spend_alerts(
+    authority: &Pubkey,
+    recipient: &Pubkey,
+    payer: &Pubkey,
+    thresholds: Vec<u64>,
+) -> Instruction {
+    let subscription = subscription_address(authority, recipient).0;
+    build(
+        accounts::CreateSpendAlerts {
+            spend_alerts: spend_alerts_address(&subscription).0,
+            subscription,
+            authority: *authority,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreateSpendAlerts { thresholds },
+    )
+}
+
+/// Replace the spend milestones; an empty list turns the alerts off
+pub fn update_spend_alerts(
+    authority: &Pubkey,
+    recipient: &Pubkey,
+    thresholds: Vec<u64>,
+) -> Instruction {
+    let subscription = subscription_address(authority, recipient).0;
+    build(
+        accounts::UpdateSpendAlerts {
+            spend_alerts: spend_alerts_address(&subscription).0,
+            subscription,
+            authority: *authority,
+        },
+        instruction::UpdateSpendAlerts { thresholds },
+    )
+}
+
+pub fn close_spend_alerts(authority: &Pubkey, recipient: &Pubkey) -> Instruction {
+    let subscription = subscription_address(authority, recipient).0;
+    build(
+        accounts::CloseSpendAlerts {
+            spend_alerts: spend_alerts_address(&subscription).0,
+            authority: *authority,
+        },
+        instruction::CloseSpendAlerts {},
+    )
+}
+
+/// Cap the subscription's lifetime spend at `max_total_spend`, or lift the
+/// cap with `None`. `user_token_account` must be the subscription's.
+pub fn set_spend_limit(
+    authority: &Pubkey,
+    recipient: &Pubkey,
+    user_token_account: &Pubkey,
+    max_total_spend: Option<u64>,
+) -> Instruction {
+    build(
+        accounts::SetSpendLimit {
+            subscription: subscription_address(authority, recipient).0,
+            authority: *authority,
+            user_token_account: *user_token_account,
+            token_program: spl_token::ID,
+        },
+        instruction::SetSpendLimit { max_total_spend },
+    )
+}
+
+/// Append `token_accounts` as the read-only remaining accounts the program
+/// checks a fallback list against
+fn with_token_accounts(mut instruction: Instruction, token_accounts: &[Pubkey]) -> Instruction {
+    instruction.accounts.extend(
+        token_accounts
+            .iter()
+            .map(|account| AccountMeta::new_readonly(*account, false)),
+    );
+    instruction
+}
+
+/// Register fallback token accounts of `authority`, tried in order when the
+/// subscription's own account is short. `payer` can be a relayer.
+pub fn create_funding_sources(
+    authority: &Pubkey,
+    recipient: &Pubkey,
+    payer: &Pubkey,
+    token_accounts: Vec<Pubkey>,
+) -> Instruction {
+    let subscription = subscription_address(authority, recipient).0;
+    let instruction = build(
+        accounts::CreateFundingSources {
+            funding_sources: funding_sources_address(&subscription).0,
+            subscription,
+            authority: *authority,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreateFundingSources {
+            token_accounts: token_accounts.clone(),
+        },
+    );
+    with_token_accounts(instruction, &token_accounts)
+}
+
+/// Replace the fallback list; an empty list turns fallbacks off
+pub fn update_funding_sources(
+    authority: &Pubkey,
+    recipient: &Pubkey,
+    token_accounts: Vec<Pubkey>,
+) -> Instruction {
+    let subscription = subscription_address(authority, recipient).0;
+    let instruction = build(
+        accounts::UpdateFundingSources {
+            funding_sources: funding_sources_address(&subscription).0,
+            subscription,
+            authority: *authority,
+        },
+        instruction::UpdateFundingSources {
+            token_accounts: token_accounts.clone(),
+        },
+    );
+    with_token_accounts(instruction, &token_accounts)
+}
+
+pub fn close_funding_sources(authority: &Pubkey, recipient: &Pubkey) -> Instruction {
+    let subscription = subscription_address(authority, recipient).0;
+    build(
+        accounts::CloseFundingSources {
+            funding_sources: funding_sources_address(&subscription).0,
+            authority: *authority,
+        },
+        instruction::CloseFundingSources {},
+    )
+}
+
+/// Create the platform config; `authority` must be the program's upgrade
+/// authority and becomes the admin
+pub fn create_platform_config(authority: &Pubkey, fee_bps: u16, treasury: Pubkey) -> Instruction {
+    build(
+        accounts::CreatePlatformConfig {
+            platform_config: platform_config_address().0,
+            program: PROGRAM_ID,
+            program_data: program_data_address().0,
+            authority: *authority,
+            system_program: system_program::ID,
+        },
+        instruction::CreatePlatformConfig { fee_bps, treasury },
+    )
+}
+
+/// Queue a new platform fee, treasury or admin, effective after the
+/// program's timelock; `None` leaves that part as it is
+pub fn queue_platform_config_change(
+    admin: &Pubkey,
+    fee_bps: Option<u16>,
+    treasury: Option<Pubkey>,
+    new_admin: Option<Pubkey>,
+) -> Instruction {
+    build(
+        accounts::QueuePlatformConfigChange {
+            config_change: platform_config_change_address().0,
+            platform_config: platform_config_address().0,
+            admin: *admin,
+            system_program: system_program::ID,
+        },
+        instruction::QueuePlatformConfigChange {
+            fee_bps,
+            treasury,
+            admin: new_admin,
+        },
+    )
+}
+
+/// Apply the pending platform config change; permissionless once the
+/// timelock has run out. `admin` is the current admin, which gets the
+/// change's rent back.
+pub fn apply_platform_config_change(admin: &Pubkey) -> Instruction {
+    build(
+        accounts::ApplyPlatformConfigChange {
+            config_change: platform_config_change_address().0,
+            platform_config: platform_config_address().0,
+            admin: *admin,
+        },
+        instruction::ApplyPlatformConfigChange {},
+    )
+}
+
+/// Drop the pending platform config change
+pub fn cancel_platform_config_change(admin: &Pubkey) -> Instruction {
+    build(
+        accounts::CancelPlatformConfigChange {
+            config_change: platform_config_change_address().0,
+            platform_config: platform_config_address().0,
+            admin: *admin,
+        },
+        instruction::CancelPlatformConfigChange {},
+    )
+}
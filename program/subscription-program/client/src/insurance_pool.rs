@@ -0,0 +1,212 @@
+//! Client for the insurance pool recipe program.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use insurance_pool::{accounts, instruction};
+
+pub use insurance_pool::{Claim, ClaimStatus, Policy, Pool, ID as INSURANCE_POOL_PROGRAM_ID};
+
+use crate::pda::associated_token_address;
+
+pub const POOL_SEED: &[u8] = b"pool";
+pub const POLICY_SEED: &[u8] = b"policy";
+pub const CLAIM_SEED: &[u8] = b"claim";
+
+/// Pool PDA of an admin
+pub fn pool_address(admin: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[POOL_SEED, admin.as_ref()], &INSURANCE_POOL_PROGRAM_ID)
+}
+
+/// Policy PDA of a holder in a pool
+pub fn policy_address(pool: &Pubkey, holder: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[POLICY_SEED, pool.as_ref(), holder.as_ref()],
+        &INSURANCE_POOL_PROGRAM_ID,
+    )
+}
+
+/// Claim PDA for the `claim_id`th claim on a policy
+pub fn claim_address(policy: &Pubkey, claim_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[CLAIM_SEED, policy.as_ref(), &claim_id.to_le_bytes()],
+        &INSURANCE_POOL_PROGRAM_ID,
+    )
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: INSURANCE_POOL_PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// The vault is the pool PDA's ATA for `mint`, which must already exist
+#[allow(clippy::too_many_arguments)]
+pub fn create_pool(
+    admin: &Pubkey,
+    mint: &Pubkey,
+    payer: &Pubkey,
+    premium_amount: u64,
+    interval_seconds: i64,
+    grace_seconds: i64,
+    coverage_limit: u64,
+    min_reserve: u64,
+) -> Instruction {
+    let pool = pool_address(admin).0;
+    build(
+        accounts::CreatePool {
+            pool,
+            admin: *admin,
+            mint: *mint,
+            vault: associated_token_address(&pool, mint),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreatePool {
+            premium_amount,
+            interval_seconds,
+            grace_seconds,
+            coverage_limit,
+            min_reserve,
+        },
+    )
+}
+
+/// The holder pays premiums from, and receives payouts into, their ATA
+pub fn enroll(pool: &Pool, holder: &Pubkey, payer: &Pubkey) -> Instruction {
+    let pool_key = pool_address(&pool.admin).0;
+    build(
+        accounts::Enroll {
+            pool: pool_key,
+            policy: policy_address(&pool_key, holder).0,
+            holder: *holder,
+            holder_token_account: associated_token_address(holder, &pool.mint),
+            payer: *payer,
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+        },
+        instruction::Enroll {},
+    )
+}
+
+pub fn collect_premium(pool: &Pool, policy: &Policy) -> Instruction {
+    build(
+        accounts::CollectPremium {
+            pool: policy.pool,
+            policy: policy_address(&policy.pool, &policy.holder).0,
+            holder_token_account: policy.token_account,
+            vault: pool.vault,
+            token_program: spl_token::ID,
+        },
+        instruction::CollectPremium {},
+    )
+}
+
+pub fn lapse_policy(policy: &Policy) -> Instruction {
+    build(
+        accounts::LapsePolicy {
+            pool: policy.pool,
+            policy: policy_address(&policy.pool, &policy.holder).0,
+        },
+        instruction::LapsePolicy {},
+    )
+}
+
+/// Files the policy's next claim
+pub fn file_claim(
+    policy: &Policy,
+    payer: &Pubkey,
+    amount: u64,
+    evidence_hash: [u8; 32],
+) -> Instruction {
+    let policy_key = policy_address(&policy.pool, &policy.holder).0;
+    build(
+        accounts::FileClaim {
+            pool: policy.pool,
+            policy: policy_key,
+            claim: claim_address(&policy_key, policy.next_claim_id).0,
+            holder: policy.holder,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::FileClaim {
+            amount,
+            evidence_hash,
+        },
+    )
+}
+
+pub fn approve_claim(pool: &Pool, policy: &Policy, claim_id: u64, payout: u64) -> Instruction {
+    let policy_key = policy_address(&policy.pool, &policy.holder).0;
+    build(
+        accounts::DecideClaim {
+            pool: policy.pool,
+            policy: policy_key,
+            claim: claim_address(&policy_key, claim_id).0,
+            admin: pool.admin,
+            vault: pool.vault,
+            holder_token_account: policy.token_account,
+            token_program: spl_token::ID,
+        },
+        instruction::ApproveClaim { payout },
+    )
+}
+
+pub fn reject_claim(pool: &Pool, policy: &Policy, claim_id: u64) -> Instruction {
+    let policy_key = policy_address(&policy.pool, &policy.holder).0;
+    build(
+        accounts::RejectClaim {
+            pool: policy.pool,
+            policy: policy_key,
+            claim: claim_address(&policy_key, claim_id).0,
+            admin: pool.admin,
+        },
+        instruction::RejectClaim {},
+    )
+}
+
+/// Pays the admin's ATA for the pool's mint
+pub fn withdraw_surplus(pool: &Pool, amount: u64) -> Instruction {
+    build(
+        accounts::WithdrawSurplus {
+            pool: pool_address(&pool.admin).0,
+            admin: pool.admin,
+            vault: pool.vault,
+            admin_token_account: associated_token_address(&pool.admin, &pool.mint),
+            token_program: spl_token::ID,
+        },
+        instruction::WithdrawSurplus { amount },
+    )
+}
+
+pub fn cancel_policy(policy: &Policy) -> Instruction {
+    build(
+        accounts::CancelPolicy {
+            pool: policy.pool,
+            policy: policy_address(&policy.pool, &policy.holder).0,
+            holder: policy.holder,
+            holder_token_account: policy.token_account,
+            token_program: spl_token::ID,
+        },
+        instruction::CancelPolicy {},
+    )
+}
+
+/// One keeper pass over a pool's policies: a `collect_premium` for every
+/// policy due at `now`, and a `lapse_policy` for every one past its grace
+pub fn due_premiums(pool: &Pool, policies: &[Policy], now: i64) -> Vec<Instruction> {
+    policies
+        .iter()
+        .filter_map(|policy| {
+            if policy.check_premium_due(now, pool.grace_seconds).is_ok() {
+                Some(collect_premium(pool, policy))
+            } else if policy.check_lapsed(now, pool.grace_seconds).is_ok() {
+                Some(lapse_policy(policy))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,120 @@
+//! Client for the invoicing recipe program.
+//!
+//! [`pay_invoice`] only needs the invoice, so a payment link can carry just
+//! its address. Keepers call [`due_overdue`] to flip unpaid invoices past
+//! their due date.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use invoicing::{accounts, instruction};
+
+pub use invoicing::{Invoice, InvoiceStatus, ID as INVOICING_PROGRAM_ID};
+
+use crate::pda::associated_token_address;
+
+pub const INVOICE_SEED: &[u8] = b"invoice";
+
+/// Invoice PDA, numbered per merchant
+pub fn invoice_address(merchant: &Pubkey, invoice_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            INVOICE_SEED,
+            merchant.as_ref(),
+            invoice_id.to_le_bytes().as_ref(),
+        ],
+        &INVOICING_PROGRAM_ID,
+    )
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: INVOICING_PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// Payments go to the merchant's ATA for `mint`
+#[allow(clippy::too_many_arguments)]
+pub fn issue_invoice(
+    merchant: &Pubkey,
+    mint: &Pubkey,
+    rent_payer: &Pubkey,
+    invoice_id: u64,
+    payer: &Pubkey,
+    amount: u64,
+    due_at: i64,
+    reference: [u8; 32],
+) -> Instruction {
+    build(
+        accounts::IssueInvoice {
+            invoice: invoice_address(merchant, invoice_id).0,
+            merchant: *merchant,
+            mint: *mint,
+            merchant_token_account: associated_token_address(merchant, mint),
+            rent_payer: *rent_payer,
+            system_program: system_program::ID,
+        },
+        instruction::IssueInvoice {
+            invoice_id,
+            payer: *payer,
+            amount,
+            due_at,
+            reference,
+        },
+    )
+}
+
+/// Paid from the payer's ATA for the invoice's mint
+pub fn pay_invoice(invoice_address: &Pubkey, invoice: &Invoice) -> Instruction {
+    build(
+        accounts::PayInvoice {
+            invoice: *invoice_address,
+            payer: invoice.payer,
+            payer_token_account: associated_token_address(&invoice.payer, &invoice.mint),
+            merchant_token_account: invoice.merchant_token_account,
+            token_program: spl_token::ID,
+        },
+        instruction::PayInvoice {},
+    )
+}
+
+pub fn mark_overdue(invoice_address: &Pubkey) -> Instruction {
+    build(
+        accounts::MarkOverdue {
+            invoice: *invoice_address,
+        },
+        instruction::MarkOverdue {},
+    )
+}
+
+pub fn cancel_invoice(merchant: &Pubkey, invoice_id: u64) -> Instruction {
+    build(
+        accounts::CancelInvoice {
+            invoice: invoice_address(merchant, invoice_id).0,
+            merchant: *merchant,
+        },
+        instruction::CancelInvoice {},
+    )
+}
+
+pub fn close_invoice(merchant: &Pubkey, invoice_id: u64) -> Instruction {
+    build(
+        accounts::CloseInvoice {
+            invoice: invoice_address(merchant, invoice_id).0,
+            merchant: *merchant,
+        },
+        instruction::CloseInvoice {},
+    )
+}
+
+/// One keeper pass: a `mark_overdue` for every open invoice past its due
+/// date at `now`
+pub fn due_overdue(invoices: &[(Pubkey, Invoice)], now: i64) -> Vec<Instruction> {
+    invoices
+        .iter()
+        .filter(|(_, invoice)| invoice.status == InvoiceStatus::Open && now > invoice.due_at)
+        .map(|(address, _)| mark_overdue(address))
+        .collect()
+}
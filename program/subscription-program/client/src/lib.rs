@@ -0,0 +1,56 @@
+//! Rust client for the subscription program.
+//!
+//! Everything that only needs account bytes (PDA derivation, instruction
+//! building, decoding) is always available and builds for
+//! `wasm32-unknown-unknown`, so browser and extension apps can share this code.
+//! Helpers that talk to an RPC node live behind the `rpc` feature, which pulls
+//! in `solana-client` and is native-only.
+
+#[cfg(all(feature = "rpc", target_arch = "wasm32"))]
+compile_error!("the `rpc` feature depends on solana-client, which does not build for wasm32");
+
+pub mod accounts;
+pub mod allowance;
+pub mod api_credits;
+pub mod builder;
+pub mod charity_donations;
+pub mod compressed_subscriptions;
+pub mod dao_membership;
+pub mod dca;
+pub mod error;
+pub mod escrow;
+pub mod events;
+pub mod family_plan;
+pub mod hybrid_auth;
+pub mod instructions;
+pub mod insurance_pool;
+pub mod invoicing;
+pub mod listing_fees;
+pub mod loyalty_points;
+pub mod milestone_escrow;
+pub mod nft_rental;
+pub mod offline;
+pub mod passkey_devices;
+pub mod passkey_recovery;
+pub mod paymaster;
+pub mod payroll;
+pub mod paywall;
+pub mod pda;
+pub mod preflight;
+pub mod raffle;
+pub mod round_up_savings;
+pub mod sealed_auction;
+pub mod send;
+pub mod session_keys;
+pub mod spending_limits;
+pub mod split_checkout;
+pub mod stake_discounts;
+pub mod tipping;
+pub mod transaction;
+pub mod vesting;
+pub mod vouchers;
+
+pub use error::{ClientError, Result};
+pub use subscription_program::{
+    BillingCadence, CronSchedule, Interval, RampStep, Subscription, ID as PROGRAM_ID,
+};
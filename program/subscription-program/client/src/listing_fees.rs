@@ -0,0 +1,249 @@
+//! Client for the marketplace listing fees recipe program.
+//!
+//! The keeper renews due listings and delists lapsed ones with
+//! [`due_listing_fees`], the same way it charges subscriptions.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use listing_fees::{accounts, instruction};
+
+pub use listing_fees::{Listing, Marketplace, SellerAccount, ID as LISTING_FEES_PROGRAM_ID};
+
+use crate::pda::associated_token_address;
+
+pub const MARKETPLACE_SEED: &[u8] = b"marketplace";
+pub const SELLER_SEED: &[u8] = b"seller";
+pub const LISTING_SEED: &[u8] = b"listing";
+
+/// Marketplace PDA of an authority; an authority runs one marketplace
+pub fn marketplace_address(authority: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[MARKETPLACE_SEED, authority.as_ref()],
+        &LISTING_FEES_PROGRAM_ID,
+    )
+}
+
+/// Seller account PDA of `seller` at a marketplace; the delegate of their fee
+/// token account
+pub fn seller_account_address(marketplace: &Pubkey, seller: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[SELLER_SEED, marketplace.as_ref(), seller.as_ref()],
+        &LISTING_FEES_PROGRAM_ID,
+    )
+}
+
+/// Listing PDA; listing ids count up per seller from 0
+pub fn listing_address(seller_account: &Pubkey, listing_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            LISTING_SEED,
+            seller_account.as_ref(),
+            &listing_id.to_le_bytes(),
+        ],
+        &LISTING_FEES_PROGRAM_ID,
+    )
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: LISTING_FEES_PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// Fees land in the authority's ATA for `mint`
+pub fn create_marketplace(
+    authority: &Pubkey,
+    mint: &Pubkey,
+    payer: &Pubkey,
+    fee_per_period: u64,
+    interval_seconds: i64,
+    grace_seconds: i64,
+) -> Instruction {
+    build(
+        accounts::CreateMarketplace {
+            marketplace: marketplace_address(authority).0,
+            authority: *authority,
+            mint: *mint,
+            treasury_token_account: associated_token_address(authority, mint),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreateMarketplace {
+            fee_per_period,
+            interval_seconds,
+            grace_seconds,
+        },
+    )
+}
+
+pub fn update_marketplace(
+    authority: &Pubkey,
+    fee_per_period: u64,
+    interval_seconds: i64,
+    grace_seconds: i64,
+) -> Instruction {
+    build(
+        accounts::UpdateMarketplace {
+            marketplace: marketplace_address(authority).0,
+            authority: *authority,
+        },
+        instruction::UpdateMarketplace {
+            fee_per_period,
+            interval_seconds,
+            grace_seconds,
+        },
+    )
+}
+
+/// Delegates the seller's ATA for the marketplace mint to their seller account
+pub fn register_seller(marketplace: &Marketplace, seller: &Pubkey, payer: &Pubkey) -> Instruction {
+    let marketplace_key = marketplace_address(&marketplace.authority).0;
+    build(
+        accounts::RegisterSeller {
+            marketplace: marketplace_key,
+            seller_account: seller_account_address(&marketplace_key, seller).0,
+            seller: *seller,
+            fee_token_account: associated_token_address(seller, &marketplace.mint),
+            payer: *payer,
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+        },
+        instruction::RegisterSeller {},
+    )
+}
+
+/// Creates listing number `seller_account.next_listing_id`
+pub fn create_listing(
+    marketplace: &Marketplace,
+    seller_account: &SellerAccount,
+    payer: &Pubkey,
+    price: u64,
+    uri: String,
+) -> Instruction {
+    let seller_key = seller_account_address(&seller_account.marketplace, &seller_account.seller).0;
+    build(
+        accounts::CreateListing {
+            marketplace: seller_account.marketplace,
+            seller_account: seller_key,
+            listing: listing_address(&seller_key, seller_account.next_listing_id).0,
+            seller: seller_account.seller,
+            fee_token_account: seller_account.fee_token_account,
+            treasury_token_account: marketplace.treasury_token_account,
+            payer: *payer,
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+        },
+        instruction::CreateListing { price, uri },
+    )
+}
+
+pub fn renew_listing(
+    marketplace: &Marketplace,
+    seller_account: &SellerAccount,
+    listing: &Listing,
+) -> Instruction {
+    let seller_key = seller_account_address(&listing.marketplace, &listing.seller).0;
+    build(
+        accounts::RenewListing {
+            marketplace: listing.marketplace,
+            seller_account: seller_key,
+            listing: listing_address(&seller_key, listing.listing_id).0,
+            fee_token_account: seller_account.fee_token_account,
+            treasury_token_account: marketplace.treasury_token_account,
+            token_program: spl_token::ID,
+        },
+        instruction::RenewListing {},
+    )
+}
+
+pub fn delist_lapsed(listing: &Listing) -> Instruction {
+    let seller_key = seller_account_address(&listing.marketplace, &listing.seller).0;
+    build(
+        accounts::DelistLapsed {
+            marketplace: listing.marketplace,
+            seller_account: seller_key,
+            listing: listing_address(&seller_key, listing.listing_id).0,
+        },
+        instruction::DelistLapsed {},
+    )
+}
+
+pub fn relist(
+    marketplace: &Marketplace,
+    seller_account: &SellerAccount,
+    listing: &Listing,
+    price: u64,
+) -> Instruction {
+    let seller_key = seller_account_address(&listing.marketplace, &listing.seller).0;
+    build(
+        accounts::Relist {
+            marketplace: listing.marketplace,
+            seller_account: seller_key,
+            listing: listing_address(&seller_key, listing.listing_id).0,
+            seller: listing.seller,
+            fee_token_account: seller_account.fee_token_account,
+            treasury_token_account: marketplace.treasury_token_account,
+            token_program: spl_token::ID,
+        },
+        instruction::Relist { price },
+    )
+}
+
+pub fn close_listing(listing: &Listing) -> Instruction {
+    let seller_key = seller_account_address(&listing.marketplace, &listing.seller).0;
+    build(
+        accounts::CloseListing {
+            marketplace: listing.marketplace,
+            seller_account: seller_key,
+            listing: listing_address(&seller_key, listing.listing_id).0,
+            seller: listing.seller,
+        },
+        instruction::CloseListing {},
+    )
+}
+
+pub fn close_seller(seller_account: &SellerAccount) -> Instruction {
+    build(
+        accounts::CloseSeller {
+            seller_account: seller_account_address(
+                &seller_account.marketplace,
+                &seller_account.seller,
+            )
+            .0,
+            seller: seller_account.seller,
+            fee_token_account: seller_account.fee_token_account,
+            token_program: spl_token::ID,
+        },
+        instruction::CloseSeller {},
+    )
+}
+
+/// One keeper pass over a marketplace's listings: a `renew_listing` for every
+/// listing with a fee due at `now`, and a `delist_lapsed` for every listing
+/// past its grace period. Listings whose seller is missing from `sellers`
+/// are skipped for renewal.
+pub fn due_listing_fees(
+    marketplace: &Marketplace,
+    sellers: &[SellerAccount],
+    listings: &[Listing],
+    now: i64,
+) -> Vec<Instruction> {
+    listings
+        .iter()
+        .filter_map(|listing| {
+            if listing.check_lapsed(now, marketplace.grace_seconds).is_ok() {
+                return Some(delist_lapsed(listing));
+            }
+            listing
+                .check_renewal_due(now, marketplace.grace_seconds)
+                .ok()?;
+            let seller_account = sellers.iter().find(|seller| {
+                seller.marketplace == listing.marketplace && seller.seller == listing.seller
+            })?;
+            Some(renew_listing(marketplace, seller_account, listing))
+        })
+        .collect()
+}
@@ -0,0 +1,149 @@
+//! Client for the loyalty points recipe program.
+//!
+//! Subscription spend is credited by a crank: [`due_credits`] returns an
+//! `earn_from_subscription` for every member whose subscription has been
+//! charged since it was last credited.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use loyalty_points::{accounts, instruction};
+
+pub use loyalty_points::{Loyalty, Member, ID as LOYALTY_POINTS_PROGRAM_ID};
+
+use crate::pda::subscription_address;
+use crate::Subscription;
+
+pub const LOYALTY_SEED: &[u8] = b"loyalty";
+pub const MEMBER_SEED: &[u8] = b"member";
+
+/// Loyalty PDA of a merchant; one per merchant
+pub fn loyalty_address(merchant: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[LOYALTY_SEED, merchant.as_ref()],
+        &LOYALTY_POINTS_PROGRAM_ID,
+    )
+}
+
+/// Member PDA holding an owner's points balance
+pub fn member_address(loyalty: &Pubkey, owner: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[MEMBER_SEED, loyalty.as_ref(), owner.as_ref()],
+        &LOYALTY_POINTS_PROGRAM_ID,
+    )
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: LOYALTY_POINTS_PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// Members earn `earn_points` per `per_amount` base units spent
+pub fn create_loyalty(
+    merchant: &Pubkey,
+    payer: &Pubkey,
+    earn_authority: &Pubkey,
+    earn_points: u64,
+    per_amount: u64,
+    min_redemption: u64,
+) -> Instruction {
+    build(
+        accounts::CreateLoyalty {
+            loyalty: loyalty_address(merchant).0,
+            merchant: *merchant,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreateLoyalty {
+            earn_authority: *earn_authority,
+            earn_points,
+            per_amount,
+            min_redemption,
+        },
+    )
+}
+
+pub fn update_loyalty(
+    merchant: &Pubkey,
+    earn_authority: &Pubkey,
+    earn_points: u64,
+    per_amount: u64,
+    min_redemption: u64,
+) -> Instruction {
+    build(
+        accounts::UpdateLoyalty {
+            loyalty: loyalty_address(merchant).0,
+            merchant: *merchant,
+        },
+        instruction::UpdateLoyalty {
+            earn_authority: *earn_authority,
+            earn_points,
+            per_amount,
+            min_redemption,
+        },
+    )
+}
+
+pub fn join(merchant: &Pubkey, owner: &Pubkey, payer: &Pubkey) -> Instruction {
+    let loyalty = loyalty_address(merchant).0;
+    build(
+        accounts::Join {
+            loyalty,
+            member: member_address(&loyalty, owner).0,
+            owner: *owner,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::Join {},
+    )
+}
+
+/// Signed by the loyalty program's earn authority
+pub fn earn(loyalty: &Loyalty, owner: &Pubkey, amount: u64) -> Instruction {
+    let address = loyalty_address(&loyalty.merchant).0;
+    build(
+        accounts::Earn {
+            loyalty: address,
+            member: member_address(&address, owner).0,
+            earn_authority: loyalty.earn_authority,
+        },
+        instruction::Earn { amount },
+    )
+}
+
+pub fn earn_from_subscription(merchant: &Pubkey, owner: &Pubkey) -> Instruction {
+    let loyalty = loyalty_address(merchant).0;
+    build(
+        accounts::EarnFromSubscription {
+            loyalty,
+            member: member_address(&loyalty, owner).0,
+            subscription: subscription_address(owner, merchant).0,
+        },
+        instruction::EarnFromSubscription {},
+    )
+}
+
+pub fn redeem(merchant: &Pubkey, owner: &Pubkey, points: u64, reward_id: u64) -> Instruction {
+    let loyalty = loyalty_address(merchant).0;
+    build(
+        accounts::Redeem {
+            loyalty,
+            member: member_address(&loyalty, owner).0,
+            owner: *owner,
+        },
+        instruction::Redeem { points, reward_id },
+    )
+}
+
+/// One crank pass over a merchant's members and their subscriptions to it:
+/// an `earn_from_subscription` for every member with uncredited charges
+pub fn due_credits(merchant: &Pubkey, members: &[(Member, Subscription)]) -> Vec<Instruction> {
+    members
+        .iter()
+        .filter(|(member, subscription)| member.uncredited_charges(subscription.total_charged) > 0)
+        .map(|(member, _)| earn_from_subscription(merchant, &member.owner))
+        .collect()
+}
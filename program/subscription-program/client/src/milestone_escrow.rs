@@ -0,0 +1,212 @@
+//! Client for the milestone escrow recipe program.
+//!
+//! Every instruction takes its signer apart from the transaction's fee payer,
+//! so a relayer can submit them all for passkey wallets. Vaults are always
+//! the contract PDA's ATA.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use milestone_escrow::{accounts, instruction};
+
+pub use milestone_escrow::{
+    Contract, Milestone, MilestoneStatus, ID as MILESTONE_ESCROW_PROGRAM_ID, MAX_MILESTONES,
+};
+
+use crate::pda::associated_token_address;
+
+pub const CONTRACT_SEED: &[u8] = b"contract";
+
+/// Contract PDA for a (client, freelancer, contract_id) triple
+pub fn contract_address(client: &Pubkey, freelancer: &Pubkey, contract_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            CONTRACT_SEED,
+            client.as_ref(),
+            freelancer.as_ref(),
+            &contract_id.to_le_bytes(),
+        ],
+        &MILESTONE_ESCROW_PROGRAM_ID,
+    )
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: MILESTONE_ESCROW_PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// Fund a contract from the client's ATA. The vault ATA must exist, so
+/// prepend a `CreateIdempotent` for `associated_token_address(&contract, mint)`.
+#[allow(clippy::too_many_arguments)]
+pub fn create_contract(
+    client: &Pubkey,
+    freelancer: &Pubkey,
+    arbiter: &Pubkey,
+    mint: &Pubkey,
+    payer: &Pubkey,
+    contract_id: u64,
+    amounts: Vec<u64>,
+    review_period: i64,
+) -> Instruction {
+    let contract = contract_address(client, freelancer, contract_id).0;
+    build(
+        accounts::CreateContract {
+            contract,
+            client: *client,
+            freelancer: *freelancer,
+            arbiter: *arbiter,
+            mint: *mint,
+            client_token_account: associated_token_address(client, mint),
+            freelancer_token_account: associated_token_address(freelancer, mint),
+            vault: associated_token_address(&contract, mint),
+            token_program: spl_token::ID,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreateContract {
+            contract_id,
+            amounts,
+            review_period,
+        },
+    )
+}
+
+pub fn submit_milestone(contract_address: &Pubkey, contract: &Contract, index: u8) -> Instruction {
+    build(
+        accounts::SubmitMilestone {
+            contract: *contract_address,
+            freelancer: contract.freelancer,
+        },
+        instruction::SubmitMilestone { index },
+    )
+}
+
+/// `signer` is the client or the freelancer
+pub fn open_dispute(contract_address: &Pubkey, signer: &Pubkey, index: u8) -> Instruction {
+    build(
+        accounts::OpenDispute {
+            contract: *contract_address,
+            signer: *signer,
+        },
+        instruction::OpenDispute { index },
+    )
+}
+
+fn payout(
+    contract_address: &Pubkey,
+    contract: &Contract,
+    signer: &Pubkey,
+    data: impl InstructionData,
+) -> Instruction {
+    build(
+        accounts::Payout {
+            contract: *contract_address,
+            signer: *signer,
+            client_token_account: contract.client_token_account,
+            freelancer_token_account: contract.freelancer_token_account,
+            vault: contract.vault,
+            token_program: spl_token::ID,
+        },
+        data,
+    )
+}
+
+/// Client pays `amount` of a milestone to the freelancer
+pub fn release_milestone(
+    contract_address: &Pubkey,
+    contract: &Contract,
+    index: u8,
+    amount: u64,
+) -> Instruction {
+    payout(
+        contract_address,
+        contract,
+        &contract.client,
+        instruction::ReleaseMilestone { index, amount },
+    )
+}
+
+/// Freelancer returns what is left of a milestone to the client
+pub fn refund_milestone(contract_address: &Pubkey, contract: &Contract, index: u8) -> Instruction {
+    payout(
+        contract_address,
+        contract,
+        &contract.freelancer,
+        instruction::RefundMilestone { index },
+    )
+}
+
+pub fn resolve_dispute(
+    contract_address: &Pubkey,
+    contract: &Contract,
+    index: u8,
+    freelancer_share: u64,
+) -> Instruction {
+    payout(
+        contract_address,
+        contract,
+        &contract.arbiter,
+        instruction::ResolveDispute {
+            index,
+            freelancer_share,
+        },
+    )
+}
+
+/// Permissionless; `keeper` only signs
+pub fn settle_milestone(
+    contract_address: &Pubkey,
+    contract: &Contract,
+    keeper: &Pubkey,
+    index: u8,
+) -> Instruction {
+    payout(
+        contract_address,
+        contract,
+        keeper,
+        instruction::SettleMilestone { index },
+    )
+}
+
+/// Permissionless once every milestone is settled
+pub fn close_contract(contract_address: &Pubkey, contract: &Contract) -> Instruction {
+    build(
+        accounts::CloseContract {
+            contract: *contract_address,
+            client: contract.client,
+            client_token_account: contract.client_token_account,
+            vault: contract.vault,
+            token_program: spl_token::ID,
+        },
+        instruction::CloseContract {},
+    )
+}
+
+/// One keeper pass over a contract: a `settle_milestone` for every milestone
+/// whose review ended by `now`, then a `close_contract` if that finishes it
+pub fn due_settlements(
+    contract_address: &Pubkey,
+    contract: &Contract,
+    keeper: &Pubkey,
+    now: i64,
+) -> Vec<Instruction> {
+    let due = contract.due_settlements(now);
+    let finishes = contract
+        .milestones
+        .iter()
+        .enumerate()
+        .all(|(index, milestone)| {
+            milestone.status == MilestoneStatus::Settled || due.contains(&(index as u8))
+        });
+    let mut instructions: Vec<Instruction> = due
+        .into_iter()
+        .map(|index| settle_milestone(contract_address, contract, keeper, index))
+        .collect();
+    if finishes {
+        instructions.push(close_contract(contract_address, contract));
+    }
+    instructions
+}
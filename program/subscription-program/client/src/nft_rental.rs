@@ -0,0 +1,127 @@
+//! Client for the NFT rental recipe program.
+//!
+//! Apps decide who may use a rented NFT with `Rental::current_renter`. The
+//! keeper collects rent with [`due_rent`], the same way it charges
+//! subscriptions.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use nft_rental::{accounts, instruction};
+
+pub use nft_rental::{Lease, Rental, ID as NFT_RENTAL_PROGRAM_ID};
+
+use crate::pda::associated_token_address;
+
+pub const RENTAL_SEED: &[u8] = b"rental";
+
+/// Rental PDA of an NFT; an NFT is listed at most once
+pub fn rental_address(nft_mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[RENTAL_SEED, nft_mint.as_ref()], &NFT_RENTAL_PROGRAM_ID)
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: NFT_RENTAL_PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// Escrows the NFT from the owner's ATA into the rental PDA's ATA, which must
+/// exist; rent is paid into the owner's ATA for `payment_mint`
+pub fn list_nft(
+    owner: &Pubkey,
+    nft_mint: &Pubkey,
+    payment_mint: &Pubkey,
+    payer: &Pubkey,
+    rent_per_period: u64,
+    interval_seconds: i64,
+    grace_seconds: i64,
+) -> Instruction {
+    let rental = rental_address(nft_mint).0;
+    build(
+        accounts::ListNft {
+            rental,
+            owner: *owner,
+            nft_mint: *nft_mint,
+            owner_nft_account: associated_token_address(owner, nft_mint),
+            vault: associated_token_address(&rental, nft_mint),
+            payment_mint: *payment_mint,
+            owner_token_account: associated_token_address(owner, payment_mint),
+            payer: *payer,
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+        },
+        instruction::ListNft {
+            rent_per_period,
+            interval_seconds,
+            grace_seconds,
+        },
+    )
+}
+
+/// Rent is pulled from the renter's ATA for the rental's payment mint
+pub fn start_rental(rental: &Rental, renter: &Pubkey) -> Instruction {
+    build(
+        accounts::StartRental {
+            rental: rental_address(&rental.nft_mint).0,
+            renter: *renter,
+            renter_token_account: associated_token_address(renter, &rental.payment_mint),
+            owner_token_account: rental.owner_token_account,
+            token_program: spl_token::ID,
+        },
+        instruction::StartRental {},
+    )
+}
+
+/// `None` when the NFT has no renter
+pub fn collect_rent(rental: &Rental) -> Option<Instruction> {
+    let lease = rental.lease?;
+    Some(build(
+        accounts::CollectRent {
+            rental: rental_address(&rental.nft_mint).0,
+            renter_token_account: lease.renter_token_account,
+            owner_token_account: rental.owner_token_account,
+            token_program: spl_token::ID,
+        },
+        instruction::CollectRent {},
+    ))
+}
+
+/// `None` when the NFT has no renter
+pub fn end_rental(rental: &Rental) -> Option<Instruction> {
+    let lease = rental.lease?;
+    Some(build(
+        accounts::EndRental {
+            rental: rental_address(&rental.nft_mint).0,
+            renter: lease.renter,
+            renter_token_account: lease.renter_token_account,
+            token_program: spl_token::ID,
+        },
+        instruction::EndRental {},
+    ))
+}
+
+/// Returns the NFT to the owner's ATA, which must exist
+pub fn reclaim_nft(rental: &Rental) -> Instruction {
+    build(
+        accounts::ReclaimNft {
+            rental: rental_address(&rental.nft_mint).0,
+            owner: rental.owner,
+            vault: rental.vault,
+            owner_nft_account: associated_token_address(&rental.owner, &rental.nft_mint),
+            token_program: spl_token::ID,
+        },
+        instruction::ReclaimNft {},
+    )
+}
+
+/// One keeper pass: a `collect_rent` for every rental with rent due at `now`
+pub fn due_rent(rentals: &[Rental], now: i64) -> Vec<Instruction> {
+    rentals
+        .iter()
+        .filter(|rental| rental.check_rent_due(now).is_ok())
+        .filter_map(collect_rent)
+        .collect()
+}
@@ -0,0 +1,383 @@
+//! Offline signing.
+//!
+//! Merchants that keep their treasury key on an air-gapped machine cannot sign
+//! in the same process that builds the transaction. The flow is:
+//!
+//! 1. build the message online (use a durable nonce so it does not expire
+//!    while it is being carried around) and [`OfflineTransaction::write_to`] a file;
+//! 2. on the offline machine, [`OfflineTransaction::read_from`] the file and
+//!    [`OfflineTransaction::sign`] it, or sign [`OfflineTransaction::message_bytes`]
+//!    with a separate tool and [`OfflineTransaction::add_signature`] the result;
+//! 3. back online, [`OfflineTransaction::into_transaction`] and submit.
+//!
+//! The file is JSON so it can be inspected before anything is signed.
+
+use std::fs;
+use std::path::Path;
+
+use anchor_lang::prelude::Pubkey;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use solana_hash::Hash;
+use solana_instruction::Instruction;
+use solana_message::VersionedMessage;
+use solana_nonce::state::State as NonceState;
+use solana_nonce::versions::Versions as NonceVersions;
+use solana_signature::Signature;
+use solana_signer::Signer;
+use solana_system_interface::instruction::advance_nonce_account;
+use solana_transaction::versioned::VersionedTransaction;
+
+use crate::{ClientError, Result};
+
+/// Bump when the file layout changes
+pub const OFFLINE_FORMAT_VERSION: u8 = 1;
+
+/// A message waiting for signatures, plus whatever signatures it has collected
+#[derive(Debug, Clone, PartialEq)]
+pub struct OfflineTransaction {
+    message: VersionedMessage,
+    signatures: Vec<Option<Signature>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct OfflineFile {
+    version: u8,
+    /// base64 of the serialized message, exactly the bytes signers sign
+    message: String,
+    signers: Vec<SignerEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SignerEntry {
+    pubkey: String,
+    signature: Option<String>,
+}
+
+impl OfflineTransaction {
+    pub fn new(message: VersionedMessage) -> Self {
+        let signatures = vec![None; message.header().num_required_signatures as usize];
+        Self {
+            message,
+            signatures,
+        }
+    }
+
+    pub fn message(&self) -> &VersionedMessage {
+        &self.message
+    }
+
+    /// The bytes every signer has to sign
+    pub fn message_bytes(&self) -> Vec<u8> {
+        self.message.serialize()
+    }
+
+    /// Signers in the order the runtime expects their signatures
+    pub fn required_signers(&self) -> &[Pubkey] {
+        &self.message.static_account_keys()[..self.signatures.len()]
+    }
+
+    pub fn missing_signers(&self) -> Vec<Pubkey> {
+        self.required_signers()
+            .iter()
+            .zip(&self.signatures)
+            .filter(|(_, signature)| signature.is_none())
+            .map(|(pubkey, _)| *pubkey)
+            .collect()
+    }
+
+    pub fn is_fully_signed(&self) -> bool {
+        self.signatures.iter().all(Option::is_some)
+    }
+
+    /// Sign with a key available on this machine
+    pub fn sign(&mut self, signer: &dyn Signer) -> Result<()> {
+        let pubkey = signer.try_pubkey().map_err(signing_error)?;
+        let signature = signer
+            .try_sign_message(&self.message_bytes())
+            .map_err(signing_error)?;
+        self.add_signature(&pubkey, signature)
+    }
+
+    /// Attach a signature produced elsewhere (hardware wallet, `solana sign-offchain`
+    /// style tools). It is verified against the message before being accepted.
+    pub fn add_signature(&mut self, pubkey: &Pubkey, signature: Signature) -> Result<()> {
+        let index = self
+            .required_signers()
+            .iter()
+            .position(|signer| signer == pubkey)
+            .ok_or(ClientError::UnexpectedSigner(*pubkey))?;
+
+        if !signature.verify(pubkey.as_ref(), &self.message_bytes()) {
+            return Err(ClientError::InvalidSignature(*pubkey));
+        }
+
+        self.signatures[index] = Some(signature);
+        Ok(())
+    }
+
+    /// The submittable transaction. Errors if any required signature is missing.
+    pub fn into_transaction(self) -> Result<VersionedTransaction> {
+        let missing = self.missing_signers();
+        if !missing.is_empty() {
+            return Err(ClientError::MissingSignatures(missing));
+        }
+
+        Ok(VersionedTransaction {
+            signatures: self.signatures.into_iter().flatten().collect(),
+            message: self.message,
+        })
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        let file = OfflineFile {
+            version: OFFLINE_FORMAT_VERSION,
+            message: STANDARD.encode(self.message_bytes()),
+            signers: self
+                .required_signers()
+                .iter()
+                .zip(&self.signatures)
+                .map(|(pubkey, signature)| SignerEntry {
+                    pubkey: pubkey.to_string(),
+                    signature: signature.map(|signature| signature.to_string()),
+                })
+                .collect(),
+        };
+
+        serde_json::to_string_pretty(&file).map_err(offline_error)
+    }
+
+    /// Parse a file written by [`Self::to_json`]. Signatures are re-verified,
+    /// so a tampered message or signature is rejected here rather than on submit.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let file: OfflineFile = serde_json::from_str(json).map_err(offline_error)?;
+        if file.version != OFFLINE_FORMAT_VERSION {
+            return Err(ClientError::Offline(format!(
+                "unsupported format version {}",
+                file.version
+            )));
+        }
+
+        let bytes = STANDARD.decode(&file.message).map_err(offline_error)?;
+        let message: VersionedMessage = bincode::deserialize(&bytes).map_err(offline_error)?;
+        let mut transaction = Self::new(message);
+
+        if file.signers.len() != transaction.signatures.len() {
+            return Err(ClientError::Offline(format!(
+                "message requires {} signers, file lists {}",
+                transaction.signatures.len(),
+                file.signers.len()
+            )));
+        }
+
+        for entry in file.signers {
+            let pubkey: Pubkey = entry.pubkey.parse().map_err(offline_error)?;
+            match entry.signature {
+                Some(signature) => {
+                    transaction.add_signature(&pubkey, signature.parse().map_err(offline_error)?)?
+                }
+                None if transaction.required_signers().contains(&pubkey) => {}
+                None => return Err(ClientError::UnexpectedSigner(pubkey)),
+            }
+        }
+
+        Ok(transaction)
+    }
+
+    pub fn write_to(&self, path: impl AsRef<Path>) -> Result<()> {
+        fs::write(path, self.to_json()?).map_err(offline_error)
+    }
+
+    pub fn read_from(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_json(&fs::read_to_string(path).map_err(offline_error)?)
+    }
+}
+
+/// Prepend `AdvanceNonceAccount` so the message can use the nonce as its
+/// blockhash and stay valid for as long as signing takes
+pub fn with_durable_nonce(
+    nonce_account: &Pubkey,
+    nonce_authority: &Pubkey,
+    instructions: &[Instruction],
+) -> Vec<Instruction> {
+    let mut with_nonce = Vec::with_capacity(instructions.len() + 1);
+    with_nonce.push(advance_nonce_account(nonce_account, nonce_authority));
+    with_nonce.extend_from_slice(instructions);
+    with_nonce
+}
+
+/// Authority and stored nonce (usable as a blockhash) of a nonce account
+pub fn decode_nonce_account(address: &Pubkey, data: &[u8]) -> Result<(Pubkey, Hash)> {
+    let versions: NonceVersions = bincode::deserialize(data)
+        .map_err(|err| ClientError::Offline(format!("nonce account {address}: {err}")))?;
+
+    match versions.state() {
+        NonceState::Initialized(data) => Ok((data.authority, data.blockhash())),
+        NonceState::Uninitialized => Err(ClientError::Offline(format!(
+            "nonce account {address} is not initialized"
+        ))),
+    }
+}
+
+fn offline_error(err: impl std::fmt::Display) -> ClientError {
+    ClientError::Offline(err.to_string())
+}
+
+fn signing_error(err: impl std::fmt::Display) -> ClientError {
+    ClientError::Signing(err.to_string())
+}
+
+#[cfg(feature = "rpc")]
+pub use self::rpc::*;
+
+#[cfg(feature = "rpc")]
+mod rpc {
+    use solana_client::nonblocking::rpc_client::RpcClient;
+
+    use super::*;
+    use crate::send::{send_and_confirm, Expiry, SendConfig};
+
+    /// Current authority and nonce of a durable nonce account
+    pub async fn fetch_nonce(rpc: &RpcClient, address: &Pubkey) -> Result<(Pubkey, Hash)> {
+        let account = rpc
+            .get_account_with_commitment(address, rpc.commitment())
+            .await?
+            .value
+            .ok_or(ClientError::AccountNotFound(*address))?;
+
+        decode_nonce_account(address, &account.data)
+    }
+
+    /// Submit a transaction signed offline and wait for confirmation, until
+    /// `expiry`: usually [`Expiry::DurableNonce`] of the account passed to
+    /// [`with_durable_nonce`]. It cannot be re-signed here, so an expiry is
+    /// reported rather than retried.
+    pub async fn submit_offline(
+        rpc: &RpcClient,
+        transaction: OfflineTransaction,
+        expiry: Expiry,
+        config: &SendConfig,
+    ) -> Result<Signature> {
+        send_and_confirm(rpc, &transaction.into_transaction()?, expiry, config).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anchor_lang::solana_program::instruction::AccountMeta;
+    use solana_keypair::Keypair;
+    use solana_message::Message;
+    use solana_nonce::state::{Data as NonceData, DurableNonce};
+
+    use super::*;
+
+    /// A message `payer` and `merchant` both have to sign
+    fn offline(payer: &Keypair, merchant: &Keypair) -> OfflineTransaction {
+        let ix = Instruction::new_with_bytes(
+            Pubkey::new_unique(),
+            &[1, 2, 3],
+            vec![AccountMeta::new_readonly(merchant.pubkey(), true)],
+        );
+        let message =
+            Message::new_with_blockhash(&[ix], Some(&payer.pubkey()), &Hash::new_unique());
+        OfflineTransaction::new(VersionedMessage::Legacy(message))
+    }
+
+    #[test]
+    fn signatures_survive_the_file_round_trip() {
+        let (payer, merchant) = (Keypair::new(), Keypair::new());
+        let mut transaction = offline(&payer, &merchant);
+        assert_eq!(
+            transaction.required_signers(),
+            [payer.pubkey(), merchant.pubkey()]
+        );
+
+        transaction.sign(&merchant).unwrap();
+        let mut carried = OfflineTransaction::from_json(&transaction.to_json().unwrap()).unwrap();
+        assert_eq!(carried, transaction);
+        assert_eq!(carried.missing_signers(), [payer.pubkey()]);
+
+        carried.sign(&payer).unwrap();
+        let signed = carried.into_transaction().unwrap();
+        let bytes = signed.message.serialize();
+        for (signature, signer) in signed.signatures.iter().zip([&payer, &merchant]) {
+            assert!(signature.verify(signer.pubkey().as_ref(), &bytes));
+        }
+    }
+
+    #[test]
+    fn unsigned_transaction_is_not_submittable() {
+        let (payer, merchant) = (Keypair::new(), Keypair::new());
+        let mut transaction = offline(&payer, &merchant);
+        transaction.sign(&payer).unwrap();
+
+        assert!(matches!(
+            transaction.into_transaction(),
+            Err(ClientError::MissingSignatures(missing)) if missing == [merchant.pubkey()]
+        ));
+    }
+
+    #[test]
+    fn foreign_signatures_are_rejected() {
+        let (payer, merchant) = (Keypair::new(), Keypair::new());
+        let mut transaction = offline(&payer, &merchant);
+        let stranger = Keypair::new();
+
+        assert!(matches!(
+            transaction.sign(&stranger),
+            Err(ClientError::UnexpectedSigner(signer)) if signer == stranger.pubkey()
+        ));
+
+        // Right signer, wrong message
+        let other = offline(&payer, &merchant);
+        let signature = payer.sign_message(&other.message_bytes());
+        assert!(matches!(
+            transaction.add_signature(&payer.pubkey(), signature),
+            Err(ClientError::InvalidSignature(signer)) if signer == payer.pubkey()
+        ));
+        assert!(!transaction.is_fully_signed());
+    }
+
+    #[test]
+    fn tampered_file_is_rejected() {
+        let (payer, merchant) = (Keypair::new(), Keypair::new());
+        let mut transaction = offline(&payer, &merchant);
+        transaction.sign(&payer).unwrap();
+        let json = transaction.to_json().unwrap();
+
+        let mut file: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let other = offline(&payer, &merchant);
+        file["message"] = STANDARD.encode(other.message_bytes()).into();
+        assert!(matches!(
+            OfflineTransaction::from_json(&file.to_string()),
+            Err(ClientError::InvalidSignature(_))
+        ));
+
+        let mut file: serde_json::Value = serde_json::from_str(&json).unwrap();
+        file["version"] = (OFFLINE_FORMAT_VERSION + 1).into();
+        assert!(matches!(
+            OfflineTransaction::from_json(&file.to_string()),
+            Err(ClientError::Offline(_))
+        ));
+    }
+
+    #[test]
+    fn nonce_account_decodes() {
+        let (address, authority) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let durable_nonce = DurableNonce::from_blockhash(&Hash::new_unique());
+        let data = bincode::serialize(&NonceVersions::new(NonceState::Initialized(
+            NonceData::new(authority, durable_nonce, 5_000),
+        )))
+        .unwrap();
+
+        assert_eq!(
+            decode_nonce_account(&address, &data).unwrap(),
+            (authority, *durable_nonce.as_hash())
+        );
+
+        let uninitialized =
+            bincode::serialize(&NonceVersions::new(NonceState::Uninitialized)).unwrap();
+        assert!(decode_nonce_account(&address, &uninitialized).is_err());
+    }
+}
@@ -0,0 +1,146 @@
+//! Client for the passkey devices recipe program.
+//!
+//! Changes to a wallet's devices are approved by passkey signatures rather
+//! than a transaction signer: each approving device signs the
+//! [`change_message`], and the transaction carries one
+//! [`secp256r1_instruction`] per device ahead of the change itself.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::sysvar;
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use passkey_devices::{accounts, instruction};
+
+pub use passkey_devices::{
+    secp256r1_signatures, Device, DeviceChange, DeviceSet, CHANGE_DOMAIN,
+    ID as PASSKEY_DEVICES_PROGRAM_ID, MAX_DEVICES, MAX_NAME_LEN, SECP256R1_PROGRAM_ID,
+};
+
+pub const DEVICES_SEED: &[u8] = b"devices";
+
+/// Device set PDA of a wallet
+pub fn device_set_address(wallet: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[DEVICES_SEED, wallet.as_ref()],
+        &PASSKEY_DEVICES_PROGRAM_ID,
+    )
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: PASSKEY_DEVICES_PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// `payer` can be a relayer
+pub fn create_device_set(
+    wallet: &Pubkey,
+    payer: &Pubkey,
+    pubkey: [u8; 33],
+    name: &str,
+) -> Instruction {
+    build(
+        accounts::CreateDeviceSet {
+            device_set: device_set_address(wallet).0,
+            wallet: *wallet,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreateDeviceSet {
+            pubkey,
+            name: name.to_string(),
+        },
+    )
+}
+
+fn change_accounts(wallet: &Pubkey) -> accounts::ChangeDevices {
+    accounts::ChangeDevices {
+        device_set: device_set_address(wallet).0,
+        instructions: sysvar::instructions::ID,
+    }
+}
+
+/// Needs the quorum's signatures over the matching [`change_message`]
+pub fn add_device(wallet: &Pubkey, pubkey: [u8; 33], name: &str) -> Instruction {
+    build(
+        change_accounts(wallet),
+        instruction::AddDevice {
+            pubkey,
+            name: name.to_string(),
+        },
+    )
+}
+
+/// Needs the quorum's signatures over the matching [`change_message`]
+pub fn remove_device(wallet: &Pubkey, pubkey: [u8; 33]) -> Instruction {
+    build(
+        change_accounts(wallet),
+        instruction::RemoveDevice { pubkey },
+    )
+}
+
+/// Needs the quorum's signatures over the matching [`change_message`]
+pub fn set_quorum(wallet: &Pubkey, quorum: u8) -> Instruction {
+    build(change_accounts(wallet), instruction::SetQuorum { quorum })
+}
+
+/// The CPI a consuming program makes to accept any one registered device
+pub fn verify_device(wallet: &Pubkey, message: &[u8]) -> Instruction {
+    build(
+        accounts::VerifyDevice {
+            device_set: device_set_address(wallet).0,
+            instructions: sysvar::instructions::ID,
+        },
+        instruction::VerifyDevice {
+            message: message.to_vec(),
+        },
+    )
+}
+
+/// What each approving device signs for `change` at the set's current nonce
+pub fn change_message(device_set: &DeviceSet, change: &DeviceChange) -> Vec<u8> {
+    device_set
+        .change_message(change)
+        .expect("serializing into a Vec does not fail")
+}
+
+/// Secp256r1 precompile instruction verifying one passkey `signature` (64
+/// bytes, r || s) over `message`, with the key, signature and message all in
+/// the instruction's own data
+pub fn secp256r1_instruction(
+    pubkey: &[u8; 33],
+    signature: &[u8; 64],
+    message: &[u8],
+) -> Instruction {
+    const HEADER_LEN: u16 = 2;
+    const OFFSETS_LEN: u16 = 14;
+    const THIS_INSTRUCTION: u16 = u16::MAX;
+
+    let pubkey_offset = HEADER_LEN + OFFSETS_LEN;
+    let signature_offset = pubkey_offset + 33;
+    let message_offset = signature_offset + 64;
+
+    let mut data = vec![1, 0];
+    for field in [
+        signature_offset,
+        THIS_INSTRUCTION,
+        pubkey_offset,
+        THIS_INSTRUCTION,
+        message_offset,
+        message.len() as u16,
+        THIS_INSTRUCTION,
+    ] {
+        data.extend_from_slice(&field.to_le_bytes());
+    }
+    data.extend_from_slice(pubkey);
+    data.extend_from_slice(signature);
+    data.extend_from_slice(message);
+
+    Instruction {
+        program_id: SECP256R1_PROGRAM_ID,
+        accounts: vec![],
+        data,
+    }
+}
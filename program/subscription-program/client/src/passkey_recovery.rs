@@ -0,0 +1,145 @@
+//! Client for the passkey recovery recipe program.
+//!
+//! The wallet registers its passkey and guardians once. After a lost device,
+//! guardians [`propose_recovery`] and [`approve_recovery`] a new passkey, and
+//! anyone can [`execute_recovery`] when the timelock has run.
+//! [`recovery_status`] tells a frontend where a recovery stands.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use passkey_recovery::{accounts, instruction};
+
+pub use passkey_recovery::{
+    PendingRecovery, RecoveryConfig, ID as PASSKEY_RECOVERY_PROGRAM_ID, MAX_GUARDIANS,
+    MIN_DELAY_SECONDS,
+};
+
+pub const RECOVERY_SEED: &[u8] = b"recovery";
+
+/// Recovery config PDA of a wallet
+pub fn recovery_address(wallet: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[RECOVERY_SEED, wallet.as_ref()],
+        &PASSKEY_RECOVERY_PROGRAM_ID,
+    )
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: PASSKEY_RECOVERY_PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// `signer` is the wallet's current passkey; `payer` can be a relayer
+pub fn create_recovery(
+    wallet: &Pubkey,
+    payer: &Pubkey,
+    signer: [u8; 33],
+    guardians: &[Pubkey],
+    threshold: u8,
+    delay_seconds: i64,
+) -> Instruction {
+    build(
+        accounts::CreateRecovery {
+            config: recovery_address(wallet).0,
+            wallet: *wallet,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreateRecovery {
+            signer,
+            guardians: guardians.to_vec(),
+            threshold,
+            delay_seconds,
+        },
+    )
+}
+
+pub fn update_guardians(
+    wallet: &Pubkey,
+    guardians: &[Pubkey],
+    threshold: u8,
+    delay_seconds: i64,
+) -> Instruction {
+    build(
+        accounts::WalletOnly {
+            config: recovery_address(wallet).0,
+            wallet: *wallet,
+        },
+        instruction::UpdateGuardians {
+            guardians: guardians.to_vec(),
+            threshold,
+            delay_seconds,
+        },
+    )
+}
+
+pub fn propose_recovery(wallet: &Pubkey, guardian: &Pubkey, new_signer: [u8; 33]) -> Instruction {
+    build(
+        accounts::GuardianAction {
+            config: recovery_address(wallet).0,
+            guardian: *guardian,
+        },
+        instruction::ProposeRecovery { new_signer },
+    )
+}
+
+pub fn approve_recovery(wallet: &Pubkey, guardian: &Pubkey) -> Instruction {
+    build(
+        accounts::GuardianAction {
+            config: recovery_address(wallet).0,
+            guardian: *guardian,
+        },
+        instruction::ApproveRecovery {},
+    )
+}
+
+pub fn cancel_recovery(wallet: &Pubkey) -> Instruction {
+    build(
+        accounts::WalletOnly {
+            config: recovery_address(wallet).0,
+            wallet: *wallet,
+        },
+        instruction::CancelRecovery {},
+    )
+}
+
+/// Needs no signer beyond the fee payer
+pub fn execute_recovery(wallet: &Pubkey) -> Instruction {
+    build(
+        accounts::ExecuteRecovery {
+            config: recovery_address(wallet).0,
+        },
+        instruction::ExecuteRecovery {},
+    )
+}
+
+/// Where a wallet's recovery stands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecoveryStatus {
+    /// No recovery in progress
+    Idle,
+    /// Waiting for more guardians to approve
+    Collecting { approvals: u8, threshold: u8 },
+    /// Threshold met; the owner can still cancel until `executable_at`
+    Timelocked { executable_at: i64 },
+    /// [`execute_recovery`] will rotate the signer
+    Executable,
+}
+
+pub fn recovery_status(config: &RecoveryConfig, now: i64) -> RecoveryStatus {
+    match &config.pending {
+        None => RecoveryStatus::Idle,
+        Some(pending) if pending.executable_at == 0 => RecoveryStatus::Collecting {
+            approvals: config.approval_count(),
+            threshold: config.threshold,
+        },
+        Some(pending) if now < pending.executable_at => RecoveryStatus::Timelocked {
+            executable_at: pending.executable_at,
+        },
+        Some(_) => RecoveryStatus::Executable,
+    }
+}
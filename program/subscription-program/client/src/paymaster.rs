@@ -0,0 +1,288 @@
+//! Client for the paymaster recipe program.
+//!
+//! An allowlisted relayer fronts the fee and rent of a transaction and
+//! appends [`sponsor`], which pays it back from the vault and takes the same
+//! value from the user in the fee mint. [`sponsored_initialize_subscription`] builds the whole
+//! instruction list for opening a subscription this way, and
+//! [`subscription_init_quote`] what it will cost the user.
+
+use anchor_lang::prelude::{Pubkey, Rent};
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, AnchorDeserialize, InstructionData, Space, ToAccountMetas};
+use paymaster::{accounts, instruction};
+
+pub use paymaster::{
+    ConfigChange, Paymaster, Quote, RelayerAllowlist, CONFIG_TIMELOCK_SECONDS,
+    ID as PAYMASTER_PROGRAM_ID, LAMPORTS_PER_SOL, MAX_MARKUP_BPS, MAX_PRICE_RISE_BPS, MAX_RELAYERS,
+    PRICE_RISE_INTERVAL_SECONDS,
+};
+
+use crate::instructions::initialize_subscription;
+use crate::pda::associated_token_address;
+use crate::Subscription;
+
+pub const PAYMASTER_SEED: &[u8] = b"paymaster";
+pub const RELAYERS_SEED: &[u8] = b"relayers";
+pub const CONFIG_CHANGE_SEED: &[u8] = b"config_change";
+
+/// Base fee per transaction signature
+pub const LAMPORTS_PER_SIGNATURE: u64 = 5_000;
+
+/// Paymaster PDA of the sponsor that created it; it is also the SOL vault
+pub fn paymaster_address(creator: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[PAYMASTER_SEED, creator.as_ref()], &PAYMASTER_PROGRAM_ID)
+}
+
+/// Relayer allowlist PDA of a paymaster
+pub fn allowlist_address(paymaster: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[RELAYERS_SEED, paymaster.as_ref()], &PAYMASTER_PROGRAM_ID)
+}
+
+/// Pending config change PDA of a paymaster
+pub fn config_change_address(paymaster: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[CONFIG_CHANGE_SEED, paymaster.as_ref()],
+        &PAYMASTER_PROGRAM_ID,
+    )
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: PAYMASTER_PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+pub fn create_paymaster(
+    sponsor: &Pubkey,
+    fee_token_account: &Pubkey,
+    price_per_sol: u64,
+    markup_bps: u16,
+) -> Instruction {
+    build(
+        accounts::CreatePaymaster {
+            paymaster: paymaster_address(sponsor).0,
+            sponsor: *sponsor,
+            fee_token_account: *fee_token_account,
+            system_program: system_program::ID,
+        },
+        instruction::CreatePaymaster {
+            price_per_sol,
+            markup_bps,
+        },
+    )
+}
+
+/// Create the relayer allowlist of the paymaster `sponsor` created, before
+/// any handover
+pub fn create_allowlist(sponsor: &Pubkey, relayers: &[Pubkey]) -> Instruction {
+    let paymaster = paymaster_address(sponsor).0;
+    build(
+        accounts::CreateAllowlist {
+            allowlist: allowlist_address(&paymaster).0,
+            paymaster,
+            sponsor: *sponsor,
+            system_program: system_program::ID,
+        },
+        instruction::CreateAllowlist {
+            relayers: relayers.to_vec(),
+        },
+    )
+}
+
+pub fn set_relayers(paymaster: &Paymaster, relayers: &[Pubkey]) -> Instruction {
+    let address = paymaster_address(&paymaster.creator).0;
+    build(
+        accounts::SetRelayers {
+            allowlist: allowlist_address(&address).0,
+            paymaster: address,
+            sponsor: paymaster.sponsor,
+        },
+        instruction::SetRelayers {
+            relayers: relayers.to_vec(),
+        },
+    )
+}
+
+/// Reprice SOL; a rise is bounded by [`MAX_PRICE_RISE_BPS`] per
+/// [`PRICE_RISE_INTERVAL_SECONDS`]
+pub fn update_price(paymaster: &Paymaster, price_per_sol: u64) -> Instruction {
+    build(
+        accounts::UpdatePaymaster {
+            paymaster: paymaster_address(&paymaster.creator).0,
+            sponsor: paymaster.sponsor,
+        },
+        instruction::UpdatePrice { price_per_sol },
+    )
+}
+
+pub fn withdraw(paymaster: &Paymaster, lamports: u64) -> Instruction {
+    build(
+        accounts::UpdatePaymaster {
+            paymaster: paymaster_address(&paymaster.creator).0,
+            sponsor: paymaster.sponsor,
+        },
+        instruction::Withdraw { lamports },
+    )
+}
+
+/// Queue a new markup, fee account or sponsor, signed by the sponsor; it
+/// can be applied after [`CONFIG_TIMELOCK_SECONDS`]
+pub fn queue_config_change(
+    paymaster: &Paymaster,
+    markup_bps: Option<u16>,
+    fee_token_account: Option<&Pubkey>,
+    new_sponsor: Option<Pubkey>,
+) -> Instruction {
+    let address = paymaster_address(&paymaster.creator).0;
+    build(
+        accounts::QueueConfigChange {
+            config_change: config_change_address(&address).0,
+            paymaster: address,
+            sponsor: paymaster.sponsor,
+            fee_token_account: fee_token_account.copied(),
+            system_program: system_program::ID,
+        },
+        instruction::QueueConfigChange {
+            markup_bps,
+            new_sponsor,
+        },
+    )
+}
+
+/// Apply the paymaster's queued config change; anyone can send it
+pub fn apply_config_change(paymaster: &Paymaster) -> Instruction {
+    let address = paymaster_address(&paymaster.creator).0;
+    build(
+        accounts::ApplyConfigChange {
+            config_change: config_change_address(&address).0,
+            paymaster: address,
+            sponsor: paymaster.sponsor,
+        },
+        instruction::ApplyConfigChange {},
+    )
+}
+
+/// Drop the paymaster's queued config change, signed by the sponsor
+pub fn cancel_config_change(paymaster: &Paymaster) -> Instruction {
+    let address = paymaster_address(&paymaster.creator).0;
+    build(
+        accounts::CancelConfigChange {
+            config_change: config_change_address(&address).0,
+            paymaster: address,
+            sponsor: paymaster.sponsor,
+        },
+        instruction::CancelConfigChange {},
+    )
+}
+
+/// Pay `relayer` back `lamports` and charge `user` their price, at most
+/// `max_fee`, from the user's ATA for the fee mint; `relayer` must be on the
+/// allowlist
+pub fn sponsor(
+    paymaster: &Paymaster,
+    user: &Pubkey,
+    relayer: &Pubkey,
+    lamports: u64,
+    max_fee: u64,
+) -> Instruction {
+    let address = paymaster_address(&paymaster.creator).0;
+    build(
+        accounts::Sponsor {
+            paymaster: address,
+            allowlist: allowlist_address(&address).0,
+            user: *user,
+            user_token_account: associated_token_address(user, &paymaster.fee_mint),
+            fee_token_account: paymaster.fee_token_account,
+            relayer: *relayer,
+            token_program: spl_token::ID,
+        },
+        instruction::Sponsor { lamports, max_fee },
+    )
+}
+
+/// Read-only quote of a sponsored operation; simulate it and pass the return
+/// data to [`decode_quote`]
+pub fn quote(
+    creator: &Pubkey,
+    charge_amount: u64,
+    rent_lamports: u64,
+    fee_lamports: u64,
+) -> Instruction {
+    build(
+        accounts::QuotePaymaster {
+            paymaster: paymaster_address(creator).0,
+        },
+        instruction::Quote {
+            charge_amount,
+            rent_lamports,
+            fee_lamports,
+        },
+    )
+}
+
+/// The [`Quote`] in a simulation's return data, if it came from the paymaster
+pub fn decode_quote(program_id: &Pubkey, data: &[u8]) -> Option<Quote> {
+    if *program_id != PAYMASTER_PROGRAM_ID {
+        return None;
+    }
+    Quote::try_from_slice(data).ok()
+}
+
+/// Lamports a relayer fronts for `initialize_subscription`: the subscription's
+/// rent plus the fee for `signatures` signatures
+pub fn subscription_init_lamports(rent: &Rent, signatures: u64) -> u64 {
+    rent.minimum_balance(8 + Subscription::INIT_SPACE) + LAMPORTS_PER_SIGNATURE * signatures
+}
+
+/// `initialize_subscription` with `relayer` as fee and rent payer, followed by
+/// the `sponsor` that pays the relayer back and charges `authority` for it.
+/// `relayer` must be on the paymaster's allowlist.
+/// The user pays the first period and the sponsorship fee in one transaction,
+/// signed by `authority` and `relayer`. The fee is capped at its price in the
+/// fetched `paymaster`, so the transaction fails rather than pay more if the
+/// price rises before it lands.
+#[allow(clippy::too_many_arguments)]
+pub fn sponsored_initialize_subscription(
+    paymaster: &Paymaster,
+    authority: &Pubkey,
+    recipient: &Pubkey,
+    token_mint: &Pubkey,
+    relayer: &Pubkey,
+    rent: &Rent,
+    amount_per_period: u64,
+    interval_seconds: i64,
+    expires_at: Option<i64>,
+) -> Vec<Instruction> {
+    let lamports = subscription_init_lamports(rent, 2);
+    let max_fee = paymaster.fee_for(lamports).unwrap_or(u64::MAX);
+    vec![
+        initialize_subscription(
+            authority,
+            recipient,
+            token_mint,
+            relayer,
+            amount_per_period,
+            interval_seconds,
+            expires_at,
+        ),
+        sponsor(paymaster, authority, relayer, lamports, max_fee),
+    ]
+}
+
+/// What [`sponsored_initialize_subscription`] costs the user: the first
+/// period plus the sponsorship, computed locally from a fetched paymaster
+pub fn subscription_init_quote(
+    paymaster: &Paymaster,
+    rent: &Rent,
+    amount_per_period: u64,
+) -> Option<Quote> {
+    paymaster
+        .quote(
+            amount_per_period,
+            rent.minimum_balance(8 + Subscription::INIT_SPACE),
+            LAMPORTS_PER_SIGNATURE * 2,
+        )
+        .ok()
+}
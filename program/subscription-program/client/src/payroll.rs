@@ -0,0 +1,198 @@
+//! Client for the payroll recipe program.
+//!
+//! Payroll runs on the same keeper loop as subscriptions: `pay_employee` is
+//! the counterpart of `charge_subscription`, and [`check_payment`] reports
+//! blockers with the same [`ChargeBlocker`] so one keeper can crank both.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use payroll::{accounts, instruction};
+use spl_token::state::{Account as TokenAccount, AccountState};
+
+pub use payroll::{Employee, Payroll, ID as PAYROLL_PROGRAM_ID};
+
+use crate::pda::associated_token_address;
+use crate::preflight::ChargeBlocker;
+
+pub const PAYROLL_SEED: &[u8] = b"payroll";
+pub const EMPLOYEE_SEED: &[u8] = b"employee";
+
+/// Payroll PDA of an employer
+pub fn payroll_address(employer: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[PAYROLL_SEED, employer.as_ref()], &PAYROLL_PROGRAM_ID)
+}
+
+/// Employee record PDA within a payroll
+pub fn employee_address(payroll: &Pubkey, employee: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[EMPLOYEE_SEED, payroll.as_ref(), employee.as_ref()],
+        &PAYROLL_PROGRAM_ID,
+    )
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: PAYROLL_PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// The vault is the payroll PDA's ATA and must exist, so prepend a
+/// `CreateIdempotent` for it
+pub fn create_payroll(employer: &Pubkey, mint: &Pubkey, payer: &Pubkey) -> Instruction {
+    let payroll = payroll_address(employer).0;
+    build(
+        accounts::CreatePayroll {
+            payroll,
+            employer: *employer,
+            mint: *mint,
+            vault: associated_token_address(&payroll, mint),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreatePayroll {},
+    )
+}
+
+/// Fund from the employer's ATA
+pub fn fund_payroll(employer: &Pubkey, payroll: &Payroll, amount: u64) -> Instruction {
+    build(
+        accounts::FundPayroll {
+            payroll: payroll_address(employer).0,
+            employer: *employer,
+            employer_token_account: associated_token_address(employer, &payroll.mint),
+            vault: payroll.vault,
+            token_program: spl_token::ID,
+        },
+        instruction::FundPayroll { amount },
+    )
+}
+
+pub fn withdraw_funds(payroll: &Payroll, destination: &Pubkey, amount: u64) -> Instruction {
+    build(
+        accounts::WithdrawFunds {
+            payroll: payroll_address(&payroll.employer).0,
+            employer: payroll.employer,
+            vault: payroll.vault,
+            destination: *destination,
+            token_program: spl_token::ID,
+        },
+        instruction::WithdrawFunds { amount },
+    )
+}
+
+/// Pay `employee` into their ATA for the payroll's mint
+pub fn add_employee(
+    payroll: &Payroll,
+    employee: &Pubkey,
+    payer: &Pubkey,
+    salary_per_period: u64,
+    interval_seconds: i64,
+) -> Instruction {
+    let payroll_address = payroll_address(&payroll.employer).0;
+    build(
+        accounts::AddEmployee {
+            payroll: payroll_address,
+            employee_record: employee_address(&payroll_address, employee).0,
+            employer: payroll.employer,
+            employee: *employee,
+            employee_token_account: associated_token_address(employee, &payroll.mint),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::AddEmployee {
+            salary_per_period,
+            interval_seconds,
+        },
+    )
+}
+
+pub fn update_employee(
+    employer: &Pubkey,
+    employee: &Pubkey,
+    salary_per_period: u64,
+    interval_seconds: i64,
+) -> Instruction {
+    let payroll = payroll_address(employer).0;
+    build(
+        accounts::UpdateEmployee {
+            payroll,
+            employee_record: employee_address(&payroll, employee).0,
+            employer: *employer,
+        },
+        instruction::UpdateEmployee {
+            salary_per_period,
+            interval_seconds,
+        },
+    )
+}
+
+pub fn remove_employee(employer: &Pubkey, employee: &Pubkey) -> Instruction {
+    let payroll = payroll_address(employer).0;
+    build(
+        accounts::RemoveEmployee {
+            payroll,
+            employee_record: employee_address(&payroll, employee).0,
+            employer: *employer,
+        },
+        instruction::RemoveEmployee {},
+    )
+}
+
+/// Permissionless payment; only the fee payer signs
+pub fn pay_employee(payroll: &Payroll, record_address: &Pubkey, record: &Employee) -> Instruction {
+    build(
+        accounts::PayEmployee {
+            payroll: record.payroll,
+            employee_record: *record_address,
+            vault: payroll.vault,
+            employee_token_account: record.employee_token_account,
+            token_program: spl_token::ID,
+        },
+        instruction::PayEmployee {},
+    )
+}
+
+/// Check a payment against already-fetched state, mirroring `pay_employee`
+/// and the transfer it makes. `now` is the cluster unix timestamp.
+pub fn check_payment(
+    payroll: &Payroll,
+    record: &Employee,
+    vault: Option<&TokenAccount>,
+    employee_token_account: Option<&TokenAccount>,
+    now: i64,
+) -> Result<(), ChargeBlocker> {
+    if record.check_payable(now).is_err() {
+        return Err(ChargeBlocker::IntervalNotMet {
+            next_charge_at: record.next_payment_at(),
+        });
+    }
+
+    let vault_account = vault.ok_or(ChargeBlocker::TokenAccountMissing(payroll.vault))?;
+    let employee_account = employee_token_account.ok_or(ChargeBlocker::TokenAccountMissing(
+        record.employee_token_account,
+    ))?;
+
+    for (address, account) in [
+        (payroll.vault, vault_account),
+        (record.employee_token_account, employee_account),
+    ] {
+        if account.state == AccountState::Frozen {
+            return Err(ChargeBlocker::TokenAccountFrozen(address));
+        }
+        if account.mint != payroll.mint {
+            return Err(ChargeBlocker::MintMismatch(address));
+        }
+    }
+
+    if vault_account.amount < record.salary_per_period {
+        return Err(ChargeBlocker::InsufficientBalance {
+            balance: vault_account.amount,
+            required: record.salary_per_period,
+        });
+    }
+
+    Ok(())
+}
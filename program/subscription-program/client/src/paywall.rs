@@ -0,0 +1,153 @@
+//! Client for the paywall recipe program.
+//!
+//! Content servers can decide access without a transaction: fetch the receipt
+//! and the reader's subscription, then call [`check_access`]. [`verify_access`]
+//! builds the same check as an instruction for simulation or composition.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use paywall::{accounts, instruction};
+
+pub use paywall::{check_access, Access, AccessReceipt, Article, ID as PAYWALL_PROGRAM_ID};
+
+use crate::pda::{associated_token_address, subscription_address};
+
+pub const ARTICLE_SEED: &[u8] = b"article";
+pub const RECEIPT_SEED: &[u8] = b"receipt";
+
+/// Article PDA of a creator's content, keyed by its hash
+pub fn article_address(creator: &Pubkey, content_hash: &[u8; 32]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[ARTICLE_SEED, creator.as_ref(), content_hash.as_ref()],
+        &PAYWALL_PROGRAM_ID,
+    )
+}
+
+/// Access receipt PDA of a reader for one article
+pub fn receipt_address(article: &Pubkey, reader: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[RECEIPT_SEED, article.as_ref(), reader.as_ref()],
+        &PAYWALL_PROGRAM_ID,
+    )
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: PAYWALL_PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// Unlocks pay into the creator's ATA for `mint`
+pub fn publish_article(
+    creator: &Pubkey,
+    mint: &Pubkey,
+    payer: &Pubkey,
+    content_hash: [u8; 32],
+    price: u64,
+    access_seconds: i64,
+    subscribers_unlock: bool,
+) -> Instruction {
+    build(
+        accounts::PublishArticle {
+            article: article_address(creator, &content_hash).0,
+            creator: *creator,
+            mint: *mint,
+            creator_token_account: associated_token_address(creator, mint),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::PublishArticle {
+            content_hash,
+            price,
+            access_seconds,
+            subscribers_unlock,
+        },
+    )
+}
+
+pub fn update_article(
+    article: &Article,
+    price: u64,
+    access_seconds: i64,
+    subscribers_unlock: bool,
+) -> Instruction {
+    build(
+        accounts::UpdateArticle {
+            article: article_address(&article.creator, &article.content_hash).0,
+            creator: article.creator,
+        },
+        instruction::UpdateArticle {
+            price,
+            access_seconds,
+            subscribers_unlock,
+        },
+    )
+}
+
+/// First purchase, paid from the reader's ATA
+pub fn unlock_article(article: &Article, reader: &Pubkey, payer: &Pubkey) -> Instruction {
+    let article_address = article_address(&article.creator, &article.content_hash).0;
+    build(
+        accounts::UnlockArticle {
+            article: article_address,
+            receipt: receipt_address(&article_address, reader).0,
+            reader: *reader,
+            reader_token_account: associated_token_address(reader, &article.mint),
+            creator_token_account: article.creator_token_account,
+            payer: *payer,
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+        },
+        instruction::UnlockArticle {},
+    )
+}
+
+/// Another access period for a reader who already has a receipt
+pub fn renew_access(article: &Article, reader: &Pubkey) -> Instruction {
+    let article_address = article_address(&article.creator, &article.content_hash).0;
+    build(
+        accounts::RenewAccess {
+            article: article_address,
+            receipt: receipt_address(&article_address, reader).0,
+            reader: *reader,
+            reader_token_account: associated_token_address(reader, &article.mint),
+            creator_token_account: article.creator_token_account,
+            token_program: spl_token::ID,
+        },
+        instruction::RenewAccess {},
+    )
+}
+
+/// Access check for simulation. Optional accounts that do not exist must be
+/// left out, so pass `has_receipt` / `has_subscription` from a prior fetch.
+pub fn verify_access(
+    article: &Article,
+    reader: &Pubkey,
+    has_receipt: bool,
+    has_subscription: bool,
+) -> Instruction {
+    let article_address = article_address(&article.creator, &article.content_hash).0;
+    build(
+        accounts::VerifyAccess {
+            article: article_address,
+            reader: *reader,
+            receipt: has_receipt.then(|| receipt_address(&article_address, reader).0),
+            subscription: has_subscription
+                .then(|| subscription_address(reader, &article.creator).0),
+        },
+        instruction::VerifyAccess {},
+    )
+}
+
+pub fn close_receipt(receipt_address: &Pubkey, receipt: &AccessReceipt) -> Instruction {
+    build(
+        accounts::CloseReceipt {
+            receipt: *receipt_address,
+            reader: receipt.reader,
+        },
+        instruction::CloseReceipt {},
+    )
+}
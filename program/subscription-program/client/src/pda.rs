@@ -0,0 +1,421 @@
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::pubkey;
+use anchor_lang::solana_program::bpf_loader_upgradeable;
+
+pub use subscription_program::squads_vault_address;
+use subscription_program::{CLOCKWORK_THREAD_PROGRAM_ID, TUKTUK_PROGRAM_ID};
+
+use crate::PROGRAM_ID;
+
+pub const SUBSCRIPTION_SEED: &[u8] = b"subscription";
+
+pub const ASSOCIATED_TOKEN_PROGRAM_ID: Pubkey =
+    pubkey!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+
+/// Subscription PDA for a (user, merchant) pair
+pub fn subscription_address(authority: &Pubkey, recipient: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[SUBSCRIPTION_SEED, authority.as_ref(), recipient.as_ref()],
+        &PROGRAM_ID,
+    )
+}
+
+pub const MERCHANT_CONFIG_SEED: &[u8] = b"merchant_config";
+
+/// Merchant settings PDA of a recipient
+pub fn merchant_config_address(recipient: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[MERCHANT_CONFIG_SEED, recipient.as_ref()], &PROGRAM_ID)
+}
+
+pub const MERCHANT_MULTISIG_SEED: &[u8] = b"merchant_multisig";
+
+/// PDA recording the Squads multisig that controls a recipient's settings
+pub fn merchant_multisig_address(recipient: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[MERCHANT_MULTISIG_SEED, recipient.as_ref()], &PROGRAM_ID)
+}
+
+pub const PAYOUT_CHANGE_SEED: &[u8] = b"payout_change";
+
+/// PDA holding a recipient's pending payout account
+pub fn payout_change_address(recipient: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[PAYOUT_CHANGE_SEED, recipient.as_ref()], &PROGRAM_ID)
+}
+
+pub const MERCHANT_VAULT_SEED: &[u8] = b"merchant_vault";
+
+/// Revenue vault PDA of a recipient, owner of the vault's token account
+pub fn merchant_vault_address(recipient: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[MERCHANT_VAULT_SEED, recipient.as_ref()], &PROGRAM_ID)
+}
+
+pub const SLA_SEED: &[u8] = b"sla";
+
+/// SLA commitment PDA of a recipient
+pub fn sla_address(recipient: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[SLA_SEED, recipient.as_ref()], &PROGRAM_ID)
+}
+
+pub const DOWNTIME_SEED: &[u8] = b"downtime";
+
+/// Attestation PDA of the recipient's outage starting at `started_at`
+pub fn downtime_address(recipient: &Pubkey, started_at: i64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[DOWNTIME_SEED, recipient.as_ref(), &started_at.to_le_bytes()],
+        &PROGRAM_ID,
+    )
+}
+
+pub const SLA_CREDIT_SEED: &[u8] = b"sla_credit";
+
+/// Receipt PDA of a subscription's credit for one outage
+pub fn sla_credit_address(attestation: &Pubkey, subscription: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[SLA_CREDIT_SEED, attestation.as_ref(), subscription.as_ref()],
+        &PROGRAM_ID,
+    )
+}
+
+pub const PRICE_CACHE_SEED: &[u8] = b"price_cache";
+
+/// Price cache PDA of Pyth feed `feed_id`
+pub fn price_cache_address(feed_id: &[u8; 32]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[PRICE_CACHE_SEED, feed_id.as_ref()], &PROGRAM_ID)
+}
+
+pub const USD_PEG_SEED: &[u8] = b"usd_peg";
+
+/// USD price PDA of a subscription
+pub fn usd_peg_address(subscription: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[USD_PEG_SEED, subscription.as_ref()], &PROGRAM_ID)
+}
+
+pub const DEPOSIT_SEED: &[u8] = b"deposit";
+
+/// Deposit PDA of a subscription, which owns the deposit's escrow
+pub fn deposit_address(subscription: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[DEPOSIT_SEED, subscription.as_ref()], &PROGRAM_ID)
+}
+
+pub const PLAN_SEED: &[u8] = b"plan";
+
+/// Plan `plan_id` of a recipient
+pub fn plan_address(recipient: &Pubkey, plan_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[PLAN_SEED, recipient.as_ref(), &plan_id.to_le_bytes()],
+        &PROGRAM_ID,
+    )
+}
+
+pub const PLAN_SUBSCRIPTION_SEED: &[u8] = b"plan_subscription";
+
+/// Which plan a subscription is billed at, and how often
+pub fn plan_subscription_address(subscription: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[PLAN_SUBSCRIPTION_SEED, subscription.as_ref()],
+        &PROGRAM_ID,
+    )
+}
+
+pub const WAITLIST_SEED: &[u8] = b"waitlist";
+
+/// Queue of users waiting for a seat on a plan
+pub fn waitlist_address(plan: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[WAITLIST_SEED, plan.as_ref()], &PROGRAM_ID)
+}
+
+pub const WAITLIST_ENTRY_SEED: &[u8] = b"waitlist_entry";
+
+/// Entry at `position` of a plan's waitlist
+pub fn waitlist_entry_address(plan: &Pubkey, position: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[WAITLIST_ENTRY_SEED, plan.as_ref(), &position.to_le_bytes()],
+        &PROGRAM_ID,
+    )
+}
+
+pub const SETUP_FEE_SEED: &[u8] = b"setup_fee";
+
+/// Setup fee record of a subscription opened with one
+pub fn setup_fee_address(subscription: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[SETUP_FEE_SEED, subscription.as_ref()], &PROGRAM_ID)
+}
+
+pub const SPEND_ALERTS_SEED: &[u8] = b"spend_alerts";
+
+/// Spend milestones PDA of a subscription
+pub fn spend_alerts_address(subscription: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[SPEND_ALERTS_SEED, subscription.as_ref()], &PROGRAM_ID)
+}
+
+pub const DENYLIST_SEED: &[u8] = b"denylist";
+
+/// PDA that exists while `recipient` bans `wallet` from subscribing
+pub fn denylist_address(recipient: &Pubkey, wallet: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[DENYLIST_SEED, recipient.as_ref(), wallet.as_ref()],
+        &PROGRAM_ID,
+    )
+}
+
+pub const ALLOWLIST_SEED: &[u8] = b"allowlist";
+
+/// PDA that exists while `recipient` is invite-only
+pub fn subscriber_allowlist_address(recipient: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[ALLOWLIST_SEED, recipient.as_ref()], &PROGRAM_ID)
+}
+
+/// PDA that exists while `recipient` has invited `wallet` to subscribe
+pub fn allowlist_entry_address(recipient: &Pubkey, wallet: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[ALLOWLIST_SEED, recipient.as_ref(), wallet.as_ref()],
+        &PROGRAM_ID,
+    )
+}
+
+pub const ACCEPTED_MINT_SEED: &[u8] = b"accepted_mint";
+
+/// PDA of a fallback mint the recipient accepts
+pub fn accepted_mint_address(recipient: &Pubkey, token_mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[ACCEPTED_MINT_SEED, recipient.as_ref(), token_mint.as_ref()],
+        &PROGRAM_ID,
+    )
+}
+
+pub const FALLBACK_PAYMENT_SEED: &[u8] = b"fallback_payment";
+
+/// Fallback mint PDA of a subscription
+pub fn fallback_payment_address(subscription: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[FALLBACK_PAYMENT_SEED, subscription.as_ref()], &PROGRAM_ID)
+}
+
+pub const FUNDING_SOURCES_SEED: &[u8] = b"funding";
+
+/// Fallback funding PDA of a subscription
+pub fn funding_sources_address(subscription: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[FUNDING_SOURCES_SEED, subscription.as_ref()], &PROGRAM_ID)
+}
+
+pub const CHARGE_FUNCTION_SEED: &[u8] = b"charge_function";
+
+/// Registered Switchboard Function PDA of a recipient
+pub fn charge_function_address(recipient: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[CHARGE_FUNCTION_SEED, recipient.as_ref()], &PROGRAM_ID)
+}
+
+pub const KEEPER_LEASE_SEED: &[u8] = b"keeper_lease";
+
+/// Leader-election lease PDA of a keeper group
+pub fn keeper_lease_address(group: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[KEEPER_LEASE_SEED, group.as_ref()], &PROGRAM_ID)
+}
+
+pub const PLATFORM_CONFIG_SEED: &[u8] = b"platform_config";
+
+/// The deployment's platform config PDA
+pub fn platform_config_address() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[PLATFORM_CONFIG_SEED], &PROGRAM_ID)
+}
+
+pub const PLATFORM_CONFIG_CHANGE_SEED: &[u8] = b"platform_config_change";
+
+/// PDA of the pending platform config change
+pub fn platform_config_change_address() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[PLATFORM_CONFIG_CHANGE_SEED], &PROGRAM_ID)
+}
+
+/// Upgradeable loader account holding the program's upgrade authority
+pub fn program_data_address() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[PROGRAM_ID.as_ref()], &bpf_loader_upgradeable::ID)
+}
+
+pub const THREAD_SEED: &[u8] = b"thread";
+
+/// Thread id the client's builders use when a subscription has one thread
+pub const CHARGE_THREAD_ID: &[u8] = b"charge";
+
+/// Clockwork thread that charges `subscription`, created under `id`
+pub fn charge_thread_address(subscription: &Pubkey, id: &[u8]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[THREAD_SEED, subscription.as_ref(), id],
+        &CLOCKWORK_THREAD_PROGRAM_ID,
+    )
+}
+
+pub const QUEUE_AUTHORITY_SEED: &[u8] = b"queue_authority";
+
+/// PDA that queues a recipient's tuktuk charge tasks; a task queue's owner
+/// adds it as a queue authority
+pub fn queue_authority_address(recipient: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[QUEUE_AUTHORITY_SEED, recipient.as_ref()], &PROGRAM_ID)
+}
+
+/// tuktuk's record that `queue_authority` may queue tasks on `task_queue`
+pub fn task_queue_authority_address(task_queue: &Pubkey, queue_authority: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            b"task_queue_authority",
+            task_queue.as_ref(),
+            queue_authority.as_ref(),
+        ],
+        &TUKTUK_PROGRAM_ID,
+    )
+}
+
+pub const TASK_SEED: &[u8] = b"task";
+
+/// tuktuk task `id` of `task_queue`
+pub fn charge_task_address(task_queue: &Pubkey, id: u16) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[TASK_SEED, task_queue.as_ref(), &id.to_le_bytes()],
+        &TUKTUK_PROGRAM_ID,
+    )
+}
+
+/// Associated token account of `owner` for `mint` (classic SPL Token program)
+pub fn associated_token_address(owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[owner.as_ref(), spl_token::ID.as_ref(), mint.as_ref()],
+        &ASSOCIATED_TOKEN_PROGRAM_ID,
+    )
+    .0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use serde_json::Value;
+
+    use super::*;
+
+    const IDL: &str = include_str!(
+        "../../programs/subscription-program/tests/snapshots/subscription_program.json"
+    );
+
+    /// Every seed prefix the client derives the program's PDAs with
+    const SEEDS: &[&[u8]] = &[
+        SUBSCRIPTION_SEED,
+        MERCHANT_CONFIG_SEED,
+        MERCHANT_MULTISIG_SEED,
+        PAYOUT_CHANGE_SEED,
+        MERCHANT_VAULT_SEED,
+        SLA_SEED,
+        DOWNTIME_SEED,
+        SLA_CREDIT_SEED,
+        PRICE_CACHE_SEED,
+        USD_PEG_SEED,
+        DEPOSIT_SEED,
+        PLAN_SEED,
+        PLAN_SUBSCRIPTION_SEED,
+        WAITLIST_SEED,
+        WAITLIST_ENTRY_SEED,
+        SETUP_FEE_SEED,
+        SPEND_ALERTS_SEED,
+        DENYLIST_SEED,
+        ALLOWLIST_SEED,
+        ACCEPTED_MINT_SEED,
+        FALLBACK_PAYMENT_SEED,
+        FUNDING_SOURCES_SEED,
+        CHARGE_FUNCTION_SEED,
+        KEEPER_LEASE_SEED,
+        PLATFORM_CONFIG_SEED,
+        PLATFORM_CONFIG_CHANGE_SEED,
+        QUEUE_AUTHORITY_SEED,
+    ];
+
+    #[test]
+    fn seeds_match_the_idl() {
+        let idl: Value = serde_json::from_str(IDL).unwrap();
+        let idl_seeds: BTreeSet<Vec<u8>> = idl["instructions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .flat_map(|ix| ix["accounts"].as_array().unwrap())
+            .filter_map(|account| account["pda"]["seeds"].get(0))
+            .filter(|seed| seed["kind"] == "const")
+            .map(|seed| serde_json::from_value(seed["value"].clone()).unwrap())
+            .collect();
+        let client_seeds: BTreeSet<Vec<u8>> = SEEDS.iter().map(|seed| seed.to_vec()).collect();
+
+        assert_eq!(client_seeds, idl_seeds);
+    }
+
+    #[test]
+    fn bumps_recreate_the_address() {
+        let (authority, recipient) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (address, bump) = subscription_address(&authority, &recipient);
+
+        assert_eq!(
+            Pubkey::create_program_address(
+                &[
+                    SUBSCRIPTION_SEED,
+                    authority.as_ref(),
+                    recipient.as_ref(),
+                    &[bump]
+                ],
+                &PROGRAM_ID
+            ),
+            Ok(address)
+        );
+        assert!(!address.is_on_curve());
+        // Order matters: the pair is (user, merchant)
+        assert_ne!(address, subscription_address(&recipient, &authority).0);
+    }
+
+    #[test]
+    fn numeric_seeds_are_little_endian() {
+        let recipient = Pubkey::new_unique();
+        let (address, bump) = plan_address(&recipient, 0x0102);
+
+        assert_eq!(
+            Pubkey::create_program_address(
+                &[
+                    PLAN_SEED,
+                    recipient.as_ref(),
+                    &[2, 1, 0, 0, 0, 0, 0, 0],
+                    &[bump]
+                ],
+                &PROGRAM_ID
+            ),
+            Ok(address)
+        );
+        assert_ne!(address, plan_address(&recipient, 0x0201).0);
+    }
+
+    #[test]
+    fn external_program_addresses_match_the_program() {
+        let (recipient, task_queue) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let subscription = Pubkey::new_unique();
+
+        assert_eq!(
+            queue_authority_address(&recipient),
+            subscription_program::queue_authority_address(&recipient)
+        );
+        let queue_authority = queue_authority_address(&recipient).0;
+        assert_eq!(
+            task_queue_authority_address(&task_queue, &queue_authority),
+            subscription_program::task_queue_authority_address(&task_queue, &queue_authority)
+        );
+        assert_eq!(
+            charge_task_address(&task_queue, 7),
+            subscription_program::charge_task_address(&task_queue, 7)
+        );
+        assert_eq!(
+            charge_thread_address(&subscription, CHARGE_THREAD_ID),
+            subscription_program::charge_thread_address(&subscription, CHARGE_THREAD_ID)
+        );
+    }
+
+    #[test]
+    fn associated_token_address_matches_spl() {
+        let (owner, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        assert_eq!(
+            associated_token_address(&owner, &mint),
+            spl_associated_token_account_client::address::get_associated_token_address(
+                &owner, &mint
+            )
+        );
+    }
+}
@@ -0,0 +1,483 @@
+//! "Will this charge succeed?" checks for keepers.
+//!
+//! Mirrors the validation order of `charge_subscription` (and
+//! `charge_subscription_usd`) and the SPL Token transfer it performs, so a
+//! keeper can skip (or flag) a subscription without paying for a failed
+//! transaction. A charge that deactivates the subscription instead of
+//! moving tokens still succeeds, and is reported as such.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::program_option::COption;
+use spl_token::state::{Account as TokenAccount, AccountState};
+use subscription_program::PlanSubscription;
+use thiserror::Error;
+
+use crate::{spending_limits, Subscription};
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ChargeBlocker {
+    #[error("subscription is not active")]
+    Inactive,
+    #[error("subscription expired at {expires_at}")]
+    Expired { expires_at: i64 },
+    #[error("subscription has no valid billing interval")]
+    InvalidInterval,
+    #[error("interval not met, next charge at {next_charge_at}")]
+    IntervalNotMet { next_charge_at: i64 },
+    #[error("subscription is priced in USD and needs charge_subscription_usd")]
+    UsdPriceRequired,
+    #[error("subscription has a token price and cannot be charged in USD")]
+    InvalidUsdPeg,
+    #[error("cached USD price is stale")]
+    StalePrice,
+    #[error("USD price does not convert to a token amount")]
+    PriceOverflow,
+    #[error("token account {0} does not exist")]
+    TokenAccountMissing(Pubkey),
+    #[error("token account is delegated to its spending policy")]
+    DelegatedToPolicy,
+    #[error("subscription is billed by its plan subscription, which was not given")]
+    PlanSubscriptionRequired,
+    #[error("token account {0} is frozen")]
+    TokenAccountFrozen(Pubkey),
+    #[error("token account {0} holds the wrong mint")]
+    MintMismatch(Pubkey),
+    #[error("delegated allowance {delegated} is below {required}")]
+    AllowanceTooLow { delegated: u64, required: u64 },
+    #[error("balance {balance} is below {required}")]
+    InsufficientBalance { balance: u64, required: u64 },
+}
+
+impl ChargeBlocker {
+    /// Whether the same charge might succeed later without user action
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ChargeBlocker::IntervalNotMet { .. }
+                | ChargeBlocker::InsufficientBalance { .. }
+                | ChargeBlocker::StalePrice
+        )
+    }
+}
+
+/// Why a charge ends the subscription instead of charging it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum Deactivation {
+    #[error("delegation to the subscription PDA was revoked")]
+    DelegationRevoked,
+    #[error("spend limit reached")]
+    SpendLimitReached,
+}
+
+/// What a charge that goes through does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChargeOutcome {
+    /// Takes at least one period at `period_amount`
+    Charged { period_amount: u64 },
+    /// Lands, but only deactivates the subscription
+    Deactivated(Deactivation),
+}
+
+/// Accounts a charge reads besides the subscription and its token accounts
+#[derive(Clone, Copy, Default)]
+pub struct ChargeContext<'a> {
+    /// Required when the subscription is `on_plan`, for its price schedule
+    pub plan_subscription: Option<&'a PlanSubscription>,
+    /// What `charge_subscription_usd` converts the peg to at the cached
+    /// price; `None` for `charge_subscription`
+    pub usd_amount: Option<u64>,
+}
+
+/// Check a charge against already-fetched state. `now` is the cluster unix timestamp.
+pub fn check_charge(
+    subscription_address: &Pubkey,
+    subscription: &Subscription,
+    user_token_account: Option<&TokenAccount>,
+    recipient_token_account: Option<&TokenAccount>,
+    context: ChargeContext,
+    now: i64,
+) -> Result<ChargeOutcome, ChargeBlocker> {
+    if !subscription.is_active {
+        return Err(ChargeBlocker::Inactive);
+    }
+
+    if let Some(expires_at) = subscription.expires_at {
+        if now >= expires_at {
+            return Err(ChargeBlocker::Expired { expires_at });
+        }
+    }
+
+    // Same rule as `Subscription::check_chargeable`
+    if subscription.interval().is_none() {
+        return Err(ChargeBlocker::InvalidInterval);
+    }
+    let due = subscription.period_end(subscription.last_charge_timestamp);
+    if due.is_none_or(|due| now < due) {
+        return Err(ChargeBlocker::IntervalNotMet {
+            next_charge_at: due.unwrap_or(i64::MAX),
+        });
+    }
+
+    let mut period_amount = match context.usd_amount {
+        Some(_) if subscription.amount_per_period != 0 => return Err(ChargeBlocker::InvalidUsdPeg),
+        Some(amount) => amount,
+        None if subscription.amount_per_period == 0 => return Err(ChargeBlocker::UsdPriceRequired),
+        None => subscription.amount_per_period,
+    };
+
+    let user = user_token_account.ok_or(ChargeBlocker::TokenAccountMissing(
+        subscription.user_token_account,
+    ))?;
+    let recipient = recipient_token_account.ok_or(ChargeBlocker::TokenAccountMissing(
+        subscription.recipient_token_account,
+    ))?;
+
+    // A revoked delegation deactivates rather than fails, unless the
+    // account moved to its spending policy
+    if !subscription.is_delegated(subscription_address, user) {
+        let (policy, _) = spending_limits::policy_address(&subscription.user_token_account);
+        if user.delegate == COption::Some(policy) {
+            return Err(ChargeBlocker::DelegatedToPolicy);
+        }
+        let reason = if user.delegate == COption::Some(*subscription_address) {
+            Deactivation::SpendLimitReached
+        } else {
+            Deactivation::DelegationRevoked
+        };
+        return Ok(ChargeOutcome::Deactivated(reason));
+    }
+
+    if subscription.on_plan && context.plan_subscription.is_none() {
+        return Err(ChargeBlocker::PlanSubscriptionRequired);
+    }
+    if let Some(plan_subscription) = context
+        .plan_subscription
+        .filter(|_| context.usd_amount.is_none())
+    {
+        let mut stepped = subscription.clone();
+        if plan_subscription.clone().apply_price(&mut stepped) {
+            period_amount = stepped.amount_per_period;
+        }
+    }
+
+    if subscription.spend_left() < period_amount {
+        return Ok(ChargeOutcome::Deactivated(Deactivation::SpendLimitReached));
+    }
+
+    for (address, account) in [
+        (subscription.user_token_account, user),
+        (subscription.recipient_token_account, recipient),
+    ] {
+        if account.state == AccountState::Frozen {
+            return Err(ChargeBlocker::TokenAccountFrozen(address));
+        }
+        if account.mint != subscription.token_mint {
+            return Err(ChargeBlocker::MintMismatch(address));
+        }
+    }
+
+    if user.delegated_amount < period_amount {
+        return Err(ChargeBlocker::AllowanceTooLow {
+            delegated: user.delegated_amount,
+            required: period_amount,
+        });
+    }
+    if user.amount < period_amount {
+        return Err(ChargeBlocker::InsufficientBalance {
+            balance: user.amount,
+            required: period_amount,
+        });
+    }
+
+    Ok(ChargeOutcome::Charged { period_amount })
+}
+
+#[cfg(feature = "rpc")]
+pub use self::rpc::*;
+
+#[cfg(feature = "rpc")]
+mod rpc {
+    use anchor_lang::solana_program::sysvar;
+    use solana_client::nonblocking::rpc_client::RpcClient;
+    use subscription_program::{ErrorCode, PriceCache, UsdPeg};
+
+    use super::*;
+    use crate::accounts::{decode_account, decode_subscription, decode_token_account};
+    use crate::{pda, ClientError, Result};
+
+    /// `unix_timestamp` is the last field of the Clock sysvar
+    const CLOCK_UNIX_TIMESTAMP_OFFSET: usize = 32;
+
+    /// Fetch the subscription, both token accounts, the cluster clock and
+    /// whatever prices the subscription, then run [`check_charge`]. A
+    /// USD-priced subscription is checked as `charge_subscription_usd`.
+    pub async fn preflight_charge(
+        rpc: &RpcClient,
+        subscription_address: &Pubkey,
+    ) -> Result<std::result::Result<ChargeOutcome, ChargeBlocker>> {
+        let subscription = match rpc
+            .get_account_with_commitment(subscription_address, rpc.commitment())
+            .await?
+            .value
+        {
+            Some(account) => decode_subscription(subscription_address, &account.data)?,
+            None => return Err(ClientError::AccountNotFound(*subscription_address)),
+        };
+
+        let plan_subscription_address = pda::plan_subscription_address(subscription_address).0;
+        let usd_peg_address = pda::usd_peg_address(subscription_address).0;
+        let keys = [
+            subscription.user_token_account,
+            subscription.recipient_token_account,
+            sysvar::clock::ID,
+            plan_subscription_address,
+            usd_peg_address,
+        ];
+        let accounts = rpc.get_multiple_accounts(&keys).await?;
+        let [user, recipient, clock, plan_subscription, usd_peg] = <[_; 5]>::try_from(accounts)
+            .map_err(|accounts| {
+                ClientError::UnexpectedResponse(format!(
+                    "{} accounts for {} keys",
+                    accounts.len(),
+                    keys.len()
+                ))
+            })?;
+
+        let clock = clock.ok_or(ClientError::AccountNotFound(sysvar::clock::ID))?;
+        let now = clock
+            .data
+            .get(CLOCK_UNIX_TIMESTAMP_OFFSET..CLOCK_UNIX_TIMESTAMP_OFFSET + 8)
+            .and_then(|bytes| bytes.try_into().ok())
+            .map(i64::from_le_bytes)
+            .ok_or_else(|| {
+                ClientError::UnexpectedResponse(format!(
+                    "clock sysvar of {} bytes",
+                    clock.data.len()
+                ))
+            })?;
+
+        let user = user
+            .map(|account| decode_token_account(&keys[0], &account.data))
+            .transpose()?;
+        let recipient = recipient
+            .map(|account| decode_token_account(&keys[1], &account.data))
+            .transpose()?;
+        let plan_subscription: Option<PlanSubscription> = plan_subscription
+            .filter(|_| subscription.on_plan)
+            .map(|account| decode_account(&plan_subscription_address, &account.data))
+            .transpose()?;
+
+        // Only a subscription without a token price is charged in USD
+        let usd_amount = match usd_peg.filter(|_| subscription.amount_per_period == 0) {
+            Some(account) => {
+                let usd_peg: UsdPeg = decode_account(&usd_peg_address, &account.data)?;
+                let price_cache: Option<PriceCache> = rpc
+                    .get_account_with_commitment(&usd_peg.price_cache, rpc.commitment())
+                    .await?
+                    .value
+                    .map(|account| decode_account(&usd_peg.price_cache, &account.data))
+                    .transpose()?;
+                let price_cache =
+                    price_cache.ok_or(ClientError::AccountNotFound(usd_peg.price_cache))?;
+                match usd_peg.token_amount(&price_cache, now) {
+                    Ok(amount) => Some(amount),
+                    Err(error) if error == ErrorCode::StalePrice.into() => {
+                        return Ok(Err(ChargeBlocker::StalePrice))
+                    }
+                    Err(_) => return Ok(Err(ChargeBlocker::PriceOverflow)),
+                }
+            }
+            None => None,
+        };
+
+        Ok(check_charge(
+            subscription_address,
+            &subscription,
+            user.as_ref(),
+            recipient.as_ref(),
+            ChargeContext {
+                plan_subscription: plan_subscription.as_ref(),
+                usd_amount,
+            },
+            now,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use subscription_program::{BillingCadence, PriceStep};
+
+    use super::*;
+
+    const DAY: i64 = 86_400;
+    const AMOUNT: u64 = 1_000;
+
+    fn subscription() -> (Pubkey, Subscription) {
+        let subscription = Subscription {
+            authority: Pubkey::new_unique(),
+            recipient: Pubkey::new_unique(),
+            user_token_account: Pubkey::new_unique(),
+            recipient_token_account: Pubkey::new_unique(),
+            token_mint: Pubkey::new_unique(),
+            amount_per_period: AMOUNT,
+            interval_seconds: DAY,
+            last_charge_timestamp: 0,
+            next_charge_at: DAY,
+            created_at: 0,
+            expires_at: None,
+            is_active: true,
+            total_charged: AMOUNT,
+            bump: 255,
+            on_plan: false,
+            max_total_spend: Subscription::NO_SPEND_LIMIT,
+        };
+        (Pubkey::new_unique(), subscription)
+    }
+
+    fn token_account(mint: Pubkey, delegate: Option<Pubkey>, amount: u64) -> TokenAccount {
+        TokenAccount {
+            mint,
+            owner: Pubkey::new_unique(),
+            amount,
+            delegate: delegate.into(),
+            state: AccountState::Initialized,
+            is_native: COption::None,
+            delegated_amount: if delegate.is_some() { u64::MAX } else { 0 },
+            close_authority: COption::None,
+        }
+    }
+
+    fn check(
+        address: &Pubkey,
+        subscription: &Subscription,
+        user: &TokenAccount,
+        context: ChargeContext,
+    ) -> Result<ChargeOutcome, ChargeBlocker> {
+        let recipient = token_account(subscription.token_mint, None, 0);
+        check_charge(
+            address,
+            subscription,
+            Some(user),
+            Some(&recipient),
+            context,
+            DAY,
+        )
+    }
+
+    #[test]
+    fn due_subscription_is_charged() {
+        let (address, subscription) = subscription();
+        let user = token_account(subscription.token_mint, Some(address), AMOUNT);
+
+        assert_eq!(
+            check(&address, &subscription, &user, ChargeContext::default()),
+            Ok(ChargeOutcome::Charged {
+                period_amount: AMOUNT
+            })
+        );
+    }
+
+    #[test]
+    fn revoked_delegation_deactivates() {
+        let (address, subscription) = subscription();
+        let user = token_account(subscription.token_mint, None, AMOUNT);
+
+        assert_eq!(
+            check(&address, &subscription, &user, ChargeContext::default()),
+            Ok(ChargeOutcome::Deactivated(Deactivation::DelegationRevoked))
+        );
+
+        // Moving the delegation to the spending policy fails the charge instead
+        let (policy, _) = spending_limits::policy_address(&subscription.user_token_account);
+        let user = token_account(subscription.token_mint, Some(policy), AMOUNT);
+        assert_eq!(
+            check(&address, &subscription, &user, ChargeContext::default()),
+            Err(ChargeBlocker::DelegatedToPolicy)
+        );
+    }
+
+    #[test]
+    fn spend_limit_deactivates() {
+        let (address, mut subscription) = subscription();
+        subscription.max_total_spend = AMOUNT + AMOUNT / 2;
+        let user = token_account(subscription.token_mint, Some(address), AMOUNT);
+
+        assert_eq!(
+            check(&address, &subscription, &user, ChargeContext::default()),
+            Ok(ChargeOutcome::Deactivated(Deactivation::SpendLimitReached))
+        );
+    }
+
+    #[test]
+    fn usd_priced_subscription_needs_a_usd_amount() {
+        let (address, mut subscription) = subscription();
+        let user = token_account(subscription.token_mint, Some(address), AMOUNT);
+        let usd = ChargeContext {
+            usd_amount: Some(AMOUNT / 2),
+            ..ChargeContext::default()
+        };
+
+        assert_eq!(
+            check(&address, &subscription, &user, usd),
+            Err(ChargeBlocker::InvalidUsdPeg)
+        );
+
+        subscription.amount_per_period = 0;
+        assert_eq!(
+            check(&address, &subscription, &user, ChargeContext::default()),
+            Err(ChargeBlocker::UsdPriceRequired)
+        );
+        assert_eq!(
+            check(&address, &subscription, &user, usd),
+            Ok(ChargeOutcome::Charged {
+                period_amount: AMOUNT / 2
+            })
+        );
+    }
+
+    #[test]
+    fn plan_price_step_sets_the_period_amount() {
+        let (address, mut subscription) = subscription();
+        subscription.on_plan = true;
+        // Funds the intro price, not the full price the next period is at
+        let user = token_account(subscription.token_mint, Some(address), AMOUNT);
+        let plan_subscription = PlanSubscription {
+            subscription: address,
+            plan: Pubkey::new_unique(),
+            cadence: BillingCadence::Monthly,
+            period_start: 0,
+            units: 0,
+            periods_over: 0,
+            upgrade_at: None,
+            overage_billed: 0,
+            overage_charged: 0,
+            price_steps: vec![PriceStep {
+                until: DAY,
+                amount_per_period: AMOUNT,
+            }],
+            full_price: 2 * AMOUNT,
+            bump: 255,
+        };
+
+        assert_eq!(
+            check(&address, &subscription, &user, ChargeContext::default()),
+            Err(ChargeBlocker::PlanSubscriptionRequired)
+        );
+        assert_eq!(
+            check(
+                &address,
+                &subscription,
+                &user,
+                ChargeContext {
+                    plan_subscription: Some(&plan_subscription),
+                    usd_amount: None,
+                }
+            ),
+            Err(ChargeBlocker::InsufficientBalance {
+                balance: AMOUNT,
+                required: 2 * AMOUNT,
+            })
+        );
+    }
+}
@@ -0,0 +1,222 @@
+//! Client for the subscription raffle recipe program.
+//!
+//! Randomness comes from Switchboard On-Demand: put Switchboard's commit
+//! instruction for the randomness account just before [`request_draw`] in the
+//! same transaction, then send its reveal instruction ahead of
+//! [`draw_winner`]. Everything else is cranked by [`due_cranks`].
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use raffle::{accounts, instruction};
+
+pub use raffle::{
+    Entry, EntryRange, Raffle, Randomness, ID as RAFFLE_PROGRAM_ID, MAX_RANGES,
+    SWITCHBOARD_PROGRAM_ID,
+};
+
+use crate::pda::{associated_token_address, subscription_address};
+use crate::Subscription;
+
+pub const RAFFLE_SEED: &[u8] = b"raffle";
+pub const ENTRY_SEED: &[u8] = b"entry";
+
+/// Raffle PDA for a (merchant, raffle_id) pair
+pub fn raffle_address(merchant: &Pubkey, raffle_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[RAFFLE_SEED, merchant.as_ref(), &raffle_id.to_le_bytes()],
+        &RAFFLE_PROGRAM_ID,
+    )
+}
+
+/// Entry PDA of an entrant in a raffle
+pub fn entry_address(raffle: &Pubkey, owner: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[ENTRY_SEED, raffle.as_ref(), owner.as_ref()],
+        &RAFFLE_PROGRAM_ID,
+    )
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: RAFFLE_PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// Escrow the prize from the merchant's ATA. The vault ATA must exist, so
+/// prepend a `CreateIdempotent` for `associated_token_address(&raffle, mint)`.
+pub fn create_raffle(
+    merchant: &Pubkey,
+    mint: &Pubkey,
+    payer: &Pubkey,
+    raffle_id: u64,
+    prize_amount: u64,
+    entry_end: i64,
+) -> Instruction {
+    let raffle = raffle_address(merchant, raffle_id).0;
+    build(
+        accounts::CreateRaffle {
+            raffle,
+            merchant: *merchant,
+            mint: *mint,
+            merchant_token_account: associated_token_address(merchant, mint),
+            vault: associated_token_address(&raffle, mint),
+            token_program: spl_token::ID,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreateRaffle {
+            raffle_id,
+            prize_amount,
+            entry_end,
+        },
+    )
+}
+
+/// Join with the owner's subscription to the merchant; `payer` can be a relayer
+pub fn enter(raffle: &Raffle, owner: &Pubkey, payer: &Pubkey) -> Instruction {
+    let address = raffle_address(&raffle.merchant, raffle.raffle_id).0;
+    build(
+        accounts::Enter {
+            raffle: address,
+            entry: entry_address(&address, owner).0,
+            owner: *owner,
+            subscription: subscription_address(owner, &raffle.merchant).0,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::Enter {},
+    )
+}
+
+pub fn claim_entries(raffle: &Raffle, owner: &Pubkey) -> Instruction {
+    let address = raffle_address(&raffle.merchant, raffle.raffle_id).0;
+    build(
+        accounts::ClaimEntries {
+            raffle: address,
+            entry: entry_address(&address, owner).0,
+            subscription: subscription_address(owner, &raffle.merchant).0,
+        },
+        instruction::ClaimEntries {},
+    )
+}
+
+/// Merchant commits the draw to `randomness`, which Switchboard's commit
+/// instruction must have seeded earlier in the same transaction
+pub fn request_draw(raffle: &Raffle, randomness: &Pubkey) -> Instruction {
+    build(
+        accounts::RequestDraw {
+            raffle: raffle_address(&raffle.merchant, raffle.raffle_id).0,
+            merchant: raffle.merchant,
+            randomness: *randomness,
+        },
+        instruction::RequestDraw {},
+    )
+}
+
+/// Fails until the committed randomness is revealed
+pub fn draw_winner(raffle: &Raffle, randomness: &Pubkey) -> Instruction {
+    build(
+        accounts::DrawWinner {
+            raffle: raffle_address(&raffle.merchant, raffle.raffle_id).0,
+            randomness: *randomness,
+        },
+        instruction::DrawWinner {},
+    )
+}
+
+/// Pays the winner's ATA, which must exist
+pub fn claim_prize(raffle: &Raffle, winner: &Pubkey) -> Instruction {
+    let address = raffle_address(&raffle.merchant, raffle.raffle_id).0;
+    build(
+        accounts::ClaimPrize {
+            raffle: address,
+            entry: entry_address(&address, winner).0,
+            winner_token_account: associated_token_address(winner, &raffle.mint),
+            vault: raffle.vault,
+            token_program: spl_token::ID,
+        },
+        instruction::ClaimPrize {},
+    )
+}
+
+pub fn close_entry(raffle: &Raffle, owner: &Pubkey) -> Instruction {
+    let address = raffle_address(&raffle.merchant, raffle.raffle_id).0;
+    build(
+        accounts::CloseEntry {
+            raffle: address,
+            entry: entry_address(&address, owner).0,
+            owner: *owner,
+        },
+        instruction::CloseEntry {},
+    )
+}
+
+pub fn close_raffle(raffle: &Raffle) -> Instruction {
+    build(
+        accounts::CloseRaffle {
+            raffle: raffle_address(&raffle.merchant, raffle.raffle_id).0,
+            merchant: raffle.merchant,
+            merchant_token_account: associated_token_address(&raffle.merchant, &raffle.mint),
+            vault: raffle.vault,
+            token_program: spl_token::ID,
+        },
+        instruction::CloseRaffle {},
+    )
+}
+
+/// One keeper pass over a raffle and its open entries with their
+/// subscriptions at `now`. While entries are open: a `claim_entries` for
+/// every entrant with an active subscription charged since their last
+/// claim. Once decided: the winner's `claim_prize`, a `close_entry` for
+/// every entry that can close, then `close_raffle` if that closes them all.
+pub fn due_cranks(
+    raffle: &Raffle,
+    entrants: &[(Entry, Subscription)],
+    now: i64,
+) -> Vec<Instruction> {
+    if raffle.check_entering(now).is_ok() {
+        return entrants
+            .iter()
+            .filter(|(entry, subscription)| {
+                subscription_program::assert_active_subscription(subscription, now).is_ok()
+                    && entry
+                        .clone()
+                        .credit_charges(subscription.total_charged, subscription.amount_per_period)
+                        .is_ok_and(|periods| periods > 0)
+            })
+            .map(|(entry, _)| claim_entries(raffle, &entry.owner))
+            .collect();
+    }
+    if !raffle.is_decided(now) {
+        return Vec::new();
+    }
+
+    let mut instructions = Vec::new();
+    let mut raffle = raffle.clone();
+    if let Some(winning_entry) = raffle.winning_entry.filter(|_| !raffle.prize_claimed) {
+        if let Some((winner, _)) = entrants
+            .iter()
+            .find(|(entry, _)| entry.holds(winning_entry))
+        {
+            instructions.push(claim_prize(&raffle, &winner.owner));
+            raffle.prize_claimed = true;
+        }
+    }
+    let closable: Vec<&Entry> = entrants
+        .iter()
+        .map(|(entry, _)| entry)
+        .filter(|entry| raffle.check_entry_closable(entry, now).is_ok())
+        .collect();
+    instructions.extend(
+        closable
+            .iter()
+            .map(|entry| close_entry(&raffle, &entry.owner)),
+    );
+    if raffle.closed_entries as usize + closable.len() == raffle.entrant_count as usize {
+        instructions.push(close_raffle(&raffle));
+    }
+    instructions
+}
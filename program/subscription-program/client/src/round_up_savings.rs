@@ -0,0 +1,138 @@
+//! Client for the round-up savings recipe program.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use round_up_savings::{accounts, instruction};
+
+pub use round_up_savings::{Savings, ID as ROUND_UP_SAVINGS_PROGRAM_ID};
+
+use crate::pda::associated_token_address;
+
+pub const SAVINGS_SEED: &[u8] = b"savings";
+
+/// Savings PDA of `owner` for one mint
+pub fn savings_address(owner: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[SAVINGS_SEED, owner.as_ref(), mint.as_ref()],
+        &ROUND_UP_SAVINGS_PROGRAM_ID,
+    )
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: ROUND_UP_SAVINGS_PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// Sweeps from the owner's ATA for `mint`. The vault, the savings PDA's ATA
+/// for `mint`, must exist; create it beforehand with `CreateIdempotent`.
+pub fn open_savings(
+    owner: &Pubkey,
+    mint: &Pubkey,
+    payer: &Pubkey,
+    round_to: u64,
+    interval_seconds: i64,
+    max_per_sweep: u64,
+    goal_amount: u64,
+) -> Instruction {
+    let savings = savings_address(owner, mint).0;
+    build(
+        accounts::OpenSavings {
+            savings,
+            owner: *owner,
+            mint: *mint,
+            source_account: associated_token_address(owner, mint),
+            vault: associated_token_address(&savings, mint),
+            payer: *payer,
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+        },
+        instruction::OpenSavings {
+            round_to,
+            interval_seconds,
+            max_per_sweep,
+            goal_amount,
+        },
+    )
+}
+
+fn update_accounts(savings: &Savings) -> accounts::UpdateSavings {
+    accounts::UpdateSavings {
+        savings: savings_address(&savings.owner, &savings.mint).0,
+        owner: savings.owner,
+    }
+}
+
+pub fn update_savings(
+    savings: &Savings,
+    round_to: u64,
+    interval_seconds: i64,
+    max_per_sweep: u64,
+) -> Instruction {
+    build(
+        update_accounts(savings),
+        instruction::UpdateSavings {
+            round_to,
+            interval_seconds,
+            max_per_sweep,
+        },
+    )
+}
+
+pub fn set_goal(savings: &Savings, goal_amount: u64) -> Instruction {
+    build(
+        update_accounts(savings),
+        instruction::SetGoal { goal_amount },
+    )
+}
+
+pub fn sweep(savings: &Savings) -> Instruction {
+    build(
+        accounts::Sweep {
+            savings: savings_address(&savings.owner, &savings.mint).0,
+            source_account: savings.source_account,
+            vault: savings.vault,
+            token_program: spl_token::ID,
+        },
+        instruction::Sweep {},
+    )
+}
+
+/// Withdraws to the source account
+pub fn withdraw(savings: &Savings, amount: u64) -> Instruction {
+    build(
+        accounts::Withdraw {
+            savings: savings_address(&savings.owner, &savings.mint).0,
+            owner: savings.owner,
+            vault: savings.vault,
+            source_account: savings.source_account,
+            token_program: spl_token::ID,
+        },
+        instruction::Withdraw { amount },
+    )
+}
+
+pub fn close_savings(savings: &Savings) -> Instruction {
+    build(
+        accounts::CloseSavings {
+            savings: savings_address(&savings.owner, &savings.mint).0,
+            owner: savings.owner,
+            vault: savings.vault,
+            source_account: savings.source_account,
+            token_program: spl_token::ID,
+        },
+        instruction::CloseSavings {},
+    )
+}
+
+/// One keeper pass: a `sweep` for every savings account due at `now`
+pub fn due_sweeps(savings: &[Savings], now: i64) -> Vec<Instruction> {
+    savings
+        .iter()
+        .filter(|savings| savings.check_due(now).is_ok())
+        .map(sweep)
+        .collect()
+}
@@ -0,0 +1,188 @@
+//! Client for the sealed-bid auction recipe program.
+//!
+//! Bidders only sign; every instruction takes the fee and rent payer
+//! separately, so a relayer can sponsor bids from passkey wallets. Vaults
+//! are always the auction PDA's ATAs.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use sealed_auction::{accounts, instruction};
+
+pub use sealed_auction::{Auction, Bid, ID as SEALED_AUCTION_PROGRAM_ID};
+
+use crate::pda::associated_token_address;
+
+pub const AUCTION_SEED: &[u8] = b"auction";
+pub const BID_SEED: &[u8] = b"bid";
+
+/// Auction PDA for a (seller, auction_id) pair
+pub fn auction_address(seller: &Pubkey, auction_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[AUCTION_SEED, seller.as_ref(), &auction_id.to_le_bytes()],
+        &SEALED_AUCTION_PROGRAM_ID,
+    )
+}
+
+/// Bid PDA of a bidder in an auction
+pub fn bid_address(auction: &Pubkey, bidder: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[BID_SEED, auction.as_ref(), bidder.as_ref()],
+        &SEALED_AUCTION_PROGRAM_ID,
+    )
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: SEALED_AUCTION_PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// Escrow the item from the seller's ATA. Both vault ATAs must exist, so
+/// prepend `CreateIdempotent`s for the auction PDA's payment and item ATAs.
+#[allow(clippy::too_many_arguments)]
+pub fn create_auction(
+    seller: &Pubkey,
+    payment_mint: &Pubkey,
+    item_mint: &Pubkey,
+    payer: &Pubkey,
+    auction_id: u64,
+    item_amount: u64,
+    bond_amount: u64,
+    reserve_price: u64,
+    bid_end: i64,
+    reveal_end: i64,
+) -> Instruction {
+    let auction = auction_address(seller, auction_id).0;
+    build(
+        accounts::CreateAuction {
+            auction,
+            seller: *seller,
+            payment_mint: *payment_mint,
+            item_mint: *item_mint,
+            seller_token_account: associated_token_address(seller, payment_mint),
+            seller_item_account: associated_token_address(seller, item_mint),
+            vault: associated_token_address(&auction, payment_mint),
+            item_vault: associated_token_address(&auction, item_mint),
+            token_program: spl_token::ID,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreateAuction {
+            auction_id,
+            item_amount,
+            bond_amount,
+            reserve_price,
+            bid_end,
+            reveal_end,
+        },
+    )
+}
+
+/// Seal a bid of `amount` from the bidder's ATA. Keep `salt` secret and
+/// random; it is needed again to reveal.
+pub fn place_bid(
+    auction: &Auction,
+    bidder: &Pubkey,
+    payer: &Pubkey,
+    amount: u64,
+    salt: &[u8; 32],
+) -> Instruction {
+    let auction_key = auction_address(&auction.seller, auction.auction_id).0;
+    build(
+        accounts::PlaceBid {
+            auction: auction_key,
+            bid: bid_address(&auction_key, bidder).0,
+            bidder: *bidder,
+            bidder_token_account: associated_token_address(bidder, &auction.payment_mint),
+            vault: auction.vault,
+            token_program: spl_token::ID,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::PlaceBid {
+            commitment: Bid::commitment(bidder, amount, salt),
+        },
+    )
+}
+
+pub fn reveal_bid(auction: &Auction, bid: &Bid, amount: u64, salt: [u8; 32]) -> Instruction {
+    build(
+        accounts::RevealBid {
+            auction: bid.auction,
+            bid: bid_address(&bid.auction, &bid.bidder).0,
+            bidder: bid.bidder,
+            bidder_token_account: bid.token_account,
+            vault: auction.vault,
+            token_program: spl_token::ID,
+        },
+        instruction::RevealBid { amount, salt },
+    )
+}
+
+/// Sends the item to the winner's ATA, which must exist, or back to the
+/// seller without a winner
+pub fn settle_auction(auction: &Auction) -> Instruction {
+    let item_destination = match auction.winner {
+        Some(winner) => associated_token_address(&winner, &auction.item_mint),
+        None => auction.seller_item_account,
+    };
+    build(
+        accounts::SettleAuction {
+            auction: auction_address(&auction.seller, auction.auction_id).0,
+            vault: auction.vault,
+            item_vault: auction.item_vault,
+            seller_token_account: auction.seller_token_account,
+            item_destination,
+            token_program: spl_token::ID,
+        },
+        instruction::SettleAuction {},
+    )
+}
+
+pub fn close_bid(auction: &Auction, bid: &Bid) -> Instruction {
+    build(
+        accounts::CloseBid {
+            auction: bid.auction,
+            bid: bid_address(&bid.auction, &bid.bidder).0,
+            bidder: bid.bidder,
+            bidder_token_account: bid.token_account,
+            seller_token_account: auction.seller_token_account,
+            vault: auction.vault,
+            token_program: spl_token::ID,
+        },
+        instruction::CloseBid {},
+    )
+}
+
+pub fn close_auction(auction: &Auction) -> Instruction {
+    build(
+        accounts::CloseAuction {
+            auction: auction_address(&auction.seller, auction.auction_id).0,
+            seller: auction.seller,
+            vault: auction.vault,
+            item_vault: auction.item_vault,
+            token_program: spl_token::ID,
+        },
+        instruction::CloseAuction {},
+    )
+}
+
+/// One keeper pass over an auction and its open `bids` at `now`: settle it
+/// once reveals are over, then close every bid and the auction itself
+pub fn due_settlement(auction: &Auction, bids: &[Bid], now: i64) -> Vec<Instruction> {
+    if !auction.settled && auction.check_settleable(now).is_err() {
+        return Vec::new();
+    }
+    let mut instructions = Vec::new();
+    if !auction.settled {
+        instructions.push(settle_auction(auction));
+    }
+    instructions.extend(bids.iter().map(|bid| close_bid(auction, bid)));
+    if auction.closed_bids as usize + bids.len() == auction.bid_count as usize {
+        instructions.push(close_auction(auction));
+    }
+    instructions
+}
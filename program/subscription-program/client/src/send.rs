@@ -0,0 +1,251 @@
+//! Reliable submission.
+//!
+//! `sendTransaction` is fire-and-forget: the leader may drop the packet, and a
+//! transaction whose blockhash expires before it lands is simply gone. The
+//! helpers here resend until the signature reaches the requested commitment,
+//! and once the blockhash is past its last valid block height they rebuild
+//! the transaction with a fresh one and try again. A transaction signed
+//! elsewhere cannot be rebuilt, so its caller says when it expires.
+
+use std::time::Duration;
+
+use anchor_lang::prelude::Pubkey;
+use solana_commitment_config::CommitmentConfig;
+
+/// How hard [`send_with_retry`] tries before giving up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendConfig {
+    /// Commitment used for the blockhash, block height and confirmation
+    pub commitment: CommitmentConfig,
+    /// Fresh blockhashes to try after the first one expires
+    pub max_blockhash_refreshes: usize,
+    /// How often the same signed transaction is re-broadcast while pending
+    pub resend_interval: Duration,
+    /// How often the signature status is polled
+    pub poll_interval: Duration,
+    pub skip_preflight: bool,
+}
+
+impl Default for SendConfig {
+    fn default() -> Self {
+        Self {
+            commitment: CommitmentConfig::confirmed(),
+            max_blockhash_refreshes: 2,
+            resend_interval: Duration::from_secs(2),
+            poll_interval: Duration::from_millis(500),
+            skip_preflight: false,
+        }
+    }
+}
+
+impl SendConfig {
+    pub fn commitment(mut self, commitment: CommitmentConfig) -> Self {
+        self.commitment = commitment;
+        self
+    }
+
+    pub fn max_blockhash_refreshes(mut self, refreshes: usize) -> Self {
+        self.max_blockhash_refreshes = refreshes;
+        self
+    }
+
+    pub fn resend_interval(mut self, interval: Duration) -> Self {
+        self.resend_interval = interval;
+        self
+    }
+
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    pub fn skip_preflight(mut self, skip: bool) -> Self {
+        self.skip_preflight = skip;
+        self
+    }
+}
+
+/// A transaction built on `last_valid_block_height` can no longer land
+pub fn is_blockhash_expired(block_height: u64, last_valid_block_height: u64) -> bool {
+    block_height > last_valid_block_height
+}
+
+/// When a signed transaction stops being able to land
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expiry {
+    /// Built on a recent blockhash; the `last_valid_block_height` returned
+    /// with it
+    BlockHeight(u64),
+    /// Built on the nonce stored in this durable nonce account, and valid
+    /// until the account advances past it
+    DurableNonce(Pubkey),
+}
+
+#[cfg(feature = "rpc")]
+pub use self::rpc::*;
+
+#[cfg(feature = "rpc")]
+mod rpc {
+    use std::time::Instant;
+
+    use solana_client::nonblocking::rpc_client::RpcClient;
+    use solana_client::rpc_config::RpcSendTransactionConfig;
+    use solana_instruction::Instruction;
+    use solana_signature::Signature;
+    use solana_signer::Signer;
+    use solana_transaction::versioned::VersionedTransaction;
+    use solana_transaction_error::TransactionError;
+
+    use super::*;
+    use crate::offline::fetch_nonce;
+    use crate::transaction::{compile_message, AddressLookupTableAccount};
+    use crate::{ClientError, Result};
+
+    /// Build, sign, send and confirm `instructions`, refreshing the blockhash
+    /// when it expires. Returns the signature of the transaction that landed.
+    pub async fn send_with_retry(
+        rpc: &RpcClient,
+        payer: &Pubkey,
+        instructions: &[Instruction],
+        lookup_tables: &[AddressLookupTableAccount],
+        signers: &[&dyn Signer],
+        config: &SendConfig,
+    ) -> Result<Signature> {
+        for _ in 0..=config.max_blockhash_refreshes {
+            let (blockhash, last_valid_block_height) = rpc
+                .get_latest_blockhash_with_commitment(config.commitment)
+                .await?;
+            let message = compile_message(payer, instructions, lookup_tables, blockhash)?;
+            let transaction = VersionedTransaction::try_new(message, signers)
+                .map_err(|err| ClientError::Signing(err.to_string()))?;
+
+            let expiry = Expiry::BlockHeight(last_valid_block_height);
+            match send_and_confirm(rpc, &transaction, expiry, config).await {
+                Err(ClientError::BlockhashExpired { .. }) => continue,
+                result => return result,
+            }
+        }
+
+        Err(ClientError::BlockhashExpired {
+            attempts: config.max_blockhash_refreshes + 1,
+        })
+    }
+
+    /// Send an already signed transaction and poll until it reaches the
+    /// configured commitment, resending while it is pending and giving up
+    /// at `expiry`. Use this directly for transactions that cannot be
+    /// re-signed here (offline or durable nonce).
+    pub async fn send_and_confirm(
+        rpc: &RpcClient,
+        transaction: &VersionedTransaction,
+        expiry: Expiry,
+        config: &SendConfig,
+    ) -> Result<Signature> {
+        let send_config = RpcSendTransactionConfig {
+            skip_preflight: config.skip_preflight,
+            preflight_commitment: Some(config.commitment.commitment),
+            max_retries: Some(0),
+            ..RpcSendTransactionConfig::default()
+        };
+
+        // The first send surfaces preflight failures; a stale blockhash there
+        // is the same as expiry
+        let signature = match rpc
+            .send_transaction_with_config(transaction, send_config)
+            .await
+        {
+            Ok(signature) => signature,
+            Err(err)
+                if err.get_transaction_error() == Some(TransactionError::BlockhashNotFound) =>
+            {
+                return Err(ClientError::BlockhashExpired { attempts: 1 });
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let mut last_sent = Instant::now();
+
+        loop {
+            tokio::time::sleep(config.poll_interval).await;
+
+            match poll(rpc, &signature, config).await? {
+                Status::Done(result) => return result,
+                // Landed but not yet at the requested commitment; it can no
+                // longer expire, so keep polling without resending
+                Status::Landed => continue,
+                Status::Pending => {}
+            }
+
+            let expired = match expiry {
+                Expiry::BlockHeight(last_valid_block_height) => {
+                    let block_height = rpc
+                        .get_block_height_with_commitment(config.commitment)
+                        .await?;
+                    is_blockhash_expired(block_height, last_valid_block_height)
+                }
+                Expiry::DurableNonce(nonce_account) => {
+                    let (_, nonce) = fetch_nonce(rpc, &nonce_account).await?;
+                    nonce != *transaction.message.recent_blockhash()
+                }
+            };
+            if expired {
+                // The transaction itself advances the nonce; it may have
+                // landed between the two reads
+                match poll(rpc, &signature, config).await? {
+                    Status::Done(result) => return result,
+                    Status::Landed => continue,
+                    Status::Pending => {}
+                }
+                return Err(match expiry {
+                    Expiry::BlockHeight(_) => ClientError::BlockhashExpired { attempts: 1 },
+                    Expiry::DurableNonce(nonce_account) => {
+                        ClientError::NonceAdvanced(nonce_account)
+                    }
+                });
+            }
+
+            if last_sent.elapsed() >= config.resend_interval {
+                // Resends routinely fail with "already processed"; the status
+                // poll is the source of truth
+                let _ = rpc
+                    .send_transaction_with_config(
+                        transaction,
+                        RpcSendTransactionConfig {
+                            skip_preflight: true,
+                            ..send_config
+                        },
+                    )
+                    .await;
+                last_sent = Instant::now();
+            }
+        }
+    }
+
+    enum Status {
+        Pending,
+        Landed,
+        Done(Result<Signature>),
+    }
+
+    async fn poll(rpc: &RpcClient, signature: &Signature, config: &SendConfig) -> Result<Status> {
+        let status = rpc
+            .get_signature_statuses(&[*signature])
+            .await?
+            .value
+            .pop()
+            .flatten();
+
+        Ok(match status {
+            None => Status::Pending,
+            Some(status) => match status.err {
+                Some(error) => Status::Done(Err(ClientError::TransactionFailed {
+                    signature: *signature,
+                    error,
+                })),
+                None if status.satisfies_commitment(config.commitment) => {
+                    Status::Done(Ok(*signature))
+                }
+                None => Status::Landed,
+            },
+        })
+    }
+}
@@ -0,0 +1,106 @@
+//! Client for the session keys recipe program.
+//!
+//! Owners open and revoke sessions. Consuming programs call `use_session` by
+//! CPI, so [`use_session`] here is mostly for building the account list those
+//! programs expect; [`usable_sessions`] tells a frontend which of a wallet's
+//! sessions can still cover an action.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use session_keys::{accounts, instruction};
+
+pub use session_keys::{
+    caller_address, Session, CALLER_SEED, ID as SESSION_KEYS_PROGRAM_ID, MAX_INSTRUCTIONS,
+};
+
+pub const SESSION_SEED: &[u8] = b"session";
+
+/// Session PDA of an owner's session key
+pub fn session_address(owner: &Pubkey, session_key: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[SESSION_SEED, owner.as_ref(), session_key.as_ref()],
+        &SESSION_KEYS_PROGRAM_ID,
+    )
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: SESSION_KEYS_PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// `instructions` are the 8-byte discriminators the key may call in
+/// `program_id`, empty for any; `payer` can be a relayer
+pub fn create_session(
+    owner: &Pubkey,
+    payer: &Pubkey,
+    session_key: &Pubkey,
+    program_id: &Pubkey,
+    instructions: &[[u8; 8]],
+    max_amount: u64,
+    expires_at: i64,
+) -> Instruction {
+    build(
+        accounts::CreateSession {
+            session: session_address(owner, session_key).0,
+            owner: *owner,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreateSession {
+            session_key: *session_key,
+            program_id: *program_id,
+            instructions: instructions.to_vec(),
+            max_amount,
+            expires_at,
+        },
+    )
+}
+
+/// The CPI a consuming program makes; `caller` is its [`caller_address`]
+pub fn use_session(session: &Session, instruction: [u8; 8], amount: u64) -> Instruction {
+    build(
+        accounts::UseSession {
+            session: session_address(&session.owner, &session.session_key).0,
+            session_key: session.session_key,
+            caller: caller_address(&session.program_id),
+        },
+        instruction::UseSession {
+            instruction,
+            amount,
+        },
+    )
+}
+
+pub fn revoke_session(owner: &Pubkey, session_key: &Pubkey) -> Instruction {
+    build(
+        accounts::RevokeSession {
+            session: session_address(owner, session_key).0,
+            owner: *owner,
+        },
+        instruction::RevokeSession {},
+    )
+}
+
+/// The sessions that would let their key run `instruction` in `program_id`
+/// moving `amount` at `now`
+pub fn usable_sessions<'a>(
+    sessions: &'a [Session],
+    program_id: &Pubkey,
+    instruction: [u8; 8],
+    amount: u64,
+    now: i64,
+) -> Vec<&'a Session> {
+    let caller = caller_address(program_id);
+    sessions
+        .iter()
+        .filter(|session| {
+            Session::clone(session)
+                .authorize(&caller, instruction, amount, now)
+                .is_ok()
+        })
+        .collect()
+}
@@ -0,0 +1,111 @@
+//! Client for the spending limits recipe program.
+//!
+//! A policy is keyed by the token account it guards. Listed spenders move
+//! tokens with [`spend`], usually by CPI; [`spendable`] tells a frontend or
+//! keeper whether a spend would fit the limits before it is sent.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use spending_limits::{accounts, instruction};
+
+pub use spending_limits::{Policy, DAY, ID as SPENDING_LIMITS_PROGRAM_ID, MAX_SPENDERS, WEEK};
+
+pub const POLICY_SEED: &[u8] = b"spending_policy";
+
+/// Policy PDA guarding a token account
+pub fn policy_address(token_account: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[POLICY_SEED, token_account.as_ref()],
+        &SPENDING_LIMITS_PROGRAM_ID,
+    )
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: SPENDING_LIMITS_PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// Limits of 0 mean none; `payer` can be a relayer
+pub fn create_policy(
+    wallet: &Pubkey,
+    token_account: &Pubkey,
+    payer: &Pubkey,
+    daily_limit: u64,
+    weekly_limit: u64,
+    spenders: &[Pubkey],
+) -> Instruction {
+    build(
+        accounts::CreatePolicy {
+            policy: policy_address(token_account).0,
+            wallet: *wallet,
+            token_account: *token_account,
+            payer: *payer,
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+        },
+        instruction::CreatePolicy {
+            daily_limit,
+            weekly_limit,
+            spenders: spenders.to_vec(),
+        },
+    )
+}
+
+pub fn update_policy(
+    wallet: &Pubkey,
+    token_account: &Pubkey,
+    daily_limit: u64,
+    weekly_limit: u64,
+    spenders: &[Pubkey],
+) -> Instruction {
+    build(
+        accounts::UpdatePolicy {
+            policy: policy_address(token_account).0,
+            wallet: *wallet,
+        },
+        instruction::UpdatePolicy {
+            daily_limit,
+            weekly_limit,
+            spenders: spenders.to_vec(),
+        },
+    )
+}
+
+pub fn spend(
+    spender: &Pubkey,
+    token_account: &Pubkey,
+    destination: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    build(
+        accounts::Spend {
+            policy: policy_address(token_account).0,
+            spender: *spender,
+            token_account: *token_account,
+            destination: *destination,
+            token_program: spl_token::ID,
+        },
+        instruction::Spend { amount },
+    )
+}
+
+pub fn close_policy(wallet: &Pubkey, token_account: &Pubkey) -> Instruction {
+    build(
+        accounts::ClosePolicy {
+            policy: policy_address(token_account).0,
+            wallet: *wallet,
+            token_account: *token_account,
+            token_program: spl_token::ID,
+        },
+        instruction::ClosePolicy {},
+    )
+}
+
+/// Whether `spender` could move `amount` through the policy at `now`
+pub fn spendable(policy: &Policy, spender: &Pubkey, amount: u64, now: i64) -> bool {
+    amount > 0 && policy.spenders.contains(spender) && amount <= policy.available_at(now)
+}
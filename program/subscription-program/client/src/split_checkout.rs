@@ -0,0 +1,200 @@
+//! Client for the split checkout recipe program.
+//!
+//! Storefronts build payments with [`CheckoutBuilder`], which appends the payee
+//! accounts the program expects and can quote each party's share before the
+//! buyer signs.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use split_checkout::{accounts, instruction};
+use thiserror::Error;
+
+pub use split_checkout::{
+    Payee, SplitConfig, BPS_DENOMINATOR, ID as SPLIT_CHECKOUT_PROGRAM_ID, MAX_PAYEES,
+};
+
+use crate::pda::associated_token_address;
+
+pub const SPLIT_SEED: &[u8] = b"split";
+
+/// Split PDA of an owner; one owner can run several splits
+pub fn split_address(owner: &Pubkey, split_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[SPLIT_SEED, owner.as_ref(), split_id.to_le_bytes().as_ref()],
+        &SPLIT_CHECKOUT_PROGRAM_ID,
+    )
+}
+
+/// Payee token accounts, in order, as the program's remaining accounts
+fn payee_metas(payees: &[Payee]) -> impl Iterator<Item = AccountMeta> + '_ {
+    payees
+        .iter()
+        .map(|payee| AccountMeta::new(payee.token_account, false))
+}
+
+fn build(
+    accounts: impl ToAccountMetas,
+    payees: &[Payee],
+    data: impl InstructionData,
+) -> Instruction {
+    let mut metas = accounts.to_account_metas(None);
+    metas.extend(payee_metas(payees));
+    Instruction {
+        program_id: SPLIT_CHECKOUT_PROGRAM_ID,
+        accounts: metas,
+        data: data.data(),
+    }
+}
+
+pub fn create_split(
+    owner: &Pubkey,
+    mint: &Pubkey,
+    payer: &Pubkey,
+    split_id: u64,
+    payees: &[Payee],
+    referral_bps: u16,
+) -> Instruction {
+    build(
+        accounts::CreateSplit {
+            split: split_address(owner, split_id).0,
+            owner: *owner,
+            mint: *mint,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        payees,
+        instruction::CreateSplit {
+            split_id,
+            payees: payees.to_vec(),
+            referral_bps,
+        },
+    )
+}
+
+pub fn update_split(split: &SplitConfig, payees: &[Payee], referral_bps: u16) -> Instruction {
+    build(
+        accounts::UpdateSplit {
+            split: split_address(&split.owner, split.split_id).0,
+            owner: split.owner,
+        },
+        payees,
+        instruction::UpdateSplit {
+            payees: payees.to_vec(),
+            referral_bps,
+        },
+    )
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum CheckoutError {
+    #[error("amount is required")]
+    MissingAmount,
+    #[error("amount must be greater than zero")]
+    ZeroAmount,
+    #[error("split has no referral share, so a referrer would receive nothing")]
+    NoReferralShare,
+    #[error("buyer {0} cannot be their own referrer")]
+    SelfReferral(Pubkey),
+}
+
+/// What each party receives from one checkout
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Quote {
+    pub referral: u64,
+    /// `(payee token account, amount)`, in the split's order
+    pub payees: Vec<(Pubkey, u64)>,
+}
+
+#[derive(Clone)]
+pub struct CheckoutBuilder {
+    split: SplitConfig,
+    buyer: Pubkey,
+    amount: Option<u64>,
+    buyer_token_account: Option<Pubkey>,
+    referrer: Option<Pubkey>,
+}
+
+impl CheckoutBuilder {
+    pub fn new(split: SplitConfig, buyer: Pubkey) -> Self {
+        Self {
+            split,
+            buyer,
+            amount: None,
+            buyer_token_account: None,
+            referrer: None,
+        }
+    }
+
+    pub fn amount(mut self, amount: u64) -> Self {
+        self.amount = Some(amount);
+        self
+    }
+
+    /// Source account. Defaults to the buyer's ATA for the split's mint.
+    pub fn buyer_token_account(mut self, token_account: Pubkey) -> Self {
+        self.buyer_token_account = Some(token_account);
+        self
+    }
+
+    /// Wallet that referred the buyer; paid into its ATA
+    pub fn referrer(mut self, referrer: Pubkey) -> Self {
+        self.referrer = Some(referrer);
+        self
+    }
+
+    fn validate(&self) -> Result<u64, CheckoutError> {
+        let amount = self.amount.ok_or(CheckoutError::MissingAmount)?;
+        if amount == 0 {
+            return Err(CheckoutError::ZeroAmount);
+        }
+        if let Some(referrer) = self.referrer {
+            if self.split.referral_bps == 0 {
+                return Err(CheckoutError::NoReferralShare);
+            }
+            if referrer == self.buyer {
+                return Err(CheckoutError::SelfReferral(referrer));
+            }
+        }
+        Ok(amount)
+    }
+
+    /// The split `checkout` will make, using the program's own arithmetic
+    pub fn quote(&self) -> Result<Quote, CheckoutError> {
+        let amount = self.validate()?;
+        let (referral, shares) = self
+            .split
+            .shares(amount, self.referrer.is_some())
+            .expect("u128 share math cannot overflow u64");
+        Ok(Quote {
+            referral,
+            payees: self
+                .split
+                .payees
+                .iter()
+                .map(|payee| payee.token_account)
+                .zip(shares)
+                .collect(),
+        })
+    }
+
+    pub fn build(self) -> Result<Instruction, CheckoutError> {
+        let amount = self.validate()?;
+        let mint = self.split.mint;
+        Ok(build(
+            accounts::Checkout {
+                split: split_address(&self.split.owner, self.split.split_id).0,
+                buyer: self.buyer,
+                buyer_token_account: self
+                    .buyer_token_account
+                    .unwrap_or_else(|| associated_token_address(&self.buyer, &mint)),
+                referrer_token_account: self
+                    .referrer
+                    .map(|referrer| associated_token_address(&referrer, &mint)),
+                token_program: spl_token::ID,
+            },
+            &self.split.payees,
+            instruction::Checkout { amount },
+        ))
+    }
+}
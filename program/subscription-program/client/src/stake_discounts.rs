@@ -0,0 +1,179 @@
+//! Client for the stake-gated subscription discounts recipe program.
+//!
+//! A discounted subscription is an ordinary
+//! [`Subscription`](crate::Subscription) created through [`subscribe`].
+//! Merchants confirm the discount still holds with `Attestation::covers`
+//! before serving it.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use stake_discounts::{accounts, instruction};
+
+pub use stake_discounts::{
+    Attestation, DiscountConfig, DiscountTier, Stake, ID as STAKE_DISCOUNTS_PROGRAM_ID,
+};
+
+use crate::pda::{
+    allowlist_entry_address, associated_token_address, denylist_address,
+    subscriber_allowlist_address, subscription_address,
+};
+use crate::PROGRAM_ID;
+
+pub const DISCOUNT_SEED: &[u8] = b"discount";
+pub const STAKE_SEED: &[u8] = b"stake";
+pub const ATTESTATION_SEED: &[u8] = b"attestation";
+
+/// Discount config PDA of a merchant; a merchant offers one discount schedule
+pub fn discount_address(merchant: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[DISCOUNT_SEED, merchant.as_ref()],
+        &STAKE_DISCOUNTS_PROGRAM_ID,
+    )
+}
+
+/// Stake PDA of `user` under a discount config
+pub fn stake_address(config: &Pubkey, user: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[STAKE_SEED, config.as_ref(), user.as_ref()],
+        &STAKE_DISCOUNTS_PROGRAM_ID,
+    )
+}
+
+/// Attestation PDA of `user` under a discount config
+pub fn attestation_address(config: &Pubkey, user: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[ATTESTATION_SEED, config.as_ref(), user.as_ref()],
+        &STAKE_DISCOUNTS_PROGRAM_ID,
+    )
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: STAKE_DISCOUNTS_PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// Subscriptions pay into the merchant's ATA for `payment_mint`
+#[allow(clippy::too_many_arguments)]
+pub fn create_discount(
+    merchant: &Pubkey,
+    stake_mint: &Pubkey,
+    payment_mint: &Pubkey,
+    payer: &Pubkey,
+    list_price: u64,
+    interval_seconds: i64,
+    lock_seconds: i64,
+    tiers: Vec<DiscountTier>,
+) -> Instruction {
+    build(
+        accounts::CreateDiscount {
+            config: discount_address(merchant).0,
+            merchant: *merchant,
+            stake_mint: *stake_mint,
+            payment_mint: *payment_mint,
+            merchant_token_account: associated_token_address(merchant, payment_mint),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreateDiscount {
+            list_price,
+            interval_seconds,
+            lock_seconds,
+            tiers,
+        },
+    )
+}
+
+/// The vault, the stake PDA's ATA for the stake mint, must exist
+pub fn open_stake(config: &DiscountConfig, user: &Pubkey, payer: &Pubkey) -> Instruction {
+    let config_key = discount_address(&config.merchant).0;
+    let stake = stake_address(&config_key, user).0;
+    build(
+        accounts::OpenStake {
+            config: config_key,
+            stake,
+            attestation: attestation_address(&config_key, user).0,
+            user: *user,
+            vault: associated_token_address(&stake, &config.stake_mint),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::OpenStake {},
+    )
+}
+
+/// Locks from the user's ATA for the stake mint
+pub fn lock_tokens(config: &DiscountConfig, user: &Pubkey, amount: u64) -> Instruction {
+    let config_key = discount_address(&config.merchant).0;
+    let stake = stake_address(&config_key, user).0;
+    build(
+        accounts::LockTokens {
+            config: config_key,
+            stake,
+            user: *user,
+            user_stake_account: associated_token_address(user, &config.stake_mint),
+            vault: associated_token_address(&stake, &config.stake_mint),
+            token_program: spl_token::ID,
+        },
+        instruction::LockTokens { amount },
+    )
+}
+
+pub fn attest(config: &DiscountConfig, user: &Pubkey) -> Instruction {
+    let config_key = discount_address(&config.merchant).0;
+    build(
+        accounts::Attest {
+            config: config_key,
+            stake: stake_address(&config_key, user).0,
+            attestation: attestation_address(&config_key, user).0,
+            user: *user,
+        },
+        instruction::Attest {},
+    )
+}
+
+/// Subscribes from the user's ATA for the payment mint at the attested price
+pub fn subscribe(config: &DiscountConfig, user: &Pubkey, payer: &Pubkey) -> Instruction {
+    let config_key = discount_address(&config.merchant).0;
+    build(
+        accounts::Subscribe {
+            config: config_key,
+            attestation: attestation_address(&config_key, user).0,
+            subscription: subscription_address(user, &config.merchant).0,
+            user: *user,
+            merchant: config.merchant,
+            user_token_account: associated_token_address(user, &config.payment_mint),
+            merchant_token_account: config.merchant_token_account,
+            payment_mint: config.payment_mint,
+            token_program: spl_token::ID,
+            payer: *payer,
+            system_program: system_program::ID,
+            denylist_entry: denylist_address(&config.merchant, user).0,
+            allowlist: subscriber_allowlist_address(&config.merchant).0,
+            allowlist_entry: allowlist_entry_address(&config.merchant, user).0,
+            subscription_program: PROGRAM_ID,
+        },
+        instruction::Subscribe {},
+    )
+}
+
+/// Returns the tokens to the user's ATA for the stake mint
+pub fn unlock_tokens(config: &DiscountConfig, user: &Pubkey) -> Instruction {
+    let config_key = discount_address(&config.merchant).0;
+    let stake = stake_address(&config_key, user).0;
+    build(
+        accounts::UnlockTokens {
+            config: config_key,
+            stake,
+            attestation: attestation_address(&config_key, user).0,
+            user: *user,
+            vault: associated_token_address(&stake, &config.stake_mint),
+            user_stake_account: associated_token_address(user, &config.stake_mint),
+            token_program: spl_token::ID,
+        },
+        instruction::UnlockTokens {},
+    )
+}
@@ -0,0 +1,149 @@
+//! Client for the tipping recipe program.
+//!
+//! Recurring tips are cranked like subscription charges; [`due_recurring_tips`]
+//! is the keeper pass.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use tipping::{accounts, instruction};
+
+pub use tipping::{
+    LeaderboardEntry, RecurringTip, Supporter, TipJar, ID as TIPPING_PROGRAM_ID, LEADERBOARD_SIZE,
+};
+
+use crate::pda::associated_token_address;
+
+pub const TIP_JAR_SEED: &[u8] = b"tip_jar";
+pub const SUPPORTER_SEED: &[u8] = b"supporter";
+
+/// Tip jar PDA of a creator
+pub fn tip_jar_address(creator: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[TIP_JAR_SEED, creator.as_ref()], &TIPPING_PROGRAM_ID)
+}
+
+/// Stats PDA of a fan for one tip jar
+pub fn supporter_address(tip_jar: &Pubkey, fan: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[SUPPORTER_SEED, tip_jar.as_ref(), fan.as_ref()],
+        &TIPPING_PROGRAM_ID,
+    )
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: TIPPING_PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// Tips land in the creator's ATA for `mint`
+pub fn create_tip_jar(creator: &Pubkey, mint: &Pubkey, payer: &Pubkey) -> Instruction {
+    build(
+        accounts::CreateTipJar {
+            tip_jar: tip_jar_address(creator).0,
+            creator: *creator,
+            mint: *mint,
+            creator_token_account: associated_token_address(creator, mint),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreateTipJar {},
+    )
+}
+
+/// Needed once per fan and jar; send it in the same transaction as the first tip
+pub fn open_supporter(creator: &Pubkey, fan: &Pubkey, payer: &Pubkey) -> Instruction {
+    let tip_jar = tip_jar_address(creator).0;
+    build(
+        accounts::OpenSupporter {
+            tip_jar,
+            supporter: supporter_address(&tip_jar, fan).0,
+            fan: *fan,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::OpenSupporter {},
+    )
+}
+
+/// One-off tip from the fan's ATA
+pub fn tip(tip_jar: &TipJar, fan: &Pubkey, amount: u64) -> Instruction {
+    let tip_jar_address = tip_jar_address(&tip_jar.creator).0;
+    build(
+        accounts::Tip {
+            tip_jar: tip_jar_address,
+            supporter: supporter_address(&tip_jar_address, fan).0,
+            fan: *fan,
+            fan_token_account: associated_token_address(fan, &tip_jar.mint),
+            creator_token_account: tip_jar.creator_token_account,
+            token_program: spl_token::ID,
+        },
+        instruction::Tip { amount },
+    )
+}
+
+/// Recurring tip from the fan's ATA, which gets the supporter PDA as delegate
+pub fn set_recurring_tip(
+    tip_jar: &TipJar,
+    fan: &Pubkey,
+    amount: u64,
+    interval_seconds: i64,
+) -> Instruction {
+    let tip_jar_address = tip_jar_address(&tip_jar.creator).0;
+    build(
+        accounts::SetRecurringTip {
+            tip_jar: tip_jar_address,
+            supporter: supporter_address(&tip_jar_address, fan).0,
+            fan: *fan,
+            fan_token_account: associated_token_address(fan, &tip_jar.mint),
+            token_program: spl_token::ID,
+        },
+        instruction::SetRecurringTip {
+            amount,
+            interval_seconds,
+        },
+    )
+}
+
+pub fn cancel_recurring_tip(
+    supporter_address: &Pubkey,
+    supporter: &Supporter,
+    recurring: &RecurringTip,
+) -> Instruction {
+    build(
+        accounts::CancelRecurringTip {
+            supporter: *supporter_address,
+            fan: supporter.fan,
+            fan_token_account: recurring.fan_token_account,
+            token_program: spl_token::ID,
+        },
+        instruction::CancelRecurringTip {},
+    )
+}
+
+/// One keeper pass: a crank for every supporter whose recurring tip is due at
+/// `now`. Only the keeper signs.
+pub fn due_recurring_tips(
+    tip_jar: &TipJar,
+    supporters: &[(Pubkey, Supporter)],
+    now: i64,
+) -> Vec<Instruction> {
+    supporters
+        .iter()
+        .filter_map(|(address, supporter)| {
+            let recurring = supporter.check_recurring_due(now).ok()?;
+            Some(build(
+                accounts::CrankRecurringTip {
+                    tip_jar: supporter.tip_jar,
+                    supporter: *address,
+                    fan_token_account: recurring.fan_token_account,
+                    creator_token_account: tip_jar.creator_token_account,
+                    token_program: spl_token::ID,
+                },
+                instruction::CrankRecurringTip {},
+            ))
+        })
+        .collect()
+}
@@ -0,0 +1,241 @@
+//! Transaction assembly.
+//!
+//! A legacy transaction tops out at a handful of charge instructions because
+//! every account is spelled out in full. With address lookup tables the same
+//! packet fits far more, so batch charges and multi-instruction gasless flows
+//! compile to v0 whenever tables are supplied and fall back to legacy otherwise.
+
+use anchor_lang::prelude::{ProgramError, Pubkey};
+use solana_address_lookup_table_interface::state::AddressLookupTable;
+use solana_hash::Hash;
+use solana_instruction::Instruction;
+use solana_message::{v0, Message, VersionedMessage};
+
+pub use solana_message::AddressLookupTableAccount;
+
+use crate::{ClientError, Result};
+
+/// Maximum serialized transaction size accepted by the cluster
+pub const PACKET_DATA_SIZE: usize = 1232;
+
+const SIGNATURE_SIZE: usize = 64;
+
+/// v0 when lookup tables are given, legacy otherwise
+pub fn compile_message(
+    payer: &Pubkey,
+    instructions: &[Instruction],
+    lookup_tables: &[AddressLookupTableAccount],
+    recent_blockhash: Hash,
+) -> Result<VersionedMessage> {
+    if lookup_tables.is_empty() {
+        return Ok(VersionedMessage::Legacy(Message::new_with_blockhash(
+            instructions,
+            Some(payer),
+            &recent_blockhash,
+        )));
+    }
+
+    v0::Message::try_compile(payer, instructions, lookup_tables, recent_blockhash)
+        .map(VersionedMessage::V0)
+        .map_err(|err| ClientError::CompileMessage(err.to_string()))
+}
+
+/// Wire size of the signed transaction this message would produce
+pub fn transaction_size(message: &VersionedMessage) -> usize {
+    let signatures = message.header().num_required_signatures as usize;
+    short_vec_len(signatures) + signatures * SIGNATURE_SIZE + message.serialize().len()
+}
+
+/// Greedily split `instructions` into as few messages as fit in a packet,
+/// preserving order. Errors if a single instruction cannot fit on its own.
+pub fn pack_instructions(
+    payer: &Pubkey,
+    instructions: &[Instruction],
+    lookup_tables: &[AddressLookupTableAccount],
+    recent_blockhash: Hash,
+) -> Result<Vec<VersionedMessage>> {
+    let mut messages = Vec::new();
+    let mut start = 0;
+
+    while start < instructions.len() {
+        let mut end = start + 1;
+        let mut message = compile_message(
+            payer,
+            &instructions[start..end],
+            lookup_tables,
+            recent_blockhash,
+        )?;
+        if transaction_size(&message) > PACKET_DATA_SIZE {
+            return Err(ClientError::TransactionTooLarge {
+                size: transaction_size(&message),
+            });
+        }
+
+        while end < instructions.len() {
+            let candidate = compile_message(
+                payer,
+                &instructions[start..end + 1],
+                lookup_tables,
+                recent_blockhash,
+            )?;
+            if transaction_size(&candidate) > PACKET_DATA_SIZE {
+                break;
+            }
+            message = candidate;
+            end += 1;
+        }
+
+        messages.push(message);
+        start = end;
+    }
+
+    Ok(messages)
+}
+
+/// Decode an on-chain address lookup table account
+pub fn decode_lookup_table(address: &Pubkey, data: &[u8]) -> Result<AddressLookupTableAccount> {
+    let table = AddressLookupTable::deserialize(data).map_err(|err| ClientError::Decode {
+        address: *address,
+        source: ProgramError::try_from(err)
+            .unwrap_or(ProgramError::InvalidAccountData)
+            .into(),
+    })?;
+
+    Ok(AddressLookupTableAccount {
+        key: *address,
+        addresses: table.addresses.to_vec(),
+    })
+}
+
+fn short_vec_len(len: usize) -> usize {
+    match len {
+        0..=0x7f => 1,
+        0x80..=0x3fff => 2,
+        _ => 3,
+    }
+}
+
+#[cfg(feature = "rpc")]
+pub use self::rpc::*;
+
+#[cfg(feature = "rpc")]
+mod rpc {
+    use solana_client::nonblocking::rpc_client::RpcClient;
+
+    use super::*;
+
+    /// Fetch lookup tables by address, e.g. the keeper's charge table
+    pub async fn fetch_lookup_tables(
+        rpc: &RpcClient,
+        addresses: &[Pubkey],
+    ) -> Result<Vec<AddressLookupTableAccount>> {
+        let accounts = rpc.get_multiple_accounts(addresses).await?;
+
+        addresses
+            .iter()
+            .zip(accounts)
+            .map(|(address, account)| {
+                let account = account.ok_or(ClientError::AccountNotFound(*address))?;
+                decode_lookup_table(address, &account.data)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anchor_lang::solana_program::instruction::AccountMeta;
+    use solana_transaction::versioned::VersionedTransaction;
+
+    use super::*;
+
+    fn memo(len: usize, accounts: usize) -> Instruction {
+        Instruction::new_with_bytes(
+            Pubkey::new_unique(),
+            &vec![7; len],
+            (0..accounts)
+                .map(|_| AccountMeta::new(Pubkey::new_unique(), false))
+                .collect(),
+        )
+    }
+
+    fn instructions(messages: &[VersionedMessage]) -> usize {
+        messages
+            .iter()
+            .map(|message| message.instructions().len())
+            .sum()
+    }
+
+    #[test]
+    fn size_matches_the_signed_transaction() {
+        let payer = Pubkey::new_unique();
+        let message = compile_message(&payer, &[memo(100, 3)], &[], Hash::default()).unwrap();
+        let transaction = VersionedTransaction {
+            signatures: vec![Default::default(); message.header().num_required_signatures as usize],
+            message: message.clone(),
+        };
+
+        assert_eq!(
+            transaction_size(&message),
+            bincode::serialize(&transaction).unwrap().len()
+        );
+    }
+
+    #[test]
+    fn splits_into_packets_in_order() {
+        let payer = Pubkey::new_unique();
+        let batch: Vec<Instruction> = (0..20).map(|_| memo(200, 2)).collect();
+
+        let messages = pack_instructions(&payer, &batch, &[], Hash::default()).unwrap();
+
+        assert!(messages.len() > 1);
+        assert!(messages
+            .iter()
+            .all(|message| transaction_size(message) <= PACKET_DATA_SIZE));
+        assert_eq!(instructions(&messages), batch.len());
+        // Each message starts with the program of the next unpacked instruction
+        let mut next = 0;
+        for message in &messages {
+            let keys = message.static_account_keys();
+            for compiled in message.instructions() {
+                assert_eq!(
+                    keys[compiled.program_id_index as usize],
+                    batch[next].program_id
+                );
+                next += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn instruction_over_a_packet_is_an_error() {
+        let payer = Pubkey::new_unique();
+        let batch = [memo(10, 1), memo(PACKET_DATA_SIZE, 0)];
+
+        assert!(matches!(
+            pack_instructions(&payer, &batch, &[], Hash::default()),
+            Err(ClientError::TransactionTooLarge { size }) if size > PACKET_DATA_SIZE
+        ));
+    }
+
+    #[test]
+    fn lookup_tables_fit_more_per_packet() {
+        let payer = Pubkey::new_unique();
+        let batch: Vec<Instruction> = (0..30).map(|_| memo(8, 4)).collect();
+        let table = AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: batch
+                .iter()
+                .flat_map(|ix| ix.accounts.iter().map(|meta| meta.pubkey))
+                .take(256)
+                .collect(),
+        };
+
+        let legacy = pack_instructions(&payer, &batch, &[], Hash::default()).unwrap();
+        let v0 = pack_instructions(&payer, &batch, &[table], Hash::default()).unwrap();
+
+        assert!(matches!(v0[0], VersionedMessage::V0(_)));
+        assert!(v0.len() < legacy.len());
+        assert_eq!(instructions(&v0), batch.len());
+    }
+}
@@ -0,0 +1,117 @@
+//! Client for the vesting recipe program, including the keeper pass that
+//! claims for recipients. `claim` needs no signature from the recipient, so the
+//! keeper pays the fees and vested tokens simply arrive in the recipient's
+//! wallet.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use vesting::{accounts, instruction};
+
+pub use vesting::{Grant, ID as VESTING_PROGRAM_ID};
+
+use crate::pda::associated_token_address;
+
+pub const GRANT_SEED: &[u8] = b"grant";
+
+/// Grant PDA for a (grantor, recipient, grant_id) triple
+pub fn grant_address(grantor: &Pubkey, recipient: &Pubkey, grant_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            GRANT_SEED,
+            grantor.as_ref(),
+            recipient.as_ref(),
+            &grant_id.to_le_bytes(),
+        ],
+        &VESTING_PROGRAM_ID,
+    )
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: VESTING_PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// Fund a grant from the grantor's ATA. The vault is the grant PDA's ATA and
+/// must exist, so prepend a `CreateIdempotent` for it.
+#[allow(clippy::too_many_arguments)]
+pub fn create_grant(
+    grantor: &Pubkey,
+    recipient: &Pubkey,
+    mint: &Pubkey,
+    payer: &Pubkey,
+    grant_id: u64,
+    amount: u64,
+    start_time: Option<i64>,
+    cliff_seconds: i64,
+    duration_seconds: i64,
+    revocable: bool,
+) -> Instruction {
+    let grant = grant_address(grantor, recipient, grant_id).0;
+    build(
+        accounts::CreateGrant {
+            grant,
+            grantor: *grantor,
+            recipient: *recipient,
+            mint: *mint,
+            grantor_token_account: associated_token_address(grantor, mint),
+            vault: associated_token_address(&grant, mint),
+            recipient_token_account: associated_token_address(recipient, mint),
+            token_program: spl_token::ID,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreateGrant {
+            grant_id,
+            amount,
+            start_time,
+            cliff_seconds,
+            duration_seconds,
+            revocable,
+        },
+    )
+}
+
+/// Claim `amount`, or everything vested if `None`. Signed only by the fee payer.
+pub fn claim(grant_address: &Pubkey, grant: &Grant, amount: Option<u64>) -> Instruction {
+    build(
+        accounts::Claim {
+            grant: *grant_address,
+            vault: grant.vault,
+            recipient_token_account: grant.recipient_token_account,
+            token_program: spl_token::ID,
+        },
+        instruction::Claim { amount },
+    )
+}
+
+pub fn revoke(grant_address: &Pubkey, grant: &Grant) -> Instruction {
+    build(
+        accounts::Revoke {
+            grant: *grant_address,
+            grantor: grant.grantor,
+            grantor_token_account: grant.grantor_token_account,
+            recipient_token_account: grant.recipient_token_account,
+            vault: grant.vault,
+            token_program: spl_token::ID,
+        },
+        instruction::Revoke {},
+    )
+}
+
+/// One keeper pass: a claim for every grant with at least `min_claim`
+/// claimable at `now`, plus any grant that has fully vested so nothing is left
+/// behind. `min_claim` keeps the keeper from paying a fee per dust amount.
+pub fn due_claims(grants: &[(Pubkey, Grant)], now: i64, min_claim: u64) -> Vec<Instruction> {
+    grants
+        .iter()
+        .filter(|(_, grant)| {
+            let claimable = grant.claimable_at(now);
+            claimable > 0 && (claimable >= min_claim || now >= grant.end_time)
+        })
+        .map(|(address, grant)| claim(address, grant, None))
+        .collect()
+}
@@ -0,0 +1,114 @@
+//! Client for the vouchers recipe program.
+//!
+//! A gift link only needs the issuer and the secret: [`voucher_address`]
+//! derives the voucher from them and [`claim_voucher`] claims it.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use vouchers::{accounts, instruction};
+
+pub use vouchers::{secret_hash, Voucher, ID as VOUCHERS_PROGRAM_ID};
+
+use crate::pda::associated_token_address;
+
+pub const VOUCHER_SEED: &[u8] = b"voucher";
+
+/// Voucher PDA for an issuer's claim secret hash
+pub fn voucher_address(issuer: &Pubkey, secret_hash: &[u8; 32]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[VOUCHER_SEED, issuer.as_ref(), secret_hash.as_ref()],
+        &VOUCHERS_PROGRAM_ID,
+    )
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: VOUCHERS_PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// Funded from the issuer's ATA into the voucher PDA's ATA, which must exist
+pub fn create_voucher(
+    issuer: &Pubkey,
+    mint: &Pubkey,
+    payer: &Pubkey,
+    secret: &[u8; 32],
+    amount: u64,
+    expires_at: i64,
+) -> Instruction {
+    let secret_hash = secret_hash(secret);
+    let voucher = voucher_address(issuer, &secret_hash).0;
+    build(
+        accounts::CreateVoucher {
+            voucher,
+            issuer: *issuer,
+            mint: *mint,
+            issuer_token_account: associated_token_address(issuer, mint),
+            vault: associated_token_address(&voucher, mint),
+            payer: *payer,
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+        },
+        instruction::CreateVoucher {
+            secret_hash,
+            amount,
+            expires_at,
+        },
+    )
+}
+
+pub fn claim_voucher(issuer: &Pubkey, secret: &[u8; 32], holder: &Pubkey) -> Instruction {
+    build(
+        accounts::ClaimVoucher {
+            voucher: voucher_address(issuer, &secret_hash(secret)).0,
+            holder: *holder,
+        },
+        instruction::ClaimVoucher { secret: *secret },
+    )
+}
+
+/// Spend `amount` into `destination`, a merchant's or the holder's token account
+pub fn redeem(
+    voucher_address: &Pubkey,
+    voucher: &Voucher,
+    holder: &Pubkey,
+    destination: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    build(
+        accounts::Redeem {
+            voucher: *voucher_address,
+            holder: *holder,
+            vault: voucher.vault,
+            destination: *destination,
+            token_program: spl_token::ID,
+        },
+        instruction::Redeem { amount },
+    )
+}
+
+pub fn sweep(voucher_address: &Pubkey, voucher: &Voucher) -> Instruction {
+    build(
+        accounts::Sweep {
+            voucher: *voucher_address,
+            issuer: voucher.issuer,
+            issuer_token_account: voucher.issuer_token_account,
+            vault: voucher.vault,
+            token_program: spl_token::ID,
+        },
+        instruction::Sweep {},
+    )
+}
+
+/// One keeper pass: a sweep for every voucher that has expired or been spent
+/// by `now`
+pub fn due_sweeps(vouchers: &[(Pubkey, Voucher)], now: i64) -> Vec<Instruction> {
+    vouchers
+        .iter()
+        .filter(|(_, voucher)| voucher.check_sweepable(now).is_ok())
+        .map(|(address, voucher)| sweep(address, voucher))
+        .collect()
+}
@@ -0,0 +1,131 @@
+//! Byte-for-byte comparison with the app's TypeScript instruction builders
+//! (`app/lib/program/subscription-service.ts`).
+//!
+//! `vectors/ts-client.json` is written by `app/scripts/encoding-vectors.ts`;
+//! rerun it (`npx tsx scripts/encoding-vectors.ts` in `app/`) whenever either
+//! SDK changes how an instruction is built.
+
+use std::str::FromStr;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use serde::Deserialize;
+use subscription_client::{instructions, PROGRAM_ID};
+
+const VECTORS: &str = include_str!("vectors/ts-client.json");
+
+#[derive(Deserialize)]
+struct File {
+    program_id: String,
+    vectors: Vec<Vector>,
+}
+
+#[derive(Deserialize)]
+struct Vector {
+    name: String,
+    args: Args,
+    program_id: String,
+    accounts: Vec<Meta>,
+    data: String,
+}
+
+#[derive(Deserialize)]
+struct Args {
+    authority: String,
+    recipient: String,
+    token_mint: Option<String>,
+    amount_per_period: Option<u64>,
+    interval_seconds: Option<i64>,
+    expires_at: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct Meta {
+    pubkey: String,
+    is_signer: bool,
+    is_writable: bool,
+}
+
+fn key(value: &str) -> Pubkey {
+    Pubkey::from_str(value).unwrap()
+}
+
+fn decode_hex(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+/// The instruction the Rust client builds for the same inputs
+fn rust_instruction(vector: &Vector) -> Instruction {
+    let args = &vector.args;
+    let authority = key(&args.authority);
+    let recipient = key(&args.recipient);
+
+    match vector.name.as_str() {
+        "initialize_subscription" => instructions::initialize_subscription(
+            &authority,
+            &recipient,
+            &key(args.token_mint.as_ref().unwrap()),
+            // The app pays rent from the user's wallet
+            &authority,
+            args.amount_per_period.unwrap(),
+            args.interval_seconds.unwrap(),
+            args.expires_at,
+        ),
+        "cancel_subscription" => {
+            let mint = key(args.token_mint.as_ref().unwrap());
+            instructions::cancel_subscription(
+                &authority,
+                &recipient,
+                &subscription_client::pda::associated_token_address(&authority, &mint),
+            )
+        }
+        "cleanup_cancelled_subscription" => {
+            instructions::cleanup_cancelled_subscription(&authority, &recipient)
+        }
+        other => panic!("no Rust builder mapped for `{other}`"),
+    }
+}
+
+#[test]
+fn rust_client_matches_typescript_vectors() {
+    let file: File = serde_json::from_str(VECTORS).unwrap();
+    assert_eq!(key(&file.program_id), PROGRAM_ID);
+    assert!(!file.vectors.is_empty());
+
+    for (index, vector) in file.vectors.iter().enumerate() {
+        let context = format!("vector {index} ({})", vector.name);
+        let ix = rust_instruction(vector);
+
+        assert_eq!(ix.program_id, key(&vector.program_id), "{context}");
+        assert_eq!(ix.data, decode_hex(&vector.data), "{context}: data");
+
+        let expected: Vec<AccountMeta> = vector
+            .accounts
+            .iter()
+            .map(|meta| AccountMeta {
+                pubkey: key(&meta.pubkey),
+                is_signer: meta.is_signer,
+                is_writable: meta.is_writable,
+            })
+            .collect();
+        assert_eq!(ix.accounts, expected, "{context}: accounts");
+    }
+}
+
+#[test]
+fn every_typescript_builder_is_covered() {
+    let file: File = serde_json::from_str(VECTORS).unwrap();
+    for name in [
+        "initialize_subscription",
+        "cancel_subscription",
+        "cleanup_cancelled_subscription",
+    ] {
+        assert!(
+            file.vectors.iter().any(|vector| vector.name == name),
+            "no vector for {name}"
+        );
+    }
+}
@@ -0,0 +1,40 @@
+//! Assertions on a [`TransactionResult`], shared by the program tests. Each
+//! takes the caller's location, so a failure points at the test.
+
+use solana_instruction::error::InstructionError;
+use solana_transaction_error::TransactionError;
+
+use crate::svm::{TransactionMetadata, TransactionResult};
+
+/// Error of the instruction that failed; panics if the transaction succeeded
+/// or failed before any instruction ran
+#[track_caller]
+pub fn instruction_error(result: &TransactionResult) -> InstructionError {
+    match result {
+        Err(failed) => match &failed.err {
+            TransactionError::InstructionError(_, error) => error.clone(),
+            other => panic!("expected an instruction error, got {other:?}"),
+        },
+        Ok(meta) => panic!("expected failure, transaction succeeded: {:#?}", meta.logs),
+    }
+}
+
+/// Assert the transaction failed with the custom error `code`, e.g. a
+/// program's `ErrorCode` variant or Anchor's own
+#[track_caller]
+pub fn assert_error(result: TransactionResult, code: impl Into<u32>) {
+    assert_eq!(
+        instruction_error(&result),
+        InstructionError::Custom(code.into())
+    );
+}
+
+/// Assert the transaction succeeded, CPIs included; on failure the panic
+/// shows the error and the logs
+#[track_caller]
+pub fn assert_success(result: TransactionResult) -> TransactionMetadata {
+    match result {
+        Ok(meta) => meta,
+        Err(failed) => panic!("transaction failed: {:?}\n{:#?}", failed.err, failed.meta.logs),
+    }
+}
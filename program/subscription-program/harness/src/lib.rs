@@ -0,0 +1,43 @@
+//! In-process runtime for native Rust tests of the cookbook programs.
+//!
+//! [`TestSvm`] keeps an account store, a clock and a blockhash, and executes
+//! transactions by serializing each instruction's accounts in the loader's
+//! input format and calling the program's Anchor `entry` directly. After every
+//! instruction it applies the runtime's rules (read-only accounts untouched,
+//! only the owner debits lamports or writes data, balanced lamports), so
+//! account-substitution bugs fail here the same way they fail on-chain.
+//!
+//! CPIs run too. The harness implements the invoke syscall: it checks the
+//! caller's writes so far, derives PDA signers from the seeds, rejects
+//! privilege escalation, and runs the callee against the caller's accounts.
+//! The SPL Token program is the real `spl-token` processor and the system
+//! program covers the instructions the cookbook uses, so a delegated
+//! transfer or a PDA-signed `close_account` moves balances and lamports the
+//! way it does on-chain. [`assert_success`] checks a transaction went
+//! through, and [`assert_error`] that it failed with a program's error code.
+//!
+//! The API follows LiteSVM (`send_transaction`, `set_account`, `airdrop`,
+//! `expire_blockhash`) so tests can move to it once the program is built for
+//! SBF.
+
+mod assert;
+mod runtime;
+mod stubs;
+mod svm;
+mod system;
+mod token;
+
+pub use solana_account::Account;
+pub use solana_instruction::error::InstructionError;
+pub use solana_keypair::Keypair;
+pub use solana_signer::Signer;
+pub use solana_system_interface::error::SystemError;
+pub use solana_transaction_error::TransactionError;
+
+pub use assert::{assert_error, assert_success, instruction_error};
+pub use stubs::forward_cpi_to_syscall_stubs;
+pub use svm::{
+    FailedTransactionMetadata, ProcessInstruction, TestSvm, TransactionMetadata, TransactionResult,
+    DEFAULT_UNIX_TIMESTAMP, LAMPORTS_PER_SIGNATURE, SLOT_DURATION_MS,
+};
+pub use token::{associated_token_address, ASSOCIATED_TOKEN_PROGRAM_ID};
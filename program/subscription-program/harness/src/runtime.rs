@@ -0,0 +1,446 @@
+//! Executes one instruction: serialize the accounts in the loader's aligned
+//! input format, call the program, read the results back and check them
+//! against the runtime's rules before they reach the account store.
+//!
+//! A CPI goes through the same path one level down. The caller's writes so
+//! far are checked first, as the runtime does when it syncs accounts for an
+//! invoke; the callee runs against the caller's `AccountInfo`s, and what it
+//! writes is copied back into them. A failed CPI aborts the whole
+//! instruction with the callee's error, as it does on-chain, so a caller
+//! cannot catch it.
+
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Once;
+
+use solana_account::Account;
+use solana_instruction::error::InstructionError;
+use solana_program::account_info::AccountInfo;
+use solana_program::entrypoint::{deserialize, MAX_PERMITTED_DATA_INCREASE, NON_DUP_MARKER};
+use solana_program::instruction::Instruction;
+use solana_program::pubkey::Pubkey;
+
+use crate::stubs::{self, Context};
+use crate::svm::ProcessInstruction;
+
+/// Most programs on the stack at once: the top-level instruction and four
+/// nested invokes
+const MAX_INVOKE_STACK_HEIGHT: usize = 5;
+
+/// Panic payload that unwinds a caller whose CPI failed
+struct CpiFailed(InstructionError);
+
+static QUIET_CPI_FAILURES: Once = Once::new();
+
+/// A failed CPI unwinds through its callers and is reported as the
+/// instruction's error; keep those panics off stderr
+fn install_panic_hook() {
+    QUIET_CPI_FAILURES.call_once(|| {
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if !info.payload().is::<CpiFailed>() {
+                default_hook(info);
+            }
+        }));
+    });
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct InstructionAccount {
+    pub key: Pubkey,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+/// Where each unique account landed in the input buffer
+struct Slot {
+    key: Pubkey,
+    is_writable: bool,
+    owner: usize,
+    lamports: usize,
+    data_len: usize,
+    data: usize,
+}
+
+/// A program executing on this thread
+pub(crate) struct Frame {
+    program_id: Pubkey,
+    /// The input buffer, owned by the `execute` call that pushed the frame
+    input: *const u8,
+    input_len: usize,
+    slots: Vec<Slot>,
+    /// Each slot's state as the program found it, moved forward past what
+    /// its CPIs wrote so only the program's own writes are checked
+    pre: Vec<Account>,
+}
+
+impl Frame {
+    pub(crate) fn program_id(&self) -> Pubkey {
+        self.program_id
+    }
+
+    /// The accounts as the program has left them so far
+    fn current(&self) -> Vec<Account> {
+        let bytes = unsafe { std::slice::from_raw_parts(self.input, self.input_len) };
+        read_accounts(bytes, &self.slots, &self.pre)
+    }
+}
+
+/// Run `entry` against `store`. The store is only updated if the program
+/// succeeds and its writes are ones the runtime would allow. Invocations
+/// below the top level go to `logs`. If the program makes a CPI, the
+/// writable accounts as it left them at the first one replace
+/// `accounts_at_cpi`.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn process_instruction(
+    entry: ProcessInstruction,
+    program_id: &Pubkey,
+    accounts: &[InstructionAccount],
+    data: &[u8],
+    store: &mut HashMap<Pubkey, Account>,
+    context: Context,
+    logs: &mut Vec<String>,
+    accounts_at_cpi: &mut Vec<(Pubkey, Account)>,
+) -> Result<(), InstructionError> {
+    install_panic_hook();
+    stubs::enter(context);
+    let result = execute(entry, program_id, accounts, data, store);
+    let (nested_logs, at_cpi) = stubs::leave();
+    logs.extend(nested_logs);
+    if let Some(at_cpi) = at_cpi {
+        *accounts_at_cpi = at_cpi;
+    }
+    result
+}
+
+fn execute(
+    entry: ProcessInstruction,
+    program_id: &Pubkey,
+    accounts: &[InstructionAccount],
+    data: &[u8],
+    store: &mut HashMap<Pubkey, Account>,
+) -> Result<(), InstructionError> {
+    let unique = unique(accounts);
+    let pre: Vec<Account> = unique
+        .iter()
+        .map(|account| store.get(&account.key).cloned().unwrap_or_default())
+        .collect();
+    let pre_total: u128 = pre.iter().map(|account| account.lamports as u128).sum();
+
+    let (mut buffer, slots) = serialize(program_id, accounts, &unique, &pre, data);
+
+    stubs::with(|context| {
+        context.frames.push(Frame {
+            program_id: *program_id,
+            input: buffer.as_ptr() as *const u8,
+            input_len: buffer.len() * size_of::<u64>(),
+            slots,
+            pre,
+        })
+    });
+    let result = {
+        let input = buffer.as_mut_ptr() as *mut u8;
+        let (program_id, account_infos, data) = unsafe { deserialize(input) };
+        panic::catch_unwind(AssertUnwindSafe(|| entry(program_id, &account_infos, data)))
+    };
+    let frame = stubs::with(|context| context.frames.pop()).expect("frame pushed above");
+
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(err)) => return Err(InstructionError::from(u64::from(err))),
+        Err(payload) => {
+            if let Some(CpiFailed(err)) = payload.downcast_ref::<CpiFailed>() {
+                return Err(err.clone());
+            }
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            log(format!("Program {program_id} panicked: {message}"));
+            return Err(InstructionError::ProgramFailedToComplete);
+        }
+    }
+
+    let post = frame.current();
+    verify(program_id, &frame.slots, &frame.pre, &post)?;
+    let post_total: u128 = post.iter().map(|account| account.lamports as u128).sum();
+    if pre_total != post_total {
+        return Err(InstructionError::UnbalancedInstruction);
+    }
+
+    for (slot, account) in frame.slots.iter().zip(post) {
+        if slot.is_writable {
+            store.insert(slot.key, account);
+        }
+    }
+    Ok(())
+}
+
+/// `sol_invoke_signed` from the program on top of the stack. Returns only if
+/// the callee succeeded; otherwise unwinds the caller with its error.
+pub(crate) fn invoke(
+    instruction: &Instruction,
+    account_infos: &[AccountInfo],
+    signers_seeds: &[&[&[u8]]],
+) {
+    if let Err(err) = try_invoke(instruction, account_infos, signers_seeds) {
+        panic::panic_any(CpiFailed(err));
+    }
+}
+
+fn try_invoke(
+    instruction: &Instruction,
+    account_infos: &[AccountInfo],
+    signers_seeds: &[&[&[u8]]],
+) -> Result<(), InstructionError> {
+    let callee = instruction.program_id;
+    let (caller, height, entry, at_cpi) = stubs::with(|context| {
+        let frame = context.frames.last().expect("a program is executing");
+        let top_level = context.frames.len() == 1 && context.accounts_at_cpi.is_none();
+        let callee_running = context.frames.iter().any(|frame| frame.program_id == callee);
+        let caller = frame.program_id;
+
+        // The caller's own writes must be allowed before anything sees them
+        let current = frame.current();
+        verify(&caller, &frame.slots, &frame.pre, &current)?;
+        let at_cpi = top_level.then(|| {
+            frame
+                .slots
+                .iter()
+                .zip(&current)
+                .filter(|(slot, _)| slot.is_writable)
+                .map(|(slot, account)| (slot.key, account.clone()))
+                .collect::<Vec<_>>()
+        });
+        let frame = context.frames.last_mut().expect("a program is executing");
+        frame.pre = current;
+
+        if callee_running && callee != caller {
+            return Err(InstructionError::ReentrancyNotAllowed);
+        }
+        let entry = context
+            .programs
+            .get(&callee)
+            .copied()
+            .ok_or(InstructionError::UnsupportedProgramId)?;
+        Ok((caller, context.frames.len(), entry, at_cpi))
+    })?;
+    if height >= MAX_INVOKE_STACK_HEIGHT {
+        return Err(InstructionError::CallDepth);
+    }
+
+    let signers: Vec<Pubkey> = signers_seeds
+        .iter()
+        .map(|seeds| Pubkey::create_program_address(seeds, &caller))
+        .collect::<Result<_, _>>()
+        .map_err(|_| InstructionError::InvalidSeeds)?;
+
+    let mut accounts = Vec::with_capacity(instruction.accounts.len());
+    let mut store = HashMap::new();
+    for meta in &instruction.accounts {
+        let info = account_infos
+            .iter()
+            .find(|info| *info.key == meta.pubkey)
+            .ok_or(InstructionError::MissingAccount)?;
+        if (meta.is_writable && !info.is_writable)
+            || (meta.is_signer && !info.is_signer && !signers.contains(info.key))
+        {
+            return Err(InstructionError::PrivilegeEscalation);
+        }
+        accounts.push(InstructionAccount {
+            key: meta.pubkey,
+            is_signer: meta.is_signer,
+            is_writable: meta.is_writable,
+        });
+        store.entry(meta.pubkey).or_insert_with(|| Account {
+            lamports: info.lamports(),
+            data: info.data.borrow().to_vec(),
+            owner: *info.owner,
+            executable: info.executable,
+            rent_epoch: info.rent_epoch,
+        });
+    }
+
+    stubs::with(|context| {
+        if at_cpi.is_some() {
+            context.accounts_at_cpi = at_cpi;
+        }
+        context.return_data = None;
+    });
+    log(format!("Program {callee} invoke [{}]", height + 1));
+    if let Err(err) = execute(entry, &callee, &accounts, &instruction.data, &mut store) {
+        log(format!("Program {callee} failed: {err}"));
+        return Err(err);
+    }
+    log(format!("Program {callee} success"));
+
+    // Hand the callee's writes back to the caller, and count them as the
+    // caller's starting point rather than its own writes
+    for (key, account) in &store {
+        for info in account_infos.iter().filter(|info| info.key == key) {
+            if info.owner != &account.owner {
+                info.assign(&account.owner);
+            }
+            **info.try_borrow_mut_lamports().map_err(|_| InstructionError::AccountBorrowFailed)? =
+                account.lamports;
+            if info.data_len() != account.data.len() {
+                info.resize(account.data.len())
+                    .map_err(|_| InstructionError::InvalidRealloc)?;
+            }
+            info.try_borrow_mut_data()
+                .map_err(|_| InstructionError::AccountBorrowFailed)?
+                .copy_from_slice(&account.data);
+        }
+    }
+    stubs::with(|context| {
+        let frame = context.frames.last_mut().expect("a program is executing");
+        for (slot, pre) in frame.slots.iter().zip(frame.pre.iter_mut()) {
+            if let Some(account) = store.get(&slot.key) {
+                *pre = Account {
+                    executable: pre.executable,
+                    rent_epoch: pre.rent_epoch,
+                    ..account.clone()
+                };
+            }
+        }
+    });
+    Ok(())
+}
+
+fn log(message: String) {
+    stubs::with(|context| context.logs.push(message));
+}
+
+/// The accounts as the program left them in the input buffer
+fn read_accounts(bytes: &[u8], slots: &[Slot], pre: &[Account]) -> Vec<Account> {
+    slots
+        .iter()
+        .zip(pre)
+        .map(|(slot, pre)| {
+            let data_len = read_u64(bytes, slot.data_len) as usize;
+            Account {
+                lamports: read_u64(bytes, slot.lamports),
+                data: bytes[slot.data..slot.data + data_len].to_vec(),
+                owner: Pubkey::try_from(&bytes[slot.owner..slot.owner + 32]).unwrap(),
+                executable: pre.executable,
+                rent_epoch: pre.rent_epoch,
+            }
+        })
+        .collect()
+}
+
+/// The rules the runtime enforces on each account a program writes
+fn verify(
+    program_id: &Pubkey,
+    slots: &[Slot],
+    pre: &[Account],
+    post: &[Account],
+) -> Result<(), InstructionError> {
+    for ((slot, pre), post) in slots.iter().zip(pre).zip(post) {
+        let owned = pre.owner == *program_id;
+        if pre.owner != post.owner && (!slot.is_writable || !owned) {
+            return Err(InstructionError::ModifiedProgramId);
+        }
+        if pre.lamports != post.lamports && !slot.is_writable {
+            return Err(InstructionError::ReadonlyLamportChange);
+        }
+        if post.lamports < pre.lamports && !owned {
+            return Err(InstructionError::ExternalAccountLamportSpend);
+        }
+        if pre.data != post.data {
+            if !slot.is_writable {
+                return Err(InstructionError::ReadonlyDataModified);
+            }
+            if !owned {
+                return Err(InstructionError::ExternalAccountDataModified);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// First occurrence of every account, in instruction order
+fn unique(accounts: &[InstructionAccount]) -> Vec<InstructionAccount> {
+    let mut unique: Vec<InstructionAccount> = Vec::with_capacity(accounts.len());
+    for account in accounts {
+        if !unique.iter().any(|seen| seen.key == account.key) {
+            unique.push(account.clone());
+        }
+    }
+    unique
+}
+
+fn serialize(
+    program_id: &Pubkey,
+    accounts: &[InstructionAccount],
+    unique: &[InstructionAccount],
+    pre: &[Account],
+    data: &[u8],
+) -> (Vec<u64>, Vec<Slot>) {
+    let mut bytes = Vec::new();
+    let mut slots = Vec::with_capacity(pre.len());
+    let mut positions: Vec<Pubkey> = Vec::with_capacity(accounts.len());
+
+    bytes.extend_from_slice(&(accounts.len() as u64).to_le_bytes());
+    for account in accounts {
+        if let Some(index) = positions.iter().position(|key| *key == account.key) {
+            bytes.push(index as u8);
+            bytes.extend_from_slice(&[0; 7]);
+            positions.push(account.key);
+            continue;
+        }
+        positions.push(account.key);
+
+        let state = unique
+            .iter()
+            .position(|unique| unique.key == account.key)
+            .map(|index| &pre[index])
+            .expect("every account has a pre-state");
+
+        bytes.push(NON_DUP_MARKER);
+        bytes.push(account.is_signer as u8);
+        bytes.push(account.is_writable as u8);
+        bytes.push(state.executable as u8);
+        bytes.extend_from_slice(&[0; 4]);
+        bytes.extend_from_slice(account.key.as_ref());
+        let owner = bytes.len();
+        bytes.extend_from_slice(state.owner.as_ref());
+        let lamports = bytes.len();
+        bytes.extend_from_slice(&state.lamports.to_le_bytes());
+        let data_len = bytes.len();
+        bytes.extend_from_slice(&(state.data.len() as u64).to_le_bytes());
+        let data = bytes.len();
+        bytes.extend_from_slice(&state.data);
+        bytes.resize(bytes.len() + MAX_PERMITTED_DATA_INCREASE, 0);
+        bytes.resize(bytes.len().next_multiple_of(size_of::<u64>()), 0);
+        bytes.extend_from_slice(&state.rent_epoch.to_le_bytes());
+
+        slots.push(Slot {
+            key: account.key,
+            is_writable: account.is_writable,
+            owner,
+            lamports,
+            data_len,
+            data,
+        });
+    }
+
+    bytes.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(data);
+    bytes.extend_from_slice(program_id.as_ref());
+
+    // Back the buffer with u64s so the aligned reads in `deserialize` are valid
+    let mut buffer = vec![0u64; bytes.len().div_ceil(size_of::<u64>())];
+    as_bytes_mut(&mut buffer)[..bytes.len()].copy_from_slice(&bytes);
+    (buffer, slots)
+}
+
+fn as_bytes_mut(buffer: &mut [u64]) -> &mut [u8] {
+    unsafe { std::slice::from_raw_parts_mut(buffer.as_mut_ptr() as *mut u8, buffer.len() * 8) }
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
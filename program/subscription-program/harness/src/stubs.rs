@@ -0,0 +1,124 @@
+//! Syscall stubs. They are installed process-wide while tests run on many
+//! threads, so the stub object is stateless and reads the context of whichever
+//! [`crate::TestSvm`] is executing on the current thread.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Once;
+
+use solana_account::Account;
+use solana_program::account_info::AccountInfo;
+use solana_program::clock::Clock;
+use solana_program::entrypoint::{ProgramResult, SUCCESS};
+use solana_program::instruction::Instruction;
+use solana_program::program_stubs::{self, set_syscall_stubs, SyscallStubs};
+use solana_program::pubkey::Pubkey;
+use solana_program::rent::Rent;
+
+use crate::runtime::{self, Frame};
+use crate::svm::ProcessInstruction;
+
+#[derive(Default)]
+pub(crate) struct Context {
+    pub programs: HashMap<Pubkey, ProcessInstruction>,
+    pub clock: Clock,
+    pub rent: Rent,
+    pub return_data: Option<(Pubkey, Vec<u8>)>,
+    /// The executing program and every caller above it
+    pub frames: Vec<Frame>,
+    /// Invocations below the top level, in the order they happened
+    pub logs: Vec<String>,
+    /// Writable accounts of the top-level instruction at its first CPI
+    pub accounts_at_cpi: Option<Vec<(Pubkey, Account)>>,
+}
+
+thread_local! {
+    static CONTEXT: RefCell<Context> = RefCell::new(Context::default());
+}
+
+static INSTALL: Once = Once::new();
+
+/// Make `context` visible to the program about to run on this thread
+pub(crate) fn enter(context: Context) {
+    INSTALL.call_once(|| {
+        set_syscall_stubs(Box::new(HarnessStubs));
+        forward_cpi_to_syscall_stubs();
+    });
+    CONTEXT.with(|current| *current.borrow_mut() = context);
+}
+
+/// Send the invokes and return data Anchor makes through `solana-cpi` to
+/// whichever syscall stubs are installed. `solana-cpi` cannot see them
+/// itself, so a runtime other than [`crate::TestSvm`], such as
+/// `solana-program-test` running a program natively, needs this too.
+pub fn forward_cpi_to_syscall_stubs() {
+    let _ = solana_cpi::stubs::set_stubs(solana_cpi::stubs::Stubs {
+        invoke_signed: program_stubs::sol_invoke_signed,
+        set_return_data: program_stubs::sol_set_return_data,
+        get_return_data: program_stubs::sol_get_return_data,
+    });
+}
+
+/// Take what the instruction left behind: its nested logs and the
+/// accounts it had written when it first made a CPI
+pub(crate) fn leave() -> (Vec<String>, Option<Vec<(Pubkey, Account)>>) {
+    with(|context| {
+        (
+            std::mem::take(&mut context.logs),
+            context.accounts_at_cpi.take(),
+        )
+    })
+}
+
+pub(crate) fn take_return_data() -> Option<(Pubkey, Vec<u8>)> {
+    with(|context| context.return_data.take())
+}
+
+/// Run `f` on this thread's context. Must not be nested, so nothing that
+/// runs a program may be called from `f`.
+pub(crate) fn with<T>(f: impl FnOnce(&mut Context) -> T) -> T {
+    CONTEXT.with(|current| f(&mut current.borrow_mut()))
+}
+
+struct HarnessStubs;
+
+impl SyscallStubs for HarnessStubs {
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        runtime::invoke(instruction, account_infos, signers_seeds);
+        Ok(())
+    }
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        let clock = with(|context| context.clock.clone());
+        unsafe { std::ptr::write_unaligned(var_addr as *mut Clock, clock) };
+        SUCCESS
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        let rent = with(|context| context.rent.clone());
+        unsafe { std::ptr::write_unaligned(var_addr as *mut Rent, rent) };
+        SUCCESS
+    }
+
+    fn sol_get_stack_height(&self) -> u64 {
+        with(|context| context.frames.len() as u64)
+    }
+
+    fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+        with(|context| context.return_data.clone())
+    }
+
+    fn sol_set_return_data(&self, data: &[u8]) {
+        with(|context| {
+            let program_id = context.frames.last().map(Frame::program_id);
+            context.return_data = program_id
+                .filter(|_| !data.is_empty())
+                .map(|program_id| (program_id, data.to_vec()));
+        });
+    }
+}
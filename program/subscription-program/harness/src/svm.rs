@@ -0,0 +1,429 @@
+use std::collections::{HashMap, HashSet};
+
+use anchor_lang::{AccountDeserialize, AccountSerialize, Owner};
+use solana_account::Account;
+use solana_hash::Hash;
+use solana_instruction::Instruction;
+use solana_keypair::Keypair;
+use solana_program::account_info::AccountInfo;
+use solana_program::clock::Clock;
+use solana_program::entrypoint::ProgramResult;
+use solana_program::pubkey::Pubkey;
+use solana_program::rent::Rent;
+use solana_sdk_ids::{bpf_loader_upgradeable, system_program};
+use solana_signature::Signature;
+use solana_signer::Signer;
+use solana_transaction::versioned::VersionedTransaction;
+use solana_transaction::Transaction;
+use solana_transaction_error::TransactionError;
+
+use crate::runtime::{self, InstructionAccount};
+use crate::stubs::{self, Context};
+
+/// A program's native entrypoint, e.g. the Anchor-generated `my_program::entry`
+pub type ProcessInstruction = for<'a> fn(&Pubkey, &'a [AccountInfo<'a>], &[u8]) -> ProgramResult;
+
+pub const LAMPORTS_PER_SIGNATURE: u64 = 5_000;
+
+/// Clock time a fresh [`TestSvm`] starts at (2025-01-01T00:00:00Z)
+pub const DEFAULT_UNIX_TIMESTAMP: i64 = 1_735_689_600;
+
+/// Slot time the warp helpers assume when moving one of slot/timestamp
+pub const SLOT_DURATION_MS: i64 = 400;
+
+const SLOTS_PER_EPOCH: u64 = 432_000;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TransactionMetadata {
+    pub signature: Signature,
+    pub logs: Vec<String>,
+    pub fee: u64,
+    pub return_data: Option<(Pubkey, Vec<u8>)>,
+    /// Writable accounts of the last instruction that made a CPI, as they
+    /// stood when it made its first one; empty if none did. Shows what a
+    /// program committed before handing control to another program.
+    pub accounts_at_cpi: Vec<(Pubkey, Account)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailedTransactionMetadata {
+    pub err: TransactionError,
+    pub meta: TransactionMetadata,
+}
+
+/// Failures are boxed; they carry the logs and would otherwise dwarf `Ok`
+pub type TransactionResult = Result<TransactionMetadata, Box<FailedTransactionMetadata>>;
+
+pub struct TestSvm {
+    accounts: HashMap<Pubkey, Account>,
+    programs: HashMap<Pubkey, ProcessInstruction>,
+    clock: Clock,
+    rent: Rent,
+    blockhash: Hash,
+    processed: HashSet<Signature>,
+}
+
+impl Default for TestSvm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TestSvm {
+    /// Empty ledger with the System and SPL Token programs loaded
+    pub fn new() -> Self {
+        let mut svm = Self {
+            accounts: HashMap::new(),
+            programs: HashMap::new(),
+            clock: Clock {
+                slot: 1,
+                unix_timestamp: DEFAULT_UNIX_TIMESTAMP,
+                epoch_start_timestamp: DEFAULT_UNIX_TIMESTAMP,
+                ..Clock::default()
+            },
+            rent: Rent::default(),
+            blockhash: Hash::new_unique(),
+            processed: HashSet::new(),
+        };
+        svm.add_program(system_program::ID, crate::system::process_instruction);
+        svm.add_program(spl_token::ID, spl_token::processor::Processor::process);
+        svm
+    }
+
+    /// Load a program by its native entrypoint. Unlike LiteSVM's `add_program`
+    /// this takes the function rather than an SBF binary.
+    pub fn add_program(&mut self, program_id: Pubkey, entry: ProcessInstruction) {
+        self.programs.insert(program_id, entry);
+        self.accounts.insert(
+            program_id,
+            Account {
+                lamports: 1,
+                data: vec![],
+                owner: bpf_loader_upgradeable::ID,
+                executable: true,
+                rent_epoch: 0,
+            },
+        );
+    }
+
+    /// Give a loaded program a ProgramData account with `authority` as its
+    /// upgrade authority (`None` for a frozen program), for instructions that
+    /// check who deployed them. Returns the ProgramData address.
+    pub fn set_upgrade_authority(
+        &mut self,
+        program_id: Pubkey,
+        authority: Option<Pubkey>,
+    ) -> Pubkey {
+        let (program_data, _) =
+            Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::ID);
+
+        // UpgradeableLoaderState, bincode-encoded: a u32 variant tag, then
+        // Program { programdata_address } or ProgramData { slot, authority }
+        let mut program = 2u32.to_le_bytes().to_vec();
+        program.extend_from_slice(program_data.as_ref());
+        let account = self
+            .accounts
+            .get_mut(&program_id)
+            .expect("program is loaded");
+        account.data = program;
+
+        let mut data = 3u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&0u64.to_le_bytes());
+        match authority {
+            Some(authority) => {
+                data.push(1);
+                data.extend_from_slice(authority.as_ref());
+            }
+            None => data.push(0),
+        }
+        self.set_account(
+            program_data,
+            Account {
+                lamports: self.minimum_balance_for_rent_exemption(data.len()),
+                data,
+                owner: bpf_loader_upgradeable::ID,
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+        program_data
+    }
+
+    /// Ledger with `entry` loaded at `program_id` and a fee payer holding
+    /// 10 SOL, where most program tests start
+    pub fn for_program(program_id: Pubkey, entry: ProcessInstruction) -> (Self, Keypair) {
+        let mut svm = Self::new();
+        svm.add_program(program_id, entry);
+        let payer = Keypair::new();
+        svm.airdrop(&payer.pubkey(), 10_000_000_000);
+        (svm, payer)
+    }
+
+    pub fn get_account(&self, address: &Pubkey) -> Option<Account> {
+        self.accounts.get(address).cloned()
+    }
+
+    pub fn set_account(&mut self, address: Pubkey, account: Account) {
+        self.accounts.insert(address, account);
+    }
+
+    pub fn remove_account(&mut self, address: &Pubkey) -> Option<Account> {
+        self.accounts.remove(address)
+    }
+
+    /// Every stored account, program placeholders included, e.g. to export a snapshot
+    pub fn accounts(&self) -> impl Iterator<Item = (&Pubkey, &Account)> {
+        self.accounts.iter()
+    }
+
+    pub fn get_balance(&self, address: &Pubkey) -> u64 {
+        self.accounts
+            .get(address)
+            .map_or(0, |account| account.lamports)
+    }
+
+    /// Credit lamports, creating a system account if needed
+    pub fn airdrop(&mut self, address: &Pubkey, lamports: u64) {
+        self.accounts.entry(*address).or_default().lamports += lamports;
+    }
+
+    /// Store an Anchor account (discriminator included) owned by its program
+    pub fn set_anchor_account<T: AccountSerialize + Owner>(
+        &mut self,
+        address: Pubkey,
+        value: &T,
+        space: usize,
+    ) {
+        let mut data = Vec::with_capacity(space);
+        value.try_serialize(&mut data).expect("account serializes");
+        assert!(data.len() <= space, "account needs {} bytes", data.len());
+        data.resize(space, 0);
+
+        self.set_account(
+            address,
+            Account {
+                lamports: self.minimum_balance_for_rent_exemption(space),
+                data,
+                owner: T::owner(),
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+    }
+
+    pub fn get_anchor_account<T: AccountDeserialize>(&self, address: &Pubkey) -> Option<T> {
+        let account = self.accounts.get(address)?;
+        T::try_deserialize(&mut account.data.as_slice()).ok()
+    }
+
+    pub fn minimum_balance_for_rent_exemption(&self, data_len: usize) -> u64 {
+        self.rent.minimum_balance(data_len)
+    }
+
+    pub fn latest_blockhash(&self) -> Hash {
+        self.blockhash
+    }
+
+    /// Replace the blockhash; transactions signed against the old one fail
+    /// with `BlockhashNotFound`
+    pub fn expire_blockhash(&mut self) {
+        self.blockhash = Hash::new_unique();
+    }
+
+    pub fn clock(&self) -> Clock {
+        self.clock.clone()
+    }
+
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = clock;
+    }
+
+    /// Move the clock to `unix_timestamp`, advancing the slot to match.
+    /// Moving backwards only changes the timestamp, as a skewed validator clock would.
+    pub fn warp_to_timestamp(&mut self, unix_timestamp: i64) {
+        let elapsed = unix_timestamp - self.clock.unix_timestamp;
+        if elapsed > 0 {
+            let slots = (elapsed * 1_000 / SLOT_DURATION_MS) as u64;
+            self.set_slot(self.clock.slot + slots);
+        }
+        self.clock.unix_timestamp = unix_timestamp;
+    }
+
+    /// Move the clock to `slot`, advancing the timestamp to match
+    pub fn warp_to_slot(&mut self, slot: u64) {
+        if slot > self.clock.slot {
+            let elapsed_ms = (slot - self.clock.slot) as i64 * SLOT_DURATION_MS;
+            self.clock.unix_timestamp += elapsed_ms / 1_000;
+        }
+        self.set_slot(slot);
+    }
+
+    /// Shorthand for `warp_to_timestamp(now + seconds)`
+    pub fn advance_time(&mut self, seconds: i64) {
+        self.warp_to_timestamp(self.clock.unix_timestamp + seconds);
+    }
+
+    /// A new slot brings a new blockhash, so repeating an identical
+    /// transaction after a warp is not rejected as `AlreadyProcessed`
+    fn set_slot(&mut self, slot: u64) {
+        if slot != self.clock.slot {
+            self.expire_blockhash();
+        }
+        self.clock.slot = slot;
+        self.clock.epoch = slot / SLOTS_PER_EPOCH;
+        self.clock.leader_schedule_epoch = self.clock.epoch + 1;
+    }
+
+    pub fn rent(&self) -> Rent {
+        self.rent.clone()
+    }
+
+    /// Sign `instructions` with `payer` plus `signers` against the latest
+    /// blockhash and send them as one legacy transaction
+    pub fn send_instructions(
+        &mut self,
+        instructions: &[Instruction],
+        payer: &Keypair,
+        signers: &[&Keypair],
+    ) -> TransactionResult {
+        let mut keypairs = vec![payer];
+        for signer in signers {
+            if !keypairs.iter().any(|k| k.pubkey() == signer.pubkey()) {
+                keypairs.push(signer);
+            }
+        }
+
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&payer.pubkey()),
+            &keypairs,
+            self.blockhash,
+        );
+        self.send_transaction(transaction)
+    }
+
+    /// Execute a transaction atomically. The fee is charged and the signature
+    /// recorded even if an instruction fails, as on a real cluster.
+    pub fn send_transaction(
+        &mut self,
+        transaction: impl Into<VersionedTransaction>,
+    ) -> TransactionResult {
+        let transaction = transaction.into();
+        let message = &transaction.message;
+        let mut meta = TransactionMetadata {
+            signature: transaction.signatures.first().copied().unwrap_or_default(),
+            ..TransactionMetadata::default()
+        };
+        let fail = |err, meta| Err(Box::new(FailedTransactionMetadata { err, meta }));
+
+        if message
+            .address_table_lookups()
+            .is_some_and(|lookups| !lookups.is_empty())
+        {
+            return fail(TransactionError::UnsupportedVersion, meta);
+        }
+        let keys = message.static_account_keys();
+        if transaction.signatures.len() != message.header().num_required_signatures as usize
+            || keys.is_empty()
+        {
+            return fail(TransactionError::SanitizeFailure, meta);
+        }
+        if *message.recent_blockhash() != self.blockhash {
+            return fail(TransactionError::BlockhashNotFound, meta);
+        }
+        if self.processed.contains(&meta.signature) {
+            return fail(TransactionError::AlreadyProcessed, meta);
+        }
+        let message_bytes = message.serialize();
+        if !transaction
+            .signatures
+            .iter()
+            .zip(keys)
+            .all(|(signature, key)| signature.verify(key.as_ref(), &message_bytes))
+        {
+            return fail(TransactionError::SignatureFailure, meta);
+        }
+
+        let fee = LAMPORTS_PER_SIGNATURE * transaction.signatures.len() as u64;
+        match self.accounts.get_mut(&keys[0]) {
+            None => return fail(TransactionError::AccountNotFound, meta),
+            Some(payer) if payer.lamports < fee => {
+                return fail(TransactionError::InsufficientFundsForFee, meta)
+            }
+            Some(payer) => payer.lamports -= fee,
+        }
+        meta.fee = fee;
+        self.processed.insert(meta.signature);
+
+        let mut working = self.accounts.clone();
+        for (index, instruction) in message.instructions().iter().enumerate() {
+            let program_id = keys[instruction.program_id_index as usize];
+            let Some(entry) = self.programs.get(&program_id).copied() else {
+                return fail(TransactionError::ProgramAccountNotFound, meta);
+            };
+            let accounts: Vec<InstructionAccount> = instruction
+                .accounts
+                .iter()
+                .map(|&index| InstructionAccount {
+                    key: keys[index as usize],
+                    is_signer: message.is_signer(index as usize),
+                    is_writable: message.is_maybe_writable(index as usize, None),
+                })
+                .collect();
+
+            meta.logs.push(format!("Program {program_id} invoke [1]"));
+            let context = Context {
+                programs: self.programs.clone(),
+                clock: self.clock.clone(),
+                rent: self.rent.clone(),
+                ..Context::default()
+            };
+            let result = runtime::process_instruction(
+                entry,
+                &program_id,
+                &accounts,
+                &instruction.data,
+                &mut working,
+                context,
+                &mut meta.logs,
+                &mut meta.accounts_at_cpi,
+            );
+            meta.return_data = stubs::take_return_data();
+
+            if let Err(err) = result {
+                meta.logs
+                    .push(format!("Program {program_id} failed: {err}"));
+                return fail(TransactionError::InstructionError(index as u8, err), meta);
+            }
+            meta.logs.push(format!("Program {program_id} success"));
+        }
+
+        // Accounts drained to zero are garbage collected; anything left must be
+        // rent exempt
+        for (index, key) in keys.iter().enumerate() {
+            if !message.is_maybe_writable(index, None) {
+                continue;
+            }
+            match working.get(key) {
+                Some(account) if account.lamports == 0 => {
+                    working.remove(key);
+                }
+                Some(account)
+                    if !account.executable
+                        && account.lamports < self.rent.minimum_balance(account.data.len()) =>
+                {
+                    return fail(
+                        TransactionError::InsufficientFundsForRent {
+                            account_index: index as u8,
+                        },
+                        meta,
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        self.accounts = working;
+        Ok(meta)
+    }
+}
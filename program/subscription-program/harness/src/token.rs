@@ -0,0 +1,172 @@
+//! SPL Token fixtures. State is written directly (the way a test validator
+//! loads accounts from a snapshot), so setup does not need a mint authority
+//! keypair or a transaction per account.
+
+use solana_account::Account;
+use solana_program::program_option::COption;
+use solana_program::program_pack::Pack;
+use solana_program::pubkey;
+use solana_program::pubkey::Pubkey;
+use spl_token::state::{Account as TokenAccount, AccountState, Mint};
+
+use crate::TestSvm;
+
+pub const ASSOCIATED_TOKEN_PROGRAM_ID: Pubkey =
+    pubkey!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+
+/// Associated token account of `owner` for `mint`
+pub fn associated_token_address(owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[owner.as_ref(), spl_token::ID.as_ref(), mint.as_ref()],
+        &ASSOCIATED_TOKEN_PROGRAM_ID,
+    )
+    .0
+}
+
+impl TestSvm {
+    /// Initialized mint at a fresh address
+    pub fn create_mint(&mut self, mint_authority: &Pubkey, decimals: u8) -> Pubkey {
+        let address = Pubkey::new_unique();
+        self.create_mint_at(address, mint_authority, decimals);
+        address
+    }
+
+    pub fn create_mint_at(&mut self, address: Pubkey, mint_authority: &Pubkey, decimals: u8) {
+        let mint = Mint {
+            mint_authority: COption::Some(*mint_authority),
+            supply: 0,
+            decimals,
+            is_initialized: true,
+            freeze_authority: COption::Some(*mint_authority),
+        };
+        self.set_packed(address, &mint);
+    }
+
+    /// Token account at a fresh (non-ATA) address holding `amount`
+    pub fn create_token_account(&mut self, owner: &Pubkey, mint: &Pubkey, amount: u64) -> Pubkey {
+        let address = Pubkey::new_unique();
+        self.create_token_account_at(address, owner, mint, amount);
+        address
+    }
+
+    /// The owner's ATA for `mint`, holding `amount`
+    pub fn create_associated_token_account(
+        &mut self,
+        owner: &Pubkey,
+        mint: &Pubkey,
+        amount: u64,
+    ) -> Pubkey {
+        let address = associated_token_address(owner, mint);
+        self.create_token_account_at(address, owner, mint, amount);
+        address
+    }
+
+    pub fn create_token_account_at(
+        &mut self,
+        address: Pubkey,
+        owner: &Pubkey,
+        mint: &Pubkey,
+        amount: u64,
+    ) {
+        let account = TokenAccount {
+            mint: *mint,
+            owner: *owner,
+            amount,
+            delegate: COption::None,
+            state: AccountState::Initialized,
+            is_native: COption::None,
+            delegated_amount: 0,
+            close_authority: COption::None,
+        };
+        self.set_packed(address, &account);
+        self.update_mint(mint, |mint| mint.supply += amount);
+    }
+
+    /// Mint `amount` more into `token_account`
+    pub fn mint_tokens(&mut self, token_account: &Pubkey, amount: u64) {
+        let mint = self.update_token_account(token_account, |account| account.amount += amount);
+        self.update_mint(&mint, |mint| mint.supply += amount);
+    }
+
+    /// Move tokens between two accounts of the same mint
+    pub fn transfer_tokens(&mut self, from: &Pubkey, to: &Pubkey, amount: u64) {
+        self.update_token_account(from, |account| {
+            account.amount = account
+                .amount
+                .checked_sub(amount)
+                .expect("insufficient token balance")
+        });
+        self.update_token_account(to, |account| account.amount += amount);
+    }
+
+    /// Set the delegate as `Approve` would
+    pub fn approve(&mut self, token_account: &Pubkey, delegate: &Pubkey, amount: u64) {
+        self.update_token_account(token_account, |account| {
+            account.delegate = COption::Some(*delegate);
+            account.delegated_amount = amount;
+        });
+    }
+
+    pub fn revoke(&mut self, token_account: &Pubkey) {
+        self.update_token_account(token_account, |account| {
+            account.delegate = COption::None;
+            account.delegated_amount = 0;
+        });
+    }
+
+    pub fn freeze(&mut self, token_account: &Pubkey) {
+        self.update_token_account(token_account, |account| {
+            account.state = AccountState::Frozen;
+        });
+    }
+
+    pub fn get_token_account(&self, address: &Pubkey) -> Option<TokenAccount> {
+        TokenAccount::unpack(&self.get_account(address)?.data).ok()
+    }
+
+    pub fn get_mint(&self, address: &Pubkey) -> Option<Mint> {
+        Mint::unpack(&self.get_account(address)?.data).ok()
+    }
+
+    /// Token balance, zero if the account does not exist
+    pub fn token_balance(&self, address: &Pubkey) -> u64 {
+        self.get_token_account(address)
+            .map_or(0, |account| account.amount)
+    }
+
+    fn set_packed<T: Pack>(&mut self, address: Pubkey, state: &T) {
+        let mut data = vec![0; T::LEN];
+        T::pack_into_slice(state, &mut data);
+        self.set_account(
+            address,
+            Account {
+                lamports: self.minimum_balance_for_rent_exemption(T::LEN),
+                data,
+                owner: spl_token::ID,
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+    }
+
+    /// Returns the account's mint
+    fn update_token_account(
+        &mut self,
+        address: &Pubkey,
+        update: impl FnOnce(&mut TokenAccount),
+    ) -> Pubkey {
+        let mut account = self
+            .get_token_account(address)
+            .unwrap_or_else(|| panic!("{address} is not a token account"));
+        update(&mut account);
+        self.set_packed(*address, &account);
+        account.mint
+    }
+
+    fn update_mint(&mut self, address: &Pubkey, update: impl FnOnce(&mut Mint)) {
+        if let Some(mut mint) = self.get_mint(address) {
+            update(&mut mint);
+            self.set_packed(*address, &mint);
+        }
+    }
+}
@@ -0,0 +1,777 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use spl_token::instruction as token_instruction;
+use token_utils::token_account;
+
+declare_id!("8NR9UBL8B3XLiuoaBHJEGq8CdqDLUB9XESto6fVLCwsM");
+
+/// Categories per allowance; session keys select them with a `u8` bitmask
+pub const MAX_CATEGORIES: usize = 8;
+
+#[program]
+pub mod allowance {
+    use super::*;
+
+    /// Give `child` an allowance of `amount_per_period` every
+    /// `interval_seconds`, split into spending categories with a cap each.
+    /// As with a subscription, the allowance PDA becomes the delegate of
+    /// `funder_token_account`; the first top-up is due at once.
+    pub fn create_allowance(
+        ctx: Context<CreateAllowance>,
+        amount_per_period: u64,
+        interval_seconds: i64,
+        caps: Vec<u64>,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        Allowance::check_params(amount_per_period, interval_seconds, &caps)?;
+
+        let funder = ctx.accounts.funder.key();
+        let mint = ctx.accounts.mint.key();
+        let funder_account = token_account(
+            &ctx.accounts.funder_token_account,
+            ErrorCode::InvalidTokenAccount,
+        )?;
+        require_keys_eq!(funder_account.owner, funder, ErrorCode::InvalidTokenAccount);
+        require_keys_eq!(funder_account.mint, mint, ErrorCode::InvalidTokenAccount);
+        let vault = token_account(&ctx.accounts.vault, ErrorCode::InvalidTokenAccount)?;
+        require_keys_eq!(
+            vault.owner,
+            ctx.accounts.allowance.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+        require_keys_eq!(vault.mint, mint, ErrorCode::InvalidTokenAccount);
+
+        let delegate_ix = token_instruction::approve(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.funder_token_account.key(),
+            &ctx.accounts.allowance.key(),
+            &funder,
+            &[],
+            u64::MAX,
+        )?;
+
+        invoke(
+            &delegate_ix,
+            &[
+                ctx.accounts.funder_token_account.to_account_info(),
+                ctx.accounts.allowance.to_account_info(),
+                ctx.accounts.funder.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        let allowance = &mut ctx.accounts.allowance;
+        allowance.funder = funder;
+        allowance.child = ctx.accounts.child.key();
+        allowance.mint = mint;
+        allowance.funder_token_account = ctx.accounts.funder_token_account.key();
+        allowance.vault = ctx.accounts.vault.key();
+        allowance.amount_per_period = amount_per_period;
+        allowance.interval_seconds = interval_seconds;
+        allowance.categories = caps
+            .iter()
+            .map(|&cap| CategoryCap { cap, spent: 0 })
+            .collect();
+        allowance.period_started_at = clock.unix_timestamp;
+        allowance.next_top_up_at = clock.unix_timestamp;
+        allowance.total_funded = 0;
+        allowance.total_spent = 0;
+        allowance.created_at = clock.unix_timestamp;
+        allowance.bump = ctx.bumps.allowance;
+
+        emit!(AllowanceCreated {
+            allowance: allowance.key(),
+            funder,
+            child: allowance.child,
+            amount_per_period,
+            interval_seconds,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("Allowance created: {} per period", amount_per_period);
+        msg!("{} spending categories", caps.len());
+        msg!("Token delegation approved");
+
+        Ok(())
+    }
+
+    /// Replace the category caps. Spending so far this period still counts.
+    pub fn set_caps(ctx: Context<SetCaps>, caps: Vec<u64>) -> Result<()> {
+        let allowance = &mut ctx.accounts.allowance;
+        Allowance::check_params(
+            allowance.amount_per_period,
+            allowance.interval_seconds,
+            &caps,
+        )?;
+        allowance.set_caps(&caps);
+
+        msg!("Allowance caps updated: {} categories", caps.len());
+
+        Ok(())
+    }
+
+    /// Pull the next period's allowance from the funder and reset every
+    /// category's spending. Permissionless, like `charge_subscription`.
+    /// Unspent allowance stays in the vault.
+    pub fn top_up(ctx: Context<TopUp>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let allowance = &ctx.accounts.allowance;
+        allowance.check_due(now)?;
+
+        let amount = allowance.amount_per_period;
+        let seeds = &[
+            b"allowance",
+            allowance.funder.as_ref(),
+            allowance.child.as_ref(),
+            &[allowance.bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.funder_token_account.key(),
+            &ctx.accounts.vault.key(),
+            &allowance.key(),
+            &[],
+            amount,
+        )?;
+
+        invoke_signed(
+            &transfer_ix,
+            &[
+                ctx.accounts.funder_token_account.to_account_info(),
+                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.allowance.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+            signer_seeds,
+        )?;
+
+        let allowance = &mut ctx.accounts.allowance;
+        allowance.start_period(now)?;
+        allowance.total_funded = allowance
+            .total_funded
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        emit!(AllowanceToppedUp {
+            allowance: allowance.key(),
+            child: allowance.child,
+            amount,
+            timestamp: now,
+        });
+
+        msg!("Allowance topped up: {}", amount);
+        msg!("Next top-up at {}", allowance.next_top_up_at);
+
+        Ok(())
+    }
+
+    /// Authorize `key`, typically a session key held by the child's device,
+    /// to spend without a passkey prompt: only in the categories set in
+    /// `category_mask`, at most `max_per_spend` at a time, until `expires_at`
+    pub fn create_session(
+        ctx: Context<CreateSession>,
+        key: Pubkey,
+        category_mask: u8,
+        max_per_spend: u64,
+        expires_at: i64,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        require!(category_mask != 0, ErrorCode::SessionNotAllowed);
+        require!(max_per_spend > 0, ErrorCode::InvalidAmount);
+        require!(expires_at > now, ErrorCode::SessionExpired);
+
+        let session = &mut ctx.accounts.session;
+        session.allowance = ctx.accounts.allowance.key();
+        session.key = key;
+        session.category_mask = category_mask;
+        session.max_per_spend = max_per_spend;
+        session.expires_at = expires_at;
+        session.bump = ctx.bumps.session;
+
+        emit!(SessionCreated {
+            allowance: session.allowance,
+            key,
+            category_mask,
+            max_per_spend,
+            expires_at,
+            timestamp: now,
+        });
+
+        msg!("Session key created: {}", key);
+        msg!("Expires at {}", expires_at);
+
+        Ok(())
+    }
+
+    /// End a session early. The child or the funder may revoke it.
+    pub fn revoke_session(ctx: Context<RevokeSession>) -> Result<()> {
+        emit!(SessionRevoked {
+            allowance: ctx.accounts.allowance.key(),
+            key: ctx.accounts.session.key,
+            revoked_by: ctx.accounts.authority.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Session key revoked: {}", ctx.accounts.session.key);
+
+        Ok(())
+    }
+
+    /// Pay `amount` from the allowance to `destination`, counted against
+    /// `category`. The child signs with their passkey wallet, or a session
+    /// key signs within its policy.
+    pub fn spend(ctx: Context<Spend>, category: u8, amount: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let spender = ctx.accounts.spender.key();
+        if spender != ctx.accounts.allowance.child {
+            let session = ctx
+                .accounts
+                .session
+                .as_ref()
+                .ok_or(ErrorCode::Unauthorized)?;
+            require_keys_eq!(session.key, spender, ErrorCode::Unauthorized);
+            session.check_allows(category, amount, now)?;
+        }
+
+        let destination = token_account(&ctx.accounts.destination, ErrorCode::InvalidTokenAccount)?;
+        require_keys_eq!(
+            destination.mint,
+            ctx.accounts.allowance.mint,
+            ErrorCode::InvalidTokenAccount
+        );
+
+        ctx.accounts.allowance.record_spend(category, amount)?;
+
+        let allowance = &ctx.accounts.allowance;
+        let seeds = &[
+            b"allowance",
+            allowance.funder.as_ref(),
+            allowance.child.as_ref(),
+            &[allowance.bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.vault.key(),
+            &ctx.accounts.destination.key(),
+            &allowance.key(),
+            &[],
+            amount,
+        )?;
+
+        invoke_signed(
+            &transfer_ix,
+            &[
+                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.destination.to_account_info(),
+                ctx.accounts.allowance.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+            signer_seeds,
+        )?;
+
+        emit!(AllowanceSpent {
+            allowance: allowance.key(),
+            spender,
+            destination: ctx.accounts.destination.key(),
+            category,
+            amount,
+            timestamp: now,
+        });
+
+        msg!("Spent {} in category {}", amount, category);
+        msg!(
+            "{} left in category this period",
+            allowance.remaining(category).unwrap_or_default()
+        );
+
+        Ok(())
+    }
+
+    /// End the allowance: return the vault to the funder, revoke the
+    /// delegation and close the vault and the allowance account. Revoke
+    /// open sessions first: their rent cannot be reclaimed afterwards.
+    pub fn close_allowance(ctx: Context<CloseAllowance>) -> Result<()> {
+        let allowance = &ctx.accounts.allowance;
+        let remaining = token_account(&ctx.accounts.vault, ErrorCode::InvalidTokenAccount)?.amount;
+
+        let seeds = &[
+            b"allowance",
+            allowance.funder.as_ref(),
+            allowance.child.as_ref(),
+            &[allowance.bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+        let allowance_info = ctx.accounts.allowance.to_account_info();
+
+        if remaining > 0 {
+            let transfer_ix = token_instruction::transfer(
+                &ctx.accounts.token_program.key(),
+                &ctx.accounts.vault.key(),
+                &ctx.accounts.funder_token_account.key(),
+                &allowance_info.key(),
+                &[],
+                remaining,
+            )?;
+            invoke_signed(
+                &transfer_ix,
+                &[
+                    ctx.accounts.vault.to_account_info(),
+                    ctx.accounts.funder_token_account.to_account_info(),
+                    allowance_info.clone(),
+                    ctx.accounts.token_program.to_account_info(),
+                ],
+                signer_seeds,
+            )?;
+        }
+
+        let close_ix = token_instruction::close_account(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.vault.key(),
+            &ctx.accounts.funder.key(),
+            &allowance_info.key(),
+            &[],
+        )?;
+        invoke_signed(
+            &close_ix,
+            &[
+                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.funder.to_account_info(),
+                allowance_info.clone(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+            signer_seeds,
+        )?;
+
+        let revoke_ix = token_instruction::revoke(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.funder_token_account.key(),
+            &ctx.accounts.funder.key(),
+            &[],
+        )?;
+        invoke(
+            &revoke_ix,
+            &[
+                ctx.accounts.funder_token_account.to_account_info(),
+                ctx.accounts.funder.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        emit!(AllowanceClosed {
+            allowance: allowance_info.key(),
+            funder: ctx.accounts.funder.key(),
+            returned: remaining,
+            total_spent: ctx.accounts.allowance.total_spent,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Allowance closed: {} returned", remaining);
+        msg!("Token delegation revoked");
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct CreateAllowance<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Allowance::INIT_SPACE,
+        seeds = [b"allowance", funder.key().as_ref(), child.key().as_ref()],
+        bump
+    )]
+    pub allowance: Account<'info, Allowance>,
+
+    pub funder: Signer<'info>,
+
+    /// CHECK: The child's passkey wallet; it does not sign
+    pub child: UncheckedAccount<'info>,
+
+    /// CHECK: Mint the allowance is paid in
+    pub mint: UncheckedAccount<'info>,
+
+    /// CHECK: Funder's token account, checked in the handler and delegated to the allowance PDA
+    #[account(mut)]
+    pub funder_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: The allowance PDA's token account, checked in the handler
+    pub vault: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetCaps<'info> {
+    #[account(
+        mut,
+        seeds = [b"allowance", funder.key().as_ref(), allowance.child.as_ref()],
+        bump = allowance.bump,
+        has_one = funder
+    )]
+    pub allowance: Account<'info, Allowance>,
+
+    pub funder: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct TopUp<'info> {
+    #[account(
+        mut,
+        seeds = [b"allowance", allowance.funder.as_ref(), allowance.child.as_ref()],
+        bump = allowance.bump,
+        has_one = funder_token_account,
+        has_one = vault
+    )]
+    pub allowance: Account<'info, Allowance>,
+
+    /// CHECK: The delegated funder account
+    #[account(mut)]
+    pub funder_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: The allowance vault
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(key: Pubkey)]
+pub struct CreateSession<'info> {
+    #[account(
+        seeds = [b"allowance", allowance.funder.as_ref(), child.key().as_ref()],
+        bump = allowance.bump,
+        has_one = child
+    )]
+    pub allowance: Account<'info, Allowance>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + SessionKey::INIT_SPACE,
+        seeds = [b"session", allowance.key().as_ref(), key.as_ref()],
+        bump
+    )]
+    pub session: Account<'info, SessionKey>,
+
+    pub child: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeSession<'info> {
+    #[account(
+        seeds = [b"allowance", allowance.funder.as_ref(), child.key().as_ref()],
+        bump = allowance.bump,
+        has_one = child
+    )]
+    pub allowance: Account<'info, Allowance>,
+
+    #[account(
+        mut,
+        seeds = [b"session", allowance.key().as_ref(), session.key.as_ref()],
+        bump = session.bump,
+        has_one = allowance,
+        close = child
+    )]
+    pub session: Account<'info, SessionKey>,
+
+    /// The child or the funder
+    #[account(
+        constraint = authority.key() == allowance.child
+            || authority.key() == allowance.funder @ ErrorCode::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+
+    /// CHECK: The child's wallet, refunded the session's rent
+    #[account(mut)]
+    pub child: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Spend<'info> {
+    #[account(
+        mut,
+        seeds = [b"allowance", allowance.funder.as_ref(), allowance.child.as_ref()],
+        bump = allowance.bump,
+        has_one = vault
+    )]
+    pub allowance: Account<'info, Allowance>,
+
+    /// Required when the spender is not the child
+    #[account(
+        seeds = [b"session", allowance.key().as_ref(), spender.key().as_ref()],
+        bump = session.bump,
+        has_one = allowance
+    )]
+    pub session: Option<Account<'info, SessionKey>>,
+
+    /// The child, or a session key of theirs
+    pub spender: Signer<'info>,
+
+    /// CHECK: The allowance vault
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: Any token account for the allowance mint, checked in the handler
+    #[account(mut)]
+    pub destination: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseAllowance<'info> {
+    #[account(
+        mut,
+        seeds = [b"allowance", funder.key().as_ref(), allowance.child.as_ref()],
+        bump = allowance.bump,
+        has_one = funder,
+        has_one = funder_token_account,
+        has_one = vault,
+        close = funder
+    )]
+    pub allowance: Account<'info, Allowance>,
+
+    #[account(mut)]
+    pub funder: Signer<'info>,
+
+    /// CHECK: The allowance vault, emptied and closed
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: The delegated funder account
+    #[account(mut)]
+    pub funder_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub struct CategoryCap {
+    /// Most the child may spend in this category per period
+    pub cap: u64,
+    /// Spent since the last top-up
+    pub spent: u64,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Allowance {
+    pub funder: Pubkey,
+    pub child: Pubkey,
+    pub mint: Pubkey,
+    /// Delegated to this PDA; each top-up is pulled from it
+    pub funder_token_account: Pubkey,
+    /// Owned by this PDA; the child spends from it
+    pub vault: Pubkey,
+    pub amount_per_period: u64,
+    pub interval_seconds: i64,
+    /// Indexed by category; labels live off-chain
+    #[max_len(MAX_CATEGORIES)]
+    pub categories: Vec<CategoryCap>,
+    pub period_started_at: i64,
+    pub next_top_up_at: i64,
+    pub total_funded: u64,
+    pub total_spent: u64,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl Allowance {
+    pub fn check_params(amount_per_period: u64, interval_seconds: i64, caps: &[u64]) -> Result<()> {
+        require!(amount_per_period > 0, ErrorCode::InvalidAmount);
+        require!(interval_seconds > 0, ErrorCode::InvalidInterval);
+        require!(
+            !caps.is_empty() && caps.len() <= MAX_CATEGORIES && caps.iter().all(|&cap| cap > 0),
+            ErrorCode::InvalidCaps
+        );
+        Ok(())
+    }
+
+    pub fn check_due(&self, now: i64) -> Result<()> {
+        require!(now >= self.next_top_up_at, ErrorCode::TopUpNotDue);
+        Ok(())
+    }
+
+    /// Open a new period at `now`: every category's spending is reset
+    pub fn start_period(&mut self, now: i64) -> Result<()> {
+        for category in self.categories.iter_mut() {
+            category.spent = 0;
+        }
+        self.period_started_at = now;
+        self.next_top_up_at = now
+            .checked_add(self.interval_seconds)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+
+    /// Replace the caps, keeping this period's spending for categories that remain
+    pub fn set_caps(&mut self, caps: &[u64]) {
+        self.categories = caps
+            .iter()
+            .enumerate()
+            .map(|(index, &cap)| CategoryCap {
+                cap,
+                spent: self.categories.get(index).map_or(0, |old| old.spent),
+            })
+            .collect();
+    }
+
+    /// Left to spend in `category` this period
+    pub fn remaining(&self, category: u8) -> Option<u64> {
+        self.categories
+            .get(category as usize)
+            .map(|category| category.cap.saturating_sub(category.spent))
+    }
+
+    pub fn record_spend(&mut self, category: u8, amount: u64) -> Result<()> {
+        let remaining = self.remaining(category).ok_or(ErrorCode::UnknownCategory)?;
+        require!(amount <= remaining, ErrorCode::CategoryCapExceeded);
+        let entry = &mut self.categories[category as usize];
+        entry.spent = entry
+            .spent
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.total_spent = self
+            .total_spent
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+}
+
+/// A spending policy for a key other than the child's wallet
+#[account]
+#[derive(InitSpace)]
+pub struct SessionKey {
+    pub allowance: Pubkey,
+    pub key: Pubkey,
+    /// Bit `n` allows category `n`
+    pub category_mask: u8,
+    pub max_per_spend: u64,
+    pub expires_at: i64,
+    pub bump: u8,
+}
+
+impl SessionKey {
+    pub fn check_allows(&self, category: u8, amount: u64, now: i64) -> Result<()> {
+        require!(now < self.expires_at, ErrorCode::SessionExpired);
+        let allowed = (category as usize) < MAX_CATEGORIES
+            && self.category_mask & (1 << category) != 0
+            && amount <= self.max_per_spend;
+        require!(allowed, ErrorCode::SessionNotAllowed);
+        Ok(())
+    }
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct AllowanceCreated {
+    pub allowance: Pubkey,
+    pub funder: Pubkey,
+    pub child: Pubkey,
+    pub amount_per_period: u64,
+    pub interval_seconds: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct AllowanceToppedUp {
+    pub allowance: Pubkey,
+    pub child: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionCreated {
+    pub allowance: Pubkey,
+    pub key: Pubkey,
+    pub category_mask: u8,
+    pub max_per_spend: u64,
+    pub expires_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionRevoked {
+    pub allowance: Pubkey,
+    pub key: Pubkey,
+    pub revoked_by: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct AllowanceSpent {
+    pub allowance: Pubkey,
+    pub spender: Pubkey,
+    pub destination: Pubkey,
+    pub category: u8,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct AllowanceClosed {
+    pub allowance: Pubkey,
+    pub funder: Pubkey,
+    pub returned: u64,
+    pub total_spent: u64,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Amount must be greater than zero")]
+    InvalidAmount,
+    #[msg("Interval must be positive")]
+    InvalidInterval,
+    #[msg("Caps must be 1 to 8 categories, each above zero")]
+    InvalidCaps,
+    #[msg("Invalid token account - wrong program, owner or mint")]
+    InvalidTokenAccount,
+    #[msg("Next top-up is not due yet")]
+    TopUpNotDue,
+    #[msg("No such spending category")]
+    UnknownCategory,
+    #[msg("Spend exceeds what is left in the category this period")]
+    CategoryCapExceeded,
+    #[msg("Signer is neither the child nor one of their session keys")]
+    Unauthorized,
+    #[msg("Session key has expired")]
+    SessionExpired,
+    #[msg("Session key policy does not allow this spend")]
+    SessionNotAllowed,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
@@ -0,0 +1,501 @@
+//! Native tests for the allowance program.
+//!
+//! Cap, period and session policy rules are pure methods and are tested
+//! directly. Instruction tests run end to end and check the vault, the shop
+//! and the funder's delegation.
+
+use allowance::{
+    accounts, instruction, Allowance, CategoryCap, ErrorCode, SessionKey, ID as PROGRAM_ID,
+};
+use anchor_lang::error::ErrorCode as AnchorErrorCode;
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, Space, ToAccountMetas};
+use test_harness::{assert_error, assert_success, Keypair, Signer, TestSvm, TransactionResult};
+
+const WEEK: i64 = 7 * 86_400;
+const FOOD: u8 = 0;
+const GAMES: u8 = 1;
+
+fn allowance() -> Allowance {
+    Allowance {
+        funder: Pubkey::new_unique(),
+        child: Pubkey::new_unique(),
+        mint: Pubkey::new_unique(),
+        funder_token_account: Pubkey::new_unique(),
+        vault: Pubkey::new_unique(),
+        amount_per_period: 20_000_000,
+        interval_seconds: WEEK,
+        categories: vec![
+            CategoryCap {
+                cap: 15_000_000,
+                spent: 0,
+            },
+            CategoryCap {
+                cap: 5_000_000,
+                spent: 0,
+            },
+        ],
+        period_started_at: 0,
+        next_top_up_at: 0,
+        total_funded: 0,
+        total_spent: 0,
+        created_at: 0,
+        bump: 255,
+    }
+}
+
+fn policy(allowance: Pubkey, key: Pubkey, expires_at: i64) -> SessionKey {
+    SessionKey {
+        allowance,
+        key,
+        category_mask: 1 << GAMES,
+        max_per_spend: 1_000_000,
+        expires_at,
+        bump: 255,
+    }
+}
+
+#[test]
+fn caps_are_one_to_eight_nonzero_categories() {
+    Allowance::check_params(1, WEEK, &[1; 8]).unwrap();
+    assert_eq!(
+        Allowance::check_params(0, WEEK, &[1]).unwrap_err(),
+        ErrorCode::InvalidAmount.into()
+    );
+    assert_eq!(
+        Allowance::check_params(1, 0, &[1]).unwrap_err(),
+        ErrorCode::InvalidInterval.into()
+    );
+    for caps in [vec![], vec![1; 9], vec![1, 0]] {
+        assert_eq!(
+            Allowance::check_params(1, WEEK, &caps).unwrap_err(),
+            ErrorCode::InvalidCaps.into()
+        );
+    }
+}
+
+#[test]
+fn spending_is_capped_per_category_until_the_next_period() {
+    let mut state = allowance();
+    state.record_spend(GAMES, 4_000_000).unwrap();
+    assert_eq!(state.remaining(GAMES), Some(1_000_000));
+    assert_eq!(
+        state.record_spend(GAMES, 1_000_001).unwrap_err(),
+        ErrorCode::CategoryCapExceeded.into()
+    );
+    assert_eq!(
+        state.record_spend(2, 1).unwrap_err(),
+        ErrorCode::UnknownCategory.into()
+    );
+    state.record_spend(FOOD, 15_000_000).unwrap();
+    assert_eq!(state.total_spent, 19_000_000);
+
+    // Lowering a cap below this period's spending leaves nothing to spend
+    state.set_caps(&[15_000_000, 2_000_000, 3_000_000]);
+    assert_eq!(state.remaining(GAMES), Some(0));
+    assert_eq!(state.remaining(2), Some(3_000_000));
+
+    state.start_period(100).unwrap();
+    assert_eq!(state.remaining(GAMES), Some(2_000_000));
+    assert_eq!(state.remaining(FOOD), Some(15_000_000));
+    assert_eq!(state.next_top_up_at, 100 + WEEK);
+    state.check_due(100 + WEEK).unwrap();
+    assert_eq!(
+        state.check_due(100 + WEEK - 1).unwrap_err(),
+        ErrorCode::TopUpNotDue.into()
+    );
+}
+
+#[test]
+fn session_policies_limit_category_amount_and_time() {
+    let key = policy(Pubkey::new_unique(), Pubkey::new_unique(), 1_000);
+    key.check_allows(GAMES, 1_000_000, 999).unwrap();
+    for (category, amount) in [(FOOD, 1), (GAMES, 1_000_001), (7, 1), (200, 1)] {
+        assert_eq!(
+            key.check_allows(category, amount, 0).unwrap_err(),
+            ErrorCode::SessionNotAllowed.into()
+        );
+    }
+    assert_eq!(
+        key.check_allows(GAMES, 1, 1_000).unwrap_err(),
+        ErrorCode::SessionExpired.into()
+    );
+}
+
+struct Fixture {
+    svm: TestSvm,
+    payer: Keypair,
+    funder: Keypair,
+    child: Keypair,
+    session_key: Keypair,
+    allowance: Pubkey,
+    state: Allowance,
+    session: Pubkey,
+    shop: Pubkey,
+}
+
+impl Fixture {
+    /// A topped-up allowance, due again at `next_top_up_at`, with a games
+    /// session key expiring at `session_expires_at`
+    fn new(next_top_up_at: i64, session_expires_at: i64) -> Self {
+        let (mut svm, payer) = TestSvm::for_program(PROGRAM_ID, allowance::entry);
+
+        let funder = Keypair::new();
+        let child = Keypair::new();
+        let session_key = Keypair::new();
+
+        let mint = svm.create_mint(&Pubkey::new_unique(), 6);
+        let (address, bump) = Pubkey::find_program_address(
+            &[
+                b"allowance",
+                funder.pubkey().as_ref(),
+                child.pubkey().as_ref(),
+            ],
+            &PROGRAM_ID,
+        );
+        let funder_token_account =
+            svm.create_associated_token_account(&funder.pubkey(), &mint, 100_000_000);
+        svm.approve(&funder_token_account, &address, u64::MAX);
+        let vault = svm.create_associated_token_account(&address, &mint, 20_000_000);
+        let shop = svm.create_associated_token_account(&Pubkey::new_unique(), &mint, 0);
+
+        let state = Allowance {
+            funder: funder.pubkey(),
+            child: child.pubkey(),
+            mint,
+            funder_token_account,
+            vault,
+            next_top_up_at,
+            total_funded: 20_000_000,
+            bump,
+            ..allowance()
+        };
+        svm.set_anchor_account(address, &state, 8 + Allowance::INIT_SPACE);
+
+        let (session, bump) = Pubkey::find_program_address(
+            &[b"session", address.as_ref(), session_key.pubkey().as_ref()],
+            &PROGRAM_ID,
+        );
+        let session_state = SessionKey {
+            bump,
+            ..policy(address, session_key.pubkey(), session_expires_at)
+        };
+        svm.set_anchor_account(session, &session_state, 8 + SessionKey::INIT_SPACE);
+
+        Self {
+            svm,
+            payer,
+            funder,
+            child,
+            session_key,
+            allowance: address,
+            state,
+            session,
+            shop,
+        }
+    }
+
+    fn now(&self) -> i64 {
+        self.svm.clock().unix_timestamp
+    }
+
+    fn create_allowance(&self, caps: Vec<u64>) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CreateAllowance {
+                allowance: self.allowance,
+                funder: self.funder.pubkey(),
+                child: self.child.pubkey(),
+                mint: self.state.mint,
+                funder_token_account: self.state.funder_token_account,
+                vault: self.state.vault,
+                payer: self.payer.pubkey(),
+                token_program: spl_token::ID,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CreateAllowance {
+                amount_per_period: self.state.amount_per_period,
+                interval_seconds: WEEK,
+                caps,
+            }
+            .data(),
+        }
+    }
+
+    fn create_session(&self, expires_at: i64) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CreateSession {
+                allowance: self.allowance,
+                session: self.session,
+                child: self.child.pubkey(),
+                payer: self.payer.pubkey(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CreateSession {
+                key: self.session_key.pubkey(),
+                category_mask: 1 << GAMES,
+                max_per_spend: 1_000_000,
+                expires_at,
+            }
+            .data(),
+        }
+    }
+
+    fn top_up(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::TopUp {
+                allowance: self.allowance,
+                funder_token_account: self.state.funder_token_account,
+                vault: self.state.vault,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::TopUp {}.data(),
+        }
+    }
+
+    fn set_caps(&self, funder: Pubkey, caps: Vec<u64>) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::SetCaps {
+                allowance: self.allowance,
+                funder,
+            }
+            .to_account_metas(None),
+            data: instruction::SetCaps { caps }.data(),
+        }
+    }
+
+    fn spend(
+        &self,
+        spender: Pubkey,
+        session: Option<Pubkey>,
+        category: u8,
+        amount: u64,
+    ) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::Spend {
+                allowance: self.allowance,
+                session,
+                spender,
+                vault: self.state.vault,
+                destination: self.shop,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::Spend { category, amount }.data(),
+        }
+    }
+
+    fn revoke(&self, authority: Pubkey) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::RevokeSession {
+                allowance: self.allowance,
+                session: self.session,
+                authority,
+                child: self.child.pubkey(),
+            }
+            .to_account_metas(None),
+            data: instruction::RevokeSession {}.data(),
+        }
+    }
+
+    fn close(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CloseAllowance {
+                allowance: self.allowance,
+                funder: self.funder.pubkey(),
+                vault: self.state.vault,
+                funder_token_account: self.state.funder_token_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CloseAllowance {}.data(),
+        }
+    }
+
+    fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TransactionResult {
+        let payer = self.payer.insecure_clone();
+        self.svm.send_instructions(&[instruction], &payer, signers)
+    }
+
+    fn send_as(&mut self, signer: &Keypair, instruction: Instruction) -> TransactionResult {
+        let signer = signer.insecure_clone();
+        self.send(instruction, &[&signer])
+    }
+
+    fn allowance(&self) -> Allowance {
+        self.svm.get_anchor_account(&self.allowance).unwrap()
+    }
+
+    fn vault_balance(&self) -> u64 {
+        self.svm.token_balance(&self.state.vault)
+    }
+}
+
+#[test]
+fn funders_delegate_when_creating_an_allowance() {
+    let mut fx = Fixture::new(0, 0);
+    fx.svm.remove_account(&fx.allowance);
+    fx.svm.revoke(&fx.state.funder_token_account);
+    let funder = fx.funder.insecure_clone();
+    let result = fx.send_as(&funder, fx.create_allowance(vec![]));
+    assert_error(result, ErrorCode::InvalidCaps);
+
+    let mut elsewhere = fx.create_allowance(vec![15_000_000, 5_000_000]);
+    elsewhere.accounts[5].pubkey = fx.shop;
+    let result = fx.send_as(&funder, elsewhere);
+    assert_error(result, ErrorCode::InvalidTokenAccount);
+
+    let result = fx.send_as(&funder, fx.create_allowance(vec![15_000_000, 5_000_000]));
+    assert_success(result);
+    let account = fx
+        .svm
+        .get_token_account(&fx.state.funder_token_account)
+        .unwrap();
+    assert_eq!(account.delegate, Some(fx.allowance).into());
+    assert_eq!(account.delegated_amount, u64::MAX);
+    let state = fx.allowance();
+    assert_eq!(state.child, fx.child.pubkey());
+    assert_eq!(state.vault, fx.state.vault);
+    assert_eq!(state.remaining(GAMES), Some(5_000_000));
+    // The first top-up is due at once
+    state.check_due(fx.now()).unwrap();
+}
+
+#[test]
+fn the_child_opens_sessions_that_expire_later() {
+    let now = Fixture::new(0, 0).now();
+    let mut fx = Fixture::new(0, 0);
+    fx.svm.remove_account(&fx.session);
+    let child = fx.child.insecure_clone();
+    let result = fx.send_as(&child, fx.create_session(now));
+    assert_error(result, ErrorCode::SessionExpired);
+
+    let result = fx.send_as(&child, fx.create_session(now + 3_600));
+    assert_success(result);
+    let session: SessionKey = fx.svm.get_anchor_account(&fx.session).unwrap();
+    assert_eq!(session.key, fx.session_key.pubkey());
+    assert_eq!(session.expires_at, now + 3_600);
+    session.check_allows(GAMES, 1_000_000, now).unwrap();
+}
+
+#[test]
+fn top_ups_wait_for_the_interval() {
+    let now = Fixture::new(0, 0).now();
+    let mut fx = Fixture::new(now + 1, now);
+    let result = fx.send(fx.top_up(), &[]);
+    assert_error(result, ErrorCode::TopUpNotDue);
+
+    fx.svm.warp_to_timestamp(now + 1);
+    let result = fx.send(fx.top_up(), &[]);
+    assert_success(result);
+    assert_eq!(fx.vault_balance(), 40_000_000);
+    assert_eq!(
+        fx.svm.token_balance(&fx.state.funder_token_account),
+        80_000_000
+    );
+    let state = fx.allowance();
+    assert_eq!(state.total_funded, 40_000_000);
+    assert_eq!(state.next_top_up_at, now + 1 + WEEK);
+}
+
+#[test]
+fn the_child_spends_within_category_caps() {
+    let mut fx = Fixture::new(0, 0);
+    let child = fx.child.insecure_clone();
+    let result = fx.send_as(&child, fx.spend(child.pubkey(), None, GAMES, 5_000_001));
+    assert_error(result, ErrorCode::CategoryCapExceeded);
+
+    let result = fx.send_as(&child, fx.spend(child.pubkey(), None, GAMES, 5_000_000));
+    assert_success(result);
+    assert_eq!(fx.vault_balance(), 15_000_000);
+    assert_eq!(fx.svm.token_balance(&fx.shop), 5_000_000);
+    assert_eq!(fx.allowance().remaining(GAMES), Some(0));
+}
+
+#[test]
+fn session_keys_spend_only_within_their_policy() {
+    let now = Fixture::new(0, 0).now();
+    let mut fx = Fixture::new(0, now + 3_600);
+    let key = fx.session_key.insecure_clone();
+
+    let result = fx.send_as(&key, fx.spend(key.pubkey(), None, GAMES, 1));
+    assert_error(result, ErrorCode::Unauthorized);
+
+    let session = Some(fx.session);
+    let result = fx.send_as(&key, fx.spend(key.pubkey(), session, FOOD, 1));
+    assert_error(result, ErrorCode::SessionNotAllowed);
+
+    let result = fx.send_as(&key, fx.spend(key.pubkey(), session, GAMES, 1_000_000));
+    assert_success(result);
+    assert_eq!(fx.svm.token_balance(&fx.shop), 1_000_000);
+
+    fx.svm.warp_to_timestamp(now + 3_600);
+    let result = fx.send_as(&key, fx.spend(key.pubkey(), session, GAMES, 1));
+    assert_error(result, ErrorCode::SessionExpired);
+}
+
+#[test]
+fn only_the_funder_sets_caps() {
+    let mut fx = Fixture::new(0, 0);
+    let child = fx.child.insecure_clone();
+    let result = fx.send_as(&child, fx.set_caps(child.pubkey(), vec![1]));
+    assert!(result.is_err());
+
+    let funder = fx.funder.insecure_clone();
+    let result = fx.send_as(&funder, fx.set_caps(funder.pubkey(), vec![0]));
+    assert_error(result, ErrorCode::InvalidCaps);
+
+    let result = fx.send_as(&funder, fx.set_caps(funder.pubkey(), vec![10_000_000]));
+    assert!(result.is_ok(), "{result:#?}");
+    assert_eq!(fx.allowance().remaining(FOOD), Some(10_000_000));
+    assert_eq!(fx.allowance().remaining(GAMES), None);
+}
+
+#[test]
+fn the_funder_or_child_revokes_a_session() {
+    let mut fx = Fixture::new(0, 0);
+    let stranger = Keypair::new();
+    let result = fx.send_as(&stranger, fx.revoke(stranger.pubkey()));
+    assert_error(result, ErrorCode::Unauthorized);
+
+    let funder = fx.funder.insecure_clone();
+    let result = fx.send_as(&funder, fx.revoke(funder.pubkey()));
+    assert!(result.is_ok(), "{result:#?}");
+    assert!(fx.svm.get_account(&fx.session).is_none());
+    assert!(fx.svm.get_balance(&fx.child.pubkey()) > 0);
+}
+
+#[test]
+fn closing_returns_the_vault_and_revokes() {
+    let mut fx = Fixture::new(0, 0);
+    let child = fx.child.insecure_clone();
+    let mut by_child = fx.close();
+    by_child.accounts[1].pubkey = child.pubkey();
+    let result = fx.send_as(&child, by_child);
+    assert_error(result, AnchorErrorCode::ConstraintSeeds);
+
+    let funder = fx.funder.insecure_clone();
+    let rent = fx.svm.get_balance(&fx.allowance) + fx.svm.get_balance(&fx.state.vault);
+    let lamports = fx.svm.get_balance(&funder.pubkey());
+    let result = fx.send_as(&funder, fx.close());
+    assert_success(result);
+    let account = fx
+        .svm
+        .get_token_account(&fx.state.funder_token_account)
+        .unwrap();
+    assert_eq!(account.amount, 120_000_000);
+    assert!(account.delegate.is_none());
+    assert!(fx.svm.get_account(&fx.state.vault).is_none());
+    assert!(fx.svm.get_account(&fx.allowance).is_none());
+    assert_eq!(fx.svm.get_balance(&funder.pubkey()), lamports + rent);
+}
@@ -0,0 +1,687 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use spl_token::instruction as token_instruction;
+use token_utils::token_account;
+
+declare_id!("868uoKBo1NSr6TK3sv5QUwpSxzpd4trc7j5nb8a4X3Dh");
+
+#[program]
+pub mod api_credits {
+    use super::*;
+
+    /// Sell credits at `price_per_credit` base units each. Usage is reported
+    /// by `meter_authority`, the provider's metering key; balances under
+    /// `low_balance_threshold` credits raise `LowBalance` and can be topped up
+    /// by the keeper.
+    pub fn create_service(
+        ctx: Context<CreateService>,
+        meter_authority: Pubkey,
+        price_per_credit: u64,
+        low_balance_threshold: u64,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        require!(price_per_credit > 0, ErrorCode::InvalidAmount);
+
+        let provider_account = token_account(
+            &ctx.accounts.provider_token_account,
+            ErrorCode::InvalidTokenAccount,
+        )?;
+        require_keys_eq!(
+            provider_account.owner,
+            ctx.accounts.provider.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+        require_keys_eq!(
+            provider_account.mint,
+            ctx.accounts.mint.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+
+        let service = &mut ctx.accounts.service;
+        service.provider = ctx.accounts.provider.key();
+        service.meter_authority = meter_authority;
+        service.mint = ctx.accounts.mint.key();
+        service.provider_token_account = ctx.accounts.provider_token_account.key();
+        service.price_per_credit = price_per_credit;
+        service.low_balance_threshold = low_balance_threshold;
+        service.credits_sold = 0;
+        service.credits_used = 0;
+        service.created_at = clock.unix_timestamp;
+        service.bump = ctx.bumps.service;
+
+        emit!(ServiceCreated {
+            service: service.key(),
+            provider: service.provider,
+            meter_authority,
+            price_per_credit,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("Service created: {} tokens per credit", price_per_credit);
+
+        Ok(())
+    }
+
+    /// Change the price, threshold or metering key; balances are kept
+    pub fn update_service(
+        ctx: Context<UpdateService>,
+        meter_authority: Pubkey,
+        price_per_credit: u64,
+        low_balance_threshold: u64,
+    ) -> Result<()> {
+        require!(price_per_credit > 0, ErrorCode::InvalidAmount);
+
+        let service = &mut ctx.accounts.service;
+        service.meter_authority = meter_authority;
+        service.price_per_credit = price_per_credit;
+        service.low_balance_threshold = low_balance_threshold;
+
+        msg!("Service updated: {} tokens per credit", price_per_credit);
+
+        Ok(())
+    }
+
+    /// Open a zero balance for `user`
+    pub fn open_account(ctx: Context<OpenAccount>) -> Result<()> {
+        let account = &mut ctx.accounts.credit_account;
+        account.service = ctx.accounts.service.key();
+        account.user = ctx.accounts.user.key();
+        account.balance = 0;
+        account.last_report_id = 0;
+        account.auto_top_up = None;
+        account.total_purchased = 0;
+        account.total_used = 0;
+        account.bump = ctx.bumps.credit_account;
+
+        msg!("Credit account opened for {}", account.user);
+
+        Ok(())
+    }
+
+    /// Buy `credits` at the current price
+    pub fn buy_credits(ctx: Context<BuyCredits>, credits: u64) -> Result<()> {
+        let cost = ctx.accounts.service.cost(credits)?;
+
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.user_token_account.key(),
+            &ctx.accounts.provider_token_account.key(),
+            &ctx.accounts.user.key(),
+            &[],
+            cost,
+        )?;
+
+        invoke(
+            &transfer_ix,
+            &[
+                ctx.accounts.user_token_account.to_account_info(),
+                ctx.accounts.provider_token_account.to_account_info(),
+                ctx.accounts.user.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        book_purchase(
+            &mut ctx.accounts.service,
+            &mut ctx.accounts.credit_account,
+            credits,
+            cost,
+            false,
+        )
+    }
+
+    /// Debit `credits` for usage report `report_id`. Signed by the service's
+    /// meter authority; report ids must increase, so a report cannot be
+    /// replayed.
+    pub fn report_usage(ctx: Context<ReportUsage>, report_id: u64, credits: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let service = &mut ctx.accounts.service;
+        let account = &mut ctx.accounts.credit_account;
+        account.debit(report_id, credits)?;
+        service.credits_used = service
+            .credits_used
+            .checked_add(credits)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        emit!(UsageReported {
+            service: service.key(),
+            user: account.user,
+            report_id,
+            credits,
+            balance: account.balance,
+            timestamp: now,
+        });
+
+        if account.is_low(service.low_balance_threshold) {
+            emit!(LowBalance {
+                service: service.key(),
+                user: account.user,
+                balance: account.balance,
+                auto_top_up: account.auto_top_up.is_some(),
+                timestamp: now,
+            });
+            msg!("Low balance: {} credits left", account.balance);
+        }
+
+        msg!("Usage report {}: {} credits", report_id, credits);
+
+        Ok(())
+    }
+
+    /// Let the keeper buy `credits` whenever the balance is low. The credit
+    /// account PDA becomes the delegate of `user_token_account`, as with a
+    /// subscription.
+    pub fn set_auto_top_up(ctx: Context<SetAutoTopUp>, credits: u64) -> Result<()> {
+        require!(credits > 0, ErrorCode::InvalidAmount);
+
+        let user_account = token_account(
+            &ctx.accounts.user_token_account,
+            ErrorCode::InvalidTokenAccount,
+        )?;
+        require_keys_eq!(
+            user_account.owner,
+            ctx.accounts.user.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+        require_keys_eq!(
+            user_account.mint,
+            ctx.accounts.service.mint,
+            ErrorCode::InvalidTokenAccount
+        );
+
+        let delegate_ix = token_instruction::approve(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.user_token_account.key(),
+            &ctx.accounts.credit_account.key(),
+            &ctx.accounts.user.key(),
+            &[],
+            u64::MAX,
+        )?;
+
+        invoke(
+            &delegate_ix,
+            &[
+                ctx.accounts.user_token_account.to_account_info(),
+                ctx.accounts.credit_account.to_account_info(),
+                ctx.accounts.user.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        let account = &mut ctx.accounts.credit_account;
+        account.auto_top_up = Some(AutoTopUp {
+            user_token_account: ctx.accounts.user_token_account.key(),
+            credits,
+        });
+
+        msg!("Auto top-up set: {} credits when low", credits);
+
+        Ok(())
+    }
+
+    /// Stop auto top-ups and revoke the delegation
+    pub fn cancel_auto_top_up(ctx: Context<CancelAutoTopUp>) -> Result<()> {
+        let top_up = ctx
+            .accounts
+            .credit_account
+            .auto_top_up
+            .ok_or(ErrorCode::NoAutoTopUp)?;
+        require_keys_eq!(
+            ctx.accounts.user_token_account.key(),
+            top_up.user_token_account,
+            ErrorCode::InvalidTokenAccount
+        );
+
+        let revoke_ix = token_instruction::revoke(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.user_token_account.key(),
+            &ctx.accounts.user.key(),
+            &[],
+        )?;
+
+        invoke(
+            &revoke_ix,
+            &[
+                ctx.accounts.user_token_account.to_account_info(),
+                ctx.accounts.user.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        ctx.accounts.credit_account.auto_top_up = None;
+
+        msg!("Auto top-up cancelled");
+        msg!("Token delegation revoked");
+
+        Ok(())
+    }
+
+    /// Buy the configured top-up for a low balance. Permissionless, like
+    /// `charge_subscription`.
+    pub fn top_up(ctx: Context<TopUp>) -> Result<()> {
+        let service = &ctx.accounts.service;
+        let account = &ctx.accounts.credit_account;
+        let top_up = account.check_top_up_due(service.low_balance_threshold)?;
+        require_keys_eq!(
+            ctx.accounts.user_token_account.key(),
+            top_up.user_token_account,
+            ErrorCode::InvalidTokenAccount
+        );
+        let cost = service.cost(top_up.credits)?;
+
+        let service_key = service.key();
+        let user_key = account.user;
+        let seeds = &[
+            b"credits",
+            service_key.as_ref(),
+            user_key.as_ref(),
+            &[account.bump],
+        ];
+
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.user_token_account.key(),
+            &ctx.accounts.provider_token_account.key(),
+            &account.key(),
+            &[],
+            cost,
+        )?;
+
+        invoke_signed(
+            &transfer_ix,
+            &[
+                ctx.accounts.user_token_account.to_account_info(),
+                ctx.accounts.provider_token_account.to_account_info(),
+                ctx.accounts.credit_account.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+            &[&seeds[..]],
+        )?;
+
+        book_purchase(
+            &mut ctx.accounts.service,
+            &mut ctx.accounts.credit_account,
+            top_up.credits,
+            cost,
+            true,
+        )
+    }
+}
+
+/// Add purchased credits to both sides once the payment has moved
+fn book_purchase(
+    service: &mut Account<Service>,
+    account: &mut Account<CreditAccount>,
+    credits: u64,
+    cost: u64,
+    auto: bool,
+) -> Result<()> {
+    account.credit(credits)?;
+    service.credits_sold = service
+        .credits_sold
+        .checked_add(credits)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+    emit!(CreditsPurchased {
+        service: service.key(),
+        user: account.user,
+        credits,
+        cost,
+        balance: account.balance,
+        auto,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!("Bought {} credits for {} tokens", credits, cost);
+    msg!("Balance: {} credits", account.balance);
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CreateService<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Service::INIT_SPACE,
+        seeds = [b"service", provider.key().as_ref()],
+        bump
+    )]
+    pub service: Account<'info, Service>,
+
+    pub provider: Signer<'info>,
+
+    /// CHECK: Token mint (USDC)
+    pub mint: UncheckedAccount<'info>,
+
+    /// CHECK: Provider's token account, checked in the handler; purchases land here
+    pub provider_token_account: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateService<'info> {
+    #[account(
+        mut,
+        seeds = [b"service", provider.key().as_ref()],
+        bump = service.bump,
+        has_one = provider
+    )]
+    pub service: Account<'info, Service>,
+
+    pub provider: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct OpenAccount<'info> {
+    #[account(
+        seeds = [b"service", service.provider.as_ref()],
+        bump = service.bump,
+    )]
+    pub service: Account<'info, Service>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + CreditAccount::INIT_SPACE,
+        seeds = [b"credits", service.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub credit_account: Account<'info, CreditAccount>,
+
+    pub user: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct BuyCredits<'info> {
+    #[account(
+        mut,
+        seeds = [b"service", service.provider.as_ref()],
+        bump = service.bump,
+        has_one = provider_token_account
+    )]
+    pub service: Account<'info, Service>,
+
+    #[account(
+        mut,
+        seeds = [b"credits", service.key().as_ref(), user.key().as_ref()],
+        bump = credit_account.bump,
+        has_one = service,
+        has_one = user
+    )]
+    pub credit_account: Account<'info, CreditAccount>,
+
+    pub user: Signer<'info>,
+
+    /// CHECK: Any token account the user owns, debited by the token program
+    #[account(mut)]
+    pub user_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Provider's token account
+    #[account(mut)]
+    pub provider_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ReportUsage<'info> {
+    #[account(
+        mut,
+        seeds = [b"service", service.provider.as_ref()],
+        bump = service.bump,
+        has_one = meter_authority
+    )]
+    pub service: Account<'info, Service>,
+
+    #[account(
+        mut,
+        seeds = [b"credits", service.key().as_ref(), credit_account.user.as_ref()],
+        bump = credit_account.bump,
+        has_one = service
+    )]
+    pub credit_account: Account<'info, CreditAccount>,
+
+    pub meter_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetAutoTopUp<'info> {
+    #[account(
+        seeds = [b"service", service.provider.as_ref()],
+        bump = service.bump,
+    )]
+    pub service: Account<'info, Service>,
+
+    #[account(
+        mut,
+        seeds = [b"credits", service.key().as_ref(), user.key().as_ref()],
+        bump = credit_account.bump,
+        has_one = service,
+        has_one = user
+    )]
+    pub credit_account: Account<'info, CreditAccount>,
+
+    pub user: Signer<'info>,
+
+    /// CHECK: User's token account, checked in the handler and delegated to the credit account PDA
+    #[account(mut)]
+    pub user_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelAutoTopUp<'info> {
+    #[account(
+        mut,
+        seeds = [b"credits", credit_account.service.as_ref(), user.key().as_ref()],
+        bump = credit_account.bump,
+        has_one = user
+    )]
+    pub credit_account: Account<'info, CreditAccount>,
+
+    pub user: Signer<'info>,
+
+    /// CHECK: The delegated token account, checked in the handler
+    #[account(mut)]
+    pub user_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct TopUp<'info> {
+    #[account(
+        mut,
+        seeds = [b"service", service.provider.as_ref()],
+        bump = service.bump,
+        has_one = provider_token_account
+    )]
+    pub service: Account<'info, Service>,
+
+    #[account(
+        mut,
+        seeds = [b"credits", service.key().as_ref(), credit_account.user.as_ref()],
+        bump = credit_account.bump,
+        has_one = service
+    )]
+    pub credit_account: Account<'info, CreditAccount>,
+
+    /// CHECK: The delegated token account, checked in the handler
+    #[account(mut)]
+    pub user_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Provider's token account
+    #[account(mut)]
+    pub provider_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Service {
+    pub provider: Pubkey,
+    /// Key that signs usage reports, usually the provider's metering backend
+    pub meter_authority: Pubkey,
+    pub mint: Pubkey,
+    pub provider_token_account: Pubkey,
+    pub price_per_credit: u64,
+    pub low_balance_threshold: u64,
+    pub credits_sold: u64,
+    pub credits_used: u64,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl Service {
+    /// Token cost of `credits` at the current price
+    pub fn cost(&self, credits: u64) -> Result<u64> {
+        require!(credits > 0, ErrorCode::InvalidAmount);
+        credits
+            .checked_mul(self.price_per_credit)
+            .ok_or(error!(ErrorCode::ArithmeticOverflow))
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub struct AutoTopUp {
+    pub user_token_account: Pubkey,
+    pub credits: u64,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct CreditAccount {
+    pub service: Pubkey,
+    pub user: Pubkey,
+    pub balance: u64,
+    /// Highest usage report applied; later reports must use a larger id
+    pub last_report_id: u64,
+    pub auto_top_up: Option<AutoTopUp>,
+    pub total_purchased: u64,
+    pub total_used: u64,
+    pub bump: u8,
+}
+
+impl CreditAccount {
+    pub fn credit(&mut self, credits: u64) -> Result<()> {
+        self.balance = self
+            .balance
+            .checked_add(credits)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.total_purchased = self
+            .total_purchased
+            .checked_add(credits)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+
+    /// Apply one usage report. Reports that would overdraw are rejected
+    /// whole, so the meter can retry after a top-up.
+    pub fn debit(&mut self, report_id: u64, credits: u64) -> Result<()> {
+        require!(credits > 0, ErrorCode::InvalidAmount);
+        require!(report_id > self.last_report_id, ErrorCode::StaleReport);
+        self.balance = self
+            .balance
+            .checked_sub(credits)
+            .ok_or(ErrorCode::InsufficientCredits)?;
+        self.total_used = self
+            .total_used
+            .checked_add(credits)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.last_report_id = report_id;
+        Ok(())
+    }
+
+    pub fn is_low(&self, threshold: u64) -> bool {
+        self.balance < threshold
+    }
+
+    /// The top-up the keeper may buy now
+    pub fn check_top_up_due(&self, threshold: u64) -> Result<AutoTopUp> {
+        let top_up = self.auto_top_up.ok_or(ErrorCode::NoAutoTopUp)?;
+        require!(self.is_low(threshold), ErrorCode::BalanceNotLow);
+        Ok(top_up)
+    }
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServiceCreated {
+    pub service: Pubkey,
+    pub provider: Pubkey,
+    pub meter_authority: Pubkey,
+    pub price_per_credit: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreditsPurchased {
+    pub service: Pubkey,
+    pub user: Pubkey,
+    pub credits: u64,
+    pub cost: u64,
+    pub balance: u64,
+    /// Bought by the keeper's auto top-up rather than the user
+    pub auto: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsageReported {
+    pub service: Pubkey,
+    pub user: Pubkey,
+    pub report_id: u64,
+    pub credits: u64,
+    pub balance: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct LowBalance {
+    pub service: Pubkey,
+    pub user: Pubkey,
+    pub balance: u64,
+    pub auto_top_up: bool,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Amount must be greater than zero")]
+    InvalidAmount,
+    #[msg("Invalid token account - wrong program, owner or mint")]
+    InvalidTokenAccount,
+    #[msg("Not enough credits for this usage report")]
+    InsufficientCredits,
+    #[msg("Usage report id must be greater than the last one applied")]
+    StaleReport,
+    #[msg("No auto top-up is set up")]
+    NoAutoTopUp,
+    #[msg("Balance is not below the low-balance threshold")]
+    BalanceNotLow,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
@@ -0,0 +1,370 @@
+//! Native tests for the API credits program.
+//!
+//! Metering and top-up rules are pure methods on `Service` and
+//! `CreditAccount` and are tested directly. The instructions run end to end,
+//! token transfers included.
+
+use anchor_lang::error::ErrorCode as AnchorErrorCode;
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, Space, ToAccountMetas};
+use api_credits::{
+    accounts, instruction, AutoTopUp, CreditAccount, ErrorCode, Service, ID as PROGRAM_ID,
+};
+use test_harness::{assert_error, assert_success, Keypair, Signer, TestSvm, TransactionResult};
+
+/// 0.002 USDC per credit
+const PRICE: u64 = 2_000;
+const THRESHOLD: u64 = 100;
+
+fn service() -> Service {
+    Service {
+        provider: Pubkey::new_unique(),
+        meter_authority: Pubkey::new_unique(),
+        mint: Pubkey::new_unique(),
+        provider_token_account: Pubkey::new_unique(),
+        price_per_credit: PRICE,
+        low_balance_threshold: THRESHOLD,
+        credits_sold: 0,
+        credits_used: 0,
+        created_at: 0,
+        bump: 255,
+    }
+}
+
+fn credit_account(balance: u64) -> CreditAccount {
+    CreditAccount {
+        service: Pubkey::new_unique(),
+        user: Pubkey::new_unique(),
+        balance,
+        last_report_id: 0,
+        auto_top_up: None,
+        total_purchased: balance,
+        total_used: 0,
+        bump: 255,
+    }
+}
+
+#[test]
+fn credits_cost_the_service_price() {
+    let config = service();
+    assert_eq!(config.cost(500).unwrap(), 1_000_000);
+    assert_eq!(config.cost(0).unwrap_err(), ErrorCode::InvalidAmount.into());
+    assert_eq!(
+        config.cost(u64::MAX).unwrap_err(),
+        ErrorCode::ArithmeticOverflow.into()
+    );
+}
+
+#[test]
+fn usage_reports_apply_once_and_never_overdraw() {
+    let mut account = credit_account(150);
+    account.debit(1, 40).unwrap();
+    assert_eq!(account.balance, 110);
+    assert!(!account.is_low(THRESHOLD));
+
+    // Replayed or out-of-order reports are refused
+    assert_eq!(
+        account.debit(1, 40).unwrap_err(),
+        ErrorCode::StaleReport.into()
+    );
+
+    assert_eq!(
+        account.debit(2, 111).unwrap_err(),
+        ErrorCode::InsufficientCredits.into()
+    );
+    assert_eq!(account.last_report_id, 1);
+
+    account.debit(5, 20).unwrap();
+    assert_eq!(account.balance, 90);
+    assert_eq!(account.total_used, 60);
+    assert!(account.is_low(THRESHOLD));
+}
+
+#[test]
+fn top_ups_wait_for_a_low_balance() {
+    let mut account = credit_account(THRESHOLD);
+    assert_eq!(
+        account.check_top_up_due(THRESHOLD).unwrap_err(),
+        ErrorCode::NoAutoTopUp.into()
+    );
+
+    let top_up = AutoTopUp {
+        user_token_account: Pubkey::new_unique(),
+        credits: 1_000,
+    };
+    account.auto_top_up = Some(top_up);
+    assert_eq!(
+        account.check_top_up_due(THRESHOLD).unwrap_err(),
+        ErrorCode::BalanceNotLow.into()
+    );
+
+    account.balance = THRESHOLD - 1;
+    assert_eq!(account.check_top_up_due(THRESHOLD).unwrap(), top_up);
+}
+
+struct Fixture {
+    svm: TestSvm,
+    payer: Keypair,
+    provider: Keypair,
+    meter: Keypair,
+    user: Keypair,
+    service: Pubkey,
+    state: Service,
+    credit_account: Pubkey,
+    user_token_account: Pubkey,
+}
+
+impl Fixture {
+    /// A user holding `balance` credits, with auto top-up of 1 000 credits
+    fn new(balance: u64) -> Self {
+        let (mut svm, payer) = TestSvm::for_program(PROGRAM_ID, api_credits::entry);
+
+        let provider = Keypair::new();
+        let meter = Keypair::new();
+        let user = Keypair::new();
+
+        let mint = svm.create_mint(&Pubkey::new_unique(), 6);
+        let provider_token_account =
+            svm.create_associated_token_account(&provider.pubkey(), &mint, 0);
+        let user_token_account =
+            svm.create_associated_token_account(&user.pubkey(), &mint, 100_000_000);
+
+        let (service_address, service_bump) =
+            Pubkey::find_program_address(&[b"service", provider.pubkey().as_ref()], &PROGRAM_ID);
+        let state = Service {
+            provider: provider.pubkey(),
+            meter_authority: meter.pubkey(),
+            mint,
+            provider_token_account,
+            bump: service_bump,
+            ..service()
+        };
+        svm.set_anchor_account(service_address, &state, 8 + Service::INIT_SPACE);
+
+        let (account_address, account_bump) = Pubkey::find_program_address(
+            &[b"credits", service_address.as_ref(), user.pubkey().as_ref()],
+            &PROGRAM_ID,
+        );
+        let account = CreditAccount {
+            service: service_address,
+            user: user.pubkey(),
+            auto_top_up: Some(AutoTopUp {
+                user_token_account,
+                credits: 1_000,
+            }),
+            bump: account_bump,
+            ..credit_account(balance)
+        };
+        svm.set_anchor_account(account_address, &account, 8 + CreditAccount::INIT_SPACE);
+        // As `enable_auto_top_up` leaves it
+        svm.approve(&user_token_account, &account_address, u64::MAX);
+
+        Self {
+            svm,
+            payer,
+            provider,
+            meter,
+            user,
+            service: service_address,
+            state,
+            credit_account: account_address,
+            user_token_account,
+        }
+    }
+
+    fn report(&self, report_id: u64, credits: u64) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::ReportUsage {
+                service: self.service,
+                credit_account: self.credit_account,
+                meter_authority: self.meter.pubkey(),
+            }
+            .to_account_metas(None),
+            data: instruction::ReportUsage { report_id, credits }.data(),
+        }
+    }
+
+    fn buy(&self, credits: u64) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::BuyCredits {
+                service: self.service,
+                credit_account: self.credit_account,
+                user: self.user.pubkey(),
+                user_token_account: self.user_token_account,
+                provider_token_account: self.state.provider_token_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::BuyCredits { credits }.data(),
+        }
+    }
+
+    fn top_up(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::TopUp {
+                service: self.service,
+                credit_account: self.credit_account,
+                user_token_account: self.user_token_account,
+                provider_token_account: self.state.provider_token_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::TopUp {}.data(),
+        }
+    }
+
+    fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TransactionResult {
+        let payer = self.payer.insecure_clone();
+        self.svm.send_instructions(&[instruction], &payer, signers)
+    }
+
+    fn send_as_meter(&mut self, instruction: Instruction) -> TransactionResult {
+        let meter = self.meter.insecure_clone();
+        self.send(instruction, &[&meter])
+    }
+
+    fn send_as_user(&mut self, instruction: Instruction) -> TransactionResult {
+        let user = self.user.insecure_clone();
+        self.send(instruction, &[&user])
+    }
+
+    fn stored(&self) -> CreditAccount {
+        self.svm.get_anchor_account(&self.credit_account).unwrap()
+    }
+}
+
+#[test]
+fn only_the_meter_reports_usage() {
+    let mut fx = Fixture::new(500);
+
+    let forger = Keypair::new();
+    let mut forged = fx.report(1, 50);
+    forged.accounts[2].pubkey = forger.pubkey();
+    let result = fx.send(forged, &[&forger]);
+    assert_error(result, AnchorErrorCode::ConstraintHasOne);
+
+    let result = fx.send_as_meter(fx.report(1, 50));
+    assert!(result.is_ok(), "{result:#?}");
+    let account = fx.stored();
+    assert_eq!(account.balance, 450);
+    assert_eq!(account.last_report_id, 1);
+
+    fx.svm.expire_blockhash();
+    let result = fx.send_as_meter(fx.report(1, 50));
+    assert_error(result, ErrorCode::StaleReport);
+}
+
+#[test]
+fn usage_runs_the_balance_down_to_zero() {
+    let mut fx = Fixture::new(150);
+    let result = fx.send_as_meter(fx.report(1, 60));
+    assert!(result.is_ok(), "{result:#?}");
+    assert!(fx.stored().is_low(THRESHOLD));
+
+    let result = fx.send_as_meter(fx.report(2, 91));
+    assert_error(result, ErrorCode::InsufficientCredits);
+
+    let result = fx.send_as_meter(fx.report(3, 90));
+    assert!(result.is_ok(), "{result:#?}");
+    let account = fx.stored();
+    assert_eq!(account.balance, 0);
+    assert_eq!(account.total_used, 150);
+    let stored: Service = fx.svm.get_anchor_account(&fx.service).unwrap();
+    assert_eq!(stored.credits_used, 150);
+}
+
+#[test]
+fn keeper_tops_up_low_balances() {
+    let mut fx = Fixture::new(THRESHOLD);
+    let result = fx.send(fx.top_up(), &[]);
+    assert_error(result, ErrorCode::BalanceNotLow);
+
+    let result = fx.send_as_meter(fx.report(1, 1));
+    assert!(result.is_ok(), "{result:#?}");
+
+    let mut redirected = fx.top_up();
+    redirected.accounts[2].pubkey =
+        fx.svm
+            .create_token_account(&fx.user.pubkey(), &fx.state.mint, 100_000_000);
+    let result = fx.send(redirected, &[]);
+    assert_error(result, ErrorCode::InvalidTokenAccount);
+
+    fx.svm.expire_blockhash();
+    let result = fx.send(fx.top_up(), &[]);
+    assert_success(result);
+    assert_eq!(fx.stored().balance, THRESHOLD - 1 + 1_000);
+    assert_eq!(
+        fx.svm.token_balance(&fx.state.provider_token_account),
+        1_000 * PRICE
+    );
+}
+
+#[test]
+fn users_open_an_account_and_buy_credits() {
+    let mut fx = Fixture::new(0);
+    fx.svm.remove_account(&fx.credit_account);
+    let open = Instruction {
+        program_id: PROGRAM_ID,
+        accounts: accounts::OpenAccount {
+            service: fx.service,
+            credit_account: fx.credit_account,
+            user: fx.user.pubkey(),
+            payer: fx.payer.pubkey(),
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: instruction::OpenAccount {}.data(),
+    };
+    assert_success(fx.send_as_user(open));
+    let account = fx.stored();
+    assert_eq!(account.balance, 0);
+    assert!(account.auto_top_up.is_none());
+
+    let result = fx.send_as_user(fx.buy(0));
+    assert_error(result, ErrorCode::InvalidAmount);
+
+    let result = fx.send_as_user(fx.buy(5_000));
+    assert_success(result);
+    assert_eq!(fx.stored().balance, 5_000);
+    assert_eq!(
+        fx.svm.token_balance(&fx.state.provider_token_account),
+        5_000 * PRICE
+    );
+}
+
+#[test]
+fn provider_reprices_the_service() {
+    let mut fx = Fixture::new(0);
+    let update = |fx: &Fixture, provider: Pubkey, price_per_credit: u64| Instruction {
+        program_id: PROGRAM_ID,
+        accounts: accounts::UpdateService {
+            service: fx.service,
+            provider,
+        }
+        .to_account_metas(None),
+        data: instruction::UpdateService {
+            meter_authority: fx.meter.pubkey(),
+            price_per_credit,
+            low_balance_threshold: 0,
+        }
+        .data(),
+    };
+
+    let impostor = Keypair::new();
+    let result = fx.send(update(&fx, impostor.pubkey(), PRICE), &[&impostor]);
+    assert_error(result, AnchorErrorCode::ConstraintSeeds);
+
+    let provider = fx.provider.insecure_clone();
+    let result = fx.send(update(&fx, provider.pubkey(), 0), &[&provider]);
+    assert_error(result, ErrorCode::InvalidAmount);
+
+    let result = fx.send(update(&fx, provider.pubkey(), 2 * PRICE), &[&provider]);
+    assert!(result.is_ok(), "{result:#?}");
+    let stored: Service = fx.svm.get_anchor_account(&fx.service).unwrap();
+    assert_eq!(stored.price_per_credit, 2 * PRICE);
+    assert_eq!(stored.low_balance_threshold, 0);
+}
@@ -0,0 +1,484 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::invoke;
+use spl_token::instruction as token_instruction;
+use split_checkout::program::SplitCheckout;
+use split_checkout::Payee;
+use token_utils::token_account;
+
+declare_id!("C8Aiz71QmAajUxRYmmnNPEyf9gWJU7XNUA9TvJdZ9b7G");
+
+/// `split_id` of the split each donation owns; the donation PDA is its owner,
+/// so one is enough
+pub const SPLIT_ID: u64 = 0;
+
+#[program]
+pub mod charity_donations {
+    use super::*;
+
+    /// Give `amount_per_period` every `interval_seconds`, shared between
+    /// `charities` by their bps weights. The donation PDA becomes the
+    /// delegate of `donor_token_account` and the owner of a split-checkout
+    /// split holding the weights. Pass each charity's token account as a
+    /// remaining account, in order. The first donation is due at once.
+    pub fn create_donation<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CreateDonation<'info>>,
+        donation_id: u64,
+        charities: Vec<Payee>,
+        amount_per_period: u64,
+        interval_seconds: i64,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        Donation::check_params(amount_per_period, interval_seconds)?;
+
+        let donor = ctx.accounts.donor.key();
+        let mint = ctx.accounts.mint.key();
+        let donor_account = token_account(
+            &ctx.accounts.donor_token_account,
+            ErrorCode::InvalidTokenAccount,
+        )?;
+        require_keys_eq!(donor_account.owner, donor, ErrorCode::InvalidTokenAccount);
+        require_keys_eq!(donor_account.mint, mint, ErrorCode::InvalidTokenAccount);
+
+        let delegate_ix = token_instruction::approve(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.donor_token_account.key(),
+            &ctx.accounts.donation.key(),
+            &donor,
+            &[],
+            u64::MAX,
+        )?;
+
+        invoke(
+            &delegate_ix,
+            &[
+                ctx.accounts.donor_token_account.to_account_info(),
+                ctx.accounts.donation.to_account_info(),
+                ctx.accounts.donor.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        let id_bytes = donation_id.to_le_bytes();
+        let seeds = &[
+            b"donation",
+            donor.as_ref(),
+            id_bytes.as_ref(),
+            &[ctx.bumps.donation],
+        ];
+        let charity_count = charities.len() as u8;
+        split_checkout::cpi::create_split(
+            CpiContext::new_with_signer(
+                ctx.accounts.split_checkout_program.to_account_info(),
+                split_checkout::cpi::accounts::CreateSplit {
+                    split: ctx.accounts.split.to_account_info(),
+                    owner: ctx.accounts.donation.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    payer: ctx.accounts.payer.to_account_info(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                },
+                &[&seeds[..]],
+            )
+            .with_remaining_accounts(ctx.remaining_accounts.to_vec()),
+            SPLIT_ID,
+            charities,
+            0,
+        )?;
+
+        let donation = &mut ctx.accounts.donation;
+        donation.donor = donor;
+        donation.donation_id = donation_id;
+        donation.mint = mint;
+        donation.donor_token_account = ctx.accounts.donor_token_account.key();
+        donation.split = ctx.accounts.split.key();
+        donation.amount_per_period = amount_per_period;
+        donation.interval_seconds = interval_seconds;
+        donation.next_donation_at = clock.unix_timestamp;
+        donation.donation_count = 0;
+        donation.total_donated = 0;
+        donation.created_at = clock.unix_timestamp;
+        donation.bump = ctx.bumps.donation;
+
+        emit!(DonationCreated {
+            donation: donation.key(),
+            donor,
+            split: donation.split,
+            charity_count,
+            amount_per_period,
+            interval_seconds,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("Donation created: {} tokens per period", amount_per_period);
+        msg!("Shared between {} charities", charity_count);
+        msg!("Token delegation approved");
+
+        Ok(())
+    }
+
+    /// Replace the charities and their weights from the next donation on.
+    /// Pass each charity's token account as a remaining account, in order.
+    pub fn update_charities<'info>(
+        ctx: Context<'_, '_, 'info, 'info, UpdateCharities<'info>>,
+        charities: Vec<Payee>,
+    ) -> Result<()> {
+        let donation = &ctx.accounts.donation;
+        let id_bytes = donation.donation_id.to_le_bytes();
+        let seeds = &[
+            b"donation",
+            donation.donor.as_ref(),
+            id_bytes.as_ref(),
+            &[donation.bump],
+        ];
+        let charity_count = charities.len() as u8;
+        split_checkout::cpi::update_split(
+            CpiContext::new_with_signer(
+                ctx.accounts.split_checkout_program.to_account_info(),
+                split_checkout::cpi::accounts::UpdateSplit {
+                    split: ctx.accounts.split.to_account_info(),
+                    owner: ctx.accounts.donation.to_account_info(),
+                },
+                &[&seeds[..]],
+            )
+            .with_remaining_accounts(ctx.remaining_accounts.to_vec()),
+            charities,
+            0,
+        )?;
+
+        emit!(CharitiesUpdated {
+            donation: ctx.accounts.donation.key(),
+            charity_count,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Charities updated: {}", charity_count);
+
+        Ok(())
+    }
+
+    /// Change the amount or interval of later donations
+    pub fn update_amount(
+        ctx: Context<UpdateDonation>,
+        amount_per_period: u64,
+        interval_seconds: i64,
+    ) -> Result<()> {
+        Donation::check_params(amount_per_period, interval_seconds)?;
+
+        let donation = &mut ctx.accounts.donation;
+        donation.amount_per_period = amount_per_period;
+        donation.interval_seconds = interval_seconds;
+
+        msg!("Donation updated: {} tokens per period", amount_per_period);
+
+        Ok(())
+    }
+
+    /// Make a due donation. Permissionless, like `charge_subscription`: a
+    /// split-checkout `checkout` of `amount_per_period` from the donor's
+    /// token account, with the donation PDA signing as the buyer. Remaining
+    /// accounts are the charities' token accounts in the split's order.
+    pub fn donate<'info>(ctx: Context<'_, '_, 'info, 'info, Donate<'info>>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let donation = &ctx.accounts.donation;
+        donation.check_due(now)?;
+
+        let amount = donation.amount_per_period;
+        let id_bytes = donation.donation_id.to_le_bytes();
+        let seeds = &[
+            b"donation",
+            donation.donor.as_ref(),
+            id_bytes.as_ref(),
+            &[donation.bump],
+        ];
+        split_checkout::cpi::checkout(
+            CpiContext::new_with_signer(
+                ctx.accounts.split_checkout_program.to_account_info(),
+                split_checkout::cpi::accounts::Checkout {
+                    split: ctx.accounts.split.to_account_info(),
+                    buyer: ctx.accounts.donation.to_account_info(),
+                    buyer_token_account: ctx.accounts.donor_token_account.to_account_info(),
+                    referrer_token_account: None,
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                },
+                &[&seeds[..]],
+            )
+            .with_remaining_accounts(ctx.remaining_accounts.to_vec()),
+            amount,
+        )?;
+
+        let donation = &mut ctx.accounts.donation;
+        donation.record_donation(now)?;
+
+        emit!(DonationMade {
+            donation: donation.key(),
+            donor: donation.donor,
+            amount,
+            donation_count: donation.donation_count,
+            timestamp: now,
+        });
+
+        msg!("Donation {}: {} tokens", donation.donation_count, amount);
+        msg!("Next donation at {}", donation.next_donation_at);
+
+        Ok(())
+    }
+
+    /// Stop giving: revoke the delegation and close the donation. The split
+    /// stays behind, since split-checkout has no way to close one.
+    pub fn cancel_donation(ctx: Context<CancelDonation>) -> Result<()> {
+        let revoke_ix = token_instruction::revoke(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.donor_token_account.key(),
+            &ctx.accounts.donor.key(),
+            &[],
+        )?;
+        invoke(
+            &revoke_ix,
+            &[
+                ctx.accounts.donor_token_account.to_account_info(),
+                ctx.accounts.donor.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        let donation = &ctx.accounts.donation;
+        emit!(DonationCancelled {
+            donation: donation.key(),
+            donor: donation.donor,
+            donation_count: donation.donation_count,
+            total_donated: donation.total_donated,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!(
+            "Donation cancelled after {} donations",
+            donation.donation_count
+        );
+        msg!("Token delegation revoked");
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(donation_id: u64)]
+pub struct CreateDonation<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Donation::INIT_SPACE,
+        seeds = [b"donation", donor.key().as_ref(), donation_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub donation: Account<'info, Donation>,
+
+    /// CHECK: The donation's split, created by split-checkout at
+    /// `["split", donation, SPLIT_ID]`
+    #[account(mut)]
+    pub split: UncheckedAccount<'info>,
+
+    pub donor: Signer<'info>,
+
+    /// CHECK: Token mint (USDC)
+    pub mint: UncheckedAccount<'info>,
+
+    /// CHECK: Donor's token account, checked in the handler and delegated to the donation PDA
+    #[account(mut)]
+    pub donor_token_account: UncheckedAccount<'info>,
+
+    /// Fee and rent payer, usually a relayer
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    pub split_checkout_program: Program<'info, SplitCheckout>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateCharities<'info> {
+    #[account(
+        seeds = [b"donation", donor.key().as_ref(), donation.donation_id.to_le_bytes().as_ref()],
+        bump = donation.bump,
+        has_one = donor,
+        has_one = split
+    )]
+    pub donation: Account<'info, Donation>,
+
+    /// CHECK: The donation's split
+    #[account(mut)]
+    pub split: UncheckedAccount<'info>,
+
+    pub donor: Signer<'info>,
+
+    pub split_checkout_program: Program<'info, SplitCheckout>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateDonation<'info> {
+    #[account(
+        mut,
+        seeds = [b"donation", donor.key().as_ref(), donation.donation_id.to_le_bytes().as_ref()],
+        bump = donation.bump,
+        has_one = donor
+    )]
+    pub donation: Account<'info, Donation>,
+
+    pub donor: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Donate<'info> {
+    #[account(
+        mut,
+        seeds = [b"donation", donation.donor.as_ref(), donation.donation_id.to_le_bytes().as_ref()],
+        bump = donation.bump,
+        has_one = split,
+        has_one = donor_token_account
+    )]
+    pub donation: Account<'info, Donation>,
+
+    /// CHECK: The donation's split
+    #[account(mut)]
+    pub split: UncheckedAccount<'info>,
+
+    /// CHECK: The delegated token account
+    #[account(mut)]
+    pub donor_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    pub split_checkout_program: Program<'info, SplitCheckout>,
+}
+
+#[derive(Accounts)]
+pub struct CancelDonation<'info> {
+    #[account(
+        mut,
+        seeds = [b"donation", donor.key().as_ref(), donation.donation_id.to_le_bytes().as_ref()],
+        bump = donation.bump,
+        has_one = donor,
+        has_one = donor_token_account,
+        close = donor
+    )]
+    pub donation: Account<'info, Donation>,
+
+    #[account(mut)]
+    pub donor: Signer<'info>,
+
+    /// CHECK: The delegated token account
+    #[account(mut)]
+    pub donor_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Donation {
+    pub donor: Pubkey,
+    pub donation_id: u64,
+    pub mint: Pubkey,
+    /// Delegated to this PDA; each donation is paid from it
+    pub donor_token_account: Pubkey,
+    /// Split-checkout split owned by this PDA; holds the charities and weights
+    pub split: Pubkey,
+    pub amount_per_period: u64,
+    pub interval_seconds: i64,
+    pub next_donation_at: i64,
+    pub donation_count: u64,
+    pub total_donated: u64,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl Donation {
+    pub fn check_params(amount_per_period: u64, interval_seconds: i64) -> Result<()> {
+        require!(amount_per_period > 0, ErrorCode::InvalidAmount);
+        require!(interval_seconds > 0, ErrorCode::InvalidInterval);
+        Ok(())
+    }
+
+    pub fn check_due(&self, now: i64) -> Result<()> {
+        require!(now >= self.next_donation_at, ErrorCode::DonationNotDue);
+        Ok(())
+    }
+
+    /// Book a donation made at `now`. The next one is scheduled from `now`,
+    /// so a late keeper gives once rather than catching up in a burst.
+    pub fn record_donation(&mut self, now: i64) -> Result<()> {
+        self.donation_count = self
+            .donation_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.total_donated = self
+            .total_donated
+            .checked_add(self.amount_per_period)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.next_donation_at = now
+            .checked_add(self.interval_seconds)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DonationCreated {
+    pub donation: Pubkey,
+    pub donor: Pubkey,
+    pub split: Pubkey,
+    pub charity_count: u8,
+    pub amount_per_period: u64,
+    pub interval_seconds: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CharitiesUpdated {
+    pub donation: Pubkey,
+    pub charity_count: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DonationMade {
+    pub donation: Pubkey,
+    pub donor: Pubkey,
+    pub amount: u64,
+    pub donation_count: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DonationCancelled {
+    pub donation: Pubkey,
+    pub donor: Pubkey,
+    pub donation_count: u64,
+    pub total_donated: u64,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Amount must be greater than zero")]
+    InvalidAmount,
+    #[msg("Interval must be positive")]
+    InvalidInterval,
+    #[msg("Invalid token account - wrong program, owner or mint")]
+    InvalidTokenAccount,
+    #[msg("Next donation is not due yet")]
+    DonationNotDue,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
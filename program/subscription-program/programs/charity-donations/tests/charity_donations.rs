@@ -0,0 +1,387 @@
+//! Native tests for the charity donations program.
+//!
+//! Scheduling is a pure method and is tested directly. Instruction tests run
+//! end to end, through the real split-checkout program, and check what each
+//! charity received.
+
+use anchor_lang::error::ErrorCode as AnchorErrorCode;
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::{system_program, InstructionData, Space, ToAccountMetas};
+use charity_donations::{accounts, instruction, Donation, ErrorCode, ID as PROGRAM_ID, SPLIT_ID};
+use split_checkout::{Payee, SplitConfig};
+use test_harness::{assert_error, assert_success, Keypair, Signer, TestSvm, TransactionResult};
+
+const AMOUNT: u64 = 30_000_000;
+const MONTH: i64 = 30 * 86_400;
+
+fn pledge() -> Donation {
+    Donation {
+        donor: Pubkey::new_unique(),
+        donation_id: 0,
+        mint: Pubkey::new_unique(),
+        donor_token_account: Pubkey::new_unique(),
+        split: Pubkey::new_unique(),
+        amount_per_period: AMOUNT,
+        interval_seconds: MONTH,
+        next_donation_at: 1_000,
+        donation_count: 0,
+        total_donated: 0,
+        created_at: 1_000,
+        bump: 255,
+    }
+}
+
+#[test]
+fn params_must_be_positive() {
+    Donation::check_params(AMOUNT, MONTH).unwrap();
+    assert_eq!(
+        Donation::check_params(0, MONTH).unwrap_err(),
+        ErrorCode::InvalidAmount.into()
+    );
+    assert_eq!(
+        Donation::check_params(AMOUNT, -1).unwrap_err(),
+        ErrorCode::InvalidInterval.into()
+    );
+}
+
+#[test]
+fn donations_are_booked_and_rescheduled_from_when_they_ran() {
+    let mut donation = pledge();
+    assert_eq!(
+        donation.check_due(999).unwrap_err(),
+        ErrorCode::DonationNotDue.into()
+    );
+    donation.check_due(1_000).unwrap();
+
+    donation.record_donation(1_000).unwrap();
+    assert_eq!(donation.donation_count, 1);
+    assert_eq!(donation.total_donated, AMOUNT);
+    assert_eq!(donation.next_donation_at, 1_000 + MONTH);
+
+    // A keeper two months late gives once, not twice
+    let late = 1_000 + 2 * MONTH;
+    donation.record_donation(late).unwrap();
+    assert_eq!(donation.donation_count, 2);
+    assert_eq!(donation.total_donated, 2 * AMOUNT);
+    assert_eq!(donation.next_donation_at, late + MONTH);
+}
+
+struct Fixture {
+    svm: TestSvm,
+    payer: Keypair,
+    donor: Keypair,
+    donation: Pubkey,
+    state: Donation,
+    charities: Vec<Payee>,
+}
+
+impl Fixture {
+    /// A donation to two charities whose next donation is due at
+    /// `next_donation_at`
+    fn new(next_donation_at: i64) -> Self {
+        let (mut svm, payer) = TestSvm::for_program(PROGRAM_ID, charity_donations::entry);
+        svm.add_program(split_checkout::ID, split_checkout::entry);
+
+        let donor = Keypair::new();
+        svm.airdrop(&donor.pubkey(), 1_000_000_000);
+
+        let mint = svm.create_mint(&Pubkey::new_unique(), 6);
+        let (donation, bump) = Pubkey::find_program_address(
+            &[b"donation", donor.pubkey().as_ref(), &0u64.to_le_bytes()],
+            &PROGRAM_ID,
+        );
+        let donor_token_account =
+            svm.create_associated_token_account(&donor.pubkey(), &mint, 500_000_000);
+        svm.approve(&donor_token_account, &donation, u64::MAX);
+
+        let charities = vec![
+            Payee {
+                token_account: svm.create_token_account(&Pubkey::new_unique(), &mint, 0),
+                bps: 7_000,
+            },
+            Payee {
+                token_account: svm.create_token_account(&Pubkey::new_unique(), &mint, 0),
+                bps: 3_000,
+            },
+        ];
+        let (split, split_bump) = Pubkey::find_program_address(
+            &[b"split", donation.as_ref(), &SPLIT_ID.to_le_bytes()],
+            &split_checkout::ID,
+        );
+        svm.set_anchor_account(
+            split,
+            &SplitConfig {
+                owner: donation,
+                mint,
+                split_id: SPLIT_ID,
+                payees: charities.clone(),
+                referral_bps: 0,
+                total_processed: 0,
+                checkout_count: 0,
+                created_at: 0,
+                bump: split_bump,
+            },
+            8 + SplitConfig::INIT_SPACE,
+        );
+
+        let state = Donation {
+            donor: donor.pubkey(),
+            mint,
+            donor_token_account,
+            split,
+            next_donation_at,
+            bump,
+            ..pledge()
+        };
+        svm.set_anchor_account(donation, &state, 8 + Donation::INIT_SPACE);
+
+        Self {
+            svm,
+            payer,
+            donor,
+            donation,
+            state,
+            charities,
+        }
+    }
+
+    fn now(&self) -> i64 {
+        self.svm.clock().unix_timestamp
+    }
+
+    fn with_charities(mut ix: Instruction, charities: &[Payee]) -> Instruction {
+        ix.accounts.extend(
+            charities
+                .iter()
+                .map(|charity| AccountMeta::new(charity.token_account, false)),
+        );
+        ix
+    }
+
+    fn create(&self, charities: &[Payee]) -> Instruction {
+        let ix = Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CreateDonation {
+                donation: self.donation,
+                split: self.state.split,
+                donor: self.donor.pubkey(),
+                mint: self.state.mint,
+                donor_token_account: self.state.donor_token_account,
+                payer: self.payer.pubkey(),
+                token_program: spl_token::ID,
+                system_program: system_program::ID,
+                split_checkout_program: split_checkout::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CreateDonation {
+                donation_id: 0,
+                charities: charities.to_vec(),
+                amount_per_period: AMOUNT,
+                interval_seconds: MONTH,
+            }
+            .data(),
+        };
+        Self::with_charities(ix, charities)
+    }
+
+    fn donate(&self, split: Pubkey) -> Instruction {
+        let ix = Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::Donate {
+                donation: self.donation,
+                split,
+                donor_token_account: self.state.donor_token_account,
+                token_program: spl_token::ID,
+                split_checkout_program: split_checkout::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::Donate {}.data(),
+        };
+        Self::with_charities(ix, &self.charities)
+    }
+
+    fn update_charities(&self, donor: Pubkey, charities: &[Payee]) -> Instruction {
+        let ix = Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::UpdateCharities {
+                donation: self.donation,
+                split: self.state.split,
+                donor,
+                split_checkout_program: split_checkout::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::UpdateCharities {
+                charities: charities.to_vec(),
+            }
+            .data(),
+        };
+        Self::with_charities(ix, charities)
+    }
+
+    fn update_amount(&self, donor: Pubkey, amount_per_period: u64) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::UpdateDonation {
+                donation: self.donation,
+                donor,
+            }
+            .to_account_metas(None),
+            data: instruction::UpdateAmount {
+                amount_per_period,
+                interval_seconds: MONTH,
+            }
+            .data(),
+        }
+    }
+
+    fn cancel(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CancelDonation {
+                donation: self.donation,
+                donor: self.donor.pubkey(),
+                donor_token_account: self.state.donor_token_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CancelDonation {}.data(),
+        }
+    }
+
+    fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TransactionResult {
+        let payer = self.payer.insecure_clone();
+        self.svm.send_instructions(&[instruction], &payer, signers)
+    }
+
+    fn send_as_donor(&mut self, instruction: Instruction) -> TransactionResult {
+        let donor = self.donor.insecure_clone();
+        self.send(instruction, &[&donor])
+    }
+
+    fn donation(&self) -> Donation {
+        self.svm.get_anchor_account(&self.donation).unwrap()
+    }
+
+    fn split(&self) -> SplitConfig {
+        self.svm.get_anchor_account(&self.state.split).unwrap()
+    }
+
+    fn received(&self) -> Vec<u64> {
+        self.charities
+            .iter()
+            .map(|charity| self.svm.token_balance(&charity.token_account))
+            .collect()
+    }
+}
+
+#[test]
+fn creating_delegates_and_opens_the_split() {
+    let mut fx = Fixture::new(0);
+    fx.svm.remove_account(&fx.donation);
+    fx.svm.remove_account(&fx.state.split);
+    fx.svm.revoke(&fx.state.donor_token_account);
+    let charities = fx.charities.clone();
+    let lopsided = vec![
+        charities[0],
+        Payee {
+            bps: 1,
+            ..charities[1]
+        },
+    ];
+    // Weights that do not add up are rejected by split-checkout
+    let result = fx.send_as_donor(fx.create(&lopsided));
+    assert_error(result, split_checkout::ErrorCode::InvalidShares);
+
+    fx.svm.expire_blockhash();
+    let result = fx.send_as_donor(fx.create(&charities));
+    assert_success(result);
+    let account = fx
+        .svm
+        .get_token_account(&fx.state.donor_token_account)
+        .unwrap();
+    assert_eq!(account.delegate, Some(fx.donation).into());
+    let split = fx.split();
+    assert_eq!(split.owner, fx.donation);
+    assert_eq!(split.payees, charities);
+    let donation = fx.donation();
+    assert_eq!(donation.split, fx.state.split);
+    // The first donation is due at once
+    donation.check_due(fx.now()).unwrap();
+}
+
+#[test]
+fn donations_run_once_due() {
+    let now = Fixture::new(0).now();
+    let mut fx = Fixture::new(now + 1);
+    let result = fx.send(fx.donate(fx.state.split), &[]);
+    assert_error(result, ErrorCode::DonationNotDue);
+
+    fx.svm.warp_to_timestamp(now + 1);
+    let result = fx.send(fx.donate(fx.state.split), &[]);
+    assert_success(result);
+    assert_eq!(fx.received(), vec![21_000_000, 9_000_000]);
+    assert_eq!(
+        fx.svm.token_balance(&fx.state.donor_token_account),
+        500_000_000 - AMOUNT
+    );
+    let donation = fx.donation();
+    assert_eq!(donation.total_donated, AMOUNT);
+    assert_eq!(donation.next_donation_at, now + 1 + MONTH);
+}
+
+#[test]
+fn donations_only_pay_through_their_own_split() {
+    let mut fx = Fixture::new(0);
+    let result = fx.send(fx.donate(Pubkey::new_unique()), &[]);
+    assert_error(result, AnchorErrorCode::ConstraintHasOne);
+}
+
+#[test]
+fn only_the_donor_edits_the_donation() {
+    let mut fx = Fixture::new(0);
+    let stranger = Keypair::new();
+    let charities = fx.charities.clone();
+    let result = fx.send(
+        fx.update_charities(stranger.pubkey(), &charities),
+        &[&stranger],
+    );
+    assert!(result.is_err());
+    let result = fx.send(fx.update_amount(stranger.pubkey(), 1), &[&stranger]);
+    assert!(result.is_err());
+
+    let reweighted = vec![
+        Payee {
+            bps: 5_000,
+            ..charities[0]
+        },
+        Payee {
+            bps: 5_000,
+            ..charities[1]
+        },
+    ];
+    let result = fx.send_as_donor(fx.update_charities(fx.donor.pubkey(), &reweighted));
+    assert_success(result);
+    assert_eq!(fx.split().payees, reweighted);
+
+    let result = fx.send_as_donor(fx.update_amount(fx.donor.pubkey(), 0));
+    assert_error(result, ErrorCode::InvalidAmount);
+    let result = fx.send_as_donor(fx.update_amount(fx.donor.pubkey(), 10_000_000));
+    assert!(result.is_ok(), "{result:#?}");
+    assert_eq!(fx.donation().amount_per_period, 10_000_000);
+}
+
+#[test]
+fn cancelling_revokes_and_closes_the_donation() {
+    let mut fx = Fixture::new(0);
+    let result = fx.send_as_donor(fx.cancel());
+    assert_success(result);
+    let account = fx
+        .svm
+        .get_token_account(&fx.state.donor_token_account)
+        .unwrap();
+    assert!(account.delegate.is_none());
+    assert!(fx.svm.get_account(&fx.donation).is_none());
+    // split-checkout cannot close the split
+    assert!(fx.svm.get_account(&fx.state.split).is_some());
+}
@@ -0,0 +1,840 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use spl_token::instruction as token_instruction;
+use subscription_program::Interval;
+use token_utils::token_account;
+
+declare_id!("AKn8fW95eEzhcMJPvQRkACVn6aXSJPDviX9vDrjSLJXn");
+
+/// Light Protocol's system program, which owns the state trees' bookkeeping
+/// and verifies validity proofs
+pub const LIGHT_SYSTEM_PROGRAM_ID: Pubkey = pubkey!("SySTEM1eSU2p4BGQfQpjtqNm9GaN4Rhbf2RkZt5WXVs");
+
+/// Light's account compression program, which owns the Merkle trees
+pub const ACCOUNT_COMPRESSION_PROGRAM_ID: Pubkey =
+    pubkey!("compr6CUsB5m2jS4Y3831ztGSTnDpnKJTKS95d64XVq");
+
+/// The noop program Light logs compressed account data through
+pub const NOOP_PROGRAM_ID: Pubkey = pubkey!("noopb9bkMVfRPU8AsbpTUg8AQkHtKwMYZiFUjNRtMmV");
+
+/// Light's public address tree. Addresses are only unique within a tree, so
+/// pinning one keeps a (user, merchant) pair to one subscription.
+pub const ADDRESS_TREE: Pubkey = pubkey!("amt1Ayt45jfbdw5YSo7iz6WZxUmnZsQTYXy82hVwyC2");
+
+/// Anchor discriminator of the Light system program's `invoke_cpi`
+pub const INVOKE_CPI_DISCRIMINATOR: [u8; 8] = [49, 212, 191, 129, 39, 194, 43, 196];
+
+/// Seed of the PDA that signs this program's Light CPIs, as Light expects
+pub const CPI_AUTHORITY_SEED: &[u8] = b"cpi_authority";
+
+/// Seed of the per-subscription delegate; also the address seed
+pub const SUBSCRIPTION_SEED: &[u8] = b"subscription";
+
+#[program]
+pub mod compressed_subscriptions {
+    use super::*;
+
+    /// Open a subscription as a compressed account and charge the first
+    /// period. Nothing is allocated on-chain: the state is a leaf in a Light
+    /// state tree, and its address is created in `ADDRESS_TREE` under a
+    /// validity proof that it did not exist yet.
+    #[allow(clippy::too_many_arguments)]
+    pub fn open_subscription<'info>(
+        ctx: Context<'_, '_, '_, 'info, OpenSubscription<'info>>,
+        proof: CompressedProof,
+        address_tree: PackedAddressTreeInfo,
+        output_tree_index: u8,
+        amount_per_period: u64,
+        interval_seconds: i64,
+        expires_at: Option<i64>,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let authority = ctx.accounts.authority.key();
+        let recipient = ctx.accounts.recipient.key();
+        let mint = ctx.accounts.token_mint.key();
+
+        let user_token = token_account(
+            &ctx.accounts.user_token_account,
+            ErrorCode::InvalidTokenAccount,
+        )?;
+        require!(
+            user_token.owner == authority && user_token.mint == mint,
+            ErrorCode::InvalidTokenAccount
+        );
+        let recipient_token = token_account(
+            &ctx.accounts.recipient_token_account,
+            ErrorCode::InvalidTokenAccount,
+        )?;
+        require_keys_eq!(recipient_token.mint, mint, ErrorCode::InvalidTokenAccount);
+
+        let tree = ctx
+            .remaining_accounts
+            .get(address_tree.address_merkle_tree_pubkey_index as usize)
+            .ok_or(ErrorCode::InvalidAddressTree)?;
+        require_keys_eq!(tree.key(), ADDRESS_TREE, ErrorCode::InvalidAddressTree);
+
+        let state = CompressedSubscription::open(
+            authority,
+            recipient,
+            ctx.accounts.user_token_account.key(),
+            ctx.accounts.recipient_token_account.key(),
+            mint,
+            amount_per_period,
+            interval_seconds,
+            expires_at,
+            now,
+        )?;
+        let seed = address_seed(&authority, &recipient);
+        let address = derive_address(&seed, &ADDRESS_TREE);
+
+        let approve_ix = token_instruction::approve(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.user_token_account.key(),
+            &ctx.accounts.delegate.key(),
+            &authority,
+            &[],
+            u64::MAX,
+        )?;
+        invoke(
+            &approve_ix,
+            &[
+                ctx.accounts.user_token_account.to_account_info(),
+                ctx.accounts.delegate.to_account_info(),
+                ctx.accounts.authority.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        transfer(
+            &ctx.accounts.token_program,
+            &ctx.accounts.user_token_account,
+            &ctx.accounts.recipient_token_account,
+            &ctx.accounts.delegate,
+            &state,
+            ctx.bumps.delegate,
+        )?;
+
+        invoke_light(
+            &ctx.accounts.light,
+            ctx.remaining_accounts,
+            ctx.bumps.light.cpi_authority,
+            InstructionDataInvokeCpi {
+                proof: Some(proof),
+                new_address_params: vec![NewAddressParamsPacked {
+                    seed,
+                    address_queue_account_index: address_tree.address_queue_pubkey_index,
+                    address_merkle_tree_account_index: address_tree
+                        .address_merkle_tree_pubkey_index,
+                    address_merkle_tree_root_index: address_tree.root_index,
+                }],
+                output_compressed_accounts: vec![OutputCompressedAccountWithPackedContext {
+                    compressed_account: state.output_account(address)?,
+                    merkle_tree_index: output_tree_index,
+                }],
+                ..Default::default()
+            },
+        )?;
+
+        emit!(CompressedSubscriptionOpened {
+            address,
+            authority,
+            recipient,
+            amount_per_period,
+            interval_seconds,
+            next_charge_at: state.next_charge_at,
+            timestamp: now,
+        });
+
+        msg!("Compressed subscription opened");
+
+        Ok(())
+    }
+
+    /// Charge a due period. The caller passes the current state, which the
+    /// program hashes into the input account: the Light system program only
+    /// accepts it if that hash is the leaf `account` points at, so a forged
+    /// state fails the proof. The old leaf is nullified and the charged state
+    /// appended. Permissionless, like the subscription program's charge.
+    pub fn charge_subscription<'info>(
+        ctx: Context<'_, '_, '_, 'info, ChargeSubscription<'info>>,
+        proof: CompressedProof,
+        account: CompressedAccountMeta,
+        current: CompressedSubscription,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        current.check_chargeable(now)?;
+
+        let mut next = current.clone();
+        next.record_charge(now)?;
+
+        transfer(
+            &ctx.accounts.token_program,
+            &ctx.accounts.user_token_account,
+            &ctx.accounts.recipient_token_account,
+            &ctx.accounts.delegate,
+            &current,
+            ctx.bumps.delegate,
+        )?;
+
+        invoke_light(
+            &ctx.accounts.light,
+            ctx.remaining_accounts,
+            ctx.bumps.light.cpi_authority,
+            InstructionDataInvokeCpi {
+                proof: Some(proof),
+                input_compressed_accounts_with_merkle_context: vec![
+                    current.input_account(&account)?
+                ],
+                output_compressed_accounts: vec![OutputCompressedAccountWithPackedContext {
+                    compressed_account: next.output_account(account.address)?,
+                    merkle_tree_index: account.output_state_tree_index,
+                }],
+                ..Default::default()
+            },
+        )?;
+
+        emit!(CompressedSubscriptionCharged {
+            address: account.address,
+            amount: current.amount_per_period,
+            total_charged: next.total_charged,
+            next_charge_at: next.next_charge_at,
+            timestamp: now,
+        });
+
+        msg!("Charged {} tokens", current.amount_per_period);
+
+        Ok(())
+    }
+
+    /// End the subscription: revoke the delegation and nullify the leaf
+    /// without a successor. There is no rent to refund.
+    pub fn cancel_subscription<'info>(
+        ctx: Context<'_, '_, '_, 'info, CancelSubscription<'info>>,
+        proof: CompressedProof,
+        account: CompressedAccountMeta,
+        current: CompressedSubscription,
+    ) -> Result<()> {
+        let revoke_ix = token_instruction::revoke(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.user_token_account.key(),
+            &ctx.accounts.authority.key(),
+            &[],
+        )?;
+        invoke(
+            &revoke_ix,
+            &[
+                ctx.accounts.user_token_account.to_account_info(),
+                ctx.accounts.authority.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        invoke_light(
+            &ctx.accounts.light,
+            ctx.remaining_accounts,
+            ctx.bumps.light.cpi_authority,
+            InstructionDataInvokeCpi {
+                proof: Some(proof),
+                input_compressed_accounts_with_merkle_context: vec![
+                    current.input_account(&account)?
+                ],
+                ..Default::default()
+            },
+        )?;
+
+        emit!(CompressedSubscriptionCancelled {
+            address: account.address,
+            authority: current.authority,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Compressed subscription cancelled");
+
+        Ok(())
+    }
+}
+
+/// Accounts every Light CPI needs, in the order the recipe takes them; the
+/// state and address trees follow as remaining accounts
+#[derive(Accounts)]
+pub struct LightSystemAccounts<'info> {
+    /// Pays the transaction and Light's fees
+    #[account(mut)]
+    pub fee_payer: Signer<'info>,
+
+    /// CHECK: signs for this program's compressed accounts
+    #[account(seeds = [CPI_AUTHORITY_SEED], bump)]
+    pub cpi_authority: UncheckedAccount<'info>,
+
+    /// CHECK: Light's registration of its system program; checked by Light
+    pub registered_program_pda: UncheckedAccount<'info>,
+
+    /// CHECK: program ID
+    #[account(address = NOOP_PROGRAM_ID)]
+    pub noop_program: UncheckedAccount<'info>,
+
+    /// CHECK: the system program's authority over the trees; checked by Light
+    pub account_compression_authority: UncheckedAccount<'info>,
+
+    /// CHECK: program ID
+    #[account(address = ACCOUNT_COMPRESSION_PROGRAM_ID)]
+    pub account_compression_program: UncheckedAccount<'info>,
+
+    /// CHECK: this program, which Light records as the invoking program
+    #[account(address = crate::ID)]
+    pub self_program: UncheckedAccount<'info>,
+
+    /// CHECK: program ID
+    #[account(address = LIGHT_SYSTEM_PROGRAM_ID)]
+    pub light_system_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct OpenSubscription<'info> {
+    pub authority: Signer<'info>,
+
+    /// CHECK: the merchant; only its token account is used
+    pub recipient: UncheckedAccount<'info>,
+
+    /// CHECK: unpacked and checked against `authority` and `token_mint`
+    #[account(mut)]
+    pub user_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: unpacked and checked against `token_mint`
+    #[account(mut)]
+    pub recipient_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: the mint both token accounts must hold
+    pub token_mint: UncheckedAccount<'info>,
+
+    /// CHECK: holds the delegation; no account lives here
+    #[account(
+        seeds = [SUBSCRIPTION_SEED, authority.key().as_ref(), recipient.key().as_ref()],
+        bump
+    )]
+    pub delegate: UncheckedAccount<'info>,
+
+    /// CHECK: program ID
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    pub light: LightSystemAccounts<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(proof: CompressedProof, account: CompressedAccountMeta, current: CompressedSubscription)]
+pub struct ChargeSubscription<'info> {
+    /// CHECK: the state's token account
+    #[account(mut, address = current.user_token_account @ ErrorCode::InvalidTokenAccount)]
+    pub user_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: the state's token account
+    #[account(mut, address = current.recipient_token_account @ ErrorCode::InvalidTokenAccount)]
+    pub recipient_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: holds the delegation; no account lives here
+    #[account(
+        seeds = [SUBSCRIPTION_SEED, current.authority.as_ref(), current.recipient.as_ref()],
+        bump
+    )]
+    pub delegate: UncheckedAccount<'info>,
+
+    /// CHECK: program ID
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    pub light: LightSystemAccounts<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(proof: CompressedProof, account: CompressedAccountMeta, current: CompressedSubscription)]
+pub struct CancelSubscription<'info> {
+    #[account(address = current.authority @ ErrorCode::Unauthorized)]
+    pub authority: Signer<'info>,
+
+    /// CHECK: the state's token account
+    #[account(mut, address = current.user_token_account @ ErrorCode::InvalidTokenAccount)]
+    pub user_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: program ID
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    pub light: LightSystemAccounts<'info>,
+}
+
+/// Subscription state, stored as the data of a compressed account rather
+/// than in an account of its own. There is no `is_active` or `bump`: a
+/// cancelled subscription's leaf is simply gone, and the delegate PDA is
+/// derived from the pair.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CompressedSubscription {
+    pub authority: Pubkey,
+    pub recipient: Pubkey,
+    pub user_token_account: Pubkey,
+    pub recipient_token_account: Pubkey,
+    pub token_mint: Pubkey,
+    pub amount_per_period: u64,
+    /// Encoded as in the subscription program; see `Interval`
+    pub interval_seconds: i64,
+    pub created_at: i64,
+    pub last_charge_timestamp: i64,
+    pub next_charge_at: i64,
+    pub expires_at: Option<i64>,
+    pub total_charged: u64,
+}
+
+impl CompressedSubscription {
+    /// `sha256("account:CompressedSubscription")[..8]`, kept in the
+    /// compressed account's data so indexers can tell it apart
+    pub const DISCRIMINATOR: [u8; 8] = [169, 57, 51, 203, 213, 40, 213, 185];
+
+    /// State after the first period was paid at `now`
+    #[allow(clippy::too_many_arguments)]
+    pub fn open(
+        authority: Pubkey,
+        recipient: Pubkey,
+        user_token_account: Pubkey,
+        recipient_token_account: Pubkey,
+        token_mint: Pubkey,
+        amount_per_period: u64,
+        interval_seconds: i64,
+        expires_at: Option<i64>,
+        now: i64,
+    ) -> Result<Self> {
+        require!(amount_per_period > 0, ErrorCode::InvalidAmount);
+        let interval =
+            Interval::from_seconds_field(interval_seconds).ok_or(ErrorCode::InvalidInterval)?;
+        require!(
+            expires_at.is_none_or(|expires_at| expires_at > now),
+            ErrorCode::InvalidExpiry
+        );
+
+        Ok(Self {
+            authority,
+            recipient,
+            user_token_account,
+            recipient_token_account,
+            token_mint,
+            amount_per_period,
+            interval_seconds,
+            created_at: now,
+            last_charge_timestamp: now,
+            next_charge_at: interval
+                .advance(now, now)
+                .ok_or(ErrorCode::InvalidInterval)?,
+            expires_at,
+            total_charged: amount_per_period,
+        })
+    }
+
+    pub fn check_chargeable(&self, now: i64) -> Result<()> {
+        if let Some(expires_at) = self.expires_at {
+            require!(now < expires_at, ErrorCode::SubscriptionExpired);
+        }
+        require!(now >= self.next_charge_at, ErrorCode::IntervalNotMet);
+        Ok(())
+    }
+
+    /// Book one period paid at `now` and schedule the next
+    pub fn record_charge(&mut self, now: i64) -> Result<()> {
+        let interval = Interval::from_seconds_field(self.interval_seconds)
+            .ok_or(ErrorCode::InvalidInterval)?;
+        self.last_charge_timestamp = now;
+        self.next_charge_at = interval
+            .advance(now, self.created_at)
+            .ok_or(ErrorCode::InvalidInterval)?;
+        self.total_charged = self
+            .total_charged
+            .checked_add(self.amount_per_period)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+
+    /// SHA-256 of the Borsh bytes with the top byte cleared, so it fits the
+    /// BN254 field Light's circuits work in
+    pub fn data_hash(&self) -> Result<[u8; 32]> {
+        Ok(truncated_hash(
+            solana_sha256_hasher::hash(&self.try_to_vec()?).to_bytes(),
+        ))
+    }
+
+    /// The compressed account to append; it carries the full data, which
+    /// Light logs for indexers
+    pub fn output_account(&self, address: [u8; 32]) -> Result<CompressedAccount> {
+        Ok(CompressedAccount {
+            owner: crate::ID,
+            lamports: 0,
+            address: Some(address),
+            data: Some(CompressedAccountData {
+                discriminator: Self::DISCRIMINATOR,
+                data: self.try_to_vec()?,
+                data_hash: self.data_hash()?,
+            }),
+        })
+    }
+
+    /// The compressed account to nullify. Light only hashes inputs, so the
+    /// data itself is left out to keep the transaction small.
+    pub fn input_account(
+        &self,
+        meta: &CompressedAccountMeta,
+    ) -> Result<PackedCompressedAccountWithMerkleContext> {
+        Ok(PackedCompressedAccountWithMerkleContext {
+            compressed_account: CompressedAccount {
+                owner: crate::ID,
+                lamports: 0,
+                address: Some(meta.address),
+                data: Some(CompressedAccountData {
+                    discriminator: Self::DISCRIMINATOR,
+                    data: Vec::new(),
+                    data_hash: self.data_hash()?,
+                }),
+            },
+            merkle_context: PackedMerkleContext {
+                merkle_tree_pubkey_index: meta.tree_info.merkle_tree_pubkey_index,
+                nullifier_queue_pubkey_index: meta.tree_info.queue_pubkey_index,
+                leaf_index: meta.tree_info.leaf_index,
+                queue_index: None,
+            },
+            root_index: meta.tree_info.root_index,
+            read_only: false,
+        })
+    }
+
+    /// Read the data of a compressed account, as an indexer returns it
+    pub fn decode(discriminator: &[u8; 8], data: &[u8]) -> Result<Self> {
+        require!(
+            *discriminator == Self::DISCRIMINATOR,
+            ErrorCode::InvalidCompressedAccount
+        );
+        Self::try_from_slice(data).map_err(|_| ErrorCode::InvalidCompressedAccount.into())
+    }
+}
+
+/// Clear the top byte so a 32-byte hash is a BN254 field element
+fn truncated_hash(mut hash: [u8; 32]) -> [u8; 32] {
+    hash[0] = 0;
+    hash
+}
+
+/// Seed of a pair's address: this program's ID, `SUBSCRIPTION_SEED` and the
+/// pair, hashed with Keccak-256 into the field as Light's SDK does
+pub fn address_seed(authority: &Pubkey, recipient: &Pubkey) -> [u8; 32] {
+    truncated_hash(
+        solana_keccak_hasher::hashv(&[
+            crate::ID.as_ref(),
+            SUBSCRIPTION_SEED,
+            authority.as_ref(),
+            recipient.as_ref(),
+        ])
+        .to_bytes(),
+    )
+}
+
+/// The address `seed` gets in `address_tree`
+pub fn derive_address(seed: &[u8; 32], address_tree: &Pubkey) -> [u8; 32] {
+    truncated_hash(solana_keccak_hasher::hashv(&[address_tree.as_ref(), seed]).to_bytes())
+}
+
+/// The PDA that signs Light CPIs for this program
+pub fn cpi_authority_address() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[CPI_AUTHORITY_SEED], &crate::ID)
+}
+
+/// The PDA that holds a subscriber's delegation for one merchant
+pub fn delegate_address(authority: &Pubkey, recipient: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[SUBSCRIPTION_SEED, authority.as_ref(), recipient.as_ref()],
+        &crate::ID,
+    )
+}
+
+/// Light's registration of its system program with account compression
+pub fn registered_program_address() -> Pubkey {
+    Pubkey::find_program_address(
+        &[LIGHT_SYSTEM_PROGRAM_ID.as_ref()],
+        &ACCOUNT_COMPRESSION_PROGRAM_ID,
+    )
+    .0
+}
+
+/// The Light system program's authority over the trees
+pub fn account_compression_authority_address() -> Pubkey {
+    Pubkey::find_program_address(&[CPI_AUTHORITY_SEED], &LIGHT_SYSTEM_PROGRAM_ID).0
+}
+
+/// Move one period from the user to the merchant, signed by the delegate
+fn transfer<'info>(
+    token_program: &AccountInfo<'info>,
+    from: &AccountInfo<'info>,
+    to: &AccountInfo<'info>,
+    delegate: &AccountInfo<'info>,
+    state: &CompressedSubscription,
+    bump: u8,
+) -> Result<()> {
+    let transfer_ix = token_instruction::transfer(
+        &token_program.key(),
+        &from.key(),
+        &to.key(),
+        &delegate.key(),
+        &[],
+        state.amount_per_period,
+    )?;
+    invoke_signed(
+        &transfer_ix,
+        &[
+            from.clone(),
+            to.clone(),
+            delegate.clone(),
+            token_program.clone(),
+        ],
+        &[&[
+            SUBSCRIPTION_SEED,
+            state.authority.as_ref(),
+            state.recipient.as_ref(),
+            &[bump],
+        ]],
+    )?;
+    Ok(())
+}
+
+/// Send `data` to the Light system program, signed by the CPI authority,
+/// with `trees` as the accounts its indices point into
+fn invoke_light<'info>(
+    light: &LightSystemAccounts<'info>,
+    trees: &[AccountInfo<'info>],
+    bump: u8,
+    data: InstructionDataInvokeCpi,
+) -> Result<()> {
+    let tree_metas: Vec<AccountMeta> = trees
+        .iter()
+        .map(|tree| AccountMeta::new(tree.key(), false))
+        .collect();
+    let instruction = invoke_cpi_instruction(
+        &light.fee_payer.key(),
+        &light.registered_program_pda.key(),
+        &light.account_compression_authority.key(),
+        &data,
+        tree_metas,
+    )?;
+
+    let mut infos = vec![
+        light.fee_payer.to_account_info(),
+        light.cpi_authority.to_account_info(),
+        light.registered_program_pda.to_account_info(),
+        light.noop_program.to_account_info(),
+        light.account_compression_authority.to_account_info(),
+        light.account_compression_program.to_account_info(),
+        light.self_program.to_account_info(),
+        light.light_system_program.to_account_info(),
+        light.system_program.to_account_info(),
+    ];
+    infos.extend(trees.iter().cloned());
+
+    invoke_signed(&instruction, &infos, &[&[CPI_AUTHORITY_SEED, &[bump]]])?;
+    Ok(())
+}
+
+/// The Light system program's `invoke_cpi` for this program. Optional
+/// accounts Light does not need here (SOL pool, decompression recipient,
+/// CPI context) are passed as the Light system program, Anchor's `None`.
+pub fn invoke_cpi_instruction(
+    fee_payer: &Pubkey,
+    registered_program_pda: &Pubkey,
+    account_compression_authority: &Pubkey,
+    data: &InstructionDataInvokeCpi,
+    trees: Vec<AccountMeta>,
+) -> Result<Instruction> {
+    let mut accounts = vec![
+        AccountMeta::new(*fee_payer, true),
+        AccountMeta::new_readonly(cpi_authority_address().0, true),
+        AccountMeta::new_readonly(*registered_program_pda, false),
+        AccountMeta::new_readonly(NOOP_PROGRAM_ID, false),
+        AccountMeta::new_readonly(*account_compression_authority, false),
+        AccountMeta::new_readonly(ACCOUNT_COMPRESSION_PROGRAM_ID, false),
+        AccountMeta::new_readonly(crate::ID, false),
+        AccountMeta::new_readonly(LIGHT_SYSTEM_PROGRAM_ID, false),
+        AccountMeta::new_readonly(LIGHT_SYSTEM_PROGRAM_ID, false),
+        AccountMeta::new_readonly(anchor_lang::system_program::ID, false),
+        AccountMeta::new_readonly(LIGHT_SYSTEM_PROGRAM_ID, false),
+    ];
+    accounts.extend(trees);
+
+    // `invoke_cpi(inputs: Vec<u8>)`: the arguments travel Borsh-encoded
+    // inside a byte vector
+    let mut instruction_data = INVOKE_CPI_DISCRIMINATOR.to_vec();
+    data.try_to_vec()?.serialize(&mut instruction_data)?;
+
+    Ok(Instruction {
+        program_id: LIGHT_SYSTEM_PROGRAM_ID,
+        accounts,
+        data: instruction_data,
+    })
+}
+
+// Light's instruction types, mirrored so the recipe does not pull in the
+// Light SDK. Layouts follow the system program's `invoke_cpi`.
+
+/// A compressed Groth16 proof over the state and address tree roots
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CompressedProof {
+    pub a: [u8; 32],
+    pub b: [u8; 64],
+    pub c: [u8; 32],
+}
+
+/// Where a new address goes: indices into the remaining accounts, and the
+/// address tree root the proof was made against
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PackedAddressTreeInfo {
+    pub address_merkle_tree_pubkey_index: u8,
+    pub address_queue_pubkey_index: u8,
+    pub root_index: u16,
+}
+
+/// Where an existing leaf is: indices into the remaining accounts, its
+/// position and the state tree root the proof was made against
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PackedStateTreeInfo {
+    pub merkle_tree_pubkey_index: u8,
+    pub queue_pubkey_index: u8,
+    pub leaf_index: u32,
+    pub root_index: u16,
+}
+
+/// An existing compressed subscription and the tree its next state goes to
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CompressedAccountMeta {
+    pub tree_info: PackedStateTreeInfo,
+    pub address: [u8; 32],
+    pub output_state_tree_index: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct NewAddressParamsPacked {
+    pub seed: [u8; 32],
+    pub address_queue_account_index: u8,
+    pub address_merkle_tree_account_index: u8,
+    pub address_merkle_tree_root_index: u16,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CompressedAccountData {
+    pub discriminator: [u8; 8],
+    pub data: Vec<u8>,
+    pub data_hash: [u8; 32],
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CompressedAccount {
+    pub owner: Pubkey,
+    pub lamports: u64,
+    pub address: Option<[u8; 32]>,
+    pub data: Option<CompressedAccountData>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct QueueIndex {
+    pub queue_id: u8,
+    pub index: u16,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PackedMerkleContext {
+    pub merkle_tree_pubkey_index: u8,
+    pub nullifier_queue_pubkey_index: u8,
+    pub leaf_index: u32,
+    pub queue_index: Option<QueueIndex>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PackedCompressedAccountWithMerkleContext {
+    pub compressed_account: CompressedAccount,
+    pub merkle_context: PackedMerkleContext,
+    pub root_index: u16,
+    pub read_only: bool,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct OutputCompressedAccountWithPackedContext {
+    pub compressed_account: CompressedAccount,
+    pub merkle_tree_index: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CompressedCpiContext {
+    pub set_context: bool,
+    pub first_set_context: bool,
+    pub cpi_context_account_index: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct InstructionDataInvokeCpi {
+    pub proof: Option<CompressedProof>,
+    pub new_address_params: Vec<NewAddressParamsPacked>,
+    pub input_compressed_accounts_with_merkle_context:
+        Vec<PackedCompressedAccountWithMerkleContext>,
+    pub output_compressed_accounts: Vec<OutputCompressedAccountWithPackedContext>,
+    pub relay_fee: Option<u64>,
+    pub compress_or_decompress_lamports: Option<u64>,
+    pub is_compress: bool,
+    pub cpi_context: Option<CompressedCpiContext>,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompressedSubscriptionOpened {
+    pub address: [u8; 32],
+    pub authority: Pubkey,
+    pub recipient: Pubkey,
+    pub amount_per_period: u64,
+    pub interval_seconds: i64,
+    pub next_charge_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompressedSubscriptionCharged {
+    pub address: [u8; 32],
+    pub amount: u64,
+    pub total_charged: u64,
+    pub next_charge_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompressedSubscriptionCancelled {
+    pub address: [u8; 32],
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Amount must be greater than zero")]
+    InvalidAmount,
+    #[msg("Interval is not a valid subscription interval")]
+    InvalidInterval,
+    #[msg("Expiry must be in the future")]
+    InvalidExpiry,
+    #[msg("Token account does not match the subscription")]
+    InvalidTokenAccount,
+    #[msg("New addresses must go to Light's public address tree")]
+    InvalidAddressTree,
+    #[msg("Compressed account is not a subscription")]
+    InvalidCompressedAccount,
+    #[msg("Subscription has expired")]
+    SubscriptionExpired,
+    #[msg("Not enough time has passed since last charge")]
+    IntervalNotMet,
+    #[msg("Only the subscriber can cancel")]
+    Unauthorized,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
@@ -0,0 +1,465 @@
+//! Native tests for the compressed subscriptions program.
+//!
+//! The state rules, hashing, address derivation and the Light `invoke_cpi`
+//! layout are pure and tested directly. The instructions run end to end
+//! against the real token program. Every one ends in a Light CPI, which goes
+//! to a stand-in that takes an `invoke_cpi` signed by the program's CPI
+//! authority; proofs and tree updates are Light's and not checked here.
+
+use anchor_lang::prelude::{AccountInfo, Pubkey};
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program_error::ProgramError;
+use anchor_lang::{system_program, AnchorDeserialize, InstructionData, ToAccountMetas};
+use compressed_subscriptions::{
+    account_compression_authority_address, accounts, address_seed, cpi_authority_address,
+    delegate_address, derive_address, instruction, invoke_cpi_instruction,
+    registered_program_address, CompressedAccountMeta, CompressedProof, CompressedSubscription,
+    ErrorCode, InstructionDataInvokeCpi, NewAddressParamsPacked, PackedAddressTreeInfo,
+    PackedStateTreeInfo, ACCOUNT_COMPRESSION_PROGRAM_ID, ADDRESS_TREE, ID as PROGRAM_ID,
+    INVOKE_CPI_DISCRIMINATOR, LIGHT_SYSTEM_PROGRAM_ID, NOOP_PROGRAM_ID,
+};
+use subscription_program::Interval;
+use test_harness::{assert_error, assert_success, Keypair, Signer, TestSvm, TransactionResult};
+
+const AMOUNT: u64 = 10_000_000;
+const DAY: i64 = 86_400;
+const INTERVAL: i64 = 30 * DAY;
+/// 2024-01-31 00:00 UTC
+const JAN_31: i64 = 1_706_659_200;
+
+fn state(now: i64) -> CompressedSubscription {
+    CompressedSubscription::open(
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        AMOUNT,
+        INTERVAL,
+        None,
+        now,
+    )
+    .unwrap()
+}
+
+fn proof() -> CompressedProof {
+    CompressedProof {
+        a: [1; 32],
+        b: [2; 64],
+        c: [3; 32],
+    }
+}
+
+fn meta() -> CompressedAccountMeta {
+    CompressedAccountMeta {
+        tree_info: PackedStateTreeInfo {
+            merkle_tree_pubkey_index: 0,
+            queue_pubkey_index: 1,
+            leaf_index: 42,
+            root_index: 7,
+        },
+        address: [9; 32],
+        output_state_tree_index: 0,
+    }
+}
+
+#[test]
+fn opening_checks_the_terms() {
+    let open = |amount, interval, expires_at| {
+        let key = Pubkey::new_unique();
+        CompressedSubscription::open(key, key, key, key, key, amount, interval, expires_at, 100)
+    };
+
+    let fresh = open(AMOUNT, INTERVAL, Some(101)).unwrap();
+    assert_eq!(fresh.next_charge_at, 100 + INTERVAL);
+    assert_eq!(fresh.total_charged, AMOUNT);
+    assert_eq!(
+        open(0, INTERVAL, None).unwrap_err(),
+        ErrorCode::InvalidAmount.into()
+    );
+    assert_eq!(
+        open(AMOUNT, 0, None).unwrap_err(),
+        ErrorCode::InvalidInterval.into()
+    );
+    assert_eq!(
+        open(AMOUNT, INTERVAL, Some(100)).unwrap_err(),
+        ErrorCode::InvalidExpiry.into()
+    );
+}
+
+#[test]
+fn charges_follow_the_schedule() {
+    let mut subscription = state(0);
+    assert_eq!(
+        subscription.check_chargeable(INTERVAL - 1).unwrap_err(),
+        ErrorCode::IntervalNotMet.into()
+    );
+    subscription.check_chargeable(INTERVAL).unwrap();
+
+    subscription.record_charge(INTERVAL + 5).unwrap();
+    assert_eq!(subscription.last_charge_timestamp, INTERVAL + 5);
+    assert_eq!(subscription.next_charge_at, 2 * INTERVAL + 5);
+    assert_eq!(subscription.total_charged, 2 * AMOUNT);
+
+    subscription.expires_at = Some(2 * INTERVAL + 5);
+    assert_eq!(
+        subscription.check_chargeable(2 * INTERVAL + 5).unwrap_err(),
+        ErrorCode::SubscriptionExpired.into()
+    );
+}
+
+#[test]
+fn calendar_intervals_keep_the_day_of_the_month() {
+    let mut subscription = state(JAN_31);
+    subscription.interval_seconds = Interval::Monthly.to_seconds_field();
+    subscription.next_charge_at = JAN_31;
+
+    // February 29, then back to the 31st in March
+    subscription.record_charge(JAN_31).unwrap();
+    assert_eq!(subscription.next_charge_at, JAN_31 + 29 * DAY);
+    let charged = subscription.next_charge_at;
+    subscription.record_charge(charged).unwrap();
+    assert_eq!(subscription.next_charge_at, JAN_31 + 60 * DAY);
+}
+
+#[test]
+fn hashes_fit_the_field_and_bind_the_state() {
+    let subscription = state(0);
+    let hash = subscription.data_hash().unwrap();
+    assert_eq!(hash[0], 0);
+    assert_eq!(hash, subscription.clone().data_hash().unwrap());
+
+    let mut cheaper = subscription.clone();
+    cheaper.amount_per_period -= 1;
+    assert_ne!(cheaper.data_hash().unwrap(), hash);
+
+    let (authority, recipient) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let seed = address_seed(&authority, &recipient);
+    assert_eq!(seed[0], 0);
+    assert_ne!(seed, address_seed(&recipient, &authority));
+
+    let address = derive_address(&seed, &ADDRESS_TREE);
+    assert_eq!(address[0], 0);
+    assert_ne!(address, derive_address(&seed, &Pubkey::new_unique()));
+}
+
+#[test]
+fn inputs_carry_the_hash_and_outputs_the_data() {
+    let subscription = state(0);
+    let output = subscription.output_account([9; 32]).unwrap();
+    let input = subscription.input_account(&meta()).unwrap();
+
+    assert_eq!(output.owner, PROGRAM_ID);
+    assert_eq!(output.address, Some([9; 32]));
+    let output_data = output.data.unwrap();
+    let input_data = input.compressed_account.data.unwrap();
+    assert_eq!(output_data.data_hash, input_data.data_hash);
+    assert!(input_data.data.is_empty());
+    assert_eq!(input.merkle_context.leaf_index, 42);
+    assert_eq!(input.merkle_context.nullifier_queue_pubkey_index, 1);
+    assert_eq!(input.root_index, 7);
+
+    assert_eq!(
+        CompressedSubscription::decode(&output_data.discriminator, &output_data.data).unwrap(),
+        subscription
+    );
+    assert_eq!(
+        CompressedSubscription::decode(&[0; 8], &output_data.data).unwrap_err(),
+        ErrorCode::InvalidCompressedAccount.into()
+    );
+}
+
+#[test]
+fn invoke_cpi_matches_the_light_layout() {
+    let fee_payer = Pubkey::new_unique();
+    let trees = vec![
+        AccountMeta::new(ADDRESS_TREE, false),
+        AccountMeta::new(Pubkey::new_unique(), false),
+    ];
+    let data = InstructionDataInvokeCpi {
+        proof: Some(proof()),
+        new_address_params: vec![NewAddressParamsPacked {
+            seed: [4; 32],
+            address_queue_account_index: 1,
+            address_merkle_tree_account_index: 0,
+            address_merkle_tree_root_index: 3,
+        }],
+        ..Default::default()
+    };
+    let instruction = invoke_cpi_instruction(
+        &fee_payer,
+        &registered_program_address(),
+        &account_compression_authority_address(),
+        &data,
+        trees.clone(),
+    )
+    .unwrap();
+
+    assert_eq!(instruction.program_id, LIGHT_SYSTEM_PROGRAM_ID);
+    let keys: Vec<Pubkey> = instruction
+        .accounts
+        .iter()
+        .map(|meta| meta.pubkey)
+        .collect();
+    assert_eq!(keys[0], fee_payer);
+    assert_eq!(keys[1], cpi_authority_address().0);
+    assert_eq!(keys[3], NOOP_PROGRAM_ID);
+    assert_eq!(keys[5], ACCOUNT_COMPRESSION_PROGRAM_ID);
+    assert_eq!(keys[6], PROGRAM_ID);
+    assert_eq!(keys[9], system_program::ID);
+    assert_eq!(&instruction.accounts[11..], &trees[..]);
+    let signers: Vec<Pubkey> = instruction
+        .accounts
+        .iter()
+        .filter(|meta| meta.is_signer)
+        .map(|meta| meta.pubkey)
+        .collect();
+    assert_eq!(signers, vec![fee_payer, cpi_authority_address().0]);
+
+    // Discriminator, then the arguments as one Borsh byte vector
+    assert_eq!(instruction.data[..8], INVOKE_CPI_DISCRIMINATOR);
+    let inputs = Vec::<u8>::try_from_slice(&instruction.data[8..]).unwrap();
+    assert_eq!(
+        InstructionDataInvokeCpi::try_from_slice(&inputs).unwrap(),
+        data
+    );
+}
+
+// ---------- instructions ----------
+
+/// Light system program stand-in: accepts `invoke_cpi` when the program's
+/// CPI authority signed it
+fn light_system<'a>(_: &Pubkey, accounts: &'a [AccountInfo<'a>], data: &[u8]) -> ProgramResult {
+    if !data.starts_with(&INVOKE_CPI_DISCRIMINATOR) {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    match accounts.get(1) {
+        Some(authority) if authority.is_signer && *authority.key == cpi_authority_address().0 => {
+            Ok(())
+        }
+        _ => Err(ProgramError::MissingRequiredSignature),
+    }
+}
+
+struct Fixture {
+    svm: TestSvm,
+    payer: Keypair,
+    authority: Keypair,
+    recipient: Pubkey,
+    mint: Pubkey,
+    user_token_account: Pubkey,
+    recipient_token_account: Pubkey,
+}
+
+impl Fixture {
+    fn new() -> Self {
+        let (mut svm, payer) = TestSvm::for_program(PROGRAM_ID, compressed_subscriptions::entry);
+        svm.add_program(LIGHT_SYSTEM_PROGRAM_ID, light_system);
+        let authority = Keypair::new();
+        let recipient = Pubkey::new_unique();
+
+        let mint = svm.create_mint(&Pubkey::new_unique(), 6);
+        let user_token_account =
+            svm.create_associated_token_account(&authority.pubkey(), &mint, 10 * AMOUNT);
+        let recipient_token_account = svm.create_associated_token_account(&recipient, &mint, 0);
+
+        Self {
+            svm,
+            payer,
+            authority,
+            recipient,
+            mint,
+            user_token_account,
+            recipient_token_account,
+        }
+    }
+
+    /// Approve the delegate and return the subscription, as
+    /// `open_subscription` leaves them
+    fn subscribe(&mut self) -> CompressedSubscription {
+        let delegate = delegate_address(&self.authority.pubkey(), &self.recipient).0;
+        self.svm
+            .approve(&self.user_token_account, &delegate, u64::MAX);
+        self.state()
+    }
+
+    /// The subscription as `open_subscription` would have stored it
+    fn state(&self) -> CompressedSubscription {
+        let now = self.svm.clock().unix_timestamp;
+        CompressedSubscription::open(
+            self.authority.pubkey(),
+            self.recipient,
+            self.user_token_account,
+            self.recipient_token_account,
+            self.mint,
+            AMOUNT,
+            INTERVAL,
+            None,
+            now,
+        )
+        .unwrap()
+    }
+
+    fn light(&self) -> accounts::LightSystemAccounts {
+        accounts::LightSystemAccounts {
+            fee_payer: self.payer.pubkey(),
+            cpi_authority: cpi_authority_address().0,
+            registered_program_pda: registered_program_address(),
+            noop_program: NOOP_PROGRAM_ID,
+            account_compression_authority: account_compression_authority_address(),
+            account_compression_program: ACCOUNT_COMPRESSION_PROGRAM_ID,
+            self_program: PROGRAM_ID,
+            light_system_program: LIGHT_SYSTEM_PROGRAM_ID,
+            system_program: system_program::ID,
+        }
+    }
+
+    fn open_ix(&self, address_tree: Pubkey) -> Instruction {
+        let mut instruction = build(
+            accounts::OpenSubscription {
+                authority: self.authority.pubkey(),
+                recipient: self.recipient,
+                user_token_account: self.user_token_account,
+                recipient_token_account: self.recipient_token_account,
+                token_mint: self.mint,
+                delegate: delegate_address(&self.authority.pubkey(), &self.recipient).0,
+                token_program: spl_token::ID,
+                light: self.light(),
+            },
+            instruction::OpenSubscription {
+                proof: proof(),
+                address_tree: PackedAddressTreeInfo {
+                    address_merkle_tree_pubkey_index: 0,
+                    address_queue_pubkey_index: 1,
+                    root_index: 0,
+                },
+                output_tree_index: 2,
+                amount_per_period: AMOUNT,
+                interval_seconds: INTERVAL,
+                expires_at: None,
+            },
+        );
+        instruction.accounts.extend([
+            AccountMeta::new(address_tree, false),
+            AccountMeta::new(Pubkey::new_unique(), false),
+            AccountMeta::new(Pubkey::new_unique(), false),
+        ]);
+        instruction
+    }
+
+    fn charge_ix(&self, current: &CompressedSubscription) -> Instruction {
+        build(
+            accounts::ChargeSubscription {
+                user_token_account: self.user_token_account,
+                recipient_token_account: self.recipient_token_account,
+                delegate: delegate_address(&current.authority, &current.recipient).0,
+                token_program: spl_token::ID,
+                light: self.light(),
+            },
+            instruction::ChargeSubscription {
+                proof: proof(),
+                account: meta(),
+                current: current.clone(),
+            },
+        )
+    }
+
+    fn cancel_ix(&self, authority: Pubkey, current: &CompressedSubscription) -> Instruction {
+        build(
+            accounts::CancelSubscription {
+                authority,
+                user_token_account: self.user_token_account,
+                token_program: spl_token::ID,
+                light: self.light(),
+            },
+            instruction::CancelSubscription {
+                proof: proof(),
+                account: meta(),
+                current: current.clone(),
+            },
+        )
+    }
+
+    fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TransactionResult {
+        let payer = self.payer.insecure_clone();
+        self.svm.expire_blockhash();
+        self.svm.send_instructions(&[instruction], &payer, signers)
+    }
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+#[test]
+fn open_needs_the_public_address_tree() {
+    let mut fx = Fixture::new();
+    let authority = fx.authority.insecure_clone();
+
+    let elsewhere = fx.open_ix(Pubkey::new_unique());
+    assert_error(
+        fx.send(elsewhere, &[&authority]),
+        ErrorCode::InvalidAddressTree,
+    );
+
+    assert_success(fx.send(fx.open_ix(ADDRESS_TREE), &[&authority]));
+    // First period paid, and the delegate approved for the rest
+    assert_eq!(fx.svm.token_balance(&fx.recipient_token_account), AMOUNT);
+    let user = fx.svm.get_token_account(&fx.user_token_account).unwrap();
+    assert_eq!(
+        Option::from(user.delegate),
+        Some(delegate_address(&fx.authority.pubkey(), &fx.recipient).0)
+    );
+}
+
+#[test]
+fn charge_waits_for_the_due_date() {
+    let mut fx = Fixture::new();
+    let current = fx.subscribe();
+
+    assert_error(
+        fx.send(fx.charge_ix(&current), &[]),
+        ErrorCode::IntervalNotMet,
+    );
+
+    fx.svm.advance_time(INTERVAL);
+    assert_success(fx.send(fx.charge_ix(&current), &[]));
+    assert_eq!(fx.svm.token_balance(&fx.recipient_token_account), AMOUNT);
+}
+
+#[test]
+fn charge_pays_only_the_states_accounts() {
+    let mut fx = Fixture::new();
+    let mut current = fx.subscribe();
+    fx.svm.advance_time(INTERVAL);
+
+    // A state naming someone else's payout account does not match the
+    // accounts passed, and a matching one would not match the leaf
+    current.recipient_token_account = Pubkey::new_unique();
+    assert_error(
+        fx.send(fx.charge_ix(&current), &[]),
+        ErrorCode::InvalidTokenAccount,
+    );
+}
+
+#[test]
+fn only_the_subscriber_cancels() {
+    let mut fx = Fixture::new();
+    let current = fx.subscribe();
+    let intruder = Keypair::new();
+
+    assert_error(
+        fx.send(fx.cancel_ix(intruder.pubkey(), &current), &[&intruder]),
+        ErrorCode::Unauthorized,
+    );
+
+    let authority = fx.authority.insecure_clone();
+    assert_success(fx.send(fx.cancel_ix(authority.pubkey(), &current), &[&authority]));
+    let user = fx.svm.get_token_account(&fx.user_token_account).unwrap();
+    assert!(user.delegate.is_none());
+}
@@ -0,0 +1,778 @@
+use anchor_lang::prelude::*;
+use subscription_program::{assert_active_subscription, Subscription};
+
+declare_id!("6jkSsuXsAbMWhhLcdPRtbv1Xc2HHvLZoaLFSj11SxuAx");
+
+#[program]
+pub mod dao_membership {
+    use super::*;
+
+    /// Start a DAO whose members pay at least `dues_amount` every
+    /// `dues_interval` seconds to `treasury` through the subscription
+    /// program. A member whose dues are more than `grace_period` seconds
+    /// overdue cannot vote.
+    pub fn create_dao(
+        ctx: Context<CreateDao>,
+        treasury: Pubkey,
+        dues_amount: u64,
+        dues_interval: i64,
+        grace_period: i64,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        Dao::check_params(dues_amount, dues_interval, grace_period)?;
+
+        let dao = &mut ctx.accounts.dao;
+        dao.admin = ctx.accounts.admin.key();
+        dao.treasury = treasury;
+        dao.dues_amount = dues_amount;
+        dao.dues_interval = dues_interval;
+        dao.grace_period = grace_period;
+        dao.member_count = 0;
+        dao.active_members = 0;
+        dao.active_weight = 0;
+        dao.proposal_count = 0;
+        dao.created_at = clock.unix_timestamp;
+        dao.bump = ctx.bumps.dao;
+
+        emit!(DaoCreated {
+            dao: dao.key(),
+            admin: dao.admin,
+            treasury,
+            dues_amount,
+            dues_interval,
+            grace_period,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("DAO created");
+        msg!("Dues: {} every {} seconds", dues_amount, dues_interval);
+
+        Ok(())
+    }
+
+    /// Change the dues terms. Members are measured against them from now on;
+    /// `refresh_member` brings stored statuses up to date.
+    pub fn update_dues(
+        ctx: Context<UpdateDao>,
+        dues_amount: u64,
+        dues_interval: i64,
+        grace_period: i64,
+    ) -> Result<()> {
+        Dao::check_params(dues_amount, dues_interval, grace_period)?;
+
+        let dao = &mut ctx.accounts.dao;
+        dao.dues_amount = dues_amount;
+        dao.dues_interval = dues_interval;
+        dao.grace_period = grace_period;
+
+        emit!(DuesUpdated {
+            dao: dao.key(),
+            dues_amount,
+            dues_interval,
+            grace_period,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Dues: {} every {} seconds", dues_amount, dues_interval);
+
+        Ok(())
+    }
+
+    /// Admit `owner` with `weight` votes. The member starts suspended until
+    /// `refresh_member` sees their dues paid.
+    pub fn admit_member(ctx: Context<AdmitMember>, weight: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        require!(weight > 0, ErrorCode::InvalidWeight);
+
+        let member = &mut ctx.accounts.member;
+        member.dao = ctx.accounts.dao.key();
+        member.owner = ctx.accounts.owner.key();
+        member.weight = weight;
+        member.active = false;
+        member.joined_at = now;
+        member.status_changed_at = now;
+        member.bump = ctx.bumps.member;
+
+        let dao = &mut ctx.accounts.dao;
+        dao.member_count = dao
+            .member_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        emit!(MemberAdmitted {
+            dao: dao.key(),
+            owner: member.owner,
+            weight,
+            timestamp: now,
+        });
+
+        msg!("Member admitted: {}", member.owner);
+        msg!("Voting weight: {}", weight);
+
+        Ok(())
+    }
+
+    pub fn set_weight(ctx: Context<SetWeight>, weight: u64) -> Result<()> {
+        require!(weight > 0, ErrorCode::InvalidWeight);
+
+        let dao = &mut ctx.accounts.dao;
+        let member = &mut ctx.accounts.member;
+        dao.set_weight(member, weight)?;
+
+        emit!(WeightChanged {
+            dao: dao.key(),
+            owner: member.owner,
+            weight,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Voting weight: {}", weight);
+
+        Ok(())
+    }
+
+    /// Reinstate a member whose dues are paid or suspend one whose dues
+    /// lapsed, keeping the DAO's active weight current. Reads the member's
+    /// subscription to the treasury; anyone can crank it.
+    pub fn refresh_member(ctx: Context<RefreshMember>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let active = ctx
+            .accounts
+            .dao
+            .check_dues(&ctx.accounts.subscription, now)
+            .is_ok();
+
+        let dao = &mut ctx.accounts.dao;
+        let member = &mut ctx.accounts.member;
+        dao.set_active(member, active, now)?;
+
+        if active {
+            emit!(MemberReinstated {
+                dao: dao.key(),
+                owner: member.owner,
+                weight: member.weight,
+                timestamp: now,
+            });
+            msg!("Member reinstated: {}", member.owner);
+        } else {
+            emit!(MemberSuspended {
+                dao: dao.key(),
+                owner: member.owner,
+                timestamp: now,
+            });
+            msg!("Member suspended: {}", member.owner);
+        }
+        msg!("Active weight: {}", dao.active_weight);
+
+        Ok(())
+    }
+
+    /// Remove a member, returning their account's rent to the admin
+    pub fn remove_member(ctx: Context<RemoveMember>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let dao = &mut ctx.accounts.dao;
+        let member = &ctx.accounts.member;
+        dao.remove(member);
+
+        emit!(MemberRemoved {
+            dao: dao.key(),
+            owner: member.owner,
+            timestamp: now,
+        });
+
+        msg!("Member removed: {}", member.owner);
+
+        Ok(())
+    }
+
+    /// Open proposal number `dao.proposal_count` for voting until
+    /// `voting_end`. The proposer must have their dues paid; `payer` pays
+    /// the rent.
+    pub fn create_proposal(
+        ctx: Context<CreateProposal>,
+        description_hash: [u8; 32],
+        voting_end: i64,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        Proposal::check_params(voting_end, now)?;
+        ctx.accounts
+            .dao
+            .check_dues(&ctx.accounts.subscription, now)?;
+
+        let dao = &mut ctx.accounts.dao;
+        let proposal = &mut ctx.accounts.proposal;
+        proposal.dao = dao.key();
+        proposal.proposal_id = dao.proposal_count;
+        proposal.proposer = ctx.accounts.owner.key();
+        proposal.description_hash = description_hash;
+        proposal.voting_end = voting_end;
+        proposal.yes_weight = 0;
+        proposal.no_weight = 0;
+        proposal.voter_count = 0;
+        proposal.created_at = now;
+        proposal.bump = ctx.bumps.proposal;
+
+        dao.proposal_count = dao
+            .proposal_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        emit!(ProposalCreated {
+            dao: dao.key(),
+            proposal_id: proposal.proposal_id,
+            proposer: proposal.proposer,
+            description_hash,
+            voting_end,
+            timestamp: now,
+        });
+
+        msg!("Proposal {} created", proposal.proposal_id);
+        msg!("Voting until {}", voting_end);
+
+        Ok(())
+    }
+
+    /// Vote with the member's full weight. Dues are checked against the
+    /// subscription itself, so a lapsed member cannot vote even before
+    /// anyone refreshes them. `payer` pays the vote record's rent.
+    pub fn cast_vote(ctx: Context<CastVote>, approve: bool) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        ctx.accounts
+            .dao
+            .check_dues(&ctx.accounts.subscription, now)?;
+
+        let weight = ctx.accounts.member.weight;
+        let proposal = &mut ctx.accounts.proposal;
+        proposal.record_vote(weight, approve, now)?;
+
+        let vote = &mut ctx.accounts.vote;
+        vote.proposal = proposal.key();
+        vote.voter = ctx.accounts.owner.key();
+        vote.weight = weight;
+        vote.approve = approve;
+        vote.voted_at = now;
+        vote.bump = ctx.bumps.vote;
+
+        emit!(VoteCast {
+            dao: proposal.dao,
+            proposal_id: proposal.proposal_id,
+            voter: vote.voter,
+            weight,
+            approve,
+            timestamp: now,
+        });
+
+        msg!(
+            "Voted {} with weight {}",
+            if approve { "yes" } else { "no" },
+            weight
+        );
+        msg!(
+            "Tally: {} yes, {} no",
+            proposal.yes_weight,
+            proposal.no_weight
+        );
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct CreateDao<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Dao::INIT_SPACE,
+        seeds = [b"dao", admin.key().as_ref()],
+        bump
+    )]
+    pub dao: Account<'info, Dao>,
+
+    pub admin: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateDao<'info> {
+    #[account(
+        mut,
+        seeds = [b"dao", admin.key().as_ref()],
+        bump = dao.bump,
+        has_one = admin
+    )]
+    pub dao: Account<'info, Dao>,
+
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AdmitMember<'info> {
+    #[account(
+        mut,
+        seeds = [b"dao", admin.key().as_ref()],
+        bump = dao.bump,
+        has_one = admin
+    )]
+    pub dao: Account<'info, Dao>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Member::INIT_SPACE,
+        seeds = [b"member", dao.key().as_ref(), owner.key().as_ref()],
+        bump
+    )]
+    pub member: Account<'info, Member>,
+
+    pub admin: Signer<'info>,
+
+    /// CHECK: Wallet being admitted; it does not need to sign
+    pub owner: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetWeight<'info> {
+    #[account(
+        mut,
+        seeds = [b"dao", admin.key().as_ref()],
+        bump = dao.bump,
+        has_one = admin
+    )]
+    pub dao: Account<'info, Dao>,
+
+    #[account(
+        mut,
+        seeds = [b"member", dao.key().as_ref(), member.owner.as_ref()],
+        bump = member.bump,
+        has_one = dao
+    )]
+    pub member: Account<'info, Member>,
+
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RefreshMember<'info> {
+    #[account(
+        mut,
+        seeds = [b"dao", dao.admin.as_ref()],
+        bump = dao.bump,
+    )]
+    pub dao: Account<'info, Dao>,
+
+    #[account(
+        mut,
+        seeds = [b"member", dao.key().as_ref(), member.owner.as_ref()],
+        bump = member.bump,
+        has_one = dao
+    )]
+    pub member: Account<'info, Member>,
+
+    /// The member's dues subscription, owned by the subscription program
+    #[account(
+        constraint = subscription.authority == member.owner @ ErrorCode::SubscriptionMismatch,
+        constraint = subscription.recipient == dao.treasury @ ErrorCode::SubscriptionMismatch
+    )]
+    pub subscription: Account<'info, Subscription>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveMember<'info> {
+    #[account(
+        mut,
+        seeds = [b"dao", admin.key().as_ref()],
+        bump = dao.bump,
+        has_one = admin
+    )]
+    pub dao: Account<'info, Dao>,
+
+    #[account(
+        mut,
+        seeds = [b"member", dao.key().as_ref(), member.owner.as_ref()],
+        bump = member.bump,
+        has_one = dao,
+        close = admin
+    )]
+    pub member: Account<'info, Member>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateProposal<'info> {
+    #[account(
+        mut,
+        seeds = [b"dao", dao.admin.as_ref()],
+        bump = dao.bump,
+    )]
+    pub dao: Account<'info, Dao>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Proposal::INIT_SPACE,
+        seeds = [b"proposal", dao.key().as_ref(), dao.proposal_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+
+    #[account(
+        seeds = [b"member", dao.key().as_ref(), owner.key().as_ref()],
+        bump = member.bump,
+        has_one = dao,
+        has_one = owner
+    )]
+    pub member: Account<'info, Member>,
+
+    pub owner: Signer<'info>,
+
+    /// The proposer's dues subscription, owned by the subscription program
+    #[account(
+        constraint = subscription.authority == owner.key() @ ErrorCode::SubscriptionMismatch,
+        constraint = subscription.recipient == dao.treasury @ ErrorCode::SubscriptionMismatch
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    /// Fee and rent payer, usually a relayer
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CastVote<'info> {
+    #[account(
+        seeds = [b"dao", dao.admin.as_ref()],
+        bump = dao.bump,
+    )]
+    pub dao: Account<'info, Dao>,
+
+    #[account(
+        mut,
+        seeds = [b"proposal", dao.key().as_ref(), proposal.proposal_id.to_le_bytes().as_ref()],
+        bump = proposal.bump,
+        has_one = dao
+    )]
+    pub proposal: Account<'info, Proposal>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Vote::INIT_SPACE,
+        seeds = [b"vote", proposal.key().as_ref(), owner.key().as_ref()],
+        bump
+    )]
+    pub vote: Account<'info, Vote>,
+
+    #[account(
+        seeds = [b"member", dao.key().as_ref(), owner.key().as_ref()],
+        bump = member.bump,
+        has_one = dao,
+        has_one = owner
+    )]
+    pub member: Account<'info, Member>,
+
+    pub owner: Signer<'info>,
+
+    /// The voter's dues subscription, owned by the subscription program
+    #[account(
+        constraint = subscription.authority == owner.key() @ ErrorCode::SubscriptionMismatch,
+        constraint = subscription.recipient == dao.treasury @ ErrorCode::SubscriptionMismatch
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    /// Fee and rent payer, usually a relayer
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Dao {
+    pub admin: Pubkey,
+    /// Recipient every member's dues subscription must pay
+    pub treasury: Pubkey,
+    /// Least a dues subscription may charge per period
+    pub dues_amount: u64,
+    /// Longest period a dues subscription may have
+    pub dues_interval: i64,
+    /// How long past a missed charge a member keeps their vote
+    pub grace_period: i64,
+    pub member_count: u32,
+    pub active_members: u32,
+    /// Sum of active members' weights, as of their last refresh
+    pub active_weight: u64,
+    /// Id of the next proposal
+    pub proposal_count: u64,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl Dao {
+    pub fn check_params(dues_amount: u64, dues_interval: i64, grace_period: i64) -> Result<()> {
+        require!(dues_amount > 0, ErrorCode::InvalidDues);
+        require!(dues_interval > 0, ErrorCode::InvalidDues);
+        require!(grace_period >= 0, ErrorCode::InvalidDues);
+        Ok(())
+    }
+
+    /// Whether `subscription` keeps its owner's dues paid at `now`: it pays
+    /// the treasury at least the DAO's rate, is active, and its last charge
+    /// covers `now` give or take the grace period. The subscription
+    /// program charges up front, so a charge covers one interval ahead.
+    pub fn check_dues(&self, subscription: &Subscription, now: i64) -> Result<()> {
+        require_keys_eq!(
+            subscription.recipient,
+            self.treasury,
+            ErrorCode::SubscriptionMismatch
+        );
+        require!(
+            subscription.amount_per_period >= self.dues_amount
+                && subscription.interval_seconds <= self.dues_interval,
+            ErrorCode::DuesTooLow
+        );
+        assert_active_subscription(subscription, now).map_err(|_| error!(ErrorCode::DuesLapsed))?;
+        let paid_until = subscription
+            .last_charge_timestamp
+            .saturating_add(subscription.interval_seconds)
+            .saturating_add(self.grace_period);
+        require!(now < paid_until, ErrorCode::DuesLapsed);
+        Ok(())
+    }
+
+    /// Reinstate or suspend `member`, moving their weight in or out of the
+    /// active total
+    pub fn set_active(&mut self, member: &mut Member, active: bool, now: i64) -> Result<()> {
+        require!(member.active != active, ErrorCode::StatusUnchanged);
+        if active {
+            self.active_members = self
+                .active_members
+                .checked_add(1)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            self.active_weight = self
+                .active_weight
+                .checked_add(member.weight)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+        } else {
+            self.deactivate(member);
+        }
+        member.active = active;
+        member.status_changed_at = now;
+        Ok(())
+    }
+
+    pub fn set_weight(&mut self, member: &mut Member, weight: u64) -> Result<()> {
+        if member.active {
+            self.active_weight = (self.active_weight - member.weight)
+                .checked_add(weight)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+        }
+        member.weight = weight;
+        Ok(())
+    }
+
+    /// Drop `member` from the counts before their account closes
+    pub fn remove(&mut self, member: &Member) {
+        if member.active {
+            self.deactivate(member);
+        }
+        self.member_count -= 1;
+    }
+
+    fn deactivate(&mut self, member: &Member) {
+        self.active_members -= 1;
+        self.active_weight -= member.weight;
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Member {
+    pub dao: Pubkey,
+    pub owner: Pubkey,
+    pub weight: u64,
+    /// Whether the weight counts towards the DAO's active total, as of the
+    /// last refresh
+    pub active: bool,
+    pub joined_at: i64,
+    pub status_changed_at: i64,
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Proposal {
+    pub dao: Pubkey,
+    pub proposal_id: u64,
+    pub proposer: Pubkey,
+    /// Hash of the proposal text, which lives off-chain
+    pub description_hash: [u8; 32],
+    pub voting_end: i64,
+    pub yes_weight: u64,
+    pub no_weight: u64,
+    pub voter_count: u32,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl Proposal {
+    pub fn check_params(voting_end: i64, now: i64) -> Result<()> {
+        require!(voting_end > now, ErrorCode::InvalidSchedule);
+        Ok(())
+    }
+
+    pub fn record_vote(&mut self, weight: u64, approve: bool, now: i64) -> Result<()> {
+        require!(now < self.voting_end, ErrorCode::VotingClosed);
+        let tally = if approve {
+            &mut self.yes_weight
+        } else {
+            &mut self.no_weight
+        };
+        *tally = tally
+            .checked_add(weight)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.voter_count = self
+            .voter_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+
+    /// Passed once voting is over with more weight for than against
+    pub fn passed(&self, now: i64) -> bool {
+        now >= self.voting_end && self.yes_weight > self.no_weight
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Vote {
+    pub proposal: Pubkey,
+    pub voter: Pubkey,
+    pub weight: u64,
+    pub approve: bool,
+    pub voted_at: i64,
+    pub bump: u8,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DaoCreated {
+    pub dao: Pubkey,
+    pub admin: Pubkey,
+    pub treasury: Pubkey,
+    pub dues_amount: u64,
+    pub dues_interval: i64,
+    pub grace_period: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuesUpdated {
+    pub dao: Pubkey,
+    pub dues_amount: u64,
+    pub dues_interval: i64,
+    pub grace_period: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemberAdmitted {
+    pub dao: Pubkey,
+    pub owner: Pubkey,
+    pub weight: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct WeightChanged {
+    pub dao: Pubkey,
+    pub owner: Pubkey,
+    pub weight: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemberReinstated {
+    pub dao: Pubkey,
+    pub owner: Pubkey,
+    pub weight: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemberSuspended {
+    pub dao: Pubkey,
+    pub owner: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemberRemoved {
+    pub dao: Pubkey,
+    pub owner: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProposalCreated {
+    pub dao: Pubkey,
+    pub proposal_id: u64,
+    pub proposer: Pubkey,
+    pub description_hash: [u8; 32],
+    pub voting_end: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoteCast {
+    pub dao: Pubkey,
+    pub proposal_id: u64,
+    pub voter: Pubkey,
+    pub weight: u64,
+    pub approve: bool,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Dues need a non-zero amount and interval and a non-negative grace period")]
+    InvalidDues,
+    #[msg("Voting weight must be greater than zero")]
+    InvalidWeight,
+    #[msg("Voting must end in the future")]
+    InvalidSchedule,
+    #[msg("Subscription does not belong to this member and treasury")]
+    SubscriptionMismatch,
+    #[msg("Subscription pays less than the DAO's dues")]
+    DuesTooLow,
+    #[msg("Dues have lapsed")]
+    DuesLapsed,
+    #[msg("Member already has this status")]
+    StatusUnchanged,
+    #[msg("Voting has closed")]
+    VotingClosed,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
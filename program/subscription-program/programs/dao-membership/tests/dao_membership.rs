@@ -0,0 +1,609 @@
+//! Native tests for the DAO membership program.
+//!
+//! The dues check, active weight bookkeeping and vote tallies are pure
+//! methods on `Dao` and `Proposal` and are tested directly. Every
+//! instruction also runs end to end against dues subscriptions written into
+//! the harness.
+
+use anchor_lang::error::ErrorCode as AnchorErrorCode;
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, Space, ToAccountMetas};
+use dao_membership::{
+    accounts, instruction, Dao, ErrorCode, Member, Proposal, Vote, ID as PROGRAM_ID,
+};
+use subscription_program::Subscription;
+use test_harness::{
+    assert_error, assert_success, Keypair, Signer, SystemError, TestSvm, TransactionResult,
+};
+
+/// 5 USDC a month
+const DUES: u64 = 5_000_000;
+const MONTH: i64 = 30 * 86_400;
+const GRACE: i64 = 7 * 86_400;
+
+fn dao(admin: Pubkey, treasury: Pubkey) -> Dao {
+    Dao {
+        admin,
+        treasury,
+        dues_amount: DUES,
+        dues_interval: MONTH,
+        grace_period: GRACE,
+        member_count: 1,
+        active_members: 0,
+        active_weight: 0,
+        proposal_count: 0,
+        created_at: 0,
+        bump: 255,
+    }
+}
+
+fn member(dao: Pubkey, owner: Pubkey, weight: u64) -> Member {
+    Member {
+        dao,
+        owner,
+        weight,
+        active: false,
+        joined_at: 0,
+        status_changed_at: 0,
+        bump: 255,
+    }
+}
+
+/// Monthly dues to `treasury`, last charged at `last_charge`
+fn dues(owner: Pubkey, treasury: Pubkey, last_charge: i64) -> Subscription {
+    Subscription {
+        authority: owner,
+        recipient: treasury,
+        user_token_account: Pubkey::new_unique(),
+        recipient_token_account: Pubkey::new_unique(),
+        token_mint: Pubkey::new_unique(),
+        amount_per_period: DUES,
+        interval_seconds: MONTH,
+        last_charge_timestamp: last_charge,
+        next_charge_at: last_charge + MONTH,
+        created_at: last_charge,
+        expires_at: None,
+        is_active: true,
+        total_charged: DUES,
+        bump: 255,
+        on_plan: false,
+        max_total_spend: Subscription::NO_SPEND_LIMIT,
+    }
+}
+
+#[test]
+fn dues_and_voting_terms_are_checked() {
+    assert!(Dao::check_params(DUES, MONTH, 0).is_ok());
+    for (amount, interval, grace) in [(0, MONTH, GRACE), (DUES, 0, GRACE), (DUES, MONTH, -1)] {
+        assert_eq!(
+            Dao::check_params(amount, interval, grace).unwrap_err(),
+            ErrorCode::InvalidDues.into()
+        );
+    }
+
+    assert!(Proposal::check_params(1, 0).is_ok());
+    assert_eq!(
+        Proposal::check_params(0, 0).unwrap_err(),
+        ErrorCode::InvalidSchedule.into()
+    );
+}
+
+#[test]
+fn paid_dues_keep_a_member_in_good_standing() {
+    let treasury = Pubkey::new_unique();
+    let config = dao(Pubkey::new_unique(), treasury);
+    let subscription = dues(Pubkey::new_unique(), treasury, 0);
+
+    // Prepaid for a month, then the grace period
+    assert!(config.check_dues(&subscription, 0).is_ok());
+    assert!(config.check_dues(&subscription, MONTH + GRACE - 1).is_ok());
+    assert_eq!(
+        config.check_dues(&subscription, MONTH + GRACE).unwrap_err(),
+        ErrorCode::DuesLapsed.into()
+    );
+
+    let cancelled = Subscription {
+        is_active: false,
+        ..subscription.clone()
+    };
+    assert_eq!(
+        config.check_dues(&cancelled, 0).unwrap_err(),
+        ErrorCode::DuesLapsed.into()
+    );
+    let expired = Subscription {
+        expires_at: Some(MONTH / 2),
+        ..subscription.clone()
+    };
+    assert_eq!(
+        config.check_dues(&expired, MONTH / 2).unwrap_err(),
+        ErrorCode::DuesLapsed.into()
+    );
+
+    let cheaper = Subscription {
+        amount_per_period: DUES - 1,
+        ..subscription.clone()
+    };
+    assert_eq!(
+        config.check_dues(&cheaper, 0).unwrap_err(),
+        ErrorCode::DuesTooLow.into()
+    );
+    let quarterly = Subscription {
+        interval_seconds: 3 * MONTH,
+        amount_per_period: 3 * DUES,
+        ..subscription.clone()
+    };
+    assert_eq!(
+        config.check_dues(&quarterly, 0).unwrap_err(),
+        ErrorCode::DuesTooLow.into()
+    );
+    // Paying more often is fine
+    let weekly = Subscription {
+        interval_seconds: MONTH / 4,
+        ..subscription.clone()
+    };
+    assert!(config.check_dues(&weekly, 0).is_ok());
+
+    let elsewhere = Subscription {
+        recipient: Pubkey::new_unique(),
+        ..subscription
+    };
+    assert_eq!(
+        config.check_dues(&elsewhere, 0).unwrap_err(),
+        ErrorCode::SubscriptionMismatch.into()
+    );
+}
+
+#[test]
+fn status_changes_move_the_active_weight() {
+    let mut config = dao(Pubkey::new_unique(), Pubkey::new_unique());
+    config.member_count = 2;
+    let mut first = member(Pubkey::new_unique(), Pubkey::new_unique(), 3);
+    let mut second = member(Pubkey::new_unique(), Pubkey::new_unique(), 5);
+
+    config.set_active(&mut first, true, 10).unwrap();
+    config.set_active(&mut second, true, 10).unwrap();
+    assert_eq!((config.active_members, config.active_weight), (2, 8));
+    assert_eq!(first.status_changed_at, 10);
+    assert_eq!(
+        config.set_active(&mut first, true, 20).unwrap_err(),
+        ErrorCode::StatusUnchanged.into()
+    );
+
+    config.set_weight(&mut first, 10).unwrap();
+    assert_eq!(config.active_weight, 15);
+    config.set_active(&mut second, false, 20).unwrap();
+    assert_eq!((config.active_members, config.active_weight), (1, 10));
+
+    // A suspended member's weight changes without touching the total
+    config.set_weight(&mut second, 1).unwrap();
+    assert_eq!(config.active_weight, 10);
+
+    config.remove(&first);
+    config.remove(&second);
+    assert_eq!(
+        (
+            config.member_count,
+            config.active_members,
+            config.active_weight
+        ),
+        (0, 0, 0)
+    );
+}
+
+#[test]
+fn votes_tally_until_voting_ends() {
+    let mut proposal = Proposal {
+        dao: Pubkey::new_unique(),
+        proposal_id: 0,
+        proposer: Pubkey::new_unique(),
+        description_hash: [7; 32],
+        voting_end: 100,
+        yes_weight: 0,
+        no_weight: 0,
+        voter_count: 0,
+        created_at: 0,
+        bump: 255,
+    };
+    proposal.record_vote(5, true, 10).unwrap();
+    proposal.record_vote(3, false, 99).unwrap();
+    assert_eq!(
+        proposal.record_vote(9, false, 100).unwrap_err(),
+        ErrorCode::VotingClosed.into()
+    );
+    assert_eq!((proposal.yes_weight, proposal.no_weight), (5, 3));
+    assert_eq!(proposal.voter_count, 2);
+
+    assert!(!proposal.passed(99));
+    assert!(proposal.passed(100));
+    proposal.no_weight = 5;
+    assert!(!proposal.passed(100));
+}
+
+struct Fixture {
+    svm: TestSvm,
+    payer: Keypair,
+    admin: Keypair,
+    owner: Keypair,
+    dao: Pubkey,
+    member: Pubkey,
+    subscription: Pubkey,
+    state: Dao,
+    member_state: Member,
+    subscription_state: Subscription,
+}
+
+impl Fixture {
+    /// A DAO with one suspended member of weight 3 whose monthly dues were
+    /// just charged
+    fn new() -> Self {
+        let (mut svm, payer) = TestSvm::for_program(PROGRAM_ID, dao_membership::entry);
+
+        let admin = Keypair::new();
+        let owner = Keypair::new();
+        let treasury = Pubkey::new_unique();
+        svm.airdrop(&admin.pubkey(), 1_000_000_000);
+
+        let (address, bump) =
+            Pubkey::find_program_address(&[b"dao", admin.pubkey().as_ref()], &PROGRAM_ID);
+        let (member_address, member_bump) = Pubkey::find_program_address(
+            &[b"member", address.as_ref(), owner.pubkey().as_ref()],
+            &PROGRAM_ID,
+        );
+        let (subscription, subscription_bump) = Pubkey::find_program_address(
+            &[b"subscription", owner.pubkey().as_ref(), treasury.as_ref()],
+            &subscription_program::ID,
+        );
+
+        let now = svm.clock().unix_timestamp;
+        let mut fx = Self {
+            svm,
+            payer,
+            state: Dao {
+                bump,
+                ..dao(admin.pubkey(), treasury)
+            },
+            member_state: Member {
+                bump: member_bump,
+                ..member(address, owner.pubkey(), 3)
+            },
+            subscription_state: Subscription {
+                bump: subscription_bump,
+                ..dues(owner.pubkey(), treasury, now)
+            },
+            admin,
+            owner,
+            dao: address,
+            member: member_address,
+            subscription,
+        };
+        fx.update(|_| {}, |_| {}, |_| {});
+        fx
+    }
+
+    /// Rewrite the DAO, member and subscription accounts, with a fresh
+    /// blockhash so the same instruction can be sent again
+    fn update(
+        &mut self,
+        dao: impl FnOnce(&mut Dao),
+        member: impl FnOnce(&mut Member),
+        subscription: impl FnOnce(&mut Subscription),
+    ) {
+        dao(&mut self.state);
+        member(&mut self.member_state);
+        subscription(&mut self.subscription_state);
+        self.svm
+            .set_anchor_account(self.dao, &self.state, 8 + Dao::INIT_SPACE);
+        self.svm
+            .set_anchor_account(self.member, &self.member_state, 8 + Member::INIT_SPACE);
+        self.svm.set_anchor_account(
+            self.subscription,
+            &self.subscription_state,
+            8 + Subscription::INIT_SPACE,
+        );
+        self.svm.expire_blockhash();
+    }
+
+    fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts.to_account_metas(None),
+            data: data.data(),
+        }
+    }
+
+    fn create_dao(&self) -> Instruction {
+        Self::build(
+            accounts::CreateDao {
+                dao: self.dao,
+                admin: self.admin.pubkey(),
+                payer: self.payer.pubkey(),
+                system_program: system_program::ID,
+            },
+            instruction::CreateDao {
+                treasury: self.state.treasury,
+                dues_amount: DUES,
+                dues_interval: MONTH,
+                grace_period: GRACE,
+            },
+        )
+    }
+
+    fn admit(&self, weight: u64) -> Instruction {
+        Self::build(
+            accounts::AdmitMember {
+                dao: self.dao,
+                member: self.member,
+                admin: self.admin.pubkey(),
+                owner: self.owner.pubkey(),
+                payer: self.payer.pubkey(),
+                system_program: system_program::ID,
+            },
+            instruction::AdmitMember { weight },
+        )
+    }
+
+    fn proposal(&self, proposal_id: u64) -> Pubkey {
+        Pubkey::find_program_address(
+            &[b"proposal", self.dao.as_ref(), &proposal_id.to_le_bytes()],
+            &PROGRAM_ID,
+        )
+        .0
+    }
+
+    fn vote(&self, proposal_id: u64) -> Pubkey {
+        Pubkey::find_program_address(
+            &[
+                b"vote",
+                self.proposal(proposal_id).as_ref(),
+                self.owner.pubkey().as_ref(),
+            ],
+            &PROGRAM_ID,
+        )
+        .0
+    }
+
+    fn create_proposal(&self, voting_end: i64) -> Instruction {
+        Self::build(
+            accounts::CreateProposal {
+                dao: self.dao,
+                proposal: self.proposal(self.dao_state().proposal_count),
+                member: self.member,
+                owner: self.owner.pubkey(),
+                subscription: self.subscription,
+                payer: self.payer.pubkey(),
+                system_program: system_program::ID,
+            },
+            instruction::CreateProposal {
+                description_hash: [7; 32],
+                voting_end,
+            },
+        )
+    }
+
+    fn cast_vote(&self, proposal_id: u64, approve: bool) -> Instruction {
+        Self::build(
+            accounts::CastVote {
+                dao: self.dao,
+                proposal: self.proposal(proposal_id),
+                vote: self.vote(proposal_id),
+                member: self.member,
+                owner: self.owner.pubkey(),
+                subscription: self.subscription,
+                payer: self.payer.pubkey(),
+                system_program: system_program::ID,
+            },
+            instruction::CastVote { approve },
+        )
+    }
+
+    fn update_dues(&self, admin: Pubkey, dues_amount: u64) -> Instruction {
+        Self::build(
+            accounts::UpdateDao {
+                dao: self.dao,
+                admin,
+            },
+            instruction::UpdateDues {
+                dues_amount,
+                dues_interval: MONTH,
+                grace_period: GRACE,
+            },
+        )
+    }
+
+    fn set_weight(&self, weight: u64) -> Instruction {
+        Self::build(
+            accounts::SetWeight {
+                dao: self.dao,
+                member: self.member,
+                admin: self.admin.pubkey(),
+            },
+            instruction::SetWeight { weight },
+        )
+    }
+
+    fn refresh(&self) -> Instruction {
+        Self::build(
+            accounts::RefreshMember {
+                dao: self.dao,
+                member: self.member,
+                subscription: self.subscription,
+            },
+            instruction::RefreshMember {},
+        )
+    }
+
+    fn remove(&self) -> Instruction {
+        Self::build(
+            accounts::RemoveMember {
+                dao: self.dao,
+                member: self.member,
+                admin: self.admin.pubkey(),
+            },
+            instruction::RemoveMember {},
+        )
+    }
+
+    fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TransactionResult {
+        let payer = self.payer.insecure_clone();
+        self.svm.send_instructions(&[instruction], &payer, signers)
+    }
+
+    fn send_as_admin(&mut self, instruction: Instruction) -> TransactionResult {
+        let admin = self.admin.insecure_clone();
+        self.send(instruction, &[&admin])
+    }
+
+    fn send_as_owner(&mut self, instruction: Instruction) -> TransactionResult {
+        let owner = self.owner.insecure_clone();
+        self.send(instruction, &[&owner])
+    }
+
+    fn dao_state(&self) -> Dao {
+        self.svm.get_anchor_account(&self.dao).unwrap()
+    }
+
+    fn member_account(&self) -> Option<Member> {
+        self.svm.get_anchor_account(&self.member)
+    }
+}
+
+#[test]
+fn admins_create_a_dao_and_admit_suspended_members() {
+    let mut fx = Fixture::new();
+    fx.svm.remove_account(&fx.dao);
+    fx.svm.remove_account(&fx.member);
+    let result = fx.send_as_admin(fx.create_dao());
+    assert_success(result);
+    let dao = fx.dao_state();
+    assert_eq!(dao.admin, fx.admin.pubkey());
+    assert_eq!(dao.treasury, fx.state.treasury);
+    assert_eq!(dao.dues_amount, DUES);
+    assert_eq!(dao.member_count, 0);
+
+    let result = fx.send_as_admin(fx.admit(0));
+    assert_error(result, ErrorCode::InvalidWeight);
+    let result = fx.send_as_admin(fx.admit(3));
+    assert_success(result);
+    let member = fx.member_account().unwrap();
+    assert_eq!(member.owner, fx.owner.pubkey());
+    assert_eq!(member.weight, 3);
+    assert!(!member.active);
+    let dao = fx.dao_state();
+    assert_eq!((dao.member_count, dao.active_weight), (1, 0));
+}
+
+#[test]
+fn members_with_paid_dues_propose_and_vote_once() {
+    let mut fx = Fixture::new();
+    let now = fx.svm.clock().unix_timestamp;
+    let result = fx.send_as_owner(fx.create_proposal(now));
+    assert_error(result, ErrorCode::InvalidSchedule);
+
+    let result = fx.send_as_owner(fx.create_proposal(now + 3 * 86_400));
+    assert_success(result);
+    let proposal: Proposal = fx.svm.get_anchor_account(&fx.proposal(0)).unwrap();
+    assert_eq!(proposal.proposer, fx.owner.pubkey());
+    assert_eq!(proposal.voting_end, now + 3 * 86_400);
+    assert_eq!(fx.dao_state().proposal_count, 1);
+
+    let result = fx.send_as_owner(fx.cast_vote(0, true));
+    assert_success(result);
+    let vote: Vote = fx.svm.get_anchor_account(&fx.vote(0)).unwrap();
+    assert_eq!((vote.weight, vote.approve), (3, true));
+    let proposal: Proposal = fx.svm.get_anchor_account(&fx.proposal(0)).unwrap();
+    assert_eq!((proposal.yes_weight, proposal.voter_count), (3, 1));
+
+    // The vote record already exists
+    fx.svm.expire_blockhash();
+    let result = fx.send_as_owner(fx.cast_vote(0, false));
+    assert_error(result, SystemError::AccountAlreadyInUse as u32);
+
+    // Lapsed dues stop a second proposal
+    fx.svm.advance_time(MONTH + GRACE);
+    let result = fx.send_as_owner(fx.create_proposal(now + 2 * MONTH));
+    assert_error(result, ErrorCode::DuesLapsed);
+}
+
+#[test]
+fn paying_dues_reinstates_and_lapsing_suspends() {
+    let mut fx = Fixture::new();
+    let result = fx.send(fx.refresh(), &[]);
+    assert!(result.is_ok(), "{result:#?}");
+    assert!(fx.member_account().unwrap().active);
+    let dao = fx.dao_state();
+    assert_eq!((dao.active_members, dao.active_weight), (1, 3));
+
+    fx.svm.expire_blockhash();
+    let result = fx.send(fx.refresh(), &[]);
+    assert_error(result, ErrorCode::StatusUnchanged);
+
+    // No charge for a month plus the grace period
+    fx.svm.advance_time(MONTH + GRACE);
+    let result = fx.send(fx.refresh(), &[]);
+    assert!(result.is_ok(), "{result:#?}");
+    assert!(!fx.member_account().unwrap().active);
+    let dao = fx.dao_state();
+    assert_eq!((dao.active_members, dao.active_weight), (0, 0));
+
+    // The keeper charges again
+    let now = fx.svm.clock().unix_timestamp;
+    fx.state = fx.dao_state();
+    fx.member_state = fx.member_account().unwrap();
+    fx.update(|_| {}, |_| {}, |sub| sub.last_charge_timestamp = now);
+    let result = fx.send(fx.refresh(), &[]);
+    assert!(result.is_ok(), "{result:#?}");
+    assert_eq!(fx.dao_state().active_weight, 3);
+}
+
+#[test]
+fn only_dues_to_the_treasury_count() {
+    let mut fx = Fixture::new();
+    let elsewhere = Pubkey::new_unique();
+    fx.update(|_| {}, |_| {}, |sub| sub.recipient = elsewhere);
+    let result = fx.send(fx.refresh(), &[]);
+    assert_error(result, ErrorCode::SubscriptionMismatch);
+
+    // Raising the dues leaves the old subscription short, so the member
+    // stays suspended
+    let treasury = fx.state.treasury;
+    fx.update(|_| {}, |_| {}, |sub| sub.recipient = treasury);
+    let admin = fx.admin.pubkey();
+    let result = fx.send_as_admin(fx.update_dues(admin, 2 * DUES));
+    assert!(result.is_ok(), "{result:#?}");
+    let result = fx.send(fx.refresh(), &[]);
+    assert_error(result, ErrorCode::StatusUnchanged);
+
+    let stranger = Keypair::new();
+    let result = fx.send(fx.update_dues(stranger.pubkey(), DUES), &[&stranger]);
+    assert_error(result, AnchorErrorCode::ConstraintSeeds);
+}
+
+#[test]
+fn weight_changes_follow_an_active_member() {
+    let mut fx = Fixture::new();
+    fx.update(
+        |dao| {
+            dao.active_members = 1;
+            dao.active_weight = 3;
+        },
+        |member| member.active = true,
+        |_| {},
+    );
+    let result = fx.send_as_admin(fx.set_weight(0));
+    assert_error(result, ErrorCode::InvalidWeight);
+
+    let result = fx.send_as_admin(fx.set_weight(8));
+    assert!(result.is_ok(), "{result:#?}");
+    assert_eq!(fx.member_account().unwrap().weight, 8);
+    assert_eq!(fx.dao_state().active_weight, 8);
+
+    let result = fx.send_as_admin(fx.remove());
+    assert!(result.is_ok(), "{result:#?}");
+    assert!(fx.member_account().is_none());
+    let dao = fx.dao_state();
+    assert_eq!(
+        (dao.member_count, dao.active_members, dao.active_weight),
+        (0, 0, 0)
+    );
+}
@@ -0,0 +1,580 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use spl_token::instruction as token_instruction;
+use token_utils::token_account;
+
+declare_id!("FzhPRC7fy1wqFUcS7U5qWi5RNiDUU4nU7Rzd3RtGCytx");
+
+/// Jupiter aggregator v6, the only program cycles may swap through
+pub const JUPITER_PROGRAM_ID: Pubkey = pubkey!("JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4");
+
+#[program]
+pub mod dca {
+    use super::*;
+
+    /// Buy `output_mint` with `amount_per_cycle` of `input_mint` every
+    /// `interval_seconds`. As with a subscription, the DCA PDA becomes the
+    /// delegate of `user_input_account`. Each cycle must return at least
+    /// `min_out_per_cycle`; 0 accepts any price. The first cycle is due at
+    /// once.
+    pub fn open_dca(
+        ctx: Context<OpenDca>,
+        amount_per_cycle: u64,
+        interval_seconds: i64,
+        min_out_per_cycle: u64,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        Dca::check_params(amount_per_cycle, interval_seconds)?;
+
+        let owner = ctx.accounts.owner.key();
+        let input_mint = ctx.accounts.input_mint.key();
+        let output_mint = ctx.accounts.output_mint.key();
+        let input_account = token_account(
+            &ctx.accounts.user_input_account,
+            ErrorCode::InvalidTokenAccount,
+        )?;
+        require_keys_eq!(input_account.owner, owner, ErrorCode::InvalidTokenAccount);
+        require_keys_eq!(
+            input_account.mint,
+            input_mint,
+            ErrorCode::InvalidTokenAccount
+        );
+        let output_account = token_account(
+            &ctx.accounts.user_output_account,
+            ErrorCode::InvalidTokenAccount,
+        )?;
+        require_keys_eq!(output_account.owner, owner, ErrorCode::InvalidTokenAccount);
+        require_keys_eq!(
+            output_account.mint,
+            output_mint,
+            ErrorCode::InvalidTokenAccount
+        );
+        let vault = token_account(&ctx.accounts.input_vault, ErrorCode::InvalidTokenAccount)?;
+        require_keys_eq!(
+            vault.owner,
+            ctx.accounts.dca.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+        require_keys_eq!(vault.mint, input_mint, ErrorCode::InvalidTokenAccount);
+
+        let delegate_ix = token_instruction::approve(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.user_input_account.key(),
+            &ctx.accounts.dca.key(),
+            &owner,
+            &[],
+            u64::MAX,
+        )?;
+
+        invoke(
+            &delegate_ix,
+            &[
+                ctx.accounts.user_input_account.to_account_info(),
+                ctx.accounts.dca.to_account_info(),
+                ctx.accounts.owner.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        let dca = &mut ctx.accounts.dca;
+        dca.owner = owner;
+        dca.input_mint = input_mint;
+        dca.output_mint = output_mint;
+        dca.user_input_account = ctx.accounts.user_input_account.key();
+        dca.user_output_account = ctx.accounts.user_output_account.key();
+        dca.input_vault = ctx.accounts.input_vault.key();
+        dca.amount_per_cycle = amount_per_cycle;
+        dca.interval_seconds = interval_seconds;
+        dca.min_out_per_cycle = min_out_per_cycle;
+        dca.next_cycle_at = clock.unix_timestamp;
+        dca.cycles_completed = 0;
+        dca.total_in = 0;
+        dca.total_out = 0;
+        dca.created_at = clock.unix_timestamp;
+        dca.bump = ctx.bumps.dca;
+
+        emit!(DcaOpened {
+            dca: dca.key(),
+            owner,
+            input_mint,
+            output_mint,
+            amount_per_cycle,
+            interval_seconds,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("DCA opened: {} tokens per cycle", amount_per_cycle);
+        msg!("Every {} seconds", interval_seconds);
+        msg!("Token delegation approved");
+
+        Ok(())
+    }
+
+    /// Change the amount, interval or price floor for later cycles
+    pub fn update_dca(
+        ctx: Context<UpdateDca>,
+        amount_per_cycle: u64,
+        interval_seconds: i64,
+        min_out_per_cycle: u64,
+    ) -> Result<()> {
+        Dca::check_params(amount_per_cycle, interval_seconds)?;
+
+        let dca = &mut ctx.accounts.dca;
+        dca.amount_per_cycle = amount_per_cycle;
+        dca.interval_seconds = interval_seconds;
+        dca.min_out_per_cycle = min_out_per_cycle;
+
+        msg!("DCA updated: {} tokens per cycle", amount_per_cycle);
+
+        Ok(())
+    }
+
+    /// Run one cycle. Permissionless, like `charge_subscription`: pulls
+    /// `amount_per_cycle` into the input vault, then swaps it through Jupiter
+    /// with the route the keeper passes as `route_data` and remaining
+    /// accounts. The whole amount must be swapped and at least
+    /// `min_out_per_cycle` must reach the owner's output account.
+    pub fn execute_cycle<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ExecuteCycle<'info>>,
+        route_data: Vec<u8>,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let dca = &ctx.accounts.dca;
+        dca.check_due(now)?;
+
+        let amount = dca.amount_per_cycle;
+        let vault_before =
+            token_account(&ctx.accounts.input_vault, ErrorCode::InvalidTokenAccount)?.amount;
+        let output_before = token_account(
+            &ctx.accounts.user_output_account,
+            ErrorCode::InvalidTokenAccount,
+        )?
+        .amount;
+
+        let owner = dca.owner;
+        let input_mint = dca.input_mint;
+        let output_mint = dca.output_mint;
+        let seeds = &[
+            b"dca",
+            owner.as_ref(),
+            input_mint.as_ref(),
+            output_mint.as_ref(),
+            &[dca.bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+        let dca_key = dca.key();
+
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.user_input_account.key(),
+            &ctx.accounts.input_vault.key(),
+            &dca_key,
+            &[],
+            amount,
+        )?;
+
+        invoke_signed(
+            &transfer_ix,
+            &[
+                ctx.accounts.user_input_account.to_account_info(),
+                ctx.accounts.input_vault.to_account_info(),
+                ctx.accounts.dca.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+            signer_seeds,
+        )?;
+
+        // The DCA PDA is the swap's transfer authority, so it signs here
+        // even though the keeper could not mark it a signer
+        let swap_ix = Instruction {
+            program_id: JUPITER_PROGRAM_ID,
+            accounts: ctx
+                .remaining_accounts
+                .iter()
+                .map(|account| AccountMeta {
+                    pubkey: account.key(),
+                    is_signer: account.is_signer || account.key() == dca_key,
+                    is_writable: account.is_writable,
+                })
+                .collect(),
+            data: route_data,
+        };
+        let mut swap_accounts = ctx.remaining_accounts.to_vec();
+        swap_accounts.push(ctx.accounts.jupiter_program.to_account_info());
+        invoke_signed(&swap_ix, &swap_accounts, signer_seeds)?;
+
+        let vault_after =
+            token_account(&ctx.accounts.input_vault, ErrorCode::InvalidTokenAccount)?.amount;
+        require!(vault_after <= vault_before, ErrorCode::InputNotSpent);
+        let received = token_account(
+            &ctx.accounts.user_output_account,
+            ErrorCode::InvalidTokenAccount,
+        )?
+        .amount
+        .checked_sub(output_before)
+        .ok_or(ErrorCode::SlippageExceeded)?;
+
+        let dca = &mut ctx.accounts.dca;
+        dca.record_cycle(now, received)?;
+
+        emit!(CycleExecuted {
+            dca: dca_key,
+            owner,
+            amount_in: amount,
+            amount_out: received,
+            cycles_completed: dca.cycles_completed,
+            timestamp: now,
+        });
+
+        msg!(
+            "Cycle {}: {} in, {} out",
+            dca.cycles_completed,
+            amount,
+            received
+        );
+        msg!("Next cycle at {}", dca.next_cycle_at);
+
+        Ok(())
+    }
+
+    /// Stop buying: return anything left in the vault, revoke the delegation
+    /// and close the vault and the DCA account
+    pub fn close_dca(ctx: Context<CloseDca>) -> Result<()> {
+        let dca = &ctx.accounts.dca;
+        let leftover =
+            token_account(&ctx.accounts.input_vault, ErrorCode::InvalidTokenAccount)?.amount;
+
+        let seeds = &[
+            b"dca",
+            dca.owner.as_ref(),
+            dca.input_mint.as_ref(),
+            dca.output_mint.as_ref(),
+            &[dca.bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+        let dca_info = ctx.accounts.dca.to_account_info();
+
+        if leftover > 0 {
+            let transfer_ix = token_instruction::transfer(
+                &ctx.accounts.token_program.key(),
+                &ctx.accounts.input_vault.key(),
+                &ctx.accounts.user_input_account.key(),
+                &dca_info.key(),
+                &[],
+                leftover,
+            )?;
+            invoke_signed(
+                &transfer_ix,
+                &[
+                    ctx.accounts.input_vault.to_account_info(),
+                    ctx.accounts.user_input_account.to_account_info(),
+                    dca_info.clone(),
+                    ctx.accounts.token_program.to_account_info(),
+                ],
+                signer_seeds,
+            )?;
+        }
+
+        let close_ix = token_instruction::close_account(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.input_vault.key(),
+            &ctx.accounts.owner.key(),
+            &dca_info.key(),
+            &[],
+        )?;
+        invoke_signed(
+            &close_ix,
+            &[
+                ctx.accounts.input_vault.to_account_info(),
+                ctx.accounts.owner.to_account_info(),
+                dca_info.clone(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+            signer_seeds,
+        )?;
+
+        let revoke_ix = token_instruction::revoke(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.user_input_account.key(),
+            &ctx.accounts.owner.key(),
+            &[],
+        )?;
+        invoke(
+            &revoke_ix,
+            &[
+                ctx.accounts.user_input_account.to_account_info(),
+                ctx.accounts.owner.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        emit!(DcaClosed {
+            dca: dca_info.key(),
+            owner: ctx.accounts.owner.key(),
+            cycles_completed: ctx.accounts.dca.cycles_completed,
+            total_in: ctx.accounts.dca.total_in,
+            total_out: ctx.accounts.dca.total_out,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!(
+            "DCA closed after {} cycles",
+            ctx.accounts.dca.cycles_completed
+        );
+        msg!("Token delegation revoked");
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct OpenDca<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Dca::INIT_SPACE,
+        seeds = [
+            b"dca",
+            owner.key().as_ref(),
+            input_mint.key().as_ref(),
+            output_mint.key().as_ref()
+        ],
+        bump
+    )]
+    pub dca: Account<'info, Dca>,
+
+    pub owner: Signer<'info>,
+
+    /// CHECK: Mint spent each cycle (USDC)
+    pub input_mint: UncheckedAccount<'info>,
+
+    /// CHECK: Mint bought each cycle
+    pub output_mint: UncheckedAccount<'info>,
+
+    /// CHECK: Owner's token account, checked in the handler and delegated to the DCA PDA
+    #[account(mut)]
+    pub user_input_account: UncheckedAccount<'info>,
+
+    /// CHECK: Owner's token account for the output mint, checked in the handler
+    pub user_output_account: UncheckedAccount<'info>,
+
+    /// CHECK: The DCA PDA's token account for the input mint, checked in the handler
+    pub input_vault: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateDca<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"dca",
+            owner.key().as_ref(),
+            dca.input_mint.as_ref(),
+            dca.output_mint.as_ref()
+        ],
+        bump = dca.bump,
+        has_one = owner
+    )]
+    pub dca: Account<'info, Dca>,
+
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteCycle<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"dca",
+            dca.owner.as_ref(),
+            dca.input_mint.as_ref(),
+            dca.output_mint.as_ref()
+        ],
+        bump = dca.bump,
+        has_one = user_input_account,
+        has_one = user_output_account,
+        has_one = input_vault
+    )]
+    pub dca: Account<'info, Dca>,
+
+    /// CHECK: The delegated token account
+    #[account(mut)]
+    pub user_input_account: UncheckedAccount<'info>,
+
+    /// CHECK: Receives the swap output
+    #[account(mut)]
+    pub user_output_account: UncheckedAccount<'info>,
+
+    /// CHECK: The DCA PDA's input vault, the swap's source
+    #[account(mut)]
+    pub input_vault: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    /// CHECK: Jupiter aggregator v6
+    #[account(address = JUPITER_PROGRAM_ID @ ErrorCode::InvalidSwapProgram)]
+    pub jupiter_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseDca<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"dca",
+            owner.key().as_ref(),
+            dca.input_mint.as_ref(),
+            dca.output_mint.as_ref()
+        ],
+        bump = dca.bump,
+        has_one = owner,
+        has_one = user_input_account,
+        has_one = input_vault,
+        close = owner
+    )]
+    pub dca: Account<'info, Dca>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// CHECK: The delegated token account
+    #[account(mut)]
+    pub user_input_account: UncheckedAccount<'info>,
+
+    /// CHECK: The DCA PDA's input vault, emptied and closed
+    #[account(mut)]
+    pub input_vault: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Dca {
+    pub owner: Pubkey,
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    /// Delegated to this PDA; each cycle's input is pulled from it
+    pub user_input_account: Pubkey,
+    /// Swap output lands here
+    pub user_output_account: Pubkey,
+    /// Owned by this PDA; holds the input only for the length of a swap
+    pub input_vault: Pubkey,
+    pub amount_per_cycle: u64,
+    pub interval_seconds: i64,
+    /// Least output a cycle may return; 0 for no floor
+    pub min_out_per_cycle: u64,
+    pub next_cycle_at: i64,
+    pub cycles_completed: u64,
+    pub total_in: u64,
+    pub total_out: u64,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl Dca {
+    pub fn check_params(amount_per_cycle: u64, interval_seconds: i64) -> Result<()> {
+        require!(amount_per_cycle > 0, ErrorCode::InvalidAmount);
+        require!(interval_seconds > 0, ErrorCode::InvalidInterval);
+        Ok(())
+    }
+
+    pub fn check_due(&self, now: i64) -> Result<()> {
+        require!(now >= self.next_cycle_at, ErrorCode::CycleNotDue);
+        Ok(())
+    }
+
+    /// Book a cycle run at `now` that returned `received`. The next cycle is
+    /// scheduled from `now`, so missed cycles are skipped rather than
+    /// bought in a burst.
+    pub fn record_cycle(&mut self, now: i64, received: u64) -> Result<()> {
+        require!(
+            received >= self.min_out_per_cycle,
+            ErrorCode::SlippageExceeded
+        );
+        self.cycles_completed = self
+            .cycles_completed
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.total_in = self
+            .total_in
+            .checked_add(self.amount_per_cycle)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.total_out = self
+            .total_out
+            .checked_add(received)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.next_cycle_at = now
+            .checked_add(self.interval_seconds)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DcaOpened {
+    pub dca: Pubkey,
+    pub owner: Pubkey,
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub amount_per_cycle: u64,
+    pub interval_seconds: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CycleExecuted {
+    pub dca: Pubkey,
+    pub owner: Pubkey,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub cycles_completed: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DcaClosed {
+    pub dca: Pubkey,
+    pub owner: Pubkey,
+    pub cycles_completed: u64,
+    pub total_in: u64,
+    pub total_out: u64,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Amount must be greater than zero")]
+    InvalidAmount,
+    #[msg("Interval must be positive")]
+    InvalidInterval,
+    #[msg("Invalid token account - wrong program, owner or mint")]
+    InvalidTokenAccount,
+    #[msg("Next cycle is not due yet")]
+    CycleNotDue,
+    #[msg("Swaps must go through Jupiter")]
+    InvalidSwapProgram,
+    #[msg("Swap returned less than the minimum output")]
+    SlippageExceeded,
+    #[msg("Swap did not spend the cycle's input")]
+    InputNotSpent,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
@@ -0,0 +1,386 @@
+//! Native tests for the DCA program.
+//!
+//! Cycle scheduling and slippage accounting are pure methods and are tested
+//! directly. The instructions run end to end against the real token and
+//! system programs. Jupiter is a stand-in that swaps whatever the vault holds
+//! against a pool at a fixed rate, spending the vault with the DCA PDA's
+//! signature the way a real route does.
+
+use anchor_lang::prelude::{AccountInfo, Pubkey};
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use anchor_lang::solana_program::program_error::ProgramError;
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::{system_program, InstructionData, Space, ToAccountMetas};
+use dca::{accounts, instruction, Dca, ErrorCode, ID as PROGRAM_ID, JUPITER_PROGRAM_ID};
+use test_harness::{assert_error, assert_success, Keypair, Signer, TestSvm, TransactionResult};
+
+const AMOUNT: u64 = 50_000_000;
+const WEEK: i64 = 7 * 86_400;
+/// Input units per output unit at the stand-in pool
+const RATE: u64 = 100;
+
+fn plan() -> Dca {
+    Dca {
+        owner: Pubkey::new_unique(),
+        input_mint: Pubkey::new_unique(),
+        output_mint: Pubkey::new_unique(),
+        user_input_account: Pubkey::new_unique(),
+        user_output_account: Pubkey::new_unique(),
+        input_vault: Pubkey::new_unique(),
+        amount_per_cycle: AMOUNT,
+        interval_seconds: WEEK,
+        min_out_per_cycle: 300_000,
+        next_cycle_at: 1_000,
+        cycles_completed: 0,
+        total_in: 0,
+        total_out: 0,
+        created_at: 1_000,
+        bump: 255,
+    }
+}
+
+#[test]
+fn params_must_be_positive() {
+    Dca::check_params(AMOUNT, WEEK).unwrap();
+    assert_eq!(
+        Dca::check_params(0, WEEK).unwrap_err(),
+        ErrorCode::InvalidAmount.into()
+    );
+    assert_eq!(
+        Dca::check_params(AMOUNT, 0).unwrap_err(),
+        ErrorCode::InvalidInterval.into()
+    );
+}
+
+#[test]
+fn cycles_are_booked_and_rescheduled_from_when_they_ran() {
+    let mut dca = plan();
+    assert_eq!(
+        dca.check_due(999).unwrap_err(),
+        ErrorCode::CycleNotDue.into()
+    );
+    dca.check_due(1_000).unwrap();
+
+    assert_eq!(
+        dca.record_cycle(1_000, 299_999).unwrap_err(),
+        ErrorCode::SlippageExceeded.into()
+    );
+    dca.record_cycle(1_000, 310_000).unwrap();
+    assert_eq!(dca.cycles_completed, 1);
+    assert_eq!(dca.total_in, AMOUNT);
+    assert_eq!(dca.total_out, 310_000);
+    assert_eq!(dca.next_cycle_at, 1_000 + WEEK);
+
+    // A keeper three weeks late buys once, not three times
+    let late = 1_000 + 3 * WEEK;
+    dca.record_cycle(late, 300_000).unwrap();
+    assert_eq!(dca.cycles_completed, 2);
+    assert_eq!(dca.total_out, 610_000);
+    assert_eq!(dca.next_cycle_at, late + WEEK);
+    assert_eq!(
+        dca.check_due(late + WEEK - 1).unwrap_err(),
+        ErrorCode::CycleNotDue.into()
+    );
+}
+
+fn pool_authority() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"pool"], &JUPITER_PROGRAM_ID)
+}
+
+/// Jupiter stand-in. Route accounts: token program, DCA PDA (the source's
+/// authority), input vault, pool input, pool output, pool authority, user
+/// output. Swaps the whole vault at `RATE`.
+fn jupiter<'a>(_: &Pubkey, accounts: &'a [AccountInfo<'a>], _: &[u8]) -> ProgramResult {
+    let [token_program, authority, vault, pool_input, pool_output, pool, output, ..] = accounts
+    else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+    let amount_in = spl_token::state::Account::unpack(&vault.data.borrow())?.amount;
+    invoke(
+        &spl_token::instruction::transfer(
+            token_program.key,
+            vault.key,
+            pool_input.key,
+            authority.key,
+            &[],
+            amount_in,
+        )?,
+        &[vault.clone(), pool_input.clone(), authority.clone()],
+    )?;
+    invoke_signed(
+        &spl_token::instruction::transfer(
+            token_program.key,
+            pool_output.key,
+            output.key,
+            pool.key,
+            &[],
+            amount_in / RATE,
+        )?,
+        &[pool_output.clone(), output.clone(), pool.clone()],
+        &[&[b"pool", &[pool_authority().1]]],
+    )
+}
+
+struct Fixture {
+    svm: TestSvm,
+    payer: Keypair,
+    owner: Keypair,
+    dca: Pubkey,
+    state: Dca,
+    pool_input: Pubkey,
+    pool_output: Pubkey,
+}
+
+impl Fixture {
+    /// An open plan whose next cycle is due at `next_cycle_at`
+    fn new(next_cycle_at: i64) -> Self {
+        let (mut svm, payer) = TestSvm::for_program(PROGRAM_ID, dca::entry);
+        svm.add_program(JUPITER_PROGRAM_ID, jupiter);
+
+        let owner = Keypair::new();
+        svm.airdrop(&owner.pubkey(), 1_000_000_000);
+
+        let input_mint = svm.create_mint(&Pubkey::new_unique(), 6);
+        let output_mint = svm.create_mint(&Pubkey::new_unique(), 9);
+        let (dca, bump) = Pubkey::find_program_address(
+            &[
+                b"dca",
+                owner.pubkey().as_ref(),
+                input_mint.as_ref(),
+                output_mint.as_ref(),
+            ],
+            &PROGRAM_ID,
+        );
+        let user_input_account =
+            svm.create_associated_token_account(&owner.pubkey(), &input_mint, 500_000_000);
+        svm.approve(&user_input_account, &dca, u64::MAX);
+        let user_output_account =
+            svm.create_associated_token_account(&owner.pubkey(), &output_mint, 0);
+        let input_vault = svm.create_associated_token_account(&dca, &input_mint, 0);
+        let pool = pool_authority().0;
+        let pool_input = svm.create_token_account(&pool, &input_mint, 0);
+        let pool_output = svm.create_token_account(&pool, &output_mint, 1_000_000_000);
+
+        let state = Dca {
+            owner: owner.pubkey(),
+            input_mint,
+            output_mint,
+            user_input_account,
+            user_output_account,
+            input_vault,
+            next_cycle_at,
+            bump,
+            ..plan()
+        };
+        svm.set_anchor_account(dca, &state, 8 + Dca::INIT_SPACE);
+
+        Self {
+            svm,
+            payer,
+            owner,
+            dca,
+            state,
+            pool_input,
+            pool_output,
+        }
+    }
+
+    fn now(&self) -> i64 {
+        self.svm.clock().unix_timestamp
+    }
+
+    fn execute(&self, jupiter_program: Pubkey) -> Instruction {
+        let mut accounts = accounts::ExecuteCycle {
+            dca: self.dca,
+            user_input_account: self.state.user_input_account,
+            user_output_account: self.state.user_output_account,
+            input_vault: self.state.input_vault,
+            token_program: spl_token::ID,
+            jupiter_program,
+        }
+        .to_account_metas(None);
+        accounts.extend([
+            AccountMeta::new_readonly(spl_token::ID, false),
+            AccountMeta::new_readonly(self.dca, false),
+            AccountMeta::new(self.state.input_vault, false),
+            AccountMeta::new(self.pool_input, false),
+            AccountMeta::new(self.pool_output, false),
+            AccountMeta::new_readonly(pool_authority().0, false),
+            AccountMeta::new(self.state.user_output_account, false),
+        ]);
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts,
+            data: instruction::ExecuteCycle {
+                route_data: vec![0xe5, 0x17, 0xcb, 0x97],
+            }
+            .data(),
+        }
+    }
+
+    fn open(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::OpenDca {
+                dca: self.dca,
+                owner: self.owner.pubkey(),
+                input_mint: self.state.input_mint,
+                output_mint: self.state.output_mint,
+                user_input_account: self.state.user_input_account,
+                user_output_account: self.state.user_output_account,
+                input_vault: self.state.input_vault,
+                payer: self.payer.pubkey(),
+                token_program: spl_token::ID,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::OpenDca {
+                amount_per_cycle: AMOUNT,
+                interval_seconds: WEEK,
+                min_out_per_cycle: 300_000,
+            }
+            .data(),
+        }
+    }
+
+    fn update(&self, owner: Pubkey, amount_per_cycle: u64) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::UpdateDca {
+                dca: self.dca,
+                owner,
+            }
+            .to_account_metas(None),
+            data: instruction::UpdateDca {
+                amount_per_cycle,
+                interval_seconds: WEEK,
+                min_out_per_cycle: 0,
+            }
+            .data(),
+        }
+    }
+
+    fn close(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CloseDca {
+                dca: self.dca,
+                owner: self.owner.pubkey(),
+                user_input_account: self.state.user_input_account,
+                input_vault: self.state.input_vault,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CloseDca {}.data(),
+        }
+    }
+
+    fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TransactionResult {
+        let payer = self.payer.insecure_clone();
+        self.svm.send_instructions(&[instruction], &payer, signers)
+    }
+
+    fn send_as_owner(&mut self, instruction: Instruction) -> TransactionResult {
+        let owner = self.owner.insecure_clone();
+        self.send(instruction, &[&owner])
+    }
+
+    fn dca(&self) -> Dca {
+        self.svm.get_anchor_account(&self.dca).unwrap()
+    }
+}
+
+#[test]
+fn opening_delegates_and_is_due_at_once() {
+    let mut fx = Fixture::new(0);
+    fx.svm.remove_account(&fx.dca);
+    fx.svm.revoke(&fx.state.user_input_account);
+
+    let result = fx.send_as_owner(fx.open());
+    assert_success(result);
+    let input = fx.svm.get_token_account(&fx.state.user_input_account).unwrap();
+    assert_eq!(input.delegate, Some(fx.dca).into());
+    assert_eq!(input.delegated_amount, u64::MAX);
+    let dca = fx.dca();
+    assert_eq!(dca.owner, fx.owner.pubkey());
+    assert_eq!(dca.input_vault, fx.state.input_vault);
+    assert_eq!(dca.amount_per_cycle, AMOUNT);
+    assert_eq!(dca.next_cycle_at, fx.now());
+    assert_eq!(dca.bump, fx.state.bump);
+}
+
+#[test]
+fn cycles_run_once_due() {
+    let now = Fixture::new(0).now();
+    let mut fx = Fixture::new(now + 1);
+    let result = fx.send(fx.execute(JUPITER_PROGRAM_ID), &[]);
+    assert_error(result, ErrorCode::CycleNotDue);
+
+    fx.svm.warp_to_timestamp(now + 1);
+    let result = fx.send(fx.execute(JUPITER_PROGRAM_ID), &[]);
+    assert_success(result);
+    assert_eq!(fx.svm.token_balance(&fx.state.user_input_account), 450_000_000);
+    assert_eq!(fx.svm.token_balance(&fx.state.input_vault), 0);
+    assert_eq!(fx.svm.token_balance(&fx.pool_input), AMOUNT);
+    assert_eq!(
+        fx.svm.token_balance(&fx.state.user_output_account),
+        AMOUNT / RATE
+    );
+    let dca = fx.dca();
+    assert_eq!(dca.cycles_completed, 1);
+    assert_eq!(dca.total_out, AMOUNT / RATE);
+    assert_eq!(dca.next_cycle_at, now + 1 + WEEK);
+
+    fx.svm.expire_blockhash();
+    let result = fx.send(fx.execute(JUPITER_PROGRAM_ID), &[]);
+    assert_error(result, ErrorCode::CycleNotDue);
+}
+
+#[test]
+fn cycles_only_swap_through_jupiter() {
+    let now = Fixture::new(0).now();
+    let mut fx = Fixture::new(now);
+    let result = fx.send(fx.execute(Pubkey::new_unique()), &[]);
+    assert_error(result, ErrorCode::InvalidSwapProgram);
+}
+
+#[test]
+fn only_the_owner_updates_the_plan() {
+    let mut fx = Fixture::new(0);
+    let stranger = Keypair::new();
+    let result = fx.send(fx.update(stranger.pubkey(), 1), &[&stranger]);
+    assert!(result.is_err());
+
+    let result = fx.send_as_owner(fx.update(fx.owner.pubkey(), 0));
+    assert_error(result, ErrorCode::InvalidAmount);
+
+    let result = fx.send_as_owner(fx.update(fx.owner.pubkey(), 10_000_000));
+    assert!(result.is_ok(), "{result:#?}");
+    let dca = fx.dca();
+    assert_eq!(dca.amount_per_cycle, 10_000_000);
+    assert_eq!(dca.min_out_per_cycle, 0);
+}
+
+#[test]
+fn closing_returns_the_vault_and_revokes() {
+    let mut fx = Fixture::new(0);
+    fx.svm
+        .transfer_tokens(&fx.state.user_input_account, &fx.state.input_vault, 7);
+    let vault_rent = fx.svm.get_balance(&fx.state.input_vault);
+    let dca_rent = fx.svm.get_balance(&fx.dca);
+    let owner_before = fx.svm.get_balance(&fx.owner.pubkey());
+
+    let result = fx.send_as_owner(fx.close());
+    assert_success(result);
+    let input = fx.svm.get_token_account(&fx.state.user_input_account).unwrap();
+    assert_eq!(input.amount, 500_000_000);
+    assert!(input.delegate.is_none());
+    assert!(fx.svm.get_account(&fx.state.input_vault).is_none());
+    assert!(fx.svm.get_account(&fx.dca).is_none());
+    assert_eq!(
+        fx.svm.get_balance(&fx.owner.pubkey()),
+        owner_before + vault_rent + dca_rent
+    );
+}
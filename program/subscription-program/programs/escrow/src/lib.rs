@@ -0,0 +1,569 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use spl_token::instruction as token_instruction;
+use token_utils::token_account;
+
+declare_id!("J8eBkrBQiGh7aLSHtHfAe7YG2yfQcSKm6k8Enkeww1e");
+
+#[program]
+pub mod escrow {
+    use super::*;
+
+    /// Buyer locks `amount` in a vault owned by the escrow PDA. The seller has
+    /// until `delivery_deadline` to deliver; the buyer then has
+    /// `review_period` seconds to dispute before funds release to the seller.
+    pub fn create_escrow(
+        ctx: Context<CreateEscrow>,
+        escrow_id: u64,
+        amount: u64,
+        delivery_deadline: i64,
+        review_period: i64,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        Escrow::check_params(
+            amount,
+            delivery_deadline,
+            review_period,
+            clock.unix_timestamp,
+        )?;
+
+        let escrow_key = ctx.accounts.escrow.key();
+        let mint_key = ctx.accounts.mint.key();
+        let vault = token_account(&ctx.accounts.vault, ErrorCode::InvalidTokenAccount)?;
+        require_keys_eq!(vault.owner, escrow_key, ErrorCode::InvalidTokenAccount);
+        require_keys_eq!(vault.mint, mint_key, ErrorCode::InvalidTokenAccount);
+        let seller_account = token_account(
+            &ctx.accounts.seller_token_account,
+            ErrorCode::InvalidTokenAccount,
+        )?;
+        require_keys_eq!(
+            seller_account.owner,
+            ctx.accounts.seller.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+        require_keys_eq!(
+            seller_account.mint,
+            mint_key,
+            ErrorCode::InvalidTokenAccount
+        );
+
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.buyer_token_account.key(),
+            &ctx.accounts.vault.key(),
+            &ctx.accounts.buyer.key(),
+            &[],
+            amount,
+        )?;
+
+        invoke(
+            &transfer_ix,
+            &[
+                ctx.accounts.buyer_token_account.to_account_info(),
+                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.buyer.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.buyer = ctx.accounts.buyer.key();
+        escrow.seller = ctx.accounts.seller.key();
+        escrow.arbiter = ctx.accounts.arbiter.key();
+        escrow.mint = mint_key;
+        escrow.buyer_token_account = ctx.accounts.buyer_token_account.key();
+        escrow.seller_token_account = ctx.accounts.seller_token_account.key();
+        escrow.vault = ctx.accounts.vault.key();
+        escrow.escrow_id = escrow_id;
+        escrow.amount = amount;
+        escrow.state = EscrowState::Funded;
+        escrow.delivery_deadline = delivery_deadline;
+        escrow.review_period = review_period;
+        escrow.delivered_at = None;
+        escrow.created_at = clock.unix_timestamp;
+        escrow.bump = ctx.bumps.escrow;
+
+        emit!(EscrowCreated {
+            escrow: escrow_key,
+            buyer: escrow.buyer,
+            seller: escrow.seller,
+            arbiter: escrow.arbiter,
+            amount,
+            delivery_deadline,
+            review_period,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("Escrow funded: {} tokens", amount);
+        msg!("Delivery due by {}", delivery_deadline);
+
+        Ok(())
+    }
+
+    /// Seller marks the order delivered, starting the buyer's review period
+    pub fn mark_delivered(ctx: Context<MarkDelivered>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.record_delivery(now)?;
+
+        emit!(EscrowDelivered {
+            escrow: escrow.key(),
+            review_ends_at: escrow.review_ends_at().unwrap_or(now),
+            timestamp: now,
+        });
+
+        msg!("Marked delivered");
+
+        Ok(())
+    }
+
+    /// Buyer or seller freezes the escrow until the arbiter resolves it
+    pub fn open_dispute(ctx: Context<OpenDispute>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let opened_by = ctx.accounts.signer.key();
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.record_dispute(&opened_by, now)?;
+
+        emit!(EscrowDisputed {
+            escrow: escrow.key(),
+            opened_by,
+            timestamp: now,
+        });
+
+        msg!("Dispute opened by {}", opened_by);
+
+        Ok(())
+    }
+
+    /// Buyer pays the seller early (before or after delivery)
+    pub fn release(ctx: Context<Settle>) -> Result<()> {
+        let escrow = &ctx.accounts.escrow;
+        require_keys_eq!(
+            ctx.accounts.signer.key(),
+            escrow.buyer,
+            ErrorCode::Unauthorized
+        );
+        require!(
+            escrow.state != EscrowState::Disputed,
+            ErrorCode::InvalidState
+        );
+        settle(ctx, Resolution::Release)
+    }
+
+    /// Seller returns the funds to the buyer
+    pub fn refund(ctx: Context<Settle>) -> Result<()> {
+        let escrow = &ctx.accounts.escrow;
+        require_keys_eq!(
+            ctx.accounts.signer.key(),
+            escrow.seller,
+            ErrorCode::Unauthorized
+        );
+        require!(
+            escrow.state != EscrowState::Disputed,
+            ErrorCode::InvalidState
+        );
+        settle(ctx, Resolution::Refund)
+    }
+
+    /// Arbiter splits a disputed escrow: `seller_share` to the seller, the rest
+    /// back to the buyer
+    pub fn resolve_dispute(ctx: Context<Settle>, seller_share: u64) -> Result<()> {
+        let escrow = &ctx.accounts.escrow;
+        require_keys_eq!(
+            ctx.accounts.signer.key(),
+            escrow.arbiter,
+            ErrorCode::Unauthorized
+        );
+        require!(
+            escrow.state == EscrowState::Disputed,
+            ErrorCode::InvalidState
+        );
+        settle(ctx, Resolution::Split { seller_share })
+    }
+
+    /// Permissionless crank for keepers: release once the review period has
+    /// passed without a dispute, refund if the seller missed the deadline
+    pub fn settle_expired(ctx: Context<Settle>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let resolution = ctx.accounts.escrow.expired_resolution(now)?;
+        settle(ctx, resolution)
+    }
+}
+
+/// Pay out the vault per `resolution`, then close the vault and the escrow
+fn settle(ctx: Context<Settle>, resolution: Resolution) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let escrow = &ctx.accounts.escrow;
+    let vault_balance = token_account(&ctx.accounts.vault, ErrorCode::InvalidTokenAccount)?.amount;
+    let (to_seller, to_buyer) = Escrow::payouts(resolution, vault_balance)?;
+
+    let escrow_id = escrow.escrow_id.to_le_bytes();
+    let seeds = &[
+        b"escrow",
+        escrow.buyer.as_ref(),
+        escrow.seller.as_ref(),
+        escrow_id.as_ref(),
+        &[escrow.bump],
+    ];
+    let signer_seeds = &[&seeds[..]];
+    let escrow_info = ctx.accounts.escrow.to_account_info();
+
+    for (destination, amount) in [
+        (&ctx.accounts.seller_token_account, to_seller),
+        (&ctx.accounts.buyer_token_account, to_buyer),
+    ] {
+        if amount == 0 {
+            continue;
+        }
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.vault.key(),
+            &destination.key(),
+            &escrow_info.key(),
+            &[],
+            amount,
+        )?;
+        invoke_signed(
+            &transfer_ix,
+            &[
+                ctx.accounts.vault.to_account_info(),
+                destination.to_account_info(),
+                escrow_info.clone(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+            signer_seeds,
+        )?;
+    }
+
+    let close_ix = token_instruction::close_account(
+        &ctx.accounts.token_program.key(),
+        &ctx.accounts.vault.key(),
+        &ctx.accounts.buyer.key(),
+        &escrow_info.key(),
+        &[],
+    )?;
+    invoke_signed(
+        &close_ix,
+        &[
+            ctx.accounts.vault.to_account_info(),
+            ctx.accounts.buyer.to_account_info(),
+            escrow_info.clone(),
+            ctx.accounts.token_program.to_account_info(),
+        ],
+        signer_seeds,
+    )?;
+
+    emit!(EscrowSettled {
+        escrow: escrow.key(),
+        settled_by: ctx.accounts.signer.key(),
+        to_seller,
+        to_buyer,
+        timestamp: now,
+    });
+
+    msg!("Escrow settled");
+    msg!("Paid to seller: {} tokens", to_seller);
+    msg!("Refunded to buyer: {} tokens", to_buyer);
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(escrow_id: u64)]
+pub struct CreateEscrow<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Escrow::INIT_SPACE,
+        seeds = [
+            b"escrow",
+            buyer.key().as_ref(),
+            seller.key().as_ref(),
+            escrow_id.to_le_bytes().as_ref(),
+        ],
+        bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    pub buyer: Signer<'info>,
+
+    /// CHECK: Seller wallet
+    pub seller: UncheckedAccount<'info>,
+
+    /// CHECK: Resolves disputes; any wallet both parties trust
+    pub arbiter: UncheckedAccount<'info>,
+
+    /// CHECK: Token mint (USDC)
+    pub mint: UncheckedAccount<'info>,
+
+    /// CHECK: Buyer's token account, debited by the token program
+    #[account(mut)]
+    pub buyer_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Seller's token account, checked in the handler
+    pub seller_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Token account owned by the escrow PDA (usually its ATA)
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MarkDelivered<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"escrow",
+            escrow.buyer.as_ref(),
+            escrow.seller.as_ref(),
+            escrow.escrow_id.to_le_bytes().as_ref(),
+        ],
+        bump = escrow.bump,
+        has_one = seller
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    pub seller: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct OpenDispute<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"escrow",
+            escrow.buyer.as_ref(),
+            escrow.seller.as_ref(),
+            escrow.escrow_id.to_le_bytes().as_ref(),
+        ],
+        bump = escrow.bump,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// Buyer or seller
+    pub signer: Signer<'info>,
+}
+
+/// Shared by every instruction that pays out and closes the escrow; each
+/// checks who `signer` must be
+#[derive(Accounts)]
+pub struct Settle<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"escrow",
+            escrow.buyer.as_ref(),
+            escrow.seller.as_ref(),
+            escrow.escrow_id.to_le_bytes().as_ref(),
+        ],
+        bump = escrow.bump,
+        has_one = buyer,
+        close = buyer
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    pub signer: Signer<'info>,
+
+    /// CHECK: Receives the escrow and vault rent
+    #[account(mut)]
+    pub buyer: UncheckedAccount<'info>,
+
+    /// CHECK: Buyer's token account
+    #[account(
+        mut,
+        constraint = buyer_token_account.key() == escrow.buyer_token_account
+    )]
+    pub buyer_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Seller's token account
+    #[account(
+        mut,
+        constraint = seller_token_account.key() == escrow.seller_token_account
+    )]
+    pub seller_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Escrow vault, closed here
+    #[account(
+        mut,
+        constraint = vault.key() == escrow.vault
+    )]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub enum EscrowState {
+    Funded,
+    Delivered,
+    Disputed,
+}
+
+/// How a vault is paid out
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resolution {
+    Release,
+    Refund,
+    Split { seller_share: u64 },
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Escrow {
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub arbiter: Pubkey,
+    pub mint: Pubkey,
+    pub buyer_token_account: Pubkey,
+    pub seller_token_account: Pubkey,
+    pub vault: Pubkey,
+    pub escrow_id: u64,
+    pub amount: u64,
+    pub state: EscrowState,
+    pub delivery_deadline: i64,
+    pub review_period: i64,
+    pub delivered_at: Option<i64>,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl Escrow {
+    pub fn check_params(
+        amount: u64,
+        delivery_deadline: i64,
+        review_period: i64,
+        now: i64,
+    ) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        require!(delivery_deadline > now, ErrorCode::InvalidDeadline);
+        require!(review_period > 0, ErrorCode::InvalidDeadline);
+        Ok(())
+    }
+
+    /// End of the buyer's window to dispute a delivery
+    pub fn review_ends_at(&self) -> Option<i64> {
+        self.delivered_at
+            .map(|delivered_at| delivered_at.saturating_add(self.review_period))
+    }
+
+    pub fn record_delivery(&mut self, now: i64) -> Result<()> {
+        require!(self.state == EscrowState::Funded, ErrorCode::InvalidState);
+        require!(
+            now <= self.delivery_deadline,
+            ErrorCode::DeliveryDeadlinePassed
+        );
+        self.state = EscrowState::Delivered;
+        self.delivered_at = Some(now);
+        Ok(())
+    }
+
+    /// Either party may dispute until the escrow can be settled by the crank
+    pub fn record_dispute(&mut self, opened_by: &Pubkey, now: i64) -> Result<()> {
+        require!(
+            *opened_by == self.buyer || *opened_by == self.seller,
+            ErrorCode::Unauthorized
+        );
+        require!(self.state != EscrowState::Disputed, ErrorCode::InvalidState);
+        require!(
+            self.expired_resolution(now).is_err(),
+            ErrorCode::ReviewPeriodEnded
+        );
+        self.state = EscrowState::Disputed;
+        Ok(())
+    }
+
+    /// What `settle_expired` does at `now`, if anything
+    pub fn expired_resolution(&self, now: i64) -> Result<Resolution> {
+        match self.state {
+            EscrowState::Funded if now > self.delivery_deadline => Ok(Resolution::Refund),
+            EscrowState::Delivered if self.review_ends_at().is_some_and(|end| now >= end) => {
+                Ok(Resolution::Release)
+            }
+            _ => err!(ErrorCode::NotExpired),
+        }
+    }
+
+    /// (to seller, to buyer) out of `vault_balance`
+    pub fn payouts(resolution: Resolution, vault_balance: u64) -> Result<(u64, u64)> {
+        match resolution {
+            Resolution::Release => Ok((vault_balance, 0)),
+            Resolution::Refund => Ok((0, vault_balance)),
+            Resolution::Split { seller_share } => {
+                require!(seller_share <= vault_balance, ErrorCode::InvalidSplit);
+                Ok((seller_share, vault_balance - seller_share))
+            }
+        }
+    }
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct EscrowCreated {
+    pub escrow: Pubkey,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub arbiter: Pubkey,
+    pub amount: u64,
+    pub delivery_deadline: i64,
+    pub review_period: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct EscrowDelivered {
+    pub escrow: Pubkey,
+    pub review_ends_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct EscrowDisputed {
+    pub escrow: Pubkey,
+    pub opened_by: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct EscrowSettled {
+    pub escrow: Pubkey,
+    pub settled_by: Pubkey,
+    pub to_seller: u64,
+    pub to_buyer: u64,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Amount must be greater than zero")]
+    InvalidAmount,
+    #[msg("Delivery deadline must be in the future and the review period positive")]
+    InvalidDeadline,
+    #[msg("Invalid token account - wrong program, owner or mint")]
+    InvalidTokenAccount,
+    #[msg("Escrow is not in a state that allows this")]
+    InvalidState,
+    #[msg("Delivery deadline has passed")]
+    DeliveryDeadlinePassed,
+    #[msg("Review period has ended - the escrow can only be settled")]
+    ReviewPeriodEnded,
+    #[msg("Neither the delivery deadline nor the review period has passed")]
+    NotExpired,
+    #[msg("Signer is not allowed to do this")]
+    Unauthorized,
+    #[msg("Seller share exceeds the escrowed amount")]
+    InvalidSplit,
+}
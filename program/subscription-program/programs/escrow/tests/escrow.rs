@@ -0,0 +1,468 @@
+//! Native tests for the escrow program.
+//!
+//! The state machine and payout split are pure methods on `Escrow` and are
+//! tested directly. Instruction tests cover who may sign what and run end to
+//! end against the real token and system programs, checking where the vault
+//! ends up.
+
+use anchor_lang::error::ErrorCode as AnchorErrorCode;
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, Space, ToAccountMetas};
+use escrow::{accounts, instruction, ErrorCode, Escrow, EscrowState, Resolution, ID as PROGRAM_ID};
+use test_harness::{assert_error, assert_success, Keypair, Signer, TestSvm, TransactionResult};
+
+const AMOUNT: u64 = 25_000_000;
+const ESCROW_ID: u64 = 3;
+const DAY: i64 = 86_400;
+
+fn escrow_at(now: i64) -> Escrow {
+    Escrow {
+        buyer: Pubkey::new_unique(),
+        seller: Pubkey::new_unique(),
+        arbiter: Pubkey::new_unique(),
+        mint: Pubkey::new_unique(),
+        buyer_token_account: Pubkey::new_unique(),
+        seller_token_account: Pubkey::new_unique(),
+        vault: Pubkey::new_unique(),
+        escrow_id: ESCROW_ID,
+        amount: AMOUNT,
+        state: EscrowState::Funded,
+        delivery_deadline: now + 7 * DAY,
+        review_period: 3 * DAY,
+        delivered_at: None,
+        created_at: now,
+        bump: 255,
+    }
+}
+
+#[test]
+fn rejects_empty_amount_and_past_deadline() {
+    assert_eq!(
+        Escrow::check_params(0, DAY, DAY, 0).unwrap_err(),
+        ErrorCode::InvalidAmount.into()
+    );
+    assert_eq!(
+        Escrow::check_params(AMOUNT, 0, DAY, 0).unwrap_err(),
+        ErrorCode::InvalidDeadline.into()
+    );
+    assert_eq!(
+        Escrow::check_params(AMOUNT, DAY, 0, 0).unwrap_err(),
+        ErrorCode::InvalidDeadline.into()
+    );
+    Escrow::check_params(AMOUNT, DAY, DAY, 0).unwrap();
+}
+
+#[test]
+fn delivery_must_beat_the_deadline() {
+    let mut late = escrow_at(0);
+    assert_eq!(
+        late.record_delivery(7 * DAY + 1).unwrap_err(),
+        ErrorCode::DeliveryDeadlinePassed.into()
+    );
+
+    let mut escrow = escrow_at(0);
+    escrow.record_delivery(DAY).unwrap();
+    assert_eq!(escrow.state, EscrowState::Delivered);
+    assert_eq!(escrow.review_ends_at(), Some(4 * DAY));
+    assert_eq!(
+        escrow.record_delivery(DAY).unwrap_err(),
+        ErrorCode::InvalidState.into()
+    );
+}
+
+#[test]
+fn disputes_close_with_the_review_period() {
+    let mut escrow = escrow_at(0);
+    let buyer = escrow.buyer;
+    escrow.record_delivery(DAY).unwrap();
+
+    assert_eq!(
+        escrow
+            .record_dispute(&Pubkey::new_unique(), DAY)
+            .unwrap_err(),
+        ErrorCode::Unauthorized.into()
+    );
+    assert_eq!(
+        escrow.clone().record_dispute(&buyer, 4 * DAY).unwrap_err(),
+        ErrorCode::ReviewPeriodEnded.into()
+    );
+
+    escrow.record_dispute(&buyer, 4 * DAY - 1).unwrap();
+    assert_eq!(escrow.state, EscrowState::Disputed);
+    assert_eq!(
+        escrow.record_dispute(&buyer, 4 * DAY - 1).unwrap_err(),
+        ErrorCode::InvalidState.into()
+    );
+}
+
+#[test]
+fn expiry_releases_after_review_and_refunds_missed_deadlines() {
+    let funded = escrow_at(0);
+    assert_eq!(
+        funded.expired_resolution(7 * DAY).unwrap_err(),
+        ErrorCode::NotExpired.into()
+    );
+    assert_eq!(
+        funded.expired_resolution(7 * DAY + 1).unwrap(),
+        Resolution::Refund
+    );
+
+    let mut delivered = escrow_at(0);
+    delivered.record_delivery(DAY).unwrap();
+    assert_eq!(
+        delivered.expired_resolution(4 * DAY - 1).unwrap_err(),
+        ErrorCode::NotExpired.into()
+    );
+    assert_eq!(
+        delivered.expired_resolution(4 * DAY).unwrap(),
+        Resolution::Release
+    );
+
+    let mut disputed = delivered;
+    disputed.state = EscrowState::Disputed;
+    assert_eq!(
+        disputed.expired_resolution(i64::MAX).unwrap_err(),
+        ErrorCode::NotExpired.into()
+    );
+}
+
+#[test]
+fn payouts_split_the_vault_balance() {
+    assert_eq!(
+        Escrow::payouts(Resolution::Release, AMOUNT).unwrap(),
+        (AMOUNT, 0)
+    );
+    assert_eq!(
+        Escrow::payouts(Resolution::Refund, AMOUNT).unwrap(),
+        (0, AMOUNT)
+    );
+    let seller_share = AMOUNT / 5;
+    assert_eq!(
+        Escrow::payouts(Resolution::Split { seller_share }, AMOUNT).unwrap(),
+        (seller_share, AMOUNT - seller_share)
+    );
+    assert_eq!(
+        Escrow::payouts(
+            Resolution::Split {
+                seller_share: AMOUNT + 1
+            },
+            AMOUNT
+        )
+        .unwrap_err(),
+        ErrorCode::InvalidSplit.into()
+    );
+}
+
+struct Fixture {
+    svm: TestSvm,
+    payer: Keypair,
+    buyer: Keypair,
+    seller: Keypair,
+    arbiter: Keypair,
+    escrow: Pubkey,
+    state: Escrow,
+}
+
+impl Fixture {
+    /// A funded escrow created at the harness's default time
+    fn new() -> Self {
+        let (mut svm, payer) = TestSvm::for_program(PROGRAM_ID, escrow::entry);
+
+        let buyer = Keypair::new();
+        let seller = Keypair::new();
+        let arbiter = Keypair::new();
+
+        let mint = svm.create_mint(&Pubkey::new_unique(), 6);
+        let (escrow, bump) = Pubkey::find_program_address(
+            &[
+                b"escrow",
+                buyer.pubkey().as_ref(),
+                seller.pubkey().as_ref(),
+                &ESCROW_ID.to_le_bytes(),
+            ],
+            &PROGRAM_ID,
+        );
+        let now = svm.clock().unix_timestamp;
+        let state = Escrow {
+            buyer: buyer.pubkey(),
+            seller: seller.pubkey(),
+            arbiter: arbiter.pubkey(),
+            mint,
+            buyer_token_account: svm.create_associated_token_account(&buyer.pubkey(), &mint, 0),
+            seller_token_account: svm.create_associated_token_account(&seller.pubkey(), &mint, 0),
+            vault: svm.create_associated_token_account(&escrow, &mint, AMOUNT),
+            bump,
+            ..escrow_at(now)
+        };
+        svm.set_anchor_account(escrow, &state, 8 + Escrow::INIT_SPACE);
+
+        Self {
+            svm,
+            payer,
+            buyer,
+            seller,
+            arbiter,
+            escrow,
+            state,
+        }
+    }
+
+    fn mark_delivered(&self, seller: &Pubkey) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::MarkDelivered {
+                escrow: self.escrow,
+                seller: *seller,
+            }
+            .to_account_metas(None),
+            data: instruction::MarkDelivered {}.data(),
+        }
+    }
+
+    fn open_dispute(&self, signer: &Pubkey) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::OpenDispute {
+                escrow: self.escrow,
+                signer: *signer,
+            }
+            .to_account_metas(None),
+            data: instruction::OpenDispute {}.data(),
+        }
+    }
+
+    fn settle(&self, signer: &Pubkey, data: Vec<u8>) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::Settle {
+                escrow: self.escrow,
+                signer: *signer,
+                buyer: self.state.buyer,
+                buyer_token_account: self.state.buyer_token_account,
+                seller_token_account: self.state.seller_token_account,
+                vault: self.state.vault,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data,
+        }
+    }
+
+    fn create(&self, escrow_id: u64) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CreateEscrow {
+                escrow: self.escrow,
+                buyer: self.state.buyer,
+                seller: self.state.seller,
+                arbiter: self.state.arbiter,
+                mint: self.state.mint,
+                buyer_token_account: self.state.buyer_token_account,
+                seller_token_account: self.state.seller_token_account,
+                vault: self.state.vault,
+                token_program: spl_token::ID,
+                payer: self.payer.pubkey(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CreateEscrow {
+                escrow_id,
+                amount: AMOUNT,
+                delivery_deadline: self.state.delivery_deadline,
+                review_period: self.state.review_period,
+            }
+            .data(),
+        }
+    }
+
+    /// Assert the vault paid out this split, then closed with the escrow
+    fn assert_settled(&self, to_seller: u64, to_buyer: u64) {
+        assert_eq!(
+            self.svm.token_balance(&self.state.seller_token_account),
+            to_seller
+        );
+        assert_eq!(
+            self.svm.token_balance(&self.state.buyer_token_account),
+            to_buyer
+        );
+        assert!(self.svm.get_account(&self.state.vault).is_none());
+        assert!(self.svm.get_account(&self.escrow).is_none());
+    }
+
+    fn escrow_state(&self) -> Escrow {
+        self.svm.get_anchor_account(&self.escrow).unwrap()
+    }
+
+    fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TransactionResult {
+        let payer = self.payer.insecure_clone();
+        self.svm.send_instructions(&[instruction], &payer, signers)
+    }
+
+    fn deliver(&mut self) {
+        let seller = self.seller.insecure_clone();
+        self.send(self.mark_delivered(&seller.pubkey()), &[&seller])
+            .unwrap();
+    }
+}
+
+#[test]
+fn buyer_funds_the_vault_on_creation() {
+    let mut fx = Fixture::new();
+    fx.svm.remove_account(&fx.escrow);
+    fx.svm
+        .transfer_tokens(&fx.state.vault, &fx.state.buyer_token_account, AMOUNT);
+    let buyer = fx.buyer.insecure_clone();
+
+    let result = fx.send(fx.create(ESCROW_ID + 1), &[&buyer]);
+    assert_error(result, AnchorErrorCode::ConstraintSeeds);
+
+    let result = fx.send(fx.create(ESCROW_ID), &[&buyer]);
+    assert_success(result);
+    assert_eq!(fx.svm.token_balance(&fx.state.buyer_token_account), 0);
+    assert_eq!(fx.svm.token_balance(&fx.state.vault), AMOUNT);
+    let state = fx.escrow_state();
+    assert_eq!(state.state, EscrowState::Funded);
+    assert_eq!(state.arbiter, fx.state.arbiter);
+    assert_eq!(state.bump, fx.state.bump);
+}
+
+#[test]
+fn only_the_seller_marks_delivered() {
+    let mut fx = Fixture::new();
+    let buyer = fx.buyer.insecure_clone();
+    let result = fx.send(fx.mark_delivered(&buyer.pubkey()), &[&buyer]);
+    assert_error(result, AnchorErrorCode::ConstraintHasOne);
+
+    fx.deliver();
+    let state = fx.escrow_state();
+    assert_eq!(state.state, EscrowState::Delivered);
+    assert_eq!(state.delivered_at, Some(fx.svm.clock().unix_timestamp));
+}
+
+#[test]
+fn late_delivery_is_rejected() {
+    let mut fx = Fixture::new();
+    fx.svm.warp_to_timestamp(fx.state.delivery_deadline + 1);
+    let seller = fx.seller.insecure_clone();
+    let result = fx.send(fx.mark_delivered(&seller.pubkey()), &[&seller]);
+    assert_error(result, ErrorCode::DeliveryDeadlinePassed);
+}
+
+#[test]
+fn dispute_freezes_everything_but_the_arbiter() {
+    let mut fx = Fixture::new();
+    fx.deliver();
+
+    let arbiter = fx.arbiter.insecure_clone();
+    let result = fx.send(fx.open_dispute(&arbiter.pubkey()), &[&arbiter]);
+    assert_error(result, ErrorCode::Unauthorized);
+
+    let buyer = fx.buyer.insecure_clone();
+    fx.send(fx.open_dispute(&buyer.pubkey()), &[&buyer])
+        .unwrap();
+    assert_eq!(fx.escrow_state().state, EscrowState::Disputed);
+
+    let result = fx.send(
+        fx.settle(&buyer.pubkey(), instruction::Release {}.data()),
+        &[&buyer],
+    );
+    assert_error(result, ErrorCode::InvalidState);
+
+    fx.svm.advance_time(30 * DAY);
+    let keeper = Keypair::new();
+    let result = fx.send(
+        fx.settle(&keeper.pubkey(), instruction::SettleExpired {}.data()),
+        &[&keeper],
+    );
+    assert_error(result, ErrorCode::NotExpired);
+
+    let resolve = instruction::ResolveDispute {
+        seller_share: AMOUNT / 2,
+    };
+    let result = fx.send(fx.settle(&buyer.pubkey(), resolve.data()), &[&buyer]);
+    assert_error(result, ErrorCode::Unauthorized);
+    let result = fx.send(fx.settle(&arbiter.pubkey(), resolve.data()), &[&arbiter]);
+    assert_success(result);
+    fx.assert_settled(AMOUNT / 2, AMOUNT / 2);
+}
+
+#[test]
+fn arbiter_cannot_step_into_an_undisputed_escrow() {
+    let mut fx = Fixture::new();
+    let arbiter = fx.arbiter.insecure_clone();
+    let resolve = instruction::ResolveDispute {
+        seller_share: AMOUNT,
+    };
+    let result = fx.send(fx.settle(&arbiter.pubkey(), resolve.data()), &[&arbiter]);
+    assert_error(result, ErrorCode::InvalidState);
+}
+
+#[test]
+fn buyer_releases_and_seller_refunds() {
+    let mut refunded = Fixture::new();
+    let seller = refunded.seller.insecure_clone();
+    let result = refunded.send(
+        refunded.settle(&seller.pubkey(), instruction::Refund {}.data()),
+        &[&seller],
+    );
+    assert_success(result);
+    refunded.assert_settled(0, AMOUNT);
+
+    let mut fx = Fixture::new();
+    let buyer = fx.buyer.insecure_clone();
+    let seller = fx.seller.insecure_clone();
+
+    let result = fx.send(
+        fx.settle(&seller.pubkey(), instruction::Release {}.data()),
+        &[&seller],
+    );
+    assert_error(result, ErrorCode::Unauthorized);
+    let result = fx.send(
+        fx.settle(&buyer.pubkey(), instruction::Refund {}.data()),
+        &[&buyer],
+    );
+    assert_error(result, ErrorCode::Unauthorized);
+
+    let result = fx.send(
+        fx.settle(&buyer.pubkey(), instruction::Release {}.data()),
+        &[&buyer],
+    );
+    assert_success(result);
+    fx.assert_settled(AMOUNT, 0);
+}
+
+#[test]
+fn keeper_settles_once_the_review_period_ends() {
+    let mut fx = Fixture::new();
+    fx.deliver();
+    let keeper = Keypair::new();
+
+    let result = fx.send(
+        fx.settle(&keeper.pubkey(), instruction::SettleExpired {}.data()),
+        &[&keeper],
+    );
+    assert_error(result, ErrorCode::NotExpired);
+
+    fx.svm.advance_time(fx.state.review_period);
+    let result = fx.send(
+        fx.settle(&keeper.pubkey(), instruction::SettleExpired {}.data()),
+        &[&keeper],
+    );
+    assert_success(result);
+    fx.assert_settled(AMOUNT, 0);
+}
+
+#[test]
+fn settle_rejects_a_swapped_payout_account() {
+    let mut fx = Fixture::new();
+    let buyer = fx.buyer.insecure_clone();
+    let attacker = fx
+        .svm
+        .create_token_account(&Pubkey::new_unique(), &fx.state.mint, 0);
+    let mut release = fx.settle(&buyer.pubkey(), instruction::Release {}.data());
+    release.accounts[4].pubkey = attacker;
+
+    let result = fx.send(release, &[&buyer]);
+    assert_error(result, AnchorErrorCode::ConstraintRaw);
+}
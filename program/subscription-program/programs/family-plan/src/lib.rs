@@ -0,0 +1,792 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use anchor_lang::solana_program::program_option::COption;
+use spl_token::instruction as token_instruction;
+use spl_token::state::AccountState;
+use token_utils::{token_account, TokenAccount};
+
+declare_id!("CMFYzD9fKGn2nKHPhCrm6vJHuzAjYZm1TiSCMPxXWY9J");
+
+/// Members per plan, the organizer included
+pub const MAX_MEMBERS: usize = 6;
+
+#[program]
+pub mod family_plan {
+    use super::*;
+
+    /// Start a shared plan paying `merchant` `amount_per_period` every
+    /// `interval_seconds`. The organizer is the first member, paying by
+    /// `weight` like everyone else, and never more than `max_share` a
+    /// period. The first charge is due at once.
+    pub fn create_plan(
+        ctx: Context<CreatePlan>,
+        amount_per_period: u64,
+        interval_seconds: i64,
+        weight: u16,
+        max_share: u64,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        FamilyPlan::check_params(amount_per_period, interval_seconds)?;
+        require!(weight > 0, ErrorCode::InvalidWeight);
+
+        let organizer = ctx.accounts.organizer.key();
+        let mint = ctx.accounts.mint.key();
+        let merchant_account = token_account(
+            &ctx.accounts.merchant_token_account,
+            ErrorCode::InvalidTokenAccount,
+        )?;
+        require_keys_eq!(
+            merchant_account.owner,
+            ctx.accounts.merchant.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+        require_keys_eq!(merchant_account.mint, mint, ErrorCode::InvalidTokenAccount);
+
+        let plan_key = ctx.accounts.plan.key();
+        approve_plan(
+            &ctx.accounts.organizer_token_account,
+            &ctx.accounts.organizer,
+            &ctx.accounts.plan.to_account_info(),
+            &ctx.accounts.token_program,
+            &mint,
+        )?;
+
+        let plan = &mut ctx.accounts.plan;
+        plan.organizer = organizer;
+        plan.merchant = ctx.accounts.merchant.key();
+        plan.mint = mint;
+        plan.merchant_token_account = ctx.accounts.merchant_token_account.key();
+        plan.amount_per_period = amount_per_period;
+        plan.interval_seconds = interval_seconds;
+        plan.members = vec![Member {
+            wallet: organizer,
+            token_account: ctx.accounts.organizer_token_account.key(),
+            weight,
+            max_share,
+            status: MemberStatus::Active,
+            total_paid: 0,
+        }];
+        plan.next_charge_at = clock.unix_timestamp;
+        plan.total_charged = 0;
+        plan.created_at = clock.unix_timestamp;
+        plan.bump = ctx.bumps.plan;
+
+        emit!(PlanCreated {
+            plan: plan_key,
+            organizer,
+            merchant: plan.merchant,
+            amount_per_period,
+            interval_seconds,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("Family plan created: {} per period", amount_per_period);
+        msg!("Every {} seconds", interval_seconds);
+        msg!("Token delegation approved");
+
+        Ok(())
+    }
+
+    /// Invite `wallet` to share the plan with `weight`; they join with
+    /// `accept_invite`
+    pub fn invite_member(ctx: Context<ManagePlan>, wallet: Pubkey, weight: u16) -> Result<()> {
+        let plan = &mut ctx.accounts.plan;
+        plan.invite(wallet, weight)?;
+
+        emit!(MemberInvited {
+            plan: plan.key(),
+            wallet,
+            weight,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Member invited: {}", wallet);
+        msg!("{} of {} seats taken", plan.members.len(), MAX_MEMBERS);
+
+        Ok(())
+    }
+
+    /// Join, or rejoin after lapsing, by delegating `member_token_account` to
+    /// the plan. The member never pays more than `max_share` a period.
+    pub fn accept_invite(ctx: Context<AcceptInvite>, max_share: u64) -> Result<()> {
+        let wallet = ctx.accounts.member.key();
+        let member_token_account = ctx.accounts.member_token_account.key();
+        ctx.accounts
+            .plan
+            .accept(&wallet, member_token_account, max_share)?;
+
+        let mint = ctx.accounts.plan.mint;
+        approve_plan(
+            &ctx.accounts.member_token_account,
+            &ctx.accounts.member,
+            &ctx.accounts.plan.to_account_info(),
+            &ctx.accounts.token_program,
+            &mint,
+        )?;
+
+        let plan = &ctx.accounts.plan;
+
+        emit!(MemberJoined {
+            plan: plan.key(),
+            wallet,
+            max_share,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Member joined: {}", wallet);
+        msg!("Token delegation approved");
+
+        Ok(())
+    }
+
+    /// Leave the plan and revoke the delegation. The others' shares grow
+    /// from the next charge on. Invites not yet accepted are withdrawn by
+    /// the organizer with `remove_member`.
+    pub fn leave_plan(ctx: Context<LeavePlan>) -> Result<()> {
+        let wallet = ctx.accounts.member.key();
+        let plan = &mut ctx.accounts.plan;
+        require_keys_neq!(wallet, plan.organizer, ErrorCode::OrganizerCannotLeave);
+        let member = plan.remove(&wallet)?;
+        require!(member.status != MemberStatus::Invited, ErrorCode::NotMember);
+        require_keys_eq!(
+            ctx.accounts.member_token_account.key(),
+            member.token_account,
+            ErrorCode::InvalidTokenAccount
+        );
+
+        let revoke_ix = token_instruction::revoke(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.member_token_account.key(),
+            &wallet,
+            &[],
+        )?;
+        invoke(
+            &revoke_ix,
+            &[
+                ctx.accounts.member_token_account.to_account_info(),
+                ctx.accounts.member.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        emit!(MemberLeft {
+            plan: plan.key(),
+            wallet,
+            removed_by: wallet,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Member left: {}", wallet);
+        msg!("Token delegation revoked");
+
+        Ok(())
+    }
+
+    /// Remove a member or withdraw an invite. The plan stops charging them at
+    /// once; the member revokes their delegation themselves.
+    pub fn remove_member(ctx: Context<ManagePlan>, wallet: Pubkey) -> Result<()> {
+        let plan = &mut ctx.accounts.plan;
+        require_keys_neq!(wallet, plan.organizer, ErrorCode::OrganizerCannotLeave);
+        plan.remove(&wallet)?;
+
+        emit!(MemberLeft {
+            plan: plan.key(),
+            wallet,
+            removed_by: plan.organizer,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Member removed: {}", wallet);
+
+        Ok(())
+    }
+
+    /// Charge the period. Permissionless, like `charge_subscription`.
+    /// Remaining accounts are the token accounts of the active members, in
+    /// plan order. Members who can no longer cover their share lapse and the
+    /// rest is split among the others, up to each one's `max_share`.
+    pub fn charge<'info>(ctx: Context<'_, '_, 'info, 'info, Charge<'info>>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let plan = &ctx.accounts.plan;
+        plan.check_due(now)?;
+
+        let plan_key = plan.key();
+        let active = plan.active_members();
+        require!(
+            ctx.remaining_accounts.len() == active.len(),
+            ErrorCode::MemberAccountMismatch
+        );
+        let mut available = Vec::with_capacity(active.len());
+        for (&index, account) in active.iter().zip(ctx.remaining_accounts) {
+            require_keys_eq!(
+                account.key(),
+                plan.members[index].token_account,
+                ErrorCode::MemberAccountMismatch
+            );
+            available.push((
+                index,
+                spendable(
+                    &token_account(account, ErrorCode::InvalidTokenAccount)?,
+                    &plan_key,
+                ),
+            ));
+        }
+        let (payers, dropped) = plan.split(&available)?;
+
+        let seeds = &[
+            b"family",
+            plan.organizer.as_ref(),
+            plan.merchant.as_ref(),
+            &[plan.bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        for (&index, source) in active.iter().zip(ctx.remaining_accounts) {
+            let share = payers
+                .iter()
+                .find(|&&(payer, _)| payer == index)
+                .map_or(0, |&(_, share)| share);
+            if share == 0 {
+                continue;
+            }
+            let transfer_ix = token_instruction::transfer(
+                &ctx.accounts.token_program.key(),
+                &source.key(),
+                &ctx.accounts.merchant_token_account.key(),
+                &plan_key,
+                &[],
+                share,
+            )?;
+            invoke_signed(
+                &transfer_ix,
+                &[
+                    source.clone(),
+                    ctx.accounts.merchant_token_account.to_account_info(),
+                    ctx.accounts.plan.to_account_info(),
+                    ctx.accounts.token_program.to_account_info(),
+                ],
+                signer_seeds,
+            )?;
+        }
+
+        let plan = &mut ctx.accounts.plan;
+        plan.record_charge(&payers, &dropped, now)?;
+
+        for &index in &dropped {
+            emit!(MemberLapsed {
+                plan: plan_key,
+                wallet: plan.members[index].wallet,
+                timestamp: now,
+            });
+        }
+        emit!(PlanCharged {
+            plan: plan_key,
+            amount: plan.amount_per_period,
+            payers: payers.len() as u8,
+            lapsed: dropped.len() as u8,
+            timestamp: now,
+        });
+
+        msg!(
+            "Plan charged: {} split {} ways",
+            plan.amount_per_period,
+            payers.len()
+        );
+        msg!("{} members lapsed", dropped.len());
+
+        Ok(())
+    }
+
+    /// End the plan. Only the organizer's delegation is revoked here; other
+    /// members revoke theirs, which the plan can no longer use.
+    pub fn cancel_plan(ctx: Context<CancelPlan>) -> Result<()> {
+        let revoke_ix = token_instruction::revoke(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.organizer_token_account.key(),
+            &ctx.accounts.organizer.key(),
+            &[],
+        )?;
+        invoke(
+            &revoke_ix,
+            &[
+                ctx.accounts.organizer_token_account.to_account_info(),
+                ctx.accounts.organizer.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        emit!(PlanCancelled {
+            plan: ctx.accounts.plan.key(),
+            organizer: ctx.accounts.organizer.key(),
+            total_charged: ctx.accounts.plan.total_charged,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Family plan cancelled");
+        msg!("Token delegation revoked");
+
+        Ok(())
+    }
+}
+
+/// Delegate `token_account` to the plan PDA for recurring shares
+fn approve_plan<'info>(
+    token_account_info: &AccountInfo<'info>,
+    owner: &Signer<'info>,
+    plan: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+    mint: &Pubkey,
+) -> Result<()> {
+    let decoded = token_account(token_account_info, ErrorCode::InvalidTokenAccount)?;
+    require_keys_eq!(decoded.owner, owner.key(), ErrorCode::InvalidTokenAccount);
+    require_keys_eq!(decoded.mint, *mint, ErrorCode::InvalidTokenAccount);
+
+    let delegate_ix = token_instruction::approve(
+        &token_program.key(),
+        &token_account_info.key(),
+        &plan.key(),
+        &owner.key(),
+        &[],
+        u64::MAX,
+    )?;
+    invoke(
+        &delegate_ix,
+        &[
+            token_account_info.clone(),
+            plan.clone(),
+            owner.to_account_info(),
+            token_program.clone(),
+        ],
+    )?;
+    Ok(())
+}
+
+/// What the plan can pull from a member's account: nothing unless it is
+/// still the delegate and the account is not frozen
+pub fn spendable(account: &TokenAccount, plan: &Pubkey) -> u64 {
+    if account.state != AccountState::Initialized || account.delegate != COption::Some(*plan) {
+        return 0;
+    }
+    account.amount.min(account.delegated_amount)
+}
+
+#[derive(Accounts)]
+pub struct CreatePlan<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + FamilyPlan::INIT_SPACE,
+        seeds = [b"family", organizer.key().as_ref(), merchant.key().as_ref()],
+        bump
+    )]
+    pub plan: Account<'info, FamilyPlan>,
+
+    pub organizer: Signer<'info>,
+
+    /// CHECK: Merchant wallet being paid
+    pub merchant: UncheckedAccount<'info>,
+
+    /// CHECK: Token mint (USDC)
+    pub mint: UncheckedAccount<'info>,
+
+    /// CHECK: Organizer's token account, checked in the handler and delegated to the plan PDA
+    #[account(mut)]
+    pub organizer_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Merchant's token account, checked in the handler
+    pub merchant_token_account: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ManagePlan<'info> {
+    #[account(
+        mut,
+        seeds = [b"family", organizer.key().as_ref(), plan.merchant.as_ref()],
+        bump = plan.bump,
+        has_one = organizer
+    )]
+    pub plan: Account<'info, FamilyPlan>,
+
+    pub organizer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptInvite<'info> {
+    #[account(
+        mut,
+        seeds = [b"family", plan.organizer.as_ref(), plan.merchant.as_ref()],
+        bump = plan.bump
+    )]
+    pub plan: Account<'info, FamilyPlan>,
+
+    pub member: Signer<'info>,
+
+    /// CHECK: Member's token account, checked in the handler and delegated to the plan PDA
+    #[account(mut)]
+    pub member_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct LeavePlan<'info> {
+    #[account(
+        mut,
+        seeds = [b"family", plan.organizer.as_ref(), plan.merchant.as_ref()],
+        bump = plan.bump
+    )]
+    pub plan: Account<'info, FamilyPlan>,
+
+    pub member: Signer<'info>,
+
+    /// CHECK: The member's delegated token account, checked against the plan
+    #[account(mut)]
+    pub member_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Charge<'info> {
+    #[account(
+        mut,
+        seeds = [b"family", plan.organizer.as_ref(), plan.merchant.as_ref()],
+        bump = plan.bump,
+        has_one = merchant_token_account
+    )]
+    pub plan: Account<'info, FamilyPlan>,
+
+    /// CHECK: Merchant's token account, checked against the plan
+    #[account(mut)]
+    pub merchant_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelPlan<'info> {
+    #[account(
+        mut,
+        seeds = [b"family", organizer.key().as_ref(), plan.merchant.as_ref()],
+        bump = plan.bump,
+        has_one = organizer,
+        constraint = plan.members[0].token_account == organizer_token_account.key()
+            @ ErrorCode::InvalidTokenAccount,
+        close = organizer
+    )]
+    pub plan: Account<'info, FamilyPlan>,
+
+    #[account(mut)]
+    pub organizer: Signer<'info>,
+
+    /// CHECK: The organizer's delegated token account, checked against the plan
+    #[account(mut)]
+    pub organizer_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+/// `(member index, amount)` pairs
+pub type Shares = Vec<(usize, u64)>;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub enum MemberStatus {
+    /// Invited by the organizer, not yet delegated
+    Invited,
+    /// Pays a share of every charge
+    Active,
+    /// Could not cover a share; rejoins with `accept_invite`
+    Lapsed,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub struct Member {
+    pub wallet: Pubkey,
+    /// Delegated to the plan PDA; default until the invite is accepted
+    pub token_account: Pubkey,
+    /// Shares are split in proportion to the active members' weights
+    pub weight: u16,
+    /// Most this member agreed to pay in one period
+    pub max_share: u64,
+    pub status: MemberStatus,
+    pub total_paid: u64,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct FamilyPlan {
+    pub organizer: Pubkey,
+    pub merchant: Pubkey,
+    pub mint: Pubkey,
+    pub merchant_token_account: Pubkey,
+    pub amount_per_period: u64,
+    pub interval_seconds: i64,
+    /// The organizer is always first
+    #[max_len(MAX_MEMBERS)]
+    pub members: Vec<Member>,
+    pub next_charge_at: i64,
+    pub total_charged: u64,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl FamilyPlan {
+    pub fn check_params(amount_per_period: u64, interval_seconds: i64) -> Result<()> {
+        require!(amount_per_period > 0, ErrorCode::InvalidAmount);
+        require!(interval_seconds > 0, ErrorCode::InvalidInterval);
+        Ok(())
+    }
+
+    pub fn check_due(&self, now: i64) -> Result<()> {
+        require!(now >= self.next_charge_at, ErrorCode::ChargeNotDue);
+        Ok(())
+    }
+
+    pub fn invite(&mut self, wallet: Pubkey, weight: u16) -> Result<()> {
+        require!(weight > 0, ErrorCode::InvalidWeight);
+        require!(self.members.len() < MAX_MEMBERS, ErrorCode::PlanFull);
+        require!(
+            self.members.iter().all(|member| member.wallet != wallet),
+            ErrorCode::AlreadyMember
+        );
+        self.members.push(Member {
+            wallet,
+            token_account: Pubkey::default(),
+            weight,
+            max_share: 0,
+            status: MemberStatus::Invited,
+            total_paid: 0,
+        });
+        Ok(())
+    }
+
+    /// Activate an invited or lapsed member paying from `token_account`
+    pub fn accept(&mut self, wallet: &Pubkey, token_account: Pubkey, max_share: u64) -> Result<()> {
+        require!(max_share > 0, ErrorCode::InvalidAmount);
+        let member = self
+            .members
+            .iter_mut()
+            .find(|member| member.wallet == *wallet)
+            .ok_or(ErrorCode::NotMember)?;
+        require!(
+            member.status != MemberStatus::Active,
+            ErrorCode::AlreadyMember
+        );
+        member.token_account = token_account;
+        member.max_share = max_share;
+        member.status = MemberStatus::Active;
+        Ok(())
+    }
+
+    pub fn remove(&mut self, wallet: &Pubkey) -> Result<Member> {
+        let index = self
+            .members
+            .iter()
+            .position(|member| member.wallet == *wallet)
+            .ok_or(ErrorCode::NotMember)?;
+        Ok(self.members.remove(index))
+    }
+
+    /// Indexes of the members who pay, in plan order
+    pub fn active_members(&self) -> Vec<usize> {
+        (0..self.members.len())
+            .filter(|&index| self.members[index].status == MemberStatus::Active)
+            .collect()
+    }
+
+    /// Each payer's share of `amount_per_period`, by weight. Rounding dust
+    /// goes to the first payer so the shares always add up to the amount.
+    pub fn shares(&self, payers: &[usize]) -> Result<Vec<u64>> {
+        let total_weight: u128 = payers
+            .iter()
+            .map(|&index| u128::from(self.members[index].weight))
+            .sum();
+        require!(total_weight > 0, ErrorCode::NoPayingMembers);
+        let mut shares = payers
+            .iter()
+            .map(|&index| {
+                let share = u128::from(self.amount_per_period)
+                    * u128::from(self.members[index].weight)
+                    / total_weight;
+                u64::try_from(share).map_err(|_| error!(ErrorCode::ArithmeticOverflow))
+            })
+            .collect::<Result<Vec<u64>>>()?;
+        let dust = self.amount_per_period - shares.iter().sum::<u64>();
+        if let Some(first) = shares.first_mut() {
+            *first += dust;
+        }
+        Ok(shares)
+    }
+
+    /// Who pays what this period, given `(member index, spendable)` for each
+    /// active member. Members who cannot cover their share are dropped and
+    /// the amount re-split among the rest until everyone left can pay.
+    /// Returns `(payer shares, dropped)`; fails if a share would exceed its
+    /// payer's `max_share`.
+    pub fn split(&self, available: &[(usize, u64)]) -> Result<(Shares, Vec<usize>)> {
+        let mut payers = available.to_vec();
+        let mut dropped = Vec::new();
+        loop {
+            let indexes: Vec<usize> = payers.iter().map(|&(index, _)| index).collect();
+            let shares = self.shares(&indexes)?;
+            let short: Vec<usize> = payers
+                .iter()
+                .zip(&shares)
+                .filter(|((_, spendable), share)| spendable < share)
+                .map(|((index, _), _)| *index)
+                .collect();
+            if short.is_empty() {
+                let split: Shares = indexes.into_iter().zip(shares).collect();
+                for &(index, share) in &split {
+                    require!(
+                        share <= self.members[index].max_share,
+                        ErrorCode::ShareLimitExceeded
+                    );
+                }
+                return Ok((split, dropped));
+            }
+            payers.retain(|(index, _)| !short.contains(index));
+            dropped.extend(short);
+        }
+    }
+
+    /// Book a charge at `now`: shares paid, `dropped` members lapsed
+    pub fn record_charge(
+        &mut self,
+        payers: &[(usize, u64)],
+        dropped: &[usize],
+        now: i64,
+    ) -> Result<()> {
+        for &(index, share) in payers {
+            let member = &mut self.members[index];
+            member.total_paid = member
+                .total_paid
+                .checked_add(share)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+        }
+        for &index in dropped {
+            self.members[index].status = MemberStatus::Lapsed;
+        }
+        self.total_charged = self
+            .total_charged
+            .checked_add(self.amount_per_period)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.next_charge_at = now
+            .checked_add(self.interval_seconds)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanCreated {
+    pub plan: Pubkey,
+    pub organizer: Pubkey,
+    pub merchant: Pubkey,
+    pub amount_per_period: u64,
+    pub interval_seconds: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemberInvited {
+    pub plan: Pubkey,
+    pub wallet: Pubkey,
+    pub weight: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemberJoined {
+    pub plan: Pubkey,
+    pub wallet: Pubkey,
+    pub max_share: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemberLeft {
+    pub plan: Pubkey,
+    pub wallet: Pubkey,
+    /// The member, or the organizer for a removal
+    pub removed_by: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemberLapsed {
+    pub plan: Pubkey,
+    pub wallet: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanCharged {
+    pub plan: Pubkey,
+    pub amount: u64,
+    pub payers: u8,
+    pub lapsed: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanCancelled {
+    pub plan: Pubkey,
+    pub organizer: Pubkey,
+    pub total_charged: u64,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Amount must be greater than zero")]
+    InvalidAmount,
+    #[msg("Interval must be positive")]
+    InvalidInterval,
+    #[msg("Weight must be greater than zero")]
+    InvalidWeight,
+    #[msg("Invalid token account - wrong program, owner or mint")]
+    InvalidTokenAccount,
+    #[msg("Plan already has the maximum number of members")]
+    PlanFull,
+    #[msg("Wallet is already a member or already active")]
+    AlreadyMember,
+    #[msg("Wallet is not a member of this plan")]
+    NotMember,
+    #[msg("The organizer cannot leave; cancel the plan instead")]
+    OrganizerCannotLeave,
+    #[msg("Next charge is not due yet")]
+    ChargeNotDue,
+    #[msg("Member token accounts do not match the active members")]
+    MemberAccountMismatch,
+    #[msg("No member can cover a share of the charge")]
+    NoPayingMembers,
+    #[msg("A share would exceed the most its member agreed to pay")]
+    ShareLimitExceeded,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
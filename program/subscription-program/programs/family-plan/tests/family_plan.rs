@@ -0,0 +1,545 @@
+//! Native tests for the family plan program.
+//!
+//! Membership and share-splitting rules are pure methods and are tested
+//! directly. Instruction tests run end to end and check each member's
+//! delegation and what they paid.
+
+use anchor_lang::error::ErrorCode as AnchorErrorCode;
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::{system_program, InstructionData, Space, ToAccountMetas};
+use family_plan::{
+    accounts, instruction, ErrorCode, FamilyPlan, Member, MemberStatus, ID as PROGRAM_ID,
+    MAX_MEMBERS,
+};
+use test_harness::{assert_error, assert_success, Keypair, Signer, TestSvm, TransactionResult};
+
+const PRICE: u64 = 20_000_000;
+const MONTH: i64 = 30 * 86_400;
+
+fn member(weight: u16, status: MemberStatus) -> Member {
+    Member {
+        wallet: Pubkey::new_unique(),
+        token_account: Pubkey::new_unique(),
+        weight,
+        max_share: PRICE,
+        status,
+        total_paid: 0,
+    }
+}
+
+fn plan(members: Vec<Member>) -> FamilyPlan {
+    FamilyPlan {
+        organizer: members[0].wallet,
+        merchant: Pubkey::new_unique(),
+        mint: Pubkey::new_unique(),
+        merchant_token_account: Pubkey::new_unique(),
+        amount_per_period: PRICE,
+        interval_seconds: MONTH,
+        members,
+        next_charge_at: 0,
+        total_charged: 0,
+        created_at: 0,
+        bump: 255,
+    }
+}
+
+#[test]
+fn shares_follow_weights_with_dust_to_the_first_payer() {
+    let family = plan(vec![
+        member(2, MemberStatus::Active),
+        member(1, MemberStatus::Active),
+        member(1, MemberStatus::Invited),
+        member(3, MemberStatus::Active),
+    ]);
+    assert_eq!(family.active_members(), vec![0, 1, 3]);
+    assert_eq!(
+        family.shares(&[0, 1, 3]).unwrap(),
+        vec![6_666_667, 3_333_333, 10_000_000]
+    );
+    assert_eq!(family.shares(&[1]).unwrap(), vec![PRICE]);
+    assert_eq!(
+        family.shares(&[]).unwrap_err(),
+        ErrorCode::NoPayingMembers.into()
+    );
+}
+
+#[test]
+fn members_who_cannot_pay_lapse_and_the_rest_re_split() {
+    let mut family = plan(vec![
+        member(1, MemberStatus::Active),
+        member(1, MemberStatus::Active),
+        member(1, MemberStatus::Active),
+        member(1, MemberStatus::Active),
+    ]);
+
+    // Everyone covers an even quarter
+    let (payers, dropped) = family
+        .split(&[(0, PRICE), (1, PRICE), (2, PRICE), (3, PRICE)])
+        .unwrap();
+    assert_eq!(
+        payers,
+        vec![
+            (0, 5_000_000),
+            (1, 5_000_000),
+            (2, 5_000_000),
+            (3, 5_000_000)
+        ]
+    );
+    assert!(dropped.is_empty());
+
+    // Member 3 revoked. A third is then too much for member 2, who drops
+    // too, and the last two split the price
+    let (payers, dropped) = family
+        .split(&[(0, PRICE), (1, PRICE), (2, 6_000_000), (3, 0)])
+        .unwrap();
+    assert_eq!(payers, vec![(0, 10_000_000), (1, 10_000_000)]);
+    assert_eq!(dropped, vec![3, 2]);
+
+    family.record_charge(&payers, &dropped, 100).unwrap();
+    assert_eq!(family.active_members(), vec![0, 1]);
+    assert_eq!(family.members[3].status, MemberStatus::Lapsed);
+    assert_eq!(family.members[0].total_paid, 10_000_000);
+    assert_eq!(family.total_charged, PRICE);
+    assert_eq!(family.next_charge_at, 100 + MONTH);
+
+    // A bigger share than a member agreed to fails the charge
+    family.members[1].max_share = 9_999_999;
+    assert_eq!(
+        family.split(&[(0, PRICE), (1, PRICE)]).unwrap_err(),
+        ErrorCode::ShareLimitExceeded.into()
+    );
+    assert_eq!(
+        family.split(&[(0, 0), (1, 0)]).unwrap_err(),
+        ErrorCode::NoPayingMembers.into()
+    );
+}
+
+#[test]
+fn invites_are_unique_and_limited() {
+    let mut family = plan(vec![member(1, MemberStatus::Active)]);
+    let wallet = Pubkey::new_unique();
+    assert_eq!(
+        family.invite(wallet, 0).unwrap_err(),
+        ErrorCode::InvalidWeight.into()
+    );
+    family.invite(wallet, 1).unwrap();
+    assert_eq!(
+        family.invite(wallet, 1).unwrap_err(),
+        ErrorCode::AlreadyMember.into()
+    );
+    for _ in 2..MAX_MEMBERS {
+        family.invite(Pubkey::new_unique(), 1).unwrap();
+    }
+    assert_eq!(
+        family.invite(Pubkey::new_unique(), 1).unwrap_err(),
+        ErrorCode::PlanFull.into()
+    );
+
+    let token_account = Pubkey::new_unique();
+    family.accept(&wallet, token_account, PRICE).unwrap();
+    assert_eq!(family.members[1].status, MemberStatus::Active);
+    assert_eq!(family.members[1].token_account, token_account);
+    assert_eq!(
+        family.accept(&wallet, token_account, PRICE).unwrap_err(),
+        ErrorCode::AlreadyMember.into()
+    );
+    assert_eq!(
+        family
+            .accept(&Pubkey::new_unique(), token_account, PRICE)
+            .unwrap_err(),
+        ErrorCode::NotMember.into()
+    );
+}
+
+struct Fixture {
+    svm: TestSvm,
+    payer: Keypair,
+    organizer: Keypair,
+    /// Active, sharing equally with the organizer
+    partner: Keypair,
+    /// Invited, not yet accepted
+    invitee: Keypair,
+    plan: Pubkey,
+    state: FamilyPlan,
+}
+
+impl Fixture {
+    /// An organizer and a partner sharing equally, due at `next_charge_at`,
+    /// with the partner's account delegated for `partner_allowance`
+    fn new(next_charge_at: i64, partner_allowance: u64) -> Self {
+        let (mut svm, payer) = TestSvm::for_program(PROGRAM_ID, family_plan::entry);
+
+        let organizer = Keypair::new();
+        let partner = Keypair::new();
+        let invitee = Keypair::new();
+        let merchant = Pubkey::new_unique();
+        svm.airdrop(&organizer.pubkey(), 1_000_000_000);
+
+        let mint = svm.create_mint(&Pubkey::new_unique(), 6);
+        let (address, bump) = Pubkey::find_program_address(
+            &[b"family", organizer.pubkey().as_ref(), merchant.as_ref()],
+            &PROGRAM_ID,
+        );
+        let organizer_account =
+            svm.create_associated_token_account(&organizer.pubkey(), &mint, PRICE);
+        svm.approve(&organizer_account, &address, u64::MAX);
+        let partner_account = svm.create_associated_token_account(&partner.pubkey(), &mint, PRICE);
+        if partner_allowance > 0 {
+            svm.approve(&partner_account, &address, partner_allowance);
+        }
+        svm.create_associated_token_account(&invitee.pubkey(), &mint, PRICE);
+        let merchant_token_account = svm.create_associated_token_account(&merchant, &mint, 0);
+
+        let state = FamilyPlan {
+            organizer: organizer.pubkey(),
+            merchant,
+            mint,
+            merchant_token_account,
+            members: vec![
+                Member {
+                    wallet: organizer.pubkey(),
+                    token_account: organizer_account,
+                    ..member(1, MemberStatus::Active)
+                },
+                Member {
+                    wallet: partner.pubkey(),
+                    token_account: partner_account,
+                    ..member(1, MemberStatus::Active)
+                },
+                Member {
+                    wallet: invitee.pubkey(),
+                    token_account: Pubkey::default(),
+                    ..member(1, MemberStatus::Invited)
+                },
+            ],
+            next_charge_at,
+            bump,
+            ..plan(vec![member(1, MemberStatus::Active)])
+        };
+        svm.set_anchor_account(address, &state, 8 + FamilyPlan::INIT_SPACE);
+
+        Self {
+            svm,
+            payer,
+            organizer,
+            partner,
+            invitee,
+            plan: address,
+            state,
+        }
+    }
+
+    fn now(&self) -> i64 {
+        self.svm.clock().unix_timestamp
+    }
+
+    fn token_account(&self, wallet: &Pubkey) -> Pubkey {
+        test_harness::associated_token_address(wallet, &self.state.mint)
+    }
+
+    fn create(&self, weight: u16) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CreatePlan {
+                plan: self.plan,
+                organizer: self.organizer.pubkey(),
+                merchant: self.state.merchant,
+                mint: self.state.mint,
+                organizer_token_account: self.state.members[0].token_account,
+                merchant_token_account: self.state.merchant_token_account,
+                payer: self.payer.pubkey(),
+                token_program: spl_token::ID,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CreatePlan {
+                amount_per_period: PRICE,
+                interval_seconds: MONTH,
+                weight,
+                max_share: PRICE,
+            }
+            .data(),
+        }
+    }
+
+    fn cancel(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CancelPlan {
+                plan: self.plan,
+                organizer: self.organizer.pubkey(),
+                organizer_token_account: self.state.members[0].token_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CancelPlan {}.data(),
+        }
+    }
+
+    fn manage(&self, organizer: Pubkey, data: impl InstructionData) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::ManagePlan {
+                plan: self.plan,
+                organizer,
+            }
+            .to_account_metas(None),
+            data: data.data(),
+        }
+    }
+
+    fn accept(&self, member: Pubkey) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::AcceptInvite {
+                plan: self.plan,
+                member,
+                member_token_account: self.token_account(&member),
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::AcceptInvite { max_share: PRICE }.data(),
+        }
+    }
+
+    fn leave(&self, member: Pubkey) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::LeavePlan {
+                plan: self.plan,
+                member,
+                member_token_account: self.token_account(&member),
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::LeavePlan {}.data(),
+        }
+    }
+
+    fn charge(&self, member_accounts: &[Pubkey]) -> Instruction {
+        let mut accounts = accounts::Charge {
+            plan: self.plan,
+            merchant_token_account: self.state.merchant_token_account,
+            token_program: spl_token::ID,
+        }
+        .to_account_metas(None);
+        accounts.extend(
+            member_accounts
+                .iter()
+                .map(|account| AccountMeta::new(*account, false)),
+        );
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts,
+            data: instruction::Charge {}.data(),
+        }
+    }
+
+    fn active_accounts(&self) -> Vec<Pubkey> {
+        vec![
+            self.state.members[0].token_account,
+            self.state.members[1].token_account,
+        ]
+    }
+
+    fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TransactionResult {
+        let payer = self.payer.insecure_clone();
+        self.svm.send_instructions(&[instruction], &payer, signers)
+    }
+
+    fn send_as(&mut self, signer: &Keypair, instruction: Instruction) -> TransactionResult {
+        let signer = signer.insecure_clone();
+        self.send(instruction, &[&signer])
+    }
+
+    fn plan(&self) -> FamilyPlan {
+        self.svm.get_anchor_account(&self.plan).unwrap()
+    }
+
+    fn delegate(&self, token_account: &Pubkey) -> Option<Pubkey> {
+        self.svm
+            .get_token_account(token_account)
+            .unwrap()
+            .delegate
+            .into()
+    }
+}
+
+#[test]
+fn organizers_start_a_plan_as_its_first_member() {
+    let mut fx = Fixture::new(0, 0);
+    let organizer_account = fx.state.members[0].token_account;
+    fx.svm.remove_account(&fx.plan);
+    fx.svm.revoke(&organizer_account);
+    let organizer = fx.organizer.insecure_clone();
+    let result = fx.send_as(&organizer, fx.create(0));
+    assert_error(result, ErrorCode::InvalidWeight);
+
+    let result = fx.send_as(&organizer, fx.create(1));
+    assert_success(result);
+    assert_eq!(fx.delegate(&organizer_account), Some(fx.plan));
+    let plan = fx.plan();
+    assert_eq!(plan.organizer, organizer.pubkey());
+    assert_eq!(plan.members.len(), 1);
+    assert_eq!(plan.members[0].token_account, organizer_account);
+    assert_eq!(plan.active_members(), vec![0]);
+    // The first charge is due at once
+    plan.check_due(fx.now()).unwrap();
+}
+
+#[test]
+fn the_organizer_manages_members() {
+    let mut fx = Fixture::new(0, u64::MAX);
+    let partner = fx.partner.insecure_clone();
+    let invite = instruction::InviteMember {
+        wallet: Pubkey::new_unique(),
+        weight: 1,
+    };
+    let result = fx.send_as(&partner, fx.manage(partner.pubkey(), invite));
+    assert!(result.is_err());
+
+    let organizer = fx.organizer.insecure_clone();
+    let wallet = Pubkey::new_unique();
+    let invite = instruction::InviteMember { wallet, weight: 2 };
+    let result = fx.send_as(&organizer, fx.manage(organizer.pubkey(), invite));
+    assert!(result.is_ok(), "{result:#?}");
+    assert_eq!(fx.plan().members[3].wallet, wallet);
+    assert_eq!(fx.plan().members[3].status, MemberStatus::Invited);
+
+    let remove = instruction::RemoveMember {
+        wallet: organizer.pubkey(),
+    };
+    let result = fx.send_as(&organizer, fx.manage(organizer.pubkey(), remove));
+    assert_error(result, ErrorCode::OrganizerCannotLeave);
+
+    let remove = instruction::RemoveMember {
+        wallet: partner.pubkey(),
+    };
+    let result = fx.send_as(&organizer, fx.manage(organizer.pubkey(), remove));
+    assert!(result.is_ok(), "{result:#?}");
+    assert_eq!(fx.plan().members.len(), 3);
+    assert_eq!(fx.plan().active_members(), vec![0]);
+}
+
+#[test]
+fn only_invitees_accept() {
+    let mut fx = Fixture::new(0, u64::MAX);
+    let stranger = Keypair::new();
+    fx.svm
+        .create_associated_token_account(&stranger.pubkey(), &fx.state.mint, 0);
+    let result = fx.send_as(&stranger, fx.accept(stranger.pubkey()));
+    assert_error(result, ErrorCode::NotMember);
+
+    let partner = fx.partner.insecure_clone();
+    let result = fx.send_as(&partner, fx.accept(partner.pubkey()));
+    assert_error(result, ErrorCode::AlreadyMember);
+
+    let invitee = fx.invitee.insecure_clone();
+    let result = fx.send_as(&invitee, fx.accept(invitee.pubkey()));
+    assert_success(result);
+    let invitee_account = fx.token_account(&invitee.pubkey());
+    assert_eq!(fx.delegate(&invitee_account), Some(fx.plan));
+    let joined = &fx.plan().members[2];
+    assert_eq!(joined.status, MemberStatus::Active);
+    assert_eq!(joined.token_account, invitee_account);
+}
+
+#[test]
+fn only_joined_members_leave() {
+    let mut fx = Fixture::new(0, u64::MAX);
+    let organizer = fx.organizer.insecure_clone();
+    let result = fx.send_as(&organizer, fx.leave(organizer.pubkey()));
+    assert_error(result, ErrorCode::OrganizerCannotLeave);
+
+    let invitee = fx.invitee.insecure_clone();
+    let result = fx.send_as(&invitee, fx.leave(invitee.pubkey()));
+    assert_error(result, ErrorCode::NotMember);
+
+    let partner = fx.partner.insecure_clone();
+    let result = fx.send_as(&partner, fx.leave(partner.pubkey()));
+    assert_success(result);
+    assert_eq!(fx.delegate(&fx.state.members[1].token_account), None);
+    assert_eq!(fx.plan().members.len(), 2);
+}
+
+#[test]
+fn charges_take_every_active_members_account() {
+    let now = Fixture::new(0, 0).now();
+    let mut fx = Fixture::new(now + 1, u64::MAX);
+    let result = fx.send(fx.charge(&fx.active_accounts()), &[]);
+    assert_error(result, ErrorCode::ChargeNotDue);
+
+    fx.svm.warp_to_timestamp(now + 1);
+    let accounts = fx.active_accounts();
+    let result = fx.send(fx.charge(&accounts[..1]), &[]);
+    assert_error(result, ErrorCode::MemberAccountMismatch);
+
+    let result = fx.send(fx.charge(&[accounts[1], accounts[0]]), &[]);
+    assert_error(result, ErrorCode::MemberAccountMismatch);
+
+    let result = fx.send(fx.charge(&accounts), &[]);
+    assert_success(result);
+    assert_eq!(
+        fx.svm.token_balance(&fx.state.merchant_token_account),
+        PRICE
+    );
+    for account in accounts {
+        assert_eq!(fx.svm.token_balance(&account), PRICE / 2);
+    }
+    let plan = fx.plan();
+    assert_eq!(plan.total_charged, PRICE);
+    assert_eq!(plan.next_charge_at, now + 1 + MONTH);
+}
+
+#[test]
+fn a_member_who_cannot_pay_lapses_and_the_rest_cover_the_price() {
+    // The partner has revoked; the organizer pays alone
+    let mut fx = Fixture::new(0, 0);
+    let result = fx.send(fx.charge(&fx.active_accounts()), &[]);
+    assert_success(result);
+    assert_eq!(
+        fx.svm.token_balance(&fx.state.merchant_token_account),
+        PRICE
+    );
+    assert_eq!(fx.svm.token_balance(&fx.state.members[0].token_account), 0);
+    assert_eq!(
+        fx.svm.token_balance(&fx.state.members[1].token_account),
+        PRICE
+    );
+    let plan = fx.plan();
+    assert_eq!(plan.members[1].status, MemberStatus::Lapsed);
+    assert_eq!(plan.members[0].total_paid, PRICE);
+}
+
+#[test]
+fn cancelling_revokes_the_organizer_and_closes_the_plan() {
+    let mut fx = Fixture::new(0, u64::MAX);
+    let partner = fx.partner.insecure_clone();
+    let mut by_partner = fx.cancel();
+    by_partner.accounts[1].pubkey = partner.pubkey();
+    let result = fx.send_as(&partner, by_partner);
+    assert_error(result, AnchorErrorCode::ConstraintSeeds);
+
+    let organizer = fx.organizer.insecure_clone();
+    let result = fx.send_as(&organizer, fx.cancel());
+    assert_success(result);
+    assert_eq!(fx.delegate(&fx.state.members[0].token_account), None);
+    assert!(fx.svm.get_account(&fx.plan).is_none());
+}
+
+#[test]
+fn a_charge_nobody_can_cover_fails() {
+    // The partner's delegation covers less than half; the organizer's
+    // account is emptied, so neither can pay the whole price alone
+    let mut fx = Fixture::new(0, PRICE / 2 - 1);
+    let organizer_account = fx.state.members[0].token_account;
+    let drain = fx.svm.token_balance(&organizer_account);
+    let sink = fx
+        .svm
+        .create_token_account(&Pubkey::new_unique(), &fx.state.mint, 0);
+    fx.svm.transfer_tokens(&organizer_account, &sink, drain);
+
+    let result = fx.send(fx.charge(&fx.active_accounts()), &[]);
+    assert_error(result, ErrorCode::NoPayingMembers);
+}
@@ -0,0 +1,451 @@
+use anchor_lang::prelude::*;
+use passkey_devices::transaction_signatures;
+use subscription_program::program::SubscriptionProgram;
+use subscription_program::Subscription;
+
+declare_id!("GQ54LTTiZCzVTTsFsgvYV6edvb9w7G4DXMt8zFD2cXFw");
+
+/// Prefix of the message the passkey signs to approve an action
+pub const ACTION_DOMAIN: &[u8] = b"lazorkit-hybrid";
+
+#[program]
+pub mod hybrid_auth {
+    use super::*;
+
+    /// Create a guard that needs both `passkey` and the signing keypair for
+    /// high-value actions, and either one for the rest. The guard PDA is the
+    /// authority of the subscriptions it opens and owns their token account.
+    pub fn create_guard(ctx: Context<CreateGuard>, passkey: [u8; 33]) -> Result<()> {
+        let guard = &mut ctx.accounts.guard;
+        guard.keypair = ctx.accounts.keypair.key();
+        guard.passkey = passkey;
+        guard.nonce = 0;
+        guard.bump = ctx.bumps.guard;
+
+        emit!(GuardCreated {
+            guard: guard.key(),
+            keypair: guard.keypair,
+            passkey,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Hybrid guard created");
+
+        Ok(())
+    }
+
+    /// Open a subscription paid from the guard's token account. Always high
+    /// value: it delegates the account and charges the first period.
+    pub fn open_subscription(
+        ctx: Context<OpenGuardedSubscription>,
+        amount_per_period: u64,
+        interval_seconds: i64,
+        expires_at: Option<i64>,
+    ) -> Result<()> {
+        let action = GuardedAction::Open {
+            recipient: ctx.accounts.recipient.key(),
+            token_mint: ctx.accounts.token_mint.key(),
+            amount_per_period,
+            interval_seconds,
+            expires_at,
+        };
+        authorize(
+            &mut ctx.accounts.guard,
+            &ctx.accounts.keypair,
+            &ctx.accounts.instructions,
+            &action,
+            None,
+        )?;
+
+        let guard = &ctx.accounts.guard;
+        let seeds: &[&[u8]] = &[b"hybrid", guard.keypair.as_ref(), &[guard.bump]];
+        subscription_program::cpi::initialize_subscription(
+            CpiContext::new_with_signer(
+                ctx.accounts.subscription_program.to_account_info(),
+                subscription_program::cpi::accounts::InitializeSubscription {
+                    subscription: ctx.accounts.subscription.to_account_info(),
+                    authority: ctx.accounts.guard.to_account_info(),
+                    recipient: ctx.accounts.recipient.to_account_info(),
+                    user_token_account: ctx.accounts.guard_token_account.to_account_info(),
+                    recipient_token_account: ctx.accounts.recipient_token_account.to_account_info(),
+                    token_mint: ctx.accounts.token_mint.to_account_info(),
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                    payer: ctx.accounts.payer.to_account_info(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                    denylist_entry: ctx.accounts.denylist_entry.to_account_info(),
+                    allowlist: ctx.accounts.allowlist.to_account_info(),
+                    allowlist_entry: ctx.accounts.allowlist_entry.to_account_info(),
+                },
+                &[seeds],
+            ),
+            amount_per_period,
+            interval_seconds,
+            expires_at,
+        )?;
+
+        msg!("Guarded subscription opened with both factors");
+
+        Ok(())
+    }
+
+    /// Update a guarded subscription. Raising the amount or shortening the
+    /// interval needs both factors; anything else needs one.
+    pub fn update_subscription(
+        ctx: Context<UpdateGuardedSubscription>,
+        new_amount: Option<u64>,
+        new_interval: Option<i64>,
+        new_expires_at: Option<i64>,
+    ) -> Result<()> {
+        let action = GuardedAction::Update {
+            subscription: ctx.accounts.subscription.key(),
+            new_amount,
+            new_interval,
+            new_expires_at,
+        };
+        authorize(
+            &mut ctx.accounts.guard,
+            &ctx.accounts.keypair,
+            &ctx.accounts.instructions,
+            &action,
+            Some(&ctx.accounts.subscription),
+        )?;
+
+        let guard = &ctx.accounts.guard;
+        let seeds: &[&[u8]] = &[b"hybrid", guard.keypair.as_ref(), &[guard.bump]];
+        subscription_program::cpi::update_subscription(
+            CpiContext::new_with_signer(
+                ctx.accounts.subscription_program.to_account_info(),
+                subscription_program::cpi::accounts::UpdateSubscription {
+                    subscription: ctx.accounts.subscription.to_account_info(),
+                    authority: ctx.accounts.guard.to_account_info(),
+                },
+                &[seeds],
+            ),
+            new_amount,
+            new_interval,
+            new_expires_at,
+        )?;
+
+        msg!("Guarded subscription updated");
+
+        Ok(())
+    }
+
+    /// Cancel a guarded subscription with either factor; the rent goes back
+    /// to the guard
+    pub fn cancel_subscription(ctx: Context<CancelGuardedSubscription>) -> Result<()> {
+        let action = GuardedAction::Cancel {
+            subscription: ctx.accounts.subscription.key(),
+        };
+        authorize(
+            &mut ctx.accounts.guard,
+            &ctx.accounts.keypair,
+            &ctx.accounts.instructions,
+            &action,
+            Some(&ctx.accounts.subscription),
+        )?;
+
+        let guard = &ctx.accounts.guard;
+        let seeds: &[&[u8]] = &[b"hybrid", guard.keypair.as_ref(), &[guard.bump]];
+        subscription_program::cpi::cancel_subscription(CpiContext::new_with_signer(
+            ctx.accounts.subscription_program.to_account_info(),
+            subscription_program::cpi::accounts::CancelSubscription {
+                subscription: ctx.accounts.subscription.to_account_info(),
+                authority: ctx.accounts.guard.to_account_info(),
+                user_token_account: ctx.accounts.guard_token_account.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+            },
+            &[seeds],
+        ))?;
+
+        msg!("Guarded subscription cancelled");
+
+        Ok(())
+    }
+}
+
+/// Check the factors `action` needs and use up the nonce
+fn authorize(
+    guard: &mut Account<Guard>,
+    keypair: &UncheckedAccount,
+    instructions: &UncheckedAccount,
+    action: &GuardedAction,
+    current: Option<&Subscription>,
+) -> Result<()> {
+    let message = guard.action_message(&guard.key(), action)?;
+    let passkey_signed = transaction_signatures(instructions)?
+        .iter()
+        .any(|(pubkey, signed)| *pubkey == guard.passkey && *signed == message);
+    let high_value = action.is_high_value(current);
+    guard.check_factors(passkey_signed, keypair.is_signer, high_value)?;
+    guard.nonce = guard
+        .nonce
+        .checked_add(1)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+    emit!(ActionAuthorized {
+        guard: guard.key(),
+        passkey_signed,
+        keypair_signed: keypair.is_signer,
+        high_value,
+        nonce: guard.nonce,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CreateGuard<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Guard::INIT_SPACE,
+        seeds = [b"hybrid", keypair.key().as_ref()],
+        bump
+    )]
+    pub guard: Account<'info, Guard>,
+
+    pub keypair: Signer<'info>,
+
+    /// Fee and rent payer, usually a relayer
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct OpenGuardedSubscription<'info> {
+    #[account(
+        mut,
+        seeds = [b"hybrid", keypair.key().as_ref()],
+        bump = guard.bump
+    )]
+    pub guard: Account<'info, Guard>,
+
+    /// CHECK: The guard's keypair; whether it signed is checked in the handler
+    pub keypair: UncheckedAccount<'info>,
+
+    /// CHECK: Instructions sysvar, to read the secp256r1 instructions
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+
+    /// CHECK: Created by the subscription program
+    #[account(mut)]
+    pub subscription: UncheckedAccount<'info>,
+
+    /// CHECK: Merchant/recipient address
+    pub recipient: UncheckedAccount<'info>,
+
+    /// CHECK: Guard's token account, checked by the subscription program
+    #[account(mut)]
+    pub guard_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Recipient's token account, checked by the subscription program
+    #[account(mut)]
+    pub recipient_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Token mint (USDC)
+    pub token_mint: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    /// Fee and rent payer, usually a relayer
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    /// CHECK: The guard's entry on the merchant's denylist, checked by the
+    /// subscription program
+    pub denylist_entry: UncheckedAccount<'info>,
+
+    /// CHECK: The merchant's allowlist, set while it is invite-only; checked
+    /// by the subscription program
+    pub allowlist: UncheckedAccount<'info>,
+
+    /// CHECK: The guard's entry on the merchant's allowlist, checked by the
+    /// subscription program
+    pub allowlist_entry: UncheckedAccount<'info>,
+
+    pub subscription_program: Program<'info, SubscriptionProgram>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateGuardedSubscription<'info> {
+    #[account(
+        mut,
+        seeds = [b"hybrid", keypair.key().as_ref()],
+        bump = guard.bump
+    )]
+    pub guard: Account<'info, Guard>,
+
+    /// CHECK: The guard's keypair; whether it signed is checked in the handler
+    pub keypair: UncheckedAccount<'info>,
+
+    /// CHECK: Instructions sysvar, to read the secp256r1 instructions
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = subscription.authority == guard.key() @ ErrorCode::NotGuarded
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    pub subscription_program: Program<'info, SubscriptionProgram>,
+}
+
+#[derive(Accounts)]
+pub struct CancelGuardedSubscription<'info> {
+    #[account(
+        mut,
+        seeds = [b"hybrid", keypair.key().as_ref()],
+        bump = guard.bump
+    )]
+    pub guard: Account<'info, Guard>,
+
+    /// CHECK: The guard's keypair; whether it signed is checked in the handler
+    pub keypair: UncheckedAccount<'info>,
+
+    /// CHECK: Instructions sysvar, to read the secp256r1 instructions
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = subscription.authority == guard.key() @ ErrorCode::NotGuarded
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    /// CHECK: Guard's token account, checked by the subscription program
+    #[account(mut)]
+    pub guard_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    pub subscription_program: Program<'info, SubscriptionProgram>,
+}
+
+/// An action the guard authorizes; the passkey signs its
+/// [`Guard::action_message`]
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub enum GuardedAction {
+    Open {
+        recipient: Pubkey,
+        token_mint: Pubkey,
+        amount_per_period: u64,
+        interval_seconds: i64,
+        expires_at: Option<i64>,
+    },
+    Update {
+        subscription: Pubkey,
+        new_amount: Option<u64>,
+        new_interval: Option<i64>,
+        new_expires_at: Option<i64>,
+    },
+    Cancel {
+        subscription: Pubkey,
+    },
+}
+
+impl GuardedAction {
+    /// Whether the action can make the guard pay more: opening, raising the
+    /// amount or charging more often
+    pub fn is_high_value(&self, current: Option<&Subscription>) -> bool {
+        match self {
+            GuardedAction::Open { .. } => true,
+            GuardedAction::Update {
+                new_amount,
+                new_interval,
+                ..
+            } => match current {
+                Some(current) => {
+                    new_amount.is_some_and(|amount| amount > current.amount_per_period)
+                        || new_interval.is_some_and(|interval| interval < current.interval_seconds)
+                }
+                None => true,
+            },
+            GuardedAction::Cancel { .. } => false,
+        }
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Guard {
+    /// Traditional keypair, e.g. from a seed phrase; also the PDA seed
+    pub keypair: Pubkey,
+    /// Compressed secp256r1 key of the passkey
+    pub passkey: [u8; 33],
+    /// Bumped by every authorized action, so a passkey signature is used once
+    pub nonce: u64,
+    pub bump: u8,
+}
+
+impl Guard {
+    /// The bytes the passkey signs to approve `action`: the domain, the
+    /// guard, the current nonce and the action
+    pub fn action_message(&self, guard: &Pubkey, action: &GuardedAction) -> Result<Vec<u8>> {
+        let mut message = ACTION_DOMAIN.to_vec();
+        message.extend_from_slice(guard.as_ref());
+        message.extend_from_slice(&self.nonce.to_le_bytes());
+        action.serialize(&mut message)?;
+        Ok(message)
+    }
+
+    /// High-value actions need both factors, the rest either one
+    pub fn check_factors(
+        &self,
+        passkey_signed: bool,
+        keypair_signed: bool,
+        high_value: bool,
+    ) -> Result<()> {
+        if high_value {
+            require!(
+                passkey_signed && keypair_signed,
+                ErrorCode::BothFactorsRequired
+            );
+        } else {
+            require!(passkey_signed || keypair_signed, ErrorCode::MissingFactor);
+        }
+        Ok(())
+    }
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct GuardCreated {
+    pub guard: Pubkey,
+    pub keypair: Pubkey,
+    pub passkey: [u8; 33],
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActionAuthorized {
+    pub guard: Pubkey,
+    pub passkey_signed: bool,
+    pub keypair_signed: bool,
+    pub high_value: bool,
+    pub nonce: u64,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("High-value actions need both the passkey and the keypair")]
+    BothFactorsRequired,
+    #[msg("Action needs the passkey or the keypair")]
+    MissingFactor,
+    #[msg("Subscription is not held by this guard")]
+    NotGuarded,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
@@ -0,0 +1,500 @@
+//! Native tests for the hybrid auth program.
+//!
+//! Which actions are high value, the factor rule and the signed message are
+//! pure and are tested directly. The instructions run end to end, through
+//! the subscription program and the real token and system programs. The
+//! harness neither runs the secp256r1 precompile nor builds the instructions
+//! sysvar, so the fixture writes a sysvar holding the precompile instruction a
+//! client would send.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::sysvar;
+use anchor_lang::{system_program, InstructionData, Space, ToAccountMetas};
+use hybrid_auth::{accounts, instruction, ErrorCode, Guard, GuardedAction, ID as PROGRAM_ID};
+use passkey_devices::SECP256R1_PROGRAM_ID;
+use subscription_program::Subscription;
+use test_harness::{
+    assert_error, assert_success, Account, Keypair, Signer, TestSvm, TransactionResult,
+};
+
+const PASSKEY: [u8; 33] = [2; 33];
+const AMOUNT: u64 = 10_000_000;
+const DAY: i64 = 86_400;
+
+fn subscription(authority: Pubkey) -> Subscription {
+    Subscription {
+        authority,
+        recipient: Pubkey::new_unique(),
+        user_token_account: Pubkey::new_unique(),
+        recipient_token_account: Pubkey::new_unique(),
+        token_mint: Pubkey::new_unique(),
+        amount_per_period: AMOUNT,
+        interval_seconds: 30 * DAY,
+        last_charge_timestamp: 0,
+        next_charge_at: 30 * DAY,
+        created_at: 0,
+        expires_at: None,
+        is_active: true,
+        total_charged: AMOUNT,
+        bump: 255,
+        on_plan: false,
+        max_total_spend: Subscription::NO_SPEND_LIMIT,
+    }
+}
+
+fn update(
+    subscription: Pubkey,
+    new_amount: Option<u64>,
+    new_interval: Option<i64>,
+) -> GuardedAction {
+    GuardedAction::Update {
+        subscription,
+        new_amount,
+        new_interval,
+        new_expires_at: None,
+    }
+}
+
+#[test]
+fn paying_more_is_high_value() {
+    let current = subscription(Pubkey::new_unique());
+    let address = Pubkey::new_unique();
+
+    assert!(update(address, Some(AMOUNT + 1), None).is_high_value(Some(&current)));
+    assert!(update(address, None, Some(7 * DAY)).is_high_value(Some(&current)));
+    assert!(!update(address, Some(AMOUNT), None).is_high_value(Some(&current)));
+    assert!(!update(address, Some(AMOUNT - 1), Some(60 * DAY)).is_high_value(Some(&current)));
+    assert!(!update(address, None, None).is_high_value(Some(&current)));
+
+    let open = GuardedAction::Open {
+        recipient: Pubkey::new_unique(),
+        token_mint: Pubkey::new_unique(),
+        amount_per_period: 1,
+        interval_seconds: DAY,
+        expires_at: None,
+    };
+    assert!(open.is_high_value(None));
+    assert!(!GuardedAction::Cancel {
+        subscription: address
+    }
+    .is_high_value(Some(&current)));
+}
+
+#[test]
+fn high_value_needs_both_factors_and_the_rest_either() {
+    let guard = Guard {
+        keypair: Pubkey::new_unique(),
+        passkey: PASSKEY,
+        nonce: 0,
+        bump: 255,
+    };
+
+    guard.check_factors(true, true, true).unwrap();
+    for (passkey, keypair) in [(true, false), (false, true), (false, false)] {
+        assert_eq!(
+            guard.check_factors(passkey, keypair, true).unwrap_err(),
+            ErrorCode::BothFactorsRequired.into()
+        );
+    }
+    guard.check_factors(true, false, false).unwrap();
+    guard.check_factors(false, true, false).unwrap();
+    assert_eq!(
+        guard.check_factors(false, false, false).unwrap_err(),
+        ErrorCode::MissingFactor.into()
+    );
+}
+
+#[test]
+fn the_message_binds_guard_nonce_and_action() {
+    let guard = Guard {
+        keypair: Pubkey::new_unique(),
+        passkey: PASSKEY,
+        nonce: 0,
+        bump: 255,
+    };
+    let address = Pubkey::new_unique();
+    let action = update(Pubkey::new_unique(), Some(AMOUNT * 2), None);
+    let message = guard.action_message(&address, &action).unwrap();
+
+    assert!(message.starts_with(hybrid_auth::ACTION_DOMAIN));
+    assert_ne!(
+        guard
+            .action_message(&Pubkey::new_unique(), &action)
+            .unwrap(),
+        message
+    );
+    assert_ne!(
+        Guard { nonce: 1, ..guard }
+            .action_message(&address, &action)
+            .unwrap(),
+        message
+    );
+    assert_ne!(
+        guard
+            .action_message(
+                &address,
+                &update(Pubkey::new_unique(), Some(AMOUNT * 2), None)
+            )
+            .unwrap(),
+        message
+    );
+}
+
+/// Secp256r1 instruction data with the key and message in the instruction
+/// itself, as the client builds it; the signature is zeroed
+fn secp256r1_data(pubkey: &[u8; 33], message: &[u8]) -> Vec<u8> {
+    let pubkey_offset = 16u16;
+    let signature_offset = pubkey_offset + 33;
+    let message_offset = signature_offset + 64;
+    let mut data = vec![1, 0];
+    for field in [
+        signature_offset,
+        u16::MAX,
+        pubkey_offset,
+        u16::MAX,
+        message_offset,
+        message.len() as u16,
+        u16::MAX,
+    ] {
+        data.extend_from_slice(&field.to_le_bytes());
+    }
+    data.extend_from_slice(pubkey);
+    data.extend_from_slice(&[0; 64]);
+    data.extend_from_slice(message);
+    data
+}
+
+struct Fixture {
+    svm: TestSvm,
+    payer: Keypair,
+    keypair: Keypair,
+    guard: Pubkey,
+    subscription: Pubkey,
+    recipient: Pubkey,
+    guard_token_account: Pubkey,
+}
+
+impl Fixture {
+    fn new() -> Self {
+        let (mut svm, payer) = TestSvm::for_program(PROGRAM_ID, hybrid_auth::entry);
+        svm.add_program(subscription_program::ID, subscription_program::entry);
+
+        let keypair = Keypair::new();
+
+        let (guard, bump) =
+            Pubkey::find_program_address(&[b"hybrid", keypair.pubkey().as_ref()], &PROGRAM_ID);
+        let state = Guard {
+            keypair: keypair.pubkey(),
+            passkey: PASSKEY,
+            nonce: 3,
+            bump,
+        };
+        svm.set_anchor_account(guard, &state, 8 + Guard::INIT_SPACE);
+
+        // The guard's subscription as `open_subscription` leaves it
+        let mint = svm.create_mint(&Pubkey::new_unique(), 6);
+        let recipient = Pubkey::new_unique();
+        let (subscription, bump) = Pubkey::find_program_address(
+            &[b"subscription", guard.as_ref(), recipient.as_ref()],
+            &subscription_program::ID,
+        );
+        let guard_token_account = svm.create_associated_token_account(&guard, &mint, 0);
+        svm.approve(&guard_token_account, &subscription, u64::MAX);
+        let state = Subscription {
+            recipient,
+            user_token_account: guard_token_account,
+            recipient_token_account: svm.create_associated_token_account(&recipient, &mint, 0),
+            token_mint: mint,
+            bump,
+            ..self::subscription(guard)
+        };
+        svm.set_anchor_account(subscription, &state, 8 + Subscription::INIT_SPACE);
+        set_instructions_sysvar(&mut svm, &[]);
+
+        Self {
+            svm,
+            payer,
+            keypair,
+            guard,
+            subscription,
+            recipient,
+            guard_token_account,
+        }
+    }
+
+    fn subscription(&self) -> Option<Subscription> {
+        self.svm.get_anchor_account(&self.subscription)
+    }
+
+    /// Have the passkey sign `action` at the guard's current nonce
+    fn passkey_signs(&mut self, action: &GuardedAction) {
+        let guard: Guard = self.svm.get_anchor_account(&self.guard).unwrap();
+        let message = guard.action_message(&self.guard, action).unwrap();
+        set_instructions_sysvar(
+            &mut self.svm,
+            &[(SECP256R1_PROGRAM_ID, secp256r1_data(&PASSKEY, &message))],
+        );
+    }
+
+    fn update(&self, keypair_signs: bool, new_amount: Option<u64>) -> Instruction {
+        let mut accounts = accounts::UpdateGuardedSubscription {
+            guard: self.guard,
+            keypair: self.keypair.pubkey(),
+            instructions: sysvar::instructions::ID,
+            subscription: self.subscription,
+            subscription_program: subscription_program::ID,
+        }
+        .to_account_metas(None);
+        accounts[1].is_signer = keypair_signs;
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts,
+            data: instruction::UpdateSubscription {
+                new_amount,
+                new_interval: None,
+                new_expires_at: None,
+            }
+            .data(),
+        }
+    }
+
+    fn open(&self, keypair_signs: bool) -> Instruction {
+        let state = self.subscription().unwrap();
+        let subscription_pda = |seeds: &[&[u8]]| {
+            Pubkey::find_program_address(seeds, &subscription_program::ID).0
+        };
+        let mut accounts = accounts::OpenGuardedSubscription {
+            guard: self.guard,
+            keypair: self.keypair.pubkey(),
+            instructions: sysvar::instructions::ID,
+            subscription: self.subscription,
+            recipient: self.recipient,
+            guard_token_account: self.guard_token_account,
+            recipient_token_account: state.recipient_token_account,
+            token_mint: state.token_mint,
+            token_program: spl_token::ID,
+            payer: self.payer.pubkey(),
+            system_program: system_program::ID,
+            denylist_entry: subscription_pda(&[
+                b"denylist",
+                self.recipient.as_ref(),
+                self.guard.as_ref(),
+            ]),
+            allowlist: subscription_pda(&[b"allowlist", self.recipient.as_ref()]),
+            allowlist_entry: subscription_pda(&[
+                b"allowlist",
+                self.recipient.as_ref(),
+                self.guard.as_ref(),
+            ]),
+            subscription_program: subscription_program::ID,
+        }
+        .to_account_metas(None);
+        accounts[1].is_signer = keypair_signs;
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts,
+            data: instruction::OpenSubscription {
+                amount_per_period: AMOUNT,
+                interval_seconds: 30 * DAY,
+                expires_at: None,
+            }
+            .data(),
+        }
+    }
+
+    fn open_action(&self) -> GuardedAction {
+        let state = self.subscription().unwrap();
+        GuardedAction::Open {
+            recipient: self.recipient,
+            token_mint: state.token_mint,
+            amount_per_period: AMOUNT,
+            interval_seconds: 30 * DAY,
+            expires_at: None,
+        }
+    }
+
+    fn cancel(&self, keypair_signs: bool) -> Instruction {
+        let mut accounts = accounts::CancelGuardedSubscription {
+            guard: self.guard,
+            keypair: self.keypair.pubkey(),
+            instructions: sysvar::instructions::ID,
+            subscription: self.subscription,
+            guard_token_account: self.guard_token_account,
+            token_program: spl_token::ID,
+            subscription_program: subscription_program::ID,
+        }
+        .to_account_metas(None);
+        accounts[1].is_signer = keypair_signs;
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts,
+            data: instruction::CancelSubscription {}.data(),
+        }
+    }
+
+    fn update_action(&self, new_amount: u64) -> GuardedAction {
+        update(self.subscription, Some(new_amount), None)
+    }
+
+    fn send(&mut self, instruction: Instruction) -> TransactionResult {
+        let payer = self.payer.insecure_clone();
+        let keypair = self.keypair.insecure_clone();
+        let signers: Vec<&Keypair> = if instruction.accounts[1].is_signer {
+            vec![&keypair]
+        } else {
+            vec![]
+        };
+        self.svm.expire_blockhash();
+        self.svm.send_instructions(&[instruction], &payer, &signers)
+    }
+}
+
+/// Write the harness's stand-in for the instructions sysvar of a transaction
+/// made of `instructions` (program and data, no accounts) followed by the one
+/// under test
+fn set_instructions_sysvar(svm: &mut TestSvm, instructions: &[(Pubkey, Vec<u8>)]) {
+    let mut all = instructions.to_vec();
+    all.push((PROGRAM_ID, vec![]));
+
+    let mut data = (all.len() as u16).to_le_bytes().to_vec();
+    let mut offset = 2 + 2 * all.len();
+    for (_, ix_data) in &all {
+        data.extend_from_slice(&(offset as u16).to_le_bytes());
+        offset += 2 + 32 + 2 + ix_data.len();
+    }
+    for (program_id, ix_data) in &all {
+        data.extend_from_slice(&0u16.to_le_bytes());
+        data.extend_from_slice(program_id.as_ref());
+        data.extend_from_slice(&(ix_data.len() as u16).to_le_bytes());
+        data.extend_from_slice(ix_data);
+    }
+    data.extend_from_slice(&(instructions.len() as u16).to_le_bytes());
+
+    svm.set_account(
+        sysvar::instructions::ID,
+        Account {
+            lamports: 1,
+            data,
+            owner: sysvar::ID,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+}
+
+#[test]
+fn keypairs_create_their_guard() {
+    let mut fx = Fixture::new();
+    fx.svm.remove_account(&fx.guard);
+    let instruction = Instruction {
+        program_id: PROGRAM_ID,
+        accounts: accounts::CreateGuard {
+            guard: fx.guard,
+            keypair: fx.keypair.pubkey(),
+            payer: fx.payer.pubkey(),
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: instruction::CreateGuard { passkey: PASSKEY }.data(),
+    };
+
+    let result = fx.send(instruction);
+    assert_success(result);
+    let guard: Guard = fx.svm.get_anchor_account(&fx.guard).unwrap();
+    assert_eq!(guard.keypair, fx.keypair.pubkey());
+    assert_eq!(guard.passkey, PASSKEY);
+    assert_eq!(guard.nonce, 0);
+}
+
+#[test]
+fn opening_needs_both_factors_and_charges_the_first_period() {
+    let mut fx = Fixture::new();
+    let open = fx.open(true);
+    let action = fx.open_action();
+    let recipient_token_account = fx.subscription().unwrap().recipient_token_account;
+    fx.svm.remove_account(&fx.subscription);
+    fx.svm.revoke(&fx.guard_token_account);
+    fx.svm.mint_tokens(&fx.guard_token_account, 10 * AMOUNT);
+
+    let result = fx.send(open.clone());
+    assert_error(result, ErrorCode::BothFactorsRequired);
+
+    fx.passkey_signs(&action);
+    let result = fx.send(open);
+    assert_success(result);
+    let subscription = fx.subscription().unwrap();
+    assert_eq!(subscription.authority, fx.guard);
+    assert_eq!(subscription.amount_per_period, AMOUNT);
+    let guard_account = fx.svm.get_token_account(&fx.guard_token_account).unwrap();
+    assert_eq!(guard_account.amount, 9 * AMOUNT);
+    assert_eq!(guard_account.delegate, Some(fx.subscription).into());
+    assert_eq!(fx.svm.token_balance(&recipient_token_account), AMOUNT);
+}
+
+#[test]
+fn raising_the_amount_needs_passkey_and_keypair() {
+    let mut fx = Fixture::new();
+    let raise = fx.update_action(AMOUNT * 2);
+
+    let result = fx.send(fx.update(true, Some(AMOUNT * 2)));
+    assert_error(result, ErrorCode::BothFactorsRequired);
+
+    fx.passkey_signs(&raise);
+    let result = fx.send(fx.update(false, Some(AMOUNT * 2)));
+    assert_error(result, ErrorCode::BothFactorsRequired);
+
+    let result = fx.send(fx.update(true, Some(AMOUNT * 2)));
+    assert_success(result);
+    assert_eq!(fx.subscription().unwrap().amount_per_period, AMOUNT * 2);
+}
+
+#[test]
+fn lowering_the_amount_needs_one_factor() {
+    let mut fx = Fixture::new();
+    let result = fx.send(fx.update(false, Some(AMOUNT / 2)));
+    assert_error(result, ErrorCode::MissingFactor);
+
+    // A passkey signature for another action does not count
+    fx.passkey_signs(&fx.update_action(AMOUNT / 4));
+    let result = fx.send(fx.update(false, Some(AMOUNT / 2)));
+    assert_error(result, ErrorCode::MissingFactor);
+
+    fx.passkey_signs(&fx.update_action(AMOUNT / 2));
+    let result = fx.send(fx.update(false, Some(AMOUNT / 2)));
+    assert_success(result);
+    assert_eq!(fx.subscription().unwrap().amount_per_period, AMOUNT / 2);
+
+    set_instructions_sysvar(&mut fx.svm, &[]);
+    let result = fx.send(fx.update(true, Some(AMOUNT / 4)));
+    assert_success(result);
+    assert_eq!(fx.subscription().unwrap().amount_per_period, AMOUNT / 4);
+}
+
+#[test]
+fn either_factor_cancels() {
+    let mut fx = Fixture::new();
+    let result = fx.send(fx.cancel(false));
+    assert_error(result, ErrorCode::MissingFactor);
+
+    let result = fx.send(fx.cancel(true));
+    assert_success(result);
+    assert!(fx.subscription().is_none());
+    let guard_account = fx.svm.get_token_account(&fx.guard_token_account).unwrap();
+    assert!(guard_account.delegate.is_none());
+}
+
+#[test]
+fn only_the_guards_subscriptions_are_touched() {
+    let mut fx = Fixture::new();
+    let other = Pubkey::new_unique();
+    fx.svm.set_anchor_account(
+        other,
+        &subscription(Pubkey::new_unique()),
+        8 + Subscription::INIT_SPACE,
+    );
+    fx.subscription = other;
+
+    let result = fx.send(fx.update(true, Some(AMOUNT / 2)));
+    assert_error(result, ErrorCode::NotGuarded);
+}
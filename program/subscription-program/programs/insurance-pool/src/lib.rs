@@ -0,0 +1,1036 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use spl_token::instruction as token_instruction;
+use token_utils::token_account;
+
+declare_id!("GtMqYV1NyoUVpGUZ9NFHjVWPXzPsBZJ5YNjuJunwoQup");
+
+#[program]
+pub mod insurance_pool {
+    use super::*;
+
+    /// Open a pool charging `premium_amount` every `interval_seconds`, with
+    /// `grace_seconds` to pay a late premium. Each policy can be paid out up
+    /// to `coverage_limit` over its life. `min_reserve` stays in the vault
+    /// whatever the admin withdraws.
+    pub fn create_pool(
+        ctx: Context<CreatePool>,
+        premium_amount: u64,
+        interval_seconds: i64,
+        grace_seconds: i64,
+        coverage_limit: u64,
+        min_reserve: u64,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        Pool::check_params(
+            premium_amount,
+            interval_seconds,
+            grace_seconds,
+            coverage_limit,
+        )?;
+
+        let mint = ctx.accounts.mint.key();
+        let vault = token_account(&ctx.accounts.vault, ErrorCode::InvalidTokenAccount)?;
+        require_keys_eq!(
+            vault.owner,
+            ctx.accounts.pool.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+        require_keys_eq!(vault.mint, mint, ErrorCode::InvalidTokenAccount);
+
+        let pool = &mut ctx.accounts.pool;
+        pool.admin = ctx.accounts.admin.key();
+        pool.mint = mint;
+        pool.vault = ctx.accounts.vault.key();
+        pool.premium_amount = premium_amount;
+        pool.interval_seconds = interval_seconds;
+        pool.grace_seconds = grace_seconds;
+        pool.coverage_limit = coverage_limit;
+        pool.min_reserve = min_reserve;
+        pool.active_policies = 0;
+        pool.pending_claims = 0;
+        pool.pending_amount = 0;
+        pool.total_premiums = 0;
+        pool.total_payouts = 0;
+        pool.total_withdrawn = 0;
+        pool.created_at = clock.unix_timestamp;
+        pool.bump = ctx.bumps.pool;
+
+        emit!(PoolCreated {
+            pool: pool.key(),
+            admin: pool.admin,
+            premium_amount,
+            interval_seconds,
+            coverage_limit,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("Pool created: {} premium", premium_amount);
+        msg!("Every {} seconds", interval_seconds);
+
+        Ok(())
+    }
+
+    /// Take out a policy. As with a subscription, the pool PDA becomes the
+    /// delegate of `holder_token_account`; the first premium is due at once
+    /// and cover starts when it is collected.
+    pub fn enroll(ctx: Context<Enroll>) -> Result<()> {
+        let clock = Clock::get()?;
+        let holder = ctx.accounts.holder.key();
+        let holder_account = token_account(
+            &ctx.accounts.holder_token_account,
+            ErrorCode::InvalidTokenAccount,
+        )?;
+        require_keys_eq!(holder_account.owner, holder, ErrorCode::InvalidTokenAccount);
+        require_keys_eq!(
+            holder_account.mint,
+            ctx.accounts.pool.mint,
+            ErrorCode::InvalidTokenAccount
+        );
+
+        let delegate_ix = token_instruction::approve(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.holder_token_account.key(),
+            &ctx.accounts.pool.key(),
+            &holder,
+            &[],
+            u64::MAX,
+        )?;
+
+        invoke(
+            &delegate_ix,
+            &[
+                ctx.accounts.holder_token_account.to_account_info(),
+                ctx.accounts.pool.to_account_info(),
+                ctx.accounts.holder.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        let policy = &mut ctx.accounts.policy;
+        policy.pool = ctx.accounts.pool.key();
+        policy.holder = holder;
+        policy.token_account = ctx.accounts.holder_token_account.key();
+        policy.paid_until = clock.unix_timestamp;
+        policy.active = true;
+        policy.premiums_paid = 0;
+        policy.claims_paid = 0;
+        policy.next_claim_id = 0;
+        policy.pending_claims = 0;
+        policy.created_at = clock.unix_timestamp;
+        policy.bump = ctx.bumps.policy;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.active_policies = pool
+            .active_policies
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        emit!(PolicyEnrolled {
+            pool: pool.key(),
+            policy: policy.key(),
+            holder,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("Policy enrolled for {}", holder);
+        msg!("Token delegation approved");
+
+        Ok(())
+    }
+
+    /// Collect the next premium into the pool. Permissionless, like
+    /// `charge_subscription`, from when cover runs out until the grace
+    /// period ends.
+    pub fn collect_premium(ctx: Context<CollectPremium>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let pool = &ctx.accounts.pool;
+        ctx.accounts
+            .policy
+            .check_premium_due(now, pool.grace_seconds)?;
+
+        let premium = pool.premium_amount;
+        let seeds = &[b"pool", pool.admin.as_ref(), &[pool.bump]];
+        let signer_seeds = &[&seeds[..]];
+
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.holder_token_account.key(),
+            &ctx.accounts.vault.key(),
+            &pool.key(),
+            &[],
+            premium,
+        )?;
+
+        invoke_signed(
+            &transfer_ix,
+            &[
+                ctx.accounts.holder_token_account.to_account_info(),
+                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.pool.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+            signer_seeds,
+        )?;
+
+        let interval = pool.interval_seconds;
+        let policy = &mut ctx.accounts.policy;
+        policy.record_premium(premium, interval)?;
+        let pool = &mut ctx.accounts.pool;
+        pool.total_premiums = pool
+            .total_premiums
+            .checked_add(premium)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        emit!(PremiumCollected {
+            pool: pool.key(),
+            policy: policy.key(),
+            amount: premium,
+            paid_until: policy.paid_until,
+            timestamp: now,
+        });
+
+        msg!("Premium collected: {}", premium);
+        msg!("Covered until {}", policy.paid_until);
+
+        Ok(())
+    }
+
+    /// Mark a policy whose grace period passed unpaid as lapsed.
+    /// Permissionless and CPI-free, so a keeper can run it even when the
+    /// holder's delegation is gone.
+    pub fn lapse_policy(ctx: Context<LapsePolicy>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let grace = ctx.accounts.pool.grace_seconds;
+        let policy = &mut ctx.accounts.policy;
+        policy.check_lapsed(now, grace)?;
+        policy.active = false;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.active_policies = pool.active_policies.saturating_sub(1);
+
+        emit!(PolicyLapsed {
+            pool: pool.key(),
+            policy: policy.key(),
+            holder: policy.holder,
+            timestamp: now,
+        });
+
+        msg!("Policy lapsed: {}", policy.holder);
+
+        Ok(())
+    }
+
+    /// File a claim for `amount` while covered. `evidence_hash` commits to the
+    /// documents the admin reviews off-chain.
+    pub fn file_claim(ctx: Context<FileClaim>, amount: u64, evidence_hash: [u8; 32]) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let coverage_limit = ctx.accounts.pool.coverage_limit;
+        let policy = &mut ctx.accounts.policy;
+        let claim_id = policy.file_claim(amount, now, coverage_limit)?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.add_pending(amount)?;
+
+        let claim = &mut ctx.accounts.claim;
+        claim.policy = policy.key();
+        claim.claim_id = claim_id;
+        claim.amount = amount;
+        claim.payout = 0;
+        claim.evidence_hash = evidence_hash;
+        claim.status = ClaimStatus::Pending;
+        claim.filed_at = now;
+        claim.decided_at = None;
+        claim.bump = ctx.bumps.claim;
+
+        emit!(ClaimFiled {
+            pool: pool.key(),
+            policy: policy.key(),
+            claim: claim.key(),
+            amount,
+            timestamp: now,
+        });
+
+        msg!("Claim {} filed: {}", claim_id, amount);
+
+        Ok(())
+    }
+
+    /// Approve a pending claim and pay `payout`, at most the amount claimed,
+    /// from the pool to the holder
+    pub fn approve_claim(ctx: Context<DecideClaim>, payout: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let vault_balance =
+            token_account(&ctx.accounts.vault, ErrorCode::InvalidTokenAccount)?.amount;
+        let claim = &mut ctx.accounts.claim;
+        claim.decide(ClaimStatus::Paid, payout, now)?;
+        require!(payout <= vault_balance, ErrorCode::InsufficientPool);
+        let requested = claim.amount;
+
+        let coverage_limit = ctx.accounts.pool.coverage_limit;
+        ctx.accounts.policy.settle_claim(payout, coverage_limit)?;
+        ctx.accounts.pool.settle_pending(requested, payout)?;
+
+        let pool = &ctx.accounts.pool;
+        let seeds = &[b"pool", pool.admin.as_ref(), &[pool.bump]];
+        let signer_seeds = &[&seeds[..]];
+
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.vault.key(),
+            &ctx.accounts.holder_token_account.key(),
+            &pool.key(),
+            &[],
+            payout,
+        )?;
+
+        invoke_signed(
+            &transfer_ix,
+            &[
+                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.holder_token_account.to_account_info(),
+                ctx.accounts.pool.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+            signer_seeds,
+        )?;
+
+        emit!(ClaimPaid {
+            pool: pool.key(),
+            claim: ctx.accounts.claim.key(),
+            holder: ctx.accounts.policy.holder,
+            amount: requested,
+            payout,
+            timestamp: now,
+        });
+
+        msg!("Claim approved: {} of {} paid", payout, requested);
+
+        Ok(())
+    }
+
+    /// Reject a pending claim
+    pub fn reject_claim(ctx: Context<RejectClaim>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let claim = &mut ctx.accounts.claim;
+        claim.decide(ClaimStatus::Rejected, 0, now)?;
+        let requested = claim.amount;
+
+        let coverage_limit = ctx.accounts.pool.coverage_limit;
+        ctx.accounts.policy.settle_claim(0, coverage_limit)?;
+        ctx.accounts.pool.settle_pending(requested, 0)?;
+
+        emit!(ClaimRejected {
+            pool: ctx.accounts.pool.key(),
+            claim: ctx.accounts.claim.key(),
+            holder: ctx.accounts.policy.holder,
+            amount: requested,
+            timestamp: now,
+        });
+
+        msg!("Claim rejected: {}", requested);
+
+        Ok(())
+    }
+
+    /// Withdraw pool surplus to the admin. The vault always keeps
+    /// `min_reserve` plus everything still claimed in pending claims.
+    pub fn withdraw_surplus(ctx: Context<WithdrawSurplus>, amount: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        let vault_balance =
+            token_account(&ctx.accounts.vault, ErrorCode::InvalidTokenAccount)?.amount;
+        let pool = &ctx.accounts.pool;
+        require!(
+            amount <= pool.surplus(vault_balance),
+            ErrorCode::ReserveRequired
+        );
+
+        let seeds = &[b"pool", pool.admin.as_ref(), &[pool.bump]];
+        let signer_seeds = &[&seeds[..]];
+
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.vault.key(),
+            &ctx.accounts.admin_token_account.key(),
+            &pool.key(),
+            &[],
+            amount,
+        )?;
+
+        invoke_signed(
+            &transfer_ix,
+            &[
+                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.admin_token_account.to_account_info(),
+                ctx.accounts.pool.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+            signer_seeds,
+        )?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.total_withdrawn = pool
+            .total_withdrawn
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        emit!(SurplusWithdrawn {
+            pool: pool.key(),
+            amount,
+            timestamp: now,
+        });
+
+        msg!("Surplus withdrawn: {}", amount);
+
+        Ok(())
+    }
+
+    /// End a policy with no claims pending: revoke the delegation and close it
+    pub fn cancel_policy(ctx: Context<CancelPolicy>) -> Result<()> {
+        let policy = &ctx.accounts.policy;
+        require!(policy.pending_claims == 0, ErrorCode::ClaimsPending);
+
+        let revoke_ix = token_instruction::revoke(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.holder_token_account.key(),
+            &ctx.accounts.holder.key(),
+            &[],
+        )?;
+        invoke(
+            &revoke_ix,
+            &[
+                ctx.accounts.holder_token_account.to_account_info(),
+                ctx.accounts.holder.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        if policy.active {
+            let pool = &mut ctx.accounts.pool;
+            pool.active_policies = pool.active_policies.saturating_sub(1);
+        }
+
+        emit!(PolicyCancelled {
+            pool: ctx.accounts.pool.key(),
+            policy: ctx.accounts.policy.key(),
+            holder: ctx.accounts.holder.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Policy cancelled");
+        msg!("Token delegation revoked");
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct CreatePool<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Pool::INIT_SPACE,
+        seeds = [b"pool", admin.key().as_ref()],
+        bump
+    )]
+    pub pool: Account<'info, Pool>,
+
+    pub admin: Signer<'info>,
+
+    /// CHECK: Token mint (USDC)
+    pub mint: UncheckedAccount<'info>,
+
+    /// CHECK: The pool PDA's token account, checked in the handler
+    pub vault: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Enroll<'info> {
+    #[account(
+        mut,
+        seeds = [b"pool", pool.admin.as_ref()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Policy::INIT_SPACE,
+        seeds = [b"policy", pool.key().as_ref(), holder.key().as_ref()],
+        bump
+    )]
+    pub policy: Account<'info, Policy>,
+
+    pub holder: Signer<'info>,
+
+    /// CHECK: Holder's token account, checked in the handler and delegated to the pool PDA
+    #[account(mut)]
+    pub holder_token_account: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CollectPremium<'info> {
+    #[account(
+        mut,
+        seeds = [b"pool", pool.admin.as_ref()],
+        bump = pool.bump,
+        has_one = vault
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        mut,
+        seeds = [b"policy", pool.key().as_ref(), policy.holder.as_ref()],
+        bump = policy.bump,
+        has_one = pool,
+        constraint = policy.token_account == holder_token_account.key()
+            @ ErrorCode::InvalidTokenAccount
+    )]
+    pub policy: Account<'info, Policy>,
+
+    /// CHECK: The delegated holder account, checked against the policy
+    #[account(mut)]
+    pub holder_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: The pool vault
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct LapsePolicy<'info> {
+    #[account(
+        mut,
+        seeds = [b"pool", pool.admin.as_ref()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        mut,
+        seeds = [b"policy", pool.key().as_ref(), policy.holder.as_ref()],
+        bump = policy.bump,
+        has_one = pool
+    )]
+    pub policy: Account<'info, Policy>,
+}
+
+#[derive(Accounts)]
+pub struct FileClaim<'info> {
+    #[account(
+        mut,
+        seeds = [b"pool", pool.admin.as_ref()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        mut,
+        seeds = [b"policy", pool.key().as_ref(), holder.key().as_ref()],
+        bump = policy.bump,
+        has_one = pool,
+        has_one = holder
+    )]
+    pub policy: Account<'info, Policy>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Claim::INIT_SPACE,
+        seeds = [
+            b"claim",
+            policy.key().as_ref(),
+            policy.next_claim_id.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub claim: Account<'info, Claim>,
+
+    pub holder: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DecideClaim<'info> {
+    #[account(
+        mut,
+        seeds = [b"pool", admin.key().as_ref()],
+        bump = pool.bump,
+        has_one = admin,
+        has_one = vault
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        mut,
+        seeds = [b"policy", pool.key().as_ref(), policy.holder.as_ref()],
+        bump = policy.bump,
+        has_one = pool,
+        constraint = policy.token_account == holder_token_account.key()
+            @ ErrorCode::InvalidTokenAccount
+    )]
+    pub policy: Account<'info, Policy>,
+
+    #[account(
+        mut,
+        seeds = [b"claim", policy.key().as_ref(), claim.claim_id.to_le_bytes().as_ref()],
+        bump = claim.bump,
+        has_one = policy
+    )]
+    pub claim: Account<'info, Claim>,
+
+    pub admin: Signer<'info>,
+
+    /// CHECK: The pool vault
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: The holder's token account, checked against the policy
+    #[account(mut)]
+    pub holder_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RejectClaim<'info> {
+    #[account(
+        mut,
+        seeds = [b"pool", admin.key().as_ref()],
+        bump = pool.bump,
+        has_one = admin
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        mut,
+        seeds = [b"policy", pool.key().as_ref(), policy.holder.as_ref()],
+        bump = policy.bump,
+        has_one = pool
+    )]
+    pub policy: Account<'info, Policy>,
+
+    #[account(
+        mut,
+        seeds = [b"claim", policy.key().as_ref(), claim.claim_id.to_le_bytes().as_ref()],
+        bump = claim.bump,
+        has_one = policy
+    )]
+    pub claim: Account<'info, Claim>,
+
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawSurplus<'info> {
+    #[account(
+        mut,
+        seeds = [b"pool", admin.key().as_ref()],
+        bump = pool.bump,
+        has_one = admin,
+        has_one = vault
+    )]
+    pub pool: Account<'info, Pool>,
+
+    pub admin: Signer<'info>,
+
+    /// CHECK: The pool vault
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: Any token account for the pool mint; the token program checks it
+    #[account(mut)]
+    pub admin_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelPolicy<'info> {
+    #[account(
+        mut,
+        seeds = [b"pool", pool.admin.as_ref()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        mut,
+        seeds = [b"policy", pool.key().as_ref(), holder.key().as_ref()],
+        bump = policy.bump,
+        has_one = pool,
+        has_one = holder,
+        constraint = policy.token_account == holder_token_account.key()
+            @ ErrorCode::InvalidTokenAccount,
+        close = holder
+    )]
+    pub policy: Account<'info, Policy>,
+
+    #[account(mut)]
+    pub holder: Signer<'info>,
+
+    /// CHECK: The delegated holder account, checked against the policy
+    #[account(mut)]
+    pub holder_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Pool {
+    pub admin: Pubkey,
+    pub mint: Pubkey,
+    /// Owned by this PDA; premiums in, payouts out
+    pub vault: Pubkey,
+    pub premium_amount: u64,
+    pub interval_seconds: i64,
+    pub grace_seconds: i64,
+    /// Most one policy can be paid out over its life
+    pub coverage_limit: u64,
+    /// Kept in the vault whatever the admin withdraws
+    pub min_reserve: u64,
+    pub active_policies: u64,
+    pub pending_claims: u64,
+    /// Claimed in pending claims, also kept in the vault
+    pub pending_amount: u64,
+    pub total_premiums: u64,
+    pub total_payouts: u64,
+    pub total_withdrawn: u64,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl Pool {
+    pub fn check_params(
+        premium_amount: u64,
+        interval_seconds: i64,
+        grace_seconds: i64,
+        coverage_limit: u64,
+    ) -> Result<()> {
+        require!(premium_amount > 0, ErrorCode::InvalidAmount);
+        require!(coverage_limit > 0, ErrorCode::InvalidAmount);
+        require!(interval_seconds > 0, ErrorCode::InvalidInterval);
+        require!(grace_seconds >= 0, ErrorCode::InvalidInterval);
+        Ok(())
+    }
+
+    /// What the admin may withdraw from a vault holding `vault_balance`
+    pub fn surplus(&self, vault_balance: u64) -> u64 {
+        vault_balance
+            .saturating_sub(self.min_reserve)
+            .saturating_sub(self.pending_amount)
+    }
+
+    pub fn add_pending(&mut self, amount: u64) -> Result<()> {
+        self.pending_claims = self
+            .pending_claims
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.pending_amount = self
+            .pending_amount
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+
+    /// Release a decided claim of `requested` that paid out `payout`
+    pub fn settle_pending(&mut self, requested: u64, payout: u64) -> Result<()> {
+        self.pending_claims = self.pending_claims.saturating_sub(1);
+        self.pending_amount = self.pending_amount.saturating_sub(requested);
+        self.total_payouts = self
+            .total_payouts
+            .checked_add(payout)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Policy {
+    pub pool: Pubkey,
+    pub holder: Pubkey,
+    /// Delegated to the pool PDA; premiums are pulled from and payouts sent to it
+    pub token_account: Pubkey,
+    /// Covered until this time
+    pub paid_until: i64,
+    /// Cleared when the policy lapses
+    pub active: bool,
+    pub premiums_paid: u64,
+    pub claims_paid: u64,
+    pub next_claim_id: u64,
+    pub pending_claims: u64,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl Policy {
+    pub fn is_covered(&self, now: i64) -> bool {
+        self.active && now < self.paid_until
+    }
+
+    pub fn check_premium_due(&self, now: i64, grace_seconds: i64) -> Result<()> {
+        require!(self.active, ErrorCode::PolicyLapsed);
+        require!(now >= self.paid_until, ErrorCode::PremiumNotDue);
+        require!(
+            now <= self.paid_until.saturating_add(grace_seconds),
+            ErrorCode::PolicyLapsed
+        );
+        Ok(())
+    }
+
+    pub fn check_lapsed(&self, now: i64, grace_seconds: i64) -> Result<()> {
+        require!(self.active, ErrorCode::PolicyLapsed);
+        require!(
+            now > self.paid_until.saturating_add(grace_seconds),
+            ErrorCode::PremiumsCurrent
+        );
+        Ok(())
+    }
+
+    /// Extend cover after a premium has moved. The first premium starts
+    /// cover at enrolment; later ones extend it back to back.
+    pub fn record_premium(&mut self, premium: u64, interval_seconds: i64) -> Result<()> {
+        self.paid_until = self
+            .paid_until
+            .checked_add(interval_seconds)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.premiums_paid = self
+            .premiums_paid
+            .checked_add(premium)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+
+    /// Left to pay out under `coverage_limit`
+    pub fn remaining_coverage(&self, coverage_limit: u64) -> u64 {
+        coverage_limit.saturating_sub(self.claims_paid)
+    }
+
+    /// Open a claim for `amount`. Returns its id.
+    pub fn file_claim(&mut self, amount: u64, now: i64, coverage_limit: u64) -> Result<u64> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        require!(self.is_covered(now), ErrorCode::NotCovered);
+        require!(
+            amount <= self.remaining_coverage(coverage_limit),
+            ErrorCode::CoverageExceeded
+        );
+        let claim_id = self.next_claim_id;
+        self.next_claim_id = claim_id
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.pending_claims = self
+            .pending_claims
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(claim_id)
+    }
+
+    /// Close out a decided claim that paid `payout`
+    pub fn settle_claim(&mut self, payout: u64, coverage_limit: u64) -> Result<()> {
+        require!(
+            payout <= self.remaining_coverage(coverage_limit),
+            ErrorCode::CoverageExceeded
+        );
+        self.claims_paid = self
+            .claims_paid
+            .checked_add(payout)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.pending_claims = self.pending_claims.saturating_sub(1);
+        Ok(())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub enum ClaimStatus {
+    /// Waiting for the admin
+    Pending,
+    /// Approved and paid out
+    Paid,
+    Rejected,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Claim {
+    pub policy: Pubkey,
+    pub claim_id: u64,
+    /// Amount claimed
+    pub amount: u64,
+    /// Amount paid out; 0 unless paid
+    pub payout: u64,
+    /// Commits to the evidence reviewed off-chain
+    pub evidence_hash: [u8; 32],
+    pub status: ClaimStatus,
+    pub filed_at: i64,
+    pub decided_at: Option<i64>,
+    pub bump: u8,
+}
+
+impl Claim {
+    /// Settle a pending claim as `status`, paying `payout`
+    pub fn decide(&mut self, status: ClaimStatus, payout: u64, now: i64) -> Result<()> {
+        require!(
+            self.status == ClaimStatus::Pending,
+            ErrorCode::ClaimAlreadyDecided
+        );
+        require!(payout <= self.amount, ErrorCode::InvalidPayout);
+        if status == ClaimStatus::Paid {
+            require!(payout > 0, ErrorCode::InvalidPayout);
+        }
+        self.status = status;
+        self.payout = payout;
+        self.decided_at = Some(now);
+        Ok(())
+    }
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolCreated {
+    pub pool: Pubkey,
+    pub admin: Pubkey,
+    pub premium_amount: u64,
+    pub interval_seconds: i64,
+    pub coverage_limit: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyEnrolled {
+    pub pool: Pubkey,
+    pub policy: Pubkey,
+    pub holder: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PremiumCollected {
+    pub pool: Pubkey,
+    pub policy: Pubkey,
+    pub amount: u64,
+    pub paid_until: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyLapsed {
+    pub pool: Pubkey,
+    pub policy: Pubkey,
+    pub holder: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClaimFiled {
+    pub pool: Pubkey,
+    pub policy: Pubkey,
+    pub claim: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClaimPaid {
+    pub pool: Pubkey,
+    pub claim: Pubkey,
+    pub holder: Pubkey,
+    pub amount: u64,
+    pub payout: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClaimRejected {
+    pub pool: Pubkey,
+    pub claim: Pubkey,
+    pub holder: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SurplusWithdrawn {
+    pub pool: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyCancelled {
+    pub pool: Pubkey,
+    pub policy: Pubkey,
+    pub holder: Pubkey,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Amount must be greater than zero")]
+    InvalidAmount,
+    #[msg("Interval must be positive and grace period not negative")]
+    InvalidInterval,
+    #[msg("Invalid token account - wrong program, owner or mint")]
+    InvalidTokenAccount,
+    #[msg("Next premium is not due yet")]
+    PremiumNotDue,
+    #[msg("Policy has lapsed")]
+    PolicyLapsed,
+    #[msg("Premiums are current; the policy has not lapsed")]
+    PremiumsCurrent,
+    #[msg("Policy is not covered right now")]
+    NotCovered,
+    #[msg("Claim exceeds the policy's remaining coverage")]
+    CoverageExceeded,
+    #[msg("Claim has already been decided")]
+    ClaimAlreadyDecided,
+    #[msg("Payout must be positive and at most the amount claimed")]
+    InvalidPayout,
+    #[msg("Pool vault cannot cover the payout")]
+    InsufficientPool,
+    #[msg("Withdrawal would dip into the reserve or pending claims")]
+    ReserveRequired,
+    #[msg("Policy has claims pending")]
+    ClaimsPending,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
@@ -0,0 +1,638 @@
+//! Native tests for the insurance pool program.
+//!
+//! The premium schedule, claim accounting and reserve rules are pure methods
+//! and are tested directly. The instructions run end to end against the real
+//! token and system programs, and the tests check where the premiums and
+//! payouts land.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, Space, ToAccountMetas};
+use insurance_pool::{
+    accounts, instruction, Claim, ClaimStatus, ErrorCode, Policy, Pool, ID as PROGRAM_ID,
+};
+use test_harness::{assert_error, assert_success, Keypair, Signer, TestSvm, TransactionResult};
+
+const PREMIUM: u64 = 10_000_000;
+const COVERAGE: u64 = 500_000_000;
+const RESERVE: u64 = 100_000_000;
+const MONTH: i64 = 30 * 86_400;
+const GRACE: i64 = 3 * 86_400;
+
+fn pool() -> Pool {
+    Pool {
+        admin: Pubkey::new_unique(),
+        mint: Pubkey::new_unique(),
+        vault: Pubkey::new_unique(),
+        premium_amount: PREMIUM,
+        interval_seconds: MONTH,
+        grace_seconds: GRACE,
+        coverage_limit: COVERAGE,
+        min_reserve: RESERVE,
+        active_policies: 1,
+        pending_claims: 0,
+        pending_amount: 0,
+        total_premiums: 0,
+        total_payouts: 0,
+        total_withdrawn: 0,
+        created_at: 0,
+        bump: 255,
+    }
+}
+
+fn policy(paid_until: i64) -> Policy {
+    Policy {
+        pool: Pubkey::new_unique(),
+        holder: Pubkey::new_unique(),
+        token_account: Pubkey::new_unique(),
+        paid_until,
+        active: true,
+        premiums_paid: 0,
+        claims_paid: 0,
+        next_claim_id: 0,
+        pending_claims: 0,
+        created_at: 0,
+        bump: 255,
+    }
+}
+
+fn claim(amount: u64) -> Claim {
+    Claim {
+        policy: Pubkey::new_unique(),
+        claim_id: 0,
+        amount,
+        payout: 0,
+        evidence_hash: [7; 32],
+        status: ClaimStatus::Pending,
+        filed_at: 0,
+        decided_at: None,
+        bump: 255,
+    }
+}
+
+#[test]
+fn params_are_validated() {
+    assert!(Pool::check_params(PREMIUM, MONTH, GRACE, COVERAGE).is_ok());
+    assert!(Pool::check_params(PREMIUM, MONTH, 0, COVERAGE).is_ok());
+    assert_eq!(
+        Pool::check_params(0, MONTH, GRACE, COVERAGE).unwrap_err(),
+        ErrorCode::InvalidAmount.into()
+    );
+    assert_eq!(
+        Pool::check_params(PREMIUM, MONTH, GRACE, 0).unwrap_err(),
+        ErrorCode::InvalidAmount.into()
+    );
+    assert_eq!(
+        Pool::check_params(PREMIUM, 0, GRACE, COVERAGE).unwrap_err(),
+        ErrorCode::InvalidInterval.into()
+    );
+    assert_eq!(
+        Pool::check_params(PREMIUM, MONTH, -1, COVERAGE).unwrap_err(),
+        ErrorCode::InvalidInterval.into()
+    );
+}
+
+#[test]
+fn premiums_are_due_until_the_grace_period_ends() {
+    let mut policy = policy(1_000);
+    assert!(policy.is_covered(999));
+    assert!(!policy.is_covered(1_000));
+
+    assert_eq!(
+        policy.check_premium_due(999, GRACE).unwrap_err(),
+        ErrorCode::PremiumNotDue.into()
+    );
+    assert!(policy.check_premium_due(1_000, GRACE).is_ok());
+    assert!(policy.check_premium_due(1_000 + GRACE, GRACE).is_ok());
+    assert_eq!(
+        policy.check_premium_due(1_001 + GRACE, GRACE).unwrap_err(),
+        ErrorCode::PolicyLapsed.into()
+    );
+
+    assert_eq!(
+        policy.check_lapsed(1_000 + GRACE, GRACE).unwrap_err(),
+        ErrorCode::PremiumsCurrent.into()
+    );
+    assert!(policy.check_lapsed(1_001 + GRACE, GRACE).is_ok());
+
+    // A late premium extends cover from where it ran out, not from now
+    policy.record_premium(PREMIUM, MONTH).unwrap();
+    assert_eq!(policy.paid_until, 1_000 + MONTH);
+    assert_eq!(policy.premiums_paid, PREMIUM);
+
+    policy.active = false;
+    assert!(!policy.is_covered(1_000));
+    assert_eq!(
+        policy.check_premium_due(1_000 + MONTH, GRACE).unwrap_err(),
+        ErrorCode::PolicyLapsed.into()
+    );
+}
+
+#[test]
+fn claims_stay_within_coverage() {
+    let mut policy = policy(1_000);
+    assert_eq!(
+        policy.file_claim(1, 1_000, COVERAGE).unwrap_err(),
+        ErrorCode::NotCovered.into()
+    );
+    assert_eq!(
+        policy.file_claim(0, 0, COVERAGE).unwrap_err(),
+        ErrorCode::InvalidAmount.into()
+    );
+    assert_eq!(
+        policy.file_claim(COVERAGE + 1, 0, COVERAGE).unwrap_err(),
+        ErrorCode::CoverageExceeded.into()
+    );
+    assert_eq!(policy.file_claim(300_000_000, 0, COVERAGE).unwrap(), 0);
+    assert_eq!(policy.file_claim(300_000_000, 0, COVERAGE).unwrap(), 1);
+    assert_eq!(policy.pending_claims, 2);
+
+    // Both fit when filed; the second can only be paid what the first left
+    policy.settle_claim(300_000_000, COVERAGE).unwrap();
+    assert_eq!(policy.remaining_coverage(COVERAGE), 200_000_000);
+    assert_eq!(
+        policy.settle_claim(300_000_000, COVERAGE).unwrap_err(),
+        ErrorCode::CoverageExceeded.into()
+    );
+    policy.settle_claim(200_000_000, COVERAGE).unwrap();
+    assert_eq!(policy.pending_claims, 0);
+    assert_eq!(
+        policy.file_claim(1, 0, COVERAGE).unwrap_err(),
+        ErrorCode::CoverageExceeded.into()
+    );
+}
+
+#[test]
+fn claims_are_decided_once() {
+    let mut pending = claim(50_000_000);
+    assert_eq!(
+        pending.decide(ClaimStatus::Paid, 0, 5).unwrap_err(),
+        ErrorCode::InvalidPayout.into()
+    );
+    assert_eq!(
+        pending
+            .decide(ClaimStatus::Paid, 50_000_001, 5)
+            .unwrap_err(),
+        ErrorCode::InvalidPayout.into()
+    );
+    pending.decide(ClaimStatus::Paid, 40_000_000, 5).unwrap();
+    assert_eq!(pending.status, ClaimStatus::Paid);
+    assert_eq!(pending.payout, 40_000_000);
+    assert_eq!(pending.decided_at, Some(5));
+    assert_eq!(
+        pending.decide(ClaimStatus::Rejected, 0, 6).unwrap_err(),
+        ErrorCode::ClaimAlreadyDecided.into()
+    );
+}
+
+#[test]
+fn the_reserve_and_pending_claims_stay_in_the_vault() {
+    let mut pool = pool();
+    assert_eq!(pool.surplus(RESERVE - 1), 0);
+    assert_eq!(pool.surplus(RESERVE + 30), 30);
+
+    pool.add_pending(20).unwrap();
+    assert_eq!(pool.surplus(RESERVE + 30), 10);
+    pool.settle_pending(20, 15).unwrap();
+    assert_eq!(pool.pending_claims, 0);
+    assert_eq!(pool.total_payouts, 15);
+    assert_eq!(pool.surplus(RESERVE + 30), 30);
+}
+
+struct Fixture {
+    svm: TestSvm,
+    payer: Keypair,
+    admin: Keypair,
+    holder: Keypair,
+    pool: Pubkey,
+    policy: Pubkey,
+    claim: Pubkey,
+    state: Pool,
+}
+
+impl Fixture {
+    /// A pool holding `vault_balance` and one policy covered until
+    /// `paid_until`, with a pending claim for `claimed`
+    fn new(vault_balance: u64, paid_until: i64, claimed: u64) -> Self {
+        let (mut svm, payer) = TestSvm::for_program(PROGRAM_ID, insurance_pool::entry);
+
+        let admin = Keypair::new();
+        let holder = Keypair::new();
+        svm.airdrop(&holder.pubkey(), 1_000_000_000);
+
+        let mint = svm.create_mint(&Pubkey::new_unique(), 6);
+        let (address, bump) =
+            Pubkey::find_program_address(&[b"pool", admin.pubkey().as_ref()], &PROGRAM_ID);
+        let vault = svm.create_associated_token_account(&address, &mint, vault_balance);
+        let holder_account = svm.create_associated_token_account(&holder.pubkey(), &mint, PREMIUM);
+        svm.approve(&holder_account, &address, u64::MAX);
+        svm.create_associated_token_account(&admin.pubkey(), &mint, 0);
+
+        let pending = u64::from(claimed > 0);
+        let state = Pool {
+            admin: admin.pubkey(),
+            mint,
+            vault,
+            pending_claims: pending,
+            pending_amount: claimed,
+            bump,
+            ..pool()
+        };
+        svm.set_anchor_account(address, &state, 8 + Pool::INIT_SPACE);
+
+        let (policy_address, policy_bump) = Pubkey::find_program_address(
+            &[b"policy", address.as_ref(), holder.pubkey().as_ref()],
+            &PROGRAM_ID,
+        );
+        svm.set_anchor_account(
+            policy_address,
+            &Policy {
+                pool: address,
+                holder: holder.pubkey(),
+                token_account: holder_account,
+                next_claim_id: pending,
+                pending_claims: pending,
+                bump: policy_bump,
+                ..policy(paid_until)
+            },
+            8 + Policy::INIT_SPACE,
+        );
+
+        let (claim_address, claim_bump) = Pubkey::find_program_address(
+            &[b"claim", policy_address.as_ref(), &0u64.to_le_bytes()],
+            &PROGRAM_ID,
+        );
+        if claimed > 0 {
+            svm.set_anchor_account(
+                claim_address,
+                &Claim {
+                    policy: policy_address,
+                    bump: claim_bump,
+                    ..claim(claimed)
+                },
+                8 + Claim::INIT_SPACE,
+            );
+        }
+
+        Self {
+            svm,
+            payer,
+            admin,
+            holder,
+            pool: address,
+            policy: policy_address,
+            claim: claim_address,
+            state,
+        }
+    }
+
+    fn now(&self) -> i64 {
+        self.svm.clock().unix_timestamp
+    }
+
+    fn holder_account(&self) -> Pubkey {
+        test_harness::associated_token_address(&self.holder.pubkey(), &self.state.mint)
+    }
+
+    fn admin_account(&self) -> Pubkey {
+        test_harness::associated_token_address(&self.admin.pubkey(), &self.state.mint)
+    }
+
+    fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts.to_account_metas(None),
+            data: data.data(),
+        }
+    }
+
+    fn collect(&self) -> Instruction {
+        Self::build(
+            accounts::CollectPremium {
+                pool: self.pool,
+                policy: self.policy,
+                holder_token_account: self.holder_account(),
+                vault: self.state.vault,
+                token_program: spl_token::ID,
+            },
+            instruction::CollectPremium {},
+        )
+    }
+
+    fn lapse(&self) -> Instruction {
+        Self::build(
+            accounts::LapsePolicy {
+                pool: self.pool,
+                policy: self.policy,
+            },
+            instruction::LapsePolicy {},
+        )
+    }
+
+    fn approve(&self, admin: Pubkey, payout: u64) -> Instruction {
+        Self::build(
+            accounts::DecideClaim {
+                pool: self.pool,
+                policy: self.policy,
+                claim: self.claim,
+                admin,
+                vault: self.state.vault,
+                holder_token_account: self.holder_account(),
+                token_program: spl_token::ID,
+            },
+            instruction::ApproveClaim { payout },
+        )
+    }
+
+    fn reject(&self, admin: Pubkey) -> Instruction {
+        Self::build(
+            accounts::RejectClaim {
+                pool: self.pool,
+                policy: self.policy,
+                claim: self.claim,
+                admin,
+            },
+            instruction::RejectClaim {},
+        )
+    }
+
+    fn withdraw(&self, amount: u64) -> Instruction {
+        Self::build(
+            accounts::WithdrawSurplus {
+                pool: self.pool,
+                admin: self.admin.pubkey(),
+                vault: self.state.vault,
+                admin_token_account: self.admin_account(),
+                token_program: spl_token::ID,
+            },
+            instruction::WithdrawSurplus { amount },
+        )
+    }
+
+    fn cancel(&self) -> Instruction {
+        Self::build(
+            accounts::CancelPolicy {
+                pool: self.pool,
+                policy: self.policy,
+                holder: self.holder.pubkey(),
+                holder_token_account: self.holder_account(),
+                token_program: spl_token::ID,
+            },
+            instruction::CancelPolicy {},
+        )
+    }
+
+    fn create_pool(&self) -> Instruction {
+        Self::build(
+            accounts::CreatePool {
+                pool: self.pool,
+                admin: self.admin.pubkey(),
+                mint: self.state.mint,
+                vault: self.state.vault,
+                payer: self.payer.pubkey(),
+                system_program: system_program::ID,
+            },
+            instruction::CreatePool {
+                premium_amount: PREMIUM,
+                interval_seconds: MONTH,
+                grace_seconds: GRACE,
+                coverage_limit: COVERAGE,
+                min_reserve: RESERVE,
+            },
+        )
+    }
+
+    fn enroll(&self) -> Instruction {
+        Self::build(
+            accounts::Enroll {
+                pool: self.pool,
+                policy: self.policy,
+                holder: self.holder.pubkey(),
+                holder_token_account: self.holder_account(),
+                payer: self.payer.pubkey(),
+                token_program: spl_token::ID,
+                system_program: system_program::ID,
+            },
+            instruction::Enroll {},
+        )
+    }
+
+    fn file_claim(&self, amount: u64) -> Instruction {
+        Self::build(
+            accounts::FileClaim {
+                pool: self.pool,
+                policy: self.policy,
+                claim: self.claim,
+                holder: self.holder.pubkey(),
+                payer: self.payer.pubkey(),
+                system_program: system_program::ID,
+            },
+            instruction::FileClaim {
+                amount,
+                evidence_hash: [7; 32],
+            },
+        )
+    }
+
+    fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TransactionResult {
+        let payer = self.payer.insecure_clone();
+        self.svm.send_instructions(&[instruction], &payer, signers)
+    }
+
+    fn send_as(&mut self, signer: &Keypair, instruction: Instruction) -> TransactionResult {
+        let signer = signer.insecure_clone();
+        self.send(instruction, &[&signer])
+    }
+
+    fn pool(&self) -> Pool {
+        self.svm.get_anchor_account(&self.pool).unwrap()
+    }
+
+    fn policy(&self) -> Policy {
+        self.svm.get_anchor_account(&self.policy).unwrap()
+    }
+
+    fn claim(&self) -> Claim {
+        self.svm.get_anchor_account(&self.claim).unwrap()
+    }
+}
+
+#[test]
+fn admins_create_pools_over_their_vault() {
+    let mut fx = Fixture::new(0, 0, 0);
+    fx.svm.remove_account(&fx.pool);
+    let admin = fx.admin.insecure_clone();
+
+    let result = fx.send_as(&admin, fx.create_pool());
+    assert_success(result);
+    let pool = fx.pool();
+    assert_eq!(pool.admin, admin.pubkey());
+    assert_eq!(pool.vault, fx.state.vault);
+    assert_eq!(pool.min_reserve, RESERVE);
+    assert_eq!(pool.active_policies, 0);
+    assert_eq!(pool.bump, fx.state.bump);
+}
+
+#[test]
+fn enrolling_delegates_and_is_due_at_once() {
+    let mut fx = Fixture::new(0, 0, 0);
+    fx.svm.remove_account(&fx.policy);
+    fx.svm.revoke(&fx.holder_account());
+    let holder = fx.holder.insecure_clone();
+
+    let result = fx.send_as(&holder, fx.enroll());
+    assert_success(result);
+    let account = fx.svm.get_token_account(&fx.holder_account()).unwrap();
+    assert_eq!(account.delegate, Some(fx.pool).into());
+    let policy = fx.policy();
+    assert!(policy.active);
+    assert_eq!(policy.paid_until, fx.now());
+    assert_eq!(fx.pool().active_policies, 2);
+
+    let result = fx.send(fx.collect(), &[]);
+    assert_success(result);
+    assert_eq!(fx.svm.token_balance(&fx.holder_account()), 0);
+    assert_eq!(fx.svm.token_balance(&fx.state.vault), PREMIUM);
+}
+
+#[test]
+fn covered_holders_file_claims() {
+    let now = Fixture::new(0, 0, 0).now();
+    let claimed = 50_000_000;
+    let mut fx = Fixture::new(RESERVE, now + MONTH, 0);
+    let holder = fx.holder.insecure_clone();
+
+    let result = fx.send_as(&holder, fx.file_claim(COVERAGE + 1));
+    assert_error(result, ErrorCode::CoverageExceeded);
+
+    let result = fx.send_as(&holder, fx.file_claim(claimed));
+    assert_success(result);
+    let claim = fx.claim();
+    assert_eq!(claim.status, ClaimStatus::Pending);
+    assert_eq!(claim.amount, claimed);
+    assert_eq!(claim.filed_at, now);
+    assert_eq!(fx.policy().next_claim_id, 1);
+    assert_eq!(fx.pool().pending_amount, claimed);
+}
+
+#[test]
+fn premiums_are_collected_once_cover_runs_out() {
+    let now = Fixture::new(0, 0, 0).now();
+    let mut fx = Fixture::new(0, now + 1, 0);
+    let result = fx.send(fx.collect(), &[]);
+    assert_error(result, ErrorCode::PremiumNotDue);
+
+    let mut fx = Fixture::new(0, now - GRACE - 1, 0);
+    let result = fx.send(fx.collect(), &[]);
+    assert_error(result, ErrorCode::PolicyLapsed);
+
+    let mut fx = Fixture::new(0, now, 0);
+    let result = fx.send(fx.collect(), &[]);
+    assert_success(result);
+    assert_eq!(fx.svm.token_balance(&fx.holder_account()), 0);
+    assert_eq!(fx.svm.token_balance(&fx.state.vault), PREMIUM);
+    assert_eq!(fx.policy().paid_until, now + MONTH);
+    assert_eq!(fx.pool().total_premiums, PREMIUM);
+}
+
+#[test]
+fn unpaid_policies_lapse() {
+    let now = Fixture::new(0, 0, 0).now();
+    let mut fx = Fixture::new(0, now - GRACE, 0);
+    let result = fx.send(fx.lapse(), &[]);
+    assert_error(result, ErrorCode::PremiumsCurrent);
+
+    fx.svm.warp_to_timestamp(now + 1);
+    let result = fx.send(fx.lapse(), &[]);
+    assert!(result.is_ok(), "{result:#?}");
+    assert!(!fx.policy().active);
+    assert_eq!(fx.pool().active_policies, 0);
+
+    fx.svm.expire_blockhash();
+    let result = fx.send(fx.lapse(), &[]);
+    assert_error(result, ErrorCode::PolicyLapsed);
+}
+
+#[test]
+fn the_admin_approves_payouts_the_pool_can_cover() {
+    let now = Fixture::new(0, 0, 0).now();
+    let claimed = 50_000_000;
+    let mut fx = Fixture::new(claimed - 1, now + MONTH, claimed);
+    let holder = fx.holder.insecure_clone();
+    let result = fx.send_as(&holder, fx.approve(holder.pubkey(), claimed));
+    assert!(result.is_err());
+
+    let admin = fx.admin.insecure_clone();
+    let result = fx.send_as(&admin, fx.approve(admin.pubkey(), claimed + 1));
+    assert_error(result, ErrorCode::InvalidPayout);
+
+    let result = fx.send_as(&admin, fx.approve(admin.pubkey(), claimed));
+    assert_error(result, ErrorCode::InsufficientPool);
+
+    let result = fx.send_as(&admin, fx.approve(admin.pubkey(), claimed - 1));
+    assert_success(result);
+    assert_eq!(fx.svm.token_balance(&fx.state.vault), 0);
+    assert_eq!(
+        fx.svm.token_balance(&fx.holder_account()),
+        PREMIUM + claimed - 1
+    );
+    assert_eq!(fx.claim().status, ClaimStatus::Paid);
+    assert_eq!(fx.pool().pending_amount, 0);
+}
+
+#[test]
+fn rejected_claims_release_the_reserve() {
+    let now = Fixture::new(0, 0, 0).now();
+    let mut fx = Fixture::new(RESERVE, now + MONTH, 50_000_000);
+    let holder = fx.holder.insecure_clone();
+    let result = fx.send_as(&holder, fx.reject(holder.pubkey()));
+    assert!(result.is_err());
+
+    let admin = fx.admin.insecure_clone();
+    let result = fx.send_as(&admin, fx.reject(admin.pubkey()));
+    assert!(result.is_ok(), "{result:#?}");
+    assert_eq!(fx.claim().status, ClaimStatus::Rejected);
+    assert_eq!(fx.claim().decided_at, Some(now));
+    assert_eq!(fx.policy().pending_claims, 0);
+    assert_eq!(fx.pool().pending_claims, 0);
+    assert_eq!(fx.pool().pending_amount, 0);
+
+    fx.svm.expire_blockhash();
+    let result = fx.send_as(&admin, fx.reject(admin.pubkey()));
+    assert_error(result, ErrorCode::ClaimAlreadyDecided);
+}
+
+#[test]
+fn surplus_withdrawals_keep_the_reserve() {
+    let claimed = 50_000_000;
+    let mut fx = Fixture::new(RESERVE + claimed + 10, 0, claimed);
+    let admin = fx.admin.insecure_clone();
+    let result = fx.send_as(&admin, fx.withdraw(0));
+    assert_error(result, ErrorCode::InvalidAmount);
+
+    let result = fx.send_as(&admin, fx.withdraw(11));
+    assert_error(result, ErrorCode::ReserveRequired);
+
+    let result = fx.send_as(&admin, fx.withdraw(10));
+    assert_success(result);
+    assert_eq!(fx.svm.token_balance(&fx.admin_account()), 10);
+    assert_eq!(fx.svm.token_balance(&fx.state.vault), RESERVE + claimed);
+    assert_eq!(fx.pool().total_withdrawn, 10);
+}
+
+#[test]
+fn policies_with_pending_claims_cannot_cancel() {
+    let mut fx = Fixture::new(RESERVE, 0, 50_000_000);
+    let holder = fx.holder.insecure_clone();
+    let result = fx.send_as(&holder, fx.cancel());
+    assert_error(result, ErrorCode::ClaimsPending);
+
+    let mut fx = Fixture::new(RESERVE, 0, 0);
+    let holder = fx.holder.insecure_clone();
+    let result = fx.send_as(&holder, fx.cancel());
+    assert_success(result);
+    assert!(fx.svm.get_account(&fx.policy).is_none());
+    let account = fx.svm.get_token_account(&fx.holder_account()).unwrap();
+    assert!(account.delegate.is_none());
+    assert_eq!(fx.pool().active_policies, 0);
+}
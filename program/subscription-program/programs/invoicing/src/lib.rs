@@ -0,0 +1,364 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::invoke;
+use spl_token::instruction as token_instruction;
+use token_utils::token_account;
+
+declare_id!("WJ1U3KJe47Y8equVV72ehjjCqkkzZ2LzV28w7s1w4Nk");
+
+#[program]
+pub mod invoicing {
+    use super::*;
+
+    /// Bill `payer` for `amount`, due at `due_at`. `reference` is the
+    /// merchant's own invoice number or a hash of the invoice document.
+    pub fn issue_invoice(
+        ctx: Context<IssueInvoice>,
+        invoice_id: u64,
+        payer: Pubkey,
+        amount: u64,
+        due_at: i64,
+        reference: [u8; 32],
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        Invoice::check_params(amount, due_at, clock.unix_timestamp)?;
+
+        let merchant_account = token_account(
+            &ctx.accounts.merchant_token_account,
+            ErrorCode::InvalidTokenAccount,
+        )?;
+        require_keys_eq!(
+            merchant_account.owner,
+            ctx.accounts.merchant.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+        require_keys_eq!(
+            merchant_account.mint,
+            ctx.accounts.mint.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+
+        let invoice = &mut ctx.accounts.invoice;
+        invoice.merchant = ctx.accounts.merchant.key();
+        invoice.payer = payer;
+        invoice.mint = ctx.accounts.mint.key();
+        invoice.merchant_token_account = ctx.accounts.merchant_token_account.key();
+        invoice.invoice_id = invoice_id;
+        invoice.amount = amount;
+        invoice.reference = reference;
+        invoice.status = InvoiceStatus::Open;
+        invoice.due_at = due_at;
+        invoice.issued_at = clock.unix_timestamp;
+        invoice.paid_at = None;
+        invoice.bump = ctx.bumps.invoice;
+
+        emit!(InvoiceIssued {
+            invoice: invoice.key(),
+            merchant: invoice.merchant,
+            payer,
+            amount,
+            due_at,
+            reference,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("Invoice {} issued: {} tokens", invoice_id, amount);
+        msg!("Due at {}", due_at);
+
+        Ok(())
+    }
+
+    /// Settle the invoice in full. Overdue invoices can still be paid.
+    pub fn pay_invoice(ctx: Context<PayInvoice>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let invoice = &mut ctx.accounts.invoice;
+        let late = invoice.status == InvoiceStatus::Overdue;
+        let amount = invoice.amount;
+        invoice.record_payment(now)?;
+
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.payer_token_account.key(),
+            &ctx.accounts.merchant_token_account.key(),
+            &ctx.accounts.payer.key(),
+            &[],
+            amount,
+        )?;
+
+        invoke(
+            &transfer_ix,
+            &[
+                ctx.accounts.payer_token_account.to_account_info(),
+                ctx.accounts.merchant_token_account.to_account_info(),
+                ctx.accounts.payer.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        emit!(InvoicePaid {
+            invoice: ctx.accounts.invoice.key(),
+            payer: ctx.accounts.payer.key(),
+            amount,
+            late,
+            timestamp: now,
+        });
+
+        msg!("Invoice paid: {} tokens", amount);
+        if late {
+            msg!("Paid after the due date");
+        }
+
+        Ok(())
+    }
+
+    /// Flip an unpaid invoice past its due date to overdue. Permissionless,
+    /// so a keeper can run it for every merchant.
+    pub fn mark_overdue(ctx: Context<MarkOverdue>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let invoice = &mut ctx.accounts.invoice;
+        invoice.mark_overdue(now)?;
+
+        emit!(InvoiceOverdue {
+            invoice: invoice.key(),
+            payer: invoice.payer,
+            amount: invoice.amount,
+            due_at: invoice.due_at,
+            timestamp: now,
+        });
+
+        msg!("Invoice {} is overdue", invoice.invoice_id);
+
+        Ok(())
+    }
+
+    /// Withdraw an unpaid invoice
+    pub fn cancel_invoice(ctx: Context<CancelInvoice>) -> Result<()> {
+        let invoice = &mut ctx.accounts.invoice;
+        invoice.cancel()?;
+
+        emit!(InvoiceCancelled {
+            invoice: invoice.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Invoice {} cancelled", invoice.invoice_id);
+
+        Ok(())
+    }
+
+    /// Reclaim rent from a paid or cancelled invoice
+    pub fn close_invoice(ctx: Context<CloseInvoice>) -> Result<()> {
+        let invoice = &ctx.accounts.invoice;
+        require!(
+            matches!(
+                invoice.status,
+                InvoiceStatus::Paid | InvoiceStatus::Cancelled
+            ),
+            ErrorCode::InvalidStatus
+        );
+
+        msg!("Invoice {} closed", invoice.invoice_id);
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(invoice_id: u64)]
+pub struct IssueInvoice<'info> {
+    #[account(
+        init,
+        payer = rent_payer,
+        space = 8 + Invoice::INIT_SPACE,
+        seeds = [b"invoice", merchant.key().as_ref(), invoice_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    pub merchant: Signer<'info>,
+
+    /// CHECK: Token mint (USDC)
+    pub mint: UncheckedAccount<'info>,
+
+    /// CHECK: Merchant's token account, checked in the handler; payments land here
+    pub merchant_token_account: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub rent_payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PayInvoice<'info> {
+    #[account(
+        mut,
+        seeds = [b"invoice", invoice.merchant.as_ref(), invoice.invoice_id.to_le_bytes().as_ref()],
+        bump = invoice.bump,
+        has_one = payer,
+        has_one = merchant_token_account
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    pub payer: Signer<'info>,
+
+    /// CHECK: Any token account the payer owns, debited by the token program
+    #[account(mut)]
+    pub payer_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Merchant's token account
+    #[account(mut)]
+    pub merchant_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct MarkOverdue<'info> {
+    #[account(
+        mut,
+        seeds = [b"invoice", invoice.merchant.as_ref(), invoice.invoice_id.to_le_bytes().as_ref()],
+        bump = invoice.bump,
+    )]
+    pub invoice: Account<'info, Invoice>,
+}
+
+#[derive(Accounts)]
+pub struct CancelInvoice<'info> {
+    #[account(
+        mut,
+        seeds = [b"invoice", merchant.key().as_ref(), invoice.invoice_id.to_le_bytes().as_ref()],
+        bump = invoice.bump,
+        has_one = merchant
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseInvoice<'info> {
+    #[account(
+        mut,
+        seeds = [b"invoice", merchant.key().as_ref(), invoice.invoice_id.to_le_bytes().as_ref()],
+        bump = invoice.bump,
+        has_one = merchant,
+        close = merchant
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    #[account(mut)]
+    pub merchant: Signer<'info>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub enum InvoiceStatus {
+    Open,
+    Overdue,
+    Paid,
+    Cancelled,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Invoice {
+    pub merchant: Pubkey,
+    pub payer: Pubkey,
+    pub mint: Pubkey,
+    pub merchant_token_account: Pubkey,
+    pub invoice_id: u64,
+    pub amount: u64,
+    pub reference: [u8; 32],
+    pub status: InvoiceStatus,
+    pub due_at: i64,
+    pub issued_at: i64,
+    pub paid_at: Option<i64>,
+    pub bump: u8,
+}
+
+impl Invoice {
+    pub fn check_params(amount: u64, due_at: i64, now: i64) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        require!(due_at > now, ErrorCode::InvalidDueDate);
+        Ok(())
+    }
+
+    /// Whether `payer` still owes this invoice
+    pub fn is_unpaid(&self) -> bool {
+        matches!(self.status, InvoiceStatus::Open | InvoiceStatus::Overdue)
+    }
+
+    pub fn record_payment(&mut self, now: i64) -> Result<()> {
+        require!(self.is_unpaid(), ErrorCode::InvalidStatus);
+        self.status = InvoiceStatus::Paid;
+        self.paid_at = Some(now);
+        Ok(())
+    }
+
+    pub fn mark_overdue(&mut self, now: i64) -> Result<()> {
+        require!(self.status == InvoiceStatus::Open, ErrorCode::InvalidStatus);
+        require!(now > self.due_at, ErrorCode::NotYetDue);
+        self.status = InvoiceStatus::Overdue;
+        Ok(())
+    }
+
+    pub fn cancel(&mut self) -> Result<()> {
+        require!(self.is_unpaid(), ErrorCode::InvalidStatus);
+        self.status = InvoiceStatus::Cancelled;
+        Ok(())
+    }
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvoiceIssued {
+    pub invoice: Pubkey,
+    pub merchant: Pubkey,
+    pub payer: Pubkey,
+    pub amount: u64,
+    pub due_at: i64,
+    pub reference: [u8; 32],
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvoicePaid {
+    pub invoice: Pubkey,
+    pub payer: Pubkey,
+    pub amount: u64,
+    pub late: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvoiceOverdue {
+    pub invoice: Pubkey,
+    pub payer: Pubkey,
+    pub amount: u64,
+    pub due_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvoiceCancelled {
+    pub invoice: Pubkey,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Amount must be greater than zero")]
+    InvalidAmount,
+    #[msg("Due date must be in the future")]
+    InvalidDueDate,
+    #[msg("Invalid token account - wrong program, owner or mint")]
+    InvalidTokenAccount,
+    #[msg("Invoice is not in a status that allows this")]
+    InvalidStatus,
+    #[msg("Invoice is not past its due date")]
+    NotYetDue,
+}
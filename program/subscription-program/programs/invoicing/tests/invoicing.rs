@@ -0,0 +1,358 @@
+//! Native tests for the invoicing program.
+//!
+//! The status transitions are pure methods on `Invoice` and are tested
+//! directly. Every instruction also runs end to end.
+
+use anchor_lang::error::ErrorCode as AnchorErrorCode;
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, Space, ToAccountMetas};
+use invoicing::{accounts, instruction, ErrorCode, Invoice, InvoiceStatus, ID as PROGRAM_ID};
+use test_harness::{assert_error, assert_success, Keypair, Signer, TestSvm, TransactionResult};
+
+const AMOUNT: u64 = 1_250_000_000;
+const INVOICE_ID: u64 = 1042;
+const DAY: i64 = 86_400;
+
+fn invoice_at(now: i64) -> Invoice {
+    Invoice {
+        merchant: Pubkey::new_unique(),
+        payer: Pubkey::new_unique(),
+        mint: Pubkey::new_unique(),
+        merchant_token_account: Pubkey::new_unique(),
+        invoice_id: INVOICE_ID,
+        amount: AMOUNT,
+        reference: [4; 32],
+        status: InvoiceStatus::Open,
+        due_at: now + 30 * DAY,
+        issued_at: now,
+        paid_at: None,
+        bump: 255,
+    }
+}
+
+#[test]
+fn rejects_empty_amount_and_past_due_date() {
+    assert_eq!(
+        Invoice::check_params(0, DAY, 0).unwrap_err(),
+        ErrorCode::InvalidAmount.into()
+    );
+    assert_eq!(
+        Invoice::check_params(AMOUNT, 0, 0).unwrap_err(),
+        ErrorCode::InvalidDueDate.into()
+    );
+    Invoice::check_params(AMOUNT, DAY, 0).unwrap();
+}
+
+#[test]
+fn invoices_go_overdue_only_after_the_due_date() {
+    let mut invoice = invoice_at(0);
+    assert_eq!(
+        invoice.mark_overdue(invoice.due_at).unwrap_err(),
+        ErrorCode::NotYetDue.into()
+    );
+
+    invoice.mark_overdue(invoice.due_at + 1).unwrap();
+    assert_eq!(invoice.status, InvoiceStatus::Overdue);
+    assert_eq!(
+        invoice.mark_overdue(invoice.due_at + 1).unwrap_err(),
+        ErrorCode::InvalidStatus.into()
+    );
+
+    // Overdue invoices can still be paid, but only once
+    invoice.record_payment(invoice.due_at + DAY).unwrap();
+    assert_eq!(invoice.status, InvoiceStatus::Paid);
+    assert_eq!(invoice.paid_at, Some(invoice.due_at + DAY));
+    assert_eq!(
+        invoice.record_payment(invoice.due_at + DAY).unwrap_err(),
+        ErrorCode::InvalidStatus.into()
+    );
+    assert_eq!(
+        invoice.mark_overdue(invoice.due_at + DAY).unwrap_err(),
+        ErrorCode::InvalidStatus.into()
+    );
+    assert_eq!(
+        invoice.cancel().unwrap_err(),
+        ErrorCode::InvalidStatus.into()
+    );
+}
+
+#[test]
+fn cancelled_invoices_cannot_be_paid() {
+    let mut invoice = invoice_at(0);
+    invoice.cancel().unwrap();
+    assert!(!invoice.is_unpaid());
+    assert_eq!(
+        invoice.record_payment(DAY).unwrap_err(),
+        ErrorCode::InvalidStatus.into()
+    );
+}
+
+struct Fixture {
+    svm: TestSvm,
+    payer: Keypair,
+    merchant: Keypair,
+    customer: Keypair,
+    invoice: Pubkey,
+    state: Invoice,
+    customer_token_account: Pubkey,
+}
+
+impl Fixture {
+    /// An open invoice from the merchant to the customer, due in 30 days
+    fn new() -> Self {
+        let (mut svm, payer) = TestSvm::for_program(PROGRAM_ID, invoicing::entry);
+
+        let merchant = Keypair::new();
+        let customer = Keypair::new();
+        svm.airdrop(&merchant.pubkey(), 1_000_000_000);
+
+        let mint = svm.create_mint(&Pubkey::new_unique(), 6);
+        let merchant_token_account =
+            svm.create_associated_token_account(&merchant.pubkey(), &mint, 0);
+        let customer_token_account =
+            svm.create_associated_token_account(&customer.pubkey(), &mint, 2 * AMOUNT);
+
+        let (address, bump) = Pubkey::find_program_address(
+            &[
+                b"invoice",
+                merchant.pubkey().as_ref(),
+                INVOICE_ID.to_le_bytes().as_ref(),
+            ],
+            &PROGRAM_ID,
+        );
+        let now = svm.clock().unix_timestamp;
+        let state = Invoice {
+            merchant: merchant.pubkey(),
+            payer: customer.pubkey(),
+            mint,
+            merchant_token_account,
+            bump,
+            ..invoice_at(now)
+        };
+        svm.set_anchor_account(address, &state, 8 + Invoice::INIT_SPACE);
+
+        Self {
+            svm,
+            payer,
+            merchant,
+            customer,
+            invoice: address,
+            state,
+            customer_token_account,
+        }
+    }
+
+    fn issue(&self, merchant_token_account: Pubkey) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::IssueInvoice {
+                invoice: self.invoice,
+                merchant: self.merchant.pubkey(),
+                mint: self.state.mint,
+                merchant_token_account,
+                rent_payer: self.payer.pubkey(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::IssueInvoice {
+                invoice_id: INVOICE_ID,
+                payer: self.customer.pubkey(),
+                amount: AMOUNT,
+                due_at: self.state.due_at,
+                reference: self.state.reference,
+            }
+            .data(),
+        }
+    }
+
+    fn pay(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::PayInvoice {
+                invoice: self.invoice,
+                payer: self.customer.pubkey(),
+                payer_token_account: self.customer_token_account,
+                merchant_token_account: self.state.merchant_token_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::PayInvoice {}.data(),
+        }
+    }
+
+    fn mark_overdue(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::MarkOverdue {
+                invoice: self.invoice,
+            }
+            .to_account_metas(None),
+            data: instruction::MarkOverdue {}.data(),
+        }
+    }
+
+    fn cancel(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CancelInvoice {
+                invoice: self.invoice,
+                merchant: self.merchant.pubkey(),
+            }
+            .to_account_metas(None),
+            data: instruction::CancelInvoice {}.data(),
+        }
+    }
+
+    fn close(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CloseInvoice {
+                invoice: self.invoice,
+                merchant: self.merchant.pubkey(),
+            }
+            .to_account_metas(None),
+            data: instruction::CloseInvoice {}.data(),
+        }
+    }
+
+    fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TransactionResult {
+        let payer = self.payer.insecure_clone();
+        self.svm.send_instructions(&[instruction], &payer, signers)
+    }
+
+    fn send_as_customer(&mut self, instruction: Instruction) -> TransactionResult {
+        let customer = self.customer.insecure_clone();
+        self.send(instruction, &[&customer])
+    }
+
+    fn send_as_merchant(&mut self, instruction: Instruction) -> TransactionResult {
+        let merchant = self.merchant.insecure_clone();
+        self.send(instruction, &[&merchant])
+    }
+
+    fn set_status(&mut self, status: InvoiceStatus) {
+        let state = Invoice {
+            status,
+            ..self.state
+        };
+        self.svm
+            .set_anchor_account(self.invoice, &state, 8 + Invoice::INIT_SPACE);
+    }
+
+    fn stored(&self) -> Invoice {
+        self.svm.get_anchor_account(&self.invoice).unwrap()
+    }
+}
+
+#[test]
+fn merchants_issue_invoices_payable_to_their_account() {
+    let mut fx = Fixture::new();
+    fx.svm.remove_account(&fx.invoice);
+    let result = fx.send_as_merchant(fx.issue(fx.customer_token_account));
+    assert_error(result, ErrorCode::InvalidTokenAccount);
+
+    let result = fx.send_as_merchant(fx.issue(fx.state.merchant_token_account));
+    assert_success(result);
+    let issued = fx.stored();
+    assert_eq!(issued.payer, fx.customer.pubkey());
+    assert_eq!(
+        issued.merchant_token_account,
+        fx.state.merchant_token_account
+    );
+    assert_eq!(issued.amount, AMOUNT);
+    assert_eq!(issued.status, InvoiceStatus::Open);
+    assert_eq!(issued.due_at, fx.state.due_at);
+    assert_eq!(issued.reference, fx.state.reference);
+}
+
+#[test]
+fn only_the_billed_payer_settles() {
+    let mut fx = Fixture::new();
+
+    let stranger = Keypair::new();
+    let mut forged = fx.pay();
+    forged.accounts[1].pubkey = stranger.pubkey();
+    let result = fx.send(forged, &[&stranger]);
+    assert_error(result, AnchorErrorCode::ConstraintHasOne);
+
+    let mut redirected = fx.pay();
+    redirected.accounts[3].pubkey = fx.customer_token_account;
+    let result = fx.send_as_customer(redirected);
+    assert_error(result, AnchorErrorCode::ConstraintHasOne);
+
+    let result = fx.send_as_customer(fx.pay());
+    assert_success(result);
+    assert_eq!(
+        fx.svm.token_balance(&fx.state.merchant_token_account),
+        AMOUNT
+    );
+    assert_eq!(fx.svm.token_balance(&fx.customer_token_account), AMOUNT);
+    let paid = fx.stored();
+    assert_eq!(paid.status, InvoiceStatus::Paid);
+    assert_eq!(paid.paid_at, Some(fx.svm.clock().unix_timestamp));
+
+    fx.svm.expire_blockhash();
+    let result = fx.send_as_customer(fx.pay());
+    assert_error(result, ErrorCode::InvalidStatus);
+}
+
+#[test]
+fn overdue_invoices_stay_payable() {
+    let mut fx = Fixture::new();
+    fx.set_status(InvoiceStatus::Overdue);
+    let result = fx.send_as_customer(fx.pay());
+    assert_success(result);
+    assert_eq!(
+        fx.svm.token_balance(&fx.state.merchant_token_account),
+        AMOUNT
+    );
+
+    fx.set_status(InvoiceStatus::Cancelled);
+    fx.svm.expire_blockhash();
+    let result = fx.send_as_customer(fx.pay());
+    assert_error(result, ErrorCode::InvalidStatus);
+}
+
+#[test]
+fn anyone_marks_an_invoice_overdue() {
+    let mut fx = Fixture::new();
+    let result = fx.send(fx.mark_overdue(), &[]);
+    assert_error(result, ErrorCode::NotYetDue);
+
+    fx.svm.warp_to_timestamp(fx.state.due_at + 1);
+    let result = fx.send(fx.mark_overdue(), &[]);
+    assert!(result.is_ok(), "{result:#?}");
+    assert_eq!(fx.stored().status, InvoiceStatus::Overdue);
+
+    fx.svm.expire_blockhash();
+    let result = fx.send(fx.mark_overdue(), &[]);
+    assert_error(result, ErrorCode::InvalidStatus);
+}
+
+#[test]
+fn merchant_cancels_then_closes() {
+    let mut fx = Fixture::new();
+
+    let impostor = Keypair::new();
+    let mut forged = fx.cancel();
+    forged.accounts[1].pubkey = impostor.pubkey();
+    let result = fx.send(forged, &[&impostor]);
+    assert_error(result, AnchorErrorCode::ConstraintSeeds);
+
+    // Open invoices cannot be closed
+    let result = fx.send_as_merchant(fx.close());
+    assert_error(result, ErrorCode::InvalidStatus);
+
+    let result = fx.send_as_merchant(fx.cancel());
+    assert!(result.is_ok(), "{result:#?}");
+    assert_eq!(fx.stored().status, InvoiceStatus::Cancelled);
+
+    let rent = fx.svm.get_balance(&fx.invoice);
+    let before = fx.svm.get_balance(&fx.merchant.pubkey());
+    fx.svm.expire_blockhash();
+    let result = fx.send_as_merchant(fx.close());
+    assert!(result.is_ok(), "{result:#?}");
+    assert!(fx.svm.get_account(&fx.invoice).is_none());
+    assert_eq!(fx.svm.get_balance(&fx.merchant.pubkey()), before + rent);
+}
@@ -0,0 +1,882 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use spl_token::instruction as token_instruction;
+use token_utils::token_account;
+
+declare_id!("5HMfbkR2VEMBs38vYvu78z15nkr6sZc2Hd9QfM7oVRy2");
+
+pub const MAX_URI_LEN: usize = 200;
+
+#[program]
+pub mod listing_fees {
+    use super::*;
+
+    /// Open a marketplace charging `fee_per_period` per active listing every
+    /// `interval_seconds`. A listing whose fee is more than `grace_seconds`
+    /// late is delisted by the keeper.
+    pub fn create_marketplace(
+        ctx: Context<CreateMarketplace>,
+        fee_per_period: u64,
+        interval_seconds: i64,
+        grace_seconds: i64,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        Marketplace::check_params(fee_per_period, interval_seconds, grace_seconds)?;
+
+        let treasury = token_account(
+            &ctx.accounts.treasury_token_account,
+            ErrorCode::InvalidTokenAccount,
+        )?;
+        require_keys_eq!(
+            treasury.owner,
+            ctx.accounts.authority.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+        require_keys_eq!(
+            treasury.mint,
+            ctx.accounts.mint.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+
+        let marketplace = &mut ctx.accounts.marketplace;
+        marketplace.authority = ctx.accounts.authority.key();
+        marketplace.mint = ctx.accounts.mint.key();
+        marketplace.treasury_token_account = ctx.accounts.treasury_token_account.key();
+        marketplace.fee_per_period = fee_per_period;
+        marketplace.interval_seconds = interval_seconds;
+        marketplace.grace_seconds = grace_seconds;
+        marketplace.active_listings = 0;
+        marketplace.total_fees = 0;
+        marketplace.created_at = clock.unix_timestamp;
+        marketplace.bump = ctx.bumps.marketplace;
+
+        emit!(MarketplaceCreated {
+            marketplace: marketplace.key(),
+            authority: marketplace.authority,
+            fee_per_period,
+            interval_seconds,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("Marketplace created: {} tokens per listing", fee_per_period);
+        msg!("Period: {} seconds", interval_seconds);
+
+        Ok(())
+    }
+
+    /// Change the fee schedule. Periods already paid for are kept; the new
+    /// fee and interval apply from each listing's next renewal.
+    pub fn update_marketplace(
+        ctx: Context<UpdateMarketplace>,
+        fee_per_period: u64,
+        interval_seconds: i64,
+        grace_seconds: i64,
+    ) -> Result<()> {
+        Marketplace::check_params(fee_per_period, interval_seconds, grace_seconds)?;
+
+        let marketplace = &mut ctx.accounts.marketplace;
+        marketplace.fee_per_period = fee_per_period;
+        marketplace.interval_seconds = interval_seconds;
+        marketplace.grace_seconds = grace_seconds;
+
+        msg!("Marketplace updated: {} tokens per listing", fee_per_period);
+
+        Ok(())
+    }
+
+    /// Register as a seller. As with a subscription, the seller account PDA
+    /// becomes the delegate of `fee_token_account`, so every listing's fee
+    /// can be pulled from one token account.
+    pub fn register_seller(ctx: Context<RegisterSeller>) -> Result<()> {
+        let fee_account = token_account(
+            &ctx.accounts.fee_token_account,
+            ErrorCode::InvalidTokenAccount,
+        )?;
+        require_keys_eq!(
+            fee_account.owner,
+            ctx.accounts.seller.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+        require_keys_eq!(
+            fee_account.mint,
+            ctx.accounts.marketplace.mint,
+            ErrorCode::InvalidTokenAccount
+        );
+
+        let delegate_ix = token_instruction::approve(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.fee_token_account.key(),
+            &ctx.accounts.seller_account.key(),
+            &ctx.accounts.seller.key(),
+            &[],
+            u64::MAX,
+        )?;
+
+        invoke(
+            &delegate_ix,
+            &[
+                ctx.accounts.fee_token_account.to_account_info(),
+                ctx.accounts.seller_account.to_account_info(),
+                ctx.accounts.seller.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        let seller_account = &mut ctx.accounts.seller_account;
+        seller_account.marketplace = ctx.accounts.marketplace.key();
+        seller_account.seller = ctx.accounts.seller.key();
+        seller_account.fee_token_account = ctx.accounts.fee_token_account.key();
+        seller_account.next_listing_id = 0;
+        seller_account.active_listings = 0;
+        seller_account.bump = ctx.bumps.seller_account;
+
+        msg!("Seller registered: {}", seller_account.seller);
+        msg!("Token delegation approved");
+
+        Ok(())
+    }
+
+    /// List an item at `price`, described off-chain at `uri`. The first
+    /// period's fee is charged immediately.
+    pub fn create_listing(ctx: Context<CreateListing>, price: u64, uri: String) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        require!(price > 0, ErrorCode::InvalidAmount);
+        require!(uri.len() <= MAX_URI_LEN, ErrorCode::UriTooLong);
+
+        pull_fee(
+            &ctx.accounts.marketplace,
+            &ctx.accounts.seller_account,
+            &ctx.accounts.fee_token_account,
+            &ctx.accounts.treasury_token_account,
+            &ctx.accounts.token_program,
+        )?;
+
+        let listing_id = ctx.accounts.seller_account.next_listing_id;
+        let listing = &mut ctx.accounts.listing;
+        listing.marketplace = ctx.accounts.marketplace.key();
+        listing.seller = ctx.accounts.seller.key();
+        listing.listing_id = listing_id;
+        listing.price = price;
+        listing.uri = uri;
+        listing.fees_paid = 0;
+        listing.created_at = now;
+        listing.bump = ctx.bumps.listing;
+
+        ctx.accounts.seller_account.next_listing_id = listing_id
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        activate(
+            &mut ctx.accounts.marketplace,
+            &mut ctx.accounts.seller_account,
+            &mut ctx.accounts.listing,
+            now,
+        )?;
+
+        emit!(ListingCreated {
+            listing: ctx.accounts.listing.key(),
+            seller: ctx.accounts.seller.key(),
+            listing_id,
+            price,
+            paid_until: ctx.accounts.listing.paid_until,
+            timestamp: now,
+        });
+
+        msg!("Listing {} created at {}", listing_id, price);
+        msg!("Paid until {}", ctx.accounts.listing.paid_until);
+
+        Ok(())
+    }
+
+    /// Charge the next period's fee for an active listing. Permissionless,
+    /// like `charge_subscription`, and only possible within the grace period.
+    pub fn renew_listing(ctx: Context<RenewListing>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let marketplace = &ctx.accounts.marketplace;
+        ctx.accounts
+            .listing
+            .check_renewal_due(now, marketplace.grace_seconds)?;
+
+        pull_fee(
+            &ctx.accounts.marketplace,
+            &ctx.accounts.seller_account,
+            &ctx.accounts.fee_token_account,
+            &ctx.accounts.treasury_token_account,
+            &ctx.accounts.token_program,
+        )?;
+
+        let marketplace = &mut ctx.accounts.marketplace;
+        let listing = &mut ctx.accounts.listing;
+        listing.record_fee(marketplace.fee_per_period, marketplace.interval_seconds)?;
+        marketplace.total_fees = marketplace
+            .total_fees
+            .checked_add(marketplace.fee_per_period)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        emit!(ListingRenewed {
+            listing: listing.key(),
+            seller: listing.seller,
+            fee: marketplace.fee_per_period,
+            paid_until: listing.paid_until,
+            timestamp: now,
+        });
+
+        msg!("Listing {} renewed", listing.listing_id);
+        msg!("Paid until {}", listing.paid_until);
+
+        Ok(())
+    }
+
+    /// Delist a listing whose fee is past the grace period. Permissionless;
+    /// the listing stays on-chain with `active` cleared so the seller can
+    /// relist it.
+    pub fn delist_lapsed(ctx: Context<DelistLapsed>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let marketplace = &mut ctx.accounts.marketplace;
+        let seller_account = &mut ctx.accounts.seller_account;
+        let listing = &mut ctx.accounts.listing;
+        listing.check_lapsed(now, marketplace.grace_seconds)?;
+        deactivate(marketplace, seller_account, listing)?;
+
+        emit!(ListingDelisted {
+            listing: listing.key(),
+            seller: listing.seller,
+            lapsed: true,
+            timestamp: now,
+        });
+
+        msg!("Listing {} delisted: fees lapsed", listing.listing_id);
+
+        Ok(())
+    }
+
+    /// Put a delisted listing back up, at a new `price`, charging a fresh
+    /// first period
+    pub fn relist(ctx: Context<Relist>, price: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        require!(price > 0, ErrorCode::InvalidAmount);
+        require!(!ctx.accounts.listing.active, ErrorCode::ListingActive);
+
+        pull_fee(
+            &ctx.accounts.marketplace,
+            &ctx.accounts.seller_account,
+            &ctx.accounts.fee_token_account,
+            &ctx.accounts.treasury_token_account,
+            &ctx.accounts.token_program,
+        )?;
+
+        ctx.accounts.listing.price = price;
+        activate(
+            &mut ctx.accounts.marketplace,
+            &mut ctx.accounts.seller_account,
+            &mut ctx.accounts.listing,
+            now,
+        )?;
+
+        let listing = &ctx.accounts.listing;
+        emit!(ListingCreated {
+            listing: listing.key(),
+            seller: listing.seller,
+            listing_id: listing.listing_id,
+            price,
+            paid_until: listing.paid_until,
+            timestamp: now,
+        });
+
+        msg!("Listing {} relisted at {}", listing.listing_id, price);
+        msg!("Paid until {}", listing.paid_until);
+
+        Ok(())
+    }
+
+    /// Take a listing down and close it. Fees already paid are not refunded.
+    pub fn close_listing(ctx: Context<CloseListing>) -> Result<()> {
+        let marketplace = &mut ctx.accounts.marketplace;
+        let seller_account = &mut ctx.accounts.seller_account;
+        let listing = &mut ctx.accounts.listing;
+        if listing.active {
+            deactivate(marketplace, seller_account, listing)?;
+            emit!(ListingDelisted {
+                listing: listing.key(),
+                seller: listing.seller,
+                lapsed: false,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+        }
+
+        msg!("Listing {} closed", listing.listing_id);
+
+        Ok(())
+    }
+
+    /// Stop selling: revoke the fee delegation and close the seller account.
+    /// Every listing must be closed or delisted first.
+    pub fn close_seller(ctx: Context<CloseSeller>) -> Result<()> {
+        require!(
+            ctx.accounts.seller_account.active_listings == 0,
+            ErrorCode::ListingActive
+        );
+
+        let revoke_ix = token_instruction::revoke(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.fee_token_account.key(),
+            &ctx.accounts.seller.key(),
+            &[],
+        )?;
+
+        invoke(
+            &revoke_ix,
+            &[
+                ctx.accounts.fee_token_account.to_account_info(),
+                ctx.accounts.seller.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        msg!("Seller account closed");
+        msg!("Token delegation revoked");
+
+        Ok(())
+    }
+}
+
+/// Move one period's fee from the seller to the treasury, signed by the
+/// seller account PDA as the fee account's delegate
+fn pull_fee<'info>(
+    marketplace: &Account<'info, Marketplace>,
+    seller_account: &Account<'info, SellerAccount>,
+    fee_token_account: &UncheckedAccount<'info>,
+    treasury_token_account: &UncheckedAccount<'info>,
+    token_program: &UncheckedAccount<'info>,
+) -> Result<()> {
+    let marketplace_key = marketplace.key();
+    let seeds = &[
+        b"seller",
+        marketplace_key.as_ref(),
+        seller_account.seller.as_ref(),
+        &[seller_account.bump],
+    ];
+
+    let transfer_ix = token_instruction::transfer(
+        &token_program.key(),
+        &fee_token_account.key(),
+        &treasury_token_account.key(),
+        &seller_account.key(),
+        &[],
+        marketplace.fee_per_period,
+    )?;
+
+    invoke_signed(
+        &transfer_ix,
+        &[
+            fee_token_account.to_account_info(),
+            treasury_token_account.to_account_info(),
+            seller_account.to_account_info(),
+            token_program.to_account_info(),
+        ],
+        &[&seeds[..]],
+    )?;
+
+    Ok(())
+}
+
+/// Mark a listing live for one period that has just been paid for
+fn activate(
+    marketplace: &mut Marketplace,
+    seller_account: &mut SellerAccount,
+    listing: &mut Listing,
+    now: i64,
+) -> Result<()> {
+    listing.active = true;
+    listing.paid_until = now;
+    listing.record_fee(marketplace.fee_per_period, marketplace.interval_seconds)?;
+    marketplace.total_fees = marketplace
+        .total_fees
+        .checked_add(marketplace.fee_per_period)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    marketplace.active_listings = marketplace
+        .active_listings
+        .checked_add(1)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    seller_account.active_listings = seller_account
+        .active_listings
+        .checked_add(1)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    Ok(())
+}
+
+fn deactivate(
+    marketplace: &mut Marketplace,
+    seller_account: &mut SellerAccount,
+    listing: &mut Listing,
+) -> Result<()> {
+    listing.active = false;
+    marketplace.active_listings = marketplace
+        .active_listings
+        .checked_sub(1)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    seller_account.active_listings = seller_account
+        .active_listings
+        .checked_sub(1)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CreateMarketplace<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Marketplace::INIT_SPACE,
+        seeds = [b"marketplace", authority.key().as_ref()],
+        bump
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    pub authority: Signer<'info>,
+
+    /// CHECK: Token mint (USDC)
+    pub mint: UncheckedAccount<'info>,
+
+    /// CHECK: Marketplace's token account, checked in the handler; fees land here
+    pub treasury_token_account: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateMarketplace<'info> {
+    #[account(
+        mut,
+        seeds = [b"marketplace", authority.key().as_ref()],
+        bump = marketplace.bump,
+        has_one = authority
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterSeller<'info> {
+    #[account(
+        seeds = [b"marketplace", marketplace.authority.as_ref()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + SellerAccount::INIT_SPACE,
+        seeds = [b"seller", marketplace.key().as_ref(), seller.key().as_ref()],
+        bump
+    )]
+    pub seller_account: Account<'info, SellerAccount>,
+
+    pub seller: Signer<'info>,
+
+    /// CHECK: Seller's token account, checked in the handler and delegated to the seller account PDA
+    #[account(mut)]
+    pub fee_token_account: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CreateListing<'info> {
+    #[account(
+        mut,
+        seeds = [b"marketplace", marketplace.authority.as_ref()],
+        bump = marketplace.bump,
+        has_one = treasury_token_account
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    #[account(
+        mut,
+        seeds = [b"seller", marketplace.key().as_ref(), seller.key().as_ref()],
+        bump = seller_account.bump,
+        has_one = marketplace,
+        has_one = seller,
+        has_one = fee_token_account
+    )]
+    pub seller_account: Account<'info, SellerAccount>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Listing::INIT_SPACE,
+        seeds = [
+            b"listing",
+            seller_account.key().as_ref(),
+            &seller_account.next_listing_id.to_le_bytes()
+        ],
+        bump
+    )]
+    pub listing: Account<'info, Listing>,
+
+    pub seller: Signer<'info>,
+
+    /// CHECK: The delegated token account
+    #[account(mut)]
+    pub fee_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Marketplace's token account
+    #[account(mut)]
+    pub treasury_token_account: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RenewListing<'info> {
+    #[account(
+        mut,
+        seeds = [b"marketplace", marketplace.authority.as_ref()],
+        bump = marketplace.bump,
+        has_one = treasury_token_account
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    #[account(
+        seeds = [b"seller", marketplace.key().as_ref(), seller_account.seller.as_ref()],
+        bump = seller_account.bump,
+        has_one = marketplace,
+        has_one = fee_token_account
+    )]
+    pub seller_account: Account<'info, SellerAccount>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"listing",
+            seller_account.key().as_ref(),
+            &listing.listing_id.to_le_bytes()
+        ],
+        bump = listing.bump
+    )]
+    pub listing: Account<'info, Listing>,
+
+    /// CHECK: The delegated token account
+    #[account(mut)]
+    pub fee_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Marketplace's token account
+    #[account(mut)]
+    pub treasury_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct DelistLapsed<'info> {
+    #[account(
+        mut,
+        seeds = [b"marketplace", marketplace.authority.as_ref()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    #[account(
+        mut,
+        seeds = [b"seller", marketplace.key().as_ref(), seller_account.seller.as_ref()],
+        bump = seller_account.bump,
+        has_one = marketplace
+    )]
+    pub seller_account: Account<'info, SellerAccount>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"listing",
+            seller_account.key().as_ref(),
+            &listing.listing_id.to_le_bytes()
+        ],
+        bump = listing.bump
+    )]
+    pub listing: Account<'info, Listing>,
+}
+
+#[derive(Accounts)]
+pub struct Relist<'info> {
+    #[account(
+        mut,
+        seeds = [b"marketplace", marketplace.authority.as_ref()],
+        bump = marketplace.bump,
+        has_one = treasury_token_account
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    #[account(
+        mut,
+        seeds = [b"seller", marketplace.key().as_ref(), seller.key().as_ref()],
+        bump = seller_account.bump,
+        has_one = marketplace,
+        has_one = seller,
+        has_one = fee_token_account
+    )]
+    pub seller_account: Account<'info, SellerAccount>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"listing",
+            seller_account.key().as_ref(),
+            &listing.listing_id.to_le_bytes()
+        ],
+        bump = listing.bump
+    )]
+    pub listing: Account<'info, Listing>,
+
+    pub seller: Signer<'info>,
+
+    /// CHECK: The delegated token account
+    #[account(mut)]
+    pub fee_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Marketplace's token account
+    #[account(mut)]
+    pub treasury_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseListing<'info> {
+    #[account(
+        mut,
+        seeds = [b"marketplace", marketplace.authority.as_ref()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    #[account(
+        mut,
+        seeds = [b"seller", marketplace.key().as_ref(), seller.key().as_ref()],
+        bump = seller_account.bump,
+        has_one = marketplace,
+        has_one = seller
+    )]
+    pub seller_account: Account<'info, SellerAccount>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"listing",
+            seller_account.key().as_ref(),
+            &listing.listing_id.to_le_bytes()
+        ],
+        bump = listing.bump,
+        close = seller
+    )]
+    pub listing: Account<'info, Listing>,
+
+    #[account(mut)]
+    pub seller: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseSeller<'info> {
+    #[account(
+        mut,
+        seeds = [b"seller", seller_account.marketplace.as_ref(), seller.key().as_ref()],
+        bump = seller_account.bump,
+        has_one = seller,
+        has_one = fee_token_account,
+        close = seller
+    )]
+    pub seller_account: Account<'info, SellerAccount>,
+
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    /// CHECK: The delegated token account
+    #[account(mut)]
+    pub fee_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Marketplace {
+    pub authority: Pubkey,
+    pub mint: Pubkey,
+    pub treasury_token_account: Pubkey,
+    pub fee_per_period: u64,
+    pub interval_seconds: i64,
+    /// How late a fee may be before the listing can be delisted
+    pub grace_seconds: i64,
+    pub active_listings: u64,
+    pub total_fees: u64,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl Marketplace {
+    pub fn check_params(
+        fee_per_period: u64,
+        interval_seconds: i64,
+        grace_seconds: i64,
+    ) -> Result<()> {
+        require!(fee_per_period > 0, ErrorCode::InvalidAmount);
+        require!(
+            interval_seconds > 0 && grace_seconds >= 0,
+            ErrorCode::InvalidInterval
+        );
+        Ok(())
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct SellerAccount {
+    pub marketplace: Pubkey,
+    pub seller: Pubkey,
+    /// Delegated to this PDA; every listing's fee is pulled from it
+    pub fee_token_account: Pubkey,
+    pub next_listing_id: u64,
+    pub active_listings: u64,
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Listing {
+    pub marketplace: Pubkey,
+    pub seller: Pubkey,
+    pub listing_id: u64,
+    pub price: u64,
+    #[max_len(MAX_URI_LEN)]
+    pub uri: String,
+    /// Cleared when fees lapse or the seller takes the listing down
+    pub active: bool,
+    /// End of the last period paid for
+    pub paid_until: i64,
+    pub fees_paid: u64,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl Listing {
+    /// Renewal is due from the end of the paid period until the grace runs out
+    pub fn check_renewal_due(&self, now: i64, grace_seconds: i64) -> Result<()> {
+        require!(self.active, ErrorCode::ListingInactive);
+        require!(now >= self.paid_until, ErrorCode::FeeNotDue);
+        require!(
+            now <= self.paid_until.saturating_add(grace_seconds),
+            ErrorCode::FeesLapsed
+        );
+        Ok(())
+    }
+
+    pub fn check_lapsed(&self, now: i64, grace_seconds: i64) -> Result<()> {
+        require!(self.active, ErrorCode::ListingInactive);
+        require!(
+            now > self.paid_until.saturating_add(grace_seconds),
+            ErrorCode::FeesCurrent
+        );
+        Ok(())
+    }
+
+    /// Extend the paid period after a fee has moved
+    pub fn record_fee(&mut self, fee: u64, interval_seconds: i64) -> Result<()> {
+        self.paid_until = self
+            .paid_until
+            .checked_add(interval_seconds)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.fees_paid = self
+            .fees_paid
+            .checked_add(fee)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarketplaceCreated {
+    pub marketplace: Pubkey,
+    pub authority: Pubkey,
+    pub fee_per_period: u64,
+    pub interval_seconds: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListingCreated {
+    pub listing: Pubkey,
+    pub seller: Pubkey,
+    pub listing_id: u64,
+    pub price: u64,
+    pub paid_until: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListingRenewed {
+    pub listing: Pubkey,
+    pub seller: Pubkey,
+    pub fee: u64,
+    pub paid_until: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListingDelisted {
+    pub listing: Pubkey,
+    pub seller: Pubkey,
+    /// Delisted by the keeper for unpaid fees rather than by the seller
+    pub lapsed: bool,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Amount must be greater than zero")]
+    InvalidAmount,
+    #[msg("Interval must be positive and grace period non-negative")]
+    InvalidInterval,
+    #[msg("Invalid token account - wrong program, owner or mint")]
+    InvalidTokenAccount,
+    #[msg("Listing URI is too long")]
+    UriTooLong,
+    #[msg("Listing is active")]
+    ListingActive,
+    #[msg("Listing is not active")]
+    ListingInactive,
+    #[msg("Listing fee is not due yet")]
+    FeeNotDue,
+    #[msg("Listing fee is past the grace period; the listing can be delisted")]
+    FeesLapsed,
+    #[msg("Listing fees are paid up")]
+    FeesCurrent,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
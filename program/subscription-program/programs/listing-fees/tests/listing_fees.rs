@@ -0,0 +1,490 @@
+//! Native tests for the listing fees program.
+//!
+//! The renewal and lapse rules are pure methods on `Listing` and are tested
+//! directly. The instructions run end to end against the real token and
+//! system programs, and the tests check the fees that reach the treasury.
+
+use anchor_lang::error::ErrorCode as AnchorErrorCode;
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, Space, ToAccountMetas};
+use listing_fees::{
+    accounts, instruction, ErrorCode, Listing, Marketplace, SellerAccount, ID as PROGRAM_ID,
+};
+use test_harness::{assert_error, assert_success, Keypair, Signer, TestSvm, TransactionResult};
+
+const FEE: u64 = 1_000_000;
+const MONTH: i64 = 30 * 86_400;
+const GRACE: i64 = 3 * 86_400;
+
+fn listing(paid_until: i64) -> Listing {
+    Listing {
+        marketplace: Pubkey::new_unique(),
+        seller: Pubkey::new_unique(),
+        listing_id: 0,
+        price: 250_000_000,
+        uri: "https://example.com/items/1.json".to_string(),
+        active: true,
+        paid_until,
+        fees_paid: FEE,
+        created_at: paid_until - MONTH,
+        bump: 255,
+    }
+}
+
+#[test]
+fn rejects_free_or_instant_periods() {
+    assert_eq!(
+        Marketplace::check_params(0, MONTH, GRACE).unwrap_err(),
+        ErrorCode::InvalidAmount.into()
+    );
+    assert_eq!(
+        Marketplace::check_params(FEE, 0, GRACE).unwrap_err(),
+        ErrorCode::InvalidInterval.into()
+    );
+    assert_eq!(
+        Marketplace::check_params(FEE, MONTH, -1).unwrap_err(),
+        ErrorCode::InvalidInterval.into()
+    );
+    Marketplace::check_params(FEE, MONTH, 0).unwrap();
+}
+
+#[test]
+fn listings_renew_within_grace_then_lapse() {
+    let mut item = listing(MONTH);
+    assert_eq!(
+        item.check_renewal_due(MONTH - 1, GRACE).unwrap_err(),
+        ErrorCode::FeeNotDue.into()
+    );
+    for now in [MONTH, MONTH + GRACE] {
+        item.check_renewal_due(now, GRACE).unwrap();
+        assert_eq!(
+            item.check_lapsed(now, GRACE).unwrap_err(),
+            ErrorCode::FeesCurrent.into()
+        );
+    }
+
+    let lapsed = MONTH + GRACE + 1;
+    item.check_lapsed(lapsed, GRACE).unwrap();
+    assert_eq!(
+        item.check_renewal_due(lapsed, GRACE).unwrap_err(),
+        ErrorCode::FeesLapsed.into()
+    );
+
+    item.active = false;
+    assert_eq!(
+        item.check_renewal_due(MONTH, GRACE).unwrap_err(),
+        ErrorCode::ListingInactive.into()
+    );
+    assert_eq!(
+        item.check_lapsed(lapsed, GRACE).unwrap_err(),
+        ErrorCode::ListingInactive.into()
+    );
+}
+
+#[test]
+fn each_fee_extends_the_listing_by_one_period() {
+    let mut item = listing(MONTH);
+    item.record_fee(FEE, MONTH).unwrap();
+    item.record_fee(2 * FEE, MONTH).unwrap();
+    assert_eq!(item.paid_until, 3 * MONTH);
+    assert_eq!(item.fees_paid, 4 * FEE);
+}
+
+struct Fixture {
+    svm: TestSvm,
+    payer: Keypair,
+    authority: Keypair,
+    seller: Keypair,
+    marketplace: Pubkey,
+    state: Marketplace,
+    seller_account: Pubkey,
+    listing: Pubkey,
+    fee_token_account: Pubkey,
+}
+
+impl Fixture {
+    /// A marketplace with one seller and one active listing, with two other
+    /// active listings elsewhere
+    fn new(paid_until: i64) -> Self {
+        let (mut svm, payer) = TestSvm::for_program(PROGRAM_ID, listing_fees::entry);
+
+        let authority = Keypair::new();
+        let seller = Keypair::new();
+
+        let mint = svm.create_mint(&Pubkey::new_unique(), 6);
+        let treasury_token_account =
+            svm.create_associated_token_account(&authority.pubkey(), &mint, 0);
+        let fee_token_account =
+            svm.create_associated_token_account(&seller.pubkey(), &mint, 10 * FEE);
+
+        let (marketplace, bump) = Pubkey::find_program_address(
+            &[b"marketplace", authority.pubkey().as_ref()],
+            &PROGRAM_ID,
+        );
+        let state = Marketplace {
+            authority: authority.pubkey(),
+            mint,
+            treasury_token_account,
+            fee_per_period: FEE,
+            interval_seconds: MONTH,
+            grace_seconds: GRACE,
+            active_listings: 3,
+            total_fees: 3 * FEE,
+            created_at: 0,
+            bump,
+        };
+        svm.set_anchor_account(marketplace, &state, 8 + Marketplace::INIT_SPACE);
+
+        let (seller_account, bump) = Pubkey::find_program_address(
+            &[b"seller", marketplace.as_ref(), seller.pubkey().as_ref()],
+            &PROGRAM_ID,
+        );
+        let account = SellerAccount {
+            marketplace,
+            seller: seller.pubkey(),
+            fee_token_account,
+            next_listing_id: 1,
+            active_listings: 1,
+            bump,
+        };
+        svm.set_anchor_account(seller_account, &account, 8 + SellerAccount::INIT_SPACE);
+        svm.approve(&fee_token_account, &seller_account, u64::MAX);
+
+        let (listing_address, bump) = Pubkey::find_program_address(
+            &[b"listing", seller_account.as_ref(), &0u64.to_le_bytes()],
+            &PROGRAM_ID,
+        );
+        let item = Listing {
+            marketplace,
+            seller: seller.pubkey(),
+            bump,
+            ..listing(paid_until)
+        };
+        svm.set_anchor_account(listing_address, &item, 8 + Listing::INIT_SPACE);
+
+        Self {
+            svm,
+            payer,
+            authority,
+            seller,
+            marketplace,
+            state,
+            seller_account,
+            listing: listing_address,
+            fee_token_account,
+        }
+    }
+
+    fn now(&self) -> i64 {
+        self.svm.clock().unix_timestamp
+    }
+
+    fn create_marketplace(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CreateMarketplace {
+                marketplace: self.marketplace,
+                authority: self.authority.pubkey(),
+                mint: self.state.mint,
+                treasury_token_account: self.state.treasury_token_account,
+                payer: self.payer.pubkey(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CreateMarketplace {
+                fee_per_period: FEE,
+                interval_seconds: MONTH,
+                grace_seconds: GRACE,
+            }
+            .data(),
+        }
+    }
+
+    fn register(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::RegisterSeller {
+                marketplace: self.marketplace,
+                seller_account: self.seller_account,
+                seller: self.seller.pubkey(),
+                fee_token_account: self.fee_token_account,
+                payer: self.payer.pubkey(),
+                token_program: spl_token::ID,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::RegisterSeller {}.data(),
+        }
+    }
+
+    fn create_listing(&self, price: u64) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CreateListing {
+                marketplace: self.marketplace,
+                seller_account: self.seller_account,
+                listing: self.listing,
+                seller: self.seller.pubkey(),
+                fee_token_account: self.fee_token_account,
+                treasury_token_account: self.state.treasury_token_account,
+                payer: self.payer.pubkey(),
+                token_program: spl_token::ID,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CreateListing {
+                price,
+                uri: "https://example.com/items/1.json".to_string(),
+            }
+            .data(),
+        }
+    }
+
+    fn close_seller(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CloseSeller {
+                seller_account: self.seller_account,
+                seller: self.seller.pubkey(),
+                fee_token_account: self.fee_token_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CloseSeller {}.data(),
+        }
+    }
+
+    fn treasury_balance(&self) -> u64 {
+        self.svm.token_balance(&self.state.treasury_token_account)
+    }
+
+    fn renew(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::RenewListing {
+                marketplace: self.marketplace,
+                seller_account: self.seller_account,
+                listing: self.listing,
+                fee_token_account: self.fee_token_account,
+                treasury_token_account: self.state.treasury_token_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::RenewListing {}.data(),
+        }
+    }
+
+    fn delist(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::DelistLapsed {
+                marketplace: self.marketplace,
+                seller_account: self.seller_account,
+                listing: self.listing,
+            }
+            .to_account_metas(None),
+            data: instruction::DelistLapsed {}.data(),
+        }
+    }
+
+    fn relist(&self, price: u64) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::Relist {
+                marketplace: self.marketplace,
+                seller_account: self.seller_account,
+                listing: self.listing,
+                seller: self.seller.pubkey(),
+                fee_token_account: self.fee_token_account,
+                treasury_token_account: self.state.treasury_token_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::Relist { price }.data(),
+        }
+    }
+
+    fn close(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CloseListing {
+                marketplace: self.marketplace,
+                seller_account: self.seller_account,
+                listing: self.listing,
+                seller: self.seller.pubkey(),
+            }
+            .to_account_metas(None),
+            data: instruction::CloseListing {}.data(),
+        }
+    }
+
+    fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TransactionResult {
+        let payer = self.payer.insecure_clone();
+        self.svm.send_instructions(&[instruction], &payer, signers)
+    }
+
+    fn send_as_seller(&mut self, instruction: Instruction) -> TransactionResult {
+        let seller = self.seller.insecure_clone();
+        self.send(instruction, &[&seller])
+    }
+
+    fn active_listings(&self) -> (u64, u64) {
+        let marketplace: Marketplace = self.svm.get_anchor_account(&self.marketplace).unwrap();
+        let seller: SellerAccount = self.svm.get_anchor_account(&self.seller_account).unwrap();
+        (marketplace.active_listings, seller.active_listings)
+    }
+}
+
+#[test]
+fn authorities_open_a_marketplace_over_their_treasury() {
+    let mut fx = Fixture::new(0);
+    fx.svm.remove_account(&fx.marketplace);
+    let authority = fx.authority.insecure_clone();
+
+    let result = fx.send(fx.create_marketplace(), &[&authority]);
+    assert_success(result);
+    let marketplace: Marketplace = fx.svm.get_anchor_account(&fx.marketplace).unwrap();
+    assert_eq!(marketplace.authority, authority.pubkey());
+    assert_eq!(
+        marketplace.treasury_token_account,
+        fx.state.treasury_token_account
+    );
+    assert_eq!(marketplace.active_listings, 0);
+    assert_eq!(marketplace.bump, fx.state.bump);
+}
+
+#[test]
+fn registered_sellers_pay_the_first_period_on_listing() {
+    let mut fx = Fixture::new(0);
+    fx.svm.remove_account(&fx.seller_account);
+    fx.svm.remove_account(&fx.listing);
+    fx.svm.revoke(&fx.fee_token_account);
+
+    let result = fx.send_as_seller(fx.register());
+    assert_success(result);
+    let fee_account = fx.svm.get_token_account(&fx.fee_token_account).unwrap();
+    assert_eq!(fee_account.delegate, Some(fx.seller_account).into());
+
+    let result = fx.send_as_seller(fx.create_listing(0));
+    assert_error(result, ErrorCode::InvalidAmount);
+
+    let result = fx.send_as_seller(fx.create_listing(250_000_000));
+    assert_success(result);
+    assert_eq!(fx.svm.token_balance(&fx.fee_token_account), 9 * FEE);
+    assert_eq!(fx.treasury_balance(), FEE);
+    let item: Listing = fx.svm.get_anchor_account(&fx.listing).unwrap();
+    assert!(item.active);
+    assert_eq!(item.paid_until, fx.now() + MONTH);
+    assert_eq!(fx.active_listings(), (4, 1));
+}
+
+#[test]
+fn sellers_close_out_once_nothing_is_listed() {
+    let now = Fixture::new(0).now();
+    let mut fx = Fixture::new(now + MONTH);
+    let result = fx.send_as_seller(fx.close_seller());
+    assert_error(result, ErrorCode::ListingActive);
+
+    let result = fx.send_as_seller(fx.close());
+    assert!(result.is_ok(), "{result:#?}");
+    fx.svm.expire_blockhash();
+    let result = fx.send_as_seller(fx.close_seller());
+    assert_success(result);
+    assert!(fx.svm.get_account(&fx.seller_account).is_none());
+    let fee_account = fx.svm.get_token_account(&fx.fee_token_account).unwrap();
+    assert!(fee_account.delegate.is_none());
+}
+
+#[test]
+fn keeper_renews_due_listings() {
+    let now = Fixture::new(0).now();
+    let mut fx = Fixture::new(now + MONTH);
+    let result = fx.send(fx.renew(), &[]);
+    assert_error(result, ErrorCode::FeeNotDue);
+
+    fx.svm.warp_to_timestamp(now + MONTH);
+    let mut redirected = fx.renew();
+    redirected.accounts[3].pubkey =
+        fx.svm
+            .create_token_account(&fx.seller.pubkey(), &fx.state.mint, 10 * FEE);
+    let result = fx.send(redirected, &[]);
+    assert_error(result, AnchorErrorCode::ConstraintHasOne);
+
+    let result = fx.send(fx.renew(), &[]);
+    assert_success(result);
+    assert_eq!(fx.svm.token_balance(&fx.fee_token_account), 9 * FEE);
+    assert_eq!(fx.treasury_balance(), FEE);
+    let item: Listing = fx.svm.get_anchor_account(&fx.listing).unwrap();
+    assert_eq!(item.paid_until, now + 2 * MONTH);
+
+    fx.svm.warp_to_timestamp(now + 2 * MONTH + GRACE + 1);
+    let result = fx.send(fx.renew(), &[]);
+    assert_error(result, ErrorCode::FeesLapsed);
+}
+
+#[test]
+fn keeper_delists_lapsed_listings() {
+    let now = Fixture::new(0).now();
+    let mut fx = Fixture::new(now + MONTH);
+    fx.svm.warp_to_timestamp(now + MONTH + GRACE);
+    let result = fx.send(fx.delist(), &[]);
+    assert_error(result, ErrorCode::FeesCurrent);
+
+    fx.svm.warp_to_timestamp(now + MONTH + GRACE + 1);
+    let result = fx.send(fx.delist(), &[]);
+    assert!(result.is_ok(), "{result:#?}");
+    let item: Listing = fx.svm.get_anchor_account(&fx.listing).unwrap();
+    assert!(!item.active);
+    assert_eq!(fx.active_listings(), (2, 0));
+
+    fx.svm.expire_blockhash();
+    let result = fx.send(fx.delist(), &[]);
+    assert_error(result, ErrorCode::ListingInactive);
+}
+
+#[test]
+fn sellers_relist_delisted_items() {
+    let now = Fixture::new(0).now();
+    let mut fx = Fixture::new(now + MONTH);
+    let result = fx.send_as_seller(fx.relist(100));
+    assert_error(result, ErrorCode::ListingActive);
+
+    fx.svm.warp_to_timestamp(now + MONTH + GRACE + 1);
+    let result = fx.send(fx.delist(), &[]);
+    assert!(result.is_ok(), "{result:#?}");
+
+    let result = fx.send_as_seller(fx.relist(0));
+    assert_error(result, ErrorCode::InvalidAmount);
+
+    let stranger = Keypair::new();
+    let mut forged = fx.relist(100);
+    forged.accounts[3].pubkey = stranger.pubkey();
+    let result = fx.send(forged, &[&stranger]);
+    assert_error(result, AnchorErrorCode::ConstraintSeeds);
+
+    let result = fx.send_as_seller(fx.relist(100));
+    assert_success(result);
+    assert_eq!(fx.treasury_balance(), FEE);
+    let item: Listing = fx.svm.get_anchor_account(&fx.listing).unwrap();
+    assert!(item.active);
+    assert_eq!(item.price, 100);
+    assert_eq!(fx.active_listings(), (3, 1));
+}
+
+#[test]
+fn closing_an_active_listing_frees_its_slot() {
+    let now = Fixture::new(0).now();
+    let mut fx = Fixture::new(now + MONTH);
+
+    let stranger = Keypair::new();
+    let mut forged = fx.close();
+    forged.accounts[3].pubkey = stranger.pubkey();
+    let result = fx.send(forged, &[&stranger]);
+    assert_error(result, AnchorErrorCode::ConstraintSeeds);
+
+    let result = fx.send_as_seller(fx.close());
+    assert!(result.is_ok(), "{result:#?}");
+    assert!(fx.svm.get_account(&fx.listing).is_none());
+    assert_eq!(fx.active_listings(), (2, 0));
+}
@@ -0,0 +1,448 @@
+use anchor_lang::prelude::*;
+use subscription_program::Subscription;
+
+declare_id!("EzmPQR4ck9mbT4wpRDSeTW8rya7hrZ7HZFE1B3XS4FpV");
+
+#[program]
+pub mod loyalty_points {
+    use super::*;
+
+    /// Start a points program. Members earn `earn_points` for every
+    /// `per_amount` base units they spend; `earn_authority` is the signer
+    /// allowed to report purchases, which may be another program's PDA.
+    pub fn create_loyalty(
+        ctx: Context<CreateLoyalty>,
+        earn_authority: Pubkey,
+        earn_points: u64,
+        per_amount: u64,
+        min_redemption: u64,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        Loyalty::check_rate(earn_points, per_amount)?;
+
+        let loyalty = &mut ctx.accounts.loyalty;
+        loyalty.merchant = ctx.accounts.merchant.key();
+        loyalty.earn_authority = earn_authority;
+        loyalty.earn_points = earn_points;
+        loyalty.per_amount = per_amount;
+        loyalty.min_redemption = min_redemption;
+        loyalty.points_issued = 0;
+        loyalty.points_redeemed = 0;
+        loyalty.created_at = clock.unix_timestamp;
+        loyalty.bump = ctx.bumps.loyalty;
+
+        emit!(LoyaltyCreated {
+            loyalty: loyalty.key(),
+            merchant: loyalty.merchant,
+            earn_authority,
+            earn_points,
+            per_amount,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("Loyalty program created");
+        msg!("Rate: {} points per {} spent", earn_points, per_amount);
+
+        Ok(())
+    }
+
+    /// Change the rate, redemption minimum or earn authority; balances keep
+    /// the points already earned
+    pub fn update_loyalty(
+        ctx: Context<UpdateLoyalty>,
+        earn_authority: Pubkey,
+        earn_points: u64,
+        per_amount: u64,
+        min_redemption: u64,
+    ) -> Result<()> {
+        Loyalty::check_rate(earn_points, per_amount)?;
+
+        let loyalty = &mut ctx.accounts.loyalty;
+        loyalty.earn_authority = earn_authority;
+        loyalty.earn_points = earn_points;
+        loyalty.per_amount = per_amount;
+        loyalty.min_redemption = min_redemption;
+
+        emit!(LoyaltyUpdated {
+            loyalty: loyalty.key(),
+            earn_authority,
+            earn_points,
+            per_amount,
+            min_redemption,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Rate: {} points per {} spent", earn_points, per_amount);
+
+        Ok(())
+    }
+
+    /// Open a points balance for `owner`
+    pub fn join(ctx: Context<Join>) -> Result<()> {
+        let clock = Clock::get()?;
+
+        let member = &mut ctx.accounts.member;
+        member.loyalty = ctx.accounts.loyalty.key();
+        member.owner = ctx.accounts.owner.key();
+        member.balance = 0;
+        member.lifetime_earned = 0;
+        member.lifetime_redeemed = 0;
+        member.subscription_credited = 0;
+        member.joined_at = clock.unix_timestamp;
+        member.bump = ctx.bumps.member;
+
+        emit!(MemberJoined {
+            loyalty: member.loyalty,
+            owner: member.owner,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("Member joined: {}", member.owner);
+
+        Ok(())
+    }
+
+    /// Award points for a purchase of `amount`. Signed by the earn authority,
+    /// so a checkout or billing program can CPI in with its PDA after taking
+    /// payment.
+    pub fn earn(ctx: Context<Earn>, amount: u64) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        award(&mut ctx.accounts.loyalty, &mut ctx.accounts.member, amount)
+    }
+
+    /// Award points for subscription charges not yet credited. The
+    /// subscription program has no charge callback, so this reads the
+    /// member's subscription to the merchant instead and anyone can crank it
+    /// after a charge.
+    pub fn earn_from_subscription(ctx: Context<EarnFromSubscription>) -> Result<()> {
+        let total_charged = ctx.accounts.subscription.total_charged;
+        let amount = ctx.accounts.member.uncredited_charges(total_charged);
+        require!(amount > 0, ErrorCode::NothingToCredit);
+
+        ctx.accounts.member.subscription_credited = total_charged;
+        award(&mut ctx.accounts.loyalty, &mut ctx.accounts.member, amount)
+    }
+
+    /// Burn `points` for `reward_id`. The merchant fulfils the reward off-chain
+    /// from the `PointsRedeemed` event.
+    pub fn redeem(ctx: Context<Redeem>, points: u64, reward_id: u64) -> Result<()> {
+        let loyalty = &mut ctx.accounts.loyalty;
+        let member = &mut ctx.accounts.member;
+        member.burn(points, loyalty.min_redemption)?;
+        loyalty.points_redeemed = loyalty
+            .points_redeemed
+            .checked_add(points)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        emit!(PointsRedeemed {
+            loyalty: loyalty.key(),
+            owner: member.owner,
+            points,
+            reward_id,
+            balance: member.balance,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Redeemed {} points for reward {}", points, reward_id);
+        msg!("Balance: {} points", member.balance);
+
+        Ok(())
+    }
+}
+
+/// Credit the points `amount` earns at the current rate
+fn award(loyalty: &mut Account<Loyalty>, member: &mut Account<Member>, amount: u64) -> Result<()> {
+    let points = loyalty.points_for(amount)?;
+    member.credit(points)?;
+    loyalty.points_issued = loyalty
+        .points_issued
+        .checked_add(points)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+    emit!(PointsEarned {
+        loyalty: loyalty.key(),
+        owner: member.owner,
+        amount,
+        points,
+        balance: member.balance,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!("Earned {} points on {} spent", points, amount);
+    msg!("Balance: {} points", member.balance);
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CreateLoyalty<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Loyalty::INIT_SPACE,
+        seeds = [b"loyalty", merchant.key().as_ref()],
+        bump
+    )]
+    pub loyalty: Account<'info, Loyalty>,
+
+    pub merchant: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateLoyalty<'info> {
+    #[account(
+        mut,
+        seeds = [b"loyalty", merchant.key().as_ref()],
+        bump = loyalty.bump,
+        has_one = merchant
+    )]
+    pub loyalty: Account<'info, Loyalty>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Join<'info> {
+    #[account(
+        seeds = [b"loyalty", loyalty.merchant.as_ref()],
+        bump = loyalty.bump,
+    )]
+    pub loyalty: Account<'info, Loyalty>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Member::INIT_SPACE,
+        seeds = [b"member", loyalty.key().as_ref(), owner.key().as_ref()],
+        bump
+    )]
+    pub member: Account<'info, Member>,
+
+    pub owner: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Earn<'info> {
+    #[account(
+        mut,
+        seeds = [b"loyalty", loyalty.merchant.as_ref()],
+        bump = loyalty.bump,
+        has_one = earn_authority
+    )]
+    pub loyalty: Account<'info, Loyalty>,
+
+    #[account(
+        mut,
+        seeds = [b"member", loyalty.key().as_ref(), member.owner.as_ref()],
+        bump = member.bump,
+        has_one = loyalty
+    )]
+    pub member: Account<'info, Member>,
+
+    pub earn_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct EarnFromSubscription<'info> {
+    #[account(
+        mut,
+        seeds = [b"loyalty", loyalty.merchant.as_ref()],
+        bump = loyalty.bump,
+    )]
+    pub loyalty: Account<'info, Loyalty>,
+
+    #[account(
+        mut,
+        seeds = [b"member", loyalty.key().as_ref(), member.owner.as_ref()],
+        bump = member.bump,
+        has_one = loyalty
+    )]
+    pub member: Account<'info, Member>,
+
+    /// The member's subscription to the merchant, owned by the subscription program
+    #[account(
+        constraint = subscription.authority == member.owner @ ErrorCode::SubscriptionMismatch,
+        constraint = subscription.recipient == loyalty.merchant @ ErrorCode::SubscriptionMismatch
+    )]
+    pub subscription: Account<'info, Subscription>,
+}
+
+#[derive(Accounts)]
+pub struct Redeem<'info> {
+    #[account(
+        mut,
+        seeds = [b"loyalty", loyalty.merchant.as_ref()],
+        bump = loyalty.bump,
+    )]
+    pub loyalty: Account<'info, Loyalty>,
+
+    #[account(
+        mut,
+        seeds = [b"member", loyalty.key().as_ref(), owner.key().as_ref()],
+        bump = member.bump,
+        has_one = loyalty,
+        has_one = owner
+    )]
+    pub member: Account<'info, Member>,
+
+    pub owner: Signer<'info>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Loyalty {
+    pub merchant: Pubkey,
+    /// Signer allowed to call `earn`: the merchant, a backend key or a program PDA
+    pub earn_authority: Pubkey,
+    pub earn_points: u64,
+    pub per_amount: u64,
+    pub min_redemption: u64,
+    pub points_issued: u64,
+    pub points_redeemed: u64,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl Loyalty {
+    pub fn check_rate(earn_points: u64, per_amount: u64) -> Result<()> {
+        require!(earn_points > 0 && per_amount > 0, ErrorCode::InvalidRate);
+        Ok(())
+    }
+
+    /// Points earned by spending `amount`, rounded down
+    pub fn points_for(&self, amount: u64) -> Result<u64> {
+        let points =
+            u128::from(amount) * u128::from(self.earn_points) / u128::from(self.per_amount);
+        u64::try_from(points).map_err(|_| error!(ErrorCode::ArithmeticOverflow))
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Member {
+    pub loyalty: Pubkey,
+    pub owner: Pubkey,
+    pub balance: u64,
+    pub lifetime_earned: u64,
+    pub lifetime_redeemed: u64,
+    /// The subscription's `total_charged` already turned into points
+    pub subscription_credited: u64,
+    pub joined_at: i64,
+    pub bump: u8,
+}
+
+impl Member {
+    /// Subscription spend not yet credited. A total below what was credited
+    /// means the subscription was closed and opened again, so all of it is new.
+    pub fn uncredited_charges(&self, total_charged: u64) -> u64 {
+        total_charged
+            .checked_sub(self.subscription_credited)
+            .unwrap_or(total_charged)
+    }
+
+    pub fn credit(&mut self, points: u64) -> Result<()> {
+        self.balance = self
+            .balance
+            .checked_add(points)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.lifetime_earned = self
+            .lifetime_earned
+            .checked_add(points)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+
+    pub fn burn(&mut self, points: u64, min_redemption: u64) -> Result<()> {
+        require!(points > 0, ErrorCode::InvalidAmount);
+        require!(points >= min_redemption, ErrorCode::BelowMinimumRedemption);
+        self.balance = self
+            .balance
+            .checked_sub(points)
+            .ok_or(ErrorCode::InsufficientPoints)?;
+        self.lifetime_redeemed = self
+            .lifetime_redeemed
+            .checked_add(points)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoyaltyCreated {
+    pub loyalty: Pubkey,
+    pub merchant: Pubkey,
+    pub earn_authority: Pubkey,
+    pub earn_points: u64,
+    pub per_amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoyaltyUpdated {
+    pub loyalty: Pubkey,
+    pub earn_authority: Pubkey,
+    pub earn_points: u64,
+    pub per_amount: u64,
+    pub min_redemption: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemberJoined {
+    pub loyalty: Pubkey,
+    pub owner: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointsEarned {
+    pub loyalty: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub points: u64,
+    pub balance: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointsRedeemed {
+    pub loyalty: Pubkey,
+    pub owner: Pubkey,
+    pub points: u64,
+    pub reward_id: u64,
+    pub balance: u64,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Amount must be greater than zero")]
+    InvalidAmount,
+    #[msg("Earn rate needs non-zero points and amount")]
+    InvalidRate,
+    #[msg("Not enough points")]
+    InsufficientPoints,
+    #[msg("Redemption is below the program's minimum")]
+    BelowMinimumRedemption,
+    #[msg("Subscription does not belong to this member and merchant")]
+    SubscriptionMismatch,
+    #[msg("No subscription charges left to credit")]
+    NothingToCredit,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
@@ -0,0 +1,441 @@
+//! Native tests for the loyalty points program.
+//!
+//! The earn rate and balance rules are pure methods on `Loyalty` and `Member`
+//! and are tested directly. Every instruction also runs end to end.
+
+use anchor_lang::error::ErrorCode as AnchorErrorCode;
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, Space, ToAccountMetas};
+use loyalty_points::{accounts, instruction, ErrorCode, Loyalty, Member, ID as PROGRAM_ID};
+use subscription_program::Subscription;
+use test_harness::{assert_error, assert_success, Keypair, Signer, TestSvm, TransactionResult};
+
+/// 1 USDC in base units
+const USDC: u64 = 1_000_000;
+
+fn loyalty(merchant: Pubkey, earn_authority: Pubkey) -> Loyalty {
+    // 10 points per USDC, 500 points minimum per redemption
+    Loyalty {
+        merchant,
+        earn_authority,
+        earn_points: 10,
+        per_amount: USDC,
+        min_redemption: 500,
+        points_issued: 0,
+        points_redeemed: 0,
+        created_at: 0,
+        bump: 255,
+    }
+}
+
+fn member(loyalty: Pubkey, owner: Pubkey) -> Member {
+    Member {
+        loyalty,
+        owner,
+        balance: 0,
+        lifetime_earned: 0,
+        lifetime_redeemed: 0,
+        subscription_credited: 0,
+        joined_at: 0,
+        bump: 255,
+    }
+}
+
+#[test]
+fn points_follow_the_merchant_rate() {
+    let config = loyalty(Pubkey::new_unique(), Pubkey::new_unique());
+    assert_eq!(config.points_for(25 * USDC).unwrap(), 250);
+    // Partial units round down
+    assert_eq!(config.points_for(USDC / 10 - 1).unwrap(), 0);
+    assert_eq!(config.points_for(u64::MAX).unwrap(), 184_467_440_737_095);
+
+    let generous = Loyalty {
+        earn_points: u64::MAX,
+        per_amount: 1,
+        ..config
+    };
+    assert_eq!(
+        generous.points_for(2).unwrap_err(),
+        ErrorCode::ArithmeticOverflow.into()
+    );
+
+    assert_eq!(
+        Loyalty::check_rate(0, USDC).unwrap_err(),
+        ErrorCode::InvalidRate.into()
+    );
+    assert_eq!(
+        Loyalty::check_rate(10, 0).unwrap_err(),
+        ErrorCode::InvalidRate.into()
+    );
+}
+
+#[test]
+fn redemptions_respect_balance_and_minimum() {
+    let mut account = member(Pubkey::new_unique(), Pubkey::new_unique());
+    account.credit(800).unwrap();
+
+    assert_eq!(
+        account.burn(400, 500).unwrap_err(),
+        ErrorCode::BelowMinimumRedemption.into()
+    );
+    assert_eq!(
+        account.burn(900, 500).unwrap_err(),
+        ErrorCode::InsufficientPoints.into()
+    );
+    assert_eq!(
+        account.burn(0, 0).unwrap_err(),
+        ErrorCode::InvalidAmount.into()
+    );
+
+    account.burn(600, 500).unwrap();
+    assert_eq!(account.balance, 200);
+    assert_eq!(account.lifetime_earned, 800);
+    assert_eq!(account.lifetime_redeemed, 600);
+}
+
+#[test]
+fn subscription_charges_are_credited_once() {
+    let mut account = member(Pubkey::new_unique(), Pubkey::new_unique());
+    assert_eq!(account.uncredited_charges(30 * USDC), 30 * USDC);
+
+    account.subscription_credited = 30 * USDC;
+    assert_eq!(account.uncredited_charges(30 * USDC), 0);
+    assert_eq!(account.uncredited_charges(40 * USDC), 10 * USDC);
+
+    // Closed and re-subscribed: the new subscription's total starts over
+    assert_eq!(account.uncredited_charges(10 * USDC), 10 * USDC);
+}
+
+struct Fixture {
+    svm: TestSvm,
+    payer: Keypair,
+    merchant: Keypair,
+    earn_authority: Keypair,
+    owner: Keypair,
+    loyalty: Pubkey,
+    member: Pubkey,
+}
+
+impl Fixture {
+    /// A member of a 10-points-per-USDC program with no points yet
+    fn new() -> Self {
+        let (mut svm, payer) = TestSvm::for_program(PROGRAM_ID, loyalty_points::entry);
+
+        let merchant = Keypair::new();
+        let earn_authority = Keypair::new();
+        let owner = Keypair::new();
+
+        let (loyalty_address, loyalty_bump) =
+            Pubkey::find_program_address(&[b"loyalty", merchant.pubkey().as_ref()], &PROGRAM_ID);
+        let stored = Loyalty {
+            bump: loyalty_bump,
+            ..loyalty(merchant.pubkey(), earn_authority.pubkey())
+        };
+        svm.set_anchor_account(loyalty_address, &stored, 8 + Loyalty::INIT_SPACE);
+
+        let (member_address, member_bump) = Pubkey::find_program_address(
+            &[b"member", loyalty_address.as_ref(), owner.pubkey().as_ref()],
+            &PROGRAM_ID,
+        );
+        let stored = Member {
+            bump: member_bump,
+            ..member(loyalty_address, owner.pubkey())
+        };
+        svm.set_anchor_account(member_address, &stored, 8 + Member::INIT_SPACE);
+
+        Self {
+            svm,
+            payer,
+            merchant,
+            earn_authority,
+            owner,
+            loyalty: loyalty_address,
+            member: member_address,
+        }
+    }
+
+    /// Store a subscription from `authority` to `recipient` that has been
+    /// charged `total_charged`, returning its address
+    fn subscribe(&mut self, authority: Pubkey, recipient: Pubkey, total_charged: u64) -> Pubkey {
+        let (address, bump) = Pubkey::find_program_address(
+            &[b"subscription", authority.as_ref(), recipient.as_ref()],
+            &subscription_program::ID,
+        );
+        let stored = Subscription {
+            authority,
+            recipient,
+            user_token_account: Pubkey::new_unique(),
+            recipient_token_account: Pubkey::new_unique(),
+            token_mint: Pubkey::new_unique(),
+            amount_per_period: 10 * USDC,
+            interval_seconds: 30 * 86_400,
+            last_charge_timestamp: 0,
+            next_charge_at: 30 * 86_400,
+            created_at: 0,
+            expires_at: None,
+            is_active: true,
+            total_charged,
+            bump,
+            on_plan: false,
+            max_total_spend: Subscription::NO_SPEND_LIMIT,
+        };
+        self.svm
+            .set_anchor_account(address, &stored, 8 + Subscription::INIT_SPACE);
+        address
+    }
+
+    fn create_loyalty(&self, earn_points: u64) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CreateLoyalty {
+                loyalty: self.loyalty,
+                merchant: self.merchant.pubkey(),
+                payer: self.payer.pubkey(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CreateLoyalty {
+                earn_authority: self.earn_authority.pubkey(),
+                earn_points,
+                per_amount: USDC,
+                min_redemption: 500,
+            }
+            .data(),
+        }
+    }
+
+    fn join(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::Join {
+                loyalty: self.loyalty,
+                member: self.member,
+                owner: self.owner.pubkey(),
+                payer: self.payer.pubkey(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::Join {}.data(),
+        }
+    }
+
+    fn earn(&self, amount: u64) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::Earn {
+                loyalty: self.loyalty,
+                member: self.member,
+                earn_authority: self.earn_authority.pubkey(),
+            }
+            .to_account_metas(None),
+            data: instruction::Earn { amount }.data(),
+        }
+    }
+
+    fn earn_from_subscription(&self, subscription: Pubkey) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::EarnFromSubscription {
+                loyalty: self.loyalty,
+                member: self.member,
+                subscription,
+            }
+            .to_account_metas(None),
+            data: instruction::EarnFromSubscription {}.data(),
+        }
+    }
+
+    fn redeem(&self, points: u64) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::Redeem {
+                loyalty: self.loyalty,
+                member: self.member,
+                owner: self.owner.pubkey(),
+            }
+            .to_account_metas(None),
+            data: instruction::Redeem {
+                points,
+                reward_id: 7,
+            }
+            .data(),
+        }
+    }
+
+    fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TransactionResult {
+        let payer = self.payer.insecure_clone();
+        self.svm.send_instructions(&[instruction], &payer, signers)
+    }
+
+    fn send_as_earn_authority(&mut self, instruction: Instruction) -> TransactionResult {
+        let earn_authority = self.earn_authority.insecure_clone();
+        self.send(instruction, &[&earn_authority])
+    }
+
+    fn send_as_owner(&mut self, instruction: Instruction) -> TransactionResult {
+        let owner = self.owner.insecure_clone();
+        self.send(instruction, &[&owner])
+    }
+
+    fn member_account(&self) -> Member {
+        self.svm.get_anchor_account(&self.member).unwrap()
+    }
+
+    fn loyalty_account(&self) -> Loyalty {
+        self.svm.get_anchor_account(&self.loyalty).unwrap()
+    }
+}
+
+#[test]
+fn merchants_create_a_program_and_customers_join() {
+    let mut fx = Fixture::new();
+    fx.svm.remove_account(&fx.loyalty);
+    fx.svm.remove_account(&fx.member);
+    let merchant = fx.merchant.insecure_clone();
+    let result = fx.send(fx.create_loyalty(0), &[&merchant]);
+    assert_error(result, ErrorCode::InvalidRate);
+
+    let result = fx.send(fx.create_loyalty(10), &[&merchant]);
+    assert_success(result);
+    let program = fx.loyalty_account();
+    assert_eq!(program.merchant, merchant.pubkey());
+    assert_eq!(program.earn_authority, fx.earn_authority.pubkey());
+    assert_eq!(program.points_for(USDC).unwrap(), 10);
+
+    let result = fx.send_as_owner(fx.join());
+    assert_success(result);
+    let member = fx.member_account();
+    assert_eq!(member.loyalty, fx.loyalty);
+    assert_eq!(member.owner, fx.owner.pubkey());
+    assert_eq!(member.balance, 0);
+}
+
+#[test]
+fn earn_authority_awards_points() {
+    let mut fx = Fixture::new();
+
+    let impostor = Keypair::new();
+    let mut forged = fx.earn(25 * USDC);
+    forged.accounts[2].pubkey = impostor.pubkey();
+    let result = fx.send(forged, &[&impostor]);
+    assert_error(result, AnchorErrorCode::ConstraintHasOne);
+
+    let result = fx.send_as_earn_authority(fx.earn(0));
+    assert_error(result, ErrorCode::InvalidAmount);
+
+    let result = fx.send_as_earn_authority(fx.earn(25 * USDC));
+    assert!(result.is_ok(), "{result:#?}");
+    assert_eq!(fx.member_account().balance, 250);
+    assert_eq!(fx.loyalty_account().points_issued, 250);
+}
+
+#[test]
+fn subscription_charges_earn_points_once() {
+    let mut fx = Fixture::new();
+    let owner = fx.owner.pubkey();
+    let merchant = fx.merchant.pubkey();
+
+    let elsewhere = fx.subscribe(owner, Pubkey::new_unique(), 30 * USDC);
+    let result = fx.send(fx.earn_from_subscription(elsewhere), &[]);
+    assert_error(result, ErrorCode::SubscriptionMismatch);
+
+    let someone_else = fx.subscribe(Pubkey::new_unique(), merchant, 30 * USDC);
+    let result = fx.send(fx.earn_from_subscription(someone_else), &[]);
+    assert_error(result, ErrorCode::SubscriptionMismatch);
+
+    // Permissionless: only the fee payer signs
+    let subscription = fx.subscribe(owner, merchant, 30 * USDC);
+    let result = fx.send(fx.earn_from_subscription(subscription), &[]);
+    assert!(result.is_ok(), "{result:#?}");
+    let account = fx.member_account();
+    assert_eq!(account.balance, 300);
+    assert_eq!(account.subscription_credited, 30 * USDC);
+
+    fx.svm.expire_blockhash();
+    let result = fx.send(fx.earn_from_subscription(subscription), &[]);
+    assert_error(result, ErrorCode::NothingToCredit);
+
+    // The next charge lands; only it is credited
+    fx.subscribe(owner, merchant, 40 * USDC);
+    fx.svm.expire_blockhash();
+    let result = fx.send(fx.earn_from_subscription(subscription), &[]);
+    assert!(result.is_ok(), "{result:#?}");
+    assert_eq!(fx.member_account().balance, 400);
+}
+
+#[test]
+fn subscriptions_must_come_from_the_subscription_program() {
+    let mut fx = Fixture::new();
+    let subscription = fx.subscribe(fx.owner.pubkey(), fx.merchant.pubkey(), 30 * USDC);
+    let mut forged = fx.svm.get_account(&subscription).unwrap();
+    forged.owner = PROGRAM_ID;
+    fx.svm.set_account(subscription, forged);
+
+    let result = fx.send(fx.earn_from_subscription(subscription), &[]);
+    assert_error(result, AnchorErrorCode::AccountOwnedByWrongProgram);
+}
+
+#[test]
+fn members_redeem_their_own_points() {
+    let mut fx = Fixture::new();
+    let result = fx.send_as_earn_authority(fx.earn(100 * USDC));
+    assert!(result.is_ok(), "{result:#?}");
+
+    let impostor = Keypair::new();
+    let mut forged = fx.redeem(500);
+    forged.accounts[2].pubkey = impostor.pubkey();
+    let result = fx.send(forged, &[&impostor]);
+    assert_error(result, AnchorErrorCode::ConstraintSeeds);
+
+    let result = fx.send_as_owner(fx.redeem(100));
+    assert_error(result, ErrorCode::BelowMinimumRedemption);
+
+    let result = fx.send_as_owner(fx.redeem(2_000));
+    assert_error(result, ErrorCode::InsufficientPoints);
+
+    let result = fx.send_as_owner(fx.redeem(600));
+    assert!(result.is_ok(), "{result:#?}");
+    assert_eq!(fx.member_account().balance, 400);
+    assert_eq!(fx.loyalty_account().points_redeemed, 600);
+}
+
+#[test]
+fn merchant_changes_the_rate() {
+    let mut fx = Fixture::new();
+    let backend = Pubkey::new_unique();
+    let update = |fx: &Fixture, merchant: Pubkey, earn_points: u64| Instruction {
+        program_id: PROGRAM_ID,
+        accounts: accounts::UpdateLoyalty {
+            loyalty: fx.loyalty,
+            merchant,
+        }
+        .to_account_metas(None),
+        data: instruction::UpdateLoyalty {
+            earn_authority: backend,
+            earn_points,
+            per_amount: USDC,
+            min_redemption: 0,
+        }
+        .data(),
+    };
+
+    let impostor = Keypair::new();
+    let result = fx.send(update(&fx, impostor.pubkey(), 20), &[&impostor]);
+    assert_error(result, AnchorErrorCode::ConstraintSeeds);
+
+    let merchant = fx.merchant.insecure_clone();
+    let result = fx.send(update(&fx, merchant.pubkey(), 0), &[&merchant]);
+    assert_error(result, ErrorCode::InvalidRate);
+
+    let result = fx.send(update(&fx, merchant.pubkey(), 20), &[&merchant]);
+    assert!(result.is_ok(), "{result:#?}");
+    let config = fx.loyalty_account();
+    assert_eq!(config.earn_points, 20);
+    assert_eq!(config.earn_authority, backend);
+    assert_eq!(config.min_redemption, 0);
+
+    // The previous earn authority is no longer accepted
+    let result = fx.send_as_earn_authority(fx.earn(USDC));
+    assert_error(result, AnchorErrorCode::ConstraintHasOne);
+}
@@ -0,0 +1,812 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use spl_token::instruction as token_instruction;
+use token_utils::token_account;
+
+declare_id!("FPt1DmB21A79E86qHDSnoC2smmTBi1HGoZh89jqEAXLV");
+
+/// Most milestones one contract can hold
+pub const MAX_MILESTONES: usize = 8;
+
+#[program]
+pub mod milestone_escrow {
+    use super::*;
+
+    /// Client locks the sum of `amounts`, one per milestone, in a vault owned
+    /// by the contract PDA. After the freelancer submits a milestone the
+    /// client has `review_period` seconds to release or dispute it.
+    pub fn create_contract(
+        ctx: Context<CreateContract>,
+        contract_id: u64,
+        amounts: Vec<u64>,
+        review_period: i64,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let total = Contract::check_params(&amounts, review_period)?;
+
+        let contract_key = ctx.accounts.contract.key();
+        let mint_key = ctx.accounts.mint.key();
+        let vault = token_account(&ctx.accounts.vault, ErrorCode::InvalidTokenAccount)?;
+        require_keys_eq!(vault.owner, contract_key, ErrorCode::InvalidTokenAccount);
+        require_keys_eq!(vault.mint, mint_key, ErrorCode::InvalidTokenAccount);
+        let freelancer_account = token_account(
+            &ctx.accounts.freelancer_token_account,
+            ErrorCode::InvalidTokenAccount,
+        )?;
+        require_keys_eq!(
+            freelancer_account.owner,
+            ctx.accounts.freelancer.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+        require_keys_eq!(
+            freelancer_account.mint,
+            mint_key,
+            ErrorCode::InvalidTokenAccount
+        );
+
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.client_token_account.key(),
+            &ctx.accounts.vault.key(),
+            &ctx.accounts.client.key(),
+            &[],
+            total,
+        )?;
+
+        invoke(
+            &transfer_ix,
+            &[
+                ctx.accounts.client_token_account.to_account_info(),
+                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.client.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        let contract = &mut ctx.accounts.contract;
+        contract.client = ctx.accounts.client.key();
+        contract.freelancer = ctx.accounts.freelancer.key();
+        contract.arbiter = ctx.accounts.arbiter.key();
+        contract.mint = mint_key;
+        contract.client_token_account = ctx.accounts.client_token_account.key();
+        contract.freelancer_token_account = ctx.accounts.freelancer_token_account.key();
+        contract.vault = ctx.accounts.vault.key();
+        contract.contract_id = contract_id;
+        contract.milestones = amounts
+            .iter()
+            .map(|&amount| Milestone::new(amount))
+            .collect();
+        contract.review_period = review_period;
+        contract.created_at = clock.unix_timestamp;
+        contract.bump = ctx.bumps.contract;
+
+        emit!(ContractCreated {
+            contract: contract_key,
+            client: contract.client,
+            freelancer: contract.freelancer,
+            arbiter: contract.arbiter,
+            milestones: amounts.len() as u8,
+            total,
+            review_period,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("Contract funded: {} tokens", total);
+        msg!("{} milestones", amounts.len());
+
+        Ok(())
+    }
+
+    /// Freelancer submits milestone `index` for review
+    pub fn submit_milestone(ctx: Context<SubmitMilestone>, index: u8) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let contract = &mut ctx.accounts.contract;
+        contract.record_submission(index, now)?;
+
+        emit!(MilestoneSubmitted {
+            contract: contract.key(),
+            index,
+            review_ends_at: contract.review_ends_at(index).unwrap_or(now),
+            timestamp: now,
+        });
+
+        msg!("Milestone {} submitted", index);
+
+        Ok(())
+    }
+
+    /// Client pays `amount` of milestone `index` to the freelancer, before or
+    /// after submission. Less than what is left releases part of it.
+    pub fn release_milestone(ctx: Context<Payout>, index: u8, amount: u64) -> Result<()> {
+        let contract = &mut ctx.accounts.contract;
+        require_keys_eq!(
+            ctx.accounts.signer.key(),
+            contract.client,
+            ErrorCode::Unauthorized
+        );
+        contract.record_release(index, amount)?;
+        pay_out(ctx, index, amount, 0)
+    }
+
+    /// Freelancer returns what is left of milestone `index` to the client
+    pub fn refund_milestone(ctx: Context<Payout>, index: u8) -> Result<()> {
+        let contract = &mut ctx.accounts.contract;
+        require_keys_eq!(
+            ctx.accounts.signer.key(),
+            contract.freelancer,
+            ErrorCode::Unauthorized
+        );
+        let amount = contract.record_refund(index)?;
+        pay_out(ctx, index, 0, amount)
+    }
+
+    /// Client or freelancer freezes milestone `index` until the arbiter
+    /// resolves it. The other milestones carry on.
+    pub fn open_dispute(ctx: Context<OpenDispute>, index: u8) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let opened_by = ctx.accounts.signer.key();
+        let contract = &mut ctx.accounts.contract;
+        contract.record_dispute(index, &opened_by, now)?;
+
+        emit!(MilestoneDisputed {
+            contract: contract.key(),
+            index,
+            opened_by,
+            timestamp: now,
+        });
+
+        msg!("Dispute on milestone {} opened by {}", index, opened_by);
+
+        Ok(())
+    }
+
+    /// Arbiter splits what is left of a disputed milestone: `freelancer_share`
+    /// to the freelancer, the rest back to the client
+    pub fn resolve_dispute(ctx: Context<Payout>, index: u8, freelancer_share: u64) -> Result<()> {
+        let contract = &mut ctx.accounts.contract;
+        require_keys_eq!(
+            ctx.accounts.signer.key(),
+            contract.arbiter,
+            ErrorCode::Unauthorized
+        );
+        let (to_freelancer, to_client) = contract.record_resolution(index, freelancer_share)?;
+        pay_out(ctx, index, to_freelancer, to_client)
+    }
+
+    /// Permissionless crank for keepers: release what is left of a submitted
+    /// milestone once its review period has passed without a dispute
+    pub fn settle_milestone(ctx: Context<Payout>, index: u8) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let amount = ctx.accounts.contract.record_settlement(index, now)?;
+        pay_out(ctx, index, amount, 0)
+    }
+
+    /// Close a contract whose milestones are all settled, returning anything
+    /// left in the vault and all rent to the client. Permissionless.
+    pub fn close_contract(ctx: Context<CloseContract>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let contract = &ctx.accounts.contract;
+        require!(contract.is_finished(), ErrorCode::ContractNotFinished);
+
+        let contract_id = contract.contract_id.to_le_bytes();
+        let seeds = &[
+            b"contract",
+            contract.client.as_ref(),
+            contract.freelancer.as_ref(),
+            contract_id.as_ref(),
+            &[contract.bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+        let contract_info = ctx.accounts.contract.to_account_info();
+
+        let leftover = token_account(&ctx.accounts.vault, ErrorCode::InvalidTokenAccount)?.amount;
+        if leftover > 0 {
+            let transfer_ix = token_instruction::transfer(
+                &ctx.accounts.token_program.key(),
+                &ctx.accounts.vault.key(),
+                &ctx.accounts.client_token_account.key(),
+                &contract_info.key(),
+                &[],
+                leftover,
+            )?;
+            invoke_signed(
+                &transfer_ix,
+                &[
+                    ctx.accounts.vault.to_account_info(),
+                    ctx.accounts.client_token_account.to_account_info(),
+                    contract_info.clone(),
+                    ctx.accounts.token_program.to_account_info(),
+                ],
+                signer_seeds,
+            )?;
+        }
+
+        let close_ix = token_instruction::close_account(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.vault.key(),
+            &ctx.accounts.client.key(),
+            &contract_info.key(),
+            &[],
+        )?;
+        invoke_signed(
+            &close_ix,
+            &[
+                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.client.to_account_info(),
+                contract_info.clone(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+            signer_seeds,
+        )?;
+
+        emit!(ContractClosed {
+            contract: contract.key(),
+            released: contract.total_released(),
+            refunded: contract.total_refunded(),
+            timestamp: now,
+        });
+
+        msg!("Contract closed");
+        msg!(
+            "Released to freelancer: {} tokens",
+            contract.total_released()
+        );
+
+        Ok(())
+    }
+}
+
+/// Pay `to_freelancer` and `to_client` out of the vault for milestone `index`
+fn pay_out(ctx: Context<Payout>, index: u8, to_freelancer: u64, to_client: u64) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let contract = &ctx.accounts.contract;
+
+    let contract_id = contract.contract_id.to_le_bytes();
+    let seeds = &[
+        b"contract",
+        contract.client.as_ref(),
+        contract.freelancer.as_ref(),
+        contract_id.as_ref(),
+        &[contract.bump],
+    ];
+    let signer_seeds = &[&seeds[..]];
+    let contract_info = ctx.accounts.contract.to_account_info();
+
+    for (destination, amount) in [
+        (&ctx.accounts.freelancer_token_account, to_freelancer),
+        (&ctx.accounts.client_token_account, to_client),
+    ] {
+        if amount == 0 {
+            continue;
+        }
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.vault.key(),
+            &destination.key(),
+            &contract_info.key(),
+            &[],
+            amount,
+        )?;
+        invoke_signed(
+            &transfer_ix,
+            &[
+                ctx.accounts.vault.to_account_info(),
+                destination.to_account_info(),
+                contract_info.clone(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+            signer_seeds,
+        )?;
+    }
+
+    let milestone = contract.milestone(index)?;
+    emit!(MilestonePaid {
+        contract: contract.key(),
+        index,
+        paid_by: ctx.accounts.signer.key(),
+        to_freelancer,
+        to_client,
+        remaining: milestone.remaining(),
+        timestamp: now,
+    });
+
+    msg!("Milestone {} paid out", index);
+    msg!("To freelancer: {} tokens", to_freelancer);
+    msg!("To client: {} tokens", to_client);
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(contract_id: u64, amounts: Vec<u64>)]
+pub struct CreateContract<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Contract::INIT_SPACE,
+        seeds = [
+            b"contract",
+            client.key().as_ref(),
+            freelancer.key().as_ref(),
+            contract_id.to_le_bytes().as_ref(),
+        ],
+        bump
+    )]
+    pub contract: Account<'info, Contract>,
+
+    pub client: Signer<'info>,
+
+    /// CHECK: Freelancer wallet
+    pub freelancer: UncheckedAccount<'info>,
+
+    /// CHECK: Resolves disputes; any wallet both parties trust
+    pub arbiter: UncheckedAccount<'info>,
+
+    /// CHECK: Token mint (USDC)
+    pub mint: UncheckedAccount<'info>,
+
+    /// CHECK: Client's token account, debited by the token program
+    #[account(mut)]
+    pub client_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Freelancer's token account, checked in the handler
+    pub freelancer_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Token account owned by the contract PDA (usually its ATA)
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    /// Rent payer, e.g. the relayer's paymaster
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SubmitMilestone<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"contract",
+            contract.client.as_ref(),
+            contract.freelancer.as_ref(),
+            contract.contract_id.to_le_bytes().as_ref(),
+        ],
+        bump = contract.bump,
+        has_one = freelancer
+    )]
+    pub contract: Account<'info, Contract>,
+
+    pub freelancer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct OpenDispute<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"contract",
+            contract.client.as_ref(),
+            contract.freelancer.as_ref(),
+            contract.contract_id.to_le_bytes().as_ref(),
+        ],
+        bump = contract.bump,
+    )]
+    pub contract: Account<'info, Contract>,
+
+    /// Client or freelancer
+    pub signer: Signer<'info>,
+}
+
+/// Shared by every instruction that pays out of one milestone; each checks
+/// who `signer` must be
+#[derive(Accounts)]
+pub struct Payout<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"contract",
+            contract.client.as_ref(),
+            contract.freelancer.as_ref(),
+            contract.contract_id.to_le_bytes().as_ref(),
+        ],
+        bump = contract.bump,
+    )]
+    pub contract: Account<'info, Contract>,
+
+    pub signer: Signer<'info>,
+
+    /// CHECK: Client's token account
+    #[account(
+        mut,
+        constraint = client_token_account.key() == contract.client_token_account
+    )]
+    pub client_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Freelancer's token account
+    #[account(
+        mut,
+        constraint = freelancer_token_account.key() == contract.freelancer_token_account
+    )]
+    pub freelancer_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Contract vault
+    #[account(
+        mut,
+        constraint = vault.key() == contract.vault
+    )]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseContract<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"contract",
+            contract.client.as_ref(),
+            contract.freelancer.as_ref(),
+            contract.contract_id.to_le_bytes().as_ref(),
+        ],
+        bump = contract.bump,
+        has_one = client,
+        close = client
+    )]
+    pub contract: Account<'info, Contract>,
+
+    /// CHECK: Receives the contract and vault rent
+    #[account(mut)]
+    pub client: UncheckedAccount<'info>,
+
+    /// CHECK: Client's token account
+    #[account(
+        mut,
+        constraint = client_token_account.key() == contract.client_token_account
+    )]
+    pub client_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Contract vault, closed here
+    #[account(
+        mut,
+        constraint = vault.key() == contract.vault
+    )]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub enum MilestoneStatus {
+    /// Funded, not yet submitted
+    Pending,
+    /// Waiting for the client's review
+    Submitted,
+    /// Frozen for the arbiter
+    Disputed,
+    /// Fully paid out one way or the other
+    Settled,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub struct Milestone {
+    pub amount: u64,
+    /// Paid to the freelancer so far
+    pub released: u64,
+    /// Returned to the client
+    pub refunded: u64,
+    pub status: MilestoneStatus,
+    pub submitted_at: Option<i64>,
+}
+
+impl Milestone {
+    pub fn new(amount: u64) -> Self {
+        Self {
+            amount,
+            released: 0,
+            refunded: 0,
+            status: MilestoneStatus::Pending,
+            submitted_at: None,
+        }
+    }
+
+    /// Still in the vault for this milestone
+    pub fn remaining(&self) -> u64 {
+        self.amount
+            .saturating_sub(self.released)
+            .saturating_sub(self.refunded)
+    }
+
+    fn is_open(&self) -> bool {
+        matches!(
+            self.status,
+            MilestoneStatus::Pending | MilestoneStatus::Submitted
+        )
+    }
+
+    fn settle_if_empty(&mut self) {
+        if self.remaining() == 0 {
+            self.status = MilestoneStatus::Settled;
+        }
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Contract {
+    pub client: Pubkey,
+    pub freelancer: Pubkey,
+    pub arbiter: Pubkey,
+    pub mint: Pubkey,
+    pub client_token_account: Pubkey,
+    pub freelancer_token_account: Pubkey,
+    pub vault: Pubkey,
+    pub contract_id: u64,
+    #[max_len(MAX_MILESTONES)]
+    pub milestones: Vec<Milestone>,
+    pub review_period: i64,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl Contract {
+    /// Validate the milestones and return their total
+    pub fn check_params(amounts: &[u64], review_period: i64) -> Result<u64> {
+        require!(
+            !amounts.is_empty() && amounts.len() <= MAX_MILESTONES,
+            ErrorCode::InvalidMilestones
+        );
+        require!(
+            amounts.iter().all(|&amount| amount > 0),
+            ErrorCode::InvalidAmount
+        );
+        require!(review_period > 0, ErrorCode::InvalidReviewPeriod);
+        amounts.iter().try_fold(0u64, |total, &amount| {
+            total
+                .checked_add(amount)
+                .ok_or(error!(ErrorCode::ArithmeticOverflow))
+        })
+    }
+
+    pub fn milestone(&self, index: u8) -> Result<&Milestone> {
+        self.milestones
+            .get(index as usize)
+            .ok_or(error!(ErrorCode::InvalidMilestone))
+    }
+
+    fn milestone_mut(&mut self, index: u8) -> Result<&mut Milestone> {
+        self.milestones
+            .get_mut(index as usize)
+            .ok_or(error!(ErrorCode::InvalidMilestone))
+    }
+
+    /// End of the client's window to dispute a submitted milestone
+    pub fn review_ends_at(&self, index: u8) -> Option<i64> {
+        let milestone = self.milestones.get(index as usize)?;
+        milestone
+            .submitted_at
+            .map(|submitted_at| submitted_at.saturating_add(self.review_period))
+    }
+
+    fn review_over(&self, index: u8, now: i64) -> bool {
+        self.review_ends_at(index).is_some_and(|end| now >= end)
+    }
+
+    pub fn record_submission(&mut self, index: u8, now: i64) -> Result<()> {
+        let milestone = self.milestone_mut(index)?;
+        require!(
+            milestone.status == MilestoneStatus::Pending,
+            ErrorCode::InvalidState
+        );
+        milestone.status = MilestoneStatus::Submitted;
+        milestone.submitted_at = Some(now);
+        Ok(())
+    }
+
+    /// Release `amount` of an open milestone to the freelancer
+    pub fn record_release(&mut self, index: u8, amount: u64) -> Result<()> {
+        let milestone = self.milestone_mut(index)?;
+        require!(milestone.is_open(), ErrorCode::InvalidState);
+        require!(
+            amount > 0 && amount <= milestone.remaining(),
+            ErrorCode::InvalidAmount
+        );
+        milestone.released = milestone
+            .released
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        milestone.settle_if_empty();
+        Ok(())
+    }
+
+    /// Refund what is left of an open milestone. Returns the amount.
+    pub fn record_refund(&mut self, index: u8) -> Result<u64> {
+        let milestone = self.milestone_mut(index)?;
+        require!(milestone.is_open(), ErrorCode::InvalidState);
+        let amount = milestone.remaining();
+        milestone.refunded = milestone
+            .refunded
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        milestone.status = MilestoneStatus::Settled;
+        Ok(amount)
+    }
+
+    /// Either party may dispute an open milestone until the crank could
+    /// settle it
+    pub fn record_dispute(&mut self, index: u8, opened_by: &Pubkey, now: i64) -> Result<()> {
+        require!(
+            *opened_by == self.client || *opened_by == self.freelancer,
+            ErrorCode::Unauthorized
+        );
+        let review_over = self.review_over(index, now);
+        let milestone = self.milestone_mut(index)?;
+        require!(milestone.is_open(), ErrorCode::InvalidState);
+        require!(!review_over, ErrorCode::ReviewPeriodEnded);
+        milestone.status = MilestoneStatus::Disputed;
+        Ok(())
+    }
+
+    /// Split what is left of a disputed milestone. Returns (to freelancer,
+    /// to client).
+    pub fn record_resolution(&mut self, index: u8, freelancer_share: u64) -> Result<(u64, u64)> {
+        let milestone = self.milestone_mut(index)?;
+        require!(
+            milestone.status == MilestoneStatus::Disputed,
+            ErrorCode::InvalidState
+        );
+        let remaining = milestone.remaining();
+        require!(freelancer_share <= remaining, ErrorCode::InvalidSplit);
+        let to_client = remaining - freelancer_share;
+        milestone.released = milestone
+            .released
+            .checked_add(freelancer_share)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        milestone.refunded = milestone
+            .refunded
+            .checked_add(to_client)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        milestone.status = MilestoneStatus::Settled;
+        Ok((freelancer_share, to_client))
+    }
+
+    /// Release what is left of a submitted milestone whose review is over.
+    /// Returns the amount.
+    pub fn record_settlement(&mut self, index: u8, now: i64) -> Result<u64> {
+        let review_over = self.review_over(index, now);
+        let milestone = self.milestone_mut(index)?;
+        require!(
+            milestone.status == MilestoneStatus::Submitted,
+            ErrorCode::InvalidState
+        );
+        require!(review_over, ErrorCode::ReviewPeriodActive);
+        let amount = milestone.remaining();
+        milestone.released = milestone
+            .released
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        milestone.status = MilestoneStatus::Settled;
+        Ok(amount)
+    }
+
+    /// Submitted milestones a keeper can settle at `now`
+    pub fn due_settlements(&self, now: i64) -> Vec<u8> {
+        (0..self.milestones.len() as u8)
+            .filter(|&index| {
+                self.milestones[index as usize].status == MilestoneStatus::Submitted
+                    && self.review_over(index, now)
+            })
+            .collect()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.milestones
+            .iter()
+            .all(|milestone| milestone.status == MilestoneStatus::Settled)
+    }
+
+    pub fn total_released(&self) -> u64 {
+        self.milestones
+            .iter()
+            .map(|milestone| milestone.released)
+            .sum()
+    }
+
+    pub fn total_refunded(&self) -> u64 {
+        self.milestones
+            .iter()
+            .map(|milestone| milestone.refunded)
+            .sum()
+    }
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContractCreated {
+    pub contract: Pubkey,
+    pub client: Pubkey,
+    pub freelancer: Pubkey,
+    pub arbiter: Pubkey,
+    pub milestones: u8,
+    pub total: u64,
+    pub review_period: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MilestoneSubmitted {
+    pub contract: Pubkey,
+    pub index: u8,
+    pub review_ends_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MilestoneDisputed {
+    pub contract: Pubkey,
+    pub index: u8,
+    pub opened_by: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MilestonePaid {
+    pub contract: Pubkey,
+    pub index: u8,
+    pub paid_by: Pubkey,
+    pub to_freelancer: u64,
+    pub to_client: u64,
+    /// Left in the vault for this milestone
+    pub remaining: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContractClosed {
+    pub contract: Pubkey,
+    pub released: u64,
+    pub refunded: u64,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Amount must be greater than zero and at most what is left of the milestone")]
+    InvalidAmount,
+    #[msg("A contract needs between one and eight milestones")]
+    InvalidMilestones,
+    #[msg("Review period must be positive")]
+    InvalidReviewPeriod,
+    #[msg("Invalid token account - wrong program, owner or mint")]
+    InvalidTokenAccount,
+    #[msg("No milestone at that index")]
+    InvalidMilestone,
+    #[msg("Milestone is not in a state that allows this")]
+    InvalidState,
+    #[msg("Review period has ended - the milestone can only be settled")]
+    ReviewPeriodEnded,
+    #[msg("Milestone is still under review")]
+    ReviewPeriodActive,
+    #[msg("Signer is not allowed to do this")]
+    Unauthorized,
+    #[msg("Freelancer share exceeds what is left of the milestone")]
+    InvalidSplit,
+    #[msg("Every milestone must be settled first")]
+    ContractNotFinished,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
@@ -0,0 +1,530 @@
+//! Native tests for the milestone escrow program.
+//!
+//! Milestone state and payout amounts are pure methods on `Contract` and are
+//! tested directly. Instruction tests run end to end and check where each
+//! payout went.
+
+use anchor_lang::error::ErrorCode as AnchorErrorCode;
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, Space, ToAccountMetas};
+use milestone_escrow::{
+    accounts, instruction, Contract, ErrorCode, Milestone, MilestoneStatus, ID as PROGRAM_ID,
+    MAX_MILESTONES,
+};
+use test_harness::{assert_error, assert_success, Keypair, Signer, TestSvm, TransactionResult};
+
+const AMOUNTS: [u64; 3] = [10_000_000, 20_000_000, 30_000_000];
+const CONTRACT_ID: u64 = 7;
+const DAY: i64 = 86_400;
+
+fn contract() -> Contract {
+    Contract {
+        client: Pubkey::new_unique(),
+        freelancer: Pubkey::new_unique(),
+        arbiter: Pubkey::new_unique(),
+        mint: Pubkey::new_unique(),
+        client_token_account: Pubkey::new_unique(),
+        freelancer_token_account: Pubkey::new_unique(),
+        vault: Pubkey::new_unique(),
+        contract_id: CONTRACT_ID,
+        milestones: AMOUNTS
+            .iter()
+            .map(|&amount| Milestone::new(amount))
+            .collect(),
+        review_period: 3 * DAY,
+        created_at: 0,
+        bump: 255,
+    }
+}
+
+#[test]
+fn milestones_are_validated_and_summed() {
+    assert_eq!(Contract::check_params(&AMOUNTS, DAY).unwrap(), 60_000_000);
+    assert_eq!(
+        Contract::check_params(&[], DAY).unwrap_err(),
+        ErrorCode::InvalidMilestones.into()
+    );
+    assert_eq!(
+        Contract::check_params(&[1; MAX_MILESTONES + 1], DAY).unwrap_err(),
+        ErrorCode::InvalidMilestones.into()
+    );
+    assert_eq!(
+        Contract::check_params(&[1, 0], DAY).unwrap_err(),
+        ErrorCode::InvalidAmount.into()
+    );
+    assert_eq!(
+        Contract::check_params(&AMOUNTS, 0).unwrap_err(),
+        ErrorCode::InvalidReviewPeriod.into()
+    );
+    assert_eq!(
+        Contract::check_params(&[u64::MAX, 1], DAY).unwrap_err(),
+        ErrorCode::ArithmeticOverflow.into()
+    );
+}
+
+#[test]
+fn partial_releases_settle_a_milestone_once_it_is_paid() {
+    let mut contract = contract();
+    contract.record_release(1, 5_000_000).unwrap();
+    assert_eq!(contract.milestones[1].remaining(), 15_000_000);
+    assert_eq!(contract.milestones[1].status, MilestoneStatus::Pending);
+    assert_eq!(
+        contract.record_release(1, 15_000_001).unwrap_err(),
+        ErrorCode::InvalidAmount.into()
+    );
+    assert_eq!(
+        contract.record_release(1, 0).unwrap_err(),
+        ErrorCode::InvalidAmount.into()
+    );
+
+    // The freelancer refunds the rest of a part-paid milestone
+    assert_eq!(contract.record_refund(1).unwrap(), 15_000_000);
+    assert_eq!(contract.milestones[1].status, MilestoneStatus::Settled);
+    assert_eq!(
+        contract.record_release(1, 1).unwrap_err(),
+        ErrorCode::InvalidState.into()
+    );
+
+    contract.record_release(0, 10_000_000).unwrap();
+    assert_eq!(contract.milestones[0].status, MilestoneStatus::Settled);
+    assert_eq!(
+        contract.record_release(3, 1).unwrap_err(),
+        ErrorCode::InvalidMilestone.into()
+    );
+    assert!(!contract.is_finished());
+    contract.record_refund(2).unwrap();
+    assert!(contract.is_finished());
+    assert_eq!(contract.total_released(), 15_000_000);
+    assert_eq!(contract.total_refunded(), 45_000_000);
+}
+
+#[test]
+fn submitted_milestones_settle_after_review() {
+    let mut contract = contract();
+    assert_eq!(
+        contract.record_settlement(0, 0).unwrap_err(),
+        ErrorCode::InvalidState.into()
+    );
+    contract.record_submission(0, 100).unwrap();
+    assert_eq!(
+        contract.record_submission(0, 100).unwrap_err(),
+        ErrorCode::InvalidState.into()
+    );
+    assert_eq!(contract.review_ends_at(0), Some(100 + 3 * DAY));
+    assert!(contract.due_settlements(99 + 3 * DAY).is_empty());
+    assert_eq!(
+        contract.record_settlement(0, 99 + 3 * DAY).unwrap_err(),
+        ErrorCode::ReviewPeriodActive.into()
+    );
+
+    // A partial release during review leaves the rest for the crank
+    contract.record_release(0, 4_000_000).unwrap();
+    assert_eq!(contract.due_settlements(100 + 3 * DAY), vec![0]);
+    assert_eq!(
+        contract.record_settlement(0, 100 + 3 * DAY).unwrap(),
+        6_000_000
+    );
+    assert_eq!(contract.milestones[0].released, 10_000_000);
+    assert_eq!(contract.milestones[0].status, MilestoneStatus::Settled);
+}
+
+#[test]
+fn disputes_freeze_one_milestone_until_resolved() {
+    let mut contract = contract();
+    let client = contract.client;
+    assert_eq!(
+        contract
+            .record_dispute(0, &Pubkey::new_unique(), 0)
+            .unwrap_err(),
+        ErrorCode::Unauthorized.into()
+    );
+    contract.record_submission(0, 0).unwrap();
+    assert_eq!(
+        contract.record_dispute(0, &client, 3 * DAY).unwrap_err(),
+        ErrorCode::ReviewPeriodEnded.into()
+    );
+
+    contract.record_release(1, 8_000_000).unwrap();
+    contract.record_dispute(1, &client, 0).unwrap();
+    assert_eq!(
+        contract.record_release(1, 1).unwrap_err(),
+        ErrorCode::InvalidState.into()
+    );
+    assert_eq!(
+        contract.record_refund(1).unwrap_err(),
+        ErrorCode::InvalidState.into()
+    );
+    assert_eq!(
+        contract.record_resolution(0, 0).unwrap_err(),
+        ErrorCode::InvalidState.into()
+    );
+    assert_eq!(
+        contract.record_resolution(1, 12_000_001).unwrap_err(),
+        ErrorCode::InvalidSplit.into()
+    );
+    assert_eq!(
+        contract.record_resolution(1, 2_000_000).unwrap(),
+        (2_000_000, 10_000_000)
+    );
+    assert_eq!(contract.milestones[1].released, 10_000_000);
+    assert_eq!(contract.milestones[1].refunded, 10_000_000);
+    assert_eq!(contract.milestones[1].status, MilestoneStatus::Settled);
+
+    // The other milestones are untouched
+    assert_eq!(contract.milestones[2].status, MilestoneStatus::Pending);
+}
+
+struct Fixture {
+    svm: TestSvm,
+    payer: Keypair,
+    client: Keypair,
+    freelancer: Keypair,
+    arbiter: Keypair,
+    contract: Pubkey,
+    state: Contract,
+}
+
+impl Fixture {
+    /// A funded contract with `milestones`, created at the harness's default
+    /// time
+    fn new(milestones: Vec<Milestone>) -> Self {
+        let (mut svm, payer) = TestSvm::for_program(PROGRAM_ID, milestone_escrow::entry);
+
+        let client = Keypair::new();
+        let freelancer = Keypair::new();
+        let arbiter = Keypair::new();
+
+        let mint = svm.create_mint(&Pubkey::new_unique(), 6);
+        let (address, bump) = Pubkey::find_program_address(
+            &[
+                b"contract",
+                client.pubkey().as_ref(),
+                freelancer.pubkey().as_ref(),
+                &CONTRACT_ID.to_le_bytes(),
+            ],
+            &PROGRAM_ID,
+        );
+        let funded = milestones.iter().map(Milestone::remaining).sum();
+        let state = Contract {
+            client: client.pubkey(),
+            freelancer: freelancer.pubkey(),
+            arbiter: arbiter.pubkey(),
+            mint,
+            client_token_account: svm.create_associated_token_account(&client.pubkey(), &mint, 0),
+            freelancer_token_account: svm.create_associated_token_account(
+                &freelancer.pubkey(),
+                &mint,
+                0,
+            ),
+            vault: svm.create_associated_token_account(&address, &mint, funded),
+            milestones,
+            created_at: svm.clock().unix_timestamp,
+            bump,
+            ..contract()
+        };
+        svm.set_anchor_account(address, &state, 8 + Contract::INIT_SPACE);
+
+        Self {
+            svm,
+            payer,
+            client,
+            freelancer,
+            arbiter,
+            contract: address,
+            state,
+        }
+    }
+
+    fn funded() -> Self {
+        Self::new(contract().milestones)
+    }
+
+    fn create(&self, amounts: Vec<u64>) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CreateContract {
+                contract: self.contract,
+                client: self.state.client,
+                freelancer: self.state.freelancer,
+                arbiter: self.state.arbiter,
+                mint: self.state.mint,
+                client_token_account: self.state.client_token_account,
+                freelancer_token_account: self.state.freelancer_token_account,
+                vault: self.state.vault,
+                token_program: spl_token::ID,
+                payer: self.payer.pubkey(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CreateContract {
+                contract_id: CONTRACT_ID,
+                amounts,
+                review_period: self.state.review_period,
+            }
+            .data(),
+        }
+    }
+
+    fn submit(&self, freelancer: &Pubkey, index: u8) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::SubmitMilestone {
+                contract: self.contract,
+                freelancer: *freelancer,
+            }
+            .to_account_metas(None),
+            data: instruction::SubmitMilestone { index }.data(),
+        }
+    }
+
+    fn open_dispute(&self, signer: &Pubkey, index: u8) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::OpenDispute {
+                contract: self.contract,
+                signer: *signer,
+            }
+            .to_account_metas(None),
+            data: instruction::OpenDispute { index }.data(),
+        }
+    }
+
+    fn payout(&self, signer: &Pubkey, data: Vec<u8>) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::Payout {
+                contract: self.contract,
+                signer: *signer,
+                client_token_account: self.state.client_token_account,
+                freelancer_token_account: self.state.freelancer_token_account,
+                vault: self.state.vault,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data,
+        }
+    }
+
+    fn close(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CloseContract {
+                contract: self.contract,
+                client: self.state.client,
+                client_token_account: self.state.client_token_account,
+                vault: self.state.vault,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CloseContract {}.data(),
+        }
+    }
+
+    fn contract_state(&self) -> Contract {
+        self.svm.get_anchor_account(&self.contract).unwrap()
+    }
+
+    /// What the client and the freelancer hold, and what is left in the vault
+    fn balances(&self) -> (u64, u64, u64) {
+        (
+            self.svm.token_balance(&self.state.client_token_account),
+            self.svm.token_balance(&self.state.freelancer_token_account),
+            self.svm.token_balance(&self.state.vault),
+        )
+    }
+
+    fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TransactionResult {
+        let payer = self.payer.insecure_clone();
+        self.svm.send_instructions(&[instruction], &payer, signers)
+    }
+
+    fn submit_first(&mut self) {
+        let freelancer = self.freelancer.insecure_clone();
+        self.send(self.submit(&freelancer.pubkey(), 0), &[&freelancer])
+            .unwrap();
+    }
+}
+
+#[test]
+fn the_client_funds_every_milestone_up_front() {
+    let mut fx = Fixture::funded();
+    let total: u64 = AMOUNTS.iter().sum();
+    fx.svm.remove_account(&fx.contract);
+    fx.svm
+        .transfer_tokens(&fx.state.vault, &fx.state.client_token_account, total);
+    let client = fx.client.insecure_clone();
+    let result = fx.send(fx.create(vec![]), &[&client]);
+    assert_error(result, ErrorCode::InvalidMilestones);
+
+    let mut elsewhere = fx.create(AMOUNTS.to_vec());
+    elsewhere.accounts[6].pubkey = fx.state.client_token_account;
+    let result = fx.send(elsewhere, &[&client]);
+    assert_error(result, ErrorCode::InvalidTokenAccount);
+
+    let result = fx.send(fx.create(AMOUNTS.to_vec()), &[&client]);
+    assert_success(result);
+    assert_eq!(fx.balances(), (0, 0, total));
+    let state = fx.contract_state();
+    assert_eq!(state.arbiter, fx.arbiter.pubkey());
+    assert_eq!(state.milestones, contract().milestones);
+}
+
+#[test]
+fn only_the_freelancer_submits() {
+    let mut fx = Fixture::funded();
+    let client = fx.client.insecure_clone();
+    let result = fx.send(fx.submit(&client.pubkey(), 0), &[&client]);
+    assert_error(result, AnchorErrorCode::ConstraintHasOne);
+
+    let freelancer = fx.freelancer.insecure_clone();
+    let result = fx.send(fx.submit(&freelancer.pubkey(), 3), &[&freelancer]);
+    assert_error(result, ErrorCode::InvalidMilestone);
+
+    fx.submit_first();
+    let state = fx.contract_state();
+    assert_eq!(state.milestones[0].status, MilestoneStatus::Submitted);
+    assert_eq!(
+        state.milestones[0].submitted_at,
+        Some(fx.svm.clock().unix_timestamp)
+    );
+    assert_eq!(state.milestones[1].status, MilestoneStatus::Pending);
+}
+
+#[test]
+fn a_dispute_leaves_only_the_arbiter() {
+    let mut fx = Fixture::funded();
+    fx.submit_first();
+
+    let arbiter = fx.arbiter.insecure_clone();
+    let result = fx.send(fx.open_dispute(&arbiter.pubkey(), 0), &[&arbiter]);
+    assert_error(result, ErrorCode::Unauthorized);
+
+    let client = fx.client.insecure_clone();
+    fx.send(fx.open_dispute(&client.pubkey(), 0), &[&client])
+        .unwrap();
+    assert_eq!(
+        fx.contract_state().milestones[0].status,
+        MilestoneStatus::Disputed
+    );
+
+    let release = instruction::ReleaseMilestone {
+        index: 0,
+        amount: 1,
+    };
+    let result = fx.send(fx.payout(&client.pubkey(), release.data()), &[&client]);
+    assert_error(result, ErrorCode::InvalidState);
+
+    fx.svm.advance_time(30 * DAY);
+    let keeper = Keypair::new();
+    let settle = instruction::SettleMilestone { index: 0 };
+    let result = fx.send(fx.payout(&keeper.pubkey(), settle.data()), &[&keeper]);
+    assert_error(result, ErrorCode::InvalidState);
+
+    let resolve = instruction::ResolveDispute {
+        index: 0,
+        freelancer_share: AMOUNTS[0] / 2,
+    };
+    let result = fx.send(fx.payout(&client.pubkey(), resolve.data()), &[&client]);
+    assert_error(result, ErrorCode::Unauthorized);
+    let result = fx.send(fx.payout(&arbiter.pubkey(), resolve.data()), &[&arbiter]);
+    assert_success(result);
+    let rest = AMOUNTS[1] + AMOUNTS[2];
+    assert_eq!(fx.balances(), (AMOUNTS[0] / 2, AMOUNTS[0] / 2, rest));
+    assert_eq!(
+        fx.contract_state().milestones[0].status,
+        MilestoneStatus::Settled
+    );
+}
+
+#[test]
+fn the_client_releases_and_the_freelancer_refunds() {
+    let mut fx = Fixture::funded();
+    let client = fx.client.insecure_clone();
+    let freelancer = fx.freelancer.insecure_clone();
+    let release = instruction::ReleaseMilestone {
+        index: 1,
+        amount: AMOUNTS[1] / 4,
+    };
+    let refund = instruction::RefundMilestone { index: 1 };
+
+    let result = fx.send(
+        fx.payout(&freelancer.pubkey(), release.data()),
+        &[&freelancer],
+    );
+    assert_error(result, ErrorCode::Unauthorized);
+    let result = fx.send(fx.payout(&client.pubkey(), refund.data()), &[&client]);
+    assert_error(result, ErrorCode::Unauthorized);
+
+    let result = fx.send(fx.payout(&client.pubkey(), release.data()), &[&client]);
+    assert_success(result);
+    let rest = AMOUNTS[0] + AMOUNTS[2];
+    assert_eq!(
+        fx.balances(),
+        (0, AMOUNTS[1] / 4, rest + AMOUNTS[1] * 3 / 4)
+    );
+    let result = fx.send(
+        fx.payout(&freelancer.pubkey(), refund.data()),
+        &[&freelancer],
+    );
+    assert_success(result);
+    assert_eq!(fx.balances(), (AMOUNTS[1] * 3 / 4, AMOUNTS[1] / 4, rest));
+    assert_eq!(fx.contract_state().milestones[1].remaining(), 0);
+}
+
+#[test]
+fn keeper_settles_once_the_review_period_ends() {
+    let mut fx = Fixture::funded();
+    fx.submit_first();
+    let keeper = Keypair::new();
+    let settle = instruction::SettleMilestone { index: 0 };
+
+    let result = fx.send(fx.payout(&keeper.pubkey(), settle.data()), &[&keeper]);
+    assert_error(result, ErrorCode::ReviewPeriodActive);
+
+    fx.svm.advance_time(fx.state.review_period);
+    let result = fx.send(fx.payout(&keeper.pubkey(), settle.data()), &[&keeper]);
+    assert_success(result);
+    assert_eq!(fx.balances(), (0, AMOUNTS[0], AMOUNTS[1] + AMOUNTS[2]));
+}
+
+#[test]
+fn payouts_reject_a_swapped_token_account() {
+    let mut fx = Fixture::funded();
+    let client = fx.client.insecure_clone();
+    let attacker = fx
+        .svm
+        .create_token_account(&Pubkey::new_unique(), &fx.state.mint, 0);
+    let release = instruction::ReleaseMilestone {
+        index: 0,
+        amount: AMOUNTS[0],
+    };
+    let mut release = fx.payout(&client.pubkey(), release.data());
+    release.accounts[3].pubkey = attacker;
+
+    let result = fx.send(release, &[&client]);
+    assert_error(result, AnchorErrorCode::ConstraintRaw);
+}
+
+#[test]
+fn contracts_close_once_every_milestone_settles() {
+    let mut fx = Fixture::funded();
+    let result = fx.send(fx.close(), &[]);
+    assert_error(result, ErrorCode::ContractNotFinished);
+
+    let mut settled = contract().milestones;
+    for milestone in &mut settled {
+        milestone.released = milestone.amount;
+        milestone.status = MilestoneStatus::Settled;
+    }
+    let mut fx = Fixture::new(settled);
+    // Tokens sent to the vault directly go back to the client
+    fx.svm.mint_tokens(&fx.state.vault, 5);
+    let rent = fx.svm.get_balance(&fx.contract) + fx.svm.get_balance(&fx.state.vault);
+    let result = fx.send(fx.close(), &[]);
+    assert_success(result);
+    assert_eq!(fx.svm.token_balance(&fx.state.client_token_account), 5);
+    assert!(fx.svm.get_account(&fx.state.vault).is_none());
+    assert!(fx.svm.get_account(&fx.contract).is_none());
+    assert_eq!(fx.svm.get_balance(&fx.state.client), rent);
+}
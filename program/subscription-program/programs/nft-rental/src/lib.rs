@@ -0,0 +1,635 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use anchor_lang::solana_program::program_pack::Pack;
+use spl_token::instruction as token_instruction;
+use spl_token::state::Mint;
+use token_utils::token_account;
+
+declare_id!("HWBAr7e8ymfBgGFRvf7HfFzEW2XLu2sWZCsyALDhNeSq");
+
+#[program]
+pub mod nft_rental {
+    use super::*;
+
+    /// Escrow an NFT for rent. Renters pay `rent_per_period` every
+    /// `interval_seconds`; a renter `grace_seconds` behind on rent loses the
+    /// NFT and the owner may reclaim it.
+    pub fn list_nft(
+        ctx: Context<ListNft>,
+        rent_per_period: u64,
+        interval_seconds: i64,
+        grace_seconds: i64,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        Rental::check_params(rent_per_period, interval_seconds, grace_seconds)?;
+
+        let nft_mint = ctx.accounts.nft_mint.key();
+        let rental_key = ctx.accounts.rental.key();
+        require_keys_eq!(
+            *ctx.accounts.nft_mint.owner,
+            spl_token::ID,
+            ErrorCode::NotAnNft
+        );
+        let mint = Mint::unpack(&ctx.accounts.nft_mint.try_borrow_data()?)
+            .map_err(|_| error!(ErrorCode::NotAnNft))?;
+        require!(mint.decimals == 0 && mint.supply == 1, ErrorCode::NotAnNft);
+
+        let vault = token_account(&ctx.accounts.vault, ErrorCode::InvalidTokenAccount)?;
+        require_keys_eq!(vault.owner, rental_key, ErrorCode::InvalidTokenAccount);
+        require_keys_eq!(vault.mint, nft_mint, ErrorCode::InvalidTokenAccount);
+        let owner_account = token_account(
+            &ctx.accounts.owner_token_account,
+            ErrorCode::InvalidTokenAccount,
+        )?;
+        require_keys_eq!(
+            owner_account.owner,
+            ctx.accounts.owner.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+        require_keys_eq!(
+            owner_account.mint,
+            ctx.accounts.payment_mint.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.owner_nft_account.key(),
+            &ctx.accounts.vault.key(),
+            &ctx.accounts.owner.key(),
+            &[],
+            1,
+        )?;
+
+        invoke(
+            &transfer_ix,
+            &[
+                ctx.accounts.owner_nft_account.to_account_info(),
+                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.owner.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        let rental = &mut ctx.accounts.rental;
+        rental.owner = ctx.accounts.owner.key();
+        rental.nft_mint = nft_mint;
+        rental.vault = ctx.accounts.vault.key();
+        rental.payment_mint = ctx.accounts.payment_mint.key();
+        rental.owner_token_account = ctx.accounts.owner_token_account.key();
+        rental.rent_per_period = rent_per_period;
+        rental.interval_seconds = interval_seconds;
+        rental.grace_seconds = grace_seconds;
+        rental.lease = None;
+        rental.total_collected = 0;
+        rental.created_at = clock.unix_timestamp;
+        rental.bump = ctx.bumps.rental;
+
+        emit!(NftListed {
+            rental: rental_key,
+            owner: rental.owner,
+            nft_mint,
+            rent_per_period,
+            interval_seconds,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("NFT listed: {} tokens per period", rent_per_period);
+        msg!("Period: {} seconds", interval_seconds);
+
+        Ok(())
+    }
+
+    /// Rent the NFT. As with a subscription, the rental PDA becomes the
+    /// delegate of the renter's token account and the first period is
+    /// charged immediately.
+    pub fn start_rental(ctx: Context<StartRental>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let rental = &ctx.accounts.rental;
+        rental.check_available(now)?;
+
+        let renter_account = token_account(
+            &ctx.accounts.renter_token_account,
+            ErrorCode::InvalidTokenAccount,
+        )?;
+        require_keys_eq!(
+            renter_account.owner,
+            ctx.accounts.renter.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+        require_keys_eq!(
+            renter_account.mint,
+            rental.payment_mint,
+            ErrorCode::InvalidTokenAccount
+        );
+
+        let delegate_ix = token_instruction::approve(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.renter_token_account.key(),
+            &rental.key(),
+            &ctx.accounts.renter.key(),
+            &[],
+            u64::MAX,
+        )?;
+
+        invoke(
+            &delegate_ix,
+            &[
+                ctx.accounts.renter_token_account.to_account_info(),
+                ctx.accounts.rental.to_account_info(),
+                ctx.accounts.renter.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        pull_rent(
+            &ctx.accounts.rental,
+            &ctx.accounts.renter_token_account,
+            &ctx.accounts.owner_token_account,
+            &ctx.accounts.token_program,
+        )?;
+
+        let rental = &mut ctx.accounts.rental;
+        rental.lease = Some(Lease {
+            renter: ctx.accounts.renter.key(),
+            renter_token_account: ctx.accounts.renter_token_account.key(),
+            started_at: now,
+            paid_until: now,
+        });
+        rental.record_rent()?;
+
+        emit!(RentalStarted {
+            rental: rental.key(),
+            renter: ctx.accounts.renter.key(),
+            paid_until: rental.paid_until(),
+            timestamp: now,
+        });
+
+        msg!("Rental started: {} tokens charged", rental.rent_per_period);
+        msg!("Paid until {}", rental.paid_until());
+
+        Ok(())
+    }
+
+    /// Collect the next period of rent. Permissionless, like
+    /// `charge_subscription`, and only possible within the grace period.
+    pub fn collect_rent(ctx: Context<CollectRent>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let lease = ctx.accounts.rental.check_rent_due(now)?;
+        require_keys_eq!(
+            ctx.accounts.renter_token_account.key(),
+            lease.renter_token_account,
+            ErrorCode::InvalidTokenAccount
+        );
+
+        pull_rent(
+            &ctx.accounts.rental,
+            &ctx.accounts.renter_token_account,
+            &ctx.accounts.owner_token_account,
+            &ctx.accounts.token_program,
+        )?;
+
+        let rental = &mut ctx.accounts.rental;
+        rental.record_rent()?;
+
+        emit!(RentCollected {
+            rental: rental.key(),
+            renter: lease.renter,
+            amount: rental.rent_per_period,
+            paid_until: rental.paid_until(),
+            timestamp: now,
+        });
+
+        msg!("Rent collected: {} tokens", rental.rent_per_period);
+        msg!("Paid until {}", rental.paid_until());
+
+        Ok(())
+    }
+
+    /// Stop renting and revoke the delegation. Prepaid time is not refunded.
+    pub fn end_rental(ctx: Context<EndRental>) -> Result<()> {
+        let lease = ctx.accounts.rental.lease.ok_or(ErrorCode::NoRenter)?;
+        require_keys_eq!(
+            lease.renter,
+            ctx.accounts.renter.key(),
+            ErrorCode::NotRenter
+        );
+        require_keys_eq!(
+            ctx.accounts.renter_token_account.key(),
+            lease.renter_token_account,
+            ErrorCode::InvalidTokenAccount
+        );
+
+        let revoke_ix = token_instruction::revoke(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.renter_token_account.key(),
+            &ctx.accounts.renter.key(),
+            &[],
+        )?;
+
+        invoke(
+            &revoke_ix,
+            &[
+                ctx.accounts.renter_token_account.to_account_info(),
+                ctx.accounts.renter.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        let rental = &mut ctx.accounts.rental;
+        rental.lease = None;
+
+        emit!(RentalEnded {
+            rental: rental.key(),
+            renter: lease.renter,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Rental ended");
+        msg!("Token delegation revoked");
+
+        Ok(())
+    }
+
+    /// Take the NFT back and close the listing. Blocked while a renter is
+    /// paid up or within the grace period.
+    pub fn reclaim_nft(ctx: Context<ReclaimNft>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let rental = &ctx.accounts.rental;
+        rental.check_available(now)?;
+
+        let nft_mint = rental.nft_mint;
+        let seeds = &[b"rental", nft_mint.as_ref(), &[rental.bump]];
+        let signer_seeds = &[&seeds[..]];
+        let rental_info = ctx.accounts.rental.to_account_info();
+
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.vault.key(),
+            &ctx.accounts.owner_nft_account.key(),
+            &rental_info.key(),
+            &[],
+            1,
+        )?;
+        invoke_signed(
+            &transfer_ix,
+            &[
+                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.owner_nft_account.to_account_info(),
+                rental_info.clone(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+            signer_seeds,
+        )?;
+
+        let close_ix = token_instruction::close_account(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.vault.key(),
+            &ctx.accounts.owner.key(),
+            &rental_info.key(),
+            &[],
+        )?;
+        invoke_signed(
+            &close_ix,
+            &[
+                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.owner.to_account_info(),
+                rental_info.clone(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+            signer_seeds,
+        )?;
+
+        emit!(NftReclaimed {
+            rental: rental_info.key(),
+            owner: ctx.accounts.owner.key(),
+            lapsed_renter: ctx.accounts.rental.lease.map(|lease| lease.renter),
+            timestamp: now,
+        });
+
+        msg!("NFT reclaimed by owner");
+
+        Ok(())
+    }
+}
+
+/// Move one period of rent from the renter to the owner, signed by the
+/// rental PDA as the renter account's delegate
+fn pull_rent<'info>(
+    rental: &Account<'info, Rental>,
+    renter_token_account: &UncheckedAccount<'info>,
+    owner_token_account: &UncheckedAccount<'info>,
+    token_program: &UncheckedAccount<'info>,
+) -> Result<()> {
+    let seeds = &[b"rental", rental.nft_mint.as_ref(), &[rental.bump]];
+
+    let transfer_ix = token_instruction::transfer(
+        &token_program.key(),
+        &renter_token_account.key(),
+        &owner_token_account.key(),
+        &rental.key(),
+        &[],
+        rental.rent_per_period,
+    )?;
+
+    invoke_signed(
+        &transfer_ix,
+        &[
+            renter_token_account.to_account_info(),
+            owner_token_account.to_account_info(),
+            rental.to_account_info(),
+            token_program.to_account_info(),
+        ],
+        &[&seeds[..]],
+    )?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct ListNft<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Rental::INIT_SPACE,
+        seeds = [b"rental", nft_mint.key().as_ref()],
+        bump
+    )]
+    pub rental: Account<'info, Rental>,
+
+    pub owner: Signer<'info>,
+
+    /// CHECK: NFT mint, checked in the handler
+    pub nft_mint: UncheckedAccount<'info>,
+
+    /// CHECK: Owner's NFT account, debited by the token program
+    #[account(mut)]
+    pub owner_nft_account: UncheckedAccount<'info>,
+
+    /// CHECK: NFT vault owned by the rental PDA, checked in the handler
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: Rent mint (USDC)
+    pub payment_mint: UncheckedAccount<'info>,
+
+    /// CHECK: Owner's token account, checked in the handler; rent lands here
+    pub owner_token_account: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct StartRental<'info> {
+    #[account(
+        mut,
+        seeds = [b"rental", rental.nft_mint.as_ref()],
+        bump = rental.bump,
+        has_one = owner_token_account
+    )]
+    pub rental: Account<'info, Rental>,
+
+    pub renter: Signer<'info>,
+
+    /// CHECK: Renter's token account, checked in the handler and delegated to the rental PDA
+    #[account(mut)]
+    pub renter_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Owner's token account
+    #[account(mut)]
+    pub owner_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CollectRent<'info> {
+    #[account(
+        mut,
+        seeds = [b"rental", rental.nft_mint.as_ref()],
+        bump = rental.bump,
+        has_one = owner_token_account
+    )]
+    pub rental: Account<'info, Rental>,
+
+    /// CHECK: The delegated token account, checked in the handler
+    #[account(mut)]
+    pub renter_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Owner's token account
+    #[account(mut)]
+    pub owner_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct EndRental<'info> {
+    #[account(
+        mut,
+        seeds = [b"rental", rental.nft_mint.as_ref()],
+        bump = rental.bump,
+    )]
+    pub rental: Account<'info, Rental>,
+
+    pub renter: Signer<'info>,
+
+    /// CHECK: The delegated token account, checked in the handler
+    #[account(mut)]
+    pub renter_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ReclaimNft<'info> {
+    #[account(
+        mut,
+        seeds = [b"rental", rental.nft_mint.as_ref()],
+        bump = rental.bump,
+        has_one = owner,
+        has_one = vault,
+        close = owner
+    )]
+    pub rental: Account<'info, Rental>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// CHECK: NFT vault, closed here
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: Any token account of the NFT mint the owner chooses
+    #[account(mut)]
+    pub owner_nft_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub struct Lease {
+    pub renter: Pubkey,
+    pub renter_token_account: Pubkey,
+    pub started_at: i64,
+    /// End of the last period paid for
+    pub paid_until: i64,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Rental {
+    pub owner: Pubkey,
+    pub nft_mint: Pubkey,
+    pub vault: Pubkey,
+    pub payment_mint: Pubkey,
+    pub owner_token_account: Pubkey,
+    pub rent_per_period: u64,
+    pub interval_seconds: i64,
+    pub grace_seconds: i64,
+    pub lease: Option<Lease>,
+    pub total_collected: u64,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl Rental {
+    pub fn check_params(
+        rent_per_period: u64,
+        interval_seconds: i64,
+        grace_seconds: i64,
+    ) -> Result<()> {
+        require!(rent_per_period > 0, ErrorCode::InvalidAmount);
+        require!(interval_seconds > 0, ErrorCode::InvalidInterval);
+        require!(grace_seconds >= 0, ErrorCode::InvalidInterval);
+        Ok(())
+    }
+
+    pub fn paid_until(&self) -> i64 {
+        self.lease.map_or(0, |lease| lease.paid_until)
+    }
+
+    /// Who may use the NFT at `now`: the renter, until they are more than
+    /// the grace period behind on rent. Games and apps gate on this.
+    pub fn current_renter(&self, now: i64) -> Option<Pubkey> {
+        self.lease
+            .filter(|lease| now <= lease.paid_until.saturating_add(self.grace_seconds))
+            .map(|lease| lease.renter)
+    }
+
+    /// Whether a new renter may start, or the owner may reclaim, at `now`
+    pub fn check_available(&self, now: i64) -> Result<()> {
+        require!(self.current_renter(now).is_none(), ErrorCode::RentalActive);
+        Ok(())
+    }
+
+    /// The lease whose next period can be collected at `now`
+    pub fn check_rent_due(&self, now: i64) -> Result<Lease> {
+        let lease = self.lease.ok_or(ErrorCode::NoRenter)?;
+        require!(now >= lease.paid_until, ErrorCode::RentNotDue);
+        require!(self.current_renter(now).is_some(), ErrorCode::RentalLapsed);
+        Ok(lease)
+    }
+
+    /// Book one period of rent against the lease
+    pub fn record_rent(&mut self) -> Result<()> {
+        let interval_seconds = self.interval_seconds;
+        let lease = self.lease.as_mut().ok_or(ErrorCode::NoRenter)?;
+        lease.paid_until = lease
+            .paid_until
+            .checked_add(interval_seconds)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.total_collected = self
+            .total_collected
+            .checked_add(self.rent_per_period)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct NftListed {
+    pub rental: Pubkey,
+    pub owner: Pubkey,
+    pub nft_mint: Pubkey,
+    pub rent_per_period: u64,
+    pub interval_seconds: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RentalStarted {
+    pub rental: Pubkey,
+    pub renter: Pubkey,
+    pub paid_until: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RentCollected {
+    pub rental: Pubkey,
+    pub renter: Pubkey,
+    pub amount: u64,
+    pub paid_until: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RentalEnded {
+    pub rental: Pubkey,
+    pub renter: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct NftReclaimed {
+    pub rental: Pubkey,
+    pub owner: Pubkey,
+    /// Set when the owner took the NFT back from a renter behind on rent
+    pub lapsed_renter: Option<Pubkey>,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Amount must be greater than zero")]
+    InvalidAmount,
+    #[msg("Interval must be positive and grace period non-negative")]
+    InvalidInterval,
+    #[msg("Invalid token account - wrong program, owner or mint")]
+    InvalidTokenAccount,
+    #[msg("Mint is not an NFT - needs 0 decimals and a supply of 1")]
+    NotAnNft,
+    #[msg("NFT is rented and the renter is paid up")]
+    RentalActive,
+    #[msg("NFT has no renter")]
+    NoRenter,
+    #[msg("Only the current renter can do this")]
+    NotRenter,
+    #[msg("Rent is not due yet")]
+    RentNotDue,
+    #[msg("Renter is past the grace period; the owner may reclaim the NFT")]
+    RentalLapsed,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
@@ -0,0 +1,390 @@
+//! Native tests for the NFT rental program.
+//!
+//! The lease rules are pure methods on `Rental` and are tested directly.
+//! Instruction tests cover who may sign what and run end to end against the
+//! real token and system programs, following the NFT and the rent.
+
+use anchor_lang::error::ErrorCode as AnchorErrorCode;
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, Space, ToAccountMetas};
+use nft_rental::{accounts, instruction, ErrorCode, Lease, Rental, ID as PROGRAM_ID};
+use test_harness::{assert_error, assert_success, Keypair, Signer, TestSvm, TransactionResult};
+
+const RENT: u64 = 5_000_000;
+const WEEK: i64 = 7 * 86_400;
+const GRACE: i64 = 86_400;
+
+fn rental() -> Rental {
+    Rental {
+        owner: Pubkey::new_unique(),
+        nft_mint: Pubkey::new_unique(),
+        vault: Pubkey::new_unique(),
+        payment_mint: Pubkey::new_unique(),
+        owner_token_account: Pubkey::new_unique(),
+        rent_per_period: RENT,
+        interval_seconds: WEEK,
+        grace_seconds: GRACE,
+        lease: None,
+        total_collected: 0,
+        created_at: 0,
+        bump: 255,
+    }
+}
+
+fn lease(renter: Pubkey, renter_token_account: Pubkey, paid_until: i64) -> Lease {
+    Lease {
+        renter,
+        renter_token_account,
+        started_at: paid_until - WEEK,
+        paid_until,
+    }
+}
+
+#[test]
+fn rejects_free_or_instant_rentals() {
+    assert_eq!(
+        Rental::check_params(0, WEEK, GRACE).unwrap_err(),
+        ErrorCode::InvalidAmount.into()
+    );
+    assert_eq!(
+        Rental::check_params(RENT, 0, GRACE).unwrap_err(),
+        ErrorCode::InvalidInterval.into()
+    );
+    assert_eq!(
+        Rental::check_params(RENT, WEEK, -1).unwrap_err(),
+        ErrorCode::InvalidInterval.into()
+    );
+    Rental::check_params(RENT, WEEK, 0).unwrap();
+}
+
+#[test]
+fn renters_keep_the_nft_through_the_grace_period() {
+    let renter = Pubkey::new_unique();
+    let mut listing = rental();
+    assert_eq!(listing.current_renter(0), None);
+    listing.check_available(0).unwrap();
+    assert_eq!(
+        listing.check_rent_due(0).unwrap_err(),
+        ErrorCode::NoRenter.into()
+    );
+
+    listing.lease = Some(lease(renter, Pubkey::new_unique(), WEEK));
+    assert_eq!(
+        listing.check_rent_due(WEEK - 1).unwrap_err(),
+        ErrorCode::RentNotDue.into()
+    );
+
+    // Due at the end of the period and collectable until the grace runs out
+    for now in [WEEK, WEEK + GRACE] {
+        assert_eq!(listing.current_renter(now), Some(renter));
+        listing.check_rent_due(now).unwrap();
+        assert_eq!(
+            listing.check_available(now).unwrap_err(),
+            ErrorCode::RentalActive.into()
+        );
+    }
+
+    // Missed: the owner can take the NFT back and the keeper stops collecting
+    let lapsed = WEEK + GRACE + 1;
+    assert_eq!(listing.current_renter(lapsed), None);
+    listing.check_available(lapsed).unwrap();
+    assert_eq!(
+        listing.check_rent_due(lapsed).unwrap_err(),
+        ErrorCode::RentalLapsed.into()
+    );
+}
+
+#[test]
+fn each_payment_extends_the_lease_by_one_period() {
+    let mut listing = rental();
+    assert_eq!(
+        listing.record_rent().unwrap_err(),
+        ErrorCode::NoRenter.into()
+    );
+
+    listing.lease = Some(lease(Pubkey::new_unique(), Pubkey::new_unique(), WEEK));
+    listing.record_rent().unwrap();
+    listing.record_rent().unwrap();
+    assert_eq!(listing.paid_until(), 3 * WEEK);
+    assert_eq!(listing.total_collected, 2 * RENT);
+}
+
+struct Fixture {
+    svm: TestSvm,
+    payer: Keypair,
+    owner: Keypair,
+    renter: Keypair,
+    rental: Pubkey,
+    state: Rental,
+    renter_token_account: Pubkey,
+    owner_nft_account: Pubkey,
+}
+
+impl Fixture {
+    /// A listed NFT with no renter yet
+    fn new() -> Self {
+        let (mut svm, payer) = TestSvm::for_program(PROGRAM_ID, nft_rental::entry);
+
+        let owner = Keypair::new();
+        let renter = Keypair::new();
+
+        let nft_mint = svm.create_mint(&owner.pubkey(), 0);
+        let payment_mint = svm.create_mint(&Pubkey::new_unique(), 6);
+        let (address, bump) =
+            Pubkey::find_program_address(&[b"rental", nft_mint.as_ref()], &PROGRAM_ID);
+        let vault = svm.create_associated_token_account(&address, &nft_mint, 1);
+        let owner_token_account =
+            svm.create_associated_token_account(&owner.pubkey(), &payment_mint, 0);
+        let renter_token_account =
+            svm.create_associated_token_account(&renter.pubkey(), &payment_mint, 10 * RENT);
+        let owner_nft_account = svm.create_associated_token_account(&owner.pubkey(), &nft_mint, 0);
+
+        let state = Rental {
+            owner: owner.pubkey(),
+            nft_mint,
+            vault,
+            payment_mint,
+            owner_token_account,
+            bump,
+            ..rental()
+        };
+        svm.set_anchor_account(address, &state, 8 + Rental::INIT_SPACE);
+
+        Self {
+            svm,
+            payer,
+            owner,
+            renter,
+            rental: address,
+            state,
+            renter_token_account,
+            owner_nft_account,
+        }
+    }
+
+    /// Rent it out to the fixture's renter, paid until `paid_until`, with the
+    /// delegation `start_rental` leaves
+    fn leased(mut self, paid_until: i64) -> Self {
+        self.state.lease = Some(lease(
+            self.renter.pubkey(),
+            self.renter_token_account,
+            paid_until,
+        ));
+        self.svm
+            .set_anchor_account(self.rental, &self.state, 8 + Rental::INIT_SPACE);
+        self.svm
+            .approve(&self.renter_token_account, &self.rental, u64::MAX);
+        self
+    }
+
+    fn rental(&self) -> Option<Rental> {
+        self.svm.get_anchor_account(&self.rental)
+    }
+
+    fn list(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::ListNft {
+                rental: self.rental,
+                owner: self.owner.pubkey(),
+                nft_mint: self.state.nft_mint,
+                owner_nft_account: self.owner_nft_account,
+                vault: self.state.vault,
+                payment_mint: self.state.payment_mint,
+                owner_token_account: self.state.owner_token_account,
+                payer: self.payer.pubkey(),
+                token_program: spl_token::ID,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::ListNft {
+                rent_per_period: RENT,
+                interval_seconds: WEEK,
+                grace_seconds: GRACE,
+            }
+            .data(),
+        }
+    }
+
+    fn now(&self) -> i64 {
+        self.svm.clock().unix_timestamp
+    }
+
+    fn start(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::StartRental {
+                rental: self.rental,
+                renter: self.renter.pubkey(),
+                renter_token_account: self.renter_token_account,
+                owner_token_account: self.state.owner_token_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::StartRental {}.data(),
+        }
+    }
+
+    fn collect(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CollectRent {
+                rental: self.rental,
+                renter_token_account: self.renter_token_account,
+                owner_token_account: self.state.owner_token_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CollectRent {}.data(),
+        }
+    }
+
+    fn end(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::EndRental {
+                rental: self.rental,
+                renter: self.renter.pubkey(),
+                renter_token_account: self.renter_token_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::EndRental {}.data(),
+        }
+    }
+
+    fn reclaim(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::ReclaimNft {
+                rental: self.rental,
+                owner: self.owner.pubkey(),
+                vault: self.state.vault,
+                owner_nft_account: self.owner_nft_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::ReclaimNft {}.data(),
+        }
+    }
+
+    fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TransactionResult {
+        let payer = self.payer.insecure_clone();
+        self.svm.send_instructions(&[instruction], &payer, signers)
+    }
+
+    fn send_as_renter(&mut self, instruction: Instruction) -> TransactionResult {
+        let renter = self.renter.insecure_clone();
+        self.send(instruction, &[&renter])
+    }
+
+    fn send_as_owner(&mut self, instruction: Instruction) -> TransactionResult {
+        let owner = self.owner.insecure_clone();
+        self.send(instruction, &[&owner])
+    }
+}
+
+#[test]
+fn owners_list_by_moving_the_nft_into_the_vault() {
+    let mut fx = Fixture::new();
+    fx.svm.remove_account(&fx.rental);
+    fx.svm
+        .transfer_tokens(&fx.state.vault, &fx.owner_nft_account, 1);
+
+    let result = fx.send_as_owner(fx.list());
+    assert_success(result);
+    assert_eq!(fx.svm.token_balance(&fx.owner_nft_account), 0);
+    assert_eq!(fx.svm.token_balance(&fx.state.vault), 1);
+    let rental = fx.rental().unwrap();
+    assert_eq!(rental.owner, fx.owner.pubkey());
+    assert_eq!(rental.lease, None);
+    assert_eq!(rental.bump, fx.state.bump);
+}
+
+#[test]
+fn renting_needs_a_free_nft_and_a_matching_account() {
+    let mut fx = Fixture::new();
+    let mut foreign = fx.start();
+    foreign.accounts[2].pubkey =
+        fx.svm
+            .create_token_account(&Pubkey::new_unique(), &fx.state.payment_mint, 10 * RENT);
+    let result = fx.send_as_renter(foreign);
+    assert_error(result, ErrorCode::InvalidTokenAccount);
+
+    let result = fx.send_as_renter(fx.start());
+    assert_success(result);
+    assert_eq!(fx.svm.token_balance(&fx.renter_token_account), 9 * RENT);
+    assert_eq!(fx.svm.token_balance(&fx.state.owner_token_account), RENT);
+    let now = fx.now();
+    assert_eq!(fx.rental().unwrap().paid_until(), now + WEEK);
+
+    let mut fx = Fixture::new().leased(now + WEEK);
+    let result = fx.send_as_renter(fx.start());
+    assert_error(result, ErrorCode::RentalActive);
+}
+
+#[test]
+fn keeper_collects_due_rent_within_grace() {
+    let now = Fixture::new().now();
+    let mut fx = Fixture::new().leased(now + WEEK);
+    let result = fx.send(fx.collect(), &[]);
+    assert_error(result, ErrorCode::RentNotDue);
+
+    fx.svm.warp_to_timestamp(now + WEEK);
+    let mut redirected = fx.collect();
+    redirected.accounts[1].pubkey =
+        fx.svm
+            .create_token_account(&fx.renter.pubkey(), &fx.state.payment_mint, RENT);
+    let result = fx.send(redirected, &[]);
+    assert_error(result, ErrorCode::InvalidTokenAccount);
+
+    let result = fx.send(fx.collect(), &[]);
+    assert_success(result);
+    assert_eq!(fx.svm.token_balance(&fx.state.owner_token_account), RENT);
+    assert_eq!(fx.rental().unwrap().paid_until(), now + 2 * WEEK);
+
+    fx.svm.warp_to_timestamp(now + 2 * WEEK + GRACE + 1);
+    let result = fx.send(fx.collect(), &[]);
+    assert_error(result, ErrorCode::RentalLapsed);
+}
+
+#[test]
+fn only_the_renter_ends_a_rental() {
+    let now = Fixture::new().now();
+    let mut fx = Fixture::new().leased(now + WEEK);
+
+    let stranger = Keypair::new();
+    let mut forged = fx.end();
+    forged.accounts[1].pubkey = stranger.pubkey();
+    let result = fx.send(forged, &[&stranger]);
+    assert_error(result, ErrorCode::NotRenter);
+
+    let result = fx.send_as_renter(fx.end());
+    assert_success(result);
+    assert_eq!(fx.rental().unwrap().lease, None);
+    let account = fx.svm.get_token_account(&fx.renter_token_account).unwrap();
+    assert!(account.delegate.is_none());
+}
+
+#[test]
+fn owner_reclaims_after_missed_rent() {
+    let now = Fixture::new().now();
+    let mut fx = Fixture::new().leased(now + WEEK);
+
+    let impostor = Keypair::new();
+    let mut forged = fx.reclaim();
+    forged.accounts[1].pubkey = impostor.pubkey();
+    let result = fx.send(forged, &[&impostor]);
+    assert_error(result, AnchorErrorCode::ConstraintHasOne);
+
+    let result = fx.send_as_owner(fx.reclaim());
+    assert_error(result, ErrorCode::RentalActive);
+
+    fx.svm.warp_to_timestamp(now + WEEK + GRACE + 1);
+    let result = fx.send_as_owner(fx.reclaim());
+    assert_success(result);
+    assert_eq!(fx.svm.token_balance(&fx.owner_nft_account), 1);
+    assert!(fx.svm.get_account(&fx.state.vault).is_none());
+    assert!(fx.rental().is_none());
+}
@@ -0,0 +1,371 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::sysvar::instructions::load_instruction_at_checked;
+
+declare_id!("J518Ra3PcJN4LmXA8tLXhu3wM42bCEMRmRevh6WG3eh4");
+
+/// Native program that verifies secp256r1 (passkey) signatures. A
+/// transaction carrying one of its instructions fails unless every signature
+/// in it checks out, so a program only has to read what it verified.
+pub const SECP256R1_PROGRAM_ID: Pubkey = pubkey!("Secp256r1SigVerify1111111111111111111111111");
+
+/// Most devices a wallet can register
+pub const MAX_DEVICES: usize = 8;
+
+/// Longest device name, in bytes
+pub const MAX_NAME_LEN: usize = 32;
+
+/// Prefix of the message devices sign to approve a change
+pub const CHANGE_DOMAIN: &[u8] = b"lazorkit-devices";
+
+#[program]
+pub mod passkey_devices {
+    use super::*;
+
+    /// Register the wallet's first passkey. The quorum starts at 1, so this
+    /// device alone approves the next change.
+    pub fn create_device_set(
+        ctx: Context<CreateDeviceSet>,
+        pubkey: [u8; 33],
+        name: String,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let device = Device::new(pubkey, name, now)?;
+
+        let device_set = &mut ctx.accounts.device_set;
+        device_set.wallet = ctx.accounts.wallet.key();
+        device_set.devices = vec![device];
+        device_set.quorum = 1;
+        device_set.nonce = 0;
+        device_set.bump = ctx.bumps.device_set;
+
+        emit!(DeviceAdded {
+            wallet: device_set.wallet,
+            pubkey,
+            devices: 1,
+            timestamp: now,
+        });
+
+        msg!("Device set created");
+
+        Ok(())
+    }
+
+    /// Register another passkey, e.g. a laptop next to the phone
+    pub fn add_device(ctx: Context<ChangeDevices>, pubkey: [u8; 33], name: String) -> Result<()> {
+        apply_change(ctx, DeviceChange::Add { pubkey, name })
+    }
+
+    /// Drop a lost or retired passkey
+    pub fn remove_device(ctx: Context<ChangeDevices>, pubkey: [u8; 33]) -> Result<()> {
+        apply_change(ctx, DeviceChange::Remove { pubkey })
+    }
+
+    /// Change how many devices must approve the next change
+    pub fn set_quorum(ctx: Context<ChangeDevices>, quorum: u8) -> Result<()> {
+        apply_change(ctx, DeviceChange::SetQuorum { quorum })
+    }
+
+    /// Succeeds if any one registered device signed `message` in this
+    /// transaction. Other programs call it by CPI to accept whichever
+    /// device the user has at hand; `message` should carry their own nonce.
+    pub fn verify_device(ctx: Context<VerifyDevice>, message: Vec<u8>) -> Result<()> {
+        let signatures = transaction_signatures(&ctx.accounts.instructions)?;
+        require!(
+            ctx.accounts.device_set.approvals(&signatures, &message) > 0,
+            ErrorCode::QuorumNotMet
+        );
+
+        msg!("Signed by a registered device");
+
+        Ok(())
+    }
+}
+
+/// Check the quorum signed `change` against the current nonce, then apply it
+fn apply_change(ctx: Context<ChangeDevices>, change: DeviceChange) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let signatures = transaction_signatures(&ctx.accounts.instructions)?;
+
+    let device_set = &mut ctx.accounts.device_set;
+    let message = device_set.change_message(&change)?;
+    require!(
+        device_set.approvals(&signatures, &message) >= device_set.quorum,
+        ErrorCode::QuorumNotMet
+    );
+    device_set.apply(&change, now)?;
+
+    let wallet = device_set.wallet;
+    let devices = device_set.devices.len() as u8;
+    match change {
+        DeviceChange::Add { pubkey, .. } => emit!(DeviceAdded {
+            wallet,
+            pubkey,
+            devices,
+            timestamp: now,
+        }),
+        DeviceChange::Remove { pubkey } => emit!(DeviceRemoved {
+            wallet,
+            pubkey,
+            devices,
+            timestamp: now,
+        }),
+        DeviceChange::SetQuorum { quorum } => emit!(QuorumChanged {
+            wallet,
+            quorum,
+            timestamp: now,
+        }),
+    }
+
+    msg!("Devices: {}, quorum: {}", devices, device_set.quorum);
+
+    Ok(())
+}
+
+/// Every (public key, message) pair verified by a secp256r1 instruction in
+/// the current transaction
+pub fn transaction_signatures(instructions: &AccountInfo) -> Result<Vec<([u8; 33], Vec<u8>)>> {
+    let mut signatures = Vec::new();
+    let mut index = 0;
+    while let Ok(instruction) = load_instruction_at_checked(index, instructions) {
+        if instruction.program_id == SECP256R1_PROGRAM_ID {
+            signatures.extend(secp256r1_signatures(&instruction.data)?);
+        }
+        index += 1;
+    }
+    Ok(signatures)
+}
+
+/// Read the public keys and messages out of a secp256r1 instruction. Only
+/// keys and messages stored in the instruction itself are supported, which is
+/// how clients build it.
+pub fn secp256r1_signatures(data: &[u8]) -> Result<Vec<([u8; 33], Vec<u8>)>> {
+    const HEADER_LEN: usize = 2;
+    const OFFSETS_LEN: usize = 14;
+    const THIS_INSTRUCTION: u16 = u16::MAX;
+
+    let read_u16 = |at: usize| -> Result<u16> {
+        data.get(at..at + 2)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+            .ok_or_else(|| ErrorCode::InvalidSignatureInstruction.into())
+    };
+    let count = *data.first().ok_or(ErrorCode::InvalidSignatureInstruction)? as usize;
+
+    let mut signatures = Vec::with_capacity(count);
+    for i in 0..count {
+        let offsets = HEADER_LEN + i * OFFSETS_LEN;
+        let pubkey_offset = read_u16(offsets + 4)? as usize;
+        let pubkey_instruction = read_u16(offsets + 6)?;
+        let message_offset = read_u16(offsets + 8)? as usize;
+        let message_len = read_u16(offsets + 10)? as usize;
+        let message_instruction = read_u16(offsets + 12)?;
+        require!(
+            pubkey_instruction == THIS_INSTRUCTION && message_instruction == THIS_INSTRUCTION,
+            ErrorCode::InvalidSignatureInstruction
+        );
+
+        let pubkey: [u8; 33] = data
+            .get(pubkey_offset..pubkey_offset + 33)
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or(ErrorCode::InvalidSignatureInstruction)?;
+        let message = data
+            .get(message_offset..message_offset + message_len)
+            .ok_or(ErrorCode::InvalidSignatureInstruction)?;
+        signatures.push((pubkey, message.to_vec()));
+    }
+    Ok(signatures)
+}
+
+#[derive(Accounts)]
+pub struct CreateDeviceSet<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + DeviceSet::INIT_SPACE,
+        seeds = [b"devices", wallet.key().as_ref()],
+        bump
+    )]
+    pub device_set: Account<'info, DeviceSet>,
+
+    pub wallet: Signer<'info>,
+
+    /// Fee and rent payer, usually a relayer
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ChangeDevices<'info> {
+    #[account(
+        mut,
+        seeds = [b"devices", device_set.wallet.as_ref()],
+        bump = device_set.bump
+    )]
+    pub device_set: Account<'info, DeviceSet>,
+
+    /// CHECK: Instructions sysvar, to read the secp256r1 instructions
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct VerifyDevice<'info> {
+    #[account(
+        seeds = [b"devices", device_set.wallet.as_ref()],
+        bump = device_set.bump
+    )]
+    pub device_set: Account<'info, DeviceSet>,
+
+    /// CHECK: Instructions sysvar, to read the secp256r1 instructions
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, InitSpace, Debug, Clone, PartialEq, Eq)]
+pub struct Device {
+    /// Compressed secp256r1 public key
+    pub pubkey: [u8; 33],
+    #[max_len(MAX_NAME_LEN)]
+    pub name: String,
+    pub added_at: i64,
+}
+
+impl Device {
+    pub fn new(pubkey: [u8; 33], name: String, now: i64) -> Result<Self> {
+        require!(name.len() <= MAX_NAME_LEN, ErrorCode::NameTooLong);
+        Ok(Self {
+            pubkey,
+            name,
+            added_at: now,
+        })
+    }
+}
+
+/// A change to a device set; devices sign its [`DeviceSet::change_message`]
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub enum DeviceChange {
+    Add { pubkey: [u8; 33], name: String },
+    Remove { pubkey: [u8; 33] },
+    SetQuorum { quorum: u8 },
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct DeviceSet {
+    pub wallet: Pubkey,
+    #[max_len(MAX_DEVICES)]
+    pub devices: Vec<Device>,
+    /// Devices that must sign a change to the set
+    pub quorum: u8,
+    /// Bumped by every change, so a signed change cannot be replayed
+    pub nonce: u64,
+    pub bump: u8,
+}
+
+impl DeviceSet {
+    /// The bytes each device signs to approve `change`: the domain, the
+    /// wallet, the current nonce and the change
+    pub fn change_message(&self, change: &DeviceChange) -> Result<Vec<u8>> {
+        let mut message = CHANGE_DOMAIN.to_vec();
+        message.extend_from_slice(self.wallet.as_ref());
+        message.extend_from_slice(&self.nonce.to_le_bytes());
+        change.serialize(&mut message)?;
+        Ok(message)
+    }
+
+    /// How many distinct registered devices signed `message`
+    pub fn approvals(&self, signatures: &[([u8; 33], Vec<u8>)], message: &[u8]) -> u8 {
+        self.devices
+            .iter()
+            .filter(|device| {
+                signatures
+                    .iter()
+                    .any(|(pubkey, signed)| *pubkey == device.pubkey && signed == message)
+            })
+            .count() as u8
+    }
+
+    pub fn apply(&mut self, change: &DeviceChange, now: i64) -> Result<()> {
+        match change {
+            DeviceChange::Add { pubkey, name } => {
+                require!(self.devices.len() < MAX_DEVICES, ErrorCode::TooManyDevices);
+                require!(
+                    self.devices.iter().all(|device| device.pubkey != *pubkey),
+                    ErrorCode::DuplicateDevice
+                );
+                self.devices.push(Device::new(*pubkey, name.clone(), now)?);
+            }
+            DeviceChange::Remove { pubkey } => {
+                let index = self
+                    .devices
+                    .iter()
+                    .position(|device| device.pubkey == *pubkey)
+                    .ok_or(ErrorCode::DeviceNotFound)?;
+                require!(
+                    self.devices.len() > self.quorum as usize,
+                    ErrorCode::InvalidQuorum
+                );
+                self.devices.remove(index);
+            }
+            DeviceChange::SetQuorum { quorum } => {
+                require!(
+                    *quorum > 0 && *quorum as usize <= self.devices.len(),
+                    ErrorCode::InvalidQuorum
+                );
+                self.quorum = *quorum;
+            }
+        }
+        self.nonce = self
+            .nonce
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceAdded {
+    pub wallet: Pubkey,
+    pub pubkey: [u8; 33],
+    pub devices: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceRemoved {
+    pub wallet: Pubkey,
+    pub pubkey: [u8; 33],
+    pub devices: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuorumChanged {
+    pub wallet: Pubkey,
+    pub quorum: u8,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("A wallet can register at most 8 devices")]
+    TooManyDevices,
+    #[msg("Device is already registered")]
+    DuplicateDevice,
+    #[msg("Device is not registered")]
+    DeviceNotFound,
+    #[msg("Device name is longer than 32 bytes")]
+    NameTooLong,
+    #[msg("Quorum must be between 1 and the number of devices")]
+    InvalidQuorum,
+    #[msg("Not enough registered devices signed the change")]
+    QuorumNotMet,
+    #[msg("Malformed secp256r1 instruction")]
+    InvalidSignatureInstruction,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
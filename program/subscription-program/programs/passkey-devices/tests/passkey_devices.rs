@@ -0,0 +1,423 @@
+//! Native tests for the passkey devices program.
+//!
+//! Reading secp256r1 instructions, counting approvals and the device rules
+//! are pure and are tested directly. `add_device`, `remove_device`,
+//! `set_quorum` and `verify_device` run end to end: the harness neither runs
+//! the precompile nor builds the instructions sysvar, so the fixture writes a
+//! sysvar holding the secp256r1 instruction a client would send. Checking the
+//! signatures themselves is the runtime's job. `create_device_set` needs no
+//! signature check and runs end to end as well.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::sysvar;
+use anchor_lang::{system_program, InstructionData, Space, ToAccountMetas};
+use passkey_devices::{
+    accounts, instruction, secp256r1_signatures, Device, DeviceChange, DeviceSet, ErrorCode,
+    ID as PROGRAM_ID, MAX_DEVICES, SECP256R1_PROGRAM_ID,
+};
+use test_harness::{
+    assert_error, assert_success, Account, Keypair, Signer, SystemError, TestSvm, TransactionResult,
+};
+
+const PHONE: [u8; 33] = [2; 33];
+const LAPTOP: [u8; 33] = [3; 33];
+const TABLET: [u8; 33] = [4; 33];
+
+fn device(pubkey: [u8; 33]) -> Device {
+    Device {
+        pubkey,
+        name: "device".to_string(),
+        added_at: 0,
+    }
+}
+
+fn phone_and_laptop(quorum: u8) -> DeviceSet {
+    DeviceSet {
+        wallet: Pubkey::new_unique(),
+        devices: vec![device(PHONE), device(LAPTOP)],
+        quorum,
+        nonce: 0,
+        bump: 255,
+    }
+}
+
+/// Secp256r1 instruction data with each key and message in the instruction
+/// itself, as the client builds it; the signatures are zeroed
+fn secp256r1_data(signed: &[([u8; 33], Vec<u8>)]) -> Vec<u8> {
+    let mut data = vec![signed.len() as u8, 0];
+    let mut payload = Vec::new();
+    let payload_start = 2 + 14 * signed.len();
+    for (pubkey, message) in signed {
+        let pubkey_offset = payload_start + payload.len();
+        payload.extend_from_slice(pubkey);
+        let signature_offset = payload_start + payload.len();
+        payload.extend_from_slice(&[0; 64]);
+        let message_offset = payload_start + payload.len();
+        payload.extend_from_slice(message);
+        for field in [
+            signature_offset,
+            u16::MAX as usize,
+            pubkey_offset,
+            u16::MAX as usize,
+            message_offset,
+            message.len(),
+            u16::MAX as usize,
+        ] {
+            data.extend_from_slice(&(field as u16).to_le_bytes());
+        }
+    }
+    data.extend_from_slice(&payload);
+    data
+}
+
+#[test]
+fn signatures_are_read_from_the_precompile_instruction() {
+    let signed = vec![(PHONE, b"hello".to_vec()), (LAPTOP, b"world!".to_vec())];
+    assert_eq!(
+        secp256r1_signatures(&secp256r1_data(&signed)).unwrap(),
+        signed
+    );
+
+    // Keys or messages held in another instruction are not supported
+    let mut data = secp256r1_data(&signed[..1]);
+    data[8..10].copy_from_slice(&0u16.to_le_bytes());
+    assert_eq!(
+        secp256r1_signatures(&data).unwrap_err(),
+        ErrorCode::InvalidSignatureInstruction.into()
+    );
+    assert_eq!(
+        secp256r1_signatures(&data[..20]).unwrap_err(),
+        ErrorCode::InvalidSignatureInstruction.into()
+    );
+}
+
+#[test]
+fn approvals_count_registered_devices_that_signed_the_message() {
+    let set = phone_and_laptop(2);
+    let change = DeviceChange::SetQuorum { quorum: 1 };
+    let message = set.change_message(&change).unwrap();
+
+    assert_eq!(set.approvals(&[(PHONE, message.clone())], &message), 1);
+    assert_eq!(
+        set.approvals(
+            &[
+                (PHONE, message.clone()),
+                (PHONE, message.clone()),
+                (TABLET, message.clone()),
+                (LAPTOP, b"something else".to_vec()),
+            ],
+            &message
+        ),
+        1
+    );
+    assert_eq!(
+        set.approvals(
+            &[(LAPTOP, message.clone()), (PHONE, message.clone())],
+            &message
+        ),
+        2
+    );
+
+    // The nonce is in the message, so an applied change cannot be replayed
+    let mut applied = set.clone();
+    applied.apply(&change, 0).unwrap();
+    assert_ne!(applied.change_message(&change).unwrap(), message);
+}
+
+#[test]
+fn changes_keep_the_set_consistent() {
+    let mut set = phone_and_laptop(2);
+
+    assert_eq!(
+        set.apply(&DeviceChange::Remove { pubkey: PHONE }, 0)
+            .unwrap_err(),
+        ErrorCode::InvalidQuorum.into()
+    );
+    assert_eq!(
+        set.apply(&DeviceChange::SetQuorum { quorum: 3 }, 0)
+            .unwrap_err(),
+        ErrorCode::InvalidQuorum.into()
+    );
+    assert_eq!(
+        set.apply(&DeviceChange::SetQuorum { quorum: 0 }, 0)
+            .unwrap_err(),
+        ErrorCode::InvalidQuorum.into()
+    );
+    assert_eq!(
+        set.apply(
+            &DeviceChange::Add {
+                pubkey: LAPTOP,
+                name: "laptop".to_string()
+            },
+            0
+        )
+        .unwrap_err(),
+        ErrorCode::DuplicateDevice.into()
+    );
+    assert_eq!(
+        set.apply(
+            &DeviceChange::Add {
+                pubkey: TABLET,
+                name: "x".repeat(33)
+            },
+            0
+        )
+        .unwrap_err(),
+        ErrorCode::NameTooLong.into()
+    );
+    assert_eq!(
+        set.apply(&DeviceChange::Remove { pubkey: TABLET }, 0)
+            .unwrap_err(),
+        ErrorCode::DeviceNotFound.into()
+    );
+    assert_eq!(set.nonce, 0);
+
+    set.apply(
+        &DeviceChange::Add {
+            pubkey: TABLET,
+            name: "tablet".to_string(),
+        },
+        7,
+    )
+    .unwrap();
+    set.apply(&DeviceChange::Remove { pubkey: PHONE }, 8)
+        .unwrap();
+    assert_eq!(
+        set.devices.iter().map(|d| d.pubkey).collect::<Vec<_>>(),
+        vec![LAPTOP, TABLET]
+    );
+    assert_eq!(set.devices[1].added_at, 7);
+    assert_eq!(set.nonce, 2);
+
+    let mut full = phone_and_laptop(1);
+    full.devices = (0..MAX_DEVICES as u8).map(|i| device([i; 33])).collect();
+    assert_eq!(
+        full.apply(
+            &DeviceChange::Add {
+                pubkey: [99; 33],
+                name: String::new()
+            },
+            0
+        )
+        .unwrap_err(),
+        ErrorCode::TooManyDevices.into()
+    );
+}
+
+struct Fixture {
+    svm: TestSvm,
+    payer: Keypair,
+    device_set: Pubkey,
+}
+
+impl Fixture {
+    fn new(quorum: u8) -> Self {
+        let (mut svm, payer) = TestSvm::for_program(PROGRAM_ID, passkey_devices::entry);
+
+        let wallet = Pubkey::new_unique();
+
+        let (device_set, bump) =
+            Pubkey::find_program_address(&[b"devices", wallet.as_ref()], &PROGRAM_ID);
+        let state = DeviceSet {
+            wallet,
+            bump,
+            ..phone_and_laptop(quorum)
+        };
+        svm.set_anchor_account(device_set, &state, 8 + DeviceSet::INIT_SPACE);
+
+        Self {
+            svm,
+            payer,
+            device_set,
+        }
+    }
+
+    fn state(&self) -> DeviceSet {
+        self.svm.get_anchor_account(&self.device_set).unwrap()
+    }
+
+    /// Write the instructions sysvar of a transaction whose first
+    /// instruction is a secp256r1 check of `signed`
+    fn set_signed(&mut self, signed: &[([u8; 33], Vec<u8>)]) {
+        let precompile = secp256r1_data(signed);
+        let mut data = Vec::new();
+        data.extend_from_slice(&2u16.to_le_bytes());
+        let first = 2 + 2 * 2;
+        let second = first + 2 + 32 + 2 + precompile.len();
+        data.extend_from_slice(&(first as u16).to_le_bytes());
+        data.extend_from_slice(&(second as u16).to_le_bytes());
+        for (program_id, ix_data) in [(SECP256R1_PROGRAM_ID, precompile), (PROGRAM_ID, vec![])] {
+            data.extend_from_slice(&0u16.to_le_bytes());
+            data.extend_from_slice(program_id.as_ref());
+            data.extend_from_slice(&(ix_data.len() as u16).to_le_bytes());
+            data.extend_from_slice(&ix_data);
+        }
+        data.extend_from_slice(&1u16.to_le_bytes());
+
+        self.svm.set_account(
+            sysvar::instructions::ID,
+            Account {
+                lamports: 1,
+                data,
+                owner: sysvar::ID,
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+    }
+
+    /// Have each of `devices` sign `change` at the current nonce
+    fn sign_change(&mut self, devices: &[[u8; 33]], change: &DeviceChange) {
+        let message = self.state().change_message(change).unwrap();
+        let signed: Vec<_> = devices.iter().map(|d| (*d, message.clone())).collect();
+        self.set_signed(&signed);
+    }
+
+    fn change(&self, data: impl InstructionData) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::ChangeDevices {
+                device_set: self.device_set,
+                instructions: sysvar::instructions::ID,
+            }
+            .to_account_metas(None),
+            data: data.data(),
+        }
+    }
+
+    fn verify(&self, message: &[u8]) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::VerifyDevice {
+                device_set: self.device_set,
+                instructions: sysvar::instructions::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::VerifyDevice {
+                message: message.to_vec(),
+            }
+            .data(),
+        }
+    }
+
+    fn send(&mut self, instruction: Instruction) -> TransactionResult {
+        let payer = self.payer.insecure_clone();
+        self.svm.expire_blockhash();
+        self.svm.send_instructions(&[instruction], &payer, &[])
+    }
+}
+
+#[test]
+fn wallets_create_a_set_with_their_first_device() {
+    let (mut svm, payer) = TestSvm::for_program(PROGRAM_ID, passkey_devices::entry);
+    let wallet = Keypair::new();
+    let (device_set, bump) =
+        Pubkey::find_program_address(&[b"devices", wallet.pubkey().as_ref()], &PROGRAM_ID);
+    let create = |name: String| Instruction {
+        program_id: PROGRAM_ID,
+        accounts: accounts::CreateDeviceSet {
+            device_set,
+            wallet: wallet.pubkey(),
+            payer: payer.pubkey(),
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: instruction::CreateDeviceSet {
+            pubkey: PHONE,
+            name,
+        }
+        .data(),
+    };
+
+    let result = svm.send_instructions(&[create("x".repeat(33))], &payer, &[&wallet]);
+    assert_error(result, ErrorCode::NameTooLong);
+
+    let result = svm.send_instructions(&[create("phone".to_string())], &payer, &[&wallet]);
+    assert_success(result);
+    let state: DeviceSet = svm.get_anchor_account(&device_set).unwrap();
+    assert_eq!(state.wallet, wallet.pubkey());
+    assert_eq!(state.devices.len(), 1);
+    assert_eq!(state.devices[0].pubkey, PHONE);
+    assert_eq!(state.devices[0].added_at, svm.clock().unix_timestamp);
+    assert_eq!((state.quorum, state.nonce, state.bump), (1, 0, bump));
+
+    // A wallet has one device set
+    svm.expire_blockhash();
+    let result = svm.send_instructions(&[create("laptop".to_string())], &payer, &[&wallet]);
+    assert_error(result, SystemError::AccountAlreadyInUse as u32);
+}
+
+#[test]
+fn either_device_adds_a_third_at_quorum_one() {
+    let mut fx = Fixture::new(1);
+    let change = DeviceChange::Add {
+        pubkey: TABLET,
+        name: "tablet".to_string(),
+    };
+    fx.sign_change(&[LAPTOP], &change);
+    let result = fx.send(fx.change(instruction::AddDevice {
+        pubkey: TABLET,
+        name: "tablet".to_string(),
+    }));
+    assert!(result.is_ok(), "{result:#?}");
+
+    let state = fx.state();
+    assert_eq!(state.devices.len(), 3);
+    assert_eq!(state.devices[2].name, "tablet");
+    assert_eq!(state.nonce, 1);
+
+    // The same signatures do not approve the change again
+    let result = fx.send(fx.change(instruction::SetQuorum { quorum: 2 }));
+    assert_error(result, ErrorCode::QuorumNotMet);
+}
+
+#[test]
+fn a_quorum_of_two_needs_both_devices() {
+    let mut fx = Fixture::new(2);
+    let change = DeviceChange::SetQuorum { quorum: 1 };
+
+    fx.sign_change(&[PHONE], &change);
+    let result = fx.send(fx.change(instruction::SetQuorum { quorum: 1 }));
+    assert_error(result, ErrorCode::QuorumNotMet);
+
+    // Signed, but for a different change
+    fx.sign_change(&[PHONE, LAPTOP], &DeviceChange::SetQuorum { quorum: 2 });
+    let result = fx.send(fx.change(instruction::SetQuorum { quorum: 1 }));
+    assert_error(result, ErrorCode::QuorumNotMet);
+
+    fx.sign_change(&[PHONE, LAPTOP], &change);
+    let result = fx.send(fx.change(instruction::SetQuorum { quorum: 1 }));
+    assert!(result.is_ok(), "{result:#?}");
+    assert_eq!(fx.state().quorum, 1);
+}
+
+#[test]
+fn a_lost_device_is_removed_by_the_one_left() {
+    let mut fx = Fixture::new(1);
+    let change = DeviceChange::Remove { pubkey: PHONE };
+
+    // An unregistered passkey approves nothing
+    fx.sign_change(&[TABLET], &change);
+    let result = fx.send(fx.change(instruction::RemoveDevice { pubkey: PHONE }));
+    assert_error(result, ErrorCode::QuorumNotMet);
+
+    fx.sign_change(&[LAPTOP], &change);
+    let result = fx.send(fx.change(instruction::RemoveDevice { pubkey: PHONE }));
+    assert!(result.is_ok(), "{result:#?}");
+    assert_eq!(fx.state().devices, vec![device(LAPTOP)]);
+}
+
+#[test]
+fn any_registered_device_verifies_a_message() {
+    let mut fx = Fixture::new(2);
+    let message = b"charge 10 USDC, nonce 7";
+
+    fx.set_signed(&[(LAPTOP, message.to_vec())]);
+    let result = fx.send(fx.verify(message));
+    assert!(result.is_ok(), "{result:#?}");
+
+    fx.set_signed(&[(TABLET, message.to_vec())]);
+    let result = fx.send(fx.verify(message));
+    assert_error(result, ErrorCode::QuorumNotMet);
+}
@@ -0,0 +1,420 @@
+use anchor_lang::prelude::*;
+
+declare_id!("D9Nhexyghf9bqi1q5xpLi6TyweuDjGqs6HugyRg74L8Z");
+
+/// Most guardians a wallet can register; approvals are a bitmask over them
+pub const MAX_GUARDIANS: usize = 8;
+
+/// Shortest timelock between reaching the threshold and rotating the signer
+pub const MIN_DELAY_SECONDS: i64 = 86_400;
+
+#[program]
+pub mod passkey_recovery {
+    use super::*;
+
+    /// Register the passkey that controls `wallet` and the guardians who can
+    /// replace it. `threshold` guardians must approve a recovery, and it can
+    /// run `delay_seconds` after the last approval. The wallet signs: a
+    /// keypair, or a LazorKit smart-wallet PDA through the wallet program.
+    pub fn create_recovery(
+        ctx: Context<CreateRecovery>,
+        signer: [u8; 33],
+        guardians: Vec<Pubkey>,
+        threshold: u8,
+        delay_seconds: i64,
+    ) -> Result<()> {
+        let wallet = ctx.accounts.wallet.key();
+        RecoveryConfig::check_params(&wallet, &guardians, threshold, delay_seconds)?;
+
+        let config = &mut ctx.accounts.config;
+        config.wallet = wallet;
+        config.signer = signer;
+        config.guardians = guardians;
+        config.threshold = threshold;
+        config.delay_seconds = delay_seconds;
+        config.pending = None;
+        config.recovery_count = 0;
+        config.bump = ctx.bumps.config;
+
+        emit!(RecoveryConfigured {
+            wallet,
+            guardians: config.guardians.clone(),
+            threshold,
+            delay_seconds,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Recovery configured");
+        msg!(
+            "{} of {} guardians, {}s delay",
+            threshold,
+            config.guardians.len(),
+            delay_seconds
+        );
+
+        Ok(())
+    }
+
+    /// Change the guardians, threshold or delay. Any recovery in progress is
+    /// dropped, since its approvals were counted against the old guardians.
+    pub fn update_guardians(
+        ctx: Context<WalletOnly>,
+        guardians: Vec<Pubkey>,
+        threshold: u8,
+        delay_seconds: i64,
+    ) -> Result<()> {
+        let wallet = ctx.accounts.wallet.key();
+        RecoveryConfig::check_params(&wallet, &guardians, threshold, delay_seconds)?;
+
+        let config = &mut ctx.accounts.config;
+        config.guardians = guardians;
+        config.threshold = threshold;
+        config.delay_seconds = delay_seconds;
+        config.pending = None;
+
+        emit!(RecoveryConfigured {
+            wallet,
+            guardians: config.guardians.clone(),
+            threshold,
+            delay_seconds,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!(
+            "{} of {} guardians, {}s delay",
+            threshold,
+            config.guardians.len(),
+            delay_seconds
+        );
+
+        Ok(())
+    }
+
+    /// Start replacing the wallet's passkey with `new_signer`; the proposing
+    /// guardian's approval counts
+    pub fn propose_recovery(ctx: Context<GuardianAction>, new_signer: [u8; 33]) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let config = &mut ctx.accounts.config;
+        config.propose(&ctx.accounts.guardian.key(), new_signer, now)?;
+
+        emit!(RecoveryProposed {
+            wallet: config.wallet,
+            guardian: ctx.accounts.guardian.key(),
+            new_signer,
+            timestamp: now,
+        });
+
+        msg!("Recovery proposed");
+        msg!(
+            "{} of {} approvals",
+            config.approval_count(),
+            config.threshold
+        );
+
+        Ok(())
+    }
+
+    /// Add a guardian's approval; the timelock starts once the threshold is met
+    pub fn approve_recovery(ctx: Context<GuardianAction>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let config = &mut ctx.accounts.config;
+        config.approve(&ctx.accounts.guardian.key(), now)?;
+
+        emit!(RecoveryApproved {
+            wallet: config.wallet,
+            guardian: ctx.accounts.guardian.key(),
+            approvals: config.approval_count(),
+            executable_at: config.pending.as_ref().map_or(0, |p| p.executable_at),
+            timestamp: now,
+        });
+
+        msg!(
+            "{} of {} approvals",
+            config.approval_count(),
+            config.threshold
+        );
+
+        Ok(())
+    }
+
+    /// The owner still has a working passkey: stop the recovery
+    pub fn cancel_recovery(ctx: Context<WalletOnly>) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        require!(config.pending.is_some(), ErrorCode::NoRecoveryPending);
+        config.pending = None;
+
+        emit!(RecoveryCancelled {
+            wallet: config.wallet,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Recovery cancelled by the wallet");
+
+        Ok(())
+    }
+
+    /// Rotate the signer once the threshold is met and the timelock has run;
+    /// anyone can send it
+    pub fn execute_recovery(ctx: Context<ExecuteRecovery>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let config = &mut ctx.accounts.config;
+        let old_signer = config.signer;
+        config.execute(now)?;
+
+        emit!(SignerRotated {
+            wallet: config.wallet,
+            old_signer,
+            new_signer: config.signer,
+            recovery_count: config.recovery_count,
+            timestamp: now,
+        });
+
+        msg!("Wallet signer rotated");
+        msg!("Recovery #{}", config.recovery_count);
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct CreateRecovery<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + RecoveryConfig::INIT_SPACE,
+        seeds = [b"recovery", wallet.key().as_ref()],
+        bump
+    )]
+    pub config: Account<'info, RecoveryConfig>,
+
+    pub wallet: Signer<'info>,
+
+    /// Fee and rent payer, usually a relayer
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WalletOnly<'info> {
+    #[account(
+        mut,
+        seeds = [b"recovery", wallet.key().as_ref()],
+        bump = config.bump,
+        has_one = wallet
+    )]
+    pub config: Account<'info, RecoveryConfig>,
+
+    pub wallet: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct GuardianAction<'info> {
+    #[account(
+        mut,
+        seeds = [b"recovery", config.wallet.as_ref()],
+        bump = config.bump
+    )]
+    pub config: Account<'info, RecoveryConfig>,
+
+    /// One of the registered guardians, checked in the handler
+    pub guardian: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteRecovery<'info> {
+    #[account(
+        mut,
+        seeds = [b"recovery", config.wallet.as_ref()],
+        bump = config.bump
+    )]
+    pub config: Account<'info, RecoveryConfig>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, InitSpace, Debug, Clone, PartialEq, Eq)]
+pub struct PendingRecovery {
+    pub new_signer: [u8; 33],
+    /// Bit `i` is set once `guardians[i]` approved
+    pub approvals: u8,
+    pub proposed_at: i64,
+    /// When the signer can be rotated; 0 until the threshold is met
+    pub executable_at: i64,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct RecoveryConfig {
+    pub wallet: Pubkey,
+    /// Compressed secp256r1 key of the passkey currently in control
+    pub signer: [u8; 33],
+    #[max_len(MAX_GUARDIANS)]
+    pub guardians: Vec<Pubkey>,
+    pub threshold: u8,
+    pub delay_seconds: i64,
+    pub pending: Option<PendingRecovery>,
+    pub recovery_count: u64,
+    pub bump: u8,
+}
+
+impl RecoveryConfig {
+    pub fn check_params(
+        wallet: &Pubkey,
+        guardians: &[Pubkey],
+        threshold: u8,
+        delay_seconds: i64,
+    ) -> Result<()> {
+        require!(
+            guardians.len() <= MAX_GUARDIANS,
+            ErrorCode::TooManyGuardians
+        );
+        require!(
+            threshold > 0 && threshold as usize <= guardians.len(),
+            ErrorCode::InvalidThreshold
+        );
+        for (i, guardian) in guardians.iter().enumerate() {
+            require!(
+                guardian != wallet && !guardians[..i].contains(guardian),
+                ErrorCode::InvalidGuardian
+            );
+        }
+        require!(delay_seconds >= MIN_DELAY_SECONDS, ErrorCode::InvalidDelay);
+        Ok(())
+    }
+
+    fn guardian_bit(&self, guardian: &Pubkey) -> Result<u8> {
+        let index = self
+            .guardians
+            .iter()
+            .position(|g| g == guardian)
+            .ok_or(ErrorCode::NotAGuardian)?;
+        Ok(1 << index)
+    }
+
+    pub fn approval_count(&self) -> u8 {
+        self.pending
+            .as_ref()
+            .map_or(0, |pending| pending.approvals.count_ones() as u8)
+    }
+
+    /// Start a recovery to `new_signer` with `guardian`'s approval
+    pub fn propose(&mut self, guardian: &Pubkey, new_signer: [u8; 33], now: i64) -> Result<()> {
+        let bit = self.guardian_bit(guardian)?;
+        require!(self.pending.is_none(), ErrorCode::RecoveryPending);
+        self.pending = Some(PendingRecovery {
+            new_signer,
+            approvals: 0,
+            proposed_at: now,
+            executable_at: 0,
+        });
+        self.record_approval(bit, now)
+    }
+
+    /// Count `guardian`'s approval of the pending recovery
+    pub fn approve(&mut self, guardian: &Pubkey, now: i64) -> Result<()> {
+        let bit = self.guardian_bit(guardian)?;
+        let pending = self.pending.as_ref().ok_or(ErrorCode::NoRecoveryPending)?;
+        require!(pending.approvals & bit == 0, ErrorCode::AlreadyApproved);
+        self.record_approval(bit, now)
+    }
+
+    fn record_approval(&mut self, bit: u8, now: i64) -> Result<()> {
+        let threshold = self.threshold as u32;
+        let delay_seconds = self.delay_seconds;
+        let pending = self.pending.as_mut().ok_or(ErrorCode::NoRecoveryPending)?;
+        pending.approvals |= bit;
+        if pending.executable_at == 0 && pending.approvals.count_ones() >= threshold {
+            pending.executable_at = now
+                .checked_add(delay_seconds)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+        }
+        Ok(())
+    }
+
+    /// Rotate to the pending signer if the threshold and timelock allow it
+    pub fn execute(&mut self, now: i64) -> Result<()> {
+        let pending = self.pending.as_ref().ok_or(ErrorCode::NoRecoveryPending)?;
+        require!(pending.executable_at != 0, ErrorCode::ThresholdNotMet);
+        require!(now >= pending.executable_at, ErrorCode::TimelockActive);
+        self.signer = pending.new_signer;
+        self.pending = None;
+        self.recovery_count = self
+            .recovery_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecoveryConfigured {
+    pub wallet: Pubkey,
+    pub guardians: Vec<Pubkey>,
+    pub threshold: u8,
+    pub delay_seconds: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecoveryProposed {
+    pub wallet: Pubkey,
+    pub guardian: Pubkey,
+    pub new_signer: [u8; 33],
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecoveryApproved {
+    pub wallet: Pubkey,
+    pub guardian: Pubkey,
+    pub approvals: u8,
+    /// 0 while the threshold is not met
+    pub executable_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecoveryCancelled {
+    pub wallet: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignerRotated {
+    pub wallet: Pubkey,
+    pub old_signer: [u8; 33],
+    pub new_signer: [u8; 33],
+    pub recovery_count: u64,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("A wallet can register at most 8 guardians")]
+    TooManyGuardians,
+    #[msg("Threshold must be between 1 and the number of guardians")]
+    InvalidThreshold,
+    #[msg("Guardians must be distinct and not the wallet itself")]
+    InvalidGuardian,
+    #[msg("Delay must be at least one day")]
+    InvalidDelay,
+    #[msg("Signer is not one of the wallet's guardians")]
+    NotAGuardian,
+    #[msg("A recovery is already in progress")]
+    RecoveryPending,
+    #[msg("No recovery is in progress")]
+    NoRecoveryPending,
+    #[msg("Guardian has already approved this recovery")]
+    AlreadyApproved,
+    #[msg("Not enough guardians have approved the recovery")]
+    ThresholdNotMet,
+    #[msg("Recovery timelock has not passed")]
+    TimelockActive,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
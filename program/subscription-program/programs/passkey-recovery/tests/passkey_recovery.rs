@@ -0,0 +1,379 @@
+//! Native tests for the passkey recovery program.
+//!
+//! Guardian checks, approvals and the timelock are pure methods and are
+//! tested directly. Every instruction runs end to end.
+
+use anchor_lang::error::ErrorCode as AnchorErrorCode;
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, Space, ToAccountMetas};
+use passkey_recovery::{
+    accounts, instruction, ErrorCode, PendingRecovery, RecoveryConfig, ID as PROGRAM_ID,
+    MAX_GUARDIANS, MIN_DELAY_SECONDS,
+};
+use test_harness::{
+    assert_error, assert_success, Keypair, Signer, SystemError, TestSvm, TransactionResult,
+};
+
+const OLD_PASSKEY: [u8; 33] = [2; 33];
+const NEW_PASSKEY: [u8; 33] = [3; 33];
+const DAY: i64 = 86_400;
+
+fn two_of_three(guardians: &[Pubkey]) -> RecoveryConfig {
+    RecoveryConfig {
+        wallet: Pubkey::new_unique(),
+        signer: OLD_PASSKEY,
+        guardians: guardians.to_vec(),
+        threshold: 2,
+        delay_seconds: 2 * DAY,
+        pending: None,
+        recovery_count: 0,
+        bump: 255,
+    }
+}
+
+#[test]
+fn guardians_must_be_distinct_and_reach_the_threshold() {
+    let wallet = Pubkey::new_unique();
+    let a = Pubkey::new_unique();
+    let b = Pubkey::new_unique();
+
+    RecoveryConfig::check_params(&wallet, &[a, b], 2, DAY).unwrap();
+    assert_eq!(
+        RecoveryConfig::check_params(&wallet, &[a, b], 3, DAY).unwrap_err(),
+        ErrorCode::InvalidThreshold.into()
+    );
+    assert_eq!(
+        RecoveryConfig::check_params(&wallet, &[a, b], 0, DAY).unwrap_err(),
+        ErrorCode::InvalidThreshold.into()
+    );
+    assert_eq!(
+        RecoveryConfig::check_params(&wallet, &[a, a], 1, DAY).unwrap_err(),
+        ErrorCode::InvalidGuardian.into()
+    );
+    assert_eq!(
+        RecoveryConfig::check_params(&wallet, &[a, wallet], 1, DAY).unwrap_err(),
+        ErrorCode::InvalidGuardian.into()
+    );
+    assert_eq!(
+        RecoveryConfig::check_params(&wallet, &[a], 1, MIN_DELAY_SECONDS - 1).unwrap_err(),
+        ErrorCode::InvalidDelay.into()
+    );
+    let many: Vec<Pubkey> = (0..=MAX_GUARDIANS).map(|_| Pubkey::new_unique()).collect();
+    assert_eq!(
+        RecoveryConfig::check_params(&wallet, &many, 1, DAY).unwrap_err(),
+        ErrorCode::TooManyGuardians.into()
+    );
+}
+
+#[test]
+fn the_timelock_starts_when_the_threshold_is_met() {
+    let guardians = [
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    ];
+    let mut config = two_of_three(&guardians);
+
+    assert_eq!(
+        config.approve(&guardians[0], 10).unwrap_err(),
+        ErrorCode::NoRecoveryPending.into()
+    );
+    assert_eq!(
+        config
+            .propose(&Pubkey::new_unique(), NEW_PASSKEY, 10)
+            .unwrap_err(),
+        ErrorCode::NotAGuardian.into()
+    );
+
+    config.propose(&guardians[0], NEW_PASSKEY, 10).unwrap();
+    assert_eq!(config.approval_count(), 1);
+    assert_eq!(
+        config.propose(&guardians[1], OLD_PASSKEY, 10).unwrap_err(),
+        ErrorCode::RecoveryPending.into()
+    );
+    assert_eq!(
+        config.approve(&guardians[0], 20).unwrap_err(),
+        ErrorCode::AlreadyApproved.into()
+    );
+    assert_eq!(
+        config.execute(i64::MAX).unwrap_err(),
+        ErrorCode::ThresholdNotMet.into()
+    );
+
+    config.approve(&guardians[2], 100).unwrap();
+    assert_eq!(
+        config.pending,
+        Some(PendingRecovery {
+            new_signer: NEW_PASSKEY,
+            approvals: 0b101,
+            proposed_at: 10,
+            executable_at: 100 + 2 * DAY,
+        })
+    );
+    // A late approval does not push the timelock back
+    config.approve(&guardians[1], 200).unwrap();
+    assert_eq!(
+        config.pending.as_ref().unwrap().executable_at,
+        100 + 2 * DAY
+    );
+
+    assert_eq!(
+        config.execute(100 + 2 * DAY - 1).unwrap_err(),
+        ErrorCode::TimelockActive.into()
+    );
+    config.execute(100 + 2 * DAY).unwrap();
+    assert_eq!(config.signer, NEW_PASSKEY);
+    assert_eq!(config.pending, None);
+    assert_eq!(config.recovery_count, 1);
+}
+
+struct Fixture {
+    svm: TestSvm,
+    payer: Keypair,
+    wallet: Keypair,
+    guardians: Vec<Keypair>,
+    config: Pubkey,
+}
+
+impl Fixture {
+    fn new() -> Self {
+        let (mut svm, payer) = TestSvm::for_program(PROGRAM_ID, passkey_recovery::entry);
+
+        let wallet = Keypair::new();
+        let guardians: Vec<Keypair> = (0..3).map(|_| Keypair::new()).collect();
+
+        let (config, bump) =
+            Pubkey::find_program_address(&[b"recovery", wallet.pubkey().as_ref()], &PROGRAM_ID);
+        let keys: Vec<Pubkey> = guardians.iter().map(|g| g.pubkey()).collect();
+        let state = RecoveryConfig {
+            wallet: wallet.pubkey(),
+            bump,
+            ..two_of_three(&keys)
+        };
+        svm.set_anchor_account(config, &state, 8 + RecoveryConfig::INIT_SPACE);
+
+        Self {
+            svm,
+            payer,
+            wallet,
+            guardians,
+            config,
+        }
+    }
+
+    fn config(&self) -> RecoveryConfig {
+        self.svm.get_anchor_account(&self.config).unwrap()
+    }
+
+    fn create(&self, guardians: Vec<Pubkey>, threshold: u8) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CreateRecovery {
+                config: self.config,
+                wallet: self.wallet.pubkey(),
+                payer: self.payer.pubkey(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CreateRecovery {
+                signer: OLD_PASSKEY,
+                guardians,
+                threshold,
+                delay_seconds: 2 * DAY,
+            }
+            .data(),
+        }
+    }
+
+    fn propose(&self, guardian: Pubkey) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::GuardianAction {
+                config: self.config,
+                guardian,
+            }
+            .to_account_metas(None),
+            data: instruction::ProposeRecovery {
+                new_signer: NEW_PASSKEY,
+            }
+            .data(),
+        }
+    }
+
+    fn approve(&self, guardian: Pubkey) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::GuardianAction {
+                config: self.config,
+                guardian,
+            }
+            .to_account_metas(None),
+            data: instruction::ApproveRecovery {}.data(),
+        }
+    }
+
+    fn cancel(&self, wallet: Pubkey) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::WalletOnly {
+                config: self.config,
+                wallet,
+            }
+            .to_account_metas(None),
+            data: instruction::CancelRecovery {}.data(),
+        }
+    }
+
+    fn update_guardians(&self, wallet: Pubkey, guardians: Vec<Pubkey>) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::WalletOnly {
+                config: self.config,
+                wallet,
+            }
+            .to_account_metas(None),
+            data: instruction::UpdateGuardians {
+                guardians,
+                threshold: 1,
+                delay_seconds: DAY,
+            }
+            .data(),
+        }
+    }
+
+    fn execute(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::ExecuteRecovery {
+                config: self.config,
+            }
+            .to_account_metas(None),
+            data: instruction::ExecuteRecovery {}.data(),
+        }
+    }
+
+    fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TransactionResult {
+        let payer = self.payer.insecure_clone();
+        self.svm.expire_blockhash();
+        self.svm.send_instructions(&[instruction], &payer, signers)
+    }
+
+    fn send_as_guardian(&mut self, index: usize, propose: bool) -> TransactionResult {
+        let guardian = self.guardians[index].insecure_clone();
+        let instruction = if propose {
+            self.propose(guardian.pubkey())
+        } else {
+            self.approve(guardian.pubkey())
+        };
+        self.send(instruction, &[&guardian])
+    }
+}
+
+#[test]
+fn wallets_name_their_guardians_once() {
+    let mut fx = Fixture::new();
+    fx.svm.remove_account(&fx.config);
+    let keys: Vec<Pubkey> = fx.guardians.iter().map(|g| g.pubkey()).collect();
+    let wallet = fx.wallet.insecure_clone();
+
+    let result = fx.send(fx.create(keys.clone(), 4), &[&wallet]);
+    assert_error(result, ErrorCode::InvalidThreshold);
+    let result = fx.send(fx.create(vec![keys[0], wallet.pubkey()], 1), &[&wallet]);
+    assert_error(result, ErrorCode::InvalidGuardian);
+
+    let result = fx.send(fx.create(keys.clone(), 2), &[&wallet]);
+    assert_success(result);
+    let config = fx.config();
+    assert_eq!(config.wallet, wallet.pubkey());
+    assert_eq!(config.signer, OLD_PASSKEY);
+    assert_eq!(config.guardians, keys);
+    assert_eq!((config.threshold, config.delay_seconds), (2, 2 * DAY));
+    assert_eq!((config.pending, config.recovery_count), (None, 0));
+
+    // Later changes go through `update_guardians`
+    let result = fx.send(fx.create(keys, 1), &[&wallet]);
+    assert_error(result, SystemError::AccountAlreadyInUse as u32);
+}
+
+#[test]
+fn guardians_rotate_a_lost_passkey_after_the_timelock() {
+    let mut fx = Fixture::new();
+    let result = fx.send_as_guardian(0, true);
+    assert!(result.is_ok(), "{result:#?}");
+
+    let result = fx.send(fx.execute(), &[]);
+    assert_error(result, ErrorCode::ThresholdNotMet);
+
+    let result = fx.send_as_guardian(1, false);
+    assert!(result.is_ok(), "{result:#?}");
+    let result = fx.send(fx.execute(), &[]);
+    assert_error(result, ErrorCode::TimelockActive);
+
+    fx.svm.advance_time(2 * DAY);
+    let result = fx.send(fx.execute(), &[]);
+    assert!(result.is_ok(), "{result:#?}");
+
+    let config = fx.config();
+    assert_eq!(config.signer, NEW_PASSKEY);
+    assert_eq!(config.pending, None);
+    assert_eq!(config.recovery_count, 1);
+}
+
+#[test]
+fn only_guardians_propose_and_approve() {
+    let mut fx = Fixture::new();
+    let stranger = Keypair::new();
+    let result = fx.send(fx.propose(stranger.pubkey()), &[&stranger]);
+    assert_error(result, ErrorCode::NotAGuardian);
+
+    let result = fx.send_as_guardian(0, true);
+    assert!(result.is_ok(), "{result:#?}");
+    let result = fx.send(fx.approve(stranger.pubkey()), &[&stranger]);
+    assert_error(result, ErrorCode::NotAGuardian);
+    let result = fx.send_as_guardian(0, false);
+    assert_error(result, ErrorCode::AlreadyApproved);
+}
+
+#[test]
+fn the_owner_cancels_a_recovery_during_the_timelock() {
+    let mut fx = Fixture::new();
+    fx.send_as_guardian(0, true).unwrap();
+    fx.send_as_guardian(2, false).unwrap();
+
+    let guardian = fx.guardians[0].insecure_clone();
+    let result = fx.send(fx.cancel(guardian.pubkey()), &[&guardian]);
+    assert_error(result, AnchorErrorCode::ConstraintSeeds);
+
+    let wallet = fx.wallet.insecure_clone();
+    let result = fx.send(fx.cancel(wallet.pubkey()), &[&wallet]);
+    assert!(result.is_ok(), "{result:#?}");
+    assert_eq!(fx.config().pending, None);
+
+    fx.svm.advance_time(2 * DAY);
+    let result = fx.send(fx.execute(), &[]);
+    assert_error(result, ErrorCode::NoRecoveryPending);
+    assert_eq!(fx.config().signer, OLD_PASSKEY);
+}
+
+#[test]
+fn changing_guardians_drops_a_pending_recovery() {
+    let mut fx = Fixture::new();
+    fx.send_as_guardian(0, true).unwrap();
+
+    let wallet = fx.wallet.insecure_clone();
+    let friend = Pubkey::new_unique();
+    let result = fx.send(
+        fx.update_guardians(wallet.pubkey(), vec![friend]),
+        &[&wallet],
+    );
+    assert!(result.is_ok(), "{result:#?}");
+
+    let config = fx.config();
+    assert_eq!(config.guardians, vec![friend]);
+    assert_eq!(config.threshold, 1);
+    assert_eq!(config.pending, None);
+
+    let result = fx.send_as_guardian(1, false);
+    assert_error(result, ErrorCode::NotAGuardian);
+}
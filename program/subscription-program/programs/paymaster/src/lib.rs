@@ -0,0 +1,918 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::invoke;
+use spl_token::instruction as token_instruction;
+use token_utils::token_account;
+
+declare_id!("HeA6aSFCCn8D9Fifw2ruKxktvoEF91rbs6H9WgBULfAk");
+
+pub const LAMPORTS_PER_SOL: u64 = 1_000_000_000;
+
+/// Most a sponsor can add on top of the SOL price, 100%
+pub const MAX_MARKUP_BPS: u16 = 10_000;
+
+/// Most relayers one allowlist can hold
+pub const MAX_RELAYERS: usize = 16;
+
+/// Most `update_price` can raise the SOL price at a time, 10%
+pub const MAX_PRICE_RISE_BPS: u16 = 1_000;
+
+/// How long a raised price holds before `update_price` can raise it again,
+/// so it climbs at most `MAX_PRICE_RISE_BPS` an hour
+pub const PRICE_RISE_INTERVAL_SECONDS: i64 = 60 * 60;
+
+/// How long a queued config change waits before it can be applied, so users
+/// see a new markup, fee account or sponsor coming
+pub const CONFIG_TIMELOCK_SECONDS: i64 = 2 * 24 * 60 * 60;
+
+#[program]
+pub mod paymaster {
+    use super::*;
+
+    /// Open a SOL vault that sells fee and rent sponsorship for the token
+    /// held by `fee_token_account`, usually USDC. `price_per_sol` is what one
+    /// SOL costs in the token's base units; `markup_bps` is added on top.
+    /// Fund the vault with a plain SOL transfer to the paymaster address.
+    pub fn create_paymaster(
+        ctx: Context<CreatePaymaster>,
+        price_per_sol: u64,
+        markup_bps: u16,
+    ) -> Result<()> {
+        Paymaster::check_price(price_per_sol, markup_bps)?;
+
+        let sponsor = ctx.accounts.sponsor.key();
+        let account = token_account(
+            &ctx.accounts.fee_token_account,
+            ErrorCode::InvalidTokenAccount,
+        )?;
+        require_keys_eq!(account.owner, sponsor, ErrorCode::InvalidTokenAccount);
+
+        let paymaster = &mut ctx.accounts.paymaster;
+        paymaster.sponsor = sponsor;
+        paymaster.fee_mint = account.mint;
+        paymaster.fee_token_account = ctx.accounts.fee_token_account.key();
+        paymaster.price_per_sol = price_per_sol;
+        paymaster.markup_bps = markup_bps;
+        paymaster.total_sponsored = 0;
+        paymaster.total_fees = 0;
+        paymaster.creator = sponsor;
+        paymaster.price_updated_at = Clock::get()?.unix_timestamp;
+        paymaster.bump = ctx.bumps.paymaster;
+
+        emit!(PaymasterCreated {
+            paymaster: paymaster.key(),
+            sponsor,
+            fee_mint: account.mint,
+            price_per_sol,
+            markup_bps,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Paymaster created");
+        msg!("1 SOL = {} tokens + {} bps", price_per_sol, markup_bps);
+
+        Ok(())
+    }
+
+    /// Create the list of relayers `sponsor` will pay back. Until a relayer
+    /// is on it, the paymaster sponsors nobody.
+    pub fn create_allowlist(ctx: Context<CreateAllowlist>, relayers: Vec<Pubkey>) -> Result<()> {
+        RelayerAllowlist::check_relayers(&relayers)?;
+
+        let allowlist = &mut ctx.accounts.allowlist;
+        allowlist.paymaster = ctx.accounts.paymaster.key();
+        allowlist.relayers = relayers;
+        allowlist.bump = ctx.bumps.allowlist;
+
+        emit!(RelayersUpdated {
+            paymaster: allowlist.paymaster,
+            relayers: allowlist.relayers.clone(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Relayer allowlist created");
+        msg!("{} relayers allowed", allowlist.relayers.len());
+
+        Ok(())
+    }
+
+    /// Replace the allowed relayers, e.g. to rotate a relayer's fee-payer key
+    pub fn set_relayers(ctx: Context<SetRelayers>, relayers: Vec<Pubkey>) -> Result<()> {
+        RelayerAllowlist::check_relayers(&relayers)?;
+
+        let allowlist = &mut ctx.accounts.allowlist;
+        allowlist.relayers = relayers;
+
+        emit!(RelayersUpdated {
+            paymaster: allowlist.paymaster,
+            relayers: allowlist.relayers.clone(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("{} relayers allowed", allowlist.relayers.len());
+
+        Ok(())
+    }
+
+    /// Reprice SOL as its price moves. This stays out of the config
+    /// timelock, since the price has to follow SOL within hours, not days.
+    /// Instead a rise is at most `MAX_PRICE_RISE_BPS` and waits
+    /// `PRICE_RISE_INTERVAL_SECONDS` after the last update, and a user never
+    /// pays over the `max_fee` they signed; a cut applies at once. The markup
+    /// on top only changes through `queue_config_change`.
+    pub fn update_price(ctx: Context<UpdatePaymaster>, price_per_sol: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let paymaster = &mut ctx.accounts.paymaster;
+        Paymaster::check_price(price_per_sol, paymaster.markup_bps)?;
+        paymaster.check_price_rise(price_per_sol, now)?;
+        paymaster.price_per_sol = price_per_sol;
+        paymaster.price_updated_at = now;
+
+        emit!(PriceUpdated {
+            paymaster: paymaster.key(),
+            price_per_sol,
+            markup_bps: paymaster.markup_bps,
+            timestamp: now,
+        });
+
+        msg!(
+            "1 SOL = {} tokens + {} bps",
+            price_per_sol,
+            paymaster.markup_bps
+        );
+
+        Ok(())
+    }
+
+    /// Queue a new markup, fee account (passed as `fee_token_account`) or
+    /// sponsor, any of them. It applies after `CONFIG_TIMELOCK_SECONDS`, so
+    /// users and relayers can react first; one change is pending at a time.
+    pub fn queue_config_change(
+        ctx: Context<QueueConfigChange>,
+        markup_bps: Option<u16>,
+        new_sponsor: Option<Pubkey>,
+    ) -> Result<()> {
+        let fee_token_account = match &ctx.accounts.fee_token_account {
+            Some(info) => Some((
+                info.key(),
+                token_account(info, ErrorCode::InvalidTokenAccount)?.mint,
+            )),
+            None => None,
+        };
+        ctx.accounts
+            .paymaster
+            .check_config_change(markup_bps, fee_token_account, new_sponsor)?;
+
+        let now = Clock::get()?.unix_timestamp;
+        let change = &mut ctx.accounts.config_change;
+        change.paymaster = ctx.accounts.paymaster.key();
+        change.markup_bps = markup_bps;
+        change.fee_token_account = fee_token_account.map(|(key, _)| key);
+        change.sponsor = new_sponsor;
+        change.effective_at = now
+            .checked_add(CONFIG_TIMELOCK_SECONDS)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        change.bump = ctx.bumps.config_change;
+
+        emit!(ConfigChangeQueued {
+            paymaster: change.paymaster,
+            markup_bps,
+            fee_token_account: change.fee_token_account,
+            sponsor: new_sponsor,
+            effective_at: change.effective_at,
+            timestamp: now,
+        });
+
+        msg!("Config change effective at: {}", change.effective_at);
+
+        Ok(())
+    }
+
+    /// Apply a queued config change once its timelock has run out. Anyone
+    /// can send it; the change's rent goes back to the sponsor that queued it.
+    pub fn apply_config_change(ctx: Context<ApplyConfigChange>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let change = &ctx.accounts.config_change;
+        require!(change.is_effective(now), ErrorCode::ConfigChangeTimelocked);
+
+        let paymaster = &mut ctx.accounts.paymaster;
+        if let Some(markup_bps) = change.markup_bps {
+            paymaster.markup_bps = markup_bps;
+        }
+        if let Some(fee_token_account) = change.fee_token_account {
+            paymaster.fee_token_account = fee_token_account;
+        }
+        if let Some(sponsor) = change.sponsor {
+            paymaster.sponsor = sponsor;
+        }
+
+        emit!(ConfigChangeApplied {
+            paymaster: paymaster.key(),
+            markup_bps: paymaster.markup_bps,
+            fee_token_account: paymaster.fee_token_account,
+            sponsor: paymaster.sponsor,
+            timestamp: now,
+        });
+
+        msg!("Config change applied");
+
+        Ok(())
+    }
+
+    /// Drop a queued config change before or after its timelock
+    pub fn cancel_config_change(ctx: Context<CancelConfigChange>) -> Result<()> {
+        emit!(ConfigChangeCancelled {
+            paymaster: ctx.accounts.paymaster.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Config change cancelled");
+
+        Ok(())
+    }
+
+    /// Pay back `lamports` of fees and rent the relayer fronted in this
+    /// transaction, and collect their price in the fee token from the user.
+    /// Both legs happen here, so the vault is never out of pocket and the
+    /// user never pays for a transaction that failed. Only relayers on the
+    /// sponsor's allowlist are paid back. `max_fee` is the most the user
+    /// agreed to pay, usually what they were quoted, so a price raised
+    /// between quote and landing fails the transaction instead.
+    pub fn sponsor(ctx: Context<Sponsor>, lamports: u64, max_fee: u64) -> Result<()> {
+        require!(lamports > 0, ErrorCode::InvalidAmount);
+        ctx.accounts
+            .allowlist
+            .check_relayer(&ctx.accounts.relayer.key())?;
+
+        let paymaster = &ctx.accounts.paymaster;
+        let fee = paymaster.fee_for(lamports)?;
+        require!(fee <= max_fee, ErrorCode::FeeAboveMax);
+
+        let info = paymaster.to_account_info();
+        let reserve = Rent::get()?.minimum_balance(info.data_len());
+        require!(
+            info.lamports().saturating_sub(reserve) >= lamports,
+            ErrorCode::InsufficientVault
+        );
+
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.user_token_account.key(),
+            &ctx.accounts.fee_token_account.key(),
+            &ctx.accounts.user.key(),
+            &[],
+            fee,
+        )?;
+
+        invoke(
+            &transfer_ix,
+            &[
+                ctx.accounts.user_token_account.to_account_info(),
+                ctx.accounts.fee_token_account.to_account_info(),
+                ctx.accounts.user.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        ctx.accounts.paymaster.sub_lamports(lamports)?;
+        ctx.accounts.relayer.add_lamports(lamports)?;
+
+        let paymaster = &mut ctx.accounts.paymaster;
+        paymaster.record_sponsorship(lamports, fee)?;
+
+        emit!(Sponsored {
+            paymaster: paymaster.key(),
+            user: ctx.accounts.user.key(),
+            relayer: ctx.accounts.relayer.key(),
+            lamports,
+            fee,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Sponsored {} lamports", lamports);
+        msg!("Fee: {} tokens", fee);
+
+        Ok(())
+    }
+
+    /// Quote what a sponsored operation costs the user in the fee mint:
+    /// `charge_amount` the operation itself moves, plus the fee `sponsor`
+    /// would take for `rent_lamports` of new accounts and `fee_lamports` of
+    /// transaction fees. Read only; simulate it and decode the return data.
+    pub fn quote(
+        ctx: Context<QuotePaymaster>,
+        charge_amount: u64,
+        rent_lamports: u64,
+        fee_lamports: u64,
+    ) -> Result<Quote> {
+        ctx.accounts
+            .paymaster
+            .quote(charge_amount, rent_lamports, fee_lamports)
+    }
+
+    /// Take SOL back out of the vault, keeping it rent exempt
+    pub fn withdraw(ctx: Context<UpdatePaymaster>, lamports: u64) -> Result<()> {
+        require!(lamports > 0, ErrorCode::InvalidAmount);
+
+        let info = ctx.accounts.paymaster.to_account_info();
+        let reserve = Rent::get()?.minimum_balance(info.data_len());
+        require!(
+            info.lamports().saturating_sub(reserve) >= lamports,
+            ErrorCode::InsufficientVault
+        );
+
+        ctx.accounts.paymaster.sub_lamports(lamports)?;
+        ctx.accounts.sponsor.add_lamports(lamports)?;
+
+        emit!(Withdrawn {
+            paymaster: ctx.accounts.paymaster.key(),
+            lamports,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Withdrew {} lamports", lamports);
+
+        Ok(())
+    }
+
+    /// Rewrite a paymaster created before `creator` and `price_updated_at`
+    /// were added. No handover could have happened yet, so `creator` is its
+    /// sponsor. Anyone can send it; `payer` tops up the rent of the larger
+    /// account.
+    pub fn migrate_paymaster(ctx: Context<MigratePaymaster>) -> Result<()> {
+        let account = ctx.accounts.paymaster.to_account_info();
+        let paymaster = {
+            let data = account.try_borrow_data()?;
+            require!(
+                data.starts_with(Paymaster::DISCRIMINATOR)
+                    && data.len() == 8 + PaymasterV1::INIT_SPACE,
+                ErrorCode::PaymasterAlreadyMigrated
+            );
+            PaymasterV1::deserialize(&mut &data[8..])?.migrate()
+        };
+
+        // Rewrite in place before the rent CPI
+        let space = 8 + Paymaster::INIT_SPACE;
+        account.resize(space)?;
+        paymaster.try_serialize(&mut &mut account.try_borrow_mut_data()?[..])?;
+
+        emit!(PaymasterMigrated {
+            paymaster: account.key(),
+            sponsor: paymaster.sponsor,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Paymaster migrated to the current layout");
+
+        let top_up = Rent::get()?
+            .minimum_balance(space)
+            .saturating_sub(account.lamports());
+        if top_up > 0 {
+            anchor_lang::system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.payer.to_account_info(),
+                        to: account,
+                    },
+                ),
+                top_up,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct CreatePaymaster<'info> {
+    #[account(
+        init,
+        payer = sponsor,
+        space = 8 + Paymaster::INIT_SPACE,
+        seeds = [b"paymaster", sponsor.key().as_ref()],
+        bump
+    )]
+    pub paymaster: Account<'info, Paymaster>,
+
+    #[account(mut)]
+    pub sponsor: Signer<'info>,
+
+    /// CHECK: Sponsor's token account that collects fees, checked in the handler
+    pub fee_token_account: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CreateAllowlist<'info> {
+    #[account(
+        init,
+        payer = sponsor,
+        space = 8 + RelayerAllowlist::INIT_SPACE,
+        seeds = [b"relayers", paymaster.key().as_ref()],
+        bump
+    )]
+    pub allowlist: Account<'info, RelayerAllowlist>,
+
+    #[account(
+        seeds = [b"paymaster", paymaster.creator.as_ref()],
+        bump = paymaster.bump,
+        has_one = sponsor
+    )]
+    pub paymaster: Account<'info, Paymaster>,
+
+    #[account(mut)]
+    pub sponsor: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetRelayers<'info> {
+    #[account(
+        mut,
+        seeds = [b"relayers", paymaster.key().as_ref()],
+        bump = allowlist.bump,
+        has_one = paymaster
+    )]
+    pub allowlist: Account<'info, RelayerAllowlist>,
+
+    #[account(
+        seeds = [b"paymaster", paymaster.creator.as_ref()],
+        bump = paymaster.bump,
+        has_one = sponsor
+    )]
+    pub paymaster: Account<'info, Paymaster>,
+
+    pub sponsor: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePaymaster<'info> {
+    #[account(
+        mut,
+        seeds = [b"paymaster", paymaster.creator.as_ref()],
+        bump = paymaster.bump,
+        has_one = sponsor
+    )]
+    pub paymaster: Account<'info, Paymaster>,
+
+    #[account(mut)]
+    pub sponsor: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct QueueConfigChange<'info> {
+    #[account(
+        init,
+        payer = sponsor,
+        space = 8 + ConfigChange::INIT_SPACE,
+        seeds = [b"config_change", paymaster.key().as_ref()],
+        bump
+    )]
+    pub config_change: Account<'info, ConfigChange>,
+
+    #[account(
+        seeds = [b"paymaster", paymaster.creator.as_ref()],
+        bump = paymaster.bump,
+        has_one = sponsor
+    )]
+    pub paymaster: Account<'info, Paymaster>,
+
+    #[account(mut)]
+    pub sponsor: Signer<'info>,
+
+    /// CHECK: the new fee account, if the change moves it; token program
+    /// owner and mint are checked in the handler
+    pub fee_token_account: Option<UncheckedAccount<'info>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ApplyConfigChange<'info> {
+    #[account(
+        mut,
+        seeds = [b"config_change", paymaster.key().as_ref()],
+        bump = config_change.bump,
+        has_one = paymaster,
+        close = sponsor
+    )]
+    pub config_change: Account<'info, ConfigChange>,
+
+    #[account(
+        mut,
+        seeds = [b"paymaster", paymaster.creator.as_ref()],
+        bump = paymaster.bump,
+        has_one = sponsor
+    )]
+    pub paymaster: Account<'info, Paymaster>,
+
+    /// CHECK: the sponsor that queued the change; gets its rent back
+    #[account(mut)]
+    pub sponsor: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelConfigChange<'info> {
+    #[account(
+        mut,
+        seeds = [b"config_change", paymaster.key().as_ref()],
+        bump = config_change.bump,
+        has_one = paymaster,
+        close = sponsor
+    )]
+    pub config_change: Account<'info, ConfigChange>,
+
+    #[account(
+        seeds = [b"paymaster", paymaster.creator.as_ref()],
+        bump = paymaster.bump,
+        has_one = sponsor
+    )]
+    pub paymaster: Account<'info, Paymaster>,
+
+    #[account(mut)]
+    pub sponsor: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct QuotePaymaster<'info> {
+    #[account(
+        seeds = [b"paymaster", paymaster.creator.as_ref()],
+        bump = paymaster.bump
+    )]
+    pub paymaster: Account<'info, Paymaster>,
+}
+
+#[derive(Accounts)]
+pub struct Sponsor<'info> {
+    #[account(
+        mut,
+        seeds = [b"paymaster", paymaster.creator.as_ref()],
+        bump = paymaster.bump,
+        has_one = fee_token_account
+    )]
+    pub paymaster: Account<'info, Paymaster>,
+
+    #[account(
+        seeds = [b"relayers", paymaster.key().as_ref()],
+        bump = allowlist.bump,
+        has_one = paymaster
+    )]
+    pub allowlist: Account<'info, RelayerAllowlist>,
+
+    /// Owner of `user_token_account`; a keypair or a LazorKit smart-wallet PDA
+    pub user: Signer<'info>,
+
+    /// CHECK: User's token account in the fee mint, checked by the token program
+    #[account(mut)]
+    pub user_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: The paymaster's fee account
+    #[account(mut)]
+    pub fee_token_account: UncheckedAccount<'info>,
+
+    /// Fee and rent payer being paid back; must be on the allowlist
+    #[account(mut)]
+    pub relayer: Signer<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct MigratePaymaster<'info> {
+    /// CHECK: a paymaster in the layout before `creator`; the handler checks
+    /// its discriminator and size and rewrites it
+    #[account(mut, owner = crate::ID)]
+    pub paymaster: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Paymaster {
+    /// Signs for the paymaster; `creator` until handed over
+    pub sponsor: Pubkey,
+    /// Token fees are paid in, usually USDC
+    pub fee_mint: Pubkey,
+    /// Account that collects the fees
+    pub fee_token_account: Pubkey,
+    /// Base units of the fee mint one SOL costs
+    pub price_per_sol: u64,
+    /// Added on top of the SOL price
+    pub markup_bps: u16,
+    /// Lamports paid back to relayers over the vault's life
+    pub total_sponsored: u64,
+    /// Fees collected over the vault's life
+    pub total_fees: u64,
+    /// Sponsor that created the paymaster, which its address derives from
+    pub creator: Pubkey,
+    /// When `price_per_sol` last changed
+    pub price_updated_at: i64,
+    pub bump: u8,
+}
+
+impl Paymaster {
+    pub fn check_price(price_per_sol: u64, markup_bps: u16) -> Result<()> {
+        require!(price_per_sol > 0, ErrorCode::InvalidPrice);
+        require!(markup_bps <= MAX_MARKUP_BPS, ErrorCode::InvalidPrice);
+        Ok(())
+    }
+
+    /// Check `update_price` may move the price to `price_per_sol` at `now`:
+    /// cuts always can, a rise only by `MAX_PRICE_RISE_BPS` and once
+    /// `PRICE_RISE_INTERVAL_SECONDS` have passed since the last update
+    pub fn check_price_rise(&self, price_per_sol: u64, now: i64) -> Result<()> {
+        if price_per_sol <= self.price_per_sol {
+            return Ok(());
+        }
+        require!(
+            now >= self
+                .price_updated_at
+                .saturating_add(PRICE_RISE_INTERVAL_SECONDS),
+            ErrorCode::PriceRiseTooSoon
+        );
+        let cap = self.price_per_sol as u128 * (10_000 + MAX_PRICE_RISE_BPS as u128) / 10_000;
+        require!(price_per_sol as u128 <= cap, ErrorCode::PriceRiseTooLarge);
+        Ok(())
+    }
+
+    /// Fee for sponsoring `lamports`, rounded up so the vault never undercharges
+    pub fn fee_for(&self, lamports: u64) -> Result<u64> {
+        let numerator = (lamports as u128)
+            .checked_mul(self.price_per_sol as u128)
+            .and_then(|value| value.checked_mul(10_000 + self.markup_bps as u128))
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        let denominator = LAMPORTS_PER_SOL as u128 * 10_000;
+        u64::try_from(numerator.div_ceil(denominator))
+            .map_err(|_| ErrorCode::ArithmeticOverflow.into())
+    }
+
+    /// Cost of a sponsored operation, matching what `sponsor(rent_lamports +
+    /// fee_lamports)` then collects
+    pub fn quote(
+        &self,
+        charge_amount: u64,
+        rent_lamports: u64,
+        fee_lamports: u64,
+    ) -> Result<Quote> {
+        let lamports = rent_lamports
+            .checked_add(fee_lamports)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        let sponsorship = self.fee_for(lamports)?;
+        // Split for display; the parts add up to exactly what `sponsor` takes
+        let rent = self.fee_for(rent_lamports)?.min(sponsorship);
+        let total = charge_amount
+            .checked_add(sponsorship)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(Quote {
+            mint: self.fee_mint,
+            charge_amount,
+            rent,
+            network_fee: sponsorship - rent,
+            total,
+        })
+    }
+
+    /// Check a config change changes something and each part of it is
+    /// valid; `fee_token_account` comes with its mint
+    pub fn check_config_change(
+        &self,
+        markup_bps: Option<u16>,
+        fee_token_account: Option<(Pubkey, Pubkey)>,
+        sponsor: Option<Pubkey>,
+    ) -> Result<()> {
+        require!(
+            markup_bps.is_some() || fee_token_account.is_some() || sponsor.is_some(),
+            ErrorCode::EmptyConfigChange
+        );
+        if let Some(markup_bps) = markup_bps {
+            Self::check_price(self.price_per_sol, markup_bps)?;
+        }
+        if let Some((_, mint)) = fee_token_account {
+            require_keys_eq!(mint, self.fee_mint, ErrorCode::InvalidTokenAccount);
+        }
+        Ok(())
+    }
+
+    pub fn record_sponsorship(&mut self, lamports: u64, fee: u64) -> Result<()> {
+        self.total_sponsored = self
+            .total_sponsored
+            .checked_add(lamports)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.total_fees = self
+            .total_fees
+            .checked_add(fee)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+}
+
+/// `Paymaster` as `create_paymaster` wrote it before `creator` and
+/// `price_updated_at`; `migrate_paymaster` rewrites it
+#[derive(AnchorSerialize, AnchorDeserialize, InitSpace)]
+pub struct PaymasterV1 {
+    pub sponsor: Pubkey,
+    pub fee_mint: Pubkey,
+    pub fee_token_account: Pubkey,
+    pub price_per_sol: u64,
+    pub markup_bps: u16,
+    pub total_sponsored: u64,
+    pub total_fees: u64,
+    pub bump: u8,
+}
+
+impl PaymasterV1 {
+    pub fn migrate(self) -> Paymaster {
+        Paymaster {
+            sponsor: self.sponsor,
+            fee_mint: self.fee_mint,
+            fee_token_account: self.fee_token_account,
+            price_per_sol: self.price_per_sol,
+            markup_bps: self.markup_bps,
+            total_sponsored: self.total_sponsored,
+            total_fees: self.total_fees,
+            creator: self.sponsor,
+            price_updated_at: 0,
+            bump: self.bump,
+        }
+    }
+}
+
+/// Relayers a paymaster pays back: the fee-payer keys of the platform's
+/// official gasless service
+#[account]
+#[derive(InitSpace)]
+pub struct RelayerAllowlist {
+    pub paymaster: Pubkey,
+    #[max_len(MAX_RELAYERS)]
+    pub relayers: Vec<Pubkey>,
+    pub bump: u8,
+}
+
+impl RelayerAllowlist {
+    pub fn check_relayers(relayers: &[Pubkey]) -> Result<()> {
+        require!(relayers.len() <= MAX_RELAYERS, ErrorCode::TooManyRelayers);
+        Ok(())
+    }
+
+    pub fn check_relayer(&self, relayer: &Pubkey) -> Result<()> {
+        require!(
+            self.relayers.contains(relayer),
+            ErrorCode::RelayerNotAllowed
+        );
+        Ok(())
+    }
+}
+
+/// A markup, fee account or sponsor change waiting out
+/// `CONFIG_TIMELOCK_SECONDS`; `None` leaves that part as it is
+#[account]
+#[derive(InitSpace)]
+pub struct ConfigChange {
+    pub paymaster: Pubkey,
+    pub markup_bps: Option<u16>,
+    pub fee_token_account: Option<Pubkey>,
+    pub sponsor: Option<Pubkey>,
+    /// When it can be applied
+    pub effective_at: i64,
+    pub bump: u8,
+}
+
+impl ConfigChange {
+    pub fn is_effective(&self, now: i64) -> bool {
+        now >= self.effective_at
+    }
+}
+
+/// User cost of a sponsored operation, in base units of `mint`
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quote {
+    pub mint: Pubkey,
+    pub charge_amount: u64,
+    /// Rent of new accounts, converted at the paymaster's price
+    pub rent: u64,
+    /// Transaction fees, converted at the paymaster's price
+    pub network_fee: u64,
+    pub total: u64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaymasterCreated {
+    pub paymaster: Pubkey,
+    pub sponsor: Pubkey,
+    pub fee_mint: Pubkey,
+    pub price_per_sol: u64,
+    pub markup_bps: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriceUpdated {
+    pub paymaster: Pubkey,
+    pub price_per_sol: u64,
+    pub markup_bps: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sponsored {
+    pub paymaster: Pubkey,
+    pub user: Pubkey,
+    pub relayer: Pubkey,
+    pub lamports: u64,
+    pub fee: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RelayersUpdated {
+    pub paymaster: Pubkey,
+    pub relayers: Vec<Pubkey>,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Withdrawn {
+    pub paymaster: Pubkey,
+    pub lamports: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaymasterMigrated {
+    pub paymaster: Pubkey,
+    pub sponsor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigChangeQueued {
+    pub paymaster: Pubkey,
+    pub markup_bps: Option<u16>,
+    pub fee_token_account: Option<Pubkey>,
+    pub sponsor: Option<Pubkey>,
+    pub effective_at: i64,
+    pub timestamp: i64,
+}
+
+/// The paymaster's config after the change
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigChangeApplied {
+    pub paymaster: Pubkey,
+    pub markup_bps: u16,
+    pub fee_token_account: Pubkey,
+    pub sponsor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigChangeCancelled {
+    pub paymaster: Pubkey,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Amount must be greater than zero")]
+    InvalidAmount,
+    #[msg("Price must be positive and markup at most 10000 bps")]
+    InvalidPrice,
+    #[msg("Vault does not hold enough SOL above its rent reserve")]
+    InsufficientVault,
+    #[msg("Invalid token account - wrong program, owner or mint")]
+    InvalidTokenAccount,
+    #[msg("An allowlist can hold at most 16 relayers")]
+    TooManyRelayers,
+    #[msg("Relayer is not on the paymaster's allowlist")]
+    RelayerNotAllowed,
+    #[msg("A config change must change the markup, fee account or sponsor")]
+    EmptyConfigChange,
+    #[msg("Config change is still timelocked")]
+    ConfigChangeTimelocked,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+    #[msg("Sponsorship fee is above the user's max_fee")]
+    FeeAboveMax,
+    #[msg("Price can rise at most 1000 bps at a time")]
+    PriceRiseTooLarge,
+    #[msg("Price was updated less than an hour ago")]
+    PriceRiseTooSoon,
+    #[msg("Paymaster is already in the current layout")]
+    PaymasterAlreadyMigrated,
+}
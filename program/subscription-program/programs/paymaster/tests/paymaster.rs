@@ -0,0 +1,787 @@
+//! Native tests for the paymaster program.
+//!
+//! Pricing, quotes and config change checks are pure methods and are tested
+//! directly. Every instruction runs end to end, from a funded paymaster that
+//! already has its allowlist.
+
+use anchor_lang::error::ErrorCode as AnchorErrorCode;
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{
+    system_program, AnchorDeserialize, AnchorSerialize, Discriminator, InstructionData, Space,
+    ToAccountMetas,
+};
+use paymaster::{
+    accounts, instruction, ConfigChange, ErrorCode, Paymaster, PaymasterV1, Quote,
+    RelayerAllowlist, CONFIG_TIMELOCK_SECONDS, ID as PROGRAM_ID, LAMPORTS_PER_SOL, MAX_RELAYERS,
+    PRICE_RISE_INTERVAL_SECONDS,
+};
+use test_harness::{
+    assert_error, assert_success, Account, Keypair, Signer, SystemError, TestSvm, TransactionResult,
+};
+
+/// 150 USDC per SOL
+const PRICE: u64 = 150_000_000;
+const VAULT: u64 = 2 * LAMPORTS_PER_SOL;
+
+fn priced(price_per_sol: u64, markup_bps: u16) -> Paymaster {
+    Paymaster {
+        sponsor: Pubkey::new_unique(),
+        fee_mint: Pubkey::new_unique(),
+        fee_token_account: Pubkey::new_unique(),
+        price_per_sol,
+        markup_bps,
+        total_sponsored: 0,
+        total_fees: 0,
+        creator: Pubkey::new_unique(),
+        price_updated_at: 0,
+        bump: 255,
+    }
+}
+
+#[test]
+fn prices_must_be_positive_with_a_bounded_markup() {
+    Paymaster::check_price(PRICE, 10_000).unwrap();
+    assert_eq!(
+        Paymaster::check_price(0, 0).unwrap_err(),
+        ErrorCode::InvalidPrice.into()
+    );
+    assert_eq!(
+        Paymaster::check_price(PRICE, 10_001).unwrap_err(),
+        ErrorCode::InvalidPrice.into()
+    );
+}
+
+#[test]
+fn fees_convert_lamports_at_the_price_plus_markup_rounding_up() {
+    let paymaster = priced(PRICE, 0);
+    assert_eq!(paymaster.fee_for(LAMPORTS_PER_SOL).unwrap(), PRICE);
+    // Rent of a subscription account plus two signatures
+    assert_eq!(paymaster.fee_for(2_425_120).unwrap(), 363_768);
+    // A fraction of a base unit is still charged
+    assert_eq!(paymaster.fee_for(1).unwrap(), 1);
+
+    let paymaster = priced(PRICE, 500);
+    assert_eq!(paymaster.fee_for(LAMPORTS_PER_SOL).unwrap(), 157_500_000);
+
+    let paymaster = priced(u64::MAX, 10_000);
+    assert_eq!(
+        paymaster.fee_for(u64::MAX).unwrap_err(),
+        ErrorCode::ArithmeticOverflow.into()
+    );
+}
+
+#[test]
+fn quotes_add_up_to_what_sponsor_collects() {
+    let paymaster = priced(PRICE, 0);
+    let quote = paymaster.quote(10_000_000, 2_415_120, 10_000).unwrap();
+    assert_eq!(quote.mint, paymaster.fee_mint);
+    assert_eq!(quote.charge_amount, 10_000_000);
+    assert_eq!(quote.rent, 362_268);
+    assert_eq!(quote.network_fee, 1_500);
+    assert_eq!(quote.total, 10_363_768);
+    assert_eq!(
+        quote.rent + quote.network_fee,
+        paymaster.fee_for(2_425_120).unwrap()
+    );
+
+    // Each part rounds up alone, but the parts never exceed the whole
+    let quote = paymaster.quote(0, 1, 1).unwrap();
+    assert_eq!((quote.rent, quote.network_fee, quote.total), (1, 0, 1));
+
+    assert_eq!(
+        paymaster.quote(u64::MAX, 0, 1).unwrap_err(),
+        ErrorCode::ArithmeticOverflow.into()
+    );
+}
+
+struct Fixture {
+    svm: TestSvm,
+    relayer: Keypair,
+    sponsor: Keypair,
+    user: Keypair,
+    user_token_account: Pubkey,
+    paymaster: Pubkey,
+    allowlist: Pubkey,
+    state: Paymaster,
+}
+
+impl Fixture {
+    fn new() -> Self {
+        let mut svm = TestSvm::new();
+        svm.add_program(PROGRAM_ID, paymaster::entry);
+
+        let relayer = Keypair::new();
+        let sponsor = Keypair::new();
+        let user = Keypair::new();
+        svm.airdrop(&relayer.pubkey(), 10_000_000_000);
+        svm.airdrop(&sponsor.pubkey(), 1_000_000_000);
+
+        let mint = svm.create_mint(&Pubkey::new_unique(), 6);
+        let user_token_account =
+            svm.create_associated_token_account(&user.pubkey(), &mint, 50_000_000);
+        let fee_token_account = svm.create_associated_token_account(&sponsor.pubkey(), &mint, 0);
+
+        let (paymaster, bump) =
+            Pubkey::find_program_address(&[b"paymaster", sponsor.pubkey().as_ref()], &PROGRAM_ID);
+        let state = Paymaster {
+            sponsor: sponsor.pubkey(),
+            fee_mint: mint,
+            fee_token_account,
+            creator: sponsor.pubkey(),
+            bump,
+            ..priced(PRICE, 0)
+        };
+        svm.set_anchor_account(paymaster, &state, 8 + Paymaster::INIT_SPACE);
+        svm.airdrop(&paymaster, VAULT);
+
+        let (allowlist, bump) =
+            Pubkey::find_program_address(&[b"relayers", paymaster.as_ref()], &PROGRAM_ID);
+        let relayers = RelayerAllowlist {
+            paymaster,
+            relayers: vec![relayer.pubkey()],
+            bump,
+        };
+        svm.set_anchor_account(allowlist, &relayers, 8 + RelayerAllowlist::INIT_SPACE);
+
+        Self {
+            svm,
+            relayer,
+            sponsor,
+            user,
+            user_token_account,
+            paymaster,
+            allowlist,
+            state,
+        }
+    }
+
+    fn create(&self, fee_token_account: Pubkey, price_per_sol: u64) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CreatePaymaster {
+                paymaster: self.paymaster,
+                sponsor: self.sponsor.pubkey(),
+                fee_token_account,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CreatePaymaster {
+                price_per_sol,
+                markup_bps: 250,
+            }
+            .data(),
+        }
+    }
+
+    fn create_allowlist(&self, relayers: Vec<Pubkey>) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CreateAllowlist {
+                allowlist: self.allowlist,
+                paymaster: self.paymaster,
+                sponsor: self.sponsor.pubkey(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CreateAllowlist { relayers }.data(),
+        }
+    }
+
+    fn config_change_address(&self) -> Pubkey {
+        Pubkey::find_program_address(&[b"config_change", self.paymaster.as_ref()], &PROGRAM_ID).0
+    }
+
+    fn queue_config_change(
+        &self,
+        markup_bps: Option<u16>,
+        fee_token_account: Option<Pubkey>,
+    ) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::QueueConfigChange {
+                config_change: self.config_change_address(),
+                paymaster: self.paymaster,
+                sponsor: self.sponsor.pubkey(),
+                fee_token_account,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::QueueConfigChange {
+                markup_bps,
+                new_sponsor: None,
+            }
+            .data(),
+        }
+    }
+
+    fn sponsor_ix(&self, relayer: Pubkey, fee_token_account: Pubkey, lamports: u64) -> Instruction {
+        let max_fee = self.state.fee_for(lamports).unwrap_or(u64::MAX);
+        self.sponsor_ix_with_max_fee(relayer, fee_token_account, lamports, max_fee)
+    }
+
+    fn sponsor_ix_with_max_fee(
+        &self,
+        relayer: Pubkey,
+        fee_token_account: Pubkey,
+        lamports: u64,
+        max_fee: u64,
+    ) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::Sponsor {
+                paymaster: self.paymaster,
+                allowlist: self.allowlist,
+                user: self.user.pubkey(),
+                user_token_account: self.user_token_account,
+                fee_token_account,
+                relayer,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::Sponsor { lamports, max_fee }.data(),
+        }
+    }
+
+    fn update_price(&self, sponsor: Pubkey, price_per_sol: u64) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::UpdatePaymaster {
+                paymaster: self.paymaster,
+                sponsor,
+            }
+            .to_account_metas(None),
+            data: instruction::UpdatePrice { price_per_sol }.data(),
+        }
+    }
+
+    fn quote(&self, charge_amount: u64, rent_lamports: u64) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::QuotePaymaster {
+                paymaster: self.paymaster,
+            }
+            .to_account_metas(None),
+            data: instruction::Quote {
+                charge_amount,
+                rent_lamports,
+                fee_lamports: 10_000,
+            }
+            .data(),
+        }
+    }
+
+    fn set_relayers(&self, sponsor: Pubkey, relayers: Vec<Pubkey>) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::SetRelayers {
+                allowlist: self.allowlist,
+                paymaster: self.paymaster,
+                sponsor,
+            }
+            .to_account_metas(None),
+            data: instruction::SetRelayers { relayers }.data(),
+        }
+    }
+
+    fn withdraw(&self, lamports: u64) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::UpdatePaymaster {
+                paymaster: self.paymaster,
+                sponsor: self.sponsor.pubkey(),
+            }
+            .to_account_metas(None),
+            data: instruction::Withdraw { lamports }.data(),
+        }
+    }
+
+    fn migrate(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::MigratePaymaster {
+                paymaster: self.paymaster,
+                payer: self.relayer.pubkey(),
+                system_program: anchor_lang::system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::MigratePaymaster {}.data(),
+        }
+    }
+
+    fn set_config_change(&mut self, change: ConfigChange) -> Pubkey {
+        let (address, bump) =
+            Pubkey::find_program_address(&[b"config_change", self.paymaster.as_ref()], &PROGRAM_ID);
+        debug_assert_eq!(address, self.config_change_address());
+        let change = ConfigChange {
+            paymaster: self.paymaster,
+            bump,
+            ..change
+        };
+        self.svm
+            .set_anchor_account(address, &change, 8 + ConfigChange::INIT_SPACE);
+        address
+    }
+
+    fn apply_config_change(&self, config_change: Pubkey, sponsor: Pubkey) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::ApplyConfigChange {
+                config_change,
+                paymaster: self.paymaster,
+                sponsor,
+            }
+            .to_account_metas(None),
+            data: instruction::ApplyConfigChange {}.data(),
+        }
+    }
+
+    fn cancel_config_change(&self, config_change: Pubkey, sponsor: Pubkey) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CancelConfigChange {
+                config_change,
+                paymaster: self.paymaster,
+                sponsor,
+            }
+            .to_account_metas(None),
+            data: instruction::CancelConfigChange {}.data(),
+        }
+    }
+
+    /// Send with the relayer as fee payer, as in a sponsored transaction
+    fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TransactionResult {
+        let relayer = self.relayer.insecure_clone();
+        self.svm
+            .send_instructions(&[instruction], &relayer, signers)
+    }
+
+    fn send_as_user(&mut self, instruction: Instruction) -> TransactionResult {
+        let user = self.user.insecure_clone();
+        self.send(instruction, &[&user])
+    }
+
+    fn send_as_sponsor(&mut self, instruction: Instruction) -> TransactionResult {
+        let sponsor = self.sponsor.insecure_clone();
+        self.send(instruction, &[&sponsor])
+    }
+
+    fn paymaster(&self) -> Paymaster {
+        self.svm.get_anchor_account(&self.paymaster).unwrap()
+    }
+
+    fn allowlist(&self) -> RelayerAllowlist {
+        self.svm.get_anchor_account(&self.allowlist).unwrap()
+    }
+}
+
+#[test]
+fn sponsors_create_a_paymaster_then_its_allowlist() {
+    let mut fx = Fixture::new();
+    fx.svm.remove_account(&fx.paymaster);
+    fx.svm.remove_account(&fx.allowlist);
+
+    // Fees must go to an account the sponsor owns
+    let result = fx.send_as_sponsor(fx.create(fx.user_token_account, PRICE));
+    assert_error(result, ErrorCode::InvalidTokenAccount);
+    let result = fx.send_as_sponsor(fx.create(fx.state.fee_token_account, 0));
+    assert_error(result, ErrorCode::InvalidPrice);
+
+    let result = fx.send_as_sponsor(fx.create(fx.state.fee_token_account, PRICE));
+    assert_success(result);
+    let expected = Paymaster {
+        markup_bps: 250,
+        price_updated_at: fx.svm.clock().unix_timestamp,
+        ..fx.state
+    };
+    let paymaster = fx.paymaster();
+    assert_eq!(
+        (paymaster.sponsor, paymaster.creator, paymaster.fee_mint),
+        (expected.sponsor, expected.creator, expected.fee_mint)
+    );
+    assert_eq!(paymaster.fee_token_account, expected.fee_token_account);
+    assert_eq!(
+        (paymaster.price_per_sol, paymaster.markup_bps),
+        (PRICE, 250)
+    );
+    assert_eq!(paymaster.price_updated_at, expected.price_updated_at);
+    assert_eq!((paymaster.total_sponsored, paymaster.total_fees), (0, 0));
+
+    let result =
+        fx.send_as_sponsor(fx.create_allowlist(vec![Pubkey::new_unique(); MAX_RELAYERS + 1]));
+    assert_error(result, ErrorCode::TooManyRelayers);
+    let result = fx.send_as_sponsor(fx.create_allowlist(vec![fx.relayer.pubkey()]));
+    assert_success(result);
+    let allowlist = fx.allowlist();
+    assert_eq!(allowlist.paymaster, fx.paymaster);
+    assert_eq!(allowlist.relayers, vec![fx.relayer.pubkey()]);
+
+    // A sponsor has one paymaster
+    fx.svm.expire_blockhash();
+    let result = fx.send_as_sponsor(fx.create(fx.state.fee_token_account, PRICE));
+    assert_error(result, SystemError::AccountAlreadyInUse as u32);
+}
+
+#[test]
+fn sponsoring_collects_the_fee_from_the_user() {
+    let mut fx = Fixture::new();
+    let fee_account = fx.state.fee_token_account;
+    let result = fx.send_as_user(fx.sponsor_ix(fx.relayer.pubkey(), fee_account, 0));
+    assert_error(result, ErrorCode::InvalidAmount);
+
+    let lamports = 2_425_120;
+    let fee = fx.state.fee_for(lamports).unwrap();
+    let vault = fx.svm.get_balance(&fx.paymaster);
+    let result = fx.send_as_user(fx.sponsor_ix(fx.relayer.pubkey(), fee_account, lamports));
+    assert_success(result);
+    assert_eq!(fx.svm.token_balance(&fee_account), fee);
+    assert_eq!(
+        fx.svm.token_balance(&fx.user_token_account),
+        50_000_000 - fee
+    );
+    assert_eq!(fx.svm.get_balance(&fx.paymaster), vault - lamports);
+    let paymaster = fx.paymaster();
+    assert_eq!(
+        (paymaster.total_sponsored, paymaster.total_fees),
+        (lamports, fee)
+    );
+}
+
+#[test]
+fn sponsoring_never_takes_more_than_max_fee() {
+    let mut fx = Fixture::new();
+    let fee_account = fx.state.fee_token_account;
+    let fee = fx.state.fee_for(2_425_120).unwrap();
+    let result = fx.send_as_user(fx.sponsor_ix_with_max_fee(
+        fx.relayer.pubkey(),
+        fee_account,
+        2_425_120,
+        fee - 1,
+    ));
+    assert_error(result, ErrorCode::FeeAboveMax);
+
+    // A user quoted before a price rise is not charged the new price
+    fx.svm.advance_time(PRICE_RISE_INTERVAL_SECONDS);
+    let result = fx.send_as_sponsor(fx.update_price(fx.sponsor.pubkey(), PRICE + PRICE / 10));
+    assert!(result.is_ok(), "{result:#?}");
+    let result = fx.send_as_user(fx.sponsor_ix_with_max_fee(
+        fx.relayer.pubkey(),
+        fee_account,
+        2_425_120,
+        fee,
+    ));
+    assert_error(result, ErrorCode::FeeAboveMax);
+}
+
+#[test]
+fn only_allowlisted_relayers_are_paid_back() {
+    let mut fx = Fixture::new();
+    let fee_account = fx.state.fee_token_account;
+    // A rogue relayer paying the fee itself, posing as the gasless service
+    let rogue = Keypair::new();
+    fx.svm.airdrop(&rogue.pubkey(), 1_000_000_000);
+    let user = fx.user.insecure_clone();
+    let result = fx.svm.send_instructions(
+        &[fx.sponsor_ix(rogue.pubkey(), fee_account, 5_000)],
+        &rogue,
+        &[&user],
+    );
+    assert_error(result, ErrorCode::RelayerNotAllowed);
+
+    // Rotating the official relayer out stops it too
+    let result = fx.send_as_sponsor(fx.set_relayers(fx.sponsor.pubkey(), vec![rogue.pubkey()]));
+    assert!(result.is_ok(), "{result:#?}");
+    let result = fx.send_as_user(fx.sponsor_ix(fx.relayer.pubkey(), fee_account, 5_000));
+    assert_error(result, ErrorCode::RelayerNotAllowed);
+}
+
+#[test]
+fn only_the_sponsor_manages_relayers() {
+    let mut fx = Fixture::new();
+    let stranger = Keypair::new();
+    let result = fx.send(
+        fx.set_relayers(stranger.pubkey(), vec![stranger.pubkey()]),
+        &[&stranger],
+    );
+    assert_error(result, AnchorErrorCode::ConstraintHasOne);
+
+    let result = fx.send_as_sponsor(fx.set_relayers(
+        fx.sponsor.pubkey(),
+        vec![Pubkey::new_unique(); MAX_RELAYERS + 1],
+    ));
+    assert_error(result, ErrorCode::TooManyRelayers);
+
+    let relayers = vec![fx.relayer.pubkey(), Pubkey::new_unique()];
+    let result = fx.send_as_sponsor(fx.set_relayers(fx.sponsor.pubkey(), relayers.clone()));
+    assert!(result.is_ok(), "{result:#?}");
+    assert_eq!(fx.allowlist().relayers, relayers);
+}
+
+#[test]
+fn fees_only_go_to_the_paymasters_account() {
+    let mut fx = Fixture::new();
+    let elsewhere = fx
+        .svm
+        .create_token_account(&fx.user.pubkey(), &fx.state.fee_mint, 0);
+    let result = fx.send_as_user(fx.sponsor_ix(fx.relayer.pubkey(), elsewhere, 5_000));
+    assert_error(result, AnchorErrorCode::ConstraintHasOne);
+}
+
+#[test]
+fn the_vault_keeps_its_rent_reserve() {
+    let mut fx = Fixture::new();
+    let fee_account = fx.state.fee_token_account;
+    let result = fx.send_as_user(fx.sponsor_ix(fx.relayer.pubkey(), fee_account, VAULT + 1));
+    assert_error(result, ErrorCode::InsufficientVault);
+
+    let result = fx.send_as_sponsor(fx.withdraw(VAULT + 1));
+    assert_error(result, ErrorCode::InsufficientVault);
+
+    let before = fx.svm.get_balance(&fx.sponsor.pubkey());
+    let result = fx.send_as_sponsor(fx.withdraw(VAULT));
+    assert!(result.is_ok(), "{result:#?}");
+    assert_eq!(fx.svm.get_balance(&fx.sponsor.pubkey()), before + VAULT);
+    assert_eq!(
+        fx.svm.get_balance(&fx.paymaster),
+        fx.svm
+            .minimum_balance_for_rent_exemption(8 + Paymaster::INIT_SPACE)
+    );
+}
+
+#[test]
+fn only_the_sponsor_reprices() {
+    let mut fx = Fixture::new();
+    let stranger = Keypair::new();
+    let result = fx.send(fx.update_price(stranger.pubkey(), 1), &[&stranger]);
+    assert_error(result, AnchorErrorCode::ConstraintHasOne);
+
+    let result = fx.send_as_sponsor(fx.update_price(fx.sponsor.pubkey(), 0));
+    assert_error(result, ErrorCode::InvalidPrice);
+
+    let result = fx.send_as_sponsor(fx.update_price(fx.sponsor.pubkey(), 140_000_000));
+    assert!(result.is_ok(), "{result:#?}");
+    let paymaster = fx.paymaster();
+    assert_eq!(paymaster.price_per_sol, 140_000_000);
+    // The markup only moves through a queued config change
+    assert_eq!(paymaster.markup_bps, 0);
+}
+
+#[test]
+fn price_rises_are_bounded_per_hour() {
+    let mut fx = Fixture::new();
+    let now = fx.svm.clock().unix_timestamp;
+    fx.state.price_updated_at = now;
+    fx.svm
+        .set_anchor_account(fx.paymaster, &fx.state, 8 + Paymaster::INIT_SPACE);
+    fx.svm.airdrop(&fx.paymaster, VAULT);
+    let sponsor = fx.sponsor.pubkey();
+
+    let result = fx.send_as_sponsor(fx.update_price(sponsor, PRICE + 1));
+    assert_error(result, ErrorCode::PriceRiseTooSoon);
+
+    fx.svm.warp_to_timestamp(now + PRICE_RISE_INTERVAL_SECONDS);
+    let result = fx.send_as_sponsor(fx.update_price(sponsor, PRICE + PRICE / 10 + 1));
+    assert_error(result, ErrorCode::PriceRiseTooLarge);
+    let result = fx.send_as_sponsor(fx.update_price(sponsor, PRICE + PRICE / 10));
+    assert!(result.is_ok(), "{result:#?}");
+    let paymaster = fx.paymaster();
+    assert_eq!(paymaster.price_per_sol, PRICE + PRICE / 10);
+    assert_eq!(
+        paymaster.price_updated_at,
+        now + PRICE_RISE_INTERVAL_SECONDS
+    );
+
+    // Cuts are never held back
+    fx.svm.expire_blockhash();
+    let result = fx.send_as_sponsor(fx.update_price(sponsor, PRICE / 2));
+    assert!(result.is_ok(), "{result:#?}");
+    assert_eq!(fx.paymaster().price_per_sol, PRICE / 2);
+}
+
+#[test]
+fn migrate_gives_old_paymasters_their_creator() {
+    let mut fx = Fixture::new();
+    let old = PaymasterV1 {
+        sponsor: fx.state.sponsor,
+        fee_mint: fx.state.fee_mint,
+        fee_token_account: fx.state.fee_token_account,
+        price_per_sol: PRICE,
+        markup_bps: 250,
+        total_sponsored: 7,
+        total_fees: 11,
+        bump: fx.state.bump,
+    };
+    let mut data = Paymaster::DISCRIMINATOR.to_vec();
+    old.serialize(&mut data).unwrap();
+    assert_eq!(data.len(), 8 + PaymasterV1::INIT_SPACE);
+    fx.svm.set_account(
+        fx.paymaster,
+        Account {
+            lamports: VAULT,
+            data,
+            owner: PROGRAM_ID,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+
+    let result = fx.send(fx.migrate(), &[]);
+    assert!(result.is_ok(), "{result:#?}");
+    let paymaster = fx.paymaster();
+    assert_eq!(paymaster.creator, fx.state.sponsor);
+    assert_eq!(paymaster.sponsor, fx.state.sponsor);
+    assert_eq!(paymaster.markup_bps, 250);
+    assert_eq!((paymaster.total_sponsored, paymaster.total_fees), (7, 11));
+    assert_eq!(paymaster.price_updated_at, 0);
+
+    fx.svm.expire_blockhash();
+    let result = fx.send(fx.migrate(), &[]);
+    assert_error(result, ErrorCode::PaymasterAlreadyMigrated);
+}
+
+#[test]
+fn config_changes_must_change_something_valid() {
+    let paymaster = priced(PRICE, 0);
+    let fee_account = Some((Pubkey::new_unique(), paymaster.fee_mint));
+    paymaster
+        .check_config_change(Some(500), fee_account, Some(Pubkey::new_unique()))
+        .unwrap();
+    for (markup_bps, fee_token_account, sponsor, error) in [
+        (None, None, None, ErrorCode::EmptyConfigChange),
+        (Some(10_001), None, None, ErrorCode::InvalidPrice),
+        (
+            None,
+            Some((Pubkey::new_unique(), Pubkey::new_unique())),
+            None,
+            ErrorCode::InvalidTokenAccount,
+        ),
+    ] {
+        assert_eq!(
+            paymaster
+                .check_config_change(markup_bps, fee_token_account, sponsor)
+                .unwrap_err(),
+            error.into()
+        );
+    }
+}
+
+#[test]
+fn queuing_a_config_change_starts_the_timelock() {
+    let mut fx = Fixture::new();
+    let result = fx.send_as_sponsor(fx.queue_config_change(None, None));
+    assert_error(result, ErrorCode::EmptyConfigChange);
+    let other_mint = fx.svm.create_mint(&Pubkey::new_unique(), 6);
+    let wrong_mint = fx
+        .svm
+        .create_token_account(&fx.sponsor.pubkey(), &other_mint, 0);
+    let result = fx.send_as_sponsor(fx.queue_config_change(None, Some(wrong_mint)));
+    assert_error(result, ErrorCode::InvalidTokenAccount);
+
+    let new_fee_account = fx
+        .svm
+        .create_token_account(&fx.sponsor.pubkey(), &fx.state.fee_mint, 0);
+    let result = fx.send_as_sponsor(fx.queue_config_change(Some(500), Some(new_fee_account)));
+    assert_success(result);
+    let change: ConfigChange = fx
+        .svm
+        .get_anchor_account(&fx.config_change_address())
+        .unwrap();
+    assert_eq!(change.paymaster, fx.paymaster);
+    assert_eq!(change.markup_bps, Some(500));
+    assert_eq!(change.fee_token_account, Some(new_fee_account));
+    assert_eq!(change.sponsor, None);
+    assert_eq!(
+        change.effective_at,
+        fx.svm.clock().unix_timestamp + CONFIG_TIMELOCK_SECONDS
+    );
+    // Nothing changes until it is applied
+    assert_eq!(fx.paymaster().markup_bps, 0);
+
+    // One change is pending at a time
+    let result = fx.send_as_sponsor(fx.queue_config_change(Some(100), None));
+    assert_error(result, SystemError::AccountAlreadyInUse as u32);
+}
+
+#[test]
+fn config_changes_apply_after_the_timelock() {
+    let mut fx = Fixture::new();
+    let new_sponsor = Keypair::new();
+    let new_fee_account = Pubkey::new_unique();
+    let now = fx.svm.clock().unix_timestamp;
+    let change = fx.set_config_change(ConfigChange {
+        paymaster: Pubkey::default(),
+        markup_bps: Some(500),
+        fee_token_account: Some(new_fee_account),
+        sponsor: Some(new_sponsor.pubkey()),
+        effective_at: now + CONFIG_TIMELOCK_SECONDS,
+        bump: 0,
+    });
+    let sponsor = fx.sponsor.pubkey();
+
+    let result = fx.send(fx.apply_config_change(change, sponsor), &[]);
+    assert_error(result, ErrorCode::ConfigChangeTimelocked);
+
+    // Anyone can apply it once it is due; the rent goes back to the sponsor
+    fx.svm.warp_to_timestamp(now + CONFIG_TIMELOCK_SECONDS);
+    let before = fx.svm.get_balance(&sponsor);
+    let rent = fx.svm.get_balance(&change);
+    let result = fx.send(fx.apply_config_change(change, sponsor), &[]);
+    assert!(result.is_ok(), "{result:#?}");
+    assert_eq!(fx.svm.get_balance(&sponsor), before + rent);
+    assert!(fx.svm.get_account(&change).is_none());
+    let paymaster = fx.paymaster();
+    assert_eq!(paymaster.markup_bps, 500);
+    assert_eq!(paymaster.fee_token_account, new_fee_account);
+    assert_eq!(paymaster.sponsor, new_sponsor.pubkey());
+    // The address still derives from the creator
+    assert_eq!(paymaster.creator, sponsor);
+
+    // The handover moved the sponsor's powers with it
+    let result = fx.send_as_sponsor(fx.update_price(sponsor, PRICE));
+    assert_error(result, AnchorErrorCode::ConstraintHasOne);
+    let result = fx.send(
+        fx.update_price(new_sponsor.pubkey(), 140_000_000),
+        &[&new_sponsor],
+    );
+    assert!(result.is_ok(), "{result:#?}");
+}
+
+#[test]
+fn only_the_sponsor_cancels_a_config_change() {
+    let mut fx = Fixture::new();
+    let change = fx.set_config_change(ConfigChange {
+        paymaster: Pubkey::default(),
+        markup_bps: Some(10_000),
+        fee_token_account: None,
+        sponsor: None,
+        effective_at: fx.svm.clock().unix_timestamp + CONFIG_TIMELOCK_SECONDS,
+        bump: 0,
+    });
+    let stranger = Keypair::new();
+    let result = fx.send(
+        fx.cancel_config_change(change, stranger.pubkey()),
+        &[&stranger],
+    );
+    assert_error(result, AnchorErrorCode::ConstraintHasOne);
+
+    let result = fx.send_as_sponsor(fx.cancel_config_change(change, fx.sponsor.pubkey()));
+    assert!(result.is_ok(), "{result:#?}");
+    assert!(fx.svm.get_account(&change).is_none());
+    assert_eq!(fx.paymaster().markup_bps, 0);
+}
+
+#[test]
+fn anyone_can_ask_for_a_quote() {
+    let mut fx = Fixture::new();
+    let result = fx.send(fx.quote(10_000_000, 2_415_120), &[]);
+    let meta = assert_success(result);
+    let (program_id, data) = meta.return_data.unwrap();
+    assert_eq!(program_id, PROGRAM_ID);
+    assert_eq!(
+        Quote::try_from_slice(&data).unwrap(),
+        fx.state.quote(10_000_000, 2_415_120, 10_000).unwrap()
+    );
+
+    let result = fx.send(fx.quote(u64::MAX, 2_415_120), &[]);
+    assert_error(result, ErrorCode::ArithmeticOverflow);
+}
@@ -0,0 +1,590 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use spl_token::instruction as token_instruction;
+use token_utils::token_account;
+
+declare_id!("5mf1avMHTfphD6dJhMr753mmDPCwc1nxjSgujyP8hmKY");
+
+#[program]
+pub mod payroll {
+    use super::*;
+
+    /// Set up an employer's payroll around a vault owned by the payroll PDA
+    pub fn create_payroll(ctx: Context<CreatePayroll>) -> Result<()> {
+        let clock = Clock::get()?;
+        let payroll_key = ctx.accounts.payroll.key();
+        let vault = token_account(&ctx.accounts.vault, ErrorCode::InvalidTokenAccount)?;
+        require_keys_eq!(vault.owner, payroll_key, ErrorCode::InvalidTokenAccount);
+        require_keys_eq!(
+            vault.mint,
+            ctx.accounts.mint.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+
+        let payroll = &mut ctx.accounts.payroll;
+        payroll.employer = ctx.accounts.employer.key();
+        payroll.mint = ctx.accounts.mint.key();
+        payroll.vault = ctx.accounts.vault.key();
+        payroll.employee_count = 0;
+        payroll.total_paid = 0;
+        payroll.created_at = clock.unix_timestamp;
+        payroll.bump = ctx.bumps.payroll;
+
+        emit!(PayrollCreated {
+            payroll: payroll_key,
+            employer: payroll.employer,
+            vault: payroll.vault,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("Payroll created for {}", payroll.employer);
+
+        Ok(())
+    }
+
+    /// Employer tops up the vault from any token account they own
+    pub fn fund_payroll(ctx: Context<FundPayroll>, amount: u64) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.employer_token_account.key(),
+            &ctx.accounts.vault.key(),
+            &ctx.accounts.employer.key(),
+            &[],
+            amount,
+        )?;
+
+        invoke(
+            &transfer_ix,
+            &[
+                ctx.accounts.employer_token_account.to_account_info(),
+                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.employer.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        emit!(PayrollFunded {
+            payroll: ctx.accounts.payroll.key(),
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Payroll funded: {} tokens", amount);
+
+        Ok(())
+    }
+
+    /// Employer takes unused funds back out of the vault
+    pub fn withdraw_funds(ctx: Context<WithdrawFunds>, amount: u64) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        let balance = token_account(&ctx.accounts.vault, ErrorCode::InvalidTokenAccount)?.amount;
+        require!(amount <= balance, ErrorCode::InsufficientFunds);
+
+        transfer_from_vault(
+            &ctx.accounts.payroll,
+            &ctx.accounts.vault,
+            &ctx.accounts.destination,
+            &ctx.accounts.payroll.to_account_info(),
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+
+        msg!("Withdrawn from payroll: {} tokens", amount);
+
+        Ok(())
+    }
+
+    /// Put an employee on the payroll. The first payment is due one interval
+    /// from now, like the subscription program's next charge.
+    pub fn add_employee(
+        ctx: Context<AddEmployee>,
+        salary_per_period: u64,
+        interval_seconds: i64,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        Employee::check_params(salary_per_period, interval_seconds)?;
+
+        let employee_account = token_account(
+            &ctx.accounts.employee_token_account,
+            ErrorCode::InvalidTokenAccount,
+        )?;
+        require_keys_eq!(
+            employee_account.owner,
+            ctx.accounts.employee.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+        require_keys_eq!(
+            employee_account.mint,
+            ctx.accounts.payroll.mint,
+            ErrorCode::InvalidTokenAccount
+        );
+
+        let payroll = &mut ctx.accounts.payroll;
+        payroll.employee_count = payroll
+            .employee_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        let employee = &mut ctx.accounts.employee_record;
+        employee.payroll = payroll.key();
+        employee.employee = ctx.accounts.employee.key();
+        employee.employee_token_account = ctx.accounts.employee_token_account.key();
+        employee.salary_per_period = salary_per_period;
+        employee.interval_seconds = interval_seconds;
+        employee.last_paid_timestamp = clock.unix_timestamp;
+        employee.total_paid = 0;
+        employee.bump = ctx.bumps.employee_record;
+
+        emit!(EmployeeAdded {
+            payroll: employee.payroll,
+            employee: employee.employee,
+            salary_per_period,
+            interval_seconds,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!(
+            "Employee added: {} tokens every {} seconds",
+            salary_per_period,
+            interval_seconds
+        );
+
+        Ok(())
+    }
+
+    /// Change salary or schedule; takes effect from the next payment
+    pub fn update_employee(
+        ctx: Context<UpdateEmployee>,
+        salary_per_period: u64,
+        interval_seconds: i64,
+    ) -> Result<()> {
+        Employee::check_params(salary_per_period, interval_seconds)?;
+
+        let employee = &mut ctx.accounts.employee_record;
+        employee.salary_per_period = salary_per_period;
+        employee.interval_seconds = interval_seconds;
+
+        msg!(
+            "Employee updated: {} tokens every {} seconds",
+            salary_per_period,
+            interval_seconds
+        );
+
+        Ok(())
+    }
+
+    /// Take an employee off the payroll and close their record
+    pub fn remove_employee(ctx: Context<RemoveEmployee>) -> Result<()> {
+        let payroll = &mut ctx.accounts.payroll;
+        payroll.employee_count = payroll.employee_count.saturating_sub(1);
+        let employee = &ctx.accounts.employee_record;
+
+        emit!(EmployeeRemoved {
+            payroll: payroll.key(),
+            employee: employee.employee,
+            total_paid: employee.total_paid,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Employee removed after {} tokens paid", employee.total_paid);
+
+        Ok(())
+    }
+
+    /// Pay one period's salary. Permissionless, like `charge_subscription`:
+    /// any keeper may crank it once the interval has passed.
+    pub fn pay_employee(ctx: Context<PayEmployee>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let employee = &ctx.accounts.employee_record;
+        employee.check_payable(now)?;
+
+        let amount = employee.salary_per_period;
+        let balance = token_account(&ctx.accounts.vault, ErrorCode::InvalidTokenAccount)?.amount;
+        require!(amount <= balance, ErrorCode::InsufficientFunds);
+
+        transfer_from_vault(
+            &ctx.accounts.payroll,
+            &ctx.accounts.vault,
+            &ctx.accounts.employee_token_account,
+            &ctx.accounts.payroll.to_account_info(),
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+
+        let employee = &mut ctx.accounts.employee_record;
+        employee.record_payment(now)?;
+        let payroll = &mut ctx.accounts.payroll;
+        payroll.total_paid = payroll
+            .total_paid
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        emit!(SalaryPaid {
+            payroll: payroll.key(),
+            employee: employee.employee,
+            amount,
+            total_paid: employee.total_paid,
+            timestamp: now,
+        });
+
+        msg!("Salary paid: {} tokens", amount);
+        msg!("Next payment in {} seconds", employee.interval_seconds);
+
+        Ok(())
+    }
+}
+
+/// Move `amount` out of the vault, signed by the payroll PDA
+fn transfer_from_vault<'info>(
+    payroll: &Payroll,
+    vault: &UncheckedAccount<'info>,
+    destination: &UncheckedAccount<'info>,
+    payroll_info: &AccountInfo<'info>,
+    token_program: &UncheckedAccount<'info>,
+    amount: u64,
+) -> Result<()> {
+    let seeds = &[b"payroll", payroll.employer.as_ref(), &[payroll.bump]];
+
+    let transfer_ix = token_instruction::transfer(
+        &token_program.key(),
+        &vault.key(),
+        &destination.key(),
+        &payroll_info.key(),
+        &[],
+        amount,
+    )?;
+
+    invoke_signed(
+        &transfer_ix,
+        &[
+            vault.to_account_info(),
+            destination.to_account_info(),
+            payroll_info.clone(),
+            token_program.to_account_info(),
+        ],
+        &[&seeds[..]],
+    )?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CreatePayroll<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Payroll::INIT_SPACE,
+        seeds = [b"payroll", employer.key().as_ref()],
+        bump
+    )]
+    pub payroll: Account<'info, Payroll>,
+
+    pub employer: Signer<'info>,
+
+    /// CHECK: Token mint (USDC)
+    pub mint: UncheckedAccount<'info>,
+
+    /// CHECK: Token account owned by the payroll PDA (usually its ATA)
+    pub vault: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FundPayroll<'info> {
+    #[account(
+        seeds = [b"payroll", payroll.employer.as_ref()],
+        bump = payroll.bump,
+        has_one = employer,
+        has_one = vault
+    )]
+    pub payroll: Account<'info, Payroll>,
+
+    pub employer: Signer<'info>,
+
+    /// CHECK: Employer's token account, debited by the token program
+    #[account(mut)]
+    pub employer_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Payroll vault
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFunds<'info> {
+    #[account(
+        seeds = [b"payroll", payroll.employer.as_ref()],
+        bump = payroll.bump,
+        has_one = employer,
+        has_one = vault
+    )]
+    pub payroll: Account<'info, Payroll>,
+
+    pub employer: Signer<'info>,
+
+    /// CHECK: Payroll vault
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: Any token account the employer chooses
+    #[account(mut)]
+    pub destination: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AddEmployee<'info> {
+    #[account(
+        mut,
+        seeds = [b"payroll", payroll.employer.as_ref()],
+        bump = payroll.bump,
+        has_one = employer
+    )]
+    pub payroll: Account<'info, Payroll>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Employee::INIT_SPACE,
+        seeds = [b"employee", payroll.key().as_ref(), employee.key().as_ref()],
+        bump
+    )]
+    pub employee_record: Account<'info, Employee>,
+
+    pub employer: Signer<'info>,
+
+    /// CHECK: Employee wallet
+    pub employee: UncheckedAccount<'info>,
+
+    /// CHECK: Employee's token account, checked in the handler
+    pub employee_token_account: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateEmployee<'info> {
+    #[account(
+        seeds = [b"payroll", payroll.employer.as_ref()],
+        bump = payroll.bump,
+        has_one = employer
+    )]
+    pub payroll: Account<'info, Payroll>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"employee",
+            payroll.key().as_ref(),
+            employee_record.employee.as_ref(),
+        ],
+        bump = employee_record.bump,
+        has_one = payroll
+    )]
+    pub employee_record: Account<'info, Employee>,
+
+    pub employer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveEmployee<'info> {
+    #[account(
+        mut,
+        seeds = [b"payroll", payroll.employer.as_ref()],
+        bump = payroll.bump,
+        has_one = employer
+    )]
+    pub payroll: Account<'info, Payroll>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"employee",
+            payroll.key().as_ref(),
+            employee_record.employee.as_ref(),
+        ],
+        bump = employee_record.bump,
+        has_one = payroll,
+        close = employer
+    )]
+    pub employee_record: Account<'info, Employee>,
+
+    /// Receives the record's rent
+    #[account(mut)]
+    pub employer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct PayEmployee<'info> {
+    #[account(
+        mut,
+        seeds = [b"payroll", payroll.employer.as_ref()],
+        bump = payroll.bump,
+        has_one = vault
+    )]
+    pub payroll: Account<'info, Payroll>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"employee",
+            payroll.key().as_ref(),
+            employee_record.employee.as_ref(),
+        ],
+        bump = employee_record.bump,
+        has_one = payroll,
+        has_one = employee_token_account
+    )]
+    pub employee_record: Account<'info, Employee>,
+
+    /// CHECK: Payroll vault
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: Employee's token account
+    #[account(mut)]
+    pub employee_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Payroll {
+    pub employer: Pubkey,
+    pub mint: Pubkey,
+    pub vault: Pubkey,
+    pub employee_count: u32,
+    pub total_paid: u64,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Employee {
+    pub payroll: Pubkey,
+    pub employee: Pubkey,
+    pub employee_token_account: Pubkey,
+    pub salary_per_period: u64,
+    pub interval_seconds: i64,
+    pub last_paid_timestamp: i64,
+    pub total_paid: u64,
+    pub bump: u8,
+}
+
+impl Employee {
+    pub fn check_params(salary_per_period: u64, interval_seconds: i64) -> Result<()> {
+        require!(salary_per_period > 0, ErrorCode::InvalidAmount);
+        require!(interval_seconds > 0, ErrorCode::InvalidInterval);
+        Ok(())
+    }
+
+    /// Whether a payment may be made at `now`; same rule as the subscription
+    /// program's `check_chargeable`
+    pub fn check_payable(&self, now: i64) -> Result<()> {
+        let time_since_last_payment = now.saturating_sub(self.last_paid_timestamp);
+        require!(
+            time_since_last_payment >= self.interval_seconds,
+            ErrorCode::IntervalNotMet
+        );
+
+        Ok(())
+    }
+
+    /// First time at which `check_payable` passes
+    pub fn next_payment_at(&self) -> i64 {
+        self.last_paid_timestamp
+            .saturating_add(self.interval_seconds)
+    }
+
+    /// Book one period's salary paid at `now`
+    pub fn record_payment(&mut self, now: i64) -> Result<()> {
+        self.total_paid = self
+            .total_paid
+            .checked_add(self.salary_per_period)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.last_paid_timestamp = now;
+        Ok(())
+    }
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PayrollCreated {
+    pub payroll: Pubkey,
+    pub employer: Pubkey,
+    pub vault: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PayrollFunded {
+    pub payroll: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmployeeAdded {
+    pub payroll: Pubkey,
+    pub employee: Pubkey,
+    pub salary_per_period: u64,
+    pub interval_seconds: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmployeeRemoved {
+    pub payroll: Pubkey,
+    pub employee: Pubkey,
+    pub total_paid: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SalaryPaid {
+    pub payroll: Pubkey,
+    pub employee: Pubkey,
+    pub amount: u64,
+    pub total_paid: u64,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Amount must be greater than zero")]
+    InvalidAmount,
+    #[msg("Interval must be greater than zero")]
+    InvalidInterval,
+    #[msg("Invalid token account - wrong program, owner or mint")]
+    InvalidTokenAccount,
+    #[msg("Not enough time has passed since last payment")]
+    IntervalNotMet,
+    #[msg("Payroll vault cannot cover this payment")]
+    InsufficientFunds,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
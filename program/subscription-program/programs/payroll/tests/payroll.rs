@@ -0,0 +1,370 @@
+//! Native tests for the payroll program.
+//!
+//! The pay schedule is a pure method on `Employee` and is tested directly.
+//! Instruction tests cover account validation and run end to end against the
+//! real token and system programs, checking the vault and the payslips.
+
+use anchor_lang::error::ErrorCode as AnchorErrorCode;
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, Space, ToAccountMetas};
+use payroll::{accounts, instruction, Employee, ErrorCode, Payroll, ID as PROGRAM_ID};
+use test_harness::{assert_error, assert_success, Keypair, Signer, TestSvm, TransactionResult};
+
+const SALARY: u64 = 2_000_000_000;
+const TWO_WEEKS: i64 = 14 * 86_400;
+
+fn employee_at(last_paid_timestamp: i64) -> Employee {
+    Employee {
+        payroll: Pubkey::new_unique(),
+        employee: Pubkey::new_unique(),
+        employee_token_account: Pubkey::new_unique(),
+        salary_per_period: SALARY,
+        interval_seconds: TWO_WEEKS,
+        last_paid_timestamp,
+        total_paid: 0,
+        bump: 255,
+    }
+}
+
+#[test]
+fn rejects_empty_salary_and_interval() {
+    assert_eq!(
+        Employee::check_params(0, TWO_WEEKS).unwrap_err(),
+        ErrorCode::InvalidAmount.into()
+    );
+    assert_eq!(
+        Employee::check_params(SALARY, 0).unwrap_err(),
+        ErrorCode::InvalidInterval.into()
+    );
+}
+
+#[test]
+fn pays_once_per_interval() {
+    let mut employee = employee_at(1_000);
+    assert_eq!(employee.next_payment_at(), 1_000 + TWO_WEEKS);
+    assert_eq!(
+        employee.check_payable(1_000 + TWO_WEEKS - 1).unwrap_err(),
+        ErrorCode::IntervalNotMet.into()
+    );
+    // A clock behind the last payment is just "not yet due"
+    assert_eq!(
+        employee.check_payable(0).unwrap_err(),
+        ErrorCode::IntervalNotMet.into()
+    );
+
+    let payday = 1_000 + TWO_WEEKS;
+    employee.check_payable(payday).unwrap();
+    employee.record_payment(payday).unwrap();
+    assert_eq!(employee.total_paid, SALARY);
+    assert_eq!(employee.next_payment_at(), payday + TWO_WEEKS);
+}
+
+struct Fixture {
+    svm: TestSvm,
+    payer: Keypair,
+    employer: Keypair,
+    payroll: Pubkey,
+    payroll_state: Payroll,
+    record: Pubkey,
+    employee: Employee,
+    employer_token_account: Pubkey,
+}
+
+impl Fixture {
+    /// A payroll holding `vault_balance` with one employee added at the
+    /// harness's default time
+    fn new(vault_balance: u64) -> Self {
+        let (mut svm, payer) = TestSvm::for_program(PROGRAM_ID, payroll::entry);
+
+        let employer = Keypair::new();
+        let worker = Pubkey::new_unique();
+
+        let mint = svm.create_mint(&Pubkey::new_unique(), 6);
+        let (payroll, payroll_bump) =
+            Pubkey::find_program_address(&[b"payroll", employer.pubkey().as_ref()], &PROGRAM_ID);
+        let (record, record_bump) = Pubkey::find_program_address(
+            &[b"employee", payroll.as_ref(), worker.as_ref()],
+            &PROGRAM_ID,
+        );
+        let now = svm.clock().unix_timestamp;
+
+        let payroll_state = Payroll {
+            employer: employer.pubkey(),
+            mint,
+            vault: svm.create_associated_token_account(&payroll, &mint, vault_balance),
+            employee_count: 1,
+            total_paid: 0,
+            created_at: now,
+            bump: payroll_bump,
+        };
+        svm.set_anchor_account(payroll, &payroll_state, 8 + Payroll::INIT_SPACE);
+
+        let employee = Employee {
+            payroll,
+            employee: worker,
+            employee_token_account: svm.create_associated_token_account(&worker, &mint, 0),
+            bump: record_bump,
+            ..employee_at(now)
+        };
+        svm.set_anchor_account(record, &employee, 8 + Employee::INIT_SPACE);
+        let employer_token_account =
+            svm.create_associated_token_account(&employer.pubkey(), &mint, 0);
+
+        Self {
+            svm,
+            payer,
+            employer,
+            payroll,
+            payroll_state,
+            record,
+            employee,
+            employer_token_account,
+        }
+    }
+
+    fn create(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CreatePayroll {
+                payroll: self.payroll,
+                employer: self.employer.pubkey(),
+                mint: self.payroll_state.mint,
+                vault: self.payroll_state.vault,
+                payer: self.payer.pubkey(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CreatePayroll {}.data(),
+        }
+    }
+
+    fn fund(&self, amount: u64) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::FundPayroll {
+                payroll: self.payroll,
+                employer: self.employer.pubkey(),
+                employer_token_account: self.employer_token_account,
+                vault: self.payroll_state.vault,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::FundPayroll { amount }.data(),
+        }
+    }
+
+    fn add(&self, salary_per_period: u64) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::AddEmployee {
+                payroll: self.payroll,
+                employee_record: self.record,
+                employer: self.employer.pubkey(),
+                employee: self.employee.employee,
+                employee_token_account: self.employee.employee_token_account,
+                payer: self.payer.pubkey(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::AddEmployee {
+                salary_per_period,
+                interval_seconds: TWO_WEEKS,
+            }
+            .data(),
+        }
+    }
+
+    fn payroll(&self) -> Payroll {
+        self.svm.get_anchor_account(&self.payroll).unwrap()
+    }
+
+    fn pay(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::PayEmployee {
+                payroll: self.payroll,
+                employee_record: self.record,
+                vault: self.payroll_state.vault,
+                employee_token_account: self.employee.employee_token_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::PayEmployee {}.data(),
+        }
+    }
+
+    fn update(&self, employer: &Pubkey, salary_per_period: u64) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::UpdateEmployee {
+                payroll: self.payroll,
+                employee_record: self.record,
+                employer: *employer,
+            }
+            .to_account_metas(None),
+            data: instruction::UpdateEmployee {
+                salary_per_period,
+                interval_seconds: TWO_WEEKS,
+            }
+            .data(),
+        }
+    }
+
+    fn remove(&self, employer: &Pubkey) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::RemoveEmployee {
+                payroll: self.payroll,
+                employee_record: self.record,
+                employer: *employer,
+            }
+            .to_account_metas(None),
+            data: instruction::RemoveEmployee {}.data(),
+        }
+    }
+
+    fn withdraw(&self, employer: &Pubkey, amount: u64) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::WithdrawFunds {
+                payroll: self.payroll,
+                employer: *employer,
+                vault: self.payroll_state.vault,
+                destination: self.employer_token_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::WithdrawFunds { amount }.data(),
+        }
+    }
+
+    fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TransactionResult {
+        let payer = self.payer.insecure_clone();
+        self.svm.send_instructions(&[instruction], &payer, signers)
+    }
+}
+
+#[test]
+fn employers_create_fund_and_staff_a_payroll() {
+    let mut fx = Fixture::new(0);
+    fx.svm.remove_account(&fx.payroll);
+    fx.svm.remove_account(&fx.record);
+    fx.svm.mint_tokens(&fx.employer_token_account, 3 * SALARY);
+    let employer = fx.employer.insecure_clone();
+
+    let result = fx.send(fx.create(), &[&employer]);
+    assert_success(result);
+    assert_eq!(fx.payroll().employer, employer.pubkey());
+    assert_eq!(fx.payroll().employee_count, 0);
+
+    let result = fx.send(fx.fund(0), &[&employer]);
+    assert_error(result, ErrorCode::InvalidAmount);
+    let result = fx.send(fx.fund(2 * SALARY), &[&employer]);
+    assert_success(result);
+    assert_eq!(fx.svm.token_balance(&fx.payroll_state.vault), 2 * SALARY);
+    assert_eq!(fx.svm.token_balance(&fx.employer_token_account), SALARY);
+
+    let result = fx.send(fx.add(0), &[&employer]);
+    assert_error(result, ErrorCode::InvalidAmount);
+    let result = fx.send(fx.add(SALARY), &[&employer]);
+    assert_success(result);
+    let employee: Employee = fx.svm.get_anchor_account(&fx.record).unwrap();
+    assert_eq!(employee.employee, fx.employee.employee);
+    assert_eq!(employee.salary_per_period, SALARY);
+    assert_eq!(
+        employee.next_payment_at(),
+        fx.svm.clock().unix_timestamp + TWO_WEEKS
+    );
+    assert_eq!(fx.payroll().employee_count, 1);
+}
+
+#[test]
+fn anyone_cranks_a_due_payment() {
+    let mut fx = Fixture::new(10 * SALARY);
+    let result = fx.send(fx.pay(), &[]);
+    assert_error(result, ErrorCode::IntervalNotMet);
+
+    fx.svm.advance_time(TWO_WEEKS);
+    // Only the payer signs: a keeper runs payroll, the employee pays nothing
+    let result = fx.send(fx.pay(), &[]);
+    assert_success(result);
+    assert_eq!(
+        fx.svm.token_balance(&fx.employee.employee_token_account),
+        SALARY
+    );
+    assert_eq!(fx.svm.token_balance(&fx.payroll_state.vault), 9 * SALARY);
+    assert_eq!(fx.payroll().total_paid, SALARY);
+
+    fx.svm.expire_blockhash();
+    let result = fx.send(fx.pay(), &[]);
+    assert_error(result, ErrorCode::IntervalNotMet);
+}
+
+#[test]
+fn underfunded_vault_blocks_payment() {
+    let mut fx = Fixture::new(SALARY - 1);
+    fx.svm.advance_time(TWO_WEEKS);
+    let result = fx.send(fx.pay(), &[]);
+    assert_error(result, ErrorCode::InsufficientFunds);
+}
+
+#[test]
+fn pay_rejects_another_destination() {
+    let mut fx = Fixture::new(10 * SALARY);
+    fx.svm.advance_time(TWO_WEEKS);
+    let attacker = fx
+        .svm
+        .create_token_account(&Pubkey::new_unique(), &fx.payroll_state.mint, 0);
+    let mut pay = fx.pay();
+    pay.accounts[3].pubkey = attacker;
+
+    let result = fx.send(pay, &[]);
+    assert_error(result, AnchorErrorCode::ConstraintHasOne);
+}
+
+#[test]
+fn only_the_employer_changes_salaries() {
+    let mut fx = Fixture::new(0);
+    let intruder = Keypair::new();
+    let result = fx.send(fx.update(&intruder.pubkey(), 1), &[&intruder]);
+    assert_error(result, AnchorErrorCode::ConstraintHasOne);
+
+    let employer = fx.employer.insecure_clone();
+    let result = fx.send(fx.update(&employer.pubkey(), 0), &[&employer]);
+    assert_error(result, ErrorCode::InvalidAmount);
+
+    fx.send(fx.update(&employer.pubkey(), 2 * SALARY), &[&employer])
+        .unwrap();
+    let employee: Employee = fx.svm.get_anchor_account(&fx.record).unwrap();
+    assert_eq!(employee.salary_per_period, 2 * SALARY);
+}
+
+#[test]
+fn removing_an_employee_closes_the_record() {
+    let mut fx = Fixture::new(0);
+    let employer = fx.employer.insecure_clone();
+    fx.send(fx.remove(&employer.pubkey()), &[&employer])
+        .unwrap();
+
+    assert!(fx.svm.get_anchor_account::<Employee>(&fx.record).is_none());
+    let payroll: Payroll = fx.svm.get_anchor_account(&fx.payroll).unwrap();
+    assert_eq!(payroll.employee_count, 0);
+}
+
+#[test]
+fn only_the_employer_withdraws_what_is_there() {
+    let mut fx = Fixture::new(SALARY);
+    let intruder = Keypair::new();
+    let result = fx.send(fx.withdraw(&intruder.pubkey(), 1), &[&intruder]);
+    assert_error(result, AnchorErrorCode::ConstraintHasOne);
+
+    let employer = fx.employer.insecure_clone();
+    let result = fx.send(fx.withdraw(&employer.pubkey(), SALARY + 1), &[&employer]);
+    assert_error(result, ErrorCode::InsufficientFunds);
+
+    let result = fx.send(fx.withdraw(&employer.pubkey(), SALARY), &[&employer]);
+    assert_success(result);
+    assert_eq!(fx.svm.token_balance(&fx.employer_token_account), SALARY);
+    assert_eq!(fx.svm.token_balance(&fx.payroll_state.vault), 0);
+}
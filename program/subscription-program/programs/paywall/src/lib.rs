@@ -0,0 +1,530 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::invoke;
+use spl_token::instruction as token_instruction;
+use subscription_program::{assert_active_subscription, Subscription};
+use token_utils::token_account;
+
+declare_id!("97yNvdB1fna4ozV7QESbLc5dq2WuerGjzfSeMmWBJvxj");
+
+#[program]
+pub mod paywall {
+    use super::*;
+
+    /// List a piece of content, identified by the hash of its body. Each
+    /// unlock costs `price` and lasts `access_seconds`; with
+    /// `subscribers_unlock`, readers subscribed to the creator get in free.
+    pub fn publish_article(
+        ctx: Context<PublishArticle>,
+        content_hash: [u8; 32],
+        price: u64,
+        access_seconds: i64,
+        subscribers_unlock: bool,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        Article::check_params(price, access_seconds)?;
+
+        let creator_account = token_account(
+            &ctx.accounts.creator_token_account,
+            ErrorCode::InvalidTokenAccount,
+        )?;
+        require_keys_eq!(
+            creator_account.owner,
+            ctx.accounts.creator.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+        require_keys_eq!(
+            creator_account.mint,
+            ctx.accounts.mint.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+
+        let article = &mut ctx.accounts.article;
+        article.creator = ctx.accounts.creator.key();
+        article.mint = ctx.accounts.mint.key();
+        article.creator_token_account = ctx.accounts.creator_token_account.key();
+        article.content_hash = content_hash;
+        article.price = price;
+        article.access_seconds = access_seconds;
+        article.subscribers_unlock = subscribers_unlock;
+        article.unlock_count = 0;
+        article.total_earned = 0;
+        article.created_at = clock.unix_timestamp;
+        article.bump = ctx.bumps.article;
+
+        emit!(ArticlePublished {
+            article: article.key(),
+            creator: article.creator,
+            price,
+            access_seconds,
+            subscribers_unlock,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("Article published: {} tokens per unlock", price);
+        msg!("Access lasts {} seconds", access_seconds);
+
+        Ok(())
+    }
+
+    /// Change the terms of future unlocks; existing receipts keep their expiry
+    pub fn update_article(
+        ctx: Context<UpdateArticle>,
+        price: u64,
+        access_seconds: i64,
+        subscribers_unlock: bool,
+    ) -> Result<()> {
+        Article::check_params(price, access_seconds)?;
+
+        let article = &mut ctx.accounts.article;
+        article.price = price;
+        article.access_seconds = access_seconds;
+        article.subscribers_unlock = subscribers_unlock;
+
+        emit!(ArticleUpdated {
+            article: article.key(),
+            price,
+            access_seconds,
+            subscribers_unlock,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Article updated: {} tokens per unlock", price);
+
+        Ok(())
+    }
+
+    /// First unlock: pay the creator and open the reader's access receipt.
+    /// The paymaster covers fees and rent, so the reader only pays `price`.
+    pub fn unlock_article(ctx: Context<UnlockArticle>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        pay_creator(
+            &ctx.accounts.reader_token_account,
+            &ctx.accounts.creator_token_account,
+            &ctx.accounts.reader,
+            &ctx.accounts.token_program,
+            ctx.accounts.article.price,
+        )?;
+
+        let receipt = &mut ctx.accounts.receipt;
+        receipt.article = ctx.accounts.article.key();
+        receipt.reader = ctx.accounts.reader.key();
+        receipt.total_paid = 0;
+        receipt.unlocked_at = now;
+        receipt.expires_at = now;
+        receipt.bump = ctx.bumps.receipt;
+
+        book_unlock(&mut ctx.accounts.article, receipt, now)
+    }
+
+    /// Buy another access period. Time left on the receipt is kept, so
+    /// renewing early never loses what the reader already paid for.
+    pub fn renew_access(ctx: Context<RenewAccess>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        pay_creator(
+            &ctx.accounts.reader_token_account,
+            &ctx.accounts.creator_token_account,
+            &ctx.accounts.reader,
+            &ctx.accounts.token_program,
+            ctx.accounts.article.price,
+        )?;
+
+        book_unlock(&mut ctx.accounts.article, &mut ctx.accounts.receipt, now)
+    }
+
+    /// Succeeds only if `reader` may read the article right now, through an
+    /// unexpired receipt or an active subscription to the creator. Content
+    /// servers simulate it; other programs can CPI into it.
+    pub fn verify_access(ctx: Context<VerifyAccess>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let access = check_access(
+            &ctx.accounts.article,
+            &ctx.accounts.reader.key(),
+            ctx.accounts.receipt.as_deref(),
+            ctx.accounts.subscription.as_deref(),
+            now,
+        )?;
+
+        msg!("Access granted via {:?}", access);
+
+        Ok(())
+    }
+
+    /// Reader reclaims the receipt's rent once access has run out
+    pub fn close_receipt(ctx: Context<CloseReceipt>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            !ctx.accounts.receipt.is_valid(now),
+            ErrorCode::ReceiptStillValid
+        );
+
+        msg!("Receipt closed - rent refunded to reader");
+
+        Ok(())
+    }
+}
+
+/// Move `price` from the reader to the creator; the reader signs the transfer
+fn pay_creator<'info>(
+    reader_token_account: &UncheckedAccount<'info>,
+    creator_token_account: &UncheckedAccount<'info>,
+    reader: &Signer<'info>,
+    token_program: &UncheckedAccount<'info>,
+    price: u64,
+) -> Result<()> {
+    let transfer_ix = token_instruction::transfer(
+        &token_program.key(),
+        &reader_token_account.key(),
+        &creator_token_account.key(),
+        &reader.key(),
+        &[],
+        price,
+    )?;
+
+    invoke(
+        &transfer_ix,
+        &[
+            reader_token_account.to_account_info(),
+            creator_token_account.to_account_info(),
+            reader.to_account_info(),
+            token_program.to_account_info(),
+        ],
+    )?;
+
+    Ok(())
+}
+
+/// Extend the receipt by one access period and book the payment on both sides
+fn book_unlock(
+    article: &mut Account<Article>,
+    receipt: &mut Account<AccessReceipt>,
+    now: i64,
+) -> Result<()> {
+    receipt.extend(article.price, article.access_seconds, now)?;
+    article.record_unlock()?;
+
+    emit!(ArticleUnlocked {
+        article: article.key(),
+        reader: receipt.reader,
+        amount: article.price,
+        expires_at: receipt.expires_at,
+        timestamp: now,
+    });
+
+    msg!("Article unlocked: {} tokens", article.price);
+    msg!("Access until {}", receipt.expires_at);
+
+    Ok(())
+}
+
+/// How a reader got access to an article
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Receipt,
+    Subscription,
+}
+
+/// The paywall rule, shared by `verify_access` and off-chain readers. A
+/// receipt is tried first; a subscription counts only if the article lets
+/// subscribers in and it pays the article's creator.
+pub fn check_access(
+    article: &Article,
+    reader: &Pubkey,
+    receipt: Option<&AccessReceipt>,
+    subscription: Option<&Subscription>,
+    now: i64,
+) -> Result<Access> {
+    if let Some(receipt) = receipt {
+        if receipt.reader == *reader && receipt.is_valid(now) {
+            return Ok(Access::Receipt);
+        }
+    }
+
+    if let Some(subscription) = subscription {
+        require!(article.subscribers_unlock, ErrorCode::AccessDenied);
+        require_keys_eq!(
+            subscription.authority,
+            *reader,
+            ErrorCode::SubscriptionMismatch
+        );
+        require_keys_eq!(
+            subscription.recipient,
+            article.creator,
+            ErrorCode::SubscriptionMismatch
+        );
+        // Reported as our own error; the subscription program's codes overlap ours
+        assert_active_subscription(subscription, now)
+            .map_err(|_| error!(ErrorCode::SubscriptionNotActive))?;
+        return Ok(Access::Subscription);
+    }
+
+    err!(ErrorCode::AccessDenied)
+}
+
+#[derive(Accounts)]
+#[instruction(content_hash: [u8; 32])]
+pub struct PublishArticle<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Article::INIT_SPACE,
+        seeds = [b"article", creator.key().as_ref(), content_hash.as_ref()],
+        bump
+    )]
+    pub article: Account<'info, Article>,
+
+    pub creator: Signer<'info>,
+
+    /// CHECK: Token mint (USDC)
+    pub mint: UncheckedAccount<'info>,
+
+    /// CHECK: Creator's token account, checked in the handler
+    pub creator_token_account: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateArticle<'info> {
+    #[account(
+        mut,
+        seeds = [b"article", creator.key().as_ref(), article.content_hash.as_ref()],
+        bump = article.bump,
+        has_one = creator
+    )]
+    pub article: Account<'info, Article>,
+
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UnlockArticle<'info> {
+    #[account(
+        mut,
+        seeds = [b"article", article.creator.as_ref(), article.content_hash.as_ref()],
+        bump = article.bump,
+        has_one = creator_token_account
+    )]
+    pub article: Account<'info, Article>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + AccessReceipt::INIT_SPACE,
+        seeds = [b"receipt", article.key().as_ref(), reader.key().as_ref()],
+        bump
+    )]
+    pub receipt: Account<'info, AccessReceipt>,
+
+    pub reader: Signer<'info>,
+
+    /// CHECK: Any token account the reader owns, debited by the token program
+    #[account(mut)]
+    pub reader_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Creator's token account
+    #[account(mut)]
+    pub creator_token_account: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RenewAccess<'info> {
+    #[account(
+        mut,
+        seeds = [b"article", article.creator.as_ref(), article.content_hash.as_ref()],
+        bump = article.bump,
+        has_one = creator_token_account
+    )]
+    pub article: Account<'info, Article>,
+
+    #[account(
+        mut,
+        seeds = [b"receipt", article.key().as_ref(), reader.key().as_ref()],
+        bump = receipt.bump,
+        has_one = article,
+        has_one = reader
+    )]
+    pub receipt: Account<'info, AccessReceipt>,
+
+    pub reader: Signer<'info>,
+
+    /// CHECK: Any token account the reader owns, debited by the token program
+    #[account(mut)]
+    pub reader_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Creator's token account
+    #[account(mut)]
+    pub creator_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct VerifyAccess<'info> {
+    #[account(
+        seeds = [b"article", article.creator.as_ref(), article.content_hash.as_ref()],
+        bump = article.bump,
+    )]
+    pub article: Account<'info, Article>,
+
+    /// CHECK: The wallet being checked; it does not sign
+    pub reader: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"receipt", article.key().as_ref(), reader.key().as_ref()],
+        bump = receipt.bump,
+        has_one = article
+    )]
+    pub receipt: Option<Account<'info, AccessReceipt>>,
+
+    /// The reader's subscription to the creator, owned by the subscription program
+    pub subscription: Option<Account<'info, Subscription>>,
+}
+
+#[derive(Accounts)]
+pub struct CloseReceipt<'info> {
+    #[account(
+        mut,
+        seeds = [b"receipt", receipt.article.as_ref(), reader.key().as_ref()],
+        bump = receipt.bump,
+        has_one = reader,
+        close = reader
+    )]
+    pub receipt: Account<'info, AccessReceipt>,
+
+    #[account(mut)]
+    pub reader: Signer<'info>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Article {
+    pub creator: Pubkey,
+    pub mint: Pubkey,
+    pub creator_token_account: Pubkey,
+    /// Hash of the content body; the body itself stays off-chain
+    pub content_hash: [u8; 32],
+    pub price: u64,
+    pub access_seconds: i64,
+    pub subscribers_unlock: bool,
+    pub unlock_count: u64,
+    pub total_earned: u64,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl Article {
+    pub fn check_params(price: u64, access_seconds: i64) -> Result<()> {
+        require!(price > 0, ErrorCode::InvalidAmount);
+        require!(access_seconds > 0, ErrorCode::InvalidAccessPeriod);
+        Ok(())
+    }
+
+    pub fn record_unlock(&mut self) -> Result<()> {
+        self.unlock_count = self
+            .unlock_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.total_earned = self
+            .total_earned
+            .checked_add(self.price)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+}
+
+/// Proof that one reader paid for one article, valid until `expires_at`
+#[account]
+#[derive(InitSpace)]
+pub struct AccessReceipt {
+    pub article: Pubkey,
+    pub reader: Pubkey,
+    pub total_paid: u64,
+    pub unlocked_at: i64,
+    pub expires_at: i64,
+    pub bump: u8,
+}
+
+impl AccessReceipt {
+    pub fn is_valid(&self, now: i64) -> bool {
+        now < self.expires_at
+    }
+
+    /// Add one paid access period, counted from expiry if time is left
+    pub fn extend(&mut self, price: u64, access_seconds: i64, now: i64) -> Result<()> {
+        self.total_paid = self
+            .total_paid
+            .checked_add(price)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.expires_at = self
+            .expires_at
+            .max(now)
+            .checked_add(access_seconds)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArticlePublished {
+    pub article: Pubkey,
+    pub creator: Pubkey,
+    pub price: u64,
+    pub access_seconds: i64,
+    pub subscribers_unlock: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArticleUpdated {
+    pub article: Pubkey,
+    pub price: u64,
+    pub access_seconds: i64,
+    pub subscribers_unlock: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArticleUnlocked {
+    pub article: Pubkey,
+    pub reader: Pubkey,
+    pub amount: u64,
+    pub expires_at: i64,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Amount must be greater than zero")]
+    InvalidAmount,
+    #[msg("Access period must be greater than zero")]
+    InvalidAccessPeriod,
+    #[msg("Invalid token account - wrong program, owner or mint")]
+    InvalidTokenAccount,
+    #[msg("No valid receipt or subscription for this article")]
+    AccessDenied,
+    #[msg("Subscription does not belong to this reader and creator")]
+    SubscriptionMismatch,
+    #[msg("Subscription is cancelled or expired")]
+    SubscriptionNotActive,
+    #[msg("Receipt has not expired yet")]
+    ReceiptStillValid,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
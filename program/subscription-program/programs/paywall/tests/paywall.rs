@@ -0,0 +1,553 @@
+//! Native tests for the paywall program.
+//!
+//! The access rule is a pure function, `check_access`, and is tested directly.
+//! Instruction tests cover account validation and run every instruction end
+//! to end, payments included.
+
+use anchor_lang::error::ErrorCode as AnchorErrorCode;
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, Space, ToAccountMetas};
+use paywall::{
+    accounts, check_access, instruction, Access, AccessReceipt, Article, ErrorCode,
+    ID as PROGRAM_ID,
+};
+use subscription_program::Subscription;
+use test_harness::{
+    assert_error, assert_success, Keypair, Signer, SystemError, TestSvm, TransactionResult,
+};
+
+const PRICE: u64 = 50_000;
+const DAY: i64 = 86_400;
+
+fn article(creator: Pubkey, subscribers_unlock: bool) -> Article {
+    Article {
+        creator,
+        mint: Pubkey::new_unique(),
+        creator_token_account: Pubkey::new_unique(),
+        content_hash: [7; 32],
+        price: PRICE,
+        access_seconds: DAY,
+        subscribers_unlock,
+        unlock_count: 0,
+        total_earned: 0,
+        created_at: 0,
+        bump: 255,
+    }
+}
+
+fn receipt(article: Pubkey, reader: Pubkey, expires_at: i64) -> AccessReceipt {
+    AccessReceipt {
+        article,
+        reader,
+        total_paid: PRICE,
+        unlocked_at: expires_at - DAY,
+        expires_at,
+        bump: 255,
+    }
+}
+
+fn subscription(authority: Pubkey, recipient: Pubkey) -> Subscription {
+    Subscription {
+        authority,
+        recipient,
+        user_token_account: Pubkey::new_unique(),
+        recipient_token_account: Pubkey::new_unique(),
+        token_mint: Pubkey::new_unique(),
+        amount_per_period: 10_000_000,
+        interval_seconds: 30 * DAY,
+        last_charge_timestamp: 0,
+        next_charge_at: 30 * DAY,
+        created_at: 0,
+        expires_at: None,
+        is_active: true,
+        total_charged: 10_000_000,
+        bump: 255,
+        on_plan: false,
+        max_total_spend: Subscription::NO_SPEND_LIMIT,
+    }
+}
+
+#[test]
+fn renewals_stack_on_remaining_time() {
+    let mut receipt = receipt(Pubkey::new_unique(), Pubkey::new_unique(), 1_000 + DAY);
+
+    // Renewed with half a day left: the new period starts at expiry
+    receipt.extend(PRICE, DAY, 1_000 + DAY / 2).unwrap();
+    assert_eq!(receipt.expires_at, 1_000 + 2 * DAY);
+    assert_eq!(receipt.total_paid, 2 * PRICE);
+
+    // Renewed after lapsing: the new period starts now
+    receipt.extend(PRICE, DAY, 10 * DAY).unwrap();
+    assert_eq!(receipt.expires_at, 11 * DAY);
+    assert!(receipt.is_valid(11 * DAY - 1));
+    assert!(!receipt.is_valid(11 * DAY));
+}
+
+#[test]
+fn receipts_and_subscriptions_grant_access() {
+    let creator = Pubkey::new_unique();
+    let reader = Pubkey::new_unique();
+    let article = article(creator, true);
+    let paid = receipt(Pubkey::new_unique(), reader, DAY);
+    let subscribed = subscription(reader, creator);
+
+    let access =
+        |receipt, subscription, now| check_access(&article, &reader, receipt, subscription, now);
+
+    assert_eq!(access(Some(&paid), None, DAY - 1), Ok(Access::Receipt));
+    assert_eq!(
+        access(Some(&paid), None, DAY),
+        Err(ErrorCode::AccessDenied.into())
+    );
+    // An expired receipt falls back to the subscription
+    assert_eq!(
+        access(Some(&paid), Some(&subscribed), DAY),
+        Ok(Access::Subscription)
+    );
+    assert_eq!(access(None, None, 0), Err(ErrorCode::AccessDenied.into()));
+
+    let someone_else = receipt(Pubkey::new_unique(), Pubkey::new_unique(), DAY);
+    assert_eq!(
+        access(Some(&someone_else), None, 0),
+        Err(ErrorCode::AccessDenied.into())
+    );
+}
+
+#[test]
+fn subscriptions_must_match_and_be_active() {
+    let creator = Pubkey::new_unique();
+    let reader = Pubkey::new_unique();
+    let check = |article: &Article, subscription: &Subscription| {
+        check_access(article, &reader, None, Some(subscription), 100).map(|_| ())
+    };
+
+    let open = article(creator, true);
+    assert_eq!(
+        check(&article(creator, false), &subscription(reader, creator)).unwrap_err(),
+        ErrorCode::AccessDenied.into()
+    );
+    assert_eq!(
+        check(&open, &subscription(Pubkey::new_unique(), creator)).unwrap_err(),
+        ErrorCode::SubscriptionMismatch.into()
+    );
+    assert_eq!(
+        check(&open, &subscription(reader, Pubkey::new_unique())).unwrap_err(),
+        ErrorCode::SubscriptionMismatch.into()
+    );
+
+    let expired = Subscription {
+        expires_at: Some(100),
+        ..subscription(reader, creator)
+    };
+    assert_eq!(
+        check(&open, &expired).unwrap_err(),
+        ErrorCode::SubscriptionNotActive.into()
+    );
+    let cancelled = Subscription {
+        is_active: false,
+        ..subscription(reader, creator)
+    };
+    assert_eq!(
+        check(&open, &cancelled).unwrap_err(),
+        ErrorCode::SubscriptionNotActive.into()
+    );
+}
+
+#[test]
+fn rejects_free_or_permanent_articles() {
+    assert_eq!(
+        Article::check_params(0, DAY).unwrap_err(),
+        ErrorCode::InvalidAmount.into()
+    );
+    assert_eq!(
+        Article::check_params(PRICE, 0).unwrap_err(),
+        ErrorCode::InvalidAccessPeriod.into()
+    );
+}
+
+struct Fixture {
+    svm: TestSvm,
+    payer: Keypair,
+    creator: Keypair,
+    reader: Keypair,
+    article: Pubkey,
+    listing: Article,
+    receipt: Pubkey,
+    reader_token_account: Pubkey,
+}
+
+impl Fixture {
+    /// A published article and a reader with tokens but no receipt yet
+    fn new(subscribers_unlock: bool) -> Self {
+        let (mut svm, payer) = TestSvm::for_program(PROGRAM_ID, paywall::entry);
+
+        let creator = Keypair::new();
+        let reader = Keypair::new();
+
+        let mint = svm.create_mint(&Pubkey::new_unique(), 6);
+        let listing = article(creator.pubkey(), subscribers_unlock);
+        let (article_address, article_bump) = Pubkey::find_program_address(
+            &[
+                b"article",
+                creator.pubkey().as_ref(),
+                listing.content_hash.as_ref(),
+            ],
+            &PROGRAM_ID,
+        );
+        let listing = Article {
+            mint,
+            creator_token_account: svm.create_associated_token_account(&creator.pubkey(), &mint, 0),
+            bump: article_bump,
+            ..listing
+        };
+        svm.set_anchor_account(article_address, &listing, 8 + Article::INIT_SPACE);
+
+        let (receipt, _) = Pubkey::find_program_address(
+            &[
+                b"receipt",
+                article_address.as_ref(),
+                reader.pubkey().as_ref(),
+            ],
+            &PROGRAM_ID,
+        );
+        let reader_token_account =
+            svm.create_associated_token_account(&reader.pubkey(), &mint, 100 * PRICE);
+
+        Self {
+            svm,
+            payer,
+            creator,
+            reader,
+            article: article_address,
+            listing,
+            receipt,
+            reader_token_account,
+        }
+    }
+
+    /// Give the reader a receipt valid for one more day, as `unlock_article` would
+    fn with_receipt(mut self) -> Self {
+        let now = self.svm.clock().unix_timestamp;
+        let (_, bump) = Pubkey::find_program_address(
+            &[
+                b"receipt",
+                self.article.as_ref(),
+                self.reader.pubkey().as_ref(),
+            ],
+            &PROGRAM_ID,
+        );
+        let paid = AccessReceipt {
+            bump,
+            ..receipt(self.article, self.reader.pubkey(), now + DAY)
+        };
+        self.svm
+            .set_anchor_account(self.receipt, &paid, 8 + AccessReceipt::INIT_SPACE);
+        self
+    }
+
+    /// Store a subscription from the reader to `recipient`, returning its address
+    fn subscribe(&mut self, recipient: Pubkey) -> Pubkey {
+        let (address, bump) = Pubkey::find_program_address(
+            &[
+                b"subscription",
+                self.reader.pubkey().as_ref(),
+                recipient.as_ref(),
+            ],
+            &subscription_program::ID,
+        );
+        let stored = Subscription {
+            bump,
+            ..subscription(self.reader.pubkey(), recipient)
+        };
+        self.svm
+            .set_anchor_account(address, &stored, 8 + Subscription::INIT_SPACE);
+        address
+    }
+
+    fn publish(&self, creator_token_account: Pubkey, price: u64) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::PublishArticle {
+                article: self.article,
+                creator: self.creator.pubkey(),
+                mint: self.listing.mint,
+                creator_token_account,
+                payer: self.payer.pubkey(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::PublishArticle {
+                content_hash: self.listing.content_hash,
+                price,
+                access_seconds: DAY,
+                subscribers_unlock: true,
+            }
+            .data(),
+        }
+    }
+
+    fn unlock(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::UnlockArticle {
+                article: self.article,
+                receipt: self.receipt,
+                reader: self.reader.pubkey(),
+                reader_token_account: self.reader_token_account,
+                creator_token_account: self.listing.creator_token_account,
+                payer: self.payer.pubkey(),
+                token_program: spl_token::ID,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::UnlockArticle {}.data(),
+        }
+    }
+
+    fn receipt(&self) -> AccessReceipt {
+        self.svm.get_anchor_account(&self.receipt).unwrap()
+    }
+
+    fn listing(&self) -> Article {
+        self.svm.get_anchor_account(&self.article).unwrap()
+    }
+
+    fn creator_balance(&self) -> u64 {
+        self.svm.token_balance(&self.listing.creator_token_account)
+    }
+
+    fn verify(&self, receipt: Option<Pubkey>, subscription: Option<Pubkey>) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::VerifyAccess {
+                article: self.article,
+                reader: self.reader.pubkey(),
+                receipt,
+                subscription,
+            }
+            .to_account_metas(None),
+            data: instruction::VerifyAccess {}.data(),
+        }
+    }
+
+    fn renew(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::RenewAccess {
+                article: self.article,
+                receipt: self.receipt,
+                reader: self.reader.pubkey(),
+                reader_token_account: self.reader_token_account,
+                creator_token_account: self.listing.creator_token_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::RenewAccess {}.data(),
+        }
+    }
+
+    fn close(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CloseReceipt {
+                receipt: self.receipt,
+                reader: self.reader.pubkey(),
+            }
+            .to_account_metas(None),
+            data: instruction::CloseReceipt {}.data(),
+        }
+    }
+
+    fn update(&self, price: u64) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::UpdateArticle {
+                article: self.article,
+                creator: self.creator.pubkey(),
+            }
+            .to_account_metas(None),
+            data: instruction::UpdateArticle {
+                price,
+                access_seconds: DAY,
+                subscribers_unlock: true,
+            }
+            .data(),
+        }
+    }
+
+    fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TransactionResult {
+        let payer = self.payer.insecure_clone();
+        self.svm.send_instructions(&[instruction], &payer, signers)
+    }
+
+    fn send_as_reader(&mut self, instruction: Instruction) -> TransactionResult {
+        let reader = self.reader.insecure_clone();
+        self.send(instruction, &[&reader])
+    }
+}
+
+#[test]
+fn creators_publish_articles_paid_into_their_account() {
+    let mut fx = Fixture::new(false);
+    fx.svm.remove_account(&fx.article);
+    let creator = fx.creator.insecure_clone();
+
+    let result = fx.send(fx.publish(fx.reader_token_account, PRICE), &[&creator]);
+    assert_error(result, ErrorCode::InvalidTokenAccount);
+    let result = fx.send(fx.publish(fx.listing.creator_token_account, 0), &[&creator]);
+    assert_error(result, ErrorCode::InvalidAmount);
+
+    let result = fx.send(
+        fx.publish(fx.listing.creator_token_account, PRICE),
+        &[&creator],
+    );
+    assert_success(result);
+    let listing = fx.listing();
+    assert_eq!(listing.creator, creator.pubkey());
+    assert_eq!(listing.mint, fx.listing.mint);
+    assert_eq!(
+        listing.creator_token_account,
+        fx.listing.creator_token_account
+    );
+    assert_eq!((listing.price, listing.access_seconds), (PRICE, DAY));
+    assert!(listing.subscribers_unlock);
+    assert_eq!(listing.created_at, fx.svm.clock().unix_timestamp);
+    assert_eq!((listing.unlock_count, listing.total_earned), (0, 0));
+}
+
+#[test]
+fn unlocking_pays_the_creator_for_a_period_of_access() {
+    let mut fx = Fixture::new(false);
+    let result = fx.send_as_reader(fx.unlock());
+    assert_success(result);
+    let now = fx.svm.clock().unix_timestamp;
+    assert_eq!(fx.creator_balance(), PRICE);
+    assert_eq!(fx.svm.token_balance(&fx.reader_token_account), 99 * PRICE);
+    let receipt = fx.receipt();
+    assert_eq!(
+        (receipt.article, receipt.reader),
+        (fx.article, fx.reader.pubkey())
+    );
+    assert_eq!((receipt.unlocked_at, receipt.expires_at), (now, now + DAY));
+    assert_eq!(receipt.total_paid, PRICE);
+    let listing = fx.listing();
+    assert_eq!((listing.unlock_count, listing.total_earned), (1, PRICE));
+
+    // Later periods are bought with `renew_access`
+    fx.svm.expire_blockhash();
+    let result = fx.send_as_reader(fx.unlock());
+    assert_error(result, SystemError::AccountAlreadyInUse as u32);
+}
+
+#[test]
+fn receipt_unlocks_until_expiry() {
+    let mut fx = Fixture::new(false).with_receipt();
+    let receipt = Some(fx.receipt);
+    let result = fx.send(fx.verify(receipt, None), &[]);
+    assert!(result.is_ok(), "{result:#?}");
+
+    fx.svm.advance_time(DAY);
+    let result = fx.send(fx.verify(receipt, None), &[]);
+    assert_error(result, ErrorCode::AccessDenied);
+}
+
+#[test]
+fn subscribers_read_free_when_the_article_allows_it() {
+    let mut fx = Fixture::new(true);
+    let creator = fx.creator.pubkey();
+    let subscription = fx.subscribe(creator);
+    let result = fx.send(fx.verify(None, Some(subscription)), &[]);
+    assert!(result.is_ok(), "{result:#?}");
+
+    let mut fx = Fixture::new(false);
+    let creator = fx.creator.pubkey();
+    let subscription = fx.subscribe(creator);
+    let result = fx.send(fx.verify(None, Some(subscription)), &[]);
+    assert_error(result, ErrorCode::AccessDenied);
+}
+
+#[test]
+fn subscription_must_pay_this_creator() {
+    let mut fx = Fixture::new(true);
+    let subscription = fx.subscribe(Pubkey::new_unique());
+    let result = fx.send(fx.verify(None, Some(subscription)), &[]);
+    assert_error(result, ErrorCode::SubscriptionMismatch);
+}
+
+#[test]
+fn subscription_must_come_from_the_subscription_program() {
+    let mut fx = Fixture::new(true);
+    let forged = subscription(fx.reader.pubkey(), fx.creator.pubkey());
+    let address = Pubkey::new_unique();
+    fx.svm
+        .set_anchor_account(address, &forged, 8 + Subscription::INIT_SPACE);
+    let mut account = fx.svm.get_account(&address).unwrap();
+    account.owner = Pubkey::new_unique();
+    fx.svm.set_account(address, account);
+
+    let result = fx.send(fx.verify(None, Some(address)), &[]);
+    assert_error(result, AnchorErrorCode::AccountOwnedByWrongProgram);
+}
+
+#[test]
+fn renewal_pays_this_article_creator() {
+    let mut fx = Fixture::new(false).with_receipt();
+    let expires_at = fx.receipt().expires_at;
+    let result = fx.send_as_reader(fx.renew());
+    assert_success(result);
+    assert_eq!(fx.creator_balance(), PRICE);
+    let receipt = fx.receipt();
+    assert_eq!(receipt.expires_at, expires_at + DAY);
+    assert_eq!(receipt.total_paid, 2 * PRICE);
+
+    let attacker = fx
+        .svm
+        .create_token_account(&Pubkey::new_unique(), &fx.listing.mint, 0);
+    let mut renew = fx.renew();
+    renew.accounts[4].pubkey = attacker;
+    let result = fx.send_as_reader(renew);
+    assert_error(result, AnchorErrorCode::ConstraintHasOne);
+}
+
+#[test]
+fn receipts_belong_to_their_reader() {
+    let mut fx = Fixture::new(false).with_receipt();
+    let impostor = Keypair::new();
+    let mut renew = fx.renew();
+    renew.accounts[2].pubkey = impostor.pubkey();
+
+    let result = fx.send(renew, &[&impostor]);
+    assert_error(result, AnchorErrorCode::ConstraintSeeds);
+}
+
+#[test]
+fn expired_receipts_refund_rent_to_the_reader() {
+    let mut fx = Fixture::new(false).with_receipt();
+    let result = fx.send_as_reader(fx.close());
+    assert_error(result, ErrorCode::ReceiptStillValid);
+
+    fx.svm.advance_time(DAY);
+    let rent = fx.svm.get_balance(&fx.receipt);
+    let result = fx.send_as_reader(fx.close());
+    assert!(result.is_ok(), "{result:#?}");
+    assert!(fx.svm.get_account(&fx.receipt).is_none());
+    assert_eq!(fx.svm.get_balance(&fx.reader.pubkey()), rent);
+}
+
+#[test]
+fn only_the_creator_updates_terms() {
+    let mut fx = Fixture::new(false);
+    let impostor = Keypair::new();
+    let mut update = fx.update(2 * PRICE);
+    update.accounts[1].pubkey = impostor.pubkey();
+    let result = fx.send(update, &[&impostor]);
+    assert_error(result, AnchorErrorCode::ConstraintSeeds);
+
+    let creator = fx.creator.insecure_clone();
+    let result = fx.send(fx.update(2 * PRICE), &[&creator]);
+    assert!(result.is_ok(), "{result:#?}");
+    let listing = fx.svm.get_anchor_account::<Article>(&fx.article).unwrap();
+    assert_eq!(listing.price, 2 * PRICE);
+    assert!(listing.subscribers_unlock);
+}
@@ -0,0 +1,944 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use spl_token::instruction as token_instruction;
+use subscription_program::{assert_active_subscription, Subscription};
+use token_utils::token_account;
+
+declare_id!("CNfdUGhqapMZZpuv68WtTHaAtbtFTurH9McE2Uo5jPQg");
+
+/// Switchboard On-Demand, which owns the randomness accounts draws commit to
+pub const SWITCHBOARD_PROGRAM_ID: Pubkey = pubkey!("SBondMDrcV3K4kxZR1HNVT7osZxAHVHgYXL5Ze1oMUv");
+
+/// Most separate entry ranges one entrant can hold; claims that land right
+/// after the entrant's last range extend it instead
+pub const MAX_RANGES: usize = 12;
+
+#[program]
+pub mod raffle {
+    use super::*;
+
+    /// Merchant escrows `prize_amount` and opens a raffle for their
+    /// subscribers. Every period charged until `entry_end` is one entry.
+    pub fn create_raffle(
+        ctx: Context<CreateRaffle>,
+        raffle_id: u64,
+        prize_amount: u64,
+        entry_end: i64,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        Raffle::check_params(prize_amount, entry_end, clock.unix_timestamp)?;
+
+        let raffle_key = ctx.accounts.raffle.key();
+        let merchant = ctx.accounts.merchant.key();
+        let mint = ctx.accounts.mint.key();
+        let vault = token_account(&ctx.accounts.vault, ErrorCode::InvalidTokenAccount)?;
+        require_keys_eq!(vault.owner, raffle_key, ErrorCode::InvalidTokenAccount);
+        require_keys_eq!(vault.mint, mint, ErrorCode::InvalidTokenAccount);
+
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.merchant_token_account.key(),
+            &ctx.accounts.vault.key(),
+            &merchant,
+            &[],
+            prize_amount,
+        )?;
+
+        invoke(
+            &transfer_ix,
+            &[
+                ctx.accounts.merchant_token_account.to_account_info(),
+                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.merchant.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        let raffle = &mut ctx.accounts.raffle;
+        raffle.merchant = merchant;
+        raffle.raffle_id = raffle_id;
+        raffle.mint = mint;
+        raffle.vault = ctx.accounts.vault.key();
+        raffle.prize_amount = prize_amount;
+        raffle.entry_end = entry_end;
+        raffle.entrant_count = 0;
+        raffle.closed_entries = 0;
+        raffle.total_entries = 0;
+        raffle.randomness = None;
+        raffle.seed_slot = 0;
+        raffle.winning_entry = None;
+        raffle.winner = None;
+        raffle.prize_claimed = false;
+        raffle.created_at = clock.unix_timestamp;
+        raffle.bump = ctx.bumps.raffle;
+
+        emit!(RaffleCreated {
+            raffle: raffle_key,
+            merchant,
+            mint,
+            prize_amount,
+            entry_end,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("Raffle created: prize {}", prize_amount);
+        msg!("Entries close at {}", entry_end);
+
+        Ok(())
+    }
+
+    /// Join with an active subscription to the merchant. Only charges from
+    /// now on earn entries. The owner signs; `payer` pays the rent.
+    pub fn enter(ctx: Context<Enter>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        ctx.accounts.raffle.check_entering(now)?;
+        let subscription = &ctx.accounts.subscription;
+        assert_active_subscription(subscription, now)
+            .map_err(|_| error!(ErrorCode::SubscriptionNotActive))?;
+
+        let entry = &mut ctx.accounts.entry;
+        entry.raffle = ctx.accounts.raffle.key();
+        entry.owner = ctx.accounts.owner.key();
+        entry.subscription_credited = subscription.total_charged;
+        entry.entries = 0;
+        entry.ranges = Vec::new();
+        entry.joined_at = now;
+        entry.bump = ctx.bumps.entry;
+
+        let raffle = &mut ctx.accounts.raffle;
+        raffle.entrant_count = raffle
+            .entrant_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        emit!(EntrantJoined {
+            raffle: raffle.key(),
+            owner: entry.owner,
+            timestamp: now,
+        });
+
+        msg!("Entrant joined: {}", entry.owner);
+
+        Ok(())
+    }
+
+    /// Turn periods charged since the last claim into entries. The
+    /// subscription program has no charge callback, so this reads the
+    /// subscription instead and anyone can crank it after a charge.
+    pub fn claim_entries(ctx: Context<ClaimEntries>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        ctx.accounts.raffle.check_entering(now)?;
+        let subscription = &ctx.accounts.subscription;
+        assert_active_subscription(subscription, now)
+            .map_err(|_| error!(ErrorCode::SubscriptionNotActive))?;
+
+        let count = ctx
+            .accounts
+            .entry
+            .credit_charges(subscription.total_charged, subscription.amount_per_period)?;
+        require!(count > 0, ErrorCode::NothingToClaim);
+
+        let raffle = &mut ctx.accounts.raffle;
+        let first = raffle.record_entries(count)?;
+        let entry = &mut ctx.accounts.entry;
+        entry.record_range(first, count)?;
+
+        emit!(EntriesClaimed {
+            raffle: raffle.key(),
+            owner: entry.owner,
+            first_entry: first,
+            count,
+            total_entries: raffle.total_entries,
+            timestamp: now,
+        });
+
+        msg!("Claimed {} entries from #{}", count, first);
+        msg!(
+            "Entrant holds {} of {}",
+            entry.entries,
+            raffle.total_entries
+        );
+
+        Ok(())
+    }
+
+    /// Commit the draw to a Switchboard randomness account seeded in the
+    /// previous slot and not yet revealed, so nobody knows the outcome when
+    /// it is chosen. Merchant only, once, after entries close.
+    pub fn request_draw(ctx: Context<RequestDraw>) -> Result<()> {
+        let clock = Clock::get()?;
+        let randomness = Randomness::load(&ctx.accounts.randomness)?;
+        let raffle = &mut ctx.accounts.raffle;
+        raffle.request_draw(
+            ctx.accounts.randomness.key(),
+            &randomness,
+            clock.slot,
+            clock.unix_timestamp,
+        )?;
+
+        emit!(DrawRequested {
+            raffle: raffle.key(),
+            randomness: ctx.accounts.randomness.key(),
+            seed_slot: randomness.seed_slot,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("Draw committed to slot {}", randomness.seed_slot);
+
+        Ok(())
+    }
+
+    /// Pick the winning entry from the revealed randomness. Permissionless.
+    pub fn draw_winner(ctx: Context<DrawWinner>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let randomness = Randomness::load(&ctx.accounts.randomness)?;
+        let raffle = &mut ctx.accounts.raffle;
+        let winning_entry = raffle.draw(&randomness)?;
+
+        emit!(WinnerDrawn {
+            raffle: raffle.key(),
+            winning_entry,
+            total_entries: raffle.total_entries,
+            timestamp: now,
+        });
+
+        msg!(
+            "Winning entry: #{} of {}",
+            winning_entry,
+            raffle.total_entries
+        );
+
+        Ok(())
+    }
+
+    /// Pay the prize to whoever holds the winning entry. Permissionless;
+    /// `winner_token_account` must belong to the entry's owner.
+    pub fn claim_prize(ctx: Context<ClaimPrize>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let raffle = &ctx.accounts.raffle;
+        let entry = &ctx.accounts.entry;
+        raffle.check_prize_claim(entry)?;
+        let destination = token_account(
+            &ctx.accounts.winner_token_account,
+            ErrorCode::InvalidTokenAccount,
+        )?;
+        require_keys_eq!(
+            destination.owner,
+            entry.owner,
+            ErrorCode::InvalidTokenAccount
+        );
+        require_keys_eq!(
+            destination.mint,
+            raffle.mint,
+            ErrorCode::InvalidTokenAccount
+        );
+
+        let raffle_id = raffle.raffle_id.to_le_bytes();
+        let seeds = &[
+            b"raffle",
+            raffle.merchant.as_ref(),
+            raffle_id.as_ref(),
+            &[raffle.bump],
+        ];
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.vault.key(),
+            &ctx.accounts.winner_token_account.key(),
+            &raffle.key(),
+            &[],
+            raffle.prize_amount,
+        )?;
+
+        invoke_signed(
+            &transfer_ix,
+            &[
+                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.winner_token_account.to_account_info(),
+                ctx.accounts.raffle.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+            &[&seeds[..]],
+        )?;
+
+        let winner = ctx.accounts.entry.owner;
+        let raffle = &mut ctx.accounts.raffle;
+        raffle.winner = Some(winner);
+        raffle.prize_claimed = true;
+
+        emit!(PrizeClaimed {
+            raffle: raffle.key(),
+            winner,
+            amount: raffle.prize_amount,
+            timestamp: now,
+        });
+
+        msg!("Prize of {} paid to {}", raffle.prize_amount, winner);
+
+        Ok(())
+    }
+
+    /// Close an entry once the raffle is decided, returning its rent to the
+    /// owner. Permissionless; the winning entry waits for its prize.
+    pub fn close_entry(ctx: Context<CloseEntry>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        ctx.accounts
+            .raffle
+            .check_entry_closable(&ctx.accounts.entry, now)?;
+
+        let raffle = &mut ctx.accounts.raffle;
+        raffle.closed_entries = raffle
+            .closed_entries
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        emit!(EntryClosed {
+            raffle: raffle.key(),
+            owner: ctx.accounts.entry.owner,
+            timestamp: now,
+        });
+
+        msg!("Entry closed");
+
+        Ok(())
+    }
+
+    /// Close a decided raffle whose entries are all closed. An unclaimed
+    /// prize (nobody entered) goes back to the merchant with the vault rent.
+    pub fn close_raffle(ctx: Context<CloseRaffle>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let raffle = &ctx.accounts.raffle;
+        raffle.check_closable(now)?;
+
+        let raffle_id = raffle.raffle_id.to_le_bytes();
+        let seeds = &[
+            b"raffle",
+            raffle.merchant.as_ref(),
+            raffle_id.as_ref(),
+            &[raffle.bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+        let raffle_info = ctx.accounts.raffle.to_account_info();
+
+        let refund = if raffle.prize_claimed {
+            0
+        } else {
+            raffle.prize_amount
+        };
+        if refund > 0 {
+            let transfer_ix = token_instruction::transfer(
+                &ctx.accounts.token_program.key(),
+                &ctx.accounts.vault.key(),
+                &ctx.accounts.merchant_token_account.key(),
+                &raffle_info.key(),
+                &[],
+                refund,
+            )?;
+            invoke_signed(
+                &transfer_ix,
+                &[
+                    ctx.accounts.vault.to_account_info(),
+                    ctx.accounts.merchant_token_account.to_account_info(),
+                    raffle_info.clone(),
+                    ctx.accounts.token_program.to_account_info(),
+                ],
+                signer_seeds,
+            )?;
+        }
+
+        let close_ix = token_instruction::close_account(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.vault.key(),
+            &ctx.accounts.merchant.key(),
+            &raffle_info.key(),
+            &[],
+        )?;
+        invoke_signed(
+            &close_ix,
+            &[
+                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.merchant.to_account_info(),
+                raffle_info,
+                ctx.accounts.token_program.to_account_info(),
+            ],
+            signer_seeds,
+        )?;
+
+        emit!(RaffleClosed {
+            raffle: ctx.accounts.raffle.key(),
+            refunded: refund,
+            timestamp: now,
+        });
+
+        msg!("Raffle closed");
+        msg!("Prize refunded: {}", refund);
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(raffle_id: u64)]
+pub struct CreateRaffle<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Raffle::INIT_SPACE,
+        seeds = [b"raffle", merchant.key().as_ref(), raffle_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub raffle: Account<'info, Raffle>,
+
+    pub merchant: Signer<'info>,
+
+    /// CHECK: Mint the prize is paid in
+    pub mint: UncheckedAccount<'info>,
+
+    /// CHECK: Merchant's prize account, debited by the token program
+    #[account(mut)]
+    pub merchant_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Token account owned by the raffle PDA (usually its ATA)
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Enter<'info> {
+    #[account(
+        mut,
+        seeds = [b"raffle", raffle.merchant.as_ref(), raffle.raffle_id.to_le_bytes().as_ref()],
+        bump = raffle.bump,
+    )]
+    pub raffle: Account<'info, Raffle>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Entry::INIT_SPACE,
+        seeds = [b"entry", raffle.key().as_ref(), owner.key().as_ref()],
+        bump
+    )]
+    pub entry: Account<'info, Entry>,
+
+    pub owner: Signer<'info>,
+
+    /// The owner's subscription to the merchant, owned by the subscription program
+    #[account(
+        constraint = subscription.authority == owner.key() @ ErrorCode::SubscriptionMismatch,
+        constraint = subscription.recipient == raffle.merchant @ ErrorCode::SubscriptionMismatch
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    /// Fee and rent payer, usually a relayer
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimEntries<'info> {
+    #[account(
+        mut,
+        seeds = [b"raffle", raffle.merchant.as_ref(), raffle.raffle_id.to_le_bytes().as_ref()],
+        bump = raffle.bump,
+    )]
+    pub raffle: Account<'info, Raffle>,
+
+    #[account(
+        mut,
+        seeds = [b"entry", raffle.key().as_ref(), entry.owner.as_ref()],
+        bump = entry.bump,
+        has_one = raffle
+    )]
+    pub entry: Account<'info, Entry>,
+
+    /// The entrant's subscription to the merchant, owned by the subscription program
+    #[account(
+        constraint = subscription.authority == entry.owner @ ErrorCode::SubscriptionMismatch,
+        constraint = subscription.recipient == raffle.merchant @ ErrorCode::SubscriptionMismatch
+    )]
+    pub subscription: Account<'info, Subscription>,
+}
+
+#[derive(Accounts)]
+pub struct RequestDraw<'info> {
+    #[account(
+        mut,
+        seeds = [b"raffle", merchant.key().as_ref(), raffle.raffle_id.to_le_bytes().as_ref()],
+        bump = raffle.bump,
+        has_one = merchant
+    )]
+    pub raffle: Account<'info, Raffle>,
+
+    pub merchant: Signer<'info>,
+
+    /// CHECK: Switchboard randomness account, decoded in the handler
+    pub randomness: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct DrawWinner<'info> {
+    #[account(
+        mut,
+        seeds = [b"raffle", raffle.merchant.as_ref(), raffle.raffle_id.to_le_bytes().as_ref()],
+        bump = raffle.bump,
+        constraint = raffle.randomness == Some(randomness.key()) @ ErrorCode::RandomnessMismatch
+    )]
+    pub raffle: Account<'info, Raffle>,
+
+    /// CHECK: The randomness account committed to in `request_draw`
+    pub randomness: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimPrize<'info> {
+    #[account(
+        mut,
+        seeds = [b"raffle", raffle.merchant.as_ref(), raffle.raffle_id.to_le_bytes().as_ref()],
+        bump = raffle.bump,
+        has_one = vault
+    )]
+    pub raffle: Account<'info, Raffle>,
+
+    #[account(
+        seeds = [b"entry", raffle.key().as_ref(), entry.owner.as_ref()],
+        bump = entry.bump,
+        has_one = raffle
+    )]
+    pub entry: Account<'info, Entry>,
+
+    /// CHECK: The entry owner's prize account, checked in the handler
+    #[account(mut)]
+    pub winner_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: The raffle's prize vault
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseEntry<'info> {
+    #[account(
+        mut,
+        seeds = [b"raffle", raffle.merchant.as_ref(), raffle.raffle_id.to_le_bytes().as_ref()],
+        bump = raffle.bump,
+    )]
+    pub raffle: Account<'info, Raffle>,
+
+    #[account(
+        mut,
+        seeds = [b"entry", raffle.key().as_ref(), owner.key().as_ref()],
+        bump = entry.bump,
+        has_one = raffle,
+        has_one = owner,
+        close = owner
+    )]
+    pub entry: Account<'info, Entry>,
+
+    /// CHECK: Receives the entry rent
+    #[account(mut)]
+    pub owner: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseRaffle<'info> {
+    #[account(
+        mut,
+        seeds = [b"raffle", merchant.key().as_ref(), raffle.raffle_id.to_le_bytes().as_ref()],
+        bump = raffle.bump,
+        has_one = merchant,
+        has_one = vault,
+        close = merchant
+    )]
+    pub raffle: Account<'info, Raffle>,
+
+    /// CHECK: Receives the raffle and vault rent
+    #[account(mut)]
+    pub merchant: UncheckedAccount<'info>,
+
+    /// CHECK: Receives an unclaimed prize
+    #[account(mut)]
+    pub merchant_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: The raffle's prize vault, closed here
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Raffle {
+    pub merchant: Pubkey,
+    /// Lets one merchant run several raffles
+    pub raffle_id: u64,
+    pub mint: Pubkey,
+    /// Prize account owned by this PDA
+    pub vault: Pubkey,
+    pub prize_amount: u64,
+    /// Entries can be claimed until here
+    pub entry_end: i64,
+    pub entrant_count: u32,
+    pub closed_entries: u32,
+    /// Entries are numbered 0..total_entries in claim order
+    pub total_entries: u64,
+    /// Switchboard randomness account the draw is committed to
+    pub randomness: Option<Pubkey>,
+    /// Slot the committed randomness was seeded in
+    pub seed_slot: u64,
+    pub winning_entry: Option<u64>,
+    pub winner: Option<Pubkey>,
+    pub prize_claimed: bool,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl Raffle {
+    pub fn check_params(prize_amount: u64, entry_end: i64, now: i64) -> Result<()> {
+        require!(prize_amount > 0, ErrorCode::InvalidAmount);
+        require!(entry_end > now, ErrorCode::InvalidSchedule);
+        Ok(())
+    }
+
+    pub fn check_entering(&self, now: i64) -> Result<()> {
+        require!(now < self.entry_end, ErrorCode::EntriesClosed);
+        Ok(())
+    }
+
+    /// Number the next `count` entries, returning the first
+    pub fn record_entries(&mut self, count: u64) -> Result<u64> {
+        let first = self.total_entries;
+        self.total_entries = first
+            .checked_add(count)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(first)
+    }
+
+    /// Commit to `randomness` at `slot`. It must have been seeded in the
+    /// slot before and not be revealed yet.
+    pub fn request_draw(
+        &mut self,
+        address: Pubkey,
+        randomness: &Randomness,
+        slot: u64,
+        now: i64,
+    ) -> Result<()> {
+        require!(now >= self.entry_end, ErrorCode::EntriesOpen);
+        require!(self.total_entries > 0, ErrorCode::NoEntries);
+        require!(self.randomness.is_none(), ErrorCode::DrawAlreadyRequested);
+        require!(
+            randomness.seed_slot.checked_add(1) == Some(slot) && randomness.reveal_slot == 0,
+            ErrorCode::StaleRandomness
+        );
+        self.randomness = Some(address);
+        self.seed_slot = randomness.seed_slot;
+        Ok(())
+    }
+
+    /// Draw the winning entry from the committed, now revealed, randomness
+    pub fn draw(&mut self, randomness: &Randomness) -> Result<u64> {
+        require!(self.winning_entry.is_none(), ErrorCode::AlreadyDrawn);
+        require!(self.randomness.is_some(), ErrorCode::DrawNotRequested);
+        require!(
+            randomness.seed_slot == self.seed_slot,
+            ErrorCode::RandomnessMismatch
+        );
+        require!(
+            randomness.reveal_slot > randomness.seed_slot,
+            ErrorCode::RandomnessNotRevealed
+        );
+        let winning_entry = randomness.pick(self.total_entries);
+        self.winning_entry = Some(winning_entry);
+        Ok(winning_entry)
+    }
+
+    pub fn check_prize_claim(&self, entry: &Entry) -> Result<()> {
+        let winning_entry = self.winning_entry.ok_or(ErrorCode::NotDrawn)?;
+        require!(!self.prize_claimed, ErrorCode::PrizeClaimed);
+        require!(entry.holds(winning_entry), ErrorCode::NotTheWinner);
+        Ok(())
+    }
+
+    /// Whether the raffle is over: drawn, or closed with nobody entered
+    pub fn is_decided(&self, now: i64) -> bool {
+        self.winning_entry.is_some() || (now >= self.entry_end && self.total_entries == 0)
+    }
+
+    pub fn check_entry_closable(&self, entry: &Entry, now: i64) -> Result<()> {
+        require!(self.is_decided(now), ErrorCode::NotDrawn);
+        if let Some(winning_entry) = self.winning_entry {
+            require!(
+                self.prize_claimed || !entry.holds(winning_entry),
+                ErrorCode::PrizeUnclaimed
+            );
+        }
+        Ok(())
+    }
+
+    pub fn check_closable(&self, now: i64) -> Result<()> {
+        require!(self.is_decided(now), ErrorCode::NotDrawn);
+        require!(
+            self.winning_entry.is_none() || self.prize_claimed,
+            ErrorCode::PrizeUnclaimed
+        );
+        require!(
+            self.closed_entries == self.entrant_count,
+            ErrorCode::EntriesOutstanding
+        );
+        Ok(())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub struct EntryRange {
+    pub first: u64,
+    pub count: u64,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Entry {
+    pub raffle: Pubkey,
+    pub owner: Pubkey,
+    /// The subscription's `total_charged` already turned into entries
+    pub subscription_credited: u64,
+    pub entries: u64,
+    /// Entry numbers this entrant holds
+    #[max_len(MAX_RANGES)]
+    pub ranges: Vec<EntryRange>,
+    pub joined_at: i64,
+    pub bump: u8,
+}
+
+impl Entry {
+    /// Credit whole periods charged since the last claim, returning how many.
+    /// A total below what was credited means the subscription was closed and
+    /// opened again, so all of it is new; a partial period carries over.
+    pub fn credit_charges(&mut self, total_charged: u64, amount_per_period: u64) -> Result<u64> {
+        require!(amount_per_period > 0, ErrorCode::InvalidAmount);
+        let credited = if total_charged < self.subscription_credited {
+            0
+        } else {
+            self.subscription_credited
+        };
+        let periods = (total_charged - credited) / amount_per_period;
+        self.subscription_credited = credited
+            .checked_add(periods * amount_per_period)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(periods)
+    }
+
+    /// Add entries `first..first + count`, extending the last range when
+    /// they follow on from it
+    pub fn record_range(&mut self, first: u64, count: u64) -> Result<()> {
+        match self.ranges.last_mut() {
+            Some(last) if last.first + last.count == first => last.count += count,
+            _ => {
+                require!(self.ranges.len() < MAX_RANGES, ErrorCode::TooManyRanges);
+                self.ranges.push(EntryRange { first, count });
+            }
+        }
+        self.entries = self
+            .entries
+            .checked_add(count)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+
+    pub fn holds(&self, entry: u64) -> bool {
+        self.ranges
+            .iter()
+            .any(|range| entry >= range.first && entry - range.first < range.count)
+    }
+}
+
+/// The fields of a Switchboard On-Demand `RandomnessAccountData` a draw
+/// needs. Decoded by offset so the recipe does not pull in the Switchboard
+/// SDK.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Randomness {
+    pub seed_slot: u64,
+    /// Zero until an oracle reveals the value
+    pub reveal_slot: u64,
+    pub value: [u8; 32],
+}
+
+impl Randomness {
+    pub const DISCRIMINATOR: [u8; 8] = [10, 66, 229, 135, 220, 239, 217, 114];
+    /// Discriminator, authority, queue and seed slothash come first
+    const SEED_SLOT_OFFSET: usize = 8 + 32 + 32 + 32;
+    /// Then the oracle
+    const REVEAL_SLOT_OFFSET: usize = Self::SEED_SLOT_OFFSET + 8 + 32;
+    const VALUE_OFFSET: usize = Self::REVEAL_SLOT_OFFSET + 8;
+    pub const LEN: usize = Self::VALUE_OFFSET + 32;
+
+    pub fn load(info: &AccountInfo) -> Result<Self> {
+        require_keys_eq!(
+            *info.owner,
+            SWITCHBOARD_PROGRAM_ID,
+            ErrorCode::InvalidRandomnessAccount
+        );
+        Self::parse(&info.try_borrow_data()?)
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        require!(
+            data.len() >= Self::LEN && data[..8] == Self::DISCRIMINATOR,
+            ErrorCode::InvalidRandomnessAccount
+        );
+        let read_u64 =
+            |offset: usize| u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+        Ok(Self {
+            seed_slot: read_u64(Self::SEED_SLOT_OFFSET),
+            reveal_slot: read_u64(Self::REVEAL_SLOT_OFFSET),
+            value: data[Self::VALUE_OFFSET..Self::LEN].try_into().unwrap(),
+        })
+    }
+
+    /// Entry number in `0..total_entries`. Reducing 128 bits keeps the
+    /// modulo bias negligible for any realistic entry count.
+    pub fn pick(&self, total_entries: u64) -> u64 {
+        let wide = u128::from_le_bytes(self.value[..16].try_into().unwrap());
+        (wide % u128::from(total_entries)) as u64
+    }
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RaffleCreated {
+    pub raffle: Pubkey,
+    pub merchant: Pubkey,
+    pub mint: Pubkey,
+    pub prize_amount: u64,
+    pub entry_end: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntrantJoined {
+    pub raffle: Pubkey,
+    pub owner: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntriesClaimed {
+    pub raffle: Pubkey,
+    pub owner: Pubkey,
+    pub first_entry: u64,
+    pub count: u64,
+    pub total_entries: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DrawRequested {
+    pub raffle: Pubkey,
+    pub randomness: Pubkey,
+    pub seed_slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct WinnerDrawn {
+    pub raffle: Pubkey,
+    pub winning_entry: u64,
+    pub total_entries: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrizeClaimed {
+    pub raffle: Pubkey,
+    pub winner: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntryClosed {
+    pub raffle: Pubkey,
+    pub owner: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RaffleClosed {
+    pub raffle: Pubkey,
+    pub refunded: u64,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Amount must be greater than zero")]
+    InvalidAmount,
+    #[msg("Entries must close in the future")]
+    InvalidSchedule,
+    #[msg("Invalid token account - wrong program, owner or mint")]
+    InvalidTokenAccount,
+    #[msg("Subscription does not belong to this entrant and merchant")]
+    SubscriptionMismatch,
+    #[msg("Subscription is not active")]
+    SubscriptionNotActive,
+    #[msg("Entries have closed")]
+    EntriesClosed,
+    #[msg("Entries are still open")]
+    EntriesOpen,
+    #[msg("No charged periods left to claim")]
+    NothingToClaim,
+    #[msg("Entrant holds too many separate entry ranges")]
+    TooManyRanges,
+    #[msg("Nobody holds an entry")]
+    NoEntries,
+    #[msg("Not a Switchboard randomness account")]
+    InvalidRandomnessAccount,
+    #[msg("Randomness must be seeded in the previous slot and unrevealed")]
+    StaleRandomness,
+    #[msg("A draw has already been requested")]
+    DrawAlreadyRequested,
+    #[msg("No draw has been requested")]
+    DrawNotRequested,
+    #[msg("Randomness does not match the committed draw")]
+    RandomnessMismatch,
+    #[msg("Randomness has not been revealed")]
+    RandomnessNotRevealed,
+    #[msg("Winner has already been drawn")]
+    AlreadyDrawn,
+    #[msg("The raffle has not been drawn")]
+    NotDrawn,
+    #[msg("Entry does not hold the winning entry")]
+    NotTheWinner,
+    #[msg("Prize has already been claimed")]
+    PrizeClaimed,
+    #[msg("The winning entry has not claimed its prize")]
+    PrizeUnclaimed,
+    #[msg("Every entry must be closed first")]
+    EntriesOutstanding,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
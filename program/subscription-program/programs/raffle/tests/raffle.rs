@@ -0,0 +1,781 @@
+//! Native tests for the raffle program.
+//!
+//! Entry crediting, entry ranges, Switchboard decoding, the draw commitment
+//! and the closing rules are pure methods and are tested directly. Every
+//! instruction runs end to end, with Switchboard's randomness account written
+//! by the fixture.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, Space, ToAccountMetas};
+use raffle::{
+    accounts, instruction, Entry, EntryRange, ErrorCode, Raffle, Randomness, ID as PROGRAM_ID,
+    MAX_RANGES, SWITCHBOARD_PROGRAM_ID,
+};
+use subscription_program::Subscription;
+use test_harness::{
+    assert_error, assert_success, Account, Keypair, Signer, SystemError, TestSvm, TransactionResult,
+};
+
+const RAFFLE_ID: u64 = 1;
+const PRIZE: u64 = 500_000_000;
+/// Monthly price of the merchant's subscription
+const PRICE: u64 = 10_000_000;
+const DAY: i64 = 86_400;
+
+fn raffle(now: i64) -> Raffle {
+    Raffle {
+        merchant: Pubkey::new_unique(),
+        raffle_id: RAFFLE_ID,
+        mint: Pubkey::new_unique(),
+        vault: Pubkey::new_unique(),
+        prize_amount: PRIZE,
+        entry_end: now + 90 * DAY,
+        entrant_count: 1,
+        closed_entries: 0,
+        total_entries: 0,
+        randomness: None,
+        seed_slot: 0,
+        winning_entry: None,
+        winner: None,
+        prize_claimed: false,
+        created_at: now,
+        bump: 255,
+    }
+}
+
+fn entry(raffle: Pubkey, owner: Pubkey) -> Entry {
+    Entry {
+        raffle,
+        owner,
+        subscription_credited: 0,
+        entries: 0,
+        ranges: Vec::new(),
+        joined_at: 0,
+        bump: 255,
+    }
+}
+
+/// A Switchboard `RandomnessAccountData` as stored on-chain
+fn randomness_data(seed_slot: u64, reveal_slot: u64, value: [u8; 32]) -> Vec<u8> {
+    let mut data = Randomness::DISCRIMINATOR.to_vec();
+    data.extend_from_slice(&[1; 96]);
+    data.extend_from_slice(&seed_slot.to_le_bytes());
+    data.extend_from_slice(&[2; 32]);
+    data.extend_from_slice(&reveal_slot.to_le_bytes());
+    data.extend_from_slice(&value);
+    // Padding Switchboard reserves after the value
+    data.extend_from_slice(&[0; 224]);
+    data
+}
+
+fn value_for(entry: u64) -> [u8; 32] {
+    let mut value = [0; 32];
+    value[..8].copy_from_slice(&entry.to_le_bytes());
+    value
+}
+
+#[test]
+fn entries_open_until_the_deadline() {
+    assert!(Raffle::check_params(PRIZE, DAY, 0).is_ok());
+    assert_eq!(
+        Raffle::check_params(0, DAY, 0).unwrap_err(),
+        ErrorCode::InvalidAmount.into()
+    );
+    assert_eq!(
+        Raffle::check_params(PRIZE, 0, 0).unwrap_err(),
+        ErrorCode::InvalidSchedule.into()
+    );
+
+    let raffle = raffle(0);
+    assert!(raffle.check_entering(90 * DAY - 1).is_ok());
+    assert_eq!(
+        raffle.check_entering(90 * DAY).unwrap_err(),
+        ErrorCode::EntriesClosed.into()
+    );
+}
+
+#[test]
+fn each_charged_period_is_one_entry() {
+    let mut account = entry(Pubkey::new_unique(), Pubkey::new_unique());
+    account.subscription_credited = 3 * PRICE;
+    assert_eq!(account.credit_charges(3 * PRICE, PRICE).unwrap(), 0);
+    assert_eq!(account.credit_charges(5 * PRICE, PRICE).unwrap(), 2);
+    assert_eq!(account.subscription_credited, 5 * PRICE);
+
+    // A partial period (the price changed mid-way) carries over
+    assert_eq!(account.credit_charges(6 * PRICE + 5, 2 * PRICE).unwrap(), 0);
+    assert_eq!(account.credit_charges(7 * PRICE, 2 * PRICE).unwrap(), 1);
+    assert_eq!(account.subscription_credited, 7 * PRICE);
+
+    // Closed and re-subscribed: the new subscription's total starts over
+    assert_eq!(account.credit_charges(PRICE, PRICE).unwrap(), 1);
+    assert_eq!(account.subscription_credited, PRICE);
+
+    assert_eq!(
+        account.credit_charges(PRICE, 0).unwrap_err(),
+        ErrorCode::InvalidAmount.into()
+    );
+}
+
+#[test]
+fn entry_ranges_merge_and_are_capped() {
+    let mut account = entry(Pubkey::new_unique(), Pubkey::new_unique());
+    account.record_range(0, 2).unwrap();
+    // Follows on directly, so the range grows
+    account.record_range(2, 1).unwrap();
+    account.record_range(7, 2).unwrap();
+    assert_eq!(
+        account.ranges,
+        vec![
+            EntryRange { first: 0, count: 3 },
+            EntryRange { first: 7, count: 2 }
+        ]
+    );
+    assert_eq!(account.entries, 5);
+    assert!(account.holds(0) && account.holds(2) && account.holds(8));
+    assert!(!account.holds(3) && !account.holds(6) && !account.holds(9));
+
+    let mut busy = entry(Pubkey::new_unique(), Pubkey::new_unique());
+    for range in 0..MAX_RANGES as u64 {
+        busy.record_range(range * 2, 1).unwrap();
+    }
+    assert_eq!(
+        busy.record_range(100, 1).unwrap_err(),
+        ErrorCode::TooManyRanges.into()
+    );
+    // Extending the last range still fits
+    busy.record_range(MAX_RANGES as u64 * 2 - 1, 1).unwrap();
+}
+
+#[test]
+fn randomness_decodes_the_switchboard_layout() {
+    let randomness = Randomness::parse(&randomness_data(40, 42, value_for(7))).unwrap();
+    assert_eq!(randomness.seed_slot, 40);
+    assert_eq!(randomness.reveal_slot, 42);
+    assert_eq!(randomness.pick(10), 7);
+    assert_eq!(randomness.pick(5), 2);
+
+    let mut wrong = randomness_data(40, 42, [0; 32]);
+    wrong[0] ^= 1;
+    assert_eq!(
+        Randomness::parse(&wrong).unwrap_err(),
+        ErrorCode::InvalidRandomnessAccount.into()
+    );
+    assert_eq!(
+        Randomness::parse(&randomness_data(40, 42, [0; 32])[..Randomness::LEN - 1]).unwrap_err(),
+        ErrorCode::InvalidRandomnessAccount.into()
+    );
+
+    // Every value lands on an entry
+    let high = Randomness::parse(&randomness_data(40, 42, [0xff; 32])).unwrap();
+    assert!(high.pick(3) < 3);
+}
+
+#[test]
+fn draws_commit_before_the_reveal() {
+    let mut raffle = raffle(0);
+    let address = Pubkey::new_unique();
+    let fresh = Randomness::parse(&randomness_data(99, 0, [0; 32])).unwrap();
+    let end = raffle.entry_end;
+
+    assert_eq!(
+        raffle.request_draw(address, &fresh, 100, end).unwrap_err(),
+        ErrorCode::NoEntries.into()
+    );
+    raffle.total_entries = 10;
+    assert_eq!(
+        raffle
+            .request_draw(address, &fresh, 100, end - 1)
+            .unwrap_err(),
+        ErrorCode::EntriesOpen.into()
+    );
+    // Seeded too long ago, or already revealed
+    assert_eq!(
+        raffle.request_draw(address, &fresh, 101, end).unwrap_err(),
+        ErrorCode::StaleRandomness.into()
+    );
+    let revealed = Randomness::parse(&randomness_data(99, 100, value_for(3))).unwrap();
+    assert_eq!(
+        raffle
+            .request_draw(address, &revealed, 100, end)
+            .unwrap_err(),
+        ErrorCode::StaleRandomness.into()
+    );
+    assert_eq!(
+        raffle.draw(&revealed).unwrap_err(),
+        ErrorCode::DrawNotRequested.into()
+    );
+
+    raffle.request_draw(address, &fresh, 100, end).unwrap();
+    assert_eq!(raffle.randomness, Some(address));
+    assert_eq!(raffle.seed_slot, 99);
+    assert_eq!(
+        raffle.request_draw(address, &fresh, 100, end).unwrap_err(),
+        ErrorCode::DrawAlreadyRequested.into()
+    );
+
+    assert_eq!(
+        raffle.draw(&fresh).unwrap_err(),
+        ErrorCode::RandomnessNotRevealed.into()
+    );
+    // The account was re-committed to another slot since
+    let recommitted = Randomness::parse(&randomness_data(150, 151, value_for(3))).unwrap();
+    assert_eq!(
+        raffle.draw(&recommitted).unwrap_err(),
+        ErrorCode::RandomnessMismatch.into()
+    );
+    assert_eq!(raffle.draw(&revealed).unwrap(), 3);
+    assert_eq!(raffle.winning_entry, Some(3));
+    assert_eq!(
+        raffle.draw(&revealed).unwrap_err(),
+        ErrorCode::AlreadyDrawn.into()
+    );
+}
+
+#[test]
+fn the_prize_goes_to_the_winning_entry_before_closing() {
+    let mut raffle = raffle(0);
+    let end = raffle.entry_end;
+    let mut winner = entry(Pubkey::new_unique(), Pubkey::new_unique());
+    winner.record_range(2, 3).unwrap();
+    let loser = entry(Pubkey::new_unique(), Pubkey::new_unique());
+
+    // Nobody entered: everything closes and the prize goes back
+    assert_eq!(
+        raffle.check_entry_closable(&loser, end - 1).unwrap_err(),
+        ErrorCode::NotDrawn.into()
+    );
+    assert!(raffle.check_entry_closable(&loser, end).is_ok());
+    assert_eq!(
+        raffle.check_closable(end).unwrap_err(),
+        ErrorCode::EntriesOutstanding.into()
+    );
+    raffle.closed_entries = 1;
+    assert!(raffle.check_closable(end).is_ok());
+
+    raffle.total_entries = 5;
+    assert_eq!(
+        raffle.check_prize_claim(&winner).unwrap_err(),
+        ErrorCode::NotDrawn.into()
+    );
+    assert_eq!(
+        raffle.check_closable(end).unwrap_err(),
+        ErrorCode::NotDrawn.into()
+    );
+
+    raffle.winning_entry = Some(4);
+    assert_eq!(
+        raffle.check_prize_claim(&loser).unwrap_err(),
+        ErrorCode::NotTheWinner.into()
+    );
+    assert!(raffle.check_prize_claim(&winner).is_ok());
+    assert!(raffle.check_entry_closable(&loser, end).is_ok());
+    assert_eq!(
+        raffle.check_entry_closable(&winner, end).unwrap_err(),
+        ErrorCode::PrizeUnclaimed.into()
+    );
+    assert_eq!(
+        raffle.check_closable(end).unwrap_err(),
+        ErrorCode::PrizeUnclaimed.into()
+    );
+
+    raffle.prize_claimed = true;
+    assert_eq!(
+        raffle.check_prize_claim(&winner).unwrap_err(),
+        ErrorCode::PrizeClaimed.into()
+    );
+    assert!(raffle.check_entry_closable(&winner, end).is_ok());
+    assert!(raffle.check_closable(end).is_ok());
+}
+
+struct Fixture {
+    svm: TestSvm,
+    payer: Keypair,
+    merchant: Keypair,
+    owner: Keypair,
+    raffle: Pubkey,
+    entry: Pubkey,
+    subscription: Pubkey,
+    randomness: Pubkey,
+    state: Raffle,
+    entry_state: Entry,
+    subscription_state: Subscription,
+}
+
+impl Fixture {
+    /// A raffle created at the harness's default time with one entrant who
+    /// has an active monthly subscription to the merchant, charged once
+    /// before they entered
+    fn new() -> Self {
+        let (mut svm, payer) = TestSvm::for_program(PROGRAM_ID, raffle::entry);
+
+        let merchant = Keypair::new();
+        let owner = Keypair::new();
+
+        let mint = svm.create_mint(&Pubkey::new_unique(), 6);
+        let (address, bump) = Pubkey::find_program_address(
+            &[
+                b"raffle",
+                merchant.pubkey().as_ref(),
+                &RAFFLE_ID.to_le_bytes(),
+            ],
+            &PROGRAM_ID,
+        );
+        let (entry_address, entry_bump) = Pubkey::find_program_address(
+            &[b"entry", address.as_ref(), owner.pubkey().as_ref()],
+            &PROGRAM_ID,
+        );
+        let (subscription, subscription_bump) = Pubkey::find_program_address(
+            &[
+                b"subscription",
+                owner.pubkey().as_ref(),
+                merchant.pubkey().as_ref(),
+            ],
+            &subscription_program::ID,
+        );
+
+        let now = svm.clock().unix_timestamp;
+        let state = Raffle {
+            merchant: merchant.pubkey(),
+            mint,
+            vault: svm.create_associated_token_account(&address, &mint, PRIZE),
+            bump,
+            ..raffle(now)
+        };
+        let entry_state = Entry {
+            subscription_credited: PRICE,
+            joined_at: now,
+            bump: entry_bump,
+            ..entry(address, owner.pubkey())
+        };
+        let subscription_state = Subscription {
+            authority: owner.pubkey(),
+            recipient: merchant.pubkey(),
+            user_token_account: Pubkey::new_unique(),
+            recipient_token_account: Pubkey::new_unique(),
+            token_mint: mint,
+            amount_per_period: PRICE,
+            interval_seconds: 30 * DAY,
+            last_charge_timestamp: now,
+            next_charge_at: now + 30 * DAY,
+            created_at: now,
+            expires_at: None,
+            is_active: true,
+            total_charged: PRICE,
+            bump: subscription_bump,
+            on_plan: false,
+            max_total_spend: Subscription::NO_SPEND_LIMIT,
+        };
+
+        let mut fx = Self {
+            svm,
+            payer,
+            merchant,
+            owner,
+            raffle: address,
+            entry: entry_address,
+            subscription,
+            randomness: Pubkey::new_unique(),
+            state,
+            entry_state,
+            subscription_state,
+        };
+        fx.update(|_| {}, |_| {}, |_| {});
+        fx
+    }
+
+    /// Rewrite the raffle, entry and subscription accounts, with a fresh
+    /// blockhash so the same instruction can be sent again
+    fn update(
+        &mut self,
+        raffle: impl FnOnce(&mut Raffle),
+        entry: impl FnOnce(&mut Entry),
+        subscription: impl FnOnce(&mut Subscription),
+    ) {
+        raffle(&mut self.state);
+        entry(&mut self.entry_state);
+        subscription(&mut self.subscription_state);
+        self.svm
+            .set_anchor_account(self.raffle, &self.state, 8 + Raffle::INIT_SPACE);
+        self.svm
+            .set_anchor_account(self.entry, &self.entry_state, 8 + Entry::INIT_SPACE);
+        self.svm.set_anchor_account(
+            self.subscription,
+            &self.subscription_state,
+            8 + Subscription::INIT_SPACE,
+        );
+        self.svm.expire_blockhash();
+    }
+
+    /// Store the randomness account as Switchboard would, owned by `owner`
+    fn set_randomness(&mut self, owner: Pubkey, seed_slot: u64, reveal_slot: u64, value: [u8; 32]) {
+        let data = randomness_data(seed_slot, reveal_slot, value);
+        let lamports = self.svm.minimum_balance_for_rent_exemption(data.len());
+        self.svm.set_account(
+            self.randomness,
+            Account {
+                lamports,
+                data,
+                owner,
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+        self.svm.expire_blockhash();
+    }
+
+    fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts.to_account_metas(None),
+            data: data.data(),
+        }
+    }
+
+    fn create_raffle(&self, merchant_token_account: Pubkey, vault: Pubkey) -> Instruction {
+        Self::build(
+            accounts::CreateRaffle {
+                raffle: self.raffle,
+                merchant: self.merchant.pubkey(),
+                mint: self.state.mint,
+                merchant_token_account,
+                vault,
+                token_program: spl_token::ID,
+                payer: self.payer.pubkey(),
+                system_program: system_program::ID,
+            },
+            instruction::CreateRaffle {
+                raffle_id: RAFFLE_ID,
+                prize_amount: PRIZE,
+                entry_end: self.state.entry_end,
+            },
+        )
+    }
+
+    fn enter(&self) -> Instruction {
+        Self::build(
+            accounts::Enter {
+                raffle: self.raffle,
+                entry: self.entry,
+                owner: self.owner.pubkey(),
+                subscription: self.subscription,
+                payer: self.payer.pubkey(),
+                system_program: system_program::ID,
+            },
+            instruction::Enter {},
+        )
+    }
+
+    fn claim_entries(&self) -> Instruction {
+        Self::build(
+            accounts::ClaimEntries {
+                raffle: self.raffle,
+                entry: self.entry,
+                subscription: self.subscription,
+            },
+            instruction::ClaimEntries {},
+        )
+    }
+
+    fn request_draw(&self) -> Instruction {
+        Self::build(
+            accounts::RequestDraw {
+                raffle: self.raffle,
+                merchant: self.merchant.pubkey(),
+                randomness: self.randomness,
+            },
+            instruction::RequestDraw {},
+        )
+    }
+
+    fn draw_winner(&self) -> Instruction {
+        Self::build(
+            accounts::DrawWinner {
+                raffle: self.raffle,
+                randomness: self.randomness,
+            },
+            instruction::DrawWinner {},
+        )
+    }
+
+    fn claim_prize(&self, winner_token_account: Pubkey) -> Instruction {
+        Self::build(
+            accounts::ClaimPrize {
+                raffle: self.raffle,
+                entry: self.entry,
+                winner_token_account,
+                vault: self.state.vault,
+                token_program: spl_token::ID,
+            },
+            instruction::ClaimPrize {},
+        )
+    }
+
+    fn close_entry(&self) -> Instruction {
+        Self::build(
+            accounts::CloseEntry {
+                raffle: self.raffle,
+                entry: self.entry,
+                owner: self.owner.pubkey(),
+            },
+            instruction::CloseEntry {},
+        )
+    }
+
+    fn close_raffle(&self, merchant_token_account: Pubkey) -> Instruction {
+        Self::build(
+            accounts::CloseRaffle {
+                raffle: self.raffle,
+                merchant: self.merchant.pubkey(),
+                merchant_token_account,
+                vault: self.state.vault,
+                token_program: spl_token::ID,
+            },
+            instruction::CloseRaffle {},
+        )
+    }
+
+    fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TransactionResult {
+        let payer = self.payer.insecure_clone();
+        self.svm.send_instructions(&[instruction], &payer, signers)
+    }
+
+    fn send_as_merchant(&mut self, instruction: Instruction) -> TransactionResult {
+        let merchant = self.merchant.insecure_clone();
+        self.send(instruction, &[&merchant])
+    }
+
+    fn raffle_state(&self) -> Raffle {
+        self.svm.get_anchor_account(&self.raffle).unwrap()
+    }
+
+    fn entry_account(&self) -> Option<Entry> {
+        self.svm.get_anchor_account(&self.entry)
+    }
+}
+
+#[test]
+fn merchants_fund_the_prize_when_creating_a_raffle() {
+    let mut fx = Fixture::new();
+    let merchant_account =
+        fx.svm
+            .create_associated_token_account(&fx.merchant.pubkey(), &fx.state.mint, 0);
+    fx.svm.remove_account(&fx.raffle);
+    fx.svm
+        .transfer_tokens(&fx.state.vault, &merchant_account, PRIZE);
+
+    // The prize cannot sit in an account the merchant still controls
+    let result = fx.send_as_merchant(fx.create_raffle(merchant_account, merchant_account));
+    assert_error(result, ErrorCode::InvalidTokenAccount);
+
+    let result = fx.send_as_merchant(fx.create_raffle(merchant_account, fx.state.vault));
+    assert_success(result);
+    assert_eq!(fx.svm.token_balance(&merchant_account), 0);
+    assert_eq!(fx.svm.token_balance(&fx.state.vault), PRIZE);
+    let raffle = fx.raffle_state();
+    assert_eq!(
+        (raffle.merchant, raffle.vault),
+        (fx.merchant.pubkey(), fx.state.vault)
+    );
+    assert_eq!(
+        (raffle.prize_amount, raffle.entry_end),
+        (PRIZE, fx.state.entry_end)
+    );
+    assert_eq!((raffle.entrant_count, raffle.total_entries), (0, 0));
+    assert_eq!(raffle.randomness, None);
+}
+
+#[test]
+fn subscribers_enter_once() {
+    let mut fx = Fixture::new();
+    fx.update(
+        |raffle| raffle.entrant_count = 0,
+        |_| {},
+        |sub| sub.recipient = Pubkey::new_unique(),
+    );
+    fx.svm.remove_account(&fx.entry);
+    let owner = fx.owner.insecure_clone();
+
+    let result = fx.send(fx.enter(), &[&owner]);
+    assert_error(result, ErrorCode::SubscriptionMismatch);
+
+    let merchant = fx.merchant.pubkey();
+    fx.update(|_| {}, |_| {}, |sub| sub.recipient = merchant);
+    fx.svm.remove_account(&fx.entry);
+    let result = fx.send(fx.enter(), &[&owner]);
+    assert_success(result);
+    // Only charges after entering count
+    let entry = fx.entry_account().unwrap();
+    assert_eq!((entry.raffle, entry.owner), (fx.raffle, owner.pubkey()));
+    assert_eq!(entry.subscription_credited, PRICE);
+    assert_eq!(entry.joined_at, fx.svm.clock().unix_timestamp);
+    assert_eq!((entry.entries, entry.ranges.len()), (0, 0));
+    assert_eq!(fx.raffle_state().entrant_count, 1);
+
+    fx.svm.expire_blockhash();
+    let result = fx.send(fx.enter(), &[&owner]);
+    assert_error(result, SystemError::AccountAlreadyInUse as u32);
+}
+
+#[test]
+fn charges_after_entering_become_entries() {
+    let mut fx = Fixture::new();
+    // The charge before entering earns nothing
+    let result = fx.send(fx.claim_entries(), &[]);
+    assert_error(result, ErrorCode::NothingToClaim);
+
+    fx.update(|_| {}, |_| {}, |sub| sub.total_charged = 3 * PRICE);
+    let result = fx.send(fx.claim_entries(), &[]);
+    assert!(result.is_ok(), "{result:#?}");
+    let entry = fx.entry_account().unwrap();
+    assert_eq!(entry.entries, 2);
+    assert_eq!(entry.ranges, vec![EntryRange { first: 0, count: 2 }]);
+    assert_eq!(fx.raffle_state().total_entries, 2);
+
+    fx.svm.expire_blockhash();
+    let result = fx.send(fx.claim_entries(), &[]);
+    assert_error(result, ErrorCode::NothingToClaim);
+}
+
+#[test]
+fn lapsed_or_foreign_subscriptions_earn_nothing() {
+    let mut fx = Fixture::new();
+    fx.update(
+        |_| {},
+        |_| {},
+        |sub| {
+            sub.total_charged = 2 * PRICE;
+            sub.is_active = false;
+        },
+    );
+    let result = fx.send(fx.claim_entries(), &[]);
+    assert_error(result, ErrorCode::SubscriptionNotActive);
+
+    let stranger = Pubkey::new_unique();
+    fx.update(
+        |_| {},
+        |_| {},
+        |sub| {
+            sub.is_active = true;
+            sub.recipient = stranger;
+        },
+    );
+    let result = fx.send(fx.claim_entries(), &[]);
+    assert_error(result, ErrorCode::SubscriptionMismatch);
+
+    let merchant = fx.merchant.pubkey();
+    fx.update(|_| {}, |_| {}, |sub| sub.recipient = merchant);
+    fx.svm.warp_to_timestamp(fx.state.entry_end);
+    let result = fx.send(fx.claim_entries(), &[]);
+    assert_error(result, ErrorCode::EntriesClosed);
+}
+
+#[test]
+fn the_draw_commits_to_fresh_switchboard_randomness() {
+    let mut fx = Fixture::new();
+    fx.update(|raffle| raffle.total_entries = 10, |_| {}, |_| {});
+    fx.svm.warp_to_timestamp(fx.state.entry_end);
+    let slot = fx.svm.clock().slot;
+
+    // Same layout, but not owned by Switchboard
+    fx.set_randomness(Pubkey::new_unique(), slot - 1, 0, [0; 32]);
+    let result = fx.send_as_merchant(fx.request_draw());
+    assert_error(result, ErrorCode::InvalidRandomnessAccount);
+
+    fx.set_randomness(SWITCHBOARD_PROGRAM_ID, slot - 2, 0, [0; 32]);
+    let result = fx.send_as_merchant(fx.request_draw());
+    assert_error(result, ErrorCode::StaleRandomness);
+
+    fx.set_randomness(SWITCHBOARD_PROGRAM_ID, slot - 1, 0, [0; 32]);
+    let result = fx.send_as_merchant(fx.request_draw());
+    assert!(result.is_ok(), "{result:#?}");
+    assert_eq!(fx.raffle_state().seed_slot, slot - 1);
+
+    let result = fx.send(fx.draw_winner(), &[]);
+    assert_error(result, ErrorCode::RandomnessNotRevealed);
+
+    fx.set_randomness(SWITCHBOARD_PROGRAM_ID, slot - 1, slot + 1, value_for(13));
+    let result = fx.send(fx.draw_winner(), &[]);
+    assert!(result.is_ok(), "{result:#?}");
+    assert_eq!(fx.raffle_state().winning_entry, Some(3));
+
+    // Only the committed account can be drawn from
+    let committed = fx.raffle_state();
+    fx.randomness = Pubkey::new_unique();
+    fx.state = Raffle {
+        winning_entry: None,
+        ..committed
+    };
+    fx.update(|_| {}, |_| {}, |_| {});
+    fx.set_randomness(SWITCHBOARD_PROGRAM_ID, slot - 1, slot + 1, value_for(1));
+    let result = fx.send(fx.draw_winner(), &[]);
+    assert_error(result, ErrorCode::RandomnessMismatch);
+}
+
+#[test]
+fn the_winning_entry_claims_the_prize() {
+    let mut fx = Fixture::new();
+    let owner = fx.owner.pubkey();
+    let mint = fx.state.mint;
+    let winner_account = fx.svm.create_associated_token_account(&owner, &mint, 0);
+    let merchant_account = fx
+        .svm
+        .create_associated_token_account(&fx.merchant.pubkey(), &mint, 0);
+    fx.update(
+        |raffle| {
+            raffle.total_entries = 4;
+            raffle.winning_entry = Some(3);
+        },
+        |entry| entry.record_range(0, 2).unwrap(),
+        |_| {},
+    );
+    let result = fx.send(fx.claim_prize(winner_account), &[]);
+    assert_error(result, ErrorCode::NotTheWinner);
+
+    fx.update(|_| {}, |entry| entry.record_range(3, 1).unwrap(), |_| {});
+    let result = fx.send(fx.claim_prize(merchant_account), &[]);
+    assert_error(result, ErrorCode::InvalidTokenAccount);
+
+    let result = fx.send(fx.claim_prize(winner_account), &[]);
+    assert_success(result);
+    assert_eq!(fx.svm.token_balance(&winner_account), PRIZE);
+    assert_eq!(fx.svm.token_balance(&fx.state.vault), 0);
+    let raffle = fx.raffle_state();
+    assert_eq!(raffle.winner, Some(owner));
+    assert!(raffle.prize_claimed);
+
+    fx.svm.expire_blockhash();
+    let result = fx.send(fx.claim_prize(winner_account), &[]);
+    assert_error(result, ErrorCode::PrizeClaimed);
+}
+
+#[test]
+fn entries_and_the_raffle_close_once_decided() {
+    let mut fx = Fixture::new();
+    let merchant_account =
+        fx.svm
+            .create_associated_token_account(&fx.merchant.pubkey(), &fx.state.mint, 0);
+    let result = fx.send(fx.close_entry(), &[]);
+    assert_error(result, ErrorCode::NotDrawn);
+
+    // Nobody earned an entry, so the entrant closes and the prize goes back
+    fx.svm.warp_to_timestamp(fx.state.entry_end);
+    let result = fx.send(fx.close_raffle(merchant_account), &[]);
+    assert_error(result, ErrorCode::EntriesOutstanding);
+
+    let result = fx.send(fx.close_entry(), &[]);
+    assert!(result.is_ok(), "{result:#?}");
+    assert!(fx.entry_account().is_none());
+    assert_eq!(fx.raffle_state().closed_entries, 1);
+
+    // The unclaimed prize and both accounts' rent go back to the merchant
+    fx.svm.expire_blockhash();
+    let rent = fx.svm.get_balance(&fx.raffle) + fx.svm.get_balance(&fx.state.vault);
+    let result = fx.send(fx.close_raffle(merchant_account), &[]);
+    assert_success(result);
+    assert_eq!(fx.svm.token_balance(&merchant_account), PRIZE);
+    assert!(fx.svm.get_account(&fx.state.vault).is_none());
+    assert!(fx.svm.get_account(&fx.raffle).is_none());
+    assert_eq!(fx.svm.get_balance(&fx.merchant.pubkey()), rent);
+}
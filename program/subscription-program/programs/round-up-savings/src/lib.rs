@@ -0,0 +1,629 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use spl_token::instruction as token_instruction;
+use token_utils::token_account;
+
+declare_id!("BiRRFTixajGN2p8PvuGDiSxNJFLKPyCvxmSBSpSmmWUp");
+
+#[program]
+pub mod round_up_savings {
+    use super::*;
+
+    /// Start saving the "change" in a spending account: every
+    /// `interval_seconds` the balance's remainder below a multiple of
+    /// `round_to` is swept into a vault, at most `max_per_sweep` at a time.
+    /// The savings PDA becomes the delegate of `source_account`.
+    pub fn open_savings(
+        ctx: Context<OpenSavings>,
+        round_to: u64,
+        interval_seconds: i64,
+        max_per_sweep: u64,
+        goal_amount: u64,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        Savings::check_params(round_to, interval_seconds, max_per_sweep)?;
+
+        let owner = ctx.accounts.owner.key();
+        let mint = ctx.accounts.mint.key();
+        let source = token_account(&ctx.accounts.source_account, ErrorCode::InvalidTokenAccount)?;
+        require_keys_eq!(source.owner, owner, ErrorCode::InvalidTokenAccount);
+        require_keys_eq!(source.mint, mint, ErrorCode::InvalidTokenAccount);
+        let vault = token_account(&ctx.accounts.vault, ErrorCode::InvalidTokenAccount)?;
+        require_keys_eq!(
+            vault.owner,
+            ctx.accounts.savings.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+        require_keys_eq!(vault.mint, mint, ErrorCode::InvalidTokenAccount);
+
+        let delegate_ix = token_instruction::approve(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.source_account.key(),
+            &ctx.accounts.savings.key(),
+            &owner,
+            &[],
+            u64::MAX,
+        )?;
+
+        invoke(
+            &delegate_ix,
+            &[
+                ctx.accounts.source_account.to_account_info(),
+                ctx.accounts.savings.to_account_info(),
+                ctx.accounts.owner.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        let savings = &mut ctx.accounts.savings;
+        savings.owner = owner;
+        savings.mint = mint;
+        savings.source_account = ctx.accounts.source_account.key();
+        savings.vault = ctx.accounts.vault.key();
+        savings.round_to = round_to;
+        savings.interval_seconds = interval_seconds;
+        savings.max_per_sweep = max_per_sweep;
+        savings.goal_amount = goal_amount;
+        savings.goal_reached_at = None;
+        savings.saved = 0;
+        savings.total_swept = 0;
+        savings.total_withdrawn = 0;
+        savings.next_sweep_at = clock.unix_timestamp;
+        savings.created_at = clock.unix_timestamp;
+        savings.bump = ctx.bumps.savings;
+
+        emit!(SavingsOpened {
+            savings: savings.key(),
+            owner,
+            mint,
+            round_to,
+            interval_seconds,
+            goal_amount,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("Savings opened: rounding to {}", round_to);
+        msg!("Sweeping every {} seconds", interval_seconds);
+        msg!("Token delegation approved");
+
+        Ok(())
+    }
+
+    /// Change how round-ups are taken from the next sweep on
+    pub fn update_savings(
+        ctx: Context<UpdateSavings>,
+        round_to: u64,
+        interval_seconds: i64,
+        max_per_sweep: u64,
+    ) -> Result<()> {
+        Savings::check_params(round_to, interval_seconds, max_per_sweep)?;
+
+        let savings = &mut ctx.accounts.savings;
+        savings.round_to = round_to;
+        savings.interval_seconds = interval_seconds;
+        savings.max_per_sweep = max_per_sweep;
+
+        msg!("Savings updated: rounding to {}", round_to);
+
+        Ok(())
+    }
+
+    /// Set a new savings goal; 0 clears it
+    pub fn set_goal(ctx: Context<UpdateSavings>, goal_amount: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let savings = &mut ctx.accounts.savings;
+        let reached = savings.set_goal(goal_amount, now);
+
+        if reached {
+            emit_goal_reached(savings, now);
+        }
+
+        msg!("Savings goal set to {}", goal_amount);
+
+        Ok(())
+    }
+
+    /// Sweep the current round-up into the vault. Permissionless, like
+    /// `charge_subscription`. A balance already on a multiple of `round_to`
+    /// has nothing to sweep; the sweep is still booked so keepers move on.
+    pub fn sweep(ctx: Context<Sweep>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        ctx.accounts.savings.check_due(now)?;
+
+        let balance =
+            token_account(&ctx.accounts.source_account, ErrorCode::InvalidTokenAccount)?.amount;
+        let amount = ctx.accounts.savings.round_up(balance);
+
+        if amount > 0 {
+            let savings = &ctx.accounts.savings;
+            let seeds = &[
+                b"savings",
+                savings.owner.as_ref(),
+                savings.mint.as_ref(),
+                &[savings.bump],
+            ];
+            let signer_seeds = &[&seeds[..]];
+
+            let transfer_ix = token_instruction::transfer(
+                &ctx.accounts.token_program.key(),
+                &ctx.accounts.source_account.key(),
+                &ctx.accounts.vault.key(),
+                &savings.key(),
+                &[],
+                amount,
+            )?;
+
+            invoke_signed(
+                &transfer_ix,
+                &[
+                    ctx.accounts.source_account.to_account_info(),
+                    ctx.accounts.vault.to_account_info(),
+                    ctx.accounts.savings.to_account_info(),
+                    ctx.accounts.token_program.to_account_info(),
+                ],
+                signer_seeds,
+            )?;
+        }
+
+        let savings = &mut ctx.accounts.savings;
+        let reached = savings.record_sweep(amount, now)?;
+
+        emit!(RoundUpSwept {
+            savings: savings.key(),
+            owner: savings.owner,
+            amount,
+            saved: savings.saved,
+            timestamp: now,
+        });
+        if reached {
+            emit_goal_reached(savings, now);
+        }
+
+        msg!("Swept {} into savings", amount);
+        msg!("Saved {} of goal {}", savings.saved, savings.goal_amount);
+
+        Ok(())
+    }
+
+    /// Move savings back to the source account
+    pub fn withdraw(ctx: Context<Withdraw>, amount: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        ctx.accounts.savings.record_withdrawal(amount)?;
+
+        let savings = &ctx.accounts.savings;
+        let seeds = &[
+            b"savings",
+            savings.owner.as_ref(),
+            savings.mint.as_ref(),
+            &[savings.bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.vault.key(),
+            &ctx.accounts.source_account.key(),
+            &savings.key(),
+            &[],
+            amount,
+        )?;
+
+        invoke_signed(
+            &transfer_ix,
+            &[
+                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.source_account.to_account_info(),
+                ctx.accounts.savings.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+            signer_seeds,
+        )?;
+
+        emit!(SavingsWithdrawn {
+            savings: savings.key(),
+            owner: savings.owner,
+            amount,
+            saved: savings.saved,
+            timestamp: now,
+        });
+
+        msg!("Withdrew {} from savings", amount);
+        msg!("{} left saved", savings.saved);
+
+        Ok(())
+    }
+
+    /// Stop saving: return the vault to the source account, revoke the
+    /// delegation and close the vault and the savings account
+    pub fn close_savings(ctx: Context<CloseSavings>) -> Result<()> {
+        let savings = &ctx.accounts.savings;
+        let remaining = token_account(&ctx.accounts.vault, ErrorCode::InvalidTokenAccount)?.amount;
+
+        let seeds = &[
+            b"savings",
+            savings.owner.as_ref(),
+            savings.mint.as_ref(),
+            &[savings.bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+        let savings_info = ctx.accounts.savings.to_account_info();
+
+        if remaining > 0 {
+            let transfer_ix = token_instruction::transfer(
+                &ctx.accounts.token_program.key(),
+                &ctx.accounts.vault.key(),
+                &ctx.accounts.source_account.key(),
+                &savings_info.key(),
+                &[],
+                remaining,
+            )?;
+            invoke_signed(
+                &transfer_ix,
+                &[
+                    ctx.accounts.vault.to_account_info(),
+                    ctx.accounts.source_account.to_account_info(),
+                    savings_info.clone(),
+                    ctx.accounts.token_program.to_account_info(),
+                ],
+                signer_seeds,
+            )?;
+        }
+
+        let close_ix = token_instruction::close_account(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.vault.key(),
+            &ctx.accounts.owner.key(),
+            &savings_info.key(),
+            &[],
+        )?;
+        invoke_signed(
+            &close_ix,
+            &[
+                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.owner.to_account_info(),
+                savings_info.clone(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+            signer_seeds,
+        )?;
+
+        let revoke_ix = token_instruction::revoke(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.source_account.key(),
+            &ctx.accounts.owner.key(),
+            &[],
+        )?;
+        invoke(
+            &revoke_ix,
+            &[
+                ctx.accounts.source_account.to_account_info(),
+                ctx.accounts.owner.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        emit!(SavingsClosed {
+            savings: savings_info.key(),
+            owner: ctx.accounts.owner.key(),
+            returned: remaining,
+            total_swept: ctx.accounts.savings.total_swept,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Savings closed: {} returned", remaining);
+        msg!("Token delegation revoked");
+
+        Ok(())
+    }
+}
+
+fn emit_goal_reached(savings: &Account<Savings>, now: i64) {
+    emit!(GoalReached {
+        savings: savings.key(),
+        owner: savings.owner,
+        goal_amount: savings.goal_amount,
+        saved: savings.saved,
+        timestamp: now,
+    });
+    msg!("Savings goal of {} reached", savings.goal_amount);
+}
+
+#[derive(Accounts)]
+pub struct OpenSavings<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Savings::INIT_SPACE,
+        seeds = [b"savings", owner.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub savings: Account<'info, Savings>,
+
+    pub owner: Signer<'info>,
+
+    /// CHECK: Mint saved in
+    pub mint: UncheckedAccount<'info>,
+
+    /// CHECK: Owner's spending account, checked in the handler and delegated to the savings PDA
+    #[account(mut)]
+    pub source_account: UncheckedAccount<'info>,
+
+    /// CHECK: The savings PDA's token account, checked in the handler
+    pub vault: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateSavings<'info> {
+    #[account(
+        mut,
+        seeds = [b"savings", owner.key().as_ref(), savings.mint.as_ref()],
+        bump = savings.bump,
+        has_one = owner
+    )]
+    pub savings: Account<'info, Savings>,
+
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Sweep<'info> {
+    #[account(
+        mut,
+        seeds = [b"savings", savings.owner.as_ref(), savings.mint.as_ref()],
+        bump = savings.bump,
+        has_one = source_account,
+        has_one = vault
+    )]
+    pub savings: Account<'info, Savings>,
+
+    /// CHECK: The delegated spending account
+    #[account(mut)]
+    pub source_account: UncheckedAccount<'info>,
+
+    /// CHECK: The savings vault
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Withdraw<'info> {
+    #[account(
+        mut,
+        seeds = [b"savings", owner.key().as_ref(), savings.mint.as_ref()],
+        bump = savings.bump,
+        has_one = owner,
+        has_one = source_account,
+        has_one = vault
+    )]
+    pub savings: Account<'info, Savings>,
+
+    pub owner: Signer<'info>,
+
+    /// CHECK: The savings vault
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: The owner's spending account, which receives the withdrawal
+    #[account(mut)]
+    pub source_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseSavings<'info> {
+    #[account(
+        mut,
+        seeds = [b"savings", owner.key().as_ref(), savings.mint.as_ref()],
+        bump = savings.bump,
+        has_one = owner,
+        has_one = source_account,
+        has_one = vault,
+        close = owner
+    )]
+    pub savings: Account<'info, Savings>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// CHECK: The savings vault, emptied and closed
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: The delegated spending account
+    #[account(mut)]
+    pub source_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Savings {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    /// Spending account, delegated to this PDA
+    pub source_account: Pubkey,
+    /// Owned by this PDA
+    pub vault: Pubkey,
+    /// Sweeps take the balance's remainder below a multiple of this
+    pub round_to: u64,
+    pub interval_seconds: i64,
+    pub max_per_sweep: u64,
+    /// 0 for no goal
+    pub goal_amount: u64,
+    /// When `saved` last reached the goal
+    pub goal_reached_at: Option<i64>,
+    /// Swept less withdrawn
+    pub saved: u64,
+    pub total_swept: u64,
+    pub total_withdrawn: u64,
+    pub next_sweep_at: i64,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl Savings {
+    pub fn check_params(round_to: u64, interval_seconds: i64, max_per_sweep: u64) -> Result<()> {
+        require!(round_to > 1, ErrorCode::InvalidRoundTo);
+        require!(interval_seconds > 0, ErrorCode::InvalidInterval);
+        require!(max_per_sweep > 0, ErrorCode::InvalidAmount);
+        Ok(())
+    }
+
+    pub fn check_due(&self, now: i64) -> Result<()> {
+        require!(now >= self.next_sweep_at, ErrorCode::SweepNotDue);
+        Ok(())
+    }
+
+    /// The round-up a sweep takes from `balance`: what is left over above
+    /// the last whole multiple of `round_to`, capped at `max_per_sweep`
+    pub fn round_up(&self, balance: u64) -> u64 {
+        (balance % self.round_to).min(self.max_per_sweep)
+    }
+
+    /// Book a sweep of `amount` at `now`. Returns whether it reached the goal.
+    pub fn record_sweep(&mut self, amount: u64, now: i64) -> Result<bool> {
+        self.saved = self
+            .saved
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.total_swept = self
+            .total_swept
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.next_sweep_at = now
+            .checked_add(self.interval_seconds)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(self.check_goal(now))
+    }
+
+    pub fn record_withdrawal(&mut self, amount: u64) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        self.saved = self
+            .saved
+            .checked_sub(amount)
+            .ok_or(ErrorCode::InsufficientSavings)?;
+        self.total_withdrawn = self
+            .total_withdrawn
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        if self.saved < self.goal_amount {
+            self.goal_reached_at = None;
+        }
+        Ok(())
+    }
+
+    /// Replace the goal. Returns whether savings already reach the new one.
+    pub fn set_goal(&mut self, goal_amount: u64, now: i64) -> bool {
+        self.goal_amount = goal_amount;
+        self.goal_reached_at = None;
+        self.check_goal(now)
+    }
+
+    /// Progress toward the goal in basis points, capped at 100%
+    pub fn goal_progress_bps(&self) -> u16 {
+        if self.goal_amount == 0 {
+            return 0;
+        }
+        let bps = (self.saved as u128 * 10_000 / self.goal_amount as u128).min(10_000);
+        bps as u16
+    }
+
+    fn check_goal(&mut self, now: i64) -> bool {
+        let reached = self.goal_amount > 0
+            && self.goal_reached_at.is_none()
+            && self.saved >= self.goal_amount;
+        if reached {
+            self.goal_reached_at = Some(now);
+        }
+        reached
+    }
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SavingsOpened {
+    pub savings: Pubkey,
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub round_to: u64,
+    pub interval_seconds: i64,
+    pub goal_amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoundUpSwept {
+    pub savings: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub saved: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct GoalReached {
+    pub savings: Pubkey,
+    pub owner: Pubkey,
+    pub goal_amount: u64,
+    pub saved: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SavingsWithdrawn {
+    pub savings: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub saved: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SavingsClosed {
+    pub savings: Pubkey,
+    pub owner: Pubkey,
+    pub returned: u64,
+    pub total_swept: u64,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Amount must be greater than zero")]
+    InvalidAmount,
+    #[msg("Round-ups must be to a multiple greater than one")]
+    InvalidRoundTo,
+    #[msg("Interval must be positive")]
+    InvalidInterval,
+    #[msg("Invalid token account - wrong program, owner or mint")]
+    InvalidTokenAccount,
+    #[msg("Next sweep is not due yet")]
+    SweepNotDue,
+    #[msg("Withdrawal exceeds the amount saved")]
+    InsufficientSavings,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
@@ -0,0 +1,348 @@
+//! Native tests for the round-up savings program.
+//!
+//! Round-up and goal rules are pure methods and are tested directly. Every
+//! instruction runs end to end against the owner's spending account and the
+//! vault.
+
+use anchor_lang::error::ErrorCode as AnchorErrorCode;
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, Space, ToAccountMetas};
+use round_up_savings::{accounts, instruction, ErrorCode, Savings, ID as PROGRAM_ID};
+use test_harness::{assert_error, assert_success, Keypair, Signer, TestSvm, TransactionResult};
+
+const DOLLAR: u64 = 1_000_000;
+const DAY: i64 = 86_400;
+
+fn savings() -> Savings {
+    Savings {
+        owner: Pubkey::new_unique(),
+        mint: Pubkey::new_unique(),
+        source_account: Pubkey::new_unique(),
+        vault: Pubkey::new_unique(),
+        round_to: DOLLAR,
+        interval_seconds: DAY,
+        max_per_sweep: DOLLAR / 2,
+        goal_amount: DOLLAR,
+        goal_reached_at: None,
+        saved: 0,
+        total_swept: 0,
+        total_withdrawn: 0,
+        next_sweep_at: 0,
+        created_at: 0,
+        bump: 255,
+    }
+}
+
+#[test]
+fn params_must_leave_something_to_round() {
+    Savings::check_params(DOLLAR, DAY, 1).unwrap();
+    assert_eq!(
+        Savings::check_params(1, DAY, 1).unwrap_err(),
+        ErrorCode::InvalidRoundTo.into()
+    );
+    assert_eq!(
+        Savings::check_params(DOLLAR, 0, 1).unwrap_err(),
+        ErrorCode::InvalidInterval.into()
+    );
+    assert_eq!(
+        Savings::check_params(DOLLAR, DAY, 0).unwrap_err(),
+        ErrorCode::InvalidAmount.into()
+    );
+}
+
+#[test]
+fn round_ups_take_the_change_up_to_the_cap() {
+    let plan = savings();
+    assert_eq!(plan.round_up(12 * DOLLAR), 0);
+    assert_eq!(plan.round_up(12 * DOLLAR + 340_000), 340_000);
+    assert_eq!(plan.round_up(12 * DOLLAR + 990_000), DOLLAR / 2);
+    assert_eq!(plan.round_up(1), 1);
+}
+
+#[test]
+fn goals_are_reached_once_and_lost_by_withdrawing() {
+    let mut plan = savings();
+    assert!(!plan.record_sweep(600_000, 10).unwrap());
+    assert_eq!(plan.next_sweep_at, 10 + DAY);
+    assert_eq!(plan.goal_progress_bps(), 6_000);
+
+    assert!(plan.record_sweep(400_000, 20).unwrap());
+    assert_eq!(plan.goal_reached_at, Some(20));
+    assert!(!plan.record_sweep(100_000, 30).unwrap());
+    assert_eq!(plan.goal_reached_at, Some(20));
+    assert_eq!(plan.goal_progress_bps(), 10_000);
+
+    assert_eq!(
+        plan.record_withdrawal(1_100_001).unwrap_err(),
+        ErrorCode::InsufficientSavings.into()
+    );
+    plan.record_withdrawal(200_000).unwrap();
+    assert_eq!(plan.saved, 900_000);
+    assert_eq!(plan.total_withdrawn, 200_000);
+    assert_eq!(plan.goal_reached_at, None);
+
+    // A lower goal already met is reached at once
+    assert!(plan.set_goal(DOLLAR / 2, 40));
+    assert_eq!(plan.goal_reached_at, Some(40));
+    assert!(!plan.set_goal(0, 50));
+    assert_eq!(plan.goal_progress_bps(), 0);
+}
+
+struct Fixture {
+    svm: TestSvm,
+    payer: Keypair,
+    owner: Keypair,
+    savings: Pubkey,
+    state: Savings,
+}
+
+impl Fixture {
+    /// Savings with `saved` in the vault and `balance` in the source account
+    fn new(balance: u64, saved: u64) -> Self {
+        let (mut svm, payer) = TestSvm::for_program(PROGRAM_ID, round_up_savings::entry);
+
+        let owner = Keypair::new();
+        svm.airdrop(&owner.pubkey(), 1_000_000_000);
+
+        let mint = svm.create_mint(&Pubkey::new_unique(), 6);
+        let (address, bump) = Pubkey::find_program_address(
+            &[b"savings", owner.pubkey().as_ref(), mint.as_ref()],
+            &PROGRAM_ID,
+        );
+        let source_account = svm.create_associated_token_account(&owner.pubkey(), &mint, balance);
+        svm.approve(&source_account, &address, u64::MAX);
+        let vault = svm.create_associated_token_account(&address, &mint, saved);
+
+        let state = Savings {
+            owner: owner.pubkey(),
+            mint,
+            source_account,
+            vault,
+            saved,
+            total_swept: saved,
+            bump,
+            ..savings()
+        };
+        svm.set_anchor_account(address, &state, 8 + Savings::INIT_SPACE);
+
+        Self {
+            svm,
+            payer,
+            owner,
+            savings: address,
+            state,
+        }
+    }
+
+    fn open(&self, round_to: u64) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::OpenSavings {
+                savings: self.savings,
+                owner: self.owner.pubkey(),
+                mint: self.state.mint,
+                source_account: self.state.source_account,
+                vault: self.state.vault,
+                payer: self.payer.pubkey(),
+                token_program: spl_token::ID,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::OpenSavings {
+                round_to,
+                interval_seconds: DAY,
+                max_per_sweep: DOLLAR / 2,
+                goal_amount: DOLLAR,
+            }
+            .data(),
+        }
+    }
+
+    fn sweep(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::Sweep {
+                savings: self.savings,
+                source_account: self.state.source_account,
+                vault: self.state.vault,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::Sweep {}.data(),
+        }
+    }
+
+    fn update(&self, owner: Pubkey, data: impl InstructionData) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::UpdateSavings {
+                savings: self.savings,
+                owner,
+            }
+            .to_account_metas(None),
+            data: data.data(),
+        }
+    }
+
+    fn withdraw(&self, amount: u64) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::Withdraw {
+                savings: self.savings,
+                owner: self.owner.pubkey(),
+                vault: self.state.vault,
+                source_account: self.state.source_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::Withdraw { amount }.data(),
+        }
+    }
+
+    fn close(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CloseSavings {
+                savings: self.savings,
+                owner: self.owner.pubkey(),
+                vault: self.state.vault,
+                source_account: self.state.source_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CloseSavings {}.data(),
+        }
+    }
+
+    fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TransactionResult {
+        let payer = self.payer.insecure_clone();
+        self.svm.send_instructions(&[instruction], &payer, signers)
+    }
+
+    fn send_as_owner(&mut self, instruction: Instruction) -> TransactionResult {
+        let owner = self.owner.insecure_clone();
+        self.send(instruction, &[&owner])
+    }
+
+    fn savings(&self) -> Savings {
+        self.svm.get_anchor_account(&self.savings).unwrap()
+    }
+
+    /// What the owner can spend, and what is put aside
+    fn balances(&self) -> (u64, u64) {
+        (
+            self.svm.token_balance(&self.state.source_account),
+            self.svm.token_balance(&self.state.vault),
+        )
+    }
+}
+
+#[test]
+fn opening_delegates_the_spending_account_to_the_savings() {
+    let mut fx = Fixture::new(12 * DOLLAR, 0);
+    fx.svm.remove_account(&fx.savings);
+    fx.svm.revoke(&fx.state.source_account);
+
+    let result = fx.send_as_owner(fx.open(1));
+    assert_error(result, ErrorCode::InvalidRoundTo);
+
+    let result = fx.send_as_owner(fx.open(DOLLAR));
+    assert_success(result);
+    let source = fx.svm.get_token_account(&fx.state.source_account).unwrap();
+    assert_eq!(source.delegate, Some(fx.savings).into());
+    assert_eq!(source.delegated_amount, u64::MAX);
+    let savings = fx.savings();
+    assert_eq!(
+        (savings.owner, savings.mint),
+        (fx.owner.pubkey(), fx.state.mint)
+    );
+    assert_eq!(
+        (savings.source_account, savings.vault),
+        (fx.state.source_account, fx.state.vault)
+    );
+    assert_eq!(
+        (savings.round_to, savings.max_per_sweep),
+        (DOLLAR, DOLLAR / 2)
+    );
+    assert_eq!((savings.goal_amount, savings.saved), (DOLLAR, 0));
+    assert_eq!(savings.created_at, fx.svm.clock().unix_timestamp);
+}
+
+#[test]
+fn whole_balances_are_swept_as_nothing_and_rescheduled() {
+    let mut fx = Fixture::new(12 * DOLLAR, 0);
+    let now = fx.svm.clock().unix_timestamp;
+    let result = fx.send(fx.sweep(), &[]);
+    assert!(result.is_ok(), "{result:#?}");
+    assert_eq!(fx.savings().next_sweep_at, now + DAY);
+    assert_eq!(fx.savings().saved, 0);
+
+    fx.svm.expire_blockhash();
+    let result = fx.send(fx.sweep(), &[]);
+    assert_error(result, ErrorCode::SweepNotDue);
+}
+
+#[test]
+fn change_is_swept_into_the_vault() {
+    let mut fx = Fixture::new(12 * DOLLAR + 340_000, 0);
+    let result = fx.send(fx.sweep(), &[]);
+    assert_success(result);
+    assert_eq!(fx.balances(), (12 * DOLLAR, 340_000));
+    let savings = fx.savings();
+    assert_eq!((savings.saved, savings.total_swept), (340_000, 340_000));
+}
+
+#[test]
+fn only_the_owner_changes_the_plan() {
+    let mut fx = Fixture::new(0, 700_000);
+    let stranger = Keypair::new();
+    let forged = fx.update(stranger.pubkey(), instruction::SetGoal { goal_amount: 0 });
+    let result = fx.send(forged, &[&stranger]);
+    assert_error(result, AnchorErrorCode::ConstraintSeeds);
+
+    let bad = instruction::UpdateSavings {
+        round_to: 1,
+        interval_seconds: DAY,
+        max_per_sweep: DOLLAR,
+    };
+    let result = fx.send_as_owner(fx.update(fx.owner.pubkey(), bad));
+    assert_error(result, ErrorCode::InvalidRoundTo);
+
+    let goal = instruction::SetGoal {
+        goal_amount: DOLLAR / 2,
+    };
+    let result = fx.send_as_owner(fx.update(fx.owner.pubkey(), goal));
+    assert!(result.is_ok(), "{result:#?}");
+    let savings = fx.savings();
+    assert_eq!(savings.goal_amount, DOLLAR / 2);
+    assert!(savings.goal_reached_at.is_some());
+}
+
+#[test]
+fn withdrawals_are_limited_to_savings() {
+    let mut fx = Fixture::new(0, 700_000);
+    let result = fx.send_as_owner(fx.withdraw(700_001));
+    assert_error(result, ErrorCode::InsufficientSavings);
+
+    let result = fx.send_as_owner(fx.withdraw(300_000));
+    assert_success(result);
+    assert_eq!(fx.balances(), (300_000, 400_000));
+    let savings = fx.savings();
+    assert_eq!((savings.saved, savings.total_withdrawn), (400_000, 300_000));
+}
+
+#[test]
+fn closing_returns_the_vault_and_revokes() {
+    let mut fx = Fixture::new(0, 700_000);
+    let rent = fx.svm.get_balance(&fx.savings) + fx.svm.get_balance(&fx.state.vault);
+    let before = fx.svm.get_balance(&fx.owner.pubkey());
+    let result = fx.send_as_owner(fx.close());
+    assert_success(result);
+    assert_eq!(fx.svm.token_balance(&fx.state.source_account), 700_000);
+    let source = fx.svm.get_token_account(&fx.state.source_account).unwrap();
+    assert_eq!(source.delegate, None.into());
+    assert!(fx.svm.get_account(&fx.state.vault).is_none());
+    assert!(fx.svm.get_account(&fx.savings).is_none());
+    assert_eq!(fx.svm.get_balance(&fx.owner.pubkey()), before + rent);
+}
@@ -0,0 +1,880 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use solana_sha256_hasher::hashv;
+use spl_token::instruction as token_instruction;
+use token_utils::token_account;
+
+declare_id!("2YTYLkH7zRHdbkRyD44FEawfFoPDpgNcDkvFAjcUX97c");
+
+#[program]
+pub mod sealed_auction {
+    use super::*;
+
+    /// Seller escrows `item_amount` of the item mint and opens sealed bidding
+    /// until `bid_end`. Bids are revealed until `reveal_end`; the highest
+    /// revealed bid of at least `reserve_price` wins.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_auction(
+        ctx: Context<CreateAuction>,
+        auction_id: u64,
+        item_amount: u64,
+        bond_amount: u64,
+        reserve_price: u64,
+        bid_end: i64,
+        reveal_end: i64,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        Auction::check_params(
+            item_amount,
+            bond_amount,
+            bid_end,
+            reveal_end,
+            clock.unix_timestamp,
+        )?;
+
+        let auction_key = ctx.accounts.auction.key();
+        let seller = ctx.accounts.seller.key();
+        let payment_mint = ctx.accounts.payment_mint.key();
+        let item_mint = ctx.accounts.item_mint.key();
+        for (info, owner, mint) in [
+            (&ctx.accounts.vault, auction_key, payment_mint),
+            (&ctx.accounts.item_vault, auction_key, item_mint),
+            (&ctx.accounts.seller_token_account, seller, payment_mint),
+        ] {
+            let account = token_account(info, ErrorCode::InvalidTokenAccount)?;
+            require_keys_eq!(account.owner, owner, ErrorCode::InvalidTokenAccount);
+            require_keys_eq!(account.mint, mint, ErrorCode::InvalidTokenAccount);
+        }
+
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.seller_item_account.key(),
+            &ctx.accounts.item_vault.key(),
+            &seller,
+            &[],
+            item_amount,
+        )?;
+
+        invoke(
+            &transfer_ix,
+            &[
+                ctx.accounts.seller_item_account.to_account_info(),
+                ctx.accounts.item_vault.to_account_info(),
+                ctx.accounts.seller.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        let auction = &mut ctx.accounts.auction;
+        auction.seller = seller;
+        auction.auction_id = auction_id;
+        auction.payment_mint = payment_mint;
+        auction.item_mint = item_mint;
+        auction.seller_token_account = ctx.accounts.seller_token_account.key();
+        auction.seller_item_account = ctx.accounts.seller_item_account.key();
+        auction.vault = ctx.accounts.vault.key();
+        auction.item_vault = ctx.accounts.item_vault.key();
+        auction.item_amount = item_amount;
+        auction.bond_amount = bond_amount;
+        auction.reserve_price = reserve_price;
+        auction.bid_end = bid_end;
+        auction.reveal_end = reveal_end;
+        auction.bid_count = 0;
+        auction.revealed_count = 0;
+        auction.closed_bids = 0;
+        auction.highest_bid = 0;
+        auction.winner = None;
+        auction.settled = false;
+        auction.created_at = clock.unix_timestamp;
+        auction.bump = ctx.bumps.auction;
+
+        emit!(AuctionCreated {
+            auction: auction_key,
+            seller,
+            item_mint,
+            item_amount,
+            bond_amount,
+            reserve_price,
+            bid_end,
+            reveal_end,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("Auction created: {} items", item_amount);
+        msg!("Bidding until {}, reveals until {}", bid_end, reveal_end);
+
+        Ok(())
+    }
+
+    /// Place a sealed bid: `commitment` is `Bid::commitment(bidder, amount,
+    /// salt)`. Only the bond moves now, so the amount stays hidden. The
+    /// bidder signs; `payer`, usually a relayer, pays the fees and rent.
+    pub fn place_bid(ctx: Context<PlaceBid>, commitment: [u8; 32]) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let auction = &ctx.accounts.auction;
+        auction.check_bidding(now)?;
+        let bidder = ctx.accounts.bidder.key();
+        let bidder_account = token_account(
+            &ctx.accounts.bidder_token_account,
+            ErrorCode::InvalidTokenAccount,
+        )?;
+        require_keys_eq!(bidder_account.owner, bidder, ErrorCode::InvalidTokenAccount);
+        require_keys_eq!(
+            bidder_account.mint,
+            auction.payment_mint,
+            ErrorCode::InvalidTokenAccount
+        );
+
+        let bond = auction.bond_amount;
+        transfer_in(
+            &ctx.accounts.token_program,
+            &ctx.accounts.bidder_token_account,
+            &ctx.accounts.vault,
+            &ctx.accounts.bidder,
+            bond,
+        )?;
+
+        let bid = &mut ctx.accounts.bid;
+        bid.auction = ctx.accounts.auction.key();
+        bid.bidder = bidder;
+        bid.token_account = ctx.accounts.bidder_token_account.key();
+        bid.commitment = commitment;
+        bid.deposit = bond;
+        bid.revealed_amount = None;
+        bid.placed_at = now;
+        bid.bump = ctx.bumps.bid;
+
+        let auction = &mut ctx.accounts.auction;
+        auction.bid_count = auction
+            .bid_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        emit!(BidPlaced {
+            auction: auction.key(),
+            bidder,
+            bond,
+            timestamp: now,
+        });
+
+        msg!("Sealed bid placed by {}", bidder);
+        msg!("Bond escrowed: {}", bond);
+
+        Ok(())
+    }
+
+    /// Reveal a bid during the reveal window. Anything the bid exceeds the
+    /// bond by moves into the vault now, so the winner is always funded.
+    pub fn reveal_bid(ctx: Context<RevealBid>, amount: u64, salt: [u8; 32]) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        ctx.accounts.auction.check_revealing(now)?;
+        let top_up = ctx.accounts.bid.record_reveal(amount, &salt)?;
+
+        if top_up > 0 {
+            transfer_in(
+                &ctx.accounts.token_program,
+                &ctx.accounts.bidder_token_account,
+                &ctx.accounts.vault,
+                &ctx.accounts.bidder,
+                top_up,
+            )?;
+        }
+
+        let bidder = ctx.accounts.bidder.key();
+        let auction = &mut ctx.accounts.auction;
+        let leading = auction.record_reveal(bidder, amount)?;
+
+        emit!(BidRevealed {
+            auction: auction.key(),
+            bidder,
+            amount,
+            leading,
+            timestamp: now,
+        });
+
+        msg!("Bid revealed: {}", amount);
+        if leading {
+            msg!("Now the highest bid");
+        }
+
+        Ok(())
+    }
+
+    /// After the reveal window, pay the winning bid to the seller and the
+    /// item to the winner, or return the item if nothing met the reserve.
+    /// Permissionless. `item_destination` is the winner's item account, or
+    /// the seller's when there is no winner.
+    pub fn settle_auction(ctx: Context<SettleAuction>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let auction = &ctx.accounts.auction;
+        auction.check_settleable(now)?;
+        let recipient = auction.winner.unwrap_or(auction.seller);
+        let destination = token_account(
+            &ctx.accounts.item_destination,
+            ErrorCode::InvalidTokenAccount,
+        )?;
+        require_keys_eq!(destination.owner, recipient, ErrorCode::InvalidTokenAccount);
+        require_keys_eq!(
+            destination.mint,
+            auction.item_mint,
+            ErrorCode::InvalidTokenAccount
+        );
+
+        let price = if auction.winner.is_some() {
+            auction.highest_bid
+        } else {
+            0
+        };
+        let auction_info = ctx.accounts.auction.to_account_info();
+        for (source, destination, amount) in [
+            (
+                &ctx.accounts.vault,
+                &ctx.accounts.seller_token_account,
+                price,
+            ),
+            (
+                &ctx.accounts.item_vault,
+                &ctx.accounts.item_destination,
+                auction.item_amount,
+            ),
+        ] {
+            if amount > 0 {
+                transfer_out(
+                    auction,
+                    &auction_info,
+                    &ctx.accounts.token_program,
+                    source,
+                    destination,
+                    amount,
+                )?;
+            }
+        }
+
+        let auction = &mut ctx.accounts.auction;
+        auction.settled = true;
+
+        emit!(AuctionSettled {
+            auction: auction.key(),
+            winner: auction.winner,
+            price,
+            timestamp: now,
+        });
+
+        msg!("Auction settled");
+        msg!("Item to {}, price {}", recipient, price);
+
+        Ok(())
+    }
+
+    /// Close a bid once the auction is settled. Losing bids get their
+    /// deposit back, the winner gets back what they paid above their bid,
+    /// and unrevealed bids forfeit their bond to the seller. Permissionless.
+    pub fn close_bid(ctx: Context<CloseBid>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let auction = &ctx.accounts.auction;
+        require!(auction.settled, ErrorCode::AuctionNotSettled);
+        let (to_bidder, to_seller) = ctx.accounts.bid.close_payouts(auction);
+
+        let auction_info = ctx.accounts.auction.to_account_info();
+        for (destination, amount) in [
+            (&ctx.accounts.bidder_token_account, to_bidder),
+            (&ctx.accounts.seller_token_account, to_seller),
+        ] {
+            if amount > 0 {
+                transfer_out(
+                    auction,
+                    &auction_info,
+                    &ctx.accounts.token_program,
+                    &ctx.accounts.vault,
+                    destination,
+                    amount,
+                )?;
+            }
+        }
+
+        let auction = &mut ctx.accounts.auction;
+        auction.closed_bids = auction
+            .closed_bids
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        emit!(BidClosed {
+            auction: auction.key(),
+            bidder: ctx.accounts.bid.bidder,
+            refunded: to_bidder,
+            forfeited: to_seller,
+            timestamp: now,
+        });
+
+        msg!("Bid closed");
+        msg!("Refunded: {}, forfeited: {}", to_bidder, to_seller);
+
+        Ok(())
+    }
+
+    /// Close a settled auction whose bids are all closed, returning the
+    /// vault and auction rent to the seller. Permissionless.
+    pub fn close_auction(ctx: Context<CloseAuction>) -> Result<()> {
+        let auction = &ctx.accounts.auction;
+        require!(auction.settled, ErrorCode::AuctionNotSettled);
+        require!(
+            auction.closed_bids == auction.bid_count,
+            ErrorCode::BidsOutstanding
+        );
+
+        let auction_id = auction.auction_id.to_le_bytes();
+        let seeds = &[
+            b"auction",
+            auction.seller.as_ref(),
+            auction_id.as_ref(),
+            &[auction.bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+        let auction_info = ctx.accounts.auction.to_account_info();
+
+        for vault in [&ctx.accounts.vault, &ctx.accounts.item_vault] {
+            let close_ix = token_instruction::close_account(
+                &ctx.accounts.token_program.key(),
+                &vault.key(),
+                &ctx.accounts.seller.key(),
+                &auction_info.key(),
+                &[],
+            )?;
+            invoke_signed(
+                &close_ix,
+                &[
+                    vault.to_account_info(),
+                    ctx.accounts.seller.to_account_info(),
+                    auction_info.clone(),
+                    ctx.accounts.token_program.to_account_info(),
+                ],
+                signer_seeds,
+            )?;
+        }
+
+        emit!(AuctionClosed {
+            auction: auction.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Auction closed");
+
+        Ok(())
+    }
+}
+
+/// Move `amount` from a signer's token account into the vault
+fn transfer_in<'info>(
+    token_program: &UncheckedAccount<'info>,
+    source: &UncheckedAccount<'info>,
+    vault: &UncheckedAccount<'info>,
+    authority: &Signer<'info>,
+    amount: u64,
+) -> Result<()> {
+    let transfer_ix = token_instruction::transfer(
+        &token_program.key(),
+        &source.key(),
+        &vault.key(),
+        &authority.key(),
+        &[],
+        amount,
+    )?;
+    invoke(
+        &transfer_ix,
+        &[
+            source.to_account_info(),
+            vault.to_account_info(),
+            authority.to_account_info(),
+            token_program.to_account_info(),
+        ],
+    )?;
+    Ok(())
+}
+
+/// Move `amount` out of one of the auction PDA's token accounts
+fn transfer_out<'info>(
+    auction: &Auction,
+    auction_info: &AccountInfo<'info>,
+    token_program: &UncheckedAccount<'info>,
+    source: &UncheckedAccount<'info>,
+    destination: &UncheckedAccount<'info>,
+    amount: u64,
+) -> Result<()> {
+    let auction_id = auction.auction_id.to_le_bytes();
+    let seeds = &[
+        b"auction",
+        auction.seller.as_ref(),
+        auction_id.as_ref(),
+        &[auction.bump],
+    ];
+    let transfer_ix = token_instruction::transfer(
+        &token_program.key(),
+        &source.key(),
+        &destination.key(),
+        &auction_info.key(),
+        &[],
+        amount,
+    )?;
+    invoke_signed(
+        &transfer_ix,
+        &[
+            source.to_account_info(),
+            destination.to_account_info(),
+            auction_info.clone(),
+            token_program.to_account_info(),
+        ],
+        &[&seeds[..]],
+    )?;
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(auction_id: u64)]
+pub struct CreateAuction<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Auction::INIT_SPACE,
+        seeds = [b"auction", seller.key().as_ref(), auction_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub auction: Account<'info, Auction>,
+
+    pub seller: Signer<'info>,
+
+    /// CHECK: Mint bids are paid in (USDC)
+    pub payment_mint: UncheckedAccount<'info>,
+
+    /// CHECK: Mint of the item on sale
+    pub item_mint: UncheckedAccount<'info>,
+
+    /// CHECK: Seller's payment account, checked in the handler
+    pub seller_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Seller's item account, debited by the token program
+    #[account(mut)]
+    pub seller_item_account: UncheckedAccount<'info>,
+
+    /// CHECK: Payment token account owned by the auction PDA (usually its ATA)
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: Item token account owned by the auction PDA (usually its ATA)
+    #[account(mut)]
+    pub item_vault: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PlaceBid<'info> {
+    #[account(
+        mut,
+        seeds = [b"auction", auction.seller.as_ref(), auction.auction_id.to_le_bytes().as_ref()],
+        bump = auction.bump,
+        has_one = vault
+    )]
+    pub auction: Account<'info, Auction>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Bid::INIT_SPACE,
+        seeds = [b"bid", auction.key().as_ref(), bidder.key().as_ref()],
+        bump
+    )]
+    pub bid: Account<'info, Bid>,
+
+    pub bidder: Signer<'info>,
+
+    /// CHECK: Bidder's payment account, checked in the handler
+    #[account(mut)]
+    pub bidder_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: The auction's payment vault
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    /// Fee and rent payer, usually a relayer
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevealBid<'info> {
+    #[account(
+        mut,
+        seeds = [b"auction", auction.seller.as_ref(), auction.auction_id.to_le_bytes().as_ref()],
+        bump = auction.bump,
+        has_one = vault
+    )]
+    pub auction: Account<'info, Auction>,
+
+    #[account(
+        mut,
+        seeds = [b"bid", auction.key().as_ref(), bidder.key().as_ref()],
+        bump = bid.bump,
+        has_one = auction,
+        has_one = bidder,
+        constraint = bid.token_account == bidder_token_account.key()
+            @ ErrorCode::InvalidTokenAccount
+    )]
+    pub bid: Account<'info, Bid>,
+
+    pub bidder: Signer<'info>,
+
+    /// CHECK: The account the bid was placed from
+    #[account(mut)]
+    pub bidder_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: The auction's payment vault
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SettleAuction<'info> {
+    #[account(
+        mut,
+        seeds = [b"auction", auction.seller.as_ref(), auction.auction_id.to_le_bytes().as_ref()],
+        bump = auction.bump,
+        has_one = vault,
+        has_one = item_vault,
+        has_one = seller_token_account
+    )]
+    pub auction: Account<'info, Auction>,
+
+    /// CHECK: The auction's payment vault
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: The auction's item vault
+    #[account(mut)]
+    pub item_vault: UncheckedAccount<'info>,
+
+    /// CHECK: Receives the winning bid
+    #[account(mut)]
+    pub seller_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: The winner's item account, or the seller's without a winner;
+    /// checked in the handler
+    #[account(mut)]
+    pub item_destination: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseBid<'info> {
+    #[account(
+        mut,
+        seeds = [b"auction", auction.seller.as_ref(), auction.auction_id.to_le_bytes().as_ref()],
+        bump = auction.bump,
+        has_one = vault,
+        has_one = seller_token_account
+    )]
+    pub auction: Account<'info, Auction>,
+
+    #[account(
+        mut,
+        seeds = [b"bid", auction.key().as_ref(), bidder.key().as_ref()],
+        bump = bid.bump,
+        has_one = auction,
+        has_one = bidder,
+        constraint = bid.token_account == bidder_token_account.key()
+            @ ErrorCode::InvalidTokenAccount,
+        close = bidder
+    )]
+    pub bid: Account<'info, Bid>,
+
+    /// CHECK: Receives the bid rent
+    #[account(mut)]
+    pub bidder: UncheckedAccount<'info>,
+
+    /// CHECK: The account the bid was placed from
+    #[account(mut)]
+    pub bidder_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Receives forfeited bonds
+    #[account(mut)]
+    pub seller_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: The auction's payment vault
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseAuction<'info> {
+    #[account(
+        mut,
+        seeds = [b"auction", seller.key().as_ref(), auction.auction_id.to_le_bytes().as_ref()],
+        bump = auction.bump,
+        has_one = seller,
+        has_one = vault,
+        has_one = item_vault,
+        close = seller
+    )]
+    pub auction: Account<'info, Auction>,
+
+    /// CHECK: Receives the auction and vault rent
+    #[account(mut)]
+    pub seller: UncheckedAccount<'info>,
+
+    /// CHECK: The auction's payment vault, closed here
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: The auction's item vault, closed here
+    #[account(mut)]
+    pub item_vault: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Auction {
+    pub seller: Pubkey,
+    /// Lets one seller run several auctions
+    pub auction_id: u64,
+    pub payment_mint: Pubkey,
+    pub item_mint: Pubkey,
+    /// Receives the winning bid and forfeited bonds
+    pub seller_token_account: Pubkey,
+    /// Gets the item back if nobody wins
+    pub seller_item_account: Pubkey,
+    /// Payment account owned by this PDA: bonds and revealed bids
+    pub vault: Pubkey,
+    /// Item account owned by this PDA
+    pub item_vault: Pubkey,
+    pub item_amount: u64,
+    /// Escrowed with every bid and forfeited if it is never revealed
+    pub bond_amount: u64,
+    pub reserve_price: u64,
+    pub bid_end: i64,
+    pub reveal_end: i64,
+    pub bid_count: u32,
+    pub revealed_count: u32,
+    pub closed_bids: u32,
+    pub highest_bid: u64,
+    pub winner: Option<Pubkey>,
+    pub settled: bool,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl Auction {
+    pub fn check_params(
+        item_amount: u64,
+        bond_amount: u64,
+        bid_end: i64,
+        reveal_end: i64,
+        now: i64,
+    ) -> Result<()> {
+        require!(item_amount > 0, ErrorCode::InvalidAmount);
+        require!(bond_amount > 0, ErrorCode::InvalidAmount);
+        require!(bid_end > now, ErrorCode::InvalidSchedule);
+        require!(reveal_end > bid_end, ErrorCode::InvalidSchedule);
+        Ok(())
+    }
+
+    pub fn check_bidding(&self, now: i64) -> Result<()> {
+        require!(now < self.bid_end, ErrorCode::BiddingClosed);
+        Ok(())
+    }
+
+    pub fn check_revealing(&self, now: i64) -> Result<()> {
+        require!(now >= self.bid_end, ErrorCode::RevealNotOpen);
+        require!(now < self.reveal_end, ErrorCode::RevealClosed);
+        Ok(())
+    }
+
+    pub fn check_settleable(&self, now: i64) -> Result<()> {
+        require!(!self.settled, ErrorCode::AuctionSettled);
+        require!(now >= self.reveal_end, ErrorCode::RevealNotOver);
+        Ok(())
+    }
+
+    /// Count a revealed bid of `amount`. Returns whether it now leads; ties
+    /// go to whoever revealed first, and bids under the reserve never lead.
+    pub fn record_reveal(&mut self, bidder: Pubkey, amount: u64) -> Result<bool> {
+        self.revealed_count = self
+            .revealed_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        let leading =
+            amount >= self.reserve_price && (self.winner.is_none() || amount > self.highest_bid);
+        if leading {
+            self.highest_bid = amount;
+            self.winner = Some(bidder);
+        }
+        Ok(leading)
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Bid {
+    pub auction: Pubkey,
+    pub bidder: Pubkey,
+    /// Pays the bond and top-up, and receives refunds
+    pub token_account: Pubkey,
+    pub commitment: [u8; 32],
+    /// Held in the vault for this bid: the bond, plus any top-up on reveal
+    pub deposit: u64,
+    pub revealed_amount: Option<u64>,
+    pub placed_at: i64,
+    pub bump: u8,
+}
+
+impl Bid {
+    /// What a bidder commits to when placing a bid for `amount`. Binding the
+    /// bidder stops anyone replaying another bidder's commitment.
+    pub fn commitment(bidder: &Pubkey, amount: u64, salt: &[u8; 32]) -> [u8; 32] {
+        hashv(&[bidder.as_ref(), &amount.to_le_bytes(), salt]).to_bytes()
+    }
+
+    /// Check a reveal against the commitment and record it. Returns the
+    /// top-up still owed above the bond.
+    pub fn record_reveal(&mut self, amount: u64, salt: &[u8; 32]) -> Result<u64> {
+        require!(self.revealed_amount.is_none(), ErrorCode::AlreadyRevealed);
+        require!(
+            Self::commitment(&self.bidder, amount, salt) == self.commitment,
+            ErrorCode::CommitmentMismatch
+        );
+        let top_up = amount.saturating_sub(self.deposit);
+        self.deposit = self
+            .deposit
+            .checked_add(top_up)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.revealed_amount = Some(amount);
+        Ok(top_up)
+    }
+
+    /// (to bidder, to seller) when the bid closes after settlement. The
+    /// winning bid itself went to the seller at settlement.
+    pub fn close_payouts(&self, auction: &Auction) -> (u64, u64) {
+        match self.revealed_amount {
+            None => (0, self.deposit),
+            Some(amount) if auction.winner == Some(self.bidder) => {
+                (self.deposit.saturating_sub(amount), 0)
+            }
+            Some(_) => (self.deposit, 0),
+        }
+    }
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuctionCreated {
+    pub auction: Pubkey,
+    pub seller: Pubkey,
+    pub item_mint: Pubkey,
+    pub item_amount: u64,
+    pub bond_amount: u64,
+    pub reserve_price: u64,
+    pub bid_end: i64,
+    pub reveal_end: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct BidPlaced {
+    pub auction: Pubkey,
+    pub bidder: Pubkey,
+    pub bond: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct BidRevealed {
+    pub auction: Pubkey,
+    pub bidder: Pubkey,
+    pub amount: u64,
+    pub leading: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuctionSettled {
+    pub auction: Pubkey,
+    pub winner: Option<Pubkey>,
+    pub price: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct BidClosed {
+    pub auction: Pubkey,
+    pub bidder: Pubkey,
+    pub refunded: u64,
+    pub forfeited: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuctionClosed {
+    pub auction: Pubkey,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Amount must be greater than zero")]
+    InvalidAmount,
+    #[msg("Bidding must end in the future and reveals after bidding")]
+    InvalidSchedule,
+    #[msg("Invalid token account - wrong program, owner or mint")]
+    InvalidTokenAccount,
+    #[msg("Bidding has closed")]
+    BiddingClosed,
+    #[msg("Reveals open when bidding closes")]
+    RevealNotOpen,
+    #[msg("The reveal window has closed")]
+    RevealClosed,
+    #[msg("Bid has already been revealed")]
+    AlreadyRevealed,
+    #[msg("Amount and salt do not match the sealed bid")]
+    CommitmentMismatch,
+    #[msg("The reveal window has not ended")]
+    RevealNotOver,
+    #[msg("Auction has already been settled")]
+    AuctionSettled,
+    #[msg("Auction has not been settled")]
+    AuctionNotSettled,
+    #[msg("Every bid must be closed first")]
+    BidsOutstanding,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
@@ -0,0 +1,589 @@
+//! Native tests for the sealed-bid auction program.
+//!
+//! Phases, commitments, winner selection and bid payouts are pure methods and
+//! are tested directly. The instructions run end to end against the real
+//! token and system programs, and the tests follow the bonds, the price and
+//! the item between the vaults and the parties.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, Space, ToAccountMetas};
+use sealed_auction::{accounts, instruction, Auction, Bid, ErrorCode, ID as PROGRAM_ID};
+use test_harness::{assert_error, assert_success, Keypair, Signer, TestSvm, TransactionResult};
+
+const AUCTION_ID: u64 = 1;
+const BOND: u64 = 10_000_000;
+const RESERVE: u64 = 20_000_000;
+const BID: u64 = 25_000_000;
+const SALT: [u8; 32] = [9; 32];
+const DAY: i64 = 86_400;
+
+fn auction(now: i64) -> Auction {
+    Auction {
+        seller: Pubkey::new_unique(),
+        auction_id: AUCTION_ID,
+        payment_mint: Pubkey::new_unique(),
+        item_mint: Pubkey::new_unique(),
+        seller_token_account: Pubkey::new_unique(),
+        seller_item_account: Pubkey::new_unique(),
+        vault: Pubkey::new_unique(),
+        item_vault: Pubkey::new_unique(),
+        item_amount: 1,
+        bond_amount: BOND,
+        reserve_price: RESERVE,
+        bid_end: now + DAY,
+        reveal_end: now + 2 * DAY,
+        bid_count: 0,
+        revealed_count: 0,
+        closed_bids: 0,
+        highest_bid: 0,
+        winner: None,
+        settled: false,
+        created_at: now,
+        bump: 255,
+    }
+}
+
+fn bid(bidder: Pubkey, amount: u64) -> Bid {
+    Bid {
+        auction: Pubkey::new_unique(),
+        bidder,
+        token_account: Pubkey::new_unique(),
+        commitment: Bid::commitment(&bidder, amount, &SALT),
+        deposit: BOND,
+        revealed_amount: None,
+        placed_at: 0,
+        bump: 255,
+    }
+}
+
+#[test]
+fn params_and_phases_are_checked() {
+    assert!(Auction::check_params(1, BOND, DAY, 2 * DAY, 0).is_ok());
+    assert_eq!(
+        Auction::check_params(0, BOND, DAY, 2 * DAY, 0).unwrap_err(),
+        ErrorCode::InvalidAmount.into()
+    );
+    assert_eq!(
+        Auction::check_params(1, 0, DAY, 2 * DAY, 0).unwrap_err(),
+        ErrorCode::InvalidAmount.into()
+    );
+    assert_eq!(
+        Auction::check_params(1, BOND, 0, 2 * DAY, 0).unwrap_err(),
+        ErrorCode::InvalidSchedule.into()
+    );
+    assert_eq!(
+        Auction::check_params(1, BOND, DAY, DAY, 0).unwrap_err(),
+        ErrorCode::InvalidSchedule.into()
+    );
+
+    let mut auction = auction(0);
+    assert!(auction.check_bidding(DAY - 1).is_ok());
+    assert_eq!(
+        auction.check_bidding(DAY).unwrap_err(),
+        ErrorCode::BiddingClosed.into()
+    );
+    assert_eq!(
+        auction.check_revealing(DAY - 1).unwrap_err(),
+        ErrorCode::RevealNotOpen.into()
+    );
+    assert!(auction.check_revealing(DAY).is_ok());
+    assert_eq!(
+        auction.check_revealing(2 * DAY).unwrap_err(),
+        ErrorCode::RevealClosed.into()
+    );
+    assert_eq!(
+        auction.check_settleable(2 * DAY - 1).unwrap_err(),
+        ErrorCode::RevealNotOver.into()
+    );
+    assert!(auction.check_settleable(2 * DAY).is_ok());
+    auction.settled = true;
+    assert_eq!(
+        auction.check_settleable(2 * DAY).unwrap_err(),
+        ErrorCode::AuctionSettled.into()
+    );
+}
+
+#[test]
+fn reveals_must_match_the_sealed_bid() {
+    let bidder = Pubkey::new_unique();
+    let mut sealed = bid(bidder, BID);
+    assert_eq!(
+        sealed.record_reveal(BID + 1, &SALT).unwrap_err(),
+        ErrorCode::CommitmentMismatch.into()
+    );
+    assert_eq!(
+        sealed.record_reveal(BID, &[0; 32]).unwrap_err(),
+        ErrorCode::CommitmentMismatch.into()
+    );
+
+    // Someone replaying the commitment cannot reveal it as their own
+    let mut copied = Bid {
+        bidder: Pubkey::new_unique(),
+        ..sealed.clone()
+    };
+    assert_eq!(
+        copied.record_reveal(BID, &SALT).unwrap_err(),
+        ErrorCode::CommitmentMismatch.into()
+    );
+
+    assert_eq!(sealed.record_reveal(BID, &SALT).unwrap(), BID - BOND);
+    assert_eq!(sealed.deposit, BID);
+    assert_eq!(sealed.revealed_amount, Some(BID));
+    assert_eq!(
+        sealed.record_reveal(BID, &SALT).unwrap_err(),
+        ErrorCode::AlreadyRevealed.into()
+    );
+
+    // A bid under the bond owes nothing more
+    let mut low = bid(bidder, BOND / 2);
+    assert_eq!(low.record_reveal(BOND / 2, &SALT).unwrap(), 0);
+    assert_eq!(low.deposit, BOND);
+}
+
+#[test]
+fn the_highest_bid_over_the_reserve_wins() {
+    let mut auction = auction(0);
+    let (first, second, third) = (
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+    assert!(!auction.record_reveal(first, RESERVE - 1).unwrap());
+    assert_eq!(auction.winner, None);
+    assert!(auction.record_reveal(second, RESERVE).unwrap());
+    // Ties go to whoever revealed first
+    assert!(!auction.record_reveal(third, RESERVE).unwrap());
+    assert!(auction.record_reveal(third, BID).unwrap());
+    assert_eq!(auction.winner, Some(third));
+    assert_eq!(auction.highest_bid, BID);
+    assert_eq!(auction.revealed_count, 4);
+}
+
+#[test]
+fn bids_close_with_refunds_and_forfeits() {
+    let mut auction = auction(0);
+    let winner = Pubkey::new_unique();
+    auction.winner = Some(winner);
+    auction.highest_bid = BOND / 2;
+
+    let unrevealed = bid(Pubkey::new_unique(), BID);
+    assert_eq!(unrevealed.close_payouts(&auction), (0, BOND));
+
+    let mut loser = bid(Pubkey::new_unique(), BID);
+    loser.record_reveal(BID, &SALT).unwrap();
+    assert_eq!(loser.close_payouts(&auction), (BID, 0));
+
+    // The winner's bid went to the seller; only the bond surplus comes back
+    let mut won = bid(winner, BOND / 2);
+    won.record_reveal(BOND / 2, &SALT).unwrap();
+    assert_eq!(won.close_payouts(&auction), (BOND - BOND / 2, 0));
+}
+
+struct Fixture {
+    svm: TestSvm,
+    payer: Keypair,
+    seller: Keypair,
+    bidder: Keypair,
+    auction: Pubkey,
+    bid: Pubkey,
+    state: Auction,
+    bid_state: Bid,
+}
+
+impl Fixture {
+    /// An auction created at the harness's default time with one sealed bid
+    /// of `amount`
+    fn new(amount: u64) -> Self {
+        let (mut svm, payer) = TestSvm::for_program(PROGRAM_ID, sealed_auction::entry);
+
+        let seller = Keypair::new();
+        let bidder = Keypair::new();
+
+        let payment_mint = svm.create_mint(&Pubkey::new_unique(), 6);
+        let item_mint = svm.create_mint(&Pubkey::new_unique(), 0);
+        let (address, bump) = Pubkey::find_program_address(
+            &[
+                b"auction",
+                seller.pubkey().as_ref(),
+                &AUCTION_ID.to_le_bytes(),
+            ],
+            &PROGRAM_ID,
+        );
+        let (bid_address, bid_bump) = Pubkey::find_program_address(
+            &[b"bid", address.as_ref(), bidder.pubkey().as_ref()],
+            &PROGRAM_ID,
+        );
+
+        let now = svm.clock().unix_timestamp;
+        let state = Auction {
+            seller: seller.pubkey(),
+            payment_mint,
+            item_mint,
+            seller_token_account: svm.create_associated_token_account(
+                &seller.pubkey(),
+                &payment_mint,
+                0,
+            ),
+            seller_item_account: svm.create_associated_token_account(
+                &seller.pubkey(),
+                &item_mint,
+                0,
+            ),
+            vault: svm.create_associated_token_account(&address, &payment_mint, BOND),
+            item_vault: svm.create_associated_token_account(&address, &item_mint, 1),
+            bid_count: 1,
+            bump,
+            ..auction(now)
+        };
+        let bid_state = Bid {
+            auction: address,
+            token_account: svm.create_associated_token_account(
+                &bidder.pubkey(),
+                &payment_mint,
+                BID,
+            ),
+            bump: bid_bump,
+            ..bid(bidder.pubkey(), amount)
+        };
+
+        let mut fx = Self {
+            svm,
+            payer,
+            seller,
+            bidder,
+            auction: address,
+            bid: bid_address,
+            state,
+            bid_state,
+        };
+        fx.update(|_| {}, |_| {});
+        fx
+    }
+
+    /// Rewrite the auction and bid accounts, with a fresh blockhash so the
+    /// same instruction can be sent again
+    fn update(&mut self, auction: impl FnOnce(&mut Auction), bid: impl FnOnce(&mut Bid)) {
+        auction(&mut self.state);
+        bid(&mut self.bid_state);
+        self.svm
+            .set_anchor_account(self.auction, &self.state, 8 + Auction::INIT_SPACE);
+        self.svm
+            .set_anchor_account(self.bid, &self.bid_state, 8 + Bid::INIT_SPACE);
+        self.svm.expire_blockhash();
+    }
+
+    fn item_account(&mut self, owner: &Pubkey) -> Pubkey {
+        self.svm
+            .create_associated_token_account(owner, &self.state.item_mint, 0)
+    }
+
+    fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts.to_account_metas(None),
+            data: data.data(),
+        }
+    }
+
+    fn create_auction(&self) -> Instruction {
+        Self::build(
+            accounts::CreateAuction {
+                auction: self.auction,
+                seller: self.seller.pubkey(),
+                payment_mint: self.state.payment_mint,
+                item_mint: self.state.item_mint,
+                seller_token_account: self.state.seller_token_account,
+                seller_item_account: self.state.seller_item_account,
+                vault: self.state.vault,
+                item_vault: self.state.item_vault,
+                token_program: spl_token::ID,
+                payer: self.payer.pubkey(),
+                system_program: system_program::ID,
+            },
+            instruction::CreateAuction {
+                auction_id: AUCTION_ID,
+                item_amount: 1,
+                bond_amount: BOND,
+                reserve_price: RESERVE,
+                bid_end: self.state.bid_end,
+                reveal_end: self.state.reveal_end,
+            },
+        )
+    }
+
+    fn place_bid(&self) -> Instruction {
+        Self::build(
+            accounts::PlaceBid {
+                auction: self.auction,
+                bid: self.bid,
+                bidder: self.bidder.pubkey(),
+                bidder_token_account: self.bid_state.token_account,
+                vault: self.state.vault,
+                token_program: spl_token::ID,
+                payer: self.payer.pubkey(),
+                system_program: system_program::ID,
+            },
+            instruction::PlaceBid {
+                commitment: self.bid_state.commitment,
+            },
+        )
+    }
+
+    fn reveal(&self, amount: u64, salt: [u8; 32]) -> Instruction {
+        Self::build(
+            accounts::RevealBid {
+                auction: self.auction,
+                bid: self.bid,
+                bidder: self.bidder.pubkey(),
+                bidder_token_account: self.bid_state.token_account,
+                vault: self.state.vault,
+                token_program: spl_token::ID,
+            },
+            instruction::RevealBid { amount, salt },
+        )
+    }
+
+    fn settle(&self, item_destination: Pubkey) -> Instruction {
+        Self::build(
+            accounts::SettleAuction {
+                auction: self.auction,
+                vault: self.state.vault,
+                item_vault: self.state.item_vault,
+                seller_token_account: self.state.seller_token_account,
+                item_destination,
+                token_program: spl_token::ID,
+            },
+            instruction::SettleAuction {},
+        )
+    }
+
+    fn close_bid(&self) -> Instruction {
+        Self::build(
+            accounts::CloseBid {
+                auction: self.auction,
+                bid: self.bid,
+                bidder: self.bidder.pubkey(),
+                bidder_token_account: self.bid_state.token_account,
+                seller_token_account: self.state.seller_token_account,
+                vault: self.state.vault,
+                token_program: spl_token::ID,
+            },
+            instruction::CloseBid {},
+        )
+    }
+
+    fn close_auction(&self) -> Instruction {
+        Self::build(
+            accounts::CloseAuction {
+                auction: self.auction,
+                seller: self.seller.pubkey(),
+                vault: self.state.vault,
+                item_vault: self.state.item_vault,
+                token_program: spl_token::ID,
+            },
+            instruction::CloseAuction {},
+        )
+    }
+
+    fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TransactionResult {
+        let payer = self.payer.insecure_clone();
+        self.svm.send_instructions(&[instruction], &payer, signers)
+    }
+
+    fn send_as_bidder(&mut self, instruction: Instruction) -> TransactionResult {
+        let bidder = self.bidder.insecure_clone();
+        self.send(instruction, &[&bidder])
+    }
+
+    fn auction_state(&self) -> Auction {
+        self.svm.get_anchor_account(&self.auction).unwrap()
+    }
+
+    fn bid_account(&self) -> Option<Bid> {
+        self.svm.get_anchor_account(&self.bid)
+    }
+}
+
+#[test]
+fn sellers_escrow_the_item_when_creating_an_auction() {
+    let mut fx = Fixture::new(BID);
+    fx.svm.remove_account(&fx.auction);
+    fx.svm
+        .transfer_tokens(&fx.state.item_vault, &fx.state.seller_item_account, 1);
+    let seller = fx.seller.insecure_clone();
+
+    let result = fx.send(fx.create_auction(), &[&seller]);
+    assert_success(result);
+    assert_eq!(fx.svm.token_balance(&fx.state.seller_item_account), 0);
+    assert_eq!(fx.svm.token_balance(&fx.state.item_vault), 1);
+    let state = fx.auction_state();
+    assert_eq!(state.seller, seller.pubkey());
+    assert_eq!(state.bid_count, 0);
+    assert_eq!(state.bump, fx.state.bump);
+}
+
+#[test]
+fn sealed_bids_escrow_only_the_bond() {
+    let mut fx = Fixture::new(BID);
+    fx.update(|auction| auction.bid_count = 0, |_| {});
+    fx.svm.remove_account(&fx.bid);
+    fx.svm
+        .transfer_tokens(&fx.state.vault, &fx.bid_state.token_account, BOND);
+
+    let result = fx.send_as_bidder(fx.place_bid());
+    assert_success(result);
+    assert_eq!(fx.svm.token_balance(&fx.bid_state.token_account), BID);
+    assert_eq!(fx.svm.token_balance(&fx.state.vault), BOND);
+    let bid = fx.bid_account().unwrap();
+    assert_eq!(bid.deposit, BOND);
+    assert_eq!(bid.revealed_amount, None);
+    assert_eq!(fx.auction_state().bid_count, 1);
+
+    fx.svm.warp_to_timestamp(fx.state.bid_end);
+    fx.svm.remove_account(&fx.bid);
+    let result = fx.send_as_bidder(fx.place_bid());
+    assert_error(result, ErrorCode::BiddingClosed);
+}
+
+#[test]
+fn bids_reveal_during_the_reveal_window() {
+    let mut fx = Fixture::new(BOND);
+    fx.update(|auction| auction.reserve_price = BOND, |_| {});
+    let result = fx.send_as_bidder(fx.reveal(BOND, SALT));
+    assert_error(result, ErrorCode::RevealNotOpen);
+
+    fx.svm.warp_to_timestamp(fx.state.bid_end);
+    let result = fx.send_as_bidder(fx.reveal(BOND, [0; 32]));
+    assert_error(result, ErrorCode::CommitmentMismatch);
+
+    // The bond already covers this bid, so nothing moves
+    let result = fx.send_as_bidder(fx.reveal(BOND, SALT));
+    assert!(result.is_ok(), "{result:#?}");
+    assert_eq!(fx.bid_account().unwrap().revealed_amount, Some(BOND));
+    let state = fx.auction_state();
+    assert_eq!(state.winner, Some(fx.bidder.pubkey()));
+    assert_eq!(state.highest_bid, BOND);
+
+    fx.svm.expire_blockhash();
+    let result = fx.send_as_bidder(fx.reveal(BOND, SALT));
+    assert_error(result, ErrorCode::AlreadyRevealed);
+
+    fx.svm.warp_to_timestamp(fx.state.reveal_end);
+    let result = fx.send_as_bidder(fx.reveal(BOND, SALT));
+    assert_error(result, ErrorCode::RevealClosed);
+}
+
+#[test]
+fn reveals_above_the_bond_top_up_the_vault() {
+    let mut fx = Fixture::new(BID);
+    fx.svm.warp_to_timestamp(fx.state.bid_end);
+    let result = fx.send_as_bidder(fx.reveal(BID, SALT));
+    assert_success(result);
+    assert_eq!(fx.svm.token_balance(&fx.bid_state.token_account), BOND);
+    assert_eq!(fx.svm.token_balance(&fx.state.vault), BID);
+    assert_eq!(fx.bid_account().unwrap().deposit, BID);
+    assert_eq!(fx.auction_state().winner, Some(fx.bidder.pubkey()));
+}
+
+#[test]
+fn settlement_sends_the_item_to_the_winner() {
+    let mut fx = Fixture::new(BID);
+    let winner = fx.bidder.pubkey();
+    // As the winner's reveal leaves it, with the whole bid in the vault
+    fx.update(
+        |auction| {
+            auction.winner = Some(winner);
+            auction.highest_bid = BID;
+        },
+        |bid| {
+            bid.deposit = BID;
+            bid.revealed_amount = Some(BID);
+        },
+    );
+    fx.svm.mint_tokens(&fx.state.vault, BID - BOND);
+    let winner_items = fx.item_account(&winner);
+    let result = fx.send(fx.settle(winner_items), &[]);
+    assert_error(result, ErrorCode::RevealNotOver);
+
+    fx.svm.warp_to_timestamp(fx.state.reveal_end);
+    let seller_items = fx.state.seller_item_account;
+    let result = fx.send(fx.settle(seller_items), &[]);
+    assert_error(result, ErrorCode::InvalidTokenAccount);
+
+    let result = fx.send(fx.settle(winner_items), &[]);
+    assert_success(result);
+    assert_eq!(fx.svm.token_balance(&fx.state.seller_token_account), BID);
+    assert_eq!(fx.svm.token_balance(&fx.state.vault), 0);
+    assert_eq!(fx.svm.token_balance(&winner_items), 1);
+    assert_eq!(fx.svm.token_balance(&fx.state.item_vault), 0);
+    assert!(fx.auction_state().settled);
+
+    fx.update(|auction| auction.settled = true, |_| {});
+    let result = fx.send(fx.settle(winner_items), &[]);
+    assert_error(result, ErrorCode::AuctionSettled);
+}
+
+#[test]
+fn bids_close_after_settlement() {
+    let mut fx = Fixture::new(BID);
+    let result = fx.send(fx.close_bid(), &[]);
+    assert_error(result, ErrorCode::AuctionNotSettled);
+
+    // An unrevealed bid forfeits its bond to the seller
+    fx.update(|auction| auction.settled = true, |_| {});
+    let result = fx.send(fx.close_bid(), &[]);
+    assert_success(result);
+    assert_eq!(fx.svm.token_balance(&fx.state.seller_token_account), BOND);
+    assert_eq!(fx.svm.token_balance(&fx.bid_state.token_account), BID);
+    assert!(fx.bid_account().is_none());
+
+    // A winner whose deposit was exactly their bid is owed nothing back
+    let winner = fx.bidder.pubkey();
+    fx.update(
+        |auction| {
+            auction.winner = Some(winner);
+            auction.highest_bid = BID;
+        },
+        |bid| {
+            bid.deposit = BID;
+            bid.revealed_amount = Some(BID);
+        },
+    );
+    let result = fx.send(fx.close_bid(), &[]);
+    assert!(result.is_ok(), "{result:#?}");
+    assert!(fx.bid_account().is_none());
+    assert_eq!(fx.auction_state().closed_bids, 1);
+}
+
+#[test]
+fn auctions_close_once_every_bid_is_closed() {
+    let mut fx = Fixture::new(BID);
+    let result = fx.send(fx.close_auction(), &[]);
+    assert_error(result, ErrorCode::AuctionNotSettled);
+
+    fx.update(|auction| auction.settled = true, |_| {});
+    let result = fx.send(fx.close_auction(), &[]);
+    assert_error(result, ErrorCode::BidsOutstanding);
+
+    // Settled and the one bid closed: both vaults are empty
+    fx.update(|auction| auction.closed_bids = 1, |_| {});
+    fx.svm
+        .transfer_tokens(&fx.state.vault, &fx.state.seller_token_account, BOND);
+    fx.svm
+        .transfer_tokens(&fx.state.item_vault, &fx.state.seller_item_account, 1);
+    let seller_before = fx.svm.get_balance(&fx.seller.pubkey());
+    let vault_rent = fx.svm.get_balance(&fx.state.vault);
+    let item_vault_rent = fx.svm.get_balance(&fx.state.item_vault);
+    let auction_rent = fx.svm.get_balance(&fx.auction);
+
+    let result = fx.send(fx.close_auction(), &[]);
+    assert_success(result);
+    assert!(fx.svm.get_account(&fx.state.vault).is_none());
+    assert!(fx.svm.get_account(&fx.state.item_vault).is_none());
+    assert!(fx.svm.get_account(&fx.auction).is_none());
+    assert_eq!(
+        fx.svm.get_balance(&fx.seller.pubkey()),
+        seller_before + vault_rent + item_vault_rent + auction_rent
+    );
+}
@@ -0,0 +1,265 @@
+use anchor_lang::prelude::*;
+
+declare_id!("FPVp3Xd8J3xe6yutdaafGJZiesJBt5ak9R5AYcRNvKqs");
+
+/// Most instructions a session can be scoped to; none means any instruction
+pub const MAX_INSTRUCTIONS: usize = 8;
+
+/// Seed of the PDA a consuming program signs with to prove it is the caller
+pub const CALLER_SEED: &[u8] = b"session_caller";
+
+#[program]
+pub mod session_keys {
+    use super::*;
+
+    /// Let `session_key` act for the owner in one program until `expires_at`.
+    /// `instructions` lists the 8-byte discriminators it may call there (empty
+    /// for any), and `max_amount` caps the total it may move across all uses.
+    pub fn create_session(
+        ctx: Context<CreateSession>,
+        session_key: Pubkey,
+        program_id: Pubkey,
+        instructions: Vec<[u8; 8]>,
+        max_amount: u64,
+        expires_at: i64,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        Session::check_params(&instructions, expires_at, clock.unix_timestamp)?;
+
+        let session = &mut ctx.accounts.session;
+        session.owner = ctx.accounts.owner.key();
+        session.session_key = session_key;
+        session.program_id = program_id;
+        session.instructions = instructions;
+        session.max_amount = max_amount;
+        session.amount_used = 0;
+        session.expires_at = expires_at;
+        session.created_at = clock.unix_timestamp;
+        session.bump = ctx.bumps.session;
+
+        emit!(SessionCreated {
+            session: session.key(),
+            owner: session.owner,
+            session_key,
+            program_id,
+            max_amount,
+            expires_at,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("Session created for program {}", program_id);
+        msg!("Up to {} tokens until {}", max_amount, expires_at);
+
+        Ok(())
+    }
+
+    /// Called by CPI from a consuming program before it acts for the owner.
+    /// The program proves who it is by signing with its `CALLER_SEED` PDA,
+    /// names the instruction it is running and the amount it is about to
+    /// move, and this books the amount against the cap.
+    pub fn use_session(ctx: Context<UseSession>, instruction: [u8; 8], amount: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let session = &mut ctx.accounts.session;
+        session.authorize(&ctx.accounts.caller.key(), instruction, amount, now)?;
+
+        emit!(SessionUsed {
+            session: session.key(),
+            program_id: session.program_id,
+            instruction,
+            amount,
+            amount_used: session.amount_used,
+            timestamp: now,
+        });
+
+        msg!("Session used: {} tokens", amount);
+        msg!("Used {} of {}", session.amount_used, session.max_amount);
+
+        Ok(())
+    }
+
+    /// End a session early and reclaim its rent; expired sessions close the same way
+    pub fn revoke_session(ctx: Context<RevokeSession>) -> Result<()> {
+        let session = &ctx.accounts.session;
+
+        emit!(SessionRevoked {
+            session: session.key(),
+            owner: session.owner,
+            session_key: session.session_key,
+            amount_used: session.amount_used,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Session revoked after {} tokens", session.amount_used);
+
+        Ok(())
+    }
+}
+
+/// `CALLER_SEED` PDA of a consuming program, the signer `use_session` expects
+pub fn caller_address(program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[CALLER_SEED], program_id).0
+}
+
+#[derive(Accounts)]
+#[instruction(session_key: Pubkey)]
+pub struct CreateSession<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Session::INIT_SPACE,
+        seeds = [b"session", owner.key().as_ref(), session_key.as_ref()],
+        bump
+    )]
+    pub session: Account<'info, Session>,
+
+    pub owner: Signer<'info>,
+
+    /// Fee and rent payer, usually a relayer
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UseSession<'info> {
+    #[account(
+        mut,
+        seeds = [b"session", session.owner.as_ref(), session_key.key().as_ref()],
+        bump = session.bump,
+        has_one = session_key
+    )]
+    pub session: Account<'info, Session>,
+
+    pub session_key: Signer<'info>,
+
+    /// The consuming program's `CALLER_SEED` PDA, signed for by that program
+    pub caller: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeSession<'info> {
+    #[account(
+        mut,
+        seeds = [b"session", owner.key().as_ref(), session.session_key.as_ref()],
+        bump = session.bump,
+        has_one = owner,
+        close = owner
+    )]
+    pub session: Account<'info, Session>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Session {
+    pub owner: Pubkey,
+    pub session_key: Pubkey,
+    /// The only program that may use the session
+    pub program_id: Pubkey,
+    /// Discriminators of the instructions it may be used for; empty for any
+    #[max_len(MAX_INSTRUCTIONS)]
+    pub instructions: Vec<[u8; 8]>,
+    /// Total the session may move over its lifetime
+    pub max_amount: u64,
+    pub amount_used: u64,
+    pub expires_at: i64,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl Session {
+    pub fn check_params(instructions: &[[u8; 8]], expires_at: i64, now: i64) -> Result<()> {
+        require!(
+            instructions.len() <= MAX_INSTRUCTIONS,
+            ErrorCode::TooManyInstructions
+        );
+        require!(expires_at > now, ErrorCode::InvalidExpiry);
+        Ok(())
+    }
+
+    /// Amount the session can still move
+    pub fn remaining(&self) -> u64 {
+        self.max_amount.saturating_sub(self.amount_used)
+    }
+
+    /// Whether `caller` (a program's `CALLER_SEED` PDA) may run
+    /// `instruction` moving `amount` at `now`; books the amount if so
+    pub fn authorize(
+        &mut self,
+        caller: &Pubkey,
+        instruction: [u8; 8],
+        amount: u64,
+        now: i64,
+    ) -> Result<()> {
+        require!(now < self.expires_at, ErrorCode::SessionExpired);
+        require_keys_eq!(
+            *caller,
+            caller_address(&self.program_id),
+            ErrorCode::WrongProgram
+        );
+        require!(
+            self.instructions.is_empty() || self.instructions.contains(&instruction),
+            ErrorCode::InstructionNotAllowed
+        );
+        require!(amount <= self.remaining(), ErrorCode::AmountExceeded);
+        self.amount_used = self
+            .amount_used
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionCreated {
+    pub session: Pubkey,
+    pub owner: Pubkey,
+    pub session_key: Pubkey,
+    pub program_id: Pubkey,
+    pub max_amount: u64,
+    pub expires_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionUsed {
+    pub session: Pubkey,
+    pub program_id: Pubkey,
+    pub instruction: [u8; 8],
+    pub amount: u64,
+    pub amount_used: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionRevoked {
+    pub session: Pubkey,
+    pub owner: Pubkey,
+    pub session_key: Pubkey,
+    pub amount_used: u64,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("A session can list at most 8 instructions")]
+    TooManyInstructions,
+    #[msg("Expiry must be in the future")]
+    InvalidExpiry,
+    #[msg("Session has expired")]
+    SessionExpired,
+    #[msg("Session is not scoped to the calling program")]
+    WrongProgram,
+    #[msg("Session is not scoped to this instruction")]
+    InstructionNotAllowed,
+    #[msg("Amount exceeds what the session has left")]
+    AmountExceeded,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
@@ -0,0 +1,344 @@
+//! Native tests for the session keys program.
+//!
+//! Scope checks are a pure method and are tested directly. Every instruction
+//! runs end to end; `use_session` is called by a stand-in consuming program,
+//! since its `caller` is a program's PDA and only signs inside a CPI.
+
+use anchor_lang::error::ErrorCode as AnchorErrorCode;
+use anchor_lang::prelude::{AccountInfo, Pubkey};
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program::invoke_signed;
+use anchor_lang::solana_program::program_error::ProgramError;
+use anchor_lang::{system_program, InstructionData, Space, ToAccountMetas};
+use session_keys::{
+    accounts, caller_address, instruction, ErrorCode, Session, CALLER_SEED, ID as PROGRAM_ID,
+    MAX_INSTRUCTIONS,
+};
+use test_harness::{
+    assert_error, assert_success, Keypair, Signer, SystemError, TestSvm, TransactionResult,
+};
+
+const CHARGE: [u8; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+const CANCEL: [u8; 8] = [8, 7, 6, 5, 4, 3, 2, 1];
+const CONSUMER_ID: Pubkey = Pubkey::new_from_array([9; 32]);
+
+fn scoped(program_id: Pubkey) -> Session {
+    Session {
+        owner: Pubkey::new_unique(),
+        session_key: Pubkey::new_unique(),
+        program_id,
+        instructions: vec![CHARGE],
+        max_amount: 100,
+        amount_used: 0,
+        expires_at: 1_000,
+        created_at: 0,
+        bump: 255,
+    }
+}
+
+#[test]
+fn sessions_need_a_future_expiry_and_a_short_scope() {
+    Session::check_params(&[CHARGE], 1_000, 999).unwrap();
+    Session::check_params(&[], 1_000, 999).unwrap();
+    assert_eq!(
+        Session::check_params(&[CHARGE], 1_000, 1_000).unwrap_err(),
+        ErrorCode::InvalidExpiry.into()
+    );
+    assert_eq!(
+        Session::check_params(&[CHARGE; MAX_INSTRUCTIONS + 1], 1_000, 0).unwrap_err(),
+        ErrorCode::TooManyInstructions.into()
+    );
+}
+
+#[test]
+fn uses_stay_within_program_instruction_amount_and_time() {
+    let program = Pubkey::new_unique();
+    let caller = caller_address(&program);
+    let mut session = scoped(program);
+
+    session.authorize(&caller, CHARGE, 60, 10).unwrap();
+    assert_eq!(session.amount_used, 60);
+    assert_eq!(session.remaining(), 40);
+
+    assert_eq!(
+        session
+            .authorize(&caller_address(&Pubkey::new_unique()), CHARGE, 1, 10)
+            .unwrap_err(),
+        ErrorCode::WrongProgram.into()
+    );
+    // The program itself is not its caller PDA
+    assert_eq!(
+        session.authorize(&program, CHARGE, 1, 10).unwrap_err(),
+        ErrorCode::WrongProgram.into()
+    );
+    assert_eq!(
+        session.authorize(&caller, CANCEL, 0, 10).unwrap_err(),
+        ErrorCode::InstructionNotAllowed.into()
+    );
+    assert_eq!(
+        session.authorize(&caller, CHARGE, 41, 10).unwrap_err(),
+        ErrorCode::AmountExceeded.into()
+    );
+    assert_eq!(
+        session.authorize(&caller, CHARGE, 1, 1_000).unwrap_err(),
+        ErrorCode::SessionExpired.into()
+    );
+
+    session.authorize(&caller, CHARGE, 40, 999).unwrap();
+    assert_eq!(session.remaining(), 0);
+    session.authorize(&caller, CHARGE, 0, 999).unwrap();
+}
+
+#[test]
+fn an_empty_scope_allows_any_instruction() {
+    let program = Pubkey::new_unique();
+    let mut session = Session {
+        instructions: vec![],
+        ..scoped(program)
+    };
+    session
+        .authorize(&caller_address(&program), CANCEL, 10, 10)
+        .unwrap();
+}
+
+/// Consuming program stand-in. Accounts: session, session key, its caller
+/// PDA, the session keys program. Spends the amount in its data as a
+/// `CHARGE`, signing as its caller PDA.
+fn consumer<'a>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'a>],
+    data: &[u8],
+) -> ProgramResult {
+    let [session, session_key, caller, ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+    let amount = u64::from_le_bytes(data.try_into().unwrap());
+    let use_session = Instruction {
+        program_id: PROGRAM_ID,
+        accounts: accounts::UseSession {
+            session: *session.key,
+            session_key: *session_key.key,
+            caller: *caller.key,
+        }
+        .to_account_metas(None),
+        data: instruction::UseSession {
+            instruction: CHARGE,
+            amount,
+        }
+        .data(),
+    };
+    let (_, bump) = Pubkey::find_program_address(&[CALLER_SEED], program_id);
+    invoke_signed(
+        &use_session,
+        &[session.clone(), session_key.clone(), caller.clone()],
+        &[&[CALLER_SEED, &[bump]]],
+    )
+}
+
+struct Fixture {
+    svm: TestSvm,
+    payer: Keypair,
+    owner: Keypair,
+    session_key: Keypair,
+    session: Pubkey,
+}
+
+impl Fixture {
+    fn new() -> Self {
+        let (mut svm, payer) = TestSvm::for_program(PROGRAM_ID, session_keys::entry);
+        svm.add_program(CONSUMER_ID, consumer);
+
+        let owner = Keypair::new();
+        let session_key = Keypair::new();
+        svm.airdrop(&owner.pubkey(), 1_000_000_000);
+
+        let (session, bump) = Pubkey::find_program_address(
+            &[
+                b"session",
+                owner.pubkey().as_ref(),
+                session_key.pubkey().as_ref(),
+            ],
+            &PROGRAM_ID,
+        );
+        let now = svm.clock().unix_timestamp;
+        let state = Session {
+            owner: owner.pubkey(),
+            session_key: session_key.pubkey(),
+            expires_at: now + 3_600,
+            bump,
+            ..scoped(CONSUMER_ID)
+        };
+        svm.set_anchor_account(session, &state, 8 + Session::INIT_SPACE);
+
+        Self {
+            svm,
+            payer,
+            owner,
+            session_key,
+            session,
+        }
+    }
+
+    fn create(&self, expires_at: i64) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CreateSession {
+                session: self.session,
+                owner: self.owner.pubkey(),
+                payer: self.payer.pubkey(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CreateSession {
+                session_key: self.session_key.pubkey(),
+                program_id: CONSUMER_ID,
+                instructions: vec![CHARGE],
+                max_amount: 100,
+                expires_at,
+            }
+            .data(),
+        }
+    }
+
+    /// The consuming program spending `amount` through the session
+    fn consume(&self, session_key: Pubkey, amount: u64) -> Instruction {
+        let mut accounts = accounts::UseSession {
+            session: self.session,
+            session_key,
+            caller: caller_address(&CONSUMER_ID),
+        }
+        .to_account_metas(None);
+        // The caller PDA signs inside the CPI, not in the transaction
+        accounts[2].is_signer = false;
+        accounts.push(AccountMeta::new_readonly(PROGRAM_ID, false));
+        Instruction {
+            program_id: CONSUMER_ID,
+            accounts,
+            data: amount.to_le_bytes().to_vec(),
+        }
+    }
+
+    fn session(&self) -> Session {
+        self.svm.get_anchor_account(&self.session).unwrap()
+    }
+
+    fn use_session(&self, session_key: Pubkey, caller: Pubkey) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::UseSession {
+                session: self.session,
+                session_key,
+                caller,
+            }
+            .to_account_metas(None),
+            data: instruction::UseSession {
+                instruction: CHARGE,
+                amount: 1,
+            }
+            .data(),
+        }
+    }
+
+    fn revoke(&self, owner: Pubkey) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::RevokeSession {
+                session: self.session,
+                owner,
+            }
+            .to_account_metas(None),
+            data: instruction::RevokeSession {}.data(),
+        }
+    }
+
+    fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TransactionResult {
+        let payer = self.payer.insecure_clone();
+        self.svm.send_instructions(&[instruction], &payer, signers)
+    }
+}
+
+#[test]
+fn owners_scope_a_session_to_one_program() {
+    let mut fx = Fixture::new();
+    fx.svm.remove_account(&fx.session);
+    let owner = fx.owner.insecure_clone();
+    let now = fx.svm.clock().unix_timestamp;
+
+    let result = fx.send(fx.create(now), &[&owner]);
+    assert_error(result, ErrorCode::InvalidExpiry);
+
+    let result = fx.send(fx.create(now + 3_600), &[&owner]);
+    assert_success(result);
+    let session = fx.session();
+    assert_eq!(
+        (session.owner, session.session_key),
+        (owner.pubkey(), fx.session_key.pubkey())
+    );
+    assert_eq!(session.program_id, CONSUMER_ID);
+    assert_eq!(session.instructions, vec![CHARGE]);
+    assert_eq!((session.max_amount, session.amount_used), (100, 0));
+    assert_eq!((session.expires_at, session.created_at), (now + 3_600, now));
+
+    fx.svm.expire_blockhash();
+    let result = fx.send(fx.create(now + 7_200), &[&owner]);
+    assert_error(result, SystemError::AccountAlreadyInUse as u32);
+}
+
+#[test]
+fn the_scoped_program_spends_through_the_session() {
+    let mut fx = Fixture::new();
+    let session_key = fx.session_key.insecure_clone();
+    let result = fx.send(fx.consume(session_key.pubkey(), 60), &[&session_key]);
+    assert_success(result);
+    assert_eq!(fx.session().amount_used, 60);
+
+    let result = fx.send(fx.consume(session_key.pubkey(), 41), &[&session_key]);
+    assert_error(result, ErrorCode::AmountExceeded);
+
+    fx.svm.advance_time(3_600);
+    let result = fx.send(fx.consume(session_key.pubkey(), 1), &[&session_key]);
+    assert_error(result, ErrorCode::SessionExpired);
+    assert_eq!(fx.session().amount_used, 60);
+}
+
+#[test]
+fn a_wallet_cannot_pose_as_the_scoped_program() {
+    let mut fx = Fixture::new();
+    let session_key = fx.session_key.insecure_clone();
+    let impostor = Keypair::new();
+    let result = fx.send(
+        fx.use_session(session_key.pubkey(), impostor.pubkey()),
+        &[&session_key, &impostor],
+    );
+    assert_error(result, ErrorCode::WrongProgram);
+}
+
+#[test]
+fn only_the_session_key_uses_the_session() {
+    let mut fx = Fixture::new();
+    let stranger = Keypair::new();
+    let impostor = Keypair::new();
+    let result = fx.send(
+        fx.use_session(stranger.pubkey(), impostor.pubkey()),
+        &[&stranger, &impostor],
+    );
+    assert_error(result, AnchorErrorCode::ConstraintSeeds);
+
+    // Nor through the scoped program
+    let result = fx.send(fx.consume(stranger.pubkey(), 1), &[&stranger]);
+    assert_error(result, AnchorErrorCode::ConstraintSeeds);
+}
+
+#[test]
+fn only_the_owner_revokes() {
+    let mut fx = Fixture::new();
+    let stranger = Keypair::new();
+    let result = fx.send(fx.revoke(stranger.pubkey()), &[&stranger]);
+    assert_error(result, AnchorErrorCode::ConstraintSeeds);
+
+    let owner = fx.owner.insecure_clone();
+    let result = fx.send(fx.revoke(owner.pubkey()), &[&owner]);
+    assert!(result.is_ok(), "{result:#?}");
+    assert!(fx.svm.get_account(&fx.session).is_none());
+}
@@ -0,0 +1,444 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use spl_token::instruction as token_instruction;
+use token_utils::token_account;
+
+declare_id!("Gu2hSu3zTHw1gp3b5eh31yz7EjyqdPSC3GmCD1ZkGmLn");
+
+/// Programs or PDAs a policy can let spend at once
+pub const MAX_SPENDERS: usize = 8;
+
+pub const DAY: i64 = 86_400;
+pub const WEEK: i64 = 7 * DAY;
+
+#[program]
+pub mod spending_limits {
+    use super::*;
+
+    /// Put `token_account` behind daily and weekly limits (0 for none). The
+    /// policy PDA becomes the account's only delegate, replacing any standing
+    /// delegation, so the listed `spenders` can only move tokens through
+    /// `spend`.
+    pub fn create_policy(
+        ctx: Context<CreatePolicy>,
+        daily_limit: u64,
+        weekly_limit: u64,
+        spenders: Vec<Pubkey>,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        Policy::check_spenders(&spenders)?;
+
+        let wallet = ctx.accounts.wallet.key();
+        let account = token_account(&ctx.accounts.token_account, ErrorCode::InvalidTokenAccount)?;
+        require_keys_eq!(account.owner, wallet, ErrorCode::InvalidTokenAccount);
+
+        let delegate_ix = token_instruction::approve(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.token_account.key(),
+            &ctx.accounts.policy.key(),
+            &wallet,
+            &[],
+            u64::MAX,
+        )?;
+
+        invoke(
+            &delegate_ix,
+            &[
+                ctx.accounts.token_account.to_account_info(),
+                ctx.accounts.policy.to_account_info(),
+                ctx.accounts.wallet.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        let policy = &mut ctx.accounts.policy;
+        policy.wallet = wallet;
+        policy.token_account = ctx.accounts.token_account.key();
+        policy.mint = account.mint;
+        policy.daily_limit = daily_limit;
+        policy.weekly_limit = weekly_limit;
+        policy.spenders = spenders;
+        policy.day = clock.unix_timestamp.div_euclid(DAY);
+        policy.day_spent = 0;
+        policy.week = clock.unix_timestamp.div_euclid(WEEK);
+        policy.week_spent = 0;
+        policy.bump = ctx.bumps.policy;
+
+        emit!(PolicyCreated {
+            policy: policy.key(),
+            wallet,
+            token_account: policy.token_account,
+            daily_limit,
+            weekly_limit,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("Spending policy created");
+        msg!(
+            "Daily limit: {}, weekly limit: {}",
+            daily_limit,
+            weekly_limit
+        );
+        msg!("Token delegation approved");
+
+        Ok(())
+    }
+
+    /// Change the limits or the spenders; amounts already spent this day and
+    /// week still count
+    pub fn update_policy(
+        ctx: Context<UpdatePolicy>,
+        daily_limit: u64,
+        weekly_limit: u64,
+        spenders: Vec<Pubkey>,
+    ) -> Result<()> {
+        Policy::check_spenders(&spenders)?;
+
+        let policy = &mut ctx.accounts.policy;
+        policy.daily_limit = daily_limit;
+        policy.weekly_limit = weekly_limit;
+        policy.spenders = spenders;
+
+        emit!(PolicyUpdated {
+            policy: policy.key(),
+            daily_limit,
+            weekly_limit,
+            spender_count: policy.spenders.len() as u8,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!(
+            "Daily limit: {}, weekly limit: {}",
+            daily_limit,
+            weekly_limit
+        );
+
+        Ok(())
+    }
+
+    /// Move `amount` to `destination` for a listed spender, within the
+    /// limits. Spenders that are PDAs, such as a subscription, call this by
+    /// CPI and sign with their seeds.
+    pub fn spend(ctx: Context<Spend>, amount: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let policy = &mut ctx.accounts.policy;
+        policy.check_spender(&ctx.accounts.spender.key())?;
+        policy.record_spend(amount, now)?;
+
+        let token_account_key = policy.token_account;
+        let bump = policy.bump;
+        let seeds = &[b"spending_policy", token_account_key.as_ref(), &[bump]];
+        let signer_seeds = &[&seeds[..]];
+
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.token_account.key(),
+            &ctx.accounts.destination.key(),
+            &ctx.accounts.policy.key(),
+            &[],
+            amount,
+        )?;
+
+        invoke_signed(
+            &transfer_ix,
+            &[
+                ctx.accounts.token_account.to_account_info(),
+                ctx.accounts.destination.to_account_info(),
+                ctx.accounts.policy.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+            signer_seeds,
+        )?;
+
+        let policy = &ctx.accounts.policy;
+        emit!(SpendRecorded {
+            policy: policy.key(),
+            spender: ctx.accounts.spender.key(),
+            destination: ctx.accounts.destination.key(),
+            amount,
+            day_spent: policy.day_spent,
+            week_spent: policy.week_spent,
+            timestamp: now,
+        });
+
+        msg!("Spent {} tokens", amount);
+        msg!(
+            "Today: {}, this week: {}",
+            policy.day_spent,
+            policy.week_spent
+        );
+
+        Ok(())
+    }
+
+    /// Drop the limits: revoke the policy's delegation and close it
+    pub fn close_policy(ctx: Context<ClosePolicy>) -> Result<()> {
+        let revoke_ix = token_instruction::revoke(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.token_account.key(),
+            &ctx.accounts.wallet.key(),
+            &[],
+        )?;
+        invoke(
+            &revoke_ix,
+            &[
+                ctx.accounts.token_account.to_account_info(),
+                ctx.accounts.wallet.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        emit!(PolicyClosed {
+            policy: ctx.accounts.policy.key(),
+            wallet: ctx.accounts.wallet.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Spending policy closed");
+        msg!("Token delegation revoked");
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct CreatePolicy<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Policy::INIT_SPACE,
+        seeds = [b"spending_policy", token_account.key().as_ref()],
+        bump
+    )]
+    pub policy: Account<'info, Policy>,
+
+    pub wallet: Signer<'info>,
+
+    /// CHECK: Wallet's token account, checked in the handler and delegated to the policy PDA
+    #[account(mut)]
+    pub token_account: UncheckedAccount<'info>,
+
+    /// Fee and rent payer, usually a relayer
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePolicy<'info> {
+    #[account(
+        mut,
+        seeds = [b"spending_policy", policy.token_account.as_ref()],
+        bump = policy.bump,
+        has_one = wallet
+    )]
+    pub policy: Account<'info, Policy>,
+
+    pub wallet: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Spend<'info> {
+    #[account(
+        mut,
+        seeds = [b"spending_policy", token_account.key().as_ref()],
+        bump = policy.bump,
+        has_one = token_account
+    )]
+    pub policy: Account<'info, Policy>,
+
+    /// A listed spender, often a PDA signing by CPI
+    pub spender: Signer<'info>,
+
+    /// CHECK: The delegated token account
+    #[account(mut)]
+    pub token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Any token account of the same mint, checked by the token program
+    #[account(mut)]
+    pub destination: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClosePolicy<'info> {
+    #[account(
+        mut,
+        seeds = [b"spending_policy", token_account.key().as_ref()],
+        bump = policy.bump,
+        has_one = wallet,
+        has_one = token_account,
+        close = wallet
+    )]
+    pub policy: Account<'info, Policy>,
+
+    #[account(mut)]
+    pub wallet: Signer<'info>,
+
+    /// CHECK: The delegated token account
+    #[account(mut)]
+    pub token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Policy {
+    pub wallet: Pubkey,
+    /// Delegated to this PDA; every spend comes out of it
+    pub token_account: Pubkey,
+    pub mint: Pubkey,
+    /// Most that may leave per UTC day; 0 for no daily limit
+    pub daily_limit: u64,
+    /// Most that may leave per 7-day window counted from the Unix epoch; 0
+    /// for no weekly limit
+    pub weekly_limit: u64,
+    /// Signers allowed to call `spend`
+    #[max_len(MAX_SPENDERS)]
+    pub spenders: Vec<Pubkey>,
+    /// Day number (`unix_timestamp / DAY`) that `day_spent` belongs to
+    pub day: i64,
+    pub day_spent: u64,
+    /// Week number (`unix_timestamp / WEEK`) that `week_spent` belongs to
+    pub week: i64,
+    pub week_spent: u64,
+    pub bump: u8,
+}
+
+impl Policy {
+    pub fn check_spenders(spenders: &[Pubkey]) -> Result<()> {
+        require!(spenders.len() <= MAX_SPENDERS, ErrorCode::TooManySpenders);
+        Ok(())
+    }
+
+    pub fn check_spender(&self, spender: &Pubkey) -> Result<()> {
+        require!(
+            self.spenders.contains(spender),
+            ErrorCode::SpenderNotAllowed
+        );
+        Ok(())
+    }
+
+    /// `(spent today, spent this week)` as of `now`, after any window rolled over
+    pub fn spent_at(&self, now: i64) -> (u64, u64) {
+        let day_spent = if now.div_euclid(DAY) == self.day {
+            self.day_spent
+        } else {
+            0
+        };
+        let week_spent = if now.div_euclid(WEEK) == self.week {
+            self.week_spent
+        } else {
+            0
+        };
+        (day_spent, week_spent)
+    }
+
+    /// Most a spend at `now` could move without breaking either limit
+    pub fn available_at(&self, now: i64) -> u64 {
+        let (day_spent, week_spent) = self.spent_at(now);
+        let daily = match self.daily_limit {
+            0 => u64::MAX,
+            limit => limit.saturating_sub(day_spent),
+        };
+        let weekly = match self.weekly_limit {
+            0 => u64::MAX,
+            limit => limit.saturating_sub(week_spent),
+        };
+        daily.min(weekly)
+    }
+
+    /// Book `amount` spent at `now`, rolling the windows first
+    pub fn record_spend(&mut self, amount: u64, now: i64) -> Result<()> {
+        let (day_spent, week_spent) = self.spent_at(now);
+        require!(
+            self.daily_limit == 0 || amount <= self.daily_limit.saturating_sub(day_spent),
+            ErrorCode::DailyLimitExceeded
+        );
+        require!(
+            self.weekly_limit == 0 || amount <= self.weekly_limit.saturating_sub(week_spent),
+            ErrorCode::WeeklyLimitExceeded
+        );
+        self.day = now.div_euclid(DAY);
+        self.day_spent = day_spent
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.week = now.div_euclid(WEEK);
+        self.week_spent = week_spent
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyCreated {
+    pub policy: Pubkey,
+    pub wallet: Pubkey,
+    pub token_account: Pubkey,
+    pub daily_limit: u64,
+    pub weekly_limit: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyUpdated {
+    pub policy: Pubkey,
+    pub daily_limit: u64,
+    pub weekly_limit: u64,
+    pub spender_count: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpendRecorded {
+    pub policy: Pubkey,
+    pub spender: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
+    pub day_spent: u64,
+    pub week_spent: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyClosed {
+    pub policy: Pubkey,
+    pub wallet: Pubkey,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Amount must be greater than zero")]
+    InvalidAmount,
+    #[msg("A policy can list at most 8 spenders")]
+    TooManySpenders,
+    #[msg("Signer is not one of the policy's spenders")]
+    SpenderNotAllowed,
+    #[msg("Spend would exceed the daily limit")]
+    DailyLimitExceeded,
+    #[msg("Spend would exceed the weekly limit")]
+    WeeklyLimitExceeded,
+    #[msg("Invalid token account - wrong program, owner or mint")]
+    InvalidTokenAccount,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
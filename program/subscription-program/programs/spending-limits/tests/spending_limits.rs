@@ -0,0 +1,323 @@
+//! Native tests for the spending limits program.
+//!
+//! The day and week windows are pure methods and are tested directly. Every
+//! instruction runs end to end against the wallet's delegated token account.
+
+use anchor_lang::error::ErrorCode as AnchorErrorCode;
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, Space, ToAccountMetas};
+use spending_limits::{
+    accounts, instruction, ErrorCode, Policy, DAY, ID as PROGRAM_ID, MAX_SPENDERS, WEEK,
+};
+use test_harness::{assert_error, assert_success, Keypair, Signer, TestSvm, TransactionResult};
+
+const DAILY: u64 = 50_000_000;
+const WEEKLY: u64 = 200_000_000;
+
+fn limits(now: i64) -> Policy {
+    Policy {
+        wallet: Pubkey::new_unique(),
+        token_account: Pubkey::new_unique(),
+        mint: Pubkey::new_unique(),
+        daily_limit: DAILY,
+        weekly_limit: WEEKLY,
+        spenders: vec![],
+        day: now.div_euclid(DAY),
+        day_spent: 0,
+        week: now.div_euclid(WEEK),
+        week_spent: 0,
+        bump: 255,
+    }
+}
+
+#[test]
+fn policies_list_a_bounded_number_of_spenders() {
+    Policy::check_spenders(&[Pubkey::new_unique(); MAX_SPENDERS]).unwrap();
+    assert_eq!(
+        Policy::check_spenders(&[Pubkey::new_unique(); MAX_SPENDERS + 1]).unwrap_err(),
+        ErrorCode::TooManySpenders.into()
+    );
+}
+
+#[test]
+fn spends_count_against_the_day_and_the_week() {
+    // Windows count from the Unix epoch, so this starts a fresh week
+    let week_start = 2_000 * WEEK;
+    let mut policy = limits(week_start);
+
+    policy.record_spend(30_000_000, week_start).unwrap();
+    assert_eq!(policy.available_at(week_start), 20_000_000);
+    assert_eq!(
+        policy.record_spend(20_000_001, week_start).unwrap_err(),
+        ErrorCode::DailyLimitExceeded.into()
+    );
+    policy.record_spend(20_000_000, week_start + 3_600).unwrap();
+    assert_eq!(policy.available_at(week_start + 3_600), 0);
+
+    // The day rolls over, the week does not
+    for day in 1..4 {
+        policy.record_spend(DAILY, week_start + day * DAY).unwrap();
+    }
+    assert_eq!(policy.spent_at(week_start + 4 * DAY), (0, WEEKLY));
+    assert_eq!(policy.available_at(week_start + 4 * DAY), 0);
+    assert_eq!(
+        policy.record_spend(1, week_start + 4 * DAY).unwrap_err(),
+        ErrorCode::WeeklyLimitExceeded.into()
+    );
+
+    assert_eq!(policy.available_at(week_start + WEEK), DAILY);
+    policy.record_spend(DAILY, week_start + WEEK).unwrap();
+    assert_eq!(policy.spent_at(week_start + WEEK), (DAILY, DAILY));
+}
+
+#[test]
+fn a_zero_limit_is_no_limit() {
+    let mut policy = Policy {
+        daily_limit: 0,
+        ..limits(0)
+    };
+    assert_eq!(policy.available_at(0), WEEKLY);
+    policy.record_spend(WEEKLY, 0).unwrap();
+
+    let mut policy = Policy {
+        daily_limit: 0,
+        weekly_limit: 0,
+        ..limits(0)
+    };
+    assert_eq!(policy.available_at(0), u64::MAX);
+    policy.record_spend(u64::MAX, 0).unwrap();
+    assert_eq!(
+        policy.record_spend(1, 1).unwrap_err(),
+        ErrorCode::ArithmeticOverflow.into()
+    );
+}
+
+struct Fixture {
+    svm: TestSvm,
+    payer: Keypair,
+    wallet: Keypair,
+    spender: Keypair,
+    token_account: Pubkey,
+    destination: Pubkey,
+    policy: Pubkey,
+}
+
+impl Fixture {
+    fn new() -> Self {
+        let (mut svm, payer) = TestSvm::for_program(PROGRAM_ID, spending_limits::entry);
+
+        let wallet = Keypair::new();
+        let spender = Keypair::new();
+        svm.airdrop(&wallet.pubkey(), 1_000_000_000);
+
+        let mint = svm.create_mint(&payer.pubkey(), 6);
+        let token_account =
+            svm.create_associated_token_account(&wallet.pubkey(), &mint, 500_000_000);
+        let destination = svm.create_token_account(&Pubkey::new_unique(), &mint, 0);
+
+        let (policy, bump) = Pubkey::find_program_address(
+            &[b"spending_policy", token_account.as_ref()],
+            &PROGRAM_ID,
+        );
+        svm.approve(&token_account, &policy, u64::MAX);
+        let state = Policy {
+            wallet: wallet.pubkey(),
+            token_account,
+            mint,
+            spenders: vec![spender.pubkey()],
+            bump,
+            ..limits(svm.clock().unix_timestamp)
+        };
+        svm.set_anchor_account(policy, &state, 8 + Policy::INIT_SPACE);
+
+        Self {
+            svm,
+            payer,
+            wallet,
+            spender,
+            token_account,
+            destination,
+            policy,
+        }
+    }
+
+    fn create(&self, spenders: Vec<Pubkey>) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CreatePolicy {
+                policy: self.policy,
+                wallet: self.wallet.pubkey(),
+                token_account: self.token_account,
+                payer: self.payer.pubkey(),
+                token_program: spl_token::ID,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CreatePolicy {
+                daily_limit: DAILY,
+                weekly_limit: WEEKLY,
+                spenders,
+            }
+            .data(),
+        }
+    }
+
+    fn spend(&self, spender: Pubkey, amount: u64) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::Spend {
+                policy: self.policy,
+                spender,
+                token_account: self.token_account,
+                destination: self.destination,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::Spend { amount }.data(),
+        }
+    }
+
+    fn update(&self, wallet: Pubkey, daily_limit: u64, spenders: Vec<Pubkey>) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::UpdatePolicy {
+                policy: self.policy,
+                wallet,
+            }
+            .to_account_metas(None),
+            data: instruction::UpdatePolicy {
+                daily_limit,
+                weekly_limit: WEEKLY,
+                spenders,
+            }
+            .data(),
+        }
+    }
+
+    fn close(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::ClosePolicy {
+                policy: self.policy,
+                wallet: self.wallet.pubkey(),
+                token_account: self.token_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::ClosePolicy {}.data(),
+        }
+    }
+
+    fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TransactionResult {
+        let payer = self.payer.insecure_clone();
+        self.svm.send_instructions(&[instruction], &payer, signers)
+    }
+
+    fn send_as_wallet(&mut self, instruction: Instruction) -> TransactionResult {
+        let wallet = self.wallet.insecure_clone();
+        self.send(instruction, &[&wallet])
+    }
+
+    fn send_as_spender(&mut self, instruction: Instruction) -> TransactionResult {
+        let spender = self.spender.insecure_clone();
+        self.send(instruction, &[&spender])
+    }
+
+    fn policy(&self) -> Policy {
+        self.svm.get_anchor_account(&self.policy).unwrap()
+    }
+
+    fn delegate(&self) -> Option<Pubkey> {
+        let account = self.svm.get_token_account(&self.token_account).unwrap();
+        account.delegate.into()
+    }
+}
+
+#[test]
+fn creating_a_policy_delegates_the_token_account() {
+    let mut fx = Fixture::new();
+    fx.svm.remove_account(&fx.policy);
+    fx.svm.revoke(&fx.token_account);
+
+    let result = fx.send_as_wallet(fx.create(vec![Pubkey::new_unique(); MAX_SPENDERS + 1]));
+    assert_error(result, ErrorCode::TooManySpenders);
+
+    let spenders = vec![fx.spender.pubkey()];
+    let result = fx.send_as_wallet(fx.create(spenders.clone()));
+    assert_success(result);
+    assert_eq!(fx.delegate(), Some(fx.policy));
+    let now = fx.svm.clock().unix_timestamp;
+    let policy = fx.policy();
+    assert_eq!(
+        (policy.wallet, policy.token_account),
+        (fx.wallet.pubkey(), fx.token_account)
+    );
+    assert_eq!((policy.daily_limit, policy.weekly_limit), (DAILY, WEEKLY));
+    assert_eq!(policy.spenders, spenders);
+    assert_eq!(
+        (policy.day, policy.week),
+        (now.div_euclid(DAY), now.div_euclid(WEEK))
+    );
+    assert_eq!((policy.day_spent, policy.week_spent), (0, 0));
+}
+
+#[test]
+fn only_listed_spenders_spend_within_the_limits() {
+    let mut fx = Fixture::new();
+    let stranger = Keypair::new();
+    let result = fx.send(fx.spend(stranger.pubkey(), 1), &[&stranger]);
+    assert_error(result, ErrorCode::SpenderNotAllowed);
+
+    let result = fx.send_as_spender(fx.spend(fx.spender.pubkey(), 0));
+    assert_error(result, ErrorCode::InvalidAmount);
+    let result = fx.send_as_spender(fx.spend(fx.spender.pubkey(), DAILY + 1));
+    assert_error(result, ErrorCode::DailyLimitExceeded);
+
+    let result = fx.send_as_spender(fx.spend(fx.spender.pubkey(), DAILY));
+    assert_success(result);
+    assert_eq!(fx.svm.token_balance(&fx.destination), DAILY);
+    assert_eq!(fx.svm.token_balance(&fx.token_account), 500_000_000 - DAILY);
+    let policy = fx.policy();
+    assert_eq!((policy.day_spent, policy.week_spent), (DAILY, DAILY));
+
+    fx.svm.expire_blockhash();
+    let result = fx.send_as_spender(fx.spend(fx.spender.pubkey(), 1));
+    assert_error(result, ErrorCode::DailyLimitExceeded);
+}
+
+#[test]
+fn only_the_wallet_changes_the_policy() {
+    let mut fx = Fixture::new();
+    let stranger = Keypair::new();
+    let result = fx.send(fx.update(stranger.pubkey(), 0, vec![]), &[&stranger]);
+    assert_error(result, AnchorErrorCode::ConstraintHasOne);
+
+    let result = fx.send_as_wallet(fx.update(
+        fx.wallet.pubkey(),
+        DAILY,
+        vec![Pubkey::new_unique(); MAX_SPENDERS + 1],
+    ));
+    assert_error(result, ErrorCode::TooManySpenders);
+
+    let result = fx.send_as_wallet(fx.update(fx.wallet.pubkey(), DAILY / 2, vec![]));
+    assert!(result.is_ok(), "{result:#?}");
+    let policy = fx.policy();
+    assert_eq!(policy.daily_limit, DAILY / 2);
+    assert!(policy.spenders.is_empty());
+
+    let result = fx.send_as_spender(fx.spend(fx.spender.pubkey(), 1));
+    assert_error(result, ErrorCode::SpenderNotAllowed);
+}
+
+#[test]
+fn closing_revokes_and_refunds_the_wallet() {
+    let mut fx = Fixture::new();
+    let rent = fx.svm.get_balance(&fx.policy);
+    let before = fx.svm.get_balance(&fx.wallet.pubkey());
+    let result = fx.send_as_wallet(fx.close());
+    assert_success(result);
+    assert_eq!(fx.delegate(), None);
+    assert!(fx.svm.get_account(&fx.policy).is_none());
+    assert_eq!(fx.svm.get_balance(&fx.wallet.pubkey()), before + rent);
+}
@@ -0,0 +1,363 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::invoke;
+use spl_token::instruction as token_instruction;
+use token_utils::token_account;
+
+declare_id!("FzJj8HmH9hDgZnkG87fBsA2Sgi4BZVZZeuhXMJyByd8G");
+
+/// Fixed payees per split, not counting the referrer
+pub const MAX_PAYEES: usize = 5;
+
+/// 100% in basis points
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+#[program]
+pub mod split_checkout {
+    use super::*;
+
+    /// Define how payments are split. `payees` shares must add up to 100%;
+    /// `referral_bps` is taken off the top when a checkout names a referrer.
+    /// Pass each payee's token account as a remaining account, in order.
+    pub fn create_split<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CreateSplit<'info>>,
+        split_id: u64,
+        payees: Vec<Payee>,
+        referral_bps: u16,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        SplitConfig::check_payees(&payees, referral_bps)?;
+        check_payee_accounts(ctx.remaining_accounts, &payees, &ctx.accounts.mint.key())?;
+
+        let split = &mut ctx.accounts.split;
+        split.owner = ctx.accounts.owner.key();
+        split.mint = ctx.accounts.mint.key();
+        split.split_id = split_id;
+        split.payees = payees;
+        split.referral_bps = referral_bps;
+        split.total_processed = 0;
+        split.checkout_count = 0;
+        split.created_at = clock.unix_timestamp;
+        split.bump = ctx.bumps.split;
+
+        emit!(SplitCreated {
+            split: split.key(),
+            owner: split.owner,
+            payee_count: split.payees.len() as u8,
+            referral_bps,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("Split created with {} payees", split.payees.len());
+        msg!("Referral share: {} bps", referral_bps);
+
+        Ok(())
+    }
+
+    /// Replace the payees and referral share; applies to the next checkout
+    pub fn update_split<'info>(
+        ctx: Context<'_, '_, 'info, 'info, UpdateSplit<'info>>,
+        payees: Vec<Payee>,
+        referral_bps: u16,
+    ) -> Result<()> {
+        SplitConfig::check_payees(&payees, referral_bps)?;
+        check_payee_accounts(ctx.remaining_accounts, &payees, &ctx.accounts.split.mint)?;
+
+        let split = &mut ctx.accounts.split;
+        split.payees = payees;
+        split.referral_bps = referral_bps;
+
+        emit!(SplitUpdated {
+            split: split.key(),
+            payee_count: split.payees.len() as u8,
+            referral_bps,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Split updated: {} payees", split.payees.len());
+
+        Ok(())
+    }
+
+    /// Pay `amount` split across every payee in one instruction, so either
+    /// all of them are paid or none is. Remaining accounts are the payee token
+    /// accounts in the split's order.
+    pub fn checkout<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Checkout<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let split = &ctx.accounts.split;
+        require!(
+            ctx.remaining_accounts.len() == split.payees.len(),
+            ErrorCode::PayeeMismatch
+        );
+        for (payee, account) in split.payees.iter().zip(ctx.remaining_accounts) {
+            require_keys_eq!(account.key(), payee.token_account, ErrorCode::PayeeMismatch);
+        }
+
+        let referrer = ctx.accounts.referrer_token_account.as_ref();
+        if let Some(referrer) = referrer {
+            let referrer_account = token_account(referrer, ErrorCode::InvalidTokenAccount)?;
+            require_keys_neq!(
+                referrer_account.owner,
+                ctx.accounts.buyer.key(),
+                ErrorCode::SelfReferral
+            );
+        }
+
+        let (referral, shares) = split.shares(amount, referrer.is_some())?;
+        let mut payouts: Vec<(AccountInfo<'info>, u64)> =
+            ctx.remaining_accounts.iter().cloned().zip(shares).collect();
+        if let Some(referrer) = referrer {
+            payouts.push((referrer.to_account_info(), referral));
+        }
+        for (destination, share) in payouts {
+            if share == 0 {
+                continue;
+            }
+            let transfer_ix = token_instruction::transfer(
+                &ctx.accounts.token_program.key(),
+                &ctx.accounts.buyer_token_account.key(),
+                &destination.key(),
+                &ctx.accounts.buyer.key(),
+                &[],
+                share,
+            )?;
+            invoke(
+                &transfer_ix,
+                &[
+                    ctx.accounts.buyer_token_account.to_account_info(),
+                    destination,
+                    ctx.accounts.buyer.to_account_info(),
+                    ctx.accounts.token_program.to_account_info(),
+                ],
+            )?;
+        }
+
+        let split = &mut ctx.accounts.split;
+        split.record_checkout(amount)?;
+
+        emit!(CheckoutCompleted {
+            split: split.key(),
+            buyer: ctx.accounts.buyer.key(),
+            amount,
+            referral,
+            timestamp: now,
+        });
+
+        msg!("Checkout: {} tokens", amount);
+        msg!("Referral paid: {} tokens", referral);
+
+        Ok(())
+    }
+}
+
+/// Every payee's token account must be passed, in order, and hold `mint`
+fn check_payee_accounts(accounts: &[AccountInfo], payees: &[Payee], mint: &Pubkey) -> Result<()> {
+    require!(accounts.len() == payees.len(), ErrorCode::PayeeMismatch);
+    for (payee, account) in payees.iter().zip(accounts) {
+        require_keys_eq!(account.key(), payee.token_account, ErrorCode::PayeeMismatch);
+        let decoded = token_account(account, ErrorCode::InvalidTokenAccount)?;
+        require_keys_eq!(decoded.mint, *mint, ErrorCode::InvalidTokenAccount);
+    }
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(split_id: u64)]
+pub struct CreateSplit<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + SplitConfig::INIT_SPACE,
+        seeds = [b"split", owner.key().as_ref(), split_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub split: Account<'info, SplitConfig>,
+
+    pub owner: Signer<'info>,
+
+    /// CHECK: Token mint (USDC)
+    pub mint: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateSplit<'info> {
+    #[account(
+        mut,
+        seeds = [b"split", owner.key().as_ref(), split.split_id.to_le_bytes().as_ref()],
+        bump = split.bump,
+        has_one = owner
+    )]
+    pub split: Account<'info, SplitConfig>,
+
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Checkout<'info> {
+    #[account(
+        mut,
+        seeds = [b"split", split.owner.as_ref(), split.split_id.to_le_bytes().as_ref()],
+        bump = split.bump,
+    )]
+    pub split: Account<'info, SplitConfig>,
+
+    pub buyer: Signer<'info>,
+
+    /// CHECK: Any token account the buyer owns, debited by the token program
+    #[account(mut)]
+    pub buyer_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Referrer's token account, checked in the handler; omit for no referral
+    #[account(mut)]
+    pub referrer_token_account: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub struct Payee {
+    pub token_account: Pubkey,
+    pub bps: u16,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct SplitConfig {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub split_id: u64,
+    /// Shares of what is left after the referral; they add up to 100%
+    #[max_len(MAX_PAYEES)]
+    pub payees: Vec<Payee>,
+    pub referral_bps: u16,
+    pub total_processed: u64,
+    pub checkout_count: u64,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl SplitConfig {
+    pub fn check_payees(payees: &[Payee], referral_bps: u16) -> Result<()> {
+        require!(
+            !payees.is_empty() && payees.len() <= MAX_PAYEES,
+            ErrorCode::InvalidPayeeCount
+        );
+        require!(
+            u64::from(referral_bps) < BPS_DENOMINATOR,
+            ErrorCode::InvalidShares
+        );
+        let mut total = 0u64;
+        for (index, payee) in payees.iter().enumerate() {
+            require!(payee.bps > 0, ErrorCode::InvalidShares);
+            require!(
+                payees[..index]
+                    .iter()
+                    .all(|other| other.token_account != payee.token_account),
+                ErrorCode::DuplicatePayee
+            );
+            total += u64::from(payee.bps);
+        }
+        require!(total == BPS_DENOMINATOR, ErrorCode::InvalidShares);
+        Ok(())
+    }
+
+    /// `(referral, payee shares)` for a checkout of `amount`. Rounding dust
+    /// goes to the first payee so the parts always add up to `amount`.
+    pub fn shares(&self, amount: u64, with_referrer: bool) -> Result<(u64, Vec<u64>)> {
+        let referral = if with_referrer {
+            bps_of(amount, self.referral_bps)?
+        } else {
+            0
+        };
+        let rest = amount - referral;
+
+        let mut shares = self
+            .payees
+            .iter()
+            .map(|payee| bps_of(rest, payee.bps))
+            .collect::<Result<Vec<u64>>>()?;
+        let dust = rest - shares.iter().sum::<u64>();
+        if let Some(first) = shares.first_mut() {
+            *first += dust;
+        }
+        Ok((referral, shares))
+    }
+
+    pub fn record_checkout(&mut self, amount: u64) -> Result<()> {
+        self.total_processed = self
+            .total_processed
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.checkout_count = self
+            .checkout_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+}
+
+/// `amount * bps / 10_000`, rounded down
+fn bps_of(amount: u64, bps: u16) -> Result<u64> {
+    let share = u128::from(amount) * u128::from(bps) / u128::from(BPS_DENOMINATOR);
+    u64::try_from(share).map_err(|_| error!(ErrorCode::ArithmeticOverflow))
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SplitCreated {
+    pub split: Pubkey,
+    pub owner: Pubkey,
+    pub payee_count: u8,
+    pub referral_bps: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SplitUpdated {
+    pub split: Pubkey,
+    pub payee_count: u8,
+    pub referral_bps: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckoutCompleted {
+    pub split: Pubkey,
+    pub buyer: Pubkey,
+    pub amount: u64,
+    pub referral: u64,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Amount must be greater than zero")]
+    InvalidAmount,
+    #[msg("A split needs between 1 and 5 payees")]
+    InvalidPayeeCount,
+    #[msg("Payee shares must be non-zero and add up to 10000 bps; referral must be below 10000")]
+    InvalidShares,
+    #[msg("The same token account appears twice")]
+    DuplicatePayee,
+    #[msg("Payee token accounts do not match the split")]
+    PayeeMismatch,
+    #[msg("Invalid token account - wrong program, owner or mint")]
+    InvalidTokenAccount,
+    #[msg("Buyer cannot refer their own purchase")]
+    SelfReferral,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
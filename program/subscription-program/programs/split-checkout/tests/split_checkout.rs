@@ -0,0 +1,353 @@
+//! Native tests for the split checkout program.
+//!
+//! Share validation and the split arithmetic are pure methods on
+//! `SplitConfig` and are tested directly. Instruction tests run every
+//! instruction end to end and check what each payee received.
+
+use anchor_lang::error::ErrorCode as AnchorErrorCode;
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::{system_program, InstructionData, Space, ToAccountMetas};
+use split_checkout::{
+    accounts, instruction, ErrorCode, Payee, SplitConfig, ID as PROGRAM_ID, MAX_PAYEES,
+};
+use test_harness::{assert_error, assert_success, Keypair, Signer, TestSvm, TransactionResult};
+
+const PRICE: u64 = 9_990_000;
+
+fn payee(bps: u16) -> Payee {
+    Payee {
+        token_account: Pubkey::new_unique(),
+        bps,
+    }
+}
+
+fn split(payees: Vec<Payee>, referral_bps: u16) -> SplitConfig {
+    SplitConfig {
+        owner: Pubkey::new_unique(),
+        mint: Pubkey::new_unique(),
+        split_id: 0,
+        payees,
+        referral_bps,
+        total_processed: 0,
+        checkout_count: 0,
+        created_at: 0,
+        bump: 255,
+    }
+}
+
+#[test]
+fn shares_add_up_to_the_payment() {
+    // Platform 10%, creator 85%, collaborator 5%, referrer 2.5% off the top
+    let config = split(vec![payee(1_000), payee(8_500), payee(500)], 250);
+
+    let (referral, shares) = config.shares(PRICE, true).unwrap();
+    assert_eq!(referral, 249_750);
+    assert_eq!(shares, vec![974_026, 8_279_212, 487_012]);
+    assert_eq!(referral + shares.iter().sum::<u64>(), PRICE);
+
+    // Without a referrer the whole amount is split; dust goes to the first payee
+    let (referral, shares) = config.shares(1_001, false).unwrap();
+    assert_eq!(referral, 0);
+    assert_eq!(shares, vec![101, 850, 50]);
+
+    let (_, shares) = config.shares(u64::MAX, true).unwrap();
+    assert!(shares.iter().all(|share| *share > 0));
+}
+
+#[test]
+fn payee_shares_must_cover_exactly_100_percent() {
+    SplitConfig::check_payees(&[payee(10_000)], 0).unwrap();
+    assert_eq!(
+        SplitConfig::check_payees(&[payee(5_000), payee(4_999)], 0).unwrap_err(),
+        ErrorCode::InvalidShares.into()
+    );
+    assert_eq!(
+        SplitConfig::check_payees(&[payee(10_000), payee(0)], 0).unwrap_err(),
+        ErrorCode::InvalidShares.into()
+    );
+    assert_eq!(
+        SplitConfig::check_payees(&[payee(10_000)], 10_000).unwrap_err(),
+        ErrorCode::InvalidShares.into()
+    );
+    assert_eq!(
+        SplitConfig::check_payees(&[], 0).unwrap_err(),
+        ErrorCode::InvalidPayeeCount.into()
+    );
+    assert_eq!(
+        SplitConfig::check_payees(&vec![payee(1_000); MAX_PAYEES + 1], 0).unwrap_err(),
+        ErrorCode::InvalidPayeeCount.into()
+    );
+
+    let twice = payee(5_000);
+    assert_eq!(
+        SplitConfig::check_payees(&[twice, twice], 0).unwrap_err(),
+        ErrorCode::DuplicatePayee.into()
+    );
+}
+
+struct Fixture {
+    svm: TestSvm,
+    payer: Keypair,
+    owner: Keypair,
+    buyer: Keypair,
+    split: Pubkey,
+    config: SplitConfig,
+    buyer_token_account: Pubkey,
+}
+
+impl Fixture {
+    /// A platform/creator split with a 2.5% referral share
+    fn new() -> Self {
+        let (mut svm, payer) = TestSvm::for_program(PROGRAM_ID, split_checkout::entry);
+
+        let owner = Keypair::new();
+        let buyer = Keypair::new();
+
+        let mint = svm.create_mint(&Pubkey::new_unique(), 6);
+        let (address, bump) = Pubkey::find_program_address(
+            &[
+                b"split",
+                owner.pubkey().as_ref(),
+                0u64.to_le_bytes().as_ref(),
+            ],
+            &PROGRAM_ID,
+        );
+        let payees = [1_000, 9_000]
+            .into_iter()
+            .map(|bps| Payee {
+                token_account: svm.create_token_account(&Pubkey::new_unique(), &mint, 0),
+                bps,
+            })
+            .collect();
+        let config = SplitConfig {
+            owner: owner.pubkey(),
+            mint,
+            bump,
+            ..split(payees, 250)
+        };
+        svm.set_anchor_account(address, &config, 8 + SplitConfig::INIT_SPACE);
+        let buyer_token_account =
+            svm.create_associated_token_account(&buyer.pubkey(), &mint, 10 * PRICE);
+
+        Self {
+            svm,
+            payer,
+            owner,
+            buyer,
+            split: address,
+            config,
+            buyer_token_account,
+        }
+    }
+
+    fn payee_metas(payees: &[Payee]) -> Vec<AccountMeta> {
+        payees
+            .iter()
+            .map(|payee| AccountMeta::new(payee.token_account, false))
+            .collect()
+    }
+
+    fn create(&self, payees: Vec<Payee>) -> Instruction {
+        let mut metas = accounts::CreateSplit {
+            split: self.split,
+            owner: self.owner.pubkey(),
+            mint: self.config.mint,
+            payer: self.payer.pubkey(),
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None);
+        metas.extend(Self::payee_metas(&payees));
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: metas,
+            data: instruction::CreateSplit {
+                split_id: 0,
+                payees,
+                referral_bps: 250,
+            }
+            .data(),
+        }
+    }
+
+    fn balances(&self) -> Vec<u64> {
+        self.config
+            .payees
+            .iter()
+            .map(|payee| self.svm.token_balance(&payee.token_account))
+            .collect()
+    }
+
+    fn checkout(&self, amount: u64, referrer: Option<Pubkey>) -> Instruction {
+        let mut metas = accounts::Checkout {
+            split: self.split,
+            buyer: self.buyer.pubkey(),
+            buyer_token_account: self.buyer_token_account,
+            referrer_token_account: referrer,
+            token_program: spl_token::ID,
+        }
+        .to_account_metas(None);
+        metas.extend(Self::payee_metas(&self.config.payees));
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: metas,
+            data: instruction::Checkout { amount }.data(),
+        }
+    }
+
+    fn update(&self, payees: Vec<Payee>) -> Instruction {
+        let mut metas = accounts::UpdateSplit {
+            split: self.split,
+            owner: self.owner.pubkey(),
+        }
+        .to_account_metas(None);
+        metas.extend(Self::payee_metas(&payees));
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: metas,
+            data: instruction::UpdateSplit {
+                payees,
+                referral_bps: 0,
+            }
+            .data(),
+        }
+    }
+
+    fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TransactionResult {
+        let payer = self.payer.insecure_clone();
+        self.svm.send_instructions(&[instruction], &payer, signers)
+    }
+
+    fn send_as_buyer(&mut self, instruction: Instruction) -> TransactionResult {
+        let buyer = self.buyer.insecure_clone();
+        self.send(instruction, &[&buyer])
+    }
+
+    fn send_as_owner(&mut self, instruction: Instruction) -> TransactionResult {
+        let owner = self.owner.insecure_clone();
+        self.send(instruction, &[&owner])
+    }
+}
+
+#[test]
+fn owners_create_a_split_over_accounts_in_its_mint() {
+    let mut fx = Fixture::new();
+    fx.svm.remove_account(&fx.split);
+    let payees = fx.config.payees.clone();
+
+    let lopsided = vec![Payee {
+        bps: 9_999,
+        ..payees[0]
+    }];
+    let result = fx.send_as_owner(fx.create(lopsided));
+    assert_error(result, ErrorCode::InvalidShares);
+    let mut missing = fx.create(payees.clone());
+    missing.accounts.pop();
+    let result = fx.send_as_owner(missing);
+    assert_error(result, ErrorCode::PayeeMismatch);
+
+    let result = fx.send_as_owner(fx.create(payees.clone()));
+    assert_success(result);
+    let config = fx.svm.get_anchor_account::<SplitConfig>(&fx.split).unwrap();
+    assert_eq!(
+        (config.owner, config.mint),
+        (fx.owner.pubkey(), fx.config.mint)
+    );
+    assert_eq!(config.payees, payees);
+    assert_eq!(config.referral_bps, 250);
+    assert_eq!((config.total_processed, config.checkout_count), (0, 0));
+    assert_eq!(config.created_at, fx.svm.clock().unix_timestamp);
+}
+
+#[test]
+fn checkout_pays_every_payee_or_none() {
+    let mut fx = Fixture::new();
+    let result = fx.send_as_buyer(fx.checkout(0, None));
+    assert_error(result, ErrorCode::InvalidAmount);
+
+    let result = fx.send_as_buyer(fx.checkout(PRICE, None));
+    assert_success(result);
+    let (_, shares) = fx.config.shares(PRICE, false).unwrap();
+    assert_eq!(fx.balances(), shares);
+    assert_eq!(shares.iter().sum::<u64>(), PRICE);
+    assert_eq!(fx.svm.token_balance(&fx.buyer_token_account), 9 * PRICE);
+
+    let referrer = fx
+        .svm
+        .create_token_account(&Pubkey::new_unique(), &fx.config.mint, 0);
+    let result = fx.send_as_buyer(fx.checkout(PRICE, Some(referrer)));
+    assert_success(result);
+    let (referral, referred) = fx.config.shares(PRICE, true).unwrap();
+    assert_eq!(fx.svm.token_balance(&referrer), referral);
+    let expected: Vec<u64> = shares.iter().zip(&referred).map(|(a, b)| a + b).collect();
+    assert_eq!(fx.balances(), expected);
+    assert_eq!(fx.svm.token_balance(&fx.buyer_token_account), 8 * PRICE);
+    let config = fx.svm.get_anchor_account::<SplitConfig>(&fx.split).unwrap();
+    assert_eq!(
+        (config.total_processed, config.checkout_count),
+        (2 * PRICE, 2)
+    );
+}
+
+#[test]
+fn payee_accounts_must_match_the_split() {
+    let mut fx = Fixture::new();
+    let mut swapped = fx.checkout(PRICE, None);
+    let count = swapped.accounts.len();
+    swapped.accounts.swap(count - 1, count - 2);
+    let result = fx.send_as_buyer(swapped);
+    assert_error(result, ErrorCode::PayeeMismatch);
+
+    let mut missing = fx.checkout(PRICE, None);
+    missing.accounts.pop();
+    let result = fx.send_as_buyer(missing);
+    assert_error(result, ErrorCode::PayeeMismatch);
+
+    let mut redirected = fx.checkout(PRICE, None);
+    redirected.accounts[count - 1].pubkey =
+        fx.svm
+            .create_token_account(&Pubkey::new_unique(), &fx.config.mint, 0);
+    let result = fx.send_as_buyer(redirected);
+    assert_error(result, ErrorCode::PayeeMismatch);
+}
+
+#[test]
+fn buyers_cannot_refer_themselves() {
+    let mut fx = Fixture::new();
+    let own_account = fx
+        .svm
+        .create_token_account(&fx.buyer.pubkey(), &fx.config.mint, 0);
+    let result = fx.send_as_buyer(fx.checkout(PRICE, Some(own_account)));
+    assert_error(result, ErrorCode::SelfReferral);
+}
+
+#[test]
+fn owner_updates_payees() {
+    let mut fx = Fixture::new();
+    let mint = fx.config.mint;
+    let solo = Payee {
+        token_account: fx.svm.create_token_account(&Pubkey::new_unique(), &mint, 0),
+        bps: 10_000,
+    };
+
+    let impostor = Keypair::new();
+    let mut update = fx.update(vec![solo]);
+    update.accounts[1].pubkey = impostor.pubkey();
+    let result = fx.send(update, &[&impostor]);
+    assert_error(result, AnchorErrorCode::ConstraintSeeds);
+
+    let wrong_mint = Payee {
+        token_account: fx
+            .svm
+            .create_token_account(&Pubkey::new_unique(), &Pubkey::new_unique(), 0),
+        bps: 10_000,
+    };
+    let result = fx.send_as_owner(fx.update(vec![wrong_mint]));
+    assert_error(result, ErrorCode::InvalidTokenAccount);
+
+    let result = fx.send_as_owner(fx.update(vec![solo]));
+    assert!(result.is_ok(), "{result:#?}");
+    let config = fx.svm.get_anchor_account::<SplitConfig>(&fx.split).unwrap();
+    assert_eq!(config.payees, vec![solo]);
+    assert_eq!(config.referral_bps, 0);
+}
@@ -0,0 +1,790 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use spl_token::instruction as token_instruction;
+use subscription_program::program::SubscriptionProgram;
+use subscription_program::Subscription;
+use token_utils::token_account;
+
+declare_id!("4coZidu7sgEX8rPR9J8wTGQLke9kZLRZ4mF8UdtUDDmj");
+
+pub const MAX_TIERS: usize = 4;
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+#[program]
+pub mod stake_discounts {
+    use super::*;
+
+    /// Offer a subscription to the merchant at `list_price` every
+    /// `interval_seconds`, discounted for holders who lock `stake_mint` for
+    /// `lock_seconds`. `tiers` are ordered by `min_stake`.
+    pub fn create_discount(
+        ctx: Context<CreateDiscount>,
+        list_price: u64,
+        interval_seconds: i64,
+        lock_seconds: i64,
+        tiers: Vec<DiscountTier>,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        DiscountConfig::check_params(list_price, interval_seconds, lock_seconds, &tiers)?;
+
+        let merchant_account = token_account(
+            &ctx.accounts.merchant_token_account,
+            ErrorCode::InvalidTokenAccount,
+        )?;
+        require_keys_eq!(
+            merchant_account.owner,
+            ctx.accounts.merchant.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+        require_keys_eq!(
+            merchant_account.mint,
+            ctx.accounts.payment_mint.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+
+        let config = &mut ctx.accounts.config;
+        config.merchant = ctx.accounts.merchant.key();
+        config.stake_mint = ctx.accounts.stake_mint.key();
+        config.payment_mint = ctx.accounts.payment_mint.key();
+        config.merchant_token_account = ctx.accounts.merchant_token_account.key();
+        config.list_price = list_price;
+        config.interval_seconds = interval_seconds;
+        config.lock_seconds = lock_seconds;
+        config.tiers = tiers;
+        config.total_locked = 0;
+        config.created_at = clock.unix_timestamp;
+        config.bump = ctx.bumps.config;
+
+        emit!(DiscountCreated {
+            config: config.key(),
+            merchant: config.merchant,
+            stake_mint: config.stake_mint,
+            list_price,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("Discount created: list price {}", list_price);
+        msg!(
+            "{} tiers, lock {} seconds",
+            config.tiers.len(),
+            lock_seconds
+        );
+
+        Ok(())
+    }
+
+    /// Open an empty stake and attestation for `user`. Create the vault
+    /// beforehand as the stake PDA's ATA for the stake mint.
+    pub fn open_stake(ctx: Context<OpenStake>) -> Result<()> {
+        let stake_key = ctx.accounts.stake.key();
+        let vault = token_account(&ctx.accounts.vault, ErrorCode::InvalidTokenAccount)?;
+        require_keys_eq!(vault.owner, stake_key, ErrorCode::InvalidTokenAccount);
+        require_keys_eq!(
+            vault.mint,
+            ctx.accounts.config.stake_mint,
+            ErrorCode::InvalidTokenAccount
+        );
+
+        let config_key = ctx.accounts.config.key();
+        let user_key = ctx.accounts.user.key();
+
+        let stake = &mut ctx.accounts.stake;
+        stake.config = config_key;
+        stake.user = user_key;
+        stake.vault = ctx.accounts.vault.key();
+        stake.amount = 0;
+        stake.locked_until = 0;
+        stake.bump = ctx.bumps.stake;
+
+        let attestation = &mut ctx.accounts.attestation;
+        attestation.config = config_key;
+        attestation.user = user_key;
+        attestation.discount_bps = 0;
+        attestation.amount_per_period = 0;
+        attestation.valid_until = 0;
+        attestation.subscription = None;
+        attestation.issued_at = 0;
+        attestation.bump = ctx.bumps.attestation;
+
+        msg!("Stake opened for {}", user_key);
+
+        Ok(())
+    }
+
+    /// Lock `amount` more tokens. The lock restarts: nothing can be withdrawn
+    /// until `lock_seconds` from now.
+    pub fn lock_tokens(ctx: Context<LockTokens>, amount: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.user_stake_account.key(),
+            &ctx.accounts.vault.key(),
+            &ctx.accounts.user.key(),
+            &[],
+            amount,
+        )?;
+
+        invoke(
+            &transfer_ix,
+            &[
+                ctx.accounts.user_stake_account.to_account_info(),
+                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.user.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        let config = &mut ctx.accounts.config;
+        let stake = &mut ctx.accounts.stake;
+        stake.lock(amount, now, config.lock_seconds)?;
+        config.total_locked = config
+            .total_locked
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        emit!(TokensLocked {
+            stake: stake.key(),
+            user: stake.user,
+            amount,
+            total: stake.amount,
+            locked_until: stake.locked_until,
+            timestamp: now,
+        });
+
+        msg!("Locked {} tokens, {} in total", amount, stake.amount);
+        msg!("Locked until {}", stake.locked_until);
+
+        Ok(())
+    }
+
+    /// Issue the attestation for the user's current stake: the discount of
+    /// the highest tier reached, valid until the lock ends. Can be refreshed
+    /// after locking more, until it has been used.
+    pub fn attest(ctx: Context<Attest>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let config = &ctx.accounts.config;
+        let stake = &ctx.accounts.stake;
+        require!(stake.locked_until > now, ErrorCode::NothingLocked);
+
+        let attestation = &mut ctx.accounts.attestation;
+        require!(
+            attestation.subscription.is_none(),
+            ErrorCode::AttestationUsed
+        );
+
+        let discount_bps = config.discount_bps(stake.amount);
+        require!(discount_bps > 0, ErrorCode::NoDiscount);
+
+        attestation.discount_bps = discount_bps;
+        attestation.amount_per_period = config.discounted_price(discount_bps)?;
+        attestation.valid_until = stake.locked_until;
+        attestation.issued_at = now;
+
+        emit!(DiscountAttested {
+            attestation: attestation.key(),
+            user: attestation.user,
+            discount_bps,
+            amount_per_period: attestation.amount_per_period,
+            valid_until: attestation.valid_until,
+            timestamp: now,
+        });
+
+        msg!("Discount attested: {} bps", discount_bps);
+        msg!(
+            "{} per period until {}",
+            attestation.amount_per_period,
+            attestation.valid_until
+        );
+
+        Ok(())
+    }
+
+    /// Subscribe to the merchant at the attested price. Calls the
+    /// subscription program's `initialize_subscription` with the discounted
+    /// amount and an expiry at the end of the lock, then marks the
+    /// attestation used.
+    pub fn subscribe(ctx: Context<Subscribe>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let attestation = &ctx.accounts.attestation;
+        attestation.check_usable(now)?;
+
+        let amount_per_period = attestation.amount_per_period;
+        let valid_until = attestation.valid_until;
+
+        subscription_program::cpi::initialize_subscription(
+            CpiContext::new(
+                ctx.accounts.subscription_program.to_account_info(),
+                subscription_program::cpi::accounts::InitializeSubscription {
+                    subscription: ctx.accounts.subscription.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                    recipient: ctx.accounts.merchant.to_account_info(),
+                    user_token_account: ctx.accounts.user_token_account.to_account_info(),
+                    recipient_token_account: ctx.accounts.merchant_token_account.to_account_info(),
+                    token_mint: ctx.accounts.payment_mint.to_account_info(),
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                    payer: ctx.accounts.payer.to_account_info(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                    denylist_entry: ctx.accounts.denylist_entry.to_account_info(),
+                    allowlist: ctx.accounts.allowlist.to_account_info(),
+                    allowlist_entry: ctx.accounts.allowlist_entry.to_account_info(),
+                },
+            ),
+            amount_per_period,
+            ctx.accounts.config.interval_seconds,
+            Some(valid_until),
+        )?;
+
+        let attestation = &mut ctx.accounts.attestation;
+        attestation.subscription = Some(ctx.accounts.subscription.key());
+
+        emit!(DiscountApplied {
+            attestation: attestation.key(),
+            subscription: ctx.accounts.subscription.key(),
+            user: attestation.user,
+            list_price: ctx.accounts.config.list_price,
+            amount_per_period,
+            timestamp: now,
+        });
+
+        msg!(
+            "Subscribed at {} instead of {}",
+            amount_per_period,
+            ctx.accounts.config.list_price
+        );
+        msg!("Discounted subscription expires at {}", valid_until);
+
+        Ok(())
+    }
+
+    /// Withdraw everything once the lock has ended, closing the vault, the
+    /// stake and the attestation
+    pub fn unlock_tokens(ctx: Context<UnlockTokens>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let stake = &ctx.accounts.stake;
+        stake.check_unlocked(now)?;
+
+        let amount = stake.amount;
+        let config_key = ctx.accounts.config.key();
+        let user_key = stake.user;
+        let seeds = &[
+            b"stake",
+            config_key.as_ref(),
+            user_key.as_ref(),
+            &[stake.bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+        let stake_info = ctx.accounts.stake.to_account_info();
+
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.vault.key(),
+            &ctx.accounts.user_stake_account.key(),
+            &stake_info.key(),
+            &[],
+            amount,
+        )?;
+        invoke_signed(
+            &transfer_ix,
+            &[
+                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.user_stake_account.to_account_info(),
+                stake_info.clone(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+            signer_seeds,
+        )?;
+
+        let close_ix = token_instruction::close_account(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.vault.key(),
+            &ctx.accounts.user.key(),
+            &stake_info.key(),
+            &[],
+        )?;
+        invoke_signed(
+            &close_ix,
+            &[
+                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.user.to_account_info(),
+                stake_info.clone(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+            signer_seeds,
+        )?;
+
+        let config = &mut ctx.accounts.config;
+        config.total_locked = config
+            .total_locked
+            .checked_sub(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        emit!(TokensUnlocked {
+            stake: stake_info.key(),
+            user: user_key,
+            amount,
+            timestamp: now,
+        });
+
+        msg!("Unlocked {} tokens", amount);
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct CreateDiscount<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + DiscountConfig::INIT_SPACE,
+        seeds = [b"discount", merchant.key().as_ref()],
+        bump
+    )]
+    pub config: Account<'info, DiscountConfig>,
+
+    pub merchant: Signer<'info>,
+
+    /// CHECK: Mint of the governance or creator token holders lock
+    pub stake_mint: UncheckedAccount<'info>,
+
+    /// CHECK: Mint subscriptions are paid in (USDC)
+    pub payment_mint: UncheckedAccount<'info>,
+
+    /// CHECK: Merchant's token account, checked in the handler; subscriptions pay here
+    pub merchant_token_account: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct OpenStake<'info> {
+    #[account(
+        seeds = [b"discount", config.merchant.as_ref()],
+        bump = config.bump,
+    )]
+    pub config: Account<'info, DiscountConfig>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Stake::INIT_SPACE,
+        seeds = [b"stake", config.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub stake: Account<'info, Stake>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Attestation::INIT_SPACE,
+        seeds = [b"attestation", config.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub attestation: Account<'info, Attestation>,
+
+    pub user: Signer<'info>,
+
+    /// CHECK: The stake PDA's token account for the stake mint, checked in the handler
+    pub vault: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct LockTokens<'info> {
+    #[account(
+        mut,
+        seeds = [b"discount", config.merchant.as_ref()],
+        bump = config.bump,
+    )]
+    pub config: Account<'info, DiscountConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"stake", config.key().as_ref(), user.key().as_ref()],
+        bump = stake.bump,
+        has_one = config,
+        has_one = user,
+        has_one = vault
+    )]
+    pub stake: Account<'info, Stake>,
+
+    pub user: Signer<'info>,
+
+    /// CHECK: Any token account the user owns, debited by the token program
+    #[account(mut)]
+    pub user_stake_account: UncheckedAccount<'info>,
+
+    /// CHECK: The stake's vault
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Attest<'info> {
+    #[account(
+        seeds = [b"discount", config.merchant.as_ref()],
+        bump = config.bump,
+    )]
+    pub config: Account<'info, DiscountConfig>,
+
+    #[account(
+        seeds = [b"stake", config.key().as_ref(), user.key().as_ref()],
+        bump = stake.bump,
+        has_one = config,
+        has_one = user
+    )]
+    pub stake: Account<'info, Stake>,
+
+    #[account(
+        mut,
+        seeds = [b"attestation", config.key().as_ref(), user.key().as_ref()],
+        bump = attestation.bump,
+        has_one = config,
+        has_one = user
+    )]
+    pub attestation: Account<'info, Attestation>,
+
+    pub user: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Subscribe<'info> {
+    #[account(
+        seeds = [b"discount", config.merchant.as_ref()],
+        bump = config.bump,
+        has_one = merchant,
+        has_one = merchant_token_account,
+        has_one = payment_mint
+    )]
+    pub config: Account<'info, DiscountConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"attestation", config.key().as_ref(), user.key().as_ref()],
+        bump = attestation.bump,
+        has_one = config,
+        has_one = user
+    )]
+    pub attestation: Account<'info, Attestation>,
+
+    /// CHECK: Subscription PDA, created and checked by the subscription program
+    #[account(mut)]
+    pub subscription: UncheckedAccount<'info>,
+
+    pub user: Signer<'info>,
+
+    /// CHECK: Merchant, the subscription's recipient
+    pub merchant: UncheckedAccount<'info>,
+
+    /// CHECK: User's token account, delegated to the subscription PDA by the subscription program
+    #[account(mut)]
+    pub user_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Merchant's token account
+    #[account(mut)]
+    pub merchant_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Payment mint
+    pub payment_mint: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    /// CHECK: The user's entry on the merchant's denylist, checked by the
+    /// subscription program
+    pub denylist_entry: UncheckedAccount<'info>,
+
+    /// CHECK: The merchant's allowlist, set while it is invite-only; checked
+    /// by the subscription program
+    pub allowlist: UncheckedAccount<'info>,
+
+    /// CHECK: The user's entry on the merchant's allowlist, checked by the
+    /// subscription program
+    pub allowlist_entry: UncheckedAccount<'info>,
+
+    pub subscription_program: Program<'info, SubscriptionProgram>,
+}
+
+#[derive(Accounts)]
+pub struct UnlockTokens<'info> {
+    #[account(
+        mut,
+        seeds = [b"discount", config.merchant.as_ref()],
+        bump = config.bump,
+    )]
+    pub config: Account<'info, DiscountConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"stake", config.key().as_ref(), user.key().as_ref()],
+        bump = stake.bump,
+        has_one = config,
+        has_one = user,
+        has_one = vault,
+        close = user
+    )]
+    pub stake: Account<'info, Stake>,
+
+    #[account(
+        mut,
+        seeds = [b"attestation", config.key().as_ref(), user.key().as_ref()],
+        bump = attestation.bump,
+        close = user
+    )]
+    pub attestation: Account<'info, Attestation>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    /// CHECK: The stake's vault, emptied and closed
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: Any token account of the user's for the stake mint
+    #[account(mut)]
+    pub user_stake_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub struct DiscountTier {
+    /// Tokens that must be locked, in base units
+    pub min_stake: u64,
+    pub discount_bps: u16,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct DiscountConfig {
+    pub merchant: Pubkey,
+    pub stake_mint: Pubkey,
+    pub payment_mint: Pubkey,
+    pub merchant_token_account: Pubkey,
+    /// Undiscounted amount per period
+    pub list_price: u64,
+    pub interval_seconds: i64,
+    pub lock_seconds: i64,
+    #[max_len(MAX_TIERS)]
+    pub tiers: Vec<DiscountTier>,
+    pub total_locked: u64,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl DiscountConfig {
+    /// Tiers must raise both the stake and the discount, and no discount may
+    /// make the subscription free
+    pub fn check_params(
+        list_price: u64,
+        interval_seconds: i64,
+        lock_seconds: i64,
+        tiers: &[DiscountTier],
+    ) -> Result<()> {
+        require!(list_price > 0, ErrorCode::InvalidAmount);
+        require!(
+            interval_seconds > 0 && lock_seconds > 0,
+            ErrorCode::InvalidInterval
+        );
+        require!(
+            !tiers.is_empty() && tiers.len() <= MAX_TIERS,
+            ErrorCode::InvalidTiers
+        );
+        require!(
+            tiers[0].min_stake > 0 && tiers[0].discount_bps > 0,
+            ErrorCode::InvalidTiers
+        );
+        require!(
+            tiers
+                .windows(2)
+                .all(|pair| pair[0].min_stake < pair[1].min_stake
+                    && pair[0].discount_bps < pair[1].discount_bps),
+            ErrorCode::InvalidTiers
+        );
+        require!(
+            u64::from(tiers[tiers.len() - 1].discount_bps) < BPS_DENOMINATOR,
+            ErrorCode::InvalidTiers
+        );
+        Ok(())
+    }
+
+    /// Discount of the highest tier `staked` reaches, 0 below the first
+    pub fn discount_bps(&self, staked: u64) -> u16 {
+        self.tiers
+            .iter()
+            .rev()
+            .find(|tier| staked >= tier.min_stake)
+            .map_or(0, |tier| tier.discount_bps)
+    }
+
+    /// List price less `discount_bps`, rounded in the merchant's favour
+    pub fn discounted_price(&self, discount_bps: u16) -> Result<u64> {
+        let discount = (self.list_price as u128)
+            .checked_mul(discount_bps as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            / BPS_DENOMINATOR as u128;
+        Ok(self.list_price - discount as u64)
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Stake {
+    pub config: Pubkey,
+    pub user: Pubkey,
+    /// Holds the locked tokens, owned by this PDA
+    pub vault: Pubkey,
+    pub amount: u64,
+    pub locked_until: i64,
+    pub bump: u8,
+}
+
+impl Stake {
+    pub fn lock(&mut self, amount: u64, now: i64, lock_seconds: i64) -> Result<()> {
+        self.amount = self
+            .amount
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.locked_until = now
+            .checked_add(lock_seconds)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+
+    pub fn check_unlocked(&self, now: i64) -> Result<()> {
+        require!(now >= self.locked_until, ErrorCode::StillLocked);
+        Ok(())
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Attestation {
+    pub config: Pubkey,
+    pub user: Pubkey,
+    /// 0 until `attest` has been called
+    pub discount_bps: u16,
+    pub amount_per_period: u64,
+    /// End of the lock the discount was earned with
+    pub valid_until: i64,
+    /// The subscription that used this attestation
+    pub subscription: Option<Pubkey>,
+    pub issued_at: i64,
+    pub bump: u8,
+}
+
+impl Attestation {
+    /// Whether `subscribe` may use this attestation at `now`
+    pub fn check_usable(&self, now: i64) -> Result<()> {
+        require!(self.discount_bps > 0, ErrorCode::NoDiscount);
+        require!(self.subscription.is_none(), ErrorCode::AttestationUsed);
+        require!(now < self.valid_until, ErrorCode::AttestationExpired);
+        Ok(())
+    }
+
+    /// For merchants: `subscription` was created from this attestation and
+    /// still pays at least the attested price. The subscription's owner can
+    /// lower its amount directly, so check this before serving it.
+    pub fn covers(&self, subscription_key: &Pubkey, subscription: &Subscription) -> bool {
+        self.subscription == Some(*subscription_key)
+            && subscription.authority == self.user
+            && subscription.amount_per_period >= self.amount_per_period
+    }
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscountCreated {
+    pub config: Pubkey,
+    pub merchant: Pubkey,
+    pub stake_mint: Pubkey,
+    pub list_price: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokensLocked {
+    pub stake: Pubkey,
+    pub user: Pubkey,
+    pub amount: u64,
+    pub total: u64,
+    pub locked_until: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscountAttested {
+    pub attestation: Pubkey,
+    pub user: Pubkey,
+    pub discount_bps: u16,
+    pub amount_per_period: u64,
+    pub valid_until: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscountApplied {
+    pub attestation: Pubkey,
+    pub subscription: Pubkey,
+    pub user: Pubkey,
+    pub list_price: u64,
+    pub amount_per_period: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokensUnlocked {
+    pub stake: Pubkey,
+    pub user: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Amount must be greater than zero")]
+    InvalidAmount,
+    #[msg("Interval and lock period must be positive")]
+    InvalidInterval,
+    #[msg("Tiers must be 1 to 4 entries rising in stake and discount, below 100%")]
+    InvalidTiers,
+    #[msg("Invalid token account - wrong program, owner or mint")]
+    InvalidTokenAccount,
+    #[msg("Stake does not reach a discount tier or no discount was attested")]
+    NoDiscount,
+    #[msg("Attestation has already been used for a subscription")]
+    AttestationUsed,
+    #[msg("Attestation expired with the lock it was issued for")]
+    AttestationExpired,
+    #[msg("Nothing is locked")]
+    NothingLocked,
+    #[msg("Tokens are still locked")]
+    StillLocked,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
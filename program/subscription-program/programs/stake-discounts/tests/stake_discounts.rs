@@ -0,0 +1,569 @@
+//! Native tests for the stake-gated discounts program.
+//!
+//! Tier and price rules are pure methods and are tested directly. Every
+//! instruction also runs end to end, with `subscribe` opening a real
+//! subscription through the subscription program.
+
+use anchor_lang::error::ErrorCode as AnchorErrorCode;
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, Space, ToAccountMetas};
+use stake_discounts::{
+    accounts, instruction, Attestation, DiscountConfig, DiscountTier, ErrorCode, Stake,
+    ID as PROGRAM_ID,
+};
+use subscription_program::Subscription;
+use test_harness::{assert_error, assert_success, Keypair, Signer, TestSvm, TransactionResult};
+
+const LIST_PRICE: u64 = 10_000_000;
+const MONTH: i64 = 30 * 86_400;
+const LOCK: i64 = 90 * 86_400;
+
+fn tiers() -> Vec<DiscountTier> {
+    vec![
+        DiscountTier {
+            min_stake: 100,
+            discount_bps: 1_000,
+        },
+        DiscountTier {
+            min_stake: 1_000,
+            discount_bps: 2_500,
+        },
+    ]
+}
+
+fn config() -> DiscountConfig {
+    DiscountConfig {
+        merchant: Pubkey::new_unique(),
+        stake_mint: Pubkey::new_unique(),
+        payment_mint: Pubkey::new_unique(),
+        merchant_token_account: Pubkey::new_unique(),
+        list_price: LIST_PRICE,
+        interval_seconds: MONTH,
+        lock_seconds: LOCK,
+        tiers: tiers(),
+        total_locked: 0,
+        created_at: 0,
+        bump: 255,
+    }
+}
+
+#[test]
+fn tiers_must_rise_and_stay_below_free() {
+    DiscountConfig::check_params(LIST_PRICE, MONTH, LOCK, &tiers()).unwrap();
+    assert_eq!(
+        DiscountConfig::check_params(0, MONTH, LOCK, &tiers()).unwrap_err(),
+        ErrorCode::InvalidAmount.into()
+    );
+    assert_eq!(
+        DiscountConfig::check_params(LIST_PRICE, MONTH, 0, &tiers()).unwrap_err(),
+        ErrorCode::InvalidInterval.into()
+    );
+
+    let mut reversed = tiers();
+    reversed.reverse();
+    let free = vec![DiscountTier {
+        min_stake: 1,
+        discount_bps: 10_000,
+    }];
+    for bad in [vec![], reversed, free, vec![tiers()[0]; 5]] {
+        assert_eq!(
+            DiscountConfig::check_params(LIST_PRICE, MONTH, LOCK, &bad).unwrap_err(),
+            ErrorCode::InvalidTiers.into()
+        );
+    }
+}
+
+#[test]
+fn stake_earns_the_highest_tier_reached() {
+    let offer = config();
+    assert_eq!(offer.discount_bps(99), 0);
+    assert_eq!(offer.discount_bps(100), 1_000);
+    assert_eq!(offer.discount_bps(999), 1_000);
+    assert_eq!(offer.discount_bps(u64::MAX), 2_500);
+
+    assert_eq!(offer.discounted_price(1_000).unwrap(), 9_000_000);
+    assert_eq!(offer.discounted_price(2_500).unwrap(), 7_500_000);
+
+    // Rounded in the merchant's favour
+    let odd = DiscountConfig {
+        list_price: 999,
+        ..config()
+    };
+    assert_eq!(odd.discounted_price(2_500).unwrap(), 750);
+}
+
+#[test]
+fn attestations_are_used_once_and_checked_against_the_subscription() {
+    let user = Pubkey::new_unique();
+    let subscription_key = Pubkey::new_unique();
+    let mut attestation = Attestation {
+        config: Pubkey::new_unique(),
+        user,
+        discount_bps: 0,
+        amount_per_period: 0,
+        valid_until: LOCK,
+        subscription: None,
+        issued_at: 0,
+        bump: 255,
+    };
+    assert_eq!(
+        attestation.check_usable(0).unwrap_err(),
+        ErrorCode::NoDiscount.into()
+    );
+
+    attestation.discount_bps = 2_500;
+    attestation.amount_per_period = 7_500_000;
+    attestation.check_usable(LOCK - 1).unwrap();
+    assert_eq!(
+        attestation.check_usable(LOCK).unwrap_err(),
+        ErrorCode::AttestationExpired.into()
+    );
+
+    let mut subscription = Subscription {
+        authority: user,
+        recipient: Pubkey::new_unique(),
+        user_token_account: Pubkey::new_unique(),
+        recipient_token_account: Pubkey::new_unique(),
+        token_mint: Pubkey::new_unique(),
+        amount_per_period: 7_500_000,
+        interval_seconds: MONTH,
+        last_charge_timestamp: 0,
+        next_charge_at: MONTH,
+        created_at: 0,
+        expires_at: Some(LOCK),
+        is_active: true,
+        total_charged: 7_500_000,
+        bump: 255,
+        on_plan: false,
+        max_total_spend: Subscription::NO_SPEND_LIMIT,
+    };
+    assert!(!attestation.covers(&subscription_key, &subscription));
+
+    attestation.subscription = Some(subscription_key);
+    assert_eq!(
+        attestation.check_usable(0).unwrap_err(),
+        ErrorCode::AttestationUsed.into()
+    );
+    assert!(attestation.covers(&subscription_key, &subscription));
+
+    // Lowered by its owner after subscribing
+    subscription.amount_per_period = 1;
+    assert!(!attestation.covers(&subscription_key, &subscription));
+}
+
+struct Fixture {
+    svm: TestSvm,
+    payer: Keypair,
+    merchant: Keypair,
+    user: Keypair,
+    config: Pubkey,
+    state: DiscountConfig,
+    stake: Pubkey,
+    vault: Pubkey,
+    attestation: Pubkey,
+    user_stake_account: Pubkey,
+    user_token_account: Pubkey,
+}
+
+impl Fixture {
+    /// A user with `amount` tokens locked until `locked_until`
+    fn new(amount: u64, locked_until: i64) -> Self {
+        let (mut svm, payer) = TestSvm::for_program(PROGRAM_ID, stake_discounts::entry);
+        svm.add_program(subscription_program::ID, subscription_program::entry);
+
+        let merchant = Keypair::new();
+        let user = Keypair::new();
+
+        let stake_mint = svm.create_mint(&Pubkey::new_unique(), 0);
+        let payment_mint = svm.create_mint(&Pubkey::new_unique(), 6);
+        let merchant_token_account =
+            svm.create_associated_token_account(&merchant.pubkey(), &payment_mint, 0);
+        let user_stake_account =
+            svm.create_associated_token_account(&user.pubkey(), &stake_mint, 5_000);
+        let user_token_account =
+            svm.create_associated_token_account(&user.pubkey(), &payment_mint, 10 * LIST_PRICE);
+
+        let (config_address, bump) =
+            Pubkey::find_program_address(&[b"discount", merchant.pubkey().as_ref()], &PROGRAM_ID);
+        let state = DiscountConfig {
+            merchant: merchant.pubkey(),
+            stake_mint,
+            payment_mint,
+            merchant_token_account,
+            total_locked: amount,
+            bump,
+            ..config()
+        };
+        svm.set_anchor_account(config_address, &state, 8 + DiscountConfig::INIT_SPACE);
+
+        let (stake, bump) = Pubkey::find_program_address(
+            &[b"stake", config_address.as_ref(), user.pubkey().as_ref()],
+            &PROGRAM_ID,
+        );
+        let vault = svm.create_associated_token_account(&stake, &stake_mint, amount);
+        let locked = Stake {
+            config: config_address,
+            user: user.pubkey(),
+            vault,
+            amount,
+            locked_until,
+            bump,
+        };
+        svm.set_anchor_account(stake, &locked, 8 + Stake::INIT_SPACE);
+
+        let (attestation, bump) = Pubkey::find_program_address(
+            &[
+                b"attestation",
+                config_address.as_ref(),
+                user.pubkey().as_ref(),
+            ],
+            &PROGRAM_ID,
+        );
+        let empty = Attestation {
+            config: config_address,
+            user: user.pubkey(),
+            discount_bps: 0,
+            amount_per_period: 0,
+            valid_until: 0,
+            subscription: None,
+            issued_at: 0,
+            bump,
+        };
+        svm.set_anchor_account(attestation, &empty, 8 + Attestation::INIT_SPACE);
+
+        Self {
+            svm,
+            payer,
+            merchant,
+            user,
+            config: config_address,
+            state,
+            stake,
+            vault,
+            attestation,
+            user_stake_account,
+            user_token_account,
+        }
+    }
+
+    fn subscription(&self) -> Pubkey {
+        Pubkey::find_program_address(
+            &[
+                b"subscription",
+                self.user.pubkey().as_ref(),
+                self.state.merchant.as_ref(),
+            ],
+            &subscription_program::ID,
+        )
+        .0
+    }
+
+    fn create_discount(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CreateDiscount {
+                config: self.config,
+                merchant: self.merchant.pubkey(),
+                stake_mint: self.state.stake_mint,
+                payment_mint: self.state.payment_mint,
+                merchant_token_account: self.state.merchant_token_account,
+                payer: self.payer.pubkey(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CreateDiscount {
+                list_price: LIST_PRICE,
+                interval_seconds: MONTH,
+                lock_seconds: LOCK,
+                tiers: tiers(),
+            }
+            .data(),
+        }
+    }
+
+    fn open_stake(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::OpenStake {
+                config: self.config,
+                stake: self.stake,
+                attestation: self.attestation,
+                user: self.user.pubkey(),
+                vault: self.vault,
+                payer: self.payer.pubkey(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::OpenStake {}.data(),
+        }
+    }
+
+    fn now(&self) -> i64 {
+        self.svm.clock().unix_timestamp
+    }
+
+    fn attest(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::Attest {
+                config: self.config,
+                stake: self.stake,
+                attestation: self.attestation,
+                user: self.user.pubkey(),
+            }
+            .to_account_metas(None),
+            data: instruction::Attest {}.data(),
+        }
+    }
+
+    fn subscribe(&self) -> Instruction {
+        let subscription = self.subscription();
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::Subscribe {
+                config: self.config,
+                attestation: self.attestation,
+                subscription,
+                user: self.user.pubkey(),
+                merchant: self.state.merchant,
+                user_token_account: self.user_token_account,
+                merchant_token_account: self.state.merchant_token_account,
+                payment_mint: self.state.payment_mint,
+                token_program: spl_token::ID,
+                payer: self.payer.pubkey(),
+                system_program: system_program::ID,
+                denylist_entry: Pubkey::find_program_address(
+                    &[
+                        b"denylist",
+                        self.state.merchant.as_ref(),
+                        self.user.pubkey().as_ref(),
+                    ],
+                    &subscription_program::ID,
+                )
+                .0,
+                allowlist: Pubkey::find_program_address(
+                    &[b"allowlist", self.state.merchant.as_ref()],
+                    &subscription_program::ID,
+                )
+                .0,
+                allowlist_entry: Pubkey::find_program_address(
+                    &[
+                        b"allowlist",
+                        self.state.merchant.as_ref(),
+                        self.user.pubkey().as_ref(),
+                    ],
+                    &subscription_program::ID,
+                )
+                .0,
+                subscription_program: subscription_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::Subscribe {}.data(),
+        }
+    }
+
+    fn lock(&self, amount: u64) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::LockTokens {
+                config: self.config,
+                stake: self.stake,
+                user: self.user.pubkey(),
+                user_stake_account: self.user_stake_account,
+                vault: self.vault,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::LockTokens { amount }.data(),
+        }
+    }
+
+    fn unlock(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::UnlockTokens {
+                config: self.config,
+                stake: self.stake,
+                attestation: self.attestation,
+                user: self.user.pubkey(),
+                vault: self.vault,
+                user_stake_account: self.user_stake_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::UnlockTokens {}.data(),
+        }
+    }
+
+    fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TransactionResult {
+        let payer = self.payer.insecure_clone();
+        self.svm.send_instructions(&[instruction], &payer, signers)
+    }
+
+    fn send_as_user(&mut self, instruction: Instruction) -> TransactionResult {
+        let user = self.user.insecure_clone();
+        self.send(instruction, &[&user])
+    }
+
+    fn attestation(&self) -> Attestation {
+        self.svm.get_anchor_account(&self.attestation).unwrap()
+    }
+}
+
+#[test]
+fn merchants_create_a_discount_paid_into_their_account() {
+    let mut fx = Fixture::new(0, 0);
+    fx.svm.remove_account(&fx.config);
+    let mut elsewhere = fx.create_discount();
+    elsewhere.accounts[4].pubkey = fx.user_token_account;
+    let merchant = fx.merchant.insecure_clone();
+    let result = fx.send(elsewhere, &[&merchant]);
+    assert_error(result, ErrorCode::InvalidTokenAccount);
+
+    let result = fx.send(fx.create_discount(), &[&merchant]);
+    assert_success(result);
+    let config: DiscountConfig = fx.svm.get_anchor_account(&fx.config).unwrap();
+    assert_eq!(config.merchant, merchant.pubkey());
+    assert_eq!(
+        config.merchant_token_account,
+        fx.state.merchant_token_account
+    );
+    assert_eq!(config.list_price, LIST_PRICE);
+    assert_eq!(config.tiers, tiers());
+    assert_eq!(config.total_locked, 0);
+    assert_eq!(config.created_at, fx.now());
+}
+
+#[test]
+fn stakes_open_empty_over_their_vault() {
+    let mut fx = Fixture::new(0, 0);
+    fx.svm.remove_account(&fx.stake);
+    fx.svm.remove_account(&fx.attestation);
+    let mut elsewhere = fx.open_stake();
+    elsewhere.accounts[4].pubkey = fx.user_stake_account;
+    let result = fx.send_as_user(elsewhere);
+    assert_error(result, ErrorCode::InvalidTokenAccount);
+
+    let result = fx.send_as_user(fx.open_stake());
+    assert_success(result);
+    let stake: Stake = fx.svm.get_anchor_account(&fx.stake).unwrap();
+    assert_eq!(stake.user, fx.user.pubkey());
+    assert_eq!(stake.vault, fx.vault);
+    assert_eq!(stake.amount, 0);
+    let attestation = fx.attestation();
+    assert_eq!(attestation.user, fx.user.pubkey());
+    assert_eq!(attestation.discount_bps, 0);
+    assert_eq!(attestation.subscription, None);
+}
+
+#[test]
+fn locked_stake_is_attested() {
+    let now = Fixture::new(0, 0).now();
+    let mut fx = Fixture::new(99, now + LOCK);
+    let result = fx.send_as_user(fx.attest());
+    assert_error(result, ErrorCode::NoDiscount);
+
+    let mut fx = Fixture::new(1_000, now + LOCK);
+    let result = fx.send_as_user(fx.attest());
+    assert!(result.is_ok(), "{result:#?}");
+    let attestation = fx.attestation();
+    assert_eq!(attestation.discount_bps, 2_500);
+    assert_eq!(attestation.amount_per_period, 7_500_000);
+    assert_eq!(attestation.valid_until, now + LOCK);
+
+    fx.svm.warp_to_timestamp(now + LOCK);
+    let result = fx.send_as_user(fx.attest());
+    assert_error(result, ErrorCode::NothingLocked);
+}
+
+#[test]
+fn attestations_cannot_be_issued_for_someone_else() {
+    let now = Fixture::new(0, 0).now();
+    let mut fx = Fixture::new(1_000, now + LOCK);
+    let stranger = Keypair::new();
+    let mut forged = fx.attest();
+    forged.accounts[3].pubkey = stranger.pubkey();
+    let result = fx.send(forged, &[&stranger]);
+    assert_error(result, AnchorErrorCode::ConstraintSeeds);
+}
+
+#[test]
+fn subscribing_needs_an_unused_attestation() {
+    let now = Fixture::new(0, 0).now();
+    let mut fx = Fixture::new(1_000, now + LOCK);
+    let result = fx.send_as_user(fx.subscribe());
+    assert_error(result, ErrorCode::NoDiscount);
+
+    let result = fx.send_as_user(fx.attest());
+    assert!(result.is_ok(), "{result:#?}");
+
+    let mut rerouted = fx.subscribe();
+    rerouted.accounts[4].pubkey = Pubkey::new_unique();
+    let result = fx.send_as_user(rerouted);
+    assert_error(result, AnchorErrorCode::ConstraintHasOne);
+
+    fx.svm.expire_blockhash();
+    let result = fx.send_as_user(fx.subscribe());
+    assert_success(result);
+    let subscription: Subscription = fx.svm.get_anchor_account(&fx.subscription()).unwrap();
+    assert_eq!(subscription.amount_per_period, 7_500_000);
+    assert_eq!(subscription.expires_at, Some(now + LOCK));
+    assert_eq!(
+        fx.svm.token_balance(&fx.state.merchant_token_account),
+        7_500_000
+    );
+    assert_eq!(fx.attestation().subscription, Some(fx.subscription()));
+    assert!(fx.attestation().covers(&fx.subscription(), &subscription));
+
+    let used = Attestation {
+        subscription: Some(Pubkey::new_unique()),
+        ..fx.attestation()
+    };
+    fx.svm
+        .set_anchor_account(fx.attestation, &used, 8 + Attestation::INIT_SPACE);
+    fx.svm.expire_blockhash();
+    let result = fx.send_as_user(fx.subscribe());
+    assert_error(result, ErrorCode::AttestationUsed);
+
+    fx.svm.expire_blockhash();
+    let result = fx.send_as_user(fx.attest());
+    assert_error(result, ErrorCode::AttestationUsed);
+}
+
+#[test]
+fn tokens_stay_locked_until_the_lock_ends() {
+    let now = Fixture::new(0, 0).now();
+    let mut fx = Fixture::new(1_000, now + LOCK);
+    let result = fx.send_as_user(fx.lock(0));
+    assert_error(result, ErrorCode::InvalidAmount);
+
+    fx.svm.warp_to_timestamp(now + 100);
+    let result = fx.send_as_user(fx.lock(500));
+    assert_success(result);
+    let stake: Stake = fx.svm.get_anchor_account(&fx.stake).unwrap();
+    assert_eq!(stake.amount, 1_500);
+    assert_eq!(stake.locked_until, now + 100 + LOCK);
+    assert_eq!(fx.svm.token_balance(&fx.vault), 1_500);
+    assert_eq!(fx.svm.token_balance(&fx.user_stake_account), 4_500);
+
+    let result = fx.send_as_user(fx.unlock());
+    assert_error(result, ErrorCode::StillLocked);
+
+    // The lock restarted when more was added
+    fx.svm.warp_to_timestamp(now + LOCK);
+    fx.svm.expire_blockhash();
+    let result = fx.send_as_user(fx.unlock());
+    assert_error(result, ErrorCode::StillLocked);
+
+    fx.svm.warp_to_timestamp(now + 100 + LOCK);
+    fx.svm.expire_blockhash();
+    let result = fx.send_as_user(fx.unlock());
+    assert_success(result);
+    assert_eq!(fx.svm.token_balance(&fx.user_stake_account), 6_000);
+    for closed in [fx.vault, fx.stake, fx.attestation] {
+        assert!(fx.svm.get_account(&closed).is_none());
+    }
+    let config: DiscountConfig = fx.svm.get_anchor_account(&fx.config).unwrap();
+    assert_eq!(config.total_locked, 0);
+}
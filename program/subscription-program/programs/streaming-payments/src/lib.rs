@@ -0,0 +1,609 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use spl_token::instruction as token_instruction;
+use token_utils::token_account;
+
+declare_id!("37HGBLeFDbYqyWjB8gswd6vdp3fjyshhiDzizi3jYSGe");
+
+#[program]
+pub mod streaming_payments {
+    use super::*;
+
+    /// Lock `deposit` in an escrow owned by the stream PDA; it unlocks to the
+    /// recipient at `rate_per_second` from `start_time` (default: now)
+    pub fn create_stream(
+        ctx: Context<CreateStream>,
+        stream_id: u64,
+        deposit: u64,
+        rate_per_second: u64,
+        start_time: Option<i64>,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        Stream::check_params(deposit, rate_per_second)?;
+
+        let stream_key = ctx.accounts.stream.key();
+        let mint_key = ctx.accounts.mint.key();
+        let escrow = token_account(
+            &ctx.accounts.escrow_token_account,
+            ErrorCode::InvalidTokenAccount,
+        )?;
+        require_keys_eq!(escrow.owner, stream_key, ErrorCode::InvalidTokenAccount);
+        require_keys_eq!(escrow.mint, mint_key, ErrorCode::InvalidTokenAccount);
+        let recipient_account = token_account(
+            &ctx.accounts.recipient_token_account,
+            ErrorCode::InvalidTokenAccount,
+        )?;
+        require_keys_eq!(
+            recipient_account.owner,
+            ctx.accounts.recipient.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+        require_keys_eq!(
+            recipient_account.mint,
+            mint_key,
+            ErrorCode::InvalidTokenAccount
+        );
+
+        // Sender signs the deposit directly; no delegation is left behind
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.sender_token_account.key(),
+            &ctx.accounts.escrow_token_account.key(),
+            &ctx.accounts.sender.key(),
+            &[],
+            deposit,
+        )?;
+
+        invoke(
+            &transfer_ix,
+            &[
+                ctx.accounts.sender_token_account.to_account_info(),
+                ctx.accounts.escrow_token_account.to_account_info(),
+                ctx.accounts.sender.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        // A start in the past would release funds for time already gone
+        let start_time = start_time
+            .unwrap_or(clock.unix_timestamp)
+            .max(clock.unix_timestamp);
+
+        let stream = &mut ctx.accounts.stream;
+        stream.sender = ctx.accounts.sender.key();
+        stream.recipient = ctx.accounts.recipient.key();
+        stream.mint = mint_key;
+        stream.sender_token_account = ctx.accounts.sender_token_account.key();
+        stream.recipient_token_account = ctx.accounts.recipient_token_account.key();
+        stream.escrow_token_account = ctx.accounts.escrow_token_account.key();
+        stream.stream_id = stream_id;
+        stream.rate_per_second = rate_per_second;
+        stream.start_time = start_time;
+        stream.deposited = deposit;
+        stream.withdrawn = 0;
+        stream.created_at = clock.unix_timestamp;
+        stream.bump = ctx.bumps.stream;
+
+        emit!(StreamCreated {
+            stream: stream_key,
+            sender: stream.sender,
+            recipient: stream.recipient,
+            deposit,
+            rate_per_second,
+            start_time,
+            end_time: stream.end_time(),
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!(
+            "Stream created: {} tokens at {} per second",
+            deposit,
+            rate_per_second
+        );
+        msg!("Streaming until {}", stream.end_time());
+
+        Ok(())
+    }
+
+    /// Pay out what has accrued to the recipient. Anyone may call it (a
+    /// keeper, the paymaster); funds only ever go to the recipient's account.
+    pub fn withdraw(ctx: Context<Withdraw>, amount: Option<u64>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let stream = &ctx.accounts.stream;
+        let amount = stream.check_withdrawal(now, amount)?;
+
+        transfer_from_escrow(
+            stream,
+            &ctx.accounts.escrow_token_account,
+            &ctx.accounts.recipient_token_account,
+            &ctx.accounts.stream.to_account_info(),
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+
+        let stream = &mut ctx.accounts.stream;
+        stream.record_withdrawal(amount)?;
+
+        emit!(StreamWithdrawn {
+            stream: stream.key(),
+            recipient: stream.recipient,
+            amount,
+            total_withdrawn: stream.withdrawn,
+            timestamp: now,
+        });
+
+        msg!("Withdrawn: {} tokens", amount);
+        msg!(
+            "Total withdrawn: {} of {}",
+            stream.withdrawn,
+            stream.deposited
+        );
+
+        Ok(())
+    }
+
+    /// Add funds to a running stream, pushing its end time out
+    pub fn top_up(ctx: Context<TopUp>, amount: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        require!(now < ctx.accounts.stream.end_time(), ErrorCode::StreamEnded);
+
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.sender_token_account.key(),
+            &ctx.accounts.escrow_token_account.key(),
+            &ctx.accounts.sender.key(),
+            &[],
+            amount,
+        )?;
+
+        invoke(
+            &transfer_ix,
+            &[
+                ctx.accounts.sender_token_account.to_account_info(),
+                ctx.accounts.escrow_token_account.to_account_info(),
+                ctx.accounts.sender.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        let stream = &mut ctx.accounts.stream;
+        stream.record_top_up(amount, now)?;
+
+        emit!(StreamToppedUp {
+            stream: stream.key(),
+            amount,
+            deposited: stream.deposited,
+            end_time: stream.end_time(),
+            timestamp: now,
+        });
+
+        msg!("Topped up: {} tokens", amount);
+        msg!("Streaming until {}", stream.end_time());
+
+        Ok(())
+    }
+
+    /// Settle and close: accrued funds go to the recipient, the rest back to
+    /// the sender. Either party may cancel.
+    pub fn cancel_stream(ctx: Context<CancelStream>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let stream = &ctx.accounts.stream;
+        let escrow_balance = token_account(
+            &ctx.accounts.escrow_token_account,
+            ErrorCode::InvalidTokenAccount,
+        )?
+        .amount;
+        let (to_recipient, to_sender) = stream.settle(now, escrow_balance);
+
+        for (destination, amount) in [
+            (&ctx.accounts.recipient_token_account, to_recipient),
+            (&ctx.accounts.sender_token_account, to_sender),
+        ] {
+            if amount > 0 {
+                transfer_from_escrow(
+                    stream,
+                    &ctx.accounts.escrow_token_account,
+                    destination,
+                    &ctx.accounts.stream.to_account_info(),
+                    &ctx.accounts.token_program,
+                    amount,
+                )?;
+            }
+        }
+
+        // Escrow rent goes back to the sender along with the stream's
+        let close_ix = token_instruction::close_account(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.escrow_token_account.key(),
+            &ctx.accounts.sender.key(),
+            &stream.key(),
+            &[],
+        )?;
+        let stream_id = stream.stream_id.to_le_bytes();
+        let seeds = &[
+            b"stream",
+            stream.sender.as_ref(),
+            stream.recipient.as_ref(),
+            stream_id.as_ref(),
+            &[stream.bump],
+        ];
+        invoke_signed(
+            &close_ix,
+            &[
+                ctx.accounts.escrow_token_account.to_account_info(),
+                ctx.accounts.sender.to_account_info(),
+                ctx.accounts.stream.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+            &[&seeds[..]],
+        )?;
+
+        emit!(StreamCancelled {
+            stream: stream.key(),
+            cancelled_by: ctx.accounts.signer.key(),
+            to_recipient,
+            to_sender,
+            timestamp: now,
+        });
+
+        msg!("Stream cancelled");
+        msg!("Paid to recipient: {} tokens", to_recipient);
+        msg!("Refunded to sender: {} tokens", to_sender);
+
+        Ok(())
+    }
+}
+
+/// Move `amount` out of the escrow, signed by the stream PDA
+fn transfer_from_escrow<'info>(
+    stream: &Stream,
+    escrow: &UncheckedAccount<'info>,
+    destination: &UncheckedAccount<'info>,
+    stream_info: &AccountInfo<'info>,
+    token_program: &UncheckedAccount<'info>,
+    amount: u64,
+) -> Result<()> {
+    let stream_id = stream.stream_id.to_le_bytes();
+    let seeds = &[
+        b"stream",
+        stream.sender.as_ref(),
+        stream.recipient.as_ref(),
+        stream_id.as_ref(),
+        &[stream.bump],
+    ];
+
+    let transfer_ix = token_instruction::transfer(
+        &token_program.key(),
+        &escrow.key(),
+        &destination.key(),
+        &stream_info.key(),
+        &[],
+        amount,
+    )?;
+
+    invoke_signed(
+        &transfer_ix,
+        &[
+            escrow.to_account_info(),
+            destination.to_account_info(),
+            stream_info.clone(),
+            token_program.to_account_info(),
+        ],
+        &[&seeds[..]],
+    )?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(stream_id: u64)]
+pub struct CreateStream<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Stream::INIT_SPACE,
+        seeds = [
+            b"stream",
+            sender.key().as_ref(),
+            recipient.key().as_ref(),
+            stream_id.to_le_bytes().as_ref(),
+        ],
+        bump
+    )]
+    pub stream: Account<'info, Stream>,
+
+    pub sender: Signer<'info>,
+
+    /// CHECK: Receiver of the stream
+    pub recipient: UncheckedAccount<'info>,
+
+    /// CHECK: Token mint (USDC)
+    pub mint: UncheckedAccount<'info>,
+
+    /// CHECK: Sender's token account, debited by the token program
+    #[account(mut)]
+    pub sender_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Token account owned by the stream PDA (usually its ATA)
+    #[account(mut)]
+    pub escrow_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Recipient's token account, checked in the handler
+    pub recipient_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Withdraw<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"stream",
+            stream.sender.as_ref(),
+            stream.recipient.as_ref(),
+            stream.stream_id.to_le_bytes().as_ref(),
+        ],
+        bump = stream.bump,
+    )]
+    pub stream: Account<'info, Stream>,
+
+    /// CHECK: Stream escrow
+    #[account(
+        mut,
+        constraint = escrow_token_account.key() == stream.escrow_token_account
+    )]
+    pub escrow_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Recipient's token account
+    #[account(
+        mut,
+        constraint = recipient_token_account.key() == stream.recipient_token_account
+    )]
+    pub recipient_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct TopUp<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"stream",
+            stream.sender.as_ref(),
+            stream.recipient.as_ref(),
+            stream.stream_id.to_le_bytes().as_ref(),
+        ],
+        bump = stream.bump,
+        has_one = sender
+    )]
+    pub stream: Account<'info, Stream>,
+
+    pub sender: Signer<'info>,
+
+    /// CHECK: Sender's token account
+    #[account(
+        mut,
+        constraint = sender_token_account.key() == stream.sender_token_account
+    )]
+    pub sender_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Stream escrow
+    #[account(
+        mut,
+        constraint = escrow_token_account.key() == stream.escrow_token_account
+    )]
+    pub escrow_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelStream<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"stream",
+            stream.sender.as_ref(),
+            stream.recipient.as_ref(),
+            stream.stream_id.to_le_bytes().as_ref(),
+        ],
+        bump = stream.bump,
+        has_one = sender,
+        close = sender
+    )]
+    pub stream: Account<'info, Stream>,
+
+    /// Sender or recipient
+    #[account(
+        constraint = signer.key() == stream.sender || signer.key() == stream.recipient
+            @ ErrorCode::Unauthorized
+    )]
+    pub signer: Signer<'info>,
+
+    /// CHECK: Receives the stream and escrow rent
+    #[account(mut)]
+    pub sender: UncheckedAccount<'info>,
+
+    /// CHECK: Sender's token account, receives the refund
+    #[account(
+        mut,
+        constraint = sender_token_account.key() == stream.sender_token_account
+    )]
+    pub sender_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Recipient's token account, receives what has accrued
+    #[account(
+        mut,
+        constraint = recipient_token_account.key() == stream.recipient_token_account
+    )]
+    pub recipient_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Stream escrow, closed here
+    #[account(
+        mut,
+        constraint = escrow_token_account.key() == stream.escrow_token_account
+    )]
+    pub escrow_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Stream {
+    pub sender: Pubkey,
+    pub recipient: Pubkey,
+    pub mint: Pubkey,
+    pub sender_token_account: Pubkey,
+    pub recipient_token_account: Pubkey,
+    pub escrow_token_account: Pubkey,
+    pub stream_id: u64,
+    pub rate_per_second: u64,
+    pub start_time: i64,
+    pub deposited: u64,
+    pub withdrawn: u64,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl Stream {
+    pub fn check_params(deposit: u64, rate_per_second: u64) -> Result<()> {
+        require!(deposit > 0, ErrorCode::InvalidAmount);
+        require!(rate_per_second > 0, ErrorCode::InvalidRate);
+        Ok(())
+    }
+
+    /// Total released to the recipient by `now`, withdrawn or not
+    pub fn streamed_at(&self, now: i64) -> u64 {
+        let elapsed = now.saturating_sub(self.start_time).max(0) as u128;
+        elapsed
+            .saturating_mul(self.rate_per_second as u128)
+            .min(self.deposited as u128) as u64
+    }
+
+    pub fn withdrawable_at(&self, now: i64) -> u64 {
+        self.streamed_at(now).saturating_sub(self.withdrawn)
+    }
+
+    /// First second at which the whole deposit has streamed
+    pub fn end_time(&self) -> i64 {
+        let seconds = self.deposited.div_ceil(self.rate_per_second);
+        self.start_time
+            .saturating_add(i64::try_from(seconds).unwrap_or(i64::MAX))
+    }
+
+    /// The amount a withdrawal at `now` pays out: `requested`, or everything
+    /// accrued if `None`
+    pub fn check_withdrawal(&self, now: i64, requested: Option<u64>) -> Result<u64> {
+        let available = self.withdrawable_at(now);
+        require!(available > 0, ErrorCode::NothingToWithdraw);
+        let amount = requested.unwrap_or(available);
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        require!(amount <= available, ErrorCode::WithdrawalExceedsAccrued);
+        Ok(amount)
+    }
+
+    pub fn record_withdrawal(&mut self, amount: u64) -> Result<()> {
+        self.withdrawn = self
+            .withdrawn
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+
+    pub fn record_top_up(&mut self, amount: u64, now: i64) -> Result<()> {
+        require!(now < self.end_time(), ErrorCode::StreamEnded);
+        self.deposited = self
+            .deposited
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+
+    /// (owed to the recipient, refunded to the sender) if cancelled at `now`.
+    /// Split from the escrow's actual balance so tokens sent to it directly are
+    /// refunded instead of blocking the close.
+    pub fn settle(&self, now: i64, escrow_balance: u64) -> (u64, u64) {
+        let to_recipient = self.withdrawable_at(now).min(escrow_balance);
+        (to_recipient, escrow_balance - to_recipient)
+    }
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamCreated {
+    pub stream: Pubkey,
+    pub sender: Pubkey,
+    pub recipient: Pubkey,
+    pub deposit: u64,
+    pub rate_per_second: u64,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamWithdrawn {
+    pub stream: Pubkey,
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub total_withdrawn: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamToppedUp {
+    pub stream: Pubkey,
+    pub amount: u64,
+    pub deposited: u64,
+    pub end_time: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamCancelled {
+    pub stream: Pubkey,
+    pub cancelled_by: Pubkey,
+    pub to_recipient: u64,
+    pub to_sender: u64,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Rate per second must be greater than zero")]
+    InvalidRate,
+    #[msg("Amount must be greater than zero")]
+    InvalidAmount,
+    #[msg("Invalid token account - wrong program, owner or mint")]
+    InvalidTokenAccount,
+    #[msg("Nothing has accrued since the last withdrawal")]
+    NothingToWithdraw,
+    #[msg("Requested more than has accrued")]
+    WithdrawalExceedsAccrued,
+    #[msg("Stream has fully streamed - create a new one")]
+    StreamEnded,
+    #[msg("Only the sender or recipient can cancel")]
+    Unauthorized,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
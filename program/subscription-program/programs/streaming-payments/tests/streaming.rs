@@ -0,0 +1,392 @@
+//! Native tests for the streaming payments program.
+//!
+//! Accrual and settlement are pure methods on `Stream` and are tested
+//! directly. Instruction tests run end to end and check where the tokens
+//! went.
+
+use anchor_lang::error::ErrorCode as AnchorErrorCode;
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, Space, ToAccountMetas};
+use streaming_payments::{accounts, instruction, ErrorCode, Stream, ID as PROGRAM_ID};
+use test_harness::{assert_error, assert_success, Keypair, Signer, TestSvm, TransactionResult};
+
+const DEPOSIT: u64 = 3_600_000;
+/// 1 USDC per hour
+const RATE: u64 = 1_000;
+const STREAM_ID: u64 = 7;
+
+fn stream_at(start_time: i64) -> Stream {
+    Stream {
+        sender: Pubkey::new_unique(),
+        recipient: Pubkey::new_unique(),
+        mint: Pubkey::new_unique(),
+        sender_token_account: Pubkey::new_unique(),
+        recipient_token_account: Pubkey::new_unique(),
+        escrow_token_account: Pubkey::new_unique(),
+        stream_id: STREAM_ID,
+        rate_per_second: RATE,
+        start_time,
+        deposited: DEPOSIT,
+        withdrawn: 0,
+        created_at: start_time,
+        bump: 255,
+    }
+}
+
+#[test]
+fn streams_linearly_and_caps_at_deposit() {
+    let stream = stream_at(1_000);
+    assert_eq!(stream.streamed_at(0), 0);
+    assert_eq!(stream.streamed_at(1_000), 0);
+    assert_eq!(stream.streamed_at(1_010), 10 * RATE);
+    assert_eq!(stream.end_time(), 1_000 + 3_600);
+    assert_eq!(stream.streamed_at(stream.end_time()), DEPOSIT);
+    assert_eq!(stream.streamed_at(i64::MAX), DEPOSIT);
+}
+
+#[test]
+fn end_time_rounds_up_a_partial_second() {
+    let mut stream = stream_at(0);
+    stream.deposited = 1_000;
+    stream.rate_per_second = 3;
+    assert_eq!(stream.end_time(), 334);
+    assert_eq!(stream.streamed_at(333), 999);
+    assert_eq!(stream.streamed_at(334), 1_000);
+}
+
+#[test]
+fn rejects_empty_deposit_and_zero_rate() {
+    assert_eq!(
+        Stream::check_params(0, RATE).unwrap_err(),
+        ErrorCode::InvalidAmount.into()
+    );
+    assert_eq!(
+        Stream::check_params(DEPOSIT, 0).unwrap_err(),
+        ErrorCode::InvalidRate.into()
+    );
+}
+
+#[test]
+fn withdrawals_take_at_most_what_has_accrued() {
+    let mut stream = stream_at(0);
+    assert_eq!(
+        stream.check_withdrawal(0, None).unwrap_err(),
+        ErrorCode::NothingToWithdraw.into()
+    );
+
+    assert_eq!(stream.check_withdrawal(100, None).unwrap(), 100 * RATE);
+    assert_eq!(stream.check_withdrawal(100, Some(RATE)).unwrap(), RATE);
+    assert_eq!(
+        stream
+            .check_withdrawal(100, Some(100 * RATE + 1))
+            .unwrap_err(),
+        ErrorCode::WithdrawalExceedsAccrued.into()
+    );
+
+    stream.record_withdrawal(100 * RATE).unwrap();
+    assert_eq!(stream.withdrawable_at(100), 0);
+    assert_eq!(stream.withdrawable_at(150), 50 * RATE);
+}
+
+#[test]
+fn top_up_extends_a_running_stream_only() {
+    let mut stream = stream_at(0);
+    stream.record_top_up(DEPOSIT, 10).unwrap();
+    assert_eq!(stream.end_time(), 7_200);
+
+    let ended = stream.end_time();
+    assert_eq!(
+        stream.record_top_up(DEPOSIT, ended).unwrap_err(),
+        ErrorCode::StreamEnded.into()
+    );
+}
+
+#[test]
+fn settle_splits_the_escrow_balance() {
+    let mut stream = stream_at(0);
+    stream.record_withdrawal(10 * RATE).unwrap();
+    let escrow = DEPOSIT - 10 * RATE;
+
+    assert_eq!(stream.settle(60, escrow), (50 * RATE, escrow - 50 * RATE));
+    // Tokens sent to the escrow directly go back to the sender
+    assert_eq!(
+        stream.settle(60, escrow + 5),
+        (50 * RATE, escrow - 50 * RATE + 5)
+    );
+    assert_eq!(stream.settle(stream.end_time(), escrow), (escrow, 0));
+}
+
+struct Fixture {
+    svm: TestSvm,
+    payer: Keypair,
+    sender: Keypair,
+    recipient: Keypair,
+    stream: Pubkey,
+    state: Stream,
+}
+
+impl Fixture {
+    /// A stream that started at the harness's default time, escrow funded,
+    /// with as much again left in the sender's account
+    fn new() -> Self {
+        let (mut svm, payer) = TestSvm::for_program(PROGRAM_ID, streaming_payments::entry);
+
+        let sender = Keypair::new();
+        let recipient = Keypair::new();
+
+        let mint = svm.create_mint(&Pubkey::new_unique(), 6);
+        let (stream, bump) = Pubkey::find_program_address(
+            &[
+                b"stream",
+                sender.pubkey().as_ref(),
+                recipient.pubkey().as_ref(),
+                &STREAM_ID.to_le_bytes(),
+            ],
+            &PROGRAM_ID,
+        );
+        let now = svm.clock().unix_timestamp;
+        let state = Stream {
+            sender: sender.pubkey(),
+            recipient: recipient.pubkey(),
+            mint,
+            sender_token_account: svm.create_associated_token_account(
+                &sender.pubkey(),
+                &mint,
+                DEPOSIT,
+            ),
+            recipient_token_account: svm.create_associated_token_account(
+                &recipient.pubkey(),
+                &mint,
+                0,
+            ),
+            escrow_token_account: svm.create_associated_token_account(&stream, &mint, DEPOSIT),
+            bump,
+            ..stream_at(now)
+        };
+        svm.set_anchor_account(stream, &state, 8 + Stream::INIT_SPACE);
+
+        Self {
+            svm,
+            payer,
+            sender,
+            recipient,
+            stream,
+            state,
+        }
+    }
+
+    fn create(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CreateStream {
+                stream: self.stream,
+                sender: self.state.sender,
+                recipient: self.state.recipient,
+                mint: self.state.mint,
+                sender_token_account: self.state.sender_token_account,
+                escrow_token_account: self.state.escrow_token_account,
+                recipient_token_account: self.state.recipient_token_account,
+                token_program: spl_token::ID,
+                payer: self.payer.pubkey(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CreateStream {
+                stream_id: STREAM_ID,
+                deposit: DEPOSIT,
+                rate_per_second: RATE,
+                start_time: Some(0),
+            }
+            .data(),
+        }
+    }
+
+    fn withdraw(&self, amount: Option<u64>) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::Withdraw {
+                stream: self.stream,
+                escrow_token_account: self.state.escrow_token_account,
+                recipient_token_account: self.state.recipient_token_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::Withdraw { amount }.data(),
+        }
+    }
+
+    fn top_up(&self, sender: &Pubkey) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::TopUp {
+                stream: self.stream,
+                sender: *sender,
+                sender_token_account: self.state.sender_token_account,
+                escrow_token_account: self.state.escrow_token_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::TopUp { amount: DEPOSIT }.data(),
+        }
+    }
+
+    fn cancel(&self, signer: &Pubkey) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CancelStream {
+                stream: self.stream,
+                signer: *signer,
+                sender: self.state.sender,
+                sender_token_account: self.state.sender_token_account,
+                recipient_token_account: self.state.recipient_token_account,
+                escrow_token_account: self.state.escrow_token_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CancelStream {}.data(),
+        }
+    }
+
+    fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TransactionResult {
+        let payer = self.payer.insecure_clone();
+        self.svm.send_instructions(&[instruction], &payer, signers)
+    }
+
+    fn stream(&self) -> Stream {
+        self.svm.get_anchor_account(&self.stream).unwrap()
+    }
+
+    fn balances(&self) -> (u64, u64, u64) {
+        (
+            self.svm.token_balance(&self.state.sender_token_account),
+            self.svm.token_balance(&self.state.escrow_token_account),
+            self.svm.token_balance(&self.state.recipient_token_account),
+        )
+    }
+}
+
+#[test]
+fn creating_a_stream_escrows_the_deposit() {
+    let mut fx = Fixture::new();
+    fx.svm.remove_account(&fx.stream);
+    let (sender_tokens, escrow_tokens, _) = fx.balances();
+    fx.svm.transfer_tokens(
+        &fx.state.escrow_token_account,
+        &fx.state.sender_token_account,
+        escrow_tokens,
+    );
+
+    let mut elsewhere = fx.create();
+    elsewhere.accounts[6].pubkey = fx.state.sender_token_account;
+    let sender = fx.sender.insecure_clone();
+    let result = fx.send(elsewhere, &[&sender]);
+    assert_error(result, ErrorCode::InvalidTokenAccount);
+
+    let result = fx.send(fx.create(), &[&sender]);
+    assert_success(result);
+    assert_eq!(fx.balances(), (sender_tokens, escrow_tokens, 0));
+    let stream = fx.stream();
+    assert_eq!(stream.deposited, DEPOSIT);
+    assert_eq!(stream.withdrawn, 0);
+    // A start in the past is moved up to now
+    assert_eq!(stream.start_time, fx.svm.clock().unix_timestamp);
+    assert_eq!(stream.escrow_token_account, fx.state.escrow_token_account);
+}
+
+#[test]
+fn withdraw_needs_accrued_funds() {
+    let mut fx = Fixture::new();
+    let result = fx.send(fx.withdraw(None), &[]);
+    assert_error(result, ErrorCode::NothingToWithdraw);
+
+    fx.svm.advance_time(60);
+    let result = fx.send(fx.withdraw(Some(61 * RATE)), &[]);
+    assert_error(result, ErrorCode::WithdrawalExceedsAccrued);
+}
+
+#[test]
+fn anyone_can_withdraw_to_the_recipient() {
+    let mut fx = Fixture::new();
+    fx.svm.advance_time(60);
+    // Only the payer signs: a keeper or paymaster can crank withdrawals
+    let result = fx.send(fx.withdraw(None), &[]);
+    assert_success(result);
+    assert_eq!(fx.balances(), (DEPOSIT, DEPOSIT - 60 * RATE, 60 * RATE));
+    assert_eq!(fx.stream().withdrawn, 60 * RATE);
+}
+
+#[test]
+fn withdraw_rejects_another_destination() {
+    let mut fx = Fixture::new();
+    fx.svm.advance_time(60);
+    let attacker = fx
+        .svm
+        .create_token_account(&Pubkey::new_unique(), &fx.state.mint, 0);
+    let mut withdraw = fx.withdraw(None);
+    withdraw.accounts[2].pubkey = attacker;
+
+    let result = fx.send(withdraw, &[]);
+    assert_error(result, AnchorErrorCode::ConstraintRaw);
+}
+
+#[test]
+fn only_the_sender_tops_up() {
+    let mut fx = Fixture::new();
+    let intruder = Keypair::new();
+    let result = fx.send(fx.top_up(&intruder.pubkey()), &[&intruder]);
+    assert_error(result, AnchorErrorCode::ConstraintHasOne);
+
+    let sender = fx.sender.insecure_clone();
+    let result = fx.send(fx.top_up(&sender.pubkey()), &[&sender]);
+    assert_success(result);
+    assert_eq!(fx.balances(), (0, 2 * DEPOSIT, 0));
+    assert_eq!(fx.stream().deposited, 2 * DEPOSIT);
+    assert_eq!(fx.stream().end_time(), fx.state.start_time + 7_200);
+}
+
+#[test]
+fn top_up_after_the_end_is_rejected() {
+    let mut fx = Fixture::new();
+    fx.svm.warp_to_timestamp(fx.state.end_time());
+    let sender = fx.sender.insecure_clone();
+    let result = fx.send(fx.top_up(&sender.pubkey()), &[&sender]);
+    assert_error(result, ErrorCode::StreamEnded);
+}
+
+#[test]
+fn either_party_cancels_but_nobody_else() {
+    let mut fx = Fixture::new();
+    fx.svm.advance_time(60);
+
+    let intruder = Keypair::new();
+    let result = fx.send(fx.cancel(&intruder.pubkey()), &[&intruder]);
+    assert_error(result, ErrorCode::Unauthorized);
+
+    let recipient = fx.recipient.insecure_clone();
+    let result = fx.send(fx.cancel(&recipient.pubkey()), &[&recipient]);
+    assert_success(result);
+    assert_settled(&fx, DEPOSIT + DEPOSIT - 60 * RATE, 60 * RATE);
+
+    let mut fx = Fixture::new();
+    fx.svm.advance_time(90);
+    let sender = fx.sender.insecure_clone();
+    let result = fx.send(fx.cancel(&sender.pubkey()), &[&sender]);
+    assert_success(result);
+    assert_settled(&fx, DEPOSIT + DEPOSIT - 90 * RATE, 90 * RATE);
+}
+
+/// The accrued part paid out, the rest returned, and the escrow and stream
+/// closed to the sender
+fn assert_settled(fx: &Fixture, sender_tokens: u64, recipient_tokens: u64) {
+    assert_eq!(
+        fx.svm.token_balance(&fx.state.sender_token_account),
+        sender_tokens
+    );
+    assert_eq!(
+        fx.svm.token_balance(&fx.state.recipient_token_account),
+        recipient_tokens
+    );
+    assert!(fx.svm.get_account(&fx.state.escrow_token_account).is_none());
+    assert!(fx.svm.get_account(&fx.stream).is_none());
+}
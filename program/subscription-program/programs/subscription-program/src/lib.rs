@@ -1,9 +1,46 @@
 use anchor_lang::prelude::*;
-use anchor_lang::solana_program::program::invoke_signed;
+use anchor_lang::solana_program::instruction::{
+    get_stack_height, AccountMeta, Instruction, TRANSACTION_LEVEL_STACK_HEIGHT,
+};
+use anchor_lang::solana_program::program::{invoke_signed, set_return_data};
+use anchor_lang::solana_program::program_option::COption;
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::solana_program::pubkey::MAX_SEED_LEN;
+use anchor_lang::solana_program::sysvar::instructions::get_instruction_relative;
+use anchor_lang::InstructionData;
 use spl_token::instruction as token_instruction;
 
 declare_id!("3kZ9Fdzadk8NXwjHaSabKrXBsU1y226BgXJdHZ78Qx4v");
 
+/// LazorKit smart-wallet program. It signs for a wallet's PDA only after
+/// verifying the owner's passkey (secp256r1) signature.
+pub const LAZORKIT_PROGRAM_ID: Pubkey = pubkey!("Gsuz7YcA5sbMGVRXT3xSYhJBessW4xFC4xYsihNCqMFh");
+
+/// Clockwork thread program (v2). A thread runs its instructions whenever its
+/// trigger fires, paying automation fees from its own lamports.
+pub const CLOCKWORK_THREAD_PROGRAM_ID: Pubkey =
+    pubkey!("CLoCKyJ6DXBJqqu2VWx9RLbgnwwR6BMHHuyasVmfMzBh");
+
+/// Switchboard attestation program. It owns the accounts of Switchboard
+/// Functions, containers run by the oracle network inside an enclave.
+pub const SWITCHBOARD_ATTESTATION_PROGRAM_ID: Pubkey =
+    pubkey!("sbattyXrzedoNATfc4L31wC9Mhxsi1BmFhTiN8gDshx");
+
+/// Helium's tuktuk program. Crankers run the tasks in a task queue once
+/// their trigger is reached, for a reward paid from the queue.
+pub const TUKTUK_PROGRAM_ID: Pubkey = pubkey!("tuktukUrfhXT6ZT77QTU8RQtvgL967uRuVagWF57zVA");
+
+/// Squads v4 multisig program. A multisig's vaults are PDAs it signs for only
+/// when a vault transaction has reached the multisig's approval threshold.
+pub const SQUADS_PROGRAM_ID: Pubkey = pubkey!("SQDS4ep65T869zMMBKyuUq6aD6EgTu8psMjkvj52pCf");
+
+/// Anchor discriminator of a Squads v4 `Multisig` account
+pub const SQUADS_MULTISIG_DISCRIMINATOR: [u8; 8] = [224, 116, 121, 186, 68, 161, 79, 236];
+
+/// Pyth Solana receiver program. It owns the `PriceUpdateV2` accounts that
+/// Pyth's pull oracle posts verified prices to.
+pub const PYTH_RECEIVER_PROGRAM_ID: Pubkey = pubkey!("rec5EKMGg6MxZYaMdyBfgwp4d5rB9T1VQH5pJv5LtFJ");
+
 #[program]
 pub mod subscription_program {
     use super::*;
@@ -15,193 +52,349 @@ pub mod subscription_program {
         interval_seconds: i64,
         expires_at: Option<i64>,
     ) -> Result<()> {
-        let clock = Clock::get()?;
+        open_subscription(
+            OpenSubscription {
+                subscription: &mut ctx.accounts.subscription,
+                authority: ctx.accounts.authority.to_account_info(),
+                recipient: ctx.accounts.recipient.to_account_info(),
+                user_token_account: ctx.accounts.user_token_account.to_account_info(),
+                recipient_token_account: ctx.accounts.recipient_token_account.to_account_info(),
+                token_mint: ctx.accounts.token_mint.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+                denylist_entry: ctx.accounts.denylist_entry.to_account_info(),
+                allowlist: ctx.accounts.allowlist.to_account_info(),
+                allowlist_entry: ctx.accounts.allowlist_entry.to_account_info(),
+                approve: true,
+                bump: ctx.bumps.subscription,
+            },
+            amount_per_period,
+            interval_seconds,
+            expires_at,
+            0,
+        )
+    }
 
-        // ========== STEP 1: DELEGATE TOKEN ACCOUNT ==========
-        // This MUST happen before we charge, so PDA can act as delegate
-        let delegate_ix = token_instruction::approve(
-            &ctx.accounts.token_program.key(),
-            &ctx.accounts.user_token_account.key(),
-            &ctx.accounts.subscription.key(),
+    /// Same as `initialize_subscription`, for a LazorKit passkey wallet.
+    /// `authority` is the smart-wallet PDA, so this must be called by CPI from
+    /// the wallet program, which signs for the PDA once the passkey checks out.
+    /// Cancel and update need no such path: a PDA signed for by CPI already
+    /// satisfies their `Signer` check.
+    pub fn initialize_wallet_subscription(
+        ctx: Context<InitializeWalletSubscription>,
+        amount_per_period: u64,
+        interval_seconds: i64,
+        expires_at: Option<i64>,
+    ) -> Result<()> {
+        let top_level = get_instruction_relative(0, &ctx.accounts.instructions)?;
+        check_wallet_authority(
             &ctx.accounts.authority.key(),
-            &[],
-            u64::MAX,
+            ctx.accounts.authority.is_signer,
+            &top_level.program_id,
+            get_stack_height(),
         )?;
 
-        anchor_lang::solana_program::program::invoke(
-            &delegate_ix,
-            &[
-                ctx.accounts.user_token_account.to_account_info(),
-                ctx.accounts.subscription.to_account_info(),
-                ctx.accounts.authority.to_account_info(),
-                ctx.accounts.token_program.to_account_info(),
-            ],
+        open_subscription(
+            OpenSubscription {
+                subscription: &mut ctx.accounts.subscription,
+                authority: ctx.accounts.authority.to_account_info(),
+                recipient: ctx.accounts.recipient.to_account_info(),
+                user_token_account: ctx.accounts.user_token_account.to_account_info(),
+                recipient_token_account: ctx.accounts.recipient_token_account.to_account_info(),
+                token_mint: ctx.accounts.token_mint.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+                denylist_entry: ctx.accounts.denylist_entry.to_account_info(),
+                allowlist: ctx.accounts.allowlist.to_account_info(),
+                allowlist_entry: ctx.accounts.allowlist_entry.to_account_info(),
+                approve: true,
+                bump: ctx.bumps.subscription,
+            },
+            amount_per_period,
+            interval_seconds,
+            expires_at,
+            0,
         )?;
 
-        // ========== STEP 2: CHARGE FIRST PAYMENT IMMEDIATELY ==========
-        // Get PDA info BEFORE borrowing subscription mutably
-        let authority_key = ctx.accounts.authority.key();
-        let recipient_key = ctx.accounts.recipient.key();
-        let subscription_key = ctx.accounts.subscription.key();
-        let bump = ctx.bumps.subscription;
+        msg!("Authority is a LazorKit smart wallet");
 
-        let seeds = &[
-            b"subscription",
-            authority_key.as_ref(),
-            recipient_key.as_ref(),
-            &[bump],
-        ];
-        let signer_seeds = &[&seeds[..]];
+        Ok(())
+    }
 
-        // Transfer first payment using PDA as delegate
-        let transfer_ix = token_instruction::transfer(
-            &ctx.accounts.token_program.key(),
-            &ctx.accounts.user_token_account.key(),
-            &ctx.accounts.recipient_token_account.key(),
-            &subscription_key,
-            &[],
+    /// `initialize_subscription` with a one-time `setup_fee` taken with the
+    /// first period. The fee is recorded in a `SetupFee` account and left
+    /// out of `total_charged`, which stays recurring revenue only.
+    pub fn initialize_subscription_with_setup_fee(
+        ctx: Context<InitializeSubscriptionWithSetupFee>,
+        amount_per_period: u64,
+        interval_seconds: i64,
+        expires_at: Option<i64>,
+        setup_fee: u64,
+    ) -> Result<()> {
+        require!(setup_fee > 0, ErrorCode::InvalidAmount);
+        ctx.accounts.setup_fee.set_inner(SetupFee {
+            subscription: ctx.accounts.init.subscription.key(),
+            amount: setup_fee,
+            paid_at: Clock::get()?.unix_timestamp,
+            bump: ctx.bumps.setup_fee,
+        });
+        ctx.accounts.setup_fee.exit(&crate::ID)?;
+
+        let accounts = &mut ctx.accounts.init;
+        open_subscription(
+            OpenSubscription {
+                subscription: &mut accounts.subscription,
+                authority: accounts.authority.to_account_info(),
+                recipient: accounts.recipient.to_account_info(),
+                user_token_account: accounts.user_token_account.to_account_info(),
+                recipient_token_account: accounts.recipient_token_account.to_account_info(),
+                token_mint: accounts.token_mint.to_account_info(),
+                token_program: accounts.token_program.to_account_info(),
+                denylist_entry: accounts.denylist_entry.to_account_info(),
+                allowlist: accounts.allowlist.to_account_info(),
+                allowlist_entry: accounts.allowlist_entry.to_account_info(),
+                approve: true,
+                bump: ctx.bumps.init.subscription,
+            },
             amount_per_period,
+            interval_seconds,
+            expires_at,
+            setup_fee,
+        )
+    }
+
+    /// `initialize_wallet_subscription` with a one-time `setup_fee`, as in
+    /// `initialize_subscription_with_setup_fee`
+    pub fn initialize_wallet_subscription_with_setup_fee(
+        ctx: Context<InitializeWalletSubscriptionWithSetupFee>,
+        amount_per_period: u64,
+        interval_seconds: i64,
+        expires_at: Option<i64>,
+        setup_fee: u64,
+    ) -> Result<()> {
+        require!(setup_fee > 0, ErrorCode::InvalidAmount);
+        let accounts = &mut ctx.accounts.init;
+        let top_level = get_instruction_relative(0, &accounts.instructions)?;
+        check_wallet_authority(
+            &accounts.authority.key(),
+            accounts.authority.is_signer,
+            &top_level.program_id,
+            get_stack_height(),
         )?;
 
-        invoke_signed(
-            &transfer_ix,
-            &[
-                ctx.accounts.user_token_account.to_account_info(),
-                ctx.accounts.recipient_token_account.to_account_info(),
-                ctx.accounts.subscription.to_account_info(),
-                ctx.accounts.token_program.to_account_info(),
-            ],
-            signer_seeds,
+        ctx.accounts.setup_fee.set_inner(SetupFee {
+            subscription: ctx.accounts.init.subscription.key(),
+            amount: setup_fee,
+            paid_at: Clock::get()?.unix_timestamp,
+            bump: ctx.bumps.setup_fee,
+        });
+        ctx.accounts.setup_fee.exit(&crate::ID)?;
+
+        let accounts = &mut ctx.accounts.init;
+        open_subscription(
+            OpenSubscription {
+                subscription: &mut accounts.subscription,
+                authority: accounts.authority.to_account_info(),
+                recipient: accounts.recipient.to_account_info(),
+                user_token_account: accounts.user_token_account.to_account_info(),
+                recipient_token_account: accounts.recipient_token_account.to_account_info(),
+                token_mint: accounts.token_mint.to_account_info(),
+                token_program: accounts.token_program.to_account_info(),
+                denylist_entry: accounts.denylist_entry.to_account_info(),
+                allowlist: accounts.allowlist.to_account_info(),
+                allowlist_entry: accounts.allowlist_entry.to_account_info(),
+                approve: true,
+                bump: ctx.bumps.init.subscription,
+            },
+            amount_per_period,
+            interval_seconds,
+            expires_at,
+            setup_fee,
         )?;
 
-        // ========== STEP 3: INITIALIZE SUBSCRIPTION STATE ==========
-        // NOW we can mutably borrow subscription to set its state
-        let subscription = &mut ctx.accounts.subscription;
-        subscription.authority = authority_key;
-        subscription.recipient = recipient_key;
-        subscription.user_token_account = ctx.accounts.user_token_account.key();
-        subscription.recipient_token_account = ctx.accounts.recipient_token_account.key();
-        subscription.token_mint = ctx.accounts.token_mint.key();
-        subscription.amount_per_period = amount_per_period;
-        subscription.interval_seconds = interval_seconds;
-        subscription.last_charge_timestamp = clock.unix_timestamp; // ← Set to NOW for prepaid
-        subscription.created_at = clock.unix_timestamp;
-        subscription.expires_at = expires_at;
-        subscription.is_active = true;
-        subscription.total_charged = amount_per_period; // ← Already charged first payment
-        subscription.bump = bump;
-
-        msg!("Subscription initialized with PREPAID model!");
-        msg!("First payment charged: {} tokens", amount_per_period);
-        msg!("Next charge in {} seconds (30 days)", interval_seconds);
-        msg!("Token account delegated to subscription PDA");
+        msg!("Authority is a LazorKit smart wallet");
 
         Ok(())
     }
 
-    /// Charge the subscription (for recurring payments after first payment)
-    pub fn charge_subscription(ctx: Context<ChargeSubscription>) -> Result<()> {
-        let subscription = &mut ctx.accounts.subscription;
-        let clock = Clock::get()?;
-        let current_time = clock.unix_timestamp;
+    /// Charge the subscription (for recurring payments after first payment).
+    /// The recipient's `MerchantConfig` can be passed as the first remaining
+    /// account. With partial charges on, a user balance below the period
+    /// amount is taken as is and the shortfall recorded; with catch-up on,
+    /// up to `max_periods_per_charge` missed periods are settled at once.
+    /// The subscription's `FundingSources` and its token accounts can follow
+    /// (with the program ID in the first slot if there is no config); they
+    /// are drawn from in order when the primary account is short. Its
+    /// `SlaCredit` receipts with a pending discount go last, writable, and
+    /// come off the first period. A subscription on a plan must pass its
+    /// `PlanSubscription`, writable, before them.
+    pub fn charge_subscription<'info>(
+        ctx: Context<'_, '_, '_, 'info, ChargeSubscription<'info>>,
+    ) -> Result<()> {
+        charge(ctx.accounts, ctx.remaining_accounts, None, None, None)
+    }
 
-        require!(subscription.is_active, ErrorCode::SubscriptionInactive);
+    /// `charge_subscription` with a `reference`, such as an order or invoice
+    /// ID, copied into each `SubscriptionCharged` so the merchant can match
+    /// the charge to its own records. The accounts and remaining accounts
+    /// are the same.
+    pub fn charge_subscription_with_reference<'info>(
+        ctx: Context<'_, '_, '_, 'info, ChargeSubscription<'info>>,
+        reference: [u8; 32],
+    ) -> Result<()> {
+        charge(ctx.accounts, ctx.remaining_accounts, None, Some(reference), None)
+    }
 
-        if let Some(expires_at) = subscription.expires_at {
-            require!(current_time < expires_at, ErrorCode::SubscriptionExpired);
-        }
+    /// Charge a subscription whose user put their token account behind a
+    /// spending-limits policy. The policy PDA holds the delegation instead
+    /// of the subscription, so the transfer goes through the policy's
+    /// `spend`, signed by the subscription PDA as a listed spender, and fails
+    /// if it would break the user's daily or weekly limit. A subscription on
+    /// a plan passes its `PlanSubscription`, writable, as the remaining
+    /// account, as with `charge_subscription`.
+    pub fn charge_subscription_with_policy<'info>(
+        ctx: Context<'_, '_, '_, 'info, ChargeSubscriptionWithPolicy<'info>>,
+    ) -> Result<()> {
+        let subscription = &mut ctx.accounts.subscription;
+        let current_time = Clock::get()?.unix_timestamp;
 
-        let time_since_last_charge = current_time - subscription.last_charge_timestamp;
-        require!(
-            time_since_last_charge >= subscription.interval_seconds,
-            ErrorCode::IntervalNotMet
-        );
+        subscription.check_chargeable(current_time)?;
 
-        require_keys_eq!(
-            *ctx.accounts.user_token_account.owner,
-            spl_token::ID,
-            ErrorCode::InvalidTokenAccount
-        );
         require_keys_eq!(
             *ctx.accounts.recipient_token_account.owner,
             spl_token::ID,
             ErrorCode::InvalidTokenAccount
         );
 
+        let (_, mut schedule) = plan_schedule(ctx.remaining_accounts, &subscription.key())?;
+        require!(
+            schedule.is_some() || !subscription.on_plan,
+            ErrorCode::PlanSubscriptionRequired
+        );
+        let stepped = match schedule.as_mut() {
+            Some((_, plan_subscription)) => plan_subscription.apply_price(subscription),
+            None => false,
+        };
+
         let amount = subscription.amount_per_period;
+        require!(
+            amount <= subscription.spend_left(),
+            ErrorCode::SpendLimitExceeded
+        );
         let authority_key = subscription.authority;
         let recipient_key = subscription.recipient;
         let bump = subscription.bump;
 
+        // As in `charge_subscription`: the period is booked before the CPI
+        subscription.book_periods(current_time, &[amount], false)?;
+        subscription.exit(&crate::ID)?;
+        if let Some((account, plan_subscription)) = schedule.as_ref().filter(|_| stepped) {
+            plan_subscription.try_serialize(&mut &mut account.try_borrow_mut_data()?[..])?;
+        }
+
         let seeds = &[
             b"subscription",
             authority_key.as_ref(),
             recipient_key.as_ref(),
             &[bump],
         ];
-        let signer_seeds = &[&seeds[..]];
 
-        let subscription_key = ctx.accounts.subscription.key();
-
-        let transfer_ix = token_instruction::transfer(
-            &ctx.accounts.token_program.key(),
-            &ctx.accounts.user_token_account.key(),
-            &ctx.accounts.recipient_token_account.key(),
-            &subscription_key,
-            &[],
+        spending_limits::cpi::spend(
+            CpiContext::new_with_signer(
+                ctx.accounts.spending_limits_program.to_account_info(),
+                spending_limits::cpi::accounts::Spend {
+                    policy: ctx.accounts.policy.to_account_info(),
+                    spender: ctx.accounts.subscription.to_account_info(),
+                    token_account: ctx.accounts.user_token_account.to_account_info(),
+                    destination: ctx.accounts.recipient_token_account.to_account_info(),
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                },
+                &[&seeds[..]],
+            ),
             amount,
         )?;
 
-        invoke_signed(
-            &transfer_ix,
-            &[
-                ctx.accounts.user_token_account.to_account_info(),
-                ctx.accounts.recipient_token_account.to_account_info(),
-                ctx.accounts.subscription.to_account_info(),
-                ctx.accounts.token_program.to_account_info(),
-            ],
-            signer_seeds,
-        )?;
+        let subscription = &ctx.accounts.subscription;
 
-        let subscription = &mut ctx.accounts.subscription;
-        subscription.last_charge_timestamp = current_time;
-        subscription.total_charged += amount;
+        if let Some((_, plan_subscription)) = schedule.as_ref().filter(|_| stepped) {
+            emit!(ScheduledPriceApplied {
+                subscription: subscription.key(),
+                plan: plan_subscription.plan,
+                amount_per_period: amount,
+                timestamp: current_time,
+            });
+            msg!("Scheduled price: {} tokens", amount);
+        }
 
-        msg!("Subscription charged!");
+        emit!(SubscriptionCharged {
+            subscription: subscription.key(),
+            authority: authority_key,
+            recipient: recipient_key,
+            amount,
+            total_charged: subscription.total_charged,
+            reference: None,
+            timestamp: current_time,
+        });
+
+        msg!("Subscription charged through spending policy");
         msg!("Amount: {} tokens", amount);
         msg!("Total charged: {} tokens", subscription.total_charged);
 
         Ok(())
     }
 
-    /// Cancel subscription - revokes delegation and closes account
-    pub fn cancel_subscription(ctx: Context<CancelSubscription>) -> Result<()> {
+    /// Cancel subscription - revokes delegation and closes account. A
+    /// subscription on a plan passes its `PlanSubscription` and `Plan` as
+    /// remaining accounts, both writable, to give up its seat.
+    pub fn cancel_subscription<'info>(
+        ctx: Context<'_, '_, '_, 'info, CancelSubscription<'info>>,
+    ) -> Result<()> {
+        release_plan_seat_of(
+            ctx.remaining_accounts,
+            &ctx.accounts.subscription.key(),
+            &ctx.accounts.authority.to_account_info(),
+        )?;
         let subscription = &mut ctx.accounts.subscription;  // ← Make mutable
 
         require!(subscription.is_active, ErrorCode::SubscriptionAlreadyCancelled);
 
-        // Revoke token delegation
-        let revoke_ix = token_instruction::revoke(
-            &ctx.accounts.token_program.key(),
-            &ctx.accounts.user_token_account.key(),
-            &ctx.accounts.authority.key(),
-            &[],
-        )?;
+        // Revoke token delegation, if there is one the token program will
+        // clear. A failed CPI fails the whole cancel, so a closed or frozen
+        // account would otherwise keep the user from ever reclaiming the rent.
+        let revoke = holds_delegation(&ctx.accounts.user_token_account, &subscription.key());
+        if revoke {
+            let revoke_ix = token_instruction::revoke(
+                &ctx.accounts.token_program.key(),
+                &ctx.accounts.user_token_account.key(),
+                &ctx.accounts.authority.key(),
+                &[],
+            )?;
 
-        anchor_lang::solana_program::program::invoke(
-            &revoke_ix,
-            &[
-                ctx.accounts.user_token_account.to_account_info(),
-                ctx.accounts.authority.to_account_info(),
-                ctx.accounts.token_program.to_account_info(),
-            ],
-        )?;
+            anchor_lang::solana_program::program::invoke(
+                &revoke_ix,
+                &[
+                    ctx.accounts.user_token_account.to_account_info(),
+                    ctx.accounts.authority.to_account_info(),
+                    ctx.accounts.token_program.to_account_info(),
+                ],
+            )?;
+        }
 
         // Mark as inactive before account closure
         subscription.is_active = false;
 
+        emit!(SubscriptionCancelled {
+            subscription: subscription.key(),
+            authority: subscription.authority,
+            recipient: subscription.recipient,
+            total_charged: subscription.total_charged,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
         msg!("Subscription cancelled by user");
-        msg!("Token delegation revoked");
+        if revoke {
+            msg!("Token delegation revoked");
+        } else {
+            msg!("Token account closed, frozen or not delegated - nothing to revoke");
+        }
         msg!("Account closed - rent refunded to user");
 
         Ok(())
@@ -218,7 +411,9 @@ pub mod subscription_program {
         Ok(())
     }
 
-    /// Update subscription
+    /// Update subscription. A subscription on a plan is priced by the plan,
+    /// and a USD-priced one by its `UsdPeg`, so neither's amount can be
+    /// changed here.
     pub fn update_subscription(
         ctx: Context<UpdateSubscription>,
         new_amount: Option<u64>,
@@ -230,13 +425,21 @@ pub mod subscription_program {
         require!(subscription.is_active, ErrorCode::SubscriptionInactive);
 
         if let Some(amount) = new_amount {
+            require!(!subscription.on_plan, ErrorCode::PricedByPlan);
+            // No token amount marks a USD price, which only close_usd_peg
+            // replaces; nor can one be set here without a peg
+            require!(subscription.amount_per_period > 0, ErrorCode::PricedInUsd);
+            require!(amount > 0, ErrorCode::InvalidAmount);
             subscription.amount_per_period = amount;
             msg!("Updated amount to: {} tokens", amount);
         }
 
-        if let Some(interval) = new_interval {
-            subscription.interval_seconds = interval;
-            msg!("Updated interval to: {} seconds", interval);
+        if let Some(interval_seconds) = new_interval {
+            let interval =
+                Interval::from_seconds_field(interval_seconds).ok_or(ErrorCode::InvalidInterval)?;
+            subscription.interval_seconds = interval_seconds;
+            subscription.schedule_next_charge();
+            msg!("Updated interval to: {:?}", interval);
         }
 
         if new_expires_at.is_some() {
@@ -244,162 +447,9343 @@ pub mod subscription_program {
             msg!("Updated expiry");
         }
 
+        emit!(SubscriptionUpdated {
+            subscription: subscription.key(),
+            amount_per_period: subscription.amount_per_period,
+            interval_seconds: subscription.interval_seconds,
+            expires_at: subscription.expires_at,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
         Ok(())
     }
-}
 
-#[derive(Accounts)]
-pub struct InitializeSubscription<'info> {
-    #[account(
-        init,
-        payer = payer,
-        space = 8 + Subscription::INIT_SPACE,
-        seeds = [
-            b"subscription",
-            authority.key().as_ref(),
-            recipient.key().as_ref(),
-        ],
-        bump
-    )]
-    pub subscription: Account<'info, Subscription>,
+    /// Create the recipient's merchant settings. Only the recipient, or its
+    /// multisig's vault once it has one, can opt its subscriptions into
+    /// partial charges or catch-up charging.
+    pub fn create_merchant_config(
+        ctx: Context<CreateMerchantConfig>,
+        allow_partial_charges: bool,
+        max_periods_per_charge: u8,
+    ) -> Result<()> {
+        check_merchant_authority(
+            &ctx.accounts.merchant_multisig,
+            &ctx.accounts.recipient.key(),
+            &ctx.accounts.authority.key(),
+        )?;
 
-    pub authority: Signer<'info>,
+        let config = &mut ctx.accounts.merchant_config;
+        config.recipient = ctx.accounts.recipient.key();
+        config.allow_partial_charges = allow_partial_charges;
+        config.max_periods_per_charge = max_periods_per_charge;
+        config.bump = ctx.bumps.merchant_config;
 
-    /// CHECK: Merchant/recipient address
-    pub recipient: UncheckedAccount<'info>,
+        emit!(MerchantConfigUpdated {
+            recipient: config.recipient,
+            allow_partial_charges,
+            max_periods_per_charge,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
 
-    /// CHECK: User's token account (will be delegated to subscription PDA)
-    #[account(mut)]
-    pub user_token_account: UncheckedAccount<'info>,
+        msg!("Merchant config created");
+        msg!("Partial charges allowed: {}", allow_partial_charges);
+        msg!(
+            "Periods per charge: up to {}",
+            max_periods_per_charge.max(1)
+        );
 
-    /// CHECK: Recipient's token account
-    #[account(mut)]
-    pub recipient_token_account: UncheckedAccount<'info>,
+        Ok(())
+    }
 
-    /// CHECK: Token mint (USDC)
-    pub token_mint: UncheckedAccount<'info>,
+    /// Register fallback token accounts that charges draw from, in order,
+    /// when the primary account is short. Each must hold the subscription's
+    /// mint and belong to its authority, and is passed again as a remaining
+    /// account so that can be checked. The authority also approves each one
+    /// to the subscription PDA; until then it contributes nothing.
+    pub fn create_funding_sources(
+        ctx: Context<CreateFundingSources>,
+        token_accounts: Vec<Pubkey>,
+    ) -> Result<()> {
+        check_funding_sources(
+            &ctx.accounts.subscription,
+            &token_accounts,
+            ctx.remaining_accounts,
+        )?;
 
-    /// CHECK: SPL Token program
-    pub token_program: UncheckedAccount<'info>,
+        let funding = &mut ctx.accounts.funding_sources;
+        funding.subscription = ctx.accounts.subscription.key();
+        funding.authority = ctx.accounts.authority.key();
+        funding.token_accounts = token_accounts;
+        funding.bump = ctx.bumps.funding_sources;
 
-    #[account(mut)]
-    pub payer: Signer<'info>,
+        emit!(FundingSourcesUpdated {
+            subscription: funding.subscription,
+            token_accounts: funding.token_accounts.clone(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
 
-    pub system_program: Program<'info, System>,
-}
+        msg!("Fallback funding sources: {}", funding.token_accounts.len());
 
-#[derive(Accounts)]
-pub struct ChargeSubscription<'info> {
-    #[account(
-        mut,
-        seeds = [
-            b"subscription",
-            subscription.authority.as_ref(),
-            subscription.recipient.as_ref(),
-        ],
-        bump = subscription.bump,
-    )]
-    pub subscription: Account<'info, Subscription>,
+        Ok(())
+    }
 
-    /// CHECK: User's token account
-    #[account(
-        mut,
-        constraint = user_token_account.key() == subscription.user_token_account
-    )]
-    pub user_token_account: UncheckedAccount<'info>,
+    /// Replace the fallback list; an empty list turns fallbacks off
+    pub fn update_funding_sources(
+        ctx: Context<UpdateFundingSources>,
+        token_accounts: Vec<Pubkey>,
+    ) -> Result<()> {
+        check_funding_sources(
+            &ctx.accounts.subscription,
+            &token_accounts,
+            ctx.remaining_accounts,
+        )?;
 
-    /// CHECK: Recipient's token account
-    #[account(
-        mut,
-        constraint = recipient_token_account.key() == subscription.recipient_token_account
-    )]
-    pub recipient_token_account: UncheckedAccount<'info>,
+        let funding = &mut ctx.accounts.funding_sources;
+        funding.token_accounts = token_accounts;
 
-    /// CHECK: SPL Token program
-    pub token_program: UncheckedAccount<'info>,
-}
+        emit!(FundingSourcesUpdated {
+            subscription: funding.subscription,
+            token_accounts: funding.token_accounts.clone(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
 
-#[derive(Accounts)]
-pub struct CancelSubscription<'info> {
-    #[account(
-        mut,
-        seeds = [
-            b"subscription",
-            subscription.authority.as_ref(),
-            subscription.recipient.as_ref(),
-        ],
-        bump = subscription.bump,
-        has_one = authority,
-        close = authority
-    )]
-    pub subscription: Account<'info, Subscription>,
+        msg!("Fallback funding sources: {}", funding.token_accounts.len());
 
-    #[account(mut)]
-    pub authority: Signer<'info>,
+        Ok(())
+    }
 
-    /// CHECK: User's token account
-    #[account(
-        mut,
-        constraint = user_token_account.key() == subscription.user_token_account
-    )]
-    pub user_token_account: UncheckedAccount<'info>,
+    /// Close the fallback list and refund its rent, also after the
+    /// subscription itself was cancelled
+    pub fn close_funding_sources(_ctx: Context<CloseFundingSources>) -> Result<()> {
+        msg!("Fallback funding sources closed - rent refunded to user");
 
-    /// CHECK: SPL Token program
-    pub token_program: UncheckedAccount<'info>,
-}
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct CleanupCancelledSubscription<'info> {
-    #[account(
-        mut,
-        seeds = [
+    /// Create a Clockwork thread that sends `charge_subscription` on
+    /// `schedule` (a 7-field Clockwork cron string), so the automation
+    /// network bills the subscription instead of a keeper. The thread is
+    /// owned by the subscription PDA and funded with `amount` lamports from
+    /// `payer`. Sent right after `initialize_subscription`, in the same
+    /// transaction. Firing early is harmless: the charge fails until due.
+    pub fn create_charge_thread(
+        ctx: Context<CreateChargeThread>,
+        thread_id: Vec<u8>,
+        schedule: String,
+        amount: u64,
+    ) -> Result<()> {
+        let subscription = &ctx.accounts.subscription;
+        let subscription_key = subscription.key();
+
+        require!(subscription.is_active, ErrorCode::SubscriptionInactive);
+        require!(
+            !thread_id.is_empty() && thread_id.len() <= MAX_SEED_LEN,
+            ErrorCode::InvalidChargeThread
+        );
+        let (thread, _) = charge_thread_address(&subscription_key, &thread_id);
+        require_keys_eq!(
+            ctx.accounts.thread.key(),
+            thread,
+            ErrorCode::InvalidChargeThread
+        );
+
+        let charge = charge_thread_instruction(&subscription_key, subscription);
+        let create_ix = thread_create_instruction(
+            &subscription_key,
+            &ctx.accounts.payer.key(),
+            &thread,
+            amount,
+            thread_id,
+            vec![charge],
+            ThreadTrigger::Cron {
+                schedule: schedule.clone(),
+                skippable: true,
+            },
+        )?;
+
+        let seeds = &[
             b"subscription",
             subscription.authority.as_ref(),
             subscription.recipient.as_ref(),
-        ],
-        bump = subscription.bump,
-        has_one = authority,
-        close = authority
-    )]
-    pub subscription: Account<'info, Subscription>,
+            &[subscription.bump],
+        ];
 
-    #[account(mut)]
+        invoke_signed(
+            &create_ix,
+            &[
+                ctx.accounts.subscription.to_account_info(),
+                ctx.accounts.payer.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+                ctx.accounts.thread.to_account_info(),
+                ctx.accounts.thread_program.to_account_info(),
+            ],
+            &[&seeds[..]],
+        )?;
+
+        emit!(ChargeThreadCreated {
+            subscription: subscription_key,
+            thread,
+            schedule: schedule.clone(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Charge thread created: {}", thread);
+        msg!("Schedule: {}", schedule);
+
+        Ok(())
+    }
+
+    /// Let a Switchboard Function bill the recipient's subscriptions. The
+    /// function checks off-chain which subscriptions are due and sends
+    /// `charge_subscription_attested`, signed by its enclave.
+    pub fn register_charge_function(ctx: Context<RegisterChargeFunction>) -> Result<()> {
+        SwitchboardFunction::load(&ctx.accounts.function)?;
+
+        let charge_function = &mut ctx.accounts.charge_function;
+        charge_function.recipient = ctx.accounts.recipient.key();
+        charge_function.function = ctx.accounts.function.key();
+        charge_function.bump = ctx.bumps.charge_function;
+
+        emit!(ChargeFunctionRegistered {
+            recipient: charge_function.recipient,
+            function: charge_function.function,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Charge function registered: {}", charge_function.function);
+
+        Ok(())
+    }
+
+    /// Stop accepting attested charges; plain charges are unaffected
+    pub fn close_charge_function(_ctx: Context<CloseChargeFunction>) -> Result<()> {
+        msg!("Charge function closed - rent refunded to recipient");
+
+        Ok(())
+    }
+
+    /// `charge_subscription`, sent by the recipient's registered Switchboard
+    /// Function. The function account's current enclave signer must sign,
+    /// which attests that the charge came from the function's verified
+    /// code. Takes the same remaining accounts as `charge_subscription`,
+    /// and a charge that is not due fails the same way.
+    pub fn charge_subscription_attested<'info>(
+        ctx: Context<'_, '_, '_, 'info, ChargeSubscriptionAttested<'info>>,
+    ) -> Result<()> {
+        let function = SwitchboardFunction::load(&ctx.accounts.function)?;
+        let enclave_signer = ctx.accounts.enclave_signer.key();
+        require_keys_eq!(
+            function.enclave_signer,
+            enclave_signer,
+            ErrorCode::InvalidAttestation
+        );
+
+        charge(
+            &mut ctx.accounts.charge,
+            ctx.remaining_accounts,
+            None,
+            None,
+            None,
+        )?;
+
+        emit!(ChargeAttested {
+            subscription: ctx.accounts.charge.subscription.key(),
+            function: ctx.accounts.function.key(),
+            enclave_signer,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Queue a tuktuk task that sends `charge_subscription_tuktuk` at the
+    /// subscription's next due time. Signed by the recipient, whose queue
+    /// authority PDA must have been added to `task_queue` by the queue's
+    /// owner. Remaining accounts are passed on to the charge as they are,
+    /// so a merchant config and fallback funding work as with a keeper.
+    pub fn queue_charge_task(ctx: Context<QueueChargeTask>, task_id: u16) -> Result<()> {
+        let subscription = &ctx.accounts.subscription;
+        let subscription_key = subscription.key();
+
+        require!(subscription.is_active, ErrorCode::SubscriptionInactive);
+        let (task, _) = charge_task_address(&ctx.accounts.task_queue.key(), task_id);
+        require_keys_eq!(ctx.accounts.task.key(), task, ErrorCode::InvalidChargeTask);
+
+        let remaining = passed_on_metas(ctx.remaining_accounts);
+        let charge = charge_task_instruction(&subscription_key, subscription, &remaining);
+        let queue_ix = queue_task_instruction(
+            &ctx.accounts.payer.key(),
+            &ctx.accounts.queue_authority.key(),
+            &ctx.accounts.task_queue.key(),
+            &task,
+            QueueTaskArgs {
+                id: task_id,
+                trigger: TaskTrigger::Timestamp(subscription.next_charge_at),
+                transaction: TaskTransaction::Compiled(compile_task(&[charge])),
+                crank_reward: None,
+                free_tasks: 1,
+                description: CHARGE_TASK_DESCRIPTION.to_string(),
+            },
+        )?;
+
+        let recipient_key = ctx.accounts.recipient.key();
+        let seeds = &[
+            b"queue_authority",
+            recipient_key.as_ref(),
+            &[ctx.bumps.queue_authority],
+        ];
+
+        invoke_signed(
+            &queue_ix,
+            &[
+                ctx.accounts.payer.to_account_info(),
+                ctx.accounts.queue_authority.to_account_info(),
+                ctx.accounts.task_queue_authority.to_account_info(),
+                ctx.accounts.task_queue.to_account_info(),
+                ctx.accounts.task.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+                ctx.accounts.tuktuk_program.to_account_info(),
+            ],
+            &[&seeds[..]],
+        )?;
+
+        emit!(ChargeTaskQueued {
+            subscription: subscription_key,
+            task_queue: ctx.accounts.task_queue.key(),
+            task,
+            trigger_at: subscription.next_charge_at,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Charge task queued: {}", task);
+        msg!("Runs at: {}", subscription.next_charge_at);
+
+        Ok(())
+    }
+
+    /// `charge_subscription` as a tuktuk task runs it. After the charge it
+    /// returns the next task, for the next due time, so tuktuk requeues the
+    /// charge from the task's free task account. Nothing is requeued once
+    /// the subscription is inactive or would have expired by then.
+    pub fn charge_subscription_tuktuk<'info>(
+        ctx: Context<'_, '_, '_, 'info, ChargeSubscription<'info>>,
+    ) -> Result<()> {
+        charge(ctx.accounts, ctx.remaining_accounts, None, None, None)?;
+
+        let subscription = &ctx.accounts.subscription;
+        if assert_active_subscription(subscription, subscription.next_charge_at).is_err() {
+            msg!("No further charge task queued");
+            return Ok(());
+        }
+
+        let remaining = passed_on_metas(ctx.remaining_accounts);
+        let charge = charge_task_instruction(&subscription.key(), subscription, &remaining);
+        let next = RunTaskReturn {
+            tasks: vec![TaskReturn {
+                trigger: TaskTrigger::Timestamp(subscription.next_charge_at),
+                transaction: TaskTransaction::Compiled(compile_task(&[charge])),
+                crank_reward: None,
+                free_tasks: 1,
+                description: CHARGE_TASK_DESCRIPTION.to_string(),
+            }],
+            accounts: Vec::new(),
+        };
+        set_return_data(&next.try_to_vec()?);
+
+        msg!("Next charge task at: {}", subscription.next_charge_at);
+
+        Ok(())
+    }
+
+    /// Create the lease that elects one of `keepers` to send a group's
+    /// charges. Replicas call `acquire_keeper_lease` before each pass and
+    /// only the holder charges; when it stops renewing, the lease expires
+    /// and the next replica to ask takes over.
+    pub fn create_keeper_lease(
+        ctx: Context<CreateKeeperLease>,
+        keepers: Vec<Pubkey>,
+    ) -> Result<()> {
+        check_lease_keepers(&keepers)?;
+
+        let lease = &mut ctx.accounts.lease;
+        lease.group = ctx.accounts.group.key();
+        lease.holder = Pubkey::default();
+        lease.term = 0;
+        lease.expires_at = 0;
+        lease.keepers = keepers;
+        lease.bump = ctx.bumps.lease;
+
+        msg!("Keeper lease created for {} keepers", lease.keepers.len());
+
+        Ok(())
+    }
+
+    /// Replace the keepers allowed to hold the lease. A holder that is no
+    /// longer listed loses the lease at once.
+    pub fn update_keeper_lease(
+        ctx: Context<UpdateKeeperLease>,
+        keepers: Vec<Pubkey>,
+    ) -> Result<()> {
+        check_lease_keepers(&keepers)?;
+
+        let now = Clock::get()?.unix_timestamp;
+        let lease = &mut ctx.accounts.lease;
+        if !keepers.contains(&lease.holder) {
+            lease.expires_at = lease.expires_at.min(now);
+        }
+        lease.keepers = keepers;
+
+        msg!("Keeper lease keepers: {}", lease.keepers.len());
+
+        Ok(())
+    }
+
+    pub fn close_keeper_lease(_ctx: Context<CloseKeeperLease>) -> Result<()> {
+        msg!("Keeper lease closed - rent refunded to group");
+
+        Ok(())
+    }
+
+    /// Take or renew the lease for `duration_seconds`. The holder can renew
+    /// at any time; anyone else only once it has expired, which starts a
+    /// new term and fences off charges the previous holder still has in
+    /// flight.
+    pub fn acquire_keeper_lease(
+        ctx: Context<AcquireKeeperLease>,
+        duration_seconds: i64,
+    ) -> Result<()> {
+        require!(
+            (1..=MAX_LEASE_SECONDS).contains(&duration_seconds),
+            ErrorCode::InvalidKeeperLease
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        let keeper = ctx.accounts.keeper.key();
+        let lease = &mut ctx.accounts.lease;
+        require!(lease.keepers.contains(&keeper), ErrorCode::NotALeaseKeeper);
+
+        if lease.holder != keeper {
+            require!(now >= lease.expires_at, ErrorCode::KeeperLeaseHeld);
+            lease.holder = keeper;
+            lease.term = lease
+                .term
+                .checked_add(1)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+            emit!(KeeperLeaseAcquired {
+                lease: lease.key(),
+                holder: keeper,
+                term: lease.term,
+                timestamp: now,
+            });
+        }
+        lease.expires_at = now
+            .checked_add(duration_seconds)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        msg!(
+            "Keeper lease term {} held until {}",
+            lease.term,
+            lease.expires_at
+        );
+
+        Ok(())
+    }
+
+    /// Give the lease up early so another keeper can take over without
+    /// waiting for it to expire
+    pub fn release_keeper_lease(ctx: Context<AcquireKeeperLease>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let lease = &mut ctx.accounts.lease;
+        require!(
+            lease.is_held_by(&ctx.accounts.keeper.key(), now),
+            ErrorCode::KeeperLeaseNotHeld
+        );
+        lease.expires_at = now;
+
+        msg!("Keeper lease term {} released", lease.term);
+
+        Ok(())
+    }
+
+    /// Fail unless `keeper` holds the lease in `term`. Keepers put it ahead
+    /// of the charges in each transaction, so charges sent by a holder that
+    /// has since lost the lease land nowhere.
+    pub fn check_keeper_lease(ctx: Context<CheckKeeperLease>, term: u64) -> Result<()> {
+        let lease = &ctx.accounts.lease;
+        require!(
+            lease.term == term
+                && lease.is_held_by(&ctx.accounts.keeper.key(), Clock::get()?.unix_timestamp),
+            ErrorCode::KeeperLeaseNotHeld
+        );
+
+        Ok(())
+    }
+
+    /// Rewrite a subscription created in an older layout, the original one,
+    /// the unpacked `SubscriptionV2`, `SubscriptionV3` or `SubscriptionV4`,
+    /// in the current one. Permissionless: the fields are carried over as
+    /// they are, and `payer` only tops up rent for a larger account. The
+    /// subscription's `PlanSubscription` address is passed whether or not it
+    /// exists, and sets `on_plan` for layouts without it. Older accounts
+    /// cannot be charged, updated or cancelled until migrated.
+    pub fn migrate_subscription(ctx: Context<MigrateSubscription>) -> Result<()> {
+        let account = ctx.accounts.subscription.to_account_info();
+        let plan_subscription = &ctx.accounts.plan_subscription;
+        let on_plan = *plan_subscription.owner == crate::ID
+            && plan_subscription
+                .try_borrow_data()?
+                .starts_with(PlanSubscription::DISCRIMINATOR);
+        let subscription = {
+            let data = account.try_borrow_data()?;
+            require!(
+                !data.starts_with(Subscription::DISCRIMINATOR),
+                ErrorCode::SubscriptionAlreadyMigrated
+            );
+            if data.starts_with(SubscriptionV4::DISCRIMINATOR) {
+                SubscriptionV4::try_deserialize(&mut &data[..])?.into()
+            } else if data.starts_with(SubscriptionV3::DISCRIMINATOR) {
+                SubscriptionV3::try_deserialize(&mut &data[..])?.migrate(on_plan)
+            } else if data.starts_with(SubscriptionV2::DISCRIMINATOR) {
+                Subscription {
+                    on_plan,
+                    ..SubscriptionV2::try_deserialize(&mut &data[..])?.into()
+                }
+            } else {
+                Subscription {
+                    on_plan,
+                    ..LegacySubscription::try_deserialize(&mut &data[..])?.into()
+                }
+            }
+        };
+
+        // Rewrite in place before the rent CPI, as everywhere else
+        let space = 8 + Subscription::INIT_SPACE;
+        account.resize(space)?;
+        subscription.try_serialize(&mut &mut account.try_borrow_mut_data()?[..])?;
+
+        emit!(SubscriptionMigrated {
+            subscription: account.key(),
+            authority: subscription.authority,
+            recipient: subscription.recipient,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Subscription migrated to the current layout");
+
+        let top_up = Rent::get()?
+            .minimum_balance(space)
+            .saturating_sub(account.lamports());
+        if top_up > 0 {
+            anchor_lang::system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.payer.to_account_info(),
+                        to: account,
+                    },
+                ),
+                top_up,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Change the charge settings for the recipient's subscriptions
+    pub fn update_merchant_config(
+        ctx: Context<UpdateMerchantConfig>,
+        allow_partial_charges: bool,
+        max_periods_per_charge: u8,
+    ) -> Result<()> {
+        check_merchant_authority(
+            &ctx.accounts.merchant_multisig,
+            &ctx.accounts.recipient.key(),
+            &ctx.accounts.authority.key(),
+        )?;
+
+        let config = &mut ctx.accounts.merchant_config;
+        config.allow_partial_charges = allow_partial_charges;
+        config.max_periods_per_charge = max_periods_per_charge;
+
+        emit!(MerchantConfigUpdated {
+            recipient: config.recipient,
+            allow_partial_charges,
+            max_periods_per_charge,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Partial charges allowed: {}", allow_partial_charges);
+        msg!(
+            "Periods per charge: up to {}",
+            max_periods_per_charge.max(1)
+        );
+
+        Ok(())
+    }
+
+    /// Put the recipient's merchant settings under a Squads multisig: from
+    /// now on they change only when `vault_index` of `multisig` signs, that
+    /// is once enough members approved, and no longer with the recipient's
+    /// own key. Signed by the recipient.
+    pub fn set_merchant_multisig(ctx: Context<SetMerchantMultisig>, vault_index: u8) -> Result<()> {
+        let multisig = ctx.accounts.multisig.key();
+        let vault = squads_vault_address(&multisig, vault_index);
+
+        let record = &mut ctx.accounts.merchant_multisig;
+        record.recipient = ctx.accounts.recipient.key();
+        record.multisig = multisig;
+        record.vault_index = vault_index;
+        record.vault = vault;
+        record.bump = ctx.bumps.merchant_multisig;
+
+        emit!(MerchantMultisigChanged {
+            recipient: record.recipient,
+            multisig: Some(multisig),
+            vault: Some(vault),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Merchant settings now need multisig {}", multisig);
+        msg!("Vault {}: {}", vault_index, vault);
+
+        Ok(())
+    }
+
+    /// Hand the merchant settings back to the recipient's key. Only the
+    /// multisig's vault can sign this.
+    pub fn close_merchant_multisig(ctx: Context<CloseMerchantMultisig>) -> Result<()> {
+        emit!(MerchantMultisigChanged {
+            recipient: ctx.accounts.recipient.key(),
+            multisig: None,
+            vault: None,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Merchant settings are back under the recipient's key");
+
+        Ok(())
+    }
+
+    /// Schedule a new payout account for the recipient's subscriptions. It
+    /// applies after `PAYOUT_TIMELOCK_SECONDS`, so a stolen merchant key
+    /// cannot redirect payments before the merchant notices and withdraws
+    /// the change. Signed by the merchant authority.
+    pub fn set_recipient_token_account(ctx: Context<SetRecipientTokenAccount>) -> Result<()> {
+        check_merchant_authority(
+            &ctx.accounts.merchant_multisig,
+            &ctx.accounts.recipient.key(),
+            &ctx.accounts.authority.key(),
+        )?;
+        require_keys_eq!(
+            *ctx.accounts.token_account.owner,
+            spl_token::ID,
+            ErrorCode::InvalidPayoutAccount
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        let change = &mut ctx.accounts.payout_change;
+        change.recipient = ctx.accounts.recipient.key();
+        change.token_account = ctx.accounts.token_account.key();
+        change.effective_at = now
+            .checked_add(PAYOUT_TIMELOCK_SECONDS)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        change.bump = ctx.bumps.payout_change;
+
+        emit!(PayoutChangeScheduled {
+            recipient: change.recipient,
+            token_account: Some(change.token_account),
+            effective_at: change.effective_at,
+            timestamp: now,
+        });
+
+        msg!("New payout account: {}", change.token_account);
+        msg!("Effective at: {}", change.effective_at);
+
+        Ok(())
+    }
+
+    /// Move one subscription to the recipient's new payout account once the
+    /// timelock has run out. Anyone can send it, so a keeper can walk the
+    /// recipient's subscriptions.
+    pub fn apply_recipient_token_account(ctx: Context<ApplyRecipientTokenAccount>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            ctx.accounts.payout_change.is_effective(now),
+            ErrorCode::PayoutChangeTimelocked
+        );
+
+        let subscription = &mut ctx.accounts.subscription;
+        require_keys_eq!(
+            *ctx.accounts.token_account.owner,
+            spl_token::ID,
+            ErrorCode::InvalidPayoutAccount
+        );
+        let token_account =
+            spl_token::state::Account::unpack(&ctx.accounts.token_account.try_borrow_data()?)
+                .map_err(|_| ErrorCode::InvalidPayoutAccount)?;
+        require_keys_eq!(
+            token_account.mint,
+            subscription.token_mint,
+            ErrorCode::InvalidPayoutAccount
+        );
+
+        subscription.recipient_token_account = ctx.accounts.token_account.key();
+
+        emit!(RecipientTokenAccountChanged {
+            subscription: subscription.key(),
+            recipient: subscription.recipient,
+            token_account: subscription.recipient_token_account,
+            timestamp: now,
+        });
+
+        msg!(
+            "Subscription now pays {}",
+            subscription.recipient_token_account
+        );
+
+        Ok(())
+    }
+
+    /// Withdraw a pending payout change, or clear an applied one once every
+    /// subscription has moved. Signed by the merchant authority.
+    pub fn close_payout_change(ctx: Context<ClosePayoutChange>) -> Result<()> {
+        check_merchant_authority(
+            &ctx.accounts.merchant_multisig,
+            &ctx.accounts.recipient.key(),
+            &ctx.accounts.authority.key(),
+        )?;
+
+        let change = &ctx.accounts.payout_change;
+        emit!(PayoutChangeScheduled {
+            recipient: change.recipient,
+            token_account: None,
+            effective_at: change.effective_at,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Payout change closed");
+
+        Ok(())
+    }
+
+    /// Shrink a deactivated subscription to a `SubscriptionTombstone` and
+    /// send the rent it no longer needs to its authority. Permissionless,
+    /// like `migrate_subscription`: nothing in the subscription can be used
+    /// again, and the authority still closes the record with
+    /// `close_subscription_tombstone`.
+    pub fn compact_subscription(ctx: Context<CompactSubscription>) -> Result<()> {
+        let account = ctx.accounts.subscription.to_account_info();
+        let subscription = Subscription::try_deserialize(&mut &account.try_borrow_data()?[..])?;
+        require!(
+            !subscription.is_active,
+            ErrorCode::SubscriptionStillActive
+        );
+        require_keys_eq!(
+            ctx.accounts.authority.key(),
+            subscription.authority,
+            anchor_lang::error::ErrorCode::ConstraintHasOne
+        );
+
+        let tombstone = SubscriptionTombstone::from(&subscription);
+        let space = 8 + SubscriptionTombstone::INIT_SPACE;
+        account.resize(space)?;
+        tombstone.try_serialize(&mut &mut account.try_borrow_mut_data()?[..])?;
+
+        let refund = account
+            .lamports()
+            .saturating_sub(Rent::get()?.minimum_balance(space));
+        account.sub_lamports(refund)?;
+        ctx.accounts.authority.add_lamports(refund)?;
+
+        emit!(SubscriptionCompacted {
+            subscription: account.key(),
+            authority: tombstone.authority,
+            recipient: tombstone.recipient,
+            refund,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Subscription compacted - {} lamports refunded", refund);
+
+        Ok(())
+    }
+
+    /// Close a tombstone left by `compact_subscription`, freeing the
+    /// subscription address for a new subscription to the same recipient
+    pub fn close_subscription_tombstone(_ctx: Context<CloseSubscriptionTombstone>) -> Result<()> {
+        msg!("Tombstone closed - rent refunded to user");
+
+        Ok(())
+    }
+
+    /// Open the recipient's revenue vault: a token account owned by its
+    /// `MerchantVault` PDA. Subscriptions paying into it settle there, and
+    /// the merchant sweeps it with `withdraw_revenue` instead of receiving
+    /// each charge directly. Signed by the merchant authority.
+    pub fn create_merchant_vault(ctx: Context<CreateMerchantVault>) -> Result<()> {
+        check_merchant_authority(
+            &ctx.accounts.merchant_multisig,
+            &ctx.accounts.recipient.key(),
+            &ctx.accounts.authority.key(),
+        )?;
+
+        require_keys_eq!(
+            *ctx.accounts.token_account.owner,
+            spl_token::ID,
+            ErrorCode::InvalidVaultTokenAccount
+        );
+        let token_account =
+            spl_token::state::Account::unpack(&ctx.accounts.token_account.try_borrow_data()?)
+                .map_err(|_| ErrorCode::InvalidVaultTokenAccount)?;
+        MerchantVault::check_token_account(&ctx.accounts.vault.key(), &token_account)?;
+
+        let vault = &mut ctx.accounts.vault;
+        vault.recipient = ctx.accounts.recipient.key();
+        vault.token_account = ctx.accounts.token_account.key();
+        vault.token_mint = token_account.mint;
+        vault.total_withdrawn = 0;
+        // Tokens already in the account are not charges; nothing holds them
+        vault.total_received = token_account.amount;
+        vault.holdback_bps = 0;
+        vault.holdback_days = 0;
+        vault.holds = Vec::new();
+        vault.withdrawal_limit = 0;
+        vault.withdrawal_period_seconds = 0;
+        vault.withdrawal_admin = Pubkey::default();
+        vault.period_started_at = 0;
+        vault.withdrawn_in_period = 0;
+        vault.destinations = Vec::new();
+        vault.bump = ctx.bumps.vault;
+
+        emit!(MerchantVaultCreated {
+            recipient: vault.recipient,
+            vault: vault.key(),
+            token_account: vault.token_account,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Revenue vault: {}", vault.token_account);
+
+        Ok(())
+    }
+
+    /// Sweep `amount` of settled revenue from the recipient's vault to one of
+    /// its approved destinations. One transfer settles every charge the
+    /// vault has collected since the last sweep. Signed by the merchant
+    /// authority, and capped by the vault's withdrawal limit if it has one.
+    pub fn withdraw_revenue(ctx: Context<WithdrawRevenue>, amount: u64) -> Result<()> {
+        check_merchant_authority(
+            &ctx.accounts.merchant_multisig,
+            &ctx.accounts.recipient.key(),
+            &ctx.accounts.authority.key(),
+        )?;
+
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            ctx.accounts
+                .vault
+                .is_approved_destination(&ctx.accounts.destination.key(), now),
+            ErrorCode::DestinationNotApproved
+        );
+        pay_out_revenue(
+            &mut ctx.accounts.vault,
+            &ctx.accounts.token_account,
+            &ctx.accounts.destination,
+            &ctx.accounts.token_program,
+            amount,
+            now,
+            true,
+        )?;
+
+        emit!(RevenueWithdrawn {
+            recipient: ctx.accounts.vault.recipient,
+            destination: ctx.accounts.destination.key(),
+            amount,
+            total_withdrawn: ctx.accounts.vault.total_withdrawn,
+            timestamp: now,
+        });
+
+        msg!("Revenue withdrawn: {} tokens", amount);
+
+        Ok(())
+    }
+
+    /// Cap what the merchant authority can withdraw to `limit` per
+    /// `period_seconds`, so a stolen merchant key drains revenue slowly
+    /// enough to notice. `admin`, a recovery key kept apart from the
+    /// merchant's, can lift the cap and recover revenue past it.
+    ///
+    /// The merchant authority sets the first limit and can tighten it
+    /// afterwards; raising, removing or handing over the limit takes the
+    /// admin's signature. The admin removes it with a `limit` of 0 and the
+    /// default `admin`.
+    pub fn set_withdrawal_limit(
+        ctx: Context<SetWithdrawalLimit>,
+        limit: u64,
+        period_seconds: i64,
+        admin: Pubkey,
+    ) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+        let signer = ctx.accounts.authority.key();
+        let removing = limit == 0 && admin == Pubkey::default();
+        require!(
+            removing || (limit > 0 && period_seconds > 0 && admin != Pubkey::default()),
+            ErrorCode::InvalidWithdrawalLimit
+        );
+        if !vault.has_withdrawal_limit() || signer != vault.withdrawal_admin {
+            check_merchant_authority(
+                &ctx.accounts.merchant_multisig,
+                &ctx.accounts.recipient.key(),
+                &signer,
+            )?;
+            require!(
+                vault.limit_change_tightens(limit, period_seconds, &admin),
+                ErrorCode::WithdrawalLimitLocked
+            );
+        }
+
+        let vault = &mut ctx.accounts.vault;
+        vault.withdrawal_limit = limit;
+        vault.withdrawal_period_seconds = if removing { 0 } else { period_seconds };
+        vault.withdrawal_admin = admin;
+
+        emit!(WithdrawalLimitUpdated {
+            recipient: vault.recipient,
+            limit,
+            period_seconds: vault.withdrawal_period_seconds,
+            admin,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Withdrawal limit: {} per {}s", limit, period_seconds);
+
+        Ok(())
+    }
+
+    /// Move revenue out of the vault past its withdrawal limit, for when
+    /// the merchant key is lost or compromised. Signed by the vault's
+    /// withdrawal admin, to any account, since the merchant key may have
+    /// emptied the approved destinations; held-back revenue stays held.
+    pub fn recover_revenue(ctx: Context<RecoverRevenue>, amount: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        pay_out_revenue(
+            &mut ctx.accounts.vault,
+            &ctx.accounts.token_account,
+            &ctx.accounts.destination,
+            &ctx.accounts.token_program,
+            amount,
+            now,
+            false,
+        )?;
+
+        emit!(RevenueRecovered {
+            recipient: ctx.accounts.vault.recipient,
+            admin: ctx.accounts.withdrawal_admin.key(),
+            destination: ctx.accounts.destination.key(),
+            amount,
+            total_withdrawn: ctx.accounts.vault.total_withdrawn,
+            timestamp: now,
+        });
+
+        msg!("Revenue recovered: {} tokens", amount);
+
+        Ok(())
+    }
+
+    /// Approve a token account of the vault's mint as a `withdraw_revenue`
+    /// destination from `PAYOUT_TIMELOCK_SECONDS` on, so a stolen merchant
+    /// key cannot sweep to an account of its own before the merchant
+    /// notices. Signed by the merchant authority.
+    pub fn add_withdrawal_destination(ctx: Context<AddWithdrawalDestination>) -> Result<()> {
+        check_merchant_authority(
+            &ctx.accounts.merchant_multisig,
+            &ctx.accounts.recipient.key(),
+            &ctx.accounts.authority.key(),
+        )?;
+        require_keys_eq!(
+            *ctx.accounts.token_account.owner,
+            spl_token::ID,
+            ErrorCode::InvalidWithdrawalDestination
+        );
+        let token_account =
+            spl_token::state::Account::unpack(&ctx.accounts.token_account.try_borrow_data()?)
+                .map_err(|_| ErrorCode::InvalidWithdrawalDestination)?;
+
+        let now = Clock::get()?.unix_timestamp;
+        let vault = &mut ctx.accounts.vault;
+        let destination = WithdrawalDestination {
+            token_account: ctx.accounts.token_account.key(),
+            effective_at: now
+                .checked_add(PAYOUT_TIMELOCK_SECONDS)
+                .ok_or(ErrorCode::ArithmeticOverflow)?,
+        };
+        require_keys_eq!(
+            token_account.mint,
+            vault.token_mint,
+            ErrorCode::InvalidWithdrawalDestination
+        );
+        vault.add_destination(destination)?;
+
+        emit!(WithdrawalDestinationChanged {
+            recipient: vault.recipient,
+            token_account: destination.token_account,
+            effective_at: Some(destination.effective_at),
+            timestamp: now,
+        });
+
+        msg!("New withdrawal destination: {}", destination.token_account);
+        msg!("Effective at: {}", destination.effective_at);
+
+        Ok(())
+    }
+
+    /// Drop `token_account` from the vault's approved destinations, at once.
+    /// Signed by the merchant authority.
+    pub fn remove_withdrawal_destination(
+        ctx: Context<RemoveWithdrawalDestination>,
+        token_account: Pubkey,
+    ) -> Result<()> {
+        check_merchant_authority(
+            &ctx.accounts.merchant_multisig,
+            &ctx.accounts.recipient.key(),
+            &ctx.accounts.authority.key(),
+        )?;
+
+        let vault = &mut ctx.accounts.vault;
+        let count = vault.destinations.len();
+        vault
+            .destinations
+            .retain(|destination| destination.token_account != token_account);
+        require!(
+            vault.destinations.len() < count,
+            ErrorCode::InvalidWithdrawalDestination
+        );
+
+        emit!(WithdrawalDestinationChanged {
+            recipient: vault.recipient,
+            token_account,
+            effective_at: None,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Withdrawal destination removed: {}", token_account);
+
+        Ok(())
+    }
+
+    /// Hold `holdback_bps` of the revenue the vault receives from now on for
+    /// `holdback_days` before it can be withdrawn. Revenue received so far
+    /// is held under the previous terms, and existing holds keep their
+    /// release times. Signed by the merchant authority.
+    pub fn set_vault_holdback(
+        ctx: Context<SetVaultHoldback>,
+        holdback_bps: u16,
+        holdback_days: u16,
+    ) -> Result<()> {
+        check_merchant_authority(
+            &ctx.accounts.merchant_multisig,
+            &ctx.accounts.recipient.key(),
+            &ctx.accounts.authority.key(),
+        )?;
+        require!(
+            holdback_bps <= MAX_BPS && holdback_days <= MAX_HOLDBACK_DAYS,
+            ErrorCode::InvalidHoldback
+        );
+
+        let balance = vault_balance(&ctx.accounts.token_account)?;
+        let now = Clock::get()?.unix_timestamp;
+        let vault = &mut ctx.accounts.vault;
+        sync_vault(vault, balance, now)?;
+        vault.holdback_bps = holdback_bps;
+        vault.holdback_days = holdback_days;
+
+        emit!(VaultHoldbackUpdated {
+            recipient: vault.recipient,
+            holdback_bps,
+            holdback_days,
+            timestamp: now,
+        });
+
+        msg!("Holdback: {} bps for {} days", holdback_bps, holdback_days);
+
+        Ok(())
+    }
+
+    /// Record the revenue the vault received since it was last synced and
+    /// start the holdback on it. `withdraw_revenue` syncs too; keepers can
+    /// call this after charging so holds start at the charge, not at the
+    /// next withdrawal. Permissionless.
+    pub fn sync_merchant_vault(ctx: Context<SyncMerchantVault>) -> Result<()> {
+        let balance = vault_balance(&ctx.accounts.token_account)?;
+        sync_vault(
+            &mut ctx.accounts.vault,
+            balance,
+            Clock::get()?.unix_timestamp,
+        )?;
+
+        Ok(())
+    }
+
+    /// Commit the recipient to crediting subscribers for downtime that
+    /// `attester` (the platform or an oracle) attests to, out of the held-back
+    /// revenue in its vault. A credit is the outage's share of the period
+    /// times `credit_multiplier`, at most one period. Signed by the merchant
+    /// authority.
+    pub fn create_sla_commitment(
+        ctx: Context<CreateSlaCommitment>,
+        attester: Pubkey,
+        credit_multiplier: u16,
+    ) -> Result<()> {
+        check_merchant_authority(
+            &ctx.accounts.merchant_multisig,
+            &ctx.accounts.recipient.key(),
+            &ctx.accounts.authority.key(),
+        )?;
+        // Credits come out of held revenue, so there has to be some
+        let vault = &ctx.accounts.vault;
+        require!(
+            (1..=MAX_SLA_CREDIT_MULTIPLIER).contains(&credit_multiplier)
+                && attester != Pubkey::default()
+                && vault.holdback_bps > 0
+                && vault.holdback_days > 0,
+            ErrorCode::InvalidSlaCommitment
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        let sla = &mut ctx.accounts.sla;
+        sla.recipient = ctx.accounts.recipient.key();
+        sla.attester = attester;
+        sla.credit_multiplier = credit_multiplier;
+        sla.committed_at = now;
+        sla.ends_at = i64::MAX;
+        sla.bump = ctx.bumps.sla;
+
+        emit!(SlaCommitmentChanged {
+            recipient: sla.recipient,
+            attester,
+            credit_multiplier,
+            ends_at: sla.ends_at,
+            timestamp: now,
+        });
+
+        msg!("SLA attester: {}", attester);
+
+        Ok(())
+    }
+
+    /// Give notice that the commitment ends in `SLA_NOTICE_SECONDS`. Outages
+    /// starting before then are still covered. Signed by the merchant
+    /// authority.
+    pub fn end_sla_commitment(ctx: Context<EndSlaCommitment>) -> Result<()> {
+        check_merchant_authority(
+            &ctx.accounts.merchant_multisig,
+            &ctx.accounts.recipient.key(),
+            &ctx.accounts.authority.key(),
+        )?;
+
+        let now = Clock::get()?.unix_timestamp;
+        let sla = &mut ctx.accounts.sla;
+        let ends_at = now
+            .checked_add(SLA_NOTICE_SECONDS)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        sla.ends_at = sla.ends_at.min(ends_at);
+
+        emit!(SlaCommitmentChanged {
+            recipient: sla.recipient,
+            attester: sla.attester,
+            credit_multiplier: sla.credit_multiplier,
+            ends_at: sla.ends_at,
+            timestamp: now,
+        });
+
+        msg!("SLA commitment ends at: {}", sla.ends_at);
+
+        Ok(())
+    }
+
+    /// Close an ended commitment and refund its rent to the recipient.
+    /// Signed by the merchant authority.
+    pub fn close_sla_commitment(ctx: Context<CloseSlaCommitment>) -> Result<()> {
+        check_merchant_authority(
+            &ctx.accounts.merchant_multisig,
+            &ctx.accounts.recipient.key(),
+            &ctx.accounts.authority.key(),
+        )?;
+        require!(
+            Clock::get()?.unix_timestamp >= ctx.accounts.sla.ends_at,
+            ErrorCode::SlaCommitmentActive
+        );
+
+        msg!("SLA commitment closed");
+
+        Ok(())
+    }
+
+    /// Record an outage of the recipient's service from `started_at` to
+    /// `ended_at`. Signed by the commitment's attester; `payer` can be a
+    /// relayer.
+    pub fn attest_downtime(
+        ctx: Context<AttestDowntime>,
+        started_at: i64,
+        ended_at: i64,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let sla = &ctx.accounts.sla;
+        require!(
+            sla.covers(started_at, ended_at, now),
+            ErrorCode::InvalidDowntime
+        );
+
+        let attestation = &mut ctx.accounts.attestation;
+        attestation.recipient = sla.recipient;
+        attestation.attester = sla.attester;
+        attestation.started_at = started_at;
+        attestation.ended_at = ended_at;
+        attestation.bump = ctx.bumps.attestation;
+
+        emit!(DowntimeAttested {
+            recipient: attestation.recipient,
+            attestation: attestation.key(),
+            attester: attestation.attester,
+            started_at,
+            ended_at,
+            timestamp: now,
+        });
+
+        msg!("Downtime attested: {} to {}", started_at, ended_at);
+
+        Ok(())
+    }
+
+    /// Pay a subscriber its credit for an attested outage from the
+    /// recipient's held-back revenue. Anyone can send it, once per
+    /// subscription and outage, so a keeper can credit every affected
+    /// subscriber.
+    pub fn credit_sla(ctx: Context<CreditSla>) -> Result<()> {
+        let attestation = &ctx.accounts.attestation;
+        let amount = ctx.accounts.sla.credit_for(
+            &ctx.accounts.subscription,
+            attestation.started_at,
+            attestation.ended_at,
+        );
+        require!(amount > 0, ErrorCode::NoSlaCredit);
+
+        let balance = vault_balance(&ctx.accounts.token_account)?;
+        let now = Clock::get()?.unix_timestamp;
+        let vault = &mut ctx.accounts.vault;
+        sync_vault(vault, balance, now)?;
+        vault.draw_held(amount, now)?;
+        // A credit leaves the vault like a withdrawal, so the next sync
+        // does not see it as missing revenue
+        vault.total_withdrawn = vault
+            .total_withdrawn
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        vault.exit(&crate::ID)?;
+
+        let credit = &mut ctx.accounts.credit;
+        credit.attestation = ctx.accounts.attestation.key();
+        credit.subscription = ctx.accounts.subscription.key();
+        credit.amount = amount;
+        credit.pending = 0;
+        credit.bump = ctx.bumps.credit;
+        credit.exit(&crate::ID)?;
+
+        let recipient = vault.recipient;
+        let seeds = &[b"merchant_vault", recipient.as_ref(), &[vault.bump]];
+        let signer_seeds = &[&seeds[..]];
+
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.token_account.key(),
+            &ctx.accounts.user_token_account.key(),
+            &vault.key(),
+            &[],
+            amount,
+        )?;
+
+        invoke_signed(
+            &transfer_ix,
+            &[
+                ctx.accounts.token_account.to_account_info(),
+                ctx.accounts.user_token_account.to_account_info(),
+                vault.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+            signer_seeds,
+        )?;
+
+        emit!(SlaCreditPaid {
+            recipient,
+            subscription: ctx.accounts.subscription.key(),
+            attestation: ctx.accounts.attestation.key(),
+            amount,
+            timestamp: now,
+        });
+
+        msg!("SLA credit: {} tokens", amount);
+
+        Ok(())
+    }
+
+    /// Take a subscriber's credit for an attested outage off its next
+    /// charges instead of paying it from held revenue. Anyone can send it,
+    /// once per subscription and outage, like `credit_sla`; the keeper then
+    /// passes the receipt with the subscription's charges until it is used
+    /// up.
+    pub fn schedule_sla_discount(ctx: Context<ScheduleSlaDiscount>) -> Result<()> {
+        let attestation = &ctx.accounts.attestation;
+        let amount = ctx.accounts.sla.credit_for(
+            &ctx.accounts.subscription,
+            attestation.started_at,
+            attestation.ended_at,
+        );
+        require!(amount > 0, ErrorCode::NoSlaCredit);
+
+        let credit = &mut ctx.accounts.credit;
+        credit.attestation = attestation.key();
+        credit.subscription = ctx.accounts.subscription.key();
+        credit.amount = amount;
+        credit.pending = amount;
+        credit.bump = ctx.bumps.credit;
+
+        emit!(SlaDiscountScheduled {
+            recipient: ctx.accounts.sla.recipient,
+            subscription: credit.subscription,
+            attestation: credit.attestation,
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("SLA discount: {} tokens", amount);
+
+        Ok(())
+    }
+
+    /// Create the cache of Pyth price feed `feed_id`. Anyone can create it,
+    /// since it only ever holds what `refresh_price_cache` copies from Pyth.
+    pub fn create_price_cache(ctx: Context<CreatePriceCache>, feed_id: [u8; 32]) -> Result<()> {
+        let cache = &mut ctx.accounts.price_cache;
+        cache.feed_id = feed_id;
+        cache.bump = ctx.bumps.price_cache;
+
+        msg!("Price cache created");
+
+        Ok(())
+    }
+
+    /// Copy the price from a fully verified Pyth `PriceUpdateV2` account of
+    /// the cache's feed, so USD-priced charges read the small cache instead
+    /// of the update. Anyone can send it; an update no newer than the cache
+    /// leaves it as it is.
+    pub fn refresh_price_cache(ctx: Context<RefreshPriceCache>) -> Result<()> {
+        let update = PythPriceUpdate::load(&ctx.accounts.price_update)?;
+        let cache = &mut ctx.accounts.price_cache;
+        require!(
+            update.feed_id == cache.feed_id,
+            ErrorCode::InvalidPriceUpdate
+        );
+        if update.publish_time <= cache.publish_time {
+            msg!("Price cache is already at {}", cache.publish_time);
+            return Ok(());
+        }
+
+        cache.price = update.price;
+        cache.conf = update.conf;
+        cache.exponent = update.exponent;
+        cache.publish_time = update.publish_time;
+
+        emit!(PriceCacheRefreshed {
+            price_cache: cache.key(),
+            price: update.price,
+            conf: update.conf,
+            exponent: update.exponent,
+            publish_time: update.publish_time,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Price: {} x 10^{}", update.price, update.exponent);
+
+        Ok(())
+    }
+
+    /// Price the subscription in USD: each charge takes `usd_per_period`
+    /// (6 decimals) worth of its token at the price in `price_cache`.
+    /// Signed by the subscription's authority, who sets the amount as with
+    /// `update_subscription`, so not on a plan; `payer` can be a relayer.
+    pub fn set_usd_price(ctx: Context<SetUsdPrice>, usd_per_period: u64) -> Result<()> {
+        require!(usd_per_period > 0, ErrorCode::InvalidAmount);
+        let subscription = &mut ctx.accounts.subscription;
+        require!(subscription.is_active, ErrorCode::SubscriptionInactive);
+        require!(!subscription.on_plan, ErrorCode::PricedByPlan);
+        let mint = spl_token::state::Mint::unpack(&ctx.accounts.token_mint.try_borrow_data()?)
+            .map_err(|_| ErrorCode::InvalidTokenAccount)?;
+
+        let peg = &mut ctx.accounts.usd_peg;
+        peg.subscription = subscription.key();
+        peg.price_cache = ctx.accounts.price_cache.key();
+        peg.usd_per_period = usd_per_period;
+        peg.token_decimals = mint.decimals;
+        peg.bump = ctx.bumps.usd_peg;
+        // No token amount marks the subscription as USD-priced, so a plain
+        // charge cannot bill it at an old conversion
+        subscription.amount_per_period = 0;
+
+        emit!(UsdPriceSet {
+            subscription: peg.subscription,
+            price_cache: peg.price_cache,
+            usd_per_period,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("USD price: {} per period", usd_per_period);
+
+        Ok(())
+    }
+
+    /// Go back to a fixed `amount_per_period` in tokens and refund the USD
+    /// price's rent. Signed by the subscription's authority.
+    pub fn close_usd_peg(ctx: Context<CloseUsdPeg>, amount_per_period: u64) -> Result<()> {
+        require!(amount_per_period > 0, ErrorCode::InvalidAmount);
+        let subscription = &mut ctx.accounts.subscription;
+        subscription.amount_per_period = amount_per_period;
+
+        emit!(SubscriptionUpdated {
+            subscription: subscription.key(),
+            amount_per_period,
+            interval_seconds: subscription.interval_seconds,
+            expires_at: subscription.expires_at,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Updated amount to: {} tokens", amount_per_period);
+
+        Ok(())
+    }
+
+    /// Charge a USD-priced subscription its `usd_per_period` in tokens at
+    /// the cached price, which must be at most `MAX_PRICE_AGE_SECONDS` old.
+    /// Takes the same remaining accounts as `charge_subscription`.
+    pub fn charge_subscription_usd<'info>(
+        ctx: Context<'_, '_, '_, 'info, ChargeSubscriptionUsd<'info>>,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let amount = ctx
+            .accounts
+            .usd_peg
+            .token_amount(&ctx.accounts.price_cache, now)?;
+
+        msg!("USD price converted to {} tokens", amount);
+
+        charge(
+            &mut ctx.accounts.charge,
+            ctx.remaining_accounts,
+            Some(amount),
+            None,
+            None,
+        )
+    }
+
+    /// Accept payment in another mint of the same value, such as USDT next
+    /// to USDC, into `token_account`. Subscribers can then fall back to it
+    /// when their primary account is empty. Signed by the merchant
+    /// authority; the token account must be the recipient's or its vault's.
+    pub fn accept_fallback_mint(ctx: Context<AcceptFallbackMint>) -> Result<()> {
+        check_merchant_authority(
+            &ctx.accounts.merchant_multisig,
+            &ctx.accounts.recipient.key(),
+            &ctx.accounts.authority.key(),
+        )?;
+
+        let recipient = ctx.accounts.recipient.key();
+        let token_account =
+            spl_token::state::Account::unpack(&ctx.accounts.token_account.try_borrow_data()?)
+                .map_err(|_| ErrorCode::InvalidFallbackMint)?;
+        let vault =
+            Pubkey::find_program_address(&[b"merchant_vault", recipient.as_ref()], &crate::ID).0;
+        require!(
+            token_account.mint == ctx.accounts.token_mint.key()
+                && (token_account.owner == recipient || token_account.owner == vault),
+            ErrorCode::InvalidFallbackMint
+        );
+
+        let accepted = &mut ctx.accounts.accepted_mint;
+        accepted.recipient = recipient;
+        accepted.token_mint = token_account.mint;
+        accepted.token_account = ctx.accounts.token_account.key();
+        accepted.bump = ctx.bumps.accepted_mint;
+
+        emit!(FallbackMintChanged {
+            recipient,
+            token_mint: accepted.token_mint,
+            token_account: Some(accepted.token_account),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Fallback mint accepted: {}", accepted.token_mint);
+
+        Ok(())
+    }
+
+    /// Stop accepting a fallback mint and refund the rent to the recipient.
+    /// Subscriptions falling back to it can no longer be charged from it.
+    /// Signed by the merchant authority.
+    pub fn close_accepted_mint(ctx: Context<CloseAcceptedMint>) -> Result<()> {
+        check_merchant_authority(
+            &ctx.accounts.merchant_multisig,
+            &ctx.accounts.recipient.key(),
+            &ctx.accounts.authority.key(),
+        )?;
+
+        emit!(FallbackMintChanged {
+            recipient: ctx.accounts.recipient.key(),
+            token_mint: ctx.accounts.accepted_mint.token_mint,
+            token_account: None,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Fallback mint closed");
+
+        Ok(())
+    }
+
+    /// Name a token account of a mint the recipient accepts to pay from when
+    /// the subscription's own account is empty. Signed by the subscription's
+    /// authority, who approves the account to the subscription PDA as with
+    /// the primary one; `payer` can be a relayer.
+    pub fn set_fallback_payment(ctx: Context<SetFallbackPayment>) -> Result<()> {
+        let token_account =
+            spl_token::state::Account::unpack(&ctx.accounts.token_account.try_borrow_data()?)
+                .map_err(|_| ErrorCode::InvalidFallbackPayment)?;
+        let decimals = |mint: &AccountInfo| -> Result<u8> {
+            let mint = spl_token::state::Mint::unpack(&mint.try_borrow_data()?)
+                .map_err(|_| ErrorCode::InvalidFallbackPayment)?;
+            Ok(mint.decimals)
+        };
+        FallbackPayment::check(
+            &ctx.accounts.subscription,
+            &ctx.accounts.accepted_mint,
+            &token_account,
+            decimals(&ctx.accounts.token_mint)?,
+            decimals(&ctx.accounts.fallback_mint)?,
+        )?;
+
+        let fallback = &mut ctx.accounts.fallback_payment;
+        fallback.subscription = ctx.accounts.subscription.key();
+        fallback.token_mint = token_account.mint;
+        fallback.token_account = ctx.accounts.token_account.key();
+        fallback.bump = ctx.bumps.fallback_payment;
+
+        msg!("Fallback payment: {}", fallback.token_account);
+
+        Ok(())
+    }
+
+    /// Stop falling back to another mint and refund the rent. Signed by the
+    /// subscription's authority.
+    pub fn close_fallback_payment(_ctx: Context<CloseFallbackPayment>) -> Result<()> {
+        msg!("Fallback payment closed");
+
+        Ok(())
+    }
+
+    /// Charge the subscription from its fallback mint, when its primary
+    /// account cannot pay a period. Priced, limited and booked as
+    /// `charge_subscription` is, with the same remaining accounts except
+    /// funding sources: the fallback mint's account pays alone.
+    /// Permissionless, like `charge_subscription`.
+    pub fn charge_subscription_fallback<'info>(
+        ctx: Context<'_, '_, '_, 'info, ChargeSubscriptionFallback<'info>>,
+    ) -> Result<()> {
+        let accounts = &mut *ctx.accounts;
+        let fallback_mint = (
+            accounts.token_account.to_account_info(),
+            accounts.merchant_token_account.to_account_info(),
+        );
+
+        msg!("Charging from fallback mint {}", accounts.fallback_payment.token_mint);
+
+        charge(
+            &mut accounts.charge,
+            ctx.remaining_accounts,
+            None,
+            None,
+            Some(fallback_mint),
+        )
+    }
+
+    /// Pay an upfront deposit, such as for equipment, into an escrow token
+    /// account owned by the deposit PDA. It is kept apart from the recurring
+    /// charges and `total_charged`, and goes back to the user's token
+    /// account once the subscription is cancelled. Signed by the
+    /// subscription's authority; `payer` can be a relayer.
+    pub fn pay_deposit(ctx: Context<PayDeposit>, amount: u64) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        let subscription = &ctx.accounts.subscription;
+        require!(subscription.is_active, ErrorCode::SubscriptionInactive);
+
+        require_keys_eq!(
+            *ctx.accounts.token_account.owner,
+            spl_token::ID,
+            ErrorCode::InvalidDepositAccount
+        );
+        let token_account =
+            spl_token::state::Account::unpack(&ctx.accounts.token_account.try_borrow_data()?)
+                .map_err(|_| ErrorCode::InvalidDepositAccount)?;
+        SubscriptionDeposit::check_escrow(
+            &ctx.accounts.deposit.key(),
+            &subscription.token_mint,
+            &token_account,
+        )?;
+
+        let now = Clock::get()?.unix_timestamp;
+        let deposit = &mut ctx.accounts.deposit;
+        deposit.subscription = subscription.key();
+        deposit.authority = subscription.authority;
+        deposit.recipient = subscription.recipient;
+        deposit.token_account = ctx.accounts.token_account.key();
+        deposit.refund_account = subscription.user_token_account;
+        deposit.amount = amount;
+        deposit.paid_at = now;
+        deposit.bump = ctx.bumps.deposit;
+
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.user_token_account.key(),
+            &ctx.accounts.token_account.key(),
+            &ctx.accounts.authority.key(),
+            &[],
+            amount,
+        )?;
+        anchor_lang::solana_program::program::invoke(
+            &transfer_ix,
+            &[
+                ctx.accounts.user_token_account.to_account_info(),
+                ctx.accounts.token_account.to_account_info(),
+                ctx.accounts.authority.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        emit!(DepositPaid {
+            subscription: deposit.subscription,
+            authority: deposit.authority,
+            recipient: deposit.recipient,
+            amount,
+            timestamp: now,
+        });
+
+        msg!("Deposit paid: {} tokens", amount);
+
+        Ok(())
+    }
+
+    /// Return a deposit once its subscription is cancelled: closed,
+    /// compacted to a tombstone or deactivated. The escrow's whole balance
+    /// goes to the account it was paid from and the rent of both accounts to
+    /// the user. Permissionless, since the tokens can only go back.
+    pub fn refund_deposit(ctx: Context<RefundDeposit>) -> Result<()> {
+        SubscriptionDeposit::check_refundable(&ctx.accounts.subscription)?;
+
+        let deposit = &ctx.accounts.deposit;
+        let amount =
+            spl_token::state::Account::unpack(&ctx.accounts.token_account.try_borrow_data()?)
+                .map_err(|_| ErrorCode::InvalidDepositAccount)?
+                .amount;
+        let subscription_key = deposit.subscription;
+        let seeds = &[b"deposit", subscription_key.as_ref(), &[deposit.bump]];
+        let signer_seeds = &[&seeds[..]];
+
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.token_account.key(),
+            &ctx.accounts.refund_account.key(),
+            &deposit.key(),
+            &[],
+            amount,
+        )?;
+        invoke_signed(
+            &transfer_ix,
+            &[
+                ctx.accounts.token_account.to_account_info(),
+                ctx.accounts.refund_account.to_account_info(),
+                deposit.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+            signer_seeds,
+        )?;
+
+        let close_ix = token_instruction::close_account(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.token_account.key(),
+            &ctx.accounts.authority.key(),
+            &deposit.key(),
+            &[],
+        )?;
+        invoke_signed(
+            &close_ix,
+            &[
+                ctx.accounts.token_account.to_account_info(),
+                ctx.accounts.authority.to_account_info(),
+                deposit.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+            signer_seeds,
+        )?;
+
+        emit!(DepositRefunded {
+            subscription: subscription_key,
+            authority: deposit.authority,
+            recipient: deposit.recipient,
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Deposit refunded: {} tokens", amount);
+
+        Ok(())
+    }
+
+    /// Offer a plan priced monthly, with `annual_discount_bps` off twelve
+    /// months for subscribers billed annually. Signed by the merchant
+    /// authority.
+    pub fn create_plan(
+        ctx: Context<CreatePlan>,
+        plan_id: u64,
+        monthly_price: u64,
+        annual_discount_bps: u16,
+    ) -> Result<()> {
+        check_merchant_authority(
+            &ctx.accounts.merchant_multisig,
+            &ctx.accounts.recipient.key(),
+            &ctx.accounts.authority.key(),
+        )?;
+        require!(monthly_price > 0, ErrorCode::InvalidAmount);
+        require!(annual_discount_bps < MAX_BPS, ErrorCode::InvalidPlan);
+
+        let plan = &mut ctx.accounts.plan;
+        plan.recipient = ctx.accounts.recipient.key();
+        plan.plan_id = plan_id;
+        plan.token_mint = ctx.accounts.token_mint.key();
+        plan.monthly_price = monthly_price;
+        plan.annual_discount_bps = annual_discount_bps;
+        plan.included_units = 0;
+        plan.upgrade_after_periods = 0;
+        plan.next_tier = None;
+        plan.overage_rate = 0;
+        plan.max_subscribers = 0;
+        plan.subscribers = 0;
+        plan.intro_discount_bps = 0;
+        plan.intro_periods = 0;
+        plan.price_ramp = Vec::new();
+        plan.bump = ctx.bumps.plan;
+        // The annual price must fit in a token amount
+        plan.annual_price()?;
+
+        emit!(PlanCreated {
+            plan: plan.key(),
+            recipient: plan.recipient,
+            plan_id,
+            token_mint: plan.token_mint,
+            monthly_price,
+            annual_discount_bps,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Plan {} created", plan_id);
+
+        Ok(())
+    }
+
+    /// Bill a subscription at one of its recipient's plans, monthly or
+    /// annually, from its next charge. Signed by the subscription's
+    /// authority; `payer` can be a relayer.
+    pub fn join_plan(ctx: Context<JoinPlan>, cadence: BillingCadence) -> Result<()> {
+        let subscription = &mut ctx.accounts.subscription;
+        require!(subscription.is_active, ErrorCode::SubscriptionInactive);
+
+        let plan = &mut ctx.accounts.plan;
+        Waitlist::check_no_queue(&ctx.accounts.waitlist)?;
+        plan.take_seat()?;
+        plan.apply(subscription, cadence)?;
+        let full_price = subscription.amount_per_period;
+        let price_steps = plan.start_schedule(subscription, cadence, 0)?;
+        subscription.on_plan = true;
+        ctx.accounts.plan_subscription.set_inner(PlanSubscription {
+            subscription: subscription.key(),
+            plan: plan.key(),
+            cadence,
+            period_start: subscription.last_charge_timestamp,
+            units: 0,
+            periods_over: 0,
+            upgrade_at: None,
+            overage_billed: 0,
+            overage_charged: 0,
+            price_steps,
+            full_price,
+            bump: ctx.bumps.plan_subscription,
+        });
+
+        emit!(BillingCadenceChanged {
+            subscription: subscription.key(),
+            plan: plan.key(),
+            cadence,
+            amount_per_period: subscription.amount_per_period,
+            prorated_amount: 0,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Joined plan {} billed {:?}", plan.plan_id, cadence);
+
+        Ok(())
+    }
+
+    /// Move a plan subscription between monthly and annual billing, with
+    /// the period it is in prorated; see [`Plan::switch_cadence`]. Going
+    /// annual charges the difference now. Signed by the subscription's
+    /// authority.
+    pub fn switch_billing_cadence(
+        ctx: Context<SwitchBillingCadence>,
+        cadence: BillingCadence,
+    ) -> Result<()> {
+        let accounts = &mut *ctx.accounts;
+        let subscription = &mut accounts.subscription;
+        let current_time = Clock::get()?.unix_timestamp;
+        assert_active_subscription(subscription, current_time)?;
+
+        let spend_left = subscription.spend_left();
+        let prorated_amount = accounts.plan.switch_cadence(
+            subscription,
+            accounts.plan_subscription.cadence,
+            cadence,
+        )?;
+        require!(
+            prorated_amount <= spend_left,
+            ErrorCode::SpendLimitExceeded
+        );
+        accounts.plan_subscription.cadence = cadence;
+        // The new price is the full one; what is left of a price schedule
+        // is gone
+        accounts.plan_subscription.price_steps.clear();
+        accounts.plan_subscription.full_price = subscription.amount_per_period;
+
+        // As in `charge_subscription`: the switch is booked before the CPI
+        subscription.exit(&crate::ID)?;
+        accounts.plan_subscription.exit(&crate::ID)?;
+
+        let subscription = &accounts.subscription;
+        if prorated_amount > 0 {
+            let seeds = &[
+                b"subscription",
+                subscription.authority.as_ref(),
+                subscription.recipient.as_ref(),
+                &[subscription.bump],
+            ];
+            let signer_seeds = &[&seeds[..]];
+
+            let transfer_ix = token_instruction::transfer(
+                &accounts.token_program.key(),
+                &accounts.user_token_account.key(),
+                &accounts.recipient_token_account.key(),
+                &subscription.key(),
+                &[],
+                prorated_amount,
+            )?;
+
+            invoke_signed(
+                &transfer_ix,
+                &[
+                    accounts.user_token_account.to_account_info(),
+                    accounts.recipient_token_account.to_account_info(),
+                    subscription.to_account_info(),
+                    accounts.token_program.to_account_info(),
+                ],
+                signer_seeds,
+            )?;
+
+            emit!(SubscriptionCharged {
+                subscription: subscription.key(),
+                authority: subscription.authority,
+                recipient: subscription.recipient,
+                amount: prorated_amount,
+                total_charged: subscription.total_charged,
+                reference: None,
+                timestamp: current_time,
+            });
+        }
+
+        emit!(BillingCadenceChanged {
+            subscription: subscription.key(),
+            plan: accounts.plan.key(),
+            cadence,
+            amount_per_period: subscription.amount_per_period,
+            prorated_amount,
+            timestamp: current_time,
+        });
+
+        msg!("Billing switched to {:?}", cadence);
+        msg!("Prorated charge: {} tokens", prorated_amount);
+
+        Ok(())
+    }
+
+    /// Meter a plan: each period includes `included_units`, and a
+    /// subscription over them for `upgrade_after_periods` periods in a row
+    /// is moved to `next_tier`, if there is one. Units beyond the allotment
+    /// can be billed at `overage_rate` each; zero turns that off. Signed by
+    /// the merchant authority.
+    pub fn set_plan_metering(
+        ctx: Context<SetPlanMetering>,
+        included_units: u64,
+        upgrade_after_periods: u8,
+        next_tier: Option<Pubkey>,
+        overage_rate: u64,
+    ) -> Result<()> {
+        check_merchant_authority(
+            &ctx.accounts.merchant_multisig,
+            &ctx.accounts.recipient.key(),
+            &ctx.accounts.authority.key(),
+        )?;
+        let plan = &mut ctx.accounts.plan;
+        require!(
+            included_units > 0
+                && (next_tier.is_none() || upgrade_after_periods > 0)
+                && next_tier != Some(plan.key()),
+            ErrorCode::InvalidPlan
+        );
+
+        plan.included_units = included_units;
+        plan.upgrade_after_periods = upgrade_after_periods;
+        plan.next_tier = next_tier;
+        plan.overage_rate = overage_rate;
+
+        emit!(PlanMeteringSet {
+            plan: plan.key(),
+            included_units,
+            upgrade_after_periods,
+            next_tier,
+            overage_rate,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Plan {} includes {} units", plan.plan_id, included_units);
+
+        Ok(())
+    }
+
+    /// Record `units` of usage in a subscription's current period. Closing
+    /// a period over the plan's included units for the plan's run of periods
+    /// schedules an upgrade to the next tier, which the user can veto for
+    /// `TIER_UPGRADE_VETO_SECONDS`. Signed by the merchant authority.
+    pub fn record_usage(ctx: Context<RecordUsage>, units: u64) -> Result<()> {
+        check_merchant_authority(
+            &ctx.accounts.merchant_multisig,
+            &ctx.accounts.recipient.key(),
+            &ctx.accounts.authority.key(),
+        )?;
+        let now = Clock::get()?.unix_timestamp;
+        let subscription = &ctx.accounts.subscription;
+        require!(subscription.is_active, ErrorCode::SubscriptionInactive);
+
+        let plan = &ctx.accounts.plan;
+        let usage = &mut ctx.accounts.plan_subscription;
+        let scheduled = usage.record_usage(plan, subscription.last_charge_timestamp, units, now)?;
+
+        emit!(UsageRecorded {
+            subscription: subscription.key(),
+            plan: plan.key(),
+            period_start: usage.period_start,
+            units,
+            period_units: usage.units,
+            timestamp: now,
+        });
+        if let Some(effective_at) = scheduled {
+            emit!(TierUpgradeScheduled {
+                subscription: subscription.key(),
+                next_tier: plan.next_tier,
+                effective_at,
+                timestamp: now,
+            });
+            msg!("Tier upgrade scheduled for {}", effective_at);
+        }
+
+        msg!("Usage this period: {} units", usage.units);
+
+        Ok(())
+    }
+
+    /// Turn down a scheduled tier upgrade while its veto window is open.
+    /// The run of periods over the tier starts again. Signed by the
+    /// subscription's authority.
+    pub fn veto_tier_upgrade(ctx: Context<VetoTierUpgrade>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let usage = &mut ctx.accounts.plan_subscription;
+        let upgrade_at = usage.upgrade_at.ok_or(ErrorCode::NoTierUpgrade)?;
+        require!(now < upgrade_at, ErrorCode::TierUpgradeVetoClosed);
+
+        usage.upgrade_at = None;
+        usage.periods_over = 0;
+
+        emit!(TierUpgradeScheduled {
+            subscription: usage.subscription,
+            next_tier: None,
+            effective_at: upgrade_at,
+            timestamp: now,
+        });
+
+        msg!("Tier upgrade vetoed");
+
+        Ok(())
+    }
+
+    /// Move a subscription to the next tier once its upgrade's veto window
+    /// has run out, billed at the new plan from the next charge. Anyone can
+    /// send it.
+    pub fn apply_tier_upgrade(ctx: Context<ApplyTierUpgrade>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let usage = &mut ctx.accounts.plan_subscription;
+        let upgrade_at = usage.upgrade_at.ok_or(ErrorCode::NoTierUpgrade)?;
+        require!(now >= upgrade_at, ErrorCode::TierUpgradeVetoOpen);
+
+        let subscription = &mut ctx.accounts.subscription;
+        require!(subscription.is_active, ErrorCode::SubscriptionInactive);
+        let next_tier = &mut ctx.accounts.next_tier;
+        next_tier.take_seat()?;
+        ctx.accounts.plan.free_seat();
+        next_tier.apply(subscription, usage.cadence)?;
+        usage.plan = next_tier.key();
+        usage.upgrade_at = None;
+        usage.periods_over = 0;
+        usage.price_steps.clear();
+        usage.full_price = subscription.amount_per_period;
+
+        emit!(TierUpgraded {
+            subscription: subscription.key(),
+            from_plan: ctx.accounts.plan.key(),
+            to_plan: next_tier.key(),
+            amount_per_period: subscription.amount_per_period,
+            timestamp: now,
+        });
+
+        msg!("Upgraded to plan {}", next_tier.plan_id);
+
+        Ok(())
+    }
+
+    /// Bill `units` of recorded usage beyond the plan's allotment at its
+    /// overage rate, apart from the scheduled charges and `total_charged`.
+    /// Only usage of the period being counted can be billed, so bill it
+    /// before recording usage in the next one. The subscription must still
+    /// be active. Signed by the merchant authority.
+    pub fn charge_overage(ctx: Context<ChargeOverage>, units: u64) -> Result<()> {
+        check_merchant_authority(
+            &ctx.accounts.merchant_multisig,
+            &ctx.accounts.recipient.key(),
+            &ctx.accounts.authority.key(),
+        )?;
+        let accounts = &mut *ctx.accounts;
+        require!(
+            accounts.subscription.is_active,
+            ErrorCode::SubscriptionInactive
+        );
+        let amount = accounts
+            .plan_subscription
+            .bill_overage(&accounts.plan, units)?;
+        require!(
+            amount <= accounts.subscription.spend_left(),
+            ErrorCode::SpendLimitExceeded
+        );
+
+        // As in `charge_subscription`: the units are booked before the CPI
+        accounts.plan_subscription.exit(&crate::ID)?;
+
+        let subscription = &accounts.subscription;
+        let seeds = &[
+            b"subscription",
+            subscription.authority.as_ref(),
+            subscription.recipient.as_ref(),
+            &[subscription.bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let transfer_ix = token_instruction::transfer(
+            &accounts.token_program.key(),
+            &accounts.user_token_account.key(),
+            &accounts.recipient_token_account.key(),
+            &subscription.key(),
+            &[],
+            amount,
+        )?;
+
+        invoke_signed(
+            &transfer_ix,
+            &[
+                accounts.user_token_account.to_account_info(),
+                accounts.recipient_token_account.to_account_info(),
+                subscription.to_account_info(),
+                accounts.token_program.to_account_info(),
+            ],
+            signer_seeds,
+        )?;
+
+        let usage = &accounts.plan_subscription;
+        emit!(OverageCharged {
+            subscription: subscription.key(),
+            plan: usage.plan,
+            period_start: usage.period_start,
+            units,
+            amount,
+            overage_charged: usage.overage_charged,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Overage charged: {} units, {} tokens", units, amount);
+
+        Ok(())
+    }
+
+    /// Set milestones of `total_charged` that emit `SpendThresholdCrossed`
+    /// when a charge passes them, so wallets can tell the user what they
+    /// have spent with the merchant. Signed by the subscription's
+    /// authority; `payer` can be a relayer.
+    pub fn create_spend_alerts(
+        ctx: Context<CreateSpendAlerts>,
+        thresholds: Vec<u64>,
+    ) -> Result<()> {
+        SpendAlerts::check(&thresholds)?;
+
+        let alerts = &mut ctx.accounts.spend_alerts;
+        alerts.subscription = ctx.accounts.subscription.key();
+        alerts.authority = ctx.accounts.authority.key();
+        alerts.thresholds = thresholds;
+        alerts.bump = ctx.bumps.spend_alerts;
+
+        msg!("Spend alerts: {}", alerts.thresholds.len());
+
+        Ok(())
+    }
+
+    /// Replace the milestones; an empty list turns the alerts off
+    pub fn update_spend_alerts(
+        ctx: Context<UpdateSpendAlerts>,
+        thresholds: Vec<u64>,
+    ) -> Result<()> {
+        SpendAlerts::check(&thresholds)?;
+
+        let alerts = &mut ctx.accounts.spend_alerts;
+        alerts.thresholds = thresholds;
+
+        msg!("Spend alerts: {}", alerts.thresholds.len());
+
+        Ok(())
+    }
+
+    /// Close the milestones and refund their rent, also after the
+    /// subscription itself was cancelled
+    pub fn close_spend_alerts(_ctx: Context<CloseSpendAlerts>) -> Result<()> {
+        msg!("Spend alerts closed - rent refunded to user");
+
+        Ok(())
+    }
+
+    /// Cap what the subscription can ever take from the user's token
+    /// account at `max_total_spend`, `total_charged` included, or lift the
+    /// cap with `None`. The delegation is re-approved for what is left, so
+    /// the token program enforces the cap whichever keeper or charge moves
+    /// the tokens. The limit is also stored on the subscription and checked
+    /// by every charge, so fallback accounts cannot go past it either. Once
+    /// too little is left for a period, the next charge deactivates the
+    /// subscription.
+    pub fn set_spend_limit(
+        ctx: Context<SetSpendLimit>,
+        max_total_spend: Option<u64>,
+    ) -> Result<()> {
+        let subscription = &mut ctx.accounts.subscription;
+        require!(subscription.is_active, ErrorCode::SubscriptionInactive);
+        // Approving would take the account from a spending policy
+        require!(
+            holds_delegation(&ctx.accounts.user_token_account, &subscription.key()),
+            ErrorCode::InvalidSpendLimit
+        );
+        let allowance = match max_total_spend {
+            Some(limit) => limit
+                .checked_sub(subscription.total_charged)
+                .ok_or(ErrorCode::InvalidSpendLimit)?,
+            None => u64::MAX,
+        };
+        subscription.max_total_spend = max_total_spend.unwrap_or(Subscription::NO_SPEND_LIMIT);
+        // As everywhere else: written before the CPI
+        subscription.exit(&crate::ID)?;
+
+        let approve_ix = token_instruction::approve(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.user_token_account.key(),
+            &subscription.key(),
+            &ctx.accounts.authority.key(),
+            &[],
+            allowance,
+        )?;
+        anchor_lang::solana_program::program::invoke(
+            &approve_ix,
+            &[
+                ctx.accounts.user_token_account.to_account_info(),
+                subscription.to_account_info(),
+                ctx.accounts.authority.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        emit!(SpendLimitSet {
+            subscription: subscription.key(),
+            authority: subscription.authority,
+            max_total_spend,
+            allowance,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        match max_total_spend {
+            Some(limit) => msg!("Spend limit: {} tokens, {} left", limit, allowance),
+            None => msg!("Spend limit lifted"),
+        }
+
+        Ok(())
+    }
+
+    /// Put `wallet` on the recipient's denylist, so it can no longer open a
+    /// subscription to the recipient; one it already has is left alone.
+    /// Signed by the merchant authority; `payer` can be a relayer.
+    pub fn deny_subscriber(ctx: Context<DenySubscriber>, wallet: Pubkey) -> Result<()> {
+        check_merchant_authority(
+            &ctx.accounts.merchant_multisig,
+            &ctx.accounts.recipient.key(),
+            &ctx.accounts.authority.key(),
+        )?;
+
+        let now = Clock::get()?.unix_timestamp;
+        let entry = &mut ctx.accounts.denylist_entry;
+        entry.recipient = ctx.accounts.recipient.key();
+        entry.wallet = wallet;
+        entry.denied_at = now;
+        entry.bump = ctx.bumps.denylist_entry;
+
+        emit!(DenylistChanged {
+            recipient: entry.recipient,
+            wallet,
+            denied: true,
+            timestamp: now,
+        });
+
+        msg!("Wallet {} denied", wallet);
+
+        Ok(())
+    }
+
+    /// Take a wallet off the denylist and refund the rent to the recipient.
+    /// Signed by the merchant authority.
+    pub fn allow_subscriber(ctx: Context<AllowSubscriber>) -> Result<()> {
+        check_merchant_authority(
+            &ctx.accounts.merchant_multisig,
+            &ctx.accounts.recipient.key(),
+            &ctx.accounts.authority.key(),
+        )?;
+
+        let wallet = ctx.accounts.denylist_entry.wallet;
+        emit!(DenylistChanged {
+            recipient: ctx.accounts.recipient.key(),
+            wallet,
+            denied: false,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Wallet {} allowed again", wallet);
+
+        Ok(())
+    }
+
+    /// Make the recipient invite-only: from now on only wallets on its
+    /// allowlist can open a subscription to it. Signed by the merchant
+    /// authority; `payer` can be a relayer.
+    pub fn create_subscriber_allowlist(ctx: Context<CreateSubscriberAllowlist>) -> Result<()> {
+        check_merchant_authority(
+            &ctx.accounts.merchant_multisig,
+            &ctx.accounts.recipient.key(),
+            &ctx.accounts.authority.key(),
+        )?;
+
+        let now = Clock::get()?.unix_timestamp;
+        let allowlist = &mut ctx.accounts.allowlist;
+        allowlist.recipient = ctx.accounts.recipient.key();
+        allowlist.created_at = now;
+        allowlist.bump = ctx.bumps.allowlist;
+
+        emit!(InviteOnlyChanged {
+            recipient: allowlist.recipient,
+            invite_only: true,
+            timestamp: now,
+        });
+
+        msg!("Subscriptions are invite-only");
+
+        Ok(())
+    }
+
+    /// Open the recipient to every wallet again and refund the rent. Its
+    /// allowlist entries stay, for when it goes invite-only again. Signed by
+    /// the merchant authority.
+    pub fn close_subscriber_allowlist(ctx: Context<CloseSubscriberAllowlist>) -> Result<()> {
+        check_merchant_authority(
+            &ctx.accounts.merchant_multisig,
+            &ctx.accounts.recipient.key(),
+            &ctx.accounts.authority.key(),
+        )?;
+
+        emit!(InviteOnlyChanged {
+            recipient: ctx.accounts.recipient.key(),
+            invite_only: false,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Subscriptions are open to everyone");
+
+        Ok(())
+    }
+
+    /// Put `wallet` on the recipient's allowlist, so it can subscribe while
+    /// the recipient is invite-only. Signed by the merchant authority;
+    /// `payer` can be a relayer.
+    pub fn add_to_allowlist(ctx: Context<AddToAllowlist>, wallet: Pubkey) -> Result<()> {
+        check_merchant_authority(
+            &ctx.accounts.merchant_multisig,
+            &ctx.accounts.recipient.key(),
+            &ctx.accounts.authority.key(),
+        )?;
+
+        let now = Clock::get()?.unix_timestamp;
+        let entry = &mut ctx.accounts.allowlist_entry;
+        entry.recipient = ctx.accounts.recipient.key();
+        entry.wallet = wallet;
+        entry.allowed_at = now;
+        entry.bump = ctx.bumps.allowlist_entry;
+
+        emit!(AllowlistChanged {
+            recipient: entry.recipient,
+            wallet,
+            allowed: true,
+            timestamp: now,
+        });
+
+        msg!("Wallet {} allowlisted", wallet);
+
+        Ok(())
+    }
+
+    /// Take a wallet off the allowlist and refund the rent to the
+    /// recipient; a subscription it already has is left alone. Signed by
+    /// the merchant authority.
+    pub fn remove_from_allowlist(ctx: Context<RemoveFromAllowlist>) -> Result<()> {
+        check_merchant_authority(
+            &ctx.accounts.merchant_multisig,
+            &ctx.accounts.recipient.key(),
+            &ctx.accounts.authority.key(),
+        )?;
+
+        let wallet = ctx.accounts.allowlist_entry.wallet;
+        emit!(AllowlistChanged {
+            recipient: ctx.accounts.recipient.key(),
+            wallet,
+            allowed: false,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Wallet {} removed from the allowlist", wallet);
+
+        Ok(())
+    }
+
+    /// Cap the subscriptions a plan takes at once; zero lifts the cap. A cap
+    /// below the current count turns new subscribers away until enough
+    /// leave. Signed by the merchant authority.
+    pub fn set_plan_capacity(ctx: Context<SetPlanCapacity>, max_subscribers: u32) -> Result<()> {
+        check_merchant_authority(
+            &ctx.accounts.merchant_multisig,
+            &ctx.accounts.recipient.key(),
+            &ctx.accounts.authority.key(),
+        )?;
+
+        let plan = &mut ctx.accounts.plan;
+        plan.max_subscribers = max_subscribers;
+
+        emit!(PlanCapacitySet {
+            plan: plan.key(),
+            max_subscribers,
+            subscribers: plan.subscribers,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!(
+            "Plan {} capacity: {} of {}",
+            plan.plan_id,
+            plan.subscribers,
+            max_subscribers
+        );
+
+        Ok(())
+    }
+
+    /// Discount a plan's first `intro_periods` for subscribers who join it
+    /// from now on, by `intro_discount_bps`; zero periods ends the offer.
+    /// Subscriptions already on the plan keep the terms they joined with.
+    /// Signed by the merchant authority.
+    pub fn set_plan_intro_pricing(
+        ctx: Context<SetPlanIntroPricing>,
+        intro_discount_bps: u16,
+        intro_periods: u8,
+    ) -> Result<()> {
+        check_merchant_authority(
+            &ctx.accounts.merchant_multisig,
+            &ctx.accounts.recipient.key(),
+            &ctx.accounts.authority.key(),
+        )?;
+        require!(
+            intro_discount_bps < MAX_BPS && (intro_discount_bps == 0) == (intro_periods == 0),
+            ErrorCode::InvalidIntroPricing
+        );
+
+        let plan = &mut ctx.accounts.plan;
+        plan.intro_discount_bps = intro_discount_bps;
+        plan.intro_periods = intro_periods;
+
+        emit!(PlanIntroPricingSet {
+            plan: plan.key(),
+            intro_discount_bps,
+            intro_periods,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!(
+            "Plan {} intro: {} bps off {} periods",
+            plan.plan_id,
+            intro_discount_bps,
+            intro_periods
+        );
+
+        Ok(())
+    }
+
+    /// Price a plan's first periods by `price_ramp`, one step after the
+    /// other, for subscribers who join it from now on; `monthly_price`
+    /// follows the last step, and an empty ramp ends the schedule. Any
+    /// intro discount comes off the ramp's prices. Subscriptions already on
+    /// the plan keep the schedule they joined with. Signed by the merchant
+    /// authority.
+    pub fn set_plan_price_ramp(
+        ctx: Context<SetPlanPriceRamp>,
+        price_ramp: Vec<RampStep>,
+    ) -> Result<()> {
+        check_merchant_authority(
+            &ctx.accounts.merchant_multisig,
+            &ctx.accounts.recipient.key(),
+            &ctx.accounts.authority.key(),
+        )?;
+        let plan = &mut ctx.accounts.plan;
+        require!(
+            price_ramp.len() <= MAX_RAMP_STEPS
+                && price_ramp
+                    .iter()
+                    .all(|step| step.periods > 0 && step.monthly_price > 0)
+                && price_ramp.iter().all(|step| {
+                    plan.cadence_price(step.monthly_price, BillingCadence::Annual)
+                        .is_ok()
+                }),
+            ErrorCode::InvalidPriceRamp
+        );
+        plan.price_ramp = price_ramp;
+
+        emit!(PlanPriceRampSet {
+            plan: plan.key(),
+            price_ramp: plan.price_ramp.clone(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!(
+            "Plan {} price ramp: {} steps",
+            plan.plan_id,
+            plan.price_ramp.len()
+        );
+
+        Ok(())
+    }
+
+    /// Give up the plan seat of a subscription that was deactivated,
+    /// compacted or cancelled without its plan accounts, and send the
+    /// `PlanSubscription`'s rent to the recipient. Permissionless, so the
+    /// merchant can reclaim seats.
+    pub fn release_plan_seat(ctx: Context<ReleasePlanSeat>) -> Result<()> {
+        require!(
+            !holds_active_subscription(&ctx.accounts.subscription)?,
+            ErrorCode::SubscriptionStillActive
+        );
+
+        let plan = &mut ctx.accounts.plan;
+        plan.free_seat();
+
+        emit!(PlanSeatReleased {
+            plan: plan.key(),
+            subscription: ctx.accounts.subscription.key(),
+            subscribers: plan.subscribers,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Plan {} seat released", plan.plan_id);
+
+        Ok(())
+    }
+
+    /// Give a plan a waitlist, so users can queue for it while it is full.
+    /// Signed by the merchant authority; `payer` can be a relayer.
+    pub fn create_waitlist(ctx: Context<CreateWaitlist>) -> Result<()> {
+        check_merchant_authority(
+            &ctx.accounts.merchant_multisig,
+            &ctx.accounts.recipient.key(),
+            &ctx.accounts.authority.key(),
+        )?;
+
+        ctx.accounts.waitlist.set_inner(Waitlist {
+            plan: ctx.accounts.plan.key(),
+            head: 0,
+            tail: 0,
+            bump: ctx.bumps.waitlist,
+        });
+
+        msg!("Plan {} has a waitlist", ctx.accounts.plan.plan_id);
+
+        Ok(())
+    }
+
+    /// Queue for a full plan at `cadence`. The user approves the
+    /// subscription PDA now, since promotion runs without them, and `payer`
+    /// also puts up the rent of the accounts promotion creates. Signed by
+    /// the user.
+    pub fn join_waitlist(ctx: Context<JoinWaitlist>, cadence: BillingCadence) -> Result<()> {
+        let accounts = &ctx.accounts;
+        accounts.waitlist.check_joinable(&accounts.plan)?;
+        accounts.plan.price(cadence)?;
+        DenylistEntry::check_allowed(&accounts.denylist_entry)?;
+        SubscriberAllowlist::check_invited(&accounts.allowlist, &accounts.allowlist_entry)?;
+
+        let approve_ix = token_instruction::approve(
+            &accounts.token_program.key(),
+            &accounts.user_token_account.key(),
+            &accounts.subscription.key(),
+            &accounts.authority.key(),
+            &[],
+            u64::MAX,
+        )?;
+        anchor_lang::solana_program::program::invoke(
+            &approve_ix,
+            &[
+                accounts.user_token_account.to_account_info(),
+                accounts.subscription.to_account_info(),
+                accounts.authority.to_account_info(),
+                accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        let rent = Rent::get()?;
+        let reserve = rent.minimum_balance(8 + Subscription::INIT_SPACE)
+            + rent.minimum_balance(8 + PlanSubscription::INIT_SPACE);
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: accounts.payer.to_account_info(),
+                    to: accounts.entry.to_account_info(),
+                },
+            ),
+            reserve,
+        )?;
+
+        let now = Clock::get()?.unix_timestamp;
+        let position = ctx.accounts.waitlist.tail;
+        ctx.accounts.entry.set_inner(WaitlistEntry {
+            plan: ctx.accounts.plan.key(),
+            position,
+            authority: ctx.accounts.authority.key(),
+            payer: ctx.accounts.payer.key(),
+            user_token_account: ctx.accounts.user_token_account.key(),
+            recipient_token_account: ctx.accounts.recipient_token_account.key(),
+            cadence,
+            joined_at: now,
+            bump: ctx.bumps.entry,
+        });
+        ctx.accounts.waitlist.tail += 1;
+
+        emit!(WaitlistJoined {
+            plan: ctx.accounts.plan.key(),
+            authority: ctx.accounts.authority.key(),
+            position,
+            timestamp: now,
+        });
+
+        msg!("Joined the waitlist at position {}", position);
+
+        Ok(())
+    }
+
+    /// Leave a waitlist and refund the entry, reserve included, to whoever
+    /// paid for it. The approval is revoked unless the user has since
+    /// subscribed to the recipient. Signed by the user.
+    pub fn leave_waitlist(ctx: Context<LeaveWaitlist>) -> Result<()> {
+        let accounts = &ctx.accounts;
+        if accounts.subscription.data_is_empty()
+            && holds_delegation(&accounts.user_token_account, &accounts.subscription.key())
+        {
+            let revoke_ix = token_instruction::revoke(
+                &accounts.token_program.key(),
+                &accounts.user_token_account.key(),
+                &accounts.authority.key(),
+                &[],
+            )?;
+            anchor_lang::solana_program::program::invoke(
+                &revoke_ix,
+                &[
+                    accounts.user_token_account.to_account_info(),
+                    accounts.authority.to_account_info(),
+                    accounts.token_program.to_account_info(),
+                ],
+            )?;
+        }
+
+        emit!(WaitlistLeft {
+            plan: accounts.entry.plan,
+            authority: accounts.authority.key(),
+            position: accounts.entry.position,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Left the waitlist");
+
+        Ok(())
+    }
+
+    /// Turn the oldest waitlist entry into a subscription on the plan once
+    /// it has a seat, charging the first period as `join_plan` prices it.
+    /// Permissionless; `payer` covers the new accounts' rent and gets the
+    /// entry's lamports, reserve included, back.
+    pub fn promote_from_waitlist(ctx: Context<PromoteFromWaitlist>) -> Result<()> {
+        let accounts = &mut *ctx.accounts;
+        accounts.plan.take_seat()?;
+        let cadence = accounts.entry.cadence;
+        let full_price = accounts.plan.price(cadence)?;
+        let amount_per_period = accounts.plan.period_price(cadence, 0)?;
+
+        open_subscription(
+            OpenSubscription {
+                subscription: &mut accounts.subscription,
+                authority: accounts.authority.to_account_info(),
+                recipient: accounts.recipient.to_account_info(),
+                user_token_account: accounts.user_token_account.to_account_info(),
+                recipient_token_account: accounts.recipient_token_account.to_account_info(),
+                token_mint: accounts.token_mint.to_account_info(),
+                token_program: accounts.token_program.to_account_info(),
+                denylist_entry: accounts.denylist_entry.to_account_info(),
+                allowlist: accounts.allowlist.to_account_info(),
+                allowlist_entry: accounts.allowlist_entry.to_account_info(),
+                approve: false,
+                bump: ctx.bumps.subscription,
+            },
+            amount_per_period,
+            cadence.interval().to_seconds_field(),
+            None,
+            0,
+        )?;
+
+        // The first period of the plan's price schedule is already paid
+        let price_steps = accounts
+            .plan
+            .start_schedule(&mut accounts.subscription, cadence, 1)?;
+        accounts.subscription.on_plan = true;
+        let subscription = &accounts.subscription;
+        accounts.plan_subscription.set_inner(PlanSubscription {
+            subscription: subscription.key(),
+            plan: accounts.plan.key(),
+            cadence,
+            period_start: subscription.last_charge_timestamp,
+            units: 0,
+            periods_over: 0,
+            upgrade_at: None,
+            overage_billed: 0,
+            overage_charged: 0,
+            price_steps,
+            full_price,
+            bump: ctx.bumps.plan_subscription,
+        });
+        accounts.waitlist.head += 1;
+
+        emit!(WaitlistPromoted {
+            plan: accounts.plan.key(),
+            subscription: subscription.key(),
+            authority: accounts.authority.key(),
+            position: accounts.entry.position,
+            timestamp: subscription.created_at,
+        });
+
+        msg!("Promoted from the waitlist on plan {}", accounts.plan.plan_id);
+
+        Ok(())
+    }
+
+    /// Move a waitlist past its oldest entry when that entry cannot be
+    /// promoted: it was left, the wallet already has a subscription to the
+    /// recipient or may no longer subscribe, or its token account cannot pay
+    /// the first period. The entry is refunded to whoever paid for it.
+    /// Permissionless.
+    pub fn skip_waitlist_entry(ctx: Context<SkipWaitlistEntry>) -> Result<()> {
+        let accounts = &ctx.accounts;
+        require!(
+            accounts.waitlist.head < accounts.waitlist.tail,
+            ErrorCode::WaitlistEmpty
+        );
+
+        let entry_info = accounts.entry.to_account_info();
+        if !entry_info.data_is_empty() {
+            let entry = WaitlistEntry::try_deserialize(&mut &entry_info.try_borrow_data()?[..])?;
+            let recipient = accounts.plan.recipient;
+            entry.check_accounts(
+                &recipient,
+                &accounts.subscription.key(),
+                &accounts.denylist_entry.key(),
+                &accounts.allowlist_entry.key(),
+                &accounts.user_token_account.key(),
+                &accounts.rent_receiver.key(),
+            )?;
+
+            let promotable = accounts.subscription.data_is_empty()
+                && DenylistEntry::check_allowed(&accounts.denylist_entry).is_ok()
+                && SubscriberAllowlist::check_invited(
+                    &accounts.allowlist,
+                    &accounts.allowlist_entry,
+                )
+                .is_ok()
+                && can_pay_first_period(
+                    &accounts.user_token_account,
+                    &accounts.subscription.key(),
+                    accounts.plan.price(entry.cadence)?,
+                );
+            require!(!promotable, ErrorCode::WaitlistEntryPromotable);
+
+            let lamports = entry_info.lamports();
+            **entry_info.try_borrow_mut_lamports()? = 0;
+            **accounts.rent_receiver.try_borrow_mut_lamports()? = accounts
+                .rent_receiver
+                .lamports()
+                .checked_add(lamports)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            entry_info.assign(&system_program::ID);
+            entry_info.resize(0)?;
+
+            emit!(WaitlistLeft {
+                plan: entry.plan,
+                authority: entry.authority,
+                position: entry.position,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+        }
+
+        let position = accounts.waitlist.head;
+        ctx.accounts.waitlist.head += 1;
+
+        msg!("Waitlist entry {} skipped", position);
+
+        Ok(())
+    }
+
+    /// Create the platform config with its fee and treasury. Only the
+    /// program's upgrade authority can, and it becomes the admin; from then
+    /// on every change waits out `PLATFORM_TIMELOCK_SECONDS`.
+    pub fn create_platform_config(
+        ctx: Context<CreatePlatformConfig>,
+        fee_bps: u16,
+        treasury: Pubkey,
+    ) -> Result<()> {
+        PlatformConfig::check_fee_bps(fee_bps)?;
+
+        let config = &mut ctx.accounts.platform_config;
+        config.admin = ctx.accounts.authority.key();
+        config.fee_bps = fee_bps;
+        config.treasury = treasury;
+        config.bump = ctx.bumps.platform_config;
+
+        emit!(PlatformConfigUpdated {
+            admin: config.admin,
+            fee_bps,
+            treasury,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Platform fee: {} bps", fee_bps);
+
+        Ok(())
+    }
+
+    /// Queue a new platform fee, treasury or admin, any of them. It applies
+    /// after `PLATFORM_TIMELOCK_SECONDS`, so merchants and subscribers can
+    /// react first; one change is pending at a time. Signed by the admin.
+    pub fn queue_platform_config_change(
+        ctx: Context<QueuePlatformConfigChange>,
+        fee_bps: Option<u16>,
+        treasury: Option<Pubkey>,
+        admin: Option<Pubkey>,
+    ) -> Result<()> {
+        require!(
+            fee_bps.is_some() || treasury.is_some() || admin.is_some(),
+            ErrorCode::EmptyPlatformConfigChange
+        );
+        if let Some(fee_bps) = fee_bps {
+            PlatformConfig::check_fee_bps(fee_bps)?;
+        }
+
+        let now = Clock::get()?.unix_timestamp;
+        let change = &mut ctx.accounts.config_change;
+        change.fee_bps = fee_bps;
+        change.treasury = treasury;
+        change.admin = admin;
+        change.effective_at = now
+            .checked_add(PLATFORM_TIMELOCK_SECONDS)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        change.bump = ctx.bumps.config_change;
+
+        emit!(PlatformConfigChangeQueued {
+            fee_bps,
+            treasury,
+            admin,
+            effective_at: change.effective_at,
+            timestamp: now,
+        });
+
+        msg!(
+            "Platform config change effective at: {}",
+            change.effective_at
+        );
+
+        Ok(())
+    }
+
+    /// Apply the queued platform config change once its timelock has run
+    /// out. Anyone can send it; the change's rent goes back to the admin
+    /// that queued it.
+    pub fn apply_platform_config_change(ctx: Context<ApplyPlatformConfigChange>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let change = &ctx.accounts.config_change;
+        require!(
+            change.is_effective(now),
+            ErrorCode::PlatformConfigChangeTimelocked
+        );
+
+        let config = &mut ctx.accounts.platform_config;
+        if let Some(fee_bps) = change.fee_bps {
+            config.fee_bps = fee_bps;
+        }
+        if let Some(treasury) = change.treasury {
+            config.treasury = treasury;
+        }
+        if let Some(admin) = change.admin {
+            config.admin = admin;
+        }
+
+        emit!(PlatformConfigUpdated {
+            admin: config.admin,
+            fee_bps: config.fee_bps,
+            treasury: config.treasury,
+            timestamp: now,
+        });
+
+        msg!("Platform config change applied");
+
+        Ok(())
+    }
+
+    /// Drop the queued platform config change, before or after its
+    /// timelock. Signed by the admin.
+    pub fn cancel_platform_config_change(_ctx: Context<CancelPlatformConfigChange>) -> Result<()> {
+        emit!(PlatformConfigChangeCancelled {
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Platform config change cancelled");
+
+        Ok(())
+    }
+}
+
+/// Transfer `amount` from a merchant vault after syncing it, leaving its
+/// held-back revenue. `limited` counts the amount against the withdrawal
+/// limit, which the admin's recovery skips.
+fn pay_out_revenue<'info>(
+    vault: &mut Account<'info, MerchantVault>,
+    token_account: &UncheckedAccount<'info>,
+    destination: &UncheckedAccount<'info>,
+    token_program: &UncheckedAccount<'info>,
+    amount: u64,
+    now: i64,
+    limited: bool,
+) -> Result<()> {
+    let balance = vault_balance(token_account)?;
+    require!(
+        amount > 0 && amount <= balance,
+        ErrorCode::InvalidWithdrawal
+    );
+
+    sync_vault(vault, balance, now)?;
+    require!(
+        amount <= vault.withdrawable(balance, now),
+        ErrorCode::RevenueHeldBack
+    );
+    if limited {
+        vault.record_limited_withdrawal(amount, now)?;
+    }
+
+    // Written before the CPI, as everywhere else
+    vault.total_withdrawn = vault
+        .total_withdrawn
+        .checked_add(amount)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    vault.exit(&crate::ID)?;
+
+    let recipient = vault.recipient;
+    let seeds = &[b"merchant_vault", recipient.as_ref(), &[vault.bump]];
+    let signer_seeds = &[&seeds[..]];
+
+    let transfer_ix = token_instruction::transfer(
+        &token_program.key(),
+        &token_account.key(),
+        &destination.key(),
+        &vault.key(),
+        &[],
+        amount,
+    )?;
+
+    invoke_signed(
+        &transfer_ix,
+        &[
+            token_account.to_account_info(),
+            destination.to_account_info(),
+            vault.to_account_info(),
+            token_program.to_account_info(),
+        ],
+        signer_seeds,
+    )?;
+
+    Ok(())
+}
+
+/// Token balance of a merchant vault's token account
+fn vault_balance(token_account: &AccountInfo) -> Result<u64> {
+    let token_account = spl_token::state::Account::unpack(&token_account.try_borrow_data()?)
+        .map_err(|_| ErrorCode::InvalidVaultTokenAccount)?;
+    Ok(token_account.amount)
+}
+
+/// `MerchantVault::sync`, announcing the hold it starts
+fn sync_vault(vault: &mut MerchantVault, balance: u64, now: i64) -> Result<()> {
+    if let Some(hold) = vault.sync(balance, now)? {
+        emit!(RevenueHeld {
+            recipient: vault.recipient,
+            amount: hold.amount,
+            release_at: hold.release_at,
+            timestamp: now,
+        });
+
+        msg!("Holding {} tokens until {}", hold.amount, hold.release_at);
+    }
+
+    Ok(())
+}
+
+/// Accounts the two initialize paths share once the authority has been checked
+struct OpenSubscription<'a, 'info> {
+    subscription: &'a mut Account<'info, Subscription>,
+    authority: AccountInfo<'info>,
+    recipient: AccountInfo<'info>,
+    user_token_account: AccountInfo<'info>,
+    recipient_token_account: AccountInfo<'info>,
+    token_mint: AccountInfo<'info>,
+    token_program: AccountInfo<'info>,
+    denylist_entry: AccountInfo<'info>,
+    allowlist: AccountInfo<'info>,
+    allowlist_entry: AccountInfo<'info>,
+    /// False when the user approved the subscription PDA already, joining a
+    /// waitlist
+    approve: bool,
+    bump: u8,
+}
+
+/// Write the state, delegate the user's token account and charge the first
+/// period, plus `setup_fee` if there is one
+fn open_subscription(
+    accounts: OpenSubscription,
+    amount_per_period: u64,
+    interval_seconds: i64,
+    expires_at: Option<i64>,
+    setup_fee: u64,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    let interval =
+        Interval::from_seconds_field(interval_seconds).ok_or(ErrorCode::InvalidInterval)?;
+    DenylistEntry::check_allowed(&accounts.denylist_entry)?;
+    SubscriberAllowlist::check_invited(&accounts.allowlist, &accounts.allowlist_entry)?;
+
+    let authority_key = accounts.authority.key();
+    let recipient_key = accounts.recipient.key();
+    let subscription_key = accounts.subscription.key();
+    let bump = accounts.bump;
+
+    // ========== STEP 1: INITIALIZE SUBSCRIPTION STATE ==========
+    // Written and persisted before any CPI, so the token program never runs
+    // against a subscription that does not yet record the first payment
+    let subscription = accounts.subscription;
+    subscription.authority = authority_key;
+    subscription.recipient = recipient_key;
+    subscription.user_token_account = accounts.user_token_account.key();
+    subscription.recipient_token_account = accounts.recipient_token_account.key();
+    subscription.token_mint = accounts.token_mint.key();
+    subscription.amount_per_period = amount_per_period;
+    subscription.interval_seconds = interval_seconds;
+    subscription.last_charge_timestamp = clock.unix_timestamp; // ← Set to NOW for prepaid
+    subscription.created_at = clock.unix_timestamp; // Anchors calendar intervals
+    subscription.schedule_next_charge();
+    subscription.expires_at = expires_at;
+    subscription.is_active = true;
+    subscription.total_charged = amount_per_period; // ← Charged in step 3
+    subscription.max_total_spend = Subscription::NO_SPEND_LIMIT;
+    subscription.bump = bump;
+    subscription.exit(&crate::ID)?;
+
+    // ========== STEP 2: DELEGATE TOKEN ACCOUNT ==========
+    // This MUST happen before we charge, so PDA can act as delegate
+    if accounts.approve {
+        let delegate_ix = token_instruction::approve(
+            &accounts.token_program.key(),
+            &accounts.user_token_account.key(),
+            &subscription_key,
+            &authority_key,
+            &[],
+            u64::MAX,
+        )?;
+
+        anchor_lang::solana_program::program::invoke(
+            &delegate_ix,
+            &[
+                accounts.user_token_account.to_account_info(),
+                subscription.to_account_info(),
+                accounts.authority.to_account_info(),
+                accounts.token_program.to_account_info(),
+            ],
+        )?;
+    }
+
+    // ========== STEP 3: CHARGE FIRST PAYMENT IMMEDIATELY ==========
+    let seeds = &[
+        b"subscription",
+        authority_key.as_ref(),
+        recipient_key.as_ref(),
+        &[bump],
+    ];
+    let signer_seeds = &[&seeds[..]];
+
+    // Transfer first payment using PDA as delegate, with the setup fee in
+    // the same transfer
+    let first_payment = amount_per_period
+        .checked_add(setup_fee)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    let transfer_ix = token_instruction::transfer(
+        &accounts.token_program.key(),
+        &accounts.user_token_account.key(),
+        &accounts.recipient_token_account.key(),
+        &subscription_key,
+        &[],
+        first_payment,
+    )?;
+
+    invoke_signed(
+        &transfer_ix,
+        &[
+            accounts.user_token_account.to_account_info(),
+            accounts.recipient_token_account.to_account_info(),
+            subscription.to_account_info(),
+            accounts.token_program.to_account_info(),
+        ],
+        signer_seeds,
+    )?;
+
+    emit!(SubscriptionCreated {
+        subscription: subscription.key(),
+        authority: authority_key,
+        recipient: recipient_key,
+        amount_per_period,
+        interval_seconds,
+        expires_at,
+        timestamp: clock.unix_timestamp,
+    });
+    if setup_fee > 0 {
+        emit!(SetupFeePaid {
+            subscription: subscription.key(),
+            authority: authority_key,
+            recipient: recipient_key,
+            amount: setup_fee,
+            timestamp: clock.unix_timestamp,
+        });
+        msg!("Setup fee charged: {} tokens", setup_fee);
+    }
+
+    msg!("Subscription initialized with PREPAID model!");
+    msg!("First payment charged: {} tokens", amount_per_period);
+    msg!(
+        "Billed {:?}, next charge at {}",
+        interval,
+        subscription.next_charge_at
+    );
+    if accounts.approve {
+        msg!("Token account delegated to subscription PDA");
+    }
+
+    Ok(())
+}
+
+/// A fallback-mint charge's token account and the merchant's account of
+/// that mint, which replace the primary account and the recipient's
+type FallbackMint<'info> = (AccountInfo<'info>, AccountInfo<'info>);
+
+/// Charge whatever is due; shared by every charge of the period amount,
+/// including one from the subscription's fallback mint
+fn charge<'info>(
+    accounts: &mut ChargeSubscription<'info>,
+    remaining_accounts: &[AccountInfo<'info>],
+    usd_amount: Option<u64>,
+    reference: Option<[u8; 32]>,
+    fallback_mint: Option<FallbackMint<'info>>,
+) -> Result<()> {
+    let subscription = &mut accounts.subscription;
+    let clock = Clock::get()?;
+    let current_time = clock.unix_timestamp;
+
+    subscription.check_chargeable(current_time)?;
+
+    // A USD-priced subscription has no token amount of its own; its charge
+    // brings one, converted at the cached price
+    let mut period_amount = match usd_amount {
+        Some(amount) => {
+            require!(
+                subscription.amount_per_period == 0,
+                ErrorCode::InvalidUsdPeg
+            );
+            amount
+        }
+        None => {
+            require!(
+                subscription.amount_per_period > 0,
+                ErrorCode::UsdPriceRequired
+            );
+            subscription.amount_per_period
+        }
+    };
+    let mut terms = Subscription {
+        amount_per_period: period_amount,
+        ..(**subscription).clone()
+    };
+
+    require_keys_eq!(
+        *accounts.user_token_account.owner,
+        spl_token::ID,
+        ErrorCode::InvalidTokenAccount
+    );
+    require_keys_eq!(
+        *accounts.recipient_token_account.owner,
+        spl_token::ID,
+        ErrorCode::InvalidTokenAccount
+    );
+
+    // A revoked delegation fails every charge from here on. Deactivate
+    // instead of failing, so keepers see it once and stop retrying; an
+    // error would roll the deactivation back.
+    let user_token =
+        spl_token::state::Account::unpack(&accounts.user_token_account.try_borrow_data()?)
+            .map_err(|_| ErrorCode::InvalidTokenAccount)?;
+    if !subscription.is_delegated(&subscription.key(), &user_token) {
+        let (policy, _) = Pubkey::find_program_address(
+            &[b"spending_policy", subscription.user_token_account.as_ref()],
+            &spending_limits::ID,
+        );
+        require!(
+            user_token.delegate != COption::Some(policy),
+            ErrorCode::DelegatedToPolicy
+        );
+
+        subscription.is_active = false;
+
+        // Still delegated, so what a spend limit left has run out
+        if user_token.delegate == COption::Some(subscription.key()) {
+            emit!(SpendLimitReached {
+                subscription: subscription.key(),
+                authority: subscription.authority,
+                recipient: subscription.recipient,
+                total_charged: subscription.total_charged,
+                timestamp: current_time,
+            });
+
+            msg!("Spend limit reached");
+        } else {
+            emit!(DelegationRevoked {
+                subscription: subscription.key(),
+                authority: subscription.authority,
+                recipient: subscription.recipient,
+                timestamp: current_time,
+            });
+
+            msg!("Token delegation was revoked outside the program");
+        }
+        msg!("Subscription deactivated");
+
+        return Ok(());
+    }
+
+    let (remaining_accounts, alerts) = spend_alerts(remaining_accounts, &subscription.key())?;
+    let (remaining_accounts, mut discounts) =
+        sla_discounts(remaining_accounts, &subscription.key())?;
+    let pending: Vec<u64> = discounts
+        .iter()
+        .map(|(_, receipt)| receipt.pending)
+        .collect();
+    let discountable = pending
+        .iter()
+        .fold(0u64, |sum, pending| sum.saturating_add(*pending));
+
+    // A plan's price schedule selects the price of the period charged
+    // next; a USD peg prices the subscription instead
+    let (remaining_accounts, mut schedule) =
+        plan_schedule(remaining_accounts, &subscription.key())?;
+    require!(
+        schedule.is_some() || !subscription.on_plan,
+        ErrorCode::PlanSubscriptionRequired
+    );
+    let mut stepped = false;
+    if let Some((_, plan_subscription)) = schedule.as_mut().filter(|_| usd_amount.is_none()) {
+        if plan_subscription.apply_price(subscription) {
+            period_amount = subscription.amount_per_period;
+            terms.amount_per_period = period_amount;
+            stepped = true;
+        }
+    }
+
+    // The stored limit holds whichever account pays, fallbacks included,
+    // and ends the subscription as a spent allowance does
+    if subscription.spend_left() < period_amount {
+        subscription.is_active = false;
+        if let Some((account, plan_subscription)) = schedule.as_ref().filter(|_| stepped) {
+            plan_subscription.try_serialize(&mut &mut account.try_borrow_mut_data()?[..])?;
+        }
+
+        emit!(SpendLimitReached {
+            subscription: subscription.key(),
+            authority: subscription.authority,
+            recipient: subscription.recipient,
+            total_charged: subscription.total_charged,
+            timestamp: current_time,
+        });
+
+        msg!("Spend limit reached");
+        msg!("Subscription deactivated");
+
+        return Ok(());
+    }
+
+    let (allow_partial, max_periods) = match remaining_accounts.first() {
+        Some(config) if config.key() != crate::ID => {
+            let config = load_merchant_config(config, &subscription.recipient)?;
+            (config.allow_partial_charges, config.max_periods_per_charge)
+        }
+        _ => (false, 1),
+    };
+    // The primary account pays, with its funding sources; or, when it
+    // cannot pay a period, the fallback mint's account alone
+    let (sources, allowance) = match &fallback_mint {
+        Some((source, _)) => {
+            require!(
+                user_token.amount < period_amount,
+                ErrorCode::PrimaryCanPay
+            );
+            let balance = fallback_mint_balance(source, &subscription.key())?;
+            (vec![(source.clone(), balance)], u64::MAX)
+        }
+        None => {
+            let mut sources = vec![(
+                accounts.user_token_account.to_account_info(),
+                user_token.amount.min(user_token.delegated_amount),
+            )];
+            sources.extend(fallback_sources(
+                remaining_accounts.get(1..).unwrap_or_default(),
+                &subscription.key(),
+                subscription,
+            )?);
+            (sources, user_token.delegated_amount)
+        }
+    };
+    let balances: Vec<u64> = sources.iter().map(|(_, balance)| *balance).collect();
+    // Fallbacks make up a short balance, never a spend limit
+    let available = balances
+        .iter()
+        .fold(0u64, |sum, balance| sum.saturating_add(*balance))
+        .min(allowance)
+        .min(subscription.spend_left());
+
+    let mut periods = subscription.due_periods(current_time, max_periods);
+    // Periods at the schedule's next price stay due for the next charge
+    if let Some((_, plan_subscription)) = &schedule {
+        periods = plan_subscription.periods_at_price(subscription, periods);
+    }
+    // Pending SLA discounts pay for part of the first period, so they
+    // count towards what is available
+    let mut charges = terms.period_charges(
+        periods,
+        available.saturating_add(discountable),
+        allow_partial,
+    );
+    let last_period = charges.last().copied().unwrap_or_default();
+    let shortfall = period_amount.saturating_sub(last_period);
+    let discount = discountable.min(charges[0]);
+    charges[0] -= discount;
+    // Oldest receipt first; whatever is left waits for the next charge
+    let discount_draws = split_funding(discount, &pending).unwrap_or_default();
+    let amount = charges
+        .iter()
+        .try_fold(0u64, |sum, charge| sum.checked_add(*charge))
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    // If the sources cannot cover it together, ask the primary for all
+    // of it, so the transfer fails as it always has
+    let draws = split_funding(amount, &balances).unwrap_or_else(|| vec![amount]);
+    let total_before = subscription.total_charged;
+    let authority_key = subscription.authority;
+    let recipient_key = subscription.recipient;
+    let bump = subscription.bump;
+
+    // Book the periods and write them to the account before the token
+    // CPI, so nothing reachable from the CPI sees them as still unpaid
+    subscription.book_periods(current_time, &charges, max_periods > 1)?;
+    subscription.exit(&crate::ID)?;
+    if let Some((account, plan_subscription)) = schedule.as_ref().filter(|_| stepped) {
+        plan_subscription.try_serialize(&mut &mut account.try_borrow_mut_data()?[..])?;
+    }
+    for ((account, receipt), draw) in discounts.iter_mut().zip(&discount_draws) {
+        receipt.pending -= draw;
+        receipt.try_serialize(&mut &mut account.try_borrow_mut_data()?[..])?;
+    }
+
+    let seeds = &[
+        b"subscription",
+        authority_key.as_ref(),
+        recipient_key.as_ref(),
+        &[bump],
+    ];
+    let signer_seeds = &[&seeds[..]];
+
+    let subscription_key = accounts.subscription.key();
+    let destination = match &fallback_mint {
+        Some((_, destination)) => destination.clone(),
+        None => accounts.recipient_token_account.to_account_info(),
+    };
+
+    for ((source, _), draw) in sources.iter().zip(&draws) {
+        if *draw == 0 {
+            continue;
+        }
+
+        let transfer_ix = token_instruction::transfer(
+            &accounts.token_program.key(),
+            source.key,
+            destination.key,
+            &subscription_key,
+            &[],
+            *draw,
+        )?;
+
+        invoke_signed(
+            &transfer_ix,
+            &[
+                source.clone(),
+                destination.clone(),
+                accounts.subscription.to_account_info(),
+                accounts.token_program.to_account_info(),
+            ],
+            signer_seeds,
+        )?;
+    }
+
+    let subscription = &accounts.subscription;
+
+    // Every source but the primary account is a fallback
+    let primary = usize::from(fallback_mint.is_none());
+    for ((source, _), draw) in sources.iter().zip(&draws).skip(primary) {
+        if *draw > 0 {
+            emit!(FallbackFundingUsed {
+                subscription: subscription.key(),
+                token_account: source.key(),
+                amount: *draw,
+                timestamp: current_time,
+            });
+            msg!("Drew {} tokens from fallback {}", draw, source.key());
+        }
+    }
+
+    for ((_, receipt), draw) in discounts.iter().zip(&discount_draws) {
+        if *draw > 0 {
+            emit!(SlaDiscountApplied {
+                subscription: subscription.key(),
+                recipient: recipient_key,
+                attestation: receipt.attestation,
+                amount: *draw,
+                pending: receipt.pending,
+                timestamp: current_time,
+            });
+        }
+    }
+    if discount > 0 {
+        msg!("SLA discount: {} tokens", discount);
+    }
+
+    if let Some((_, plan_subscription)) = schedule.as_ref().filter(|_| stepped) {
+        emit!(ScheduledPriceApplied {
+            subscription: subscription.key(),
+            plan: plan_subscription.plan,
+            amount_per_period: period_amount,
+            timestamp: current_time,
+        });
+        msg!("Scheduled price: {} tokens", period_amount);
+    }
+
+    // One event per settled period, each with the running total
+    let mut total_charged = total_before;
+    for charge in &charges {
+        total_charged += charge;
+        emit!(SubscriptionCharged {
+            subscription: subscription.key(),
+            authority: authority_key,
+            recipient: recipient_key,
+            amount: *charge,
+            total_charged,
+            reference,
+            timestamp: current_time,
+        });
+    }
+    for threshold in alerts
+        .iter()
+        .flat_map(|alerts| alerts.crossed(total_before, total_charged))
+    {
+        emit!(SpendThresholdCrossed {
+            subscription: subscription.key(),
+            authority: authority_key,
+            recipient: recipient_key,
+            threshold,
+            total_charged,
+            timestamp: current_time,
+        });
+        msg!("Spend passed {} tokens", threshold);
+    }
+
+    if shortfall > 0 {
+        emit!(ChargeShortfall {
+            subscription: subscription.key(),
+            recipient: recipient_key,
+            amount_due: period_amount,
+            amount_charged: last_period,
+            shortfall,
+            timestamp: current_time,
+        });
+        msg!("Partial charge, short by {} tokens", shortfall);
+    }
+
+    msg!("Subscription charged!");
+    if charges.len() > 1 {
+        msg!("Periods settled: {}", charges.len());
+    }
+    msg!("Amount: {} tokens", amount);
+    msg!("Total charged: {} tokens", subscription.total_charged);
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct InitializeSubscription<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Subscription::INIT_SPACE,
+        seeds = [
+            b"subscription",
+            authority.key().as_ref(),
+            recipient.key().as_ref(),
+        ],
+        bump
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    pub authority: Signer<'info>,
+
+    /// CHECK: Merchant/recipient address
+    pub recipient: UncheckedAccount<'info>,
+
+    /// CHECK: User's token account (will be delegated to subscription PDA)
+    #[account(mut)]
+    pub user_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Recipient's token account
+    #[account(mut)]
+    pub recipient_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Token mint (USDC)
+    pub token_mint: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    /// CHECK: the authority's entry on the recipient's denylist, which must
+    /// be empty
+    #[account(
+        seeds = [b"denylist", recipient.key().as_ref(), authority.key().as_ref()],
+        bump
+    )]
+    pub denylist_entry: UncheckedAccount<'info>,
+
+    /// CHECK: the recipient's `SubscriberAllowlist` address, which is empty
+    /// unless it is invite-only
+    #[account(seeds = [b"allowlist", recipient.key().as_ref()], bump)]
+    pub allowlist: UncheckedAccount<'info>,
+
+    /// CHECK: the authority's entry on the recipient's allowlist, required
+    /// while `allowlist` is set
+    #[account(
+        seeds = [b"allowlist", recipient.key().as_ref(), authority.key().as_ref()],
+        bump
+    )]
+    pub allowlist_entry: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeWalletSubscription<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Subscription::INIT_SPACE,
+        seeds = [
+            b"subscription",
+            authority.key().as_ref(),
+            recipient.key().as_ref(),
+        ],
+        bump
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    /// CHECK: Smart-wallet PDA, checked in the handler to have been signed
+    /// for by the LazorKit wallet program
+    pub authority: UncheckedAccount<'info>,
+
+    /// CHECK: Merchant/recipient address
+    pub recipient: UncheckedAccount<'info>,
+
+    /// CHECK: Smart wallet's token account (will be delegated to subscription PDA)
+    #[account(mut)]
+    pub user_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Recipient's token account
+    #[account(mut)]
+    pub recipient_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Token mint (USDC)
+    pub token_mint: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    /// CHECK: Instructions sysvar, to see which program the wallet call came from
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    /// CHECK: the authority's entry on the recipient's denylist, which must
+    /// be empty
+    #[account(
+        seeds = [b"denylist", recipient.key().as_ref(), authority.key().as_ref()],
+        bump
+    )]
+    pub denylist_entry: UncheckedAccount<'info>,
+
+    /// CHECK: the recipient's `SubscriberAllowlist` address, which is empty
+    /// unless it is invite-only
+    #[account(seeds = [b"allowlist", recipient.key().as_ref()], bump)]
+    pub allowlist: UncheckedAccount<'info>,
+
+    /// CHECK: the authority's entry on the recipient's allowlist, required
+    /// while `allowlist` is set
+    #[account(
+        seeds = [b"allowlist", recipient.key().as_ref(), authority.key().as_ref()],
+        bump
+    )]
+    pub allowlist_entry: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ChargeSubscription<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"subscription",
+            subscription.authority.as_ref(),
+            subscription.recipient.as_ref(),
+        ],
+        bump = subscription.bump,
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    /// CHECK: User's token account
+    #[account(
+        mut,
+        constraint = user_token_account.key() == subscription.user_token_account
+    )]
+    pub user_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Recipient's token account
+    #[account(
+        mut,
+        constraint = recipient_token_account.key() == subscription.recipient_token_account
+    )]
+    pub recipient_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ChargeSubscriptionWithPolicy<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"subscription",
+            subscription.authority.as_ref(),
+            subscription.recipient.as_ref(),
+        ],
+        bump = subscription.bump,
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    /// Spending policy guarding the user's token account
+    #[account(
+        mut,
+        constraint = policy.token_account == subscription.user_token_account
+            @ ErrorCode::InvalidTokenAccount
+    )]
+    pub policy: Account<'info, spending_limits::Policy>,
+
+    /// CHECK: User's token account
+    #[account(
+        mut,
+        constraint = user_token_account.key() == subscription.user_token_account
+    )]
+    pub user_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Recipient's token account
+    #[account(
+        mut,
+        constraint = recipient_token_account.key() == subscription.recipient_token_account
+    )]
+    pub recipient_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    pub spending_limits_program: Program<'info, spending_limits::program::SpendingLimits>,
+}
+
+#[derive(Accounts)]
+pub struct CancelSubscription<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"subscription",
+            subscription.authority.as_ref(),
+            subscription.recipient.as_ref(),
+        ],
+        bump = subscription.bump,
+        has_one = authority,
+        close = authority
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    /// CHECK: User's token account
+    #[account(
+        mut,
+        constraint = user_token_account.key() == subscription.user_token_account
+    )]
+    pub user_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CleanupCancelledSubscription<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"subscription",
+            subscription.authority.as_ref(),
+            subscription.recipient.as_ref(),
+        ],
+        bump = subscription.bump,
+        has_one = authority,
+        close = authority
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateSubscription<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"subscription",
+            subscription.authority.as_ref(),
+            subscription.recipient.as_ref(),  // ← FIXED: Added () after as_ref
+        ],
+        bump = subscription.bump,
+        has_one = authority
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateMerchantConfig<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + MerchantConfig::INIT_SPACE,
+        seeds = [b"merchant_config", recipient.key().as_ref()],
+        bump
+    )]
+    pub merchant_config: Account<'info, MerchantConfig>,
+
+    /// CHECK: the recipient's `MerchantMultisig` address, which may be empty;
+    /// read by `check_merchant_authority`
+    #[account(seeds = [b"merchant_multisig", recipient.key().as_ref()], bump)]
+    pub merchant_multisig: UncheckedAccount<'info>,
+
+    /// CHECK: the merchant the settings are for; `authority` signs for it
+    pub recipient: UncheckedAccount<'info>,
+
+    /// The recipient, or its multisig's vault
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateMerchantConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"merchant_config", recipient.key().as_ref()],
+        bump = merchant_config.bump,
+        has_one = recipient
+    )]
+    pub merchant_config: Account<'info, MerchantConfig>,
+
+    /// CHECK: the recipient's `MerchantMultisig` address, which may be empty;
+    /// read by `check_merchant_authority`
+    #[account(seeds = [b"merchant_multisig", recipient.key().as_ref()], bump)]
+    pub merchant_multisig: UncheckedAccount<'info>,
+
+    /// CHECK: the merchant the settings are for; `authority` signs for it
+    pub recipient: UncheckedAccount<'info>,
+
+    /// The recipient, or its multisig's vault
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetMerchantMultisig<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + MerchantMultisig::INIT_SPACE,
+        seeds = [b"merchant_multisig", recipient.key().as_ref()],
+        bump
+    )]
+    pub merchant_multisig: Account<'info, MerchantMultisig>,
+
+    /// CHECK: owner and discriminator are checked against Squads v4
+    #[account(constraint = is_squads_multisig(&multisig) @ ErrorCode::InvalidMultisig)]
+    pub multisig: UncheckedAccount<'info>,
+
+    pub recipient: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseMerchantMultisig<'info> {
+    #[account(
+        mut,
+        seeds = [b"merchant_multisig", recipient.key().as_ref()],
+        bump = merchant_multisig.bump,
+        has_one = recipient,
+        has_one = vault @ ErrorCode::MultisigApprovalRequired,
+        close = recipient
+    )]
+    pub merchant_multisig: Account<'info, MerchantMultisig>,
+
+    /// CHECK: gets the rent back
+    #[account(mut)]
+    pub recipient: UncheckedAccount<'info>,
+
+    pub vault: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetRecipientTokenAccount<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + PayoutChange::INIT_SPACE,
+        seeds = [b"payout_change", recipient.key().as_ref()],
+        bump
+    )]
+    pub payout_change: Account<'info, PayoutChange>,
+
+    /// CHECK: the recipient's `MerchantMultisig` address, which may be empty;
+    /// read by `check_merchant_authority`
+    #[account(seeds = [b"merchant_multisig", recipient.key().as_ref()], bump)]
+    pub merchant_multisig: UncheckedAccount<'info>,
+
+    /// CHECK: the merchant the payouts are for; `authority` signs for it
+    pub recipient: UncheckedAccount<'info>,
+
+    /// The recipient, or its multisig's vault
+    pub authority: Signer<'info>,
+
+    /// CHECK: must be owned by the token program; the mint is checked
+    /// against each subscription when the change is applied
+    pub token_account: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ApplyRecipientTokenAccount<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"subscription",
+            subscription.authority.as_ref(),
+            subscription.recipient.as_ref(),
+        ],
+        bump = subscription.bump
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    #[account(
+        seeds = [b"payout_change", subscription.recipient.as_ref()],
+        bump = payout_change.bump,
+        has_one = token_account
+    )]
+    pub payout_change: Account<'info, PayoutChange>,
+
+    /// CHECK: the scheduled payout account; token program owner and mint
+    /// are checked in the handler
+    pub token_account: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CompactSubscription<'info> {
+    /// CHECK: an inactive subscription; the handler checks its
+    /// discriminator and rewrites it as a tombstone
+    #[account(mut, owner = crate::ID)]
+    pub subscription: UncheckedAccount<'info>,
+
+    /// CHECK: the subscription's authority, checked in the handler; gets the
+    /// rent difference
+    #[account(mut)]
+    pub authority: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseSubscriptionTombstone<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"subscription",
+            tombstone.authority.as_ref(),
+            tombstone.recipient.as_ref(),
+        ],
+        bump = tombstone.bump,
+        has_one = authority,
+        close = authority
+    )]
+    pub tombstone: Account<'info, SubscriptionTombstone>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateMerchantVault<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + MerchantVault::INIT_SPACE,
+        seeds = [b"merchant_vault", recipient.key().as_ref()],
+        bump
+    )]
+    pub vault: Account<'info, MerchantVault>,
+
+    /// CHECK: the recipient's `MerchantMultisig` address, which may be empty;
+    /// read by `check_merchant_authority`
+    #[account(seeds = [b"merchant_multisig", recipient.key().as_ref()], bump)]
+    pub merchant_multisig: UncheckedAccount<'info>,
+
+    /// CHECK: the merchant the vault collects for; `authority` signs for it
+    pub recipient: UncheckedAccount<'info>,
+
+    /// The recipient, or its multisig's vault
+    pub authority: Signer<'info>,
+
+    /// CHECK: a token account owned by `vault`, checked in the handler
+    pub token_account: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetVaultHoldback<'info> {
+    #[account(
+        mut,
+        seeds = [b"merchant_vault", recipient.key().as_ref()],
+        bump = vault.bump,
+        has_one = recipient,
+        has_one = token_account
+    )]
+    pub vault: Account<'info, MerchantVault>,
+
+    /// CHECK: the recipient's `MerchantMultisig` address, which may be empty;
+    /// read by `check_merchant_authority`
+    #[account(seeds = [b"merchant_multisig", recipient.key().as_ref()], bump)]
+    pub merchant_multisig: UncheckedAccount<'info>,
+
+    /// CHECK: the merchant the vault collects for; `authority` signs for it
+    pub recipient: UncheckedAccount<'info>,
+
+    /// The recipient, or its multisig's vault
+    pub authority: Signer<'info>,
+
+    /// CHECK: the vault's token account, pinned by `has_one`
+    pub token_account: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AddWithdrawalDestination<'info> {
+    #[account(
+        mut,
+        seeds = [b"merchant_vault", recipient.key().as_ref()],
+        bump = vault.bump,
+        has_one = recipient
+    )]
+    pub vault: Account<'info, MerchantVault>,
+
+    /// CHECK: the recipient's `MerchantMultisig` address, which may be empty;
+    /// read by `check_merchant_authority`
+    #[account(seeds = [b"merchant_multisig", recipient.key().as_ref()], bump)]
+    pub merchant_multisig: UncheckedAccount<'info>,
+
+    /// CHECK: the merchant the vault collects for; `authority` signs for it
+    pub recipient: UncheckedAccount<'info>,
+
+    /// The recipient, or its multisig's vault
+    pub authority: Signer<'info>,
+
+    /// CHECK: a token account of the vault's mint, checked in the handler
+    pub token_account: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveWithdrawalDestination<'info> {
+    #[account(
+        mut,
+        seeds = [b"merchant_vault", recipient.key().as_ref()],
+        bump = vault.bump,
+        has_one = recipient
+    )]
+    pub vault: Account<'info, MerchantVault>,
+
+    /// CHECK: the recipient's `MerchantMultisig` address, which may be empty;
+    /// read by `check_merchant_authority`
+    #[account(seeds = [b"merchant_multisig", recipient.key().as_ref()], bump)]
+    pub merchant_multisig: UncheckedAccount<'info>,
+
+    /// CHECK: the merchant the vault collects for; `authority` signs for it
+    pub recipient: UncheckedAccount<'info>,
+
+    /// The recipient, or its multisig's vault
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetWithdrawalLimit<'info> {
+    #[account(
+        mut,
+        seeds = [b"merchant_vault", recipient.key().as_ref()],
+        bump = vault.bump,
+        has_one = recipient
+    )]
+    pub vault: Account<'info, MerchantVault>,
+
+    /// CHECK: the recipient's `MerchantMultisig` address, which may be empty;
+    /// read by `check_merchant_authority`
+    #[account(seeds = [b"merchant_multisig", recipient.key().as_ref()], bump)]
+    pub merchant_multisig: UncheckedAccount<'info>,
+
+    /// CHECK: the merchant the vault collects for
+    pub recipient: UncheckedAccount<'info>,
+
+    /// The merchant authority, or the vault's withdrawal admin
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RecoverRevenue<'info> {
+    #[account(
+        mut,
+        seeds = [b"merchant_vault", recipient.key().as_ref()],
+        bump = vault.bump,
+        has_one = recipient,
+        has_one = token_account,
+        has_one = withdrawal_admin
+    )]
+    pub vault: Account<'info, MerchantVault>,
+
+    /// CHECK: the merchant the vault collects for
+    pub recipient: UncheckedAccount<'info>,
+
+    pub withdrawal_admin: Signer<'info>,
+
+    /// CHECK: the vault's token account, pinned by `has_one`
+    #[account(mut)]
+    pub token_account: UncheckedAccount<'info>,
+
+    /// CHECK: any token account of the vault's mint; the token program
+    /// checks it
+    #[account(mut)]
+    pub destination: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateSlaCommitment<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + SlaCommitment::INIT_SPACE,
+        seeds = [b"sla", recipient.key().as_ref()],
+        bump
+    )]
+    pub sla: Account<'info, SlaCommitment>,
+
+    #[account(
+        seeds = [b"merchant_vault", recipient.key().as_ref()],
+        bump = vault.bump,
+        has_one = recipient
+    )]
+    pub vault: Account<'info, MerchantVault>,
+
+    /// CHECK: the recipient's `MerchantMultisig` address, which may be empty;
+    /// read by `check_merchant_authority`
+    #[account(seeds = [b"merchant_multisig", recipient.key().as_ref()], bump)]
+    pub merchant_multisig: UncheckedAccount<'info>,
+
+    /// CHECK: the merchant making the commitment; `authority` signs for it
+    pub recipient: UncheckedAccount<'info>,
+
+    /// The recipient, or its multisig's vault
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct EndSlaCommitment<'info> {
+    #[account(
+        mut,
+        seeds = [b"sla", recipient.key().as_ref()],
+        bump = sla.bump,
+        has_one = recipient
+    )]
+    pub sla: Account<'info, SlaCommitment>,
+
+    /// CHECK: the recipient's `MerchantMultisig` address, which may be empty;
+    /// read by `check_merchant_authority`
+    #[account(seeds = [b"merchant_multisig", recipient.key().as_ref()], bump)]
+    pub merchant_multisig: UncheckedAccount<'info>,
+
+    /// CHECK: the committed merchant; `authority` signs for it
+    pub recipient: UncheckedAccount<'info>,
+
+    /// The recipient, or its multisig's vault
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseSlaCommitment<'info> {
+    #[account(
+        mut,
+        seeds = [b"sla", recipient.key().as_ref()],
+        bump = sla.bump,
+        has_one = recipient,
+        close = recipient
+    )]
+    pub sla: Account<'info, SlaCommitment>,
+
+    /// CHECK: the recipient's `MerchantMultisig` address, which may be empty;
+    /// read by `check_merchant_authority`
+    #[account(seeds = [b"merchant_multisig", recipient.key().as_ref()], bump)]
+    pub merchant_multisig: UncheckedAccount<'info>,
+
+    /// CHECK: the committed merchant, receiving the rent; `authority` signs
+    /// for it
+    #[account(mut)]
+    pub recipient: UncheckedAccount<'info>,
+
+    /// The recipient, or its multisig's vault
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(started_at: i64)]
+pub struct AttestDowntime<'info> {
+    #[account(
+        seeds = [b"sla", recipient.key().as_ref()],
+        bump = sla.bump,
+        has_one = recipient,
+        has_one = attester
+    )]
+    pub sla: Account<'info, SlaCommitment>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + DowntimeAttestation::INIT_SPACE,
+        seeds = [b"downtime", recipient.key().as_ref(), &started_at.to_le_bytes()],
+        bump
+    )]
+    pub attestation: Account<'info, DowntimeAttestation>,
+
+    /// CHECK: the merchant whose service was down
+    pub recipient: UncheckedAccount<'info>,
+
+    pub attester: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CreditSla<'info> {
+    #[account(
+        seeds = [b"sla", sla.recipient.as_ref()],
+        bump = sla.bump
+    )]
+    pub sla: Account<'info, SlaCommitment>,
+
+    #[account(
+        seeds = [
+            b"downtime",
+            sla.recipient.as_ref(),
+            &attestation.started_at.to_le_bytes(),
+        ],
+        bump = attestation.bump,
+        constraint = attestation.recipient == sla.recipient @ ErrorCode::InvalidDowntime
+    )]
+    pub attestation: Account<'info, DowntimeAttestation>,
+
+    #[account(
+        seeds = [
+            b"subscription",
+            subscription.authority.as_ref(),
+            subscription.recipient.as_ref(),
+        ],
+        bump = subscription.bump,
+        constraint = subscription.recipient == sla.recipient @ ErrorCode::NoSlaCredit,
+        has_one = user_token_account
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    #[account(
+        mut,
+        seeds = [b"merchant_vault", sla.recipient.as_ref()],
+        bump = vault.bump,
+        has_one = token_account
+    )]
+    pub vault: Account<'info, MerchantVault>,
+
+    /// CHECK: the vault's token account, pinned by `has_one`
+    #[account(mut)]
+    pub token_account: UncheckedAccount<'info>,
+
+    /// CHECK: the subscriber's token account, pinned by `has_one`
+    #[account(mut)]
+    pub user_token_account: UncheckedAccount<'info>,
+
+    /// One per subscription and outage, so each is credited once
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + SlaCredit::INIT_SPACE,
+        seeds = [b"sla_credit", attestation.key().as_ref(), subscription.key().as_ref()],
+        bump
+    )]
+    pub credit: Account<'info, SlaCredit>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ScheduleSlaDiscount<'info> {
+    #[account(
+        seeds = [b"sla", sla.recipient.as_ref()],
+        bump = sla.bump
+    )]
+    pub sla: Account<'info, SlaCommitment>,
+
+    #[account(
+        seeds = [
+            b"downtime",
+            sla.recipient.as_ref(),
+            &attestation.started_at.to_le_bytes(),
+        ],
+        bump = attestation.bump,
+        constraint = attestation.recipient == sla.recipient @ ErrorCode::InvalidDowntime
+    )]
+    pub attestation: Account<'info, DowntimeAttestation>,
+
+    #[account(
+        seeds = [
+            b"subscription",
+            subscription.authority.as_ref(),
+            subscription.recipient.as_ref(),
+        ],
+        bump = subscription.bump,
+        constraint = subscription.recipient == sla.recipient @ ErrorCode::NoSlaCredit
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    /// The same receipt as `credit_sla`'s, so an outage is either paid or
+    /// discounted, once
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + SlaCredit::INIT_SPACE,
+        seeds = [b"sla_credit", attestation.key().as_ref(), subscription.key().as_ref()],
+        bump
+    )]
+    pub credit: Account<'info, SlaCredit>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(feed_id: [u8; 32])]
+pub struct CreatePriceCache<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + PriceCache::INIT_SPACE,
+        seeds = [b"price_cache", feed_id.as_ref()],
+        bump
+    )]
+    pub price_cache: Account<'info, PriceCache>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RefreshPriceCache<'info> {
+    #[account(
+        mut,
+        seeds = [b"price_cache", price_cache.feed_id.as_ref()],
+        bump = price_cache.bump
+    )]
+    pub price_cache: Account<'info, PriceCache>,
+
+    /// CHECK: a Pyth `PriceUpdateV2`, parsed in the handler
+    pub price_update: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetUsdPrice<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"subscription",
+            subscription.authority.as_ref(),
+            subscription.recipient.as_ref(),
+        ],
+        bump = subscription.bump,
+        has_one = authority,
+        has_one = token_mint
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + UsdPeg::INIT_SPACE,
+        seeds = [b"usd_peg", subscription.key().as_ref()],
+        bump
+    )]
+    pub usd_peg: Account<'info, UsdPeg>,
+
+    #[account(
+        seeds = [b"price_cache", price_cache.feed_id.as_ref()],
+        bump = price_cache.bump
+    )]
+    pub price_cache: Account<'info, PriceCache>,
+
+    /// CHECK: the subscription's mint, pinned by `has_one`, for its decimals
+    #[account(owner = spl_token::ID)]
+    pub token_mint: UncheckedAccount<'info>,
+
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseUsdPeg<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"subscription",
+            subscription.authority.as_ref(),
+            subscription.recipient.as_ref(),
+        ],
+        bump = subscription.bump,
+        has_one = authority
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"usd_peg", subscription.key().as_ref()],
+        bump = usd_peg.bump
+    )]
+    pub usd_peg: Account<'info, UsdPeg>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ChargeSubscriptionUsd<'info> {
+    pub charge: ChargeSubscription<'info>,
+
+    #[account(
+        seeds = [b"usd_peg", charge.subscription.key().as_ref()],
+        bump = usd_peg.bump,
+        has_one = price_cache
+    )]
+    pub usd_peg: Account<'info, UsdPeg>,
+
+    pub price_cache: Account<'info, PriceCache>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptFallbackMint<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + AcceptedMint::INIT_SPACE,
+        seeds = [b"accepted_mint", recipient.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub accepted_mint: Account<'info, AcceptedMint>,
+
+    /// CHECK: the recipient's `MerchantMultisig` address, which may be empty;
+    /// read by `check_merchant_authority`
+    #[account(seeds = [b"merchant_multisig", recipient.key().as_ref()], bump)]
+    pub merchant_multisig: UncheckedAccount<'info>,
+
+    /// CHECK: the merchant accepting the mint; `authority` signs for it
+    pub recipient: UncheckedAccount<'info>,
+
+    /// The recipient, or its multisig's vault
+    pub authority: Signer<'info>,
+
+    /// CHECK: the accepted mint, matched against `token_account`
+    pub token_mint: UncheckedAccount<'info>,
+
+    /// CHECK: the merchant's token account of `token_mint`, checked in the
+    /// handler
+    #[account(owner = spl_token::ID @ ErrorCode::InvalidFallbackMint)]
+    pub token_account: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseAcceptedMint<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"accepted_mint",
+            recipient.key().as_ref(),
+            accepted_mint.token_mint.as_ref(),
+        ],
+        bump = accepted_mint.bump,
+        has_one = recipient,
+        close = recipient
+    )]
+    pub accepted_mint: Account<'info, AcceptedMint>,
+
+    /// CHECK: the recipient's `MerchantMultisig` address, which may be empty;
+    /// read by `check_merchant_authority`
+    #[account(seeds = [b"merchant_multisig", recipient.key().as_ref()], bump)]
+    pub merchant_multisig: UncheckedAccount<'info>,
+
+    /// CHECK: the merchant, receiving the rent; `authority` signs for it
+    #[account(mut)]
+    pub recipient: UncheckedAccount<'info>,
+
+    /// The recipient, or its multisig's vault
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetFallbackPayment<'info> {
+    #[account(
+        seeds = [
+            b"subscription",
+            subscription.authority.as_ref(),
+            subscription.recipient.as_ref(),
+        ],
+        bump = subscription.bump,
+        has_one = authority,
+        has_one = token_mint
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + FallbackPayment::INIT_SPACE,
+        seeds = [b"fallback_payment", subscription.key().as_ref()],
+        bump
+    )]
+    pub fallback_payment: Account<'info, FallbackPayment>,
+
+    #[account(
+        seeds = [
+            b"accepted_mint",
+            subscription.recipient.as_ref(),
+            accepted_mint.token_mint.as_ref(),
+        ],
+        bump = accepted_mint.bump
+    )]
+    pub accepted_mint: Account<'info, AcceptedMint>,
+
+    /// CHECK: the subscription's mint, pinned by `has_one`, for its decimals
+    #[account(owner = spl_token::ID)]
+    pub token_mint: UncheckedAccount<'info>,
+
+    /// CHECK: the accepted mint, for its decimals
+    #[account(owner = spl_token::ID, address = accepted_mint.token_mint)]
+    pub fallback_mint: UncheckedAccount<'info>,
+
+    /// CHECK: the authority's token account of the accepted mint, checked
+    /// in the handler
+    #[account(owner = spl_token::ID @ ErrorCode::InvalidFallbackPayment)]
+    pub token_account: UncheckedAccount<'info>,
+
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseFallbackPayment<'info> {
+    #[account(
+        mut,
+        seeds = [b"fallback_payment", subscription.key().as_ref()],
+        bump = fallback_payment.bump,
+        has_one = subscription,
+        close = authority
+    )]
+    pub fallback_payment: Account<'info, FallbackPayment>,
+
+    #[account(has_one = authority)]
+    pub subscription: Account<'info, Subscription>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ChargeSubscriptionFallback<'info> {
+    pub charge: ChargeSubscription<'info>,
+
+    #[account(
+        seeds = [b"fallback_payment", charge.subscription.key().as_ref()],
+        bump = fallback_payment.bump,
+        has_one = token_account
+    )]
+    pub fallback_payment: Account<'info, FallbackPayment>,
+
+    /// CHECK: the subscriber's fallback account, pinned by `has_one`
+    #[account(mut)]
+    pub token_account: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [
+            b"accepted_mint",
+            charge.subscription.recipient.as_ref(),
+            fallback_payment.token_mint.as_ref(),
+        ],
+        bump = accepted_mint.bump
+    )]
+    pub accepted_mint: Account<'info, AcceptedMint>,
+
+    /// CHECK: the merchant's account of the fallback mint
+    #[account(mut, address = accepted_mint.token_account @ ErrorCode::InvalidTokenAccount)]
+    pub merchant_token_account: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct PayDeposit<'info> {
+    #[account(
+        seeds = [
+            b"subscription",
+            subscription.authority.as_ref(),
+            subscription.recipient.as_ref(),
+        ],
+        bump = subscription.bump,
+        has_one = authority,
+        has_one = user_token_account
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + SubscriptionDeposit::INIT_SPACE,
+        seeds = [b"deposit", subscription.key().as_ref()],
+        bump
+    )]
+    pub deposit: Account<'info, SubscriptionDeposit>,
+
+    /// CHECK: the escrow, a token account owned by `deposit`, checked in the
+    /// handler
+    #[account(mut)]
+    pub token_account: UncheckedAccount<'info>,
+
+    /// CHECK: the subscription's token account, pinned by `has_one`
+    #[account(mut)]
+    pub user_token_account: UncheckedAccount<'info>,
+
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RefundDeposit<'info> {
+    #[account(
+        mut,
+        seeds = [b"deposit", deposit.subscription.as_ref()],
+        bump = deposit.bump,
+        has_one = authority,
+        has_one = token_account,
+        has_one = refund_account,
+        close = authority
+    )]
+    pub deposit: Account<'info, SubscriptionDeposit>,
+
+    /// CHECK: the subscription's address, which may be closed; read by
+    /// `SubscriptionDeposit::check_refundable`
+    #[account(address = deposit.subscription)]
+    pub subscription: UncheckedAccount<'info>,
+
+    /// CHECK: the escrow, pinned by `has_one`
+    #[account(mut)]
+    pub token_account: UncheckedAccount<'info>,
+
+    /// CHECK: the account the deposit was paid from, pinned by `has_one`
+    #[account(mut)]
+    pub refund_account: UncheckedAccount<'info>,
+
+    /// CHECK: the user, receiving the rent; pinned by `has_one`
+    #[account(mut)]
+    pub authority: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeSubscriptionWithSetupFee<'info> {
+    pub init: InitializeSubscription<'info>,
+
+    #[account(
+        init,
+        payer = init.payer,
+        space = 8 + SetupFee::INIT_SPACE,
+        seeds = [b"setup_fee", init.subscription.key().as_ref()],
+        bump
+    )]
+    pub setup_fee: Account<'info, SetupFee>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeWalletSubscriptionWithSetupFee<'info> {
+    pub init: InitializeWalletSubscription<'info>,
+
+    #[account(
+        init,
+        payer = init.payer,
+        space = 8 + SetupFee::INIT_SPACE,
+        seeds = [b"setup_fee", init.subscription.key().as_ref()],
+        bump
+    )]
+    pub setup_fee: Account<'info, SetupFee>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct CreatePlan<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Plan::INIT_SPACE,
+        seeds = [b"plan", recipient.key().as_ref(), &plan_id.to_le_bytes()],
+        bump
+    )]
+    pub plan: Account<'info, Plan>,
+
+    /// CHECK: the recipient's `MerchantMultisig` address, which may be empty;
+    /// read by `check_merchant_authority`
+    #[account(seeds = [b"merchant_multisig", recipient.key().as_ref()], bump)]
+    pub merchant_multisig: UncheckedAccount<'info>,
+
+    /// CHECK: the merchant offering the plan; `authority` signs for it
+    pub recipient: UncheckedAccount<'info>,
+
+    /// The recipient, or its multisig's vault
+    pub authority: Signer<'info>,
+
+    /// CHECK: the mint the plan is priced in
+    #[account(owner = spl_token::ID)]
+    pub token_mint: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct JoinPlan<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"subscription",
+            subscription.authority.as_ref(),
+            subscription.recipient.as_ref(),
+        ],
+        bump = subscription.bump,
+        has_one = authority
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    #[account(
+        mut,
+        seeds = [b"plan", plan.recipient.as_ref(), &plan.plan_id.to_le_bytes()],
+        bump = plan.bump,
+        constraint = plan.recipient == subscription.recipient
+            && plan.token_mint == subscription.token_mint @ ErrorCode::InvalidPlan
+    )]
+    pub plan: Account<'info, Plan>,
+
+    /// CHECK: the plan's `Waitlist` address, which may be empty; a seat goes
+    /// to its entries first
+    #[account(seeds = [b"waitlist", plan.key().as_ref()], bump)]
+    pub waitlist: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + PlanSubscription::INIT_SPACE,
+        seeds = [b"plan_subscription", subscription.key().as_ref()],
+        bump
+    )]
+    pub plan_subscription: Account<'info, PlanSubscription>,
+
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SwitchBillingCadence<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"subscription",
+            subscription.authority.as_ref(),
+            subscription.recipient.as_ref(),
+        ],
+        bump = subscription.bump,
+        has_one = authority,
+        has_one = user_token_account,
+        has_one = recipient_token_account
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    #[account(
+        mut,
+        seeds = [b"plan_subscription", subscription.key().as_ref()],
+        bump = plan_subscription.bump,
+        has_one = subscription,
+        has_one = plan
+    )]
+    pub plan_subscription: Account<'info, PlanSubscription>,
+
+    #[account(
+        seeds = [b"plan", plan.recipient.as_ref(), &plan.plan_id.to_le_bytes()],
+        bump = plan.bump
+    )]
+    pub plan: Account<'info, Plan>,
+
+    /// CHECK: the subscription's token account, pinned by `has_one`
+    #[account(mut)]
+    pub user_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: the recipient's token account, pinned by `has_one`
+    #[account(mut)]
+    pub recipient_token_account: UncheckedAccount<'info>,
+
+    pub authority: Signer<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPlanMetering<'info> {
+    #[account(
+        mut,
+        seeds = [b"plan", recipient.key().as_ref(), &plan.plan_id.to_le_bytes()],
+        bump = plan.bump,
+        has_one = recipient
+    )]
+    pub plan: Account<'info, Plan>,
+
+    /// CHECK: the recipient's `MerchantMultisig` address, which may be empty;
+    /// read by `check_merchant_authority`
+    #[account(seeds = [b"merchant_multisig", recipient.key().as_ref()], bump)]
+    pub merchant_multisig: UncheckedAccount<'info>,
+
+    /// CHECK: the merchant offering the plan; `authority` signs for it
+    pub recipient: UncheckedAccount<'info>,
+
+    /// The recipient, or its multisig's vault
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RecordUsage<'info> {
+    #[account(
+        mut,
+        seeds = [b"plan_subscription", subscription.key().as_ref()],
+        bump = plan_subscription.bump,
+        has_one = subscription,
+        has_one = plan
+    )]
+    pub plan_subscription: Account<'info, PlanSubscription>,
+
+    #[account(
+        seeds = [
+            b"subscription",
+            subscription.authority.as_ref(),
+            subscription.recipient.as_ref(),
+        ],
+        bump = subscription.bump,
+        has_one = recipient
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    #[account(
+        seeds = [b"plan", plan.recipient.as_ref(), &plan.plan_id.to_le_bytes()],
+        bump = plan.bump
+    )]
+    pub plan: Account<'info, Plan>,
+
+    /// CHECK: the recipient's `MerchantMultisig` address, which may be empty;
+    /// read by `check_merchant_authority`
+    #[account(seeds = [b"merchant_multisig", recipient.key().as_ref()], bump)]
+    pub merchant_multisig: UncheckedAccount<'info>,
+
+    /// CHECK: the subscription's merchant; `authority` signs for it
+    pub recipient: UncheckedAccount<'info>,
+
+    /// The recipient, or its multisig's vault
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct VetoTierUpgrade<'info> {
+    #[account(
+        mut,
+        seeds = [b"plan_subscription", subscription.key().as_ref()],
+        bump = plan_subscription.bump,
+        has_one = subscription
+    )]
+    pub plan_subscription: Account<'info, PlanSubscription>,
+
+    #[account(
+        seeds = [
+            b"subscription",
+            subscription.authority.as_ref(),
+            subscription.recipient.as_ref(),
+        ],
+        bump = subscription.bump,
+        has_one = authority
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ApplyTierUpgrade<'info> {
+    #[account(
+        mut,
+        seeds = [b"plan_subscription", subscription.key().as_ref()],
+        bump = plan_subscription.bump,
+        has_one = subscription,
+        has_one = plan
+    )]
+    pub plan_subscription: Account<'info, PlanSubscription>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"subscription",
+            subscription.authority.as_ref(),
+            subscription.recipient.as_ref(),
+        ],
+        bump = subscription.bump,
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    #[account(
+        mut,
+        seeds = [b"plan", plan.recipient.as_ref(), &plan.plan_id.to_le_bytes()],
+        bump = plan.bump
+    )]
+    pub plan: Account<'info, Plan>,
+
+    #[account(
+        mut,
+        seeds = [b"plan", next_tier.recipient.as_ref(), &next_tier.plan_id.to_le_bytes()],
+        bump = next_tier.bump,
+        constraint = plan.next_tier == Some(next_tier.key())
+            && next_tier.recipient == subscription.recipient
+            && next_tier.token_mint == subscription.token_mint @ ErrorCode::InvalidPlan
+    )]
+    pub next_tier: Account<'info, Plan>,
+}
+
+#[derive(Accounts)]
+pub struct ChargeOverage<'info> {
+    #[account(
+        mut,
+        seeds = [b"plan_subscription", subscription.key().as_ref()],
+        bump = plan_subscription.bump,
+        has_one = subscription,
+        has_one = plan
+    )]
+    pub plan_subscription: Account<'info, PlanSubscription>,
+
+    #[account(
+        seeds = [
+            b"subscription",
+            subscription.authority.as_ref(),
+            subscription.recipient.as_ref(),
+        ],
+        bump = subscription.bump,
+        has_one = recipient,
+        has_one = user_token_account,
+        has_one = recipient_token_account
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    #[account(
+        seeds = [b"plan", plan.recipient.as_ref(), &plan.plan_id.to_le_bytes()],
+        bump = plan.bump
+    )]
+    pub plan: Account<'info, Plan>,
+
+    /// CHECK: the recipient's `MerchantMultisig` address, which may be empty;
+    /// read by `check_merchant_authority`
+    #[account(seeds = [b"merchant_multisig", recipient.key().as_ref()], bump)]
+    pub merchant_multisig: UncheckedAccount<'info>,
+
+    /// CHECK: the subscription's merchant; `authority` signs for it
+    pub recipient: UncheckedAccount<'info>,
+
+    /// The recipient, or its multisig's vault
+    pub authority: Signer<'info>,
+
+    /// CHECK: the subscription's token account, pinned by `has_one`
+    #[account(mut)]
+    pub user_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: the recipient's token account, pinned by `has_one`
+    #[account(mut)]
+    pub recipient_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateSpendAlerts<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + SpendAlerts::INIT_SPACE,
+        seeds = [b"spend_alerts", subscription.key().as_ref()],
+        bump
+    )]
+    pub spend_alerts: Account<'info, SpendAlerts>,
+
+    #[account(
+        seeds = [
+            b"subscription",
+            subscription.authority.as_ref(),
+            subscription.recipient.as_ref(),
+        ],
+        bump = subscription.bump,
+        has_one = authority
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateSpendAlerts<'info> {
+    #[account(
+        mut,
+        seeds = [b"spend_alerts", subscription.key().as_ref()],
+        bump = spend_alerts.bump,
+        has_one = subscription
+    )]
+    pub spend_alerts: Account<'info, SpendAlerts>,
+
+    #[account(
+        seeds = [
+            b"subscription",
+            subscription.authority.as_ref(),
+            subscription.recipient.as_ref(),
+        ],
+        bump = subscription.bump,
+        has_one = authority
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseSpendAlerts<'info> {
+    #[account(
+        mut,
+        seeds = [b"spend_alerts", spend_alerts.subscription.as_ref()],
+        bump = spend_alerts.bump,
+        has_one = authority,
+        close = authority
+    )]
+    pub spend_alerts: Account<'info, SpendAlerts>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetSpendLimit<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"subscription",
+            subscription.authority.as_ref(),
+            subscription.recipient.as_ref(),
+        ],
+        bump = subscription.bump,
+        has_one = authority,
+        has_one = user_token_account
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    pub authority: Signer<'info>,
+
+    /// CHECK: the subscription's token account, pinned by `has_one`
+    #[account(mut)]
+    pub user_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(wallet: Pubkey)]
+pub struct DenySubscriber<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + DenylistEntry::INIT_SPACE,
+        seeds = [b"denylist", recipient.key().as_ref(), wallet.as_ref()],
+        bump
+    )]
+    pub denylist_entry: Account<'info, DenylistEntry>,
+
+    /// CHECK: the recipient's `MerchantMultisig` address, which may be empty;
+    /// read by `check_merchant_authority`
+    #[account(seeds = [b"merchant_multisig", recipient.key().as_ref()], bump)]
+    pub merchant_multisig: UncheckedAccount<'info>,
+
+    /// CHECK: the merchant keeping the denylist; `authority` signs for it
+    pub recipient: UncheckedAccount<'info>,
+
+    /// The recipient, or its multisig's vault
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AllowSubscriber<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"denylist",
+            recipient.key().as_ref(),
+            denylist_entry.wallet.as_ref(),
+        ],
+        bump = denylist_entry.bump,
+        has_one = recipient,
+        close = recipient
+    )]
+    pub denylist_entry: Account<'info, DenylistEntry>,
+
+    /// CHECK: the recipient's `MerchantMultisig` address, which may be empty;
+    /// read by `check_merchant_authority`
+    #[account(seeds = [b"merchant_multisig", recipient.key().as_ref()], bump)]
+    pub merchant_multisig: UncheckedAccount<'info>,
+
+    /// CHECK: the merchant, receiving the rent; `authority` signs for it
+    #[account(mut)]
+    pub recipient: UncheckedAccount<'info>,
+
+    /// The recipient, or its multisig's vault
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateSubscriberAllowlist<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + SubscriberAllowlist::INIT_SPACE,
+        seeds = [b"allowlist", recipient.key().as_ref()],
+        bump
+    )]
+    pub allowlist: Account<'info, SubscriberAllowlist>,
+
+    /// CHECK: the recipient's `MerchantMultisig` address, which may be empty;
+    /// read by `check_merchant_authority`
+    #[account(seeds = [b"merchant_multisig", recipient.key().as_ref()], bump)]
+    pub merchant_multisig: UncheckedAccount<'info>,
+
+    /// CHECK: the merchant going invite-only; `authority` signs for it
+    pub recipient: UncheckedAccount<'info>,
+
+    /// The recipient, or its multisig's vault
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseSubscriberAllowlist<'info> {
+    #[account(
+        mut,
+        seeds = [b"allowlist", recipient.key().as_ref()],
+        bump = allowlist.bump,
+        has_one = recipient,
+        close = recipient
+    )]
+    pub allowlist: Account<'info, SubscriberAllowlist>,
+
+    /// CHECK: the recipient's `MerchantMultisig` address, which may be empty;
+    /// read by `check_merchant_authority`
+    #[account(seeds = [b"merchant_multisig", recipient.key().as_ref()], bump)]
+    pub merchant_multisig: UncheckedAccount<'info>,
+
+    /// CHECK: the merchant, receiving the rent; `authority` signs for it
+    #[account(mut)]
+    pub recipient: UncheckedAccount<'info>,
+
+    /// The recipient, or its multisig's vault
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(wallet: Pubkey)]
+pub struct AddToAllowlist<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + AllowlistEntry::INIT_SPACE,
+        seeds = [b"allowlist", recipient.key().as_ref(), wallet.as_ref()],
+        bump
+    )]
+    pub allowlist_entry: Account<'info, AllowlistEntry>,
+
+    /// CHECK: the recipient's `MerchantMultisig` address, which may be empty;
+    /// read by `check_merchant_authority`
+    #[account(seeds = [b"merchant_multisig", recipient.key().as_ref()], bump)]
+    pub merchant_multisig: UncheckedAccount<'info>,
+
+    /// CHECK: the merchant keeping the allowlist; `authority` signs for it
+    pub recipient: UncheckedAccount<'info>,
+
+    /// The recipient, or its multisig's vault
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveFromAllowlist<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"allowlist",
+            recipient.key().as_ref(),
+            allowlist_entry.wallet.as_ref(),
+        ],
+        bump = allowlist_entry.bump,
+        has_one = recipient,
+        close = recipient
+    )]
+    pub allowlist_entry: Account<'info, AllowlistEntry>,
+
+    /// CHECK: the recipient's `MerchantMultisig` address, which may be empty;
+    /// read by `check_merchant_authority`
+    #[account(seeds = [b"merchant_multisig", recipient.key().as_ref()], bump)]
+    pub merchant_multisig: UncheckedAccount<'info>,
+
+    /// CHECK: the merchant, receiving the rent; `authority` signs for it
+    #[account(mut)]
+    pub recipient: UncheckedAccount<'info>,
+
+    /// The recipient, or its multisig's vault
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPlanCapacity<'info> {
+    #[account(
+        mut,
+        seeds = [b"plan", recipient.key().as_ref(), &plan.plan_id.to_le_bytes()],
+        bump = plan.bump,
+        has_one = recipient
+    )]
+    pub plan: Account<'info, Plan>,
+
+    /// CHECK: the recipient's `MerchantMultisig` address, which may be empty;
+    /// read by `check_merchant_authority`
+    #[account(seeds = [b"merchant_multisig", recipient.key().as_ref()], bump)]
+    pub merchant_multisig: UncheckedAccount<'info>,
+
+    /// CHECK: the merchant offering the plan; `authority` signs for it
+    pub recipient: UncheckedAccount<'info>,
+
+    /// The recipient, or its multisig's vault
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPlanIntroPricing<'info> {
+    #[account(
+        mut,
+        seeds = [b"plan", recipient.key().as_ref(), &plan.plan_id.to_le_bytes()],
+        bump = plan.bump,
+        has_one = recipient
+    )]
+    pub plan: Account<'info, Plan>,
+
+    /// CHECK: the recipient's `MerchantMultisig` address, which may be empty;
+    /// read by `check_merchant_authority`
+    #[account(seeds = [b"merchant_multisig", recipient.key().as_ref()], bump)]
+    pub merchant_multisig: UncheckedAccount<'info>,
+
+    /// CHECK: the merchant offering the plan; `authority` signs for it
+    pub recipient: UncheckedAccount<'info>,
+
+    /// The recipient, or its multisig's vault
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPlanPriceRamp<'info> {
+    #[account(
+        mut,
+        seeds = [b"plan", recipient.key().as_ref(), &plan.plan_id.to_le_bytes()],
+        bump = plan.bump,
+        has_one = recipient
+    )]
+    pub plan: Account<'info, Plan>,
+
+    /// CHECK: the recipient's `MerchantMultisig` address, which may be empty;
+    /// read by `check_merchant_authority`
+    #[account(seeds = [b"merchant_multisig", recipient.key().as_ref()], bump)]
+    pub merchant_multisig: UncheckedAccount<'info>,
+
+    /// CHECK: the merchant offering the plan; `authority` signs for it
+    pub recipient: UncheckedAccount<'info>,
+
+    /// The recipient, or its multisig's vault
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ReleasePlanSeat<'info> {
+    #[account(
+        mut,
+        seeds = [b"plan_subscription", subscription.key().as_ref()],
+        bump = plan_subscription.bump,
+        has_one = subscription,
+        has_one = plan,
+        close = recipient
+    )]
+    pub plan_subscription: Account<'info, PlanSubscription>,
+
+    /// CHECK: the subscription's address, which must not hold an active
+    /// subscription; read by `holds_active_subscription`
+    pub subscription: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"plan", recipient.key().as_ref(), &plan.plan_id.to_le_bytes()],
+        bump = plan.bump,
+        has_one = recipient
+    )]
+    pub plan: Account<'info, Plan>,
+
+    /// CHECK: the plan's merchant, receiving the rent
+    #[account(mut)]
+    pub recipient: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateWaitlist<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Waitlist::INIT_SPACE,
+        seeds = [b"waitlist", plan.key().as_ref()],
+        bump
+    )]
+    pub waitlist: Account<'info, Waitlist>,
+
+    #[account(
+        seeds = [b"plan", recipient.key().as_ref(), &plan.plan_id.to_le_bytes()],
+        bump = plan.bump,
+        has_one = recipient
+    )]
+    pub plan: Account<'info, Plan>,
+
+    /// CHECK: the recipient's `MerchantMultisig` address, which may be empty;
+    /// read by `check_merchant_authority`
+    #[account(seeds = [b"merchant_multisig", recipient.key().as_ref()], bump)]
+    pub merchant_multisig: UncheckedAccount<'info>,
+
+    /// CHECK: the merchant offering the plan; `authority` signs for it
+    pub recipient: UncheckedAccount<'info>,
+
+    /// The recipient, or its multisig's vault
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct JoinWaitlist<'info> {
+    #[account(
+        mut,
+        seeds = [b"waitlist", plan.key().as_ref()],
+        bump = waitlist.bump,
+        has_one = plan
+    )]
+    pub waitlist: Account<'info, Waitlist>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + WaitlistEntry::INIT_SPACE,
+        seeds = [b"waitlist_entry", plan.key().as_ref(), &waitlist.tail.to_le_bytes()],
+        bump
+    )]
+    pub entry: Account<'info, WaitlistEntry>,
+
+    #[account(
+        seeds = [b"plan", plan.recipient.as_ref(), &plan.plan_id.to_le_bytes()],
+        bump = plan.bump
+    )]
+    pub plan: Account<'info, Plan>,
+
+    /// CHECK: the subscription PDA promotion will create, approved now as
+    /// the delegate of `user_token_account`
+    #[account(
+        seeds = [
+            b"subscription",
+            authority.key().as_ref(),
+            plan.recipient.as_ref(),
+        ],
+        bump
+    )]
+    pub subscription: UncheckedAccount<'info>,
+
+    pub authority: Signer<'info>,
+
+    /// CHECK: the user's token account in the plan's mint, approved to the
+    /// subscription PDA
+    #[account(mut)]
+    pub user_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: the recipient's token account the first period is paid to
+    pub recipient_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: the authority's entry on the recipient's denylist, which must
+    /// be empty
+    #[account(
+        seeds = [b"denylist", plan.recipient.as_ref(), authority.key().as_ref()],
+        bump
+    )]
+    pub denylist_entry: UncheckedAccount<'info>,
+
+    /// CHECK: the recipient's `SubscriberAllowlist` address, which is empty
+    /// unless it is invite-only
+    #[account(seeds = [b"allowlist", plan.recipient.as_ref()], bump)]
+    pub allowlist: UncheckedAccount<'info>,
+
+    /// CHECK: the authority's entry on the recipient's allowlist, required
+    /// while `allowlist` is set
+    #[account(
+        seeds = [b"allowlist", plan.recipient.as_ref(), authority.key().as_ref()],
+        bump
+    )]
+    pub allowlist_entry: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct LeaveWaitlist<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"waitlist_entry",
+            entry.plan.as_ref(),
+            &entry.position.to_le_bytes(),
+        ],
+        bump = entry.bump,
+        has_one = authority,
+        has_one = payer,
+        has_one = user_token_account,
+        close = payer
+    )]
+    pub entry: Account<'info, WaitlistEntry>,
+
+    #[account(address = entry.plan)]
+    pub plan: Account<'info, Plan>,
+
+    /// CHECK: the subscription PDA the entry approved; left alone if it
+    /// holds a subscription
+    #[account(
+        seeds = [
+            b"subscription",
+            authority.key().as_ref(),
+            plan.recipient.as_ref(),
+        ],
+        bump
+    )]
+    pub subscription: UncheckedAccount<'info>,
+
+    pub authority: Signer<'info>,
+
+    /// CHECK: whoever paid for the entry, receiving its lamports
+    #[account(mut)]
+    pub payer: UncheckedAccount<'info>,
+
+    /// CHECK: the token account the entry approved
+    #[account(mut)]
+    pub user_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct PromoteFromWaitlist<'info> {
+    #[account(
+        mut,
+        seeds = [b"waitlist", plan.key().as_ref()],
+        bump = waitlist.bump,
+        has_one = plan
+    )]
+    pub waitlist: Account<'info, Waitlist>,
+
+    #[account(
+        mut,
+        seeds = [b"waitlist_entry", plan.key().as_ref(), &waitlist.head.to_le_bytes()],
+        bump = entry.bump,
+        has_one = authority,
+        has_one = user_token_account,
+        has_one = recipient_token_account,
+        close = payer
+    )]
+    pub entry: Box<Account<'info, WaitlistEntry>>,
+
+    #[account(
+        mut,
+        seeds = [b"plan", recipient.key().as_ref(), &plan.plan_id.to_le_bytes()],
+        bump = plan.bump,
+        has_one = recipient,
+        has_one = token_mint
+    )]
+    pub plan: Box<Account<'info, Plan>>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Subscription::INIT_SPACE,
+        seeds = [
+            b"subscription",
+            authority.key().as_ref(),
+            recipient.key().as_ref(),
+        ],
+        bump
+    )]
+    pub subscription: Box<Account<'info, Subscription>>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + PlanSubscription::INIT_SPACE,
+        seeds = [b"plan_subscription", subscription.key().as_ref()],
+        bump
+    )]
+    pub plan_subscription: Box<Account<'info, PlanSubscription>>,
+
+    /// CHECK: the waiting wallet, which approved the subscription PDA when
+    /// it joined
+    pub authority: UncheckedAccount<'info>,
+
+    /// CHECK: the plan's merchant
+    pub recipient: UncheckedAccount<'info>,
+
+    /// CHECK: the token account the entry approved
+    #[account(mut)]
+    pub user_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: the recipient's token account the entry chose
+    #[account(mut)]
+    pub recipient_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: the plan's mint
+    pub token_mint: UncheckedAccount<'info>,
+
+    /// CHECK: the authority's entry on the recipient's denylist, which must
+    /// be empty
+    #[account(
+        seeds = [b"denylist", recipient.key().as_ref(), authority.key().as_ref()],
+        bump
+    )]
+    pub denylist_entry: UncheckedAccount<'info>,
+
+    /// CHECK: the recipient's `SubscriberAllowlist` address, which is empty
+    /// unless it is invite-only
+    #[account(seeds = [b"allowlist", recipient.key().as_ref()], bump)]
+    pub allowlist: UncheckedAccount<'info>,
+
+    /// CHECK: the authority's entry on the recipient's allowlist, required
+    /// while `allowlist` is set
+    #[account(
+        seeds = [b"allowlist", recipient.key().as_ref(), authority.key().as_ref()],
+        bump
+    )]
+    pub allowlist_entry: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SkipWaitlistEntry<'info> {
+    #[account(
+        mut,
+        seeds = [b"waitlist", plan.key().as_ref()],
+        bump = waitlist.bump,
+        has_one = plan
+    )]
+    pub waitlist: Account<'info, Waitlist>,
+
+    /// CHECK: the oldest entry's address, empty if it was left; read by the
+    /// handler
+    #[account(
+        mut,
+        seeds = [b"waitlist_entry", plan.key().as_ref(), &waitlist.head.to_le_bytes()],
+        bump
+    )]
+    pub entry: UncheckedAccount<'info>,
+
+    pub plan: Account<'info, Plan>,
+
+    /// CHECK: the entry wallet's subscription address; checked by
+    /// `WaitlistEntry::check_accounts`
+    pub subscription: UncheckedAccount<'info>,
+
+    /// CHECK: the entry wallet's denylist entry; checked by
+    /// `WaitlistEntry::check_accounts`
+    pub denylist_entry: UncheckedAccount<'info>,
+
+    /// CHECK: the recipient's `SubscriberAllowlist` address
+    #[account(seeds = [b"allowlist", plan.recipient.as_ref()], bump)]
+    pub allowlist: UncheckedAccount<'info>,
+
+    /// CHECK: the entry wallet's allowlist entry; checked by
+    /// `WaitlistEntry::check_accounts`
+    pub allowlist_entry: UncheckedAccount<'info>,
+
+    /// CHECK: the entry's token account; checked by
+    /// `WaitlistEntry::check_accounts`
+    pub user_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: whoever paid for the entry, receiving its lamports; checked by
+    /// `WaitlistEntry::check_accounts`
+    #[account(mut)]
+    pub rent_receiver: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreatePlatformConfig<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + PlatformConfig::INIT_SPACE,
+        seeds = [b"platform_config"],
+        bump
+    )]
+    pub platform_config: Account<'info, PlatformConfig>,
+
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::SubscriptionProgram>,
+
+    #[account(constraint = program_data.upgrade_authority_address == Some(authority.key()))]
+    pub program_data: Account<'info, ProgramData>,
+
+    /// The program's upgrade authority, which becomes the admin
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct QueuePlatformConfigChange<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + PlatformConfigChange::INIT_SPACE,
+        seeds = [b"platform_config_change"],
+        bump
+    )]
+    pub config_change: Account<'info, PlatformConfigChange>,
+
+    #[account(
+        seeds = [b"platform_config"],
+        bump = platform_config.bump,
+        has_one = admin
+    )]
+    pub platform_config: Account<'info, PlatformConfig>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ApplyPlatformConfigChange<'info> {
+    #[account(
+        mut,
+        seeds = [b"platform_config_change"],
+        bump = config_change.bump,
+        close = admin
+    )]
+    pub config_change: Account<'info, PlatformConfigChange>,
+
+    #[account(
+        mut,
+        seeds = [b"platform_config"],
+        bump = platform_config.bump,
+        has_one = admin
+    )]
+    pub platform_config: Account<'info, PlatformConfig>,
+
+    /// CHECK: the admin that queued the change; gets its rent back
+    #[account(mut)]
+    pub admin: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelPlatformConfigChange<'info> {
+    #[account(
+        mut,
+        seeds = [b"platform_config_change"],
+        bump = config_change.bump,
+        close = admin
+    )]
+    pub config_change: Account<'info, PlatformConfigChange>,
+
+    #[account(
+        seeds = [b"platform_config"],
+        bump = platform_config.bump,
+        has_one = admin
+    )]
+    pub platform_config: Account<'info, PlatformConfig>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SyncMerchantVault<'info> {
+    #[account(mut, has_one = token_account)]
+    pub vault: Account<'info, MerchantVault>,
+
+    /// CHECK: the vault's token account, pinned by `has_one`
+    pub token_account: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawRevenue<'info> {
+    #[account(
+        mut,
+        seeds = [b"merchant_vault", recipient.key().as_ref()],
+        bump = vault.bump,
+        has_one = recipient,
+        has_one = token_account
+    )]
+    pub vault: Account<'info, MerchantVault>,
+
+    /// CHECK: the recipient's `MerchantMultisig` address, which may be empty;
+    /// read by `check_merchant_authority`
+    #[account(seeds = [b"merchant_multisig", recipient.key().as_ref()], bump)]
+    pub merchant_multisig: UncheckedAccount<'info>,
+
+    /// CHECK: the merchant the vault collects for; `authority` signs for it
+    pub recipient: UncheckedAccount<'info>,
+
+    /// The recipient, or its multisig's vault
+    pub authority: Signer<'info>,
+
+    /// CHECK: the vault's token account, pinned by `has_one`
+    #[account(mut)]
+    pub token_account: UncheckedAccount<'info>,
+
+    /// CHECK: one of the vault's approved destinations, checked in the
+    /// handler
+    #[account(mut)]
+    pub destination: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClosePayoutChange<'info> {
+    #[account(
+        mut,
+        seeds = [b"payout_change", recipient.key().as_ref()],
+        bump = payout_change.bump,
+        has_one = recipient,
+        close = recipient
+    )]
+    pub payout_change: Account<'info, PayoutChange>,
+
+    /// CHECK: the recipient's `MerchantMultisig` address, which may be empty;
+    /// read by `check_merchant_authority`
+    #[account(seeds = [b"merchant_multisig", recipient.key().as_ref()], bump)]
+    pub merchant_multisig: UncheckedAccount<'info>,
+
+    /// CHECK: gets the rent back; `authority` signs for it
+    #[account(mut)]
+    pub recipient: UncheckedAccount<'info>,
+
+    /// The recipient, or its multisig's vault
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateChargeThread<'info> {
+    #[account(
+        seeds = [
+            b"subscription",
+            subscription.authority.as_ref(),
+            subscription.recipient.as_ref(),
+        ],
+        bump = subscription.bump,
+        has_one = authority
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: created by the thread program; the handler checks it is the
+    /// subscription's thread for the given id
+    #[account(mut)]
+    pub thread: UncheckedAccount<'info>,
+
+    /// CHECK: pinned to the Clockwork thread program
+    #[account(address = CLOCKWORK_THREAD_PROGRAM_ID)]
+    pub thread_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct QueueChargeTask<'info> {
+    #[account(
+        seeds = [
+            b"subscription",
+            subscription.authority.as_ref(),
+            subscription.recipient.as_ref(),
+        ],
+        bump = subscription.bump,
+        has_one = recipient
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    pub recipient: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: the recipient's queue authority PDA; signs the CPI only
+    #[account(seeds = [b"queue_authority", recipient.key().as_ref()], bump)]
+    pub queue_authority: UncheckedAccount<'info>,
+
+    /// CHECK: tuktuk's record that `queue_authority` may use the queue;
+    /// checked by tuktuk
+    pub task_queue_authority: UncheckedAccount<'info>,
+
+    /// CHECK: checked by tuktuk
+    #[account(mut, owner = TUKTUK_PROGRAM_ID)]
+    pub task_queue: UncheckedAccount<'info>,
+
+    /// CHECK: created by tuktuk; the handler checks it is the queue's task
+    /// for the given id
+    #[account(mut)]
+    pub task: UncheckedAccount<'info>,
+
+    /// CHECK: pinned to the tuktuk program
+    #[account(address = TUKTUK_PROGRAM_ID)]
+    pub tuktuk_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateSubscription<'info> {
+    /// CHECK: a legacy-layout subscription; the handler checks its
+    /// discriminator and rewrites it
+    #[account(mut, owner = crate::ID)]
+    pub subscription: UncheckedAccount<'info>,
+
+    /// CHECK: the subscription's `PlanSubscription` address, which may hold
+    /// nothing; only read to tell whether the subscription is on a plan
+    #[account(seeds = [b"plan_subscription", subscription.key().as_ref()], bump)]
+    pub plan_subscription: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterChargeFunction<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + ChargeFunction::INIT_SPACE,
+        seeds = [b"charge_function", recipient.key().as_ref()],
+        bump
+    )]
+    pub charge_function: Account<'info, ChargeFunction>,
+
+    pub recipient: Signer<'info>,
+
+    /// CHECK: a Switchboard Function account, parsed in the handler
+    #[account(owner = SWITCHBOARD_ATTESTATION_PROGRAM_ID)]
+    pub function: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseChargeFunction<'info> {
+    #[account(
+        mut,
+        seeds = [b"charge_function", recipient.key().as_ref()],
+        bump = charge_function.bump,
+        has_one = recipient,
+        close = recipient
+    )]
+    pub charge_function: Account<'info, ChargeFunction>,
+
+    #[account(mut)]
+    pub recipient: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ChargeSubscriptionAttested<'info> {
+    pub charge: ChargeSubscription<'info>,
+
+    #[account(
+        seeds = [b"charge_function", charge.subscription.recipient.as_ref()],
+        bump = charge_function.bump,
+        has_one = function
+    )]
+    pub charge_function: Account<'info, ChargeFunction>,
+
+    /// CHECK: the registered Switchboard Function, parsed in the handler
+    #[account(owner = SWITCHBOARD_ATTESTATION_PROGRAM_ID)]
+    pub function: UncheckedAccount<'info>,
+
+    pub enclave_signer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateKeeperLease<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + KeeperLease::INIT_SPACE,
+        seeds = [b"keeper_lease", group.key().as_ref()],
+        bump
+    )]
+    pub lease: Account<'info, KeeperLease>,
+
+    pub group: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateKeeperLease<'info> {
+    #[account(
+        mut,
+        seeds = [b"keeper_lease", group.key().as_ref()],
+        bump = lease.bump,
+        has_one = group
+    )]
+    pub lease: Account<'info, KeeperLease>,
+
+    pub group: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseKeeperLease<'info> {
+    #[account(
+        mut,
+        seeds = [b"keeper_lease", group.key().as_ref()],
+        bump = lease.bump,
+        has_one = group,
+        close = group
+    )]
+    pub lease: Account<'info, KeeperLease>,
+
+    #[account(mut)]
+    pub group: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcquireKeeperLease<'info> {
+    #[account(
+        mut,
+        seeds = [b"keeper_lease", lease.group.as_ref()],
+        bump = lease.bump
+    )]
+    pub lease: Account<'info, KeeperLease>,
+
+    pub keeper: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CheckKeeperLease<'info> {
+    #[account(seeds = [b"keeper_lease", lease.group.as_ref()], bump = lease.bump)]
+    pub lease: Account<'info, KeeperLease>,
+
+    pub keeper: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateFundingSources<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + FundingSources::INIT_SPACE,
+        seeds = [b"funding", subscription.key().as_ref()],
+        bump
+    )]
+    pub funding_sources: Account<'info, FundingSources>,
+
+    #[account(
+        seeds = [
+            b"subscription",
+            subscription.authority.as_ref(),
+            subscription.recipient.as_ref(),
+        ],
+        bump = subscription.bump,
+        has_one = authority
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateFundingSources<'info> {
+    #[account(
+        mut,
+        seeds = [b"funding", subscription.key().as_ref()],
+        bump = funding_sources.bump,
+        has_one = subscription
+    )]
+    pub funding_sources: Account<'info, FundingSources>,
+
+    #[account(
+        seeds = [
+            b"subscription",
+            subscription.authority.as_ref(),
+            subscription.recipient.as_ref(),
+        ],
+        bump = subscription.bump,
+        has_one = authority
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseFundingSources<'info> {
+    #[account(
+        mut,
+        seeds = [b"funding", funding_sources.subscription.as_ref()],
+        bump = funding_sources.bump,
+        has_one = authority,
+        close = authority
+    )]
+    pub funding_sources: Account<'info, FundingSources>,
+
+    #[account(mut)]
     pub authority: Signer<'info>,
 }
 
-#[derive(Accounts)]
-pub struct UpdateSubscription<'info> {
-    #[account(
-        mut,
-        seeds = [
-            b"subscription",
-            subscription.authority.as_ref(),
-            subscription.recipient.as_ref(),  // ← FIXED: Added () after as_ref
+/// Discriminator of the current `Subscription` layout: Anchor's for
+/// `SubscriptionV5`, so it cannot be mistaken for an older one
+pub const SUBSCRIPTION_DISCRIMINATOR: &[u8] = &[50, 255, 185, 59, 15, 31, 57, 178];
+
+/// Discriminator of [`SubscriptionV4`], Anchor's for `SubscriptionV4`
+pub const SUBSCRIPTION_V4_DISCRIMINATOR: &[u8] = &[184, 123, 127, 89, 178, 2, 40, 11];
+
+/// Discriminator of [`SubscriptionV3`], Anchor's for `SubscriptionV3`
+pub const SUBSCRIPTION_V3_DISCRIMINATOR: &[u8] = &[14, 43, 225, 79, 6, 27, 88, 238];
+
+/// Discriminator of [`SubscriptionV2`], Anchor's for `SubscriptionV2`
+pub const SUBSCRIPTION_V2_DISCRIMINATOR: &[u8] = &[86, 65, 12, 166, 144, 147, 252, 224];
+
+/// Discriminator of [`LegacySubscription`], Anchor's default for `Subscription`
+pub const LEGACY_SUBSCRIPTION_DISCRIMINATOR: &[u8] = &[64, 7, 26, 135, 102, 132, 98, 33];
+
+/// The fields `getProgramAccounts` and Geyser filters use most come first,
+/// and the one variable-size field comes last, so every field sits at the
+/// same offset in every account; see the `*_OFFSET` constants.
+#[account(discriminator = SUBSCRIPTION_DISCRIMINATOR)]
+#[derive(InitSpace)]
+pub struct Subscription {
+    pub is_active: bool,
+    pub recipient: Pubkey,
+    /// Kept at one interval after `last_charge_timestamp`, so keepers can
+    /// find due subscriptions without decoding them
+    pub next_charge_at: i64,
+    pub authority: Pubkey,
+    pub user_token_account: Pubkey,
+    pub recipient_token_account: Pubkey,
+    pub token_mint: Pubkey,
+    pub amount_per_period: u64,
+    /// The billing [`Interval`]: a positive number of seconds, or minus a
+    /// number of calendar months
+    pub interval_seconds: i64,
+    pub last_charge_timestamp: i64,
+    pub created_at: i64,
+    pub total_charged: u64,
+    pub bump: u8,
+    /// Whether the subscription bills at a plan. Every charge then needs its
+    /// `PlanSubscription`, which holds the plan's price schedule.
+    pub on_plan: bool,
+    /// The most `total_charged` may reach, or
+    /// [`Subscription::NO_SPEND_LIMIT`]; see `set_spend_limit`
+    pub max_total_spend: u64,
+    pub expires_at: Option<i64>,
+}
+
+/// The layout before `max_total_spend`, with the active bit and the expiry
+/// packed as in [`SubscriptionV3`]; read only by `migrate_subscription`
+#[account(discriminator = SUBSCRIPTION_V4_DISCRIMINATOR)]
+#[derive(InitSpace)]
+pub struct SubscriptionV4 {
+    pub flags: u8,
+    pub recipient: Pubkey,
+    pub next_charge_at: i64,
+    pub authority: Pubkey,
+    pub user_token_account: Pubkey,
+    pub recipient_token_account: Pubkey,
+    pub token_mint: Pubkey,
+    pub amount_per_period: u64,
+    pub interval_seconds: i64,
+    pub last_charge_timestamp: i64,
+    pub created_at: i64,
+    pub total_charged: u64,
+    pub bump: u8,
+    pub expires_at: i64,
+    pub on_plan: bool,
+}
+
+/// The layout before `on_plan`. Bit 0 of `flags` is the active bit, and an
+/// `expires_at` of `i64::MAX` means no expiry. Read only by
+/// `migrate_subscription`.
+#[account(discriminator = SUBSCRIPTION_V3_DISCRIMINATOR)]
+#[derive(InitSpace)]
+pub struct SubscriptionV3 {
+    pub flags: u8,
+    pub recipient: Pubkey,
+    pub next_charge_at: i64,
+    pub authority: Pubkey,
+    pub user_token_account: Pubkey,
+    pub recipient_token_account: Pubkey,
+    pub token_mint: Pubkey,
+    pub amount_per_period: u64,
+    pub interval_seconds: i64,
+    pub last_charge_timestamp: i64,
+    pub created_at: i64,
+    pub total_charged: u64,
+    pub bump: u8,
+    pub expires_at: i64,
+}
+
+/// The layout from `next_charge_at` until the flags and expiry were packed;
+/// read only by `migrate_subscription`
+#[account(discriminator = SUBSCRIPTION_V2_DISCRIMINATOR)]
+#[derive(InitSpace)]
+pub struct SubscriptionV2 {
+    pub is_active: bool,
+    pub recipient: Pubkey,
+    pub next_charge_at: i64,
+    pub authority: Pubkey,
+    pub user_token_account: Pubkey,
+    pub recipient_token_account: Pubkey,
+    pub token_mint: Pubkey,
+    pub amount_per_period: u64,
+    pub interval_seconds: i64,
+    pub last_charge_timestamp: i64,
+    pub created_at: i64,
+    pub total_charged: u64,
+    pub bump: u8,
+    pub expires_at: Option<i64>,
+}
+
+/// The layout subscriptions were created with before `next_charge_at` and
+/// the fixed offsets; read only by `migrate_subscription`
+#[account(discriminator = LEGACY_SUBSCRIPTION_DISCRIMINATOR)]
+#[derive(InitSpace)]
+pub struct LegacySubscription {
+    pub authority: Pubkey,
+    pub recipient: Pubkey,
+    pub user_token_account: Pubkey,
+    pub recipient_token_account: Pubkey,
+    pub token_mint: Pubkey,
+    pub amount_per_period: u64,
+    pub interval_seconds: i64,
+    pub last_charge_timestamp: i64,
+    pub created_at: i64,
+    pub expires_at: Option<i64>,
+    pub is_active: bool,
+    pub total_charged: u64,
+    pub bump: u8,
+}
+
+/// `is_active` and `expires_at` from the packed fields of
+/// [`SubscriptionV3`] and [`SubscriptionV4`]
+fn unpack_active_and_expiry(flags: u8, expires_at: i64) -> (bool, Option<i64>) {
+    (flags & 1 != 0, (expires_at != i64::MAX).then_some(expires_at))
+}
+
+impl From<SubscriptionV4> for Subscription {
+    fn from(v4: SubscriptionV4) -> Self {
+        // A limit set before was only held by the delegation, so none is
+        // stored
+        let (is_active, expires_at) = unpack_active_and_expiry(v4.flags, v4.expires_at);
+        Subscription {
+            is_active,
+            recipient: v4.recipient,
+            next_charge_at: v4.next_charge_at,
+            authority: v4.authority,
+            user_token_account: v4.user_token_account,
+            recipient_token_account: v4.recipient_token_account,
+            token_mint: v4.token_mint,
+            amount_per_period: v4.amount_per_period,
+            interval_seconds: v4.interval_seconds,
+            last_charge_timestamp: v4.last_charge_timestamp,
+            created_at: v4.created_at,
+            total_charged: v4.total_charged,
+            bump: v4.bump,
+            on_plan: v4.on_plan,
+            max_total_spend: Subscription::NO_SPEND_LIMIT,
+            expires_at,
+        }
+    }
+}
+
+impl SubscriptionV3 {
+    /// The subscription in the current layout, `on_plan` if it has a
+    /// `PlanSubscription`
+    pub fn migrate(self, on_plan: bool) -> Subscription {
+        let (is_active, expires_at) = unpack_active_and_expiry(self.flags, self.expires_at);
+        Subscription {
+            is_active,
+            recipient: self.recipient,
+            next_charge_at: self.next_charge_at,
+            authority: self.authority,
+            user_token_account: self.user_token_account,
+            recipient_token_account: self.recipient_token_account,
+            token_mint: self.token_mint,
+            amount_per_period: self.amount_per_period,
+            interval_seconds: self.interval_seconds,
+            last_charge_timestamp: self.last_charge_timestamp,
+            created_at: self.created_at,
+            total_charged: self.total_charged,
+            bump: self.bump,
+            on_plan,
+            max_total_spend: Subscription::NO_SPEND_LIMIT,
+            expires_at,
+        }
+    }
+}
+
+impl From<SubscriptionV2> for Subscription {
+    fn from(v2: SubscriptionV2) -> Self {
+        Subscription {
+            is_active: v2.is_active,
+            recipient: v2.recipient,
+            next_charge_at: v2.next_charge_at,
+            authority: v2.authority,
+            user_token_account: v2.user_token_account,
+            recipient_token_account: v2.recipient_token_account,
+            token_mint: v2.token_mint,
+            amount_per_period: v2.amount_per_period,
+            interval_seconds: v2.interval_seconds,
+            last_charge_timestamp: v2.last_charge_timestamp,
+            created_at: v2.created_at,
+            total_charged: v2.total_charged,
+            bump: v2.bump,
+            on_plan: false,
+            max_total_spend: Subscription::NO_SPEND_LIMIT,
+            expires_at: v2.expires_at,
+        }
+    }
+}
+
+impl From<LegacySubscription> for Subscription {
+    fn from(legacy: LegacySubscription) -> Self {
+        let mut subscription = Subscription {
+            is_active: legacy.is_active,
+            recipient: legacy.recipient,
+            next_charge_at: 0,
+            authority: legacy.authority,
+            user_token_account: legacy.user_token_account,
+            recipient_token_account: legacy.recipient_token_account,
+            token_mint: legacy.token_mint,
+            amount_per_period: legacy.amount_per_period,
+            interval_seconds: legacy.interval_seconds,
+            last_charge_timestamp: legacy.last_charge_timestamp,
+            created_at: legacy.created_at,
+            total_charged: legacy.total_charged,
+            bump: legacy.bump,
+            on_plan: false,
+            max_total_spend: Subscription::NO_SPEND_LIMIT,
+            expires_at: legacy.expires_at,
+        };
+        subscription.schedule_next_charge();
+        subscription
+    }
+}
+
+impl Subscription {
+    /// `max_total_spend` of a subscription without a spend limit
+    pub const NO_SPEND_LIMIT: u64 = u64::MAX;
+
+    /// Byte offset of `is_active`, discriminator included
+    pub const IS_ACTIVE_OFFSET: usize = 8;
+    /// Byte offset of `recipient`, discriminator included
+    pub const RECIPIENT_OFFSET: usize = 9;
+    /// Byte offset of `next_charge_at` (little-endian i64), discriminator
+    /// included
+    pub const NEXT_CHARGE_AT_OFFSET: usize = 41;
+    /// Byte offset of `authority`, discriminator included
+    pub const AUTHORITY_OFFSET: usize = 49;
+    /// Byte offset of `on_plan`, discriminator included
+    pub const ON_PLAN_OFFSET: usize = 218;
+
+    /// What the subscription may still charge under its spend limit
+    pub fn spend_left(&self) -> u64 {
+        self.max_total_spend.saturating_sub(self.total_charged)
+    }
+
+    /// The billing interval; `None` if `interval_seconds` holds none
+    pub fn interval(&self) -> Option<Interval> {
+        Interval::from_seconds_field(self.interval_seconds)
+    }
+
+    /// When the period paid at `paid_at` runs out; `None` if the interval
+    /// is invalid or the date is past `i64::MAX`
+    pub fn period_end(&self, paid_at: i64) -> Option<i64> {
+        self.interval()?.advance(paid_at, self.created_at)
+    }
+
+    /// Recompute `next_charge_at` after the schedule changed
+    pub fn schedule_next_charge(&mut self) {
+        self.next_charge_at = self
+            .period_end(self.last_charge_timestamp)
+            .unwrap_or(i64::MAX);
+    }
+
+    /// Whether a charge may be taken at `now`
+    pub fn check_chargeable(&self, now: i64) -> Result<()> {
+        assert_active_subscription(self, now)?;
+
+        let interval = self.interval().ok_or(ErrorCode::InvalidInterval)?;
+        // A period ending past i64::MAX is just "not yet due"
+        let due = interval.advance(self.last_charge_timestamp, self.created_at);
+        require!(due.is_some_and(|due| now >= due), ErrorCode::IntervalNotMet);
+
+        Ok(())
+    }
+
+    /// Whether the subscription at `address` can still move one period out
+    /// of `token_account` as its delegate
+    pub fn is_delegated(
+        &self,
+        address: &Pubkey,
+        token_account: &spl_token::state::Account,
+    ) -> bool {
+        token_account.delegate == COption::Some(*address)
+            && token_account.delegated_amount >= self.amount_per_period
+    }
+
+    /// What a charge takes from a token account holding `balance`: the
+    /// period amount, or with `allow_partial` whatever is left if that is
+    /// less. An empty account is still charged in full, so the transfer fails
+    /// rather than booking a period for nothing.
+    pub fn charge_amount(&self, balance: u64, allow_partial: bool) -> u64 {
+        if allow_partial && balance > 0 {
+            balance.min(self.amount_per_period)
+        } else {
+            self.amount_per_period
+        }
+    }
+
+    /// Periods one charge at `now` settles: with catch-up (`max_periods`
+    /// above 1), every full interval since the last charge up to
+    /// `max_periods`; otherwise just the current one
+    pub fn due_periods(&self, now: i64, max_periods: u8) -> u64 {
+        let mut periods = 0;
+        let mut paid_to = self.last_charge_timestamp;
+        while periods < max_periods as u64 {
+            match self.period_end(paid_to) {
+                Some(due) if due <= now => paid_to = due,
+                _ => break,
+            }
+            periods += 1;
+        }
+        periods.max(1)
+    }
+
+    /// Amount taken for each of `periods`, in order. With `allow_partial`
+    /// they are paid out of `balance` until it runs out, and the last one
+    /// may come up short; see [`Subscription::charge_amount`]. Without it,
+    /// only as many periods as `balance` pays in full are taken, the rest
+    /// staying due, and at least one, so an empty account still fails.
+    pub fn period_charges(&self, periods: u64, balance: u64, allow_partial: bool) -> Vec<u64> {
+        let periods = match balance.checked_div(self.amount_per_period) {
+            Some(affordable) if !allow_partial => periods.min(affordable.max(1)),
+            _ => periods,
+        };
+        let mut charges = Vec::new();
+        let mut left = balance;
+        for _ in 0..periods {
+            if allow_partial && left == 0 && !charges.is_empty() {
+                break;
+            }
+            let charge = self.charge_amount(left, allow_partial);
+            charges.push(charge);
+            left = left.saturating_sub(charge);
+            if charge < self.amount_per_period {
+                break;
+            }
+        }
+        charges
+    }
+
+    /// Book `charges`, one per period, taken at `now`. Without catch-up the
+    /// schedule moves to `now`, as it always has. With it, it moves one
+    /// interval per period settled, so periods past the cap stay due.
+    pub fn record_periods(&mut self, now: i64, charges: &[u64], catch_up: bool) -> Result<()> {
+        for charge in charges {
+            let paid_at = if catch_up {
+                self.period_end(self.last_charge_timestamp)
+                    .ok_or(ErrorCode::ArithmeticOverflow)?
+            } else {
+                now
+            };
+            self.record_payment(paid_at, *charge)?;
+        }
+        Ok(())
+    }
+
+    /// The period the next charge settles, named by when it falls due;
+    /// `i64::MAX` if it never does. Charges must take periods in strictly
+    /// increasing order of this index.
+    pub fn period_index(&self) -> i64 {
+        self.period_end(self.last_charge_timestamp)
+            .unwrap_or(i64::MAX)
+    }
+
+    /// [`Subscription::record_periods`] for a charge at `now`, holding the
+    /// idempotency invariant every charge instruction relies on: the period
+    /// it settles must be due, and booking must move `period_index` past
+    /// it. Keepers racing on the same period are serialized by the account
+    /// lock, so the second sees the first's booking and fails; with
+    /// catch-up it can only settle later periods still due.
+    pub fn book_periods(&mut self, now: i64, charges: &[u64], catch_up: bool) -> Result<()> {
+        let period = self.period_index();
+        require!(period <= now, ErrorCode::IntervalNotMet);
+        self.record_periods(now, charges, catch_up)?;
+        require!(
+            self.period_index() > period,
+            ErrorCode::PeriodAlreadyCharged
+        );
+        Ok(())
+    }
+
+    /// Book one period's payment taken at `now`
+    pub fn record_charge(&mut self, now: i64) -> Result<()> {
+        self.record_payment(now, self.amount_per_period)
+    }
+
+    /// Book the period taken at `now` as settled by `amount`, which is less
+    /// than `amount_per_period` for a partial charge
+    pub fn record_payment(&mut self, now: i64, amount: u64) -> Result<()> {
+        self.total_charged = self
+            .total_charged
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.last_charge_timestamp = now;
+        self.schedule_next_charge();
+        Ok(())
+    }
+}
+
+pub const SECONDS_PER_DAY: i64 = 86_400;
+
+/// How often a subscription is billed. Stored in `interval_seconds`, so the
+/// account layout is unchanged: a fixed length as that many seconds, a
+/// calendar interval as minus its number of months, a cron schedule as its
+/// tagged bits (see [`CronSchedule`]). Calendar intervals fall on the
+/// subscription's day of the month (UTC), or the month's last day when it is
+/// shorter, instead of drifting the way "30 days" does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interval {
+    Daily,
+    Weekly,
+    Monthly,
+    Quarterly,
+    Yearly,
+    CustomSeconds(i64),
+    Cron(CronSchedule),
+}
+
+impl Interval {
+    /// The value stored in `interval_seconds`
+    pub fn to_seconds_field(self) -> i64 {
+        match self {
+            Interval::Daily => SECONDS_PER_DAY,
+            Interval::Weekly => 7 * SECONDS_PER_DAY,
+            Interval::Monthly => -1,
+            Interval::Quarterly => -3,
+            Interval::Yearly => -12,
+            Interval::CustomSeconds(seconds) => seconds,
+            Interval::Cron(schedule) => schedule.to_bits().map_or(0, |bits| bits as i64),
+        }
+    }
+
+    /// Read `interval_seconds`: zero, month counts other than 1, 3 and 12 and
+    /// invalid schedules are not intervals
+    pub fn from_seconds_field(value: i64) -> Option<Self> {
+        match value {
+            bits if bits as u64 & CronSchedule::TAG_MASK == CronSchedule::TAG => {
+                CronSchedule::from_bits(bits as u64).map(Interval::Cron)
+            }
+            SECONDS_PER_DAY => Some(Interval::Daily),
+            604_800 => Some(Interval::Weekly),
+            -1 => Some(Interval::Monthly),
+            -3 => Some(Interval::Quarterly),
+            -12 => Some(Interval::Yearly),
+            seconds if seconds > 0 => Some(Interval::CustomSeconds(seconds)),
+            _ => None,
+        }
+    }
+
+    /// Calendar months in one period; `None` for fixed lengths
+    pub fn months(self) -> Option<i64> {
+        match self.to_seconds_field() {
+            months if months < 0 => Some(-months),
+            _ => None,
+        }
+    }
+
+    /// One period after `from`. Calendar intervals keep `from`'s time of day
+    /// and land on `anchor`'s day of the month, clamped to the month's
+    /// length; a schedule runs next after `from`. `None` past `i64::MAX`.
+    pub fn advance(self, from: i64, anchor: i64) -> Option<i64> {
+        if let Interval::Cron(schedule) = self {
+            return schedule.next_after(from);
+        }
+        let Some(months) = self.months() else {
+            return from.checked_add(self.to_seconds_field());
+        };
+        let (year, month, _) = civil_from_days(from.div_euclid(SECONDS_PER_DAY));
+        let (_, _, anchor_day) = civil_from_days(anchor.div_euclid(SECONDS_PER_DAY));
+
+        let index = year * 12 + (month as i64 - 1) + months;
+        let (year, month) = (index.div_euclid(12), index.rem_euclid(12) as u32 + 1);
+        let day = anchor_day.min(days_in_month(year, month));
+
+        days_from_civil(year, month, day)
+            .checked_mul(SECONDS_PER_DAY)?
+            .checked_add(from.rem_euclid(SECONDS_PER_DAY))
+    }
+}
+
+/// A compact cron-like schedule: charges fall due at `hour:minute` local time
+/// on matching days. As in cron, when both day masks are restricted a day
+/// matching either one runs. The UTC offset is fixed, so a merchant in a
+/// zone with daylight saving updates it twice a year.
+///
+/// Stored in `interval_seconds` with the top two bits `10`, which no other
+/// interval uses: minute in bits 0-5, hour in 6-10, weekdays in 11-17, days
+/// of the month in 18-48 and the offset in quarter hours plus 48 in 49-55.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CronSchedule {
+    pub minute: u8,
+    pub hour: u8,
+    /// Bit 0 for Sunday through bit 6 for Saturday
+    pub weekdays: u8,
+    /// Bit `d - 1` for day `d`; days a month does not have never run
+    pub days_of_month: u32,
+    /// Local time minus UTC in minutes, a multiple of 15 from -720 to 840
+    pub utc_offset_minutes: i16,
+}
+
+impl CronSchedule {
+    pub const EVERY_WEEKDAY: u8 = 0x7f;
+    pub const EVERY_DAY_OF_MONTH: u32 = 0x7fff_ffff;
+    const TAG: u64 = 0b10 << 62;
+    const TAG_MASK: u64 = 0b11 << 62;
+    /// The longest wait for a matching day: day 31 alone, Aug 31 to Oct 31
+    const MAX_GAP_DAYS: i64 = 62;
+
+    /// The stored bits; `None` if a field is out of range
+    pub fn to_bits(self) -> Option<u64> {
+        let valid = self.minute < 60
+            && self.hour < 24
+            && self.weekdays != 0
+            && self.weekdays <= Self::EVERY_WEEKDAY
+            && self.days_of_month != 0
+            && self.days_of_month <= Self::EVERY_DAY_OF_MONTH
+            && self.utc_offset_minutes % 15 == 0
+            && (-720..=840).contains(&self.utc_offset_minutes);
+        if !valid {
+            return None;
+        }
+        let offset = (self.utc_offset_minutes / 15 + 48) as u64;
+        Some(
+            Self::TAG
+                | self.minute as u64
+                | (self.hour as u64) << 6
+                | (self.weekdays as u64) << 11
+                | (self.days_of_month as u64) << 18
+                | offset << 49,
+        )
+    }
+
+    /// Read stored bits; `None` unless they are exactly what a valid
+    /// schedule encodes to
+    pub fn from_bits(bits: u64) -> Option<Self> {
+        let schedule = CronSchedule {
+            minute: (bits & 0x3f) as u8,
+            hour: ((bits >> 6) & 0x1f) as u8,
+            weekdays: ((bits >> 11) & 0x7f) as u8,
+            days_of_month: ((bits >> 18) & 0x7fff_ffff) as u32,
+            utc_offset_minutes: (((bits >> 49) & 0x7f) as i16 - 48) * 15,
+        };
+        (schedule.to_bits() == Some(bits)).then_some(schedule)
+    }
+
+    /// The first run strictly after `from`
+    pub fn next_after(self, from: i64) -> Option<i64> {
+        let offset = self.utc_offset_minutes as i64 * 60;
+        let local = from.checked_add(offset)?;
+        let time_of_day = self.hour as i64 * 3_600 + self.minute as i64 * 60;
+        let today = local.div_euclid(SECONDS_PER_DAY);
+        for day in today..=today.checked_add(Self::MAX_GAP_DAYS)? {
+            let runs_at = day.checked_mul(SECONDS_PER_DAY)?.checked_add(time_of_day)?;
+            if runs_at > local && self.runs_on(day) {
+                return runs_at.checked_sub(offset);
+            }
+        }
+        None
+    }
+
+    /// Whether the schedule runs on the local date `day` days after 1970-01-01
+    fn runs_on(self, day: i64) -> bool {
+        let (_, _, day_of_month) = civil_from_days(day);
+        // 1970-01-01 was a Thursday
+        let weekday = (day + 4).rem_euclid(7);
+        let on_day_of_month = self.days_of_month & (1 << (day_of_month - 1)) != 0;
+        let on_weekday = self.weekdays & (1 << weekday) != 0;
+        if self.days_of_month == Self::EVERY_DAY_OF_MONTH {
+            on_weekday
+        } else if self.weekdays == Self::EVERY_WEEKDAY {
+            on_day_of_month
+        } else {
+            on_day_of_month || on_weekday
+        }
+    }
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = month as i64;
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Year, month and day of the date `days` after 1970-01-01
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = era * 400 + year_of_era + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// A recipient's settings for charges against its subscriptions
+#[account]
+#[derive(InitSpace)]
+pub struct MerchantConfig {
+    pub recipient: Pubkey,
+    pub allow_partial_charges: bool,
+    /// Missed periods one charge may settle; 0 and 1 both mean no catch-up
+    pub max_periods_per_charge: u8,
+    pub bump: u8,
+}
+
+/// Read `account` as the `MerchantConfig` of `recipient`; it comes in through
+/// remaining accounts, so the owner, type and address are all checked here
+fn load_merchant_config(account: &AccountInfo, recipient: &Pubkey) -> Result<MerchantConfig> {
+    require_keys_eq!(*account.owner, crate::ID, ErrorCode::InvalidMerchantConfig);
+    let config = MerchantConfig::try_deserialize(&mut &account.try_borrow_data()?[..])
+        .map_err(|_| ErrorCode::InvalidMerchantConfig)?;
+    let address = Pubkey::create_program_address(
+        &[b"merchant_config", recipient.as_ref(), &[config.bump]],
+        &crate::ID,
+    )
+    .map_err(|_| ErrorCode::InvalidMerchantConfig)?;
+    require_keys_eq!(account.key(), address, ErrorCode::InvalidMerchantConfig);
+    Ok(config)
+}
+
+/// The Squads multisig whose vault controls a recipient's merchant settings
+#[account]
+#[derive(InitSpace)]
+pub struct MerchantMultisig {
+    pub recipient: Pubkey,
+    pub multisig: Pubkey,
+    pub vault_index: u8,
+    /// `vault_index` of `multisig`, the only signer settings changes accept
+    pub vault: Pubkey,
+    pub bump: u8,
+}
+
+/// A Squads v4 vault: `["multisig", multisig, "vault", index]`
+pub fn squads_vault_address(multisig: &Pubkey, vault_index: u8) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"multisig", multisig.as_ref(), b"vault", &[vault_index]],
+        &SQUADS_PROGRAM_ID,
+    )
+    .0
+}
+
+/// Whether `account` is a Squads v4 multisig, not another Squads account
+pub fn is_squads_multisig(account: &AccountInfo) -> bool {
+    *account.owner == SQUADS_PROGRAM_ID
+        && account
+            .try_borrow_data()
+            .is_ok_and(|data| data.starts_with(&SQUADS_MULTISIG_DISCRIMINATOR))
+}
+
+/// Whether `authority` may change the merchant settings of `recipient`:
+/// the multisig's vault if `merchant_multisig` holds the recipient's
+/// `MerchantMultisig`, otherwise the recipient itself. The caller checks
+/// that `merchant_multisig` is at the recipient's address.
+pub fn check_merchant_authority(
+    merchant_multisig: &AccountInfo,
+    recipient: &Pubkey,
+    authority: &Pubkey,
+) -> Result<()> {
+    if merchant_multisig.owner != &crate::ID {
+        require_keys_eq!(*authority, *recipient, ErrorCode::NotMerchantAuthority);
+        return Ok(());
+    }
+    let record = MerchantMultisig::try_deserialize(&mut &merchant_multisig.try_borrow_data()?[..])?;
+    require_keys_eq!(
+        *authority,
+        record.vault,
+        ErrorCode::MultisigApprovalRequired
+    );
+    Ok(())
+}
+
+/// How long a new payout account waits before subscriptions can move to it
+pub const PAYOUT_TIMELOCK_SECONDS: i64 = 2 * SECONDS_PER_DAY;
+
+/// A recipient's next payout account, waiting out the timelock
+#[account]
+#[derive(InitSpace)]
+pub struct PayoutChange {
+    pub recipient: Pubkey,
+    pub token_account: Pubkey,
+    /// From then on `apply_recipient_token_account` moves subscriptions
+    pub effective_at: i64,
+    pub bump: u8,
+}
+
+impl PayoutChange {
+    pub fn is_effective(&self, now: i64) -> bool {
+        now >= self.effective_at
+    }
+}
+
+/// How long a platform fee, treasury or admin change waits before it can be
+/// applied
+pub const PLATFORM_TIMELOCK_SECONDS: i64 = 2 * SECONDS_PER_DAY;
+
+/// Highest platform fee, 10%
+pub const MAX_PLATFORM_FEE_BPS: u16 = 1_000;
+
+/// The platform's fee terms and who governs them, one per deployment. The
+/// program records them for merchants and integrators; it takes no fee
+/// itself.
+#[account]
+#[derive(InitSpace)]
+pub struct PlatformConfig {
+    /// Queues and cancels config changes
+    pub admin: Pubkey,
+    /// Fee on merchant revenue, in basis points
+    pub fee_bps: u16,
+    /// Wallet the fee is paid to
+    pub treasury: Pubkey,
+    pub bump: u8,
+}
+
+impl PlatformConfig {
+    pub fn check_fee_bps(fee_bps: u16) -> Result<()> {
+        require!(
+            fee_bps <= MAX_PLATFORM_FEE_BPS,
+            ErrorCode::InvalidPlatformFee
+        );
+        Ok(())
+    }
+}
+
+/// A platform fee, treasury or admin change waiting out
+/// `PLATFORM_TIMELOCK_SECONDS`; `None` leaves that part as it is
+#[account]
+#[derive(InitSpace)]
+pub struct PlatformConfigChange {
+    pub fee_bps: Option<u16>,
+    pub treasury: Option<Pubkey>,
+    pub admin: Option<Pubkey>,
+    /// From then on `apply_platform_config_change` applies it
+    pub effective_at: i64,
+    pub bump: u8,
+}
+
+impl PlatformConfigChange {
+    pub fn is_effective(&self, now: i64) -> bool {
+        now >= self.effective_at
+    }
+}
+
+/// A recipient's revenue vault. Its token account is owned by this PDA, so
+/// charges paid into it are held by the program until the merchant
+/// withdraws them.
+#[account]
+#[derive(InitSpace)]
+pub struct MerchantVault {
+    pub recipient: Pubkey,
+    pub token_account: Pubkey,
+    pub token_mint: Pubkey,
+    pub total_withdrawn: u64,
+    /// Revenue seen by the last sync: balance plus everything withdrawn
+    pub total_received: u64,
+    /// Share of new revenue held back, in basis points
+    pub holdback_bps: u16,
+    /// How long held revenue waits before it can be withdrawn
+    pub holdback_days: u16,
+    /// Held revenue not yet released, oldest first
+    #[max_len(MAX_REVENUE_HOLDS)]
+    pub holds: Vec<RevenueHold>,
+    /// Most the merchant authority can withdraw per period; 0 for no limit
+    pub withdrawal_limit: u64,
+    pub withdrawal_period_seconds: i64,
+    /// Recovery key that can lift the limit; default while there is none
+    pub withdrawal_admin: Pubkey,
+    pub period_started_at: i64,
+    pub withdrawn_in_period: u64,
+    /// Where `withdraw_revenue` may pay, each from its `effective_at`
+    #[max_len(MAX_WITHDRAWAL_DESTINATIONS)]
+    pub destinations: Vec<WithdrawalDestination>,
+    pub bump: u8,
+}
+
+/// Basis points in 100%
+pub const MAX_BPS: u16 = 10_000;
+
+/// Longest a merchant can hold back revenue
+pub const MAX_HOLDBACK_DAYS: u16 = 180;
+
+/// Holds a vault tracks at once; past that, new revenue joins the newest
+pub const MAX_REVENUE_HOLDS: usize = 16;
+
+/// Approved withdrawal destinations per vault
+pub const MAX_WITHDRAWAL_DESTINATIONS: usize = 4;
+
+/// A token account `withdraw_revenue` may pay from `effective_at` on
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, InitSpace)]
+pub struct WithdrawalDestination {
+    pub token_account: Pubkey,
+    pub effective_at: i64,
+}
+
+/// Revenue the vault keeps until `release_at`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, InitSpace)]
+pub struct RevenueHold {
+    pub amount: u64,
+    pub release_at: i64,
+}
+
+impl MerchantVault {
+    /// Whether `withdraw_revenue` may pay `token_account` at `now`
+    pub fn is_approved_destination(&self, token_account: &Pubkey, now: i64) -> bool {
+        self.destinations.iter().any(|destination| {
+            destination.token_account == *token_account && destination.effective_at <= now
+        })
+    }
+
+    /// Add a destination that is not on the list yet
+    pub fn add_destination(&mut self, destination: WithdrawalDestination) -> Result<()> {
+        require!(
+            self.destinations
+                .iter()
+                .all(|existing| existing.token_account != destination.token_account),
+            ErrorCode::InvalidWithdrawalDestination
+        );
+        require!(
+            self.destinations.len() < MAX_WITHDRAWAL_DESTINATIONS,
+            ErrorCode::TooManyWithdrawalDestinations
+        );
+        self.destinations.push(destination);
+        Ok(())
+    }
+
+    pub fn has_withdrawal_limit(&self) -> bool {
+        self.withdrawal_limit > 0
+    }
+
+    /// Whether the merchant authority may make this limit change alone:
+    /// setting a first limit, or a lower limit over at least as long a
+    /// period with the same admin
+    pub fn limit_change_tightens(&self, limit: u64, period_seconds: i64, admin: &Pubkey) -> bool {
+        !self.has_withdrawal_limit()
+            || (limit > 0
+                && limit <= self.withdrawal_limit
+                && period_seconds >= self.withdrawal_period_seconds
+                && *admin == self.withdrawal_admin)
+    }
+
+    /// Count `amount` against the current period's limit, starting a new
+    /// period if the last one is over
+    pub fn record_limited_withdrawal(&mut self, amount: u64, now: i64) -> Result<()> {
+        if !self.has_withdrawal_limit() {
+            return Ok(());
+        }
+        if now.saturating_sub(self.period_started_at) >= self.withdrawal_period_seconds {
+            self.period_started_at = now;
+            self.withdrawn_in_period = 0;
+        }
+        let withdrawn = self
+            .withdrawn_in_period
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        require!(
+            withdrawn <= self.withdrawal_limit,
+            ErrorCode::WithdrawalLimitExceeded
+        );
+        self.withdrawn_in_period = withdrawn;
+        Ok(())
+    }
+
+    /// Revenue still held at `now`
+    pub fn held(&self, now: i64) -> u64 {
+        self.holds
+            .iter()
+            .filter(|hold| hold.release_at > now)
+            .fold(0u64, |sum, hold| sum.saturating_add(hold.amount))
+    }
+
+    /// Take `amount` out of the unreleased holds, those releasing soonest
+    /// first, to pay an SLA credit
+    pub fn draw_held(&mut self, amount: u64, now: i64) -> Result<()> {
+        require!(self.held(now) >= amount, ErrorCode::InsufficientHeldRevenue);
+        let mut remaining = amount;
+        for hold in self.holds.iter_mut().filter(|hold| hold.release_at > now) {
+            let taken = remaining.min(hold.amount);
+            hold.amount -= taken;
+            remaining -= taken;
+        }
+        self.holds
+            .retain(|hold| hold.amount > 0 && hold.release_at > now);
+        Ok(())
+    }
+
+    /// What the merchant can withdraw from a vault holding `balance`
+    pub fn withdrawable(&self, balance: u64, now: i64) -> u64 {
+        balance.saturating_sub(self.held(now))
+    }
+
+    /// Drop the holds released by `now`, then hold back the configured
+    /// share of whatever arrived since the last sync. Only withdrawals move
+    /// tokens out, so the balance plus everything withdrawn is everything
+    /// received. Returns the new hold, if any.
+    pub fn sync(&mut self, balance: u64, now: i64) -> Result<Option<RevenueHold>> {
+        self.holds.retain(|hold| hold.release_at > now);
+
+        let received = balance
+            .checked_add(self.total_withdrawn)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        let new_revenue = received.saturating_sub(self.total_received);
+        self.total_received = self.total_received.max(received);
+
+        let amount = (new_revenue as u128 * self.holdback_bps as u128 / MAX_BPS as u128) as u64;
+        if amount == 0 || self.holdback_days == 0 {
+            return Ok(None);
+        }
+        let hold = RevenueHold {
+            amount,
+            release_at: now
+                .checked_add(self.holdback_days as i64 * SECONDS_PER_DAY)
+                .ok_or(ErrorCode::ArithmeticOverflow)?,
+        };
+
+        // Full: fold into the newest hold, which then waits as long as
+        // this one. Revenue can be held longer, never shorter.
+        if self.holds.len() < MAX_REVENUE_HOLDS {
+            self.holds.push(hold);
+        } else if let Some(last) = self.holds.last_mut() {
+            last.amount = last.amount.saturating_add(hold.amount);
+            last.release_at = last.release_at.max(hold.release_at);
+        }
+
+        Ok(Some(hold))
+    }
+
+    /// Whether `token_account` can hold revenue for the vault at `vault`:
+    /// only the vault PDA may move or close its tokens
+    pub fn check_token_account(
+        vault: &Pubkey,
+        token_account: &spl_token::state::Account,
+    ) -> Result<()> {
+        require_keys_eq!(
+            token_account.owner,
+            *vault,
+            ErrorCode::InvalidVaultTokenAccount
+        );
+        require!(
+            token_account.delegate.is_none() && token_account.close_authority.is_none(),
+            ErrorCode::InvalidVaultTokenAccount
+        );
+        Ok(())
+    }
+}
+
+/// Notice a merchant gives before its SLA commitment ends
+pub const SLA_NOTICE_SECONDS: i64 = 30 * SECONDS_PER_DAY;
+
+/// Largest multiple of the outage's share of a period an SLA can credit
+pub const MAX_SLA_CREDIT_MULTIPLIER: u16 = 100;
+
+/// A merchant's promise to credit subscribers for attested downtime out
+/// of its held-back revenue
+#[account]
+#[derive(InitSpace)]
+pub struct SlaCommitment {
+    pub recipient: Pubkey,
+    /// Platform admin or oracle whose attestations count
+    pub attester: Pubkey,
+    /// Credit per outage, as a multiple of its share of the period
+    pub credit_multiplier: u16,
+    pub committed_at: i64,
+    /// `i64::MAX` until the merchant gives notice
+    pub ends_at: i64,
+    pub bump: u8,
+}
+
+impl SlaCommitment {
+    /// Whether an outage from `started_at` to `ended_at` can be attested at
+    /// `now`: over, and started while the commitment held
+    pub fn covers(&self, started_at: i64, ended_at: i64, now: i64) -> bool {
+        started_at < ended_at
+            && ended_at <= now
+            && started_at >= self.committed_at
+            && started_at < self.ends_at
+    }
+
+    /// What `subscription` is owed for an outage from `started_at` to
+    /// `ended_at`: the part it was subscribed for, as a share of its
+    /// current period, times the multiplier, capped at one period
+    pub fn credit_for(&self, subscription: &Subscription, started_at: i64, ended_at: i64) -> u64 {
+        let overlap = ended_at.saturating_sub(started_at.max(subscription.created_at));
+        let period = subscription
+            .period_end(subscription.last_charge_timestamp)
+            .map(|end| end.saturating_sub(subscription.last_charge_timestamp))
+            .unwrap_or_default();
+        if overlap <= 0 || period <= 0 {
+            return 0;
+        }
+        let credit = subscription.amount_per_period as u128
+            * overlap as u128
+            * self.credit_multiplier as u128
+            / period as u128;
+        credit.min(subscription.amount_per_period as u128) as u64
+    }
+}
+
+/// An outage of a recipient's service, posted by its SLA attester
+#[account]
+#[derive(InitSpace)]
+pub struct DowntimeAttestation {
+    pub recipient: Pubkey,
+    pub attester: Pubkey,
+    pub started_at: i64,
+    pub ended_at: i64,
+    pub bump: u8,
+}
+
+/// Receipt of the credit one subscription got for one outage
+#[account]
+#[derive(InitSpace)]
+pub struct SlaCredit {
+    pub attestation: Pubkey,
+    pub subscription: Pubkey,
+    pub amount: u64,
+    /// Part of `amount` still to come off the subscription's charges; zero
+    /// for a credit paid out by `credit_sla`
+    pub pending: u64,
+    pub bump: u8,
+}
+
+/// Oldest cached price a USD-priced charge accepts
+pub const MAX_PRICE_AGE_SECONDS: i64 = 300;
+
+/// Decimals of a USD amount, as in USDC
+pub const USD_DECIMALS: u32 = 6;
+
+/// The last price of a Pyth feed, copied from a `PriceUpdateV2` by
+/// `refresh_price_cache`; the price is `price` x 10^`exponent` USD
+#[account]
+#[derive(InitSpace)]
+pub struct PriceCache {
+    pub feed_id: [u8; 32],
+    pub price: i64,
+    pub conf: u64,
+    pub exponent: i32,
+    /// When Pyth published the price; zero until the first refresh
+    pub publish_time: i64,
+    pub bump: u8,
+}
+
+/// A subscription's price in USD, charged in its token at the price in
+/// `price_cache`
+#[account]
+#[derive(InitSpace)]
+pub struct UsdPeg {
+    pub subscription: Pubkey,
+    pub price_cache: Pubkey,
+    /// USD per period, with [`USD_DECIMALS`] decimals
+    pub usd_per_period: u64,
+    pub token_decimals: u8,
+    pub bump: u8,
+}
+
+impl UsdPeg {
+    /// `usd_per_period` in the token's base units at the cached price,
+    /// rounded up so the merchant gets at least the USD price. Fails if the
+    /// price is older than `MAX_PRICE_AGE_SECONDS` at `now`, or not positive.
+    pub fn token_amount(&self, cache: &PriceCache, now: i64) -> Result<u64> {
+        require!(
+            cache.price > 0 && now.saturating_sub(cache.publish_time) <= MAX_PRICE_AGE_SECONDS,
+            ErrorCode::StalePrice
+        );
+        // usd / 10^6 = tokens / 10^decimals * price * 10^exponent
+        let scale = self.token_decimals as i64 - cache.exponent as i64 - USD_DECIMALS as i64;
+        let power = |exponent: i64| {
+            u32::try_from(exponent)
+                .ok()
+                .and_then(|exponent| 10u128.checked_pow(exponent))
+        };
+        let (numerator, denominator) = if scale >= 0 {
+            (
+                power(scale).and_then(|power| (self.usd_per_period as u128).checked_mul(power)),
+                Some(cache.price as u128),
+            )
+        } else {
+            (
+                Some(self.usd_per_period as u128),
+                power(-scale).and_then(|power| (cache.price as u128).checked_mul(power)),
+            )
+        };
+        let amount = numerator
+            .zip(denominator)
+            .map(|(numerator, denominator)| numerator.div_ceil(denominator))
+            .and_then(|amount| u64::try_from(amount).ok())
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(amount)
+    }
+}
+
+/// A mint a recipient takes payment in besides its subscriptions' own, and
+/// the account that receives it
+#[account]
+#[derive(InitSpace)]
+pub struct AcceptedMint {
+    pub recipient: Pubkey,
+    pub token_mint: Pubkey,
+    pub token_account: Pubkey,
+    pub bump: u8,
+}
+
+/// A wallet a recipient has banned from opening subscriptions to it
+#[account]
+#[derive(InitSpace)]
+pub struct DenylistEntry {
+    pub recipient: Pubkey,
+    pub wallet: Pubkey,
+    pub denied_at: i64,
+    pub bump: u8,
+}
+
+impl DenylistEntry {
+    /// Whether the wallet whose entry is at `entry` may subscribe. Only this
+    /// program can put data there, and only while the merchant bans it.
+    pub fn check_allowed(entry: &AccountInfo) -> Result<()> {
+        require!(entry.data_is_empty(), ErrorCode::SubscriberDenied);
+        Ok(())
+    }
+}
+
+/// Marks a recipient invite-only: while it exists, only wallets with an
+/// `AllowlistEntry` can open a subscription to the recipient
+#[account]
+#[derive(InitSpace)]
+pub struct SubscriberAllowlist {
+    pub recipient: Pubkey,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl SubscriberAllowlist {
+    /// Whether the wallet whose entry is at `entry` may subscribe to the
+    /// recipient whose allowlist is at `allowlist`. As with the denylist,
+    /// only this program can put data at either address.
+    pub fn check_invited(allowlist: &AccountInfo, entry: &AccountInfo) -> Result<()> {
+        require!(
+            allowlist.data_is_empty() || !entry.data_is_empty(),
+            ErrorCode::SubscriberNotAllowlisted
+        );
+        Ok(())
+    }
+}
+
+/// A wallet a recipient has invited to subscribe while it is invite-only
+#[account]
+#[derive(InitSpace)]
+pub struct AllowlistEntry {
+    pub recipient: Pubkey,
+    pub wallet: Pubkey,
+    pub allowed_at: i64,
+    pub bump: u8,
+}
+
+/// A subscription's account in an accepted mint, charged when its primary
+/// account cannot pay
+#[account]
+#[derive(InitSpace)]
+pub struct FallbackPayment {
+    pub subscription: Pubkey,
+    pub token_mint: Pubkey,
+    pub token_account: Pubkey,
+    pub bump: u8,
+}
+
+impl FallbackPayment {
+    /// Check a fallback account of `accepted`'s mint for `subscription`: the
+    /// authority's own, in another mint than the subscription's with as
+    /// many decimals, so one period costs the same number of base units
+    pub fn check(
+        subscription: &Subscription,
+        accepted: &AcceptedMint,
+        token_account: &spl_token::state::Account,
+        decimals: u8,
+        fallback_decimals: u8,
+    ) -> Result<()> {
+        require!(
+            token_account.mint == accepted.token_mint
+                && token_account.mint != subscription.token_mint
+                && token_account.owner == subscription.authority
+                && decimals == fallback_decimals,
+            ErrorCode::InvalidFallbackPayment
+        );
+        Ok(())
+    }
+}
+
+/// The one-time fee a subscription paid when it opened, kept out of its
+/// `total_charged`
+#[account]
+#[derive(InitSpace)]
+pub struct SetupFee {
+    pub subscription: Pubkey,
+    pub amount: u64,
+    pub paid_at: i64,
+    pub bump: u8,
+}
+
+/// A deposit paid on top of the recurring price, held in `token_account`
+/// until the subscription is cancelled
+#[account]
+#[derive(InitSpace)]
+pub struct SubscriptionDeposit {
+    pub subscription: Pubkey,
+    pub authority: Pubkey,
+    pub recipient: Pubkey,
+    /// Escrow owned by this PDA
+    pub token_account: Pubkey,
+    /// Where the refund goes: the token account it was paid from
+    pub refund_account: Pubkey,
+    pub amount: u64,
+    pub paid_at: i64,
+    pub bump: u8,
+}
+
+impl SubscriptionDeposit {
+    /// Whether `token_account` can escrow a deposit of `token_mint` for the
+    /// deposit at `deposit`: only the deposit PDA may move or close it
+    pub fn check_escrow(
+        deposit: &Pubkey,
+        token_mint: &Pubkey,
+        token_account: &spl_token::state::Account,
+    ) -> Result<()> {
+        require!(
+            token_account.owner == *deposit
+                && token_account.mint == *token_mint
+                && token_account.delegate.is_none()
+                && token_account.close_authority.is_none(),
+            ErrorCode::InvalidDepositAccount
+        );
+        Ok(())
+    }
+
+    /// Whether the subscription at `subscription` is cancelled: closed,
+    /// compacted to a tombstone, or deactivated
+    pub fn check_refundable(subscription: &AccountInfo) -> Result<()> {
+        if subscription.owner != &crate::ID || subscription.data_is_empty() {
+            return Ok(());
+        }
+        let data = subscription.try_borrow_data()?;
+        if data.starts_with(SubscriptionTombstone::DISCRIMINATOR) {
+            return Ok(());
+        }
+        let subscription =
+            Subscription::try_deserialize(&mut &data[..]).map_err(|_| ErrorCode::DepositLocked)?;
+        require!(!subscription.is_active, ErrorCode::DepositLocked);
+        Ok(())
+    }
+}
+
+/// How often a plan subscription is billed
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub enum BillingCadence {
+    Monthly,
+    Annual,
+}
+
+impl BillingCadence {
+    pub fn interval(self) -> Interval {
+        match self {
+            BillingCadence::Monthly => Interval::Monthly,
+            BillingCadence::Annual => Interval::Yearly,
+        }
+    }
+}
+
+/// A merchant's plan, priced per month in `token_mint`. Subscribers billed
+/// annually pay twelve months less `annual_discount_bps`.
+#[account]
+#[derive(InitSpace)]
+pub struct Plan {
+    pub recipient: Pubkey,
+    pub plan_id: u64,
+    pub token_mint: Pubkey,
+    pub monthly_price: u64,
+    pub annual_discount_bps: u16,
+    /// Usage each period includes; zero for a plan that is not metered
+    pub included_units: u64,
+    /// Periods in a row over `included_units` before moving to `next_tier`
+    pub upgrade_after_periods: u8,
+    pub next_tier: Option<Pubkey>,
+    /// Price of each unit beyond `included_units`; zero for none
+    pub overage_rate: u64,
+    /// Most subscriptions the plan takes at once; zero for no limit
+    pub max_subscribers: u32,
+    /// Subscriptions holding a seat on the plan
+    pub subscribers: u32,
+    /// Off the price of a new subscriber's first `intro_periods`
+    pub intro_discount_bps: u16,
+    pub intro_periods: u8,
+    /// Prices of a new subscriber's first periods, in order, before
+    /// `monthly_price`
+    #[max_len(MAX_RAMP_STEPS)]
+    pub price_ramp: Vec<RampStep>,
+    pub bump: u8,
+}
+
+/// Most steps a plan's price ramp has
+pub const MAX_RAMP_STEPS: usize = 4;
+
+/// `periods` of a plan's price ramp billed at `monthly_price`, which annual
+/// billing prices as it does the plan's own
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub struct RampStep {
+    pub periods: u8,
+    pub monthly_price: u64,
+}
+
+/// A plan subscription's price up to the period indexed `until`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub struct PriceStep {
+    pub until: i64,
+    pub amount_per_period: u64,
+}
+
+/// Most steps a plan subscription's price schedule has: each of the
+/// ramp's, and the end of an intro discount that outlasts it
+pub const MAX_PRICE_STEPS: usize = MAX_RAMP_STEPS + 1;
+
+/// How long a user has to veto a scheduled tier upgrade
+pub const TIER_UPGRADE_VETO_SECONDS: i64 = 3 * SECONDS_PER_DAY;
+
+impl Plan {
+    /// Count one more subscription on the plan, if it has room
+    pub fn take_seat(&mut self) -> Result<()> {
+        require!(
+            self.max_subscribers == 0 || self.subscribers < self.max_subscribers,
+            ErrorCode::PlanFull
+        );
+        self.subscribers = self
+            .subscribers
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+
+    pub fn free_seat(&mut self) {
+        self.subscribers = self.subscribers.saturating_sub(1);
+    }
+
+    /// Twelve monthly prices less the annual discount, rounded down
+    pub fn annual_price(&self) -> Result<u64> {
+        self.cadence_price(self.monthly_price, BillingCadence::Annual)
+    }
+
+    pub fn price(&self, cadence: BillingCadence) -> Result<u64> {
+        self.cadence_price(self.monthly_price, cadence)
+    }
+
+    /// `monthly_price` billed at `cadence`, annually less the discount
+    pub fn cadence_price(&self, monthly_price: u64, cadence: BillingCadence) -> Result<u64> {
+        match cadence {
+            BillingCadence::Monthly => Ok(monthly_price),
+            BillingCadence::Annual => {
+                let full = monthly_price as u128 * 12;
+                let price = full * (MAX_BPS - self.annual_discount_bps) as u128 / MAX_BPS as u128;
+                u64::try_from(price).map_err(|_| ErrorCode::ArithmeticOverflow.into())
+            }
+        }
+    }
+
+    /// What a subscriber pays for its `period`th period on the plan, counted
+    /// from zero: the price ramp's step it falls in, or the plan's price
+    /// after it, less the intro discount over the first `intro_periods`
+    pub fn period_price(&self, cadence: BillingCadence, period: u32) -> Result<u64> {
+        let mut monthly_price = self.monthly_price;
+        let mut start = 0u32;
+        for step in &self.price_ramp {
+            if period < start + step.periods as u32 {
+                monthly_price = step.monthly_price;
+                break;
+            }
+            start += step.periods as u32;
+        }
+        let price = self.cadence_price(monthly_price, cadence)?;
+        if period >= self.intro_periods as u32 {
+            return Ok(price);
+        }
+        Ok((price as u128 * (MAX_BPS - self.intro_discount_bps) as u128 / MAX_BPS as u128) as u64)
+    }
+
+    /// Periods priced apart from the plan's own price
+    pub fn scheduled_periods(&self) -> u32 {
+        let ramp = self
+            .price_ramp
+            .iter()
+            .map(|step| step.periods as u32)
+            .sum::<u32>();
+        if self.intro_discount_bps == 0 {
+            return ramp;
+        }
+        ramp.max(self.intro_periods as u32)
+    }
+
+    /// Bill `subscription`, just put on this plan at `cadence`, at the price
+    /// of its first period after the `paid` it has already paid, and return
+    /// the schedule of the prices that follow, up to the plan's own
+    pub fn start_schedule(
+        &self,
+        subscription: &mut Subscription,
+        cadence: BillingCadence,
+        paid: u32,
+    ) -> Result<Vec<PriceStep>> {
+        subscription.amount_per_period = self.period_price(cadence, paid)?;
+        let mut steps: Vec<PriceStep> = Vec::new();
+        let mut index = subscription.period_index();
+        for period in paid..self.scheduled_periods() {
+            let amount_per_period = self.period_price(cadence, period)?;
+            index = subscription
+                .period_end(index)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            match steps.last_mut() {
+                Some(step) if step.amount_per_period == amount_per_period => step.until = index,
+                _ => steps.push(PriceStep {
+                    until: index,
+                    amount_per_period,
+                }),
+            }
+        }
+        Ok(steps)
+    }
+
+    /// Bill `subscription` at this plan's `cadence` from its next charge
+    pub fn apply(&self, subscription: &mut Subscription, cadence: BillingCadence) -> Result<()> {
+        subscription.amount_per_period = self.price(cadence)?;
+        subscription.interval_seconds = cadence.interval().to_seconds_field();
+        subscription.schedule_next_charge();
+        Ok(())
+    }
+
+    /// Move `subscription` from `from` to `to`, prorating the period it is
+    /// in, and return what it owes now, already added to `total_charged`.
+    ///
+    /// Going annual, the year runs from the start of the month paid last,
+    /// whose price counts towards it. Going monthly, the year already paid
+    /// is used up first: `last_charge_timestamp` moves to the start of its
+    /// last month, so the first monthly charge falls when the year ends.
+    /// Both keep the calendar anchor, so no fraction of a period is priced.
+    pub fn switch_cadence(
+        &self,
+        subscription: &mut Subscription,
+        from: BillingCadence,
+        to: BillingCadence,
+    ) -> Result<u64> {
+        require!(from != to, ErrorCode::CadenceUnchanged);
+        let due = match to {
+            BillingCadence::Annual => self
+                .annual_price()?
+                .saturating_sub(subscription.amount_per_period),
+            BillingCadence::Monthly => {
+                let mut paid_to = subscription.last_charge_timestamp;
+                for _ in 1..12 {
+                    paid_to = Interval::Monthly
+                        .advance(paid_to, subscription.created_at)
+                        .ok_or(ErrorCode::ArithmeticOverflow)?;
+                }
+                subscription.last_charge_timestamp = paid_to;
+                0
+            }
+        };
+        subscription.total_charged = subscription
+            .total_charged
+            .checked_add(due)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.apply(subscription, to)?;
+        Ok(due)
+    }
+}
+
+/// A subscription billed at a `Plan`, how often, and its metered usage
+#[account]
+#[derive(InitSpace)]
+pub struct PlanSubscription {
+    pub subscription: Pubkey,
+    pub plan: Pubkey,
+    pub cadence: BillingCadence,
+    /// Start of the period `units` were used in
+    pub period_start: i64,
+    pub units: u64,
+    /// Closed periods in a row that went over the plan's included units
+    pub periods_over: u8,
+    /// When a scheduled tier upgrade can be applied
+    pub upgrade_at: Option<i64>,
+    /// Units of this period billed by `charge_overage`
+    pub overage_billed: u64,
+    /// Everything `charge_overage` has taken, kept out of `total_charged`
+    pub overage_charged: u64,
+    /// What is left of the plan's price schedule, in order; charges are at
+    /// `full_price` past it
+    #[max_len(MAX_PRICE_STEPS)]
+    pub price_steps: Vec<PriceStep>,
+    pub full_price: u64,
+    pub bump: u8,
+}
+
+impl PlanSubscription {
+    /// The price of the period indexed `index`
+    pub fn price_at(&self, index: i64) -> u64 {
+        self.price_steps
+            .iter()
+            .find(|step| index < step.until)
+            .map_or(self.full_price, |step| step.amount_per_period)
+    }
+
+    /// Drop the steps the period `subscription` charges next is past, and
+    /// bill it at the price of the step it is in. Returns whether it moved
+    /// to another step.
+    pub fn apply_price(&mut self, subscription: &mut Subscription) -> bool {
+        let index = subscription.period_index();
+        let passed = self
+            .price_steps
+            .iter()
+            .take_while(|step| index >= step.until)
+            .count();
+        if passed == 0 {
+            return false;
+        }
+        self.price_steps.drain(..passed);
+        subscription.amount_per_period = self.price_at(index);
+        true
+    }
+
+    /// How many of the next `periods` are at the price of the step the
+    /// first is in, so a catch-up charge does not take the next step's
+    /// periods at it
+    pub fn periods_at_price(&self, subscription: &Subscription, periods: u64) -> u64 {
+        let Some(step) = self.price_steps.first() else {
+            return periods;
+        };
+        let mut index = subscription.period_index();
+        let mut at_price = 0;
+        while at_price < periods && index < step.until {
+            at_price += 1;
+            match subscription.period_end(index) {
+                Some(next) => index = next,
+                None => break,
+            }
+        }
+        at_price.max(1)
+    }
+
+    /// Add `units` to the period starting at `period_start`, the
+    /// subscription's last charge. The first usage of a new period closes
+    /// the one before, counting it towards an upgrade if it went over the
+    /// plan's included units. Returns when an upgrade this schedules can
+    /// be applied.
+    pub fn record_usage(
+        &mut self,
+        plan: &Plan,
+        period_start: i64,
+        units: u64,
+        now: i64,
+    ) -> Result<Option<i64>> {
+        require!(plan.included_units > 0, ErrorCode::PlanNotMetered);
+        if period_start != self.period_start {
+            self.periods_over = if self.units > plan.included_units {
+                self.periods_over.saturating_add(1)
+            } else {
+                0
+            };
+            self.period_start = period_start;
+            self.units = 0;
+            self.overage_billed = 0;
+        }
+        self.units = self
+            .units
+            .checked_add(units)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        if self.upgrade_at.is_some()
+            || plan.next_tier.is_none()
+            || self.periods_over < plan.upgrade_after_periods
+        {
+            return Ok(None);
+        }
+        let upgrade_at = now
+            .checked_add(TIER_UPGRADE_VETO_SECONDS)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.upgrade_at = Some(upgrade_at);
+        Ok(Some(upgrade_at))
+    }
+
+    /// Book `units` of this period's usage beyond the plan's allotment as
+    /// billed and return their price. Units already billed cannot be
+    /// billed again.
+    pub fn bill_overage(&mut self, plan: &Plan, units: u64) -> Result<u64> {
+        require!(units > 0, ErrorCode::InvalidAmount);
+        require!(plan.included_units > 0, ErrorCode::PlanNotMetered);
+        require!(plan.overage_rate > 0, ErrorCode::NoOverageRate);
+        let billable = self
+            .units
+            .saturating_sub(plan.included_units)
+            .saturating_sub(self.overage_billed);
+        require!(units <= billable, ErrorCode::OverageExceedsUsage);
+
+        let amount = units
+            .checked_mul(plan.overage_rate)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.overage_billed += units;
+        self.overage_charged = self
+            .overage_charged
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(amount)
+    }
+}
+
+/// Spend milestones a subscription can alert on
+pub const MAX_SPEND_ALERTS: usize = 8;
+
+/// A plan's queue of users waiting for a seat, oldest first. Entries live
+/// at positions `head` to `tail`; a left entry leaves a gap there.
+#[account]
+#[derive(InitSpace)]
+pub struct Waitlist {
+    pub plan: Pubkey,
+    /// Position of the oldest entry not yet promoted or skipped
+    pub head: u64,
+    /// Position the next entry takes
+    pub tail: u64,
+    pub bump: u8,
+}
+
+impl Waitlist {
+    /// Users queue while `plan` is full, and behind anyone already waiting
+    pub fn check_joinable(&self, plan: &Plan) -> Result<()> {
+        let full = plan.max_subscribers > 0 && plan.subscribers >= plan.max_subscribers;
+        require!(full || self.head < self.tail, ErrorCode::PlanNotFull);
+        Ok(())
+    }
+
+    /// Fail while the plan at the waitlist's address `waitlist` has users
+    /// waiting, so a seat that opens goes to them first
+    pub fn check_no_queue(waitlist: &AccountInfo) -> Result<()> {
+        if waitlist.data_is_empty() {
+            return Ok(());
+        }
+        let waitlist = Waitlist::try_deserialize(&mut &waitlist.try_borrow_data()?[..])?;
+        require!(waitlist.head == waitlist.tail, ErrorCode::PlanFull);
+        Ok(())
+    }
+}
+
+/// A user waiting for a seat on a plan. Its lamports include the rent of
+/// the subscription and plan subscription promotion creates.
+#[account]
+#[derive(InitSpace)]
+pub struct WaitlistEntry {
+    pub plan: Pubkey,
+    pub position: u64,
+    pub authority: Pubkey,
+    /// Paid for the entry, and gets its lamports back if it is left or
+    /// skipped
+    pub payer: Pubkey,
+    pub user_token_account: Pubkey,
+    pub recipient_token_account: Pubkey,
+    pub cadence: BillingCadence,
+    pub joined_at: i64,
+    pub bump: u8,
+}
+
+impl WaitlistEntry {
+    /// Check the accounts `skip_waitlist_entry` reads for this entry of a
+    /// plan of `recipient`
+    pub fn check_accounts(
+        &self,
+        recipient: &Pubkey,
+        subscription: &Pubkey,
+        denylist_entry: &Pubkey,
+        allowlist_entry: &Pubkey,
+        user_token_account: &Pubkey,
+        rent_receiver: &Pubkey,
+    ) -> Result<()> {
+        let authority = self.authority.as_ref();
+        let recipient = recipient.as_ref();
+        let expected = [
+            (&[b"subscription".as_slice(), authority, recipient], subscription),
+            (&[b"denylist".as_slice(), recipient, authority], denylist_entry),
+            (&[b"allowlist".as_slice(), recipient, authority], allowlist_entry),
+        ];
+        for (seeds, address) in expected {
+            require_keys_eq!(
+                Pubkey::find_program_address(seeds, &crate::ID).0,
+                *address,
+                anchor_lang::error::ErrorCode::ConstraintSeeds
+            );
+        }
+        require_keys_eq!(
+            *user_token_account,
+            self.user_token_account,
+            anchor_lang::error::ErrorCode::ConstraintHasOne
+        );
+        require_keys_eq!(
+            *rent_receiver,
+            self.payer,
+            anchor_lang::error::ErrorCode::ConstraintHasOne
+        );
+        Ok(())
+    }
+}
+
+/// Whether `token_account` lets the subscription PDA `subscription` take
+/// `amount`: delegated to it for at least that much and holding it
+pub fn can_pay_first_period(token_account: &AccountInfo, subscription: &Pubkey, amount: u64) -> bool {
+    if !holds_delegation(token_account, subscription) {
+        return false;
+    }
+    let Ok(data) = token_account.try_borrow_data() else {
+        return false;
+    };
+    spl_token::state::Account::unpack(&data)
+        .is_ok_and(|account| account.delegated_amount >= amount && account.amount >= amount)
+}
+
+/// Milestones of a subscription's `total_charged`, passed to its charges
+/// as the last remaining account
+#[account]
+#[derive(InitSpace)]
+pub struct SpendAlerts {
+    pub subscription: Pubkey,
+    pub authority: Pubkey,
+    /// Nonzero and ascending
+    #[max_len(MAX_SPEND_ALERTS)]
+    pub thresholds: Vec<u64>,
+    pub bump: u8,
+}
+
+impl SpendAlerts {
+    /// At most [`MAX_SPEND_ALERTS`] thresholds, nonzero and strictly
+    /// ascending
+    pub fn check(thresholds: &[u64]) -> Result<()> {
+        require!(
+            thresholds.len() <= MAX_SPEND_ALERTS
+                && thresholds.first() != Some(&0)
+                && thresholds.windows(2).all(|pair| pair[0] < pair[1]),
+            ErrorCode::InvalidSpendAlerts
+        );
+        Ok(())
+    }
+
+    /// The thresholds a total going from `before` to `after` passes
+    pub fn crossed(&self, before: u64, after: u64) -> impl Iterator<Item = u64> + '_ {
+        self.thresholds
+            .iter()
+            .copied()
+            .filter(move |threshold| before < *threshold && *threshold <= after)
+    }
+}
+
+/// The fields of a Pyth `PriceUpdateV2` the price cache copies. Decoded by
+/// offset so the program does not pull in the Pyth SDK; only fully
+/// verified updates are accepted, which fixes the offsets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PythPriceUpdate {
+    pub feed_id: [u8; 32],
+    pub price: i64,
+    pub conf: u64,
+    pub exponent: i32,
+    pub publish_time: i64,
+}
+
+impl PythPriceUpdate {
+    pub const DISCRIMINATOR: [u8; 8] = [34, 241, 35, 99, 157, 126, 244, 205];
+    /// Discriminator, then the write authority
+    pub const VERIFICATION_LEVEL_OFFSET: usize = 8 + 32;
+    /// Borsh tag of `VerificationLevel::Full`
+    pub const FULL_VERIFICATION: u8 = 1;
+    pub const FEED_ID_OFFSET: usize = Self::VERIFICATION_LEVEL_OFFSET + 1;
+    /// Through `publish_time`; the EMA fields and the posted slot follow
+    pub const LEN: usize = Self::FEED_ID_OFFSET + 32 + 8 + 8 + 4 + 8;
+
+    pub fn load(info: &AccountInfo) -> Result<Self> {
+        require_keys_eq!(
+            *info.owner,
+            PYTH_RECEIVER_PROGRAM_ID,
+            ErrorCode::InvalidPriceUpdate
+        );
+        Self::parse(&info.try_borrow_data()?)
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        require!(
+            data.len() >= Self::LEN
+                && data[..8] == Self::DISCRIMINATOR
+                && data[Self::VERIFICATION_LEVEL_OFFSET] == Self::FULL_VERIFICATION,
+            ErrorCode::InvalidPriceUpdate
+        );
+        let field = |offset: usize, len: usize| &data[Self::FEED_ID_OFFSET + offset..][..len];
+        Ok(Self {
+            feed_id: field(0, 32).try_into().unwrap(),
+            price: i64::from_le_bytes(field(32, 8).try_into().unwrap()),
+            conf: u64::from_le_bytes(field(40, 8).try_into().unwrap()),
+            exponent: i32::from_le_bytes(field(48, 4).try_into().unwrap()),
+            publish_time: i64::from_le_bytes(field(52, 8).try_into().unwrap()),
+        })
+    }
+}
+
+/// What is left of a deactivated subscription after `compact_subscription`:
+/// who paid whom, and how much, at the subscription's address
+#[account]
+#[derive(InitSpace)]
+pub struct SubscriptionTombstone {
+    pub authority: Pubkey,
+    pub recipient: Pubkey,
+    pub token_mint: Pubkey,
+    pub created_at: i64,
+    pub last_charge_timestamp: i64,
+    pub total_charged: u64,
+    pub bump: u8,
+}
+
+impl From<&Subscription> for SubscriptionTombstone {
+    fn from(subscription: &Subscription) -> Self {
+        Self {
+            authority: subscription.authority,
+            recipient: subscription.recipient,
+            token_mint: subscription.token_mint,
+            created_at: subscription.created_at,
+            last_charge_timestamp: subscription.last_charge_timestamp,
+            total_charged: subscription.total_charged,
+            bump: subscription.bump,
+        }
+    }
+}
+
+/// Clockwork's `SerializableInstruction`: an instruction a thread sends.
+/// Clockwork's types are mirrored here so the recipe does not pull in the
+/// Clockwork SDK.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct ThreadInstruction {
+    pub program_id: Pubkey,
+    pub accounts: Vec<ThreadAccount>,
+    pub data: Vec<u8>,
+}
+
+/// Clockwork's `SerializableAccount`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct ThreadAccount {
+    pub pubkey: Pubkey,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+/// The leading variants of Clockwork's `Trigger`, in its order, so the
+/// Borsh variant index matches
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub enum ThreadTrigger {
+    Account {
+        address: Pubkey,
+        offset: u64,
+        size: u64,
+    },
+    Cron {
+        schedule: String,
+        skippable: bool,
+    },
+}
+
+/// Anchor discriminator of Clockwork's `thread_create`
+pub const THREAD_CREATE_DISCRIMINATOR: [u8; 8] = [54, 1, 238, 224, 71, 244, 252, 173];
+
+/// Thread PDA of `authority` with `id`, as Clockwork derives it
+pub fn charge_thread_address(authority: &Pubkey, id: &[u8]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"thread", authority.as_ref(), id],
+        &CLOCKWORK_THREAD_PROGRAM_ID,
+    )
+}
+
+/// `charge_subscription` for the subscription at `subscription_key`, as a
+/// thread sends it. Charging is permissionless, so nothing signs.
+pub fn charge_thread_instruction(
+    subscription_key: &Pubkey,
+    subscription: &Subscription,
+) -> ThreadInstruction {
+    let accounts = accounts::ChargeSubscription {
+        subscription: *subscription_key,
+        user_token_account: subscription.user_token_account,
+        recipient_token_account: subscription.recipient_token_account,
+        token_program: spl_token::ID,
+    };
+    ThreadInstruction {
+        program_id: crate::ID,
+        accounts: accounts
+            .to_account_metas(None)
+            .into_iter()
+            .map(|meta| ThreadAccount {
+                pubkey: meta.pubkey,
+                is_signer: meta.is_signer,
+                is_writable: meta.is_writable,
+            })
+            .collect(),
+        data: instruction::ChargeSubscription {}.data(),
+    }
+}
+
+/// Clockwork's `thread_create(amount, id, instructions, trigger)`, signed by
+/// `authority` and funded by `payer`
+pub fn thread_create_instruction(
+    authority: &Pubkey,
+    payer: &Pubkey,
+    thread: &Pubkey,
+    amount: u64,
+    id: Vec<u8>,
+    instructions: Vec<ThreadInstruction>,
+    trigger: ThreadTrigger,
+) -> Result<Instruction> {
+    let mut data = THREAD_CREATE_DISCRIMINATOR.to_vec();
+    (amount, id, instructions, trigger).serialize(&mut data)?;
+
+    Ok(Instruction {
+        program_id: CLOCKWORK_THREAD_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(anchor_lang::system_program::ID, false),
+            AccountMeta::new(*thread, false),
+        ],
+        data,
+    })
+}
+
+/// The Switchboard Function a recipient lets bill its subscriptions
+#[account]
+#[derive(InitSpace)]
+pub struct ChargeFunction {
+    pub recipient: Pubkey,
+    pub function: Pubkey,
+    pub bump: u8,
+}
+
+/// The field of a Switchboard `FunctionAccountData` an attested charge
+/// needs. Decoded by offset so the program does not pull in the Switchboard
+/// SDK.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SwitchboardFunction {
+    /// Key generated inside the function's enclave and registered when its
+    /// quote was verified; it changes whenever the enclave is re-verified
+    pub enclave_signer: Pubkey,
+}
+
+impl SwitchboardFunction {
+    pub const DISCRIMINATOR: [u8; 8] = [76, 139, 47, 44, 240, 182, 148, 200];
+    /// Discriminator, then status, flags, seeds, name, metadata and the
+    /// creation and update timestamps
+    pub const ENCLAVE_SIGNER_OFFSET: usize = 8 + 1 + 1 + 4 + 1 + 1 + 32 + 64 + 256 + 8 + 8 + 8;
+    pub const LEN: usize = Self::ENCLAVE_SIGNER_OFFSET + 32;
+
+    pub fn load(info: &AccountInfo) -> Result<Self> {
+        require_keys_eq!(
+            *info.owner,
+            SWITCHBOARD_ATTESTATION_PROGRAM_ID,
+            ErrorCode::InvalidAttestation
+        );
+        Self::parse(&info.try_borrow_data()?)
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        require!(
+            data.len() >= Self::LEN && data[..8] == Self::DISCRIMINATOR,
+            ErrorCode::InvalidAttestation
+        );
+        Ok(Self {
+            enclave_signer: Pubkey::try_from(&data[Self::ENCLAVE_SIGNER_OFFSET..Self::LEN])
+                .unwrap(),
+        })
+    }
+}
+
+/// tuktuk's `TriggerV0`. tuktuk's types are mirrored here so the recipe
+/// does not pull in the tuktuk SDK.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub enum TaskTrigger {
+    Now,
+    Timestamp(i64),
+}
+
+/// tuktuk's `CompiledInstructionV0`: indexes into the task's accounts
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct CompiledTaskInstruction {
+    pub program_id_index: u8,
+    pub accounts: Vec<u8>,
+    pub data: Vec<u8>,
+}
+
+/// tuktuk's `CompiledTransactionV0`. Accounts are ordered writable
+/// signers, read-only signers, writable, then read-only, like a message.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct CompiledTask {
+    pub num_rw_signers: u8,
+    pub num_ro_signers: u8,
+    pub num_rw: u8,
+    pub accounts: Vec<Pubkey>,
+    pub instructions: Vec<CompiledTaskInstruction>,
+    pub signer_seeds: Vec<Vec<Vec<u8>>>,
+}
+
+/// The leading variant of tuktuk's `TransactionSourceV0`, so the Borsh
+/// variant index matches
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub enum TaskTransaction {
+    Compiled(CompiledTask),
+}
+
+/// tuktuk's `QueueTaskArgsV0`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct QueueTaskArgs {
+    pub id: u16,
+    pub trigger: TaskTrigger,
+    pub transaction: TaskTransaction,
+    /// `None` pays the queue's minimum reward
+    pub crank_reward: Option<u64>,
+    /// Task accounts the task may fill with follow-up tasks
+    pub free_tasks: u8,
+    pub description: String,
+}
+
+/// tuktuk's `TaskReturnV0`: a follow-up task returned by a running task
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct TaskReturn {
+    pub trigger: TaskTrigger,
+    pub transaction: TaskTransaction,
+    pub crank_reward: Option<u64>,
+    pub free_tasks: u8,
+    pub description: String,
+}
+
+/// tuktuk's `RunTaskReturnV0`, read from the return data of a task's last
+/// instruction
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct RunTaskReturn {
+    pub tasks: Vec<TaskReturn>,
+    pub accounts: Vec<Pubkey>,
+}
+
+/// Anchor discriminator of tuktuk's `queue_task_v0`
+pub const QUEUE_TASK_DISCRIMINATOR: [u8; 8] = [177, 95, 195, 252, 241, 2, 178, 88];
+
+/// Description of every charge task; tuktuk allows up to 40 bytes
+pub const CHARGE_TASK_DESCRIPTION: &str = "subscription charge";
+
+/// Queue authority PDA that signs for `recipient`'s charge tasks
+pub fn queue_authority_address(recipient: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"queue_authority", recipient.as_ref()], &crate::ID)
+}
+
+/// tuktuk's record that `queue_authority` may queue tasks on `task_queue`
+pub fn task_queue_authority_address(task_queue: &Pubkey, queue_authority: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            b"task_queue_authority",
+            task_queue.as_ref(),
+            queue_authority.as_ref(),
+        ],
+        &TUKTUK_PROGRAM_ID,
+    )
+}
+
+/// Task PDA of `task_queue` with `id`, as tuktuk derives it
+pub fn charge_task_address(task_queue: &Pubkey, id: u16) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"task", task_queue.as_ref(), &id.to_le_bytes()],
+        &TUKTUK_PROGRAM_ID,
+    )
+}
+
+/// `charge_subscription_tuktuk` for the subscription at `subscription_key`,
+/// followed by `remaining` for the charge's remaining accounts
+pub fn charge_task_instruction(
+    subscription_key: &Pubkey,
+    subscription: &Subscription,
+    remaining: &[AccountMeta],
+) -> Instruction {
+    let mut accounts = accounts::ChargeSubscription {
+        subscription: *subscription_key,
+        user_token_account: subscription.user_token_account,
+        recipient_token_account: subscription.recipient_token_account,
+        token_program: spl_token::ID,
+    }
+    .to_account_metas(None);
+    accounts.extend_from_slice(remaining);
+    Instruction {
+        program_id: crate::ID,
+        accounts,
+        data: instruction::ChargeSubscriptionTuktuk {}.data(),
+    }
+}
+
+/// Compile `instructions` into a tuktuk task: every account once, in
+/// message order, and each instruction's program and accounts as indexes
+pub fn compile_task(instructions: &[Instruction]) -> CompiledTask {
+    let mut metas: Vec<AccountMeta> = Vec::new();
+    let program_metas = instructions
+        .iter()
+        .map(|ix| AccountMeta::new_readonly(ix.program_id, false));
+    let all_metas = instructions
+        .iter()
+        .flat_map(|ix| ix.accounts.iter().cloned());
+    for meta in all_metas.chain(program_metas) {
+        match metas.iter_mut().find(|known| known.pubkey == meta.pubkey) {
+            Some(known) => {
+                known.is_signer |= meta.is_signer;
+                known.is_writable |= meta.is_writable;
+            }
+            None => metas.push(meta),
+        }
+    }
+    // Stable, so accounts keep their first-seen order within a class
+    metas.sort_by_key(|meta| (!meta.is_signer, !meta.is_writable));
+
+    let count = |is_signer: bool, is_writable: bool| {
+        metas
+            .iter()
+            .filter(|meta| meta.is_signer == is_signer && meta.is_writable == is_writable)
+            .count() as u8
+    };
+    let index = |key: &Pubkey| metas.iter().position(|meta| meta.pubkey == *key).unwrap() as u8;
+
+    CompiledTask {
+        num_rw_signers: count(true, true),
+        num_ro_signers: count(true, false),
+        num_rw: count(false, true),
+        accounts: metas.iter().map(|meta| meta.pubkey).collect(),
+        instructions: instructions
+            .iter()
+            .map(|ix| CompiledTaskInstruction {
+                program_id_index: index(&ix.program_id),
+                accounts: ix.accounts.iter().map(|meta| index(&meta.pubkey)).collect(),
+                data: ix.data.clone(),
+            })
+            .collect(),
+        signer_seeds: Vec::new(),
+    }
+}
+
+/// Remaining accounts of a charge as a task passes them on; none sign
+fn passed_on_metas(accounts: &[AccountInfo]) -> Vec<AccountMeta> {
+    accounts
+        .iter()
+        .map(|account| AccountMeta {
+            pubkey: account.key(),
+            is_signer: false,
+            is_writable: account.is_writable,
+        })
+        .collect()
+}
+
+/// tuktuk's `queue_task_v0(args)`, signed by `queue_authority` and funded by
+/// `payer`
+pub fn queue_task_instruction(
+    payer: &Pubkey,
+    queue_authority: &Pubkey,
+    task_queue: &Pubkey,
+    task: &Pubkey,
+    args: QueueTaskArgs,
+) -> Result<Instruction> {
+    let mut data = QUEUE_TASK_DISCRIMINATOR.to_vec();
+    args.serialize(&mut data)?;
+
+    Ok(Instruction {
+        program_id: TUKTUK_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(*queue_authority, true),
+            AccountMeta::new_readonly(
+                task_queue_authority_address(task_queue, queue_authority).0,
+                false,
+            ),
+            AccountMeta::new(*task_queue, false),
+            AccountMeta::new(*task, false),
+            AccountMeta::new_readonly(anchor_lang::system_program::ID, false),
+        ],
+        data,
+    })
+}
+
+/// Keepers a lease can elect between
+pub const MAX_LEASE_KEEPERS: usize = 8;
+
+/// Longest a lease can be taken for at once; also the longest charges can
+/// stall after a holder dies without releasing it
+pub const MAX_LEASE_SECONDS: i64 = 3600;
+
+/// Which of a group's keepers sends its charges, and until when
+#[account]
+#[derive(InitSpace)]
+pub struct KeeperLease {
+    /// Key the lease is created under; it manages the keeper list
+    pub group: Pubkey,
+    pub holder: Pubkey,
+    /// Bumped whenever the lease changes hands; a fencing token for the
+    /// holder's transactions
+    pub term: u64,
+    pub expires_at: i64,
+    pub bump: u8,
+    #[max_len(MAX_LEASE_KEEPERS)]
+    pub keepers: Vec<Pubkey>,
+}
+
+impl KeeperLease {
+    /// Whether `keeper` holds the lease at `now`
+    pub fn is_held_by(&self, keeper: &Pubkey, now: i64) -> bool {
+        self.holder == *keeper && now < self.expires_at
+    }
+}
+
+fn check_lease_keepers(keepers: &[Pubkey]) -> Result<()> {
+    let distinct = keepers
+        .iter()
+        .enumerate()
+        .all(|(i, keeper)| !keepers[..i].contains(keeper));
+    require!(
+        !keepers.is_empty() && keepers.len() <= MAX_LEASE_KEEPERS && distinct,
+        ErrorCode::InvalidKeeperLease
+    );
+    Ok(())
+}
+
+/// Fallback token accounts a subscription's charges may draw from
+pub const MAX_FUNDING_SOURCES: usize = 4;
+
+/// A subscription's fallback token accounts, in the order charges try them
+#[account]
+#[derive(InitSpace)]
+pub struct FundingSources {
+    pub subscription: Pubkey,
+    pub authority: Pubkey,
+    #[max_len(MAX_FUNDING_SOURCES)]
+    pub token_accounts: Vec<Pubkey>,
+    pub bump: u8,
+}
+
+/// Draw `amount` from sources holding `balances`, in order, each giving what
+/// it has until the amount is covered; `None` if together they fall short
+pub fn split_funding(amount: u64, balances: &[u64]) -> Option<Vec<u64>> {
+    let mut left = amount;
+    let draws = balances
+        .iter()
+        .map(|balance| {
+            let draw = left.min(*balance);
+            left -= draw;
+            draw
+        })
+        .collect();
+    (left == 0).then_some(draws)
+}
+
+/// Check a new fallback list for `subscription`. `accounts` are the same
+/// token accounts, passed as remaining accounts so their state can be read.
+fn check_funding_sources(
+    subscription: &Subscription,
+    token_accounts: &[Pubkey],
+    accounts: &[AccountInfo],
+) -> Result<()> {
+    require!(
+        token_accounts.len() <= MAX_FUNDING_SOURCES,
+        ErrorCode::TooManyFundingSources
+    );
+    require!(
+        accounts.len() == token_accounts.len(),
+        ErrorCode::InvalidFundingSource
+    );
+
+    for (index, (key, account)) in token_accounts.iter().zip(accounts).enumerate() {
+        require_keys_eq!(account.key(), *key, ErrorCode::InvalidFundingSource);
+        require!(
+            *key != subscription.user_token_account && !token_accounts[..index].contains(key),
+            ErrorCode::InvalidFundingSource
+        );
+        require_keys_eq!(
+            *account.owner,
+            spl_token::ID,
+            ErrorCode::InvalidFundingSource
+        );
+        let token_account = spl_token::state::Account::unpack(&account.try_borrow_data()?)
+            .map_err(|_| ErrorCode::InvalidFundingSource)?;
+        require!(
+            token_account.mint == subscription.token_mint
+                && token_account.owner == subscription.authority,
+            ErrorCode::InvalidFundingSource
+        );
+    }
+
+    Ok(())
+}
+
+/// The fallback sources a charge was given: the subscription's
+/// `FundingSources` followed by exactly its token accounts, in order. Each
+/// comes with what it can contribute; one that was closed, frozen, handed
+/// to another owner or not approved to the subscription gives nothing.
+fn fallback_sources<'info>(
+    remaining: &[AccountInfo<'info>],
+    subscription_key: &Pubkey,
+    subscription: &Subscription,
+) -> Result<Vec<(AccountInfo<'info>, u64)>> {
+    let Some((funding, accounts)) = remaining.split_first() else {
+        return Ok(Vec::new());
+    };
+
+    require_keys_eq!(*funding.owner, crate::ID, ErrorCode::InvalidFundingSource);
+    let registered = FundingSources::try_deserialize(&mut &funding.try_borrow_data()?[..])
+        .map_err(|_| ErrorCode::InvalidFundingSource)?;
+    let address = Pubkey::create_program_address(
+        &[b"funding", subscription_key.as_ref(), &[registered.bump]],
+        &crate::ID,
+    )
+    .map_err(|_| ErrorCode::InvalidFundingSource)?;
+    require_keys_eq!(funding.key(), address, ErrorCode::InvalidFundingSource);
+    require!(
+        accounts.len() == registered.token_accounts.len()
+            && accounts
+                .iter()
+                .zip(&registered.token_accounts)
+                .all(|(account, key)| account.key == key),
+        ErrorCode::InvalidFundingSource
+    );
+
+    Ok(accounts
+        .iter()
+        .map(|account| {
+            let balance = fallback_balance(account, subscription_key, subscription);
+            (account.clone(), balance)
+        })
+        .collect())
+}
+
+/// An `SlaCredit` receipt passed to a charge, with its state
+type SlaDiscount<'info> = (AccountInfo<'info>, SlaCredit);
+
+/// Split the `SlaCredit` receipts passed at the end of a charge's remaining
+/// accounts off the rest. Each must be the receipt of an outage for
+/// `subscription`, and writable, since the charge draws its discount down.
+fn sla_discounts<'a, 'info>(
+    remaining: &'a [AccountInfo<'info>],
+    subscription: &Pubkey,
+) -> Result<(&'a [AccountInfo<'info>], Vec<SlaDiscount<'info>>)> {
+    let mut rest = remaining;
+    let mut discounts = Vec::new();
+    while let Some((account, before)) = rest.split_last() {
+        if *account.owner != crate::ID
+            || !account
+                .try_borrow_data()?
+                .starts_with(SlaCredit::DISCRIMINATOR)
+        {
+            break;
+        }
+        let receipt = SlaCredit::try_deserialize(&mut &account.try_borrow_data()?[..])
+            .map_err(|_| ErrorCode::InvalidSlaDiscount)?;
+        let address = Pubkey::create_program_address(
+            &[
+                b"sla_credit",
+                receipt.attestation.as_ref(),
+                subscription.as_ref(),
+                &[receipt.bump],
+            ],
+            &crate::ID,
+        )
+        .map_err(|_| ErrorCode::InvalidSlaDiscount)?;
+        require!(
+            account.key() == address && account.is_writable,
+            ErrorCode::InvalidSlaDiscount
+        );
+        discounts.push((account.clone(), receipt));
+        rest = before;
+    }
+    discounts.reverse();
+    Ok((rest, discounts))
+}
+
+/// Free the plan seat of a subscription being cancelled, if its
+/// `PlanSubscription` and `Plan` are the remaining accounts, and close the
+/// `PlanSubscription` to `rent_receiver`. Without them the seat stays taken
+/// until `release_plan_seat`.
+fn release_plan_seat_of<'info>(
+    remaining: &[AccountInfo<'info>],
+    subscription: &Pubkey,
+    rent_receiver: &AccountInfo<'info>,
+) -> Result<()> {
+    let (plan_subscription_info, plan_info) = match remaining {
+        [] => return Ok(()),
+        [plan_subscription, plan] => (plan_subscription, plan),
+        _ => return err!(ErrorCode::InvalidPlan),
+    };
+    require!(
+        *plan_subscription_info.owner == crate::ID && *plan_info.owner == crate::ID,
+        ErrorCode::InvalidPlan
+    );
+    let plan_subscription =
+        PlanSubscription::try_deserialize(&mut &plan_subscription_info.try_borrow_data()?[..])
+            .map_err(|_| ErrorCode::InvalidPlan)?;
+    let address = Pubkey::create_program_address(
+        &[
+            b"plan_subscription",
+            subscription.as_ref(),
+            &[plan_subscription.bump],
         ],
-        bump = subscription.bump,
-        has_one = authority
-    )]
-    pub subscription: Account<'info, Subscription>,
+        &crate::ID,
+    )
+    .map_err(|_| ErrorCode::InvalidPlan)?;
+    require_keys_eq!(plan_subscription_info.key(), address, ErrorCode::InvalidPlan);
+    require_keys_eq!(plan_info.key(), plan_subscription.plan, ErrorCode::InvalidPlan);
 
-    pub authority: Signer<'info>,
+    let mut plan = Plan::try_deserialize(&mut &plan_info.try_borrow_data()?[..])
+        .map_err(|_| ErrorCode::InvalidPlan)?;
+    plan.free_seat();
+    plan.try_serialize(&mut &mut plan_info.try_borrow_mut_data()?[..])?;
+
+    let lamports = plan_subscription_info.lamports();
+    **plan_subscription_info.try_borrow_mut_lamports()? = 0;
+    **rent_receiver.try_borrow_mut_lamports()? = rent_receiver
+        .lamports()
+        .checked_add(lamports)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    plan_subscription_info.assign(&system_program::ID);
+    plan_subscription_info.resize(0)?;
+
+    emit!(PlanSeatReleased {
+        plan: plan_info.key(),
+        subscription: *subscription,
+        subscribers: plan.subscribers,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
 }
 
-#[account]
-#[derive(InitSpace)]
-pub struct Subscription {
+/// Whether `account` holds a subscription still in force, in the current
+/// layout; a closed, compacted or deactivated one frees its plan seat
+fn holds_active_subscription(account: &AccountInfo) -> Result<bool> {
+    if *account.owner != crate::ID
+        || !account
+            .try_borrow_data()?
+            .starts_with(Subscription::DISCRIMINATOR)
+    {
+        return Ok(false);
+    }
+    let subscription = Subscription::try_deserialize(&mut &account.try_borrow_data()?[..])?;
+    Ok(subscription.is_active)
+}
+
+/// Split a trailing `SpendAlerts` of the subscription off a charge's
+/// remaining accounts. It is only read, so it can be passed read-only.
+fn spend_alerts<'a, 'info>(
+    remaining: &'a [AccountInfo<'info>],
+    subscription: &Pubkey,
+) -> Result<(&'a [AccountInfo<'info>], Option<SpendAlerts>)> {
+    let Some((account, rest)) = remaining.split_last() else {
+        return Ok((remaining, None));
+    };
+    if *account.owner != crate::ID
+        || !account
+            .try_borrow_data()?
+            .starts_with(SpendAlerts::DISCRIMINATOR)
+    {
+        return Ok((remaining, None));
+    }
+    let alerts = SpendAlerts::try_deserialize(&mut &account.try_borrow_data()?[..])
+        .map_err(|_| ErrorCode::InvalidSpendAlerts)?;
+    require_keys_eq!(
+        alerts.subscription,
+        *subscription,
+        ErrorCode::InvalidSpendAlerts
+    );
+    Ok((rest, Some(alerts)))
+}
+
+/// A `PlanSubscription` passed to a charge, with its state
+type PlanSchedule<'info> = (AccountInfo<'info>, PlanSubscription);
+
+/// Split a trailing `PlanSubscription` of the subscription off a charge's
+/// remaining accounts, before its `SlaCredit` receipts. It must be writable,
+/// since the charge that moves it to its next price step writes it.
+fn plan_schedule<'a, 'info>(
+    remaining: &'a [AccountInfo<'info>],
+    subscription: &Pubkey,
+) -> Result<(&'a [AccountInfo<'info>], Option<PlanSchedule<'info>>)> {
+    let Some((account, rest)) = remaining.split_last() else {
+        return Ok((remaining, None));
+    };
+    if *account.owner != crate::ID
+        || !account
+            .try_borrow_data()?
+            .starts_with(PlanSubscription::DISCRIMINATOR)
+    {
+        return Ok((remaining, None));
+    }
+    let plan_subscription =
+        PlanSubscription::try_deserialize(&mut &account.try_borrow_data()?[..])
+            .map_err(|_| ErrorCode::InvalidPlan)?;
+    require!(
+        plan_subscription.subscription == *subscription && account.is_writable,
+        ErrorCode::InvalidPlan
+    );
+    Ok((rest, Some((account.clone(), plan_subscription))))
+}
+
+/// What a fallback token account can give a charge as the subscription's
+/// delegate
+fn fallback_balance(
+    account: &AccountInfo,
+    subscription_key: &Pubkey,
+    subscription: &Subscription,
+) -> u64 {
+    if *account.owner != spl_token::ID {
+        return 0;
+    }
+    let Ok(data) = account.try_borrow_data() else {
+        return 0;
+    };
+    match spl_token::state::Account::unpack(&data) {
+        Ok(token_account)
+            if token_account.state == spl_token::state::AccountState::Initialized
+                && token_account.mint == subscription.token_mint
+                && token_account.owner == subscription.authority
+                && token_account.delegate == COption::Some(*subscription_key) =>
+        {
+            token_account.amount.min(token_account.delegated_amount)
+        }
+        _ => 0,
+    }
+}
+
+/// What the fallback mint's token account can give a charge as the
+/// subscription's delegate; its mint and owner were checked when it was set
+fn fallback_mint_balance(account: &AccountInfo, subscription_key: &Pubkey) -> Result<u64> {
+    let token_account = spl_token::state::Account::unpack(&account.try_borrow_data()?)
+        .map_err(|_| ErrorCode::InvalidFallbackPayment)?;
+    Ok(match token_account.delegate {
+        COption::Some(delegate) if delegate == *subscription_key => {
+            token_account.amount.min(token_account.delegated_amount)
+        }
+        _ => 0,
+    })
+}
+
+/// Whether `token_account` is a live SPL token account that still delegates
+/// to `subscription`, i.e. one a revoke would succeed on and change. Closed,
+/// frozen and uninitialized accounts, and ones delegated elsewhere (such as a
+/// spending policy), are left alone.
+pub fn holds_delegation(token_account: &AccountInfo, subscription: &Pubkey) -> bool {
+    if *token_account.owner != spl_token::ID {
+        return false;
+    }
+    let Ok(data) = token_account.try_borrow_data() else {
+        return false;
+    };
+    spl_token::state::Account::unpack(&data).is_ok_and(|account| {
+        account.state == spl_token::state::AccountState::Initialized
+            && account.delegate == COption::Some(*subscription)
+    })
+}
+
+/// Gate for other programs: `subscription` is active and not past its
+/// `expires_at`. Billing does not matter here, so an overdue subscription still
+/// passes until the keeper's next charge fails.
+pub fn assert_active_subscription(subscription: &Subscription, now: i64) -> Result<()> {
+    require!(subscription.is_active, ErrorCode::SubscriptionInactive);
+
+    if let Some(expires_at) = subscription.expires_at {
+        require!(now < expires_at, ErrorCode::SubscriptionExpired);
+    }
+
+    Ok(())
+}
+
+/// Gate for `initialize_wallet_subscription`: `authority` is a PDA that
+/// signed, and the instruction is a direct CPI from the transaction's
+/// top-level LazorKit instruction. A PDA can only sign through the program
+/// that derives it, so the wallet program vouched for this authority.
+pub fn check_wallet_authority(
+    authority: &Pubkey,
+    authority_signed: bool,
+    top_level_program: &Pubkey,
+    stack_height: usize,
+) -> Result<()> {
+    require!(
+        authority_signed && !authority.is_on_curve(),
+        ErrorCode::InvalidWalletAuthority
+    );
+    require!(
+        *top_level_program == LAZORKIT_PROGRAM_ID
+            && stack_height == TRANSACTION_LEVEL_STACK_HEIGHT + 1,
+        ErrorCode::NotCalledByWallet
+    );
+
+    Ok(())
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubscriptionCreated {
+    pub subscription: Pubkey,
     pub authority: Pubkey,
     pub recipient: Pubkey,
-    pub user_token_account: Pubkey,
-    pub recipient_token_account: Pubkey,
-    pub token_mint: Pubkey,
     pub amount_per_period: u64,
     pub interval_seconds: i64,
-    pub last_charge_timestamp: i64,
-    pub created_at: i64,
     pub expires_at: Option<i64>,
-    pub is_active: bool,
+    pub timestamp: i64,
+}
+
+/// `reference` is the caller's order or invoice ID, if it charged with
+/// `charge_subscription_with_reference`
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubscriptionCharged {
+    pub subscription: Pubkey,
+    pub authority: Pubkey,
+    pub recipient: Pubkey,
+    pub amount: u64,
     pub total_charged: u64,
-    pub bump: u8,
+    pub reference: Option<[u8; 32]>,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubscriptionCancelled {
+    pub subscription: Pubkey,
+    pub authority: Pubkey,
+    pub recipient: Pubkey,
+    pub total_charged: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubscriptionUpdated {
+    pub subscription: Pubkey,
+    pub amount_per_period: u64,
+    pub interval_seconds: i64,
+    pub expires_at: Option<i64>,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DelegationRevoked {
+    pub subscription: Pubkey,
+    pub authority: Pubkey,
+    pub recipient: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChargeShortfall {
+    pub subscription: Pubkey,
+    pub recipient: Pubkey,
+    pub amount_due: u64,
+    pub amount_charged: u64,
+    pub shortfall: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChargeThreadCreated {
+    pub subscription: Pubkey,
+    pub thread: Pubkey,
+    pub schedule: String,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChargeFunctionRegistered {
+    pub recipient: Pubkey,
+    pub function: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChargeAttested {
+    pub subscription: Pubkey,
+    pub function: Pubkey,
+    pub enclave_signer: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChargeTaskQueued {
+    pub subscription: Pubkey,
+    pub task_queue: Pubkey,
+    pub task: Pubkey,
+    pub trigger_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeeperLeaseAcquired {
+    pub lease: Pubkey,
+    pub holder: Pubkey,
+    pub term: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubscriptionMigrated {
+    pub subscription: Pubkey,
+    pub authority: Pubkey,
+    pub recipient: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MerchantVaultCreated {
+    pub recipient: Pubkey,
+    pub vault: Pubkey,
+    pub token_account: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RevenueWithdrawn {
+    pub recipient: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
+    pub total_withdrawn: u64,
+    pub timestamp: i64,
+}
+
+/// `effective_at` is `None` when the destination is removed
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct WithdrawalDestinationChanged {
+    pub recipient: Pubkey,
+    pub token_account: Pubkey,
+    pub effective_at: Option<i64>,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct WithdrawalLimitUpdated {
+    pub recipient: Pubkey,
+    pub limit: u64,
+    pub period_seconds: i64,
+    pub admin: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RevenueRecovered {
+    pub recipient: Pubkey,
+    pub admin: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
+    pub total_withdrawn: u64,
+    pub timestamp: i64,
+}
+
+/// `ends_at` is `i64::MAX` while no notice has been given
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlaCommitmentChanged {
+    pub recipient: Pubkey,
+    pub attester: Pubkey,
+    pub credit_multiplier: u16,
+    pub ends_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DowntimeAttested {
+    pub recipient: Pubkey,
+    pub attestation: Pubkey,
+    pub attester: Pubkey,
+    pub started_at: i64,
+    pub ended_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlaCreditPaid {
+    pub recipient: Pubkey,
+    pub subscription: Pubkey,
+    pub attestation: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlaDiscountScheduled {
+    pub recipient: Pubkey,
+    pub subscription: Pubkey,
+    pub attestation: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriceCacheRefreshed {
+    pub price_cache: Pubkey,
+    pub price: i64,
+    pub conf: u64,
+    pub exponent: i32,
+    pub publish_time: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetupFeePaid {
+    pub subscription: Pubkey,
+    pub authority: Pubkey,
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DepositPaid {
+    pub subscription: Pubkey,
+    pub authority: Pubkey,
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DepositRefunded {
+    pub subscription: Pubkey,
+    pub authority: Pubkey,
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanCreated {
+    pub plan: Pubkey,
+    pub recipient: Pubkey,
+    pub plan_id: u64,
+    pub token_mint: Pubkey,
+    pub monthly_price: u64,
+    pub annual_discount_bps: u16,
+    pub timestamp: i64,
+}
+
+/// `prorated_amount` is what the switch charged at once
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct BillingCadenceChanged {
+    pub subscription: Pubkey,
+    pub plan: Pubkey,
+    pub cadence: BillingCadence,
+    pub amount_per_period: u64,
+    pub prorated_amount: u64,
+    pub timestamp: i64,
+}
+
+/// `next_tier` is `None` when the plan does not upgrade
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanMeteringSet {
+    pub plan: Pubkey,
+    pub included_units: u64,
+    pub upgrade_after_periods: u8,
+    pub next_tier: Option<Pubkey>,
+    pub overage_rate: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanCapacitySet {
+    pub plan: Pubkey,
+    pub max_subscribers: u32,
+    pub subscribers: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanIntroPricingSet {
+    pub plan: Pubkey,
+    pub intro_discount_bps: u16,
+    pub intro_periods: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanPriceRampSet {
+    pub plan: Pubkey,
+    pub price_ramp: Vec<RampStep>,
+    pub timestamp: i64,
+}
+
+/// Emitted by the charge that moves a plan subscription to the next step
+/// of its price schedule
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduledPriceApplied {
+    pub subscription: Pubkey,
+    pub plan: Pubkey,
+    pub amount_per_period: u64,
+    pub timestamp: i64,
+}
+
+/// `subscribers` is the count left on the plan
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanSeatReleased {
+    pub plan: Pubkey,
+    pub subscription: Pubkey,
+    pub subscribers: u32,
+    pub timestamp: i64,
+}
+
+/// `position` is the entry's place in the plan's waitlist
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct WaitlistJoined {
+    pub plan: Pubkey,
+    pub authority: Pubkey,
+    pub position: u64,
+    pub timestamp: i64,
+}
+
+/// Emitted when a user leaves a waitlist or their entry is skipped
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct WaitlistLeft {
+    pub plan: Pubkey,
+    pub authority: Pubkey,
+    pub position: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct WaitlistPromoted {
+    pub plan: Pubkey,
+    pub subscription: Pubkey,
+    pub authority: Pubkey,
+    pub position: u64,
+    pub timestamp: i64,
+}
+
+/// `period_units` is the period's usage so far
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsageRecorded {
+    pub subscription: Pubkey,
+    pub plan: Pubkey,
+    pub period_start: i64,
+    pub units: u64,
+    pub period_units: u64,
+    pub timestamp: i64,
+}
+
+/// A tier upgrade was scheduled, or `None`: the user vetoed it
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TierUpgradeScheduled {
+    pub subscription: Pubkey,
+    pub next_tier: Option<Pubkey>,
+    pub effective_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpendThresholdCrossed {
+    pub subscription: Pubkey,
+    pub authority: Pubkey,
+    pub recipient: Pubkey,
+    pub threshold: u64,
+    pub total_charged: u64,
+    pub timestamp: i64,
+}
+
+/// `max_total_spend` is `None` once the limit is lifted; `allowance` is
+/// what the subscription can still take
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpendLimitSet {
+    pub subscription: Pubkey,
+    pub authority: Pubkey,
+    pub max_total_spend: Option<u64>,
+    pub allowance: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpendLimitReached {
+    pub subscription: Pubkey,
+    pub authority: Pubkey,
+    pub recipient: Pubkey,
+    pub total_charged: u64,
+    pub timestamp: i64,
+}
+
+/// `overage_charged` is the running total
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverageCharged {
+    pub subscription: Pubkey,
+    pub plan: Pubkey,
+    pub period_start: i64,
+    pub units: u64,
+    pub amount: u64,
+    pub overage_charged: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TierUpgraded {
+    pub subscription: Pubkey,
+    pub from_plan: Pubkey,
+    pub to_plan: Pubkey,
+    pub amount_per_period: u64,
+    pub timestamp: i64,
+}
+
+/// `denied` is false when the wallet is taken off the denylist
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DenylistChanged {
+    pub recipient: Pubkey,
+    pub wallet: Pubkey,
+    pub denied: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct InviteOnlyChanged {
+    pub recipient: Pubkey,
+    pub invite_only: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct AllowlistChanged {
+    pub recipient: Pubkey,
+    pub wallet: Pubkey,
+    pub allowed: bool,
+    pub timestamp: i64,
+}
+
+/// `token_account` is `None` when the recipient stops accepting the mint
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FallbackMintChanged {
+    pub recipient: Pubkey,
+    pub token_mint: Pubkey,
+    pub token_account: Option<Pubkey>,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsdPriceSet {
+    pub subscription: Pubkey,
+    pub price_cache: Pubkey,
+    pub usd_per_period: u64,
+    pub timestamp: i64,
+}
+
+/// Part of an outage's discount taken off a charge; `pending` is what is
+/// left for later charges
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlaDiscountApplied {
+    pub subscription: Pubkey,
+    pub recipient: Pubkey,
+    pub attestation: Pubkey,
+    pub amount: u64,
+    pub pending: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct VaultHoldbackUpdated {
+    pub recipient: Pubkey,
+    pub holdback_bps: u16,
+    pub holdback_days: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RevenueHeld {
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub release_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubscriptionCompacted {
+    pub subscription: Pubkey,
+    pub authority: Pubkey,
+    pub recipient: Pubkey,
+    /// Lamports returned to the authority
+    pub refund: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FundingSourcesUpdated {
+    pub subscription: Pubkey,
+    pub token_accounts: Vec<Pubkey>,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FallbackFundingUsed {
+    pub subscription: Pubkey,
+    pub token_account: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MerchantConfigUpdated {
+    pub recipient: Pubkey,
+    pub allow_partial_charges: bool,
+    pub max_periods_per_charge: u8,
+    pub timestamp: i64,
+}
+
+/// A recipient's settings came under a multisig, or `None`: back under its key
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MerchantMultisigChanged {
+    pub recipient: Pubkey,
+    pub multisig: Option<Pubkey>,
+    pub vault: Option<Pubkey>,
+    pub timestamp: i64,
+}
+
+/// A payout account was scheduled, or `None`: the pending change was closed
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PayoutChangeScheduled {
+    pub recipient: Pubkey,
+    pub token_account: Option<Pubkey>,
+    pub effective_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecipientTokenAccountChanged {
+    pub subscription: Pubkey,
+    pub recipient: Pubkey,
+    pub token_account: Pubkey,
+    pub timestamp: i64,
+}
+
+/// The platform config as created, or after a change was applied
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlatformConfigUpdated {
+    pub admin: Pubkey,
+    pub fee_bps: u16,
+    pub treasury: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlatformConfigChangeQueued {
+    pub fee_bps: Option<u16>,
+    pub treasury: Option<Pubkey>,
+    pub admin: Option<Pubkey>,
+    pub effective_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlatformConfigChangeCancelled {
+    pub timestamp: i64,
 }
 
 #[error_code]
@@ -416,4 +9800,150 @@ pub enum ErrorCode {
     InvalidTokenAccount,
     #[msg("Cannot cleanup - subscription is still active")]
     SubscriptionStillActive,
-}
\ No newline at end of file
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+    #[msg("Wallet authority must be a PDA that signed the call")]
+    InvalidWalletAuthority,
+    #[msg("Wallet subscriptions must be opened by CPI from the LazorKit program")]
+    NotCalledByWallet,
+    #[msg("Token account is delegated to its spending policy; charge through the policy")]
+    DelegatedToPolicy,
+    #[msg("Merchant config is not the recipient's")]
+    InvalidMerchantConfig,
+    #[msg("A subscription can have at most 4 fallback funding accounts")]
+    TooManyFundingSources,
+    #[msg("Fallback funding account is not a registered token account of the subscriber for this mint")]
+    InvalidFundingSource,
+    #[msg("Subscription already uses the current account layout")]
+    SubscriptionAlreadyMigrated,
+    #[msg("Charge thread id must be 1 to 32 bytes and match the thread account")]
+    InvalidChargeThread,
+    #[msg("Charge must be signed by the registered Switchboard Function's enclave")]
+    InvalidAttestation,
+    #[msg("Charge task must be the task queue's task for the given id")]
+    InvalidChargeTask,
+    #[msg("Keeper lease needs 1 to 8 distinct keepers and a duration of 1 second to 1 hour")]
+    InvalidKeeperLease,
+    #[msg("Signer is not one of the lease's keepers")]
+    NotALeaseKeeper,
+    #[msg("Keeper lease is held by another keeper")]
+    KeeperLeaseHeld,
+    #[msg("Signer does not hold the keeper lease in this term")]
+    KeeperLeaseNotHeld,
+    #[msg("Interval must be a positive number of seconds, -1, -3 or -12 for calendar months, or a valid cron schedule")]
+    InvalidInterval,
+    #[msg("Account is not a Squads v4 multisig")]
+    InvalidMultisig,
+    #[msg("Only the recipient can change its merchant settings")]
+    NotMerchantAuthority,
+    #[msg("Merchant settings are controlled by the recipient's multisig; its vault must sign")]
+    MultisigApprovalRequired,
+    #[msg("Payout account must be a token account of the subscription's mint")]
+    InvalidPayoutAccount,
+    #[msg("Payout change is still timelocked")]
+    PayoutChangeTimelocked,
+    #[msg("Vault token account must be owned by the merchant vault, with no delegate or close authority")]
+    InvalidVaultTokenAccount,
+    #[msg("Withdrawal must be more than zero and at most the vault balance")]
+    InvalidWithdrawal,
+    #[msg("Holdback must be at most 10000 bps and 180 days")]
+    InvalidHoldback,
+    #[msg("Amount exceeds the withdrawable balance; the rest is held back")]
+    RevenueHeldBack,
+    #[msg("Withdrawal limit needs an amount, a period and an admin, or none of them")]
+    InvalidWithdrawalLimit,
+    #[msg("Only the withdrawal admin can raise, remove or hand over the withdrawal limit")]
+    WithdrawalLimitLocked,
+    #[msg("Withdrawal exceeds what the limit allows in this period")]
+    WithdrawalLimitExceeded,
+    #[msg("Withdrawal destination must be a token account of the vault's mint, listed once")]
+    InvalidWithdrawalDestination,
+    #[msg("A vault can have at most 4 withdrawal destinations")]
+    TooManyWithdrawalDestinations,
+    #[msg("Destination is not an approved withdrawal destination, or is still timelocked")]
+    DestinationNotApproved,
+    #[msg("SLA needs an attester, a credit multiplier of 1 to 100 and a vault with a holdback")]
+    InvalidSlaCommitment,
+    #[msg("SLA commitment has not ended yet")]
+    SlaCommitmentActive,
+    #[msg("Downtime must be over and have started while the SLA commitment held")]
+    InvalidDowntime,
+    #[msg("Subscription is owed no credit for this downtime")]
+    NoSlaCredit,
+    #[msg("Not enough held-back revenue to pay the credit")]
+    InsufficientHeldRevenue,
+    #[msg("SLA discount receipt is not this subscription's, or is not writable")]
+    InvalidSlaDiscount,
+    #[msg("Amount must be greater than zero")]
+    InvalidAmount,
+    #[msg("Price update must be a fully verified Pyth update of the cache's feed")]
+    InvalidPriceUpdate,
+    #[msg("Cached price is too old, or not positive")]
+    StalePrice,
+    #[msg("Subscription is priced in USD; charge it with charge_subscription_usd")]
+    UsdPriceRequired,
+    #[msg("Subscription is not priced in USD")]
+    InvalidUsdPeg,
+    #[msg("Fallback mint needs a token account of that mint owned by the recipient or its vault")]
+    InvalidFallbackMint,
+    #[msg("Fallback account must be the authority's, in an accepted mint with the same decimals")]
+    InvalidFallbackPayment,
+    #[msg("Primary token account can still pay the period")]
+    PrimaryCanPay,
+    #[msg("Charge would settle a period that is already charged")]
+    PeriodAlreadyCharged,
+    #[msg("Deposit escrow must be the deposit's own token account of the subscription's mint")]
+    InvalidDepositAccount,
+    #[msg("Deposit is refunded once the subscription is cancelled")]
+    DepositLocked,
+    #[msg("Plan must be the recipient's, in the subscription's mint, with a discount under 100%")]
+    InvalidPlan,
+    #[msg("Subscription is already billed at this cadence")]
+    CadenceUnchanged,
+    #[msg("Plan does not meter usage")]
+    PlanNotMetered,
+    #[msg("No tier upgrade is scheduled")]
+    NoTierUpgrade,
+    #[msg("Tier upgrade can no longer be vetoed")]
+    TierUpgradeVetoClosed,
+    #[msg("Tier upgrade can still be vetoed")]
+    TierUpgradeVetoOpen,
+    #[msg("Plan has no overage rate")]
+    NoOverageRate,
+    #[msg("Overage is more than the unbilled usage beyond the allotment")]
+    OverageExceedsUsage,
+    #[msg("Spend alerts are invalid or belong to another subscription")]
+    InvalidSpendAlerts,
+    #[msg("Spend limit is below the total charged, or the token account is not delegated to the subscription")]
+    InvalidSpendLimit,
+    #[msg("Wallet is on the merchant's denylist")]
+    SubscriberDenied,
+    #[msg("Wallet is not on the invite-only merchant's allowlist")]
+    SubscriberNotAllowlisted,
+    #[msg("Plan has no seats left")]
+    PlanFull,
+    #[msg("Plan has seats; join it instead")]
+    PlanNotFull,
+    #[msg("Waitlist has no entries")]
+    WaitlistEmpty,
+    #[msg("Waitlist entry can still be promoted")]
+    WaitlistEntryPromotable,
+    #[msg("Intro discount must be below 100% and come with intro periods")]
+    InvalidIntroPricing,
+    #[msg("A price ramp has at most four steps, each with periods and a price")]
+    InvalidPriceRamp,
+    #[msg("A subscription on a plan is charged with its PlanSubscription")]
+    PlanSubscriptionRequired,
+    #[msg("A subscription on a plan is priced by its plan")]
+    PricedByPlan,
+    #[msg("Subscription is priced in USD; close its UsdPeg to set a token amount")]
+    PricedInUsd,
+    #[msg("The charge would take the subscription past its spend limit")]
+    SpendLimitExceeded,
+    #[msg("Platform fee is above MAX_PLATFORM_FEE_BPS")]
+    InvalidPlatformFee,
+    #[msg("A platform config change must change the fee, treasury or admin")]
+    EmptyPlatformConfigChange,
+    #[msg("Platform config change is still timelocked")]
+    PlatformConfigChangeTimelocked,
+}
@@ -0,0 +1,321 @@
+//! The token paths end to end on `solana-program-test`, against a bank.
+//!
+//! The program runs natively, but everything around it is the validator's
+//! runtime: the SPL Token program is its bundled SBF build, and CPIs, PDA
+//! signing, rent and lamport checks are the bank's. These tests follow a
+//! subscription through the CPIs the rest of the suites run on the harness:
+//! the approval and first transfer at signup, a charge the subscription PDA
+//! signs for as delegate, and the revoke and rent refund on cancel.
+
+mod common;
+
+use anchor_lang::prelude::{AccountInfo, Clock, Pubkey};
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::{system_program, AccountDeserialize};
+use common::{
+    allowlist_entry_address, build, denylist_address, subscriber_allowlist_address, AMOUNT,
+    INTERVAL, STARTING_BALANCE,
+};
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_system_interface::instruction as system_instruction;
+use solana_transaction::Transaction;
+use spl_token::state::Account as TokenAccount;
+use subscription_program::{accounts, instruction, ErrorCode, Subscription, ID as PROGRAM_ID};
+use test_harness::{
+    forward_cpi_to_syscall_stubs, Account, InstructionError, Keypair, Signer, TransactionError,
+};
+
+/// `processor!` takes one lifetime for the accounts slice and its items,
+/// Anchor's `entry` two
+fn process(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let accounts = Box::leak(accounts.to_vec().into_boxed_slice());
+    subscription_program::entry(program_id, accounts, data)
+}
+
+struct Bank {
+    context: ProgramTestContext,
+    authority: Keypair,
+    recipient: Pubkey,
+    mint: Pubkey,
+    user_token_account: Pubkey,
+    recipient_token_account: Pubkey,
+    subscription: Pubkey,
+}
+
+impl Bank {
+    /// A funded user and a merchant, no subscription yet
+    async fn new() -> Self {
+        let mut program_test =
+            ProgramTest::new("subscription_program", PROGRAM_ID, processor!(process));
+        // Even if an SBF build is lying around in `target/deploy`
+        program_test.prefer_bpf(false);
+        let context = program_test.start_with_context().await;
+        forward_cpi_to_syscall_stubs();
+
+        let authority = Keypair::new();
+        let recipient = Pubkey::new_unique();
+        let (subscription, _) = Pubkey::find_program_address(
+            &[
+                b"subscription",
+                authority.pubkey().as_ref(),
+                recipient.as_ref(),
+            ],
+            &PROGRAM_ID,
+        );
+        let mut bank = Self {
+            context,
+            authority,
+            recipient,
+            mint: Pubkey::default(),
+            user_token_account: Pubkey::default(),
+            recipient_token_account: Pubkey::default(),
+            subscription,
+        };
+
+        let airdrop = system_instruction::transfer(
+            &bank.context.payer.pubkey(),
+            &bank.authority.pubkey(),
+            1_000_000_000,
+        );
+        bank.send(&[airdrop], &[]).await.unwrap();
+        bank.mint = bank.create_mint().await;
+        bank.user_token_account = bank.create_token_account(&bank.authority.pubkey()).await;
+        bank.recipient_token_account = bank.create_token_account(&recipient).await;
+        let mint_to = spl_token::instruction::mint_to(
+            &spl_token::ID,
+            &bank.mint,
+            &bank.user_token_account,
+            &bank.context.payer.pubkey(),
+            &[],
+            STARTING_BALANCE,
+        )
+        .unwrap();
+        bank.send(&[mint_to], &[]).await.unwrap();
+        bank
+    }
+
+    /// A mint whose authority is the payer
+    async fn create_mint(&mut self) -> Pubkey {
+        let mint = Keypair::new();
+        let rent = self.context.banks_client.get_rent().await.unwrap();
+        let payer = self.context.payer.pubkey();
+        let instructions = [
+            system_instruction::create_account(
+                &payer,
+                &mint.pubkey(),
+                rent.minimum_balance(spl_token::state::Mint::LEN),
+                spl_token::state::Mint::LEN as u64,
+                &spl_token::ID,
+            ),
+            spl_token::instruction::initialize_mint2(
+                &spl_token::ID,
+                &mint.pubkey(),
+                &payer,
+                None,
+                6,
+            )
+            .unwrap(),
+        ];
+        self.send(&instructions, &[&mint]).await.unwrap();
+        mint.pubkey()
+    }
+
+    async fn create_token_account(&mut self, owner: &Pubkey) -> Pubkey {
+        let account = Keypair::new();
+        let rent = self.context.banks_client.get_rent().await.unwrap();
+        let instructions = [
+            system_instruction::create_account(
+                &self.context.payer.pubkey(),
+                &account.pubkey(),
+                rent.minimum_balance(TokenAccount::LEN),
+                TokenAccount::LEN as u64,
+                &spl_token::ID,
+            ),
+            spl_token::instruction::initialize_account3(
+                &spl_token::ID,
+                &account.pubkey(),
+                &self.mint,
+                owner,
+            )
+            .unwrap(),
+        ];
+        self.send(&instructions, &[&account]).await.unwrap();
+        account.pubkey()
+    }
+
+    fn initialize_ix(&self) -> Instruction {
+        build(
+            accounts::InitializeSubscription {
+                subscription: self.subscription,
+                authority: self.authority.pubkey(),
+                recipient: self.recipient,
+                user_token_account: self.user_token_account,
+                recipient_token_account: self.recipient_token_account,
+                token_mint: self.mint,
+                token_program: spl_token::ID,
+                payer: self.context.payer.pubkey(),
+                system_program: system_program::ID,
+                denylist_entry: denylist_address(&self.recipient, &self.authority.pubkey()).0,
+                allowlist: subscriber_allowlist_address(&self.recipient).0,
+                allowlist_entry: allowlist_entry_address(&self.recipient, &self.authority.pubkey())
+                    .0,
+            },
+            instruction::InitializeSubscription {
+                amount_per_period: AMOUNT,
+                interval_seconds: INTERVAL,
+                expires_at: None,
+            },
+        )
+    }
+
+    fn charge_ix(&self) -> Instruction {
+        build(
+            accounts::ChargeSubscription {
+                subscription: self.subscription,
+                user_token_account: self.user_token_account,
+                recipient_token_account: self.recipient_token_account,
+                token_program: spl_token::ID,
+            },
+            instruction::ChargeSubscription {},
+        )
+    }
+
+    fn cancel_ix(&self) -> Instruction {
+        build(
+            accounts::CancelSubscription {
+                subscription: self.subscription,
+                authority: self.authority.pubkey(),
+                user_token_account: self.user_token_account,
+                token_program: spl_token::ID,
+            },
+            instruction::CancelSubscription {},
+        )
+    }
+
+    /// Sign with the payer plus `signers` against a fresh blockhash
+    async fn send(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Result<(), BanksClientError> {
+        let blockhash = self.context.get_new_latest_blockhash().await?;
+        let mut all = vec![&self.context.payer];
+        all.extend_from_slice(signers);
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.context.payer.pubkey()),
+            &all,
+            blockhash,
+        );
+        self.context
+            .banks_client
+            .process_transaction(transaction)
+            .await
+    }
+
+    async fn subscribe(&mut self) {
+        let authority = self.authority.insecure_clone();
+        let ix = self.initialize_ix();
+        self.send(&[ix], &[&authority]).await.unwrap();
+    }
+
+    async fn subscription(&self) -> Option<Subscription> {
+        let account = self.account(&self.subscription).await?;
+        Subscription::try_deserialize(&mut account.data.as_slice()).ok()
+    }
+
+    async fn token_account(&self, address: &Pubkey) -> TokenAccount {
+        let account = self.account(address).await.unwrap();
+        TokenAccount::unpack(&account.data).unwrap()
+    }
+
+    async fn account(&self, address: &Pubkey) -> Option<Account> {
+        self.context
+            .banks_client
+            .get_account(*address)
+            .await
+            .unwrap()
+    }
+
+    async fn balance(&self, address: &Pubkey) -> u64 {
+        self.context
+            .banks_client
+            .get_balance(*address)
+            .await
+            .unwrap()
+    }
+
+    /// Move the bank's clock forward by `seconds`
+    async fn advance_time(&mut self, seconds: i64) {
+        let mut clock: Clock = self.context.banks_client.get_sysvar().await.unwrap();
+        clock.unix_timestamp += seconds;
+        self.context.set_sysvar(&clock);
+    }
+}
+
+#[tokio::test]
+async fn signup_approves_the_subscription_and_pays_the_first_period() {
+    let mut bank = Bank::new().await;
+    bank.subscribe().await;
+
+    let subscription = bank.subscription().await.unwrap();
+    assert!(subscription.is_active);
+    assert_eq!(subscription.total_charged, AMOUNT);
+
+    let user = bank.token_account(&bank.user_token_account).await;
+    assert_eq!(user.amount, STARTING_BALANCE - AMOUNT);
+    assert_eq!(Option::from(user.delegate), Some(bank.subscription));
+    // The first period already went through the new delegation
+    assert_eq!(user.delegated_amount, u64::MAX - AMOUNT);
+    let merchant = bank.token_account(&bank.recipient_token_account).await;
+    assert_eq!(merchant.amount, AMOUNT);
+}
+
+#[tokio::test]
+async fn the_subscription_pda_signs_each_charge_as_delegate() {
+    let mut bank = Bank::new().await;
+    bank.subscribe().await;
+
+    // Not due yet: nothing moves
+    let ix = bank.charge_ix();
+    let err = bank.send(&[ix], &[]).await.unwrap_err().unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(ErrorCode::IntervalNotMet.into())
+        )
+    );
+
+    bank.advance_time(INTERVAL).await;
+    let ix = bank.charge_ix();
+    bank.send(&[ix], &[]).await.unwrap();
+
+    let user = bank.token_account(&bank.user_token_account).await;
+    assert_eq!(user.amount, STARTING_BALANCE - 2 * AMOUNT);
+    assert_eq!(user.delegated_amount, u64::MAX - 2 * AMOUNT);
+    let merchant = bank.token_account(&bank.recipient_token_account).await;
+    assert_eq!(merchant.amount, 2 * AMOUNT);
+    assert_eq!(bank.subscription().await.unwrap().total_charged, 2 * AMOUNT);
+}
+
+#[tokio::test]
+async fn cancel_revokes_and_refunds_the_rent_to_the_authority() {
+    let mut bank = Bank::new().await;
+    bank.subscribe().await;
+    let rent = bank.balance(&bank.subscription).await;
+    let authority = bank.authority.insecure_clone();
+    let lamports = bank.balance(&authority.pubkey()).await;
+
+    let ix = bank.cancel_ix();
+    bank.send(&[ix], &[&authority]).await.unwrap();
+
+    assert!(bank.account(&bank.subscription).await.is_none());
+    // The payer covers the fee, so the authority gets the rent back whole
+    assert_eq!(bank.balance(&authority.pubkey()).await, lamports + rent);
+    let user = bank.token_account(&bank.user_token_account).await;
+    assert!(user.delegate.is_none());
+    assert_eq!(user.amount, STARTING_BALANCE - AMOUNT);
+}
@@ -0,0 +1,524 @@
+//! Property tests for the billing rules in `Subscription::check_chargeable`,
+//! `Subscription::charge_amount`, the catch-up helpers,
+//! `Subscription::record_charge` and its `book_periods` idempotency
+//! invariant, the `split_funding` fallback split, and for the
+//! `assert_active_subscription` gate they build on.
+
+use anchor_lang::error::Error;
+use anchor_lang::prelude::Pubkey;
+use proptest::prelude::*;
+use subscription_program::{
+    assert_active_subscription, split_funding, CronSchedule, ErrorCode, Interval, Subscription,
+    SECONDS_PER_DAY,
+};
+
+fn subscription(
+    amount_per_period: u64,
+    interval_seconds: i64,
+    last_charge_timestamp: i64,
+    expires_at: Option<i64>,
+    total_charged: u64,
+) -> Subscription {
+    Subscription {
+        authority: Pubkey::new_unique(),
+        recipient: Pubkey::new_unique(),
+        user_token_account: Pubkey::new_unique(),
+        recipient_token_account: Pubkey::new_unique(),
+        token_mint: Pubkey::new_unique(),
+        amount_per_period,
+        interval_seconds,
+        last_charge_timestamp,
+        next_charge_at: last_charge_timestamp.saturating_add(interval_seconds),
+        created_at: last_charge_timestamp,
+        expires_at,
+        is_active: true,
+        total_charged,
+        bump: 255,
+        on_plan: false,
+        max_total_spend: Subscription::NO_SPEND_LIMIT,
+    }
+}
+
+fn error_code(result: anchor_lang::Result<()>) -> Option<u32> {
+    match result {
+        Ok(()) => None,
+        Err(Error::AnchorError(error)) => Some(error.error_code_number),
+        Err(error) => panic!("unexpected error {error:?}"),
+    }
+}
+
+fn code(error: ErrorCode) -> Option<u32> {
+    Some(error.into())
+}
+
+fn cron_schedule() -> impl Strategy<Value = CronSchedule> {
+    let weekdays = prop_oneof![
+        Just(CronSchedule::EVERY_WEEKDAY),
+        1..=CronSchedule::EVERY_WEEKDAY,
+        (0..7u8).prop_map(|weekday| 1 << weekday),
+    ];
+    let days_of_month = prop_oneof![
+        Just(CronSchedule::EVERY_DAY_OF_MONTH),
+        1..=CronSchedule::EVERY_DAY_OF_MONTH,
+        (0..31u32).prop_map(|day| 1 << day),
+    ];
+    (0..60u8, 0..24u8, weekdays, days_of_month, -48..=56i16).prop_map(
+        |(minute, hour, weekdays, days_of_month, quarter_hours)| CronSchedule {
+            minute,
+            hour,
+            weekdays,
+            days_of_month,
+            utc_offset_minutes: quarter_hours * 15,
+        },
+    )
+}
+
+proptest! {
+    /// The program's decision matches the spec, for any clock and state,
+    /// without panicking on extreme timestamps
+    #[test]
+    fn chargeable_matches_model(
+        interval in any::<i64>(),
+        last_charge in any::<i64>(),
+        now in any::<i64>(),
+        expires_at in any::<Option<i64>>(),
+        is_active in any::<bool>(),
+    ) {
+        // Calendar intervals have their own tests below
+        prop_assume!(interval > 0 || Interval::from_seconds_field(interval).is_none());
+        let mut sub = subscription(1, interval, last_charge, expires_at, 0);
+        sub.is_active = is_active;
+
+        let expected = if !is_active {
+            code(ErrorCode::SubscriptionInactive)
+        } else if expires_at.is_some_and(|expires_at| now >= expires_at) {
+            code(ErrorCode::SubscriptionExpired)
+        } else if interval <= 0 {
+            code(ErrorCode::InvalidInterval)
+        } else if now.saturating_sub(last_charge) < interval {
+            code(ErrorCode::IntervalNotMet)
+        } else {
+            None
+        };
+
+        prop_assert_eq!(error_code(sub.check_chargeable(now)), expected);
+    }
+
+    /// The access gate checks status and expiry only, never the billing interval
+    #[test]
+    fn active_gate_ignores_billing(
+        interval in any::<i64>(),
+        last_charge in any::<i64>(),
+        now in any::<i64>(),
+        expires_at in any::<Option<i64>>(),
+        is_active in any::<bool>(),
+    ) {
+        let mut sub = subscription(1, interval, last_charge, expires_at, 0);
+        sub.is_active = is_active;
+
+        let expected = if !is_active {
+            code(ErrorCode::SubscriptionInactive)
+        } else if expires_at.is_some_and(|expires_at| now >= expires_at) {
+            code(ErrorCode::SubscriptionExpired)
+        } else {
+            None
+        };
+
+        prop_assert_eq!(error_code(assert_active_subscription(&sub, now)), expected);
+    }
+
+    /// Nothing can be charged before a full interval has passed
+    #[test]
+    fn interval_is_enforced(
+        interval in 1..=10 * 365 * 86_400i64,
+        last_charge in 0..=4_000_000_000i64,
+        early in 1..=10 * 365 * 86_400i64,
+    ) {
+        let sub = subscription(1, interval, last_charge, None, 0);
+        let early = early.min(interval);
+
+        prop_assert_eq!(
+            error_code(sub.check_chargeable(last_charge + interval - early)),
+            code(ErrorCode::IntervalNotMet)
+        );
+        prop_assert_eq!(error_code(sub.check_chargeable(last_charge + interval)), None);
+    }
+
+    /// A charge adds exactly one period, or fails with no state change
+    #[test]
+    fn record_charge_adds_one_period(
+        amount in any::<u64>(),
+        total_charged in any::<u64>(),
+        last_charge in any::<i64>(),
+        now in any::<i64>(),
+    ) {
+        let mut sub = subscription(amount, 1, last_charge, None, total_charged);
+
+        match total_charged.checked_add(amount) {
+            Some(expected) => {
+                prop_assert_eq!(error_code(sub.record_charge(now)), None);
+                prop_assert_eq!(sub.total_charged, expected);
+                prop_assert_eq!(sub.last_charge_timestamp, now);
+                prop_assert_eq!(sub.next_charge_at, now.saturating_add(1));
+            }
+            None => {
+                prop_assert_eq!(
+                    error_code(sub.record_charge(now)),
+                    code(ErrorCode::ArithmeticOverflow)
+                );
+                prop_assert_eq!(sub.total_charged, total_charged);
+                prop_assert_eq!(sub.last_charge_timestamp, last_charge);
+            }
+        }
+    }
+
+    /// A partial charge never takes more than the period or the balance, and
+    /// only falls short with the merchant's opt-in and a non-empty account
+    #[test]
+    fn charge_amount_is_bounded(
+        amount in any::<u64>(),
+        balance in any::<u64>(),
+        allow_partial in any::<bool>(),
+    ) {
+        let sub = subscription(amount, 1, 0, None, 0);
+        let charged = sub.charge_amount(balance, allow_partial);
+
+        prop_assert!(charged <= amount);
+        if charged < amount {
+            prop_assert!(allow_partial);
+            prop_assert!(balance > 0);
+            prop_assert_eq!(charged, balance);
+        }
+    }
+
+    /// Catch-up settles min(missed, cap) periods, one interval each, and
+    /// never moves the schedule past `now`
+    #[test]
+    fn catch_up_keeps_schedule(
+        amount in 1..=1_000_000_000u64,
+        interval in 1..=90 * 86_400i64,
+        missed in 1..=40i64,
+        jitter in 0..=1_000_000i64,
+        cap in 2..=u8::MAX,
+    ) {
+        let start = 1_700_000_000;
+        let mut sub = subscription(amount, interval, start, None, amount);
+        let now = start + missed * interval + jitter % interval;
+
+        let periods = sub.due_periods(now, cap);
+        prop_assert_eq!(periods, missed.min(cap as i64) as u64);
+
+        let charges = sub.period_charges(periods, u64::MAX, false);
+        sub.record_periods(now, &charges, true).unwrap();
+        prop_assert_eq!(sub.last_charge_timestamp, start + periods as i64 * interval);
+        prop_assert!(sub.last_charge_timestamp <= now);
+        prop_assert_eq!(sub.total_charged, amount * (1 + periods));
+    }
+
+    /// Every booked charge moves the period index past the period it
+    /// settled, so replaying it at the same time either fails or, with
+    /// catch-up periods left past the cap, settles only later ones
+    #[test]
+    fn booked_period_is_never_charged_again(
+        amount in 1..=1_000_000_000u64,
+        interval in prop_oneof![
+            1..=90 * 86_400i64,
+            Just(Interval::Monthly.to_seconds_field()),
+        ],
+        missed in 1..=40i64,
+        cap in 1..=u8::MAX,
+        balance in 0..=50_000_000_000u64,
+        allow_partial in any::<bool>(),
+    ) {
+        let start = 1_700_000_000;
+        let mut sub = subscription(amount, interval, start, None, amount);
+        let now = (0..missed).fold(start, |at, _| sub.period_end(at).unwrap());
+
+        let mut last_period = None;
+        let mut settled = 0;
+        loop {
+            let period = sub.period_index();
+            let periods = sub.due_periods(now, cap);
+            let charges = sub.period_charges(periods, balance, allow_partial);
+            match error_code(sub.book_periods(now, &charges, cap > 1)) {
+                None => {
+                    prop_assert!(last_period.is_none_or(|last| period > last));
+                    prop_assert!(sub.period_index() > period);
+                    last_period = Some(period);
+                    settled += charges.len();
+                }
+                error => {
+                    prop_assert_eq!(error, code(ErrorCode::IntervalNotMet));
+                    break;
+                }
+            }
+        }
+        let expected = if cap > 1 { missed as usize } else { 1 };
+        prop_assert_eq!(settled, expected);
+    }
+
+    /// Partial catch-up takes exactly the balance, or everything owed if the
+    /// balance covers it, and only the last period can come up short
+    #[test]
+    fn partial_catch_up_spends_balance(
+        amount in 1..=1_000_000u64,
+        periods in 1..=10u64,
+        balance in 1..=20_000_000u64,
+    ) {
+        let sub = subscription(amount, 1, 0, None, 0);
+        let charges = sub.period_charges(periods, balance, true);
+
+        prop_assert_eq!(charges.iter().sum::<u64>(), balance.min(amount * periods));
+        prop_assert!(charges.len() as u64 <= periods);
+        let full = &charges[..charges.len() - 1];
+        prop_assert!(full.iter().all(|charge| *charge == amount));
+    }
+
+    /// Without partial charges, catch-up settles only the periods the
+    /// balance pays in full, or one when it pays none, so the transfer
+    /// fails as it always has
+    #[test]
+    fn full_catch_up_stops_at_the_balance(
+        amount in 1..=1_000_000u64,
+        periods in 1..=10u64,
+        balance in 0..=20_000_000u64,
+    ) {
+        let sub = subscription(amount, 1, 0, None, 0);
+        let charges = sub.period_charges(periods, balance, false);
+
+        let affordable = (balance / amount).clamp(1, periods);
+        prop_assert_eq!(charges.len() as u64, affordable);
+        prop_assert!(charges.iter().all(|charge| *charge == amount));
+    }
+
+    /// Sources are drawn in order, each emptied before the next is touched,
+    /// and the draws add up to the amount whenever the balances cover it
+    #[test]
+    fn funding_is_drawn_in_order(
+        amount in 0..=100_000_000u64,
+        balances in prop::collection::vec(0..=50_000_000u64, 1..=5),
+    ) {
+        match split_funding(amount, &balances) {
+            Some(draws) => {
+                prop_assert_eq!(draws.iter().sum::<u64>(), amount);
+                let mut emptied = true;
+                for (draw, balance) in draws.iter().zip(&balances) {
+                    prop_assert!(draw <= balance);
+                    prop_assert!(emptied || *draw == 0);
+                    emptied = draw == balance;
+                }
+            }
+            None => prop_assert!(balances.iter().sum::<u64>() < amount),
+        }
+    }
+
+    /// Every stored value is read back as the interval it encodes, or as none
+    #[test]
+    fn interval_field_round_trips(value in any::<i64>()) {
+        match Interval::from_seconds_field(value) {
+            Some(interval) => prop_assert_eq!(interval.to_seconds_field(), value),
+            None => prop_assert!(value <= 0),
+        }
+    }
+
+    /// Calendar periods keep the time of day, last as long as their months
+    /// do, and chain: twelve monthly periods end where a yearly one does
+    #[test]
+    fn calendar_periods_follow_months(
+        anchor in 0..=4_000_000_000i64,
+        periods in 1..=24usize,
+    ) {
+        let chain = |interval: Interval, times: usize| {
+            (0..times).try_fold(anchor, |at, _| interval.advance(at, anchor))
+        };
+
+        for (interval, shortest, longest) in [
+            (Interval::Monthly, 28, 31),
+            (Interval::Quarterly, 89, 92),
+            (Interval::Yearly, 365, 366),
+        ] {
+            let from = chain(interval, periods - 1).unwrap();
+            let due = interval.advance(from, anchor).unwrap();
+            prop_assert_eq!(due.rem_euclid(SECONDS_PER_DAY), anchor.rem_euclid(SECONDS_PER_DAY));
+            let days = (due - from) / SECONDS_PER_DAY;
+            prop_assert!((shortest..=longest).contains(&days), "{:?} took {} days", interval, days);
+        }
+        prop_assert_eq!(chain(Interval::Monthly, 12 * periods), chain(Interval::Yearly, periods));
+        prop_assert_eq!(chain(Interval::Monthly, 3 * periods), chain(Interval::Quarterly, periods));
+    }
+
+    /// A schedule survives storage, and runs next at its local time of day,
+    /// on a matching weekday, within two months and with no run in between
+    #[test]
+    fn cron_schedules_run_when_due(
+        schedule in cron_schedule(),
+        from in 0..=4_000_000_000i64,
+    ) {
+        let interval = Interval::Cron(schedule);
+        prop_assert_eq!(Interval::from_seconds_field(interval.to_seconds_field()), Some(interval));
+
+        let next = interval.advance(from, 0).unwrap();
+        prop_assert!(next > from);
+        prop_assert!(next - from <= 63 * SECONDS_PER_DAY);
+        prop_assert_eq!(interval.advance(from + (next - from) / 2, 0), Some(next));
+
+        let local = next + schedule.utc_offset_minutes as i64 * 60;
+        prop_assert_eq!(
+            local.rem_euclid(SECONDS_PER_DAY),
+            schedule.hour as i64 * 3_600 + schedule.minute as i64 * 60
+        );
+        if schedule.days_of_month == CronSchedule::EVERY_DAY_OF_MONTH {
+            let weekday = (local.div_euclid(SECONDS_PER_DAY) + 4).rem_euclid(7);
+            prop_assert!(schedule.weekdays & (1 << weekday) != 0);
+        }
+    }
+
+    /// Any stored value, however extreme, schedules without panicking
+    #[test]
+    fn calendar_math_never_panics(
+        interval in prop_oneof![Just(-1i64), Just(-3), Just(-12), any::<i64>()],
+        from in any::<i64>(),
+        anchor in any::<i64>(),
+        now in any::<i64>(),
+    ) {
+        let mut sub = subscription(1, interval, from, None, 0);
+        sub.created_at = anchor;
+        sub.schedule_next_charge();
+        let _ = sub.check_chargeable(now);
+        let _ = sub.due_periods(now, u8::MAX);
+    }
+
+    /// Over many cycles charged whenever a keeper happens to run, totals only
+    /// grow, each charge is at least one interval after the previous one, and
+    /// the total is exactly amount × charges
+    #[test]
+    fn billing_cycles_accumulate(
+        amount in 1..=1_000_000_000u64,
+        interval in 1..=90 * 86_400i64,
+        keeper_delays in prop::collection::vec(0..=2 * 90 * 86_400i64, 1..50),
+    ) {
+        let start = 1_700_000_000;
+        let mut sub = subscription(amount, interval, start, None, amount);
+        let mut now = start;
+        let mut charges = 1u64;
+
+        for delay in keeper_delays {
+            now += delay;
+            let previous_total = sub.total_charged;
+            let previous_charge = sub.last_charge_timestamp;
+
+            if sub.check_chargeable(now).is_ok() {
+                sub.record_charge(now).unwrap();
+                charges += 1;
+                prop_assert!(sub.total_charged > previous_total);
+                prop_assert!(sub.last_charge_timestamp - previous_charge >= interval);
+            } else {
+                prop_assert!(now - previous_charge < interval);
+            }
+        }
+
+        prop_assert_eq!(sub.total_charged, amount * charges);
+    }
+}
+
+/// 2024-01-31 10:00 UTC
+const JAN_31_2024: i64 = 1_706_695_200;
+
+#[test]
+fn month_ends_clamp_to_shorter_months() {
+    let days = |from: i64, to: i64| (to - from) / SECONDS_PER_DAY;
+    let monthly = |from| Interval::Monthly.advance(from, JAN_31_2024).unwrap();
+
+    let feb = monthly(JAN_31_2024);
+    assert_eq!(days(JAN_31_2024, feb), 29);
+    // Back to the 31st once the month has one
+    let mar = monthly(feb);
+    assert_eq!(days(feb, mar), 31);
+    assert_eq!(days(mar, monthly(mar)), 30);
+
+    let feb_29 = feb;
+    let yearly = |from| Interval::Yearly.advance(from, feb_29).unwrap();
+    assert_eq!(days(feb_29, yearly(feb_29)), 365);
+    // 2025, 2026, 2027 fall on Feb 28, 2028 on Feb 29 again
+    let leap = (0..4).fold(feb_29, |at, _| yearly(at));
+    assert_eq!(days(feb_29, leap), 4 * 365 + 1);
+}
+
+#[test]
+fn catch_up_counts_calendar_months() {
+    let mut sub = subscription(
+        1,
+        Interval::Monthly.to_seconds_field(),
+        JAN_31_2024,
+        None,
+        1,
+    );
+    // Apr 30 2024 10:00: Feb 29, Mar 31 and Apr 30 are all due
+    let apr_30 = JAN_31_2024 + (29 + 31 + 30) * SECONDS_PER_DAY;
+
+    assert_eq!(sub.due_periods(apr_30 - 1, 12), 2);
+    assert_eq!(sub.due_periods(apr_30, 12), 3);
+    assert_eq!(sub.due_periods(apr_30, 2), 2);
+
+    sub.record_periods(apr_30, &[1, 1, 1], true).unwrap();
+    assert_eq!(sub.last_charge_timestamp, apr_30);
+    assert_eq!(sub.next_charge_at, apr_30 + 31 * SECONDS_PER_DAY);
+}
+
+/// 09:30 Monday to Friday in New York (standard time)
+const NEW_YORK_OPENING: CronSchedule = CronSchedule {
+    minute: 30,
+    hour: 9,
+    weekdays: 0b011_1110,
+    days_of_month: CronSchedule::EVERY_DAY_OF_MONTH,
+    utc_offset_minutes: -5 * 60,
+};
+
+#[test]
+fn cron_schedule_skips_to_the_next_business_day() {
+    let schedule = Interval::Cron(NEW_YORK_OPENING);
+    // Friday 2024-03-01 15:00 UTC is 10:00 in New York, past the opening
+    let friday = 1_709_305_200;
+    // Monday 2024-03-04 14:30 UTC
+    assert_eq!(schedule.advance(friday, 0), Some(1_709_562_600));
+}
+
+#[test]
+fn cron_schedule_runs_on_listed_days_of_month() {
+    // Midnight in Tokyo on the 1st and the 15th
+    let schedule = Interval::Cron(CronSchedule {
+        minute: 0,
+        hour: 0,
+        weekdays: CronSchedule::EVERY_WEEKDAY,
+        days_of_month: 1 | 1 << 14,
+        utc_offset_minutes: 9 * 60,
+    });
+    // 2024-03-14 14:00 UTC is 23:00 on the 14th in Tokyo
+    assert_eq!(schedule.advance(1_710_424_800, 0), Some(1_710_428_400));
+    // 2024-03-14 16:00 UTC is already the 15th; next is April 1
+    assert_eq!(schedule.advance(1_710_432_000, 0), Some(1_711_897_200));
+}
+
+#[test]
+fn invalid_cron_schedules_are_not_stored() {
+    for schedule in [
+        CronSchedule {
+            minute: 60,
+            ..NEW_YORK_OPENING
+        },
+        CronSchedule {
+            weekdays: 0,
+            ..NEW_YORK_OPENING
+        },
+        CronSchedule {
+            utc_offset_minutes: 20,
+            ..NEW_YORK_OPENING
+        },
+    ] {
+        assert_eq!(Interval::Cron(schedule).to_seconds_field(), 0);
+    }
+
+    // Unused bits set
+    let bits = Interval::Cron(NEW_YORK_OPENING).to_seconds_field() | 1 << 60;
+    assert_eq!(Interval::from_seconds_field(bits), None);
+}
@@ -0,0 +1,370 @@
+//! Chaos tests: the program's own accounts are corrupted between random
+//! instructions, the way a forged or bit-rotted account would look.
+//!
+//! `fuzz.rs` keeps the accounts honest and randomizes who calls what. Here
+//! the subscription, merchant config, funding list and token accounts get
+//! flipped bumps, flipped bytes, foreign discriminators and new sizes, in
+//! any order with charges, cancels, updates and migrations. Whatever the
+//! program makes of them:
+//!
+//! - it never panics; every failure is a returned error;
+//! - lamports are conserved (fees aside) and only the authority receives the
+//!   subscription's rent;
+//! - a charge only reaches the token program for the subscription at its
+//!   canonical address, with the token accounts it was opened with, checked
+//!   on the accounts as the program left them at the CPI;
+//! - tokens only move in a charge that lands, from the user and their
+//!   fallback account to the merchant.
+
+mod common;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, AccountDeserialize, Discriminator};
+use common::*;
+use proptest::prelude::*;
+use subscription_program::{
+    accounts, instruction, FundingSources, LegacySubscription, MerchantConfig, Subscription,
+};
+use test_harness::{Keypair, Signer};
+
+/// Byte offset of `bump`, discriminator included
+const BUMP_OFFSET: usize = 217;
+
+#[derive(Debug, Clone, Copy)]
+enum Target {
+    Subscription,
+    MerchantConfig,
+    FundingSources,
+    UserTokenAccount,
+}
+
+#[derive(Debug, Clone)]
+enum Mutation {
+    FlipBump(u8),
+    FlipByte {
+        offset: usize,
+        mask: u8,
+    },
+    /// Replace the first 8 bytes with another account type's discriminator,
+    /// or with `bytes` when `of` is out of range
+    Discriminator {
+        of: usize,
+        bytes: [u8; 8],
+    },
+    Resize(usize),
+}
+
+#[derive(Debug, Clone)]
+enum Op {
+    Charge,
+    ChargeWithConfig,
+    ChargeWithFunding,
+    Cancel,
+    Cleanup,
+    Update(Option<u64>, Option<i64>),
+    Migrate,
+    Warp(i64),
+}
+
+#[derive(Debug, Clone)]
+enum Step {
+    Corrupt(Target, Mutation),
+    Send { op: Op, as_attacker: bool },
+}
+
+fn target() -> impl Strategy<Value = Target> {
+    prop_oneof![
+        4 => Just(Target::Subscription),
+        1 => Just(Target::MerchantConfig),
+        1 => Just(Target::FundingSources),
+        1 => Just(Target::UserTokenAccount),
+    ]
+}
+
+fn mutation() -> impl Strategy<Value = Mutation> {
+    prop_oneof![
+        (1..=u8::MAX).prop_map(Mutation::FlipBump),
+        (0..SUBSCRIPTION_SPACE, 1..=u8::MAX)
+            .prop_map(|(offset, mask)| Mutation::FlipByte { offset, mask }),
+        (0..5usize, any::<[u8; 8]>()).prop_map(|(of, bytes)| Mutation::Discriminator { of, bytes }),
+        (0..=SUBSCRIPTION_SPACE + 64).prop_map(Mutation::Resize),
+    ]
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        3 => Just(Op::Charge),
+        2 => Just(Op::ChargeWithConfig),
+        2 => Just(Op::ChargeWithFunding),
+        1 => Just(Op::Cancel),
+        1 => Just(Op::Cleanup),
+        1 => (any::<Option<u64>>(), any::<Option<i64>>())
+            .prop_map(|(amount, interval)| Op::Update(amount, interval)),
+        1 => Just(Op::Migrate),
+        2 => (0..=2 * INTERVAL).prop_map(Op::Warp),
+    ]
+}
+
+fn step() -> impl Strategy<Value = Step> {
+    prop_oneof![
+        2 => (target(), mutation()).prop_map(|(target, mutation)| Step::Corrupt(target, mutation)),
+        3 => (op(), prop::bool::weighted(0.3))
+            .prop_map(|(op, as_attacker)| Step::Send { op, as_attacker }),
+    ]
+}
+
+struct World {
+    fx: Fixture,
+    attacker: Keypair,
+    /// The subscription as it was opened
+    original: Subscription,
+    config: Pubkey,
+    funding: Pubkey,
+    fallback: Pubkey,
+    pool: Vec<Pubkey>,
+    fees: u64,
+    initial_lamports: u64,
+}
+
+impl World {
+    fn new() -> Self {
+        let mut fx = Fixture::new();
+        let original = fx.subscribe(None);
+        let attacker = Keypair::new();
+        fx.svm.airdrop(&attacker.pubkey(), 1_000_000_000);
+        let fallback = fx.fallback_account(STARTING_BALANCE);
+        let config = fx.set_merchant_config(true, 3);
+        let funding = fx.set_funding_sources(&[fallback]);
+
+        let pool = vec![
+            fx.subscription,
+            fx.authority.pubkey(),
+            fx.recipient,
+            fx.user_token_account,
+            fx.recipient_token_account,
+            attacker.pubkey(),
+            config,
+            funding,
+            fallback,
+            fx.payer.pubkey(),
+        ];
+
+        let mut world = Self {
+            fx,
+            attacker,
+            original,
+            config,
+            funding,
+            fallback,
+            pool,
+            fees: 0,
+            initial_lamports: 0,
+        };
+        world.initial_lamports = world.total_lamports();
+        world
+    }
+
+    fn total_lamports(&self) -> u64 {
+        self.pool
+            .iter()
+            .map(|address| self.fx.svm.get_balance(address))
+            .sum()
+    }
+
+    /// What the user holds across their own and fallback accounts, and the
+    /// merchant's balance, where the accounts still unpack
+    fn token_balances(&self) -> (Option<u64>, Option<u64>) {
+        let balance = |address| {
+            self.fx
+                .svm
+                .get_token_account(address)
+                .map(|account| account.amount)
+        };
+        let user = balance(&self.fx.user_token_account)
+            .zip(balance(&self.fallback))
+            .map(|(own, fallback)| own + fallback);
+        (user, balance(&self.fx.recipient_token_account))
+    }
+
+    fn corrupt(&mut self, target: Target, mutation: &Mutation) {
+        let address = match target {
+            Target::Subscription => self.fx.subscription,
+            Target::MerchantConfig => self.config,
+            Target::FundingSources => self.funding,
+            Target::UserTokenAccount => self.fx.user_token_account,
+        };
+        // Closed by an earlier step
+        let Some(mut account) = self.fx.svm.get_account(&address) else {
+            return;
+        };
+
+        let data = &mut account.data;
+        match *mutation {
+            Mutation::FlipBump(mask) => {
+                if let Some(byte) = data.get_mut(BUMP_OFFSET) {
+                    *byte ^= mask;
+                }
+            }
+            Mutation::FlipByte { offset, mask } => {
+                if let Some(byte) = data.get_mut(offset) {
+                    *byte ^= mask;
+                }
+            }
+            Mutation::Discriminator { of, bytes } => {
+                let discriminator = match of {
+                    0 => Subscription::DISCRIMINATOR,
+                    1 => LegacySubscription::DISCRIMINATOR,
+                    2 => MerchantConfig::DISCRIMINATOR,
+                    3 => FundingSources::DISCRIMINATOR,
+                    _ => &bytes[..],
+                };
+                let len = data.len().min(8);
+                data[..len].copy_from_slice(&discriminator[..len]);
+            }
+            Mutation::Resize(len) => data.resize(len, 0),
+        }
+        self.fx.svm.set_account(address, account);
+    }
+
+    fn instruction(&self, op: &Op) -> Instruction {
+        match op {
+            Op::Charge => self.fx.charge_ix(),
+            Op::ChargeWithConfig => self.fx.charge_with_config_ix(self.config),
+            Op::ChargeWithFunding => {
+                self.fx
+                    .charge_with_funding_ix(Some(self.config), self.funding, &[self.fallback])
+            }
+            Op::Cancel => self.fx.cancel_ix(),
+            Op::Cleanup => self.fx.cleanup_ix(),
+            Op::Update(amount, interval) => self.fx.update_ix(*amount, *interval, None),
+            Op::Migrate => build(
+                accounts::MigrateSubscription {
+                    subscription: self.fx.subscription,
+                    plan_subscription: plan_subscription_address(&self.fx.subscription).0,
+                    payer: self.fx.payer.pubkey(),
+                    system_program: system_program::ID,
+                },
+                instruction::MigrateSubscription {},
+            ),
+            Op::Warp(_) => unreachable!("a warp sends nothing"),
+        }
+    }
+
+    fn run(&mut self, step: &Step) -> Result<(), TestCaseError> {
+        let (op, as_attacker) = match step {
+            Step::Corrupt(target, mutation) => {
+                self.corrupt(*target, mutation);
+                return Ok(());
+            }
+            Step::Send { op, as_attacker } => (op, *as_attacker),
+        };
+        if let Op::Warp(seconds) = op {
+            self.fx.svm.advance_time(*seconds);
+            return Ok(());
+        }
+
+        let mut instruction = self.instruction(op);
+        let authority = self.fx.authority.pubkey();
+        let signer = if as_attacker {
+            for meta in instruction.accounts.iter_mut() {
+                if meta.pubkey == authority {
+                    meta.pubkey = self.attacker.pubkey();
+                }
+            }
+            &self.attacker
+        } else {
+            &self.fx.authority
+        };
+        let signers: Vec<&Keypair> = if instruction
+            .accounts
+            .iter()
+            .any(|meta| meta.is_signer && meta.pubkey == signer.pubkey())
+        {
+            vec![signer]
+        } else {
+            Vec::new()
+        };
+        let authority_signed = signers.iter().any(|key| key.pubkey() == authority);
+
+        let authority_lamports_before = self.fx.svm.get_balance(&authority);
+        let subscription_lamports_before = self.fx.svm.get_balance(&self.fx.subscription);
+        let tokens_before = self.token_balances();
+
+        self.fx.svm.expire_blockhash();
+        let payer = self.fx.payer.insecure_clone();
+        let result = self
+            .fx
+            .svm
+            .send_instructions(&[instruction], &payer, &signers);
+        let meta = match &result {
+            Ok(meta) => meta,
+            Err(failed) => &failed.meta,
+        };
+        self.fees += meta.fee;
+
+        // Never a panic, only returned errors
+        let panicked = meta.logs.iter().find(|log| log.contains("panicked"));
+        prop_assert!(panicked.is_none(), "{:?}: {:?}", step, panicked);
+
+        // Lamports: conserved, and the subscription's rent only goes home
+        prop_assert_eq!(self.total_lamports() + self.fees, self.initial_lamports);
+        let subscription_lamports = self.fx.svm.get_balance(&self.fx.subscription);
+        if subscription_lamports < subscription_lamports_before {
+            prop_assert!(authority_signed, "{:?} closed the subscription", step);
+            prop_assert_eq!(
+                self.fx.svm.get_balance(&authority),
+                authority_lamports_before + subscription_lamports_before - subscription_lamports
+            );
+        }
+
+        // Tokens: a charge only moves them for the genuine subscription
+        let charging = matches!(
+            op,
+            Op::Charge | Op::ChargeWithConfig | Op::ChargeWithFunding
+        );
+        let tokens_after = self.token_balances();
+        if tokens_after != tokens_before {
+            prop_assert!(charging && result.is_ok(), "{:?} moved tokens", step);
+            let (Some(user_before), Some(merchant_before)) = tokens_before else {
+                unreachable!("a charge that lands reads both token accounts")
+            };
+            let (Some(user_after), Some(merchant_after)) = tokens_after else {
+                unreachable!("a charge that lands leaves both token accounts")
+            };
+            prop_assert_eq!(user_before - user_after, merchant_after - merchant_before);
+        }
+        if charging && !meta.accounts_at_cpi.is_empty() {
+            let (_, account) = meta
+                .accounts_at_cpi
+                .iter()
+                .find(|(key, _)| *key == self.fx.subscription)
+                .expect("subscription is writable");
+            let at_cpi = Subscription::try_deserialize(&mut &account.data[..]);
+            prop_assert!(at_cpi.is_ok(), "{:?} charged an unreadable account", step);
+            let at_cpi = at_cpi.unwrap();
+            prop_assert_eq!(at_cpi.authority, self.original.authority);
+            prop_assert_eq!(at_cpi.recipient, self.original.recipient);
+            prop_assert_eq!(at_cpi.bump, self.fx.bump);
+            prop_assert_eq!(at_cpi.user_token_account, self.original.user_token_account);
+            prop_assert_eq!(
+                at_cpi.recipient_token_account,
+                self.original.recipient_token_account
+            );
+        }
+
+        Ok(())
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn corrupted_accounts_keep_invariants(steps in prop::collection::vec(step(), 1..24)) {
+        let mut world = World::new();
+        for step in &steps {
+            world.run(step)?;
+        }
+    }
+}
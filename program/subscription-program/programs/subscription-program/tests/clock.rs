@@ -0,0 +1,188 @@
+//! Time-based behaviour over many billing cycles, driven by the harness's
+//! clock warps.
+
+mod common;
+
+use common::*;
+use subscription_program::{CronSchedule, ErrorCode, Interval};
+use test_harness::SLOT_DURATION_MS;
+
+const CYCLES: u64 = 24;
+
+#[test]
+fn warps_keep_slot_and_timestamp_in_step() {
+    let mut fx = Fixture::new();
+    let start = fx.svm.clock();
+
+    fx.svm.advance_time(60);
+    let clock = fx.svm.clock();
+    assert_eq!(clock.unix_timestamp, start.unix_timestamp + 60);
+    assert_eq!(
+        clock.slot,
+        start.slot + (60 * 1_000 / SLOT_DURATION_MS) as u64
+    );
+
+    fx.svm.warp_to_slot(clock.slot + 10);
+    assert_eq!(fx.svm.clock().unix_timestamp, clock.unix_timestamp + 4);
+
+    fx.svm.warp_to_timestamp(start.unix_timestamp);
+    assert_eq!(fx.svm.clock().unix_timestamp, start.unix_timestamp);
+    assert_eq!(fx.svm.clock().slot, clock.slot + 10);
+}
+
+#[test]
+fn each_cycle_opens_exactly_at_the_interval() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+
+    for _ in 0..CYCLES {
+        let due = fx.next_charge_at();
+
+        fx.svm.warp_to_timestamp(due - 1);
+        let ix = fx.charge_ix();
+        assert_program_error(fx.send(ix, &[]), ErrorCode::IntervalNotMet);
+
+        fx.svm.warp_to_timestamp(due);
+        let ix = fx.charge_ix();
+        assert_success(fx.send(ix, &[]));
+    }
+
+    let subscription = fx.subscription().unwrap();
+    assert_eq!(subscription.total_charged, (CYCLES + 1) * AMOUNT);
+    assert_eq!(
+        subscription.last_charge_timestamp,
+        subscription.created_at + CYCLES as i64 * INTERVAL
+    );
+    assert_eq!(
+        fx.svm.token_balance(&fx.recipient_token_account),
+        (CYCLES + 1) * AMOUNT
+    );
+}
+
+#[test]
+fn monthly_billing_follows_the_calendar() {
+    let mut fx = Fixture::new();
+    // 2024-01-31 10:00 UTC, in a leap year
+    fx.svm.warp_to_timestamp(1_706_695_200);
+    let created_at = fx.subscribe(None).created_at;
+    let ix = fx.update_ix(None, Some(Interval::Monthly.to_seconds_field()), None);
+    let authority = fx.authority.insecure_clone();
+    fx.send(ix, &[&authority]).unwrap();
+
+    // Feb 29, Mar 31, Apr 30, ... Jan 31, then Feb 28 of 2025
+    let days = [29, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31, 31, 28];
+    let mut expected = created_at;
+    for days in days {
+        expected += days * 86_400;
+        assert_eq!(fx.next_charge_at(), expected);
+
+        fx.svm.warp_to_timestamp(expected - 1);
+        let ix = fx.charge_ix();
+        assert_program_error(fx.send(ix, &[]), ErrorCode::IntervalNotMet);
+
+        fx.svm.warp_to_timestamp(expected);
+        let ix = fx.charge_ix();
+        assert_success(fx.send(ix, &[]));
+    }
+}
+
+#[test]
+fn cron_schedule_bills_at_local_business_time() {
+    let mut fx = Fixture::new();
+    // Friday 2024-03-01 15:00 UTC, 10:00 in New York
+    fx.svm.warp_to_timestamp(1_709_305_200);
+    fx.subscribe(None);
+    // 09:30 Monday to Friday, New York standard time
+    let schedule = Interval::Cron(CronSchedule {
+        minute: 30,
+        hour: 9,
+        weekdays: 0b011_1110,
+        days_of_month: CronSchedule::EVERY_DAY_OF_MONTH,
+        utc_offset_minutes: -5 * 60,
+    });
+    let ix = fx.update_ix(None, Some(schedule.to_seconds_field()), None);
+    let authority = fx.authority.insecure_clone();
+    fx.send(ix, &[&authority]).unwrap();
+
+    // Monday 2024-03-04 14:30 UTC
+    let monday = 1_709_562_600;
+    assert_eq!(fx.next_charge_at(), monday);
+
+    fx.svm.warp_to_timestamp(monday - 1);
+    let ix = fx.charge_ix();
+    assert_program_error(fx.send(ix, &[]), ErrorCode::IntervalNotMet);
+
+    fx.svm.warp_to_timestamp(monday);
+    let ix = fx.charge_ix();
+    assert_success(fx.send(ix, &[]));
+
+    // Then Tuesday, a day later
+    assert_eq!(fx.next_charge_at(), monday + 86_400);
+}
+
+#[test]
+fn charges_stop_at_expiry() {
+    let mut fx = Fixture::new();
+    let expires_at = fx.svm.clock().unix_timestamp + 3 * INTERVAL;
+    fx.subscribe(Some(expires_at));
+
+    for _ in 0..2 {
+        fx.svm.warp_to_timestamp(fx.next_charge_at());
+        fx.apply_charge();
+    }
+
+    // The third period would start exactly at expiry
+    fx.svm.warp_to_timestamp(fx.next_charge_at());
+    assert_eq!(fx.svm.clock().unix_timestamp, expires_at);
+    let ix = fx.charge_ix();
+    assert_program_error(fx.send(ix, &[]), ErrorCode::SubscriptionExpired);
+
+    fx.svm.advance_time(10 * INTERVAL);
+    let ix = fx.charge_ix();
+    assert_program_error(fx.send(ix, &[]), ErrorCode::SubscriptionExpired);
+    assert_eq!(fx.subscription().unwrap().total_charged, 3 * AMOUNT);
+}
+
+#[test]
+fn extending_expiry_resumes_billing() {
+    let mut fx = Fixture::new();
+    let expires_at = fx.svm.clock().unix_timestamp + INTERVAL;
+    fx.subscribe(Some(expires_at));
+    fx.svm.warp_to_timestamp(expires_at);
+
+    let ix = fx.update_ix(None, None, Some(expires_at + 12 * INTERVAL));
+    let authority = fx.authority.insecure_clone();
+    fx.send(ix, &[&authority]).unwrap();
+
+    let ix = fx.charge_ix();
+    assert_success(fx.send(ix, &[]));
+}
+
+#[test]
+fn late_charge_moves_the_schedule() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let created_at = fx.subscription().unwrap().created_at;
+    let late = 3 * 24 * 60 * 60;
+
+    // The keeper runs three days late; the next period starts from the late
+    // charge rather than the original anniversary
+    fx.svm.warp_to_timestamp(created_at + INTERVAL + late);
+    fx.apply_charge();
+    assert_eq!(fx.next_charge_at(), created_at + 2 * INTERVAL + late);
+
+    fx.svm.warp_to_timestamp(created_at + 2 * INTERVAL);
+    let ix = fx.charge_ix();
+    assert_program_error(fx.send(ix, &[]), ErrorCode::IntervalNotMet);
+}
+
+#[test]
+fn clock_behind_last_charge_is_not_due() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let created_at = fx.subscription().unwrap().created_at;
+
+    fx.svm.warp_to_timestamp(created_at - INTERVAL);
+    let ix = fx.charge_ix();
+    assert_program_error(fx.send(ix, &[]), ErrorCode::IntervalNotMet);
+}
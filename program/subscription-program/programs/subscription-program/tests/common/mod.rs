@@ -0,0 +1,1224 @@
+//! Fixtures shared by the native test suites.
+
+#![allow(dead_code)]
+
+use anchor_lang::error::ErrorCode as AnchorErrorCode;
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::{system_program, AccountDeserialize, InstructionData, Space, ToAccountMetas};
+use spending_limits::Policy;
+use subscription_program::{
+    accounts, instruction, AcceptedMint, AllowlistEntry, BillingCadence, DenylistEntry,
+    DowntimeAttestation, ErrorCode, FallbackPayment, FundingSources, MerchantConfig,
+    MerchantMultisig, MerchantVault, PayoutChange, Plan, PlanSubscription, PriceCache,
+    PythPriceUpdate, SlaCommitment, SlaCredit, SpendAlerts, SubscriberAllowlist, Subscription,
+    SubscriptionDeposit, UsdPeg, Waitlist, WaitlistEntry, WithdrawalDestination, ID as PROGRAM_ID,
+};
+pub use test_harness::{assert_success, instruction_error};
+use test_harness::{Account, InstructionError, Keypair, Signer, TestSvm, TransactionResult};
+
+/// 10 USDC
+pub const AMOUNT: u64 = 10_000_000;
+pub const INTERVAL: i64 = 30 * 24 * 60 * 60;
+pub const STARTING_BALANCE: u64 = 1_000 * 1_000_000;
+pub const SUBSCRIPTION_SPACE: usize = 8 + Subscription::INIT_SPACE;
+
+pub struct Fixture {
+    pub svm: TestSvm,
+    /// Rent and fee payer, standing in for the LazorKit paymaster
+    pub payer: Keypair,
+    pub authority: Keypair,
+    pub recipient: Pubkey,
+    pub mint: Pubkey,
+    pub user_token_account: Pubkey,
+    pub recipient_token_account: Pubkey,
+    pub subscription: Pubkey,
+    pub bump: u8,
+}
+
+impl Fixture {
+    /// A funded user and merchant with USDC ATAs, no subscription yet
+    pub fn new() -> Self {
+        let mut svm = TestSvm::new();
+        svm.add_program(PROGRAM_ID, subscription_program::entry);
+        svm.add_program(spending_limits::ID, spending_limits::entry);
+
+        let payer = Keypair::new();
+        let authority = Keypair::new();
+        let recipient = Pubkey::new_unique();
+        svm.airdrop(&payer.pubkey(), 10_000_000_000);
+        svm.airdrop(&authority.pubkey(), 1_000_000_000);
+
+        let mint = svm.create_mint(&Pubkey::new_unique(), 6);
+        let user_token_account =
+            svm.create_associated_token_account(&authority.pubkey(), &mint, STARTING_BALANCE);
+        let recipient_token_account = svm.create_associated_token_account(&recipient, &mint, 0);
+
+        let (subscription, bump) = Pubkey::find_program_address(
+            &[
+                b"subscription",
+                authority.pubkey().as_ref(),
+                recipient.as_ref(),
+            ],
+            &PROGRAM_ID,
+        );
+
+        Self {
+            svm,
+            payer,
+            authority,
+            recipient,
+            mint,
+            user_token_account,
+            recipient_token_account,
+            subscription,
+            bump,
+        }
+    }
+
+    /// Replace the recipient with a keypair the test can sign with, along
+    /// with its token account and the subscription address. Call it before
+    /// `subscribe`.
+    pub fn recipient_signer(&mut self) -> Keypair {
+        let recipient = Keypair::new();
+        self.recipient = recipient.pubkey();
+        self.recipient_token_account =
+            self.svm
+                .create_associated_token_account(&self.recipient, &self.mint, 0);
+        (self.subscription, self.bump) = Pubkey::find_program_address(
+            &[
+                b"subscription",
+                self.authority.pubkey().as_ref(),
+                self.recipient.as_ref(),
+            ],
+            &PROGRAM_ID,
+        );
+        recipient
+    }
+
+    /// Put the ledger in the state `initialize_subscription` leaves it in:
+    /// account created, delegation approved, first period paid
+    pub fn subscribe(&mut self, expires_at: Option<i64>) -> Subscription {
+        let now = self.svm.clock().unix_timestamp;
+        let subscription = Subscription {
+            authority: self.authority.pubkey(),
+            recipient: self.recipient,
+            user_token_account: self.user_token_account,
+            recipient_token_account: self.recipient_token_account,
+            token_mint: self.mint,
+            amount_per_period: AMOUNT,
+            interval_seconds: INTERVAL,
+            last_charge_timestamp: now,
+            next_charge_at: now + INTERVAL,
+            created_at: now,
+            expires_at,
+            is_active: true,
+            total_charged: AMOUNT,
+            bump: self.bump,
+            on_plan: false,
+            max_total_spend: Subscription::NO_SPEND_LIMIT,
+        };
+        self.set_subscription(&subscription);
+
+        self.svm
+            .approve(&self.user_token_account, &self.subscription, u64::MAX);
+        self.svm.transfer_tokens(
+            &self.user_token_account,
+            &self.recipient_token_account,
+            AMOUNT,
+        );
+
+        subscription
+    }
+
+    /// Charge the subscription, which must succeed, so multi-cycle tests can
+    /// continue past a charge
+    pub fn apply_charge(&mut self) {
+        let ix = self.charge_ix();
+        assert_success(self.send(ix, &[]));
+    }
+
+    /// Earliest time the next charge is accepted
+    pub fn next_charge_at(&self) -> i64 {
+        self.subscription()
+            .expect("subscription exists")
+            .next_charge_at
+    }
+
+    pub fn set_subscription(&mut self, subscription: &Subscription) {
+        self.svm
+            .set_anchor_account(self.subscription, subscription, SUBSCRIPTION_SPACE);
+    }
+
+    pub fn subscription(&self) -> Option<Subscription> {
+        self.svm.get_anchor_account(&self.subscription)
+    }
+
+    /// Send `instruction` with the fixture payer plus `signers`
+    pub fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TransactionResult {
+        let payer = self.payer.insecure_clone();
+        self.svm.send_instructions(&[instruction], &payer, signers)
+    }
+
+    /// Send `instruction`, which must succeed, and return the subscription as
+    /// the program had written it when it made its first CPI
+    pub fn subscription_at_cpi(&mut self, instruction: Instruction) -> Subscription {
+        let subscription = self.subscription;
+        self.accounts_at_cpi(instruction)
+            .iter()
+            .find(|(key, _)| *key == subscription)
+            .map(|(_, account)| Subscription::try_deserialize(&mut &account.data[..]).unwrap())
+            .expect("subscription is writable")
+    }
+
+    /// Send `instruction`, which must succeed, and return its writable
+    /// accounts as the program had written them when it made its first CPI
+    pub fn accounts_at_cpi(&mut self, instruction: Instruction) -> Vec<(Pubkey, Account)> {
+        let result = self.send(instruction, &[]);
+        assert_success(result).accounts_at_cpi
+    }
+
+    pub fn initialize_ix(
+        &self,
+        amount_per_period: u64,
+        interval_seconds: i64,
+        expires_at: Option<i64>,
+    ) -> Instruction {
+        build(
+            accounts::InitializeSubscription {
+                subscription: self.subscription,
+                authority: self.authority.pubkey(),
+                recipient: self.recipient,
+                user_token_account: self.user_token_account,
+                recipient_token_account: self.recipient_token_account,
+                token_mint: self.mint,
+                token_program: spl_token::ID,
+                payer: self.payer.pubkey(),
+                system_program: system_program::ID,
+                denylist_entry: denylist_address(&self.recipient, &self.authority.pubkey()).0,
+                allowlist: subscriber_allowlist_address(&self.recipient).0,
+                allowlist_entry: allowlist_entry_address(&self.recipient, &self.authority.pubkey())
+                    .0,
+            },
+            instruction::InitializeSubscription {
+                amount_per_period,
+                interval_seconds,
+                expires_at,
+            },
+        )
+    }
+
+    pub fn initialize_with_setup_fee_ix(&self, setup_fee: u64) -> Instruction {
+        build(
+            accounts::InitializeSubscriptionWithSetupFee {
+                init: accounts::InitializeSubscription {
+                    subscription: self.subscription,
+                    authority: self.authority.pubkey(),
+                    recipient: self.recipient,
+                    user_token_account: self.user_token_account,
+                    recipient_token_account: self.recipient_token_account,
+                    token_mint: self.mint,
+                    token_program: spl_token::ID,
+                    payer: self.payer.pubkey(),
+                    system_program: system_program::ID,
+                    denylist_entry: denylist_address(&self.recipient, &self.authority.pubkey()).0,
+                    allowlist: subscriber_allowlist_address(&self.recipient).0,
+                    allowlist_entry: allowlist_entry_address(
+                        &self.recipient,
+                        &self.authority.pubkey(),
+                    )
+                    .0,
+                },
+                setup_fee: setup_fee_address(&self.subscription).0,
+                system_program: system_program::ID,
+            },
+            instruction::InitializeSubscriptionWithSetupFee {
+                amount_per_period: AMOUNT,
+                interval_seconds: INTERVAL,
+                expires_at: None,
+                setup_fee,
+            },
+        )
+    }
+
+    pub fn charge_ix(&self) -> Instruction {
+        build(
+            accounts::ChargeSubscription {
+                subscription: self.subscription,
+                user_token_account: self.user_token_account,
+                recipient_token_account: self.recipient_token_account,
+                token_program: spl_token::ID,
+            },
+            instruction::ChargeSubscription {},
+        )
+    }
+
+    /// Put the user's token account behind a spending policy listing the
+    /// subscription, as `create_policy` would; returns the policy address
+    pub fn set_policy(&mut self, daily_limit: u64) -> Pubkey {
+        let (policy, bump) = Pubkey::find_program_address(
+            &[b"spending_policy", self.user_token_account.as_ref()],
+            &spending_limits::ID,
+        );
+        let now = self.svm.clock().unix_timestamp;
+        let state = Policy {
+            wallet: self.authority.pubkey(),
+            token_account: self.user_token_account,
+            mint: self.mint,
+            daily_limit,
+            weekly_limit: 0,
+            spenders: vec![self.subscription],
+            day: now.div_euclid(spending_limits::DAY),
+            day_spent: 0,
+            week: now.div_euclid(spending_limits::WEEK),
+            week_spent: 0,
+            bump,
+        };
+        self.svm
+            .set_anchor_account(policy, &state, 8 + Policy::INIT_SPACE);
+        self.svm
+            .approve(&self.user_token_account, &policy, u64::MAX);
+        policy
+    }
+
+    pub fn charge_with_policy_ix(&self, policy: Pubkey) -> Instruction {
+        build(
+            accounts::ChargeSubscriptionWithPolicy {
+                subscription: self.subscription,
+                policy,
+                user_token_account: self.user_token_account,
+                recipient_token_account: self.recipient_token_account,
+                token_program: spl_token::ID,
+                spending_limits_program: spending_limits::ID,
+            },
+            instruction::ChargeSubscriptionWithPolicy {},
+        )
+    }
+
+    /// Record the recipient's merchant settings, as `create_merchant_config`
+    /// would; returns the config address
+    pub fn set_merchant_config(
+        &mut self,
+        allow_partial_charges: bool,
+        max_periods_per_charge: u8,
+    ) -> Pubkey {
+        let (config, bump) = merchant_config_address(&self.recipient);
+        let state = MerchantConfig {
+            recipient: self.recipient,
+            allow_partial_charges,
+            max_periods_per_charge,
+            bump,
+        };
+        self.svm
+            .set_anchor_account(config, &state, 8 + MerchantConfig::INIT_SPACE);
+        config
+    }
+
+    /// Put the recipient's settings under a multisig, as
+    /// `set_merchant_multisig` would, but with `vault` standing in for the
+    /// Squads vault so tests can sign as it
+    pub fn set_merchant_multisig(&mut self, vault: Pubkey) -> Pubkey {
+        let (address, bump) = merchant_multisig_address(&self.recipient);
+        let state = MerchantMultisig {
+            recipient: self.recipient,
+            multisig: Pubkey::new_unique(),
+            vault_index: 0,
+            vault,
+            bump,
+        };
+        self.svm
+            .set_anchor_account(address, &state, 8 + MerchantMultisig::INIT_SPACE);
+        address
+    }
+
+    /// Schedule `token_account` as the recipient's payout account, as
+    /// `set_recipient_token_account` would, effective at `effective_at`;
+    /// returns the `PayoutChange` address
+    pub fn set_payout_change(&mut self, token_account: Pubkey, effective_at: i64) -> Pubkey {
+        let (address, bump) = payout_change_address(&self.recipient);
+        let state = PayoutChange {
+            recipient: self.recipient,
+            token_account,
+            effective_at,
+            bump,
+        };
+        self.svm
+            .set_anchor_account(address, &state, 8 + PayoutChange::INIT_SPACE);
+        address
+    }
+
+    /// Open the recipient's revenue vault, as `create_merchant_vault` would,
+    /// holding `balance`; returns the vault's token account
+    pub fn set_merchant_vault(&mut self, balance: u64) -> Pubkey {
+        let (address, bump) = merchant_vault_address(&self.recipient);
+        let token_account = self.svm.create_token_account(&address, &self.mint, balance);
+        let state = MerchantVault {
+            recipient: self.recipient,
+            token_account,
+            token_mint: self.mint,
+            total_withdrawn: 0,
+            total_received: 0,
+            holdback_bps: 0,
+            holdback_days: 0,
+            holds: Vec::new(),
+            withdrawal_limit: 0,
+            withdrawal_period_seconds: 0,
+            withdrawal_admin: Pubkey::default(),
+            period_started_at: 0,
+            withdrawn_in_period: 0,
+            destinations: Vec::new(),
+            bump,
+        };
+        self.svm
+            .set_anchor_account(address, &state, 8 + MerchantVault::INIT_SPACE);
+        token_account
+    }
+
+    /// Set the holdback of the recipient's vault, as `set_vault_holdback`
+    /// would before any revenue arrived
+    pub fn set_vault_holdback(&mut self, holdback_bps: u16, holdback_days: u16) {
+        let address = merchant_vault_address(&self.recipient).0;
+        let mut state: MerchantVault = self.svm.get_anchor_account(&address).unwrap();
+        state.holdback_bps = holdback_bps;
+        state.holdback_days = holdback_days;
+        self.svm
+            .set_anchor_account(address, &state, 8 + MerchantVault::INIT_SPACE);
+    }
+
+    /// Cap the recipient's vault, as `set_withdrawal_limit` would
+    pub fn set_withdrawal_limit(&mut self, limit: u64, period_seconds: i64, admin: Pubkey) {
+        let address = merchant_vault_address(&self.recipient).0;
+        let mut state: MerchantVault = self.svm.get_anchor_account(&address).unwrap();
+        state.withdrawal_limit = limit;
+        state.withdrawal_period_seconds = period_seconds;
+        state.withdrawal_admin = admin;
+        self.svm
+            .set_anchor_account(address, &state, 8 + MerchantVault::INIT_SPACE);
+    }
+
+    /// A token account of the recipient's, approved as a destination of its
+    /// vault with the timelock already run out
+    pub fn approved_destination(&mut self) -> Pubkey {
+        let token_account = self
+            .svm
+            .create_token_account(&self.recipient, &self.mint, 0);
+        let address = merchant_vault_address(&self.recipient).0;
+        let mut state: MerchantVault = self.svm.get_anchor_account(&address).unwrap();
+        state.destinations.push(WithdrawalDestination {
+            token_account,
+            effective_at: 0,
+        });
+        self.svm
+            .set_anchor_account(address, &state, 8 + MerchantVault::INIT_SPACE);
+        token_account
+    }
+
+    /// Commit the recipient to an SLA attested by `attester`, as
+    /// `create_sla_commitment` would at time 0
+    pub fn set_sla_commitment(&mut self, attester: Pubkey, credit_multiplier: u16) -> Pubkey {
+        let (address, bump) = sla_address(&self.recipient);
+        let state = SlaCommitment {
+            recipient: self.recipient,
+            attester,
+            credit_multiplier,
+            committed_at: 0,
+            ends_at: i64::MAX,
+            bump,
+        };
+        self.svm
+            .set_anchor_account(address, &state, 8 + SlaCommitment::INIT_SPACE);
+        address
+    }
+
+    /// An outage of the recipient's service, as `attest_downtime` records it
+    pub fn set_downtime(&mut self, attester: Pubkey, started_at: i64, ended_at: i64) -> Pubkey {
+        let (address, bump) = downtime_address(&self.recipient, started_at);
+        let state = DowntimeAttestation {
+            recipient: self.recipient,
+            attester,
+            started_at,
+            ended_at,
+            bump,
+        };
+        self.svm
+            .set_anchor_account(address, &state, 8 + DowntimeAttestation::INIT_SPACE);
+        address
+    }
+
+    /// The subscription's receipt for an outage, as `schedule_sla_discount`
+    /// leaves it with `pending` still to come off its charges
+    pub fn set_sla_discount(&mut self, attestation: Pubkey, pending: u64) -> Pubkey {
+        let (address, bump) = sla_credit_address(&attestation, &self.subscription);
+        let state = SlaCredit {
+            attestation,
+            subscription: self.subscription,
+            amount: pending,
+            pending,
+            bump,
+        };
+        self.svm
+            .set_anchor_account(address, &state, 8 + SlaCredit::INIT_SPACE);
+        address
+    }
+
+    pub fn merchant_vault(&self) -> MerchantVault {
+        let address = merchant_vault_address(&self.recipient).0;
+        self.svm.get_anchor_account(&address).unwrap()
+    }
+
+    /// `withdraw_revenue` from the recipient's vault, signed by `authority`
+    pub fn withdraw_revenue_ix(
+        &self,
+        authority: &Pubkey,
+        token_account: Pubkey,
+        destination: Pubkey,
+        amount: u64,
+    ) -> Instruction {
+        build(
+            accounts::WithdrawRevenue {
+                vault: merchant_vault_address(&self.recipient).0,
+                merchant_multisig: merchant_multisig_address(&self.recipient).0,
+                recipient: self.recipient,
+                authority: *authority,
+                token_account,
+                destination,
+                token_program: spl_token::ID,
+            },
+            instruction::WithdrawRevenue { amount },
+        )
+    }
+
+    pub fn apply_payout_ix(&self, token_account: Pubkey) -> Instruction {
+        build(
+            accounts::ApplyRecipientTokenAccount {
+                subscription: self.subscription,
+                payout_change: payout_change_address(&self.recipient).0,
+                token_account,
+            },
+            instruction::ApplyRecipientTokenAccount {},
+        )
+    }
+
+    /// `update_merchant_config` for the fixture's recipient, signed by `authority`
+    pub fn update_merchant_config_ix(
+        &self,
+        authority: &Pubkey,
+        allow_partial: bool,
+    ) -> Instruction {
+        build(
+            accounts::UpdateMerchantConfig {
+                merchant_config: merchant_config_address(&self.recipient).0,
+                merchant_multisig: merchant_multisig_address(&self.recipient).0,
+                recipient: self.recipient,
+                authority: *authority,
+            },
+            instruction::UpdateMerchantConfig {
+                allow_partial_charges: allow_partial,
+                max_periods_per_charge: 3,
+            },
+        )
+    }
+
+    /// `charge_subscription` with `config` as the merchant config
+    pub fn charge_with_config_ix(&self, config: Pubkey) -> Instruction {
+        let mut instruction = self.charge_ix();
+        instruction
+            .accounts
+            .push(AccountMeta::new_readonly(config, false));
+        instruction
+    }
+
+    /// The cache of `feed_id` after a refresh to `price` x 10^`exponent`
+    /// published at `publish_time`
+    pub fn set_price_cache(
+        &mut self,
+        feed_id: [u8; 32],
+        price: i64,
+        exponent: i32,
+        publish_time: i64,
+    ) -> Pubkey {
+        let (address, bump) = price_cache_address(&feed_id);
+        let state = PriceCache {
+            feed_id,
+            price,
+            conf: 0,
+            exponent,
+            publish_time,
+            bump,
+        };
+        self.svm
+            .set_anchor_account(address, &state, 8 + PriceCache::INIT_SPACE);
+        address
+    }
+
+    /// Price the subscription at `usd_per_period` through `price_cache`, as
+    /// `set_usd_price` leaves it
+    pub fn set_usd_peg(&mut self, price_cache: Pubkey, usd_per_period: u64) -> Pubkey {
+        let (address, bump) = usd_peg_address(&self.subscription);
+        let state = UsdPeg {
+            subscription: self.subscription,
+            price_cache,
+            usd_per_period,
+            token_decimals: 6,
+            bump,
+        };
+        self.svm
+            .set_anchor_account(address, &state, 8 + UsdPeg::INIT_SPACE);
+        let mut subscription = self.subscription().expect("subscription exists");
+        subscription.amount_per_period = 0;
+        self.set_subscription(&subscription);
+        address
+    }
+
+    /// `charge_subscription_usd` at the price in `price_cache`
+    pub fn charge_usd_ix(&self, price_cache: Pubkey) -> Instruction {
+        let mut instruction = self.charge_ix();
+        instruction.accounts.extend([
+            AccountMeta::new_readonly(usd_peg_address(&self.subscription).0, false),
+            AccountMeta::new_readonly(price_cache, false),
+        ]);
+        instruction.data = instruction::ChargeSubscriptionUsd {}.data();
+        instruction
+    }
+
+    /// Have the recipient accept a second 6-decimal mint and the user fall
+    /// back to an approved account of it holding `amount`, as
+    /// `accept_fallback_mint` and `set_fallback_payment` leave them
+    pub fn set_fallback_payment(&mut self, amount: u64) -> FallbackPayment {
+        let token_mint = self.svm.create_mint(&Pubkey::new_unique(), 6);
+        let merchant_account = self
+            .svm
+            .create_token_account(&self.recipient, &token_mint, 0);
+        let (accepted, bump) = accepted_mint_address(&self.recipient, &token_mint);
+        let state = AcceptedMint {
+            recipient: self.recipient,
+            token_mint,
+            token_account: merchant_account,
+            bump,
+        };
+        self.svm
+            .set_anchor_account(accepted, &state, 8 + AcceptedMint::INIT_SPACE);
+
+        let token_account =
+            self.svm
+                .create_token_account(&self.authority.pubkey(), &token_mint, amount);
+        self.svm
+            .approve(&token_account, &self.subscription, u64::MAX);
+        let (address, bump) = fallback_payment_address(&self.subscription);
+        let state = FallbackPayment {
+            subscription: self.subscription,
+            token_mint,
+            token_account,
+            bump,
+        };
+        self.svm
+            .set_anchor_account(address, &state, 8 + FallbackPayment::INIT_SPACE);
+        state
+    }
+
+    /// `charge_subscription_fallback` from `fallback` into the account the
+    /// recipient accepts its mint in
+    pub fn charge_fallback_ix(&self, fallback: &FallbackPayment) -> Instruction {
+        let accepted_mint = accepted_mint_address(&self.recipient, &fallback.token_mint).0;
+        let merchant_token_account = self
+            .svm
+            .get_anchor_account::<AcceptedMint>(&accepted_mint)
+            .expect("mint is accepted")
+            .token_account;
+        build(
+            accounts::ChargeSubscriptionFallback {
+                charge: accounts::ChargeSubscription {
+                    subscription: self.subscription,
+                    user_token_account: self.user_token_account,
+                    recipient_token_account: self.recipient_token_account,
+                    token_program: spl_token::ID,
+                },
+                fallback_payment: fallback_payment_address(&self.subscription).0,
+                token_account: fallback.token_account,
+                accepted_mint,
+                merchant_token_account,
+            },
+            instruction::ChargeSubscriptionFallback {},
+        )
+    }
+
+    /// A deposit of `amount` held in its escrow, as `pay_deposit` leaves it
+    pub fn set_deposit(&mut self, amount: u64) -> SubscriptionDeposit {
+        let (address, bump) = deposit_address(&self.subscription);
+        let token_account = self.svm.create_token_account(&address, &self.mint, amount);
+        let state = SubscriptionDeposit {
+            subscription: self.subscription,
+            authority: self.authority.pubkey(),
+            recipient: self.recipient,
+            token_account,
+            refund_account: self.user_token_account,
+            amount,
+            paid_at: self.svm.clock().unix_timestamp,
+            bump,
+        };
+        self.svm
+            .set_anchor_account(address, &state, 8 + SubscriptionDeposit::INIT_SPACE);
+        state
+    }
+
+    pub fn refund_deposit_ix(&self, deposit: &SubscriptionDeposit) -> Instruction {
+        build(
+            accounts::RefundDeposit {
+                deposit: deposit_address(&self.subscription).0,
+                subscription: self.subscription,
+                token_account: deposit.token_account,
+                refund_account: deposit.refund_account,
+                authority: deposit.authority,
+                token_program: spl_token::ID,
+            },
+            instruction::RefundDeposit {},
+        )
+    }
+
+    /// A plan at `AMOUNT` a month with 15% off annually, and the
+    /// subscription on it at `cadence`, as `create_plan` and `join_plan`
+    /// leave them
+    pub fn set_plan(&mut self, cadence: BillingCadence) -> Plan {
+        let (address, bump) = plan_address(&self.recipient, 1);
+        let plan = Plan {
+            recipient: self.recipient,
+            plan_id: 1,
+            token_mint: self.mint,
+            monthly_price: AMOUNT,
+            annual_discount_bps: 1_500,
+            included_units: 0,
+            upgrade_after_periods: 0,
+            next_tier: None,
+            overage_rate: 0,
+            max_subscribers: 0,
+            subscribers: 1,
+            intro_discount_bps: 0,
+            intro_periods: 0,
+            price_ramp: Vec::new(),
+            bump,
+        };
+        self.svm
+            .set_anchor_account(address, &plan, 8 + Plan::INIT_SPACE);
+
+        let (plan_subscription, bump) = plan_subscription_address(&self.subscription);
+        let state = PlanSubscription {
+            subscription: self.subscription,
+            plan: address,
+            cadence,
+            period_start: self
+                .subscription()
+                .expect("subscription exists")
+                .last_charge_timestamp,
+            units: 0,
+            periods_over: 0,
+            upgrade_at: None,
+            overage_billed: 0,
+            overage_charged: 0,
+            price_steps: Vec::new(),
+            full_price: plan.price(cadence).unwrap(),
+            bump,
+        };
+        self.svm
+            .set_anchor_account(plan_subscription, &state, 8 + PlanSubscription::INIT_SPACE);
+
+        let mut subscription = self.subscription().expect("subscription exists");
+        plan.apply(&mut subscription, cadence).unwrap();
+        subscription.on_plan = true;
+        self.set_subscription(&subscription);
+        plan
+    }
+
+    /// Meter plan 1 with `included_units` a period, moving to plan 2 at
+    /// twice the price after `upgrade_after_periods` over it; returns plan 2
+    pub fn set_next_tier(&mut self, included_units: u64, upgrade_after_periods: u8) -> Pubkey {
+        let (address, bump) = plan_address(&self.recipient, 2);
+        let mut plan: Plan = self
+            .svm
+            .get_anchor_account(&plan_address(&self.recipient, 1).0)
+            .expect("plan exists");
+        let next_tier = Plan {
+            plan_id: 2,
+            monthly_price: 2 * AMOUNT,
+            subscribers: 0,
+            intro_discount_bps: 0,
+            intro_periods: 0,
+            price_ramp: Vec::new(),
+            bump,
+            ..plan.clone()
+        };
+        self.svm
+            .set_anchor_account(address, &next_tier, 8 + Plan::INIT_SPACE);
+
+        plan.included_units = included_units;
+        plan.upgrade_after_periods = upgrade_after_periods;
+        plan.next_tier = Some(address);
+        self.svm.set_anchor_account(
+            plan_address(&self.recipient, 1).0,
+            &plan,
+            8 + Plan::INIT_SPACE,
+        );
+        address
+    }
+
+    pub fn plan(&self, plan_id: u64) -> Plan {
+        self.svm
+            .get_anchor_account(&plan_address(&self.recipient, plan_id).0)
+            .expect("plan exists")
+    }
+
+    pub fn plan_subscription(&self) -> PlanSubscription {
+        self.svm
+            .get_anchor_account(&plan_subscription_address(&self.subscription).0)
+            .expect("subscription is on a plan")
+    }
+
+    /// The subscription's spend milestones, as `create_spend_alerts` would
+    /// write them
+    pub fn set_spend_alerts(&mut self, thresholds: &[u64]) -> Pubkey {
+        let (address, bump) = spend_alerts_address(&self.subscription);
+        let alerts = SpendAlerts {
+            subscription: self.subscription,
+            authority: self.authority.pubkey(),
+            thresholds: thresholds.to_vec(),
+            bump,
+        };
+        self.svm
+            .set_anchor_account(address, &alerts, 8 + SpendAlerts::INIT_SPACE);
+        address
+    }
+
+    /// Put the fixture's authority on the recipient's denylist, as
+    /// `deny_subscriber` would
+    pub fn set_denylist_entry(&mut self) -> Pubkey {
+        let (address, bump) = denylist_address(&self.recipient, &self.authority.pubkey());
+        let entry = DenylistEntry {
+            recipient: self.recipient,
+            wallet: self.authority.pubkey(),
+            denied_at: self.svm.clock().unix_timestamp,
+            bump,
+        };
+        self.svm
+            .set_anchor_account(address, &entry, 8 + DenylistEntry::INIT_SPACE);
+        address
+    }
+
+    /// Make the recipient invite-only, as `create_subscriber_allowlist`
+    /// would
+    pub fn set_subscriber_allowlist(&mut self) -> Pubkey {
+        let (address, bump) = subscriber_allowlist_address(&self.recipient);
+        let allowlist = SubscriberAllowlist {
+            recipient: self.recipient,
+            created_at: self.svm.clock().unix_timestamp,
+            bump,
+        };
+        self.svm
+            .set_anchor_account(address, &allowlist, 8 + SubscriberAllowlist::INIT_SPACE);
+        address
+    }
+
+    /// Put the fixture's authority on the recipient's allowlist, as
+    /// `add_to_allowlist` would
+    pub fn set_allowlist_entry(&mut self) -> Pubkey {
+        let (address, bump) = allowlist_entry_address(&self.recipient, &self.authority.pubkey());
+        let entry = AllowlistEntry {
+            recipient: self.recipient,
+            wallet: self.authority.pubkey(),
+            allowed_at: self.svm.clock().unix_timestamp,
+            bump,
+        };
+        self.svm
+            .set_anchor_account(address, &entry, 8 + AllowlistEntry::INIT_SPACE);
+        address
+    }
+
+    /// A waitlist on plan 1 with entries from `head` to `tail`
+    pub fn set_waitlist(&mut self, head: u64, tail: u64) {
+        let plan = plan_address(&self.recipient, 1).0;
+        let (address, bump) = waitlist_address(&plan);
+        let waitlist = Waitlist {
+            plan,
+            head,
+            tail,
+            bump,
+        };
+        self.svm
+            .set_anchor_account(address, &waitlist, 8 + Waitlist::INIT_SPACE);
+    }
+
+    pub fn waitlist(&self) -> Waitlist {
+        self.svm
+            .get_anchor_account(&waitlist_address(&plan_address(&self.recipient, 1).0).0)
+            .expect("waitlist exists")
+    }
+
+    /// The fixture's authority waiting at `position` of plan 1's waitlist,
+    /// paid for by the fixture's payer, as `join_waitlist` leaves it
+    pub fn set_waitlist_entry(&mut self, position: u64) -> WaitlistEntry {
+        let plan = plan_address(&self.recipient, 1).0;
+        let (address, bump) = waitlist_entry_address(&plan, position);
+        let entry = WaitlistEntry {
+            plan,
+            position,
+            authority: self.authority.pubkey(),
+            payer: self.payer.pubkey(),
+            user_token_account: self.user_token_account,
+            recipient_token_account: self.recipient_token_account,
+            cadence: BillingCadence::Monthly,
+            joined_at: self.svm.clock().unix_timestamp,
+            bump,
+        };
+        self.svm
+            .set_anchor_account(address, &entry, 8 + WaitlistEntry::INIT_SPACE);
+        entry
+    }
+
+    /// Skip the entry at `position`, whose wallet is the fixture's authority
+    pub fn skip_waitlist_entry_ix(&self, position: u64) -> Instruction {
+        let plan = plan_address(&self.recipient, 1).0;
+        let wallet = self.authority.pubkey();
+        build(
+            accounts::SkipWaitlistEntry {
+                waitlist: waitlist_address(&plan).0,
+                entry: waitlist_entry_address(&plan, position).0,
+                plan,
+                subscription: self.subscription,
+                denylist_entry: denylist_address(&self.recipient, &wallet).0,
+                allowlist: subscriber_allowlist_address(&self.recipient).0,
+                allowlist_entry: allowlist_entry_address(&self.recipient, &wallet).0,
+                user_token_account: self.user_token_account,
+                rent_receiver: self.payer.pubkey(),
+            },
+            instruction::SkipWaitlistEntry {},
+        )
+    }
+
+    pub fn set_plan_subscription(&mut self, state: &PlanSubscription) {
+        self.svm.set_anchor_account(
+            plan_subscription_address(&self.subscription).0,
+            state,
+            8 + PlanSubscription::INIT_SPACE,
+        );
+    }
+
+    pub fn record_usage_ix(&self, authority: &Pubkey, units: u64) -> Instruction {
+        build(
+            accounts::RecordUsage {
+                plan_subscription: plan_subscription_address(&self.subscription).0,
+                subscription: self.subscription,
+                plan: plan_address(&self.recipient, 1).0,
+                merchant_multisig: merchant_multisig_address(&self.recipient).0,
+                recipient: self.recipient,
+                authority: *authority,
+            },
+            instruction::RecordUsage { units },
+        )
+    }
+
+    pub fn charge_overage_ix(&self, authority: &Pubkey, units: u64) -> Instruction {
+        build(
+            accounts::ChargeOverage {
+                plan_subscription: plan_subscription_address(&self.subscription).0,
+                subscription: self.subscription,
+                plan: plan_address(&self.recipient, 1).0,
+                merchant_multisig: merchant_multisig_address(&self.recipient).0,
+                recipient: self.recipient,
+                authority: *authority,
+                user_token_account: self.user_token_account,
+                recipient_token_account: self.recipient_token_account,
+                token_program: spl_token::ID,
+            },
+            instruction::ChargeOverage { units },
+        )
+    }
+
+    pub fn switch_cadence_ix(&self, cadence: BillingCadence) -> Instruction {
+        build(
+            accounts::SwitchBillingCadence {
+                subscription: self.subscription,
+                plan_subscription: plan_subscription_address(&self.subscription).0,
+                plan: plan_address(&self.recipient, 1).0,
+                user_token_account: self.user_token_account,
+                recipient_token_account: self.recipient_token_account,
+                authority: self.authority.pubkey(),
+                token_program: spl_token::ID,
+            },
+            instruction::SwitchBillingCadence { cadence },
+        )
+    }
+
+    /// A fallback token account of the user, approved to the subscription
+    /// and holding `amount`
+    pub fn fallback_account(&mut self, amount: u64) -> Pubkey {
+        let account = self
+            .svm
+            .create_token_account(&self.authority.pubkey(), &self.mint, amount);
+        self.svm.approve(&account, &self.subscription, u64::MAX);
+        account
+    }
+
+    /// Register `token_accounts` as fallbacks, as `create_funding_sources`
+    /// would; returns the `FundingSources` address
+    pub fn set_funding_sources(&mut self, token_accounts: &[Pubkey]) -> Pubkey {
+        let (funding, bump) = funding_sources_address(&self.subscription);
+        let state = FundingSources {
+            subscription: self.subscription,
+            authority: self.authority.pubkey(),
+            token_accounts: token_accounts.to_vec(),
+            bump,
+        };
+        self.svm
+            .set_anchor_account(funding, &state, 8 + FundingSources::INIT_SPACE);
+        funding
+    }
+
+    /// `charge_subscription` with `config` (or the program ID placeholder),
+    /// then `funding` and `sources`
+    pub fn charge_with_funding_ix(
+        &self,
+        config: Option<Pubkey>,
+        funding: Pubkey,
+        sources: &[Pubkey],
+    ) -> Instruction {
+        let mut instruction = self.charge_with_config_ix(config.unwrap_or(PROGRAM_ID));
+        instruction
+            .accounts
+            .push(AccountMeta::new_readonly(funding, false));
+        instruction.accounts.extend(
+            sources
+                .iter()
+                .map(|source| AccountMeta::new(*source, false)),
+        );
+        instruction
+    }
+
+    pub fn cancel_ix(&self) -> Instruction {
+        build(
+            accounts::CancelSubscription {
+                subscription: self.subscription,
+                authority: self.authority.pubkey(),
+                user_token_account: self.user_token_account,
+                token_program: spl_token::ID,
+            },
+            instruction::CancelSubscription {},
+        )
+    }
+
+    pub fn cleanup_ix(&self) -> Instruction {
+        build(
+            accounts::CleanupCancelledSubscription {
+                subscription: self.subscription,
+                authority: self.authority.pubkey(),
+            },
+            instruction::CleanupCancelledSubscription {},
+        )
+    }
+
+    pub fn update_ix(
+        &self,
+        new_amount: Option<u64>,
+        new_interval: Option<i64>,
+        new_expires_at: Option<i64>,
+    ) -> Instruction {
+        build(
+            accounts::UpdateSubscription {
+                subscription: self.subscription,
+                authority: self.authority.pubkey(),
+            },
+            instruction::UpdateSubscription {
+                new_amount,
+                new_interval,
+                new_expires_at,
+            },
+        )
+    }
+}
+
+pub fn merchant_config_address(recipient: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"merchant_config", recipient.as_ref()], &PROGRAM_ID)
+}
+
+pub fn merchant_multisig_address(recipient: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"merchant_multisig", recipient.as_ref()], &PROGRAM_ID)
+}
+
+pub fn payout_change_address(recipient: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"payout_change", recipient.as_ref()], &PROGRAM_ID)
+}
+
+pub fn merchant_vault_address(recipient: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"merchant_vault", recipient.as_ref()], &PROGRAM_ID)
+}
+
+pub fn sla_address(recipient: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"sla", recipient.as_ref()], &PROGRAM_ID)
+}
+
+pub fn downtime_address(recipient: &Pubkey, started_at: i64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"downtime", recipient.as_ref(), &started_at.to_le_bytes()],
+        &PROGRAM_ID,
+    )
+}
+
+pub fn sla_credit_address(attestation: &Pubkey, subscription: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"sla_credit", attestation.as_ref(), subscription.as_ref()],
+        &PROGRAM_ID,
+    )
+}
+
+pub fn price_cache_address(feed_id: &[u8; 32]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"price_cache", feed_id.as_ref()], &PROGRAM_ID)
+}
+
+pub fn usd_peg_address(subscription: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"usd_peg", subscription.as_ref()], &PROGRAM_ID)
+}
+
+pub fn accepted_mint_address(recipient: &Pubkey, token_mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"accepted_mint", recipient.as_ref(), token_mint.as_ref()],
+        &PROGRAM_ID,
+    )
+}
+
+pub fn plan_address(recipient: &Pubkey, plan_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"plan", recipient.as_ref(), &plan_id.to_le_bytes()],
+        &PROGRAM_ID,
+    )
+}
+
+pub fn plan_subscription_address(subscription: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"plan_subscription", subscription.as_ref()], &PROGRAM_ID)
+}
+
+pub fn deposit_address(subscription: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"deposit", subscription.as_ref()], &PROGRAM_ID)
+}
+
+pub fn denylist_address(recipient: &Pubkey, wallet: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"denylist", recipient.as_ref(), wallet.as_ref()],
+        &PROGRAM_ID,
+    )
+}
+
+pub fn subscriber_allowlist_address(recipient: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"allowlist", recipient.as_ref()], &PROGRAM_ID)
+}
+
+pub fn allowlist_entry_address(recipient: &Pubkey, wallet: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"allowlist", recipient.as_ref(), wallet.as_ref()],
+        &PROGRAM_ID,
+    )
+}
+
+pub fn waitlist_address(plan: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"waitlist", plan.as_ref()], &PROGRAM_ID)
+}
+
+pub fn waitlist_entry_address(plan: &Pubkey, position: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"waitlist_entry", plan.as_ref(), &position.to_le_bytes()],
+        &PROGRAM_ID,
+    )
+}
+
+pub fn spend_alerts_address(subscription: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"spend_alerts", subscription.as_ref()], &PROGRAM_ID)
+}
+
+pub fn setup_fee_address(subscription: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"setup_fee", subscription.as_ref()], &PROGRAM_ID)
+}
+
+pub fn fallback_payment_address(subscription: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"fallback_payment", subscription.as_ref()], &PROGRAM_ID)
+}
+
+/// A Pyth `PriceUpdateV2` of `feed_id`, fully verified unless `partial`
+pub fn pyth_price_update(
+    feed_id: [u8; 32],
+    price: i64,
+    exponent: i32,
+    publish_time: i64,
+    partial: bool,
+) -> Vec<u8> {
+    let mut data = PythPriceUpdate::DISCRIMINATOR.to_vec();
+    data.extend_from_slice(Pubkey::new_unique().as_ref());
+    if partial {
+        data.extend_from_slice(&[0, 5]);
+    } else {
+        data.push(PythPriceUpdate::FULL_VERIFICATION);
+    }
+    data.extend_from_slice(&feed_id);
+    data.extend_from_slice(&price.to_le_bytes());
+    data.extend_from_slice(&(price as u64 / 1_000).to_le_bytes());
+    data.extend_from_slice(&exponent.to_le_bytes());
+    data.extend_from_slice(&publish_time.to_le_bytes());
+    // Previous publish time, EMA price and confidence, posted slot
+    data.extend_from_slice(&[0; 32]);
+    data
+}
+
+pub fn funding_sources_address(subscription: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"funding", subscription.as_ref()], &PROGRAM_ID)
+}
+
+pub fn charge_function_address(recipient: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"charge_function", recipient.as_ref()], &PROGRAM_ID)
+}
+
+pub fn keeper_lease_address(group: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"keeper_lease", group.as_ref()], &PROGRAM_ID)
+}
+
+pub fn platform_config_address() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"platform_config"], &PROGRAM_ID)
+}
+
+pub fn platform_config_change_address() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"platform_config_change"], &PROGRAM_ID)
+}
+
+pub fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// Replace the account at `index` in `instruction`
+pub fn substitute(mut instruction: Instruction, index: usize, key: Pubkey) -> Instruction {
+    instruction.accounts[index].pubkey = key;
+    instruction
+}
+
+/// Drop the signer flag of `key`, as if the caller forgot to sign
+pub fn unsigned(mut instruction: Instruction, key: &Pubkey) -> Instruction {
+    for meta in instruction.accounts.iter_mut() {
+        if meta.pubkey == *key {
+            meta.is_signer = false;
+        }
+    }
+    instruction
+}
+
+pub fn assert_program_error(result: TransactionResult, code: ErrorCode) {
+    assert_eq!(
+        instruction_error(&result),
+        InstructionError::Custom(code.into()),
+        "expected {code:?}"
+    );
+}
+
+pub fn assert_anchor_error(result: TransactionResult, code: AnchorErrorCode) {
+    assert_eq!(
+        instruction_error(&result),
+        InstructionError::Custom(code.into()),
+        "expected {code:?}"
+    );
+}
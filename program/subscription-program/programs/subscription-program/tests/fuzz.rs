@@ -0,0 +1,292 @@
+//! Randomized instruction sequences against the program: a short run of the
+//! Trident target in `trident-tests/fuzz_0`, on the harness, for `cargo test`.
+//!
+//! Each case builds a live subscription, then replays a random sequence of
+//! instructions with random signers, account substitutions and clock jumps.
+//! After every step the invariants below must hold, whether or not the
+//! instruction succeeded:
+//!
+//! - lamports are conserved (fees aside) and only the authority receives the
+//!   subscription's rent;
+//! - tokens only move from the user's account to the merchant's, one period at
+//!   a time;
+//! - nobody but the authority changes the subscription, except by charging;
+//! - the identity fields of the subscription never change.
+
+mod common;
+
+use anchor_lang::prelude::Pubkey;
+use common::*;
+use proptest::prelude::*;
+use subscription_program::Subscription;
+use test_harness::{Keypair, Signer};
+
+#[derive(Debug, Clone)]
+enum Op {
+    Charge,
+    Cancel,
+    Cleanup,
+    Update {
+        amount: Option<u64>,
+        interval: Option<i64>,
+        expires_at: Option<i64>,
+    },
+    Warp(i64),
+}
+
+#[derive(Debug, Clone)]
+struct Step {
+    op: Op,
+    /// Sign as the attacker instead of the authority
+    as_attacker: bool,
+    /// (account index, pool index) replacements
+    substitutions: Vec<(usize, usize)>,
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        3 => Just(Op::Charge),
+        1 => Just(Op::Cancel),
+        1 => Just(Op::Cleanup),
+        2 => (any::<Option<u64>>(), any::<Option<i64>>(), any::<Option<i64>>()).prop_map(
+            |(amount, interval, expires_at)| Op::Update { amount, interval, expires_at }
+        ),
+        3 => (0..=2 * INTERVAL).prop_map(Op::Warp),
+    ]
+}
+
+fn step() -> impl Strategy<Value = Step> {
+    (
+        op(),
+        prop::bool::weighted(0.3),
+        prop::collection::vec((0..8usize, 0..16usize), 0..3),
+    )
+        .prop_map(|(op, as_attacker, substitutions)| Step {
+            op,
+            as_attacker,
+            substitutions,
+        })
+}
+
+struct World {
+    fx: Fixture,
+    attacker: Keypair,
+    pool: Vec<Pubkey>,
+    fees: u64,
+    initial_lamports: u64,
+}
+
+impl World {
+    fn new() -> Self {
+        let mut fx = Fixture::new();
+        fx.subscribe(None);
+
+        let attacker = Keypair::new();
+        fx.svm.airdrop(&attacker.pubkey(), 1_000_000_000);
+        let attacker_ata =
+            fx.svm
+                .create_associated_token_account(&attacker.pubkey(), &fx.mint, STARTING_BALANCE);
+        let other_mint = fx.svm.create_mint(&attacker.pubkey(), 6);
+        let other_mint_ata =
+            fx.svm
+                .create_associated_token_account(&fx.authority.pubkey(), &other_mint, 0);
+
+        // The attacker's own subscription, a valid PDA for the wrong pair
+        let (forged, _) = Pubkey::find_program_address(
+            &[
+                b"subscription",
+                attacker.pubkey().as_ref(),
+                fx.recipient.as_ref(),
+            ],
+            &subscription_program::ID,
+        );
+        let mut forged_state = fx.subscription().unwrap();
+        forged_state.authority = attacker.pubkey();
+        forged_state.user_token_account = attacker_ata;
+        fx.svm
+            .set_anchor_account(forged, &forged_state, SUBSCRIPTION_SPACE);
+
+        let pool = vec![
+            fx.subscription,
+            fx.authority.pubkey(),
+            fx.recipient,
+            fx.user_token_account,
+            fx.recipient_token_account,
+            fx.mint,
+            attacker.pubkey(),
+            attacker_ata,
+            other_mint,
+            other_mint_ata,
+            forged,
+            fx.payer.pubkey(),
+            spl_token::ID,
+            anchor_lang::system_program::ID,
+            subscription_program::ID,
+            Pubkey::new_unique(),
+        ];
+
+        let mut world = Self {
+            fx,
+            attacker,
+            pool,
+            fees: 0,
+            initial_lamports: 0,
+        };
+        world.initial_lamports = world.total_lamports();
+        world
+    }
+
+    fn total_lamports(&self) -> u64 {
+        self.pool
+            .iter()
+            .map(|address| self.fx.svm.get_balance(address))
+            .sum()
+    }
+
+    fn token_balances(&self) -> Vec<u64> {
+        self.pool
+            .iter()
+            .map(|address| self.fx.svm.token_balance(address))
+            .collect()
+    }
+
+    fn run(&mut self, step: &Step) -> Result<(), TestCaseError> {
+        let mut instruction = match &step.op {
+            Op::Charge => self.fx.charge_ix(),
+            Op::Cancel => self.fx.cancel_ix(),
+            Op::Cleanup => self.fx.cleanup_ix(),
+            Op::Update {
+                amount,
+                interval,
+                expires_at,
+            } => self.fx.update_ix(*amount, *interval, *expires_at),
+            Op::Warp(seconds) => {
+                self.fx.svm.advance_time(*seconds);
+                return Ok(());
+            }
+        };
+
+        let authority = self.fx.authority.pubkey();
+        let attacker = self.attacker.pubkey();
+        if step.as_attacker {
+            for meta in instruction.accounts.iter_mut() {
+                if meta.pubkey == authority {
+                    meta.pubkey = attacker;
+                }
+            }
+        }
+        for &(index, replacement) in &step.substitutions {
+            if let Some(meta) = instruction.accounts.get_mut(index) {
+                meta.pubkey = self.pool[replacement];
+            }
+        }
+
+        // Sign for whichever of our keys ended up in signer slots; any other
+        // key in a signer slot is sent unsigned
+        let mut signers: Vec<&Keypair> = Vec::new();
+        let mut authority_signed = false;
+        for meta in instruction
+            .accounts
+            .iter_mut()
+            .filter(|meta| meta.is_signer)
+        {
+            if meta.pubkey == authority {
+                signers.push(&self.fx.authority);
+                authority_signed = true;
+            } else if meta.pubkey == attacker {
+                signers.push(&self.attacker);
+            } else if meta.pubkey != self.fx.payer.pubkey() {
+                meta.is_signer = false;
+            }
+        }
+
+        let before: Option<Subscription> = self.fx.subscription();
+        let balances_before = self.token_balances();
+        let authority_lamports_before = self.fx.svm.get_balance(&authority);
+        let subscription_lamports_before = self.fx.svm.get_balance(&self.fx.subscription);
+
+        let payer = self.fx.payer.insecure_clone();
+        let result = self
+            .fx
+            .svm
+            .send_instructions(&[instruction], &payer, &signers);
+        self.fees += match &result {
+            Ok(meta) => meta.fee,
+            Err(failed) => failed.meta.fee,
+        };
+
+        // Lamports: conserved, and the subscription's rent only goes home
+        prop_assert_eq!(self.total_lamports() + self.fees, self.initial_lamports);
+        let subscription_lamports = self.fx.svm.get_balance(&self.fx.subscription);
+        if subscription_lamports < subscription_lamports_before {
+            prop_assert!(authority_signed, "{:?} closed the subscription", step);
+            prop_assert_eq!(
+                self.fx.svm.get_balance(&authority),
+                authority_lamports_before + subscription_lamports_before - subscription_lamports
+            );
+        }
+
+        // Tokens: only user -> merchant, one period
+        let balances = self.token_balances();
+        if balances != balances_before {
+            let before = before
+                .as_ref()
+                .expect("tokens moved without a subscription");
+            let user = 3;
+            let merchant = 4;
+            for (index, (now, then)) in balances.iter().zip(&balances_before).enumerate() {
+                if index != user && index != merchant {
+                    prop_assert_eq!(now, then, "{} balance moved", self.pool[index]);
+                }
+            }
+            prop_assert_eq!(
+                balances_before[user] - balances[user],
+                before.amount_per_period
+            );
+            prop_assert_eq!(
+                balances[merchant] - balances_before[merchant],
+                before.amount_per_period
+            );
+        }
+
+        // State: identity fixed, only the authority edits terms
+        if let (Some(before), Some(after)) = (&before, &self.fx.subscription()) {
+            prop_assert_eq!(after.authority, before.authority);
+            prop_assert_eq!(after.recipient, before.recipient);
+            prop_assert_eq!(after.user_token_account, before.user_token_account);
+            prop_assert_eq!(
+                after.recipient_token_account,
+                before.recipient_token_account
+            );
+            prop_assert_eq!(after.token_mint, before.token_mint);
+            prop_assert_eq!(after.bump, before.bump);
+            prop_assert!(after.total_charged >= before.total_charged);
+
+            let terms = |s: &Subscription| {
+                (
+                    s.amount_per_period,
+                    s.interval_seconds,
+                    s.expires_at,
+                    s.is_active,
+                )
+            };
+            if !authority_signed {
+                prop_assert_eq!(terms(after), terms(before), "{:?} changed the terms", step);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn random_sequences_keep_invariants(steps in prop::collection::vec(step(), 1..24)) {
+        let mut world = World::new();
+        for step in &steps {
+            world.run(step)?;
+        }
+    }
+}
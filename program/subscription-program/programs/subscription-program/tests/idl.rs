@@ -0,0 +1,174 @@
+//! The program's IDL, pinned to `tests/snapshots/subscription_program.json`.
+//!
+//! The IDL is regenerated the way `anchor build` does it (a second build with
+//! the `idl-build` feature) and compared with the committed one. Breaking
+//! changes for integrators (a removed or reshaped instruction, account, event
+//! or error code) are listed individually; additive changes only ask for the
+//! golden file to be refreshed:
+//!
+//! ```bash
+//! UPDATE_SNAPSHOTS=1 cargo test -p subscription-program --test idl
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use anchor_lang_idl::build::IdlBuilder;
+use anchor_lang_idl::types::{Idl, IdlInstructionAccountItem, IdlTypeDef};
+
+fn golden_path() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots/subscription_program.json")
+}
+
+fn build_idl() -> Idl {
+    // anchor-lang-idl forwards RUSTUP_TOOLCHAIN as a literal "+{toolchain}"
+    // argument; without it the nested cargo resolves rust-toolchain.toml.
+    std::env::remove_var("RUSTUP_TOOLCHAIN");
+
+    let target_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("idl-build");
+    IdlBuilder::new()
+        .program_path(env!("CARGO_MANIFEST_DIR").into())
+        .cargo_args(vec![
+            "--target-dir".into(),
+            target_dir.display().to_string(),
+        ])
+        .build()
+        .expect("IDL builds")
+}
+
+/// (name, writable, signer, optional) in order, flattening composite accounts
+fn account_shape(items: &[IdlInstructionAccountItem]) -> Vec<(String, bool, bool, bool)> {
+    items
+        .iter()
+        .flat_map(|item| match item {
+            IdlInstructionAccountItem::Single(account) => vec![(
+                account.name.clone(),
+                account.writable,
+                account.signer,
+                account.optional,
+            )],
+            IdlInstructionAccountItem::Composite(group) => account_shape(&group.accounts)
+                .into_iter()
+                .map(|(name, writable, signer, optional)| {
+                    (format!("{}.{name}", group.name), writable, signer, optional)
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+fn without_docs(ty: &IdlTypeDef) -> IdlTypeDef {
+    IdlTypeDef {
+        docs: vec![],
+        ..ty.clone()
+    }
+}
+
+/// Changes that break a client built against `old`
+fn breaking_changes(old: &Idl, new: &Idl) -> Vec<String> {
+    let mut changes = Vec::new();
+
+    if old.address != new.address {
+        changes.push(format!("address {} -> {}", old.address, new.address));
+    }
+
+    for old_ix in &old.instructions {
+        let Some(new_ix) = new.instructions.iter().find(|ix| ix.name == old_ix.name) else {
+            changes.push(format!("instruction `{}` removed", old_ix.name));
+            continue;
+        };
+        if old_ix.discriminator != new_ix.discriminator {
+            changes.push(format!(
+                "instruction `{}` discriminator changed",
+                old_ix.name
+            ));
+        }
+        if old_ix.args != new_ix.args {
+            changes.push(format!("instruction `{}` arguments changed", old_ix.name));
+        }
+        if old_ix.returns != new_ix.returns {
+            changes.push(format!("instruction `{}` return type changed", old_ix.name));
+        }
+        if account_shape(&old_ix.accounts) != account_shape(&new_ix.accounts) {
+            changes.push(format!(
+                "instruction `{}` accounts changed (order, name, writable or signer)",
+                old_ix.name
+            ));
+        }
+    }
+
+    for old_account in &old.accounts {
+        match new.accounts.iter().find(|a| a.name == old_account.name) {
+            None => changes.push(format!("account `{}` removed", old_account.name)),
+            Some(new_account) if new_account.discriminator != old_account.discriminator => changes
+                .push(format!(
+                    "account `{}` discriminator changed",
+                    old_account.name
+                )),
+            Some(_) => {}
+        }
+    }
+
+    for old_event in &old.events {
+        match new.events.iter().find(|e| e.name == old_event.name) {
+            None => changes.push(format!("event `{}` removed", old_event.name)),
+            Some(new_event) if new_event.discriminator != old_event.discriminator => {
+                changes.push(format!("event `{}` discriminator changed", old_event.name))
+            }
+            Some(_) => {}
+        }
+    }
+
+    for old_ty in &old.types {
+        match new.types.iter().find(|ty| ty.name == old_ty.name) {
+            None => changes.push(format!("type `{}` removed", old_ty.name)),
+            Some(new_ty) if without_docs(new_ty) != without_docs(old_ty) => {
+                changes.push(format!("type `{}` layout changed", old_ty.name))
+            }
+            Some(_) => {}
+        }
+    }
+
+    for old_error in &old.errors {
+        match new.errors.iter().find(|e| e.code == old_error.code) {
+            None => changes.push(format!(
+                "error {} (`{}`) removed",
+                old_error.code, old_error.name
+            )),
+            Some(new_error) if new_error.name != old_error.name => changes.push(format!(
+                "error {} renamed `{}` -> `{}`",
+                old_error.code, old_error.name, new_error.name
+            )),
+            Some(_) => {}
+        }
+    }
+
+    changes
+}
+
+#[test]
+fn idl_matches_golden() {
+    let idl = build_idl();
+    let rendered = serde_json::to_string_pretty(&idl).unwrap() + "\n";
+
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::write(golden_path(), rendered).unwrap();
+        return;
+    }
+
+    let golden: Idl = serde_json::from_str(
+        &std::fs::read_to_string(golden_path()).expect("golden IDL is committed"),
+    )
+    .unwrap();
+
+    let breaking = breaking_changes(&golden, &idl);
+    assert!(
+        breaking.is_empty(),
+        "breaking IDL changes for existing integrators:\n  {}",
+        breaking.join("\n  ")
+    );
+    assert!(
+        golden == idl,
+        "IDL changed compatibly; refresh {} with UPDATE_SNAPSHOTS=1",
+        golden_path().display()
+    );
+}
@@ -0,0 +1,293 @@
+//! On-chain account layouts, pinned to committed byte snapshots.
+//!
+//! Live accounts are decoded in place by every upgrade of the program, so a
+//! reordered field, a changed type or a new discriminator would corrupt them.
+//! Any diff here must be a deliberate migration. After one, regenerate with
+//!
+//! ```bash
+//! UPDATE_SNAPSHOTS=1 cargo test -p subscription-program --test layout
+//! ```
+
+use std::fmt::Write as _;
+use std::path::PathBuf;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::{AccountDeserialize, AccountSerialize, Discriminator, Space};
+use subscription_program::{
+    LegacySubscription, Subscription, SubscriptionV2, SubscriptionV3, SubscriptionV4,
+};
+
+/// Serialize into a zeroed buffer of the allocated size, as `init` leaves it
+fn account_bytes<T: AccountSerialize + Space>(account: &T) -> Vec<u8> {
+    let mut data = Vec::with_capacity(8 + T::INIT_SPACE);
+    account.try_serialize(&mut data).unwrap();
+    assert!(
+        data.len() <= 8 + T::INIT_SPACE,
+        "serialized {} bytes into an allocation of {}",
+        data.len(),
+        8 + T::INIT_SPACE
+    );
+    data.resize(8 + T::INIT_SPACE, 0);
+    data
+}
+
+/// `offset: hex` lines, 16 bytes each, so diffs point at the moved field
+fn hex_dump(bytes: &[u8]) -> String {
+    let mut dump = String::new();
+    for (line, chunk) in bytes.chunks(16).enumerate() {
+        write!(dump, "{:04x}:", line * 16).unwrap();
+        for byte in chunk {
+            write!(dump, " {byte:02x}").unwrap();
+        }
+        dump.push('\n');
+    }
+    dump
+}
+
+fn assert_snapshot(name: &str, bytes: &[u8]) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/snapshots")
+        .join(format!("{name}.hex"));
+    let actual = hex_dump(bytes);
+
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, &actual).unwrap();
+        return;
+    }
+
+    let expected = std::fs::read_to_string(&path)
+        .unwrap_or_else(|err| panic!("missing snapshot {}: {err}", path.display()));
+    assert!(
+        actual == expected,
+        "{name} layout changed; this breaks existing accounts unless migrated\n\
+         --- expected ({})\n{expected}\n--- actual\n{actual}",
+        path.display()
+    );
+}
+
+/// Every field distinct and non-zero, so a swap or shift shows up in the bytes
+fn reference_subscription() -> Subscription {
+    Subscription {
+        is_active: true,
+        recipient: Pubkey::new_from_array([0x22; 32]),
+        next_charge_at: 1_740_873_600,
+        authority: Pubkey::new_from_array([0x11; 32]),
+        user_token_account: Pubkey::new_from_array([0x33; 32]),
+        recipient_token_account: Pubkey::new_from_array([0x44; 32]),
+        token_mint: Pubkey::new_from_array([0x55; 32]),
+        amount_per_period: 0x0102_0304_0506_0708,
+        interval_seconds: 2_592_000,
+        last_charge_timestamp: 1_738_281_600,
+        created_at: 1_735_689_600,
+        total_charged: 0x1112_1314_1516_1718,
+        bump: 0xfe,
+        on_plan: true,
+        max_total_spend: 0x2122_2324_2526_2728,
+        expires_at: Some(1_767_225_600),
+    }
+}
+
+#[test]
+fn subscription_layout() {
+    let subscription = reference_subscription();
+    let bytes = account_bytes(&subscription);
+
+    assert_eq!(bytes.len(), 236);
+    assert_snapshot("subscription", &bytes);
+    let decoded = Subscription::try_deserialize(&mut bytes.as_slice()).unwrap();
+    assert_eq!(account_bytes(&decoded), bytes);
+}
+
+#[test]
+fn subscription_without_expiry_layout() {
+    // `None` is one byte shorter; it comes last, so nothing shifts
+    let subscription = Subscription {
+        expires_at: None,
+        ..reference_subscription()
+    };
+    let bytes = account_bytes(&subscription);
+
+    assert_snapshot("subscription_without_expiry", &bytes);
+    let decoded = Subscription::try_deserialize(&mut bytes.as_slice()).unwrap();
+    assert_eq!(decoded.expires_at, None);
+    assert_eq!(account_bytes(&decoded), bytes);
+}
+
+/// The offsets documented for `getProgramAccounts` and Geyser filters hold
+/// with and without an expiry
+#[test]
+fn subscription_filter_offsets() {
+    for expires_at in [Some(1_767_225_600), None] {
+        let subscription = Subscription {
+            expires_at,
+            ..reference_subscription()
+        };
+        let bytes = account_bytes(&subscription);
+        let field = |offset: usize, len: usize| &bytes[offset..offset + len];
+
+        assert_eq!(field(0, 8), Subscription::DISCRIMINATOR);
+        assert_eq!(field(Subscription::IS_ACTIVE_OFFSET, 1), [1]);
+        assert_eq!(
+            field(Subscription::RECIPIENT_OFFSET, 32),
+            subscription.recipient.as_ref()
+        );
+        assert_eq!(
+            field(Subscription::NEXT_CHARGE_AT_OFFSET, 8),
+            subscription.next_charge_at.to_le_bytes()
+        );
+        assert_eq!(
+            field(Subscription::AUTHORITY_OFFSET, 32),
+            subscription.authority.as_ref()
+        );
+        assert_eq!(field(Subscription::ON_PLAN_OFFSET, 1), [1]);
+    }
+}
+
+/// Accounts created before `max_total_spend`, which `migrate_subscription`
+/// still has to read
+#[test]
+fn subscription_v4_layout() {
+    let reference = reference_subscription();
+    let v4 = SubscriptionV4 {
+        flags: reference.is_active as u8,
+        recipient: reference.recipient,
+        next_charge_at: reference.next_charge_at,
+        authority: reference.authority,
+        user_token_account: reference.user_token_account,
+        recipient_token_account: reference.recipient_token_account,
+        token_mint: reference.token_mint,
+        amount_per_period: reference.amount_per_period,
+        interval_seconds: reference.interval_seconds,
+        last_charge_timestamp: reference.last_charge_timestamp,
+        created_at: reference.created_at,
+        total_charged: reference.total_charged,
+        bump: reference.bump,
+        expires_at: reference.expires_at.unwrap_or(i64::MAX),
+        on_plan: reference.on_plan,
+    };
+    let bytes = account_bytes(&v4);
+
+    assert_eq!(bytes.len(), 227);
+    assert_snapshot("subscription_v4", &bytes);
+
+    // A limit set before was only held by the delegation
+    let migrated = Subscription::from(v4);
+    let reference = Subscription {
+        max_total_spend: Subscription::NO_SPEND_LIMIT,
+        ..reference
+    };
+    assert_eq!(account_bytes(&migrated), account_bytes(&reference));
+}
+
+/// Accounts created before `on_plan` and `max_total_spend`, which
+/// `migrate_subscription` still has to read
+#[test]
+fn subscription_v3_layout() {
+    let reference = reference_subscription();
+    let v3 = SubscriptionV3 {
+        flags: reference.is_active as u8,
+        recipient: reference.recipient,
+        next_charge_at: reference.next_charge_at,
+        authority: reference.authority,
+        user_token_account: reference.user_token_account,
+        recipient_token_account: reference.recipient_token_account,
+        token_mint: reference.token_mint,
+        amount_per_period: reference.amount_per_period,
+        interval_seconds: reference.interval_seconds,
+        last_charge_timestamp: reference.last_charge_timestamp,
+        created_at: reference.created_at,
+        total_charged: reference.total_charged,
+        bump: reference.bump,
+        expires_at: reference.expires_at.unwrap_or(i64::MAX),
+    };
+    let bytes = account_bytes(&v3);
+
+    assert_eq!(bytes.len(), 226);
+    assert_snapshot("subscription_v3", &bytes);
+
+    // A limit set before was only held by the delegation
+    let migrated = v3.migrate(reference.on_plan);
+    let reference = Subscription {
+        max_total_spend: Subscription::NO_SPEND_LIMIT,
+        ..reference
+    };
+    assert_eq!(account_bytes(&migrated), account_bytes(&reference));
+}
+
+/// Accounts created before the active bit and the expiry were packed, which
+/// `migrate_subscription` still has to read
+#[test]
+fn subscription_v2_layout() {
+    for (expires_at, name, len) in [
+        (Some(1_767_225_600), "subscription_v2", 227),
+        (None, "subscription_v2_without_expiry", 219),
+    ] {
+        // Plans and stored spend limits came after this layout
+        let mut reference = Subscription {
+            on_plan: false,
+            max_total_spend: Subscription::NO_SPEND_LIMIT,
+            ..reference_subscription()
+        };
+        reference.expires_at = expires_at;
+        let v2 = SubscriptionV2 {
+            is_active: reference.is_active,
+            recipient: reference.recipient,
+            next_charge_at: reference.next_charge_at,
+            authority: reference.authority,
+            user_token_account: reference.user_token_account,
+            recipient_token_account: reference.recipient_token_account,
+            token_mint: reference.token_mint,
+            amount_per_period: reference.amount_per_period,
+            interval_seconds: reference.interval_seconds,
+            last_charge_timestamp: reference.last_charge_timestamp,
+            created_at: reference.created_at,
+            total_charged: reference.total_charged,
+            bump: reference.bump,
+            expires_at,
+        };
+        let mut bytes = Vec::new();
+        v2.try_serialize(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), len);
+
+        // Allocated at the full 227 bytes, with or without an expiry
+        let bytes = account_bytes(&v2);
+        assert_snapshot(name, &bytes);
+
+        let migrated = Subscription::from(v2);
+        assert_eq!(account_bytes(&migrated), account_bytes(&reference));
+    }
+}
+
+/// Accounts created before the current layout, which `migrate_subscription`
+/// still has to read
+#[test]
+fn legacy_subscription_layout() {
+    let reference = Subscription {
+        on_plan: false,
+        max_total_spend: Subscription::NO_SPEND_LIMIT,
+        ..reference_subscription()
+    };
+    let legacy = LegacySubscription {
+        authority: reference.authority,
+        recipient: reference.recipient,
+        user_token_account: reference.user_token_account,
+        recipient_token_account: reference.recipient_token_account,
+        token_mint: reference.token_mint,
+        amount_per_period: reference.amount_per_period,
+        interval_seconds: reference.interval_seconds,
+        last_charge_timestamp: reference.last_charge_timestamp,
+        created_at: reference.created_at,
+        expires_at: reference.expires_at,
+        is_active: reference.is_active,
+        total_charged: reference.total_charged,
+        bump: reference.bump,
+    };
+    let bytes = account_bytes(&legacy);
+
+    assert_eq!(bytes.len(), 219);
+    assert_snapshot("legacy_subscription", &bytes);
+
+    let migrated = Subscription::from(legacy);
+    assert_eq!(account_bytes(&migrated), account_bytes(&reference));
+}
@@ -0,0 +1,336 @@
+//! Account-substitution attacks, one section per instruction.
+//!
+//! Threat model: `charge_subscription` is permissionless, so any wallet can
+//! build it with whatever accounts it likes; the other instructions are
+//! signed by an authority who may be trying to reach someone else's
+//! subscription or token account. Every account an attacker can swap is
+//! swapped here, and the program must refuse before moving funds or state.
+//!
+//! Some rejections the program leaves to the SPL Token program, such as a
+//! token account the signer does not own. The harness runs the real token
+//! program under CPI, so those are asserted with its error codes.
+//!
+//! The charge instructions book the period before their CPI; the harness
+//! keeps the accounts as they stood at the CPI, and the tests check that a
+//! charge seeing that state is refused.
+
+mod common;
+
+use anchor_lang::error::ErrorCode as AnchorErrorCode;
+use anchor_lang::prelude::{AccountMeta, Pubkey};
+use anchor_lang::AccountSerialize;
+use common::*;
+use spl_token::error::TokenError;
+use subscription_program::ErrorCode;
+use test_harness::{assert_error, Account, Keypair, Signer};
+
+/// Stand-in for a malicious program that would "transfer" without moving funds
+fn fake_token_program() -> Pubkey {
+    Pubkey::new_unique()
+}
+
+/// An account with the victim's subscription bytes, owned by another program
+fn lookalike_subscription(fx: &mut Fixture, owner: Pubkey) -> Pubkey {
+    let mut data = Vec::new();
+    fx.subscription().unwrap().try_serialize(&mut data).unwrap();
+    data.resize(SUBSCRIPTION_SPACE, 0);
+
+    let address = Pubkey::new_unique();
+    fx.svm.set_account(
+        address,
+        Account {
+            lamports: fx.svm.minimum_balance_for_rent_exemption(data.len()),
+            data,
+            owner,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+    address
+}
+
+/// Wallet with its own token account for the fixture mint
+fn intruder(fx: &mut Fixture) -> (Keypair, Pubkey) {
+    let intruder = Keypair::new();
+    fx.svm.airdrop(&intruder.pubkey(), 1_000_000_000);
+    let token_account = fx
+        .svm
+        .create_associated_token_account(&intruder.pubkey(), &fx.mint, 0);
+    (intruder, token_account)
+}
+
+// ---------- initialize_subscription ----------
+
+#[test]
+fn initialize_rejects_pda_of_another_recipient() {
+    let mut fx = Fixture::new();
+    // Subscribing to merchant A while presenting merchant B's PDA
+    let ix = substitute(
+        fx.initialize_ix(AMOUNT, INTERVAL, None),
+        2,
+        Pubkey::new_unique(),
+    );
+    let authority = fx.authority.insecure_clone();
+
+    assert_anchor_error(fx.send(ix, &[&authority]), AnchorErrorCode::ConstraintSeeds);
+}
+
+#[test]
+fn initialize_rejects_non_pda_subscription() {
+    let mut fx = Fixture::new();
+    let forged = Keypair::new();
+    let ix = substitute(fx.initialize_ix(AMOUNT, INTERVAL, None), 0, forged.pubkey());
+    let authority = fx.authority.insecure_clone();
+
+    assert_anchor_error(fx.send(ix, &[&authority]), AnchorErrorCode::ConstraintSeeds);
+}
+
+#[test]
+fn initialize_rejects_foreign_token_program() {
+    let mut fx = Fixture::new();
+    let ix = substitute(fx.initialize_ix(AMOUNT, INTERVAL, None), 6, fake_token_program());
+    let authority = fx.authority.insecure_clone();
+
+    assert_anchor_error(fx.send(ix, &[&authority]), AnchorErrorCode::ConstraintAddress);
+    assert!(fx.subscription().is_none());
+}
+
+#[test]
+fn initialize_with_victim_token_account_is_rejected_by_token_program() {
+    let mut fx = Fixture::new();
+    let (intruder, _) = intruder(&mut fx);
+    // The intruder subscribes "themselves" but names the victim's token
+    // account as the source. The approve CPI is signed by the intruder, who
+    // does not own that account, so SPL Token rejects it.
+    let victim_token_account = fx.user_token_account;
+    fx.authority = intruder.insecure_clone();
+    fx.subscription = Pubkey::find_program_address(
+        &[
+            b"subscription",
+            intruder.pubkey().as_ref(),
+            fx.recipient.as_ref(),
+        ],
+        &subscription_program::ID,
+    )
+    .0;
+    let ix = fx.initialize_ix(AMOUNT, INTERVAL, None);
+    assert_eq!(ix.accounts[3].pubkey, victim_token_account);
+
+    assert_error(fx.send(ix, &[&intruder]), TokenError::OwnerMismatch as u32);
+    assert!(fx.subscription().is_none());
+    let victim = fx.svm.get_token_account(&victim_token_account).unwrap();
+    assert!(victim.delegate.is_none());
+}
+
+// ---------- charge_subscription ----------
+
+#[test]
+fn charge_rejects_attacker_source_account() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.svm.advance_time(INTERVAL);
+    let (_, attacker_account) = intruder(&mut fx);
+    let ix = substitute(fx.charge_ix(), 1, attacker_account);
+
+    assert_anchor_error(fx.send(ix, &[]), AnchorErrorCode::ConstraintRaw);
+}
+
+#[test]
+fn charge_rejects_attacker_destination_account() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.svm.advance_time(INTERVAL);
+    let (_, attacker_account) = intruder(&mut fx);
+    let ix = substitute(fx.charge_ix(), 2, attacker_account);
+
+    assert_anchor_error(fx.send(ix, &[]), AnchorErrorCode::ConstraintRaw);
+    assert_eq!(fx.svm.token_balance(&attacker_account), 0);
+}
+
+#[test]
+fn charge_rejects_foreign_token_program() {
+    let mut fx = Fixture::new();
+    let before = fx.subscribe(None);
+    fx.svm.advance_time(INTERVAL);
+    // Would record a paid period without any transfer happening
+    let ix = substitute(fx.charge_ix(), 3, fake_token_program());
+
+    assert_anchor_error(fx.send(ix, &[]), AnchorErrorCode::ConstraintAddress);
+    let after = fx.subscription().unwrap();
+    assert_eq!(after.last_charge_timestamp, before.last_charge_timestamp);
+    assert_eq!(after.total_charged, before.total_charged);
+}
+
+#[test]
+fn charge_rejects_subscription_owned_by_another_program() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.svm.advance_time(INTERVAL);
+    let forged = lookalike_subscription(&mut fx, Pubkey::new_unique());
+    let ix = substitute(fx.charge_ix(), 0, forged);
+
+    assert_anchor_error(
+        fx.send(ix, &[]),
+        AnchorErrorCode::AccountOwnedByWrongProgram,
+    );
+}
+
+#[test]
+fn charge_rejects_subscription_at_wrong_address() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.svm.advance_time(INTERVAL);
+    // Correct owner and data, but not at the PDA its seeds derive
+    let forged = lookalike_subscription(&mut fx, subscription_program::ID);
+    let ix = substitute(fx.charge_ix(), 0, forged);
+
+    assert_anchor_error(fx.send(ix, &[]), AnchorErrorCode::ConstraintSeeds);
+}
+
+// ---------- CPI ordering ----------
+//
+// Solana refuses a CPI back into a program already on the call stack, so a
+// malicious token program cannot re-enter `charge_subscription` directly. It
+// could still call into another program that reads the subscription, or the
+// transaction could carry a second charge. Either way the period must already
+// be booked when control leaves this program.
+
+#[test]
+fn charge_is_recorded_before_token_cpi() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.svm.advance_time(INTERVAL);
+    let now = fx.svm.clock().unix_timestamp;
+    // A program the attacker controls rides along as a registered
+    // fallback, which a charge reads as holding nothing
+    let hostile = fake_token_program();
+    let funding = fx.set_funding_sources(&[hostile]);
+    let mut ix = fx.charge_with_funding_ix(None, funding, &[]);
+    ix.accounts.push(AccountMeta::new_readonly(hostile, false));
+
+    let at_cpi = fx.subscription_at_cpi(ix);
+
+    assert_eq!(at_cpi.last_charge_timestamp, now);
+    assert_eq!(at_cpi.total_charged, 2 * AMOUNT);
+    assert!(at_cpi.check_chargeable(now).is_err());
+    // A charge that sees this state, re-entrant or not, is refused
+    fx.set_subscription(&at_cpi);
+    let ix = fx.charge_ix();
+    assert_program_error(fx.send(ix, &[]), ErrorCode::IntervalNotMet);
+}
+
+#[test]
+fn charge_with_policy_is_recorded_before_spend_cpi() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let policy = fx.set_policy(AMOUNT);
+    fx.svm.advance_time(INTERVAL);
+    let now = fx.svm.clock().unix_timestamp;
+
+    let ix = fx.charge_with_policy_ix(policy);
+    let at_cpi = fx.subscription_at_cpi(ix);
+
+    assert_eq!(at_cpi.last_charge_timestamp, now);
+    assert_eq!(at_cpi.total_charged, 2 * AMOUNT);
+    fx.set_subscription(&at_cpi);
+    fx.svm.expire_blockhash();
+    let ix = fx.charge_with_policy_ix(policy);
+    assert_program_error(fx.send(ix, &[]), ErrorCode::IntervalNotMet);
+}
+
+// ---------- cancel_subscription ----------
+
+#[test]
+fn cancel_rejects_other_token_account() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let (_, other_account) = intruder(&mut fx);
+    let ix = substitute(fx.cancel_ix(), 2, other_account);
+    let authority = fx.authority.insecure_clone();
+
+    assert_anchor_error(fx.send(ix, &[&authority]), AnchorErrorCode::ConstraintRaw);
+    assert!(fx.subscription().is_some());
+}
+
+#[test]
+fn cancel_rejects_foreign_token_program() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    // Would close the account while leaving the delegation in place
+    let ix = substitute(fx.cancel_ix(), 3, fake_token_program());
+    let authority = fx.authority.insecure_clone();
+
+    assert_anchor_error(
+        fx.send(ix, &[&authority]),
+        AnchorErrorCode::ConstraintAddress,
+    );
+    assert!(fx.subscription().is_some());
+}
+
+#[test]
+fn cancel_requires_authority_signature() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    // A relayer or keeper replaying the user's accounts without their signature
+    let ix = unsigned(fx.cancel_ix(), &fx.authority.pubkey());
+
+    assert_anchor_error(fx.send(ix, &[]), AnchorErrorCode::AccountNotSigner);
+    assert!(fx.subscription().is_some());
+}
+
+// ---------- cleanup_cancelled_subscription ----------
+
+#[test]
+fn cleanup_rejects_other_authority() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let (intruder, _) = intruder(&mut fx);
+    let ix = substitute(fx.cleanup_ix(), 1, intruder.pubkey());
+    let rent = fx.svm.get_balance(&fx.subscription);
+
+    assert_anchor_error(fx.send(ix, &[&intruder]), AnchorErrorCode::ConstraintHasOne);
+    assert_eq!(fx.svm.get_balance(&fx.subscription), rent);
+}
+
+#[test]
+fn cleanup_rejects_subscription_owned_by_another_program() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let forged = lookalike_subscription(&mut fx, Pubkey::new_unique());
+    let ix = substitute(fx.cleanup_ix(), 0, forged);
+    let authority = fx.authority.insecure_clone();
+
+    assert_anchor_error(
+        fx.send(ix, &[&authority]),
+        AnchorErrorCode::AccountOwnedByWrongProgram,
+    );
+}
+
+// ---------- update_subscription ----------
+
+#[test]
+fn update_rejects_lookalike_subscription() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let forged = lookalike_subscription(&mut fx, subscription_program::ID);
+    let ix = substitute(fx.update_ix(Some(1), None, None), 0, forged);
+    let authority = fx.authority.insecure_clone();
+
+    assert_anchor_error(fx.send(ix, &[&authority]), AnchorErrorCode::ConstraintSeeds);
+}
+
+#[test]
+fn update_rejects_other_authority() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let (intruder, _) = intruder(&mut fx);
+    // e.g. raising the amount of someone else's subscription
+    let ix = substitute(
+        fx.update_ix(Some(100 * AMOUNT), None, None),
+        1,
+        intruder.pubkey(),
+    );
+
+    assert_anchor_error(fx.send(ix, &[&intruder]), AnchorErrorCode::ConstraintHasOne);
+    assert_eq!(fx.subscription().unwrap().amount_per_period, AMOUNT);
+}
@@ -0,0 +1,5561 @@
+//! Native tests for every instruction, run in-process with `test-harness`.
+//!
+//! Token transfers and account creation are CPIs into the real SPL Token
+//! and system programs, so each path runs to the end and the tests check
+//! the balances it leaves.
+
+mod common;
+
+use anchor_lang::error::ErrorCode as AnchorErrorCode;
+use anchor_lang::prelude::{AccountInfo, Pubkey};
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program_error::ProgramError;
+use anchor_lang::system_program;
+use anchor_lang::{
+    AccountDeserialize, AccountSerialize, AnchorDeserialize, InstructionData, Space,
+};
+use common::*;
+use spl_token::error::TokenError;
+use subscription_program::{
+    accounts, charge_task_address, charge_task_instruction, charge_thread_address,
+    charge_thread_instruction, compile_task, instruction, is_squads_multisig,
+    queue_authority_address, task_queue_authority_address, thread_create_instruction, AcceptedMint,
+    BillingCadence, ChargeFunction, DenylistEntry, ErrorCode, FallbackPayment, FundingSources,
+    Interval, KeeperLease, LegacySubscription, MerchantConfig, MerchantVault, PayoutChange, Plan,
+    PlanSubscription, PlatformConfig, PlatformConfigChange, PriceCache, PriceStep, PythPriceUpdate,
+    RampStep, RevenueHold, SlaCommitment, SlaCredit, SpendAlerts, SubscriberAllowlist,
+    Subscription, SubscriptionDeposit, SubscriptionTombstone, SubscriptionV2, SubscriptionV3,
+    SubscriptionV4, SwitchboardFunction, ThreadInstruction, ThreadTrigger, UsdPeg, Waitlist,
+    WithdrawalDestination, CLOCKWORK_THREAD_PROGRAM_ID, ID as PROGRAM_ID, MAX_HOLDBACK_DAYS,
+    MAX_PRICE_AGE_SECONDS, MAX_RAMP_STEPS, MAX_REVENUE_HOLDS, MAX_SPEND_ALERTS,
+    MAX_WITHDRAWAL_DESTINATIONS, PAYOUT_TIMELOCK_SECONDS, PLATFORM_TIMELOCK_SECONDS,
+    PYTH_RECEIVER_PROGRAM_ID, SECONDS_PER_DAY, SLA_NOTICE_SECONDS, SQUADS_MULTISIG_DISCRIMINATOR,
+    SQUADS_PROGRAM_ID, SWITCHBOARD_ATTESTATION_PROGRAM_ID, THREAD_CREATE_DISCRIMINATOR,
+    TIER_UPGRADE_VETO_SECONDS, TUKTUK_PROGRAM_ID,
+};
+use test_harness::{assert_error, Account, Keypair, Signer, SystemError};
+
+// ---------- initialize_subscription ----------
+
+#[test]
+fn initialize_delegates_and_charges_the_first_period() {
+    let mut fx = Fixture::new();
+    let ix = fx.initialize_ix(AMOUNT, INTERVAL, None);
+    let authority = fx.authority.insecure_clone();
+
+    assert_success(fx.send(ix, &[&authority]));
+    let subscription = fx.subscription().unwrap();
+    assert_eq!(subscription.total_charged, AMOUNT);
+    assert!(subscription.is_active);
+    assert_eq!(fx.svm.token_balance(&fx.recipient_token_account), AMOUNT);
+    assert_eq!(
+        fx.svm.token_balance(&fx.user_token_account),
+        STARTING_BALANCE - AMOUNT
+    );
+    let user = fx.svm.get_token_account(&fx.user_token_account).unwrap();
+    assert_eq!(Option::from(user.delegate), Some(fx.subscription));
+}
+
+#[test]
+fn initialize_requires_authority_signature() {
+    let mut fx = Fixture::new();
+    let ix = unsigned(
+        fx.initialize_ix(AMOUNT, INTERVAL, None),
+        &fx.authority.pubkey(),
+    );
+
+    assert_anchor_error(fx.send(ix, &[]), AnchorErrorCode::AccountNotSigner);
+}
+
+#[test]
+fn initialize_rejects_fake_system_program() {
+    let mut fx = Fixture::new();
+    let ix = substitute(fx.initialize_ix(AMOUNT, INTERVAL, None), 8, spl_token::ID);
+    let authority = fx.authority.insecure_clone();
+
+    assert_anchor_error(
+        fx.send(ix, &[&authority]),
+        AnchorErrorCode::InvalidProgramId,
+    );
+}
+
+#[test]
+fn initialize_rejects_existing_subscription() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let ix = fx.initialize_ix(AMOUNT, INTERVAL, None);
+    let authority = fx.authority.insecure_clone();
+
+    // `init` refuses an address that already holds an account
+    assert_error(
+        fx.send(ix, &[&authority]),
+        SystemError::AccountAlreadyInUse as u32,
+    );
+    assert_eq!(fx.subscription().unwrap().total_charged, AMOUNT);
+}
+
+// ---------- charge_subscription ----------
+
+#[test]
+fn charge_before_interval_fails() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let ix = fx.charge_ix();
+
+    assert_program_error(fx.send(ix, &[]), ErrorCode::IntervalNotMet);
+}
+
+#[test]
+fn charge_when_due_moves_one_period() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.svm.advance_time(INTERVAL);
+    let ix = fx.charge_ix();
+
+    assert_success(fx.send(ix, &[]));
+    assert_eq!(
+        fx.svm.token_balance(&fx.recipient_token_account),
+        2 * AMOUNT
+    );
+    assert_eq!(fx.subscription().unwrap().total_charged, 2 * AMOUNT);
+}
+
+#[test]
+fn charge_with_reference_passes_validation() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.svm.advance_time(INTERVAL);
+    let mut ix = fx.charge_ix();
+    ix.data = instruction::ChargeSubscriptionWithReference { reference: [7; 32] }.data();
+
+    let subscription = fx.subscription_at_cpi(ix);
+    assert_eq!(subscription.total_charged, 2 * AMOUNT);
+}
+
+#[test]
+fn charge_inactive_subscription_fails() {
+    let mut fx = Fixture::new();
+    let mut subscription = fx.subscribe(None);
+    subscription.is_active = false;
+    fx.set_subscription(&subscription);
+    let ix = fx.charge_ix();
+
+    assert_program_error(fx.send(ix, &[]), ErrorCode::SubscriptionInactive);
+}
+
+#[test]
+fn charge_after_expiry_fails() {
+    let mut fx = Fixture::new();
+    let expires_at = fx.svm.clock().unix_timestamp + INTERVAL / 2;
+    fx.subscribe(Some(expires_at));
+    fx.svm.advance_time(INTERVAL);
+    let ix = fx.charge_ix();
+
+    assert_program_error(fx.send(ix, &[]), ErrorCode::SubscriptionExpired);
+}
+
+#[test]
+fn charge_into_other_token_account_fails() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let attacker = Pubkey::new_unique();
+    let attacker_ata = fx
+        .svm
+        .create_associated_token_account(&attacker, &fx.mint, 0);
+    let ix = substitute(fx.charge_ix(), 2, attacker_ata);
+
+    assert_anchor_error(fx.send(ix, &[]), AnchorErrorCode::ConstraintRaw);
+}
+
+#[test]
+fn charge_after_revoke_deactivates() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.svm.revoke(&fx.user_token_account);
+    fx.svm.advance_time(INTERVAL);
+    let balance = fx.svm.token_balance(&fx.user_token_account);
+    let ix = fx.charge_ix();
+
+    fx.send(ix, &[]).unwrap();
+    let subscription = fx.subscription().unwrap();
+    assert!(!subscription.is_active);
+    assert_eq!(subscription.total_charged, AMOUNT);
+    assert_eq!(fx.svm.token_balance(&fx.user_token_account), balance);
+
+    fx.svm.expire_blockhash();
+    let ix = fx.charge_ix();
+    assert_program_error(fx.send(ix, &[]), ErrorCode::SubscriptionInactive);
+}
+
+#[test]
+fn charge_with_allowance_below_amount_deactivates() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.svm
+        .approve(&fx.user_token_account, &fx.subscription, AMOUNT - 1);
+    fx.svm.advance_time(INTERVAL);
+    let ix = fx.charge_ix();
+
+    fx.send(ix, &[]).unwrap();
+    assert!(!fx.subscription().unwrap().is_active);
+}
+
+#[test]
+fn charge_before_due_ignores_revoked_delegation() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.svm.revoke(&fx.user_token_account);
+    let ix = fx.charge_ix();
+
+    assert_program_error(fx.send(ix, &[]), ErrorCode::IntervalNotMet);
+    assert!(fx.subscription().unwrap().is_active);
+}
+
+#[test]
+fn charge_delegated_to_policy_fails_without_deactivating() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.set_policy(AMOUNT);
+    fx.svm.advance_time(INTERVAL);
+    let ix = fx.charge_ix();
+
+    assert_program_error(fx.send(ix, &[]), ErrorCode::DelegatedToPolicy);
+    assert!(fx.subscription().unwrap().is_active);
+}
+
+/// Land `instruction`, which must succeed, and move to a fresh blockhash so
+/// an identical instruction after it is a new transaction
+fn land(fx: &mut Fixture, instruction: Instruction) {
+    assert_success(fx.send(instruction, &[]));
+    fx.svm.expire_blockhash();
+}
+
+#[test]
+fn racing_keepers_cannot_charge_a_period_twice() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.svm.advance_time(INTERVAL);
+    let period = fx.subscription().unwrap().period_index();
+    let (first, second) = (fx.charge_ix(), fx.charge_ix());
+
+    land(&mut fx, first);
+    assert!(fx.subscription().unwrap().period_index() > period);
+    assert_program_error(fx.send(second, &[]), ErrorCode::IntervalNotMet);
+}
+
+#[test]
+fn racing_keepers_with_catch_up_settle_distinct_periods() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let config = fx.set_merchant_config(true, 2);
+    fx.svm.advance_time(3 * INTERVAL);
+    let keepers: Vec<_> = (0..3).map(|_| fx.charge_with_config_ix(config)).collect();
+
+    let mut periods = vec![fx.subscription().unwrap().period_index()];
+    let mut keepers = keepers.into_iter();
+    for keeper in keepers.by_ref().take(2) {
+        land(&mut fx, keeper);
+        periods.push(fx.subscription().unwrap().period_index());
+    }
+    // Two periods, then the one left past the cap, each once
+    assert!(periods.windows(2).all(|pair| pair[0] < pair[1]));
+    assert_eq!(fx.subscription().unwrap().total_charged, 4 * AMOUNT);
+    assert_eq!(
+        fx.svm.token_balance(&fx.recipient_token_account),
+        4 * AMOUNT
+    );
+    assert_program_error(
+        fx.send(keepers.next().unwrap(), &[]),
+        ErrorCode::IntervalNotMet,
+    );
+}
+
+// ---------- charge_subscription_with_policy ----------
+
+#[test]
+fn charge_with_policy_when_due_spends_through_the_policy() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let policy = fx.set_policy(AMOUNT);
+    let ix = fx.charge_with_policy_ix(policy);
+    assert_program_error(fx.send(ix, &[]), ErrorCode::IntervalNotMet);
+
+    fx.svm.advance_time(INTERVAL);
+    let ix = fx.charge_with_policy_ix(policy);
+    assert_success(fx.send(ix, &[]));
+    assert_eq!(
+        fx.svm.token_balance(&fx.recipient_token_account),
+        2 * AMOUNT
+    );
+    assert_eq!(fx.subscription().unwrap().total_charged, 2 * AMOUNT);
+}
+
+#[test]
+fn charge_with_policy_on_a_plan_needs_its_plan_subscription() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.set_plan(BillingCadence::Monthly);
+    let policy = fx.set_policy(AMOUNT);
+    let due = fx.subscription().unwrap().period_index();
+    fx.svm.warp_to_timestamp(due);
+
+    let ix = fx.charge_with_policy_ix(policy);
+    assert_program_error(fx.send(ix, &[]), ErrorCode::PlanSubscriptionRequired);
+    let mut ix = fx.charge_with_policy_ix(policy);
+    ix.accounts.push(AccountMeta::new(
+        plan_subscription_address(&fx.subscription).0,
+        false,
+    ));
+    assert_success(fx.send(ix, &[]));
+}
+
+#[test]
+fn charge_with_policy_of_other_token_account_fails() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.svm.advance_time(INTERVAL);
+    // Another user's policy, which lists nobody's subscription but theirs
+    let mut other = Fixture::new();
+    let policy = other.set_policy(AMOUNT);
+    fx.svm
+        .set_account(policy, other.svm.get_account(&policy).unwrap());
+    let ix = fx.charge_with_policy_ix(policy);
+
+    assert_program_error(fx.send(ix, &[]), ErrorCode::InvalidTokenAccount);
+}
+
+// ---------- partial charges ----------
+
+/// Leave the user with `balance` tokens
+fn drain_to(fx: &mut Fixture, balance: u64) {
+    let excess = fx.svm.token_balance(&fx.user_token_account) - balance;
+    let sink = fx
+        .svm
+        .create_token_account(&Pubkey::new_unique(), &fx.mint, 0);
+    fx.svm
+        .transfer_tokens(&fx.user_token_account, &sink, excess);
+}
+
+#[test]
+fn partial_charge_takes_remaining_balance() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let config = fx.set_merchant_config(true, 1);
+    drain_to(&mut fx, AMOUNT / 4);
+    fx.svm.advance_time(INTERVAL);
+    let ix = fx.charge_with_config_ix(config);
+
+    let at_cpi = fx.subscription_at_cpi(ix);
+    assert_eq!(at_cpi.total_charged, AMOUNT + AMOUNT / 4);
+    assert_eq!(at_cpi.last_charge_timestamp, fx.svm.clock().unix_timestamp);
+}
+
+#[test]
+fn partial_charge_needs_merchant_opt_in() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let config = fx.set_merchant_config(false, 1);
+    drain_to(&mut fx, AMOUNT / 4);
+    fx.svm.advance_time(INTERVAL);
+
+    // Full amount, which the token program refuses, undoing the booking
+    let ix = fx.charge_with_config_ix(config);
+    assert_error(fx.send(ix, &[]), TokenError::InsufficientFunds as u32);
+    let ix = fx.charge_ix();
+    assert_error(fx.send(ix, &[]), TokenError::InsufficientFunds as u32);
+    assert_eq!(fx.subscription().unwrap().total_charged, AMOUNT);
+    assert_eq!(fx.svm.token_balance(&fx.user_token_account), AMOUNT / 4);
+}
+
+#[test]
+fn partial_charge_of_empty_account_charges_in_full() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let config = fx.set_merchant_config(true, 1);
+    drain_to(&mut fx, 0);
+    fx.svm.advance_time(INTERVAL);
+    let ix = fx.charge_with_config_ix(config);
+
+    assert_error(fx.send(ix, &[]), TokenError::InsufficientFunds as u32);
+    assert_eq!(fx.subscription().unwrap().total_charged, AMOUNT);
+}
+
+// ---------- catch-up charges ----------
+
+#[test]
+fn catch_up_settles_missed_periods() {
+    let mut fx = Fixture::new();
+    let start = fx.subscribe(None).last_charge_timestamp;
+    let config = fx.set_merchant_config(false, 6);
+    fx.svm.advance_time(3 * INTERVAL + INTERVAL / 2);
+    let ix = fx.charge_with_config_ix(config);
+
+    let at_cpi = fx.subscription_at_cpi(ix);
+    assert_eq!(at_cpi.total_charged, 4 * AMOUNT);
+    // Anchored to the schedule, so the next period is due in half an interval
+    assert_eq!(at_cpi.last_charge_timestamp, start + 3 * INTERVAL);
+}
+
+#[test]
+fn catch_up_stops_at_cap() {
+    let mut fx = Fixture::new();
+    let start = fx.subscribe(None).last_charge_timestamp;
+    let config = fx.set_merchant_config(false, 2);
+    fx.svm.advance_time(5 * INTERVAL);
+    let ix = fx.charge_with_config_ix(config);
+
+    let at_cpi = fx.subscription_at_cpi(ix);
+    assert_eq!(at_cpi.total_charged, 3 * AMOUNT);
+    assert_eq!(at_cpi.last_charge_timestamp, start + 2 * INTERVAL);
+    // The rest stays due
+    assert!(at_cpi
+        .check_chargeable(fx.svm.clock().unix_timestamp)
+        .is_ok());
+}
+
+#[test]
+fn charge_without_catch_up_forgives_missed_periods() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.svm.advance_time(3 * INTERVAL);
+    let ix = fx.charge_ix();
+
+    let at_cpi = fx.subscription_at_cpi(ix);
+    assert_eq!(at_cpi.total_charged, 2 * AMOUNT);
+    assert_eq!(at_cpi.last_charge_timestamp, fx.svm.clock().unix_timestamp);
+}
+
+#[test]
+fn catch_up_takes_only_the_periods_the_balance_pays() {
+    let mut fx = Fixture::new();
+    let start = fx.subscribe(None).last_charge_timestamp;
+    let config = fx.set_merchant_config(false, 4);
+    drain_to(&mut fx, 2 * AMOUNT + AMOUNT / 2);
+    fx.svm.advance_time(4 * INTERVAL);
+    let ix = fx.charge_with_config_ix(config);
+
+    let at_cpi = fx.subscription_at_cpi(ix);
+    assert_eq!(at_cpi.total_charged, 3 * AMOUNT);
+    assert_eq!(at_cpi.last_charge_timestamp, start + 2 * INTERVAL);
+    // The periods it could not pay stay due
+    assert!(at_cpi
+        .check_chargeable(fx.svm.clock().unix_timestamp)
+        .is_ok());
+}
+
+#[test]
+fn catch_up_with_partial_charges_pays_what_the_balance_covers() {
+    let mut fx = Fixture::new();
+    let start = fx.subscribe(None).last_charge_timestamp;
+    let config = fx.set_merchant_config(true, 4);
+    drain_to(&mut fx, AMOUNT + AMOUNT / 2);
+    fx.svm.advance_time(4 * INTERVAL);
+    let ix = fx.charge_with_config_ix(config);
+
+    let at_cpi = fx.subscription_at_cpi(ix);
+    assert_eq!(at_cpi.total_charged, 2 * AMOUNT + AMOUNT / 2);
+    assert_eq!(at_cpi.last_charge_timestamp, start + 2 * INTERVAL);
+}
+
+// ---------- merchant config ----------
+
+#[test]
+fn create_merchant_config_passes_validation() {
+    let mut fx = Fixture::new();
+    let recipient = Keypair::new();
+    let ix = build(
+        accounts::CreateMerchantConfig {
+            merchant_config: merchant_config_address(&recipient.pubkey()).0,
+            merchant_multisig: merchant_multisig_address(&recipient.pubkey()).0,
+            recipient: recipient.pubkey(),
+            authority: recipient.pubkey(),
+            payer: fx.payer.pubkey(),
+            system_program: system_program::ID,
+        },
+        instruction::CreateMerchantConfig {
+            allow_partial_charges: true,
+            max_periods_per_charge: 3,
+        },
+    );
+
+    assert_success(fx.send(ix, &[&recipient]));
+}
+
+#[test]
+fn update_merchant_config_by_recipient() {
+    let mut fx = Fixture::new();
+    let recipient = Keypair::new();
+    fx.recipient = recipient.pubkey();
+    let config = fx.set_merchant_config(false, 1);
+
+    let ix = fx.update_merchant_config_ix(&recipient.pubkey(), true);
+    fx.send(ix, &[&recipient]).unwrap();
+    let state: MerchantConfig = fx.svm.get_anchor_account(&config).unwrap();
+    assert!(state.allow_partial_charges);
+    assert_eq!(state.max_periods_per_charge, 3);
+
+    let intruder = Keypair::new();
+    let ix = fx.update_merchant_config_ix(&intruder.pubkey(), false);
+    assert_program_error(fx.send(ix, &[&intruder]), ErrorCode::NotMerchantAuthority);
+
+    // Another recipient's config under the intruder's key
+    let ix = substitute(
+        fx.update_merchant_config_ix(&intruder.pubkey(), false),
+        2,
+        intruder.pubkey(),
+    );
+    assert_anchor_error(fx.send(ix, &[&intruder]), AnchorErrorCode::ConstraintSeeds);
+}
+
+// ---------- merchant multisig ----------
+
+/// A Squads v4 multisig account, as far as the program looks at it
+fn squads_multisig(fx: &mut Fixture) -> Pubkey {
+    let address = Pubkey::new_unique();
+    let mut data = SQUADS_MULTISIG_DISCRIMINATOR.to_vec();
+    data.resize(128, 0);
+    fx.svm.set_account(
+        address,
+        Account {
+            lamports: 1_000_000_000,
+            data,
+            owner: SQUADS_PROGRAM_ID,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+    address
+}
+
+#[test]
+fn set_merchant_multisig_passes_validation() {
+    let mut fx = Fixture::new();
+    let recipient = Keypair::new();
+    let multisig = squads_multisig(&mut fx);
+    let ix = build(
+        accounts::SetMerchantMultisig {
+            merchant_multisig: merchant_multisig_address(&recipient.pubkey()).0,
+            multisig,
+            recipient: recipient.pubkey(),
+            payer: fx.payer.pubkey(),
+            system_program: system_program::ID,
+        },
+        instruction::SetMerchantMultisig { vault_index: 0 },
+    );
+
+    assert_success(fx.send(ix, &[&recipient]));
+}
+
+#[test]
+fn only_squads_multisigs_are_accepted() {
+    let mut fx = Fixture::new();
+    let multisig = squads_multisig(&mut fx);
+    let mut account = fx.svm.get_account(&multisig).unwrap();
+    let is_multisig = |account: &mut Account| {
+        let key = Pubkey::new_unique();
+        let info = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut account.lamports,
+            &mut account.data,
+            &account.owner,
+            false,
+            0,
+        );
+        is_squads_multisig(&info)
+    };
+    assert!(is_multisig(&mut account.clone()));
+
+    // Another Squads account, e.g. a proposal
+    let mut proposal = account.clone();
+    proposal.data[..8].copy_from_slice(&[1; 8]);
+    assert!(!is_multisig(&mut proposal));
+
+    // The right bytes under another owner
+    account.owner = Pubkey::new_unique();
+    assert!(!is_multisig(&mut account));
+}
+
+#[test]
+fn multisig_vault_replaces_the_recipient_key() {
+    let mut fx = Fixture::new();
+    let recipient = Keypair::new();
+    fx.recipient = recipient.pubkey();
+    let config = fx.set_merchant_config(false, 1);
+    let vault = Keypair::new();
+    fx.set_merchant_multisig(vault.pubkey());
+
+    let ix = fx.update_merchant_config_ix(&recipient.pubkey(), true);
+    assert_program_error(
+        fx.send(ix, &[&recipient]),
+        ErrorCode::MultisigApprovalRequired,
+    );
+
+    let ix = fx.update_merchant_config_ix(&vault.pubkey(), true);
+    fx.send(ix, &[&vault]).unwrap();
+    let state: MerchantConfig = fx.svm.get_anchor_account(&config).unwrap();
+    assert!(state.allow_partial_charges);
+}
+
+#[test]
+fn close_merchant_multisig_needs_the_vault() {
+    let mut fx = Fixture::new();
+    let recipient = Keypair::new();
+    fx.recipient = recipient.pubkey();
+    fx.set_merchant_config(false, 1);
+    let vault = Keypair::new();
+    let address = fx.set_merchant_multisig(vault.pubkey());
+    let rent = fx.svm.get_balance(&address);
+    let close = |vault: &Pubkey| {
+        build(
+            accounts::CloseMerchantMultisig {
+                merchant_multisig: address,
+                recipient: recipient.pubkey(),
+                vault: *vault,
+            },
+            instruction::CloseMerchantMultisig {},
+        )
+    };
+
+    assert_program_error(
+        fx.send(close(&recipient.pubkey()), &[&recipient]),
+        ErrorCode::MultisigApprovalRequired,
+    );
+
+    fx.send(close(&vault.pubkey()), &[&vault]).unwrap();
+    assert!(fx.svm.get_account(&address).is_none());
+    assert_eq!(fx.svm.get_balance(&recipient.pubkey()), rent);
+
+    // Back under the recipient's key
+    let ix = fx.update_merchant_config_ix(&recipient.pubkey(), true);
+    fx.send(ix, &[&recipient]).unwrap();
+}
+
+// ---------- payout account rotation ----------
+
+#[test]
+fn set_recipient_token_account_passes_validation() {
+    let mut fx = Fixture::new();
+    let recipient = Keypair::new();
+    let token_account = fx
+        .svm
+        .create_token_account(&recipient.pubkey(), &fx.mint, 0);
+    let ix = build(
+        accounts::SetRecipientTokenAccount {
+            payout_change: payout_change_address(&recipient.pubkey()).0,
+            merchant_multisig: merchant_multisig_address(&recipient.pubkey()).0,
+            recipient: recipient.pubkey(),
+            authority: recipient.pubkey(),
+            token_account,
+            payer: fx.payer.pubkey(),
+            system_program: system_program::ID,
+        },
+        instruction::SetRecipientTokenAccount {},
+    );
+
+    assert_success(fx.send(ix, &[&recipient]));
+}
+
+#[test]
+fn payout_change_waits_for_the_timelock() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let treasury = fx.svm.create_token_account(&fx.recipient, &fx.mint, 0);
+    let effective_at = fx.svm.clock().unix_timestamp + PAYOUT_TIMELOCK_SECONDS;
+    fx.set_payout_change(treasury, effective_at);
+
+    assert_program_error(
+        fx.send(fx.apply_payout_ix(treasury), &[]),
+        ErrorCode::PayoutChangeTimelocked,
+    );
+    assert_eq!(
+        fx.subscription().unwrap().recipient_token_account,
+        fx.recipient_token_account
+    );
+
+    fx.svm.warp_to_timestamp(effective_at);
+    fx.svm.expire_blockhash();
+    fx.send(fx.apply_payout_ix(treasury), &[]).unwrap();
+    assert_eq!(fx.subscription().unwrap().recipient_token_account, treasury);
+}
+
+#[test]
+fn payout_change_applies_only_the_scheduled_account() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let treasury = fx.svm.create_token_account(&fx.recipient, &fx.mint, 0);
+    let effective_at = fx.svm.clock().unix_timestamp;
+    fx.set_payout_change(treasury, effective_at);
+
+    let attacker = fx
+        .svm
+        .create_token_account(&Pubkey::new_unique(), &fx.mint, 0);
+    assert_anchor_error(
+        fx.send(fx.apply_payout_ix(attacker), &[]),
+        AnchorErrorCode::ConstraintHasOne,
+    );
+
+    // Scheduled, but for another mint than the subscription's
+    let other_mint = fx.svm.create_mint(&Pubkey::new_unique(), 6);
+    let wrong_mint = fx.svm.create_token_account(&fx.recipient, &other_mint, 0);
+    fx.set_payout_change(wrong_mint, effective_at);
+    assert_program_error(
+        fx.send(fx.apply_payout_ix(wrong_mint), &[]),
+        ErrorCode::InvalidPayoutAccount,
+    );
+}
+
+#[test]
+fn close_payout_change_needs_the_merchant_authority() {
+    let mut fx = Fixture::new();
+    let recipient = Keypair::new();
+    fx.recipient = recipient.pubkey();
+    let treasury = fx.svm.create_token_account(&fx.recipient, &fx.mint, 0);
+    let address = fx.set_payout_change(treasury, 0);
+    let rent = fx.svm.get_balance(&address);
+    let close = |authority: &Pubkey| {
+        build(
+            accounts::ClosePayoutChange {
+                payout_change: address,
+                merchant_multisig: merchant_multisig_address(&recipient.pubkey()).0,
+                recipient: recipient.pubkey(),
+                authority: *authority,
+            },
+            instruction::ClosePayoutChange {},
+        )
+    };
+
+    let intruder = Keypair::new();
+    assert_program_error(
+        fx.send(close(&intruder.pubkey()), &[&intruder]),
+        ErrorCode::NotMerchantAuthority,
+    );
+
+    // Under a multisig, the recipient's key no longer withdraws it either
+    let vault = Keypair::new();
+    let multisig = fx.set_merchant_multisig(vault.pubkey());
+    assert_program_error(
+        fx.send(close(&recipient.pubkey()), &[&recipient]),
+        ErrorCode::MultisigApprovalRequired,
+    );
+
+    fx.send(close(&vault.pubkey()), &[&vault]).unwrap();
+    assert!(fx.svm.get_account(&address).is_none());
+    assert_eq!(fx.svm.get_balance(&recipient.pubkey()), rent);
+    assert!(fx.svm.get_account(&multisig).is_some());
+}
+
+#[test]
+fn payout_change_takes_effect_at_the_deadline() {
+    let change = PayoutChange {
+        recipient: Pubkey::new_unique(),
+        token_account: Pubkey::new_unique(),
+        effective_at: 1_000,
+        bump: 255,
+    };
+
+    assert!(!change.is_effective(999));
+    assert!(change.is_effective(1_000));
+    assert!(change.is_effective(i64::MAX));
+}
+
+// ---------- merchant vault ----------
+
+#[test]
+fn create_merchant_vault_passes_validation() {
+    let mut fx = Fixture::new();
+    let recipient = Keypair::new();
+    let vault = merchant_vault_address(&recipient.pubkey()).0;
+    let token_account = fx.svm.create_token_account(&vault, &fx.mint, 0);
+    let ix = build(
+        accounts::CreateMerchantVault {
+            vault,
+            merchant_multisig: merchant_multisig_address(&recipient.pubkey()).0,
+            recipient: recipient.pubkey(),
+            authority: recipient.pubkey(),
+            token_account,
+            payer: fx.payer.pubkey(),
+            system_program: system_program::ID,
+        },
+        instruction::CreateMerchantVault {},
+    );
+
+    assert_success(fx.send(ix, &[&recipient]));
+}
+
+#[test]
+fn vault_token_account_must_be_held_by_the_vault() {
+    let vault = Pubkey::new_unique();
+    let token_account = spl_token::state::Account {
+        mint: Pubkey::new_unique(),
+        owner: vault,
+        state: spl_token::state::AccountState::Initialized,
+        ..Default::default()
+    };
+    assert!(MerchantVault::check_token_account(&vault, &token_account).is_ok());
+
+    let owned_by_merchant = spl_token::state::Account {
+        owner: Pubkey::new_unique(),
+        ..token_account
+    };
+    let delegated = spl_token::state::Account {
+        delegate: Some(Pubkey::new_unique()).into(),
+        delegated_amount: 1,
+        ..token_account
+    };
+    let closable = spl_token::state::Account {
+        close_authority: Some(Pubkey::new_unique()).into(),
+        ..token_account
+    };
+    for token_account in [owned_by_merchant, delegated, closable] {
+        assert_eq!(
+            MerchantVault::check_token_account(&vault, &token_account).unwrap_err(),
+            ErrorCode::InvalidVaultTokenAccount.into()
+        );
+    }
+}
+
+#[test]
+fn withdraw_revenue_pays_the_destination() {
+    let mut fx = Fixture::new();
+    let recipient = Keypair::new();
+    fx.recipient = recipient.pubkey();
+    let token_account = fx.set_merchant_vault(3 * AMOUNT);
+    let destination = fx.approved_destination();
+    let ix = fx.withdraw_revenue_ix(&fx.recipient, token_account, destination, 2 * AMOUNT);
+
+    let accounts = assert_success(fx.send(ix, &[&recipient])).accounts_at_cpi;
+
+    // Recorded before the transfer
+    let vault = merchant_vault_address(&fx.recipient).0;
+    let (_, account) = accounts.iter().find(|(key, _)| *key == vault).unwrap();
+    let state = MerchantVault::try_deserialize(&mut &account.data[..]).unwrap();
+    assert_eq!(state.total_withdrawn, 2 * AMOUNT);
+    assert_eq!(fx.svm.token_balance(&destination), 2 * AMOUNT);
+    assert_eq!(fx.svm.token_balance(&token_account), AMOUNT);
+}
+
+#[test]
+fn withdraw_revenue_needs_the_merchant_authority() {
+    let mut fx = Fixture::new();
+    let recipient = Keypair::new();
+    fx.recipient = recipient.pubkey();
+    let token_account = fx.set_merchant_vault(AMOUNT);
+    let destination = fx.approved_destination();
+
+    let intruder = Keypair::new();
+    let ix = fx.withdraw_revenue_ix(&intruder.pubkey(), token_account, destination, AMOUNT);
+    assert_program_error(fx.send(ix, &[&intruder]), ErrorCode::NotMerchantAuthority);
+
+    let vault = Keypair::new();
+    fx.set_merchant_multisig(vault.pubkey());
+    let ix = fx.withdraw_revenue_ix(&recipient.pubkey(), token_account, destination, AMOUNT);
+    assert_program_error(
+        fx.send(ix, &[&recipient]),
+        ErrorCode::MultisigApprovalRequired,
+    );
+
+    let ix = fx.withdraw_revenue_ix(&vault.pubkey(), token_account, destination, AMOUNT);
+    assert_success(fx.send(ix, &[&vault]));
+}
+
+#[test]
+fn withdraw_revenue_is_bounded_by_the_balance() {
+    let mut fx = Fixture::new();
+    let recipient = Keypair::new();
+    fx.recipient = recipient.pubkey();
+    let token_account = fx.set_merchant_vault(AMOUNT);
+    let destination = fx.approved_destination();
+
+    for amount in [0, AMOUNT + 1] {
+        let ix = fx.withdraw_revenue_ix(&fx.recipient, token_account, destination, amount);
+        assert_program_error(fx.send(ix, &[&recipient]), ErrorCode::InvalidWithdrawal);
+    }
+}
+
+#[test]
+fn withdraw_revenue_only_drains_the_vault_account() {
+    let mut fx = Fixture::new();
+    let recipient = Keypair::new();
+    fx.recipient = recipient.pubkey();
+    fx.set_merchant_vault(AMOUNT);
+    let destination = fx.approved_destination();
+
+    // Another account the vault PDA happens to own
+    let vault = merchant_vault_address(&fx.recipient).0;
+    let other = fx.svm.create_token_account(&vault, &fx.mint, AMOUNT);
+    let ix = fx.withdraw_revenue_ix(&fx.recipient, other, destination, AMOUNT);
+    assert_anchor_error(
+        fx.send(ix, &[&recipient]),
+        AnchorErrorCode::ConstraintHasOne,
+    );
+}
+
+#[test]
+fn withdrawal_destinations_wait_out_the_timelock() {
+    let mut fx = Fixture::new();
+    let recipient = Keypair::new();
+    fx.recipient = recipient.pubkey();
+    let token_account = fx.set_merchant_vault(AMOUNT);
+    let cold = fx
+        .svm
+        .create_token_account(&Pubkey::new_unique(), &fx.mint, 0);
+    let add = |fx: &Fixture, authority: &Pubkey, token_account: Pubkey| {
+        build(
+            accounts::AddWithdrawalDestination {
+                vault: merchant_vault_address(&fx.recipient).0,
+                merchant_multisig: merchant_multisig_address(&fx.recipient).0,
+                recipient: fx.recipient,
+                authority: *authority,
+                token_account,
+            },
+            instruction::AddWithdrawalDestination {},
+        )
+    };
+
+    let intruder = Keypair::new();
+    assert_program_error(
+        fx.send(add(&fx, &intruder.pubkey(), cold), &[&intruder]),
+        ErrorCode::NotMerchantAuthority,
+    );
+    let other_mint = fx.svm.create_mint(&Pubkey::new_unique(), 6);
+    let wrong_mint = fx.svm.create_token_account(&fx.recipient, &other_mint, 0);
+    assert_program_error(
+        fx.send(add(&fx, &fx.recipient, wrong_mint), &[&recipient]),
+        ErrorCode::InvalidWithdrawalDestination,
+    );
+
+    fx.send(add(&fx, &fx.recipient, cold), &[&recipient])
+        .unwrap();
+    let effective_at = fx.svm.clock().unix_timestamp + PAYOUT_TIMELOCK_SECONDS;
+    assert_eq!(
+        fx.merchant_vault().destinations,
+        vec![WithdrawalDestination {
+            token_account: cold,
+            effective_at,
+        }]
+    );
+
+    let ix = fx.withdraw_revenue_ix(&fx.recipient, token_account, cold, AMOUNT);
+    assert_program_error(
+        fx.send(ix.clone(), &[&recipient]),
+        ErrorCode::DestinationNotApproved,
+    );
+    fx.svm.warp_to_timestamp(effective_at);
+    assert_success(fx.send(ix, &[&recipient]));
+}
+
+#[test]
+fn withdrawal_destinations_are_removed_at_once() {
+    let mut fx = Fixture::new();
+    let recipient = Keypair::new();
+    fx.recipient = recipient.pubkey();
+    let token_account = fx.set_merchant_vault(AMOUNT);
+    let destination = fx.approved_destination();
+    let remove = |token_account: Pubkey| {
+        build(
+            accounts::RemoveWithdrawalDestination {
+                vault: merchant_vault_address(&recipient.pubkey()).0,
+                merchant_multisig: merchant_multisig_address(&recipient.pubkey()).0,
+                recipient: recipient.pubkey(),
+                authority: recipient.pubkey(),
+            },
+            instruction::RemoveWithdrawalDestination { token_account },
+        )
+    };
+
+    assert_program_error(
+        fx.send(remove(Pubkey::new_unique()), &[&recipient]),
+        ErrorCode::InvalidWithdrawalDestination,
+    );
+    fx.send(remove(destination), &[&recipient]).unwrap();
+    assert!(fx.merchant_vault().destinations.is_empty());
+
+    let ix = fx.withdraw_revenue_ix(&fx.recipient, token_account, destination, AMOUNT);
+    assert_program_error(
+        fx.send(ix, &[&recipient]),
+        ErrorCode::DestinationNotApproved,
+    );
+}
+
+#[test]
+fn withdrawal_destinations_are_few_and_distinct() {
+    let mut vault = merchant_vault();
+    let destination = |token_account| WithdrawalDestination {
+        token_account,
+        effective_at: 0,
+    };
+    let first = Pubkey::new_unique();
+    vault.add_destination(destination(first)).unwrap();
+    assert_eq!(
+        vault.add_destination(destination(first)).unwrap_err(),
+        ErrorCode::InvalidWithdrawalDestination.into()
+    );
+    while vault.destinations.len() < MAX_WITHDRAWAL_DESTINATIONS {
+        vault
+            .add_destination(destination(Pubkey::new_unique()))
+            .unwrap();
+    }
+    assert_eq!(
+        vault
+            .add_destination(destination(Pubkey::new_unique()))
+            .unwrap_err(),
+        ErrorCode::TooManyWithdrawalDestinations.into()
+    );
+
+    assert!(vault.is_approved_destination(&first, 0));
+    vault.destinations[0].effective_at = 1;
+    assert!(!vault.is_approved_destination(&first, 0));
+}
+
+// ---------- revenue holdback ----------
+
+/// An empty vault, for the pure accounting tests
+fn merchant_vault() -> MerchantVault {
+    MerchantVault {
+        recipient: Pubkey::new_unique(),
+        token_account: Pubkey::new_unique(),
+        token_mint: Pubkey::new_unique(),
+        total_withdrawn: 0,
+        total_received: 0,
+        holdback_bps: 0,
+        holdback_days: 0,
+        holds: Vec::new(),
+        withdrawal_limit: 0,
+        withdrawal_period_seconds: 0,
+        withdrawal_admin: Pubkey::default(),
+        period_started_at: 0,
+        withdrawn_in_period: 0,
+        destinations: Vec::new(),
+        bump: 255,
+    }
+}
+
+fn set_vault_holdback_ix(fx: &Fixture, authority: &Pubkey, bps: u16, days: u16) -> Instruction {
+    let vault = merchant_vault_address(&fx.recipient).0;
+    build(
+        accounts::SetVaultHoldback {
+            vault,
+            merchant_multisig: merchant_multisig_address(&fx.recipient).0,
+            recipient: fx.recipient,
+            authority: *authority,
+            token_account: fx.merchant_vault().token_account,
+        },
+        instruction::SetVaultHoldback {
+            holdback_bps: bps,
+            holdback_days: days,
+        },
+    )
+}
+
+fn sync_merchant_vault_ix(fx: &Fixture) -> Instruction {
+    build(
+        accounts::SyncMerchantVault {
+            vault: merchant_vault_address(&fx.recipient).0,
+            token_account: fx.merchant_vault().token_account,
+        },
+        instruction::SyncMerchantVault {},
+    )
+}
+
+#[test]
+fn set_vault_holdback_needs_the_merchant_authority() {
+    let mut fx = Fixture::new();
+    let recipient = Keypair::new();
+    fx.recipient = recipient.pubkey();
+    fx.set_merchant_vault(0);
+
+    let intruder = Keypair::new();
+    let ix = set_vault_holdback_ix(&fx, &intruder.pubkey(), 1_000, 14);
+    assert_program_error(fx.send(ix, &[&intruder]), ErrorCode::NotMerchantAuthority);
+
+    let ix = set_vault_holdback_ix(&fx, &fx.recipient, 10_001, 14);
+    assert_program_error(fx.send(ix, &[&recipient]), ErrorCode::InvalidHoldback);
+    let ix = set_vault_holdback_ix(&fx, &fx.recipient, 1_000, MAX_HOLDBACK_DAYS + 1);
+    assert_program_error(fx.send(ix, &[&recipient]), ErrorCode::InvalidHoldback);
+
+    let ix = set_vault_holdback_ix(&fx, &fx.recipient, 1_000, 14);
+    fx.send(ix, &[&recipient]).unwrap();
+    let vault = fx.merchant_vault();
+    assert_eq!((vault.holdback_bps, vault.holdback_days), (1_000, 14));
+}
+
+#[test]
+fn holdback_applies_to_revenue_received_after_it_is_set() {
+    let mut fx = Fixture::new();
+    let recipient = Keypair::new();
+    fx.recipient = recipient.pubkey();
+    let token_account = fx.set_merchant_vault(AMOUNT);
+
+    // The balance so far is synced under the old terms, with nothing held
+    let ix = set_vault_holdback_ix(&fx, &fx.recipient, 2_000, 7);
+    fx.send(ix, &[&recipient]).unwrap();
+    assert_eq!(fx.merchant_vault().total_received, AMOUNT);
+    assert!(fx.merchant_vault().holds.is_empty());
+
+    fx.svm.mint_tokens(&token_account, AMOUNT);
+    fx.send(sync_merchant_vault_ix(&fx), &[]).unwrap();
+    let now = fx.svm.clock().unix_timestamp;
+    let vault = fx.merchant_vault();
+    assert_eq!(vault.total_received, 2 * AMOUNT);
+    assert_eq!(
+        vault.holds,
+        vec![RevenueHold {
+            amount: AMOUNT / 5,
+            release_at: now + 7 * SECONDS_PER_DAY,
+        }]
+    );
+}
+
+#[test]
+fn withdraw_revenue_leaves_the_held_share() {
+    let mut fx = Fixture::new();
+    let recipient = Keypair::new();
+    fx.recipient = recipient.pubkey();
+    let token_account = fx.set_merchant_vault(AMOUNT);
+    fx.set_vault_holdback(2_000, 7);
+    let destination = fx.approved_destination();
+
+    let ix = fx.withdraw_revenue_ix(&fx.recipient, token_account, destination, AMOUNT);
+    assert_program_error(fx.send(ix, &[&recipient]), ErrorCode::RevenueHeldBack);
+
+    // The bulk is withdrawable at once
+    fx.svm.expire_blockhash();
+    let ix = fx.withdraw_revenue_ix(&fx.recipient, token_account, destination, AMOUNT * 4 / 5);
+    assert_success(fx.send(ix, &[&recipient]));
+
+    // The held fifth once the window has passed
+    fx.send(sync_merchant_vault_ix(&fx), &[]).unwrap();
+    fx.svm.advance_time(7 * SECONDS_PER_DAY);
+    let ix = fx.withdraw_revenue_ix(&fx.recipient, token_account, destination, AMOUNT / 5);
+    assert_success(fx.send(ix, &[&recipient]));
+    assert_eq!(fx.svm.token_balance(&token_account), 0);
+    assert_eq!(fx.svm.token_balance(&destination), AMOUNT);
+}
+
+#[test]
+fn revenue_holds_release_in_order_and_never_early() {
+    let mut vault = MerchantVault {
+        holdback_bps: 1_000,
+        holdback_days: 1,
+        ..merchant_vault()
+    };
+
+    // Nothing new, nothing held
+    assert_eq!(vault.sync(0, 0).unwrap(), None);
+
+    vault.sync(1_000, 0).unwrap();
+    vault.sync(3_000, SECONDS_PER_DAY / 2).unwrap();
+    assert_eq!(vault.held(0), 300);
+    assert_eq!(vault.withdrawable(3_000, 0), 2_700);
+    assert_eq!(vault.held(SECONDS_PER_DAY), 200);
+    assert_eq!(vault.held(SECONDS_PER_DAY * 3 / 2), 0);
+
+    // Withdrawals are not new revenue
+    vault.total_withdrawn = 2_700;
+    assert_eq!(vault.sync(300, SECONDS_PER_DAY).unwrap(), None);
+    assert_eq!(vault.holds.len(), 1);
+
+    // Once full, new revenue joins the newest hold and waits as long
+    for second in 0..=MAX_REVENUE_HOLDS as i64 {
+        let received = vault.total_received + 100;
+        vault
+            .sync(
+                received - vault.total_withdrawn,
+                2 * SECONDS_PER_DAY + second,
+            )
+            .unwrap();
+    }
+    assert_eq!(vault.holds.len(), MAX_REVENUE_HOLDS);
+    let newest = *vault.holds.last().unwrap();
+    assert_eq!(newest.amount, 20);
+    assert_eq!(
+        newest.release_at,
+        3 * SECONDS_PER_DAY + MAX_REVENUE_HOLDS as i64
+    );
+}
+
+// ---------- withdrawal limits ----------
+
+fn set_withdrawal_limit_ix(
+    fx: &Fixture,
+    authority: &Pubkey,
+    limit: u64,
+    period_seconds: i64,
+    admin: Pubkey,
+) -> Instruction {
+    build(
+        accounts::SetWithdrawalLimit {
+            vault: merchant_vault_address(&fx.recipient).0,
+            merchant_multisig: merchant_multisig_address(&fx.recipient).0,
+            recipient: fx.recipient,
+            authority: *authority,
+        },
+        instruction::SetWithdrawalLimit {
+            limit,
+            period_seconds,
+            admin,
+        },
+    )
+}
+
+#[test]
+fn merchant_can_only_tighten_a_withdrawal_limit() {
+    let mut fx = Fixture::new();
+    let recipient = Keypair::new();
+    fx.recipient = recipient.pubkey();
+    fx.set_merchant_vault(0);
+    let admin = Keypair::new();
+    let day = SECONDS_PER_DAY;
+    let send = |fx: &mut Fixture, signer: &Keypair, limit, period, admin| {
+        fx.svm.expire_blockhash();
+        let ix = set_withdrawal_limit_ix(fx, &signer.pubkey(), limit, period, admin);
+        fx.send(ix, &[signer])
+    };
+
+    // A limit needs all three parts
+    assert_program_error(
+        send(&mut fx, &recipient, AMOUNT, day, Pubkey::default()),
+        ErrorCode::InvalidWithdrawalLimit,
+    );
+    assert_program_error(
+        send(&mut fx, &recipient, AMOUNT, 0, admin.pubkey()),
+        ErrorCode::InvalidWithdrawalLimit,
+    );
+
+    send(&mut fx, &recipient, AMOUNT, day, admin.pubkey()).unwrap();
+    send(&mut fx, &recipient, AMOUNT / 2, 2 * day, admin.pubkey()).unwrap();
+    let vault = fx.merchant_vault();
+    assert_eq!(vault.withdrawal_limit, AMOUNT / 2);
+    assert_eq!(vault.withdrawal_period_seconds, 2 * day);
+
+    for (limit, period, new_admin) in [
+        (AMOUNT, 2 * day, admin.pubkey()),
+        (AMOUNT / 2, day, admin.pubkey()),
+        (AMOUNT / 2, 2 * day, recipient.pubkey()),
+        (0, 0, Pubkey::default()),
+    ] {
+        assert_program_error(
+            send(&mut fx, &recipient, limit, period, new_admin),
+            ErrorCode::WithdrawalLimitLocked,
+        );
+    }
+
+    // The admin can raise it and remove it
+    send(&mut fx, &admin, AMOUNT, day, admin.pubkey()).unwrap();
+    assert_eq!(fx.merchant_vault().withdrawal_limit, AMOUNT);
+    send(&mut fx, &admin, 0, 0, Pubkey::default()).unwrap();
+    let vault = fx.merchant_vault();
+    assert!(!vault.has_withdrawal_limit());
+    assert_eq!(vault.withdrawal_admin, Pubkey::default());
+}
+
+#[test]
+fn withdraw_revenue_is_capped_per_period() {
+    let mut fx = Fixture::new();
+    let recipient = Keypair::new();
+    fx.recipient = recipient.pubkey();
+    let token_account = fx.set_merchant_vault(3 * AMOUNT);
+    fx.set_withdrawal_limit(AMOUNT, SECONDS_PER_DAY, Pubkey::new_unique());
+    let destination = fx.approved_destination();
+
+    let ix = fx.withdraw_revenue_ix(&fx.recipient, token_account, destination, AMOUNT + 1);
+    assert_program_error(
+        fx.send(ix, &[&recipient]),
+        ErrorCode::WithdrawalLimitExceeded,
+    );
+
+    let ix = fx.withdraw_revenue_ix(&fx.recipient, token_account, destination, AMOUNT);
+    assert_success(fx.send(ix, &[&recipient]));
+}
+
+#[test]
+fn withdrawal_limit_resets_each_period() {
+    let mut vault = MerchantVault {
+        withdrawal_limit: 100,
+        withdrawal_period_seconds: 10,
+        withdrawal_admin: Pubkey::new_unique(),
+        ..merchant_vault()
+    };
+
+    vault.record_limited_withdrawal(60, 1_000).unwrap();
+    vault.record_limited_withdrawal(40, 1_009).unwrap();
+    assert_eq!(
+        vault.record_limited_withdrawal(1, 1_009).unwrap_err(),
+        ErrorCode::WithdrawalLimitExceeded.into()
+    );
+    assert_eq!(vault.withdrawn_in_period, 100);
+
+    vault.record_limited_withdrawal(100, 1_010).unwrap();
+    assert_eq!(vault.period_started_at, 1_010);
+}
+
+#[test]
+fn withdrawal_admin_recovers_past_the_limit() {
+    let mut fx = Fixture::new();
+    let recipient = Keypair::new();
+    fx.recipient = recipient.pubkey();
+    let token_account = fx.set_merchant_vault(3 * AMOUNT);
+    let admin = Keypair::new();
+    fx.set_withdrawal_limit(AMOUNT, SECONDS_PER_DAY, admin.pubkey());
+    let safe = fx.svm.create_token_account(&admin.pubkey(), &fx.mint, 0);
+    let recover = |signer: &Pubkey| {
+        build(
+            accounts::RecoverRevenue {
+                vault: merchant_vault_address(&recipient.pubkey()).0,
+                recipient: recipient.pubkey(),
+                withdrawal_admin: *signer,
+                token_account,
+                destination: safe,
+                token_program: spl_token::ID,
+            },
+            instruction::RecoverRevenue { amount: 3 * AMOUNT },
+        )
+    };
+
+    assert_anchor_error(
+        fx.send(recover(&recipient.pubkey()), &[&recipient]),
+        AnchorErrorCode::ConstraintHasOne,
+    );
+    assert_success(fx.send(recover(&admin.pubkey()), &[&admin]));
+}
+
+// ---------- SLA commitments ----------
+
+#[test]
+fn create_sla_commitment_passes_validation() {
+    let mut fx = Fixture::new();
+    let recipient = Keypair::new();
+    fx.recipient = recipient.pubkey();
+    fx.set_merchant_vault(0);
+    fx.set_vault_holdback(1_000, 14);
+    let ix = build(
+        accounts::CreateSlaCommitment {
+            sla: sla_address(&fx.recipient).0,
+            vault: merchant_vault_address(&fx.recipient).0,
+            merchant_multisig: merchant_multisig_address(&fx.recipient).0,
+            recipient: fx.recipient,
+            authority: fx.recipient,
+            payer: fx.payer.pubkey(),
+            system_program: system_program::ID,
+        },
+        instruction::CreateSlaCommitment {
+            attester: Pubkey::new_unique(),
+            credit_multiplier: 10,
+        },
+    );
+
+    assert_success(fx.send(ix, &[&recipient]));
+}
+
+#[test]
+fn sla_commitment_ends_after_notice() {
+    let mut fx = Fixture::new();
+    let recipient = Keypair::new();
+    fx.recipient = recipient.pubkey();
+    let sla = fx.set_sla_commitment(Pubkey::new_unique(), 10);
+    let rent = fx.svm.get_balance(&sla);
+    let end = |authority: &Pubkey| {
+        build(
+            accounts::EndSlaCommitment {
+                sla,
+                merchant_multisig: merchant_multisig_address(&recipient.pubkey()).0,
+                recipient: recipient.pubkey(),
+                authority: *authority,
+            },
+            instruction::EndSlaCommitment {},
+        )
+    };
+    let close = build(
+        accounts::CloseSlaCommitment {
+            sla,
+            merchant_multisig: merchant_multisig_address(&recipient.pubkey()).0,
+            recipient: recipient.pubkey(),
+            authority: recipient.pubkey(),
+        },
+        instruction::CloseSlaCommitment {},
+    );
+
+    let intruder = Keypair::new();
+    assert_program_error(
+        fx.send(end(&intruder.pubkey()), &[&intruder]),
+        ErrorCode::NotMerchantAuthority,
+    );
+    assert_program_error(
+        fx.send(close.clone(), &[&recipient]),
+        ErrorCode::SlaCommitmentActive,
+    );
+
+    fx.send(end(&recipient.pubkey()), &[&recipient]).unwrap();
+    let ends_at = fx.svm.clock().unix_timestamp + SLA_NOTICE_SECONDS;
+    let state: SlaCommitment = fx.svm.get_anchor_account(&sla).unwrap();
+    assert_eq!(state.ends_at, ends_at);
+
+    // Giving notice again does not push the end back
+    fx.svm.advance_time(SECONDS_PER_DAY);
+    fx.send(end(&recipient.pubkey()), &[&recipient]).unwrap();
+    let state: SlaCommitment = fx.svm.get_anchor_account(&sla).unwrap();
+    assert_eq!(state.ends_at, ends_at);
+
+    fx.svm.expire_blockhash();
+    assert_program_error(
+        fx.send(close.clone(), &[&recipient]),
+        ErrorCode::SlaCommitmentActive,
+    );
+    fx.svm.warp_to_timestamp(ends_at);
+    fx.send(close, &[&recipient]).unwrap();
+    assert!(fx.svm.get_account(&sla).is_none());
+    assert_eq!(fx.svm.get_balance(&recipient.pubkey()), rent);
+}
+
+#[test]
+fn attest_downtime_passes_validation() {
+    let mut fx = Fixture::new();
+    let attester = Keypair::new();
+    fx.set_sla_commitment(attester.pubkey(), 10);
+    let now = fx.svm.clock().unix_timestamp;
+    let (recipient, payer) = (fx.recipient, fx.payer.pubkey());
+    let ix = build(
+        accounts::AttestDowntime {
+            sla: sla_address(&recipient).0,
+            attestation: downtime_address(&recipient, now - 3_600).0,
+            recipient,
+            attester: attester.pubkey(),
+            payer,
+            system_program: system_program::ID,
+        },
+        instruction::AttestDowntime {
+            started_at: now - 3_600,
+            ended_at: now,
+        },
+    );
+
+    assert_success(fx.send(ix, &[&attester]));
+}
+
+#[test]
+fn sla_covers_finished_outages_while_committed() {
+    let sla = SlaCommitment {
+        recipient: Pubkey::new_unique(),
+        attester: Pubkey::new_unique(),
+        credit_multiplier: 10,
+        committed_at: 1_000,
+        ends_at: 5_000,
+        bump: 255,
+    };
+
+    assert!(sla.covers(1_000, 2_000, 2_000));
+    // Still going, or not an outage at all
+    assert!(!sla.covers(1_000, 2_000, 1_999));
+    assert!(!sla.covers(2_000, 2_000, 3_000));
+    // Before the commitment, or after its notice ran out
+    assert!(!sla.covers(999, 2_000, 3_000));
+    assert!(!sla.covers(5_000, 6_000, 7_000));
+    assert!(sla.covers(4_999, 6_000, 7_000));
+}
+
+#[test]
+fn sla_credit_is_the_outage_share_of_a_period() {
+    let mut fx = Fixture::new();
+    let subscription = fx.subscribe(None);
+    let sla = SlaCommitment {
+        recipient: fx.recipient,
+        attester: Pubkey::new_unique(),
+        credit_multiplier: 10,
+        committed_at: 0,
+        ends_at: i64::MAX,
+        bump: 255,
+    };
+    let created = subscription.created_at;
+
+    // Three hours of a 30-day period, ten times over
+    let hours = |n: i64| n * 3_600;
+    assert_eq!(
+        sla.credit_for(&subscription, created, created + hours(3)),
+        AMOUNT * 3 * 10 / (30 * 24)
+    );
+    // Only the part after the subscription started counts
+    assert_eq!(
+        sla.credit_for(&subscription, created - hours(3), created + hours(3)),
+        sla.credit_for(&subscription, created, created + hours(3))
+    );
+    assert_eq!(
+        sla.credit_for(&subscription, created - hours(3), created),
+        0
+    );
+    // At most one period
+    assert_eq!(
+        sla.credit_for(&subscription, created, created + INTERVAL),
+        AMOUNT
+    );
+}
+
+#[test]
+fn sla_credits_come_out_of_held_revenue() {
+    let mut vault = MerchantVault {
+        holdback_bps: 1_000,
+        holdback_days: 1,
+        ..merchant_vault()
+    };
+    vault.sync(1_000, 0).unwrap();
+    vault.sync(3_000, 10).unwrap();
+    assert_eq!(vault.held(0), 300);
+
+    assert_eq!(
+        vault.draw_held(301, 0).unwrap_err(),
+        ErrorCode::InsufficientHeldRevenue.into()
+    );
+    vault.draw_held(150, 0).unwrap();
+    assert_eq!(
+        vault.holds,
+        vec![RevenueHold {
+            amount: 150,
+            release_at: 10 + SECONDS_PER_DAY,
+        }]
+    );
+    // Released revenue is the merchant's; credits cannot touch it
+    assert_eq!(
+        vault.draw_held(1, 10 + SECONDS_PER_DAY).unwrap_err(),
+        ErrorCode::InsufficientHeldRevenue.into()
+    );
+}
+
+/// Ten times the pro-rata share of a period for an hour's outage
+const HOUR_OUTAGE_CREDIT: u64 = AMOUNT * 3_600 * 10 / INTERVAL as u64;
+
+#[test]
+fn credit_sla_pays_the_user_from_held_revenue() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let vault_token_account = fx.set_merchant_vault(AMOUNT);
+    fx.set_vault_holdback(2_000, 14);
+    let attester = Pubkey::new_unique();
+    fx.set_sla_commitment(attester, 10);
+    fx.svm.advance_time(2 * 3_600);
+    let now = fx.svm.clock().unix_timestamp;
+    let attestation = fx.set_downtime(attester, now - 3_600, now);
+    let (recipient, payer) = (fx.recipient, fx.payer.pubkey());
+    let ix = build(
+        accounts::CreditSla {
+            sla: sla_address(&recipient).0,
+            attestation,
+            subscription: fx.subscription,
+            vault: merchant_vault_address(&recipient).0,
+            token_account: vault_token_account,
+            user_token_account: fx.user_token_account,
+            credit: sla_credit_address(&attestation, &fx.subscription).0,
+            payer,
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+        },
+        instruction::CreditSla {},
+    );
+    let user_before = fx.svm.token_balance(&fx.user_token_account);
+
+    assert_success(fx.send(ix, &[]));
+    assert_eq!(
+        fx.svm.token_balance(&fx.user_token_account),
+        user_before + HOUR_OUTAGE_CREDIT
+    );
+    assert_eq!(
+        fx.svm.token_balance(&vault_token_account),
+        AMOUNT - HOUR_OUTAGE_CREDIT
+    );
+    assert_eq!(fx.merchant_vault().total_withdrawn, HOUR_OUTAGE_CREDIT);
+}
+
+#[test]
+fn schedule_sla_discount_records_the_pending_credit() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let attester = Pubkey::new_unique();
+    fx.set_sla_commitment(attester, 10);
+    fx.svm.advance_time(2 * 3_600);
+    let now = fx.svm.clock().unix_timestamp;
+    let attestation = fx.set_downtime(attester, now - 3_600, now);
+    let (recipient, payer) = (fx.recipient, fx.payer.pubkey());
+    let ix = build(
+        accounts::ScheduleSlaDiscount {
+            sla: sla_address(&recipient).0,
+            attestation,
+            subscription: fx.subscription,
+            credit: sla_credit_address(&attestation, &fx.subscription).0,
+            payer,
+            system_program: system_program::ID,
+        },
+        instruction::ScheduleSlaDiscount {},
+    );
+
+    assert_success(fx.send(ix, &[]));
+    let credit: SlaCredit = fx
+        .svm
+        .get_anchor_account(&sla_credit_address(&attestation, &fx.subscription).0)
+        .unwrap();
+    assert_eq!(
+        (credit.amount, credit.pending),
+        (HOUR_OUTAGE_CREDIT, HOUR_OUTAGE_CREDIT)
+    );
+}
+
+#[test]
+fn charge_takes_sla_discounts_off_the_first_period() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let first = fx.set_sla_discount(Pubkey::new_unique(), AMOUNT / 4);
+    let second = fx.set_sla_discount(Pubkey::new_unique(), AMOUNT / 2);
+    fx.svm.advance_time(INTERVAL);
+    let mut ix = fx.charge_ix();
+    ix.accounts.push(AccountMeta::new(first, false));
+    ix.accounts.push(AccountMeta::new(second, false));
+
+    let accounts = fx.accounts_at_cpi(ix);
+    let at_cpi = |address: Pubkey| {
+        let (_, account) = accounts.iter().find(|(key, _)| *key == address).unwrap();
+        account.data.clone()
+    };
+    let subscription = Subscription::try_deserialize(&mut &at_cpi(fx.subscription)[..]).unwrap();
+    assert_eq!(subscription.total_charged, AMOUNT + AMOUNT / 4);
+    for receipt in [first, second] {
+        let receipt = SlaCredit::try_deserialize(&mut &at_cpi(receipt)[..]).unwrap();
+        assert_eq!(receipt.pending, 0);
+    }
+}
+
+#[test]
+fn sla_discount_larger_than_a_period_carries_over() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let receipt = fx.set_sla_discount(Pubkey::new_unique(), AMOUNT + AMOUNT / 2);
+    fx.svm.advance_time(INTERVAL);
+    let mut ix = fx.charge_ix();
+    ix.accounts.push(AccountMeta::new(receipt, false));
+
+    // Nothing to transfer, so the charge completes natively
+    fx.send(ix, &[]).unwrap();
+    let subscription = fx.subscription().unwrap();
+    assert_eq!(subscription.total_charged, AMOUNT);
+    assert_eq!(
+        subscription.next_charge_at,
+        fx.svm.clock().unix_timestamp + INTERVAL
+    );
+    let state: SlaCredit = fx.svm.get_anchor_account(&receipt).unwrap();
+    assert_eq!(state.pending, AMOUNT / 2);
+}
+
+#[test]
+fn charge_rejects_another_subscriptions_sla_discount() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let attestation = Pubkey::new_unique();
+    let receipt = fx.set_sla_discount(attestation, AMOUNT / 2);
+    // The same receipt, at another subscription's address
+    let other = Pubkey::new_unique();
+    let (foreign, bump) = sla_credit_address(&attestation, &other);
+    let state: SlaCredit = fx.svm.get_anchor_account(&receipt).unwrap();
+    fx.svm.set_anchor_account(
+        foreign,
+        &SlaCredit {
+            subscription: other,
+            bump,
+            ..state
+        },
+        8 + SlaCredit::INIT_SPACE,
+    );
+    fx.svm.advance_time(INTERVAL);
+
+    for meta in [
+        AccountMeta::new(foreign, false),
+        AccountMeta::new_readonly(receipt, false),
+    ] {
+        let mut ix = fx.charge_ix();
+        ix.accounts.push(meta);
+        assert_program_error(fx.send(ix, &[]), ErrorCode::InvalidSlaDiscount);
+        fx.svm.expire_blockhash();
+    }
+}
+
+// ---------- USD pricing ----------
+
+/// SOL/USD's Pyth feed ID
+const FEED_ID: [u8; 32] = [
+    0xef, 0x0d, 0x8b, 0x6f, 0xda, 0x2c, 0xeb, 0xa4, 0x1d, 0xa1, 0x5d, 0x40, 0x95, 0xd1, 0xda, 0x39,
+    0x2a, 0x0d, 0x2f, 0x8e, 0xd0, 0xc6, 0xc7, 0xbc, 0x0f, 0x4c, 0xfa, 0xc8, 0xc2, 0x80, 0xb5, 0x6d,
+];
+
+#[test]
+fn pyth_price_update_is_decoded_by_offset() {
+    let data = pyth_price_update(FEED_ID, 15_000_000_000, -8, 1_700_000_000, false);
+    assert_eq!(
+        PythPriceUpdate::parse(&data).unwrap(),
+        PythPriceUpdate {
+            feed_id: FEED_ID,
+            price: 15_000_000_000,
+            conf: 15_000_000,
+            exponent: -8,
+            publish_time: 1_700_000_000,
+        }
+    );
+
+    // Partially verified, another account type, cut short
+    let partial = pyth_price_update(FEED_ID, 15_000_000_000, -8, 1_700_000_000, true);
+    let mut other = data.clone();
+    other[0] ^= 1;
+    for data in [partial, other, data[..PythPriceUpdate::LEN - 1].to_vec()] {
+        assert_eq!(
+            PythPriceUpdate::parse(&data).unwrap_err(),
+            ErrorCode::InvalidPriceUpdate.into()
+        );
+    }
+}
+
+#[test]
+fn refresh_price_cache_copies_newer_prices() {
+    let mut fx = Fixture::new();
+    let cache = fx.set_price_cache(FEED_ID, 0, 0, 0);
+    let now = fx.svm.clock().unix_timestamp;
+    let refresh = |fx: &mut Fixture, data: Vec<u8>, owner: Pubkey| {
+        let update = Pubkey::new_unique();
+        fx.svm.set_account(
+            update,
+            Account {
+                lamports: 1_000_000_000,
+                data,
+                owner,
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+        let ix = build(
+            accounts::RefreshPriceCache {
+                price_cache: cache,
+                price_update: update,
+            },
+            instruction::RefreshPriceCache {},
+        );
+        fx.send(ix, &[])
+    };
+
+    let receiver = PYTH_RECEIVER_PROGRAM_ID;
+    refresh(
+        &mut fx,
+        pyth_price_update(FEED_ID, 150, 0, now, false),
+        receiver,
+    )
+    .unwrap();
+    let state: PriceCache = fx.svm.get_anchor_account(&cache).unwrap();
+    assert_eq!(
+        (state.price, state.exponent, state.publish_time),
+        (150, 0, now)
+    );
+
+    // An older update is ignored
+    refresh(
+        &mut fx,
+        pyth_price_update(FEED_ID, 140, 0, now - 1, false),
+        receiver,
+    )
+    .unwrap();
+    let state: PriceCache = fx.svm.get_anchor_account(&cache).unwrap();
+    assert_eq!(state.price, 150);
+
+    // Another feed, or an account Pyth does not own
+    let later = now + 1;
+    assert_program_error(
+        refresh(
+            &mut fx,
+            pyth_price_update([7; 32], 140, 0, later, false),
+            receiver,
+        ),
+        ErrorCode::InvalidPriceUpdate,
+    );
+    assert_program_error(
+        refresh(
+            &mut fx,
+            pyth_price_update(FEED_ID, 140, 0, later, false),
+            Pubkey::new_unique(),
+        ),
+        ErrorCode::InvalidPriceUpdate,
+    );
+}
+
+#[test]
+fn usd_price_converts_at_the_cached_price() {
+    let cache = PriceCache {
+        feed_id: FEED_ID,
+        // $150.00000000
+        price: 15_000_000_000,
+        conf: 0,
+        exponent: -8,
+        publish_time: 1_000,
+        bump: 255,
+    };
+    let peg = UsdPeg {
+        subscription: Pubkey::new_unique(),
+        price_cache: Pubkey::new_unique(),
+        usd_per_period: 10_000_000,
+        token_decimals: 9,
+        bump: 255,
+    };
+
+    // $10 of SOL is 0.0666... SOL, rounded up to the lamport
+    assert_eq!(peg.token_amount(&cache, 1_000).unwrap(), 66_666_667);
+    // A positive exponent, and a token with fewer decimals than USD
+    let cache = PriceCache {
+        price: 2,
+        exponent: 1,
+        ..cache
+    };
+    let peg = UsdPeg {
+        token_decimals: 2,
+        ..peg
+    };
+    assert_eq!(peg.token_amount(&cache, 1_000).unwrap(), 50);
+
+    assert_eq!(
+        peg.token_amount(&cache, 1_000 + MAX_PRICE_AGE_SECONDS)
+            .unwrap(),
+        50
+    );
+    for cache in [
+        PriceCache { price: 0, ..cache },
+        PriceCache {
+            publish_time: 999 - MAX_PRICE_AGE_SECONDS,
+            ..cache
+        },
+    ] {
+        assert_eq!(
+            peg.token_amount(&cache, 1_000).unwrap_err(),
+            ErrorCode::StalePrice.into()
+        );
+    }
+}
+
+#[test]
+fn set_usd_price_passes_validation() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let now = fx.svm.clock().unix_timestamp;
+    let cache = fx.set_price_cache(FEED_ID, 100_000_000, -8, now);
+    let payer = fx.payer.pubkey();
+    let ix = build(
+        accounts::SetUsdPrice {
+            subscription: fx.subscription,
+            usd_peg: usd_peg_address(&fx.subscription).0,
+            price_cache: cache,
+            token_mint: fx.mint,
+            authority: fx.authority.pubkey(),
+            payer,
+            system_program: system_program::ID,
+        },
+        instruction::SetUsdPrice {
+            usd_per_period: AMOUNT,
+        },
+    );
+
+    let authority = fx.authority.insecure_clone();
+    assert_success(fx.send(ix, &[&authority]));
+}
+
+#[test]
+fn update_amount_of_usd_priced_subscription_fails() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let now = fx.svm.clock().unix_timestamp;
+    let cache = fx.set_price_cache(FEED_ID, 100_000_000, -8, now);
+    fx.set_usd_peg(cache, AMOUNT);
+    let authority = fx.authority.insecure_clone();
+
+    let ix = fx.update_ix(Some(AMOUNT), None, None);
+    assert_program_error(fx.send(ix, &[&authority]), ErrorCode::PricedInUsd);
+    assert_eq!(fx.subscription().unwrap().amount_per_period, 0);
+}
+
+#[test]
+fn update_amount_to_zero_fails() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let authority = fx.authority.insecure_clone();
+
+    let ix = fx.update_ix(Some(0), None, None);
+    assert_program_error(fx.send(ix, &[&authority]), ErrorCode::InvalidAmount);
+    assert_eq!(fx.subscription().unwrap().amount_per_period, AMOUNT);
+}
+
+#[test]
+fn charge_subscription_usd_charges_the_converted_amount() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.svm.advance_time(INTERVAL);
+    let now = fx.svm.clock().unix_timestamp;
+    // $20 a period at $2 a token
+    let cache = fx.set_price_cache(FEED_ID, 200_000_000, -8, now);
+    fx.set_usd_peg(cache, 2 * AMOUNT);
+
+    let subscription = fx.subscription_at_cpi(fx.charge_usd_ix(cache));
+    assert_eq!(subscription.total_charged, 2 * AMOUNT);
+    assert_eq!(subscription.amount_per_period, 0);
+}
+
+#[test]
+fn usd_priced_subscription_needs_a_fresh_price() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.svm.advance_time(INTERVAL);
+    let now = fx.svm.clock().unix_timestamp;
+    let cache = fx.set_price_cache(FEED_ID, 200_000_000, -8, now - MAX_PRICE_AGE_SECONDS - 1);
+    fx.set_usd_peg(cache, AMOUNT);
+
+    assert_program_error(fx.send(fx.charge_usd_ix(cache), &[]), ErrorCode::StalePrice);
+    // Nor can a plain charge bill it in tokens
+    assert_program_error(fx.send(fx.charge_ix(), &[]), ErrorCode::UsdPriceRequired);
+}
+
+#[test]
+fn close_usd_peg_restores_a_token_amount() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let cache = fx.set_price_cache(FEED_ID, 200_000_000, -8, 0);
+    let peg = fx.set_usd_peg(cache, AMOUNT);
+    let close = |amount_per_period: u64| {
+        build(
+            accounts::CloseUsdPeg {
+                subscription: fx.subscription,
+                usd_peg: peg,
+                authority: fx.authority.pubkey(),
+            },
+            instruction::CloseUsdPeg { amount_per_period },
+        )
+    };
+    let (zero, restore) = (close(0), close(AMOUNT));
+
+    let authority = fx.authority.insecure_clone();
+    assert_program_error(fx.send(zero, &[&authority]), ErrorCode::InvalidAmount);
+    fx.send(restore, &[&authority]).unwrap();
+    assert_eq!(fx.subscription().unwrap().amount_per_period, AMOUNT);
+    assert!(fx.svm.get_account(&peg).is_none());
+    // With its peg gone, the USD charge no longer applies
+    assert_anchor_error(
+        fx.send(fx.charge_usd_ix(cache), &[]),
+        AnchorErrorCode::AccountNotInitialized,
+    );
+}
+
+// ---------- fallback mints ----------
+
+#[test]
+fn charge_subscription_fallback_books_the_period() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let fallback = fx.set_fallback_payment(STARTING_BALANCE);
+    let balance = fx.svm.token_balance(&fx.user_token_account);
+    fx.svm
+        .transfer_tokens(&fx.user_token_account, &fx.recipient_token_account, balance);
+    fx.svm.advance_time(INTERVAL);
+    let now = fx.svm.clock().unix_timestamp;
+
+    let subscription = fx.subscription_at_cpi(fx.charge_fallback_ix(&fallback));
+    assert_eq!(subscription.total_charged, 2 * AMOUNT);
+    assert_eq!(subscription.last_charge_timestamp, now);
+}
+
+#[test]
+fn charge_subscription_fallback_needs_an_empty_primary() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let fallback = fx.set_fallback_payment(STARTING_BALANCE);
+    fx.svm.advance_time(INTERVAL);
+
+    assert_program_error(
+        fx.send(fx.charge_fallback_ix(&fallback), &[]),
+        ErrorCode::PrimaryCanPay,
+    );
+
+    // Only into the account the recipient accepts the mint in
+    let balance = fx.svm.token_balance(&fx.user_token_account);
+    fx.svm
+        .transfer_tokens(&fx.user_token_account, &fx.recipient_token_account, balance);
+    let ix = substitute(
+        fx.charge_fallback_ix(&fallback),
+        7,
+        fx.recipient_token_account,
+    );
+    assert_program_error(fx.send(ix, &[]), ErrorCode::InvalidTokenAccount);
+}
+
+#[test]
+fn charge_subscription_fallback_catches_up_under_the_merchant_config() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let fallback = fx.set_fallback_payment(STARTING_BALANCE);
+    let balance = fx.svm.token_balance(&fx.user_token_account);
+    fx.svm
+        .transfer_tokens(&fx.user_token_account, &fx.recipient_token_account, balance);
+    let config = fx.set_merchant_config(false, 4);
+    fx.svm.advance_time(3 * INTERVAL);
+
+    let mut ix = fx.charge_fallback_ix(&fallback);
+    ix.accounts.push(AccountMeta::new_readonly(config, false));
+    let subscription = fx.subscription_at_cpi(ix);
+    assert_eq!(subscription.total_charged, 4 * AMOUNT);
+}
+
+#[test]
+fn charge_subscription_fallback_on_a_plan_needs_its_plan_subscription() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.set_plan(BillingCadence::Monthly);
+    let fallback = fx.set_fallback_payment(STARTING_BALANCE);
+    let balance = fx.svm.token_balance(&fx.user_token_account);
+    fx.svm
+        .transfer_tokens(&fx.user_token_account, &fx.recipient_token_account, balance);
+    let due = fx.subscription().unwrap().period_index();
+    fx.svm.warp_to_timestamp(due);
+
+    assert_program_error(
+        fx.send(fx.charge_fallback_ix(&fallback), &[]),
+        ErrorCode::PlanSubscriptionRequired,
+    );
+    let mut ix = fx.charge_fallback_ix(&fallback);
+    ix.accounts.push(AccountMeta::new(
+        plan_subscription_address(&fx.subscription).0,
+        false,
+    ));
+    assert_success(fx.send(ix, &[]));
+}
+
+#[test]
+fn fallback_payment_is_the_authoritys_in_an_accepted_mint() {
+    let mut fx = Fixture::new();
+    let subscription = fx.subscribe(None);
+    let fallback = fx.set_fallback_payment(0);
+    let accepted: AcceptedMint = fx
+        .svm
+        .get_anchor_account(&accepted_mint_address(&fx.recipient, &fallback.token_mint).0)
+        .unwrap();
+    let token_account = fx.svm.get_token_account(&fallback.token_account).unwrap();
+
+    FallbackPayment::check(&subscription, &accepted, &token_account, 6, 6).unwrap();
+
+    let not_accepted = spl_token::state::Account {
+        mint: Pubkey::new_unique(),
+        ..token_account
+    };
+    let primary_mint = AcceptedMint {
+        token_mint: fx.mint,
+        ..accepted.clone()
+    };
+    let primary = spl_token::state::Account {
+        mint: fx.mint,
+        ..token_account
+    };
+    let someone_elses = spl_token::state::Account {
+        owner: Pubkey::new_unique(),
+        ..token_account
+    };
+    for (accepted, token_account, decimals) in [
+        (&accepted, &not_accepted, 6),
+        (&primary_mint, &primary, 6),
+        (&accepted, &someone_elses, 6),
+        (&accepted, &token_account, 9),
+    ] {
+        assert_eq!(
+            FallbackPayment::check(&subscription, accepted, token_account, 6, decimals)
+                .unwrap_err(),
+            ErrorCode::InvalidFallbackPayment.into()
+        );
+    }
+}
+
+#[test]
+fn accept_fallback_mint_passes_validation() {
+    let mut fx = Fixture::new();
+    let recipient = Keypair::new();
+    fx.recipient = recipient.pubkey();
+    let token_mint = fx.svm.create_mint(&Pubkey::new_unique(), 6);
+    let token_account = fx.svm.create_token_account(&fx.recipient, &token_mint, 0);
+    let ix = build(
+        accounts::AcceptFallbackMint {
+            accepted_mint: accepted_mint_address(&fx.recipient, &token_mint).0,
+            merchant_multisig: merchant_multisig_address(&fx.recipient).0,
+            recipient: fx.recipient,
+            authority: fx.recipient,
+            token_mint,
+            token_account,
+            payer: fx.payer.pubkey(),
+            system_program: system_program::ID,
+        },
+        instruction::AcceptFallbackMint {},
+    );
+
+    assert_success(fx.send(ix, &[&recipient]));
+}
+
+#[test]
+fn set_fallback_payment_passes_validation() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let fallback = fx.set_fallback_payment(0);
+    fx.svm
+        .remove_account(&fallback_payment_address(&fx.subscription).0);
+    let ix = build(
+        accounts::SetFallbackPayment {
+            subscription: fx.subscription,
+            fallback_payment: fallback_payment_address(&fx.subscription).0,
+            accepted_mint: accepted_mint_address(&fx.recipient, &fallback.token_mint).0,
+            token_mint: fx.mint,
+            fallback_mint: fallback.token_mint,
+            token_account: fallback.token_account,
+            authority: fx.authority.pubkey(),
+            payer: fx.payer.pubkey(),
+            system_program: system_program::ID,
+        },
+        instruction::SetFallbackPayment {},
+    );
+
+    let authority = fx.authority.insecure_clone();
+    assert_success(fx.send(ix, &[&authority]));
+}
+
+// ---------- deposits ----------
+
+#[test]
+fn pay_deposit_escrows_the_amount() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let deposit = deposit_address(&fx.subscription).0;
+    let token_account = fx.svm.create_token_account(&deposit, &fx.mint, 0);
+    let ix = build(
+        accounts::PayDeposit {
+            subscription: fx.subscription,
+            deposit,
+            token_account,
+            user_token_account: fx.user_token_account,
+            authority: fx.authority.pubkey(),
+            payer: fx.payer.pubkey(),
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+        },
+        instruction::PayDeposit { amount: AMOUNT },
+    );
+
+    let authority = fx.authority.insecure_clone();
+    assert_success(fx.send(ix, &[&authority]));
+    assert_eq!(fx.svm.token_balance(&token_account), AMOUNT);
+    let state: SubscriptionDeposit = fx.svm.get_anchor_account(&deposit).unwrap();
+    assert_eq!(state.amount, AMOUNT);
+}
+
+#[test]
+fn deposit_escrow_is_the_deposits_own() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let deposit = deposit_address(&fx.subscription).0;
+    let escrow = fx.svm.create_token_account(&deposit, &fx.mint, 0);
+    let escrow = fx.svm.get_token_account(&escrow).unwrap();
+
+    SubscriptionDeposit::check_escrow(&deposit, &fx.mint, &escrow).unwrap();
+    for escrow in [
+        spl_token::state::Account {
+            owner: fx.authority.pubkey(),
+            ..escrow
+        },
+        spl_token::state::Account {
+            mint: Pubkey::new_unique(),
+            ..escrow
+        },
+        spl_token::state::Account {
+            close_authority: Some(fx.authority.pubkey()).into(),
+            ..escrow
+        },
+    ] {
+        assert_eq!(
+            SubscriptionDeposit::check_escrow(&deposit, &fx.mint, &escrow).unwrap_err(),
+            ErrorCode::InvalidDepositAccount.into()
+        );
+    }
+}
+
+#[test]
+fn refund_deposit_waits_for_cancellation() {
+    let mut fx = Fixture::new();
+    let mut subscription = fx.subscribe(None);
+    let deposit = fx.set_deposit(AMOUNT);
+
+    assert_program_error(
+        fx.send(fx.refund_deposit_ix(&deposit), &[]),
+        ErrorCode::DepositLocked,
+    );
+
+    // Deactivated, then closed
+    subscription.is_active = false;
+    fx.set_subscription(&subscription);
+    fx.svm.expire_blockhash();
+    let user_before = fx.svm.token_balance(&fx.user_token_account);
+    assert_success(fx.send(fx.refund_deposit_ix(&deposit), &[]));
+    assert_eq!(
+        fx.svm.token_balance(&fx.user_token_account),
+        user_before + AMOUNT
+    );
+    assert!(fx
+        .svm
+        .get_account(&deposit_address(&fx.subscription).0)
+        .is_none());
+
+    fx.svm.remove_account(&fx.subscription);
+    let deposit = fx.set_deposit(AMOUNT);
+    assert_success(fx.send(fx.refund_deposit_ix(&deposit), &[]));
+    assert_eq!(
+        fx.svm.token_balance(&fx.user_token_account),
+        user_before + 2 * AMOUNT
+    );
+}
+
+#[test]
+fn refund_deposit_only_pays_the_user_back() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let deposit = fx.set_deposit(AMOUNT);
+    fx.svm.remove_account(&fx.subscription);
+    let ix = substitute(
+        fx.refund_deposit_ix(&deposit),
+        3,
+        fx.recipient_token_account,
+    );
+
+    assert_anchor_error(fx.send(ix, &[]), AnchorErrorCode::ConstraintHasOne);
+}
+
+// ---------- setup fees ----------
+
+#[test]
+fn initialize_with_setup_fee_charges_it_once_with_the_first_period() {
+    let mut fx = Fixture::new();
+    let ix = fx.initialize_with_setup_fee_ix(5 * AMOUNT);
+    let authority = fx.authority.insecure_clone();
+
+    assert_success(fx.send(ix, &[&authority]));
+    // The fee is not recurring revenue
+    assert_eq!(fx.subscription().unwrap().total_charged, AMOUNT);
+    assert_eq!(
+        fx.svm.token_balance(&fx.recipient_token_account),
+        6 * AMOUNT
+    );
+}
+
+#[test]
+fn setup_fee_is_only_charged_when_opening() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let ix = fx.initialize_with_setup_fee_ix(5 * AMOUNT);
+    let authority = fx.authority.insecure_clone();
+
+    // An open subscription cannot be opened again to take a fee
+    assert!(fx.send(ix, &[&authority]).is_err());
+    assert!(fx
+        .svm
+        .get_account(&setup_fee_address(&fx.subscription).0)
+        .is_none());
+    assert_eq!(fx.subscription().unwrap().total_charged, AMOUNT);
+}
+
+// ---------- plans ----------
+
+#[test]
+fn annual_price_takes_the_plan_discount() {
+    let plan = Plan {
+        recipient: Pubkey::new_unique(),
+        plan_id: 1,
+        token_mint: Pubkey::new_unique(),
+        monthly_price: AMOUNT,
+        annual_discount_bps: 1_500,
+        included_units: 0,
+        upgrade_after_periods: 0,
+        next_tier: None,
+        overage_rate: 0,
+        max_subscribers: 0,
+        subscribers: 0,
+        intro_discount_bps: 0,
+        intro_periods: 0,
+        price_ramp: Vec::new(),
+        bump: 255,
+    };
+    assert_eq!(plan.price(BillingCadence::Monthly).unwrap(), AMOUNT);
+    assert_eq!(plan.price(BillingCadence::Annual).unwrap(), 102_000_000);
+
+    // Rounded down, in the subscriber's favour
+    let plan = Plan {
+        monthly_price: 7,
+        annual_discount_bps: 1,
+        ..plan
+    };
+    assert_eq!(plan.annual_price().unwrap(), 83);
+    let plan = Plan {
+        monthly_price: u64::MAX,
+        ..plan
+    };
+    assert_eq!(
+        plan.annual_price().unwrap_err(),
+        ErrorCode::ArithmeticOverflow.into()
+    );
+}
+
+#[test]
+fn switching_cadence_keeps_the_paid_period() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let plan = fx.set_plan(BillingCadence::Monthly);
+    let monthly = fx.subscription().unwrap();
+    let month_end = monthly.next_charge_at;
+
+    // Going annual, the month paid counts towards the year from its start
+    let mut subscription = monthly.clone();
+    let due = plan
+        .switch_cadence(
+            &mut subscription,
+            BillingCadence::Monthly,
+            BillingCadence::Annual,
+        )
+        .unwrap();
+    assert_eq!(due, 102_000_000 - AMOUNT);
+    assert_eq!(subscription.total_charged, monthly.total_charged + due);
+    assert_eq!(subscription.amount_per_period, 102_000_000);
+    assert_eq!(subscription.interval(), Some(Interval::Yearly));
+    assert_eq!(
+        subscription.last_charge_timestamp,
+        monthly.last_charge_timestamp
+    );
+    let year_end = subscription.next_charge_at;
+    assert!(year_end > month_end);
+
+    // Going back, the year is used up before the first monthly charge
+    let annual = subscription.clone();
+    let due = plan
+        .switch_cadence(
+            &mut subscription,
+            BillingCadence::Annual,
+            BillingCadence::Monthly,
+        )
+        .unwrap();
+    assert_eq!(due, 0);
+    assert_eq!(subscription.total_charged, annual.total_charged);
+    assert_eq!(subscription.amount_per_period, AMOUNT);
+    assert_eq!(subscription.interval(), Some(Interval::Monthly));
+    assert_eq!(subscription.next_charge_at, year_end);
+    assert_eq!(subscription.period_index(), annual.period_index());
+
+    assert_eq!(
+        plan.switch_cadence(
+            &mut subscription,
+            BillingCadence::Monthly,
+            BillingCadence::Monthly
+        )
+        .unwrap_err(),
+        ErrorCode::CadenceUnchanged.into()
+    );
+}
+
+#[test]
+fn create_plan_passes_validation() {
+    let mut fx = Fixture::new();
+    let recipient = Keypair::new();
+    let ix = build(
+        accounts::CreatePlan {
+            plan: plan_address(&recipient.pubkey(), 7).0,
+            merchant_multisig: merchant_multisig_address(&recipient.pubkey()).0,
+            recipient: recipient.pubkey(),
+            authority: recipient.pubkey(),
+            token_mint: fx.mint,
+            payer: fx.payer.pubkey(),
+            system_program: system_program::ID,
+        },
+        instruction::CreatePlan {
+            plan_id: 7,
+            monthly_price: AMOUNT,
+            annual_discount_bps: 1_500,
+        },
+    );
+
+    assert_success(fx.send(ix, &[&recipient]));
+}
+
+#[test]
+fn switch_to_annual_charges_the_difference() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.set_plan(BillingCadence::Monthly);
+    fx.svm.advance_time(7 * SECONDS_PER_DAY);
+    let ix = fx.switch_cadence_ix(BillingCadence::Annual);
+    let authority = fx.authority.insecure_clone();
+
+    // Signed by the subscriber only
+    assert_anchor_error(
+        fx.send(unsigned(ix.clone(), &authority.pubkey()), &[]),
+        AnchorErrorCode::AccountNotSigner,
+    );
+
+    let accounts = assert_success(fx.send(ix, &[&authority])).accounts_at_cpi;
+    let (_, account) = accounts
+        .iter()
+        .find(|(key, _)| *key == fx.subscription)
+        .unwrap();
+    let subscription = Subscription::try_deserialize(&mut &account.data[..]).unwrap();
+    assert_eq!(subscription.total_charged, 102_000_000);
+    assert_eq!(subscription.interval(), Some(Interval::Yearly));
+}
+
+#[test]
+fn switch_to_monthly_uses_up_the_paid_year() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.set_plan(BillingCadence::Annual);
+    let annual = fx.subscription().unwrap();
+    let ix = fx.switch_cadence_ix(BillingCadence::Monthly);
+    let authority = fx.authority.insecure_clone();
+
+    // No tokens move, so it lands natively
+    fx.send(ix, &[&authority]).unwrap();
+    let subscription = fx.subscription().unwrap();
+    assert_eq!(subscription.amount_per_period, AMOUNT);
+    assert_eq!(subscription.next_charge_at, annual.next_charge_at);
+    assert_eq!(subscription.total_charged, annual.total_charged);
+    let state: PlanSubscription = fx
+        .svm
+        .get_anchor_account(&plan_subscription_address(&fx.subscription).0)
+        .unwrap();
+    assert_eq!(state.cadence, BillingCadence::Monthly);
+
+    // Nor can it be charged before then
+    assert_program_error(fx.send(fx.charge_ix(), &[]), ErrorCode::IntervalNotMet);
+}
+
+// ---------- metered tiers ----------
+
+#[test]
+fn sustained_overage_schedules_a_tier_upgrade() {
+    let plan = Plan {
+        recipient: Pubkey::new_unique(),
+        plan_id: 1,
+        token_mint: Pubkey::new_unique(),
+        monthly_price: AMOUNT,
+        annual_discount_bps: 0,
+        included_units: 100,
+        upgrade_after_periods: 2,
+        next_tier: Some(Pubkey::new_unique()),
+        overage_rate: 0,
+        max_subscribers: 0,
+        subscribers: 0,
+        intro_discount_bps: 0,
+        intro_periods: 0,
+        price_ramp: Vec::new(),
+        bump: 255,
+    };
+    let mut usage = PlanSubscription {
+        subscription: Pubkey::new_unique(),
+        plan: Pubkey::new_unique(),
+        cadence: BillingCadence::Monthly,
+        period_start: 0,
+        units: 0,
+        periods_over: 0,
+        upgrade_at: None,
+        overage_billed: 0,
+        overage_charged: 0,
+        price_steps: Vec::new(),
+        full_price: 0,
+        bump: 255,
+    };
+    let now = 1_000;
+
+    // Over in period 0, under in period 1: the run starts again
+    for (period, units) in [(0, 101), (1, 100), (2, 101), (3, 150)] {
+        assert_eq!(usage.record_usage(&plan, period, units, now).unwrap(), None);
+    }
+    assert_eq!((usage.periods_over, usage.units), (1, 150));
+    // Closing period 3 makes two in a row
+    let upgrade_at = now + TIER_UPGRADE_VETO_SECONDS;
+    assert_eq!(
+        usage.record_usage(&plan, 4, 1, now).unwrap(),
+        Some(upgrade_at)
+    );
+    // Scheduled once
+    assert_eq!(usage.record_usage(&plan, 5, 1, now).unwrap(), None);
+    assert_eq!(usage.upgrade_at, Some(upgrade_at));
+
+    // The top tier, or a plan that is not metered
+    let mut top = PlanSubscription {
+        upgrade_at: None,
+        periods_over: 5,
+        ..usage.clone()
+    };
+    let top_plan = Plan {
+        next_tier: None,
+        ..plan.clone()
+    };
+    assert_eq!(top.record_usage(&top_plan, 6, 1, now).unwrap(), None);
+    let unmetered = Plan {
+        included_units: 0,
+        ..plan
+    };
+    assert_eq!(
+        usage.record_usage(&unmetered, 6, 1, now).unwrap_err(),
+        ErrorCode::PlanNotMetered.into()
+    );
+}
+
+#[test]
+fn record_usage_needs_the_merchant_authority() {
+    let mut fx = Fixture::new();
+    let recipient = fx.recipient_signer();
+    fx.subscribe(None);
+    fx.set_plan(BillingCadence::Monthly);
+    fx.set_next_tier(100, 1);
+
+    let intruder = Keypair::new();
+    let ix = fx.record_usage_ix(&intruder.pubkey(), 150);
+    assert_program_error(fx.send(ix, &[&intruder]), ErrorCode::NotMerchantAuthority);
+
+    let ix = fx.record_usage_ix(&recipient.pubkey(), 150);
+    fx.send(ix, &[&recipient]).unwrap();
+    assert_eq!(fx.plan_subscription().units, 150);
+
+    // The first usage of the next period closes this one, over the tier
+    let mut subscription = fx.subscription().unwrap();
+    subscription.last_charge_timestamp = subscription.next_charge_at;
+    fx.set_subscription(&subscription);
+    let ix = fx.record_usage_ix(&recipient.pubkey(), 1);
+    fx.send(ix, &[&recipient]).unwrap();
+    let usage = fx.plan_subscription();
+    assert_eq!((usage.periods_over, usage.units), (1, 1));
+    assert_eq!(
+        usage.upgrade_at,
+        Some(fx.svm.clock().unix_timestamp + TIER_UPGRADE_VETO_SECONDS)
+    );
+}
+
+#[test]
+fn tier_upgrade_can_be_vetoed_within_its_window() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.set_plan(BillingCadence::Monthly);
+    let next_tier = fx.set_next_tier(100, 1);
+    let schedule = |fx: &mut Fixture| {
+        let mut usage = fx.plan_subscription();
+        usage.periods_over = 1;
+        usage.upgrade_at = Some(fx.svm.clock().unix_timestamp + TIER_UPGRADE_VETO_SECONDS);
+        fx.set_plan_subscription(&usage);
+    };
+    let veto_ix = build(
+        accounts::VetoTierUpgrade {
+            plan_subscription: plan_subscription_address(&fx.subscription).0,
+            subscription: fx.subscription,
+            authority: fx.authority.pubkey(),
+        },
+        instruction::VetoTierUpgrade {},
+    );
+    let apply_ix = build(
+        accounts::ApplyTierUpgrade {
+            plan_subscription: plan_subscription_address(&fx.subscription).0,
+            subscription: fx.subscription,
+            plan: plan_address(&fx.recipient, 1).0,
+            next_tier,
+        },
+        instruction::ApplyTierUpgrade {},
+    );
+    let authority = fx.authority.insecure_clone();
+
+    assert_program_error(
+        fx.send(veto_ix.clone(), &[&authority]),
+        ErrorCode::NoTierUpgrade,
+    );
+    schedule(&mut fx);
+    assert_program_error(
+        fx.send(apply_ix.clone(), &[]),
+        ErrorCode::TierUpgradeVetoOpen,
+    );
+    fx.svm.expire_blockhash();
+    fx.send(veto_ix.clone(), &[&authority]).unwrap();
+    let usage = fx.plan_subscription();
+    assert_eq!((usage.upgrade_at, usage.periods_over), (None, 0));
+
+    // Left alone, it applies once the window closes
+    schedule(&mut fx);
+    fx.svm.advance_time(TIER_UPGRADE_VETO_SECONDS);
+    fx.svm.expire_blockhash();
+    assert_program_error(
+        fx.send(veto_ix, &[&authority]),
+        ErrorCode::TierUpgradeVetoClosed,
+    );
+    fx.send(apply_ix, &[]).unwrap();
+    let usage = fx.plan_subscription();
+    assert_eq!(usage.plan, next_tier);
+    assert_eq!(usage.upgrade_at, None);
+    assert_eq!(fx.subscription().unwrap().amount_per_period, 2 * AMOUNT);
+    // The seat moves with it
+    assert_eq!((fx.plan(1).subscribers, fx.plan(2).subscribers), (0, 1));
+}
+
+#[test]
+fn overage_bills_only_unbilled_usage_past_the_allotment() {
+    let plan = Plan {
+        recipient: Pubkey::new_unique(),
+        plan_id: 1,
+        token_mint: Pubkey::new_unique(),
+        monthly_price: AMOUNT,
+        annual_discount_bps: 0,
+        included_units: 100,
+        upgrade_after_periods: 0,
+        next_tier: None,
+        overage_rate: 1_000,
+        max_subscribers: 0,
+        subscribers: 0,
+        intro_discount_bps: 0,
+        intro_periods: 0,
+        price_ramp: Vec::new(),
+        bump: 255,
+    };
+    let mut usage = PlanSubscription {
+        subscription: Pubkey::new_unique(),
+        plan: Pubkey::new_unique(),
+        cadence: BillingCadence::Monthly,
+        period_start: 0,
+        units: 130,
+        periods_over: 0,
+        upgrade_at: None,
+        overage_billed: 0,
+        overage_charged: 0,
+        price_steps: Vec::new(),
+        full_price: 0,
+        bump: 255,
+    };
+
+    assert_eq!(usage.bill_overage(&plan, 20).unwrap(), 20_000);
+    // 10 units are left to bill
+    assert_eq!(
+        usage.bill_overage(&plan, 11).unwrap_err(),
+        ErrorCode::OverageExceedsUsage.into()
+    );
+    assert_eq!(usage.bill_overage(&plan, 10).unwrap(), 10_000);
+    assert_eq!((usage.overage_billed, usage.overage_charged), (30, 30_000));
+
+    // A new period starts the count again; the total carries on
+    usage.record_usage(&plan, 1, 101, 1_000).unwrap();
+    assert_eq!(usage.overage_billed, 0);
+    assert_eq!(usage.bill_overage(&plan, 1).unwrap(), 1_000);
+    assert_eq!(usage.overage_charged, 31_000);
+
+    let free = Plan {
+        overage_rate: 0,
+        ..plan.clone()
+    };
+    assert_eq!(
+        usage.bill_overage(&free, 0).unwrap_err(),
+        ErrorCode::InvalidAmount.into()
+    );
+    assert_eq!(
+        usage.bill_overage(&free, 1).unwrap_err(),
+        ErrorCode::NoOverageRate.into()
+    );
+}
+
+#[test]
+fn charge_overage_needs_the_merchant_authority() {
+    let mut fx = Fixture::new();
+    let recipient = fx.recipient_signer();
+    fx.subscribe(None);
+    fx.set_plan(BillingCadence::Monthly);
+    let plan = plan_address(&fx.recipient, 1).0;
+    let mut metered: Plan = fx.svm.get_anchor_account(&plan).unwrap();
+    metered.included_units = 100;
+    metered.overage_rate = 1_000;
+    fx.svm
+        .set_anchor_account(plan, &metered, 8 + Plan::INIT_SPACE);
+    let mut usage = fx.plan_subscription();
+    usage.units = 150;
+    fx.set_plan_subscription(&usage);
+
+    let intruder = Keypair::new();
+    let ix = fx.charge_overage_ix(&intruder.pubkey(), 50);
+    assert_program_error(fx.send(ix, &[&intruder]), ErrorCode::NotMerchantAuthority);
+    let ix = fx.charge_overage_ix(&recipient.pubkey(), 51);
+    assert_program_error(fx.send(ix, &[&recipient]), ErrorCode::OverageExceedsUsage);
+
+    // Booked before the transfer
+    let merchant_before = fx.svm.token_balance(&fx.recipient_token_account);
+    let ix = fx.charge_overage_ix(&recipient.pubkey(), 50);
+    let meta = assert_success(fx.send(ix, &[&recipient]));
+    let usage_address = plan_subscription_address(&fx.subscription).0;
+    let usage = meta
+        .accounts_at_cpi
+        .iter()
+        .find(|(key, _)| *key == usage_address)
+        .map(|(_, account)| PlanSubscription::try_deserialize(&mut &account.data[..]).unwrap())
+        .expect("plan subscription is writable");
+    assert_eq!((usage.overage_billed, usage.overage_charged), (50, 50_000));
+    assert_eq!(
+        fx.svm.token_balance(&fx.recipient_token_account),
+        merchant_before + 50_000
+    );
+}
+
+#[test]
+fn charge_overage_for_inactive_subscription_fails() {
+    let mut fx = Fixture::new();
+    let recipient = fx.recipient_signer();
+    fx.subscribe(None);
+    fx.set_plan(BillingCadence::Monthly);
+    let plan = plan_address(&fx.recipient, 1).0;
+    let mut metered: Plan = fx.svm.get_anchor_account(&plan).unwrap();
+    metered.included_units = 100;
+    metered.overage_rate = 1_000;
+    fx.svm
+        .set_anchor_account(plan, &metered, 8 + Plan::INIT_SPACE);
+    let mut usage = fx.plan_subscription();
+    usage.units = 150;
+    fx.set_plan_subscription(&usage);
+    let mut subscription = fx.subscription().unwrap();
+    subscription.is_active = false;
+    fx.set_subscription(&subscription);
+
+    let ix = fx.charge_overage_ix(&recipient.pubkey(), 50);
+    assert_program_error(fx.send(ix, &[&recipient]), ErrorCode::SubscriptionInactive);
+}
+
+// ---------- plan capacity ----------
+
+#[test]
+fn full_plans_turn_subscribers_away() {
+    let mut plan = Plan {
+        recipient: Pubkey::new_unique(),
+        plan_id: 1,
+        token_mint: Pubkey::new_unique(),
+        monthly_price: AMOUNT,
+        annual_discount_bps: 0,
+        included_units: 0,
+        upgrade_after_periods: 0,
+        next_tier: None,
+        overage_rate: 0,
+        max_subscribers: 2,
+        subscribers: 0,
+        intro_discount_bps: 0,
+        intro_periods: 0,
+        price_ramp: Vec::new(),
+        bump: 255,
+    };
+
+    plan.take_seat().unwrap();
+    plan.take_seat().unwrap();
+    assert_eq!(plan.take_seat().unwrap_err(), ErrorCode::PlanFull.into());
+    plan.free_seat();
+    plan.take_seat().unwrap();
+    assert_eq!(plan.subscribers, 2);
+
+    // A cap lowered below the count waits for enough to leave
+    plan.max_subscribers = 1;
+    plan.free_seat();
+    assert_eq!(plan.take_seat().unwrap_err(), ErrorCode::PlanFull.into());
+    plan.free_seat();
+    plan.take_seat().unwrap();
+
+    // Zero is no limit
+    plan.max_subscribers = 0;
+    plan.subscribers = u32::MAX - 1;
+    plan.take_seat().unwrap();
+}
+
+#[test]
+fn cancel_gives_up_the_plan_seat() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.set_plan(BillingCadence::Monthly);
+    fx.svm.remove_account(&fx.user_token_account);
+    let plan = plan_address(&fx.recipient, 1).0;
+    let plan_subscription = plan_subscription_address(&fx.subscription).0;
+    let authority = fx.authority.insecure_clone();
+
+    // Another subscription's seat cannot be given up
+    let mut forged = fx.cancel_ix();
+    forged.accounts.extend([
+        AccountMeta::new(plan_subscription_address(&Pubkey::new_unique()).0, false),
+        AccountMeta::new(plan, false),
+    ]);
+    fx.svm.set_account(
+        forged.accounts[4].pubkey,
+        fx.svm.get_account(&plan_subscription).unwrap(),
+    );
+    assert_program_error(fx.send(forged, &[&authority]), ErrorCode::InvalidPlan);
+
+    let mut ix = fx.cancel_ix();
+    ix.accounts.extend([
+        AccountMeta::new(plan_subscription, false),
+        AccountMeta::new(plan, false),
+    ]);
+    fx.send(ix, &[&authority]).unwrap();
+    assert!(fx.subscription().is_none());
+    assert!(fx.svm.get_account(&plan_subscription).is_none());
+    assert_eq!(fx.plan(1).subscribers, 0);
+}
+
+#[test]
+fn release_plan_seat_waits_for_the_subscription_to_end() {
+    let mut fx = Fixture::new();
+    let mut subscription = fx.subscribe(None);
+    fx.set_plan(BillingCadence::Monthly);
+    let ix = build(
+        accounts::ReleasePlanSeat {
+            plan_subscription: plan_subscription_address(&fx.subscription).0,
+            subscription: fx.subscription,
+            plan: plan_address(&fx.recipient, 1).0,
+            recipient: fx.recipient,
+        },
+        instruction::ReleasePlanSeat {},
+    );
+
+    assert_program_error(fx.send(ix.clone(), &[]), ErrorCode::SubscriptionStillActive);
+
+    subscription.is_active = false;
+    fx.set_subscription(&subscription);
+    fx.svm.expire_blockhash();
+    fx.send(ix, &[]).unwrap();
+    assert_eq!(fx.plan(1).subscribers, 0);
+    assert!(fx
+        .svm
+        .get_account(&plan_subscription_address(&fx.subscription).0)
+        .is_none());
+    assert!(fx.svm.get_account(&fx.recipient).unwrap().lamports > 0);
+}
+
+// ---------- waitlists ----------
+
+#[test]
+fn waitlists_fill_seats_before_join_plan() {
+    let mut plan = Plan {
+        recipient: Pubkey::new_unique(),
+        plan_id: 1,
+        token_mint: Pubkey::new_unique(),
+        monthly_price: AMOUNT,
+        annual_discount_bps: 0,
+        included_units: 0,
+        upgrade_after_periods: 0,
+        next_tier: None,
+        overage_rate: 0,
+        max_subscribers: 0,
+        subscribers: 5,
+        intro_discount_bps: 0,
+        intro_periods: 0,
+        price_ramp: Vec::new(),
+        bump: 255,
+    };
+    let mut waitlist = Waitlist {
+        plan: Pubkey::new_unique(),
+        head: 3,
+        tail: 3,
+        bump: 255,
+    };
+
+    // Nobody queues for a plan with seats
+    assert_eq!(
+        waitlist.check_joinable(&plan).unwrap_err(),
+        ErrorCode::PlanNotFull.into()
+    );
+    plan.max_subscribers = 5;
+    waitlist.check_joinable(&plan).unwrap();
+    // Once someone waits, a freed seat is theirs, and others queue behind
+    waitlist.tail = 4;
+    plan.subscribers = 4;
+    waitlist.check_joinable(&plan).unwrap();
+
+    let key = Pubkey::new_unique();
+    let mut lamports = 0;
+    let mut data = Vec::new();
+    waitlist.try_serialize(&mut data).unwrap();
+    let info = AccountInfo::new(
+        &key,
+        false,
+        false,
+        &mut lamports,
+        &mut data,
+        &PROGRAM_ID,
+        false,
+        0,
+    );
+    assert_eq!(
+        Waitlist::check_no_queue(&info).unwrap_err(),
+        ErrorCode::PlanFull.into()
+    );
+
+    let mut lamports = 0;
+    let mut empty = Vec::new();
+    let info = AccountInfo::new(
+        &key,
+        false,
+        false,
+        &mut lamports,
+        &mut empty,
+        &PROGRAM_ID,
+        false,
+        0,
+    );
+    Waitlist::check_no_queue(&info).unwrap();
+}
+
+#[test]
+fn skip_waitlist_entry_passes_over_entries_that_cannot_be_promoted() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.set_plan(BillingCadence::Monthly);
+    fx.set_waitlist(0, 1);
+    let entry = fx.set_waitlist_entry(0);
+    let entry_address = waitlist_entry_address(&entry.plan, 0).0;
+    let subscription = fx.svm.remove_account(&fx.subscription).unwrap();
+
+    // Approved and funded, so it waits for a seat
+    assert_program_error(
+        fx.send(fx.skip_waitlist_entry_ix(0), &[]),
+        ErrorCode::WaitlistEntryPromotable,
+    );
+    let forged = substitute(fx.skip_waitlist_entry_ix(0), 8, Pubkey::new_unique());
+    assert_anchor_error(fx.send(forged, &[]), AnchorErrorCode::ConstraintHasOne);
+
+    // Already subscribed to the recipient
+    fx.svm.set_account(fx.subscription, subscription);
+    let payer_before = fx.svm.get_balance(&fx.payer.pubkey());
+    let entry_lamports = fx.svm.get_balance(&entry_address);
+    fx.svm.expire_blockhash();
+    fx.send(fx.skip_waitlist_entry_ix(0), &[]).unwrap();
+    assert!(fx.svm.get_account(&entry_address).is_none());
+    assert_eq!(fx.waitlist().head, 1);
+    // The transaction fee is the payer's; the refund is the entry's
+    assert!(fx.svm.get_balance(&fx.payer.pubkey()) > payer_before + entry_lamports - 10_000);
+
+    // A left entry is a gap to step over
+    fx.set_waitlist(1, 2);
+    fx.send(fx.skip_waitlist_entry_ix(1), &[]).unwrap();
+    assert_eq!(fx.waitlist().head, 2);
+    fx.svm.expire_blockhash();
+    assert_program_error(
+        fx.send(fx.skip_waitlist_entry_ix(2), &[]),
+        ErrorCode::WaitlistEmpty,
+    );
+
+    // Denied since joining
+    fx.set_waitlist(2, 3);
+    fx.set_waitlist_entry(2);
+    fx.svm.remove_account(&fx.subscription);
+    fx.set_denylist_entry();
+    fx.svm.expire_blockhash();
+    fx.send(fx.skip_waitlist_entry_ix(2), &[]).unwrap();
+    assert_eq!(fx.waitlist().head, 3);
+}
+
+#[test]
+fn promote_from_waitlist_needs_no_signature_from_the_user() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.set_plan(BillingCadence::Monthly);
+    // Still delegated from before, but no longer subscribed
+    fx.svm.remove_account(&fx.subscription);
+    fx.svm
+        .remove_account(&plan_subscription_address(&fx.subscription).0);
+    fx.set_waitlist(0, 1);
+    fx.set_waitlist_entry(0);
+    let plan = plan_address(&fx.recipient, 1).0;
+    let wallet = fx.authority.pubkey();
+    let payer = fx.payer.insecure_clone();
+    let ix = build(
+        accounts::PromoteFromWaitlist {
+            waitlist: waitlist_address(&plan).0,
+            entry: waitlist_entry_address(&plan, 0).0,
+            plan,
+            subscription: fx.subscription,
+            plan_subscription: plan_subscription_address(&fx.subscription).0,
+            authority: wallet,
+            recipient: fx.recipient,
+            user_token_account: fx.user_token_account,
+            recipient_token_account: fx.recipient_token_account,
+            token_mint: fx.mint,
+            denylist_entry: denylist_address(&fx.recipient, &wallet).0,
+            allowlist: subscriber_allowlist_address(&fx.recipient).0,
+            allowlist_entry: allowlist_entry_address(&fx.recipient, &wallet).0,
+            token_program: spl_token::ID,
+            payer: payer.pubkey(),
+            system_program: system_program::ID,
+        },
+        instruction::PromoteFromWaitlist {},
+    );
+    assert!(ix
+        .accounts
+        .iter()
+        .all(|meta| !meta.is_signer || meta.pubkey == payer.pubkey()));
+    let merchant_before = fx.svm.token_balance(&fx.recipient_token_account);
+
+    assert_success(fx.send(ix, &[&payer]));
+    assert!(fx.subscription().unwrap().is_active);
+    assert!(fx.svm.token_balance(&fx.recipient_token_account) > merchant_before);
+}
+
+// ---------- intro pricing and price ramps ----------
+
+#[test]
+fn intro_pricing_covers_the_first_periods() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let plan = Plan {
+        intro_discount_bps: 2_500,
+        intro_periods: 2,
+        ..fx.set_plan(BillingCadence::Monthly)
+    };
+    let mut subscription = fx.subscription().unwrap();
+    let price_steps = plan
+        .start_schedule(&mut subscription, BillingCadence::Monthly, 0)
+        .unwrap();
+    assert_eq!(subscription.amount_per_period, AMOUNT * 3 / 4);
+    assert_eq!(price_steps.len(), 1);
+    assert_eq!(price_steps[0].amount_per_period, AMOUNT * 3 / 4);
+    let mut state = PlanSubscription {
+        price_steps,
+        full_price: AMOUNT,
+        ..fx.plan_subscription()
+    };
+
+    // A catch-up charge stops at the last intro period
+    assert_eq!(state.periods_at_price(&subscription, 12), 2);
+    assert!(!state.apply_price(&mut subscription));
+    subscription
+        .record_charge(subscription.period_index())
+        .unwrap();
+    assert_eq!(state.periods_at_price(&subscription, 12), 1);
+    assert!(!state.apply_price(&mut subscription));
+    subscription
+        .record_charge(subscription.period_index())
+        .unwrap();
+    assert!(state.apply_price(&mut subscription));
+    assert_eq!(subscription.amount_per_period, AMOUNT);
+    assert!(state.price_steps.is_empty());
+    assert_eq!(state.periods_at_price(&subscription, 12), 12);
+
+    // A promotion from the waitlist paid the first at creation
+    let one = Plan {
+        intro_periods: 1,
+        ..plan
+    };
+    assert!(one
+        .start_schedule(&mut subscription, BillingCadence::Monthly, 1)
+        .unwrap()
+        .is_empty());
+    assert_eq!(subscription.amount_per_period, AMOUNT);
+}
+
+#[test]
+fn price_ramp_prices_each_period_by_its_step() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let mut plan = Plan {
+        price_ramp: vec![
+            RampStep {
+                periods: 3,
+                monthly_price: AMOUNT / 4,
+            },
+            RampStep {
+                periods: 9,
+                monthly_price: AMOUNT / 2,
+            },
+        ],
+        ..fx.set_plan(BillingCadence::Monthly)
+    };
+    let price = |plan: &Plan, period| plan.period_price(BillingCadence::Monthly, period).unwrap();
+    assert_eq!(
+        (0..13)
+            .map(|period| price(&plan, period))
+            .collect::<Vec<_>>(),
+        [[AMOUNT / 4; 3].as_slice(), &[AMOUNT / 2; 9], &[AMOUNT]].concat()
+    );
+    // Billed annually, a step is priced as the plan's own price is
+    assert_eq!(
+        plan.period_price(BillingCadence::Annual, 0).unwrap(),
+        plan.cadence_price(AMOUNT / 4, BillingCadence::Annual)
+            .unwrap()
+    );
+
+    let mut subscription = fx.subscription().unwrap();
+    let mut index = subscription.period_index();
+    let mut period_ends = Vec::new();
+    for _ in 0..12 {
+        index = subscription.period_end(index).unwrap();
+        period_ends.push(index);
+    }
+    let steps = plan
+        .start_schedule(&mut subscription, BillingCadence::Monthly, 0)
+        .unwrap();
+    assert_eq!(subscription.amount_per_period, AMOUNT / 4);
+    assert_eq!(
+        steps,
+        vec![
+            PriceStep {
+                until: period_ends[2],
+                amount_per_period: AMOUNT / 4,
+            },
+            PriceStep {
+                until: period_ends[11],
+                amount_per_period: AMOUNT / 2,
+            },
+        ]
+    );
+
+    // An intro discount comes off the ramp, and can split a step
+    plan.intro_discount_bps = 5_000;
+    plan.intro_periods = 4;
+    let steps = plan
+        .start_schedule(&mut subscription, BillingCadence::Monthly, 0)
+        .unwrap();
+    assert_eq!(
+        steps
+            .iter()
+            .map(|step| (step.until, step.amount_per_period))
+            .collect::<Vec<_>>(),
+        vec![
+            (period_ends[2], AMOUNT / 8),
+            (period_ends[3], AMOUNT / 4),
+            (period_ends[11], AMOUNT / 2),
+        ]
+    );
+    let state = PlanSubscription {
+        price_steps: steps,
+        full_price: AMOUNT,
+        ..fx.plan_subscription()
+    };
+    assert_eq!(state.price_at(period_ends[0]), AMOUNT / 8);
+    assert_eq!(state.price_at(period_ends[3]), AMOUNT / 2);
+    assert_eq!(state.price_at(period_ends[11]), AMOUNT);
+}
+
+#[test]
+fn charge_moves_to_the_next_scheduled_price() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.set_plan(BillingCadence::Monthly);
+    let mut subscription = fx.subscription().unwrap();
+    subscription.amount_per_period = AMOUNT / 4;
+    fx.set_subscription(&subscription);
+    let next = subscription.period_index();
+    let later = subscription.period_end(next).unwrap();
+    fx.set_plan_subscription(&PlanSubscription {
+        price_steps: vec![
+            PriceStep {
+                until: next,
+                amount_per_period: AMOUNT / 4,
+            },
+            PriceStep {
+                until: later,
+                amount_per_period: AMOUNT / 2,
+            },
+        ],
+        full_price: AMOUNT,
+        ..fx.plan_subscription()
+    });
+    fx.svm.warp_to_timestamp(later);
+    let plan_subscription = plan_subscription_address(&fx.subscription).0;
+    let with_schedule = |fx: &Fixture, writable: bool| {
+        let mut ix = fx.charge_ix();
+        ix.accounts.push(if writable {
+            AccountMeta::new(plan_subscription, false)
+        } else {
+            AccountMeta::new_readonly(plan_subscription, false)
+        });
+        ix
+    };
+
+    // Without it no charge could move the price on, so none is taken
+    assert_program_error(
+        fx.send(fx.charge_ix(), &[]),
+        ErrorCode::PlanSubscriptionRequired,
+    );
+    assert_program_error(
+        fx.send(with_schedule(&fx, false), &[]),
+        ErrorCode::InvalidPlan,
+    );
+
+    // The first step is behind it; the second prices this period
+    let accounts = fx.accounts_at_cpi(with_schedule(&fx, true));
+    let written = |key: Pubkey| {
+        &accounts
+            .iter()
+            .find(|(address, _)| *address == key)
+            .unwrap()
+            .1
+            .data
+    };
+    let subscription = Subscription::try_deserialize(&mut &written(fx.subscription)[..]).unwrap();
+    let state = PlanSubscription::try_deserialize(&mut &written(plan_subscription)[..]).unwrap();
+    assert_eq!(subscription.amount_per_period, AMOUNT / 2);
+    assert_eq!(subscription.total_charged, AMOUNT + AMOUNT / 2);
+    assert_eq!(state.price_steps.len(), 1);
+    assert_eq!(state.price_at(later), AMOUNT);
+}
+
+#[test]
+fn charge_past_a_ramp_step_needs_the_plan_subscription() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let plan = Plan {
+        price_ramp: vec![
+            RampStep {
+                periods: 1,
+                monthly_price: AMOUNT / 4,
+            },
+            RampStep {
+                periods: 2,
+                monthly_price: AMOUNT / 2,
+            },
+        ],
+        ..fx.set_plan(BillingCadence::Monthly)
+    };
+    let mut subscription = fx.subscription().unwrap();
+    let price_steps = plan
+        .start_schedule(&mut subscription, BillingCadence::Monthly, 0)
+        .unwrap();
+    fx.set_subscription(&subscription);
+    fx.set_plan_subscription(&PlanSubscription {
+        price_steps,
+        ..fx.plan_subscription()
+    });
+
+    // The first period is at the first step's price
+    fx.svm.warp_to_timestamp(subscription.period_index());
+    let mut ix = fx.charge_ix();
+    ix.accounts.push(AccountMeta::new(
+        plan_subscription_address(&fx.subscription).0,
+        false,
+    ));
+    let accounts = fx.accounts_at_cpi(ix);
+    let written = accounts
+        .iter()
+        .find(|(address, _)| *address == fx.subscription)
+        .unwrap();
+    let charged = Subscription::try_deserialize(&mut &written.1.data[..]).unwrap();
+    assert_eq!(
+        charged.total_charged,
+        subscription.total_charged + AMOUNT / 4
+    );
+
+    // Leaving the ramp out cannot keep that price into the second step
+    fx.set_subscription(&charged);
+    fx.svm.warp_to_timestamp(charged.period_index());
+    assert_program_error(
+        fx.send(fx.charge_ix(), &[]),
+        ErrorCode::PlanSubscriptionRequired,
+    );
+}
+
+#[test]
+fn set_plan_intro_pricing_checks_the_offer() {
+    let mut fx = Fixture::new();
+    let recipient = fx.recipient_signer();
+    fx.subscribe(None);
+    fx.set_plan(BillingCadence::Monthly);
+    let set = |fx: &Fixture, intro_discount_bps: u16, intro_periods: u8| {
+        build(
+            accounts::SetPlanIntroPricing {
+                plan: plan_address(&fx.recipient, 1).0,
+                merchant_multisig: merchant_multisig_address(&fx.recipient).0,
+                recipient: fx.recipient,
+                authority: fx.recipient,
+            },
+            instruction::SetPlanIntroPricing {
+                intro_discount_bps,
+                intro_periods,
+            },
+        )
+    };
+
+    for (bps, periods) in [(10_000, 1), (2_500, 0), (0, 3)] {
+        assert_program_error(
+            fx.send(set(&fx, bps, periods), &[&recipient]),
+            ErrorCode::InvalidIntroPricing,
+        );
+    }
+
+    fx.send(set(&fx, 2_500, 3), &[&recipient]).unwrap();
+    let plan = fx.plan(1);
+    assert_eq!((plan.intro_discount_bps, plan.intro_periods), (2_500, 3));
+}
+
+#[test]
+fn set_plan_price_ramp_checks_the_steps() {
+    let mut fx = Fixture::new();
+    let recipient = fx.recipient_signer();
+    fx.subscribe(None);
+    fx.set_plan(BillingCadence::Monthly);
+    let set = |fx: &Fixture, price_ramp: Vec<RampStep>| {
+        build(
+            accounts::SetPlanPriceRamp {
+                plan: plan_address(&fx.recipient, 1).0,
+                merchant_multisig: merchant_multisig_address(&fx.recipient).0,
+                recipient: fx.recipient,
+                authority: fx.recipient,
+            },
+            instruction::SetPlanPriceRamp { price_ramp },
+        )
+    };
+    let step = |periods, monthly_price| RampStep {
+        periods,
+        monthly_price,
+    };
+
+    for price_ramp in [
+        vec![step(0, AMOUNT)],
+        vec![step(3, 0)],
+        vec![step(3, u64::MAX)],
+        vec![step(1, AMOUNT); MAX_RAMP_STEPS + 1],
+    ] {
+        assert_program_error(
+            fx.send(set(&fx, price_ramp), &[&recipient]),
+            ErrorCode::InvalidPriceRamp,
+        );
+    }
+
+    let price_ramp = vec![step(3, AMOUNT / 4), step(9, AMOUNT / 2)];
+    fx.send(set(&fx, price_ramp.clone()), &[&recipient])
+        .unwrap();
+    assert_eq!(fx.plan(1).price_ramp, price_ramp);
+    fx.svm.expire_blockhash();
+    fx.send(set(&fx, Vec::new()), &[&recipient]).unwrap();
+    assert!(fx.plan(1).price_ramp.is_empty());
+}
+
+// ---------- spend alerts ----------
+
+#[test]
+fn spend_alerts_fire_once_per_milestone() {
+    let alerts = SpendAlerts {
+        subscription: Pubkey::new_unique(),
+        authority: Pubkey::new_unique(),
+        thresholds: vec![100, 250, 1_000],
+        bump: 255,
+    };
+    let crossed = |before, after| alerts.crossed(before, after).collect::<Vec<_>>();
+
+    assert_eq!(crossed(0, 99), Vec::<u64>::new());
+    // Reaching a milestone passes it, and the next charge does not again
+    assert_eq!(crossed(0, 100), vec![100]);
+    assert_eq!(crossed(100, 200), Vec::<u64>::new());
+    // A catch-up charge can pass several
+    assert_eq!(crossed(200, 1_000), vec![250, 1_000]);
+
+    assert!(SpendAlerts::check(&[]).is_ok());
+    assert!(SpendAlerts::check(&alerts.thresholds).is_ok());
+    for thresholds in [
+        vec![0, 100],
+        vec![250, 100],
+        vec![100, 100],
+        (1..=MAX_SPEND_ALERTS as u64 + 1).collect(),
+    ] {
+        assert_eq!(
+            SpendAlerts::check(&thresholds).unwrap_err(),
+            ErrorCode::InvalidSpendAlerts.into()
+        );
+    }
+}
+
+#[test]
+fn charge_reads_spend_alerts_as_the_last_account() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.svm.advance_time(INTERVAL);
+    let alerts = fx.set_spend_alerts(&[2 * AMOUNT]);
+    let with_alerts = |fx: &Fixture, alerts: Pubkey| {
+        let mut ix = fx.charge_ix();
+        ix.accounts.push(AccountMeta::new_readonly(alerts, false));
+        ix
+    };
+
+    // Another subscription's milestones
+    let mut other: SpendAlerts = fx.svm.get_anchor_account(&alerts).unwrap();
+    other.subscription = Pubkey::new_unique();
+    let stray = Pubkey::new_unique();
+    fx.svm
+        .set_anchor_account(stray, &other, 8 + SpendAlerts::INIT_SPACE);
+    assert_program_error(
+        fx.send(with_alerts(&fx, stray), &[]),
+        ErrorCode::InvalidSpendAlerts,
+    );
+
+    assert_success(fx.send(with_alerts(&fx, alerts), &[]));
+}
+
+#[test]
+fn update_spend_alerts_checks_the_thresholds() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let alerts = fx.set_spend_alerts(&[AMOUNT]);
+    let update = |fx: &Fixture, thresholds: Vec<u64>| {
+        build(
+            accounts::UpdateSpendAlerts {
+                spend_alerts: alerts,
+                subscription: fx.subscription,
+                authority: fx.authority.pubkey(),
+            },
+            instruction::UpdateSpendAlerts { thresholds },
+        )
+    };
+
+    let authority = fx.authority.insecure_clone();
+    assert_program_error(
+        fx.send(update(&fx, vec![2 * AMOUNT, AMOUNT]), &[&authority]),
+        ErrorCode::InvalidSpendAlerts,
+    );
+    fx.send(update(&fx, vec![AMOUNT, 10 * AMOUNT]), &[&authority])
+        .unwrap();
+    let state: SpendAlerts = fx.svm.get_anchor_account(&alerts).unwrap();
+    assert_eq!(state.thresholds, vec![AMOUNT, 10 * AMOUNT]);
+}
+
+// ---------- spend limit ----------
+
+fn set_spend_limit_ix(fx: &Fixture, max_total_spend: Option<u64>) -> Instruction {
+    build(
+        accounts::SetSpendLimit {
+            subscription: fx.subscription,
+            authority: fx.authority.pubkey(),
+            user_token_account: fx.user_token_account,
+            token_program: spl_token::ID,
+        },
+        instruction::SetSpendLimit { max_total_spend },
+    )
+}
+
+#[test]
+fn set_spend_limit_approves_what_is_left() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let authority = fx.authority.insecure_clone();
+
+    // The first period is already charged
+    let ix = set_spend_limit_ix(&fx, Some(AMOUNT - 1));
+    assert_program_error(fx.send(ix, &[&authority]), ErrorCode::InvalidSpendLimit);
+
+    let ix = set_spend_limit_ix(&fx, Some(3 * AMOUNT));
+    assert_success(fx.send(ix, &[&authority]));
+    fx.svm.expire_blockhash();
+    let ix = set_spend_limit_ix(&fx, None);
+    assert_success(fx.send(ix, &[&authority]));
+
+    // Delegated elsewhere, e.g. to a spending policy
+    fx.svm
+        .approve(&fx.user_token_account, &Pubkey::new_unique(), u64::MAX);
+    fx.svm.expire_blockhash();
+    let ix = set_spend_limit_ix(&fx, Some(3 * AMOUNT));
+    assert_program_error(fx.send(ix, &[&authority]), ErrorCode::InvalidSpendLimit);
+}
+
+#[test]
+fn catch_up_stops_at_spend_limit() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let config = fx.set_merchant_config(true, 4);
+    fx.svm.approve(
+        &fx.user_token_account,
+        &fx.subscription,
+        AMOUNT + AMOUNT / 2,
+    );
+    fx.svm.advance_time(4 * INTERVAL);
+    let ix = fx.charge_with_config_ix(config);
+
+    // One period and what the limit leaves of the next, however much the
+    // balance holds
+    let at_cpi = fx.subscription_at_cpi(ix);
+    assert_eq!(at_cpi.total_charged, 2 * AMOUNT + AMOUNT / 2);
+}
+
+#[test]
+fn set_spend_limit_stores_the_limit() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let authority = fx.authority.insecure_clone();
+    let written = |fx: &mut Fixture, max_total_spend| {
+        fx.svm.expire_blockhash();
+        let accounts =
+            assert_success(fx.send(set_spend_limit_ix(fx, max_total_spend), &[&authority]))
+                .accounts_at_cpi;
+        let (_, account) = accounts
+            .iter()
+            .find(|(key, _)| *key == fx.subscription)
+            .unwrap();
+        Subscription::try_deserialize(&mut &account.data[..]).unwrap()
+    };
+
+    // Written before the approval
+    let subscription = written(&mut fx, Some(3 * AMOUNT));
+    assert_eq!(subscription.max_total_spend, 3 * AMOUNT);
+    assert_eq!(subscription.spend_left(), 2 * AMOUNT);
+    let subscription = written(&mut fx, None);
+    assert_eq!(subscription.max_total_spend, Subscription::NO_SPEND_LIMIT);
+}
+
+/// The subscription with `left` to spend before its stored limit, its
+/// delegation still unlimited
+fn set_stored_spend_limit(fx: &mut Fixture, left: u64) {
+    let mut subscription = fx.subscription().unwrap();
+    subscription.max_total_spend = subscription.total_charged + left;
+    fx.set_subscription(&subscription);
+}
+
+#[test]
+fn charge_past_stored_spend_limit_deactivates() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    set_stored_spend_limit(&mut fx, AMOUNT - 1);
+    fx.svm.advance_time(INTERVAL);
+
+    fx.send(fx.charge_ix(), &[]).unwrap();
+    let subscription = fx.subscription().unwrap();
+    assert!(!subscription.is_active);
+    assert_eq!(subscription.total_charged, AMOUNT);
+}
+
+#[test]
+fn catch_up_stops_at_stored_spend_limit() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let config = fx.set_merchant_config(true, 4);
+    set_stored_spend_limit(&mut fx, AMOUNT + AMOUNT / 2);
+    fx.svm.advance_time(4 * INTERVAL);
+
+    let at_cpi = fx.subscription_at_cpi(fx.charge_with_config_ix(config));
+    assert_eq!(at_cpi.total_charged, 2 * AMOUNT + AMOUNT / 2);
+}
+
+#[test]
+fn fallback_mint_charge_respects_stored_spend_limit() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let fallback = fx.set_fallback_payment(STARTING_BALANCE);
+    let balance = fx.svm.token_balance(&fx.user_token_account);
+    fx.svm
+        .transfer_tokens(&fx.user_token_account, &fx.recipient_token_account, balance);
+    set_stored_spend_limit(&mut fx, AMOUNT - 1);
+    fx.svm.advance_time(INTERVAL);
+
+    // Its own approval cannot take it past the limit either
+    fx.send(fx.charge_fallback_ix(&fallback), &[]).unwrap();
+    assert!(!fx.subscription().unwrap().is_active);
+}
+
+#[test]
+fn overage_respects_stored_spend_limit() {
+    let mut fx = Fixture::new();
+    let recipient = fx.recipient_signer();
+    fx.subscribe(None);
+    fx.set_plan(BillingCadence::Monthly);
+    let plan = plan_address(&fx.recipient, 1).0;
+    let mut metered: Plan = fx.svm.get_anchor_account(&plan).unwrap();
+    metered.included_units = 100;
+    metered.overage_rate = 1_000;
+    fx.svm
+        .set_anchor_account(plan, &metered, 8 + Plan::INIT_SPACE);
+    let mut usage = fx.plan_subscription();
+    usage.units = 150;
+    fx.set_plan_subscription(&usage);
+    set_stored_spend_limit(&mut fx, 49_999);
+
+    let ix = fx.charge_overage_ix(&recipient.pubkey(), 50);
+    assert_program_error(fx.send(ix, &[&recipient]), ErrorCode::SpendLimitExceeded);
+    let ix = fx.charge_overage_ix(&recipient.pubkey(), 49);
+    assert_success(fx.send(ix, &[&recipient]));
+}
+
+#[test]
+fn cadence_switch_respects_stored_spend_limit() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.set_plan(BillingCadence::Monthly);
+    fx.svm.advance_time(7 * SECONDS_PER_DAY);
+    let authority = fx.authority.insecure_clone();
+    // Going annual owes 92 more tokens now
+    set_stored_spend_limit(&mut fx, 92_000_000 - 1);
+
+    let ix = fx.switch_cadence_ix(BillingCadence::Annual);
+    assert_program_error(fx.send(ix, &[&authority]), ErrorCode::SpendLimitExceeded);
+}
+
+// ---------- denylist ----------
+
+fn deny_subscriber_ix(fx: &Fixture, authority: &Pubkey) -> Instruction {
+    build(
+        accounts::DenySubscriber {
+            denylist_entry: denylist_address(&fx.recipient, &fx.authority.pubkey()).0,
+            merchant_multisig: merchant_multisig_address(&fx.recipient).0,
+            recipient: fx.recipient,
+            authority: *authority,
+            payer: fx.payer.pubkey(),
+            system_program: system_program::ID,
+        },
+        instruction::DenySubscriber {
+            wallet: fx.authority.pubkey(),
+        },
+    )
+}
+
+#[test]
+fn deny_subscriber_passes_validation() {
+    let mut fx = Fixture::new();
+    let recipient = Keypair::new();
+    fx.recipient = recipient.pubkey();
+
+    let ix = deny_subscriber_ix(&fx, &recipient.pubkey());
+    assert_success(fx.send(ix, &[&recipient]));
+}
+
+#[test]
+fn denied_wallets_cannot_subscribe() {
+    let mut fx = Fixture::new();
+    let entry = fx.set_denylist_entry();
+    let mut account = fx.svm.get_account(&entry).unwrap();
+    let check = |account: &mut Account| {
+        let info = AccountInfo::new(
+            &entry,
+            false,
+            false,
+            &mut account.lamports,
+            &mut account.data,
+            &account.owner,
+            false,
+            0,
+        );
+        DenylistEntry::check_allowed(&info)
+    };
+
+    assert_eq!(
+        check(&mut account.clone()).unwrap_err(),
+        ErrorCode::SubscriberDenied.into()
+    );
+    // Lamports sent to the address do not make an entry
+    account.data.clear();
+    account.owner = system_program::ID;
+    assert!(check(&mut account).is_ok());
+}
+
+#[test]
+fn allow_subscriber_closes_the_entry_for_the_merchant_authority() {
+    let mut fx = Fixture::new();
+    let recipient = Keypair::new();
+    fx.recipient = recipient.pubkey();
+    let entry = fx.set_denylist_entry();
+    let ix = build(
+        accounts::AllowSubscriber {
+            denylist_entry: entry,
+            merchant_multisig: merchant_multisig_address(&fx.recipient).0,
+            recipient: fx.recipient,
+            authority: recipient.pubkey(),
+        },
+        instruction::AllowSubscriber {},
+    );
+
+    let intruder = Keypair::new();
+    let forged = substitute(ix.clone(), 3, intruder.pubkey());
+    assert_program_error(
+        fx.send(forged, &[&intruder]),
+        ErrorCode::NotMerchantAuthority,
+    );
+
+    fx.send(ix, &[&recipient]).unwrap();
+    assert!(fx.svm.get_account(&entry).is_none());
+    assert!(fx.svm.get_account(&fx.recipient).unwrap().lamports > 0);
+}
+
+// ---------- allowlist ----------
+
+#[test]
+fn create_subscriber_allowlist_passes_validation() {
+    let mut fx = Fixture::new();
+    let recipient = Keypair::new();
+    fx.recipient = recipient.pubkey();
+
+    let ix = build(
+        accounts::CreateSubscriberAllowlist {
+            allowlist: subscriber_allowlist_address(&fx.recipient).0,
+            merchant_multisig: merchant_multisig_address(&fx.recipient).0,
+            recipient: fx.recipient,
+            authority: recipient.pubkey(),
+            payer: fx.payer.pubkey(),
+            system_program: system_program::ID,
+        },
+        instruction::CreateSubscriberAllowlist {},
+    );
+    assert_success(fx.send(ix, &[&recipient]));
+}
+
+#[test]
+fn invite_only_merchants_take_allowlisted_wallets_only() {
+    let mut fx = Fixture::new();
+    let allowlist = fx.set_subscriber_allowlist();
+    let entry = fx.set_allowlist_entry();
+    let mut allowlist_account = fx.svm.get_account(&allowlist).unwrap();
+    let mut entry_account = fx.svm.get_account(&entry).unwrap();
+    let mut empty = Account {
+        lamports: 1,
+        data: vec![],
+        owner: system_program::ID,
+        executable: false,
+        rent_epoch: 0,
+    };
+    let check = |allowlist_account: &mut Account, entry_account: &mut Account| {
+        let allowlist_info = AccountInfo::new(
+            &allowlist,
+            false,
+            false,
+            &mut allowlist_account.lamports,
+            &mut allowlist_account.data,
+            &allowlist_account.owner,
+            false,
+            0,
+        );
+        let entry_info = AccountInfo::new(
+            &entry,
+            false,
+            false,
+            &mut entry_account.lamports,
+            &mut entry_account.data,
+            &entry_account.owner,
+            false,
+            0,
+        );
+        SubscriberAllowlist::check_invited(&allowlist_info, &entry_info)
+    };
+
+    assert!(check(&mut allowlist_account, &mut entry_account).is_ok());
+    assert_eq!(
+        check(&mut allowlist_account, &mut empty.clone()).unwrap_err(),
+        ErrorCode::SubscriberNotAllowlisted.into()
+    );
+    // A merchant that is not invite-only takes everyone
+    assert!(check(&mut empty.clone(), &mut empty).is_ok());
+}
+
+#[test]
+fn remove_from_allowlist_closes_the_entry_for_the_merchant_authority() {
+    let mut fx = Fixture::new();
+    let recipient = Keypair::new();
+    fx.recipient = recipient.pubkey();
+    let entry = fx.set_allowlist_entry();
+    let ix = build(
+        accounts::RemoveFromAllowlist {
+            allowlist_entry: entry,
+            merchant_multisig: merchant_multisig_address(&fx.recipient).0,
+            recipient: fx.recipient,
+            authority: recipient.pubkey(),
+        },
+        instruction::RemoveFromAllowlist {},
+    );
+
+    let intruder = Keypair::new();
+    let forged = substitute(ix.clone(), 3, intruder.pubkey());
+    assert_program_error(
+        fx.send(forged, &[&intruder]),
+        ErrorCode::NotMerchantAuthority,
+    );
+
+    fx.send(ix, &[&recipient]).unwrap();
+    assert!(fx.svm.get_account(&entry).is_none());
+}
+
+#[test]
+fn closing_the_allowlist_opens_the_merchant_to_everyone() {
+    let mut fx = Fixture::new();
+    let recipient = Keypair::new();
+    fx.recipient = recipient.pubkey();
+    let allowlist = fx.set_subscriber_allowlist();
+
+    fx.send(
+        build(
+            accounts::CloseSubscriberAllowlist {
+                allowlist,
+                merchant_multisig: merchant_multisig_address(&fx.recipient).0,
+                recipient: fx.recipient,
+                authority: recipient.pubkey(),
+            },
+            instruction::CloseSubscriberAllowlist {},
+        ),
+        &[&recipient],
+    )
+    .unwrap();
+    assert!(fx.svm.get_account(&allowlist).is_none());
+    assert!(fx.svm.get_account(&fx.recipient).unwrap().lamports > 0);
+}
+
+// ---------- fallback funding ----------
+
+#[test]
+fn fallback_covers_primary_shortfall() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let config = fx.set_merchant_config(true, 1);
+    drain_to(&mut fx, AMOUNT / 4);
+    let first = fx.fallback_account(AMOUNT / 4);
+    let second = fx.fallback_account(STARTING_BALANCE);
+    let funding = fx.set_funding_sources(&[first, second]);
+    fx.svm.advance_time(INTERVAL);
+    let ix = fx.charge_with_funding_ix(Some(config), funding, &[first, second]);
+
+    // Covered in full, so no partial charge
+    assert_eq!(fx.subscription_at_cpi(ix).total_charged, 2 * AMOUNT);
+}
+
+#[test]
+fn fallback_without_delegation_gives_nothing() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let config = fx.set_merchant_config(true, 1);
+    drain_to(&mut fx, AMOUNT / 4);
+    let fallback = fx.fallback_account(STARTING_BALANCE);
+    fx.svm.revoke(&fallback);
+    let funding = fx.set_funding_sources(&[fallback]);
+    fx.svm.advance_time(INTERVAL);
+    let ix = fx.charge_with_funding_ix(Some(config), funding, &[fallback]);
+
+    assert_eq!(
+        fx.subscription_at_cpi(ix).total_charged,
+        AMOUNT + AMOUNT / 4
+    );
+}
+
+#[test]
+fn fallback_without_merchant_config() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    drain_to(&mut fx, 0);
+    let fallback = fx.fallback_account(STARTING_BALANCE);
+    let funding = fx.set_funding_sources(&[fallback]);
+    fx.svm.advance_time(INTERVAL);
+    let ix = fx.charge_with_funding_ix(None, funding, &[fallback]);
+
+    assert_eq!(fx.subscription_at_cpi(ix).total_charged, 2 * AMOUNT);
+}
+
+#[test]
+fn charge_with_unregistered_fallbacks_fails() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let first = fx.fallback_account(AMOUNT);
+    let second = fx.fallback_account(AMOUNT);
+    let funding = fx.set_funding_sources(&[first, second]);
+    fx.svm.advance_time(INTERVAL);
+
+    for (funding, sources) in [
+        (funding, vec![second, first]),
+        (funding, vec![first]),
+        (funding, vec![first, second, fx.user_token_account]),
+        (fx.subscription, vec![first, second]),
+    ] {
+        let ix = fx.charge_with_funding_ix(None, funding, &sources);
+        assert_program_error(fx.send(ix, &[]), ErrorCode::InvalidFundingSource);
+        fx.svm.expire_blockhash();
+    }
+}
+
+#[test]
+fn create_funding_sources_passes_validation() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let fallback = fx.fallback_account(AMOUNT);
+    let mut ix = build(
+        accounts::CreateFundingSources {
+            funding_sources: funding_sources_address(&fx.subscription).0,
+            subscription: fx.subscription,
+            authority: fx.authority.pubkey(),
+            payer: fx.payer.pubkey(),
+            system_program: system_program::ID,
+        },
+        instruction::CreateFundingSources {
+            token_accounts: vec![fallback],
+        },
+    );
+    ix.accounts.push(AccountMeta::new_readonly(fallback, false));
+    let authority = fx.authority.insecure_clone();
+
+    assert_success(fx.send(ix, &[&authority]));
+}
+
+fn update_funding_ix(fx: &Fixture, funding: Pubkey, token_accounts: &[Pubkey]) -> Instruction {
+    let mut ix = build(
+        accounts::UpdateFundingSources {
+            funding_sources: funding,
+            subscription: fx.subscription,
+            authority: fx.authority.pubkey(),
+        },
+        instruction::UpdateFundingSources {
+            token_accounts: token_accounts.to_vec(),
+        },
+    );
+    ix.accounts.extend(
+        token_accounts
+            .iter()
+            .map(|account| AccountMeta::new_readonly(*account, false)),
+    );
+    ix
+}
+
+#[test]
+fn update_funding_sources_replaces_list() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let first = fx.fallback_account(AMOUNT);
+    let second = fx.fallback_account(AMOUNT);
+    let funding = fx.set_funding_sources(&[first]);
+    let authority = fx.authority.insecure_clone();
+
+    fx.send(
+        update_funding_ix(&fx, funding, &[second, first]),
+        &[&authority],
+    )
+    .unwrap();
+    let state: FundingSources = fx.svm.get_anchor_account(&funding).unwrap();
+    assert_eq!(state.token_accounts, vec![second, first]);
+}
+
+#[test]
+fn update_funding_sources_rejects_invalid_accounts() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let funding = fx.set_funding_sources(&[]);
+    let fallback = fx.fallback_account(AMOUNT);
+    let other_mint = fx.svm.create_mint(&Pubkey::new_unique(), 6);
+    let wrong_mint = fx
+        .svm
+        .create_token_account(&fx.authority.pubkey(), &other_mint, AMOUNT);
+    let wrong_owner = fx
+        .svm
+        .create_token_account(&Pubkey::new_unique(), &fx.mint, AMOUNT);
+    let authority = fx.authority.insecure_clone();
+
+    for token_accounts in [
+        vec![wrong_mint],
+        vec![wrong_owner],
+        vec![fx.user_token_account],
+        vec![fallback, fallback],
+        vec![Pubkey::new_unique()],
+    ] {
+        let ix = update_funding_ix(&fx, funding, &token_accounts);
+        assert_program_error(fx.send(ix, &[&authority]), ErrorCode::InvalidFundingSource);
+        fx.svm.expire_blockhash();
+    }
+
+    let too_many: Vec<Pubkey> = (0..5).map(|_| fx.fallback_account(AMOUNT)).collect();
+    let ix = update_funding_ix(&fx, funding, &too_many);
+    assert_program_error(fx.send(ix, &[&authority]), ErrorCode::TooManyFundingSources);
+}
+
+#[test]
+fn update_funding_sources_by_other_wallet_fails() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let funding = fx.set_funding_sources(&[]);
+    let intruder = Keypair::new();
+    let ix = update_funding_ix(&fx, funding, &[]);
+    let ix = substitute(ix, 2, intruder.pubkey());
+
+    assert_anchor_error(fx.send(ix, &[&intruder]), AnchorErrorCode::ConstraintHasOne);
+}
+
+#[test]
+fn close_funding_sources_refunds_authority() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let funding = fx.set_funding_sources(&[]);
+    let rent = fx.svm.get_balance(&funding);
+    let before = fx.svm.get_balance(&fx.authority.pubkey());
+    let ix = build(
+        accounts::CloseFundingSources {
+            funding_sources: funding,
+            authority: fx.authority.pubkey(),
+        },
+        instruction::CloseFundingSources {},
+    );
+    let authority = fx.authority.insecure_clone();
+
+    fx.send(ix, &[&authority]).unwrap();
+    assert!(fx.svm.get_account(&funding).is_none());
+    assert_eq!(fx.svm.get_balance(&fx.authority.pubkey()), before + rent);
+}
+
+// ---------- create_charge_thread ----------
+
+const SCHEDULE: &str = "0 0 * * * * *";
+
+/// Clockwork stand-in: accepts `thread_create` when the thread authority,
+/// the subscription PDA, signed it
+fn clockwork<'a>(_: &Pubkey, accounts: &'a [AccountInfo<'a>], data: &[u8]) -> ProgramResult {
+    if !data.starts_with(&THREAD_CREATE_DISCRIMINATOR) {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    match accounts.first() {
+        Some(authority) if authority.is_signer && *authority.owner == PROGRAM_ID => Ok(()),
+        _ => Err(ProgramError::MissingRequiredSignature),
+    }
+}
+
+fn charge_thread_ix(fx: &mut Fixture, thread: Pubkey, thread_id: &[u8]) -> Instruction {
+    fx.svm.add_program(CLOCKWORK_THREAD_PROGRAM_ID, clockwork);
+    build(
+        accounts::CreateChargeThread {
+            subscription: fx.subscription,
+            authority: fx.authority.pubkey(),
+            payer: fx.payer.pubkey(),
+            thread,
+            thread_program: CLOCKWORK_THREAD_PROGRAM_ID,
+            system_program: system_program::ID,
+        },
+        instruction::CreateChargeThread {
+            thread_id: thread_id.to_vec(),
+            schedule: SCHEDULE.to_string(),
+            amount: 100_000_000,
+        },
+    )
+}
+
+#[test]
+fn create_charge_thread_signs_for_the_subscription() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let thread = charge_thread_address(&fx.subscription, b"charge").0;
+    let ix = charge_thread_ix(&mut fx, thread, b"charge");
+    let authority = fx.authority.insecure_clone();
+
+    assert_success(fx.send(ix, &[&authority]));
+}
+
+#[test]
+fn create_charge_thread_checks_thread_id() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let authority = fx.authority.insecure_clone();
+    let other = charge_thread_address(&fx.subscription, b"other").0;
+
+    for (thread, thread_id) in [
+        (other, b"charge".to_vec()),
+        (other, Vec::new()),
+        (other, vec![7; 33]),
+    ] {
+        let ix = charge_thread_ix(&mut fx, thread, &thread_id);
+        assert_program_error(fx.send(ix, &[&authority]), ErrorCode::InvalidChargeThread);
+        fx.svm.expire_blockhash();
+    }
+}
+
+#[test]
+fn create_charge_thread_for_inactive_subscription_fails() {
+    let mut fx = Fixture::new();
+    let mut subscription = fx.subscribe(None);
+    subscription.is_active = false;
+    fx.set_subscription(&subscription);
+    let thread = charge_thread_address(&fx.subscription, b"charge").0;
+    let ix = charge_thread_ix(&mut fx, thread, b"charge");
+    let authority = fx.authority.insecure_clone();
+
+    assert_program_error(fx.send(ix, &[&authority]), ErrorCode::SubscriptionInactive);
+}
+
+#[test]
+fn create_charge_thread_by_other_wallet_fails() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let thread = charge_thread_address(&fx.subscription, b"charge").0;
+    let intruder = Keypair::new();
+    let ix = substitute(
+        charge_thread_ix(&mut fx, thread, b"charge"),
+        1,
+        intruder.pubkey(),
+    );
+
+    assert_anchor_error(fx.send(ix, &[&intruder]), AnchorErrorCode::ConstraintHasOne);
+}
+
+#[test]
+fn charge_thread_sends_charge_subscription() {
+    let mut fx = Fixture::new();
+    let subscription = fx.subscribe(None);
+    let charge = charge_thread_instruction(&fx.subscription, &subscription);
+    let expected = fx.charge_ix();
+
+    assert_eq!(charge.program_id, expected.program_id);
+    assert_eq!(charge.data, expected.data);
+    let metas: Vec<_> = charge
+        .accounts
+        .iter()
+        .map(|account| (account.pubkey, account.is_signer, account.is_writable))
+        .collect();
+    let expected_metas: Vec<_> = expected
+        .accounts
+        .iter()
+        .map(|meta| (meta.pubkey, meta.is_signer, meta.is_writable))
+        .collect();
+    assert_eq!(metas, expected_metas);
+
+    // Clockwork's thread_create(amount, id, instructions, trigger)
+    let (thread, _) = charge_thread_address(&fx.subscription, b"charge");
+    let trigger = ThreadTrigger::Cron {
+        schedule: SCHEDULE.to_string(),
+        skippable: true,
+    };
+    let create = thread_create_instruction(
+        &fx.subscription,
+        &fx.payer.pubkey(),
+        &thread,
+        5,
+        b"charge".to_vec(),
+        vec![charge.clone()],
+        trigger.clone(),
+    )
+    .unwrap();
+    let (discriminator, mut args) = create.data.split_at(8);
+    assert_eq!(discriminator, THREAD_CREATE_DISCRIMINATOR);
+    let decoded =
+        <(u64, Vec<u8>, Vec<ThreadInstruction>, ThreadTrigger)>::deserialize(&mut args).unwrap();
+    assert_eq!(decoded, (5, b"charge".to_vec(), vec![charge], trigger));
+    assert!(args.is_empty());
+}
+
+// ---------- cancel_subscription ----------
+
+#[test]
+fn cancel_revokes_and_refunds_the_rent() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let rent = fx.svm.get_balance(&fx.subscription);
+    let authority = fx.authority.insecure_clone();
+    let lamports = fx.svm.get_balance(&authority.pubkey());
+    let ix = fx.cancel_ix();
+
+    assert_success(fx.send(ix, &[&authority]));
+    assert!(fx.subscription().is_none());
+    assert_eq!(fx.svm.get_balance(&authority.pubkey()), lamports + rent);
+    let user = fx.svm.get_token_account(&fx.user_token_account).unwrap();
+    assert!(user.delegate.is_none());
+}
+
+#[test]
+fn cancel_with_closed_token_account_closes_subscription() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.svm.remove_account(&fx.user_token_account);
+    let authority = fx.authority.insecure_clone();
+    let lamports = fx.svm.get_balance(&authority.pubkey());
+    let ix = fx.cancel_ix();
+
+    fx.send(ix, &[&authority]).unwrap();
+    assert!(fx.subscription().is_none());
+    assert!(fx.svm.get_balance(&authority.pubkey()) > lamports);
+}
+
+#[test]
+fn cancel_with_frozen_token_account_closes_subscription() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.svm.freeze(&fx.user_token_account);
+    let ix = fx.cancel_ix();
+    let authority = fx.authority.insecure_clone();
+
+    fx.send(ix, &[&authority]).unwrap();
+    assert!(fx.subscription().is_none());
+}
+
+#[test]
+fn cancel_after_revoke_closes_subscription() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.svm.revoke(&fx.user_token_account);
+    let ix = fx.cancel_ix();
+    let authority = fx.authority.insecure_clone();
+
+    fx.send(ix, &[&authority]).unwrap();
+    assert!(fx.subscription().is_none());
+}
+
+#[test]
+fn cancel_leaves_policy_delegation_alone() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let policy = fx.set_policy(AMOUNT);
+    let ix = fx.cancel_ix();
+    let authority = fx.authority.insecure_clone();
+
+    fx.send(ix, &[&authority]).unwrap();
+    let token_account = fx.svm.get_token_account(&fx.user_token_account).unwrap();
+    assert_eq!(token_account.delegate, Some(policy).into());
+}
+
+#[test]
+fn cancel_inactive_subscription_fails() {
+    let mut fx = Fixture::new();
+    let mut subscription = fx.subscribe(None);
+    subscription.is_active = false;
+    fx.set_subscription(&subscription);
+    let ix = fx.cancel_ix();
+    let authority = fx.authority.insecure_clone();
+
+    assert_program_error(
+        fx.send(ix, &[&authority]),
+        ErrorCode::SubscriptionAlreadyCancelled,
+    );
+}
+
+#[test]
+fn cancel_by_other_wallet_fails() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let intruder = Keypair::new();
+    let ix = substitute(fx.cancel_ix(), 1, intruder.pubkey());
+
+    assert_anchor_error(fx.send(ix, &[&intruder]), AnchorErrorCode::ConstraintHasOne);
+}
+
+// ---------- cleanup_cancelled_subscription ----------
+
+#[test]
+fn cleanup_closes_account_and_refunds_authority() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let rent = fx.svm.get_balance(&fx.subscription);
+    let before = fx.svm.get_balance(&fx.authority.pubkey());
+    let ix = fx.cleanup_ix();
+    let authority = fx.authority.insecure_clone();
+
+    fx.send(ix, &[&authority]).unwrap();
+
+    assert!(fx.svm.get_account(&fx.subscription).is_none());
+    assert_eq!(fx.svm.get_balance(&fx.authority.pubkey()), before + rent);
+}
+
+#[test]
+fn cleanup_requires_authority_signature() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let ix = unsigned(fx.cleanup_ix(), &fx.authority.pubkey());
+
+    assert_anchor_error(fx.send(ix, &[]), AnchorErrorCode::AccountNotSigner);
+    assert!(fx.subscription().is_some());
+}
+
+// ---------- compact_subscription ----------
+
+fn compact_ix(fx: &Fixture, authority: Pubkey) -> Instruction {
+    build(
+        accounts::CompactSubscription {
+            subscription: fx.subscription,
+            authority,
+        },
+        instruction::CompactSubscription {},
+    )
+}
+
+fn close_tombstone_ix(fx: &Fixture) -> Instruction {
+    build(
+        accounts::CloseSubscriptionTombstone {
+            tombstone: fx.subscription,
+            authority: fx.authority.pubkey(),
+        },
+        instruction::CloseSubscriptionTombstone {},
+    )
+}
+
+/// A subscription a charge deactivated after its delegation was revoked
+fn deactivate(fx: &mut Fixture) -> Subscription {
+    let mut subscription = fx.subscribe(None);
+    subscription.is_active = false;
+    subscription.total_charged = 3 * AMOUNT;
+    fx.set_subscription(&subscription);
+    subscription
+}
+
+#[test]
+fn compact_shrinks_inactive_subscription_and_refunds_rent() {
+    let mut fx = Fixture::new();
+    let expected = deactivate(&mut fx);
+    let rent = fx.svm.get_balance(&fx.subscription);
+    let before = fx.svm.get_balance(&fx.authority.pubkey());
+    let ix = compact_ix(&fx, fx.authority.pubkey());
+
+    // No signature needed: anyone can compact
+    fx.send(ix, &[]).unwrap();
+
+    let space = 8 + SubscriptionTombstone::INIT_SPACE;
+    let kept = fx.svm.minimum_balance_for_rent_exemption(space);
+    let account = fx.svm.get_account(&fx.subscription).unwrap();
+    assert_eq!(account.data.len(), space);
+    assert_eq!(account.lamports, kept);
+    assert_eq!(
+        fx.svm.get_balance(&fx.authority.pubkey()),
+        before + rent - kept
+    );
+
+    let tombstone: SubscriptionTombstone = fx.svm.get_anchor_account(&fx.subscription).unwrap();
+    assert_eq!(tombstone.authority, expected.authority);
+    assert_eq!(tombstone.recipient, expected.recipient);
+    assert_eq!(tombstone.token_mint, expected.token_mint);
+    assert_eq!(tombstone.created_at, expected.created_at);
+    assert_eq!(
+        tombstone.last_charge_timestamp,
+        expected.last_charge_timestamp
+    );
+    assert_eq!(tombstone.total_charged, 3 * AMOUNT);
+    assert_eq!(tombstone.bump, fx.bump);
+}
+
+#[test]
+fn compact_active_subscription_fails() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let ix = compact_ix(&fx, fx.authority.pubkey());
+
+    assert_program_error(fx.send(ix, &[]), ErrorCode::SubscriptionStillActive);
+    assert_eq!(
+        fx.svm.get_account(&fx.subscription).unwrap().data.len(),
+        SUBSCRIPTION_SPACE
+    );
+}
+
+#[test]
+fn compact_refunds_only_the_authority() {
+    let mut fx = Fixture::new();
+    deactivate(&mut fx);
+    let ix = compact_ix(&fx, fx.payer.pubkey());
+
+    assert_anchor_error(fx.send(ix, &[]), AnchorErrorCode::ConstraintHasOne);
+}
+
+#[test]
+fn compact_tombstone_fails() {
+    let mut fx = Fixture::new();
+    deactivate(&mut fx);
+    let ix = compact_ix(&fx, fx.authority.pubkey());
+    fx.send(ix.clone(), &[]).unwrap();
+    fx.svm.expire_blockhash();
+
+    assert_anchor_error(
+        fx.send(ix, &[]),
+        AnchorErrorCode::AccountDiscriminatorMismatch,
+    );
+}
+
+#[test]
+fn close_tombstone_refunds_authority() {
+    let mut fx = Fixture::new();
+    deactivate(&mut fx);
+    let ix = compact_ix(&fx, fx.authority.pubkey());
+    fx.send(ix, &[]).unwrap();
+    let rent = fx.svm.get_balance(&fx.subscription);
+    let before = fx.svm.get_balance(&fx.authority.pubkey());
+    let authority = fx.authority.insecure_clone();
+
+    let unsigned_ix = unsigned(close_tombstone_ix(&fx), &fx.authority.pubkey());
+    assert_anchor_error(fx.send(unsigned_ix, &[]), AnchorErrorCode::AccountNotSigner);
+
+    let ix = close_tombstone_ix(&fx);
+    fx.send(ix, &[&authority]).unwrap();
+
+    assert!(fx.svm.get_account(&fx.subscription).is_none());
+    assert_eq!(fx.svm.get_balance(&fx.authority.pubkey()), before + rent);
+}
+
+// ---------- update_subscription ----------
+
+#[test]
+fn update_changes_requested_fields() {
+    let mut fx = Fixture::new();
+    let before = fx.subscribe(None);
+    let expires_at = fx.svm.clock().unix_timestamp + 10 * INTERVAL;
+    let ix = fx.update_ix(Some(2 * AMOUNT), None, Some(expires_at));
+    let authority = fx.authority.insecure_clone();
+
+    fx.send(ix, &[&authority]).unwrap();
+
+    let after = fx.subscription().unwrap();
+    assert_eq!(after.amount_per_period, 2 * AMOUNT);
+    assert_eq!(after.interval_seconds, before.interval_seconds);
+    assert_eq!(after.expires_at, Some(expires_at));
+    assert_eq!(after.total_charged, before.total_charged);
+}
+
+#[test]
+fn update_amount_on_a_plan_fails() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.set_plan(BillingCadence::Monthly);
+    let expires_at = fx.svm.clock().unix_timestamp + 10 * INTERVAL;
+    let authority = fx.authority.insecure_clone();
+
+    let ix = fx.update_ix(Some(1), None, None);
+    assert_program_error(fx.send(ix, &[&authority]), ErrorCode::PricedByPlan);
+    assert_eq!(fx.subscription().unwrap().amount_per_period, AMOUNT);
+
+    // The rest can still change
+    let ix = fx.update_ix(None, None, Some(expires_at));
+    fx.send(ix, &[&authority]).unwrap();
+    assert_eq!(fx.subscription().unwrap().expires_at, Some(expires_at));
+}
+
+#[test]
+fn update_inactive_subscription_fails() {
+    let mut fx = Fixture::new();
+    let mut subscription = fx.subscribe(None);
+    subscription.is_active = false;
+    fx.set_subscription(&subscription);
+    let ix = fx.update_ix(Some(1), None, None);
+    let authority = fx.authority.insecure_clone();
+
+    assert_program_error(fx.send(ix, &[&authority]), ErrorCode::SubscriptionInactive);
+}
+
+#[test]
+fn update_by_other_wallet_fails() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let intruder = Keypair::new();
+    let ix = substitute(fx.update_ix(Some(1), None, None), 1, intruder.pubkey());
+
+    assert_anchor_error(fx.send(ix, &[&intruder]), AnchorErrorCode::ConstraintHasOne);
+    assert_eq!(fx.subscription().unwrap().amount_per_period, AMOUNT);
+}
+
+#[test]
+fn update_to_invalid_interval_fails() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let authority = fx.authority.insecure_clone();
+
+    for interval in [0, -2, -13, i64::MIN] {
+        fx.svm.expire_blockhash();
+        let ix = fx.update_ix(None, Some(interval), None);
+        assert_program_error(fx.send(ix, &[&authority]), ErrorCode::InvalidInterval);
+    }
+    assert_eq!(fx.subscription().unwrap().interval_seconds, INTERVAL);
+}
+
+#[test]
+fn update_to_calendar_interval_reschedules() {
+    let mut fx = Fixture::new();
+    let before = fx.subscribe(None);
+    let ix = fx.update_ix(None, Some(Interval::Yearly.to_seconds_field()), None);
+    let authority = fx.authority.insecure_clone();
+
+    fx.send(ix, &[&authority]).unwrap();
+
+    let after = fx.subscription().unwrap();
+    assert_eq!(after.interval(), Some(Interval::Yearly));
+    assert_eq!(
+        after.next_charge_at,
+        Interval::Yearly
+            .advance(before.last_charge_timestamp, before.created_at)
+            .unwrap()
+    );
+}
+
+// ---------- migrate_subscription ----------
+
+/// Store the fixture's subscription in the layout it had before the
+/// migration, at its old size
+fn set_legacy(fx: &mut Fixture) -> Subscription {
+    let current = fx.subscribe(Some(fx.svm.clock().unix_timestamp + 12 * INTERVAL));
+    let legacy = LegacySubscription {
+        authority: current.authority,
+        recipient: current.recipient,
+        user_token_account: current.user_token_account,
+        recipient_token_account: current.recipient_token_account,
+        token_mint: current.token_mint,
+        amount_per_period: current.amount_per_period,
+        interval_seconds: current.interval_seconds,
+        last_charge_timestamp: current.last_charge_timestamp,
+        created_at: current.created_at,
+        expires_at: current.expires_at,
+        is_active: current.is_active,
+        total_charged: current.total_charged,
+        bump: current.bump,
+    };
+    fx.svm
+        .set_anchor_account(fx.subscription, &legacy, 8 + LegacySubscription::INIT_SPACE);
+    current
+}
+
+/// Store the fixture's subscription in the `SubscriptionV2` layout, before
+/// `on_plan` and `max_total_spend`
+fn set_v2(fx: &mut Fixture, expires_at: Option<i64>) -> Subscription {
+    let current = fx.subscribe(expires_at);
+    let v2 = SubscriptionV2 {
+        is_active: current.is_active,
+        recipient: current.recipient,
+        next_charge_at: current.next_charge_at,
+        authority: current.authority,
+        user_token_account: current.user_token_account,
+        recipient_token_account: current.recipient_token_account,
+        token_mint: current.token_mint,
+        amount_per_period: current.amount_per_period,
+        interval_seconds: current.interval_seconds,
+        last_charge_timestamp: current.last_charge_timestamp,
+        created_at: current.created_at,
+        total_charged: current.total_charged,
+        bump: current.bump,
+        expires_at: current.expires_at,
+    };
+    fx.svm
+        .set_anchor_account(fx.subscription, &v2, 8 + SubscriptionV2::INIT_SPACE);
+    current
+}
+
+/// Store the fixture's subscription in the layout it had before `on_plan`,
+/// with the active bit and the expiry packed
+fn set_v3(fx: &mut Fixture) -> Subscription {
+    let current = fx.subscription().unwrap();
+    let v3 = SubscriptionV3 {
+        flags: current.is_active as u8,
+        recipient: current.recipient,
+        next_charge_at: current.next_charge_at,
+        authority: current.authority,
+        user_token_account: current.user_token_account,
+        recipient_token_account: current.recipient_token_account,
+        token_mint: current.token_mint,
+        amount_per_period: current.amount_per_period,
+        interval_seconds: current.interval_seconds,
+        last_charge_timestamp: current.last_charge_timestamp,
+        created_at: current.created_at,
+        total_charged: current.total_charged,
+        bump: current.bump,
+        expires_at: current.expires_at.unwrap_or(i64::MAX),
+    };
+    fx.svm
+        .set_anchor_account(fx.subscription, &v3, 8 + SubscriptionV3::INIT_SPACE);
+    current
+}
+
+/// Store the fixture's subscription in the layout it had before
+/// `max_total_spend`
+fn set_v4(fx: &mut Fixture) -> Subscription {
+    let current = fx.subscription().unwrap();
+    let v4 = SubscriptionV4 {
+        flags: current.is_active as u8,
+        recipient: current.recipient,
+        next_charge_at: current.next_charge_at,
+        authority: current.authority,
+        user_token_account: current.user_token_account,
+        recipient_token_account: current.recipient_token_account,
+        token_mint: current.token_mint,
+        amount_per_period: current.amount_per_period,
+        interval_seconds: current.interval_seconds,
+        last_charge_timestamp: current.last_charge_timestamp,
+        created_at: current.created_at,
+        total_charged: current.total_charged,
+        bump: current.bump,
+        expires_at: current.expires_at.unwrap_or(i64::MAX),
+        on_plan: current.on_plan,
+    };
+    fx.svm
+        .set_anchor_account(fx.subscription, &v4, 8 + SubscriptionV4::INIT_SPACE);
+    current
+}
+
+fn migrate_ix(fx: &Fixture) -> Instruction {
+    build(
+        accounts::MigrateSubscription {
+            subscription: fx.subscription,
+            plan_subscription: plan_subscription_address(&fx.subscription).0,
+            payer: fx.payer.pubkey(),
+            system_program: system_program::ID,
+        },
+        instruction::MigrateSubscription {},
+    )
+}
+
+#[test]
+fn migrate_rewrites_legacy_account() {
+    let mut fx = Fixture::new();
+    let expected = set_legacy(&mut fx);
+    let ix = migrate_ix(&fx);
+
+    // Rewritten before the rent top-up CPI
+    let migrated = fx.subscription_at_cpi(ix);
+    assert_eq!(
+        migrated.next_charge_at,
+        expected.last_charge_timestamp + INTERVAL
+    );
+    let bytes = |subscription: &Subscription| {
+        let mut data = Vec::new();
+        subscription.try_serialize(&mut data).unwrap();
+        data
+    };
+    assert_eq!(bytes(&migrated), bytes(&expected));
+}
+
+#[test]
+fn migrate_funded_account_needs_no_top_up() {
+    let mut fx = Fixture::new();
+    let expected = set_legacy(&mut fx);
+    fx.svm.airdrop(&fx.subscription, 1_000_000);
+    let ix = migrate_ix(&fx);
+
+    fx.send(ix, &[]).unwrap();
+    let account = fx.svm.get_account(&fx.subscription).unwrap();
+    assert_eq!(account.data.len(), SUBSCRIPTION_SPACE);
+    assert_eq!(
+        fx.subscription().unwrap().total_charged,
+        expected.total_charged
+    );
+}
+
+#[test]
+fn migrate_rewrites_v2_account() {
+    for expires_at in [Some(1_767_225_600), None] {
+        let mut fx = Fixture::new();
+        let expected = set_v2(&mut fx, expires_at);
+        // Funded for the larger account, so no top-up and no CPI
+        fx.svm.airdrop(&fx.subscription, 1_000_000);
+        let lamports = fx.svm.get_balance(&fx.subscription);
+        let ix = migrate_ix(&fx);
+
+        fx.send(ix, &[]).unwrap();
+
+        let account = fx.svm.get_account(&fx.subscription).unwrap();
+        assert_eq!(account.data.len(), SUBSCRIPTION_SPACE);
+        assert_eq!(account.lamports, lamports);
+        let migrated = fx.subscription().unwrap();
+        assert!(migrated.is_active);
+        assert_eq!(migrated.expires_at, expires_at);
+        assert_eq!(migrated.next_charge_at, expected.next_charge_at);
+        assert_eq!(migrated.total_charged, expected.total_charged);
+    }
+}
+
+#[test]
+fn migrate_v3_account_reads_its_plan() {
+    for on_plan in [false, true] {
+        let mut fx = Fixture::new();
+        fx.subscribe(None);
+        if on_plan {
+            fx.set_plan(BillingCadence::Monthly);
+        }
+        let expected = set_v3(&mut fx);
+        let ix = migrate_ix(&fx);
+
+        // Larger, so the rent is topped up after the rewrite
+        let migrated = fx.subscription_at_cpi(ix);
+        assert_eq!(migrated.on_plan, on_plan);
+        let bytes = |subscription: &Subscription| {
+            let mut data = Vec::new();
+            subscription.try_serialize(&mut data).unwrap();
+            data
+        };
+        assert_eq!(bytes(&migrated), bytes(&expected));
+    }
+}
+
+#[test]
+fn migrate_v4_account_stores_no_spend_limit() {
+    let mut fx = Fixture::new();
+    fx.subscribe(Some(fx.svm.clock().unix_timestamp + 12 * INTERVAL));
+    fx.set_plan(BillingCadence::Monthly);
+    let expected = set_v4(&mut fx);
+    assert_eq!(
+        fx.svm.get_account(&fx.subscription).unwrap().data.len(),
+        227
+    );
+    let ix = migrate_ix(&fx);
+
+    // Larger, so the rent is topped up after the rewrite
+    let migrated = fx.subscription_at_cpi(ix);
+    assert!(migrated.on_plan);
+    assert_eq!(migrated.max_total_spend, Subscription::NO_SPEND_LIMIT);
+    let bytes = |subscription: &Subscription| {
+        let mut data = Vec::new();
+        subscription.try_serialize(&mut data).unwrap();
+        data
+    };
+    assert_eq!(bytes(&migrated), bytes(&expected));
+
+    let account = fx.svm.get_account(&fx.subscription).unwrap();
+    assert_eq!(account.data.len(), SUBSCRIPTION_SPACE);
+    assert_eq!(
+        account.lamports,
+        fx.svm
+            .minimum_balance_for_rent_exemption(SUBSCRIPTION_SPACE)
+    );
+}
+
+#[test]
+fn migrate_v3_account_needs_its_plan_subscription_address() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.set_plan(BillingCadence::Monthly);
+    set_v3(&mut fx);
+    let mut ix = migrate_ix(&fx);
+    // Another address that holds nothing, so the plan would go unnoticed
+    ix.accounts[1].pubkey = Pubkey::new_unique();
+
+    assert_anchor_error(fx.send(ix, &[]), AnchorErrorCode::ConstraintSeeds);
+}
+
+#[test]
+fn charge_v2_account_fails() {
+    let mut fx = Fixture::new();
+    set_v2(&mut fx, None);
+    fx.svm.advance_time(INTERVAL);
+    let ix = fx.charge_ix();
+
+    assert_anchor_error(
+        fx.send(ix, &[]),
+        AnchorErrorCode::AccountDiscriminatorMismatch,
+    );
+}
+
+#[test]
+fn migrate_current_account_fails() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let ix = migrate_ix(&fx);
+
+    assert_program_error(fx.send(ix, &[]), ErrorCode::SubscriptionAlreadyMigrated);
+}
+
+#[test]
+fn charge_legacy_account_fails() {
+    let mut fx = Fixture::new();
+    set_legacy(&mut fx);
+    fx.svm.advance_time(INTERVAL);
+    let ix = fx.charge_ix();
+
+    assert_anchor_error(
+        fx.send(ix, &[]),
+        AnchorErrorCode::AccountDiscriminatorMismatch,
+    );
+}
+
+// ---------- Switchboard Function charges ----------
+
+/// A Switchboard Function account whose enclave signer is `enclave_signer`
+fn set_function(fx: &mut Fixture, enclave_signer: &Pubkey) -> Pubkey {
+    let function = Pubkey::new_unique();
+    let mut data = vec![0; SwitchboardFunction::LEN];
+    data[..8].copy_from_slice(&SwitchboardFunction::DISCRIMINATOR);
+    data[SwitchboardFunction::ENCLAVE_SIGNER_OFFSET..].copy_from_slice(enclave_signer.as_ref());
+    fx.svm.set_account(
+        function,
+        Account {
+            lamports: 1_000_000_000,
+            data,
+            owner: SWITCHBOARD_ATTESTATION_PROGRAM_ID,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+    function
+}
+
+/// Register `function` for `recipient`, as `register_charge_function` would
+fn set_charge_function(fx: &mut Fixture, recipient: Pubkey, function: Pubkey) -> Pubkey {
+    let (address, bump) = charge_function_address(&recipient);
+    let state = ChargeFunction {
+        recipient,
+        function,
+        bump,
+    };
+    fx.svm
+        .set_anchor_account(address, &state, 8 + ChargeFunction::INIT_SPACE);
+    address
+}
+
+fn attested_charge_ix(fx: &Fixture, function: Pubkey, enclave_signer: Pubkey) -> Instruction {
+    build(
+        accounts::ChargeSubscriptionAttested {
+            charge: accounts::ChargeSubscription {
+                subscription: fx.subscription,
+                user_token_account: fx.user_token_account,
+                recipient_token_account: fx.recipient_token_account,
+                token_program: spl_token::ID,
+            },
+            charge_function: charge_function_address(&fx.recipient).0,
+            function,
+            enclave_signer,
+        },
+        instruction::ChargeSubscriptionAttested {},
+    )
+}
+
+#[test]
+fn register_charge_function_passes_validation() {
+    let mut fx = Fixture::new();
+    let recipient = Keypair::new();
+    let function = set_function(&mut fx, &Pubkey::new_unique());
+    let ix = build(
+        accounts::RegisterChargeFunction {
+            charge_function: charge_function_address(&recipient.pubkey()).0,
+            recipient: recipient.pubkey(),
+            function,
+            payer: fx.payer.pubkey(),
+            system_program: system_program::ID,
+        },
+        instruction::RegisterChargeFunction {},
+    );
+
+    assert_success(fx.send(ix, &[&recipient]));
+}
+
+#[test]
+fn close_charge_function_refunds_recipient() {
+    let mut fx = Fixture::new();
+    let recipient = Keypair::new();
+    fx.svm.airdrop(&recipient.pubkey(), 1_000_000_000);
+    let address = set_charge_function(&mut fx, recipient.pubkey(), Pubkey::new_unique());
+    let rent = fx.svm.get_balance(&address);
+    let before = fx.svm.get_balance(&recipient.pubkey());
+    let ix = build(
+        accounts::CloseChargeFunction {
+            charge_function: address,
+            recipient: recipient.pubkey(),
+        },
+        instruction::CloseChargeFunction {},
+    );
+
+    fx.send(ix, &[&recipient]).unwrap();
+    assert!(fx.svm.get_account(&address).is_none());
+    assert_eq!(fx.svm.get_balance(&recipient.pubkey()), before + rent);
+}
+
+#[test]
+fn attested_charge_when_due_moves_one_period() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.svm.advance_time(INTERVAL);
+    let enclave = Keypair::new();
+    let function = set_function(&mut fx, &enclave.pubkey());
+    let recipient = fx.recipient;
+    set_charge_function(&mut fx, recipient, function);
+    let ix = attested_charge_ix(&fx, function, enclave.pubkey());
+
+    assert_success(fx.send(ix, &[&enclave]));
+    assert_eq!(
+        fx.svm.token_balance(&fx.recipient_token_account),
+        2 * AMOUNT
+    );
+    assert_eq!(fx.subscription().unwrap().total_charged, 2 * AMOUNT);
+}
+
+#[test]
+fn attested_charge_before_interval_fails() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    let enclave = Keypair::new();
+    let function = set_function(&mut fx, &enclave.pubkey());
+    let recipient = fx.recipient;
+    set_charge_function(&mut fx, recipient, function);
+    let ix = attested_charge_ix(&fx, function, enclave.pubkey());
+
+    assert_program_error(fx.send(ix, &[&enclave]), ErrorCode::IntervalNotMet);
+}
+
+#[test]
+fn attested_charge_by_other_signer_fails() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.svm.advance_time(INTERVAL);
+    let function = set_function(&mut fx, &Pubkey::new_unique());
+    let recipient = fx.recipient;
+    set_charge_function(&mut fx, recipient, function);
+    let impostor = Keypair::new();
+    let ix = attested_charge_ix(&fx, function, impostor.pubkey());
+
+    assert_program_error(fx.send(ix, &[&impostor]), ErrorCode::InvalidAttestation);
+}
+
+#[test]
+fn attested_charge_from_unregistered_function_fails() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.svm.advance_time(INTERVAL);
+    let enclave = Keypair::new();
+    let registered = set_function(&mut fx, &enclave.pubkey());
+    let recipient = fx.recipient;
+    set_charge_function(&mut fx, recipient, registered);
+    let other = set_function(&mut fx, &enclave.pubkey());
+    let ix = attested_charge_ix(&fx, other, enclave.pubkey());
+
+    assert_anchor_error(fx.send(ix, &[&enclave]), AnchorErrorCode::ConstraintHasOne);
+}
+
+#[test]
+fn attested_charge_requires_enclave_signature() {
+    let mut fx = Fixture::new();
+    fx.subscribe(None);
+    fx.svm.advance_time(INTERVAL);
+    let enclave = Keypair::new();
+    let function = set_function(&mut fx, &enclave.pubkey());
+    let recipient = fx.recipient;
+    set_charge_function(&mut fx, recipient, function);
+    let ix = unsigned(
+        attested_charge_ix(&fx, function, enclave.pubkey()),
+        &enclave.pubkey(),
+    );
+
+    assert_anchor_error(fx.send(ix, &[]), AnchorErrorCode::AccountNotSigner);
+}
+
+#[test]
+fn switchboard_function_rejects_other_accounts() {
+    let mut data = vec![0; SwitchboardFunction::LEN];
+    data[..8].copy_from_slice(&SwitchboardFunction::DISCRIMINATOR);
+    assert!(SwitchboardFunction::parse(&data).is_ok());
+    assert!(SwitchboardFunction::parse(&data[..SwitchboardFunction::LEN - 1]).is_err());
+    data[0] ^= 1;
+    assert!(SwitchboardFunction::parse(&data).is_err());
+}
+
+// ---------- tuktuk charge tasks ----------
+
+/// A task queue account owned by tuktuk; its contents are tuktuk's to check
+fn set_task_queue(fx: &mut Fixture) -> Pubkey {
+    let task_queue = Pubkey::new_unique();
+    fx.svm.set_account(
+        task_queue,
+        Account {
+            lamports: 1_000_000_000,
+            data: vec![0; 64],
+            owner: TUKTUK_PROGRAM_ID,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+    fx.svm.add_program(TUKTUK_PROGRAM_ID, tuktuk);
+    task_queue
+}
+
+/// Tuktuk stand-in: accepts `queue_task` when the queue authority PDA
+/// signed it
+fn tuktuk<'a>(_: &Pubkey, accounts: &'a [AccountInfo<'a>], _: &[u8]) -> ProgramResult {
+    match accounts.get(1) {
+        Some(queue_authority) if queue_authority.is_signer => Ok(()),
+        _ => Err(ProgramError::MissingRequiredSignature),
+    }
+}
+
+fn queue_charge_task_ix(
+    fx: &Fixture,
+    recipient: Pubkey,
+    task_queue: Pubkey,
+    task: Pubkey,
+    task_id: u16,
+) -> Instruction {
+    let queue_authority = queue_authority_address(&recipient).0;
+    build(
+        accounts::QueueChargeTask {
+            subscription: fx.subscription,
+            recipient,
+            payer: fx.payer.pubkey(),
+            queue_authority,
+            task_queue_authority: task_queue_authority_address(&task_queue, &queue_authority).0,
+            task_queue,
+            task,
+            tuktuk_program: TUKTUK_PROGRAM_ID,
+            system_program: system_program::ID,
+        },
+        instruction::QueueChargeTask { task_id },
+    )
+}
+
+/// The fixture's recipient is not a keypair, so subscribe to one that is
+fn subscribe_to_signing_recipient(fx: &mut Fixture) -> Keypair {
+    let recipient = Keypair::new();
+    let mut subscription = fx.subscribe(None);
+    subscription.recipient = recipient.pubkey();
+    (fx.subscription, subscription.bump) = Pubkey::find_program_address(
+        &[
+            b"subscription",
+            subscription.authority.as_ref(),
+            recipient.pubkey().as_ref(),
+        ],
+        &PROGRAM_ID,
+    );
+    fx.set_subscription(&subscription);
+    recipient
+}
+
+#[test]
+fn queue_charge_task_signs_as_the_queue_authority() {
+    let mut fx = Fixture::new();
+    let recipient = subscribe_to_signing_recipient(&mut fx);
+    let task_queue = set_task_queue(&mut fx);
+    let task = charge_task_address(&task_queue, 3).0;
+    let ix = queue_charge_task_ix(&fx, recipient.pubkey(), task_queue, task, 3);
+
+    assert_success(fx.send(ix, &[&recipient]));
+}
+
+#[test]
+fn queue_charge_task_checks_task_id() {
+    let mut fx = Fixture::new();
+    let recipient = subscribe_to_signing_recipient(&mut fx);
+    let task_queue = set_task_queue(&mut fx);
+    let task = charge_task_address(&task_queue, 4).0;
+    let ix = queue_charge_task_ix(&fx, recipient.pubkey(), task_queue, task, 3);
+
+    assert_program_error(fx.send(ix, &[&recipient]), ErrorCode::InvalidChargeTask);
+}
+
+#[test]
+fn queue_charge_task_for_inactive_subscription_fails() {
+    let mut fx = Fixture::new();
+    let recipient = subscribe_to_signing_recipient(&mut fx);
+    let mut subscription = fx.subscription().unwrap();
+    subscription.is_active = false;
+    fx.set_subscription(&subscription);
+    let task_queue = set_task_queue(&mut fx);
+    let task = charge_task_address(&task_queue, 3).0;
+    let ix = queue_charge_task_ix(&fx, recipient.pubkey(), task_queue, task, 3);
+
+    assert_program_error(fx.send(ix, &[&recipient]), ErrorCode::SubscriptionInactive);
+}
+
+#[test]
+fn queue_charge_task_by_other_wallet_fails() {
+    let mut fx = Fixture::new();
+    subscribe_to_signing_recipient(&mut fx);
+    let task_queue = set_task_queue(&mut fx);
+    let task = charge_task_address(&task_queue, 3).0;
+    let intruder = Keypair::new();
+    let ix = queue_charge_task_ix(&fx, intruder.pubkey(), task_queue, task, 3);
+
+    assert_anchor_error(fx.send(ix, &[&intruder]), AnchorErrorCode::ConstraintHasOne);
+}
+
+#[test]
+fn tuktuk_charge_when_due_moves_one_period() {
+    let mut fx = Fixture::new();
+    let subscription = fx.subscribe(None);
+    fx.svm.advance_time(INTERVAL);
+    let ix = charge_task_instruction(&fx.subscription, &subscription, &[]);
+
+    assert_success(fx.send(ix, &[]));
+    assert_eq!(
+        fx.svm.token_balance(&fx.recipient_token_account),
+        2 * AMOUNT
+    );
+    assert_eq!(fx.subscription().unwrap().total_charged, 2 * AMOUNT);
+}
+
+#[test]
+fn tuktuk_charge_before_interval_fails() {
+    let mut fx = Fixture::new();
+    let subscription = fx.subscribe(None);
+    let ix = charge_task_instruction(&fx.subscription, &subscription, &[]);
+
+    assert_program_error(fx.send(ix, &[]), ErrorCode::IntervalNotMet);
+}
+
+#[test]
+fn charge_task_compiles_in_message_order() {
+    let mut fx = Fixture::new();
+    let subscription = fx.subscribe(None);
+    let config = Pubkey::new_unique();
+    let charge = charge_task_instruction(
+        &fx.subscription,
+        &subscription,
+        &[AccountMeta::new_readonly(config, false)],
+    );
+    let task = compile_task(std::slice::from_ref(&charge));
+
+    assert_eq!(
+        (task.num_rw_signers, task.num_ro_signers, task.num_rw),
+        (0, 0, 3)
+    );
+    assert_eq!(
+        task.accounts,
+        vec![
+            fx.subscription,
+            fx.user_token_account,
+            fx.recipient_token_account,
+            spl_token::ID,
+            config,
+            PROGRAM_ID,
+        ]
+    );
+    let compiled = &task.instructions[0];
+    assert_eq!(compiled.program_id_index, 5);
+    assert_eq!(compiled.accounts, vec![0, 1, 2, 3, 4]);
+    assert_eq!(compiled.data, charge.data);
+    assert!(task.signer_seeds.is_empty());
+}
+
+// ---------- keeper leases ----------
+
+const LEASE_SECONDS: i64 = 60;
+
+/// A lease for `keepers` under a new group, not yet held
+fn set_keeper_lease(fx: &mut Fixture, keepers: &[&Keypair]) -> (Keypair, Pubkey) {
+    let group = Keypair::new();
+    let (address, bump) = keeper_lease_address(&group.pubkey());
+    let state = KeeperLease {
+        group: group.pubkey(),
+        holder: Pubkey::default(),
+        term: 0,
+        expires_at: 0,
+        bump,
+        keepers: keepers.iter().map(|keeper| keeper.pubkey()).collect(),
+    };
+    fx.svm
+        .set_anchor_account(address, &state, 8 + KeeperLease::INIT_SPACE);
+    (group, address)
+}
+
+fn acquire_ix(lease: Pubkey, keeper: &Keypair, duration_seconds: i64) -> Instruction {
+    build(
+        accounts::AcquireKeeperLease {
+            lease,
+            keeper: keeper.pubkey(),
+        },
+        instruction::AcquireKeeperLease { duration_seconds },
+    )
+}
+
+fn check_lease_ix(lease: Pubkey, keeper: &Keypair, term: u64) -> Instruction {
+    build(
+        accounts::CheckKeeperLease {
+            lease,
+            keeper: keeper.pubkey(),
+        },
+        instruction::CheckKeeperLease { term },
+    )
+}
+
+fn lease(fx: &Fixture, address: &Pubkey) -> KeeperLease {
+    fx.svm.get_anchor_account(address).unwrap()
+}
+
+#[test]
+fn create_keeper_lease_passes_validation() {
+    let mut fx = Fixture::new();
+    let group = Keypair::new();
+    let ix = build(
+        accounts::CreateKeeperLease {
+            lease: keeper_lease_address(&group.pubkey()).0,
+            group: group.pubkey(),
+            payer: fx.payer.pubkey(),
+            system_program: system_program::ID,
+        },
+        instruction::CreateKeeperLease {
+            keepers: vec![Pubkey::new_unique(), Pubkey::new_unique()],
+        },
+    );
+
+    assert_success(fx.send(ix, &[&group]));
+}
+
+#[test]
+fn first_keeper_to_ask_takes_the_lease() {
+    let mut fx = Fixture::new();
+    let (first, second) = (Keypair::new(), Keypair::new());
+    let (_, address) = set_keeper_lease(&mut fx, &[&first, &second]);
+    let now = fx.svm.clock().unix_timestamp;
+
+    fx.send(acquire_ix(address, &first, LEASE_SECONDS), &[&first])
+        .unwrap();
+    let state = lease(&fx, &address);
+    assert_eq!(state.holder, first.pubkey());
+    assert_eq!(state.term, 1);
+    assert_eq!(state.expires_at, now + LEASE_SECONDS);
+
+    assert_program_error(
+        fx.send(acquire_ix(address, &second, LEASE_SECONDS), &[&second]),
+        ErrorCode::KeeperLeaseHeld,
+    );
+}
+
+#[test]
+fn holder_renews_without_a_new_term() {
+    let mut fx = Fixture::new();
+    let keeper = Keypair::new();
+    let (_, address) = set_keeper_lease(&mut fx, &[&keeper]);
+
+    fx.send(acquire_ix(address, &keeper, LEASE_SECONDS), &[&keeper])
+        .unwrap();
+    fx.svm.advance_time(LEASE_SECONDS / 2);
+    fx.svm.expire_blockhash();
+    fx.send(acquire_ix(address, &keeper, LEASE_SECONDS), &[&keeper])
+        .unwrap();
+
+    let state = lease(&fx, &address);
+    assert_eq!(state.term, 1);
+    assert_eq!(
+        state.expires_at,
+        fx.svm.clock().unix_timestamp + LEASE_SECONDS
+    );
+}
+
+#[test]
+fn expired_lease_fails_over_to_another_keeper() {
+    let mut fx = Fixture::new();
+    let (first, second) = (Keypair::new(), Keypair::new());
+    let (_, address) = set_keeper_lease(&mut fx, &[&first, &second]);
+    fx.send(acquire_ix(address, &first, LEASE_SECONDS), &[&first])
+        .unwrap();
+
+    fx.svm.advance_time(LEASE_SECONDS);
+    fx.send(acquire_ix(address, &second, LEASE_SECONDS), &[&second])
+        .unwrap();
+
+    let state = lease(&fx, &address);
+    assert_eq!(state.holder, second.pubkey());
+    assert_eq!(state.term, 2);
+    assert_program_error(
+        fx.send(check_lease_ix(address, &first, 1), &[&first]),
+        ErrorCode::KeeperLeaseNotHeld,
+    );
+    fx.send(check_lease_ix(address, &second, 2), &[&second])
+        .unwrap();
+}
+
+#[test]
+fn acquire_keeper_lease_checks_keeper_and_duration() {
+    let mut fx = Fixture::new();
+    let keeper = Keypair::new();
+    let (_, address) = set_keeper_lease(&mut fx, &[&keeper]);
+    let outsider = Keypair::new();
+
+    assert_program_error(
+        fx.send(acquire_ix(address, &outsider, LEASE_SECONDS), &[&outsider]),
+        ErrorCode::NotALeaseKeeper,
+    );
+    assert_program_error(
+        fx.send(acquire_ix(address, &keeper, 0), &[&keeper]),
+        ErrorCode::InvalidKeeperLease,
+    );
+    assert_program_error(
+        fx.send(acquire_ix(address, &keeper, 3601), &[&keeper]),
+        ErrorCode::InvalidKeeperLease,
+    );
+    assert_anchor_error(
+        fx.send(
+            unsigned(
+                acquire_ix(address, &keeper, LEASE_SECONDS),
+                &keeper.pubkey(),
+            ),
+            &[],
+        ),
+        AnchorErrorCode::AccountNotSigner,
+    );
+}
+
+#[test]
+fn released_lease_is_taken_over_at_once() {
+    let mut fx = Fixture::new();
+    let (first, second) = (Keypair::new(), Keypair::new());
+    let (_, address) = set_keeper_lease(&mut fx, &[&first, &second]);
+    fx.send(acquire_ix(address, &first, LEASE_SECONDS), &[&first])
+        .unwrap();
+    let release = |keeper: &Keypair| {
+        build(
+            accounts::AcquireKeeperLease {
+                lease: address,
+                keeper: keeper.pubkey(),
+            },
+            instruction::ReleaseKeeperLease {},
+        )
+    };
+
+    assert_program_error(
+        fx.send(release(&second), &[&second]),
+        ErrorCode::KeeperLeaseNotHeld,
+    );
+    fx.send(release(&first), &[&first]).unwrap();
+    fx.send(acquire_ix(address, &second, LEASE_SECONDS), &[&second])
+        .unwrap();
+
+    assert_eq!(lease(&fx, &address).holder, second.pubkey());
+}
+
+#[test]
+fn check_keeper_lease_fences_stale_terms() {
+    let mut fx = Fixture::new();
+    let keeper = Keypair::new();
+    let (_, address) = set_keeper_lease(&mut fx, &[&keeper]);
+    fx.send(acquire_ix(address, &keeper, LEASE_SECONDS), &[&keeper])
+        .unwrap();
+
+    fx.send(check_lease_ix(address, &keeper, 1), &[&keeper])
+        .unwrap();
+    assert_program_error(
+        fx.send(check_lease_ix(address, &keeper, 0), &[&keeper]),
+        ErrorCode::KeeperLeaseNotHeld,
+    );
+
+    fx.svm.advance_time(LEASE_SECONDS);
+    fx.svm.expire_blockhash();
+    assert_program_error(
+        fx.send(check_lease_ix(address, &keeper, 1), &[&keeper]),
+        ErrorCode::KeeperLeaseNotHeld,
+    );
+}
+
+#[test]
+fn removing_the_holder_ends_its_lease() {
+    let mut fx = Fixture::new();
+    let (first, second) = (Keypair::new(), Keypair::new());
+    let (group, address) = set_keeper_lease(&mut fx, &[&first, &second]);
+    fx.send(acquire_ix(address, &first, LEASE_SECONDS), &[&first])
+        .unwrap();
+    let update = |keepers: Vec<Pubkey>| {
+        build(
+            accounts::UpdateKeeperLease {
+                lease: address,
+                group: group.pubkey(),
+            },
+            instruction::UpdateKeeperLease { keepers },
+        )
+    };
+
+    assert_program_error(
+        fx.send(update(vec![second.pubkey(); 2]), &[&group]),
+        ErrorCode::InvalidKeeperLease,
+    );
+    fx.send(update(vec![second.pubkey()]), &[&group]).unwrap();
+    fx.send(acquire_ix(address, &second, LEASE_SECONDS), &[&second])
+        .unwrap();
+
+    let state = lease(&fx, &address);
+    assert_eq!(state.keepers, vec![second.pubkey()]);
+    assert_eq!((state.holder, state.term), (second.pubkey(), 2));
+}
+
+#[test]
+fn close_keeper_lease_refunds_group() {
+    let mut fx = Fixture::new();
+    let keeper = Keypair::new();
+    let (group, address) = set_keeper_lease(&mut fx, &[&keeper]);
+    let rent = fx.svm.get_balance(&address);
+    let close = |group: &Pubkey| {
+        build(
+            accounts::CloseKeeperLease {
+                lease: address,
+                group: *group,
+            },
+            instruction::CloseKeeperLease {},
+        )
+    };
+
+    let intruder = Keypair::new();
+    assert_anchor_error(
+        fx.send(close(&intruder.pubkey()), &[&intruder]),
+        AnchorErrorCode::ConstraintSeeds,
+    );
+    fx.send(close(&group.pubkey()), &[&group]).unwrap();
+
+    assert!(fx.svm.get_account(&address).is_none());
+    assert_eq!(fx.svm.get_balance(&group.pubkey()), rent);
+}
+
+// ---------- platform config ----------
+
+/// Create the platform config as the program's upgrade authority, which
+/// becomes its admin
+fn create_platform_config(fx: &mut Fixture) -> Keypair {
+    let admin = Keypair::new();
+    fx.svm.airdrop(&admin.pubkey(), 1_000_000_000);
+    let program_data = fx
+        .svm
+        .set_upgrade_authority(PROGRAM_ID, Some(admin.pubkey()));
+    fx.send(create_platform_config_ix(&admin, program_data), &[&admin])
+        .unwrap();
+    admin
+}
+
+fn create_platform_config_ix(authority: &Keypair, program_data: Pubkey) -> Instruction {
+    build(
+        accounts::CreatePlatformConfig {
+            platform_config: platform_config_address().0,
+            program: PROGRAM_ID,
+            program_data,
+            authority: authority.pubkey(),
+            system_program: system_program::ID,
+        },
+        instruction::CreatePlatformConfig {
+            fee_bps: 100,
+            treasury: authority.pubkey(),
+        },
+    )
+}
+
+fn queue_platform_change_ix(
+    admin: &Keypair,
+    fee_bps: Option<u16>,
+    treasury: Option<Pubkey>,
+    new_admin: Option<Pubkey>,
+) -> Instruction {
+    build(
+        accounts::QueuePlatformConfigChange {
+            config_change: platform_config_change_address().0,
+            platform_config: platform_config_address().0,
+            admin: admin.pubkey(),
+            system_program: system_program::ID,
+        },
+        instruction::QueuePlatformConfigChange {
+            fee_bps,
+            treasury,
+            admin: new_admin,
+        },
+    )
+}
+
+fn apply_platform_change_ix(admin: &Pubkey) -> Instruction {
+    build(
+        accounts::ApplyPlatformConfigChange {
+            config_change: platform_config_change_address().0,
+            platform_config: platform_config_address().0,
+            admin: *admin,
+        },
+        instruction::ApplyPlatformConfigChange {},
+    )
+}
+
+fn platform_config(fx: &Fixture) -> PlatformConfig {
+    fx.svm
+        .get_anchor_account(&platform_config_address().0)
+        .unwrap()
+}
+
+#[test]
+fn create_platform_config_requires_the_upgrade_authority() {
+    let mut fx = Fixture::new();
+    let authority = Keypair::new();
+    let program_data = fx
+        .svm
+        .set_upgrade_authority(PROGRAM_ID, Some(authority.pubkey()));
+
+    let intruder = Keypair::new();
+    fx.svm.airdrop(&intruder.pubkey(), 1_000_000_000);
+    assert_anchor_error(
+        fx.send(
+            create_platform_config_ix(&intruder, program_data),
+            &[&intruder],
+        ),
+        AnchorErrorCode::ConstraintRaw,
+    );
+
+    // Nor with a copy of the ProgramData naming the intruder as authority
+    let forged = Pubkey::new_unique();
+    let mut account = fx.svm.get_account(&program_data).unwrap();
+    account.data[13..45].copy_from_slice(intruder.pubkey().as_ref());
+    fx.svm.set_account(forged, account);
+    assert_anchor_error(
+        fx.send(create_platform_config_ix(&intruder, forged), &[&intruder]),
+        AnchorErrorCode::ConstraintRaw,
+    );
+
+    fx.svm.airdrop(&authority.pubkey(), 1_000_000_000);
+    fx.send(
+        create_platform_config_ix(&authority, program_data),
+        &[&authority],
+    )
+    .unwrap();
+    let config = platform_config(&fx);
+    assert_eq!(config.admin, authority.pubkey());
+    assert_eq!((config.fee_bps, config.treasury), (100, authority.pubkey()));
+}
+
+#[test]
+fn platform_config_change_waits_for_the_timelock() {
+    let mut fx = Fixture::new();
+    let admin = create_platform_config(&mut fx);
+    let (treasury, successor) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let balance = fx.svm.get_balance(&admin.pubkey());
+    fx.send(
+        queue_platform_change_ix(&admin, Some(250), Some(treasury), Some(successor)),
+        &[&admin],
+    )
+    .unwrap();
+
+    let change: PlatformConfigChange = fx
+        .svm
+        .get_anchor_account(&platform_config_change_address().0)
+        .unwrap();
+    let effective_at = fx.svm.clock().unix_timestamp + PLATFORM_TIMELOCK_SECONDS;
+    assert_eq!(change.effective_at, effective_at);
+
+    assert_program_error(
+        fx.send(apply_platform_change_ix(&admin.pubkey()), &[]),
+        ErrorCode::PlatformConfigChangeTimelocked,
+    );
+    assert_eq!(platform_config(&fx).fee_bps, 100);
+
+    fx.svm.warp_to_timestamp(effective_at);
+    fx.svm.expire_blockhash();
+    fx.send(apply_platform_change_ix(&admin.pubkey()), &[])
+        .unwrap();
+
+    let config = platform_config(&fx);
+    assert_eq!((config.admin, config.treasury), (successor, treasury));
+    assert_eq!(config.fee_bps, 250);
+    assert!(fx
+        .svm
+        .get_account(&platform_config_change_address().0)
+        .is_none());
+    assert_eq!(fx.svm.get_balance(&admin.pubkey()), balance);
+}
+
+#[test]
+fn cancel_platform_config_change_requires_the_admin() {
+    let mut fx = Fixture::new();
+    let admin = create_platform_config(&mut fx);
+    fx.send(
+        queue_platform_change_ix(&admin, Some(1_000), None, None),
+        &[&admin],
+    )
+    .unwrap();
+    let cancel = |admin: &Pubkey| {
+        build(
+            accounts::CancelPlatformConfigChange {
+                config_change: platform_config_change_address().0,
+                platform_config: platform_config_address().0,
+                admin: *admin,
+            },
+            instruction::CancelPlatformConfigChange {},
+        )
+    };
+
+    let intruder = Keypair::new();
+    assert_anchor_error(
+        fx.send(cancel(&intruder.pubkey()), &[&intruder]),
+        AnchorErrorCode::ConstraintHasOne,
+    );
+    fx.send(cancel(&admin.pubkey()), &[&admin]).unwrap();
+
+    assert!(fx
+        .svm
+        .get_account(&platform_config_change_address().0)
+        .is_none());
+    assert_eq!(platform_config(&fx).fee_bps, 100);
+}
+
+#[test]
+fn queue_platform_config_change_rejects_bad_changes() {
+    let mut fx = Fixture::new();
+    let admin = create_platform_config(&mut fx);
+
+    assert_program_error(
+        fx.send(
+            queue_platform_change_ix(&admin, None, None, None),
+            &[&admin],
+        ),
+        ErrorCode::EmptyPlatformConfigChange,
+    );
+    assert_program_error(
+        fx.send(
+            queue_platform_change_ix(&admin, Some(1_001), None, None),
+            &[&admin],
+        ),
+        ErrorCode::InvalidPlatformFee,
+    );
+
+    let intruder = Keypair::new();
+    fx.svm.airdrop(&intruder.pubkey(), 1_000_000_000);
+    assert_anchor_error(
+        fx.send(
+            queue_platform_change_ix(&intruder, Some(0), None, None),
+            &[&intruder],
+        ),
+        AnchorErrorCode::ConstraintHasOne,
+    );
+}
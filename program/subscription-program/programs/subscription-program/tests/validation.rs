@@ -0,0 +1,387 @@
+//! Instruction-level account validation: single instructions run against
+//! hand-built account states that no honest client would send.
+//!
+//! These run on Mollusk, with SPL Token as its SBF build from
+//! `mollusk-svm-programs-token`. There is no SBF build of this program, so it
+//! is registered as a native builtin: Trident's `processor!` adapts its
+//! entrypoint to the program runtime, and Trident's syscall stubs route its
+//! sysvar reads and CPIs to the invoke context Mollusk runs it in.
+
+mod common;
+
+use anchor_lang::error::ErrorCode as AnchorErrorCode;
+use anchor_lang::prelude::{AccountInfo, Pubkey};
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::solana_program::program_option::COption;
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::AccountSerialize;
+use common::{AMOUNT, INTERVAL, SUBSCRIPTION_SPACE};
+use mollusk_svm::program::Builtin;
+use mollusk_svm::result::InstructionResult;
+use mollusk_svm::Mollusk;
+use spl_token::state::{Account as TokenAccount, AccountState};
+use subscription_program::{accounts, instruction, ErrorCode, Subscription, ID as PROGRAM_ID};
+use test_harness::{
+    forward_cpi_to_syscall_stubs, Account, InstructionError, DEFAULT_UNIX_TIMESTAMP,
+};
+use trident_svm::processor::solana_program_runtime::invoke_context::BuiltinFunctionWithContext;
+
+/// `processor!` takes one lifetime for the accounts slice and its items,
+/// Anchor's `entry` two
+fn process(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let accounts = Box::leak(accounts.to_vec().into_boxed_slice());
+    subscription_program::entry(program_id, accounts, data)
+}
+
+/// Mollusk with this program and SPL Token loaded
+fn mollusk() -> Mollusk {
+    trident_syscall_stubs_v2::set_stubs_v2();
+    forward_cpi_to_syscall_stubs();
+
+    // Typed up front so the closure comes out generic over the VM's lifetime
+    let entrypoint: Option<BuiltinFunctionWithContext> = trident_svm::processor!(process);
+    let mut mollusk = Mollusk::default();
+    mollusk.program_cache.add_builtin(Builtin::new(
+        PROGRAM_ID,
+        "subscription_program",
+        entrypoint.unwrap(),
+    ));
+    mollusk_svm_programs_token::token::add_program(&mut mollusk);
+    mollusk
+}
+
+struct Case {
+    mollusk: Mollusk,
+    authority: Pubkey,
+    recipient: Pubkey,
+    mint: Pubkey,
+    subscription: Pubkey,
+    state: Subscription,
+}
+
+impl Case {
+    /// A subscription that is due for its second charge
+    fn new() -> Self {
+        let mut mollusk = mollusk();
+        mollusk.sysvars.clock.unix_timestamp = DEFAULT_UNIX_TIMESTAMP + INTERVAL;
+
+        let authority = Pubkey::new_unique();
+        let recipient = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let (subscription, bump) = Pubkey::find_program_address(
+            &[b"subscription", authority.as_ref(), recipient.as_ref()],
+            &PROGRAM_ID,
+        );
+
+        let state = Subscription {
+            authority,
+            recipient,
+            user_token_account: Pubkey::new_unique(),
+            recipient_token_account: Pubkey::new_unique(),
+            token_mint: mint,
+            amount_per_period: AMOUNT,
+            interval_seconds: INTERVAL,
+            last_charge_timestamp: DEFAULT_UNIX_TIMESTAMP,
+            next_charge_at: DEFAULT_UNIX_TIMESTAMP + INTERVAL,
+            created_at: DEFAULT_UNIX_TIMESTAMP,
+            expires_at: None,
+            is_active: true,
+            total_charged: AMOUNT,
+            bump,
+            on_plan: false,
+            max_total_spend: Subscription::NO_SPEND_LIMIT,
+        };
+
+        Self {
+            mollusk,
+            authority,
+            recipient,
+            mint,
+            subscription,
+            state,
+        }
+    }
+
+    fn charge_ix(&self) -> anchor_lang::solana_program::instruction::Instruction {
+        common::build(
+            accounts::ChargeSubscription {
+                subscription: self.subscription,
+                user_token_account: self.state.user_token_account,
+                recipient_token_account: self.state.recipient_token_account,
+                token_program: spl_token::ID,
+            },
+            instruction::ChargeSubscription {},
+        )
+    }
+
+    fn update_ix(&self) -> anchor_lang::solana_program::instruction::Instruction {
+        common::build(
+            accounts::UpdateSubscription {
+                subscription: self.subscription,
+                authority: self.authority,
+            },
+            instruction::UpdateSubscription {
+                new_amount: Some(2 * AMOUNT),
+                new_interval: None,
+                new_expires_at: None,
+            },
+        )
+    }
+
+    /// The user's token account, delegated to the subscription as
+    /// `initialize_subscription` leaves it
+    fn user_token_account(&self) -> Account {
+        let mut account = token_account(&self.authority, &self.mint, 100 * AMOUNT);
+        let mut state = TokenAccount::unpack(&account.data).unwrap();
+        state.delegate = COption::Some(self.subscription);
+        state.delegated_amount = 100 * AMOUNT;
+        state.pack_into_slice(&mut account.data);
+        account
+    }
+
+    /// Accounts for `charge_ix`, with the subscription account supplied by the test
+    fn charge(&self, subscription: Account) -> InstructionResult {
+        let accounts = vec![
+            (self.subscription, subscription),
+            (self.state.user_token_account, self.user_token_account()),
+            (
+                self.state.recipient_token_account,
+                token_account(&self.recipient, &self.mint, 0),
+            ),
+            mollusk_svm_programs_token::token::keyed_account(),
+        ];
+        self.mollusk
+            .process_instruction(&self.charge_ix(), &accounts)
+    }
+}
+
+fn subscription_account(state: &Subscription, owner: Pubkey) -> Account {
+    let mut data = Vec::with_capacity(SUBSCRIPTION_SPACE);
+    state.try_serialize(&mut data).unwrap();
+    data.resize(SUBSCRIPTION_SPACE, 0);
+    Account {
+        lamports: 2_000_000,
+        data,
+        owner,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+fn token_account(owner: &Pubkey, mint: &Pubkey, amount: u64) -> Account {
+    let mut data = vec![0; TokenAccount::LEN];
+    TokenAccount {
+        mint: *mint,
+        owner: *owner,
+        amount,
+        delegate: COption::None,
+        state: AccountState::Initialized,
+        is_native: COption::None,
+        delegated_amount: 0,
+        close_authority: COption::None,
+    }
+    .pack_into_slice(&mut data);
+    Account {
+        lamports: 2_039_280,
+        data,
+        owner: spl_token::ID,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+fn custom(code: impl Into<u32>) -> Result<(), InstructionError> {
+    Err(InstructionError::Custom(code.into()))
+}
+
+fn token_amount(result: &InstructionResult, address: &Pubkey) -> u64 {
+    TokenAccount::unpack(&result.get_account(address).unwrap().data)
+        .unwrap()
+        .amount
+}
+
+#[test]
+fn valid_state_passes_validation() {
+    let case = Case::new();
+    let result = case.charge(subscription_account(&case.state, PROGRAM_ID));
+
+    assert_eq!(result.raw_result, Ok(()));
+    assert_eq!(
+        token_amount(&result, &case.state.user_token_account),
+        99 * AMOUNT
+    );
+    assert_eq!(
+        token_amount(&result, &case.state.recipient_token_account),
+        AMOUNT
+    );
+}
+
+#[test]
+fn subscription_owned_by_other_program_is_rejected() {
+    let case = Case::new();
+    let result = case.charge(subscription_account(&case.state, Pubkey::new_unique()));
+
+    assert_eq!(
+        result.raw_result,
+        custom(AnchorErrorCode::AccountOwnedByWrongProgram)
+    );
+}
+
+#[test]
+fn subscription_at_non_pda_address_is_rejected() {
+    let mut case = Case::new();
+    // Valid data and owner, but not at the address the seeds derive
+    case.subscription = Pubkey::new_unique();
+    let result = case.charge(subscription_account(&case.state, PROGRAM_ID));
+
+    assert_eq!(result.raw_result, custom(AnchorErrorCode::ConstraintSeeds));
+}
+
+#[test]
+fn forged_bump_is_rejected() {
+    let mut case = Case::new();
+    case.state.bump = case.state.bump.wrapping_sub(1);
+    let result = case.charge(subscription_account(&case.state, PROGRAM_ID));
+
+    assert_eq!(result.raw_result, custom(AnchorErrorCode::ConstraintSeeds));
+}
+
+#[test]
+fn seeds_from_state_must_match_address() {
+    let mut case = Case::new();
+    // The attacker's own PDA, claiming to be for the victim pair
+    let attacker = Pubkey::new_unique();
+    let (address, bump) = Pubkey::find_program_address(
+        &[b"subscription", attacker.as_ref(), case.recipient.as_ref()],
+        &PROGRAM_ID,
+    );
+    case.subscription = address;
+    case.state.bump = bump;
+    let result = case.charge(subscription_account(&case.state, PROGRAM_ID));
+
+    assert_eq!(result.raw_result, custom(AnchorErrorCode::ConstraintSeeds));
+}
+
+#[test]
+fn foreign_account_type_is_rejected() {
+    let case = Case::new();
+    let mut account = subscription_account(&case.state, PROGRAM_ID);
+    account.data[..8].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef, 0, 0, 0, 0]);
+    let result = case.charge(account);
+
+    assert_eq!(
+        result.raw_result,
+        custom(AnchorErrorCode::AccountDiscriminatorMismatch)
+    );
+}
+
+#[test]
+fn truncated_subscription_is_rejected() {
+    let case = Case::new();
+    let mut account = subscription_account(&case.state, PROGRAM_ID);
+    account.data.truncate(40);
+    let result = case.charge(account);
+
+    assert_eq!(
+        result.raw_result,
+        custom(AnchorErrorCode::AccountDidNotDeserialize)
+    );
+}
+
+#[test]
+fn token_account_not_owned_by_token_program_is_rejected() {
+    let case = Case::new();
+    let mut accounts = vec![
+        (
+            case.subscription,
+            subscription_account(&case.state, PROGRAM_ID),
+        ),
+        (case.state.user_token_account, case.user_token_account()),
+        (
+            case.state.recipient_token_account,
+            token_account(&case.recipient, &case.mint, 0),
+        ),
+        mollusk_svm_programs_token::token::keyed_account(),
+    ];
+    accounts[1].1.owner = Pubkey::new_unique();
+    let result = case
+        .mollusk
+        .process_instruction(&case.charge_ix(), &accounts);
+
+    assert_eq!(result.raw_result, custom(ErrorCode::InvalidTokenAccount));
+}
+
+#[test]
+fn mismatched_mint_is_left_to_the_token_program() {
+    // The program does not read the merchant's token account data; one of
+    // another mint passes validation and is rejected by the SPL transfer,
+    // which aborts the charge
+    let case = Case::new();
+    let accounts = vec![
+        (
+            case.subscription,
+            subscription_account(&case.state, PROGRAM_ID),
+        ),
+        (case.state.user_token_account, case.user_token_account()),
+        (
+            case.state.recipient_token_account,
+            token_account(&case.recipient, &Pubkey::new_unique(), 0),
+        ),
+        mollusk_svm_programs_token::token::keyed_account(),
+    ];
+    let result = case
+        .mollusk
+        .process_instruction(&case.charge_ix(), &accounts);
+
+    assert_eq!(
+        result.raw_result,
+        custom(spl_token::error::TokenError::MintMismatch as u32)
+    );
+    assert_eq!(result.resulting_accounts, accounts);
+}
+
+#[test]
+fn update_writes_only_on_success() {
+    let case = Case::new();
+    let ix = case.update_ix();
+    let accounts = vec![
+        (
+            case.subscription,
+            subscription_account(&case.state, PROGRAM_ID),
+        ),
+        (case.authority, Account::default()),
+    ];
+
+    let result = case.mollusk.process_instruction(&ix, &accounts);
+    assert_eq!(result.raw_result, Ok(()));
+    let updated: Subscription = anchor_lang::AccountDeserialize::try_deserialize(
+        &mut result
+            .get_account(&case.subscription)
+            .unwrap()
+            .data
+            .as_slice(),
+    )
+    .unwrap();
+    assert_eq!(updated.amount_per_period, 2 * AMOUNT);
+
+    let mut unsigned = ix.clone();
+    unsigned.accounts[1].is_signer = false;
+    let result = case.mollusk.process_instruction(&unsigned, &accounts);
+    assert_eq!(result.raw_result, custom(AnchorErrorCode::AccountNotSigner));
+    assert_eq!(result.resulting_accounts, accounts);
+}
+
+/// Mollusk resolves every meta against the accounts it is given before the
+/// program runs, so a missing one never reaches the program
+#[test]
+#[should_panic(expected = "An account required by the instruction was not provided")]
+fn missing_account_is_reported() {
+    let case = Case::new();
+    case.mollusk.process_instruction(
+        &case.charge_ix(),
+        &[(
+            case.subscription,
+            subscription_account(&case.state, PROGRAM_ID),
+        )],
+    );
+}
@@ -0,0 +1,269 @@
+//! The smart-wallet gate in front of `initialize_wallet_subscription`.
+//!
+//! The gate is tested directly with the values the handler reads from the
+//! runtime. The instruction itself only runs as a CPI from the LazorKit
+//! program, so the end-to-end tests call it through a stand-in that signs for
+//! its smart-wallet PDA. The harness does not build the instructions sysvar,
+//! so they write the one such a transaction would produce.
+
+mod common;
+
+use anchor_lang::prelude::{AccountInfo, Pubkey};
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::solana_program::instruction::{
+    AccountMeta, Instruction, TRANSACTION_LEVEL_STACK_HEIGHT,
+};
+use anchor_lang::solana_program::program::invoke_signed;
+use anchor_lang::solana_program::sysvar;
+use anchor_lang::system_program;
+use common::*;
+use subscription_program::{
+    accounts, check_wallet_authority, instruction, ErrorCode, Subscription, ID as PROGRAM_ID,
+    LAZORKIT_PROGRAM_ID,
+};
+use test_harness::{assert_error, Account, Keypair, Signer, TestSvm};
+
+const CPI_HEIGHT: usize = TRANSACTION_LEVEL_STACK_HEIGHT + 1;
+const WALLET_ID: u64 = 7;
+
+fn smart_wallet() -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"smart_wallet", &WALLET_ID.to_le_bytes()],
+        &LAZORKIT_PROGRAM_ID,
+    )
+    .0
+}
+
+#[test]
+fn a_smart_wallet_signed_for_by_lazorkit_passes() {
+    check_wallet_authority(&smart_wallet(), true, &LAZORKIT_PROGRAM_ID, CPI_HEIGHT).unwrap();
+}
+
+#[test]
+fn the_authority_must_be_a_pda_that_signed() {
+    assert_eq!(
+        check_wallet_authority(&smart_wallet(), false, &LAZORKIT_PROGRAM_ID, CPI_HEIGHT)
+            .unwrap_err(),
+        ErrorCode::InvalidWalletAuthority.into()
+    );
+
+    // A keypair wallet has `initialize_subscription`
+    let keypair = Keypair::new().pubkey();
+    assert_eq!(
+        check_wallet_authority(&keypair, true, &LAZORKIT_PROGRAM_ID, CPI_HEIGHT).unwrap_err(),
+        ErrorCode::InvalidWalletAuthority.into()
+    );
+}
+
+#[test]
+fn only_a_direct_cpi_from_lazorkit_counts() {
+    let wallet = smart_wallet();
+    assert_eq!(
+        check_wallet_authority(
+            &wallet,
+            true,
+            &LAZORKIT_PROGRAM_ID,
+            TRANSACTION_LEVEL_STACK_HEIGHT
+        )
+        .unwrap_err(),
+        ErrorCode::NotCalledByWallet.into()
+    );
+
+    // Another program's PDA, signed for by that program
+    assert_eq!(
+        check_wallet_authority(&wallet, true, &Pubkey::new_unique(), CPI_HEIGHT).unwrap_err(),
+        ErrorCode::NotCalledByWallet.into()
+    );
+
+    // LazorKit called a program that called us: the PDA is not the wallet's
+    assert_eq!(
+        check_wallet_authority(&wallet, true, &LAZORKIT_PROGRAM_ID, CPI_HEIGHT + 1).unwrap_err(),
+        ErrorCode::NotCalledByWallet.into()
+    );
+}
+
+/// LazorKit stand-in. Forwards its data to the program in its last account,
+/// with the other accounts, signing as smart wallet `WALLET_ID`.
+fn lazorkit<'a>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'a>],
+    data: &[u8],
+) -> ProgramResult {
+    let (target, forwarded) = accounts.split_last().unwrap();
+    let (wallet, bump) =
+        Pubkey::find_program_address(&[b"smart_wallet", &WALLET_ID.to_le_bytes()], program_id);
+    let call = Instruction {
+        program_id: *target.key,
+        accounts: forwarded
+            .iter()
+            .map(|info| AccountMeta {
+                pubkey: *info.key,
+                is_signer: info.is_signer || *info.key == wallet,
+                is_writable: info.is_writable,
+            })
+            .collect(),
+        data: data.to_vec(),
+    };
+    invoke_signed(
+        &call,
+        forwarded,
+        &[&[b"smart_wallet", &WALLET_ID.to_le_bytes(), &[bump]]],
+    )
+}
+
+/// Write the instructions sysvar of a transaction whose only instruction
+/// goes to `program_id`
+fn set_instructions_sysvar(svm: &mut TestSvm, program_id: &Pubkey) {
+    let mut data = 1u16.to_le_bytes().to_vec();
+    data.extend_from_slice(&4u16.to_le_bytes());
+    data.extend_from_slice(&0u16.to_le_bytes());
+    data.extend_from_slice(program_id.as_ref());
+    data.extend_from_slice(&0u16.to_le_bytes());
+    data.extend_from_slice(&0u16.to_le_bytes());
+    svm.set_account(
+        sysvar::instructions::ID,
+        Account {
+            lamports: 1,
+            data,
+            owner: sysvar::ID,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+}
+
+struct WalletFixture {
+    fx: Fixture,
+    wallet: Pubkey,
+    user_token_account: Pubkey,
+    subscription: Pubkey,
+}
+
+impl WalletFixture {
+    /// A funded smart wallet and the common fixture's merchant
+    fn new() -> Self {
+        let mut fx = Fixture::new();
+        fx.svm.add_program(LAZORKIT_PROGRAM_ID, lazorkit);
+        let wallet = smart_wallet();
+        let user_token_account =
+            fx.svm
+                .create_associated_token_account(&wallet, &fx.mint, STARTING_BALANCE);
+        let (subscription, _) = Pubkey::find_program_address(
+            &[b"subscription", wallet.as_ref(), fx.recipient.as_ref()],
+            &PROGRAM_ID,
+        );
+        Self {
+            fx,
+            wallet,
+            user_token_account,
+            subscription,
+        }
+    }
+
+    fn initialize_ix(&self) -> Instruction {
+        build(
+            accounts::InitializeWalletSubscription {
+                subscription: self.subscription,
+                authority: self.wallet,
+                recipient: self.fx.recipient,
+                user_token_account: self.user_token_account,
+                recipient_token_account: self.fx.recipient_token_account,
+                token_mint: self.fx.mint,
+                token_program: spl_token::ID,
+                instructions: sysvar::instructions::ID,
+                payer: self.fx.payer.pubkey(),
+                system_program: system_program::ID,
+                denylist_entry: denylist_address(&self.fx.recipient, &self.wallet).0,
+                allowlist: subscriber_allowlist_address(&self.fx.recipient).0,
+                allowlist_entry: allowlist_entry_address(&self.fx.recipient, &self.wallet).0,
+            },
+            instruction::InitializeWalletSubscription {
+                amount_per_period: AMOUNT,
+                interval_seconds: INTERVAL,
+                expires_at: None,
+            },
+        )
+    }
+
+    /// `initialize_ix` wrapped in the LazorKit instruction that signs for
+    /// the wallet
+    fn through_wallet(&self) -> Instruction {
+        let mut call = self.initialize_ix();
+        for meta in &mut call.accounts {
+            meta.is_signer &= meta.pubkey != self.wallet;
+        }
+        call.accounts
+            .push(AccountMeta::new_readonly(PROGRAM_ID, false));
+        Instruction {
+            program_id: LAZORKIT_PROGRAM_ID,
+            ..call
+        }
+    }
+}
+
+#[test]
+fn lazorkit_opens_a_subscription_for_its_smart_wallet() {
+    let mut wfx = WalletFixture::new();
+    set_instructions_sysvar(&mut wfx.fx.svm, &LAZORKIT_PROGRAM_ID);
+    let ix = wfx.through_wallet();
+    assert_success(wfx.fx.send(ix, &[]));
+
+    let subscription: Subscription = wfx.fx.svm.get_anchor_account(&wfx.subscription).unwrap();
+    assert_eq!(subscription.authority, wfx.wallet);
+    assert_eq!(subscription.user_token_account, wfx.user_token_account);
+    assert_eq!(subscription.total_charged, AMOUNT);
+    assert_eq!(
+        wfx.fx.svm.token_balance(&wfx.user_token_account),
+        STARTING_BALANCE - AMOUNT
+    );
+    assert_eq!(
+        wfx.fx.svm.token_balance(&wfx.fx.recipient_token_account),
+        AMOUNT
+    );
+    let user = wfx
+        .fx
+        .svm
+        .get_token_account(&wfx.user_token_account)
+        .unwrap();
+    assert_eq!(Option::from(user.delegate), Some(wfx.subscription));
+}
+
+#[test]
+fn the_wallet_instruction_refuses_a_top_level_call() {
+    let mut wfx = WalletFixture::new();
+    // Without LazorKit in the way the PDA cannot sign, whatever the sysvar says
+    set_instructions_sysvar(&mut wfx.fx.svm, &LAZORKIT_PROGRAM_ID);
+    let mut ix = wfx.initialize_ix();
+    ix.accounts[1].is_signer = false;
+    assert_error(wfx.fx.send(ix, &[]), ErrorCode::InvalidWalletAuthority);
+
+    // A keypair signing as the wallet is on the curve
+    let keypair = Keypair::new();
+    let mut ix = wfx.initialize_ix();
+    ix.accounts[1] = AccountMeta::new_readonly(keypair.pubkey(), true);
+    ix.accounts[0].pubkey = Pubkey::find_program_address(
+        &[
+            b"subscription",
+            keypair.pubkey().as_ref(),
+            wfx.fx.recipient.as_ref(),
+        ],
+        &PROGRAM_ID,
+    )
+    .0;
+    ix.accounts[10].pubkey = denylist_address(&wfx.fx.recipient, &keypair.pubkey()).0;
+    ix.accounts[12].pubkey = allowlist_entry_address(&wfx.fx.recipient, &keypair.pubkey()).0;
+    assert_error(
+        wfx.fx.send(ix, &[&keypair]),
+        ErrorCode::InvalidWalletAuthority,
+    );
+}
+
+#[test]
+fn lazorkit_must_be_the_top_level_program() {
+    let mut wfx = WalletFixture::new();
+    // LazorKit itself reached through another program's instruction
+    set_instructions_sysvar(&mut wfx.fx.svm, &Pubkey::new_unique());
+    let ix = wfx.through_wallet();
+    assert_error(wfx.fx.send(ix, &[]), ErrorCode::NotCalledByWallet);
+    assert!(wfx.fx.svm.get_account(&wfx.subscription).is_none());
+}
@@ -0,0 +1,636 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use spl_token::instruction as token_instruction;
+use token_utils::token_account;
+
+declare_id!("Bc6Biohd76maVGQEAENCEQ3whK4HoLnKHkJTKXFGykAn");
+
+/// Supporters ranked on the tip jar itself
+pub const LEADERBOARD_SIZE: usize = 10;
+
+#[program]
+pub mod tipping {
+    use super::*;
+
+    /// Open a tip jar paying into `creator_token_account`
+    pub fn create_tip_jar(ctx: Context<CreateTipJar>) -> Result<()> {
+        let clock = Clock::get()?;
+        let creator_account = token_account(
+            &ctx.accounts.creator_token_account,
+            ErrorCode::InvalidTokenAccount,
+        )?;
+        require_keys_eq!(
+            creator_account.owner,
+            ctx.accounts.creator.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+        require_keys_eq!(
+            creator_account.mint,
+            ctx.accounts.mint.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+
+        let tip_jar = &mut ctx.accounts.tip_jar;
+        tip_jar.creator = ctx.accounts.creator.key();
+        tip_jar.mint = ctx.accounts.mint.key();
+        tip_jar.creator_token_account = ctx.accounts.creator_token_account.key();
+        tip_jar.total_received = 0;
+        tip_jar.tip_count = 0;
+        tip_jar.supporter_count = 0;
+        tip_jar.leaderboard = Vec::new();
+        tip_jar.created_at = clock.unix_timestamp;
+        tip_jar.bump = ctx.bumps.tip_jar;
+
+        emit!(TipJarCreated {
+            tip_jar: tip_jar.key(),
+            creator: tip_jar.creator,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("Tip jar created for {}", tip_jar.creator);
+
+        Ok(())
+    }
+
+    /// Start tracking a fan's stats for one jar. The paymaster covers rent, so
+    /// this can ride along with the fan's first tip.
+    pub fn open_supporter(ctx: Context<OpenSupporter>) -> Result<()> {
+        let tip_jar = &mut ctx.accounts.tip_jar;
+        tip_jar.supporter_count = tip_jar
+            .supporter_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        let supporter = &mut ctx.accounts.supporter;
+        supporter.tip_jar = tip_jar.key();
+        supporter.fan = ctx.accounts.fan.key();
+        supporter.total_tipped = 0;
+        supporter.tip_count = 0;
+        supporter.last_tip_at = None;
+        supporter.recurring = None;
+        supporter.bump = ctx.bumps.supporter;
+
+        msg!("Supporter opened for {}", supporter.fan);
+
+        Ok(())
+    }
+
+    /// One-off tip, signed by the fan straight from their token account
+    pub fn tip(ctx: Context<Tip>, amount: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.fan_token_account.key(),
+            &ctx.accounts.creator_token_account.key(),
+            &ctx.accounts.fan.key(),
+            &[],
+            amount,
+        )?;
+
+        invoke(
+            &transfer_ix,
+            &[
+                ctx.accounts.fan_token_account.to_account_info(),
+                ctx.accounts.creator_token_account.to_account_info(),
+                ctx.accounts.fan.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        book_tip(
+            &mut ctx.accounts.tip_jar,
+            &mut ctx.accounts.supporter,
+            amount,
+            false,
+            now,
+        )
+    }
+
+    /// Tip `amount` every `interval_seconds` from `fan_token_account`. The
+    /// supporter PDA becomes the account's delegate, exactly like a
+    /// subscription; the first recurring tip is due one interval from now.
+    pub fn set_recurring_tip(
+        ctx: Context<SetRecurringTip>,
+        amount: u64,
+        interval_seconds: i64,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        RecurringTip::check_params(amount, interval_seconds)?;
+
+        let fan_account = token_account(
+            &ctx.accounts.fan_token_account,
+            ErrorCode::InvalidTokenAccount,
+        )?;
+        require_keys_eq!(
+            fan_account.owner,
+            ctx.accounts.fan.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+        require_keys_eq!(
+            fan_account.mint,
+            ctx.accounts.tip_jar.mint,
+            ErrorCode::InvalidTokenAccount
+        );
+
+        let delegate_ix = token_instruction::approve(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.fan_token_account.key(),
+            &ctx.accounts.supporter.key(),
+            &ctx.accounts.fan.key(),
+            &[],
+            u64::MAX,
+        )?;
+
+        invoke(
+            &delegate_ix,
+            &[
+                ctx.accounts.fan_token_account.to_account_info(),
+                ctx.accounts.supporter.to_account_info(),
+                ctx.accounts.fan.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        let supporter = &mut ctx.accounts.supporter;
+        supporter.recurring = Some(RecurringTip {
+            fan_token_account: ctx.accounts.fan_token_account.key(),
+            amount,
+            interval_seconds,
+            last_tip_at: now,
+        });
+
+        emit!(RecurringTipSet {
+            tip_jar: supporter.tip_jar,
+            fan: supporter.fan,
+            amount,
+            interval_seconds,
+            timestamp: now,
+        });
+
+        msg!(
+            "Recurring tip set: {} tokens every {} seconds",
+            amount,
+            interval_seconds
+        );
+
+        Ok(())
+    }
+
+    /// Stop recurring tips and revoke the delegation
+    pub fn cancel_recurring_tip(ctx: Context<CancelRecurringTip>) -> Result<()> {
+        let recurring = ctx
+            .accounts
+            .supporter
+            .recurring
+            .ok_or(ErrorCode::NoRecurringTip)?;
+        require_keys_eq!(
+            ctx.accounts.fan_token_account.key(),
+            recurring.fan_token_account,
+            ErrorCode::InvalidTokenAccount
+        );
+
+        let revoke_ix = token_instruction::revoke(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.fan_token_account.key(),
+            &ctx.accounts.fan.key(),
+            &[],
+        )?;
+
+        invoke(
+            &revoke_ix,
+            &[
+                ctx.accounts.fan_token_account.to_account_info(),
+                ctx.accounts.fan.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        let supporter = &mut ctx.accounts.supporter;
+        supporter.recurring = None;
+
+        emit!(RecurringTipCancelled {
+            tip_jar: supporter.tip_jar,
+            fan: supporter.fan,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Recurring tip cancelled");
+        msg!("Token delegation revoked");
+
+        Ok(())
+    }
+
+    /// Send a due recurring tip. Permissionless, like `charge_subscription`.
+    pub fn crank_recurring_tip(ctx: Context<CrankRecurringTip>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let supporter = &ctx.accounts.supporter;
+        let recurring = supporter.check_recurring_due(now)?;
+        require_keys_eq!(
+            ctx.accounts.fan_token_account.key(),
+            recurring.fan_token_account,
+            ErrorCode::InvalidTokenAccount
+        );
+
+        let tip_jar_key = supporter.tip_jar;
+        let fan_key = supporter.fan;
+        let seeds = &[
+            b"supporter",
+            tip_jar_key.as_ref(),
+            fan_key.as_ref(),
+            &[supporter.bump],
+        ];
+
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.fan_token_account.key(),
+            &ctx.accounts.creator_token_account.key(),
+            &supporter.key(),
+            &[],
+            recurring.amount,
+        )?;
+
+        invoke_signed(
+            &transfer_ix,
+            &[
+                ctx.accounts.fan_token_account.to_account_info(),
+                ctx.accounts.creator_token_account.to_account_info(),
+                ctx.accounts.supporter.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+            &[&seeds[..]],
+        )?;
+
+        if let Some(recurring) = ctx.accounts.supporter.recurring.as_mut() {
+            recurring.last_tip_at = now;
+        }
+
+        book_tip(
+            &mut ctx.accounts.tip_jar,
+            &mut ctx.accounts.supporter,
+            recurring.amount,
+            true,
+            now,
+        )
+    }
+}
+
+/// Update both stats accounts and the leaderboard after a tip has moved
+fn book_tip(
+    tip_jar: &mut Account<TipJar>,
+    supporter: &mut Account<Supporter>,
+    amount: u64,
+    recurring: bool,
+    now: i64,
+) -> Result<()> {
+    supporter.record_tip(amount, now)?;
+    tip_jar.record_tip(&supporter.fan, supporter.total_tipped, amount)?;
+
+    emit!(TipSent {
+        tip_jar: tip_jar.key(),
+        fan: supporter.fan,
+        amount,
+        total_tipped: supporter.total_tipped,
+        recurring,
+        timestamp: now,
+    });
+
+    msg!("Tip sent: {} tokens", amount);
+    msg!("Total from this fan: {}", supporter.total_tipped);
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CreateTipJar<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + TipJar::INIT_SPACE,
+        seeds = [b"tip_jar", creator.key().as_ref()],
+        bump
+    )]
+    pub tip_jar: Account<'info, TipJar>,
+
+    pub creator: Signer<'info>,
+
+    /// CHECK: Token mint (USDC)
+    pub mint: UncheckedAccount<'info>,
+
+    /// CHECK: Creator's token account, checked in the handler
+    pub creator_token_account: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct OpenSupporter<'info> {
+    #[account(
+        mut,
+        seeds = [b"tip_jar", tip_jar.creator.as_ref()],
+        bump = tip_jar.bump,
+    )]
+    pub tip_jar: Account<'info, TipJar>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Supporter::INIT_SPACE,
+        seeds = [b"supporter", tip_jar.key().as_ref(), fan.key().as_ref()],
+        bump
+    )]
+    pub supporter: Account<'info, Supporter>,
+
+    pub fan: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Tip<'info> {
+    #[account(
+        mut,
+        seeds = [b"tip_jar", tip_jar.creator.as_ref()],
+        bump = tip_jar.bump,
+        has_one = creator_token_account
+    )]
+    pub tip_jar: Account<'info, TipJar>,
+
+    #[account(
+        mut,
+        seeds = [b"supporter", tip_jar.key().as_ref(), fan.key().as_ref()],
+        bump = supporter.bump,
+        has_one = tip_jar,
+        has_one = fan
+    )]
+    pub supporter: Account<'info, Supporter>,
+
+    pub fan: Signer<'info>,
+
+    /// CHECK: Any token account the fan owns, debited by the token program
+    #[account(mut)]
+    pub fan_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Creator's token account
+    #[account(mut)]
+    pub creator_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetRecurringTip<'info> {
+    #[account(
+        seeds = [b"tip_jar", tip_jar.creator.as_ref()],
+        bump = tip_jar.bump,
+    )]
+    pub tip_jar: Account<'info, TipJar>,
+
+    #[account(
+        mut,
+        seeds = [b"supporter", tip_jar.key().as_ref(), fan.key().as_ref()],
+        bump = supporter.bump,
+        has_one = tip_jar,
+        has_one = fan
+    )]
+    pub supporter: Account<'info, Supporter>,
+
+    pub fan: Signer<'info>,
+
+    /// CHECK: Fan's token account, delegated to the supporter PDA
+    #[account(mut)]
+    pub fan_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelRecurringTip<'info> {
+    #[account(
+        mut,
+        seeds = [b"supporter", supporter.tip_jar.as_ref(), fan.key().as_ref()],
+        bump = supporter.bump,
+        has_one = fan
+    )]
+    pub supporter: Account<'info, Supporter>,
+
+    pub fan: Signer<'info>,
+
+    /// CHECK: The delegated token account, checked in the handler
+    #[account(mut)]
+    pub fan_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CrankRecurringTip<'info> {
+    #[account(
+        mut,
+        seeds = [b"tip_jar", tip_jar.creator.as_ref()],
+        bump = tip_jar.bump,
+        has_one = creator_token_account
+    )]
+    pub tip_jar: Account<'info, TipJar>,
+
+    #[account(
+        mut,
+        seeds = [b"supporter", tip_jar.key().as_ref(), supporter.fan.as_ref()],
+        bump = supporter.bump,
+        has_one = tip_jar
+    )]
+    pub supporter: Account<'info, Supporter>,
+
+    /// CHECK: The delegated token account, checked in the handler
+    #[account(mut)]
+    pub fan_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Creator's token account
+    #[account(mut)]
+    pub creator_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub struct LeaderboardEntry {
+    pub fan: Pubkey,
+    pub total_tipped: u64,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct TipJar {
+    pub creator: Pubkey,
+    pub mint: Pubkey,
+    pub creator_token_account: Pubkey,
+    pub total_received: u64,
+    pub tip_count: u64,
+    pub supporter_count: u32,
+    /// Top supporters by lifetime total, highest first
+    #[max_len(LEADERBOARD_SIZE)]
+    pub leaderboard: Vec<LeaderboardEntry>,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl TipJar {
+    /// Book a tip of `amount` from `fan`, whose lifetime total is now `fan_total`
+    pub fn record_tip(&mut self, fan: &Pubkey, fan_total: u64, amount: u64) -> Result<()> {
+        self.total_received = self
+            .total_received
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.tip_count = self
+            .tip_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        if let Some(entry) = self.leaderboard.iter_mut().find(|entry| entry.fan == *fan) {
+            entry.total_tipped = fan_total;
+        } else if self.leaderboard.len() < LEADERBOARD_SIZE {
+            self.leaderboard.push(LeaderboardEntry {
+                fan: *fan,
+                total_tipped: fan_total,
+            });
+        } else if let Some(last) = self.leaderboard.last_mut() {
+            // Ties keep the supporter who got there first
+            if fan_total > last.total_tipped {
+                *last = LeaderboardEntry {
+                    fan: *fan,
+                    total_tipped: fan_total,
+                };
+            }
+        }
+        // Stable: equal totals keep their order
+        self.leaderboard
+            .sort_by_key(|entry| std::cmp::Reverse(entry.total_tipped));
+        Ok(())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub struct RecurringTip {
+    pub fan_token_account: Pubkey,
+    pub amount: u64,
+    pub interval_seconds: i64,
+    pub last_tip_at: i64,
+}
+
+impl RecurringTip {
+    pub fn check_params(amount: u64, interval_seconds: i64) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        require!(interval_seconds > 0, ErrorCode::InvalidInterval);
+        Ok(())
+    }
+
+    pub fn next_tip_at(&self) -> i64 {
+        self.last_tip_at.saturating_add(self.interval_seconds)
+    }
+}
+
+/// One fan's stats for one tip jar
+#[account]
+#[derive(InitSpace)]
+pub struct Supporter {
+    pub tip_jar: Pubkey,
+    pub fan: Pubkey,
+    pub total_tipped: u64,
+    pub tip_count: u64,
+    pub last_tip_at: Option<i64>,
+    pub recurring: Option<RecurringTip>,
+    pub bump: u8,
+}
+
+impl Supporter {
+    pub fn record_tip(&mut self, amount: u64, now: i64) -> Result<()> {
+        self.total_tipped = self
+            .total_tipped
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.tip_count = self
+            .tip_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.last_tip_at = Some(now);
+        Ok(())
+    }
+
+    /// The recurring tip, if one is set up and due at `now`
+    pub fn check_recurring_due(&self, now: i64) -> Result<RecurringTip> {
+        let recurring = self.recurring.ok_or(ErrorCode::NoRecurringTip)?;
+        require!(
+            now.saturating_sub(recurring.last_tip_at) >= recurring.interval_seconds,
+            ErrorCode::IntervalNotMet
+        );
+        Ok(recurring)
+    }
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TipJarCreated {
+    pub tip_jar: Pubkey,
+    pub creator: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TipSent {
+    pub tip_jar: Pubkey,
+    pub fan: Pubkey,
+    pub amount: u64,
+    pub total_tipped: u64,
+    pub recurring: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecurringTipSet {
+    pub tip_jar: Pubkey,
+    pub fan: Pubkey,
+    pub amount: u64,
+    pub interval_seconds: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecurringTipCancelled {
+    pub tip_jar: Pubkey,
+    pub fan: Pubkey,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Amount must be greater than zero")]
+    InvalidAmount,
+    #[msg("Interval must be greater than zero")]
+    InvalidInterval,
+    #[msg("Invalid token account - wrong program, owner or mint")]
+    InvalidTokenAccount,
+    #[msg("No recurring tip is set up")]
+    NoRecurringTip,
+    #[msg("Not enough time has passed since the last recurring tip")]
+    IntervalNotMet,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
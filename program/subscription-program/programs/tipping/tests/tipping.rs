@@ -0,0 +1,451 @@
+//! Native tests for the tipping program.
+//!
+//! Stats and the leaderboard are pure methods on `TipJar` and `Supporter` and
+//! are tested directly. Instruction tests run end to end and check the
+//! creator's balance, the stats and the delegation.
+
+use anchor_lang::error::ErrorCode as AnchorErrorCode;
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, Space, ToAccountMetas};
+use test_harness::{assert_error, assert_success, Keypair, Signer, TestSvm, TransactionResult};
+use tipping::{
+    accounts, instruction, ErrorCode, RecurringTip, Supporter, TipJar, ID as PROGRAM_ID,
+    LEADERBOARD_SIZE,
+};
+
+const TIP: u64 = 500_000;
+const WEEK: i64 = 7 * 86_400;
+
+fn new_tip_jar() -> TipJar {
+    TipJar {
+        creator: Pubkey::new_unique(),
+        mint: Pubkey::new_unique(),
+        creator_token_account: Pubkey::new_unique(),
+        total_received: 0,
+        tip_count: 0,
+        supporter_count: 0,
+        leaderboard: Vec::new(),
+        created_at: 0,
+        bump: 255,
+    }
+}
+
+fn supporter(tip_jar: Pubkey, fan: Pubkey) -> Supporter {
+    Supporter {
+        tip_jar,
+        fan,
+        total_tipped: 0,
+        tip_count: 0,
+        last_tip_at: None,
+        recurring: None,
+        bump: 255,
+    }
+}
+
+#[test]
+fn leaderboard_ranks_lifetime_totals() {
+    let mut jar = new_tip_jar();
+    let fans: Vec<Pubkey> = (0..LEADERBOARD_SIZE)
+        .map(|_| Pubkey::new_unique())
+        .collect();
+    for (rank, fan) in fans.iter().enumerate() {
+        let total = (rank as u64 + 1) * TIP;
+        jar.record_tip(fan, total, total).unwrap();
+    }
+    assert_eq!(jar.leaderboard.len(), LEADERBOARD_SIZE);
+    assert_eq!(jar.leaderboard[0].fan, fans[LEADERBOARD_SIZE - 1]);
+    assert_eq!(jar.leaderboard[LEADERBOARD_SIZE - 1].fan, fans[0]);
+
+    // A newcomer only gets on by beating the lowest total
+    let newcomer = Pubkey::new_unique();
+    jar.record_tip(&newcomer, TIP, TIP).unwrap();
+    assert!(jar.leaderboard.iter().all(|entry| entry.fan != newcomer));
+    jar.record_tip(&newcomer, 2 * TIP + 1, TIP + 1).unwrap();
+    assert!(jar.leaderboard.iter().all(|entry| entry.fan != fans[0]));
+    assert_eq!(jar.leaderboard[LEADERBOARD_SIZE - 2].fan, newcomer);
+
+    // A returning fan moves up instead of appearing twice
+    jar.record_tip(&fans[1], 100 * TIP, 98 * TIP).unwrap();
+    assert_eq!(jar.leaderboard[0].fan, fans[1]);
+    assert_eq!(jar.leaderboard.len(), LEADERBOARD_SIZE);
+
+    assert_eq!(jar.tip_count, LEADERBOARD_SIZE as u64 + 3);
+}
+
+#[test]
+fn recurring_tips_wait_for_the_interval() {
+    let mut stats = supporter(Pubkey::new_unique(), Pubkey::new_unique());
+    assert_eq!(
+        stats.check_recurring_due(0).unwrap_err(),
+        ErrorCode::NoRecurringTip.into()
+    );
+
+    stats.recurring = Some(RecurringTip {
+        fan_token_account: Pubkey::new_unique(),
+        amount: TIP,
+        interval_seconds: WEEK,
+        last_tip_at: 100,
+    });
+    assert_eq!(
+        stats.check_recurring_due(100 + WEEK - 1).unwrap_err(),
+        ErrorCode::IntervalNotMet.into()
+    );
+    assert_eq!(stats.check_recurring_due(100 + WEEK).unwrap().amount, TIP);
+
+    stats.record_tip(TIP, 100 + WEEK).unwrap();
+    assert_eq!(stats.total_tipped, TIP);
+    assert_eq!(stats.last_tip_at, Some(100 + WEEK));
+}
+
+#[test]
+fn rejects_empty_recurring_tips() {
+    assert_eq!(
+        RecurringTip::check_params(0, WEEK).unwrap_err(),
+        ErrorCode::InvalidAmount.into()
+    );
+    assert_eq!(
+        RecurringTip::check_params(TIP, 0).unwrap_err(),
+        ErrorCode::InvalidInterval.into()
+    );
+}
+
+struct Fixture {
+    svm: TestSvm,
+    payer: Keypair,
+    creator: Keypair,
+    fan: Keypair,
+    tip_jar: Pubkey,
+    jar: TipJar,
+    supporter: Pubkey,
+    stats: Supporter,
+    fan_token_account: Pubkey,
+}
+
+impl Fixture {
+    /// A tip jar with one supporter who has not tipped yet
+    fn new() -> Self {
+        let (mut svm, payer) = TestSvm::for_program(PROGRAM_ID, tipping::entry);
+
+        let fan = Keypair::new();
+        let creator_keypair = Keypair::new();
+        let creator = creator_keypair.pubkey();
+
+        let mint = svm.create_mint(&Pubkey::new_unique(), 6);
+        let (tip_jar, jar_bump) =
+            Pubkey::find_program_address(&[b"tip_jar", creator.as_ref()], &PROGRAM_ID);
+        let (supporter_address, supporter_bump) = Pubkey::find_program_address(
+            &[b"supporter", tip_jar.as_ref(), fan.pubkey().as_ref()],
+            &PROGRAM_ID,
+        );
+
+        let jar = TipJar {
+            creator,
+            mint,
+            creator_token_account: svm.create_associated_token_account(&creator, &mint, 0),
+            supporter_count: 1,
+            bump: jar_bump,
+            ..new_tip_jar()
+        };
+        svm.set_anchor_account(tip_jar, &jar, 8 + TipJar::INIT_SPACE);
+
+        let stats = Supporter {
+            bump: supporter_bump,
+            ..supporter(tip_jar, fan.pubkey())
+        };
+        svm.set_anchor_account(supporter_address, &stats, 8 + Supporter::INIT_SPACE);
+        let fan_token_account =
+            svm.create_associated_token_account(&fan.pubkey(), &mint, 100 * TIP);
+
+        Self {
+            svm,
+            payer,
+            creator: creator_keypair,
+            fan,
+            tip_jar,
+            jar,
+            supporter: supporter_address,
+            stats,
+            fan_token_account,
+        }
+    }
+
+    /// Set up a recurring tip as `set_recurring_tip` would have
+    fn with_recurring(mut self) -> Self {
+        self.stats.recurring = Some(RecurringTip {
+            fan_token_account: self.fan_token_account,
+            amount: TIP,
+            interval_seconds: WEEK,
+            last_tip_at: self.svm.clock().unix_timestamp,
+        });
+        let stats = self.stats.clone();
+        self.svm
+            .set_anchor_account(self.supporter, &stats, 8 + Supporter::INIT_SPACE);
+        self.svm
+            .approve(&self.fan_token_account, &self.supporter, u64::MAX);
+        // Same instruction as before the change must not count as a duplicate
+        self.svm.expire_blockhash();
+        self
+    }
+
+    fn create_tip_jar(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CreateTipJar {
+                tip_jar: self.tip_jar,
+                creator: self.creator.pubkey(),
+                mint: self.jar.mint,
+                creator_token_account: self.jar.creator_token_account,
+                payer: self.payer.pubkey(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CreateTipJar {}.data(),
+        }
+    }
+
+    fn open_supporter(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::OpenSupporter {
+                tip_jar: self.tip_jar,
+                supporter: self.supporter,
+                fan: self.fan.pubkey(),
+                payer: self.payer.pubkey(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::OpenSupporter {}.data(),
+        }
+    }
+
+    fn set_recurring(&self, amount: u64) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::SetRecurringTip {
+                tip_jar: self.tip_jar,
+                supporter: self.supporter,
+                fan: self.fan.pubkey(),
+                fan_token_account: self.fan_token_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::SetRecurringTip {
+                amount,
+                interval_seconds: WEEK,
+            }
+            .data(),
+        }
+    }
+
+    fn tip(&self, amount: u64) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::Tip {
+                tip_jar: self.tip_jar,
+                supporter: self.supporter,
+                fan: self.fan.pubkey(),
+                fan_token_account: self.fan_token_account,
+                creator_token_account: self.jar.creator_token_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::Tip { amount }.data(),
+        }
+    }
+
+    fn crank(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CrankRecurringTip {
+                tip_jar: self.tip_jar,
+                supporter: self.supporter,
+                fan_token_account: self.fan_token_account,
+                creator_token_account: self.jar.creator_token_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CrankRecurringTip {}.data(),
+        }
+    }
+
+    fn cancel(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CancelRecurringTip {
+                supporter: self.supporter,
+                fan: self.fan.pubkey(),
+                fan_token_account: self.fan_token_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CancelRecurringTip {}.data(),
+        }
+    }
+
+    fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TransactionResult {
+        let payer = self.payer.insecure_clone();
+        self.svm.send_instructions(&[instruction], &payer, signers)
+    }
+
+    fn send_as_fan(&mut self, instruction: Instruction) -> TransactionResult {
+        let fan = self.fan.insecure_clone();
+        self.send(instruction, &[&fan])
+    }
+
+    fn jar(&self) -> TipJar {
+        self.svm.get_anchor_account(&self.tip_jar).unwrap()
+    }
+
+    fn stats(&self) -> Supporter {
+        self.svm.get_anchor_account(&self.supporter).unwrap()
+    }
+
+    fn creator_balance(&self) -> u64 {
+        self.svm.token_balance(&self.jar.creator_token_account)
+    }
+}
+
+#[test]
+fn creators_open_a_jar_paying_into_their_account() {
+    let mut fx = Fixture::new();
+    fx.svm.remove_account(&fx.tip_jar);
+    let mut elsewhere = fx.create_tip_jar();
+    elsewhere.accounts[3].pubkey = fx.fan_token_account;
+    let creator = fx.creator.insecure_clone();
+    let result = fx.send(elsewhere, &[&creator]);
+    assert_error(result, ErrorCode::InvalidTokenAccount);
+
+    let result = fx.send(fx.create_tip_jar(), &[&creator]);
+    assert_success(result);
+    let jar = fx.jar();
+    assert_eq!(jar.creator, creator.pubkey());
+    assert_eq!(jar.creator_token_account, fx.jar.creator_token_account);
+    assert_eq!(jar.supporter_count, 0);
+    assert!(jar.leaderboard.is_empty());
+}
+
+#[test]
+fn opening_a_supporter_counts_the_fan() {
+    let mut fx = Fixture::new();
+    fx.svm.remove_account(&fx.supporter);
+    let result = fx.send_as_fan(fx.open_supporter());
+    assert_success(result);
+    assert_eq!(fx.jar().supporter_count, 2);
+    let stats = fx.stats();
+    assert_eq!(stats.fan, fx.fan.pubkey());
+    assert_eq!(stats.tip_count, 0);
+    assert!(stats.recurring.is_none());
+}
+
+#[test]
+fn recurring_tips_delegate_to_the_supporter() {
+    let mut fx = Fixture::new();
+    let result = fx.send_as_fan(fx.set_recurring(0));
+    assert_error(result, ErrorCode::InvalidAmount);
+
+    let result = fx.send_as_fan(fx.set_recurring(TIP));
+    assert_success(result);
+    let account = fx.svm.get_token_account(&fx.fan_token_account).unwrap();
+    assert_eq!(account.delegate, Some(fx.supporter).into());
+    assert_eq!(account.delegated_amount, u64::MAX);
+    let recurring = fx.stats().recurring.unwrap();
+    assert_eq!(recurring.amount, TIP);
+    assert_eq!(
+        recurring.next_tip_at(),
+        fx.svm.clock().unix_timestamp + WEEK
+    );
+    // Nothing is paid until the first interval has passed
+    assert_eq!(fx.creator_balance(), 0);
+}
+
+#[test]
+fn fan_tips_gaslessly() {
+    let mut fx = Fixture::new();
+    let result = fx.send_as_fan(fx.tip(0));
+    assert_error(result, ErrorCode::InvalidAmount);
+
+    // The payer covers the fee; the fan only authorizes the transfer
+    let result = fx.send_as_fan(fx.tip(TIP));
+    assert_success(result);
+    assert_eq!(fx.creator_balance(), TIP);
+    assert_eq!(fx.svm.token_balance(&fx.fan_token_account), 99 * TIP);
+    assert_eq!(fx.stats().total_tipped, TIP);
+    let jar = fx.jar();
+    assert_eq!(jar.total_received, TIP);
+    assert_eq!(jar.leaderboard[0].fan, fx.fan.pubkey());
+}
+
+#[test]
+fn tips_go_to_the_creator_only() {
+    let mut fx = Fixture::new();
+    let attacker = fx
+        .svm
+        .create_token_account(&Pubkey::new_unique(), &fx.jar.mint, 0);
+    let mut tip = fx.tip(TIP);
+    tip.accounts[4].pubkey = attacker;
+
+    let result = fx.send_as_fan(tip);
+    assert_error(result, AnchorErrorCode::ConstraintHasOne);
+}
+
+#[test]
+fn stats_belong_to_the_signing_fan() {
+    let mut fx = Fixture::new();
+    let impostor = Keypair::new();
+    let mut tip = fx.tip(TIP);
+    tip.accounts[2].pubkey = impostor.pubkey();
+
+    let result = fx.send(tip, &[&impostor]);
+    assert_error(result, AnchorErrorCode::ConstraintSeeds);
+}
+
+#[test]
+fn crank_needs_a_due_recurring_tip() {
+    let mut fx = Fixture::new();
+    let result = fx.send(fx.crank(), &[]);
+    assert_error(result, ErrorCode::NoRecurringTip);
+
+    let mut fx = fx.with_recurring();
+    let result = fx.send(fx.crank(), &[]);
+    assert_error(result, ErrorCode::IntervalNotMet);
+
+    fx.svm.advance_time(WEEK);
+    let result = fx.send(fx.crank(), &[]);
+    assert_success(result);
+    assert_eq!(fx.creator_balance(), TIP);
+    assert_eq!(fx.stats().tip_count, 1);
+
+    // The next one waits another week
+    fx.svm.expire_blockhash();
+    let result = fx.send(fx.crank(), &[]);
+    assert_error(result, ErrorCode::IntervalNotMet);
+}
+
+#[test]
+fn crank_only_pulls_from_the_delegated_account() {
+    let mut fx = Fixture::new().with_recurring();
+    fx.svm.advance_time(WEEK);
+    let other = fx
+        .svm
+        .create_token_account(&fx.fan.pubkey(), &fx.jar.mint, 100 * TIP);
+    let mut crank = fx.crank();
+    crank.accounts[2].pubkey = other;
+
+    let result = fx.send(crank, &[]);
+    assert_error(result, ErrorCode::InvalidTokenAccount);
+}
+
+#[test]
+fn cancel_revokes_an_existing_recurring_tip() {
+    let mut fx = Fixture::new();
+    let result = fx.send_as_fan(fx.cancel());
+    assert_error(result, ErrorCode::NoRecurringTip);
+
+    let mut fx = fx.with_recurring();
+    let result = fx.send_as_fan(fx.cancel());
+    assert_success(result);
+    let account = fx.svm.get_token_account(&fx.fan_token_account).unwrap();
+    assert!(account.delegate.is_none());
+    assert!(fx.stats().recurring.is_none());
+}
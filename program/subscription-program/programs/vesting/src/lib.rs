@@ -0,0 +1,499 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use spl_token::instruction as token_instruction;
+use token_utils::token_account;
+
+declare_id!("9yLf4Hene13qCWc5DYLgPVkUpurYutjAvyNNoCxMwVmJ");
+
+#[program]
+pub mod vesting {
+    use super::*;
+
+    /// Lock `amount` in a vault owned by the grant PDA. Nothing vests before
+    /// `start_time + cliff_seconds`; after that it releases linearly until
+    /// `start_time + duration_seconds`.
+    pub fn create_grant(
+        ctx: Context<CreateGrant>,
+        grant_id: u64,
+        amount: u64,
+        start_time: Option<i64>,
+        cliff_seconds: i64,
+        duration_seconds: i64,
+        revocable: bool,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        Grant::check_params(amount, cliff_seconds, duration_seconds)?;
+
+        let grant_key = ctx.accounts.grant.key();
+        let mint_key = ctx.accounts.mint.key();
+        let vault = token_account(&ctx.accounts.vault, ErrorCode::InvalidTokenAccount)?;
+        require_keys_eq!(vault.owner, grant_key, ErrorCode::InvalidTokenAccount);
+        require_keys_eq!(vault.mint, mint_key, ErrorCode::InvalidTokenAccount);
+        let recipient_account = token_account(
+            &ctx.accounts.recipient_token_account,
+            ErrorCode::InvalidTokenAccount,
+        )?;
+        require_keys_eq!(
+            recipient_account.owner,
+            ctx.accounts.recipient.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+        require_keys_eq!(
+            recipient_account.mint,
+            mint_key,
+            ErrorCode::InvalidTokenAccount
+        );
+
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.grantor_token_account.key(),
+            &ctx.accounts.vault.key(),
+            &ctx.accounts.grantor.key(),
+            &[],
+            amount,
+        )?;
+
+        invoke(
+            &transfer_ix,
+            &[
+                ctx.accounts.grantor_token_account.to_account_info(),
+                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.grantor.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        // Backdating is allowed: grants often formalise service already given
+        let start_time = start_time.unwrap_or(clock.unix_timestamp);
+
+        let grant = &mut ctx.accounts.grant;
+        grant.grantor = ctx.accounts.grantor.key();
+        grant.recipient = ctx.accounts.recipient.key();
+        grant.mint = mint_key;
+        grant.grantor_token_account = ctx.accounts.grantor_token_account.key();
+        grant.recipient_token_account = ctx.accounts.recipient_token_account.key();
+        grant.vault = ctx.accounts.vault.key();
+        grant.grant_id = grant_id;
+        grant.total_amount = amount;
+        grant.start_time = start_time;
+        grant.cliff_time = start_time.saturating_add(cliff_seconds);
+        grant.end_time = start_time.saturating_add(duration_seconds);
+        grant.claimed = 0;
+        grant.revocable = revocable;
+        grant.created_at = clock.unix_timestamp;
+        grant.bump = ctx.bumps.grant;
+
+        emit!(GrantCreated {
+            grant: grant_key,
+            grantor: grant.grantor,
+            recipient: grant.recipient,
+            amount,
+            start_time,
+            cliff_time: grant.cliff_time,
+            end_time: grant.end_time,
+            revocable,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("Grant created: {} tokens", amount);
+        msg!(
+            "Cliff at {}, fully vested at {}",
+            grant.cliff_time,
+            grant.end_time
+        );
+
+        Ok(())
+    }
+
+    /// Pay vested tokens to the recipient. Anyone may call it, so a keeper can
+    /// claim on the recipient's behalf; funds only go to the recipient's account.
+    pub fn claim(ctx: Context<Claim>, amount: Option<u64>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let grant = &ctx.accounts.grant;
+        let amount = grant.check_claim(now, amount)?;
+
+        transfer_from_vault(
+            grant,
+            &ctx.accounts.vault,
+            &ctx.accounts.recipient_token_account,
+            &ctx.accounts.grant.to_account_info(),
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+
+        let grant = &mut ctx.accounts.grant;
+        grant.record_claim(amount)?;
+
+        emit!(TokensClaimed {
+            grant: grant.key(),
+            recipient: grant.recipient,
+            amount,
+            total_claimed: grant.claimed,
+            timestamp: now,
+        });
+
+        msg!("Claimed: {} tokens", amount);
+        msg!("Total claimed: {} of {}", grant.claimed, grant.total_amount);
+
+        Ok(())
+    }
+
+    /// Grantor ends a revocable grant: what has vested goes to the recipient,
+    /// the unvested rest back to the grantor, and the grant is closed
+    pub fn revoke(ctx: Context<Revoke>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let grant = &ctx.accounts.grant;
+        require!(grant.revocable, ErrorCode::NotRevocable);
+        let vault_balance =
+            token_account(&ctx.accounts.vault, ErrorCode::InvalidTokenAccount)?.amount;
+        let (to_recipient, to_grantor) = grant.settle(now, vault_balance);
+
+        for (destination, amount) in [
+            (&ctx.accounts.recipient_token_account, to_recipient),
+            (&ctx.accounts.grantor_token_account, to_grantor),
+        ] {
+            if amount > 0 {
+                transfer_from_vault(
+                    grant,
+                    &ctx.accounts.vault,
+                    destination,
+                    &ctx.accounts.grant.to_account_info(),
+                    &ctx.accounts.token_program,
+                    amount,
+                )?;
+            }
+        }
+
+        let close_ix = token_instruction::close_account(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.vault.key(),
+            &ctx.accounts.grantor.key(),
+            &grant.key(),
+            &[],
+        )?;
+        let grant_id = grant.grant_id.to_le_bytes();
+        let seeds = &[
+            b"grant",
+            grant.grantor.as_ref(),
+            grant.recipient.as_ref(),
+            grant_id.as_ref(),
+            &[grant.bump],
+        ];
+        invoke_signed(
+            &close_ix,
+            &[
+                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.grantor.to_account_info(),
+                ctx.accounts.grant.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+            &[&seeds[..]],
+        )?;
+
+        emit!(GrantRevoked {
+            grant: grant.key(),
+            to_recipient,
+            to_grantor,
+            timestamp: now,
+        });
+
+        msg!("Grant revoked");
+        msg!("Paid to recipient: {} tokens", to_recipient);
+        msg!("Returned to grantor: {} tokens", to_grantor);
+
+        Ok(())
+    }
+}
+
+/// Move `amount` out of the vault, signed by the grant PDA
+fn transfer_from_vault<'info>(
+    grant: &Grant,
+    vault: &UncheckedAccount<'info>,
+    destination: &UncheckedAccount<'info>,
+    grant_info: &AccountInfo<'info>,
+    token_program: &UncheckedAccount<'info>,
+    amount: u64,
+) -> Result<()> {
+    let grant_id = grant.grant_id.to_le_bytes();
+    let seeds = &[
+        b"grant",
+        grant.grantor.as_ref(),
+        grant.recipient.as_ref(),
+        grant_id.as_ref(),
+        &[grant.bump],
+    ];
+
+    let transfer_ix = token_instruction::transfer(
+        &token_program.key(),
+        &vault.key(),
+        &destination.key(),
+        &grant_info.key(),
+        &[],
+        amount,
+    )?;
+
+    invoke_signed(
+        &transfer_ix,
+        &[
+            vault.to_account_info(),
+            destination.to_account_info(),
+            grant_info.clone(),
+            token_program.to_account_info(),
+        ],
+        &[&seeds[..]],
+    )?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(grant_id: u64)]
+pub struct CreateGrant<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Grant::INIT_SPACE,
+        seeds = [
+            b"grant",
+            grantor.key().as_ref(),
+            recipient.key().as_ref(),
+            grant_id.to_le_bytes().as_ref(),
+        ],
+        bump
+    )]
+    pub grant: Account<'info, Grant>,
+
+    pub grantor: Signer<'info>,
+
+    /// CHECK: Receiver of the vested tokens
+    pub recipient: UncheckedAccount<'info>,
+
+    /// CHECK: Token mint
+    pub mint: UncheckedAccount<'info>,
+
+    /// CHECK: Grantor's token account, debited by the token program
+    #[account(mut)]
+    pub grantor_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Token account owned by the grant PDA (usually its ATA)
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: Recipient's token account, checked in the handler
+    pub recipient_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Claim<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"grant",
+            grant.grantor.as_ref(),
+            grant.recipient.as_ref(),
+            grant.grant_id.to_le_bytes().as_ref(),
+        ],
+        bump = grant.bump,
+    )]
+    pub grant: Account<'info, Grant>,
+
+    /// CHECK: Grant vault
+    #[account(
+        mut,
+        constraint = vault.key() == grant.vault
+    )]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: Recipient's token account
+    #[account(
+        mut,
+        constraint = recipient_token_account.key() == grant.recipient_token_account
+    )]
+    pub recipient_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Revoke<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"grant",
+            grant.grantor.as_ref(),
+            grant.recipient.as_ref(),
+            grant.grant_id.to_le_bytes().as_ref(),
+        ],
+        bump = grant.bump,
+        has_one = grantor,
+        close = grantor
+    )]
+    pub grant: Account<'info, Grant>,
+
+    /// Receives the grant and vault rent
+    #[account(mut)]
+    pub grantor: Signer<'info>,
+
+    /// CHECK: Grantor's token account, receives the unvested tokens
+    #[account(
+        mut,
+        constraint = grantor_token_account.key() == grant.grantor_token_account
+    )]
+    pub grantor_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Recipient's token account, receives what has vested
+    #[account(
+        mut,
+        constraint = recipient_token_account.key() == grant.recipient_token_account
+    )]
+    pub recipient_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Grant vault, closed here
+    #[account(
+        mut,
+        constraint = vault.key() == grant.vault
+    )]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Grant {
+    pub grantor: Pubkey,
+    pub recipient: Pubkey,
+    pub mint: Pubkey,
+    pub grantor_token_account: Pubkey,
+    pub recipient_token_account: Pubkey,
+    pub vault: Pubkey,
+    pub grant_id: u64,
+    pub total_amount: u64,
+    pub start_time: i64,
+    pub cliff_time: i64,
+    pub end_time: i64,
+    pub claimed: u64,
+    pub revocable: bool,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl Grant {
+    pub fn check_params(amount: u64, cliff_seconds: i64, duration_seconds: i64) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        require!(duration_seconds > 0, ErrorCode::InvalidSchedule);
+        require!(
+            (0..=duration_seconds).contains(&cliff_seconds),
+            ErrorCode::InvalidSchedule
+        );
+        Ok(())
+    }
+
+    /// Total vested by `now`, claimed or not. Linear from `start_time`, so the
+    /// cliff releases everything that accrued before it at once.
+    pub fn vested_at(&self, now: i64) -> u64 {
+        if now < self.cliff_time {
+            return 0;
+        }
+        if now >= self.end_time {
+            return self.total_amount;
+        }
+        let elapsed = now.saturating_sub(self.start_time).max(0) as u128;
+        let duration = self.end_time.saturating_sub(self.start_time) as u128;
+        (self.total_amount as u128 * elapsed / duration) as u64
+    }
+
+    pub fn claimable_at(&self, now: i64) -> u64 {
+        self.vested_at(now).saturating_sub(self.claimed)
+    }
+
+    /// The amount a claim at `now` pays out: `requested`, or everything
+    /// vested if `None`
+    pub fn check_claim(&self, now: i64, requested: Option<u64>) -> Result<u64> {
+        let available = self.claimable_at(now);
+        require!(available > 0, ErrorCode::NothingToClaim);
+        let amount = requested.unwrap_or(available);
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        require!(amount <= available, ErrorCode::ClaimExceedsVested);
+        Ok(amount)
+    }
+
+    pub fn record_claim(&mut self, amount: u64) -> Result<()> {
+        self.claimed = self
+            .claimed
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+
+    /// (owed to the recipient, returned to the grantor) if revoked at `now`,
+    /// split from the vault's actual balance
+    pub fn settle(&self, now: i64, vault_balance: u64) -> (u64, u64) {
+        let to_recipient = self.claimable_at(now).min(vault_balance);
+        (to_recipient, vault_balance - to_recipient)
+    }
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct GrantCreated {
+    pub grant: Pubkey,
+    pub grantor: Pubkey,
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub start_time: i64,
+    pub cliff_time: i64,
+    pub end_time: i64,
+    pub revocable: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokensClaimed {
+    pub grant: Pubkey,
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub total_claimed: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct GrantRevoked {
+    pub grant: Pubkey,
+    pub to_recipient: u64,
+    pub to_grantor: u64,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Amount must be greater than zero")]
+    InvalidAmount,
+    #[msg("Duration must be positive and the cliff within it")]
+    InvalidSchedule,
+    #[msg("Invalid token account - wrong program, owner or mint")]
+    InvalidTokenAccount,
+    #[msg("Nothing has vested since the last claim")]
+    NothingToClaim,
+    #[msg("Requested more than has vested")]
+    ClaimExceedsVested,
+    #[msg("Grant was created as irrevocable")]
+    NotRevocable,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
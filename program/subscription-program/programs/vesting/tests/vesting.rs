@@ -0,0 +1,326 @@
+//! Native tests for the vesting program.
+//!
+//! The schedule and revocation split are pure methods on `Grant` and are
+//! tested directly. Instruction tests run end to end and check where the
+//! tokens went.
+
+use anchor_lang::error::ErrorCode as AnchorErrorCode;
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, Space, ToAccountMetas};
+use test_harness::{
+    assert_error, assert_success, Keypair, Signer, SystemError, TestSvm, TransactionResult,
+};
+use vesting::{accounts, instruction, ErrorCode, Grant, ID as PROGRAM_ID};
+
+const TOTAL: u64 = 48_000_000;
+const GRANT_ID: u64 = 1;
+const MONTH: i64 = 30 * 86_400;
+
+/// 48 tokens over four months with a one-month cliff
+fn grant_at(start_time: i64) -> Grant {
+    Grant {
+        grantor: Pubkey::new_unique(),
+        recipient: Pubkey::new_unique(),
+        mint: Pubkey::new_unique(),
+        grantor_token_account: Pubkey::new_unique(),
+        recipient_token_account: Pubkey::new_unique(),
+        vault: Pubkey::new_unique(),
+        grant_id: GRANT_ID,
+        total_amount: TOTAL,
+        start_time,
+        cliff_time: start_time + MONTH,
+        end_time: start_time + 4 * MONTH,
+        claimed: 0,
+        revocable: true,
+        created_at: start_time,
+        bump: 255,
+    }
+}
+
+#[test]
+fn nothing_vests_before_the_cliff() {
+    let grant = grant_at(0);
+    assert_eq!(grant.vested_at(-1), 0);
+    assert_eq!(grant.vested_at(MONTH - 1), 0);
+    // The first month accrues in one step at the cliff
+    assert_eq!(grant.vested_at(MONTH), TOTAL / 4);
+    assert_eq!(grant.vested_at(2 * MONTH), TOTAL / 2);
+    assert_eq!(grant.vested_at(4 * MONTH), TOTAL);
+    assert_eq!(grant.vested_at(i64::MAX), TOTAL);
+}
+
+#[test]
+fn rejects_bad_schedules() {
+    assert_eq!(
+        Grant::check_params(0, 0, MONTH).unwrap_err(),
+        ErrorCode::InvalidAmount.into()
+    );
+    assert_eq!(
+        Grant::check_params(TOTAL, 0, 0).unwrap_err(),
+        ErrorCode::InvalidSchedule.into()
+    );
+    assert_eq!(
+        Grant::check_params(TOTAL, MONTH + 1, MONTH).unwrap_err(),
+        ErrorCode::InvalidSchedule.into()
+    );
+    assert_eq!(
+        Grant::check_params(TOTAL, -1, MONTH).unwrap_err(),
+        ErrorCode::InvalidSchedule.into()
+    );
+    Grant::check_params(TOTAL, MONTH, MONTH).unwrap();
+}
+
+#[test]
+fn claims_take_at_most_what_has_vested() {
+    let mut grant = grant_at(0);
+    assert_eq!(
+        grant.check_claim(MONTH - 1, None).unwrap_err(),
+        ErrorCode::NothingToClaim.into()
+    );
+    assert_eq!(grant.check_claim(MONTH, None).unwrap(), TOTAL / 4);
+    assert_eq!(
+        grant.check_claim(MONTH, Some(TOTAL / 4 + 1)).unwrap_err(),
+        ErrorCode::ClaimExceedsVested.into()
+    );
+
+    grant.record_claim(TOTAL / 4).unwrap();
+    assert_eq!(grant.claimable_at(MONTH), 0);
+    assert_eq!(grant.claimable_at(2 * MONTH), TOTAL / 4);
+}
+
+#[test]
+fn revocation_keeps_vested_tokens_for_the_recipient() {
+    let mut grant = grant_at(0);
+    grant.record_claim(TOTAL / 4).unwrap();
+    let vault = TOTAL - TOTAL / 4;
+
+    assert_eq!(grant.settle(MONTH - 1, TOTAL), (0, TOTAL));
+    assert_eq!(
+        grant.settle(2 * MONTH, vault),
+        (TOTAL / 4, vault - TOTAL / 4)
+    );
+    assert_eq!(grant.settle(4 * MONTH, vault), (vault, 0));
+}
+
+struct Fixture {
+    svm: TestSvm,
+    payer: Keypair,
+    grantor: Keypair,
+    grant: Pubkey,
+    state: Grant,
+}
+
+impl Fixture {
+    /// A revocable grant that started at the harness's default time
+    fn new() -> Self {
+        let (mut svm, payer) = TestSvm::for_program(PROGRAM_ID, vesting::entry);
+
+        let grantor = Keypair::new();
+        let recipient = Pubkey::new_unique();
+
+        let mint = svm.create_mint(&Pubkey::new_unique(), 6);
+        let (grant, bump) = Pubkey::find_program_address(
+            &[
+                b"grant",
+                grantor.pubkey().as_ref(),
+                recipient.as_ref(),
+                &GRANT_ID.to_le_bytes(),
+            ],
+            &PROGRAM_ID,
+        );
+        let now = svm.clock().unix_timestamp;
+        let state = Grant {
+            grantor: grantor.pubkey(),
+            recipient,
+            mint,
+            grantor_token_account: svm.create_associated_token_account(&grantor.pubkey(), &mint, 0),
+            recipient_token_account: svm.create_associated_token_account(&recipient, &mint, 0),
+            vault: svm.create_associated_token_account(&grant, &mint, TOTAL),
+            bump,
+            ..grant_at(now)
+        };
+        svm.set_anchor_account(grant, &state, 8 + Grant::INIT_SPACE);
+
+        Self {
+            svm,
+            payer,
+            grantor,
+            grant,
+            state,
+        }
+    }
+
+    fn create(&self, amount: u64) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CreateGrant {
+                grant: self.grant,
+                grantor: self.state.grantor,
+                recipient: self.state.recipient,
+                mint: self.state.mint,
+                grantor_token_account: self.state.grantor_token_account,
+                vault: self.state.vault,
+                recipient_token_account: self.state.recipient_token_account,
+                token_program: spl_token::ID,
+                payer: self.payer.pubkey(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CreateGrant {
+                grant_id: GRANT_ID,
+                amount,
+                start_time: None,
+                cliff_seconds: MONTH,
+                duration_seconds: 4 * MONTH,
+                revocable: true,
+            }
+            .data(),
+        }
+    }
+
+    fn claim(&self, amount: Option<u64>) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::Claim {
+                grant: self.grant,
+                vault: self.state.vault,
+                recipient_token_account: self.state.recipient_token_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::Claim { amount }.data(),
+        }
+    }
+
+    fn revoke(&self, grantor: &Pubkey) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::Revoke {
+                grant: self.grant,
+                grantor: *grantor,
+                grantor_token_account: self.state.grantor_token_account,
+                recipient_token_account: self.state.recipient_token_account,
+                vault: self.state.vault,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::Revoke {}.data(),
+        }
+    }
+
+    fn grant_state(&self) -> Grant {
+        self.svm.get_anchor_account(&self.grant).unwrap()
+    }
+
+    /// What the grantor and the recipient hold, and what is left in the vault
+    fn balances(&self) -> (u64, u64, u64) {
+        (
+            self.svm.token_balance(&self.state.grantor_token_account),
+            self.svm.token_balance(&self.state.recipient_token_account),
+            self.svm.token_balance(&self.state.vault),
+        )
+    }
+
+    fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TransactionResult {
+        let payer = self.payer.insecure_clone();
+        self.svm.send_instructions(&[instruction], &payer, signers)
+    }
+}
+
+#[test]
+fn grantors_fund_the_vault_when_creating_a_grant() {
+    let mut fx = Fixture::new();
+    fx.svm.remove_account(&fx.grant);
+    fx.svm
+        .transfer_tokens(&fx.state.vault, &fx.state.grantor_token_account, TOTAL);
+    let grantor = fx.grantor.insecure_clone();
+    let result = fx.send(fx.create(0), &[&grantor]);
+    assert_error(result, ErrorCode::InvalidAmount);
+
+    let mut elsewhere = fx.create(TOTAL);
+    elsewhere.accounts[5].pubkey = fx.state.grantor_token_account;
+    let result = fx.send(elsewhere, &[&grantor]);
+    assert_error(result, ErrorCode::InvalidTokenAccount);
+
+    let result = fx.send(fx.create(TOTAL), &[&grantor]);
+    assert_success(result);
+    assert_eq!(fx.balances(), (0, 0, TOTAL));
+    let state = fx.grant_state();
+    assert_eq!(state.recipient, fx.state.recipient);
+    assert_eq!(state.total_amount, TOTAL);
+    assert_eq!(state.cliff_time, fx.state.cliff_time);
+    assert_eq!(state.end_time, fx.state.end_time);
+    assert!(state.revocable);
+
+    fx.svm.expire_blockhash();
+    let result = fx.send(fx.create(TOTAL), &[&grantor]);
+    assert_error(result, SystemError::AccountAlreadyInUse as u32);
+}
+
+#[test]
+fn claim_waits_for_the_cliff() {
+    let mut fx = Fixture::new();
+    fx.svm.advance_time(MONTH - 1);
+    let result = fx.send(fx.claim(None), &[]);
+    assert_error(result, ErrorCode::NothingToClaim);
+}
+
+#[test]
+fn keeper_claims_for_the_recipient() {
+    let mut fx = Fixture::new();
+    fx.svm.advance_time(MONTH);
+    // Only the payer signs: a keeper can claim without the recipient's wallet
+    let result = fx.send(fx.claim(None), &[]);
+    assert_success(result);
+    assert_eq!(fx.balances(), (0, TOTAL / 4, TOTAL - TOTAL / 4));
+    assert_eq!(fx.grant_state().claimed, TOTAL / 4);
+
+    fx.svm.expire_blockhash();
+    let result = fx.send(fx.claim(None), &[]);
+    assert_error(result, ErrorCode::NothingToClaim);
+}
+
+#[test]
+fn claim_rejects_another_destination() {
+    let mut fx = Fixture::new();
+    fx.svm.advance_time(MONTH);
+    let attacker = fx
+        .svm
+        .create_token_account(&Pubkey::new_unique(), &fx.state.mint, 0);
+    let mut claim = fx.claim(None);
+    claim.accounts[2].pubkey = attacker;
+
+    let result = fx.send(claim, &[]);
+    assert_error(result, AnchorErrorCode::ConstraintRaw);
+}
+
+#[test]
+fn only_the_grantor_revokes() {
+    let mut fx = Fixture::new();
+    let intruder = Keypair::new();
+    let result = fx.send(fx.revoke(&intruder.pubkey()), &[&intruder]);
+    assert_error(result, AnchorErrorCode::ConstraintHasOne);
+
+    fx.svm.advance_time(2 * MONTH);
+    let (to_recipient, to_grantor) = fx.state.settle(fx.svm.clock().unix_timestamp, TOTAL);
+    assert_eq!((to_recipient, to_grantor), (TOTAL / 2, TOTAL / 2));
+    let grantor = fx.grantor.insecure_clone();
+    let result = fx.send(fx.revoke(&grantor.pubkey()), &[&grantor]);
+    assert_success(result);
+    assert_eq!(fx.balances(), (to_grantor, to_recipient, 0));
+    assert!(fx.svm.get_account(&fx.state.vault).is_none());
+}
+
+#[test]
+fn irrevocable_grants_cannot_be_revoked() {
+    let mut fx = Fixture::new();
+    fx.state.revocable = false;
+    let state = fx.state.clone();
+    fx.svm
+        .set_anchor_account(fx.grant, &state, 8 + Grant::INIT_SPACE);
+
+    let grantor = fx.grantor.insecure_clone();
+    let result = fx.send(fx.revoke(&grantor.pubkey()), &[&grantor]);
+    assert_error(result, ErrorCode::NotRevocable);
+}
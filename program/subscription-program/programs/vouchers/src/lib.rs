@@ -0,0 +1,459 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use solana_sha256_hasher::hash;
+use spl_token::instruction as token_instruction;
+use token_utils::token_account;
+
+declare_id!("GxEq1oZDLRbSuYGXhTc17mcDnxft7LYxtVX9JHqtmQbA");
+
+#[program]
+pub mod vouchers {
+    use super::*;
+
+    /// Issuer prepays `amount` into a vault owned by the voucher PDA. The
+    /// voucher is claimed by whoever presents the preimage of `secret_hash`,
+    /// usually carried in a gift link.
+    pub fn create_voucher(
+        ctx: Context<CreateVoucher>,
+        secret_hash: [u8; 32],
+        amount: u64,
+        expires_at: i64,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        Voucher::check_params(amount, expires_at, clock.unix_timestamp)?;
+
+        let voucher_key = ctx.accounts.voucher.key();
+        let mint_key = ctx.accounts.mint.key();
+        let vault = token_account(&ctx.accounts.vault, ErrorCode::InvalidTokenAccount)?;
+        require_keys_eq!(vault.owner, voucher_key, ErrorCode::InvalidTokenAccount);
+        require_keys_eq!(vault.mint, mint_key, ErrorCode::InvalidTokenAccount);
+        let issuer_account = token_account(
+            &ctx.accounts.issuer_token_account,
+            ErrorCode::InvalidTokenAccount,
+        )?;
+        require_keys_eq!(
+            issuer_account.owner,
+            ctx.accounts.issuer.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+        require_keys_eq!(
+            issuer_account.mint,
+            mint_key,
+            ErrorCode::InvalidTokenAccount
+        );
+
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.issuer_token_account.key(),
+            &ctx.accounts.vault.key(),
+            &ctx.accounts.issuer.key(),
+            &[],
+            amount,
+        )?;
+
+        invoke(
+            &transfer_ix,
+            &[
+                ctx.accounts.issuer_token_account.to_account_info(),
+                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.issuer.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+        )?;
+
+        let voucher = &mut ctx.accounts.voucher;
+        voucher.issuer = ctx.accounts.issuer.key();
+        voucher.mint = mint_key;
+        voucher.issuer_token_account = ctx.accounts.issuer_token_account.key();
+        voucher.vault = ctx.accounts.vault.key();
+        voucher.secret_hash = secret_hash;
+        voucher.holder = None;
+        voucher.face_value = amount;
+        voucher.balance = amount;
+        voucher.expires_at = expires_at;
+        voucher.created_at = clock.unix_timestamp;
+        voucher.bump = ctx.bumps.voucher;
+
+        emit!(VoucherCreated {
+            voucher: voucher_key,
+            issuer: voucher.issuer,
+            amount,
+            expires_at,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("Voucher created: {} tokens", amount);
+        msg!("Expires at {}", expires_at);
+
+        Ok(())
+    }
+
+    /// Bind the voucher to the signer. Only the first valid claim counts.
+    pub fn claim_voucher(ctx: Context<ClaimVoucher>, secret: [u8; 32]) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let holder = ctx.accounts.holder.key();
+        let voucher = &mut ctx.accounts.voucher;
+        voucher.claim(&secret, &holder, now)?;
+
+        emit!(VoucherClaimed {
+            voucher: voucher.key(),
+            holder,
+            timestamp: now,
+        });
+
+        msg!("Voucher claimed by {}", holder);
+
+        Ok(())
+    }
+
+    /// Spend part or all of the balance, paying any token account of the
+    /// voucher's mint: a merchant for a one-off purchase, or the holder's own
+    /// account to fund a subscription.
+    pub fn redeem(ctx: Context<Redeem>, amount: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let voucher = &ctx.accounts.voucher;
+        voucher.check_redeemable(amount, now)?;
+
+        let issuer_key = voucher.issuer;
+        let seeds = &[
+            b"voucher",
+            issuer_key.as_ref(),
+            voucher.secret_hash.as_ref(),
+            &[voucher.bump],
+        ];
+
+        let transfer_ix = token_instruction::transfer(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.vault.key(),
+            &ctx.accounts.destination.key(),
+            &voucher.key(),
+            &[],
+            amount,
+        )?;
+
+        invoke_signed(
+            &transfer_ix,
+            &[
+                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.destination.to_account_info(),
+                ctx.accounts.voucher.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+            &[&seeds[..]],
+        )?;
+
+        let voucher = &mut ctx.accounts.voucher;
+        voucher.balance -= amount;
+
+        emit!(VoucherRedeemed {
+            voucher: voucher.key(),
+            holder: ctx.accounts.holder.key(),
+            destination: ctx.accounts.destination.key(),
+            amount,
+            remaining: voucher.balance,
+            timestamp: now,
+        });
+
+        msg!("Voucher redeemed: {} tokens", amount);
+        msg!("Remaining balance: {}", voucher.balance);
+
+        Ok(())
+    }
+
+    /// Return what is left to the issuer and close the voucher. Permissionless
+    /// once the voucher has expired or been spent in full, so a keeper can
+    /// sweep in bulk.
+    pub fn sweep(ctx: Context<Sweep>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let voucher = &ctx.accounts.voucher;
+        voucher.check_sweepable(now)?;
+
+        let issuer_key = voucher.issuer;
+        let seeds = &[
+            b"voucher",
+            issuer_key.as_ref(),
+            voucher.secret_hash.as_ref(),
+            &[voucher.bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+        let voucher_info = ctx.accounts.voucher.to_account_info();
+        let returned = token_account(&ctx.accounts.vault, ErrorCode::InvalidTokenAccount)?.amount;
+
+        if returned > 0 {
+            let transfer_ix = token_instruction::transfer(
+                &ctx.accounts.token_program.key(),
+                &ctx.accounts.vault.key(),
+                &ctx.accounts.issuer_token_account.key(),
+                &voucher_info.key(),
+                &[],
+                returned,
+            )?;
+            invoke_signed(
+                &transfer_ix,
+                &[
+                    ctx.accounts.vault.to_account_info(),
+                    ctx.accounts.issuer_token_account.to_account_info(),
+                    voucher_info.clone(),
+                    ctx.accounts.token_program.to_account_info(),
+                ],
+                signer_seeds,
+            )?;
+        }
+
+        let close_ix = token_instruction::close_account(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.vault.key(),
+            &ctx.accounts.issuer.key(),
+            &voucher_info.key(),
+            &[],
+        )?;
+        invoke_signed(
+            &close_ix,
+            &[
+                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.issuer.to_account_info(),
+                voucher_info.clone(),
+                ctx.accounts.token_program.to_account_info(),
+            ],
+            signer_seeds,
+        )?;
+
+        emit!(VoucherSwept {
+            voucher: voucher_info.key(),
+            returned,
+            timestamp: now,
+        });
+
+        msg!("Voucher swept");
+        msg!("Returned to issuer: {} tokens", returned);
+
+        Ok(())
+    }
+}
+
+/// What `create_voucher` stores for a claim secret: its SHA-256
+pub fn secret_hash(secret: &[u8; 32]) -> [u8; 32] {
+    hash(secret).to_bytes()
+}
+
+#[derive(Accounts)]
+#[instruction(secret_hash: [u8; 32])]
+pub struct CreateVoucher<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Voucher::INIT_SPACE,
+        seeds = [b"voucher", issuer.key().as_ref(), secret_hash.as_ref()],
+        bump
+    )]
+    pub voucher: Account<'info, Voucher>,
+
+    pub issuer: Signer<'info>,
+
+    /// CHECK: Token mint (USDC)
+    pub mint: UncheckedAccount<'info>,
+
+    /// CHECK: Issuer's token account, checked in the handler; sweeps return here
+    #[account(mut)]
+    pub issuer_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Vault owned by the voucher PDA, checked in the handler
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimVoucher<'info> {
+    #[account(
+        mut,
+        seeds = [b"voucher", voucher.issuer.as_ref(), voucher.secret_hash.as_ref()],
+        bump = voucher.bump,
+    )]
+    pub voucher: Account<'info, Voucher>,
+
+    pub holder: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Redeem<'info> {
+    #[account(
+        mut,
+        seeds = [b"voucher", voucher.issuer.as_ref(), voucher.secret_hash.as_ref()],
+        bump = voucher.bump,
+        constraint = voucher.holder == Some(holder.key()) @ ErrorCode::NotHolder
+    )]
+    pub voucher: Account<'info, Voucher>,
+
+    pub holder: Signer<'info>,
+
+    /// CHECK: Voucher vault
+    #[account(
+        mut,
+        constraint = vault.key() == voucher.vault
+    )]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: Any token account of the voucher's mint; the token program checks the mint
+    #[account(mut)]
+    pub destination: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Sweep<'info> {
+    #[account(
+        mut,
+        seeds = [b"voucher", voucher.issuer.as_ref(), voucher.secret_hash.as_ref()],
+        bump = voucher.bump,
+        has_one = issuer,
+        has_one = issuer_token_account,
+        has_one = vault,
+        close = issuer
+    )]
+    pub voucher: Account<'info, Voucher>,
+
+    /// CHECK: Receives the voucher and vault rent
+    #[account(mut)]
+    pub issuer: UncheckedAccount<'info>,
+
+    /// CHECK: Issuer's token account
+    #[account(mut)]
+    pub issuer_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Voucher vault, closed here
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::ID)]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Voucher {
+    pub issuer: Pubkey,
+    pub mint: Pubkey,
+    pub issuer_token_account: Pubkey,
+    pub vault: Pubkey,
+    /// SHA-256 of the claim secret
+    pub secret_hash: [u8; 32],
+    /// Set by the first claim
+    pub holder: Option<Pubkey>,
+    pub face_value: u64,
+    pub balance: u64,
+    pub expires_at: i64,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl Voucher {
+    pub fn check_params(amount: u64, expires_at: i64, now: i64) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        require!(expires_at > now, ErrorCode::InvalidExpiry);
+        Ok(())
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        now >= self.expires_at
+    }
+
+    /// Record `holder` as the owner if `secret` hashes to `secret_hash`
+    pub fn claim(&mut self, secret: &[u8; 32], holder: &Pubkey, now: i64) -> Result<()> {
+        require!(!self.is_expired(now), ErrorCode::VoucherExpired);
+        require!(self.holder.is_none(), ErrorCode::AlreadyClaimed);
+        require!(
+            secret_hash(secret) == self.secret_hash,
+            ErrorCode::InvalidSecret
+        );
+        self.holder = Some(*holder);
+        Ok(())
+    }
+
+    pub fn check_redeemable(&self, amount: u64, now: i64) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        require!(!self.is_expired(now), ErrorCode::VoucherExpired);
+        require!(amount <= self.balance, ErrorCode::InsufficientBalance);
+        Ok(())
+    }
+
+    /// Expired, or nothing left to spend
+    pub fn check_sweepable(&self, now: i64) -> Result<()> {
+        require!(
+            self.is_expired(now) || self.balance == 0,
+            ErrorCode::VoucherStillValid
+        );
+        Ok(())
+    }
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoucherCreated {
+    pub voucher: Pubkey,
+    pub issuer: Pubkey,
+    pub amount: u64,
+    pub expires_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoucherClaimed {
+    pub voucher: Pubkey,
+    pub holder: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoucherRedeemed {
+    pub voucher: Pubkey,
+    pub holder: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
+    pub remaining: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoucherSwept {
+    pub voucher: Pubkey,
+    pub returned: u64,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Amount must be greater than zero")]
+    InvalidAmount,
+    #[msg("Expiry must be in the future")]
+    InvalidExpiry,
+    #[msg("Invalid token account - wrong program, owner or mint")]
+    InvalidTokenAccount,
+    #[msg("Secret does not match this voucher")]
+    InvalidSecret,
+    #[msg("Voucher has already been claimed")]
+    AlreadyClaimed,
+    #[msg("Only the voucher's holder can redeem it")]
+    NotHolder,
+    #[msg("Voucher has expired")]
+    VoucherExpired,
+    #[msg("Voucher balance is too low")]
+    InsufficientBalance,
+    #[msg("Voucher is unexpired and still has a balance")]
+    VoucherStillValid,
+}
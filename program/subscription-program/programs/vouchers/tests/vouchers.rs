@@ -0,0 +1,352 @@
+//! Native tests for the vouchers program.
+//!
+//! Claiming, redemption limits and the sweep rule are pure methods on
+//! `Voucher` and are tested directly. Instruction tests run end to end and
+//! check where the tokens went.
+
+use anchor_lang::error::ErrorCode as AnchorErrorCode;
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, Space, ToAccountMetas};
+use test_harness::{
+    assert_error, assert_success, Keypair, Signer, SystemError, TestSvm, TransactionResult,
+};
+use vouchers::{accounts, instruction, secret_hash, ErrorCode, Voucher, ID as PROGRAM_ID};
+
+const FACE_VALUE: u64 = 25_000_000;
+const DAY: i64 = 86_400;
+const SECRET: [u8; 32] = [42; 32];
+
+fn voucher(expires_at: i64) -> Voucher {
+    Voucher {
+        issuer: Pubkey::new_unique(),
+        mint: Pubkey::new_unique(),
+        issuer_token_account: Pubkey::new_unique(),
+        vault: Pubkey::new_unique(),
+        secret_hash: secret_hash(&SECRET),
+        holder: None,
+        face_value: FACE_VALUE,
+        balance: FACE_VALUE,
+        expires_at,
+        created_at: 0,
+        bump: 255,
+    }
+}
+
+#[test]
+fn first_claim_with_the_secret_wins() {
+    let mut gift = voucher(DAY);
+    let holder = Pubkey::new_unique();
+
+    assert_eq!(
+        gift.claim(&[0; 32], &holder, 0).unwrap_err(),
+        ErrorCode::InvalidSecret.into()
+    );
+    gift.claim(&SECRET, &holder, 0).unwrap();
+    assert_eq!(gift.holder, Some(holder));
+    assert_eq!(
+        gift.claim(&SECRET, &Pubkey::new_unique(), 0).unwrap_err(),
+        ErrorCode::AlreadyClaimed.into()
+    );
+
+    let mut lapsed = voucher(DAY);
+    assert_eq!(
+        lapsed.claim(&SECRET, &holder, DAY).unwrap_err(),
+        ErrorCode::VoucherExpired.into()
+    );
+}
+
+#[test]
+fn redemptions_stay_within_the_balance() {
+    let mut gift = voucher(DAY);
+    gift.check_redeemable(FACE_VALUE / 2, 0).unwrap();
+    gift.balance = FACE_VALUE / 2;
+    gift.check_redeemable(FACE_VALUE / 2, 0).unwrap();
+    assert_eq!(
+        gift.check_redeemable(FACE_VALUE / 2 + 1, 0).unwrap_err(),
+        ErrorCode::InsufficientBalance.into()
+    );
+    assert_eq!(
+        gift.check_redeemable(0, 0).unwrap_err(),
+        ErrorCode::InvalidAmount.into()
+    );
+    assert_eq!(
+        gift.check_redeemable(1, DAY).unwrap_err(),
+        ErrorCode::VoucherExpired.into()
+    );
+}
+
+#[test]
+fn sweeps_wait_for_expiry_or_an_empty_balance() {
+    let mut gift = voucher(DAY);
+    assert_eq!(
+        gift.check_sweepable(DAY - 1).unwrap_err(),
+        ErrorCode::VoucherStillValid.into()
+    );
+    gift.check_sweepable(DAY).unwrap();
+
+    gift.balance = 0;
+    gift.check_sweepable(0).unwrap();
+}
+
+#[test]
+fn rejects_empty_or_expired_vouchers() {
+    assert_eq!(
+        Voucher::check_params(0, DAY, 0).unwrap_err(),
+        ErrorCode::InvalidAmount.into()
+    );
+    assert_eq!(
+        Voucher::check_params(FACE_VALUE, 0, 0).unwrap_err(),
+        ErrorCode::InvalidExpiry.into()
+    );
+}
+
+struct Fixture {
+    svm: TestSvm,
+    payer: Keypair,
+    issuer: Keypair,
+    holder: Keypair,
+    voucher: Pubkey,
+    gift: Voucher,
+    /// Where the holder spends the voucher
+    merchant: Pubkey,
+}
+
+impl Fixture {
+    /// A funded, unclaimed voucher expiring in a week
+    fn new() -> Self {
+        let (mut svm, payer) = TestSvm::for_program(PROGRAM_ID, vouchers::entry);
+
+        let issuer = Keypair::new();
+        let holder = Keypair::new();
+
+        let mint = svm.create_mint(&Pubkey::new_unique(), 6);
+        let (address, bump) = Pubkey::find_program_address(
+            &[
+                b"voucher",
+                issuer.pubkey().as_ref(),
+                secret_hash(&SECRET).as_ref(),
+            ],
+            &PROGRAM_ID,
+        );
+        let now = svm.clock().unix_timestamp;
+        let gift = Voucher {
+            issuer: issuer.pubkey(),
+            mint,
+            issuer_token_account: svm.create_associated_token_account(&issuer.pubkey(), &mint, 0),
+            vault: svm.create_associated_token_account(&address, &mint, FACE_VALUE),
+            created_at: now,
+            bump,
+            ..voucher(now + 7 * DAY)
+        };
+        svm.set_anchor_account(address, &gift, 8 + Voucher::INIT_SPACE);
+        let merchant = svm.create_token_account(&Pubkey::new_unique(), &mint, 0);
+
+        Self {
+            svm,
+            payer,
+            issuer,
+            holder,
+            voucher: address,
+            gift,
+            merchant,
+        }
+    }
+
+    /// Claim the voucher for the fixture's holder, as `claim_voucher` would
+    fn claimed(mut self) -> Self {
+        self.gift.holder = Some(self.holder.pubkey());
+        let gift = self.gift.clone();
+        self.svm
+            .set_anchor_account(self.voucher, &gift, 8 + Voucher::INIT_SPACE);
+        self
+    }
+
+    fn create(&self, amount: u64) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CreateVoucher {
+                voucher: self.voucher,
+                issuer: self.gift.issuer,
+                mint: self.gift.mint,
+                issuer_token_account: self.gift.issuer_token_account,
+                vault: self.gift.vault,
+                payer: self.payer.pubkey(),
+                token_program: spl_token::ID,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CreateVoucher {
+                secret_hash: self.gift.secret_hash,
+                amount,
+                expires_at: self.gift.expires_at,
+            }
+            .data(),
+        }
+    }
+
+    fn claim(&self, secret: [u8; 32]) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::ClaimVoucher {
+                voucher: self.voucher,
+                holder: self.holder.pubkey(),
+            }
+            .to_account_metas(None),
+            data: instruction::ClaimVoucher { secret }.data(),
+        }
+    }
+
+    fn redeem(&self, amount: u64) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::Redeem {
+                voucher: self.voucher,
+                holder: self.holder.pubkey(),
+                vault: self.gift.vault,
+                destination: self.merchant,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::Redeem { amount }.data(),
+        }
+    }
+
+    fn sweep(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::Sweep {
+                voucher: self.voucher,
+                issuer: self.gift.issuer,
+                issuer_token_account: self.gift.issuer_token_account,
+                vault: self.gift.vault,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::Sweep {}.data(),
+        }
+    }
+
+    fn gift_state(&self) -> Voucher {
+        self.svm.get_anchor_account(&self.voucher).unwrap()
+    }
+
+    /// What the issuer and the merchant hold, and what is left in the vault
+    fn balances(&self) -> (u64, u64, u64) {
+        (
+            self.svm.token_balance(&self.gift.issuer_token_account),
+            self.svm.token_balance(&self.merchant),
+            self.svm.token_balance(&self.gift.vault),
+        )
+    }
+
+    fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TransactionResult {
+        let payer = self.payer.insecure_clone();
+        self.svm.send_instructions(&[instruction], &payer, signers)
+    }
+
+    fn send_as_holder(&mut self, instruction: Instruction) -> TransactionResult {
+        let holder = self.holder.insecure_clone();
+        self.send(instruction, &[&holder])
+    }
+}
+
+#[test]
+fn issuers_fund_the_vault_when_creating_a_voucher() {
+    let mut fx = Fixture::new();
+    fx.svm.remove_account(&fx.voucher);
+    fx.svm
+        .transfer_tokens(&fx.gift.vault, &fx.gift.issuer_token_account, FACE_VALUE);
+    let issuer = fx.issuer.insecure_clone();
+    let result = fx.send(fx.create(0), &[&issuer]);
+    assert_error(result, ErrorCode::InvalidAmount);
+
+    let mut elsewhere = fx.create(FACE_VALUE);
+    elsewhere.accounts[4].pubkey = fx.merchant;
+    let result = fx.send(elsewhere, &[&issuer]);
+    assert_error(result, ErrorCode::InvalidTokenAccount);
+
+    let result = fx.send(fx.create(FACE_VALUE), &[&issuer]);
+    assert_success(result);
+    assert_eq!(fx.balances(), (0, 0, FACE_VALUE));
+    let gift = fx.gift_state();
+    assert_eq!(gift.secret_hash, secret_hash(&SECRET));
+    assert_eq!(gift.holder, None);
+    assert_eq!(gift.balance, FACE_VALUE);
+
+    fx.svm.expire_blockhash();
+    let result = fx.send(fx.create(FACE_VALUE), &[&issuer]);
+    assert_error(result, SystemError::AccountAlreadyInUse as u32);
+}
+
+#[test]
+fn gift_link_claims_the_voucher() {
+    let mut fx = Fixture::new();
+    let result = fx.send_as_holder(fx.claim([0; 32]));
+    assert_error(result, ErrorCode::InvalidSecret);
+
+    let result = fx.send_as_holder(fx.claim(SECRET));
+    assert_success(result);
+    assert_eq!(fx.gift_state().holder, Some(fx.holder.pubkey()));
+
+    let latecomer = Keypair::new();
+    let mut claim = fx.claim(SECRET);
+    claim.accounts[1].pubkey = latecomer.pubkey();
+    let result = fx.send(claim, &[&latecomer]);
+    assert_error(result, ErrorCode::AlreadyClaimed);
+}
+
+#[test]
+fn only_the_holder_redeems() {
+    let mut fx = Fixture::new();
+    let redeem = fx.redeem(FACE_VALUE);
+    let result = fx.send_as_holder(redeem);
+    assert_error(result, ErrorCode::NotHolder);
+
+    let mut fx = fx.claimed();
+    let redeem = fx.redeem(FACE_VALUE + 1);
+    let result = fx.send_as_holder(redeem);
+    assert_error(result, ErrorCode::InsufficientBalance);
+
+    // Partial redemption to a merchant
+    let redeem = fx.redeem(FACE_VALUE / 3);
+    let result = fx.send_as_holder(redeem);
+    assert_success(result);
+    let left = FACE_VALUE - FACE_VALUE / 3;
+    assert_eq!(fx.balances(), (0, FACE_VALUE / 3, left));
+    assert_eq!(fx.gift_state().balance, left);
+}
+
+#[test]
+fn redemptions_come_from_the_voucher_vault() {
+    let mut fx = Fixture::new().claimed();
+    let other_vault = fx
+        .svm
+        .create_token_account(&Pubkey::new_unique(), &fx.gift.mint, FACE_VALUE);
+    let mut redeem = fx.redeem(FACE_VALUE);
+    redeem.accounts[2].pubkey = other_vault;
+
+    let result = fx.send_as_holder(redeem);
+    assert_error(result, AnchorErrorCode::ConstraintRaw);
+}
+
+#[test]
+fn anyone_sweeps_an_expired_voucher_back_to_the_issuer() {
+    let mut fx = Fixture::new().claimed();
+    let result = fx.send(fx.sweep(), &[]);
+    assert_error(result, ErrorCode::VoucherStillValid);
+
+    fx.svm.advance_time(7 * DAY);
+    let mut sweep = fx.sweep();
+    sweep.accounts[2].pubkey = fx
+        .svm
+        .create_token_account(&Pubkey::new_unique(), &fx.gift.mint, 0);
+    let result = fx.send(sweep, &[]);
+    assert_error(result, AnchorErrorCode::ConstraintHasOne);
+
+    let result = fx.send(fx.sweep(), &[]);
+    assert_success(result);
+    assert_eq!(fx.balances(), (FACE_VALUE, 0, 0));
+    assert!(fx.svm.get_account(&fx.gift.vault).is_none());
+    assert!(fx.svm.get_account(&fx.voucher).is_none());
+}
@@ -0,0 +1,19 @@
+//! SPL Token helpers shared by the recipe programs.
+//!
+//! The programs take token accounts as `UncheckedAccount`s and decode them in
+//! the handler, as the subscription program does, so each can reject a bad
+//! account with its own error code rather than Anchor's.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program_pack::Pack;
+
+pub use spl_token::state::Account as TokenAccount;
+
+/// Decode an SPL Token account, failing with `invalid` for anything the
+/// token program does not own or that is not an initialized token account
+pub fn token_account(info: &AccountInfo, invalid: impl Into<Error>) -> Result<TokenAccount> {
+    if *info.owner != spl_token::ID {
+        return Err(invalid.into());
+    }
+    TokenAccount::unpack(&info.try_borrow_data()?).map_err(|_| invalid.into())
+}
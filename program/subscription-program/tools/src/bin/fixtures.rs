@@ -0,0 +1,314 @@
+//! Generate a realistic ledger for demoing keepers and dashboards.
+//!
+//! Builds merchants, subscribers, a USDC-like mint, ATAs and subscriptions in
+//! every state a keeper has to handle (due, not yet due, expired, inactive,
+//! underfunded, revoked, frozen), then writes them as account JSON files that
+//! a local validator loads at genesis:
+//!
+//! ```bash
+//! cargo run -p subscription-tools --bin fixtures -- --subscriptions 50
+//! solana-test-validator --reset \
+//!     --account-dir fixtures/accounts \
+//!     --bpf-program 3kZ9Fdzadk8NXwjHaSabKrXBsU1y226BgXJdHZ78Qx4v target/deploy/subscription_program.so
+//! ```
+//!
+//! Timestamps are relative to `--now` (default: the current time), so start
+//! the validator right after generating.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::Space;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use clap::Parser;
+use serde::Serialize;
+use serde_json::json;
+use solana_keypair::{write_keypair_file, Keypair};
+use solana_sha256_hasher::hashv;
+use subscription_client::preflight::{self, ChargeContext, ChargeOutcome};
+use subscription_client::{pda, Subscription, PROGRAM_ID};
+use test_harness::{Account, Signer, TestSvm};
+
+const DECIMALS: u8 = 6;
+const USDC: u64 = 1_000_000;
+const DAY: i64 = 24 * 60 * 60;
+const WALLET_LAMPORTS: u64 = 10_000_000_000;
+
+#[derive(Debug, Parser)]
+#[command(about = "Write subscription fixtures for solana-test-validator --account-dir")]
+struct Args {
+    /// Output directory
+    #[arg(long, default_value = "fixtures")]
+    out: PathBuf,
+
+    /// Number of subscriptions, spread over every state
+    #[arg(long, default_value_t = 20)]
+    subscriptions: usize,
+
+    #[arg(long, default_value_t = 3)]
+    merchants: usize,
+
+    /// Seed for the generated keypairs; the same seed gives the same addresses
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Unix time the states are relative to
+    #[arg(long)]
+    now: Option<i64>,
+}
+
+/// What the subscription looks like to a keeper at `now`
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum State {
+    Due,
+    NotDue,
+    Expired,
+    /// Left behind by an older cancel that did not close the account; only
+    /// `cleanup_cancelled_subscription` applies
+    Inactive,
+    Underfunded,
+    Revoked,
+    Frozen,
+}
+
+const STATES: [State; 7] = [
+    State::Due,
+    State::NotDue,
+    State::Expired,
+    State::Inactive,
+    State::Underfunded,
+    State::Revoked,
+    State::Frozen,
+];
+
+/// (amount, interval) per merchant, cycled
+const PLANS: [(u64, i64); 3] = [(5 * USDC, 7 * DAY), (10 * USDC, 30 * DAY), (25 * USDC, DAY)];
+
+#[derive(Serialize)]
+struct SubscriptionEntry {
+    address: String,
+    authority: String,
+    recipient: String,
+    amount_per_period: u64,
+    interval_seconds: i64,
+    state: State,
+    /// `preflight::check_charge` at `now`: why a charge would fail, or that
+    /// it would only deactivate the subscription; `null` if it would charge
+    blocker: Option<String>,
+}
+
+fn keypair(seed: u64, label: &str, index: usize) -> Keypair {
+    let secret = hashv(&[
+        b"subscription-fixtures".as_slice(),
+        &seed.to_le_bytes(),
+        label.as_bytes(),
+        &(index as u64).to_le_bytes(),
+    ]);
+    Keypair::new_from_array(secret.to_bytes())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    let now = match args.now {
+        Some(now) => now,
+        None => SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64,
+    };
+    let merchants = args.merchants.max(1);
+
+    let mut svm = TestSvm::new();
+    let keypairs_dir = args.out.join("keypairs");
+    fs::create_dir_all(&keypairs_dir)?;
+
+    let mint_authority = keypair(args.seed, "mint-authority", 0);
+    let mint = keypair(args.seed, "mint", 0).pubkey();
+    svm.airdrop(&mint_authority.pubkey(), WALLET_LAMPORTS);
+    svm.create_mint_at(mint, &mint_authority.pubkey(), DECIMALS);
+    write_keypair_file(&mint_authority, keypairs_dir.join("mint-authority.json"))?;
+
+    let merchants: Vec<Keypair> = (0..merchants)
+        .map(|index| keypair(args.seed, "merchant", index))
+        .collect();
+    for (index, merchant) in merchants.iter().enumerate() {
+        svm.airdrop(&merchant.pubkey(), WALLET_LAMPORTS);
+        svm.create_associated_token_account(&merchant.pubkey(), &mint, 0);
+        write_keypair_file(
+            merchant,
+            keypairs_dir.join(format!("merchant-{index}.json")),
+        )?;
+    }
+
+    let mut entries = Vec::with_capacity(args.subscriptions);
+    for index in 0..args.subscriptions {
+        let state = STATES[index % STATES.len()];
+        let merchant = merchants[index % merchants.len()].pubkey();
+        let (amount, interval) = PLANS[index % PLANS.len()];
+        let subscriber = keypair(args.seed, "subscriber", index);
+        write_keypair_file(
+            &subscriber,
+            keypairs_dir.join(format!("subscriber-{index}.json")),
+        )?;
+
+        let address = subscribe(
+            &mut svm,
+            &subscriber.pubkey(),
+            &merchant,
+            &mint,
+            amount,
+            interval,
+            state,
+            now,
+        );
+        let subscription: Subscription = svm.get_anchor_account(&address).unwrap();
+        let blocker = preflight::check_charge(
+            &address,
+            &subscription,
+            svm.get_token_account(&subscription.user_token_account)
+                .as_ref(),
+            svm.get_token_account(&subscription.recipient_token_account)
+                .as_ref(),
+            ChargeContext::default(),
+            now,
+        );
+        let blocker = match blocker {
+            Ok(ChargeOutcome::Charged { .. }) => None,
+            Ok(ChargeOutcome::Deactivated(reason)) => Some(format!("deactivates: {reason}")),
+            Err(blocker) => Some(blocker.to_string()),
+        };
+
+        entries.push(SubscriptionEntry {
+            address: address.to_string(),
+            authority: subscriber.pubkey().to_string(),
+            recipient: merchant.to_string(),
+            amount_per_period: amount,
+            interval_seconds: interval,
+            state,
+            blocker,
+        });
+    }
+
+    let written = write_accounts(&svm, &args.out.join("accounts"))?;
+    let manifest = json!({
+        "now": now,
+        "program_id": PROGRAM_ID.to_string(),
+        "mint": mint.to_string(),
+        "decimals": DECIMALS,
+        "merchants": merchants.iter().map(|m| m.pubkey().to_string()).collect::<Vec<_>>(),
+        "subscriptions": entries,
+    });
+    fs::write(
+        args.out.join("manifest.json"),
+        serde_json::to_string_pretty(&manifest)?,
+    )?;
+
+    println!(
+        "wrote {written} accounts and {} subscriptions to {}",
+        args.subscriptions,
+        args.out.display()
+    );
+    println!(
+        "solana-test-validator --reset --account-dir {} --bpf-program {PROGRAM_ID} target/deploy/subscription_program.so",
+        args.out.join("accounts").display()
+    );
+    Ok(())
+}
+
+/// Lay down the accounts `initialize_subscription` would have created some
+/// periods ago, then bend them into `state`
+#[allow(clippy::too_many_arguments)]
+fn subscribe(
+    svm: &mut TestSvm,
+    authority: &Pubkey,
+    recipient: &Pubkey,
+    mint: &Pubkey,
+    amount: u64,
+    interval: i64,
+    state: State,
+    now: i64,
+) -> Pubkey {
+    let (address, bump) = pda::subscription_address(authority, recipient);
+    let user_token_account = pda::associated_token_address(authority, mint);
+    let recipient_token_account = pda::associated_token_address(recipient, mint);
+
+    let periods_paid = 3;
+    let balance = match state {
+        State::Underfunded => amount / 2,
+        _ => 12 * amount,
+    };
+    svm.airdrop(authority, WALLET_LAMPORTS);
+    svm.create_associated_token_account(authority, mint, balance);
+    svm.approve(&user_token_account, &address, u64::MAX);
+    svm.mint_tokens(&recipient_token_account, periods_paid * amount);
+
+    let last_charge_timestamp = match state {
+        State::NotDue => now - interval / 2,
+        _ => now - interval - DAY / 2,
+    };
+    let created_at = last_charge_timestamp - (periods_paid as i64 - 1) * interval;
+    let expires_at = match state {
+        State::Expired => Some(now - DAY / 4),
+        State::NotDue => Some(now + 12 * interval),
+        _ => None,
+    };
+
+    let subscription = Subscription {
+        authority: *authority,
+        recipient: *recipient,
+        user_token_account,
+        recipient_token_account,
+        token_mint: *mint,
+        amount_per_period: amount,
+        interval_seconds: interval,
+        last_charge_timestamp,
+        next_charge_at: last_charge_timestamp + interval,
+        created_at,
+        expires_at,
+        is_active: !matches!(state, State::Inactive),
+        total_charged: periods_paid * amount,
+        bump,
+        on_plan: false,
+        max_total_spend: Subscription::NO_SPEND_LIMIT,
+    };
+    svm.set_anchor_account(address, &subscription, 8 + Subscription::INIT_SPACE);
+
+    match state {
+        State::Inactive | State::Revoked => svm.revoke(&user_token_account),
+        State::Frozen => svm.freeze(&user_token_account),
+        _ => {}
+    }
+    address
+}
+
+/// One `solana account --output json` file per account, skipping the
+/// program placeholders the validator provides itself
+fn write_accounts(svm: &TestSvm, dir: &Path) -> std::io::Result<usize> {
+    fs::create_dir_all(dir)?;
+    let mut written = 0;
+    for (address, account) in svm.accounts() {
+        if account.executable {
+            continue;
+        }
+        fs::write(
+            dir.join(format!("{address}.json")),
+            serde_json::to_string_pretty(&account_json(address, account))?,
+        )?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+fn account_json(address: &Pubkey, account: &Account) -> serde_json::Value {
+    json!({
+        "pubkey": address.to_string(),
+        "account": {
+            "lamports": account.lamports,
+            "data": [BASE64_STANDARD.encode(&account.data), "base64"],
+            "owner": account.owner.to_string(),
+            "executable": account.executable,
+            "rentEpoch": account.rent_epoch,
+            "space": account.data.len(),
+        },
+    })
+}
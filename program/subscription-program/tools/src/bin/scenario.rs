@@ -0,0 +1,100 @@
+//! Play a scripted billing timeline and print what happened.
+//!
+//! A scenario file lists a plan, subscribers and what they do on which day
+//! (see `tools/scenarios/`). By default it runs on an in-process ledger whose
+//! clock jumps a day at a time, so a quarter of billing takes a second:
+//!
+//! ```bash
+//! cargo run -p subscription-tools --bin scenario -- tools/scenarios/workshop.toml
+//! ```
+//!
+//! Natively the program's `msg!` output goes to stdout too; pass `--out` for a
+//! clean report file.
+//!
+//! With the `rpc` feature it runs against a validator instead, compressing
+//! each day into `--seconds-per-day` of wall time:
+//!
+//! ```bash
+//! cargo run -p subscription-tools --features rpc --bin scenario -- \
+//!     tools/scenarios/workshop.toml --url http://127.0.0.1:8899 --seconds-per-day 10
+//! ```
+
+use std::fs;
+use std::path::PathBuf;
+
+use clap::Parser;
+use subscription_tools::scenario::{self, Script, SimulatedLedger};
+
+#[derive(Debug, Parser)]
+#[command(about = "Play a scripted billing timeline and report the outcome")]
+struct Args {
+    /// Scenario file (TOML)
+    script: PathBuf,
+
+    /// Write the report here instead of stdout
+    #[arg(long)]
+    out: Option<PathBuf>,
+
+    /// Report as JSON instead of Markdown
+    #[arg(long)]
+    json: bool,
+
+    /// Validator RPC URL; without it the in-process ledger is used
+    #[cfg(feature = "rpc")]
+    #[arg(long)]
+    url: Option<String>,
+
+    /// Fee payer, keeper and mint authority on the validator
+    #[cfg(feature = "rpc")]
+    #[arg(long, default_value = "~/.config/solana/id.json")]
+    keypair: String,
+
+    /// Wall-clock seconds per scripted day on the validator
+    #[cfg(feature = "rpc")]
+    #[arg(long, default_value_t = 10)]
+    seconds_per_day: i64,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    let script = Script::from_toml(&fs::read_to_string(&args.script)?)?;
+
+    let report = match ledger(&args)? {
+        Some(mut ledger) => scenario::run(&script, ledger.as_mut())?,
+        None => scenario::run(&script, &mut SimulatedLedger::new())?,
+    };
+
+    let rendered = if args.json {
+        scenario::to_json(&report)
+    } else {
+        format!("{report}\n")
+    };
+    match &args.out {
+        Some(path) => fs::write(path, rendered)?,
+        None => print!("{rendered}"),
+    }
+    Ok(())
+}
+
+#[cfg(feature = "rpc")]
+fn ledger(args: &Args) -> Result<Option<Box<dyn scenario::Ledger>>, Box<dyn std::error::Error>> {
+    let Some(url) = &args.url else {
+        return Ok(None);
+    };
+    let path = match args.keypair.strip_prefix("~/") {
+        Some(rest) => PathBuf::from(std::env::var("HOME")?).join(rest),
+        None => PathBuf::from(&args.keypair),
+    };
+    let payer = solana_keypair::read_keypair_file(&path)
+        .map_err(|err| format!("reading {}: {err}", path.display()))?;
+    Ok(Some(Box::new(scenario::ValidatorLedger::connect(
+        url,
+        payer,
+        args.seconds_per_day,
+    )?)))
+}
+
+#[cfg(not(feature = "rpc"))]
+fn ledger(_: &Args) -> Result<Option<Box<dyn scenario::Ledger>>, Box<dyn std::error::Error>> {
+    Ok(None)
+}
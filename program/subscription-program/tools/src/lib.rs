@@ -0,0 +1,3 @@
+//! Library side of the demo tooling; the binaries live in `src/bin`.
+
+pub mod scenario;
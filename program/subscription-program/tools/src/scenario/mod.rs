@@ -0,0 +1,493 @@
+//! Scripted billing timelines.
+//!
+//! A [`Script`] describes a plan, the subscribers who sign up to it and what
+//! they do over the following days (top up, revoke the delegation, cancel,
+//! change their amount). [`run`] plays it day by day against a [`Ledger`]:
+//! signups first, then the day's events, then a keeper pass that charges every
+//! subscription [`preflight::check_charge`] reports as due and records the
+//! rest. The resulting [`Report`] is what a workshop walks through.
+
+mod simulated;
+#[cfg(feature = "rpc")]
+mod validator;
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use anchor_lang::prelude::Pubkey;
+use serde::{Deserialize, Serialize};
+use spl_token::state::Account as TokenAccount;
+use subscription_client::preflight::{self, ChargeBlocker, ChargeContext, ChargeOutcome};
+use subscription_client::Subscription;
+
+pub use simulated::SimulatedLedger;
+#[cfg(feature = "rpc")]
+pub use validator::ValidatorLedger;
+
+pub const DECIMALS: u8 = 6;
+pub const DAY: i64 = 24 * 60 * 60;
+
+pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Script {
+    pub name: String,
+    /// Length of the timeline; day 0 is the first day
+    pub days: u32,
+    pub plan: Plan,
+    #[serde(default, rename = "subscriber")]
+    pub subscribers: Vec<SubscriberSpec>,
+    #[serde(default, rename = "event")]
+    pub events: Vec<EventSpec>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Plan {
+    /// Per period, in whole tokens (6 decimals)
+    pub amount: f64,
+    pub interval_days: u32,
+    /// Subscriptions expire this many days after signup
+    #[serde(default)]
+    pub expires_after_days: Option<u32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubscriberSpec {
+    pub name: String,
+    /// Starting token balance, in whole tokens
+    pub balance: f64,
+    #[serde(default)]
+    pub signup_day: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EventSpec {
+    pub day: u32,
+    pub subscriber: String,
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    Cancel,
+    TopUp {
+        amount: f64,
+    },
+    /// Revoke the delegation directly with the token program, bypassing `cancel`
+    Revoke,
+    ChangeAmount {
+        amount: f64,
+    },
+}
+
+impl Script {
+    pub fn from_toml(source: &str) -> Result<Self> {
+        let script: Script = toml::from_str(source)?;
+        script.validate()?;
+        Ok(script)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.plan.interval_days == 0 {
+            return Err("plan.interval_days must be at least 1".into());
+        }
+        let mut names = BTreeMap::new();
+        for subscriber in &self.subscribers {
+            if names.insert(subscriber.name.as_str(), ()).is_some() {
+                return Err(format!("subscriber `{}` is listed twice", subscriber.name).into());
+            }
+        }
+        for event in &self.events {
+            if !names.contains_key(event.subscriber.as_str()) {
+                return Err(format!("event for unknown subscriber `{}`", event.subscriber).into());
+            }
+            if event.day > self.days {
+                return Err(format!("event on day {} is past the end", event.day).into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whole tokens to base units
+pub fn to_base_units(amount: f64) -> u64 {
+    (amount * 10f64.powi(DECIMALS as i32)).round() as u64
+}
+
+fn format_amount(amount: u64) -> String {
+    let scale = 10u64.pow(DECIMALS as u32);
+    format!("{}.{:02}", amount / scale, amount % scale / (scale / 100))
+}
+
+/// A subscription and both of its token accounts, as a keeper sees them
+pub struct Snapshot {
+    pub address: Pubkey,
+    pub subscription: Subscription,
+    pub user: Option<TokenAccount>,
+    pub recipient: Option<TokenAccount>,
+}
+
+/// A ledger the timeline can be played against
+pub trait Ledger {
+    /// One-line description for the report header
+    fn describe(&self) -> String;
+
+    /// Seconds that stand for one scripted day
+    fn day_length(&self) -> i64;
+
+    /// Move the ledger clock to the start of `day`
+    fn advance_to(&mut self, day: u32) -> Result<()>;
+
+    fn now(&self) -> Result<i64>;
+
+    /// Create the subscriber's wallet and token account, funded with `balance`
+    fn create_subscriber(&mut self, name: &str, balance: u64) -> Result<()>;
+
+    /// `initialize_subscription`; `Err` carries the failure reason
+    fn subscribe(
+        &mut self,
+        name: &str,
+        amount: u64,
+        interval_seconds: i64,
+        expires_at: Option<i64>,
+    ) -> Result<std::result::Result<(), String>>;
+
+    fn charge(&mut self, name: &str) -> Result<std::result::Result<(), String>>;
+
+    fn cancel(&mut self, name: &str) -> Result<std::result::Result<(), String>>;
+
+    fn update_amount(&mut self, name: &str, amount: u64)
+        -> Result<std::result::Result<(), String>>;
+
+    fn top_up(&mut self, name: &str, amount: u64) -> Result<()>;
+
+    fn revoke(&mut self, name: &str) -> Result<()>;
+
+    /// `None` if the subscription does not exist
+    fn snapshot(&self, name: &str) -> Result<Option<Snapshot>>;
+
+    fn balance(&self, name: &str) -> Result<u64>;
+
+    fn merchant_balance(&self) -> Result<u64>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    Signup,
+    SignupFailed,
+    Charged,
+    ChargeFailed,
+    Skipped,
+    Expired,
+    Cancelled,
+    CancelFailed,
+    Updated,
+    UpdateFailed,
+    TopUp,
+    Revoked,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Entry {
+    pub day: u32,
+    pub subscriber: String,
+    pub kind: EntryKind,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SubscriberSummary {
+    pub charges: u32,
+    pub failures: u32,
+    pub paid: u64,
+    pub final_balance: u64,
+    pub status: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub scenario: String,
+    pub ledger: String,
+    pub days: u32,
+    pub entries: Vec<Entry>,
+    pub subscribers: BTreeMap<String, SubscriberSummary>,
+    pub merchant_revenue: u64,
+}
+
+impl Report {
+    pub fn count(&self, kind: EntryKind) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| entry.kind == kind)
+            .count()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# {}", self.scenario)?;
+        writeln!(f)?;
+        writeln!(f, "{} days on {}", self.days, self.ledger)?;
+        writeln!(f)?;
+        writeln!(f, "## Timeline")?;
+        writeln!(f)?;
+        writeln!(f, "| Day | Subscriber | Event | Detail |")?;
+        writeln!(f, "|-----|------------|-------|--------|")?;
+        for entry in &self.entries {
+            let kind = serde_json::to_value(&entry.kind).unwrap();
+            writeln!(
+                f,
+                "| {} | {} | {} | {} |",
+                entry.day,
+                entry.subscriber,
+                kind.as_str().unwrap_or_default(),
+                entry.detail
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(f, "## Subscribers")?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "| Subscriber | Charges | Failures | Paid | Balance | Status |"
+        )?;
+        writeln!(
+            f,
+            "|------------|---------|----------|------|---------|--------|"
+        )?;
+        for (name, summary) in &self.subscribers {
+            writeln!(
+                f,
+                "| {name} | {} | {} | {} | {} | {} |",
+                summary.charges,
+                summary.failures,
+                format_amount(summary.paid),
+                format_amount(summary.final_balance),
+                summary.status
+            )?;
+        }
+        writeln!(f)?;
+        write!(
+            f,
+            "Merchant revenue: {}",
+            format_amount(self.merchant_revenue)
+        )
+    }
+}
+
+struct Timeline<'a> {
+    day: u32,
+    entries: Vec<Entry>,
+    summaries: BTreeMap<String, SubscriberSummary>,
+    /// Last blocker recorded per subscriber, so a stuck charge is reported once
+    blocked: BTreeMap<String, String>,
+    ledger: &'a mut dyn Ledger,
+}
+
+impl Timeline<'_> {
+    fn record(&mut self, subscriber: &str, kind: EntryKind, detail: impl Into<String>) {
+        let summary = self.summaries.entry(subscriber.to_string()).or_default();
+        match kind {
+            EntryKind::SignupFailed | EntryKind::ChargeFailed => summary.failures += 1,
+            _ => {}
+        }
+        self.entries.push(Entry {
+            day: self.day,
+            subscriber: subscriber.to_string(),
+            kind,
+            detail: detail.into(),
+        });
+    }
+
+    /// A payment went through: the first one at signup or a keeper charge
+    fn paid(&mut self, subscriber: &str, kind: EntryKind, amount: u64) {
+        let summary = self.summaries.entry(subscriber.to_string()).or_default();
+        summary.charges += 1;
+        summary.paid += amount;
+        let detail = match kind {
+            EntryKind::Signup => format!("first payment {}", format_amount(amount)),
+            _ => format_amount(amount),
+        };
+        self.record(subscriber, kind, detail);
+    }
+
+    /// Charge if due; records blockers other than "not yet due"
+    fn keeper_pass(&mut self, subscriber: &str, expired: &mut Vec<String>) -> Result<()> {
+        let Some(Snapshot {
+            address,
+            subscription,
+            user,
+            recipient,
+        }) = self.ledger.snapshot(subscriber)?
+        else {
+            return Ok(());
+        };
+        let now = self.ledger.now()?;
+
+        match preflight::check_charge(
+            &address,
+            &subscription,
+            user.as_ref(),
+            recipient.as_ref(),
+            ChargeContext::default(),
+            now,
+        ) {
+            Ok(ChargeOutcome::Charged { .. }) => match self.ledger.charge(subscriber)? {
+                Ok(()) => {
+                    self.blocked.remove(subscriber);
+                    self.paid(
+                        subscriber,
+                        EntryKind::Charged,
+                        subscription.amount_per_period,
+                    );
+                }
+                Err(reason) => self.record(subscriber, EntryKind::ChargeFailed, reason),
+            },
+            // The charge lands only to end the subscription
+            Ok(ChargeOutcome::Deactivated(reason)) => match self.ledger.charge(subscriber)? {
+                Ok(()) => self.record(subscriber, EntryKind::Skipped, reason.to_string()),
+                Err(reason) => self.record(subscriber, EntryKind::ChargeFailed, reason),
+            },
+            Err(ChargeBlocker::IntervalNotMet { .. }) | Err(ChargeBlocker::Inactive) => {}
+            Err(ChargeBlocker::Expired { .. }) => {
+                if !expired.iter().any(|name| name == subscriber) {
+                    expired.push(subscriber.to_string());
+                    self.record(subscriber, EntryKind::Expired, "no further charges");
+                }
+            }
+            Err(blocker) => {
+                let reason = blocker.to_string();
+                if self.blocked.get(subscriber) != Some(&reason) {
+                    self.blocked.insert(subscriber.to_string(), reason.clone());
+                    self.record(subscriber, EntryKind::Skipped, reason);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Play `script` against `ledger`
+pub fn run(script: &Script, ledger: &mut dyn Ledger) -> Result<Report> {
+    let day_length = ledger.day_length();
+    let amount = to_base_units(script.plan.amount);
+    let interval = script.plan.interval_days as i64 * day_length;
+    let description = ledger.describe();
+
+    for subscriber in &script.subscribers {
+        ledger.create_subscriber(&subscriber.name, to_base_units(subscriber.balance))?;
+    }
+
+    let mut timeline = Timeline {
+        day: 0,
+        entries: Vec::new(),
+        summaries: BTreeMap::new(),
+        blocked: BTreeMap::new(),
+        ledger,
+    };
+    let mut subscribed: Vec<String> = Vec::new();
+    let mut cancelled: Vec<String> = Vec::new();
+    let mut expired: Vec<String> = Vec::new();
+
+    for day in 0..=script.days {
+        timeline.day = day;
+        timeline.ledger.advance_to(day)?;
+
+        for subscriber in script.subscribers.iter().filter(|s| s.signup_day == day) {
+            let expires_at = match script.plan.expires_after_days {
+                Some(days) => Some(timeline.ledger.now()? + days as i64 * day_length),
+                None => None,
+            };
+            let name = subscriber.name.as_str();
+            match timeline
+                .ledger
+                .subscribe(name, amount, interval, expires_at)?
+            {
+                Ok(()) => {
+                    subscribed.push(name.to_string());
+                    timeline.paid(name, EntryKind::Signup, amount);
+                }
+                Err(reason) => timeline.record(name, EntryKind::SignupFailed, reason),
+            }
+        }
+
+        for event in script.events.iter().filter(|e| e.day == day) {
+            let name = event.subscriber.as_str();
+            match &event.action {
+                Action::Cancel => match timeline.ledger.cancel(name)? {
+                    Ok(()) => {
+                        subscribed.retain(|s| s != name);
+                        cancelled.push(name.to_string());
+                        timeline.record(
+                            name,
+                            EntryKind::Cancelled,
+                            "delegation revoked, rent refunded",
+                        );
+                    }
+                    Err(reason) => timeline.record(name, EntryKind::CancelFailed, reason),
+                },
+                Action::TopUp { amount } => {
+                    let amount = to_base_units(*amount);
+                    timeline.ledger.top_up(name, amount)?;
+                    timeline.record(name, EntryKind::TopUp, format_amount(amount));
+                }
+                Action::Revoke => {
+                    timeline.ledger.revoke(name)?;
+                    timeline.record(
+                        name,
+                        EntryKind::Revoked,
+                        "delegation revoked outside the program",
+                    );
+                }
+                Action::ChangeAmount { amount } => {
+                    let amount = to_base_units(*amount);
+                    match timeline.ledger.update_amount(name, amount)? {
+                        Ok(()) => timeline.record(
+                            name,
+                            EntryKind::Updated,
+                            format!("amount {}", format_amount(amount)),
+                        ),
+                        Err(reason) => timeline.record(name, EntryKind::UpdateFailed, reason),
+                    }
+                }
+            }
+        }
+
+        for subscriber in subscribed.clone() {
+            timeline.keeper_pass(&subscriber, &mut expired)?;
+        }
+    }
+
+    let mut summaries = std::mem::take(&mut timeline.summaries);
+    for spec in &script.subscribers {
+        let name = &spec.name;
+        let snapshot = timeline.ledger.snapshot(name)?;
+        let summary = summaries.entry(name.clone()).or_default();
+        summary.final_balance = timeline.ledger.balance(name)?;
+        summary.status = match snapshot {
+            _ if cancelled.contains(name) => "cancelled",
+            None => "not subscribed",
+            Some(_) if expired.contains(name) => "expired",
+            Some(snapshot) if snapshot.subscription.is_active => "active",
+            Some(_) => "inactive",
+        }
+        .into();
+    }
+
+    Ok(Report {
+        scenario: script.name.clone(),
+        ledger: description,
+        days: script.days,
+        entries: timeline.entries,
+        subscribers: summaries,
+        merchant_revenue: timeline.ledger.merchant_balance()?,
+    })
+}
+
+/// Render `report` as JSON, e.g. to diff two runs
+pub fn to_json(report: &Report) -> String {
+    serde_json::to_string_pretty(report).expect("report serializes") + "\n"
+}
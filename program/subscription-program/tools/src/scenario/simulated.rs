@@ -0,0 +1,230 @@
+//! In-process ledger with a clock that can be warped.
+//!
+//! Every instruction goes through the program's real entrypoint, so account
+//! validation and the billing checks are the on-chain ones, and its SPL Token
+//! and System CPIs run against the ledger's accounts.
+
+use std::collections::HashMap;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use subscription_client::error::SubscriptionError;
+use subscription_client::{instructions, pda, Subscription, PROGRAM_ID};
+use test_harness::{InstructionError, Keypair, Signer, TestSvm, TransactionError};
+
+use super::{Ledger, Result, Snapshot, DAY, DECIMALS};
+
+const WALLET_LAMPORTS: u64 = 10_000_000_000;
+
+struct Wallet {
+    keypair: Keypair,
+    token_account: Pubkey,
+}
+
+pub struct SimulatedLedger {
+    svm: TestSvm,
+    payer: Keypair,
+    merchant: Pubkey,
+    merchant_token_account: Pubkey,
+    mint: Pubkey,
+    start: i64,
+    wallets: HashMap<String, Wallet>,
+}
+
+impl Default for SimulatedLedger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimulatedLedger {
+    pub fn new() -> Self {
+        let mut svm = TestSvm::new();
+        svm.add_program(PROGRAM_ID, subscription_program::entry);
+
+        let payer = Keypair::new();
+        svm.airdrop(&payer.pubkey(), WALLET_LAMPORTS);
+        let mint = svm.create_mint(&Pubkey::new_unique(), DECIMALS);
+        let merchant = Pubkey::new_unique();
+        let merchant_token_account = svm.create_associated_token_account(&merchant, &mint, 0);
+
+        Self {
+            start: svm.clock().unix_timestamp,
+            svm,
+            payer,
+            merchant,
+            merchant_token_account,
+            mint,
+            wallets: HashMap::new(),
+        }
+    }
+
+    fn wallet(&self, name: &str) -> Result<&Wallet> {
+        self.wallets
+            .get(name)
+            .ok_or_else(|| format!("unknown subscriber `{name}`").into())
+    }
+
+    fn subscription_address(&self, name: &str) -> Result<Pubkey> {
+        Ok(pda::subscription_address(&self.wallet(name)?.keypair.pubkey(), &self.merchant).0)
+    }
+
+    /// Land `instruction`, or say why it failed. A CPI's error is the
+    /// instruction's, so it is decoded as the program that raised it.
+    fn send(
+        &mut self,
+        instruction: Instruction,
+        signer: &Keypair,
+    ) -> std::result::Result<(), String> {
+        let payer = self.payer.insecure_clone();
+        let failed = match self
+            .svm
+            .send_instructions(&[instruction], &payer, &[signer])
+        {
+            Ok(_) => return Ok(()),
+            Err(failed) => failed,
+        };
+        Err(match failed.err {
+            TransactionError::InstructionError(_, InstructionError::Custom(code)) => {
+                SubscriptionError::from_logs(&failed.meta.logs)
+                    .unwrap_or_else(|| SubscriptionError::from_custom(&PROGRAM_ID, code))
+                    .to_string()
+            }
+            err => err.to_string(),
+        })
+    }
+}
+
+impl Ledger for SimulatedLedger {
+    fn describe(&self) -> String {
+        "the in-process ledger (the program and its SPL Token CPIs run natively)".into()
+    }
+
+    fn day_length(&self) -> i64 {
+        DAY
+    }
+
+    fn advance_to(&mut self, day: u32) -> Result<()> {
+        self.svm.warp_to_timestamp(self.start + day as i64 * DAY);
+        Ok(())
+    }
+
+    fn now(&self) -> Result<i64> {
+        Ok(self.svm.clock().unix_timestamp)
+    }
+
+    fn create_subscriber(&mut self, name: &str, balance: u64) -> Result<()> {
+        let keypair = Keypair::new();
+        self.svm.airdrop(&keypair.pubkey(), WALLET_LAMPORTS);
+        let token_account =
+            self.svm
+                .create_associated_token_account(&keypair.pubkey(), &self.mint, balance);
+        self.wallets.insert(
+            name.to_string(),
+            Wallet {
+                keypair,
+                token_account,
+            },
+        );
+        Ok(())
+    }
+
+    fn subscribe(
+        &mut self,
+        name: &str,
+        amount: u64,
+        interval_seconds: i64,
+        expires_at: Option<i64>,
+    ) -> Result<std::result::Result<(), String>> {
+        let authority = self.wallet(name)?.keypair.insecure_clone();
+        let instruction = instructions::initialize_subscription(
+            &authority.pubkey(),
+            &self.merchant,
+            &self.mint,
+            &self.payer.pubkey(),
+            amount,
+            interval_seconds,
+            expires_at,
+        );
+        Ok(self.send(instruction, &authority))
+    }
+
+    fn charge(&mut self, name: &str) -> Result<std::result::Result<(), String>> {
+        let address = self.subscription_address(name)?;
+        let Some(subscription) = self.svm.get_anchor_account::<Subscription>(&address) else {
+            return Ok(Err(format!("subscription {address} does not exist")));
+        };
+
+        let keeper = self.payer.insecure_clone();
+        Ok(self.send(
+            instructions::charge_subscription(&address, &subscription),
+            &keeper,
+        ))
+    }
+
+    fn cancel(&mut self, name: &str) -> Result<std::result::Result<(), String>> {
+        let wallet = self.wallet(name)?;
+        let authority = wallet.keypair.insecure_clone();
+        let instruction = instructions::cancel_subscription(
+            &authority.pubkey(),
+            &self.merchant,
+            &wallet.token_account,
+        );
+        Ok(self.send(instruction, &authority))
+    }
+
+    fn update_amount(
+        &mut self,
+        name: &str,
+        amount: u64,
+    ) -> Result<std::result::Result<(), String>> {
+        let authority = self.wallet(name)?.keypair.insecure_clone();
+        let instruction = instructions::update_subscription(
+            &authority.pubkey(),
+            &self.merchant,
+            Some(amount),
+            None,
+            None,
+        );
+        Ok(self.send(instruction, &authority))
+    }
+
+    fn top_up(&mut self, name: &str, amount: u64) -> Result<()> {
+        let token_account = self.wallet(name)?.token_account;
+        self.svm.mint_tokens(&token_account, amount);
+        Ok(())
+    }
+
+    fn revoke(&mut self, name: &str) -> Result<()> {
+        let token_account = self.wallet(name)?.token_account;
+        self.svm.revoke(&token_account);
+        Ok(())
+    }
+
+    fn snapshot(&self, name: &str) -> Result<Option<Snapshot>> {
+        let address = self.subscription_address(name)?;
+        Ok(self
+            .svm
+            .get_anchor_account::<Subscription>(&address)
+            .map(|subscription| {
+                let user = self.svm.get_token_account(&subscription.user_token_account);
+                let recipient = self
+                    .svm
+                    .get_token_account(&subscription.recipient_token_account);
+                Snapshot {
+                    address,
+                    subscription,
+                    user,
+                    recipient,
+                }
+            }))
+    }
+
+    fn balance(&self, name: &str) -> Result<u64> {
+        Ok(self.svm.token_balance(&self.wallet(name)?.token_account))
+    }
+
+    fn merchant_balance(&self) -> Result<u64> {
+        Ok(self.svm.token_balance(&self.merchant_token_account))
+    }
+}
@@ -0,0 +1,352 @@
+//! A running validator, usually `solana-test-validator`.
+//!
+//! The validator clock cannot be warped, so days are compressed instead: one
+//! scripted day is `seconds_per_day` of wall time and the plan interval is
+//! scaled to match. Everything else (the mint, ATAs, delegations, transfers)
+//! is real, including the token program's own failures.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use anchor_lang::prelude::{AccountMeta, Pubkey};
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::solana_program::sysvar;
+use anchor_lang::system_program;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_commitment_config::CommitmentConfig;
+use solana_keypair::Keypair;
+use spl_token::state::{Account as TokenAccount, Mint};
+use subscription_client::accounts::fetch_account_opt;
+use subscription_client::send::{send_with_retry, SendConfig};
+use subscription_client::{instructions, pda, ClientError, Subscription};
+use test_harness::{Signer, ASSOCIATED_TOKEN_PROGRAM_ID};
+use tokio::runtime::Runtime;
+
+use super::{Ledger, Result, Snapshot, DECIMALS};
+
+const MIN_PAYER_LAMPORTS: u64 = 10_000_000_000;
+const AIRDROP_LAMPORTS: u64 = 100_000_000_000;
+
+/// `unix_timestamp` is the last field of the Clock sysvar
+const CLOCK_UNIX_TIMESTAMP_OFFSET: usize = 32;
+
+struct Wallet {
+    keypair: Keypair,
+    token_account: Pubkey,
+}
+
+pub struct ValidatorLedger {
+    runtime: Runtime,
+    rpc: RpcClient,
+    url: String,
+    config: SendConfig,
+    /// Fee payer, rent payer, keeper and mint authority
+    payer: Keypair,
+    merchant: Pubkey,
+    merchant_token_account: Pubkey,
+    mint: Pubkey,
+    seconds_per_day: i64,
+    started: Instant,
+    wallets: HashMap<String, Wallet>,
+}
+
+fn create_associated_token_account(payer: &Pubkey, owner: &Pubkey, mint: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: ASSOCIATED_TOKEN_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(pda::associated_token_address(owner, mint), false),
+            AccountMeta::new_readonly(*owner, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(system_program::ID, false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+        ],
+        // CreateIdempotent
+        data: vec![1],
+    }
+}
+
+impl ValidatorLedger {
+    /// Fund `payer` if needed (localnet airdrop) and create a fresh mint and merchant
+    pub fn connect(url: &str, payer: Keypair, seconds_per_day: i64) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let rpc = RpcClient::new_with_commitment(url.to_string(), CommitmentConfig::confirmed());
+
+        runtime.block_on(async {
+            if rpc.get_balance(&payer.pubkey()).await? < MIN_PAYER_LAMPORTS {
+                let signature = rpc
+                    .request_airdrop(&payer.pubkey(), AIRDROP_LAMPORTS)
+                    .await?;
+                while !rpc.confirm_transaction(&signature).await? {
+                    tokio::time::sleep(Duration::from_millis(500)).await;
+                }
+            }
+            Ok::<_, ClientError>(())
+        })?;
+
+        let mint = Keypair::new();
+        let merchant = Keypair::new().pubkey();
+        let mut ledger = Self {
+            runtime,
+            rpc,
+            url: url.to_string(),
+            config: SendConfig::default(),
+            merchant,
+            merchant_token_account: pda::associated_token_address(&merchant, &mint.pubkey()),
+            mint: mint.pubkey(),
+            payer,
+            seconds_per_day,
+            started: Instant::now(),
+            wallets: HashMap::new(),
+        };
+
+        let rent = ledger
+            .runtime
+            .block_on(ledger.rpc.get_minimum_balance_for_rent_exemption(Mint::LEN))?;
+        let payer = ledger.payer.pubkey();
+        let setup = vec![
+            solana_system_interface::instruction::create_account(
+                &payer,
+                &ledger.mint,
+                rent,
+                Mint::LEN as u64,
+                &spl_token::ID,
+            ),
+            spl_token::instruction::initialize_mint2(
+                &spl_token::ID,
+                &ledger.mint,
+                &payer,
+                None,
+                DECIMALS,
+            )?,
+            create_associated_token_account(&payer, &merchant, &ledger.mint),
+        ];
+        ledger
+            .send(&setup, &[&mint])?
+            .map_err(|reason| format!("setup failed: {reason}"))?;
+
+        ledger.started = Instant::now();
+        Ok(ledger)
+    }
+
+    fn wallet(&self, name: &str) -> Result<&Wallet> {
+        self.wallets
+            .get(name)
+            .ok_or_else(|| format!("unknown subscriber `{name}`").into())
+    }
+
+    /// Send with the payer plus `signers`; program and token failures are the
+    /// `Err` reason, transport failures abort the run
+    fn send(
+        &self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Result<std::result::Result<(), String>> {
+        let mut all: Vec<&dyn Signer> = vec![&self.payer];
+        all.extend(signers.iter().map(|keypair| *keypair as &dyn Signer));
+
+        match self.runtime.block_on(send_with_retry(
+            &self.rpc,
+            &self.payer.pubkey(),
+            instructions,
+            &[],
+            &all,
+            &self.config,
+        )) {
+            Ok(_) => Ok(Ok(())),
+            Err(ClientError::TransactionFailed { error, .. }) => Ok(Err(error.to_string())),
+            Err(err) => match err.subscription_error() {
+                Some(error) => Ok(Err(error.to_string())),
+                None => Err(err.into()),
+            },
+        }
+    }
+
+    fn token_account(&self, address: &Pubkey) -> Result<Option<TokenAccount>> {
+        let account = self.runtime.block_on(
+            self.rpc
+                .get_account_with_commitment(address, self.rpc.commitment()),
+        )?;
+        Ok(account
+            .value
+            .and_then(|account| TokenAccount::unpack(&account.data).ok()))
+    }
+}
+
+impl Ledger for ValidatorLedger {
+    fn describe(&self) -> String {
+        format!(
+            "{} (one day every {} seconds)",
+            self.url, self.seconds_per_day
+        )
+    }
+
+    fn day_length(&self) -> i64 {
+        self.seconds_per_day
+    }
+
+    fn advance_to(&mut self, day: u32) -> Result<()> {
+        let target = self.started + Duration::from_secs(day as u64 * self.seconds_per_day as u64);
+        std::thread::sleep(target.saturating_duration_since(Instant::now()));
+        Ok(())
+    }
+
+    fn now(&self) -> Result<i64> {
+        let clock = self
+            .runtime
+            .block_on(self.rpc.get_account(&sysvar::clock::ID))?;
+        Ok(i64::from_le_bytes(
+            clock.data[CLOCK_UNIX_TIMESTAMP_OFFSET..CLOCK_UNIX_TIMESTAMP_OFFSET + 8]
+                .try_into()
+                .expect("clock sysvar layout"),
+        ))
+    }
+
+    fn create_subscriber(&mut self, name: &str, balance: u64) -> Result<()> {
+        let keypair = Keypair::new();
+        let token_account = pda::associated_token_address(&keypair.pubkey(), &self.mint);
+        let payer = self.payer.pubkey();
+        let setup = [
+            create_associated_token_account(&payer, &keypair.pubkey(), &self.mint),
+            spl_token::instruction::mint_to(
+                &spl_token::ID,
+                &self.mint,
+                &token_account,
+                &payer,
+                &[],
+                balance,
+            )?,
+        ];
+        self.send(&setup, &[])?
+            .map_err(|reason| format!("funding {name} failed: {reason}"))?;
+        self.wallets.insert(
+            name.to_string(),
+            Wallet {
+                keypair,
+                token_account,
+            },
+        );
+        Ok(())
+    }
+
+    fn subscribe(
+        &mut self,
+        name: &str,
+        amount: u64,
+        interval_seconds: i64,
+        expires_at: Option<i64>,
+    ) -> Result<std::result::Result<(), String>> {
+        let authority = &self.wallet(name)?.keypair;
+        let instruction = instructions::initialize_subscription(
+            &authority.pubkey(),
+            &self.merchant,
+            &self.mint,
+            &self.payer.pubkey(),
+            amount,
+            interval_seconds,
+            expires_at,
+        );
+        self.send(&[instruction], &[authority])
+    }
+
+    fn charge(&mut self, name: &str) -> Result<std::result::Result<(), String>> {
+        let authority = self.wallet(name)?.keypair.pubkey();
+        let address = pda::subscription_address(&authority, &self.merchant).0;
+        let Some(subscription) = self
+            .runtime
+            .block_on(fetch_account_opt::<Subscription>(&self.rpc, &address))?
+        else {
+            return Ok(Err(format!("subscription {address} does not exist")));
+        };
+        self.send(
+            &[instructions::charge_subscription(&address, &subscription)],
+            &[],
+        )
+    }
+
+    fn cancel(&mut self, name: &str) -> Result<std::result::Result<(), String>> {
+        let wallet = self.wallet(name)?;
+        let instruction = instructions::cancel_subscription(
+            &wallet.keypair.pubkey(),
+            &self.merchant,
+            &wallet.token_account,
+        );
+        self.send(&[instruction], &[&wallet.keypair])
+    }
+
+    fn update_amount(
+        &mut self,
+        name: &str,
+        amount: u64,
+    ) -> Result<std::result::Result<(), String>> {
+        let authority = &self.wallet(name)?.keypair;
+        let instruction = instructions::update_subscription(
+            &authority.pubkey(),
+            &self.merchant,
+            Some(amount),
+            None,
+            None,
+        );
+        self.send(&[instruction], &[authority])
+    }
+
+    fn top_up(&mut self, name: &str, amount: u64) -> Result<()> {
+        let instruction = spl_token::instruction::mint_to(
+            &spl_token::ID,
+            &self.mint,
+            &self.wallet(name)?.token_account,
+            &self.payer.pubkey(),
+            &[],
+            amount,
+        )?;
+        self.send(&[instruction], &[])?
+            .map_err(|reason| format!("top-up failed: {reason}").into())
+    }
+
+    fn revoke(&mut self, name: &str) -> Result<()> {
+        let wallet = self.wallet(name)?;
+        let instruction = spl_token::instruction::revoke(
+            &spl_token::ID,
+            &wallet.token_account,
+            &wallet.keypair.pubkey(),
+            &[],
+        )?;
+        self.send(&[instruction], &[&wallet.keypair])?
+            .map_err(|reason| format!("revoke failed: {reason}").into())
+    }
+
+    fn snapshot(&self, name: &str) -> Result<Option<Snapshot>> {
+        let authority = self.wallet(name)?.keypair.pubkey();
+        let address = pda::subscription_address(&authority, &self.merchant).0;
+        let Some(subscription) = self
+            .runtime
+            .block_on(fetch_account_opt::<Subscription>(&self.rpc, &address))?
+        else {
+            return Ok(None);
+        };
+        let user = self.token_account(&subscription.user_token_account)?;
+        let recipient = self.token_account(&subscription.recipient_token_account)?;
+        Ok(Some(Snapshot {
+            address,
+            subscription,
+            user,
+            recipient,
+        }))
+    }
+
+    fn balance(&self, name: &str) -> Result<u64> {
+        let token_account = self.wallet(name)?.token_account;
+        Ok(self
+            .token_account(&token_account)?
+            .map_or(0, |account| account.amount))
+    }
+
+    fn merchant_balance(&self) -> Result<u64> {
+        Ok(self
+            .token_account(&self.merchant_token_account)?
+            .map_or(0, |account| account.amount))
+    }
+}
@@ -0,0 +1,104 @@
+//! The workshop scenario on the in-process ledger.
+
+use subscription_tools::scenario::{
+    self, to_base_units, EntryKind, Report, Script, SimulatedLedger,
+};
+
+fn workshop() -> Report {
+    let script = Script::from_toml(include_str!("../scenarios/workshop.toml")).unwrap();
+    scenario::run(&script, &mut SimulatedLedger::new()).unwrap()
+}
+
+fn kinds(report: &Report, subscriber: &str) -> Vec<(u32, EntryKind)> {
+    report
+        .entries
+        .iter()
+        .filter(|entry| entry.subscriber == subscriber)
+        .map(|entry| (entry.day, entry.kind.clone()))
+        .collect()
+}
+
+#[test]
+fn weekly_charges_follow_the_interval() {
+    let report = workshop();
+    let alice = kinds(&report, "alice");
+    assert_eq!(
+        alice,
+        vec![
+            (0, EntryKind::Signup),
+            (7, EntryKind::Charged),
+            (14, EntryKind::Charged),
+            (20, EntryKind::Updated),
+            (21, EntryKind::Charged),
+            (28, EntryKind::Charged),
+        ]
+    );
+    assert_eq!(
+        report.subscribers["alice"].paid,
+        to_base_units(5.0 * 3.0 + 8.0 * 2.0)
+    );
+}
+
+#[test]
+fn underfunded_subscriber_is_charged_after_topping_up() {
+    let report = workshop();
+    let bob = kinds(&report, "bob");
+    // Reported once while stuck, not every day
+    assert_eq!(
+        bob.iter()
+            .filter(|(_, kind)| *kind == EntryKind::Skipped)
+            .count(),
+        1
+    );
+    assert!(bob.contains(&(14, EntryKind::Skipped)));
+    assert!(bob.contains(&(16, EntryKind::Charged)));
+}
+
+#[test]
+fn cancel_revoke_and_failed_signup() {
+    let report = workshop();
+
+    assert_eq!(report.subscribers["carol"].status, "cancelled");
+    assert!(!kinds(&report, "carol")
+        .iter()
+        .any(|(day, kind)| *day > 12 && *kind == EntryKind::Charged));
+
+    let dave = kinds(&report, "dave");
+    assert!(dave.contains(&(17, EntryKind::Skipped)));
+    assert_eq!(report.subscribers["dave"].charges, 2);
+
+    assert_eq!(kinds(&report, "erin"), vec![(10, EntryKind::SignupFailed)]);
+    assert_eq!(report.subscribers["erin"].status, "not subscribed");
+    assert_eq!(report.subscribers["erin"].final_balance, to_base_units(2.0));
+}
+
+#[test]
+fn merchant_receives_every_payment() {
+    let report = workshop();
+    let paid: u64 = report
+        .subscribers
+        .values()
+        .map(|summary| summary.paid)
+        .sum();
+    assert_eq!(report.merchant_revenue, paid);
+}
+
+#[test]
+fn events_for_unknown_subscribers_are_rejected() {
+    let source = r#"
+        name = "typo"
+        days = 1
+        plan = { amount = 1.0, interval_days = 1 }
+
+        [[subscriber]]
+        name = "alice"
+        balance = 10.0
+
+        [[event]]
+        day = 1
+        subscriber = "alcie"
+        action = "cancel"
+    "#;
+    let err = Script::from_toml(source).unwrap_err();
+    assert!(err.to_string().contains("alcie"), "{err}");
+}
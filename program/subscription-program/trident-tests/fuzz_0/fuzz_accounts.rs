@@ -0,0 +1,23 @@
+use trident_fuzz::fuzzing::*;
+
+/// One user of the merchant: a wallet, its token account and its
+/// subscription PDA
+#[derive(Clone, Copy)]
+pub struct Subscriber {
+    pub authority: Pubkey,
+    pub token_account: Pubkey,
+    pub subscription: Pubkey,
+}
+
+/// The accounts of one iteration. `reset_fuzz_accounts` drops them between
+/// iterations, and `start` builds a fresh set on the same SVM.
+#[derive(Default)]
+pub struct AccountAddresses {
+    pub merchant_token_account: Pubkey,
+    /// Two independent subscriptions to the same merchant
+    pub subscribers: Vec<Subscriber>,
+    /// Keys a flow may substitute into any account slot
+    pub pool: Vec<Pubkey>,
+    /// Lamports held by the pool and the payer after `start`
+    pub lamports: u64,
+}
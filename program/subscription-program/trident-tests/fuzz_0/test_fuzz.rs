@@ -0,0 +1,483 @@
+//! Trident fuzz target for the subscription program.
+//!
+//! The program runs natively, deployed as a Trident entrypoint; SPL Token is
+//! Trident's bundled mainnet build, so every charge, approval and revoke is a
+//! real CPI. Each iteration opens two subscriptions to one merchant, then
+//! runs random flows: instructions with accounts swapped for other keys in the
+//! iteration, signed by either subscriber, clock jumps, and corrupted copies
+//! of a subscription planted at new addresses. After every flow, whether its
+//! transaction succeeded or not:
+//!
+//! - tokens only move from a subscriber to the merchant, one period of that
+//!   subscriber's terms at a time;
+//! - lamports are conserved, and a subscription's rent only goes to its
+//!   authority, when that authority signed;
+//! - only a subscription's authority changes its terms, and its identity
+//!   fields never change.
+//!
+//! Run with `trident fuzz run fuzz_0`, or `cargo run --bin fuzz_0` from
+//! `trident-tests/`.
+
+use anchor_lang::prelude::AccountInfo;
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::{system_program, AccountDeserialize, InstructionData, ToAccountMetas};
+use fuzz_accounts::*;
+use spl_token::state::Account as TokenAccount;
+use subscription_program::{accounts, instruction, Subscription, ID as PROGRAM_ID};
+use test_harness::forward_cpi_to_syscall_stubs;
+use trident_fuzz::fuzzing::*;
+
+mod fuzz_accounts;
+
+const STARTING_BALANCE: u64 = 1_000 * 1_000_000;
+const INTERVAL: i64 = 30 * 24 * 60 * 60;
+
+/// `processor!` takes one lifetime for the accounts slice and its items,
+/// Anchor's `entry` two
+fn process(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let accounts = Box::leak(accounts.to_vec().into_boxed_slice());
+    subscription_program::entry(program_id, accounts, data)
+}
+
+/// What the invariants compare before and after a flow
+struct Snapshot {
+    subscriptions: Vec<Option<Subscription>>,
+    /// Per subscriber: its authority's lamports, then its subscription's
+    lamports: Vec<(u64, u64)>,
+    /// Token balance of each pool key, 0 for anything else
+    tokens: Vec<u64>,
+}
+
+#[derive(FuzzTestMethods)]
+struct FuzzTest {
+    trident: Trident,
+    fuzz_accounts: AccountAddresses,
+}
+
+#[flow_executor]
+impl FuzzTest {
+    fn new() -> Self {
+        let mut trident = Trident::default();
+        trident.deploy_entrypoint(TridentEntrypoint::new(
+            PROGRAM_ID,
+            None,
+            processor!(process),
+        ));
+        forward_cpi_to_syscall_stubs();
+        Self {
+            trident,
+            fuzz_accounts: AccountAddresses::default(),
+        }
+    }
+
+    #[init]
+    fn start(&mut self) {
+        let payer = self.trident.payer().pubkey();
+        let merchant = self.trident.random_pubkey();
+        let mint = self.trident.random_pubkey();
+        let instructions = self.trident.initialize_mint(&payer, &mint, 6, &payer, None);
+        let result = self.trident.process_transaction(&instructions, None);
+        assert!(result.is_success(), "{}", result.logs());
+        let merchant_token_account = self.create_token_account(&mint, &merchant, 0);
+
+        let mut subscribers = Vec::new();
+        for _ in 0..2 {
+            let authority = self.trident.random_pubkey();
+            let token_account = self.create_token_account(&mint, &authority, STARTING_BALANCE);
+            let (subscription, _) = Pubkey::find_program_address(
+                &[b"subscription", authority.as_ref(), merchant.as_ref()],
+                &PROGRAM_ID,
+            );
+            let ix = Instruction {
+                program_id: PROGRAM_ID,
+                accounts: accounts::InitializeSubscription {
+                    subscription,
+                    authority,
+                    recipient: merchant,
+                    user_token_account: token_account,
+                    recipient_token_account: merchant_token_account,
+                    token_mint: mint,
+                    token_program: spl_token::ID,
+                    payer,
+                    system_program: system_program::ID,
+                    denylist_entry: Pubkey::find_program_address(
+                        &[b"denylist", merchant.as_ref(), authority.as_ref()],
+                        &PROGRAM_ID,
+                    )
+                    .0,
+                    allowlist: Pubkey::find_program_address(
+                        &[b"allowlist", merchant.as_ref()],
+                        &PROGRAM_ID,
+                    )
+                    .0,
+                    allowlist_entry: Pubkey::find_program_address(
+                        &[b"allowlist", merchant.as_ref(), authority.as_ref()],
+                        &PROGRAM_ID,
+                    )
+                    .0,
+                }
+                .to_account_metas(None),
+                data: instruction::InitializeSubscription {
+                    amount_per_period: self.trident.random_from_range(1..=STARTING_BALANCE / 20),
+                    interval_seconds: INTERVAL,
+                    expires_at: None,
+                }
+                .data(),
+            };
+            let result = self
+                .trident
+                .process_transaction(&[ix], Some("initialize_subscription"));
+            assert!(result.is_success(), "{}", result.logs());
+            subscribers.push(Subscriber {
+                authority,
+                token_account,
+                subscription,
+            });
+        }
+
+        let mut pool = vec![
+            merchant,
+            mint,
+            merchant_token_account,
+            spl_token::ID,
+            system_program::ID,
+            PROGRAM_ID,
+            self.trident.random_pubkey(),
+        ];
+        for subscriber in &subscribers {
+            pool.extend([
+                subscriber.authority,
+                subscriber.token_account,
+                subscriber.subscription,
+            ]);
+        }
+        self.fuzz_accounts = AccountAddresses {
+            merchant_token_account,
+            subscribers,
+            pool,
+            lamports: 0,
+        };
+        self.fuzz_accounts.lamports = self.total_lamports();
+    }
+
+    #[flow(weight = 30)]
+    fn charge(&mut self) {
+        let target = self.pick_subscriber();
+        let ix = Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::ChargeSubscription {
+                subscription: target.subscription,
+                user_token_account: target.token_account,
+                recipient_token_account: self.fuzz_accounts.merchant_token_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::ChargeSubscription {}.data(),
+        };
+        self.attack(ix, "charge_subscription");
+    }
+
+    #[flow(weight = 10)]
+    fn cancel(&mut self) {
+        let target = self.pick_subscriber();
+        let ix = Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CancelSubscription {
+                subscription: target.subscription,
+                authority: target.authority,
+                user_token_account: target.token_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CancelSubscription {}.data(),
+        };
+        self.attack(ix, "cancel_subscription");
+    }
+
+    #[flow(weight = 10)]
+    fn cleanup(&mut self) {
+        let target = self.pick_subscriber();
+        let ix = Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::CleanupCancelledSubscription {
+                subscription: target.subscription,
+                authority: target.authority,
+            }
+            .to_account_metas(None),
+            data: instruction::CleanupCancelledSubscription {}.data(),
+        };
+        self.attack(ix, "cleanup_cancelled_subscription");
+    }
+
+    #[flow(weight = 20)]
+    fn update(&mut self) {
+        let target = self.pick_subscriber();
+        // Terms a user might pick, so later charges run at the new price
+        // rather than all failing for funds
+        let now = self.trident.get_current_timestamp();
+        let new_amount = self.maybe(|trident| trident.random_from_range(0..=STARTING_BALANCE / 20));
+        let new_interval = self.maybe(|trident| trident.random_from_range(0..=2 * INTERVAL));
+        let new_expires_at =
+            self.maybe(|trident| trident.random_from_range(now - INTERVAL..=now + 12 * INTERVAL));
+        let ix = Instruction {
+            program_id: PROGRAM_ID,
+            accounts: accounts::UpdateSubscription {
+                subscription: target.subscription,
+                authority: target.authority,
+            }
+            .to_account_metas(None),
+            data: instruction::UpdateSubscription {
+                new_amount,
+                new_interval,
+                new_expires_at,
+            }
+            .data(),
+        };
+        self.attack(ix, "update_subscription");
+    }
+
+    #[flow(weight = 20)]
+    fn warp(&mut self) {
+        let seconds = self.trident.random_from_range(0..=2 * INTERVAL);
+        self.trident.forward_in_time(seconds);
+    }
+
+    /// Plant a copy of a subscription, with a few bytes flipped, at a new
+    /// program-owned address the other flows can substitute in
+    #[flow(weight = 10)]
+    fn forge(&mut self) {
+        let target = self.pick_subscriber();
+        let mut account = self.trident.get_account(&target.subscription);
+        if account.data().is_empty() {
+            return;
+        }
+        for _ in 0..self.trident.random_from_range(1..=4) {
+            let index = self.trident.random_from_range(0..account.data().len());
+            let bit = self.trident.random_from_range(0..8);
+            account.data_as_mut_slice()[index] ^= 1 << bit;
+        }
+        let address = self.trident.random_pubkey();
+        self.trident.set_account_custom(&address, &account);
+        self.fuzz_accounts.pool.push(address);
+        self.fuzz_accounts.lamports += account.lamports();
+    }
+
+    /// Every token the subscribers paid is on their subscription's books
+    #[end]
+    fn end(&mut self) {
+        for subscriber in self.fuzz_accounts.subscribers.clone() {
+            if let Some(subscription) = self.subscription(&subscriber.subscription) {
+                let paid = STARTING_BALANCE - self.token_balance(&subscriber.token_account);
+                assert_eq!(subscription.total_charged, paid);
+            }
+        }
+    }
+}
+
+impl FuzzTest {
+    fn create_token_account(&mut self, mint: &Pubkey, owner: &Pubkey, amount: u64) -> Pubkey {
+        let payer = self.trident.payer().pubkey();
+        let address = self.trident.random_pubkey();
+        let mut instructions = self
+            .trident
+            .initialize_token_account(&payer, &address, mint, owner);
+        if amount > 0 {
+            instructions.push(self.trident.mint_to(&address, mint, &payer, amount));
+        }
+        let result = self.trident.process_transaction(&instructions, None);
+        assert!(result.is_success(), "{}", result.logs());
+        address
+    }
+
+    fn pick_subscriber(&mut self) -> Subscriber {
+        let index = self
+            .trident
+            .random_from_range(0..self.fuzz_accounts.subscribers.len());
+        self.fuzz_accounts.subscribers[index]
+    }
+
+    fn maybe<T>(&mut self, value: impl FnOnce(&mut Trident) -> T) -> Option<T> {
+        if self.trident.random_bool() {
+            Some(value(&mut self.trident))
+        } else {
+            None
+        }
+    }
+
+    /// Send `ix` with up to two accounts swapped for pool keys, signed by a
+    /// random subscriber, and check the invariants against what it did
+    fn attack(&mut self, mut ix: Instruction, name: &str) {
+        for _ in 0..self.trident.random_from_range(0..=2) {
+            let index = self.trident.random_from_range(0..ix.accounts.len());
+            let replacement = self
+                .trident
+                .random_from_range(0..self.fuzz_accounts.pool.len());
+            ix.accounts[index].pubkey = self.fuzz_accounts.pool[replacement];
+        }
+        // As the runtime does, demote a program swapped into a writable slot
+        for meta in ix.accounts.iter_mut() {
+            if self.trident.get_account(&meta.pubkey).executable() {
+                meta.is_writable = false;
+            }
+        }
+        // Trident does not check signatures, so a signer slot counts as
+        // signed. Only the acting subscriber can sign; any other key in a
+        // signer slot goes unsigned.
+        let signer = self.pick_subscriber().authority;
+        for meta in ix.accounts.iter_mut() {
+            if meta.pubkey != signer {
+                meta.is_signer = false;
+            }
+        }
+
+        let before = self.snapshot();
+        let result = self.trident.process_transaction(&[ix.clone()], Some(name));
+        let after = self.snapshot();
+        let context = format!("{:?}\n{}", ix, result.logs());
+
+        assert_eq!(
+            self.total_lamports(),
+            self.fuzz_accounts.lamports,
+            "lamports leaked\n{}",
+            context
+        );
+
+        let subscribers = &self.fuzz_accounts.subscribers;
+        let mut paid = 0;
+        for (index, subscriber) in subscribers.iter().enumerate() {
+            let signed = subscriber.authority == signer;
+
+            // Rent: only to the authority, only with its signature
+            let (authority_before, rent_before) = before.lamports[index];
+            let (authority_after, rent_after) = after.lamports[index];
+            if rent_after < rent_before {
+                assert!(signed, "closed without its authority\n{}", context);
+                assert_eq!(
+                    authority_after - authority_before,
+                    rent_before - rent_after,
+                    "rent went elsewhere\n{}",
+                    context
+                );
+            }
+
+            // Tokens: nothing, or one period of the terms in force
+            let position = self.position(&subscriber.token_account);
+            let spent = before.tokens[position] - after.tokens[position];
+            if spent > 0 {
+                let terms = before.subscriptions[index]
+                    .as_ref()
+                    .expect("tokens moved without a subscription");
+                assert_eq!(spent, terms.amount_per_period, "{}", context);
+                paid += spent;
+            }
+
+            // State: identity fixed, terms only under the authority
+            if let (Some(then), Some(now)) =
+                (&before.subscriptions[index], &after.subscriptions[index])
+            {
+                assert_eq!(now.authority, then.authority);
+                assert_eq!(now.recipient, then.recipient);
+                assert_eq!(now.user_token_account, then.user_token_account);
+                assert_eq!(now.recipient_token_account, then.recipient_token_account);
+                assert_eq!(now.token_mint, then.token_mint);
+                assert_eq!(now.bump, then.bump);
+                assert!(now.total_charged >= then.total_charged);
+                let terms = |s: &Subscription| {
+                    (
+                        s.amount_per_period,
+                        s.interval_seconds,
+                        s.expires_at,
+                        s.is_active,
+                    )
+                };
+                if !signed {
+                    assert_eq!(terms(now), terms(then), "terms changed\n{}", context);
+                }
+            }
+        }
+
+        // Whatever left the subscribers reached the merchant, and nothing
+        // else in the pool moved
+        let merchant = self.position(&self.fuzz_accounts.merchant_token_account);
+        assert_eq!(
+            after.tokens[merchant] - before.tokens[merchant],
+            paid,
+            "{}",
+            context
+        );
+        for (index, (now, then)) in after.tokens.iter().zip(&before.tokens).enumerate() {
+            let key = self.fuzz_accounts.pool[index];
+            let is_subscriber = subscribers.iter().any(|s| s.token_account == key);
+            if index != merchant && !is_subscriber {
+                assert_eq!(now, then, "{} moved\n{}", key, context);
+            }
+        }
+    }
+
+    fn position(&self, key: &Pubkey) -> usize {
+        self.fuzz_accounts
+            .pool
+            .iter()
+            .position(|address| address == key)
+            .unwrap()
+    }
+
+    fn snapshot(&mut self) -> Snapshot {
+        let subscribers = self.fuzz_accounts.subscribers.clone();
+        Snapshot {
+            subscriptions: subscribers
+                .iter()
+                .map(|subscriber| self.subscription(&subscriber.subscription))
+                .collect(),
+            lamports: subscribers
+                .iter()
+                .map(|subscriber| {
+                    (
+                        self.trident.get_account(&subscriber.authority).lamports(),
+                        self.trident
+                            .get_account(&subscriber.subscription)
+                            .lamports(),
+                    )
+                })
+                .collect(),
+            tokens: self
+                .fuzz_accounts
+                .pool
+                .clone()
+                .iter()
+                .map(|address| self.token_balance(address))
+                .collect(),
+        }
+    }
+
+    fn subscription(&mut self, address: &Pubkey) -> Option<Subscription> {
+        let account = self.trident.get_account(address);
+        if account.owner() != &PROGRAM_ID {
+            return None;
+        }
+        Subscription::try_deserialize(&mut account.data()).ok()
+    }
+
+    fn token_balance(&mut self, address: &Pubkey) -> u64 {
+        let account = self.trident.get_account(address);
+        if account.owner() != &spl_token::ID {
+            return 0;
+        }
+        TokenAccount::unpack(account.data())
+            .map(|token_account| token_account.amount)
+            .unwrap_or_default()
+    }
+
+    fn total_lamports(&mut self) -> u64 {
+        let mut keys = self.fuzz_accounts.pool.clone();
+        keys.push(self.trident.payer().pubkey());
+        keys.iter()
+            .map(|address| self.trident.get_account(address).lamports())
+            .sum()
+    }
+}
+
+fn main() {
+    FuzzTest::fuzz(1000, 100);
+}
@@ -0,0 +1,104 @@
+//! The parts of a Helius enhanced-transaction webhook the service reads.
+//!
+//! Helius posts a JSON array of parsed transactions. Only the fields used to
+//! find the subscription program's instructions and the token transfers they
+//! made are modelled; everything else is ignored.
+
+use serde::Deserialize;
+use subtle::ConstantTimeEq;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnhancedTransaction {
+    pub signature: String,
+    pub slot: u64,
+    pub timestamp: i64,
+    /// `null` for transactions that succeeded
+    #[serde(default)]
+    pub transaction_error: Option<serde_json::Value>,
+    #[serde(default)]
+    pub instructions: Vec<TransactionInstruction>,
+    #[serde(default)]
+    pub account_data: Vec<AccountData>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionInstruction {
+    pub program_id: String,
+    #[serde(default)]
+    pub accounts: Vec<String>,
+    /// Base58
+    #[serde(default)]
+    pub data: String,
+    /// Every instruction the top-level one invoked, in execution order
+    #[serde(default)]
+    pub inner_instructions: Vec<InnerInstruction>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InnerInstruction {
+    pub program_id: String,
+    #[serde(default)]
+    pub accounts: Vec<String>,
+    #[serde(default)]
+    pub data: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountData {
+    pub account: String,
+    #[serde(default)]
+    pub token_balance_changes: Vec<TokenBalanceChange>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenBalanceChange {
+    /// Owner of the token account
+    pub user_account: String,
+    pub token_account: String,
+    pub mint: String,
+    pub raw_token_amount: RawTokenAmount,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RawTokenAmount {
+    /// Signed change in base units, as a string
+    pub token_amount: String,
+    pub decimals: u8,
+}
+
+impl EnhancedTransaction {
+    pub fn succeeded(&self) -> bool {
+        self.transaction_error
+            .as_ref()
+            .is_none_or(serde_json::Value::is_null)
+    }
+
+    /// Owner and mint of `token_account`, if its balance changed
+    pub fn token_account_owner(&self, token_account: &str) -> Option<(&str, &str)> {
+        self.account_data
+            .iter()
+            .flat_map(|data| &data.token_balance_changes)
+            .find(|change| change.token_account == token_account)
+            .map(|change| (change.user_account.as_str(), change.mint.as_str()))
+    }
+}
+
+/// A webhook body: the transactions Helius matched since the last post
+pub fn parse_payload(body: &[u8]) -> serde_json::Result<Vec<EnhancedTransaction>> {
+    serde_json::from_slice(body)
+}
+
+/// Helius sends the `authHeader` set on the webhook verbatim as the
+/// `Authorization` header; compared in constant time
+pub fn verify_auth_header(expected: &str, received: Option<&str>) -> bool {
+    match received {
+        Some(received) => bool::from(expected.as_bytes().ct_eq(received.as_bytes())),
+        None => false,
+    }
+}
@@ -0,0 +1,79 @@
+//! Helius webhook consumer for the subscription program.
+//!
+//! Helius posts the program's transactions as enhanced (parsed) transactions.
+//! The service checks the webhook's auth header, turns each transaction into
+//! [`BillingEvent`]s and forwards them, signed, to the indexer and to the
+//! merchant each subscription pays. Merchants get pushed events without
+//! running an RPC node or parsing transactions themselves.
+//!
+//! [`Service::handle`] does all of it but the network I/O, which the
+//! `subscription-webhook` binary adds.
+
+pub mod helius;
+pub mod http;
+pub mod normalize;
+pub mod routing;
+
+use std::io;
+
+pub use normalize::{normalize, BillingEvent, BillingEventKind};
+pub use routing::{Config, Delivery, Directory};
+
+use http::Request;
+
+/// What to answer Helius, and what to send on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    pub status: u16,
+    pub deliveries: Vec<Delivery>,
+}
+
+impl Outcome {
+    fn reject(status: u16) -> Self {
+        Self {
+            status,
+            deliveries: Vec::new(),
+        }
+    }
+}
+
+pub struct Service {
+    pub config: Config,
+    pub directory: Directory,
+}
+
+impl Service {
+    pub fn new(config: Config) -> io::Result<Self> {
+        let directory = Directory::open(config.directory.clone())?;
+        Ok(Self { config, directory })
+    }
+
+    /// Handle one webhook request. Anything but a verified POST to the
+    /// configured path is rejected before the body is parsed.
+    pub fn handle(&mut self, request: &Request) -> Outcome {
+        if request.path != self.config.path {
+            return Outcome::reject(404);
+        }
+        if request.method != "POST" {
+            return Outcome::reject(405);
+        }
+        if !helius::verify_auth_header(&self.config.auth_header, request.header("authorization")) {
+            return Outcome::reject(401);
+        }
+        let Ok(transactions) = helius::parse_payload(&request.body) else {
+            return Outcome::reject(400);
+        };
+
+        let mut events: Vec<BillingEvent> = transactions.iter().flat_map(normalize).collect();
+        if self.directory.resolve(&mut events) {
+            if let Err(err) = self.directory.save() {
+                eprintln!("Could not save the directory: {err}");
+            }
+        }
+
+        Outcome {
+            status: 200,
+            deliveries: routing::deliveries(&self.config, &events),
+        }
+    }
+}
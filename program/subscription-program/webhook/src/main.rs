@@ -0,0 +1,99 @@
+//! Receive Helius webhooks and forward billing events.
+//!
+//! ```bash
+//! cargo run -p subscription-webhook -- webhook/webhook.example.toml
+//! ```
+//!
+//! Requests are answered as soon as they are verified and parsed; deliveries
+//! go out from a worker thread and are retried with backoff.
+
+use std::fs;
+use std::io::BufReader;
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use clap::Parser;
+use subscription_webhook::http::{self, read_request, write_response};
+use subscription_webhook::routing::SIGNATURE_HEADER;
+use subscription_webhook::{Config, Delivery, Service};
+
+#[derive(Debug, Parser)]
+#[command(about = "Forward Helius webhooks for the subscription program as billing events")]
+struct Args {
+    /// Service config (TOML)
+    config: PathBuf,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    let config = Config::from_toml(&fs::read_to_string(&args.config)?)?;
+    let listener = TcpListener::bind(&config.listen)?;
+    println!("Listening on {}{}", config.listen, config.path);
+
+    let attempts = config.attempts;
+    let service = Arc::new(Mutex::new(Service::new(config)?));
+    let (outbox, inbox) = mpsc::channel::<Delivery>();
+    thread::spawn(move || deliver(inbox, attempts));
+
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let service = Arc::clone(&service);
+        let outbox = outbox.clone();
+        thread::spawn(move || {
+            if let Err(err) = serve(stream, &service, &outbox) {
+                eprintln!("Request failed: {err}");
+            }
+        });
+    }
+    Ok(())
+}
+
+fn serve(
+    mut stream: TcpStream,
+    service: &Mutex<Service>,
+    outbox: &mpsc::Sender<Delivery>,
+) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    let request = match read_request(&mut BufReader::new(&stream)) {
+        Ok(request) => request,
+        Err(_) => return write_response(&mut stream, 400, "Bad Request"),
+    };
+
+    let outcome = service.lock().expect("service lock").handle(&request);
+    for delivery in outcome.deliveries {
+        outbox.send(delivery).expect("delivery worker is running");
+    }
+    let reason = match outcome.status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        _ => "Method Not Allowed",
+    };
+    write_response(&mut stream, outcome.status, reason)
+}
+
+/// Send deliveries in order, retrying each with exponential backoff
+fn deliver(inbox: Receiver<Delivery>, attempts: u32) {
+    for delivery in inbox {
+        let headers = [(SIGNATURE_HEADER, delivery.signature.as_str())];
+        for attempt in 1..=attempts {
+            match http::post(&delivery.url, &headers, &delivery.body) {
+                Ok(status) if (200..300).contains(&status) => break,
+                Ok(status) => eprintln!("{} answered {status}", delivery.url),
+                Err(err) => eprintln!("{} failed: {err}", delivery.url),
+            }
+            if attempt == attempts {
+                eprintln!("Dropping delivery to {}", delivery.url);
+            } else {
+                thread::sleep(Duration::from_secs(1 << attempt.min(6)));
+            }
+        }
+    }
+}
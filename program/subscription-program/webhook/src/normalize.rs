@@ -0,0 +1,162 @@
+//! Turn enhanced transactions into billing events.
+//!
+//! A transaction's instructions are walked in execution order, top-level and
+//! inner alike, so the subscription program is found whether it was called
+//! directly or through a wallet, hybrid-auth guard or automation program.
+//! Each of its instructions opens an event, and the SPL Token transfers into
+//! the event's recipient token account that follow, until the program's next
+//! instruction, are its amount.
+
+use anchor_lang::Discriminator;
+use serde::{Deserialize, Serialize};
+use spl_token::instruction::TokenInstruction;
+use subscription_program::instruction;
+
+use crate::helius::EnhancedTransaction;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BillingEventKind {
+    /// Signed up and paid the first period
+    Created,
+    Charged,
+    Cancelled,
+    Updated,
+}
+
+/// What merchants and the indexer receive. `(signature, subscription, kind)`
+/// identifies an event, so a redelivery can be dropped.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BillingEvent {
+    pub kind: BillingEventKind,
+    pub signature: String,
+    pub slot: u64,
+    pub timestamp: i64,
+    pub subscription: String,
+    /// Unknown for cancellations and updates of subscriptions the service
+    /// has not seen before; see [`Directory`](crate::routing::Directory)
+    pub recipient: Option<String>,
+    /// Base units paid to the recipient; `None` when nothing moved
+    pub amount: Option<u64>,
+    pub mint: Option<String>,
+}
+
+/// Where an instruction keeps the accounts an event needs
+struct Layout {
+    kind: BillingEventKind,
+    subscription: usize,
+    recipient: Option<usize>,
+    recipient_token_account: Option<usize>,
+}
+
+fn layout(data: &[u8]) -> Option<Layout> {
+    let discriminator = data.get(..8)?;
+    let layout = |kind, recipient, recipient_token_account| Layout {
+        kind,
+        subscription: 0,
+        recipient,
+        recipient_token_account,
+    };
+    let is = |expected: &[u8]| discriminator == expected;
+
+    if is(instruction::InitializeSubscription::DISCRIMINATOR)
+        || is(instruction::InitializeWalletSubscription::DISCRIMINATOR)
+    {
+        Some(layout(BillingEventKind::Created, Some(2), Some(4)))
+    } else if is(instruction::ChargeSubscription::DISCRIMINATOR)
+        || is(instruction::ChargeSubscriptionWithReference::DISCRIMINATOR)
+        || is(instruction::ChargeSubscriptionAttested::DISCRIMINATOR)
+        || is(instruction::ChargeSubscriptionTuktuk::DISCRIMINATOR)
+    {
+        Some(layout(BillingEventKind::Charged, None, Some(2)))
+    } else if is(instruction::ChargeSubscriptionWithPolicy::DISCRIMINATOR) {
+        Some(layout(BillingEventKind::Charged, None, Some(3)))
+    } else if is(instruction::CancelSubscription::DISCRIMINATOR) {
+        Some(layout(BillingEventKind::Cancelled, None, None))
+    } else if is(instruction::UpdateSubscription::DISCRIMINATOR) {
+        Some(layout(BillingEventKind::Updated, None, None))
+    } else {
+        None
+    }
+}
+
+/// Token amount `data` moves into `destination`, if it is an SPL Token
+/// transfer there
+fn transfer_into(accounts: &[String], data: &[u8], destination: &str) -> Option<u64> {
+    match TokenInstruction::unpack(data).ok()? {
+        TokenInstruction::Transfer { amount } if accounts.get(1)? == destination => Some(amount),
+        TokenInstruction::TransferChecked { amount, .. } if accounts.get(2)? == destination => {
+            Some(amount)
+        }
+        _ => None,
+    }
+}
+
+/// Billing events in `transaction`, in execution order. Failed transactions
+/// have none.
+pub fn normalize(transaction: &EnhancedTransaction) -> Vec<BillingEvent> {
+    if !transaction.succeeded() {
+        return Vec::new();
+    }
+
+    let program = subscription_program::ID.to_string();
+    let token_program = spl_token::ID.to_string();
+    let steps = transaction.instructions.iter().flat_map(|top| {
+        std::iter::once((&top.program_id, &top.accounts, &top.data)).chain(
+            top.inner_instructions
+                .iter()
+                .map(|inner| (&inner.program_id, &inner.accounts, &inner.data)),
+        )
+    });
+
+    let mut events = Vec::new();
+    // The open event and the token account its payment lands in
+    let mut destination: Option<String> = None;
+    for (program_id, accounts, data) in steps {
+        let Ok(data) = bs58::decode(data).into_vec() else {
+            continue;
+        };
+
+        if *program_id == program {
+            destination = None;
+            let Some(layout) = layout(&data) else {
+                continue;
+            };
+            let Some(subscription) = accounts.get(layout.subscription) else {
+                continue;
+            };
+            let recipient_token_account = layout
+                .recipient_token_account
+                .and_then(|index| accounts.get(index));
+            let (owner, mint) = recipient_token_account
+                .and_then(|account| transaction.token_account_owner(account))
+                .unzip();
+            let recipient = layout
+                .recipient
+                .and_then(|index| accounts.get(index))
+                .map(String::as_str)
+                .or(owner);
+
+            destination = recipient_token_account.cloned();
+            events.push(BillingEvent {
+                kind: layout.kind,
+                signature: transaction.signature.clone(),
+                slot: transaction.slot,
+                timestamp: transaction.timestamp,
+                subscription: subscription.clone(),
+                recipient: recipient.map(str::to_string),
+                amount: None,
+                mint: mint.map(str::to_string),
+            });
+        } else if *program_id == token_program {
+            let (Some(event), Some(destination)) = (events.last_mut(), &destination) else {
+                continue;
+            };
+            if let Some(amount) = transfer_into(accounts, &data, destination) {
+                let total = event.amount.unwrap_or_default().saturating_add(amount);
+                event.amount = Some(total);
+            }
+        }
+    }
+    events
+}
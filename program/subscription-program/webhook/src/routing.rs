@@ -0,0 +1,179 @@
+//! Who receives which events, and how deliveries are signed.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anchor_lang::prelude::Pubkey;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+use crate::normalize::BillingEvent;
+
+/// Header carrying a delivery's signature
+pub const SIGNATURE_HEADER: &str = "X-Subscription-Signature";
+
+/// Service settings, read from a TOML file
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    #[serde(default = "default_listen")]
+    pub listen: String,
+    /// Path Helius posts to
+    #[serde(default = "default_path")]
+    pub path: String,
+    /// The `authHeader` set when creating the Helius webhook
+    pub auth_header: String,
+    /// Where the subscription-to-recipient directory is kept between runs;
+    /// in memory only without it
+    #[serde(default)]
+    pub directory: Option<PathBuf>,
+    /// Attempts per delivery before it is dropped
+    #[serde(default = "default_attempts")]
+    pub attempts: u32,
+    /// Receives every event
+    #[serde(default)]
+    pub indexer: Option<Endpoint>,
+    #[serde(default, rename = "merchant")]
+    pub merchants: Vec<Merchant>,
+}
+
+fn default_listen() -> String {
+    "0.0.0.0:8080".to_string()
+}
+
+fn default_path() -> String {
+    "/helius".to_string()
+}
+
+fn default_attempts() -> u32 {
+    5
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Endpoint {
+    /// `http://` only; put a TLS-terminating proxy in front of HTTPS receivers
+    pub url: String,
+    /// Key the delivery's HMAC-SHA256 signature is made with
+    pub secret: String,
+}
+
+/// Receives the events of subscriptions paying `recipient`
+#[derive(Debug, Clone, Deserialize)]
+pub struct Merchant {
+    pub recipient: String,
+    #[serde(flatten)]
+    pub endpoint: Endpoint,
+}
+
+impl Config {
+    pub fn from_toml(source: &str) -> Result<Self, String> {
+        let config: Self = toml::from_str(source).map_err(|err| err.to_string())?;
+        for merchant in &config.merchants {
+            Pubkey::from_str(&merchant.recipient)
+                .map_err(|_| format!("invalid merchant recipient {}", merchant.recipient))?;
+        }
+        if config.attempts == 0 {
+            return Err("attempts must be at least 1".to_string());
+        }
+        Ok(config)
+    }
+}
+
+/// Recipient of every subscription seen so far. Cancellations and updates
+/// do not touch the recipient's accounts, so their events are routed by
+/// what signups and charges taught the service.
+#[derive(Debug, Default)]
+pub struct Directory {
+    recipients: BTreeMap<String, String>,
+    path: Option<PathBuf>,
+}
+
+impl Directory {
+    /// Load from `path`, or start empty if there is no file there yet
+    pub fn open(path: Option<PathBuf>) -> io::Result<Self> {
+        let recipients = match &path {
+            Some(path) if path.exists() => serde_json::from_slice(&fs::read(path)?)?,
+            _ => BTreeMap::new(),
+        };
+        Ok(Self { recipients, path })
+    }
+
+    /// Learn recipients from `events` and fill in the ones they lack.
+    /// Returns whether anything new was learned.
+    pub fn resolve(&mut self, events: &mut [BillingEvent]) -> bool {
+        let mut learned = false;
+        for event in events {
+            match &event.recipient {
+                Some(recipient) => {
+                    let previous = self
+                        .recipients
+                        .insert(event.subscription.clone(), recipient.clone());
+                    learned |= previous.as_ref() != Some(recipient);
+                }
+                None => event.recipient = self.recipients.get(&event.subscription).cloned(),
+            }
+        }
+        learned
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        match &self.path {
+            Some(path) => fs::write(path, serde_json::to_vec_pretty(&self.recipients)?),
+            None => Ok(()),
+        }
+    }
+}
+
+/// One POST to one endpoint: a JSON array of events
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delivery {
+    pub url: String,
+    pub body: Vec<u8>,
+    /// `sha256=<hex HMAC-SHA256 of the body>`, sent as [`SIGNATURE_HEADER`]
+    pub signature: String,
+}
+
+/// `sha256=<hex>` over `body` with `secret`
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(body);
+    let digest = mac.finalize().into_bytes();
+    let hex: String = digest.iter().map(|byte| format!("{byte:02x}")).collect();
+    format!("sha256={hex}")
+}
+
+fn delivery(endpoint: &Endpoint, events: &[&BillingEvent]) -> Delivery {
+    let body = serde_json::to_vec(events).expect("events serialize");
+    Delivery {
+        url: endpoint.url.clone(),
+        signature: sign(&endpoint.secret, &body),
+        body,
+    }
+}
+
+/// The indexer gets every event, each merchant those of its recipient; an
+/// endpoint with nothing to receive gets no delivery
+pub fn deliveries(config: &Config, events: &[BillingEvent]) -> Vec<Delivery> {
+    if events.is_empty() {
+        return Vec::new();
+    }
+
+    let mut deliveries = Vec::new();
+    if let Some(indexer) = &config.indexer {
+        deliveries.push(delivery(indexer, &events.iter().collect::<Vec<_>>()));
+    }
+    for merchant in &config.merchants {
+        let own: Vec<&BillingEvent> = events
+            .iter()
+            .filter(|event| event.recipient.as_ref() == Some(&merchant.recipient))
+            .collect();
+        if !own.is_empty() {
+            deliveries.push(delivery(&merchant.endpoint, &own));
+        }
+    }
+    deliveries
+}
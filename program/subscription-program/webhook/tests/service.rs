@@ -0,0 +1,351 @@
+//! Webhook handling end to end, minus the sockets: payloads are built from
+//! the client's instruction builders, shaped as Helius delivers them.
+
+use std::io::Cursor;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use serde_json::{json, Value};
+use subscription_client::instructions;
+use subscription_client::pda::{associated_token_address, subscription_address};
+use subscription_client::Subscription;
+use subscription_webhook::helius::{parse_payload, verify_auth_header};
+use subscription_webhook::http::{read_request, Request};
+use subscription_webhook::routing::sign;
+use subscription_webhook::{normalize, BillingEvent, BillingEventKind, Config, Service};
+
+const AUTH: &str = "Bearer helius-secret";
+const AMOUNT: u64 = 5_000_000;
+
+struct Parties {
+    authority: Pubkey,
+    recipient: Pubkey,
+    mint: Pubkey,
+}
+
+impl Parties {
+    fn new() -> Self {
+        Self {
+            authority: Pubkey::new_unique(),
+            recipient: Pubkey::new_unique(),
+            mint: Pubkey::new_unique(),
+        }
+    }
+
+    fn address(&self) -> Pubkey {
+        subscription_address(&self.authority, &self.recipient).0
+    }
+
+    fn user_token_account(&self) -> Pubkey {
+        associated_token_address(&self.authority, &self.mint)
+    }
+
+    fn recipient_token_account(&self) -> Pubkey {
+        associated_token_address(&self.recipient, &self.mint)
+    }
+
+    fn subscription(&self) -> Subscription {
+        Subscription {
+            is_active: true,
+            recipient: self.recipient,
+            next_charge_at: 0,
+            authority: self.authority,
+            user_token_account: self.user_token_account(),
+            recipient_token_account: self.recipient_token_account(),
+            token_mint: self.mint,
+            amount_per_period: AMOUNT,
+            interval_seconds: 30 * 86_400,
+            last_charge_timestamp: 0,
+            created_at: 0,
+            total_charged: 0,
+            bump: 255,
+            expires_at: None,
+            on_plan: false,
+            max_total_spend: Subscription::NO_SPEND_LIMIT,
+        }
+    }
+
+    fn charge(&self) -> Instruction {
+        instructions::charge_subscription(&self.address(), &self.subscription())
+    }
+
+    fn transfer(&self, amount: u64) -> Instruction {
+        spl_token::instruction::transfer(
+            &spl_token::ID,
+            &self.user_token_account(),
+            &self.recipient_token_account(),
+            &self.address(),
+            &[],
+            amount,
+        )
+        .unwrap()
+    }
+
+    /// Helius's record of the recipient's token account being credited
+    fn credit(&self, amount: u64) -> Value {
+        json!({
+            "account": self.recipient_token_account().to_string(),
+            "tokenBalanceChanges": [{
+                "userAccount": self.recipient.to_string(),
+                "tokenAccount": self.recipient_token_account().to_string(),
+                "mint": self.mint.to_string(),
+                "rawTokenAmount": { "tokenAmount": amount.to_string(), "decimals": 6 },
+            }],
+        })
+    }
+}
+
+fn instruction_json(instruction: &Instruction) -> Value {
+    json!({
+        "programId": instruction.program_id.to_string(),
+        "accounts": instruction
+            .accounts
+            .iter()
+            .map(|meta| meta.pubkey.to_string())
+            .collect::<Vec<_>>(),
+        "data": bs58::encode(&instruction.data).into_string(),
+    })
+}
+
+/// A top-level instruction and everything it invoked
+fn step(top: &Instruction, inner: &[Instruction]) -> Value {
+    let mut value = instruction_json(top);
+    value["innerInstructions"] = inner.iter().map(instruction_json).collect();
+    value
+}
+
+fn transaction(signature: &str, steps: Vec<Value>, account_data: Vec<Value>) -> Value {
+    json!({
+        "signature": signature,
+        "slot": 250_000_000u64,
+        "timestamp": 1_700_000_000i64,
+        "type": "UNKNOWN",
+        "source": "UNKNOWN",
+        "transactionError": null,
+        "instructions": steps,
+        "accountData": account_data,
+        "tokenTransfers": [],
+        "nativeTransfers": [],
+        "events": {},
+    })
+}
+
+fn events_of(transaction: Value) -> Vec<BillingEvent> {
+    let body = serde_json::to_vec(&json!([transaction])).unwrap();
+    parse_payload(&body)
+        .unwrap()
+        .iter()
+        .flat_map(normalize)
+        .collect()
+}
+
+fn config(merchant: &Pubkey) -> Config {
+    Config::from_toml(&format!(
+        r#"
+auth_header = "{AUTH}"
+
+[indexer]
+url = "http://127.0.0.1:9000/events"
+secret = "indexer-secret"
+
+[[merchant]]
+recipient = "{merchant}"
+url = "http://127.0.0.1:9100/billing"
+secret = "merchant-secret"
+"#
+    ))
+    .unwrap()
+}
+
+fn post(body: &Value) -> Request {
+    Request {
+        method: "POST".to_string(),
+        path: "/helius".to_string(),
+        headers: vec![("authorization".to_string(), AUTH.to_string())],
+        body: serde_json::to_vec(body).unwrap(),
+    }
+}
+
+#[test]
+fn charge_carries_amount_and_recipient() {
+    let parties = Parties::new();
+    let events = events_of(transaction(
+        "sig1",
+        vec![step(&parties.charge(), &[parties.transfer(AMOUNT)])],
+        vec![parties.credit(AMOUNT)],
+    ));
+
+    assert_eq!(
+        events,
+        vec![BillingEvent {
+            kind: BillingEventKind::Charged,
+            signature: "sig1".to_string(),
+            slot: 250_000_000,
+            timestamp: 1_700_000_000,
+            subscription: parties.address().to_string(),
+            recipient: Some(parties.recipient.to_string()),
+            amount: Some(AMOUNT),
+            mint: Some(parties.mint.to_string()),
+        }]
+    );
+}
+
+#[test]
+fn signup_through_a_wallet_cpi_is_found() {
+    let parties = Parties::new();
+    let signup = instructions::initialize_subscription(
+        &parties.authority,
+        &parties.recipient,
+        &parties.mint,
+        &Pubkey::new_unique(),
+        AMOUNT,
+        30 * 86_400,
+        None,
+    );
+    let wallet = Instruction::new_with_bytes(Pubkey::new_unique(), &[1, 2, 3], vec![]);
+    let events = events_of(transaction(
+        "sig2",
+        vec![step(&wallet, &[signup, parties.transfer(AMOUNT)])],
+        vec![parties.credit(AMOUNT)],
+    ));
+
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].kind, BillingEventKind::Created);
+    assert_eq!(events[0].recipient, Some(parties.recipient.to_string()));
+    assert_eq!(events[0].amount, Some(AMOUNT));
+}
+
+#[test]
+fn batched_charges_keep_their_own_amounts() {
+    let parties = Parties::new();
+    let other = Parties {
+        authority: Pubkey::new_unique(),
+        ..Parties::new()
+    };
+    let events = events_of(transaction(
+        "sig3",
+        vec![
+            step(&parties.charge(), &[parties.transfer(AMOUNT)]),
+            step(&other.charge(), &[other.transfer(2 * AMOUNT)]),
+        ],
+        vec![parties.credit(AMOUNT), other.credit(2 * AMOUNT)],
+    ));
+
+    let amounts: Vec<_> = events.iter().map(|event| event.amount).collect();
+    assert_eq!(amounts, vec![Some(AMOUNT), Some(2 * AMOUNT)]);
+}
+
+#[test]
+fn failed_transactions_have_no_events() {
+    let parties = Parties::new();
+    let mut failed = transaction(
+        "sig4",
+        vec![step(&parties.charge(), &[parties.transfer(AMOUNT)])],
+        vec![parties.credit(AMOUNT)],
+    );
+    failed["transactionError"] = json!({ "InstructionError": [0, { "Custom": 6001 }] });
+
+    assert!(events_of(failed).is_empty());
+}
+
+#[test]
+fn unverified_requests_are_rejected() {
+    let parties = Parties::new();
+    let mut service = Service::new(config(&parties.recipient)).unwrap();
+    let body = json!([]);
+
+    let mut unsigned = post(&body);
+    unsigned.headers.clear();
+    assert_eq!(service.handle(&unsigned).status, 401);
+
+    let mut forged = post(&body);
+    forged.headers[0].1 = "Bearer guess".to_string();
+    assert_eq!(service.handle(&forged).status, 401);
+
+    let mut elsewhere = post(&body);
+    elsewhere.path = "/other".to_string();
+    assert_eq!(service.handle(&elsewhere).status, 404);
+
+    let mut get = post(&body);
+    get.method = "GET".to_string();
+    assert_eq!(service.handle(&get).status, 405);
+
+    let mut garbage = post(&body);
+    garbage.body = b"{not json".to_vec();
+    assert_eq!(service.handle(&garbage).status, 400);
+
+    assert!(verify_auth_header(AUTH, Some(AUTH)));
+    assert!(!verify_auth_header(AUTH, None));
+}
+
+#[test]
+fn events_are_routed_and_signed() {
+    let parties = Parties::new();
+    let stranger = Parties::new();
+    let mut service = Service::new(config(&parties.recipient)).unwrap();
+    let body = json!([transaction(
+        "sig5",
+        vec![
+            step(&parties.charge(), &[parties.transfer(AMOUNT)]),
+            step(&stranger.charge(), &[stranger.transfer(AMOUNT)]),
+        ],
+        vec![parties.credit(AMOUNT), stranger.credit(AMOUNT)],
+    )]);
+
+    let outcome = service.handle(&post(&body));
+    assert_eq!(outcome.status, 200);
+    assert_eq!(outcome.deliveries.len(), 2);
+
+    let indexer = &outcome.deliveries[0];
+    let all: Vec<BillingEvent> = serde_json::from_slice(&indexer.body).unwrap();
+    assert_eq!(all.len(), 2);
+    assert_eq!(indexer.signature, sign("indexer-secret", &indexer.body));
+
+    let merchant = &outcome.deliveries[1];
+    assert_eq!(merchant.url, "http://127.0.0.1:9100/billing");
+    let own: Vec<BillingEvent> = serde_json::from_slice(&merchant.body).unwrap();
+    assert_eq!(own.len(), 1);
+    assert_eq!(own[0].subscription, parties.address().to_string());
+    assert_eq!(merchant.signature, sign("merchant-secret", &merchant.body));
+    assert_ne!(merchant.signature, sign("indexer-secret", &merchant.body));
+}
+
+#[test]
+fn cancellations_reach_the_merchant_a_charge_taught() {
+    let parties = Parties::new();
+    let mut service = Service::new(config(&parties.recipient)).unwrap();
+    let cancel = instructions::cancel_subscription(
+        &parties.authority,
+        &parties.recipient,
+        &parties.user_token_account(),
+    );
+    let cancelled = json!([transaction("sig7", vec![step(&cancel, &[])], vec![])]);
+
+    // Unknown until the service has seen the subscription pay someone
+    let outcome = service.handle(&post(&cancelled));
+    assert_eq!(outcome.deliveries.len(), 1);
+
+    let charged = json!([transaction(
+        "sig6",
+        vec![step(&parties.charge(), &[parties.transfer(AMOUNT)])],
+        vec![parties.credit(AMOUNT)],
+    )]);
+    service.handle(&post(&charged));
+
+    let outcome = service.handle(&post(&cancelled));
+    assert_eq!(outcome.deliveries.len(), 2);
+    let own: Vec<BillingEvent> = serde_json::from_slice(&outcome.deliveries[1].body).unwrap();
+    assert_eq!(own[0].kind, BillingEventKind::Cancelled);
+    assert_eq!(own[0].recipient, Some(parties.recipient.to_string()));
+}
+
+#[test]
+fn reads_a_helius_post() {
+    let raw = b"POST /helius HTTP/1.1\r\nHost: example.com\r\nAuthorization: Bearer x\r\nContent-Type: application/json\r\nContent-Length: 2\r\n\r\n[]";
+    let request = read_request(&mut Cursor::new(&raw[..])).unwrap();
+
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.path, "/helius");
+    assert_eq!(request.header("authorization"), Some("Bearer x"));
+    assert_eq!(request.body, b"[]");
+}